@@ -0,0 +1,24 @@
+use axdriver::cpufreq;
+use axdriver::prelude::DevError;
+use axerrno::{AxError, AxResult};
+
+fn as_ax_err(e: DevError) -> AxError {
+    match e {
+        DevError::InvalidParam => AxError::InvalidInput,
+        DevError::ResourceBusy => AxError::ResourceBusy,
+        DevError::Unsupported => AxError::Unsupported,
+        _ => AxError::Io,
+    }
+}
+
+pub fn ax_cpufreq_pin(khz: u32) -> AxResult {
+    cpufreq::pin(khz).map_err(as_ax_err)
+}
+
+pub fn ax_cpufreq_unpin() -> AxResult {
+    cpufreq::unpin().map_err(as_ax_err)
+}
+
+pub fn ax_cpufreq_cur_khz() -> Option<u32> {
+    cpufreq::cur_freq_khz()
+}
@@ -16,6 +16,11 @@ cfg_display! {
     pub use display::*;
 }
 
+cfg_cpufreq! {
+    mod cpufreq;
+    pub use cpufreq::*;
+}
+
 mod stdio {
     use core::fmt;
 
@@ -31,12 +36,23 @@ mod stdio {
     pub fn ax_console_write_fmt(args: fmt::Arguments) -> fmt::Result {
         axlog::print_fmt(args)
     }
+
+    pub fn ax_console_write_str(s: &str) -> fmt::Result {
+        axlog::print_str(s)
+    }
 }
 
 mod time {
     pub use axhal::time::{
-        monotonic_time as ax_monotonic_time, wall_time as ax_wall_time, TimeValue as AxTimeValue,
+        adjust_wall_time as ax_adjust_wall_time, boottime as ax_boottime,
+        monotonic_time as ax_monotonic_time, set_wall_time as ax_set_wall_time,
+        wall_time as ax_wall_time, TimeValue as AxTimeValue,
     };
+
+    pub fn ax_set_deadline(deadline: Option<AxTimeValue>) -> Option<AxTimeValue> {
+        let deadline = deadline.map_or(axtask::Deadline::NONE, axtask::Deadline::at);
+        axtask::set_deadline(deadline).time()
+    }
 }
 
 pub use self::mem::*;
@@ -44,5 +60,8 @@ pub use self::stdio::*;
 pub use self::task::*;
 pub use self::time::*;
 
-pub use axhal::misc::terminate as ax_terminate;
+pub use axhal::misc::{random as ax_random, terminate as ax_terminate};
+cfg_snapshot! {
+    pub use axruntime::take_snapshot as ax_snapshot;
+}
 pub use axio::PollState as AxPollState;
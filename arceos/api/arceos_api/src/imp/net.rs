@@ -2,6 +2,9 @@ use crate::io::AxPollState;
 use axerrno::AxResult;
 use axnet::{UdpSocket, TcpSocket};
 use core::net::{IpAddr, SocketAddr};
+use core::time::Duration;
+
+pub use axnet::TcpKeepAlive as AxTcpKeepAlive;
 
 /// A handle to a TCP socket.
 pub struct AxTcpSocketHandle(TcpSocket);
@@ -30,6 +33,17 @@ pub fn ax_tcp_set_nonblocking(socket: &AxTcpSocketHandle, nonblocking: bool) ->
     Ok(())
 }
 
+pub fn ax_tcp_set_keepalive(
+    socket: &AxTcpSocketHandle,
+    keepalive: Option<AxTcpKeepAlive>,
+) -> AxResult {
+    socket.0.set_keepalive(keepalive)
+}
+
+pub fn ax_tcp_set_user_timeout(socket: &AxTcpSocketHandle, timeout: Option<Duration>) -> AxResult {
+    socket.0.set_user_timeout(timeout)
+}
+
 pub fn ax_tcp_connect(socket: &AxTcpSocketHandle, addr: SocketAddr) -> AxResult {
     socket.0.connect(addr)
 }
@@ -38,7 +52,8 @@ pub fn ax_tcp_bind(socket: &AxTcpSocketHandle, addr: SocketAddr) -> AxResult {
     socket.0.bind(addr)
 }
 
-pub fn ax_tcp_listen(socket: &AxTcpSocketHandle, _backlog: usize) -> AxResult {
+pub fn ax_tcp_listen(socket: &AxTcpSocketHandle, backlog: usize) -> AxResult {
+    socket.0.set_backlog(backlog);
     socket.0.listen()
 }
 
@@ -26,6 +26,8 @@ pub fn ax_exit(_exit_code: i32) -> ! {
 cfg_task! {
     use core::time::Duration;
 
+    pub use axtask::CpuPlacement as AxCpuPlacement;
+
     /// A handle to a task.
     pub struct AxTaskHandle {
         inner: axtask::AxTaskRef,
@@ -82,6 +84,18 @@ cfg_task! {
         }
     }
 
+    pub fn ax_priority_range() -> Option<(isize, isize)> {
+        axtask::PRIORITY_RANGE
+    }
+
+    pub fn ax_scheduler_name() -> &'static str {
+        axtask::scheduler_name()
+    }
+
+    pub fn ax_set_current_placement(placement: AxCpuPlacement) {
+        axtask::set_current_placement(placement)
+    }
+
     pub fn ax_wait_queue_wait(
         wq: &AxWaitQueueHandle,
         until_condition: impl Fn() -> bool,
@@ -32,6 +32,29 @@ pub mod sys {
     define_api! {
         /// Shutdown the whole system and all CPUs.
         pub fn ax_terminate() -> !;
+        /// Returns a random number, e.g. to seed the hash tables.
+        pub fn ax_random() -> u128;
+    }
+
+    define_api! {
+        @cfg "cpufreq";
+        /// Pins the frequency of all the CPUs to the highest supported one
+        /// not above `khz`, e.g. for benchmarking.
+        pub fn ax_cpufreq_pin(khz: u32) -> crate::AxResult;
+        /// Gives the frequency of the CPUs back to the governor.
+        pub fn ax_cpufreq_unpin() -> crate::AxResult;
+        /// Returns the current frequency of the CPUs in kHz, or [`None`] if it
+        /// cannot be scaled.
+        pub fn ax_cpufreq_cur_khz() -> Option<u32>;
+    }
+
+    define_api! {
+        @cfg "snapshot";
+        /// Takes a snapshot of the system to the file of the host at `path`.
+        ///
+        /// Returns `false` once it is written, and `true` when the system is
+        /// resumed from it by a later boot.
+        pub fn ax_snapshot(path: &str) -> crate::AxResult<bool>;
     }
 }
 
@@ -46,6 +69,19 @@ pub mod time {
         pub fn ax_monotonic_time() -> AxTimeValue;
         /// Returns the time elapsed since epoch, also known as realtime.
         pub fn ax_wall_time() -> AxTimeValue;
+        /// Returns the time elapsed since system boot, including the time
+        /// the system was suspended.
+        pub fn ax_boottime() -> AxTimeValue;
+        /// Sets the realtime clock to the given time since epoch.
+        pub fn ax_set_wall_time(time: AxTimeValue);
+        /// Gradually corrects the realtime clock by the given nanoseconds,
+        /// and returns the correction of the previous call not applied yet.
+        pub fn ax_adjust_wall_time(delta_nanos: i64) -> i64;
+        /// Replaces the deadline (in monotonic time) of the current time
+        /// budget, `None` for no limit, and returns the previous one.
+        ///
+        /// Blocking I/O operations fail with `TimedOut` once it has passed.
+        pub fn ax_set_deadline(deadline: Option<AxTimeValue>) -> Option<AxTimeValue>;
     }
 }
 
@@ -112,6 +148,9 @@ pub mod stdio {
         pub fn ax_console_write_bytes(buf: &[u8]) -> crate::AxResult<usize>;
         /// Writes a formatted string to the console.
         pub fn ax_console_write_fmt(args: fmt::Arguments) -> fmt::Result;
+        /// Writes a string to the console, like [`ax_console_write_fmt`]
+        /// without formatting.
+        pub fn ax_console_write_str(s: &str) -> fmt::Result;
     }
 }
 
@@ -121,10 +160,12 @@ pub mod task {
         @cfg "multitask";
         pub type AxTaskHandle;
         pub type AxWaitQueueHandle;
+        pub type AxCpuPlacement;
     }
 
     define_api! {
-        /// Current task is going to sleep, it will be woken up at the given deadline.
+        /// Current task is going to sleep, it will be woken up at the given deadline
+        /// (in monotonic time).
         ///
         /// If the feature `multitask` is not enabled, it uses busy-wait instead
         pub fn ax_sleep_until(deadline: crate::time::AxTimeValue);
@@ -155,6 +196,13 @@ pub mod task {
         pub fn ax_wait_for_exit(task: AxTaskHandle) -> Option<i32>;
         /// Sets the priority of the current task.
         pub fn ax_set_current_priority(prio: isize) -> crate::AxResult;
+        /// Returns the range of the priorities of the scheduler, from the
+        /// highest to the lowest, or [`None`] if it has no priorities.
+        pub fn ax_priority_range() -> Option<(isize, isize)>;
+        /// Returns the name of the scheduler.
+        pub fn ax_scheduler_name() -> &'static str;
+        /// Sets the kind of CPUs (big or little) the current task runs on.
+        pub fn ax_set_current_placement(placement: AxCpuPlacement);
 
         /// Blocks the current task and put it into the wait queue, until the
         /// given condition becomes true, or the the given duration has elapsed
@@ -186,6 +234,7 @@ pub mod fs {
         pub type AxFilePerm;
         pub type AxDirEntry;
         pub type AxSeekFrom;
+        pub type AxFileSystemStat;
         #[cfg(feature = "myfs")]
         pub type AxDisk;
         #[cfg(feature = "myfs")]
@@ -249,6 +298,8 @@ pub mod fs {
         ///
         /// It will delete the original file if `old` already exists.
         pub fn ax_rename(old: &str, new: &str) -> AxResult;
+        /// Returns the usage statistics of the filesystem containing `path`.
+        pub fn ax_statfs(path: &str) -> AxResult<AxFileSystemStat>;
 
         /// Returns the current working directory.
         pub fn ax_current_dir() -> AxResult<alloc::string::String>;
@@ -261,11 +312,13 @@ pub mod fs {
 pub mod net {
     use crate::{io::AxPollState, AxResult};
     use core::net::{IpAddr, SocketAddr};
+    use core::time::Duration;
 
     define_api_type! {
         @cfg "net";
         pub type AxTcpSocketHandle;
         pub type AxUdpSocketHandle;
+        pub type AxTcpKeepAlive;
     }
 
     define_api! {
@@ -281,13 +334,20 @@ pub mod net {
         pub fn ax_tcp_peer_addr(socket: &AxTcpSocketHandle) -> AxResult<SocketAddr>;
         /// Moves this TCP socket into or out of nonblocking mode.
         pub fn ax_tcp_set_nonblocking(socket: &AxTcpSocketHandle, nonblocking: bool) -> AxResult;
+        /// Enables keepalive probes on the TCP socket with the given
+        /// parameters, or disables them with `None`.
+        pub fn ax_tcp_set_keepalive(socket: &AxTcpSocketHandle, keepalive: Option<AxTcpKeepAlive>) -> AxResult;
+        /// Sets the time sent data may stay unacknowledged before the
+        /// connection is aborted, or removes the limit with `None`.
+        pub fn ax_tcp_set_user_timeout(socket: &AxTcpSocketHandle, timeout: Option<Duration>) -> AxResult;
 
         /// Connects the TCP socket to the given address and port.
         pub fn ax_tcp_connect(handle: &AxTcpSocketHandle, addr: SocketAddr) -> AxResult;
         /// Binds the TCP socket to the given address and port.
         pub fn ax_tcp_bind(socket: &AxTcpSocketHandle, addr: SocketAddr) -> AxResult;
-        /// Starts listening on the bound address and port.
-        pub fn ax_tcp_listen(socket: &AxTcpSocketHandle, _backlog: usize) -> AxResult;
+        /// Starts listening on the bound address and port, with at most
+        /// `backlog` established connections waiting to be accepted.
+        pub fn ax_tcp_listen(socket: &AxTcpSocketHandle, backlog: usize) -> AxResult;
         /// Accepts a new connection on the TCP socket.
         ///
         /// This function will block the calling thread until a new TCP connection
@@ -388,17 +448,35 @@ pub mod modules {
 
     #[cfg(feature = "alloc")]
     pub use axalloc;
+    #[cfg(feature = "bench")]
+    pub use axbench;
+    #[cfg(feature = "checksum")]
+    pub use axchecksum;
+    #[cfg(feature = "compress")]
+    pub use axcompress;
     #[cfg(feature = "display")]
     pub use axdisplay;
     #[cfg(feature = "dma")]
     pub use axdma;
-    #[cfg(any(feature = "fs", feature = "net", feature = "display"))]
+    #[cfg(feature = "domains")]
+    pub use axdomain;
+    #[cfg(any(
+        feature = "fs",
+        feature = "net",
+        feature = "display",
+        feature = "ivshmem",
+        feature = "can"
+    ))]
     pub use axdriver;
+    #[cfg(feature = "framing")]
+    pub use axframing;
     #[cfg(feature = "fs")]
     pub use axfs;
+    #[cfg(feature = "handle")]
+    pub use axhandle;
     #[cfg(feature = "paging")]
     pub use axmm;
-    #[cfg(feature = "net")]
+    #[cfg(any(feature = "net", feature = "vsock"))]
     pub use axnet;
     #[cfg(feature = "multitask")]
     pub use axtask;
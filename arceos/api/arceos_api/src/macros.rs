@@ -101,10 +101,18 @@ macro_rules! cfg_net {
     ($($item:item)*) => { _cfg_common!{ "net" $($item)* } }
 }
 
+macro_rules! cfg_cpufreq {
+    ($($item:item)*) => { _cfg_common!{ "cpufreq" $($item)* } }
+}
+
 macro_rules! cfg_display {
     ($($item:item)*) => { _cfg_common!{ "display" $($item)* } }
 }
 
+macro_rules! cfg_snapshot {
+    ($($item:item)*) => { _cfg_common!{ "snapshot" $($item)* } }
+}
+
 macro_rules! cfg_task {
     ($($item:item)*) => { _cfg_common!{ "multitask" $($item)* } }
 }
@@ -37,6 +37,8 @@ typedef struct {{
 
         let allow_types = [
             "stat",
+            "statfs",
+            "statvfs",
             "size_t",
             "ssize_t",
             "off_t",
@@ -60,6 +62,9 @@ typedef struct {{
             "AF_.*",
             "SOCK_.*",
             "IPPROTO_.*",
+            "SOL_.*",
+            "SO_.*",
+            "TCP_.*",
             "FD_.*",
             "F_.*",
             "_SC_.*",
@@ -68,6 +73,7 @@ typedef struct {{
             "RLIMIT_.*",
             "EAI_.*",
             "MAXADDRS",
+            "ST_.*",
         ];
 
         #[derive(Debug)]
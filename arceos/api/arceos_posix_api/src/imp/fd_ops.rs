@@ -2,8 +2,8 @@ use alloc::sync::Arc;
 use core::ffi::c_int;
 
 use axerrno::{LinuxError, LinuxResult};
+use axhandle::{Handle, HandleError, HandleTable, Rights};
 use axio::PollState;
-use flatten_objects::FlattenObjects;
 use spin::RwLock;
 
 use super::stdio::{stdin, stdout};
@@ -21,33 +21,65 @@ pub trait FileLike: Send + Sync {
     fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult;
 }
 
+/// The rights of all the file descriptors, besides reading and writing.
+pub const FD_RIGHTS: Rights = Rights::STAT
+    .union(Rights::CONFIGURE)
+    .union(Rights::DUPLICATE)
+    .union(Rights::TRANSFER);
+
 lazy_static::lazy_static! {
-    static ref FD_TABLE: RwLock<FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>> = {
-        let mut fd_table = FlattenObjects::new();
-        fd_table.add_at(0, Arc::new(stdin()) as _).unwrap(); // stdin
-        fd_table.add_at(1, Arc::new(stdout()) as _).unwrap(); // stdout
-        fd_table.add_at(2, Arc::new(stdout()) as _).unwrap(); // stderr
+    /// The file descriptors are the handles of this table.
+    static ref FD_TABLE: RwLock<HandleTable<dyn FileLike>> = {
+        let mut fd_table = HandleTable::new(AX_FILE_LIMIT);
+        let (read, write) = (FD_RIGHTS | Rights::READ, FD_RIGHTS | Rights::WRITE);
+        fd_table.insert(Arc::new(stdin()) as _, read).unwrap(); // stdin
+        fd_table.insert(Arc::new(stdout()) as _, write).unwrap(); // stdout
+        fd_table.insert(Arc::new(stdout()) as _, write).unwrap(); // stderr
         RwLock::new(fd_table)
     };
 }
 
+/// The errno of the errors of the handle table: a file descriptor without
+/// the rights needed is as bad as an invalid one.
+fn fd_errno(err: HandleError) -> LinuxError {
+    match err {
+        HandleError::BadHandle | HandleError::NoRights => LinuxError::EBADF,
+        HandleError::WrongType => LinuxError::EINVAL,
+        HandleError::TableFull => LinuxError::EMFILE,
+    }
+}
+
+fn fd_handle(fd: c_int) -> LinuxResult<Handle> {
+    usize::try_from(fd)
+        .map(Handle::from_raw)
+        .map_err(|_| LinuxError::EBADF)
+}
+
 pub fn get_file_like(fd: c_int) -> LinuxResult<Arc<dyn FileLike>> {
+    get_file_like_with(fd, Rights::empty())
+}
+
+/// Returns the file of `fd`, if it has all the `rights` (e.g.
+/// [`Rights::READ`] if it was opened for reading).
+pub fn get_file_like_with(fd: c_int, rights: Rights) -> LinuxResult<Arc<dyn FileLike>> {
     FD_TABLE
         .read()
-        .get(fd as usize)
-        .cloned()
-        .ok_or(LinuxError::EBADF)
+        .get(fd_handle(fd)?, rights)
+        .map_err(fd_errno)
 }
 
 pub fn add_file_like(f: Arc<dyn FileLike>) -> LinuxResult<c_int> {
-    Ok(FD_TABLE.write().add(f).ok_or(LinuxError::EMFILE)? as c_int)
+    add_file_like_with(f, Rights::all())
+}
+
+/// Adds the file `f` with the `rights`, and returns its file descriptor.
+pub fn add_file_like_with(f: Arc<dyn FileLike>, rights: Rights) -> LinuxResult<c_int> {
+    let fd = FD_TABLE.write().insert(f, rights).map_err(fd_errno)?;
+    Ok(fd.as_raw() as c_int)
 }
 
 pub fn close_file_like(fd: c_int) -> LinuxResult {
-    let f = FD_TABLE
-        .write()
-        .remove(fd as usize)
-        .ok_or(LinuxError::EBADF)?;
+    let f = FD_TABLE.write().remove(fd_handle(fd)?).map_err(fd_errno)?;
     drop(f);
     Ok(())
 }
@@ -62,9 +94,11 @@ pub fn sys_close(fd: c_int) -> c_int {
 }
 
 fn dup_fd(old_fd: c_int) -> LinuxResult<c_int> {
-    let f = get_file_like(old_fd)?;
-    let new_fd = add_file_like(f)?;
-    Ok(new_fd)
+    let new_fd = FD_TABLE
+        .write()
+        .duplicate(fd_handle(old_fd)?, Rights::all())
+        .map_err(fd_errno)?;
+    Ok(new_fd.as_raw() as c_int)
 }
 
 /// Duplicate a file descriptor.
@@ -75,7 +109,7 @@ pub fn sys_dup(old_fd: c_int) -> c_int {
 
 /// Duplicate a file descriptor, but it uses the file descriptor number specified in `new_fd`.
 ///
-/// TODO: `dup2` should forcibly close new_fd if it is already opened.
+/// The file of `new_fd` is closed if it is already opened.
 pub fn sys_dup2(old_fd: c_int, new_fd: c_int) -> c_int {
     debug!("sys_dup2 <= old_fd: {}, new_fd: {}", old_fd, new_fd);
     syscall_body!(sys_dup2, {
@@ -91,11 +125,11 @@ pub fn sys_dup2(old_fd: c_int, new_fd: c_int) -> c_int {
             return Err(LinuxError::EBADF);
         }
 
-        let f = get_file_like(old_fd)?;
-        FD_TABLE
+        let old = FD_TABLE
             .write()
-            .add_at(new_fd as usize, f)
-            .ok_or(LinuxError::EMFILE)?;
+            .duplicate_to(fd_handle(old_fd)?, fd_handle(new_fd)?, Rights::all())
+            .map_err(fd_errno)?;
+        drop(old);
 
         Ok(new_fd)
     })
@@ -117,7 +151,8 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> c_int {
                 if fd == 0 || fd == 1 || fd == 2 {
                     return Ok(0);
                 }
-                get_file_like(fd)?.set_nonblocking(arg & (ctypes::O_NONBLOCK as usize) > 0)?;
+                get_file_like_with(fd, Rights::CONFIGURE)?
+                    .set_nonblocking(arg & (ctypes::O_NONBLOCK as usize) > 0)?;
                 Ok(0)
             }
             _ => {
@@ -1,12 +1,14 @@
 use alloc::sync::Arc;
-use core::ffi::{c_char, c_int};
+use core::ffi::{c_char, c_int, c_void};
 
 use axerrno::{LinuxError, LinuxResult};
+use axerror::ToErrno;
 use axfs::fops::OpenOptions;
+use axhandle::Rights;
 use axio::{PollState, SeekFrom};
 use axsync::Mutex;
 
-use super::fd_ops::{get_file_like, FileLike};
+use super::fd_ops::{get_file_like, FileLike, FD_RIGHTS};
 use crate::{ctypes, utils::char_ptr_to_str};
 
 pub struct File {
@@ -20,8 +22,8 @@ impl File {
         }
     }
 
-    fn add_to_fd_table(self) -> LinuxResult<c_int> {
-        super::fd_ops::add_file_like(Arc::new(self))
+    fn add_to_fd_table(self, rights: Rights) -> LinuxResult<c_int> {
+        super::fd_ops::add_file_like_with(Arc::new(self), rights)
     }
 
     fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
@@ -34,15 +36,15 @@ impl File {
 
 impl FileLike for File {
     fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
-        Ok(self.inner.lock().read(buf)?)
+        Ok(self.inner.lock().read(buf).to_errno()?)
     }
 
     fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
-        Ok(self.inner.lock().write(buf)?)
+        Ok(self.inner.lock().write(buf).to_errno()?)
     }
 
     fn stat(&self) -> LinuxResult<ctypes::stat> {
-        let metadata = self.inner.lock().get_attr()?;
+        let metadata = self.inner.lock().get_attr().to_errno()?;
         let ty = metadata.file_type() as u8;
         let perm = metadata.perm().bits() as u32;
         let st_mode = ((ty as u32) << 12) | perm;
@@ -102,6 +104,15 @@ fn flags_to_options(flags: c_int, _mode: ctypes::mode_t) -> OpenOptions {
     options
 }
 
+/// Convert open flags to the rights of the file descriptor.
+fn flags_to_rights(flags: c_int) -> Rights {
+    match flags as u32 & 0b11 {
+        ctypes::O_RDONLY => FD_RIGHTS | Rights::READ,
+        ctypes::O_WRONLY => FD_RIGHTS | Rights::WRITE,
+        _ => FD_RIGHTS | Rights::READ | Rights::WRITE,
+    }
+}
+
 /// Open a file by `filename` and insert it into the file descriptor table.
 ///
 /// Return its index in the file table (`fd`). Return `EMFILE` if it already
@@ -111,8 +122,8 @@ pub fn sys_open(filename: *const c_char, flags: c_int, mode: ctypes::mode_t) ->
     debug!("sys_open <= {:?} {:#o} {:#o}", filename, flags, mode);
     syscall_body!(sys_open, {
         let options = flags_to_options(flags, mode);
-        let file = axfs::fops::File::open(filename?, &options)?;
-        File::new(file).add_to_fd_table()
+        let file = axfs::fops::File::open(filename?, &options).to_errno()?;
+        File::new(file).add_to_fd_table(flags_to_rights(flags))
     })
 }
 
@@ -133,6 +144,39 @@ pub fn sys_lseek(fd: c_int, offset: ctypes::off_t, whence: c_int) -> ctypes::off
     })
 }
 
+const FALLOC_FL_KEEP_SIZE: c_int = 1;
+const FALLOC_FL_PUNCH_HOLE: c_int = 2;
+
+/// Manipulate the allocated space of the file `fd` in the range
+/// `offset..offset + len`.
+///
+/// With `mode` 0, the space is preallocated and the file is extended if
+/// needed. `FALLOC_FL_KEEP_SIZE` keeps the file size, and
+/// `FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE` deallocates the range.
+///
+/// Return 0 if success.
+pub fn sys_fallocate(fd: c_int, mode: c_int, offset: ctypes::off_t, len: ctypes::off_t) -> c_int {
+    debug!("sys_fallocate <= {} {:#x} {} {}", fd, mode, offset, len);
+    syscall_body!(sys_fallocate, {
+        if offset < 0 || len <= 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let file = File::from_fd(fd)?;
+        let file = file.inner.lock();
+        let (offset, len) = (offset as u64, len as u64);
+        match mode {
+            0 => file.allocate(offset, len, false).to_errno()?,
+            FALLOC_FL_KEEP_SIZE => file.allocate(offset, len, true).to_errno()?,
+            m if m == FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE => {
+                file.punch_hole(offset, len).to_errno()?
+            }
+            FALLOC_FL_PUNCH_HOLE => return Err(LinuxError::EINVAL), // requires KEEP_SIZE
+            _ => return Err(LinuxError::EOPNOTSUPP),
+        }
+        Ok(0)
+    })
+}
+
 /// Get the file metadata by `path` and write into `buf`.
 ///
 /// Return 0 if success.
@@ -145,7 +189,7 @@ pub unsafe fn sys_stat(path: *const c_char, buf: *mut ctypes::stat) -> c_int {
         }
         let mut options = OpenOptions::new();
         options.read(true);
-        let file = axfs::fops::File::open(path?, &options)?;
+        let file = axfs::fops::File::open(path?, &options).to_errno()?;
         let st = File::new(file).stat()?;
         unsafe { *buf = st };
         Ok(0)
@@ -182,6 +226,81 @@ pub unsafe fn sys_lstat(path: *const c_char, buf: *mut ctypes::stat) -> ctypes::
     })
 }
 
+/// The `f_type` of `statfs` for the filesystem type name.
+fn fs_magic(fs_type: &str) -> u64 {
+    match fs_type {
+        "fatfs" => 0x4d44,     // MSDOS_SUPER_MAGIC
+        "ramfs" => 0x858458f6, // RAMFS_MAGIC
+        "devfs" => 0x1373,     // DEVFS_SUPER_MAGIC
+        "rofs" => 0x5346_5241, // "ARFS"
+        _ => 0,
+    }
+}
+
+/// Get the statistics of the filesystem containing `path` and write into
+/// `buf`.
+///
+/// Return 0 if success.
+pub unsafe fn sys_statfs(path: *const c_char, buf: *mut ctypes::statfs) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!("sys_statfs <= {:?} {:#x}", path, buf as usize);
+    syscall_body!(sys_statfs, {
+        if buf.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let st = axfs::api::statfs(path?).to_errno()?;
+        let flags = if st.read_only { ctypes::ST_RDONLY } else { 0 };
+        unsafe {
+            *buf = ctypes::statfs {
+                f_type: fs_magic(st.fs_type) as _,
+                f_bsize: st.block_size as _,
+                f_blocks: st.blocks,
+                f_bfree: st.blocks_free,
+                f_bavail: st.blocks_avail,
+                f_files: st.files,
+                f_ffree: st.files_free,
+                f_namelen: st.name_max as _,
+                f_frsize: st.block_size as _,
+                f_flags: flags as _,
+                ..Default::default()
+            }
+        };
+        Ok(0)
+    })
+}
+
+/// Get the statistics of the filesystem containing `path` in the POSIX
+/// format and write into `buf`.
+///
+/// Return 0 if success.
+pub unsafe fn sys_statvfs(path: *const c_char, buf: *mut ctypes::statvfs) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!("sys_statvfs <= {:?} {:#x}", path, buf as usize);
+    syscall_body!(sys_statvfs, {
+        if buf.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let st = axfs::api::statfs(path?).to_errno()?;
+        let flags = if st.read_only { ctypes::ST_RDONLY } else { 0 };
+        unsafe {
+            *buf = ctypes::statvfs {
+                f_bsize: st.block_size as _,
+                f_frsize: st.block_size as _,
+                f_blocks: st.blocks,
+                f_bfree: st.blocks_free,
+                f_bavail: st.blocks_avail,
+                f_files: st.files,
+                f_ffree: st.files_free,
+                f_favail: st.files_free,
+                f_flag: flags as _,
+                f_namemax: st.name_max as _,
+                ..Default::default()
+            }
+        };
+        Ok(0)
+    })
+}
+
 /// Get the path of the current directory.
 pub fn sys_getcwd(buf: *mut c_char, size: usize) -> *mut c_char {
     debug!("sys_getcwd <= {:#x} {}", buf as usize, size);
@@ -190,7 +309,11 @@ pub fn sys_getcwd(buf: *mut c_char, size: usize) -> *mut c_char {
             return Ok(core::ptr::null::<c_char>() as _);
         }
         let dst = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, size as _) };
-        let cwd = axfs::api::current_dir()?;
+        let cwd = axfs::api::current_dir().to_errno()?;
+        let cwd = match cwd.trim_end_matches('/') {
+            "" => "/",
+            cwd => cwd, // no trailing '/'
+        };
         let cwd = cwd.as_bytes();
         if cwd.len() < size {
             dst[..cwd.len()].copy_from_slice(cwd);
@@ -202,6 +325,30 @@ pub fn sys_getcwd(buf: *mut c_char, size: usize) -> *mut c_char {
     })
 }
 
+/// Change the current working directory to `path`.
+///
+/// Return 0 if success.
+pub fn sys_chdir(path: *const c_char) -> c_int {
+    syscall_body!(sys_chdir, {
+        let path = char_ptr_to_str(path)?;
+        debug!("sys_chdir <= {:?}", path);
+        axfs::api::set_current_dir(path).to_errno()?;
+        Ok(0)
+    })
+}
+
+/// Change the root directory to `path`.
+///
+/// Return 0 if success.
+pub fn sys_chroot(path: *const c_char) -> c_int {
+    syscall_body!(sys_chroot, {
+        let path = char_ptr_to_str(path)?;
+        debug!("sys_chroot <= {:?}", path);
+        axfs::api::chroot(path).to_errno()?;
+        Ok(0)
+    })
+}
+
 /// Rename `old` to `new`
 /// If new exists, it is first removed.
 ///
@@ -211,7 +358,122 @@ pub fn sys_rename(old: *const c_char, new: *const c_char) -> c_int {
         let old_path = char_ptr_to_str(old)?;
         let new_path = char_ptr_to_str(new)?;
         debug!("sys_rename <= old: {:?}, new: {:?}", old_path, new_path);
-        axfs::api::rename(old_path, new_path)?;
+        axfs::api::rename(old_path, new_path).to_errno()?;
+        Ok(0)
+    })
+}
+
+const XATTR_CREATE: c_int = 1;
+const XATTR_REPLACE: c_int = 2;
+
+/// Copy `data` to the user buffer `buf` of `size` bytes.
+///
+/// Return the length of `data` if `size` is 0, without copying.
+fn copy_to_user_buf(data: &[u8], buf: *mut c_void, size: usize) -> LinuxResult<usize> {
+    if size == 0 {
+        return Ok(data.len());
+    }
+    if data.len() > size {
+        return Err(LinuxError::ERANGE);
+    }
+    if buf.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+    let dst = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, data.len()) };
+    dst.copy_from_slice(data);
+    Ok(data.len())
+}
+
+/// Map a missing attribute of an existing file to `ENODATA`.
+fn xattr_err(path: &str, err: axerrno::AxError) -> LinuxError {
+    match err {
+        axerrno::AxError::NotFound if axfs::api::metadata(path).is_ok() => LinuxError::ENODATA,
+        e => axerror::errno(e),
+    }
+}
+
+/// Get the value of the extended attribute `name` of the file at `path`.
+///
+/// Return the size of the value. If `size` is 0, only the size is returned.
+pub fn sys_getxattr(
+    path: *const c_char,
+    name: *const c_char,
+    value: *mut c_void,
+    size: usize,
+) -> ctypes::ssize_t {
+    syscall_body!(sys_getxattr, {
+        let path = char_ptr_to_str(path)?;
+        let name = char_ptr_to_str(name)?;
+        debug!("sys_getxattr <= {:?} {:?} {}", path, name, size);
+        let data = axfs::xattr::get(path, name).map_err(|e| xattr_err(path, e))?;
+        copy_to_user_buf(&data, value, size)
+    })
+}
+
+/// Set the value of the extended attribute `name` of the file at `path`.
+///
+/// Return 0 if success.
+pub fn sys_setxattr(
+    path: *const c_char,
+    name: *const c_char,
+    value: *const c_void,
+    size: usize,
+    flags: c_int,
+) -> c_int {
+    syscall_body!(sys_setxattr, {
+        let path = char_ptr_to_str(path)?;
+        let name = char_ptr_to_str(name)?;
+        debug!(
+            "sys_setxattr <= {:?} {:?} {} {:#x}",
+            path, name, size, flags
+        );
+        let mode = match flags {
+            0 => axfs::xattr::SetMode::Any,
+            XATTR_CREATE => axfs::xattr::SetMode::Create,
+            XATTR_REPLACE => axfs::xattr::SetMode::Replace,
+            _ => return Err(LinuxError::EINVAL),
+        };
+        if size > axfs::xattr::MAX_VALUE_SIZE {
+            return Err(LinuxError::E2BIG);
+        }
+        let value = if size == 0 {
+            &[][..]
+        } else if value.is_null() {
+            return Err(LinuxError::EFAULT);
+        } else {
+            unsafe { core::slice::from_raw_parts(value as *const u8, size) }
+        };
+        axfs::xattr::set(path, name, value, mode).map_err(|e| xattr_err(path, e))?;
+        Ok(0)
+    })
+}
+
+/// List the names of the extended attributes of the file at `path`, as a
+/// sequence of NUL-terminated strings.
+///
+/// Return the size of the list. If `size` is 0, only the size is returned.
+pub fn sys_listxattr(path: *const c_char, list: *mut c_char, size: usize) -> ctypes::ssize_t {
+    syscall_body!(sys_listxattr, {
+        let path = char_ptr_to_str(path)?;
+        debug!("sys_listxattr <= {:?} {}", path, size);
+        let mut names = alloc::vec::Vec::new();
+        for name in axfs::xattr::list(path).to_errno()? {
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        copy_to_user_buf(&names, list as *mut c_void, size)
+    })
+}
+
+/// Remove the extended attribute `name` of the file at `path`.
+///
+/// Return 0 if success.
+pub fn sys_removexattr(path: *const c_char, name: *const c_char) -> c_int {
+    syscall_body!(sys_removexattr, {
+        let path = char_ptr_to_str(path)?;
+        let name = char_ptr_to_str(name)?;
+        debug!("sys_removexattr <= {:?} {:?}", path, name);
+        axfs::xattr::remove(path, name).map_err(|e| xattr_err(path, e))?;
         Ok(0)
     })
 }
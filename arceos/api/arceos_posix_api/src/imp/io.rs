@@ -3,7 +3,9 @@ use axerrno::LinuxError;
 use core::ffi::{c_int, c_void};
 
 #[cfg(feature = "fd")]
-use crate::imp::fd_ops::get_file_like;
+use crate::imp::fd_ops::get_file_like_with;
+#[cfg(feature = "fd")]
+use axhandle::Rights;
 #[cfg(not(feature = "fd"))]
 use axio::prelude::*;
 
@@ -19,7 +21,7 @@ pub fn sys_read(fd: c_int, buf: *mut c_void, count: usize) -> ctypes::ssize_t {
         let dst = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, count) };
         #[cfg(feature = "fd")]
         {
-            Ok(get_file_like(fd)?.read(dst)? as ctypes::ssize_t)
+            Ok(get_file_like_with(fd, Rights::READ)?.read(dst)? as ctypes::ssize_t)
         }
         #[cfg(not(feature = "fd"))]
         match fd {
@@ -42,7 +44,7 @@ pub fn sys_write(fd: c_int, buf: *const c_void, count: usize) -> ctypes::ssize_t
         let src = unsafe { core::slice::from_raw_parts(buf as *const u8, count) };
         #[cfg(feature = "fd")]
         {
-            Ok(get_file_like(fd)?.write(src)? as ctypes::ssize_t)
+            Ok(get_file_like_with(fd, Rights::WRITE)?.write(src)? as ctypes::ssize_t)
         }
         #[cfg(not(feature = "fd"))]
         match fd {
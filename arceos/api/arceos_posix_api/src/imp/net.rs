@@ -2,6 +2,7 @@ use alloc::{sync::Arc, vec, vec::Vec};
 use core::ffi::{c_char, c_int, c_void};
 use core::mem::size_of;
 use core::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
+use core::time::Duration;
 
 use axerrno::{LinuxError, LinuxResult};
 use axio::PollState;
@@ -97,10 +98,14 @@ impl Socket {
         }
     }
 
-    fn listen(&self) -> LinuxResult {
+    fn listen(&self, backlog: usize) -> LinuxResult {
         match self {
             Socket::Udp(_) => Err(LinuxError::EOPNOTSUPP),
-            Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().listen()?),
+            Socket::Tcp(tcpsocket) => {
+                let tcpsocket = tcpsocket.lock();
+                tcpsocket.set_backlog(backlog);
+                Ok(tcpsocket.listen()?)
+            }
         }
     }
 
@@ -111,6 +116,91 @@ impl Socket {
         }
     }
 
+    fn tcp(&self) -> LinuxResult<&Mutex<TcpSocket>> {
+        match self {
+            Socket::Udp(_) => Err(LinuxError::ENOPROTOOPT),
+            Socket::Tcp(tcpsocket) => Ok(tcpsocket),
+        }
+    }
+
+    fn set_option(&self, level: u32, name: u32, value: c_int) -> LinuxResult {
+        match (level, name) {
+            // local ports are reusable as soon as they are closed, broadcasts
+            // are always allowed, and the buffers have a fixed size
+            (
+                ctypes::SOL_SOCKET,
+                ctypes::SO_REUSEADDR | ctypes::SO_BROADCAST | ctypes::SO_SNDBUF | ctypes::SO_RCVBUF,
+            ) => Ok(()),
+            (ctypes::SOL_SOCKET, ctypes::SO_KEEPALIVE) => {
+                let tcpsocket = self.tcp()?.lock();
+                let keepalive = (value != 0).then(|| tcpsocket.keepalive().unwrap_or_default());
+                Ok(tcpsocket.set_keepalive(keepalive)?)
+            }
+            (ctypes::IPPROTO_TCP, ctypes::TCP_NODELAY) => {
+                self.tcp()?.lock().set_nodelay(value != 0);
+                Ok(())
+            }
+            (
+                ctypes::IPPROTO_TCP,
+                ctypes::TCP_KEEPIDLE | ctypes::TCP_KEEPINTVL | ctypes::TCP_KEEPCNT,
+            ) => {
+                if value <= 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                let tcpsocket = self.tcp()?.lock();
+                // the parameters only apply to a socket with SO_KEEPALIVE
+                let Some(mut keepalive) = tcpsocket.keepalive() else {
+                    return Ok(());
+                };
+                let secs = Duration::from_secs(value as u64);
+                match name {
+                    ctypes::TCP_KEEPIDLE => keepalive.idle = secs,
+                    ctypes::TCP_KEEPINTVL => keepalive.interval = secs,
+                    _ => keepalive.count = value as u32,
+                }
+                Ok(tcpsocket.set_keepalive(Some(keepalive))?)
+            }
+            (ctypes::IPPROTO_TCP, ctypes::TCP_USER_TIMEOUT) => {
+                let timeout = (value > 0).then(|| Duration::from_millis(value as u64));
+                Ok(self.tcp()?.lock().set_user_timeout(timeout)?)
+            }
+            _ => Err(LinuxError::ENOPROTOOPT),
+        }
+    }
+
+    fn get_option(&self, level: u32, name: u32) -> LinuxResult<c_int> {
+        match (level, name) {
+            (ctypes::SOL_SOCKET, ctypes::SO_TYPE) => Ok(match self {
+                Socket::Udp(_) => ctypes::SOCK_DGRAM as _,
+                Socket::Tcp(_) => ctypes::SOCK_STREAM as _,
+            }),
+            // errors are returned by the operations themselves
+            (ctypes::SOL_SOCKET, ctypes::SO_ERROR) => Ok(0),
+            (ctypes::SOL_SOCKET, ctypes::SO_REUSEADDR) => Ok(0),
+            (ctypes::SOL_SOCKET, ctypes::SO_KEEPALIVE) => {
+                Ok(self.tcp()?.lock().keepalive().is_some() as _)
+            }
+            (ctypes::IPPROTO_TCP, ctypes::TCP_NODELAY) => Ok(self.tcp()?.lock().nodelay() as _),
+            (
+                ctypes::IPPROTO_TCP,
+                ctypes::TCP_KEEPIDLE | ctypes::TCP_KEEPINTVL | ctypes::TCP_KEEPCNT,
+            ) => {
+                let keepalive = self.tcp()?.lock().keepalive().unwrap_or_default();
+                Ok(match name {
+                    ctypes::TCP_KEEPIDLE => keepalive.idle.as_secs() as _,
+                    ctypes::TCP_KEEPINTVL => keepalive.interval.as_secs() as _,
+                    _ => keepalive.count as _,
+                })
+            }
+            (ctypes::IPPROTO_TCP, ctypes::TCP_USER_TIMEOUT) => Ok(self
+                .tcp()?
+                .lock()
+                .user_timeout()
+                .map_or(0, |t| t.as_millis() as _)),
+            _ => Err(LinuxError::ENOPROTOOPT),
+        }
+    }
+
     fn shutdown(&self) -> LinuxResult {
         match self {
             Socket::Udp(udpsocket) => {
@@ -389,13 +479,10 @@ pub fn sys_recv(
 /// Listen for connections on a socket
 ///
 /// Return 0 if success.
-pub fn sys_listen(
-    socket_fd: c_int,
-    backlog: c_int, // currently not used
-) -> c_int {
+pub fn sys_listen(socket_fd: c_int, backlog: c_int) -> c_int {
     debug!("sys_listen <= {} {}", socket_fd, backlog);
     syscall_body!(sys_listen, {
-        Socket::from_fd(socket_fd)?.listen()?;
+        Socket::from_fd(socket_fd)?.listen(backlog.max(0) as usize)?;
         Ok(0)
     })
 }
@@ -441,16 +528,82 @@ pub fn sys_shutdown(
     })
 }
 
+/// Set an option of a socket.
+///
+/// Only integer options are supported: `SO_KEEPALIVE`, `TCP_NODELAY`,
+/// `TCP_KEEPIDLE`, `TCP_KEEPINTVL`, `TCP_KEEPCNT` and `TCP_USER_TIMEOUT`.
+/// `SO_REUSEADDR`, `SO_BROADCAST`, `SO_SNDBUF` and `SO_RCVBUF` are accepted
+/// and have no effect.
+///
+/// Return 0 if success.
+pub unsafe fn sys_setsockopt(
+    socket_fd: c_int,
+    level: c_int,
+    optname: c_int,
+    optval: *const c_void,
+    optlen: ctypes::socklen_t,
+) -> c_int {
+    debug!(
+        "sys_setsockopt <= {} {} {} {:#x} {}",
+        socket_fd, level, optname, optval as usize, optlen
+    );
+    syscall_body!(sys_setsockopt, {
+        if optval.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        if optlen < size_of::<c_int>() as _ {
+            return Err(LinuxError::EINVAL);
+        }
+        let value = unsafe { (optval as *const c_int).read_unaligned() };
+        Socket::from_fd(socket_fd)?.set_option(level as _, optname as _, value)?;
+        Ok(0)
+    })
+}
+
+/// Get an option of a socket.
+///
+/// Besides the options of [`sys_setsockopt`], `SO_TYPE` and `SO_ERROR` are
+/// supported.
+///
+/// Return 0 if success.
+pub unsafe fn sys_getsockopt(
+    socket_fd: c_int,
+    level: c_int,
+    optname: c_int,
+    optval: *mut c_void,
+    optlen: *mut ctypes::socklen_t,
+) -> c_int {
+    debug!(
+        "sys_getsockopt <= {} {} {} {:#x} {:#x}",
+        socket_fd, level, optname, optval as usize, optlen as usize
+    );
+    syscall_body!(sys_getsockopt, {
+        if optval.is_null() || optlen.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        if unsafe { *optlen } < size_of::<c_int>() as _ {
+            return Err(LinuxError::EINVAL);
+        }
+        let value = Socket::from_fd(socket_fd)?.get_option(level as _, optname as _)?;
+        unsafe {
+            (optval as *mut c_int).write_unaligned(value);
+            *optlen = size_of::<c_int>() as _;
+        }
+        Ok(0)
+    })
+}
+
 /// Query addresses for a domain name.
 ///
-/// Only IPv4. Ports are always 0. Ignore servname and hint.
-/// Results' ai_flags and ai_canonname are 0 or NULL.
+/// Only IPv4. The port is taken from a numeric servname, or is 0. Only the
+/// socket type of the hints is used, and the results are for TCP unless it
+/// is `SOCK_DGRAM`. Results' ai_flags and ai_canonname are 0 or NULL.
 ///
 /// Return address number if success.
 pub unsafe fn sys_getaddrinfo(
     nodename: *const c_char,
     servname: *const c_char,
-    _hints: *const ctypes::addrinfo,
+    hints: *const ctypes::addrinfo,
     res: *mut *mut ctypes::addrinfo,
 ) -> c_int {
     let name = char_ptr_to_str(nodename);
@@ -465,6 +618,12 @@ pub unsafe fn sys_getaddrinfo(
         }
 
         let port = port.map_or(0, |p| p.parse::<u16>().unwrap_or(0));
+        let (socktype, protocol) = match unsafe { hints.as_ref() } {
+            Some(hints) if hints.ai_socktype == ctypes::SOCK_DGRAM as _ => {
+                (ctypes::SOCK_DGRAM, ctypes::IPPROTO_UDP)
+            }
+            _ => (ctypes::SOCK_STREAM, ctypes::IPPROTO_TCP),
+        };
         let ip_addrs = if let Ok(domain) = name {
             if let Ok(a) = domain.parse::<IpAddr>() {
                 vec![a]
@@ -486,9 +645,8 @@ pub unsafe fn sys_getaddrinfo(
                 IpAddr::V4(ip) => ctypes::aibuf {
                     ai: ctypes::addrinfo {
                         ai_family: ctypes::AF_INET as _,
-                        // TODO: This is a hard-code part, only return TCP parameters
-                        ai_socktype: ctypes::SOCK_STREAM as _,
-                        ai_protocol: ctypes::IPPROTO_TCP as _,
+                        ai_socktype: socktype as _,
+                        ai_protocol: protocol as _,
                         ai_addrlen: size_of::<ctypes::sockaddr_in>() as _,
                         ai_addr: core::ptr::null_mut(),
                         ai_canonname: core::ptr::null_mut(),
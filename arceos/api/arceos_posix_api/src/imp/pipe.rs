@@ -2,10 +2,11 @@ use alloc::sync::Arc;
 use core::ffi::c_int;
 
 use axerrno::{LinuxError, LinuxResult};
+use axhandle::Rights;
 use axio::PollState;
 use axsync::Mutex;
 
-use super::fd_ops::{add_file_like, close_file_like, FileLike};
+use super::fd_ops::{add_file_like_with, close_file_like, FileLike, FD_RIGHTS};
 use crate::ctypes;
 
 #[derive(Copy, Clone, PartialEq)]
@@ -201,10 +202,11 @@ pub fn sys_pipe(fds: &mut [c_int]) -> c_int {
         }
 
         let (read_end, write_end) = Pipe::new();
-        let read_fd = add_file_like(Arc::new(read_end))?;
-        let write_fd = add_file_like(Arc::new(write_end)).inspect_err(|_| {
-            close_file_like(read_fd).ok();
-        })?;
+        let read_fd = add_file_like_with(Arc::new(read_end), FD_RIGHTS | Rights::READ)?;
+        let write_fd = add_file_like_with(Arc::new(write_end), FD_RIGHTS | Rights::WRITE)
+            .inspect_err(|_| {
+                close_file_like(read_fd).ok();
+            })?;
 
         fds[0] = read_fd as c_int;
         fds[1] = write_fd as c_int;
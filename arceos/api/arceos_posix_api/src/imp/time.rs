@@ -2,8 +2,13 @@ use axerrno::LinuxError;
 use core::ffi::{c_int, c_long};
 use core::time::Duration;
 
+use axhal::time::ClockId;
+
 use crate::ctypes;
-use crate::ctypes::{CLOCK_MONOTONIC, CLOCK_REALTIME};
+use crate::ctypes::{
+    CLOCK_BOOTTIME, CLOCK_MONOTONIC, CLOCK_MONOTONIC_COARSE, CLOCK_MONOTONIC_RAW, CLOCK_REALTIME,
+    CLOCK_REALTIME_COARSE,
+};
 
 impl From<ctypes::timespec> for Duration {
     fn from(ts: ctypes::timespec) -> Self {
@@ -35,26 +40,99 @@ impl From<Duration> for ctypes::timeval {
     }
 }
 
-/// Get clock time since booting
+fn clock_id(clk: ctypes::clockid_t) -> Option<ClockId> {
+    match clk as u32 {
+        CLOCK_REALTIME | CLOCK_REALTIME_COARSE => Some(ClockId::Realtime),
+        CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_MONOTONIC_COARSE => Some(ClockId::Monotonic),
+        CLOCK_BOOTTIME => Some(ClockId::Boottime),
+        _ => None,
+    }
+}
+
+/// Get the time of the clock `clk`
 pub unsafe fn sys_clock_gettime(clk: ctypes::clockid_t, ts: *mut ctypes::timespec) -> c_int {
     syscall_body!(sys_clock_gettime, {
         if ts.is_null() {
             return Err(LinuxError::EFAULT);
         }
-        let now = match clk as u32 {
-            CLOCK_REALTIME => axhal::time::wall_time().into(),
-            CLOCK_MONOTONIC => axhal::time::monotonic_time().into(),
-            _ => {
-                warn!("Called sys_clock_gettime for unsupported clock {}", clk);
-                return Err(LinuxError::EINVAL);
-            }
+        let Some(clock) = clock_id(clk) else {
+            warn!("Called sys_clock_gettime for unsupported clock {}", clk);
+            return Err(LinuxError::EINVAL);
         };
+        let now = axhal::time::clock_time(clock).into();
         unsafe { *ts = now };
         debug!("sys_clock_gettime: {}.{:09}s", now.tv_sec, now.tv_nsec);
         Ok(0)
     })
 }
 
+/// Set the time of the clock `clk`, which must be `CLOCK_REALTIME`
+pub unsafe fn sys_clock_settime(clk: ctypes::clockid_t, ts: *const ctypes::timespec) -> c_int {
+    syscall_body!(sys_clock_settime, {
+        if ts.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let ts = unsafe { *ts };
+        debug!(
+            "sys_clock_settime <= {} {}.{:09}s",
+            clk, ts.tv_sec, ts.tv_nsec
+        );
+        match clock_id(clk) {
+            Some(ClockId::Realtime) => {}
+            Some(_) => return Err(LinuxError::EPERM),
+            None => return Err(LinuxError::EINVAL),
+        }
+        if ts.tv_sec < 0 || ts.tv_nsec < 0 || ts.tv_nsec > 999999999 {
+            return Err(LinuxError::EINVAL);
+        }
+        axhal::time::set_wall_time(ts.into());
+        Ok(0)
+    })
+}
+
+/// Get the resolution of the clock `clk`
+pub unsafe fn sys_clock_getres(clk: ctypes::clockid_t, res: *mut ctypes::timespec) -> c_int {
+    syscall_body!(sys_clock_getres, {
+        if clock_id(clk).is_none() {
+            return Err(LinuxError::EINVAL);
+        }
+        if !res.is_null() {
+            unsafe { *res = axhal::time::clock_resolution().into() };
+        }
+        Ok(0)
+    })
+}
+
+/// Gradually correct the realtime clock by `delta`, and return the
+/// correction remaining from a previous call in `olddelta`
+pub unsafe fn sys_adjtime(delta: *const ctypes::timeval, olddelta: *mut ctypes::timeval) -> c_int {
+    syscall_body!(sys_adjtime, {
+        let old = if delta.is_null() {
+            axhal::time::wall_time_adjustment()
+        } else {
+            let delta = unsafe { *delta };
+            if delta.tv_usec <= -1_000_000 || delta.tv_usec >= 1_000_000 {
+                return Err(LinuxError::EINVAL);
+            }
+            let nanos = (delta.tv_sec as i64)
+                .checked_mul(1_000_000_000)
+                .and_then(|n| n.checked_add(delta.tv_usec as i64 * 1000))
+                .ok_or(LinuxError::EINVAL)?;
+            axhal::time::adjust_wall_time(nanos)
+        };
+        if !olddelta.is_null() {
+            let micros = old / 1000;
+            unsafe {
+                *olddelta = ctypes::timeval {
+                    tv_sec: (micros / 1_000_000) as _,
+                    tv_usec: (micros % 1_000_000) as _,
+                }
+            };
+        }
+        Ok(0)
+    })
+}
+
 /// Sleep some nanoseconds
 ///
 /// TODO: should be woken by signals, and set errno
@@ -5,35 +5,84 @@
 //! - CPU
 //!     - `smp`: Enable SMP (symmetric multiprocessing) support.
 //!     - `fp_simd`: Enable floating point and SIMD support.
+//!     - `fp-lazy`: Switch the FP/SIMD registers at the first FP instruction of a task (x86_64, RISC-V).
 //! - Interrupts:
 //!     - `irq`: Enable interrupt handling support.
 //! - Memory
 //!     - `alloc`: Enable dynamic memory allocation.
-//!     - `alloc-tlsf`: Use the TLSF allocator.
+//!     - `alloc-tlsf`: Use the TLSF allocator, with O(1) allocation and deallocation.
+//!     - `alloc-tlsf-intree`: Use the in-tree TLSF allocator (`tlsf_allocator`) instead.
 //!     - `alloc-slab`: Use the slab allocator.
 //!     - `alloc-buddy`: Use the buddy system allocator.
+//!     - `alloc-trace`: Call a hook on every allocation and deallocation (`axalloc::set_trace_hook`).
+//!     - `alloc-leak`: Record the live allocations with their callers, and print the ones never freed with `axalloc::dump_leaks`.
+//!     - `alloc-percpu-cache`: Allocate the small blocks from per-CPU caches, to scale on SMP.
+//!     - `alloc-debug`: Check the heap blocks for overflows and mismatched frees, and poison the freed ones.
 //!     - `paging`: Enable page table manipulation.
 //!     - `tls`: Enable thread-local storage.
+//!     - `page-scrub`: Pre-zero free pages in a background task for faster page faults.
+//!     - `hotplug`: Add and remove RAM at runtime (`axmm::add_memory`).
+//!     - `fast-mem`: Use `memcpy`, `memset`... optimized for the CPU (ERMS, NEON, RISC-V V).
 //! - Task management
 //!     - `multitask`: Enable multi-threading support.
 //!     - `sched_fifo`: Use the FIFO cooperative scheduler.
 //!     - `sched_rr`: Use the Round-robin preemptive scheduler.
 //!     - `sched_cfs`: Use the Completely Fair Scheduler (CFS) preemptive scheduler.
+//!     - `task-sanitizer`: Log the long windows with preemption disabled, and the tasks blocking in them.
+//!     - `domains`: Run groups of tasks in isolation domains, with their own address spaces and heaps (RISC-V).
 //! - Upperlayer stacks (fs, net, display)
 //!     - `fs`: Enable file system support.
 //!     - `myfs`: Allow users to define their custom filesystems to override the default.
+//!     - `rofs`: Use a compressed read-only filesystem image as the root filesystem.
+//!     - `initramfs`: Use a RAM filesystem unpacked from an embedded CPIO archive as the root filesystem.
+//!     - `tmpfs`: Mount a sparse in-memory filesystem with hole punching on `/tmp`.
+//!     - `fs-crypt`: Encrypt the block device with XTS-AES (key given by `DISK_KEY`).
+//!     - `measured-boot`: Measure the initramfs and check its signature (if any) before using it.
+//!     - `secure-boot`: Refuse to boot unless the initramfs is signed by `SECURE_BOOT_KEY`.
 //!     - `net`: Enable networking support.
+//!     - `sntp`: Synchronize the realtime clock with an SNTP server.
+//!     - `mdns`: Announce the host and its services by mDNS.
+//!     - `syslog`: Send the kernel logs to the syslog server set by `syslog=` on the command line.
+//!     - `net-wireguard`: WireGuard tunnel interface in the network stack.
+//!     - `net-ppp`: PPPoE client interface in the network stack.
+//!     - `net-tun`: TAP interface `tap0`, with `/dev/net/tun` to exchange its frames (with `fs`).
+//!     - `net-quic`: QUIC connections over UDP, with their own TLS 1.3 handshake.
+//!     - `vsock`: Stream sockets to the host over VirtIO vsock, without IP networking.
 //!     - `display`: Enable graphics support.
 //! - Device drivers
 //!     - `bus-mmio`: Use device tree to probe all MMIO devices.
 //!     - `bus-pci`: Use PCI bus to probe all PCI devices.
 //!     - `driver-ramdisk`: Use the RAM disk to emulate the block device.
+//!     - `driver-zram`: Use a compressed RAM disk (zram) to emulate the block device.
 //!     - `driver-ixgbe`: Enable the Intel 82599 10Gbit NIC driver.
+//!     - `driver-hosted-blk`: Use a file of the host as the block device (`hosted.disk=` on the command line, with `hosted`).
+//!     - `driver-hosted-net`: Use a TAP interface of the host as the NIC (`hosted.tap=` on the command line, with `hosted`).
 //!     - `driver-bcm2835-sdhci`: Enable the BCM2835 SDHCI driver (Raspberry Pi SD card).
+//!     - `driver-virtio-balloon`: Enable the VirtIO memory balloon, so the host can reclaim memory.
+//!     - `driver-virtio-crypto`: Offload AES to VirtIO crypto devices through `axcrypto`.
+//!     - `driver-ivshmem`: Exchange messages with co-located VMs over inter-VM shared memory (ivshmem).
+//!     - `driver-can`: Send and receive CAN frames with filters, on SJA1000 (`kvaser_pci` of QEMU) or virtual controllers.
+//!     - `driver-thermal`: Monitor the temperature sensors and throttle background tasks when too hot.
+//!     - `driver-cpufreq`: Scale the CPU frequency with a governor, and allow pinning it.
+//!     - `parallel-probe`: Probe the global drivers (e.g. SD/eMMC) concurrently on the secondary CPUs.
+//!     - `deferred-probe`: Probe the drivers in `DEFERRED_DRIVERS` after the application starts.
 //! - Logging
 //!     - `log-level-off`: Disable all logging.
 //!     - `log-level-error`, `log-level-warn`, `log-level-info`, `log-level-debug`,
 //!       `log-level-trace`: Keep logging only at the specified level or higher.
+//!     - `diag-shell`: Run a diagnostic shell (`ps`, `free`, `dmesg`, ...) on the console.
+//!     - `symbols`: Print a symbolized backtrace on panic (with the symbol table embedded by `KSYMS=y`).
+//!     - `tiny-fmt`: Print the boot banner and the panic locations without the formatting machinery (with `MODE=small`).
+//!     - `boot-time`: Print the time spent in each init phase and probing each driver before `main`.
+//!     - `replay`: Record the external inputs to a log and replay them (with `REPLAY=record|replay`).
+//!     - `snapshot`: Take snapshots of the system to files, and restore them at boot (with `SNAPSHOT_RESTORE`).
+//!     - `unwind`: Unwind the panics, so that they can be caught and a panicking task only exits (with `UNWIND=y`).
+//! - Platform
+//!     - `hosted`: Run as a process of a x86_64 Linux host, to test with its sanitizers (built for the target of the host).
+//! - Hardening (set by the `STACK_PROTECTOR`, `SHADOW_CALL_STACK` and `PAC` make options)
+//!     - `stack-protector`: Randomize the stack canary, and report the task smashing its stack.
+//!     - `shadow-call-stack`: Keep return addresses on separate shadow call stacks (AArch64).
+//!     - `pointer-auth`: Enable the pointer authentication of return addresses (AArch64).
 //!
 //! [ArceOS]: https://github.com/arceos-org/arceos
 
@@ -0,0 +1,119 @@
+//! Runs random interleavings of allocations and deallocations on the
+//! allocators, in the kernel, checking their invariants after every
+//! operation (see the `allocator_stress` crate).
+//!
+//! Each round is given by its seed, printed before it runs, so that a failing
+//! one can be run again alone by setting `FIRST_SEED` to it.
+
+#![cfg_attr(feature = "axstd", no_std)]
+#![cfg_attr(feature = "axstd", no_main)]
+
+#[macro_use]
+#[cfg(feature = "axstd")]
+extern crate axstd as std;
+extern crate alloc;
+
+use alloc::alloc::{alloc_zeroed, dealloc};
+use core::alloc::Layout;
+use std::sync::Mutex;
+
+use allocator::BaseAllocator;
+use allocator_stress::{Checker, Rng};
+use bitmap_page_allocator::BitmapPageAllocator;
+use buddy_allocator::BuddyPageAllocator;
+use bump_allocator::EarlyAllocator;
+use tlsf_allocator::TlsfByteAllocator;
+
+const PAGE_SIZE: usize = 0x1000;
+const ARENA_SIZE: usize = 64 * PAGE_SIZE;
+/// Aligned to its size, for the buddy allocator to have its largest blocks.
+const ARENA_ALIGN: usize = 0x40000;
+
+const FIRST_SEED: u64 = 1;
+const NUM_ROUNDS: u64 = 50;
+const NUM_OPS: usize = 1000;
+const MAX_SIZE: usize = 3 * PAGE_SIZE;
+const MAX_PAGES: usize = 8;
+
+/// Too large for the stack of the main task.
+static BITMAP: Mutex<BitmapPageAllocator<PAGE_SIZE>> = Mutex::new(BitmapPageAllocator::new());
+
+/// Runs `f` on a zeroed arena for the allocator, given its start.
+fn with_arena(f: impl FnOnce(usize)) {
+    let layout = Layout::from_size_align(ARENA_SIZE, ARENA_ALIGN).unwrap();
+    let arena = unsafe { alloc_zeroed(layout) };
+    assert!(!arena.is_null(), "no memory for the arena");
+    f(arena as usize);
+    unsafe { dealloc(arena, layout) };
+}
+
+fn test_early(rng: &mut Rng) {
+    with_arena(|start| {
+        let mut early = EarlyAllocator::<PAGE_SIZE>::new();
+        early.init(start, ARENA_SIZE);
+        let mut checker = Checker::new(start, ARENA_SIZE);
+        for _ in 0..NUM_OPS {
+            checker.apply(&mut early, rng.op(MAX_SIZE, MAX_PAGES, PAGE_SIZE));
+        }
+        checker.free_pages(&mut early);
+        checker.free_bytes(&mut early);
+    });
+}
+
+fn test_tlsf(rng: &mut Rng) {
+    with_arena(|start| {
+        let mut tlsf = TlsfByteAllocator::new();
+        tlsf.init(start, ARENA_SIZE);
+        let mut checker = Checker::new(start, ARENA_SIZE);
+        for _ in 0..NUM_OPS {
+            checker.apply_bytes(&mut tlsf, rng.op(MAX_SIZE, 0, PAGE_SIZE));
+        }
+        checker.free_bytes(&mut tlsf);
+    });
+}
+
+fn test_bitmap(rng: &mut Rng) {
+    with_arena(|start| {
+        #[cfg(feature = "axstd")]
+        let mut bitmap = BITMAP.lock();
+        #[cfg(not(feature = "axstd"))]
+        let mut bitmap = BITMAP.lock().unwrap();
+        // all the pages were freed by the previous round
+        bitmap.init(start, ARENA_SIZE);
+        let mut checker = Checker::new(start, ARENA_SIZE);
+        for _ in 0..NUM_OPS {
+            checker.apply_pages(&mut *bitmap, rng.op(0, MAX_PAGES, PAGE_SIZE));
+        }
+        checker.free_pages(&mut *bitmap);
+    });
+}
+
+fn test_buddy(rng: &mut Rng) {
+    with_arena(|start| {
+        let mut buddy = BuddyPageAllocator::<PAGE_SIZE>::new();
+        buddy.init(start, ARENA_SIZE);
+        let mut checker = Checker::new(start, ARENA_SIZE);
+        for _ in 0..NUM_OPS {
+            checker.apply_pages(&mut buddy, rng.op(0, MAX_PAGES, PAGE_SIZE));
+        }
+        checker.free_pages(&mut buddy);
+    });
+}
+
+#[cfg_attr(feature = "axstd", no_mangle)]
+fn main() {
+    println!("Running allocator stress tests...");
+    let tests: [(&str, fn(&mut Rng)); 4] = [
+        ("early", test_early),
+        ("tlsf", test_tlsf),
+        ("bitmap", test_bitmap),
+        ("buddy", test_buddy),
+    ];
+    for seed in FIRST_SEED..FIRST_SEED + NUM_ROUNDS {
+        for (name, test) in tests {
+            println!("  {} seed {}", name, seed);
+            test(&mut Rng::new(seed));
+        }
+    }
+    println!("Allocator stress tests run OK!");
+}
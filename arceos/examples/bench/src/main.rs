@@ -0,0 +1,22 @@
+//! Runs the built-in microbenchmarks of the kernel, printing one line of
+//! cycles per iteration for each, to compare releases:
+//!
+//! ```sh
+//! make A=examples/bench LOG=warn run
+//! ```
+
+#![cfg_attr(feature = "axstd", no_std)]
+#![cfg_attr(feature = "axstd", no_main)]
+
+#[macro_use]
+#[cfg(feature = "axstd")]
+extern crate axstd as std;
+
+use std::bench::{run_builtin, Config};
+
+#[cfg_attr(feature = "axstd", no_mangle)]
+fn main() {
+    println!("Running the built-in benches...");
+    let summaries = run_builtin(&Config::default());
+    println!("{} benches done.", summaries.len());
+}
@@ -19,6 +19,8 @@ type CmdHandler = fn(&str);
 const CMD_TABLE: &[(&str, CmdHandler)] = &[
     ("cat", do_cat),
     ("cd", do_cd),
+    #[cfg(feature = "axstd")]
+    ("df", do_df),
     ("echo", do_echo),
     ("exit", do_exit),
     ("help", do_help),
@@ -178,6 +180,34 @@ fn do_echo(args: &str) {
     }
 }
 
+#[cfg(feature = "axstd")]
+fn do_df(args: &str) {
+    fn df_one(path: &str) -> io::Result<()> {
+        let st = fs::statfs(path)?;
+        let kb = |blocks: u64| blocks * st.block_size / 1024;
+        println!(
+            "{:<10} {:>10} {:>10} {:>10} {}",
+            st.fs_type,
+            kb(st.blocks),
+            kb(st.blocks - st.blocks_free),
+            kb(st.blocks_avail),
+            path
+        );
+        Ok(())
+    }
+
+    println!(
+        "{:<10} {:>10} {:>10} {:>10} Mounted on",
+        "Filesystem", "1K-blocks", "Used", "Available"
+    );
+    let paths = if args.is_empty() { "/" } else { args };
+    for path in paths.split_whitespace() {
+        if let Err(e) = df_one(path) {
+            print_err!("df", path, e);
+        }
+    }
+}
+
 fn do_mkdir(args: &str) {
     if args.is_empty() {
         print_err!("mkdir", "missing operand");
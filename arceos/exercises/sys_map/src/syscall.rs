@@ -9,7 +9,11 @@ use axtask::TaskExtRef;
 use axhal::paging::MappingFlags;
 use arceos_posix_api as api;
 
+const SYS_GETCWD: usize = 17;
 const SYS_IOCTL: usize = 29;
+const SYS_STATFS: usize = 43;
+const SYS_CHDIR: usize = 49;
+const SYS_CHROOT: usize = 51;
 const SYS_OPENAT: usize = 56;
 const SYS_CLOSE: usize = 57;
 const SYS_READ: usize = 63;
@@ -102,6 +106,10 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
     let ret = match syscall_num {
          SYS_IOCTL => sys_ioctl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _) as _,
         SYS_SET_TID_ADDRESS => sys_set_tid_address(tf.arg0() as _),
+        SYS_GETCWD => sys_getcwd(tf.arg0() as _, tf.arg1() as _),
+        SYS_STATFS => sys_statfs(tf.arg0() as _, tf.arg1() as _),
+        SYS_CHDIR => sys_chdir(tf.arg0() as _),
+        SYS_CHROOT => sys_chroot(tf.arg0() as _),
         SYS_OPENAT => sys_openat(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _, tf.arg3() as _),
         SYS_CLOSE => sys_close(tf.arg0() as _),
         SYS_READ => sys_read(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
@@ -148,6 +156,22 @@ fn sys_openat(dfd: c_int, fname: *const c_char, flags: c_int, mode: api::ctypes:
     api::sys_open(fname, flags, mode) as isize
 }
 
+fn sys_statfs(path: *const c_char, buf: *mut api::ctypes::statfs) -> isize {
+    unsafe { api::sys_statfs(path, buf) as isize }
+}
+
+fn sys_getcwd(buf: *mut c_char, size: usize) -> isize {
+    api::sys_getcwd(buf, size) as isize
+}
+
+fn sys_chdir(path: *const c_char) -> isize {
+    api::sys_chdir(path) as isize
+}
+
+fn sys_chroot(path: *const c_char) -> isize {
+    api::sys_chroot(path) as isize
+}
+
 fn sys_close(fd: i32) -> isize {
     api::sys_close(fd) as isize
 }
@@ -0,0 +1,25 @@
+//! Allocation of pages at a given address.
+//!
+//! The [`PageAllocator`] trait of the `allocator` crate only allocates pages
+//! wherever they are free. Some devices need them at given addresses, e.g.
+//! below 4 GiB for 32-bit DMA, which the page allocators implementing
+//! [`PageAllocatorAt`] can give.
+
+#![no_std]
+
+use allocator::{AllocResult, PageAllocator};
+
+/// A [`PageAllocator`] which can allocate the pages at a given address.
+pub trait PageAllocatorAt: PageAllocator {
+    /// Allocates the `num_pages` contiguous pages starting at `base`, and
+    /// returns `base`.
+    ///
+    /// Fails with [`AllocError::MemoryOverlap`] if some of the pages are
+    /// already in use, and [`AllocError::InvalidParam`] if `base` is not
+    /// aligned to [`PageAllocator::PAGE_SIZE`] or the pages are not all in
+    /// the memory of the allocator.
+    ///
+    /// [`AllocError::MemoryOverlap`]: allocator::AllocError::MemoryOverlap
+    /// [`AllocError::InvalidParam`]: allocator::AllocError::InvalidParam
+    fn alloc_pages_at(&mut self, base: usize, num_pages: usize) -> AllocResult<usize>;
+}
@@ -0,0 +1,65 @@
+//! Resizing of the blocks of byte allocators, in place when they can.
+//!
+//! The [`ByteAllocator`] trait of the `allocator` crate has no `realloc`, so
+//! growing a block (e.g. a `Vec`) always moves it to a new one. The byte
+//! allocators implementing [`ReallocAllocator`] can grow or shrink a block
+//! in place, when the memory after it is free.
+
+#![no_std]
+
+use allocator::{AllocError, AllocResult, ByteAllocator};
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+/// A [`ByteAllocator`] which can resize its blocks.
+pub trait ReallocAllocator: ByteAllocator {
+    /// Resizes the block at `pos` allocated with `old_layout` to `new_size`
+    /// bytes without moving it, and returns whether it could.
+    ///
+    /// The block must then be freed with the layout of `new_size` bytes. By
+    /// default, it never can.
+    fn resize_in_place(&mut self, pos: NonNull<u8>, old_layout: Layout, new_size: usize) -> bool {
+        let _ = (pos, old_layout, new_size);
+        false
+    }
+
+    /// Resizes the block at `pos` allocated with `old_layout` to `new_size`
+    /// bytes, with the same alignment, in place if possible, or by moving its
+    /// data to a new block.
+    ///
+    /// The block is left as it is if it fails.
+    fn realloc(
+        &mut self,
+        pos: NonNull<u8>,
+        old_layout: Layout,
+        new_size: usize,
+    ) -> AllocResult<NonNull<u8>> {
+        if self.resize_in_place(pos, old_layout, new_size) {
+            return Ok(pos);
+        }
+        let new_layout = Layout::from_size_align(new_size, old_layout.align())
+            .map_err(|_| AllocError::InvalidParam)?;
+        let new_pos = self.alloc(new_layout)?;
+        unsafe { move_block(pos, new_pos, old_layout.size().min(new_size)) };
+        self.dealloc(pos, old_layout);
+        Ok(new_pos)
+    }
+}
+
+/// Copies the first `size` bytes of the block `from` to the block `to`.
+///
+/// # Safety
+///
+/// Both blocks must be valid for `size` bytes, and must not overlap.
+pub unsafe fn move_block(from: NonNull<u8>, to: NonNull<u8>, size: usize) {
+    unsafe { core::ptr::copy_nonoverlapping(from.as_ptr(), to.as_ptr(), size) }
+}
+
+#[cfg(feature = "slab")]
+impl ReallocAllocator for allocator::SlabByteAllocator {}
+
+#[cfg(feature = "buddy")]
+impl ReallocAllocator for allocator::BuddyByteAllocator {}
+
+#[cfg(feature = "tlsf")]
+impl ReallocAllocator for allocator::TlsfByteAllocator {}
@@ -0,0 +1,316 @@
+//! Stress tests of the allocators, by random interleavings of allocations
+//! and deallocations.
+//!
+//! A [`Checker`] applies the [`Op`]s to an allocator and keeps the blocks
+//! still live, to check after every operation that:
+//!
+//! - the blocks are aligned, in the memory of the allocator, and do not
+//!   overlap, the byte blocks and the pages of the same allocator included;
+//! - the data of the blocks is not overwritten until they are freed;
+//! - the accounting (the used and available bytes or pages) covers the live
+//!   blocks, does not change when an allocation fails, and goes back to zero
+//!   once all the blocks are freed.
+//!
+//! A broken invariant panics. The operations come from [`Rng`] in the
+//! `allocator_test` app, which runs them in the kernel, and from `proptest`
+//! in the tests of this crate, which shrinks a failing sequence to a minimal
+//! one.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+#[cfg(test)]
+mod tests;
+
+use alloc::vec::Vec;
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use allocator::{ByteAllocator, PageAllocator};
+
+/// An operation on an allocator.
+///
+/// The blocks to free are given by an index, modulo the number of live
+/// blocks, so that any sequence of operations is valid.
+#[derive(Debug, Clone, Copy)]
+pub enum Op {
+    /// Allocates `size` bytes aligned to `align`, a power of two.
+    Alloc { size: usize, align: usize },
+    /// Frees a live byte block.
+    Dealloc(usize),
+    /// Allocates `num_pages` pages aligned to `align_pow2`.
+    AllocPages { num_pages: usize, align_pow2: usize },
+    /// Frees a live page block.
+    DeallocPages(usize),
+}
+
+/// A xorshift64* generator of random operations, for the targets without
+/// `proptest`.
+pub struct Rng(u64);
+
+impl Rng {
+    /// Creates a generator, whose sequence is given by `seed`.
+    pub const fn new(seed: u64) -> Self {
+        // the state must not be zero
+        Self((seed ^ 0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    /// Returns the next random number.
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Returns a random number below `n`, which must not be zero.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Returns a random operation, of at most `max_size` bytes or
+    /// `max_pages` pages of `page_size` bytes. The byte operations are left
+    /// out if `max_size` is zero, the page ones if `max_pages` is.
+    pub fn op(&mut self, max_size: usize, max_pages: usize, page_size: usize) -> Op {
+        loop {
+            // allocate a bit more often than free, for the memory to fill up
+            match self.below(5) {
+                0 | 1 if max_size > 0 => {
+                    return Op::Alloc {
+                        size: 1 + self.below(max_size),
+                        align: 1 << self.below(8),
+                    }
+                }
+                2 if max_size > 0 => return Op::Dealloc(self.below(usize::MAX)),
+                3 if max_pages > 0 => {
+                    return Op::AllocPages {
+                        num_pages: 1 + self.below(max_pages),
+                        align_pow2: page_size << self.below(4),
+                    }
+                }
+                4 if max_pages > 0 => return Op::DeallocPages(self.below(usize::MAX)),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Applies the operations to an allocator and checks its invariants, see the
+/// [crate documentation](crate).
+pub struct Checker {
+    /// The memory of the allocator.
+    start: usize,
+    end: usize,
+    /// The live byte blocks.
+    bytes: Vec<(NonNull<u8>, Layout)>,
+    /// The live page blocks, with their number of pages.
+    pages: Vec<(usize, usize)>,
+    /// The size of the pages.
+    page_size: usize,
+}
+
+impl Checker {
+    /// Creates a checker of an allocator given the `size` bytes at `start`.
+    pub fn new(start: usize, size: usize) -> Self {
+        Self {
+            start,
+            end: start + size,
+            bytes: Vec::new(),
+            pages: Vec::new(),
+            page_size: 0,
+        }
+    }
+
+    /// Number of live blocks.
+    pub fn live_blocks(&self) -> usize {
+        self.bytes.len() + self.pages.len()
+    }
+
+    /// Applies a byte operation, and ignores the page ones.
+    pub fn apply_bytes<A: ByteAllocator>(&mut self, a: &mut A, op: Op) {
+        match op {
+            Op::Alloc { size, align } => self.alloc(a, size, align),
+            Op::Dealloc(index) if !self.bytes.is_empty() => {
+                self.dealloc(a, index % self.bytes.len())
+            }
+            _ => {}
+        }
+        self.check_bytes(a);
+    }
+
+    /// Applies a page operation, and ignores the byte ones.
+    pub fn apply_pages<A: PageAllocator>(&mut self, a: &mut A, op: Op) {
+        self.page_size = A::PAGE_SIZE;
+        match op {
+            Op::AllocPages {
+                num_pages,
+                align_pow2,
+            } => self.alloc_pages(a, num_pages, align_pow2),
+            Op::DeallocPages(index) if !self.pages.is_empty() => {
+                self.dealloc_pages(a, index % self.pages.len())
+            }
+            _ => {}
+        }
+        self.check_pages(a);
+    }
+
+    /// Applies any operation to an allocator of both bytes and pages.
+    pub fn apply<A: ByteAllocator + PageAllocator>(&mut self, a: &mut A, op: Op) {
+        match op {
+            Op::Alloc { .. } | Op::Dealloc(_) => self.apply_bytes(a, op),
+            Op::AllocPages { .. } | Op::DeallocPages(_) => self.apply_pages(a, op),
+        }
+        self.check_bytes(a);
+        self.check_pages(a);
+    }
+
+    /// Frees all the byte blocks, and checks that nothing is left in use.
+    pub fn free_bytes<A: ByteAllocator>(&mut self, a: &mut A) {
+        while !self.bytes.is_empty() {
+            self.dealloc(a, self.bytes.len() - 1);
+        }
+        assert_eq!(a.used_bytes(), 0, "bytes still used after freeing all");
+        assert_eq!(a.available_bytes() + a.used_bytes(), a.total_bytes());
+    }
+
+    /// Frees all the page blocks, and checks that nothing is left in use.
+    pub fn free_pages<A: PageAllocator>(&mut self, a: &mut A) {
+        while !self.pages.is_empty() {
+            self.dealloc_pages(a, self.pages.len() - 1);
+        }
+        assert_eq!(a.used_pages(), 0, "pages still used after freeing all");
+    }
+
+    fn alloc<A: ByteAllocator>(&mut self, a: &mut A, size: usize, align: usize) {
+        let layout = Layout::from_size_align(size, align).unwrap();
+        let used = a.used_bytes();
+        let Ok(ptr) = a.alloc(layout) else {
+            assert_eq!(
+                a.used_bytes(),
+                used,
+                "failed alloc of {:?} used bytes",
+                layout
+            );
+            return;
+        };
+        let addr = ptr.as_ptr() as usize;
+        assert_eq!(addr % align, 0, "{:#x} not aligned for {:?}", addr, layout);
+        self.check_new(addr, size);
+        unsafe { core::ptr::write_bytes(ptr.as_ptr(), pattern(addr), size) };
+        self.bytes.push((ptr, layout));
+    }
+
+    fn dealloc<A: ByteAllocator>(&mut self, a: &mut A, index: usize) {
+        let (ptr, layout) = self.bytes.swap_remove(index);
+        let addr = ptr.as_ptr() as usize;
+        let data = unsafe { core::slice::from_raw_parts(ptr.as_ptr(), layout.size()) };
+        if let Some(i) = data.iter().position(|&b| b != pattern(addr)) {
+            panic!(
+                "byte {} of the block {:#x} {:?} overwritten",
+                i, addr, layout
+            );
+        }
+        a.dealloc(ptr, layout);
+    }
+
+    fn alloc_pages<A: PageAllocator>(&mut self, a: &mut A, num_pages: usize, align_pow2: usize) {
+        let used = a.used_pages();
+        let Ok(addr) = a.alloc_pages(num_pages, align_pow2) else {
+            assert_eq!(
+                a.used_pages(),
+                used,
+                "failed alloc of {} pages used pages",
+                num_pages
+            );
+            return;
+        };
+        assert_eq!(
+            addr % align_pow2,
+            0,
+            "pages {:#x} not aligned to {:#x}",
+            addr,
+            align_pow2
+        );
+        let size = num_pages * A::PAGE_SIZE;
+        self.check_new(addr, size);
+        // the first and the last words only, the pages may be large
+        unsafe {
+            for word in [addr, addr + size - 8] {
+                (word as *mut u64).write_unaligned(word as u64);
+            }
+        }
+        self.pages.push((addr, num_pages));
+    }
+
+    fn dealloc_pages<A: PageAllocator>(&mut self, a: &mut A, index: usize) {
+        let (addr, num_pages) = self.pages.swap_remove(index);
+        let size = num_pages * A::PAGE_SIZE;
+        for word in [addr, addr + size - 8] {
+            let value = unsafe { (word as *const u64).read_unaligned() };
+            assert_eq!(
+                value, word as u64,
+                "pages {:#x} overwritten at {:#x}",
+                addr, word
+            );
+        }
+        a.dealloc_pages(addr, num_pages);
+    }
+
+    /// Checks that a new block is in the memory and overlaps no live one.
+    fn check_new(&self, addr: usize, size: usize) {
+        assert!(
+            addr >= self.start && addr + size <= self.end,
+            "block {:#x}..{:#x} out of {:#x}..{:#x}",
+            addr,
+            addr + size,
+            self.start,
+            self.end
+        );
+        let bytes = self
+            .bytes
+            .iter()
+            .map(|(ptr, layout)| (ptr.as_ptr() as usize, layout.size()));
+        let pages = self
+            .pages
+            .iter()
+            .map(|&(base, num_pages)| (base, num_pages * self.page_size));
+        for (base, len) in bytes.chain(pages) {
+            assert!(
+                addr + size <= base || base + len <= addr,
+                "block {:#x}..{:#x} overlaps {:#x}..{:#x}",
+                addr,
+                addr + size,
+                base,
+                base + len
+            );
+        }
+    }
+
+    fn check_bytes<A: ByteAllocator>(&self, a: &A) {
+        let live: usize = self.bytes.iter().map(|(_, layout)| layout.size()).sum();
+        assert!(
+            a.used_bytes() >= live,
+            "{} bytes used for {} live",
+            a.used_bytes(),
+            live
+        );
+        assert!(a.used_bytes() + a.available_bytes() <= a.total_bytes());
+    }
+
+    fn check_pages<A: PageAllocator>(&self, a: &A) {
+        let live: usize = self.pages.iter().map(|&(_, num_pages)| num_pages).sum();
+        assert!(
+            a.used_pages() >= live,
+            "{} pages used for {} live",
+            a.used_pages(),
+            live
+        );
+        assert!(a.used_pages() + a.available_pages() <= a.total_pages());
+    }
+}
+
+/// The byte the data of the block at `addr` is filled with.
+fn pattern(addr: usize) -> u8 {
+    (addr >> 3) as u8 ^ 0x5a
+}
@@ -0,0 +1,86 @@
+use allocator::BaseAllocator;
+use bitmap_page_allocator::BitmapPageAllocator;
+use buddy_allocator::BuddyPageAllocator;
+use bump_allocator::EarlyAllocator;
+use proptest::prelude::*;
+use tlsf_allocator::TlsfByteAllocator;
+
+use crate::{Checker, Op};
+
+const PAGE_SIZE: usize = 0x1000;
+const NUM_PAGES: usize = 64;
+const ARENA_SIZE: usize = NUM_PAGES * PAGE_SIZE;
+
+#[repr(align(0x40000))]
+struct Arena([u8; ARENA_SIZE]);
+
+fn new_arena() -> (Box<Arena>, usize) {
+    let arena = Box::new(Arena([0; ARENA_SIZE]));
+    let start = arena.0.as_ptr() as usize;
+    (arena, start)
+}
+
+fn ops() -> impl Strategy<Value = Vec<Op>> {
+    let op = prop_oneof![
+        2 => (1..3 * PAGE_SIZE, 0..8u32)
+            .prop_map(|(size, align)| Op::Alloc { size, align: 1 << align }),
+        1 => any::<usize>().prop_map(Op::Dealloc),
+        2 => (1..8usize, 0..4u32).prop_map(|(num_pages, align)| Op::AllocPages {
+            num_pages,
+            align_pow2: PAGE_SIZE << align,
+        }),
+        1 => any::<usize>().prop_map(Op::DeallocPages),
+    ];
+    prop::collection::vec(op, 1..300)
+}
+
+proptest! {
+    #[test]
+    fn test_early(ops in ops()) {
+        let (_arena, start) = new_arena();
+        let mut early = EarlyAllocator::<PAGE_SIZE>::new();
+        early.init(start, ARENA_SIZE);
+        let mut checker = Checker::new(start, ARENA_SIZE);
+        for op in ops {
+            checker.apply(&mut early, op);
+        }
+        checker.free_pages(&mut early);
+        checker.free_bytes(&mut early);
+    }
+
+    #[test]
+    fn test_tlsf(ops in ops()) {
+        let (_arena, start) = new_arena();
+        let mut tlsf = TlsfByteAllocator::new();
+        tlsf.init(start, ARENA_SIZE);
+        let mut checker = Checker::new(start, ARENA_SIZE);
+        for op in ops {
+            checker.apply_bytes(&mut tlsf, op);
+        }
+        checker.free_bytes(&mut tlsf);
+    }
+
+    #[test]
+    fn test_bitmap(ops in ops()) {
+        let (_arena, start) = new_arena();
+        let mut bitmap = BitmapPageAllocator::<PAGE_SIZE>::new();
+        bitmap.init(start, ARENA_SIZE);
+        let mut checker = Checker::new(start, ARENA_SIZE);
+        for op in ops {
+            checker.apply_pages(&mut bitmap, op);
+        }
+        checker.free_pages(&mut bitmap);
+    }
+
+    #[test]
+    fn test_buddy(ops in ops()) {
+        let (_arena, start) = new_arena();
+        let mut buddy = BuddyPageAllocator::<PAGE_SIZE>::new();
+        buddy.init(start, ARENA_SIZE);
+        let mut checker = Checker::new(start, ARENA_SIZE);
+        for op in ops {
+            checker.apply_pages(&mut buddy, op);
+        }
+        checker.free_pages(&mut buddy);
+    }
+}
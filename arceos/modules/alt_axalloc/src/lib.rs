@@ -4,24 +4,45 @@
 extern crate log;
 extern crate alloc;
 
+mod stats;
+
 use allocator::{AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
+use allocator_pages_at::PageAllocatorAt;
+use allocator_realloc::ReallocAllocator;
 use bump_allocator::EarlyAllocator;
 use core::alloc::{GlobalAlloc, Layout};
 use core::ptr::NonNull;
 use kspin::SpinNoIrq;
+use stats::Counters;
+
+pub use stats::{size_class_limit, AllocStats, NUM_SIZE_CLASSES};
 
 const PAGE_SIZE: usize = 0x1000;
 
+#[cfg(feature = "debug")]
+type InnerAllocator = debug_allocator::DebugAllocator<EarlyAllocator<PAGE_SIZE>>;
+#[cfg(not(feature = "debug"))]
+type InnerAllocator = EarlyAllocator<PAGE_SIZE>;
+
 /// The global allocator used by ArceOS.
+///
+/// With the feature `debug`, the byte allocations are checked for
+/// corruption by a [`DebugAllocator`](debug_allocator::DebugAllocator).
 pub struct GlobalAllocator {
-    inner: SpinNoIrq<EarlyAllocator<PAGE_SIZE>>,
+    inner: SpinNoIrq<InnerAllocator>,
+    stats: Counters,
 }
 
 impl GlobalAllocator {
     /// Creates an empty [`GlobalAllocator`].
     pub const fn new() -> Self {
+        #[cfg(feature = "debug")]
+        let inner = InnerAllocator::new(EarlyAllocator::new());
+        #[cfg(not(feature = "debug"))]
+        let inner = InnerAllocator::new();
         Self {
-            inner: SpinNoIrq::new(EarlyAllocator::new()),
+            inner: SpinNoIrq::new(inner),
+            stats: Counters::new(),
         }
     }
 
@@ -36,14 +57,20 @@ impl GlobalAllocator {
     }
 
     /// Add the given region to the allocator.
-    pub fn add_memory(&self, _start_vaddr: usize, _size: usize) -> AllocResult {
-        unimplemented!()
+    pub fn add_memory(&self, start_vaddr: usize, size: usize) -> AllocResult {
+        self.inner.lock().add_memory(start_vaddr, size)
     }
 
     /// Allocate arbitrary number of bytes. Returns the left bound of the
     /// allocated region.
     pub fn alloc(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
-        self.inner.lock().alloc(layout)
+        let mut inner = self.inner.lock();
+        let res = inner.alloc(layout);
+        match res {
+            Ok(_) => self.stats.alloc(layout.size(), inner.used_bytes()),
+            Err(_) => self.stats.alloc_failed(),
+        }
+        res
     }
 
     /// Gives back the allocated region to the byte allocator.
@@ -51,14 +78,50 @@ impl GlobalAllocator {
         self.inner.lock().dealloc(pos, layout)
     }
 
+    /// Resizes the region at `pos` allocated with `layout` to `new_size`
+    /// bytes, in place if it is the most recent allocation.
+    pub fn realloc(
+        &self,
+        pos: NonNull<u8>,
+        layout: Layout,
+        new_size: usize,
+    ) -> AllocResult<NonNull<u8>> {
+        let mut inner = self.inner.lock();
+        let res = inner.realloc(pos, layout, new_size);
+        match res {
+            Ok(_) => self.stats.alloc(new_size, inner.used_bytes()),
+            Err(_) => self.stats.alloc_failed(),
+        }
+        res
+    }
+
     /// Allocates contiguous pages.
     pub fn alloc_pages(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
-        self.inner.lock().alloc_pages(num_pages, align_pow2)
+        let res = self.inner.lock().alloc_pages(num_pages, align_pow2);
+        match res {
+            Ok(_) => self.stats.alloc_pages(num_pages),
+            Err(_) => self.stats.alloc_pages_failed(),
+        }
+        res
+    }
+
+    /// Allocates the `num_pages` contiguous pages starting at `base`.
+    ///
+    /// Fails with [`AllocError::MemoryOverlap`](allocator::AllocError::MemoryOverlap)
+    /// if some of them are already in use.
+    pub fn alloc_pages_at(&self, base: usize, num_pages: usize) -> AllocResult<usize> {
+        let res = self.inner.lock().alloc_pages_at(base, num_pages);
+        match res {
+            Ok(_) => self.stats.alloc_pages(num_pages),
+            Err(_) => self.stats.alloc_pages_failed(),
+        }
+        res
     }
 
     /// Gives back the allocated pages starts from `pos` to the page allocator.
     /// [`alloc_pages`]: GlobalAllocator::alloc_pages
     pub fn dealloc_pages(&self, pos: usize, num_pages: usize) {
+        self.stats.dealloc_pages(num_pages);
         self.inner.lock().dealloc_pages(pos, num_pages)
     }
 
@@ -81,6 +144,11 @@ impl GlobalAllocator {
     pub fn available_pages(&self) -> usize {
         self.inner.lock().available_pages()
     }
+
+    /// Returns the statistics of the allocations since boot.
+    pub fn stats(&self) -> AllocStats {
+        self.stats.snapshot()
+    }
 }
 
 unsafe impl GlobalAlloc for GlobalAllocator {
@@ -95,6 +163,14 @@ unsafe impl GlobalAlloc for GlobalAllocator {
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         GlobalAllocator::dealloc(self, NonNull::new(ptr).expect("dealloc null ptr"), layout)
     }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let pos = NonNull::new(ptr).expect("realloc null ptr");
+        match GlobalAllocator::realloc(self, pos, layout, new_size) {
+            Ok(ptr) => ptr.as_ptr(),
+            Err(_) => core::ptr::null_mut(),
+        }
+    }
 }
 
 #[cfg_attr(all(target_os = "none", not(test)), global_allocator)]
@@ -115,7 +191,18 @@ pub fn global_init(start_vaddr: usize, size: usize) {
     GLOBAL_ALLOCATOR.init(start_vaddr, size);
 }
 
+/// Returns the statistics of the global allocator: the peak usage, the
+/// number of allocations by size, and the failures.
+pub fn stats() -> AllocStats {
+    GLOBAL_ALLOCATOR.stats()
+}
+
 /// Add the given memory region to the global allocator.
-pub fn global_add_memory(_start_vaddr: usize, _size: usize) -> AllocResult {
-    unimplemented!()
+pub fn global_add_memory(start_vaddr: usize, size: usize) -> AllocResult {
+    debug!(
+        "add a memory region to global allocator: [{:#x}, {:#x})",
+        start_vaddr,
+        start_vaddr + size
+    );
+    GLOBAL_ALLOCATOR.add_memory(start_vaddr, size)
 }
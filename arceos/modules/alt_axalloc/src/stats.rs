@@ -0,0 +1,102 @@
+//! Statistics of the allocations, to size the heap of a board.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Number of size classes of [`AllocStats::size_classes`].
+pub const NUM_SIZE_CLASSES: usize = 16;
+
+/// The largest size of the class `class` of [`AllocStats::size_classes`],
+/// `usize::MAX` for the last one.
+pub const fn size_class_limit(class: usize) -> usize {
+    if class + 1 < NUM_SIZE_CLASSES {
+        8 << class
+    } else {
+        usize::MAX
+    }
+}
+
+/// A snapshot of the statistics of an allocator, see [`stats`].
+///
+/// [`stats`]: crate::stats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocStats {
+    /// The most bytes allocated by the byte allocator at once.
+    pub peak_bytes: usize,
+    /// The most pages allocated by the page allocator at once, including the
+    /// ones of the heap.
+    pub peak_pages: usize,
+    /// Number of byte allocations by size: the class `i` counts the sizes up
+    /// to [`size_class_limit(i)`](size_class_limit), 8 bytes for the first,
+    /// and larger than the previous class.
+    pub size_classes: [usize; NUM_SIZE_CLASSES],
+    /// Number of byte allocations failed.
+    pub alloc_failures: usize,
+    /// Number of page allocations failed.
+    pub page_alloc_failures: usize,
+}
+
+/// The counters behind [`AllocStats`], updated without locks.
+pub(crate) struct Counters {
+    peak_bytes: AtomicUsize,
+    used_pages: AtomicUsize,
+    peak_pages: AtomicUsize,
+    size_classes: [AtomicUsize; NUM_SIZE_CLASSES],
+    alloc_failures: AtomicUsize,
+    page_alloc_failures: AtomicUsize,
+}
+
+impl Counters {
+    pub const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicUsize = AtomicUsize::new(0);
+        Self {
+            peak_bytes: ZERO,
+            used_pages: ZERO,
+            peak_pages: ZERO,
+            size_classes: [ZERO; NUM_SIZE_CLASSES],
+            alloc_failures: ZERO,
+            page_alloc_failures: ZERO,
+        }
+    }
+
+    /// Records a byte allocation of `size` bytes, after which `used_bytes`
+    /// are allocated.
+    pub fn alloc(&self, size: usize, used_bytes: usize) {
+        let class = (size.max(1).next_power_of_two().trailing_zeros() as usize)
+            .saturating_sub(3)
+            .min(NUM_SIZE_CLASSES - 1);
+        self.size_classes[class].fetch_add(1, Ordering::Relaxed);
+        self.peak_bytes.fetch_max(used_bytes, Ordering::Relaxed);
+    }
+
+    pub fn alloc_failed(&self) {
+        self.alloc_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn alloc_pages(&self, num_pages: usize) {
+        let used = self.used_pages.fetch_add(num_pages, Ordering::Relaxed) + num_pages;
+        self.peak_pages.fetch_max(used, Ordering::Relaxed);
+    }
+
+    pub fn alloc_pages_failed(&self) {
+        self.page_alloc_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dealloc_pages(&self, num_pages: usize) {
+        let sub = |n: usize| Some(n.saturating_sub(num_pages));
+        let _ = self
+            .used_pages
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, sub);
+    }
+
+    pub fn snapshot(&self) -> AllocStats {
+        let load = |c: &AtomicUsize| c.load(Ordering::Relaxed);
+        AllocStats {
+            peak_bytes: load(&self.peak_bytes),
+            peak_pages: load(&self.peak_pages),
+            size_classes: core::array::from_fn(|i| load(&self.size_classes[i])),
+            alloc_failures: load(&self.alloc_failures),
+            page_alloc_failures: load(&self.page_alloc_failures),
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Per-CPU caches of small blocks in front of the byte allocator, so that the
+//! CPUs do not take its lock for most allocations.
+//!
+//! Each CPU keeps a magazine of free blocks for each size class (16 bytes to
+//! 2 KB, powers of 2). An allocation takes a block from the magazine of the
+//! current CPU, which is refilled with [`BATCH`] blocks at once from the byte
+//! allocator when empty. A deallocation puts the block back in the magazine,
+//! and gives [`BATCH`] of them back to the byte allocator when it is full.
+//!
+//! The cached blocks are counted as used by the byte allocator. They are
+//! given back by [`GlobalAllocator::flush_cpu_cache`], e.g. before a CPU goes
+//! offline.
+
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use allocator::{AllocResult, ByteAllocator};
+use kernel_guard::NoPreemptIrqSave;
+
+use crate::{DefaultByteAllocator, GlobalAllocator};
+
+/// Size of the blocks of the first size class.
+const MIN_BLOCK_SIZE: usize = 16;
+/// Number of size classes.
+const NUM_CLASSES: usize = 8;
+/// Number of blocks in a magazine.
+const MAGAZINE_SIZE: usize = 32;
+/// Number of blocks moved between a magazine and the byte allocator at once.
+const BATCH: usize = MAGAZINE_SIZE / 2;
+
+/// A stack of free blocks of the same size class.
+struct Magazine {
+    len: usize,
+    blocks: [usize; MAGAZINE_SIZE],
+}
+
+impl Magazine {
+    const fn new() -> Self {
+        Self {
+            len: 0,
+            blocks: [0; MAGAZINE_SIZE],
+        }
+    }
+
+    fn pop(&mut self) -> Option<NonNull<u8>> {
+        self.len = self.len.checked_sub(1)?;
+        NonNull::new(self.blocks[self.len] as *mut u8)
+    }
+
+    fn push(&mut self, block: NonNull<u8>) {
+        self.blocks[self.len] = block.as_ptr() as usize;
+        self.len += 1;
+    }
+
+    /// Gives the `num` oldest blocks back to the byte allocator.
+    fn drain(&mut self, num: usize, layout: Layout, balloc: &mut DefaultByteAllocator) {
+        for &block in &self.blocks[..num] {
+            balloc.dealloc(NonNull::new(block as *mut u8).unwrap(), layout);
+        }
+        self.blocks.copy_within(num..self.len, 0);
+        self.len -= num;
+    }
+}
+
+/// The magazines of a CPU, one per size class.
+struct CpuCache {
+    magazines: [Magazine; NUM_CLASSES],
+}
+
+impl CpuCache {
+    const fn new() -> Self {
+        const EMPTY: Magazine = Magazine::new();
+        Self {
+            magazines: [EMPTY; NUM_CLASSES],
+        }
+    }
+}
+
+#[percpu::def_percpu]
+static CPU_CACHE: CpuCache = CpuCache::new();
+
+/// Returns the size class of the blocks for `layout`, if it is small enough
+/// to be cached.
+///
+/// The blocks are aligned to their size, so that any alignment up to the
+/// size is satisfied.
+fn size_class(layout: Layout) -> Option<usize> {
+    let size = layout
+        .size()
+        .max(layout.align())
+        .max(MIN_BLOCK_SIZE)
+        .next_power_of_two();
+    let class = (size / MIN_BLOCK_SIZE).trailing_zeros() as usize;
+    (class < NUM_CLASSES).then_some(class)
+}
+
+/// Returns the layout of the blocks of the size class `class` in the byte
+/// allocator.
+fn class_layout(class: usize) -> Layout {
+    let size = MIN_BLOCK_SIZE << class;
+    Layout::from_size_align(size, size).unwrap()
+}
+
+/// Runs `f` with the cache of the current CPU, with preemption and IRQs
+/// disabled.
+fn with_cpu_cache<T>(f: impl FnOnce(&mut CpuCache) -> T) -> T {
+    let _guard = NoPreemptIrqSave::new();
+    // SAFETY: the guard keeps other code off the cache on this CPU
+    f(unsafe { CPU_CACHE.current_ref_mut_raw() })
+}
+
+impl GlobalAllocator {
+    /// Allocates from the cache of the current CPU, or from the byte
+    /// allocator if the block is too large.
+    pub(crate) fn alloc_cached(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let Some(class) = size_class(layout) else {
+            return self.alloc_bytes(layout);
+        };
+        let res: AllocResult<NonNull<u8>> = with_cpu_cache(|cache| {
+            let magazine = &mut cache.magazines[class];
+            if let Some(block) = magazine.pop() {
+                self.stats.alloc(layout.size(), 0);
+                return Ok(block);
+            }
+            let block_layout = class_layout(class);
+            let mut balloc = self.balloc.lock();
+            let block = self.alloc_locked(&mut balloc, block_layout)?;
+            // only take the other blocks if they fit in the heap as it is
+            for _ in 1..BATCH {
+                match balloc.alloc(block_layout) {
+                    Ok(block) => magazine.push(block),
+                    Err(_) => break,
+                }
+            }
+            self.stats.alloc(layout.size(), balloc.used_bytes());
+            Ok(block)
+        });
+        res.or_else(|_| {
+            // the blocks cached for the other size classes may be enough
+            self.flush_cpu_cache();
+            self.alloc_bytes(class_layout(class))
+        })
+    }
+
+    /// Gives a block back to the cache of the current CPU, or to the byte
+    /// allocator if it is too large.
+    pub(crate) fn dealloc_cached(&self, pos: NonNull<u8>, layout: Layout) {
+        let Some(class) = size_class(layout) else {
+            return self.balloc.lock().dealloc(pos, layout);
+        };
+        with_cpu_cache(|cache| {
+            let magazine = &mut cache.magazines[class];
+            if magazine.len == MAGAZINE_SIZE {
+                magazine.drain(BATCH, class_layout(class), &mut self.balloc.lock());
+            }
+            magazine.push(pos);
+        })
+    }
+
+    /// Gives all the blocks cached by the current CPU back to the byte
+    /// allocator.
+    ///
+    /// It should be called before the CPU goes offline, or idle for a long
+    /// time, as no other CPU can use its blocks meanwhile.
+    pub fn flush_cpu_cache(&self) {
+        with_cpu_cache(|cache| {
+            let mut balloc = self.balloc.lock();
+            for (class, magazine) in cache.magazines.iter_mut().enumerate() {
+                magazine.drain(magazine.len, class_layout(class), &mut balloc);
+            }
+        })
+    }
+}
@@ -0,0 +1,230 @@
+//! Shadow table of the live allocations, to find the memory never freed.
+//!
+//! Every allocation of the byte allocator is recorded with its size and its
+//! caller until it is freed, in a table outside of the heap.
+//! [`dump_leaks`] prints the ones still live, by caller.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use kspin::SpinNoIrq;
+
+use crate::TraceEvent;
+
+/// Maximum number of live allocations recorded. The others are counted in
+/// [`LeakReport::untracked`].
+pub const MAX_LEAK_ENTRIES: usize = 4096;
+/// Maximum number of callers in a [`LeakReport`].
+pub const MAX_LEAK_CALLERS: usize = 32;
+
+#[derive(Clone, Copy)]
+struct Entry {
+    /// The address of the allocation, 0 if the slot is free.
+    ptr: usize,
+    size: usize,
+    caller: usize,
+    /// The value of the sequence number when it was allocated.
+    seq: usize,
+}
+
+const EMPTY: Entry = Entry {
+    ptr: 0,
+    size: 0,
+    caller: 0,
+    seq: 0,
+};
+
+/// An open addressing hash table with linear probing, whose slots are freed
+/// by shifting back the following entries, so that no tombstones are left.
+struct Table {
+    entries: [Entry; MAX_LEAK_ENTRIES],
+    len: usize,
+    untracked: usize,
+}
+
+impl Table {
+    fn slot(ptr: usize) -> usize {
+        // the low bits are mostly zeros by alignment
+        (ptr >> 4).wrapping_mul(0x9E37_79B9_7F4A_7C15) % MAX_LEAK_ENTRIES
+    }
+
+    fn insert(&mut self, entry: Entry) {
+        if self.len == MAX_LEAK_ENTRIES {
+            self.untracked += 1;
+            return;
+        }
+        let mut i = Self::slot(entry.ptr);
+        while self.entries[i].ptr != 0 {
+            i = (i + 1) % MAX_LEAK_ENTRIES;
+        }
+        self.entries[i] = entry;
+        self.len += 1;
+    }
+
+    fn remove(&mut self, ptr: usize) {
+        let mut i = Self::slot(ptr);
+        loop {
+            match self.entries[i].ptr {
+                0 => {
+                    // allocated while the table was full
+                    self.untracked = self.untracked.saturating_sub(1);
+                    return;
+                }
+                p if p == ptr => break,
+                _ => i = (i + 1) % MAX_LEAK_ENTRIES,
+            }
+        }
+        // move back the entries which would not be found past the hole
+        let mut hole = i;
+        let mut j = i;
+        loop {
+            j = (j + 1) % MAX_LEAK_ENTRIES;
+            let entry = self.entries[j];
+            if entry.ptr == 0 {
+                break;
+            }
+            let home = Self::slot(entry.ptr);
+            let dist_home = (j + MAX_LEAK_ENTRIES - home) % MAX_LEAK_ENTRIES;
+            let dist_hole = (j + MAX_LEAK_ENTRIES - hole) % MAX_LEAK_ENTRIES;
+            if dist_home >= dist_hole {
+                self.entries[hole] = entry;
+                hole = j;
+            }
+        }
+        self.entries[hole] = EMPTY;
+        self.len -= 1;
+    }
+}
+
+static TABLE: SpinNoIrq<Table> = SpinNoIrq::new(Table {
+    entries: [EMPTY; MAX_LEAK_ENTRIES],
+    len: 0,
+    untracked: 0,
+});
+
+static SEQ: AtomicUsize = AtomicUsize::new(0);
+
+/// Records an allocation or a deallocation.
+pub(crate) fn record(event: TraceEvent, ptr: usize, size: usize, caller: usize) {
+    let mut table = TABLE.lock();
+    match event {
+        TraceEvent::Alloc => {
+            let seq = SEQ.fetch_add(1, Ordering::Relaxed);
+            table.insert(Entry {
+                ptr,
+                size,
+                caller,
+                seq,
+            });
+        }
+        TraceEvent::Dealloc => table.remove(ptr),
+    }
+}
+
+/// The live allocations of a caller, see [`LeakReport`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LeakCaller {
+    /// The return address into the code which allocated, 0 if unknown.
+    pub caller: usize,
+    /// Number of live allocations.
+    pub count: usize,
+    /// Total size of the live allocations.
+    pub bytes: usize,
+}
+
+/// The allocations still live, returned by [`dump_leaks`].
+#[derive(Debug, Clone, Copy)]
+pub struct LeakReport {
+    /// Number of live allocations.
+    pub count: usize,
+    /// Total size of the live allocations.
+    pub bytes: usize,
+    /// Number of live allocations not recorded, the table being full.
+    pub untracked: usize,
+    /// The callers, sorted by bytes from the largest, in the first
+    /// `num_callers` entries.
+    pub callers: [LeakCaller; MAX_LEAK_CALLERS],
+    /// Number of entries of `callers` used.
+    pub num_callers: usize,
+    /// The allocations of the callers found after `callers` was full, with
+    /// `caller` set to 0.
+    pub others: LeakCaller,
+}
+
+/// Returns a mark to give to [`dump_leaks_since`], so that only the
+/// allocations made from now on are reported.
+pub fn leak_mark() -> usize {
+    SEQ.load(Ordering::Relaxed)
+}
+
+/// Prints the allocations still live, by caller from the one with the most
+/// bytes, and returns them.
+///
+/// The callers are symbolized if the symbol table is embedded (see
+/// `axsyms`). The report is built without allocating from the heap.
+pub fn dump_leaks() -> LeakReport {
+    dump_leaks_since(0)
+}
+
+/// Like [`dump_leaks`], but only for the allocations made since `mark` was
+/// returned by [`leak_mark`], e.g. by a test which should free everything it
+/// allocates.
+pub fn dump_leaks_since(mark: usize) -> LeakReport {
+    let report = leaks_since(mark);
+    warn!(
+        "{} live allocations ({} bytes), {} untracked",
+        report.count, report.bytes, report.untracked
+    );
+    let others = (report.others.count > 0).then_some(&report.others);
+    for c in report.callers[..report.num_callers].iter().chain(others) {
+        match axsyms::symbolize(c.caller.saturating_sub(1)) {
+            Some((name, offset)) => warn!(
+                "  {:>8} bytes in {:>5} allocations from {:#x} {}+{:#x}",
+                c.bytes,
+                c.count,
+                c.caller,
+                name,
+                offset + 1
+            ),
+            None => warn!(
+                "  {:>8} bytes in {:>5} allocations from {:#x}",
+                c.bytes, c.count, c.caller
+            ),
+        }
+    }
+    report
+}
+
+fn leaks_since(mark: usize) -> LeakReport {
+    let mut report = LeakReport {
+        count: 0,
+        bytes: 0,
+        untracked: 0,
+        callers: [LeakCaller::default(); MAX_LEAK_CALLERS],
+        num_callers: 0,
+        others: LeakCaller::default(),
+    };
+    let table = TABLE.lock();
+    report.untracked = table.untracked;
+    for e in table.entries.iter().filter(|e| e.ptr != 0 && e.seq >= mark) {
+        report.count += 1;
+        report.bytes += e.size;
+        let callers = &mut report.callers[..report.num_callers];
+        if let Some(c) = callers.iter_mut().find(|c| c.caller == e.caller) {
+            c.count += 1;
+            c.bytes += e.size;
+        } else if report.num_callers < MAX_LEAK_CALLERS {
+            report.callers[report.num_callers] = LeakCaller {
+                caller: e.caller,
+                count: 1,
+                bytes: e.size,
+            };
+            report.num_callers += 1;
+        } else {
+            report.others.count += 1;
+            report.others.bytes += e.size;
+        }
+    }
+    drop(table);
+    report.callers[..report.num_callers].sort_unstable_by(|a, b| b.bytes.cmp(&a.bytes));
+    report
+}
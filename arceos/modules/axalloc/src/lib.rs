@@ -4,6 +4,33 @@
 //! [`core::alloc::GlobalAlloc`]. A static global variable of type
 //! [`GlobalAllocator`] is defined with the `#[global_allocator]` attribute, to
 //! be registered as the standard library’s default allocator.
+//!
+//! With the feature `percpu-cache`, the small blocks are allocated from
+//! per-CPU caches, see [`GlobalAllocator::flush_cpu_cache`]. With the feature
+//! `debug`, the byte allocator is wrapped in a [`DebugAllocator`], which
+//! panics when the blocks are overflowed or freed with another layout.
+//!
+//! The byte allocator grows by huge pages when it needs at least 2 MiB more,
+//! see [`GlobalAllocator::alloc_huge_pages`], so that the heap can be mapped
+//! with huge page table entries.
+//!
+//! The memory left free by an [`EarlyAllocator`] used before it can be taken
+//! over with [`global_init_from_early`].
+//!
+//! Pages can be allocated from a [`MemZone`]: below the DMA limit for the
+//! devices, or on a NUMA node, see [`GlobalAllocator::alloc_pages_in_zone`].
+//!
+//! When the heap runs out of memory, the functions registered with
+//! [`set_oom_handler`] are asked to free some before the allocation fails.
+//!
+//! With the feature `leak`, the live allocations are recorded with their
+//! callers, and [`dump_leaks`] prints the ones never freed.
+//!
+//! [`GlobalAllocator::realloc`] grows and shrinks the blocks in place when the
+//! byte allocator can, see [`ReallocAllocator`].
+//!
+//! [`DebugAllocator`]: debug_allocator::DebugAllocator
+//! [`ReallocAllocator`]: allocator_realloc::ReallocAllocator
 
 #![no_std]
 
@@ -11,28 +38,99 @@
 extern crate log;
 extern crate alloc;
 
+#[cfg(feature = "percpu-cache")]
+mod cache;
+#[cfg(feature = "leak")]
+mod leak;
 mod page;
+mod stats;
+#[cfg(feature = "trace")]
+mod trace;
+mod zone;
 
-use allocator::{AllocResult, BaseAllocator, BitmapPageAllocator, ByteAllocator, PageAllocator};
+use allocator::{AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
+use allocator_pages_at::PageAllocatorAt;
+#[cfg(not(feature = "percpu-cache"))]
+use allocator_realloc::ReallocAllocator;
+use bitmap_page_allocator::BitmapPageAllocator;
+use bump_allocator::EarlyAllocator;
 use core::alloc::{GlobalAlloc, Layout};
 use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use kspin::SpinNoIrq;
+use stats::Counters;
+use zone::RegionZone;
 
 const PAGE_SIZE: usize = 0x1000;
 const MIN_HEAP_SIZE: usize = 0x8000; // 32 K
 
+/// Order (log2 of the number of pages) of a 2 MiB huge page.
+pub const HUGE_2M_ORDER: usize = 9;
+/// Order (log2 of the number of pages) of a 1 GiB huge page.
+pub const HUGE_1G_ORDER: usize = 18;
+
+/// Maximum number of blocks of huge pages the byte allocator grows by which
+/// are recorded, see [`GlobalAllocator::huge_heap_blocks`].
+pub const MAX_HUGE_HEAP_BLOCKS: usize = 16;
+
+/// Maximum number of memory regions that can be added to the page allocator
+/// at runtime, see [`GlobalAllocator::add_pages`].
+///
+/// Each of them has a static bitmap of about 137 KiB for up to 4 GiB of
+/// pages, so they take about 548 KiB of `.bss` in total. There are none
+/// without the feature `hotplug`, and [`GlobalAllocator::add_pages`] always
+/// fails with [`AllocError::NoMemory`].
+pub const MAX_HOTPLUG_REGIONS: usize = if cfg!(feature = "hotplug") { 4 } else { 0 };
+
+pub use allocator::AllocError;
+#[cfg(feature = "leak")]
+pub use leak::{
+    dump_leaks, dump_leaks_since, leak_mark, LeakCaller, LeakReport, MAX_LEAK_CALLERS,
+    MAX_LEAK_ENTRIES,
+};
 pub use page::GlobalPage;
+pub use stats::{size_class_limit, AllocStats, NUM_SIZE_CLASSES};
+#[cfg(feature = "trace")]
+pub use trace::{set_trace_hook, TraceEvent, TraceHook};
+pub use zone::MemZone;
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "slab")] {
-        /// The default byte allocator.
-        pub type DefaultByteAllocator = allocator::SlabByteAllocator;
+        type BaseByteAllocator =
+            slab_allocator::SlabByteAllocator<buddy_allocator::BuddyPageAllocator<PAGE_SIZE>>;
+
+        const fn new_base_byte_allocator() -> BaseByteAllocator {
+            BaseByteAllocator::new(buddy_allocator::BuddyPageAllocator::new())
+        }
     } else if #[cfg(feature = "buddy")] {
-        /// The default byte allocator.
-        pub type DefaultByteAllocator = allocator::BuddyByteAllocator;
+        type BaseByteAllocator = allocator::BuddyByteAllocator;
+    } else if #[cfg(feature = "tlsf-intree")] {
+        type BaseByteAllocator = tlsf_allocator::TlsfByteAllocator;
     } else if #[cfg(feature = "tlsf")] {
+        type BaseByteAllocator = allocator::TlsfByteAllocator;
+    }
+}
+
+#[cfg(not(feature = "slab"))]
+const fn new_base_byte_allocator() -> BaseByteAllocator {
+    BaseByteAllocator::new()
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "debug")] {
+        /// The default byte allocator, checking the blocks for corruption.
+        pub type DefaultByteAllocator = debug_allocator::DebugAllocator<BaseByteAllocator>;
+
+        const fn new_byte_allocator() -> DefaultByteAllocator {
+            DefaultByteAllocator::new(new_base_byte_allocator())
+        }
+    } else {
         /// The default byte allocator.
-        pub type DefaultByteAllocator = allocator::TlsfByteAllocator;
+        pub type DefaultByteAllocator = BaseByteAllocator;
+
+        const fn new_byte_allocator() -> DefaultByteAllocator {
+            new_base_byte_allocator()
+        }
     }
 }
 
@@ -43,21 +141,104 @@ cfg_if::cfg_if! {
 /// there is no memory, asks the page allocator for more memory and adds it to
 /// the byte allocator.
 ///
-/// Currently, [`TlsfByteAllocator`] is used as the byte allocator, while
+/// The byte allocator is the [`DefaultByteAllocator`], chosen by the features:
+/// `slab` selects a slab allocator over a buddy page allocator, `buddy` a
+/// buddy allocator, `tlsf-intree` the TLSF allocator of the `tlsf_allocator`
+/// crate, and otherwise `tlsf` (the default) the TLSF allocator of the
+/// `allocator` crate. With `debug`, it is wrapped in a [`DebugAllocator`].
 /// [`BitmapPageAllocator`] is used as the page allocator.
 ///
-/// [`TlsfByteAllocator`]: allocator::TlsfByteAllocator
+/// [`DebugAllocator`]: debug_allocator::DebugAllocator
+/// [`BitmapPageAllocator`]: bitmap_page_allocator::BitmapPageAllocator
 pub struct GlobalAllocator {
     balloc: SpinNoIrq<DefaultByteAllocator>,
     palloc: SpinNoIrq<BitmapPageAllocator<PAGE_SIZE>>,
+    hotplug: SpinNoIrq<[HotplugRegion; MAX_HOTPLUG_REGIONS]>,
+    huge_heap: SpinNoIrq<[Option<HugePages>; MAX_HUGE_HEAP_BLOCKS]>,
+    /// The end of the region given to [`init`](Self::init), on node 0.
+    main_end: AtomicUsize,
+    dma_limit: AtomicUsize,
+    stats: Counters,
+}
+
+/// Contiguous pages allocated by [`GlobalAllocator::alloc_huge_pages`],
+/// aligned so that they can be mapped with huge page table entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HugePages {
+    /// The start address, aligned to [`page_size`](Self::page_size).
+    pub start: usize,
+    /// Log2 of the number of pages, at least [`HUGE_2M_ORDER`].
+    pub order: usize,
+}
+
+impl HugePages {
+    /// Returns the size of the pages in bytes.
+    pub const fn size(&self) -> usize {
+        PAGE_SIZE << self.order
+    }
+
+    /// Returns the number of pages.
+    pub const fn num_pages(&self) -> usize {
+        1 << self.order
+    }
+
+    /// Returns the size of the huge pages they can be mapped with: 1 GiB if
+    /// they are at least that large, 2 MiB otherwise.
+    pub const fn page_size(&self) -> usize {
+        if self.order >= HUGE_1G_ORDER {
+            PAGE_SIZE << HUGE_1G_ORDER
+        } else {
+            PAGE_SIZE << HUGE_2M_ORDER
+        }
+    }
+
+    /// Returns the number of contiguous huge pages of
+    /// [`page_size`](Self::page_size) they make.
+    pub const fn num_huge_pages(&self) -> usize {
+        self.size() / self.page_size()
+    }
+}
+
+/// A memory region added to the page allocator at runtime, with its own
+/// page allocator since [`BitmapPageAllocator`] manages only one region.
+///
+/// The slots are never moved, as their bitmap is large: a free one has a
+/// `size` of 0, and its bitmap is reset to be reused by the next region.
+struct HotplugRegion {
+    start: usize,
+    size: usize,
+    node: usize,
+    palloc: BitmapPageAllocator<PAGE_SIZE>,
+}
+
+impl HotplugRegion {
+    const FREE: Self = Self {
+        start: 0,
+        size: 0,
+        node: 0,
+        palloc: BitmapPageAllocator::new(),
+    };
+
+    fn is_used(&self) -> bool {
+        self.size != 0
+    }
+
+    fn contains(&self, pos: usize) -> bool {
+        (self.start..self.start + self.size).contains(&pos)
+    }
 }
 
 impl GlobalAllocator {
     /// Creates an empty [`GlobalAllocator`].
     pub const fn new() -> Self {
         Self {
-            balloc: SpinNoIrq::new(DefaultByteAllocator::new()),
+            balloc: SpinNoIrq::new(new_byte_allocator()),
             palloc: SpinNoIrq::new(BitmapPageAllocator::new()),
+            hotplug: SpinNoIrq::new([HotplugRegion::FREE; MAX_HOTPLUG_REGIONS]),
+            huge_heap: SpinNoIrq::new([None; MAX_HUGE_HEAP_BLOCKS]),
+            main_end: AtomicUsize::new(0),
+            dma_limit: AtomicUsize::new(usize::MAX),
+            stats: Counters::new(),
         }
     }
 
@@ -68,7 +249,7 @@ impl GlobalAllocator {
                 "slab"
             } else if #[cfg(feature = "buddy")] {
                 "buddy"
-            } else if #[cfg(feature = "tlsf")] {
+            } else if #[cfg(any(feature = "tlsf-intree", feature = "tlsf"))] {
                 "TLSF"
             }
         }
@@ -83,6 +264,7 @@ impl GlobalAllocator {
         assert!(size > MIN_HEAP_SIZE);
         let init_heap_size = MIN_HEAP_SIZE;
         self.palloc.lock().init(start_vaddr, size);
+        self.main_end.store(start_vaddr + size, Ordering::Relaxed);
         let heap_ptr = self
             .alloc_pages(init_heap_size / PAGE_SIZE, PAGE_SIZE)
             .unwrap();
@@ -102,9 +284,62 @@ impl GlobalAllocator {
     /// It firstly tries to allocate from the byte allocator. If there is no
     /// memory, it asks the page allocator for more memory and adds it to the
     /// byte allocator.
+    #[cfg_attr(feature = "trace", inline(never))]
     pub fn alloc(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
-        // simple two-level allocator: if no heap memory, allocate from the page allocator.
+        let res = self.alloc_front(layout);
+        #[cfg(feature = "trace")]
+        if let Ok(ptr) = res {
+            trace::trace(TraceEvent::Alloc, ptr, layout);
+        }
+        res
+    }
+
+    /// Allocates from the per-CPU caches if they are enabled, or from the
+    /// byte allocator, and calls the [`OomHandler`]s to try again if there is
+    /// no memory.
+    #[inline]
+    fn alloc_front(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let mut res = self.try_alloc_front(layout);
+        for _ in 0..MAX_OOM_RETRIES {
+            if res.is_ok() || !handle_oom(layout) {
+                break;
+            }
+            res = self.try_alloc_front(layout);
+        }
+        res
+    }
+
+    #[inline]
+    fn try_alloc_front(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        #[cfg(feature = "percpu-cache")]
+        return self.alloc_cached(layout);
+        #[cfg(not(feature = "percpu-cache"))]
+        return self.alloc_bytes(layout);
+    }
+
+    #[inline]
+    fn dealloc_front(&self, pos: NonNull<u8>, layout: Layout) {
+        #[cfg(feature = "percpu-cache")]
+        self.dealloc_cached(pos, layout);
+        #[cfg(not(feature = "percpu-cache"))]
+        self.balloc.lock().dealloc(pos, layout);
+    }
+
+    fn alloc_bytes(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
         let mut balloc = self.balloc.lock();
+        let ptr = self
+            .alloc_locked(&mut balloc, layout)
+            .inspect_err(|_| self.stats.alloc_failed())?;
+        self.stats.alloc(layout.size(), balloc.used_bytes());
+        Ok(ptr)
+    }
+
+    fn alloc_locked(
+        &self,
+        balloc: &mut DefaultByteAllocator,
+        layout: Layout,
+    ) -> AllocResult<NonNull<u8>> {
+        // simple two-level allocator: if no heap memory, allocate from the page allocator.
         loop {
             if let Ok(ptr) = balloc.alloc(layout) {
                 return Ok(ptr);
@@ -114,7 +349,7 @@ impl GlobalAllocator {
                     .max(layout.size())
                     .next_power_of_two()
                     .max(PAGE_SIZE);
-                let heap_ptr = self.alloc_pages(expand_size / PAGE_SIZE, PAGE_SIZE)?;
+                let heap_ptr = self.alloc_heap_pages(expand_size / PAGE_SIZE)?;
                 debug!(
                     "expand heap memory: [{:#x}, {:#x})",
                     heap_ptr,
@@ -125,6 +360,26 @@ impl GlobalAllocator {
         }
     }
 
+    /// Allocates `num_pages` (a power of 2) pages to grow the byte allocator
+    /// with, as huge pages when there are enough of them, so that they can
+    /// be mapped with huge page table entries.
+    fn alloc_heap_pages(&self, num_pages: usize) -> AllocResult<usize> {
+        let order = num_pages.trailing_zeros() as usize;
+        if order < HUGE_2M_ORDER {
+            return self.alloc_pages(num_pages, PAGE_SIZE);
+        }
+        match self.alloc_huge_pages(order) {
+            Ok(pages) => {
+                if let Some(slot) = self.huge_heap.lock().iter_mut().find(|b| b.is_none()) {
+                    *slot = Some(pages);
+                }
+                Ok(pages.start)
+            }
+            // not aligned, but still usable
+            Err(_) => self.alloc_pages(num_pages, PAGE_SIZE),
+        }
+    }
+
     /// Gives back the allocated region to the byte allocator.
     ///
     /// The region should be allocated by [`alloc`], and `align_pow2` should be
@@ -132,8 +387,59 @@ impl GlobalAllocator {
     /// undefined.
     ///
     /// [`alloc`]: GlobalAllocator::alloc
+    #[cfg_attr(feature = "trace", inline(never))]
     pub fn dealloc(&self, pos: NonNull<u8>, layout: Layout) {
-        self.balloc.lock().dealloc(pos, layout)
+        self.dealloc_front(pos, layout);
+        #[cfg(feature = "trace")]
+        trace::trace(TraceEvent::Dealloc, pos, layout);
+    }
+
+    /// Resizes the region at `pos` allocated with `layout` to `new_size`
+    /// bytes, with the same alignment, and returns its new left bound.
+    ///
+    /// The byte allocator grows or shrinks it in place when it can, so that a
+    /// growing buffer (e.g. a `Vec`) is not copied each time. Otherwise, its
+    /// data is moved to a new region. The region is left as it is if it fails.
+    #[cfg_attr(feature = "trace", inline(never))]
+    pub fn realloc(
+        &self,
+        pos: NonNull<u8>,
+        layout: Layout,
+        new_size: usize,
+    ) -> AllocResult<NonNull<u8>> {
+        let new_layout = Layout::from_size_align(new_size, layout.align())
+            .map_err(|_| AllocError::InvalidParam)?;
+        let res = self.realloc_front(pos, layout, new_layout);
+        #[cfg(feature = "trace")]
+        if let Ok(new_pos) = res {
+            trace::trace(TraceEvent::Dealloc, pos, layout);
+            trace::trace(TraceEvent::Alloc, new_pos, new_layout);
+        }
+        res
+    }
+
+    /// Resizes in the byte allocator, or moves the data. The blocks of the
+    /// per-CPU caches are always moved, the caches keeping them by size.
+    fn realloc_front(
+        &self,
+        pos: NonNull<u8>,
+        layout: Layout,
+        new_layout: Layout,
+    ) -> AllocResult<NonNull<u8>> {
+        #[cfg(not(feature = "percpu-cache"))]
+        {
+            let mut balloc = self.balloc.lock();
+            if balloc.resize_in_place(pos, layout, new_layout.size()) {
+                self.stats.alloc(new_layout.size(), balloc.used_bytes());
+                return Ok(pos);
+            }
+        }
+        let new_pos = self.alloc_front(new_layout)?;
+        unsafe {
+            allocator_realloc::move_block(pos, new_pos, layout.size().min(new_layout.size()));
+        }
+        self.dealloc_front(pos, layout);
+        Ok(new_pos)
     }
 
     /// Allocates contiguous pages.
@@ -142,8 +448,157 @@ impl GlobalAllocator {
     ///
     /// `align_pow2` must be a power of 2, and the returned region bound will be
     /// aligned to it.
+    ///
+    /// If there is not enough memory, the registered [`ReclaimHook`]s are
+    /// asked to free some before trying again.
     pub fn alloc_pages(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
-        self.palloc.lock().alloc_pages(num_pages, align_pow2)
+        let res = self.try_alloc_pages(num_pages, align_pow2).or_else(|err| {
+            if reclaim(num_pages) == 0 {
+                return Err(err);
+            }
+            self.try_alloc_pages(num_pages, align_pow2)
+        });
+        match res {
+            Ok(_) => self.stats.alloc_pages(num_pages),
+            Err(_) => self.stats.alloc_pages_failed(),
+        }
+        res
+    }
+
+    /// Allocates the `num_pages` contiguous pages starting at `base`, e.g.
+    /// for a device which can only access some addresses.
+    ///
+    /// Fails with [`AllocError::MemoryOverlap`] if some of them are already
+    /// in use, and [`AllocError::InvalidParam`] if they are not all in one
+    /// region of the page allocator.
+    pub fn alloc_pages_at(&self, base: usize, num_pages: usize) -> AllocResult<usize> {
+        let mut hotplug = self.hotplug.lock();
+        let res = match hotplug.iter_mut().find(|r| r.contains(base)) {
+            Some(region) => region.palloc.alloc_pages_at(base, num_pages),
+            None => self.palloc.lock().alloc_pages_at(base, num_pages),
+        };
+        match res {
+            Ok(_) => self.stats.alloc_pages(num_pages),
+            Err(_) => self.stats.alloc_pages_failed(),
+        }
+        res
+    }
+
+    /// Allocates `num_pages` contiguous pages in `zone`.
+    ///
+    /// The regions of the page allocator are tried in the fallback order of
+    /// the zone (see [`MemZone`]), and if there is not enough memory in any of
+    /// them, the registered [`ReclaimHook`]s are asked to free some before
+    /// trying again.
+    pub fn alloc_pages_in_zone(&self, zone: MemZone, num_pages: usize) -> AllocResult<usize> {
+        let res = self
+            .try_alloc_pages_in_zone(zone, num_pages)
+            .or_else(|err| {
+                if reclaim(num_pages) == 0 {
+                    return Err(err);
+                }
+                self.try_alloc_pages_in_zone(zone, num_pages)
+            });
+        match res {
+            Ok(_) => self.stats.alloc_pages(num_pages),
+            Err(_) => self.stats.alloc_pages_failed(),
+        }
+        res
+    }
+
+    fn try_alloc_pages_in_zone(&self, zone: MemZone, num_pages: usize) -> AllocResult<usize> {
+        let dma_limit = self.dma_limit.load(Ordering::Relaxed);
+        let mut hotplug = self.hotplug.lock();
+        let mut palloc = self.palloc.lock();
+
+        // the regions the zone can use by rank, the main one being index 0
+        let mut order = [(0, 0); MAX_HOTPLUG_REGIONS + 1];
+        let mut len = 0;
+        let main = RegionZone {
+            node: 0,
+            dma: self.main_end.load(Ordering::Relaxed) <= dma_limit,
+        };
+        let regions = hotplug.iter().enumerate().filter(|(_, r)| r.is_used());
+        let regions = regions.map(|(i, r)| {
+            let region = RegionZone {
+                node: r.node,
+                dma: r.start + r.size <= dma_limit,
+            };
+            (i + 1, region)
+        });
+        for (index, region) in core::iter::once((0, main)).chain(regions) {
+            if let Some(rank) = zone.rank(region) {
+                order[len] = (rank, index);
+                len += 1;
+            }
+        }
+        order[..len].sort_unstable();
+
+        for &(_, index) in &order[..len] {
+            let res = match index {
+                0 => palloc.alloc_pages(num_pages, PAGE_SIZE),
+                _ => hotplug[index - 1].palloc.alloc_pages(num_pages, PAGE_SIZE),
+            };
+            if res.is_ok() {
+                return res;
+            }
+        }
+        Err(AllocError::NoMemory)
+    }
+
+    /// Sets the address below which the regions of the page allocator are in
+    /// the DMA zone, e.g. the virtual address of 4 GiB of physical memory
+    /// for the devices with 32-bit DMA. A region is in it only if it ends
+    /// below the limit, so a region across it should be added as two.
+    ///
+    /// There is no limit by default, all the memory being in the DMA zone.
+    pub fn set_dma_limit(&self, limit: usize) {
+        self.dma_limit.store(limit, Ordering::Relaxed);
+    }
+
+    /// Allocates `1 << order` contiguous pages, aligned to the size of the
+    /// huge pages they can be mapped with: 1 GiB if `order` is at least
+    /// [`HUGE_1G_ORDER`], 2 MiB otherwise.
+    ///
+    /// `order` must be at least [`HUGE_2M_ORDER`]. Fails rather than giving
+    /// pages less aligned.
+    pub fn alloc_huge_pages(&self, order: usize) -> AllocResult<HugePages> {
+        if !(HUGE_2M_ORDER..usize::BITS as usize - PAGE_SIZE.trailing_zeros() as usize)
+            .contains(&order)
+        {
+            return Err(AllocError::InvalidParam);
+        }
+        let pages = HugePages { start: 0, order };
+        let start = self.alloc_pages(pages.num_pages(), pages.page_size())?;
+        Ok(HugePages { start, order })
+    }
+
+    /// Gives back the pages allocated by [`alloc_huge_pages`].
+    ///
+    /// [`alloc_huge_pages`]: GlobalAllocator::alloc_huge_pages
+    pub fn dealloc_huge_pages(&self, pages: HugePages) {
+        self.dealloc_pages(pages.start, pages.num_pages())
+    }
+
+    /// Returns the blocks of huge pages the byte allocator has grown by,
+    /// which can be mapped with huge page table entries.
+    ///
+    /// Only the first [`MAX_HUGE_HEAP_BLOCKS`] ones are recorded.
+    pub fn huge_heap_blocks(&self) -> [Option<HugePages>; MAX_HUGE_HEAP_BLOCKS] {
+        *self.huge_heap.lock()
+    }
+
+    fn try_alloc_pages(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        let res = self.palloc.lock().alloc_pages(num_pages, align_pow2);
+        res.or_else(|err| {
+            // fall back to the regions added at runtime
+            let mut hotplug = self.hotplug.lock();
+            hotplug
+                .iter_mut()
+                .filter(|r| r.is_used())
+                .find_map(|r| r.palloc.alloc_pages(num_pages, align_pow2).ok())
+                .ok_or(err)
+        })
     }
 
     /// Gives back the allocated pages starts from `pos` to the page allocator.
@@ -154,10 +609,81 @@ impl GlobalAllocator {
     ///
     /// [`alloc_pages`]: GlobalAllocator::alloc_pages
     pub fn dealloc_pages(&self, pos: usize, num_pages: usize) {
-        self.palloc.lock().dealloc_pages(pos, num_pages)
+        self.stats.dealloc_pages(num_pages);
+        let mut hotplug = self.hotplug.lock();
+        match hotplug.iter_mut().find(|r| r.contains(pos)) {
+            Some(region) => region.palloc.dealloc_pages(pos, num_pages),
+            None => self.palloc.lock().dealloc_pages(pos, num_pages),
+        }
     }
 
-    /// Returns the number of allocated bytes in the byte allocator.
+    /// Adds the region `[start_vaddr, start_vaddr + size)` to the page
+    /// allocator at runtime, e.g. when memory is hot-plugged.
+    ///
+    /// Unlike [`add_memory`], the pages can be given back with
+    /// [`remove_pages`] once they are all free. The region must be page
+    /// aligned and not overlap with other regions. At most
+    /// [`MAX_HOTPLUG_REGIONS`] regions can be added, none without the feature
+    /// `hotplug`.
+    ///
+    /// [`add_memory`]: GlobalAllocator::add_memory
+    /// [`remove_pages`]: GlobalAllocator::remove_pages
+    pub fn add_pages(&self, start_vaddr: usize, size: usize) -> AllocResult {
+        self.add_pages_to_node(start_vaddr, size, 0)
+    }
+
+    /// Like [`add_pages`], but the region is the memory of the NUMA node
+    /// `node`, to be allocated from with [`MemZone::Node`].
+    ///
+    /// [`add_pages`]: GlobalAllocator::add_pages
+    pub fn add_pages_to_node(&self, start_vaddr: usize, size: usize, node: usize) -> AllocResult {
+        if size == 0 || start_vaddr % PAGE_SIZE != 0 || size % PAGE_SIZE != 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        let mut hotplug = self.hotplug.lock();
+        let overlaps = hotplug
+            .iter()
+            .filter(|r| r.is_used())
+            .any(|r| start_vaddr < r.start + r.size && r.start < start_vaddr + size);
+        if overlaps {
+            return Err(AllocError::MemoryOverlap);
+        }
+        let region = hotplug
+            .iter_mut()
+            .find(|r| !r.is_used())
+            .ok_or(AllocError::NoMemory)?;
+        // fill the free slot in place, its bitmap is too large for the stack
+        region.start = start_vaddr;
+        region.size = size;
+        region.node = node;
+        region.palloc.init(start_vaddr, size);
+        Ok(())
+    }
+
+    /// Removes a region added by [`add_pages`] from the page allocator, with
+    /// the same start address and size.
+    ///
+    /// Fails with [`AllocError::NotAllocated`] if there is no such region,
+    /// and [`AllocError::InvalidParam`] if some of its pages are still in
+    /// use.
+    ///
+    /// [`add_pages`]: GlobalAllocator::add_pages
+    pub fn remove_pages(&self, start_vaddr: usize, size: usize) -> AllocResult {
+        let mut hotplug = self.hotplug.lock();
+        let region = hotplug
+            .iter_mut()
+            .find(|r| r.is_used() && r.start == start_vaddr && r.size == size)
+            .ok_or(AllocError::NotAllocated)?;
+        if region.palloc.used_pages() > 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        region.palloc.reset();
+        region.size = 0;
+        Ok(())
+    }
+
+    /// Returns the number of allocated bytes in the byte allocator, including
+    /// the blocks in the per-CPU caches.
     pub fn used_bytes(&self) -> usize {
         self.balloc.lock().used_bytes()
     }
@@ -169,29 +695,168 @@ impl GlobalAllocator {
 
     /// Returns the number of allocated pages in the page allocator.
     pub fn used_pages(&self) -> usize {
-        self.palloc.lock().used_pages()
+        let hotplug = self.hotplug.lock();
+        let hotplug_pages: usize = hotplug
+            .iter()
+            .filter(|r| r.is_used())
+            .map(|r| r.palloc.used_pages())
+            .sum();
+        self.palloc.lock().used_pages() + hotplug_pages
     }
 
     /// Returns the number of available pages in the page allocator.
     pub fn available_pages(&self) -> usize {
-        self.palloc.lock().available_pages()
+        let hotplug = self.hotplug.lock();
+        let hotplug_pages: usize = hotplug
+            .iter()
+            .filter(|r| r.is_used())
+            .map(|r| r.palloc.available_pages())
+            .sum();
+        self.palloc.lock().available_pages() + hotplug_pages
+    }
+
+    /// Returns the statistics of the allocations since boot.
+    pub fn stats(&self) -> AllocStats {
+        self.stats.snapshot()
     }
 }
 
+// Traced here rather than in `GlobalAllocator::alloc`, so that the caller is
+// the code using the Rust heap.
 unsafe impl GlobalAlloc for GlobalAllocator {
+    #[cfg_attr(feature = "trace", inline(never))]
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        if let Ok(ptr) = GlobalAllocator::alloc(self, layout) {
+        if let Ok(ptr) = self.alloc_front(layout) {
+            #[cfg(feature = "trace")]
+            trace::trace(TraceEvent::Alloc, ptr, layout);
             ptr.as_ptr()
         } else {
             alloc::alloc::handle_alloc_error(layout)
         }
     }
 
+    #[cfg_attr(feature = "trace", inline(never))]
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        GlobalAllocator::dealloc(self, NonNull::new(ptr).expect("dealloc null ptr"), layout)
+        let pos = NonNull::new(ptr).expect("dealloc null ptr");
+        self.dealloc_front(pos, layout);
+        #[cfg(feature = "trace")]
+        trace::trace(TraceEvent::Dealloc, pos, layout);
+    }
+
+    #[cfg_attr(feature = "trace", inline(never))]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let pos = NonNull::new(ptr).expect("realloc null ptr");
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        match self.realloc_front(pos, layout, new_layout) {
+            Ok(new_pos) => {
+                #[cfg(feature = "trace")]
+                {
+                    trace::trace(TraceEvent::Dealloc, pos, layout);
+                    trace::trace(TraceEvent::Alloc, new_pos, new_layout);
+                }
+                new_pos.as_ptr()
+            }
+            Err(_) => core::ptr::null_mut(),
+        }
+    }
+}
+
+/// A function called when the page allocator runs out of memory, which should
+/// free about `num_pages` pages (e.g., by shrinking a cache or deflating a
+/// memory balloon) and return the number of pages actually freed.
+///
+/// It may be called with the byte allocator locked, so it must not allocate
+/// memory from the heap, and should not wait for locks that the allocating
+/// code may hold.
+pub type ReclaimHook = fn(num_pages: usize) -> usize;
+
+/// Maximum number of registered [`ReclaimHook`]s.
+pub const MAX_RECLAIM_HOOKS: usize = 8;
+
+static RECLAIM_HOOKS: SpinNoIrq<[Option<ReclaimHook>; MAX_RECLAIM_HOOKS]> =
+    SpinNoIrq::new([None; MAX_RECLAIM_HOOKS]);
+
+/// Registers a function to free memory when the page allocator runs out of
+/// memory.
+///
+/// Returns `false` if there are already [`MAX_RECLAIM_HOOKS`] hooks.
+pub fn register_reclaim_hook(hook: ReclaimHook) -> bool {
+    let mut hooks = RECLAIM_HOOKS.lock();
+    match hooks.iter_mut().find(|h| h.is_none()) {
+        Some(slot) => {
+            *slot = Some(hook);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Calls the reclaim hooks until `num_pages` pages are freed, and returns the
+/// number of pages freed.
+fn reclaim(num_pages: usize) -> usize {
+    let hooks = *RECLAIM_HOOKS.lock();
+    let mut freed = 0;
+    for hook in hooks.into_iter().flatten() {
+        if freed >= num_pages {
+            break;
+        }
+        freed += hook(num_pages - freed);
+    }
+    if freed > 0 {
+        debug!("reclaimed {} pages", freed);
+    }
+    freed
+}
+
+/// A function called when an allocation of `layout` from the heap fails, even
+/// after the [`ReclaimHook`]s, which should free memory (e.g., by dropping a
+/// cache or shrinking a slab) and return whether it freed any, for the
+/// allocation to be tried again.
+///
+/// Unlike a [`ReclaimHook`], it is called without the locks of the allocator
+/// held, so it can free memory to the heap. It must not allocate memory from
+/// the heap itself.
+pub type OomHandler = fn(layout: Layout) -> bool;
+
+/// Maximum number of registered [`OomHandler`]s.
+pub const MAX_OOM_HANDLERS: usize = 8;
+
+/// Number of times an allocation is tried again after the [`OomHandler`]s
+/// freed memory, before it fails.
+const MAX_OOM_RETRIES: usize = 4;
+
+static OOM_HANDLERS: SpinNoIrq<[Option<OomHandler>; MAX_OOM_HANDLERS]> =
+    SpinNoIrq::new([None; MAX_OOM_HANDLERS]);
+
+/// Registers a function to free memory before an allocation from the heap
+/// fails, and the kernel panics in the allocation error handler.
+///
+/// The handlers are called in the order they were registered. Returns `false`
+/// if there are already [`MAX_OOM_HANDLERS`] handlers.
+pub fn set_oom_handler(handler: OomHandler) -> bool {
+    let mut handlers = OOM_HANDLERS.lock();
+    match handlers.iter_mut().find(|h| h.is_none()) {
+        Some(slot) => {
+            *slot = Some(handler);
+            true
+        }
+        None => false,
     }
 }
 
+/// Calls all the OOM handlers, and returns whether any of them freed memory.
+fn handle_oom(layout: Layout) -> bool {
+    let handlers = *OOM_HANDLERS.lock();
+    let mut freed = false;
+    for handler in handlers.into_iter().flatten() {
+        freed |= handler(layout);
+    }
+    if freed {
+        debug!("OOM handlers freed memory for {:?}", layout);
+    }
+    freed
+}
+
 #[cfg_attr(all(target_os = "none", not(test)), global_allocator)]
 static GLOBAL_ALLOCATOR: GlobalAllocator = GlobalAllocator::new();
 
@@ -217,6 +882,19 @@ pub fn global_init(start_vaddr: usize, size: usize) {
     GLOBAL_ALLOCATOR.init(start_vaddr, size);
 }
 
+/// Gives the blocks cached by the current CPU back to the global allocator,
+/// see [`GlobalAllocator::flush_cpu_cache`].
+#[cfg(feature = "percpu-cache")]
+pub fn flush_cpu_cache() {
+    GLOBAL_ALLOCATOR.flush_cpu_cache();
+}
+
+/// Returns the statistics of the global allocator: the peak usage, the
+/// number of allocations by size, and the failures.
+pub fn stats() -> AllocStats {
+    GLOBAL_ALLOCATOR.stats()
+}
+
 /// Add the given memory region to the global allocator.
 ///
 /// Users should ensure that the region is valid and not being used by others,
@@ -231,3 +909,30 @@ pub fn global_add_memory(start_vaddr: usize, size: usize) -> AllocResult {
     );
     GLOBAL_ALLOCATOR.add_memory(start_vaddr, size)
 }
+
+/// Initializes the global allocator with the memory left free by the early
+/// allocator `early`, which must not be used any more.
+///
+/// The largest free range initializes the allocator, and the others are
+/// added to it. The memory of the live early allocations is kept out of the
+/// allocator, so they stay valid where they are, but they must not be freed
+/// to it.
+///
+/// This function should be called only once, instead of [`global_init`].
+pub fn global_init_from_early(early: &EarlyAllocator<PAGE_SIZE>) -> AllocResult {
+    for r in early.live_ranges() {
+        debug!("  pin early allocations at: [{:#x}, {:#x})", r.start, r.end);
+    }
+    let largest = early
+        .free_ranges()
+        .max_by_key(|r| r.len())
+        .ok_or(AllocError::NoMemory)?;
+    if largest.len() <= MIN_HEAP_SIZE {
+        return Err(AllocError::NoMemory);
+    }
+    global_init(largest.start, largest.len());
+    for r in early.free_ranges().filter(|r| *r != largest) {
+        global_add_memory(r.start, r.len())?;
+    }
+    Ok(())
+}
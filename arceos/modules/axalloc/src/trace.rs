@@ -0,0 +1,54 @@
+//! Hooks on the allocations, to build leak detectors and heap profilers.
+
+use core::alloc::Layout;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// The operation given to a [`TraceHook`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceEvent {
+    Alloc,
+    Dealloc,
+}
+
+/// A function called on every allocation and deallocation of the byte
+/// allocator, with the memory, its layout, and the address of the caller.
+///
+/// The caller is the return address into the code calling the allocator,
+/// found with the frame pointers (which `make` enables with the feature
+/// `alloc-trace`), or 0 if they cannot be followed.
+///
+/// It is called without the locks of the allocator held, but it must not
+/// allocate memory from the heap, which would call it again.
+pub type TraceHook = fn(event: TraceEvent, ptr: NonNull<u8>, layout: Layout, caller: usize);
+
+static TRACE_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Sets the function called on every allocation and deallocation, or removes
+/// it with `None`, and returns the previous one.
+pub fn set_trace_hook(hook: Option<TraceHook>) -> Option<TraceHook> {
+    let new = hook.map_or(core::ptr::null_mut(), |hook| hook as *mut ());
+    let old = TRACE_HOOK.swap(new, Ordering::AcqRel);
+    // SAFETY: only `TraceHook`s are stored
+    (!old.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), TraceHook>(old) })
+}
+
+/// Calls the hook, if any. It must be inlined into the function of the
+/// allocator called by the traced code, which must not be inlined.
+#[inline(always)]
+pub(crate) fn trace(event: TraceEvent, ptr: NonNull<u8>, layout: Layout) {
+    let hook = TRACE_HOOK.load(Ordering::Acquire);
+    if hook.is_null() && !cfg!(feature = "leak") {
+        return;
+    }
+    // the return address of the function of the allocator
+    let caller = axsyms::return_address(1).unwrap_or(0);
+    #[cfg(feature = "leak")]
+    crate::leak::record(event, ptr.as_ptr() as usize, layout.size(), caller);
+    if hook.is_null() {
+        return;
+    }
+    // SAFETY: only `TraceHook`s are stored
+    let hook = unsafe { core::mem::transmute::<*mut (), TraceHook>(hook) };
+    hook(event, ptr, layout, caller);
+}
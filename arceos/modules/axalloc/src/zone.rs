@@ -0,0 +1,43 @@
+//! Memory zones of the page allocator, see [`MemZone`].
+
+/// A zone of memory, which [`GlobalAllocator::alloc_pages_in_zone`]
+/// allocates pages from.
+///
+/// The regions of the page allocator belong to a NUMA node (node 0 unless
+/// added by [`GlobalAllocator::add_pages_to_node`]), and are in the DMA zone
+/// if they end below the limit set by [`GlobalAllocator::set_dma_limit`].
+///
+/// [`GlobalAllocator::alloc_pages_in_zone`]: crate::GlobalAllocator::alloc_pages_in_zone
+/// [`GlobalAllocator::add_pages_to_node`]: crate::GlobalAllocator::add_pages_to_node
+/// [`GlobalAllocator::set_dma_limit`]: crate::GlobalAllocator::set_dma_limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemZone {
+    /// The memory below the DMA limit, for the devices which cannot access
+    /// all the memory. It never falls back to other memory.
+    Dma,
+    /// Any memory, preferably above the DMA limit so that it is kept for the
+    /// devices.
+    Normal,
+    /// The memory of a NUMA node, then of the other nodes from the nearest
+    /// one (by node number), preferably above the DMA limit in each.
+    Node(usize),
+}
+
+/// Where a region of the page allocator is.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RegionZone {
+    pub node: usize,
+    pub dma: bool,
+}
+
+impl MemZone {
+    /// Returns the rank of `region` in the fallback order of the zone, the
+    /// lowest first, or `None` if the zone cannot use it.
+    pub(crate) fn rank(self, region: RegionZone) -> Option<usize> {
+        match self {
+            Self::Dma => region.dma.then_some(0),
+            Self::Normal => Some(region.dma as usize),
+            Self::Node(node) => Some(2 * node.abs_diff(region.node) + region.dma as usize),
+        }
+    }
+}
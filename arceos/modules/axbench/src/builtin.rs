@@ -0,0 +1,131 @@
+//! The built-in benches.
+
+use alloc::alloc::{alloc, dealloc, Layout};
+use alloc::vec::Vec;
+use core::hint::black_box;
+
+use crate::{bench, Config, Summary};
+
+/// The sizes of the blocks allocated by the allocator benches.
+const ALLOC_BENCHES: [(&str, usize); 3] =
+    [("alloc-16", 16), ("alloc-256", 256), ("alloc-4096", 4096)];
+
+/// Runs the built-in benches with `config`, printing their summaries as they
+/// complete, and returns them.
+pub fn run_builtin(config: &Config) -> Vec<Summary> {
+    let mut summaries = Vec::new();
+    let mut run = |summary: Summary| {
+        axlog::ax_println!("{}", summary);
+        summaries.push(summary);
+    };
+
+    // an allocation and a deallocation of the global allocator
+    for (name, size) in ALLOC_BENCHES {
+        let layout = Layout::from_size_align(size, 8).unwrap();
+        run(bench(name, config, |b| {
+            b.iter(|| unsafe {
+                let ptr = alloc(layout);
+                assert!(!ptr.is_null());
+                dealloc(black_box(ptr), layout);
+            })
+        }));
+    }
+
+    // a trap to the kernel and back, as done by the syscalls
+    #[cfg(target_os = "none")]
+    run(bench("trap", config, |b| b.iter(trap)));
+
+    #[cfg(feature = "multitask")]
+    {
+        run(tasks::yield_roundtrip(config));
+        run(tasks::ipc_roundtrip(config));
+    }
+    summaries
+}
+
+/// Traps to the kernel with a breakpoint, which the trap handler skips.
+#[cfg(target_os = "none")]
+fn trap() {
+    unsafe {
+        #[cfg(target_arch = "x86_64")]
+        core::arch::asm!("int3");
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        core::arch::asm!("ebreak");
+        #[cfg(target_arch = "aarch64")]
+        core::arch::asm!("brk #0");
+    }
+}
+
+#[cfg(feature = "multitask")]
+mod tasks {
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+    use axtask::WaitQueue;
+
+    use crate::{bench, Config, Summary};
+
+    /// Yields to another task, which yields back: two context switches per
+    /// iteration.
+    pub fn yield_roundtrip(config: &Config) -> Summary {
+        let stop = Arc::new(AtomicBool::new(false));
+        let partner = axtask::spawn({
+            let stop = stop.clone();
+            move || {
+                while !stop.load(Ordering::Acquire) {
+                    axtask::yield_now();
+                }
+            }
+        });
+        let summary = bench("yield-roundtrip", config, |b| b.iter(axtask::yield_now));
+        stop.store(true, Ordering::Release);
+        partner.join();
+        summary
+    }
+
+    struct PingPong {
+        pings: AtomicU64,
+        pongs: AtomicU64,
+        ping_wq: WaitQueue,
+        pong_wq: WaitQueue,
+        stop: AtomicBool,
+    }
+
+    /// Sends a message to another task blocked waiting for it, and waits for
+    /// its reply: two wakeups per iteration.
+    pub fn ipc_roundtrip(config: &Config) -> Summary {
+        let pp = Arc::new(PingPong {
+            pings: AtomicU64::new(0),
+            pongs: AtomicU64::new(0),
+            ping_wq: WaitQueue::new(),
+            pong_wq: WaitQueue::new(),
+            stop: AtomicBool::new(false),
+        });
+        let partner = axtask::spawn({
+            let pp = pp.clone();
+            move || loop {
+                pp.ping_wq.wait_until(|| {
+                    pp.stop.load(Ordering::Acquire)
+                        || pp.pings.load(Ordering::Acquire) > pp.pongs.load(Ordering::Relaxed)
+                });
+                if pp.stop.load(Ordering::Acquire) {
+                    break;
+                }
+                pp.pongs.fetch_add(1, Ordering::Release);
+                pp.pong_wq.notify_one(true);
+            }
+        });
+        let summary = bench("ipc-roundtrip", config, |b| {
+            b.iter(|| {
+                let ping = pp.pings.fetch_add(1, Ordering::Release) + 1;
+                pp.ping_wq.notify_one(true);
+                pp.pong_wq
+                    .wait_until(|| pp.pongs.load(Ordering::Acquire) >= ping);
+            })
+        });
+        pp.stop.store(true, Ordering::Release);
+        pp.ping_wq.notify_one(true);
+        partner.join();
+        summary
+    }
+}
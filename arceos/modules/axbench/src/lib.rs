@@ -0,0 +1,133 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) in-kernel microbenchmarks.
+//!
+//! [`bench`] measures a routine the way [criterion] does:
+//!
+//! 1. it runs the routine for [`Config::warmup`], doubling its iterations
+//!    each time, to warm the caches up and estimate the time it takes;
+//! 2. then takes [`Config::samples`] samples, of as many iterations as last
+//!    about [`Config::sample_time`] each;
+//! 3. and summarizes the cycles per iteration of the samples in a
+//!    [`Summary`]: their mean, median, standard deviation, extremes and
+//!    outliers.
+//!
+//! The cycles are counted by the PMU of the CPU ([`axhal::pmu::cycles`]), and
+//! the time by the monotonic clock.
+//!
+//! The built-in benches ([`run_builtin`]) measure the latencies of the
+//! allocator, of traps (the entry and exit of syscalls) and, with the
+//! `multitask` feature, of context switches and of IPC between tasks. They
+//! print their summaries one per line, so that the results of two releases
+//! can be compared (see [`Summary::change_from`]). They are meant to be run
+//! on one CPU, with the log level at `info` or lower.
+//!
+//! [criterion]: https://github.com/bheisler/criterion.rs
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+#[cfg(test)]
+mod tests;
+
+mod builtin;
+mod stats;
+
+use alloc::vec::Vec;
+use core::hint::black_box;
+use core::time::Duration;
+
+use axhal::{pmu, time::monotonic_time_nanos};
+
+pub use self::builtin::run_builtin;
+pub use self::stats::Summary;
+
+/// The most iterations of a sample.
+const MAX_ITERS: u64 = 1 << 32;
+
+/// The configuration of the benchmarks.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The time spent warming up, before taking the samples.
+    pub warmup: Duration,
+    /// The time each sample should last.
+    pub sample_time: Duration,
+    /// The number of samples.
+    pub samples: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            warmup: Duration::from_millis(100),
+            sample_time: Duration::from_millis(10),
+            samples: 50,
+        }
+    }
+}
+
+/// Times the iterations of a sample, given to the routine of [`bench`].
+pub struct Bencher {
+    iters: u64,
+    cycles: u64,
+    nanos: u64,
+}
+
+impl Bencher {
+    /// Returns the number of iterations of the sample.
+    pub fn iters(&self) -> u64 {
+        self.iters
+    }
+
+    /// Times the iterations of `routine`. Its result is dropped outside of
+    /// the timing, and kept from being optimized out.
+    pub fn iter<R>(&mut self, mut routine: impl FnMut() -> R) {
+        self.iter_many(|iters| {
+            for _ in 0..iters {
+                black_box(routine());
+            }
+        })
+    }
+
+    /// Times `routine`, given the number of iterations to run, e.g. to run
+    /// them with another task.
+    pub fn iter_many(&mut self, routine: impl FnOnce(u64)) {
+        let (start, start_nanos) = (pmu::cycles(), monotonic_time_nanos());
+        routine(self.iters);
+        self.cycles = pmu::cycles().wrapping_sub(start);
+        self.nanos = monotonic_time_nanos() - start_nanos;
+    }
+}
+
+/// Measures the routine `f`, timing its iterations with the [`Bencher`] it
+/// is given, and returns the summary of its samples.
+pub fn bench(name: &'static str, config: &Config, mut f: impl FnMut(&mut Bencher)) -> Summary {
+    let mut b = Bencher {
+        iters: 1,
+        cycles: 0,
+        nanos: 0,
+    };
+
+    let deadline = monotonic_time_nanos() + config.warmup.as_nanos() as u64;
+    let (mut warmup_iters, mut warmup_nanos) = (0, 0);
+    loop {
+        f(&mut b);
+        warmup_iters += b.iters;
+        warmup_nanos += b.nanos;
+        if monotonic_time_nanos() >= deadline {
+            break;
+        }
+        b.iters = (b.iters * 2).min(MAX_ITERS);
+    }
+
+    let sample_nanos = config.sample_time.as_nanos() as u64;
+    b.iters = (sample_nanos as u128 * warmup_iters as u128 / warmup_nanos.max(1) as u128)
+        .clamp(1, MAX_ITERS as u128) as u64;
+    let mut samples = Vec::with_capacity(config.samples);
+    let mut nanos = 0;
+    for _ in 0..config.samples {
+        f(&mut b);
+        samples.push(b.cycles / b.iters);
+        nanos += b.nanos;
+    }
+    Summary::from_samples(name, &mut samples, b.iters, nanos)
+}
@@ -0,0 +1,130 @@
+//! The statistical summary of the samples of a benchmark.
+
+use core::fmt;
+
+/// The summary of the samples of a benchmark, in cycles per iteration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+    /// The name of the benchmark.
+    pub name: &'static str,
+    /// The number of samples.
+    pub samples: usize,
+    /// The number of iterations of each sample.
+    pub iters_per_sample: u64,
+    /// The mean of the samples.
+    pub mean: u64,
+    /// The median of the samples.
+    pub median: u64,
+    /// The standard deviation of the samples.
+    pub stddev: u64,
+    /// The fastest sample.
+    pub min: u64,
+    /// The slowest sample.
+    pub max: u64,
+    /// The number of samples beyond 1.5 times the interquartile range from
+    /// the quartiles, e.g. the ones interrupted.
+    pub outliers: usize,
+    /// The mean time of an iteration, in picoseconds.
+    pub mean_picos: u64,
+}
+
+impl Summary {
+    /// Summarizes the `samples` (cycles per iteration) of the benchmark
+    /// `name`, of `iters_per_sample` iterations each, which took `nanos` in
+    /// total.
+    pub fn from_samples(
+        name: &'static str,
+        samples: &mut [u64],
+        iters_per_sample: u64,
+        nanos: u64,
+    ) -> Self {
+        let n = samples.len();
+        if n == 0 {
+            return Self {
+                name,
+                samples: 0,
+                iters_per_sample,
+                mean: 0,
+                median: 0,
+                stddev: 0,
+                min: 0,
+                max: 0,
+                outliers: 0,
+                mean_picos: 0,
+            };
+        }
+        samples.sort_unstable();
+        let median = (samples[(n - 1) / 2] + samples[n / 2]) / 2;
+        let mean = samples.iter().map(|&s| s as u128).sum::<u128>() / n as u128;
+        let variance = samples
+            .iter()
+            .map(|&s| (s as u128).abs_diff(mean).pow(2))
+            .sum::<u128>()
+            / n as u128;
+
+        let (q1, q3) = (samples[n / 4], samples[(3 * n) / 4]);
+        let fence = (q3 - q1) * 3 / 2;
+        let (low, high) = (q1.saturating_sub(fence), q3.saturating_add(fence));
+        let outliers = samples.iter().filter(|&&s| s < low || s > high).count();
+
+        let iters = (n as u128 * iters_per_sample as u128).max(1);
+        Self {
+            name,
+            samples: n,
+            iters_per_sample,
+            mean: mean as u64,
+            median,
+            stddev: isqrt(variance) as u64,
+            min: samples[0],
+            max: samples[n - 1],
+            outliers,
+            mean_picos: (nanos as u128 * 1000 / iters) as u64,
+        }
+    }
+
+    /// Returns the change of the mean from the mean `baseline` (e.g. of the
+    /// previous release), in percent.
+    pub fn change_from(&self, baseline: u64) -> i64 {
+        if baseline == 0 {
+            return 0;
+        }
+        (self.mean as i128 - baseline as i128) as i64 * 100 / baseline as i64
+    }
+}
+
+/// Returns the integer square root of `x`.
+fn isqrt(x: u128) -> u128 {
+    if x < 2 {
+        return x;
+    }
+    // Newton's method, from above
+    let mut r = 1 << ((128 - x.leading_zeros()).div_ceil(2));
+    loop {
+        let next = (r + x / r) / 2;
+        if next >= r {
+            return r;
+        }
+        r = next;
+    }
+}
+
+/// One line per benchmark, to be compared between releases.
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<16} {:>8} cycles {:>6}.{:03} ns  median {} stddev {} min {} max {} outliers {} ({} x {})",
+            self.name,
+            self.mean,
+            self.mean_picos / 1000,
+            self.mean_picos % 1000,
+            self.median,
+            self.stddev,
+            self.min,
+            self.max,
+            self.outliers,
+            self.samples,
+            self.iters_per_sample,
+        )
+    }
+}
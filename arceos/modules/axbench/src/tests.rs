@@ -0,0 +1,24 @@
+use crate::stats::Summary;
+
+#[test]
+fn test_summary() {
+    let mut samples = [12, 10, 11, 10, 13, 10, 11, 12, 100, 11];
+    let s = Summary::from_samples("test", &mut samples, 1000, 10_000);
+    assert_eq!((s.samples, s.iters_per_sample), (10, 1000));
+    assert_eq!((s.min, s.median, s.max), (10, 11, 100));
+    assert_eq!(s.mean, 20);
+    assert_eq!(s.stddev, 26);
+    assert_eq!(s.outliers, 1);
+    // 10 µs for 10000 iterations
+    assert_eq!(s.mean_picos, 1000);
+    assert!(format!("{}", s).starts_with("test                   20 cycles      1.000 ns"));
+
+    assert_eq!(s.change_from(16), 25);
+    assert_eq!(s.change_from(25), -20);
+    assert_eq!(s.change_from(0), 0);
+
+    let s = Summary::from_samples("empty", &mut [], 1, 0);
+    assert_eq!((s.samples, s.mean), (0, 0));
+    let s = Summary::from_samples("even", &mut [4, 1, 3, 2], 1, 0);
+    assert_eq!((s.median, s.outliers), (2, 0));
+}
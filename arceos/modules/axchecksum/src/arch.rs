@@ -0,0 +1,129 @@
+//! The CRC instructions of the CPU, detected at the first use.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+const CRC32: u8 = 1 << 0;
+const CRC32C: u8 = 1 << 1;
+const UNKNOWN: u8 = 1 << 7;
+
+static FEATURES: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+fn features() -> u8 {
+    let features = FEATURES.load(Ordering::Relaxed);
+    if features != UNKNOWN {
+        return features;
+    }
+    let features = detect();
+    FEATURES.store(features, Ordering::Relaxed);
+    features
+}
+
+pub fn has_crc32() -> bool {
+    features() & CRC32 != 0
+}
+
+pub fn has_crc32c() -> bool {
+    features() & CRC32C != 0
+}
+
+/// Updates the (inverted) CRC32 with `data`, or returns `None` without the
+/// instructions.
+pub fn crc32(crc: u32, data: &[u8]) -> Option<u32> {
+    #[cfg(target_arch = "aarch64")]
+    if has_crc32() {
+        return Some(unsafe { aarch64::crc32(crc, data) });
+    }
+    let _ = (crc, data);
+    None
+}
+
+/// Updates the (inverted) CRC32C with `data`, or returns `None` without the
+/// instructions.
+pub fn crc32c(crc: u32, data: &[u8]) -> Option<u32> {
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    if has_crc32c() {
+        #[cfg(target_arch = "x86_64")]
+        return Some(x86_64::crc32c(crc, data));
+        #[cfg(target_arch = "aarch64")]
+        return Some(unsafe { aarch64::crc32c(crc, data) });
+    }
+    let _ = (crc, data);
+    None
+}
+
+#[cfg(target_arch = "x86_64")]
+fn detect() -> u8 {
+    // SSE4.2, `__cpuid` is safe in newer Rust
+    #[allow(unused_unsafe)]
+    let ecx = unsafe { core::arch::x86_64::__cpuid(1) }.ecx;
+    if ecx & (1 << 20) != 0 {
+        CRC32C
+    } else {
+        0
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn detect() -> u8 {
+    let isar0: u64;
+    unsafe { core::arch::asm!("mrs {}, ID_AA64ISAR0_EL1", out(reg) isar0) };
+    if (isar0 >> 16) & 0xf != 0 {
+        CRC32 | CRC32C
+    } else {
+        0
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn detect() -> u8 {
+    0
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86_64 {
+    use core::arch::asm;
+
+    /// The `crc32` instruction of SSE4.2, which computes CRC32C only.
+    pub fn crc32c(crc: u32, data: &[u8]) -> u32 {
+        let mut crc = crc as u64;
+        let mut chunks = data.chunks_exact(8);
+        for c in &mut chunks {
+            let v = u64::from_le_bytes(c.try_into().unwrap());
+            unsafe {
+                asm!("crc32 {0}, {1}", inout(reg) crc, in(reg) v, options(pure, nomem, nostack))
+            };
+        }
+        let mut crc = crc as u32;
+        for &b in chunks.remainder() {
+            unsafe {
+                asm!("crc32 {0:e}, {1}", inout(reg) crc, in(reg_byte) b, options(pure, nomem, nostack))
+            };
+        }
+        crc
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64 {
+    use core::arch::asm;
+
+    macro_rules! crc_fn {
+        ($name:ident, $dword:literal, $byte:literal) => {
+            #[target_feature(enable = "crc")]
+            pub unsafe fn $name(mut crc: u32, data: &[u8]) -> u32 {
+                let mut chunks = data.chunks_exact(8);
+                for c in &mut chunks {
+                    let v = u64::from_le_bytes(c.try_into().unwrap());
+                    asm!(concat!($dword, " {0:w}, {0:w}, {1:x}"), inout(reg) crc, in(reg) v, options(pure, nomem, nostack));
+                }
+                for &b in chunks.remainder() {
+                    asm!(concat!($byte, " {0:w}, {0:w}, {1:w}"), inout(reg) crc, in(reg) b as u32, options(pure, nomem, nostack));
+                }
+                crc
+            }
+        };
+    }
+
+    crc_fn!(crc32, "crc32x", "crc32b");
+    crc_fn!(crc32c, "crc32cx", "crc32cb");
+}
@@ -0,0 +1,138 @@
+//! CRC32 and CRC32C, reflected, with the tables of the software fallback.
+
+use crate::arch;
+
+const CRC32_POLY: u32 = 0xedb8_8320;
+const CRC32C_POLY: u32 = 0x82f6_3b78;
+
+/// The tables to process 8 bytes at a time ("slicing-by-8"): the table `k`
+/// gives the CRC of a byte followed by `k` zero bytes.
+const fn make_tables(poly: u32) -> [[u32; 256]; 8] {
+    let mut tables = [[0u32; 256]; 8];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ poly
+            } else {
+                crc >> 1
+            };
+            j += 1;
+        }
+        tables[0][i] = crc;
+        i += 1;
+    }
+    let mut k = 1;
+    while k < 8 {
+        let mut i = 0;
+        while i < 256 {
+            let prev = tables[k - 1][i];
+            tables[k][i] = (prev >> 8) ^ tables[0][(prev & 0xff) as usize];
+            i += 1;
+        }
+        k += 1;
+    }
+    tables
+}
+
+static CRC32_TABLES: [[u32; 256]; 8] = make_tables(CRC32_POLY);
+static CRC32C_TABLES: [[u32; 256]; 8] = make_tables(CRC32C_POLY);
+
+/// Updates the (inverted) `crc` with `data`, in software.
+pub(crate) fn update_soft(tables: &[[u32; 256]; 8], mut crc: u32, data: &[u8]) -> u32 {
+    let t = |k: usize, v: u32| tables[k][(v & 0xff) as usize];
+    let mut chunks = data.chunks_exact(8);
+    for c in &mut chunks {
+        let lo = u32::from_le_bytes(c[..4].try_into().unwrap()) ^ crc;
+        let hi = u32::from_le_bytes(c[4..].try_into().unwrap());
+        crc = t(7, lo)
+            ^ t(6, lo >> 8)
+            ^ t(5, lo >> 16)
+            ^ t(4, lo >> 24)
+            ^ t(3, hi)
+            ^ t(2, hi >> 8)
+            ^ t(1, hi >> 16)
+            ^ t(0, hi >> 24);
+    }
+    for &b in chunks.remainder() {
+        crc = t(0, crc ^ b as u32) ^ (crc >> 8);
+    }
+    crc
+}
+
+pub(crate) fn crc32_soft(crc: u32, data: &[u8]) -> u32 {
+    update_soft(&CRC32_TABLES, crc, data)
+}
+
+pub(crate) fn crc32c_soft(crc: u32, data: &[u8]) -> u32 {
+    update_soft(&CRC32C_TABLES, crc, data)
+}
+
+/// A streaming CRC32 (IEEE 802.3, of zlib).
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32 {
+    state: u32,
+}
+
+impl Crc32 {
+    pub const fn new() -> Self {
+        Self { state: !0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.state = arch::crc32(self.state, data).unwrap_or_else(|| crc32_soft(self.state, data));
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.state
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A streaming CRC32C (Castagnoli).
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32c {
+    state: u32,
+}
+
+impl Crc32c {
+    pub const fn new() -> Self {
+        Self { state: !0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.state =
+            arch::crc32c(self.state, data).unwrap_or_else(|| crc32c_soft(self.state, data));
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.state
+    }
+}
+
+impl Default for Crc32c {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Computes the CRC32 of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// Computes the CRC32C of `data`.
+pub fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = Crc32c::new();
+    crc.update(data);
+    crc.finish()
+}
@@ -0,0 +1,66 @@
+//! The Internet checksum (RFC 1071): the ones' complement of the ones'
+//! complement sum of the 16-bit big-endian words.
+
+/// Folds a sum to 16 bits, with the end-around carries.
+fn fold(mut sum: u64) -> u16 {
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+/// Returns the ones' complement sum of `data`, from an even offset.
+///
+/// The sum is the same in any byte order, but swapped (RFC 1071, 2.B), so it
+/// adds native 64-bit words, and swaps the result back if needed.
+fn sum(data: &[u8]) -> u16 {
+    let mut acc = 0u64;
+    let mut add = |w: u64| {
+        let (s, carry) = acc.overflowing_add(w);
+        acc = s + carry as u64;
+    };
+    let mut chunks = data.chunks_exact(8);
+    for c in &mut chunks {
+        add(u64::from_ne_bytes(c.try_into().unwrap()));
+    }
+    // the odd byte last is the high byte of a word
+    let mut tail = [0; 8];
+    tail[..chunks.remainder().len()].copy_from_slice(chunks.remainder());
+    add(u64::from_ne_bytes(tail));
+    u16::from_be_bytes(fold(acc).to_ne_bytes())
+}
+
+/// A streaming Internet checksum, of data given in parts of any sizes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InternetChecksum {
+    sum: u16,
+    /// Whether an odd number of bytes were added.
+    odd: bool,
+}
+
+impl InternetChecksum {
+    pub const fn new() -> Self {
+        Self { sum: 0, odd: false }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        let mut s = sum(data);
+        if self.odd {
+            s = s.swap_bytes();
+        }
+        self.sum = fold(self.sum as u64 + s as u64);
+        self.odd ^= data.len() % 2 == 1;
+    }
+
+    /// Returns the checksum, to be written big-endian.
+    pub fn finish(&self) -> u16 {
+        !self.sum
+    }
+}
+
+/// Computes the Internet checksum of `data`, to be written big-endian.
+///
+/// A header including its checksum sums to 0, i.e. gives 0 here.
+pub fn internet_checksum(data: &[u8]) -> u16 {
+    !sum(data)
+}
@@ -0,0 +1,36 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) checksums.
+//!
+//! It provides:
+//!
+//! - CRC32 ([`crc32`], [`Crc32`]), of zlib, Ethernet, gzip...
+//! - CRC32C ([`crc32c`], [`Crc32c`]), the Castagnoli CRC of iSCSI, ext4,
+//!   btrfs, SCTP...
+//! - Adler-32 ([`adler32`], [`Adler32`]), of zlib.
+//! - The Internet checksum ([`internet_checksum`], [`InternetChecksum`]) of
+//!   IP, TCP, UDP and ICMP (RFC 1071).
+//!
+//! The CRCs use the CRC instructions of the CPU if it has them, as found at
+//! the first use: SSE4.2 on x86_64 (CRC32C only), and the CRC extension on
+//! AArch64 (both). Otherwise, they are computed with tables, 8 bytes at a
+//! time. The Internet checksum adds 64-bit words. No FP/SIMD registers are
+//! used, so they can run in interrupt handlers.
+
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(test)]
+mod tests;
+
+mod adler;
+mod arch;
+mod crc;
+mod inet;
+
+pub use self::adler::{adler32, Adler32};
+pub use self::crc::{crc32, crc32c, Crc32, Crc32c};
+pub use self::inet::{internet_checksum, InternetChecksum};
+
+/// Whether the CRC32 and CRC32C are computed with CPU instructions, as
+/// `(crc32, crc32c)`.
+pub fn crc_accelerated() -> (bool, bool) {
+    (arch::has_crc32(), arch::has_crc32c())
+}
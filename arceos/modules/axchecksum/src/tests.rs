@@ -0,0 +1,108 @@
+use crate::crc::{crc32_soft, crc32c_soft};
+use crate::{adler32, crc32, crc32c, internet_checksum, Adler32, Crc32, Crc32c, InternetChecksum};
+
+fn data(n: usize) -> Vec<u8> {
+    let mut x = 1u32;
+    (0..n)
+        .map(|_| {
+            x = x.wrapping_mul(1103515245).wrapping_add(12345);
+            (x >> 16) as u8
+        })
+        .collect()
+}
+
+#[test]
+fn test_vectors() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+    assert_eq!(crc32c(&[0; 32]), 0x8a91_36aa);
+    assert_eq!(adler32(b""), 1);
+    assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    // RFC 1071, 3: the sum of these bytes is 0xddf2
+    assert_eq!(
+        internet_checksum(&[0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7]),
+        0x220d
+    );
+    assert_eq!(internet_checksum(&[0x12]), !0x1200);
+}
+
+#[test]
+fn test_internet_checksum_verify() {
+    // an IPv4 header, with its checksum at 10
+    let mut header = [
+        0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0xc0, 0xa8, 0x00,
+        0x01, 0xc0, 0xa8, 0x00, 0xc7,
+    ];
+    let sum = internet_checksum(&header);
+    assert_eq!(sum, 0xb861);
+    header[10..12].copy_from_slice(&sum.to_be_bytes());
+    assert_eq!(internet_checksum(&header), 0);
+}
+
+#[test]
+fn test_streaming() {
+    let data = data(20000);
+    for split in [0, 1, 3, 7, 8, 13, 5552, 5553, 19999] {
+        let (a, b) = data.split_at(split);
+        let mut crc = Crc32::new();
+        let mut crcc = Crc32c::new();
+        let mut adler = Adler32::new();
+        let mut inet = InternetChecksum::new();
+        for part in [a, b] {
+            // odd sizes, so the Internet checksum parts start at odd offsets
+            for chunk in part.chunks(7) {
+                crc.update(chunk);
+                crcc.update(chunk);
+                adler.update(chunk);
+                inet.update(chunk);
+            }
+        }
+        assert_eq!(crc.finish(), crc32(&data));
+        assert_eq!(crcc.finish(), crc32c(&data));
+        assert_eq!(adler.finish(), adler32(&data));
+        assert_eq!(inet.finish(), internet_checksum(&data));
+    }
+}
+
+#[test]
+fn test_against_simple() {
+    for n in [0, 1, 2, 15, 16, 17, 255, 1000, 6000] {
+        let data = &data(n)[..];
+        // bit by bit
+        let crc = |poly: u32| {
+            let mut crc = !0u32;
+            for &b in data {
+                crc ^= b as u32;
+                for _ in 0..8 {
+                    crc = if crc & 1 != 0 {
+                        (crc >> 1) ^ poly
+                    } else {
+                        crc >> 1
+                    };
+                }
+            }
+            !crc
+        };
+        assert_eq!(crc32(data), crc(0xedb8_8320));
+        assert_eq!(crc32c(data), crc(0x82f6_3b78));
+        assert_eq!(!crc32_soft(!0, data), crc(0xedb8_8320));
+        assert_eq!(!crc32c_soft(!0, data), crc(0x82f6_3b78));
+
+        let (mut a, mut b) = (1u32, 0u32);
+        for &x in data {
+            a = (a + x as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        assert_eq!(adler32(data), (b << 16) | a);
+
+        let mut sum = 0u32;
+        for pair in data.chunks(2) {
+            sum += (pair[0] as u32) << 8 | *pair.get(1).unwrap_or(&0) as u32;
+        }
+        while sum > 0xffff {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        assert_eq!(internet_checksum(data), !(sum as u16));
+    }
+}
@@ -0,0 +1,219 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) compression algorithms.
+//!
+//! It provides:
+//!
+//! - LZ4 ([`lz4`]): the block format, compressed and decompressed in one
+//!   call, and the frame format, compressed by [`lz4::FrameEncoder`] and
+//!   decompressed by [`lz4::FrameDecoder`].
+//! - Zstandard ([`zstd`]): frames decompressed by [`zstd::Decoder`].
+//!
+//! The frame codecs are streaming: each call consumes what it can of an
+//! input buffer and fills an output buffer, both of any size, so the data go
+//! through fixed-size buffers (e.g. a disk block or a network packet) without
+//! being all in memory. They only allocate their windows, when starting a
+//! frame.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+#[cfg(test)]
+mod tests;
+
+pub mod lz4;
+mod xxhash;
+pub mod zstd;
+
+use alloc::vec::Vec;
+use core::fmt;
+
+/// An error of a decompression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The input is not valid compressed data.
+    Corrupted,
+    /// The input uses a feature not supported (e.g. a dictionary).
+    Unsupported,
+    /// The window the input needs is larger than the limit of the decoder.
+    WindowTooLarge,
+    /// The checksum of the data is wrong.
+    ChecksumMismatch,
+    /// The input ended in the middle of a frame.
+    Truncated,
+    /// The output buffer is too small for the data.
+    OutputTooSmall,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Corrupted => "corrupted data",
+            Self::Unsupported => "unsupported feature",
+            Self::WindowTooLarge => "window too large",
+            Self::ChecksumMismatch => "checksum mismatch",
+            Self::Truncated => "truncated data",
+            Self::OutputTooSmall => "output buffer too small",
+        })
+    }
+}
+
+/// The progress of a step of a streaming codec.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Status {
+    /// Number of bytes of the input consumed.
+    pub consumed: usize,
+    /// Number of bytes written to the output.
+    pub produced: usize,
+    /// Whether the decoder is between frames, all the data of the last one
+    /// having been written.
+    pub done: bool,
+}
+
+/// A streaming decompressor.
+pub trait Decompress {
+    /// Decompresses from `input` to `output`, as much as they allow.
+    ///
+    /// The input not consumed must be given again to the next call. A call
+    /// consuming and producing nothing needs more input, or more output room.
+    /// After a frame, the decoder takes the next one, frames being possibly
+    /// concatenated.
+    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<Status, Error>;
+
+    /// Decompresses all the frames of `input` to `output`, and returns the
+    /// size of the data.
+    fn decompress_all(&mut self, mut input: &[u8], output: &mut [u8]) -> Result<usize, Error> {
+        let mut pos = 0;
+        loop {
+            let status = self.decompress(input, &mut output[pos..])?;
+            input = &input[status.consumed..];
+            pos += status.produced;
+            if status.consumed == 0 && status.produced == 0 {
+                return if status.done && input.is_empty() {
+                    Ok(pos)
+                } else if input.is_empty() && pos < output.len() {
+                    Err(Error::Truncated)
+                } else {
+                    Err(Error::OutputTooSmall)
+                };
+            }
+        }
+    }
+}
+
+/// A compressed data format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The LZ4 frame format.
+    Lz4,
+    /// Zstandard.
+    Zstd,
+}
+
+impl Format {
+    /// Detects the format of the data by its magic number.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        match u32::from_le_bytes(data.get(..4)?.try_into().unwrap()) {
+            lz4::MAGIC => Some(Self::Lz4),
+            zstd::MAGIC => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the magic number starts a skippable frame, which both formats
+/// have, followed by the size of its content.
+fn is_skippable(magic: u32) -> bool {
+    magic & 0xffff_fff0 == 0x184d_2a50
+}
+
+/// Gathers the input of a unit (a header, a block...) whose parts may come in
+/// several calls.
+struct Staging {
+    buf: Vec<u8>,
+    /// The size of the unit.
+    need: usize,
+}
+
+impl Staging {
+    const fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            need: 0,
+        }
+    }
+
+    /// Starts gathering a unit of `need` bytes.
+    fn expect(&mut self, need: usize) {
+        self.buf.clear();
+        self.need = need;
+    }
+
+    /// Takes input to the unit, and returns the number of bytes consumed and
+    /// whether the unit is complete.
+    fn fill(&mut self, input: &[u8]) -> (usize, bool) {
+        let n = (self.need - self.buf.len()).min(input.len());
+        self.buf.extend_from_slice(&input[..n]);
+        (n, self.buf.len() == self.need)
+    }
+}
+
+/// The decompressed data of a frame: the window the next blocks may copy
+/// from, and the data not written to the output yet.
+struct Window {
+    buf: Vec<u8>,
+    /// The offset of the first byte to write.
+    flushed: usize,
+    /// The amount of past data to keep.
+    size: usize,
+    /// The length beyond which the data out of the window are dropped.
+    limit: usize,
+}
+
+impl Window {
+    const fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            flushed: 0,
+            size: 0,
+            limit: 0,
+        }
+    }
+
+    /// Starts a frame, keeping `size` bytes of past data for its blocks of at
+    /// most `block_size` bytes, and of `content_size` bytes in all if known.
+    fn reset(&mut self, size: usize, block_size: usize, content_size: Option<usize>) {
+        self.buf.clear();
+        self.flushed = 0;
+        self.size = size;
+        // some room beyond the window, for the drains to be rare
+        self.limit = size + (size / 2).max(2 * block_size);
+        if let Some(content_size) = content_size {
+            self.limit = self.limit.min(content_size);
+        }
+        self.buf.reserve_exact(self.limit);
+    }
+
+    fn has_pending(&self) -> bool {
+        self.flushed < self.buf.len()
+    }
+
+    /// Writes the pending data to `output`, and returns the number of bytes
+    /// written.
+    fn flush(&mut self, output: &mut [u8]) -> usize {
+        let n = (self.buf.len() - self.flushed).min(output.len());
+        output[..n].copy_from_slice(&self.buf[self.flushed..self.flushed + n]);
+        self.flushed += n;
+        n
+    }
+
+    /// Drops the data out of the window, once written, before a block of at
+    /// most `block_size` bytes.
+    fn make_room(&mut self, block_size: usize) {
+        let len = self.buf.len();
+        if len + block_size > self.limit {
+            let drop = len.saturating_sub(self.size).min(self.flushed);
+            self.buf.drain(..drop);
+            self.flushed -= drop;
+        }
+    }
+}
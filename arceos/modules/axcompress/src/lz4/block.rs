@@ -0,0 +1,186 @@
+//! The LZ4 block format codec.
+//!
+//! See <https://github.com/lz4/lz4/blob/dev/doc/lz4_Block_format.md>.
+
+const MIN_MATCH: usize = 4;
+/// The last match must start at least 12 bytes before the end of the block.
+const MF_LIMIT: usize = 12;
+/// The last 5 bytes of the block are always literals.
+const LAST_LITERALS: usize = 5;
+const MAX_DISTANCE: usize = 0xffff;
+const HASH_LOG: usize = 10;
+
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn push(&mut self, byte: u8) -> Option<()> {
+        *self.buf.get_mut(self.pos)? = byte;
+        self.pos += 1;
+        Some(())
+    }
+
+    fn extend(&mut self, data: &[u8]) -> Option<()> {
+        let end = self.pos.checked_add(data.len())?;
+        self.buf.get_mut(self.pos..end)?.copy_from_slice(data);
+        self.pos = end;
+        Some(())
+    }
+
+    /// Writes the extra bytes of a length field whose 4-bit part is saturated.
+    fn push_length(&mut self, mut len: usize) -> Option<()> {
+        while len >= 0xff {
+            self.push(0xff)?;
+            len -= 0xff;
+        }
+        self.push(len as u8)
+    }
+
+    fn push_sequence(&mut self, literals: &[u8], offset: usize, match_len: usize) -> Option<()> {
+        let lit_len = literals.len();
+        let ml = match_len - MIN_MATCH;
+        self.push(((lit_len.min(15) as u8) << 4) | ml.min(15) as u8)?;
+        if lit_len >= 15 {
+            self.push_length(lit_len - 15)?;
+        }
+        self.extend(literals)?;
+        self.extend(&(offset as u16).to_le_bytes())?;
+        if ml >= 15 {
+            self.push_length(ml - 15)?;
+        }
+        Some(())
+    }
+
+    fn push_last_literals(&mut self, literals: &[u8]) -> Option<()> {
+        let lit_len = literals.len();
+        self.push((lit_len.min(15) as u8) << 4)?;
+        if lit_len >= 15 {
+            self.push_length(lit_len - 15)?;
+        }
+        self.extend(literals)
+    }
+}
+
+#[inline]
+fn read_u32(buf: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([buf[pos], buf[pos + 1], buf[pos + 2], buf[pos + 3]])
+}
+
+#[inline]
+fn hash(seq: u32) -> usize {
+    (seq.wrapping_mul(2654435761) >> (32 - HASH_LOG)) as usize
+}
+
+/// Compresses `src` into `dst` in the LZ4 block format.
+///
+/// Returns the compressed size, or [`None`] if the output does not fit in
+/// `dst`. Callers can use a `dst` smaller than `src` to reject data that
+/// does not compress well enough.
+pub fn compress_block(src: &[u8], dst: &mut [u8]) -> Option<usize> {
+    let mut out = Writer { buf: dst, pos: 0 };
+    let mut table = [0u32; 1 << HASH_LOG];
+    let len = src.len();
+    let mut anchor = 0;
+
+    if len > MF_LIMIT {
+        let match_limit = len - MF_LIMIT;
+        let end_limit = len - LAST_LITERALS;
+        let mut i = 0;
+        while i < match_limit {
+            let seq = read_u32(src, i);
+            let h = hash(seq);
+            let cand = table[h] as usize;
+            table[h] = i as u32;
+            if cand >= i || i - cand > MAX_DISTANCE || read_u32(src, cand) != seq {
+                i += 1;
+                continue;
+            }
+
+            // Extend the match backwards over pending literals, then forwards.
+            let (mut start, mut ref_start) = (i, cand);
+            while start > anchor && ref_start > 0 && src[start - 1] == src[ref_start - 1] {
+                start -= 1;
+                ref_start -= 1;
+            }
+            let (mut end, mut ref_end) = (i + MIN_MATCH, cand + MIN_MATCH);
+            while end < end_limit && src[end] == src[ref_end] {
+                end += 1;
+                ref_end += 1;
+            }
+
+            out.push_sequence(&src[anchor..start], i - cand, end - start)?;
+            anchor = end;
+            i = end;
+        }
+    }
+
+    out.push_last_literals(&src[anchor..])?;
+    Some(out.pos)
+}
+
+/// Decompresses an LZ4 block from `src` into `dst`.
+///
+/// Returns the decompressed size, or [`None`] if the input is malformed or
+/// the output does not fit in `dst`.
+pub fn decompress_block(src: &[u8], dst: &mut [u8]) -> Option<usize> {
+    decompress_after(src, dst, 0)
+}
+
+/// Decompresses an LZ4 block from `src` into `dst`, from `start`, the
+/// matches reaching the data before it (from the previous blocks of a
+/// stream).
+///
+/// Returns the end of the decompressed data in `dst`.
+pub(super) fn decompress_after(src: &[u8], dst: &mut [u8], start: usize) -> Option<usize> {
+    let mut ip = 0usize;
+    let mut op = start;
+
+    let read_length = |ip: &mut usize, mut len: usize| -> Option<usize> {
+        if len == 15 {
+            loop {
+                let byte = *src.get(*ip)?;
+                *ip += 1;
+                len = len.checked_add(byte as usize)?;
+                if byte != 0xff {
+                    break;
+                }
+            }
+        }
+        Some(len)
+    };
+
+    loop {
+        let token = *src.get(ip)?;
+        ip += 1;
+
+        let lit_len = read_length(&mut ip, (token >> 4) as usize)?;
+        let lit_end = ip.checked_add(lit_len)?;
+        let out_end = op.checked_add(lit_len)?;
+        dst.get_mut(op..out_end)?
+            .copy_from_slice(src.get(ip..lit_end)?);
+        ip = lit_end;
+        op = out_end;
+
+        if ip == src.len() {
+            return Some(op); // the last sequence has no match part
+        }
+
+        let offset = u16::from_le_bytes([*src.get(ip)?, *src.get(ip + 1)?]) as usize;
+        ip += 2;
+        if offset == 0 || offset > op {
+            return None;
+        }
+        let match_len = read_length(&mut ip, (token & 0xf) as usize)? + MIN_MATCH;
+        let match_end = op.checked_add(match_len)?;
+        if match_end > dst.len() {
+            return None;
+        }
+        // Matches may overlap the output being produced, so copy bytewise.
+        for i in op..match_end {
+            dst[i] = dst[i - offset];
+        }
+        op = match_end;
+    }
+}
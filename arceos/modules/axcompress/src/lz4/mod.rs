@@ -0,0 +1,359 @@
+//! LZ4, in the block and frame formats.
+//!
+//! See <https://github.com/lz4/lz4/blob/dev/doc/lz4_Frame_format.md>.
+
+mod block;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+pub use self::block::{compress_block, decompress_block};
+use crate::xxhash::{xxh32, Xxh32};
+use crate::{is_skippable, Decompress, Error, Staging, Status, Window};
+
+/// The magic number of a frame.
+pub const MAGIC: u32 = 0x184d_2204;
+/// The magic number of the legacy frames (of `lz4 -l`), not supported.
+const LEGACY_MAGIC: u32 = 0x184c_2102;
+
+const FLG_VERSION: u8 = 0b0100_0000;
+const FLG_BLOCK_INDEPENDENT: u8 = 0b0010_0000;
+const FLG_BLOCK_CHECKSUM: u8 = 0b0001_0000;
+const FLG_CONTENT_SIZE: u8 = 0b0000_1000;
+const FLG_CONTENT_CHECKSUM: u8 = 0b0000_0100;
+const FLG_DICT_ID: u8 = 0b0000_0001;
+
+/// The high bit of a block size, for a block stored uncompressed.
+const BLOCK_UNCOMPRESSED: u32 = 1 << 31;
+/// The data a linked block may copy from.
+const LINKED_WINDOW: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Magic,
+    /// The flags and the block maximum size.
+    Descriptor,
+    /// The optional fields and the header checksum.
+    HeaderRest,
+    BlockSize,
+    Block {
+        compressed: bool,
+    },
+    ContentChecksum,
+    SkipSize,
+    Skip(usize),
+}
+
+/// A streaming decompressor of LZ4 frames.
+///
+/// Its memory is the largest block of a frame (64 KiB to 4 MiB as set by the
+/// compressor, 64 KiB for the frames of [`FrameEncoder`]), twice, and
+/// 64 KiB more for the blocks depending on the previous ones.
+pub struct FrameDecoder {
+    stage: Stage,
+    staging: Staging,
+    window: Window,
+    flags: u8,
+    block_max: usize,
+    hasher: Xxh32,
+}
+
+impl FrameDecoder {
+    pub const fn new() -> Self {
+        Self {
+            stage: Stage::Magic,
+            staging: Staging {
+                buf: Vec::new(),
+                need: 4,
+            },
+            window: Window::new(),
+            flags: 0,
+            block_max: 0,
+            hasher: Xxh32::new(),
+        }
+    }
+
+    fn goto(&mut self, stage: Stage, need: usize) {
+        self.stage = stage;
+        self.staging.expect(need);
+    }
+
+    /// Handles the complete unit of the stage.
+    fn advance(&mut self) -> Result<(), Error> {
+        let unit = &self.staging.buf;
+        let word = || u32::from_le_bytes(unit[..4].try_into().unwrap());
+        match self.stage {
+            Stage::Magic => match word() {
+                MAGIC => self.goto(Stage::Descriptor, 2),
+                LEGACY_MAGIC => return Err(Error::Unsupported),
+                magic if is_skippable(magic) => self.goto(Stage::SkipSize, 4),
+                _ => return Err(Error::Corrupted),
+            },
+            Stage::Descriptor => {
+                let (flags, bd) = (unit[0], unit[1]);
+                if flags & 0b1100_0010 != FLG_VERSION || bd & 0b1000_1111 != 0 {
+                    return Err(Error::Corrupted);
+                }
+                if flags & FLG_DICT_ID != 0 {
+                    return Err(Error::Unsupported);
+                }
+                self.block_max = match (bd >> 4) & 7 {
+                    id @ 4..=7 => LINKED_WINDOW << (2 * (id - 4)),
+                    _ => return Err(Error::Corrupted),
+                };
+                self.flags = flags;
+                let rest = if flags & FLG_CONTENT_SIZE != 0 { 9 } else { 1 };
+                self.goto(Stage::HeaderRest, 2 + rest);
+                // the descriptor is kept for its checksum
+                self.staging.buf.extend_from_slice(&[flags, bd]);
+            }
+            Stage::HeaderRest => {
+                let (desc, checksum) = unit.split_at(unit.len() - 1);
+                if (xxh32(desc) >> 8) as u8 != checksum[0] {
+                    return Err(Error::ChecksumMismatch);
+                }
+                let history = if self.flags & FLG_BLOCK_INDEPENDENT != 0 {
+                    0
+                } else {
+                    LINKED_WINDOW
+                };
+                self.window.reset(history, self.block_max, None);
+                self.hasher = Xxh32::new();
+                self.goto(Stage::BlockSize, 4);
+            }
+            Stage::BlockSize => {
+                let size = word();
+                if size == 0 {
+                    if self.flags & FLG_CONTENT_CHECKSUM != 0 {
+                        self.goto(Stage::ContentChecksum, 4);
+                    } else {
+                        self.goto(Stage::Magic, 4);
+                    }
+                    return Ok(());
+                }
+                let compressed = size & BLOCK_UNCOMPRESSED == 0;
+                let size = (size & !BLOCK_UNCOMPRESSED) as usize;
+                if size > self.block_max {
+                    return Err(Error::Corrupted);
+                }
+                let checksum = if self.flags & FLG_BLOCK_CHECKSUM != 0 {
+                    4
+                } else {
+                    0
+                };
+                self.goto(Stage::Block { compressed }, size + checksum);
+            }
+            Stage::Block { compressed } => {
+                let mut data = &unit[..];
+                if self.flags & FLG_BLOCK_CHECKSUM != 0 {
+                    let (block, checksum) = data.split_at(data.len() - 4);
+                    if xxh32(block).to_le_bytes() != checksum {
+                        return Err(Error::ChecksumMismatch);
+                    }
+                    data = block;
+                }
+                self.window.make_room(self.block_max);
+                let buf = &mut self.window.buf;
+                let start = buf.len();
+                if compressed {
+                    buf.resize(start + self.block_max, 0);
+                    let end = if self.flags & FLG_BLOCK_INDEPENDENT != 0 {
+                        block::decompress_after(data, &mut buf[start..], 0).map(|n| start + n)
+                    } else {
+                        block::decompress_after(data, buf, start)
+                    };
+                    buf.truncate(end.ok_or(Error::Corrupted)?);
+                } else {
+                    buf.extend_from_slice(data);
+                }
+                if self.flags & FLG_CONTENT_CHECKSUM != 0 {
+                    self.hasher.update(&buf[start..]);
+                }
+                self.goto(Stage::BlockSize, 4);
+            }
+            Stage::ContentChecksum => {
+                if self.hasher.finish() != word() {
+                    return Err(Error::ChecksumMismatch);
+                }
+                self.goto(Stage::Magic, 4);
+            }
+            Stage::SkipSize => {
+                self.stage = Stage::Skip(word() as usize);
+                self.staging.expect(0);
+            }
+            Stage::Skip(_) => self.goto(Stage::Magic, 4),
+        }
+        Ok(())
+    }
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decompress for FrameDecoder {
+    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<Status, Error> {
+        let mut status = Status::default();
+        loop {
+            status.produced += self.window.flush(&mut output[status.produced..]);
+            if self.window.has_pending() {
+                break;
+            }
+            let input = &input[status.consumed..];
+            if let Stage::Skip(remaining) = self.stage {
+                let n = remaining.min(input.len());
+                status.consumed += n;
+                if n < remaining {
+                    self.stage = Stage::Skip(remaining - n);
+                    break;
+                }
+                self.goto(Stage::Magic, 4);
+                continue;
+            }
+            let (n, complete) = self.staging.fill(input);
+            status.consumed += n;
+            if !complete {
+                break;
+            }
+            self.advance()?;
+        }
+        status.done =
+            self.stage == Stage::Magic && self.staging.buf.is_empty() && !self.window.has_pending();
+        Ok(status)
+    }
+}
+
+/// The size of the blocks of a [`FrameEncoder`].
+const BLOCK_SIZE: usize = 64 * 1024;
+
+/// A streaming compressor to LZ4 frames.
+///
+/// The frames have independent blocks of 64 KiB, and a checksum of their
+/// content.
+pub struct FrameEncoder {
+    block: Vec<u8>,
+    /// The frame data not written to the output yet.
+    out: Vec<u8>,
+    flushed: usize,
+    hasher: Xxh32,
+    started: bool,
+    finished: bool,
+}
+
+impl FrameEncoder {
+    pub fn new() -> Self {
+        Self {
+            block: Vec::with_capacity(BLOCK_SIZE),
+            out: Vec::with_capacity(BLOCK_SIZE + 16),
+            flushed: 0,
+            hasher: Xxh32::new(),
+            started: false,
+            finished: false,
+        }
+    }
+
+    fn write_header(&mut self) {
+        let desc = [
+            FLG_VERSION | FLG_BLOCK_INDEPENDENT | FLG_CONTENT_CHECKSUM,
+            4 << 4, // 64 KiB blocks
+        ];
+        self.out.extend_from_slice(&MAGIC.to_le_bytes());
+        self.out.extend_from_slice(&desc);
+        self.out.push((xxh32(&desc) >> 8) as u8);
+    }
+
+    fn write_block(&mut self) {
+        let block = &self.block;
+        self.hasher.update(block);
+        let start = self.out.len();
+        self.out.resize(start + 4 + block.len(), 0);
+        // kept uncompressed unless it shrinks
+        let dst = &mut self.out[start + 4..start + 4 + block.len() - 1];
+        let size = match compress_block(block, dst) {
+            Some(n) => {
+                self.out.truncate(start + 4 + n);
+                n as u32
+            }
+            None => {
+                self.out[start + 4..].copy_from_slice(block);
+                block.len() as u32 | BLOCK_UNCOMPRESSED
+            }
+        };
+        self.out[start..start + 4].copy_from_slice(&size.to_le_bytes());
+        self.block.clear();
+    }
+
+    /// Compresses from `input` to `output`, as much as they allow.
+    ///
+    /// The input not consumed must be given again to the next call. With
+    /// `finish`, once all the input is consumed, the frame is ended, and
+    /// [`Status::done`] is set when it is all written. The next call with
+    /// input starts a new frame.
+    pub fn compress(&mut self, input: &[u8], output: &mut [u8], finish: bool) -> Status {
+        let mut status = Status::default();
+        if self.finished && self.flushed == self.out.len() && !input.is_empty() {
+            self.hasher = Xxh32::new();
+            self.started = false;
+            self.finished = false;
+        }
+        loop {
+            let n = (self.out.len() - self.flushed).min(output.len() - status.produced);
+            output[status.produced..status.produced + n]
+                .copy_from_slice(&self.out[self.flushed..self.flushed + n]);
+            self.flushed += n;
+            status.produced += n;
+            if self.flushed < self.out.len() {
+                break;
+            }
+            self.out.clear();
+            self.flushed = 0;
+
+            if !self.started {
+                self.write_header();
+                self.started = true;
+                continue;
+            }
+            if self.finished {
+                status.done = true;
+                break;
+            }
+            let input = &input[status.consumed..];
+            let n = (BLOCK_SIZE - self.block.len()).min(input.len());
+            self.block.extend_from_slice(&input[..n]);
+            status.consumed += n;
+            if self.block.len() == BLOCK_SIZE {
+                self.write_block();
+            } else if finish && n == input.len() {
+                if !self.block.is_empty() {
+                    self.write_block();
+                }
+                self.out.extend_from_slice(&0u32.to_le_bytes());
+                self.out
+                    .extend_from_slice(&self.hasher.finish().to_le_bytes());
+                self.finished = true;
+            } else {
+                break;
+            }
+        }
+        status
+    }
+}
+
+impl Default for FrameEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Compresses `src` to a whole LZ4 frame.
+pub fn compress_frame(src: &[u8]) -> Vec<u8> {
+    let mut encoder = FrameEncoder::new();
+    // the blocks that do not shrink are stored as they are
+    let mut out = vec![0; src.len() + (src.len() / BLOCK_SIZE + 1) * 4 + 16];
+    let status = encoder.compress(src, &mut out, true);
+    debug_assert!(status.done);
+    out.truncate(status.produced);
+    out
+}
@@ -0,0 +1,137 @@
+use crate::lz4::{self, compress_block, decompress_block, FrameDecoder, FrameEncoder};
+use crate::zstd::{self, Decoder};
+use crate::{Decompress, Error, Format};
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// Words picked by a linear congruential generator, compressible but not
+/// trivially.
+fn sample(n: usize) -> Vec<u8> {
+    const WORDS: [&str; 13] = [
+        "the", "quick", "brown", "fox", "jumps", "over", "lazy", "dog", "kernel", "block", "frame",
+        "arceos", "\n",
+    ];
+    let mut out = Vec::new();
+    let mut x = 1u32;
+    while out.len() < n {
+        x = x.wrapping_mul(1103515245).wrapping_add(12345);
+        out.extend_from_slice(WORDS[(x >> 16) as usize % WORDS.len()].as_bytes());
+        out.push(b' ');
+    }
+    out.truncate(n);
+    out
+}
+
+/// Decompresses `input` given `in_chunk` bytes at a time, to an output
+/// buffer of `out_chunk` bytes.
+fn stream(d: &mut impl Decompress, input: &[u8], in_chunk: usize, out_chunk: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut buf = vec![0; out_chunk];
+    let mut pos = 0;
+    loop {
+        let end = (pos + in_chunk).min(input.len());
+        let status = d.decompress(&input[pos..end], &mut buf).unwrap();
+        pos += status.consumed;
+        out.extend_from_slice(&buf[..status.produced]);
+        if status.consumed == 0 && status.produced == 0 {
+            assert!(status.done && pos == input.len());
+            return out;
+        }
+    }
+}
+
+#[test]
+fn test_lz4_block() {
+    let data = sample(5000);
+    let mut compressed = [0; 5000];
+    let n = compress_block(&data, &mut compressed).unwrap();
+    assert!(n < data.len());
+    let mut out = [0; 5000];
+    assert_eq!(decompress_block(&compressed[..n], &mut out), Some(5000));
+    assert_eq!(out, data[..]);
+    assert_eq!(decompress_block(&compressed[..n], &mut out[..4999]), None);
+}
+
+#[test]
+fn test_lz4_frame() {
+    // `lz4` 1.9 of `sample(100)`
+    let frame = hex(concat!(
+        "04224d186440a754000000f31a666f7820646f67200a200a2062726f776e206c617a79206b65726e",
+        "656c206a756d707320626c6f636b0c00011e00b3717569636b206672616d65170063617263656f73",
+        "1900005000020a0060666f7820666f00000000ff54c0b1",
+    ));
+    assert_eq!(Format::detect(&frame), Some(Format::Lz4));
+    let mut out = [0; 100];
+    assert_eq!(
+        FrameDecoder::new().decompress_all(&frame, &mut out),
+        Ok(100)
+    );
+    assert_eq!(out, sample(100)[..]);
+
+    let mut bad = frame.clone();
+    *bad.last_mut().unwrap() ^= 1;
+    let result = FrameDecoder::new().decompress_all(&bad, &mut out);
+    assert_eq!(result, Err(Error::ChecksumMismatch));
+    let result = FrameDecoder::new().decompress_all(&frame[..50], &mut out);
+    assert_eq!(result, Err(Error::Truncated));
+}
+
+#[test]
+fn test_lz4_streaming() {
+    // several blocks, through small buffers both ways
+    let data = sample(200_000);
+    let mut encoder = FrameEncoder::new();
+    let mut frame = Vec::new();
+    let mut buf = [0; 100];
+    let mut pos = 0;
+    loop {
+        let end = (pos + 333).min(data.len());
+        let status = encoder.compress(&data[pos..end], &mut buf, end == data.len());
+        pos += status.consumed;
+        frame.extend_from_slice(&buf[..status.produced]);
+        if status.done {
+            break;
+        }
+    }
+    assert_eq!(frame, lz4::compress_frame(&data));
+    assert_eq!(stream(&mut FrameDecoder::new(), &frame, 7, 13), data);
+}
+
+#[test]
+fn test_zstd_frame() {
+    // `zstd -19` 1.5 of `sample(1000)`, with a checksum
+    let frame = hex(concat!(
+        "28b52ffd64e80245080092040f11a06f60834dc8239fe6985555b5a88a2c04ccccec263e9fcff5e1",
+        "bb78c01778a49338d225f12a346c4ba256ce9ea7b617a866f45f686eeef2a3eb9374a8815352d8d8",
+        "df208420c5ec3c113018de19120b1bd218f30225531ba97f12058fa37a95ad9a091fbca3342f8502",
+        "9da953a5110e6f96a19dbe0e59db656ab07ec709d841593468145d838755c6af635f8174e3d1ef09",
+        "37dadc4e241bd4206856381244b233376bb1e9b4e877468ac6251542e3711bc698a8f7d2af34c66b",
+        "12c5b8e17abf2c029843960ac6a407504a5803c41495fc4ab7bd2f8ce8be975db04ec2f51b1f6876",
+        "c75c175998f24fd00ab4fa2d9460779da7b8ef02a67dc69b2262f4571026cf115e056cf4b621",
+    ));
+    assert_eq!(Format::detect(&frame), Some(Format::Zstd));
+    let data = sample(1000);
+    assert_eq!(zstd::decompress(&frame, 1000), Ok(data.clone()));
+    assert_eq!(stream(&mut Decoder::new(), &frame, 1, 1), data);
+
+    // concatenated frames, and a skippable one between
+    let mut frames = frame.clone();
+    frames.extend_from_slice(&hex("5a2a4d1803000000616263"));
+    frames.extend_from_slice(&frame);
+    assert_eq!(
+        stream(&mut Decoder::new(), &frames, 64, 100),
+        [&data[..], &data].concat()
+    );
+
+    let mut bad = frame.clone();
+    *bad.last_mut().unwrap() ^= 1;
+    assert_eq!(zstd::decompress(&bad, 1000), Err(Error::ChecksumMismatch));
+    assert_eq!(zstd::decompress(&frame, 999), Err(Error::OutputTooSmall));
+    let result = Decoder::with_window_limit(512).decompress_all(&frame, &mut [0; 1000]);
+    assert_eq!(result, Err(Error::WindowTooLarge));
+}
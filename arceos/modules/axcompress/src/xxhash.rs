@@ -0,0 +1,216 @@
+//! The xxHash checksums of the LZ4 (XXH32) and Zstandard (XXH64) frames.
+//!
+//! See <https://github.com/Cyan4973/xxHash/blob/dev/doc/xxhash_spec.md>.
+
+const P32_1: u32 = 0x9e37_79b1;
+const P32_2: u32 = 0x85eb_ca77;
+const P32_3: u32 = 0xc2b2_ae3d;
+const P32_4: u32 = 0x27d4_eb2f;
+const P32_5: u32 = 0x1656_67b1;
+
+const P64_1: u64 = 0x9e37_79b1_85eb_ca87;
+const P64_2: u64 = 0xc2b2_ae3d_27d4_eb4f;
+const P64_3: u64 = 0x1656_67b1_9e37_79f9;
+const P64_4: u64 = 0x85eb_ca77_c2b2_ae63;
+const P64_5: u64 = 0x27d4_eb2f_1656_67c5;
+
+fn round32(acc: u32, input: u32) -> u32 {
+    acc.wrapping_add(input.wrapping_mul(P32_2))
+        .rotate_left(13)
+        .wrapping_mul(P32_1)
+}
+
+fn round64(acc: u64, input: u64) -> u64 {
+    acc.wrapping_add(input.wrapping_mul(P64_2))
+        .rotate_left(31)
+        .wrapping_mul(P64_1)
+}
+
+fn merge64(acc: u64, v: u64) -> u64 {
+    (acc ^ round64(0, v))
+        .wrapping_mul(P64_1)
+        .wrapping_add(P64_4)
+}
+
+fn u32_at(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+fn u64_at(data: &[u8], pos: usize) -> u64 {
+    u64::from_le_bytes(data[pos..pos + 8].try_into().unwrap())
+}
+
+/// An XXH32 hasher, with a seed of 0.
+pub struct Xxh32 {
+    v: [u32; 4],
+    buf: [u8; 16],
+    buf_len: usize,
+    total_len: u64,
+}
+
+impl Xxh32 {
+    pub const fn new() -> Self {
+        Self {
+            v: [
+                P32_1.wrapping_add(P32_2),
+                P32_2,
+                0,
+                0u32.wrapping_sub(P32_1),
+            ],
+            buf: [0; 16],
+            buf_len: 0,
+            total_len: 0,
+        }
+    }
+
+    fn stripe(v: &mut [u32; 4], data: &[u8]) {
+        for (i, lane) in v.iter_mut().enumerate() {
+            *lane = round32(*lane, u32_at(data, i * 4));
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len() as u64;
+        if self.buf_len > 0 {
+            let n = (16 - self.buf_len).min(data.len());
+            self.buf[self.buf_len..self.buf_len + n].copy_from_slice(&data[..n]);
+            self.buf_len += n;
+            data = &data[n..];
+            if self.buf_len < 16 {
+                return;
+            }
+            Self::stripe(&mut self.v, &self.buf);
+            self.buf_len = 0;
+        }
+        let mut chunks = data.chunks_exact(16);
+        for chunk in &mut chunks {
+            Self::stripe(&mut self.v, chunk);
+        }
+        let rest = chunks.remainder();
+        self.buf[..rest.len()].copy_from_slice(rest);
+        self.buf_len = rest.len();
+    }
+
+    pub fn finish(&self) -> u32 {
+        let [v1, v2, v3, v4] = self.v;
+        let mut h = if self.total_len >= 16 {
+            v1.rotate_left(1)
+                .wrapping_add(v2.rotate_left(7))
+                .wrapping_add(v3.rotate_left(12))
+                .wrapping_add(v4.rotate_left(18))
+        } else {
+            P32_5
+        };
+        h = h.wrapping_add(self.total_len as u32);
+        let rest = &self.buf[..self.buf_len];
+        let mut words = rest.chunks_exact(4);
+        for word in &mut words {
+            h = h.wrapping_add(u32_at(word, 0).wrapping_mul(P32_3));
+            h = h.rotate_left(17).wrapping_mul(P32_4);
+        }
+        for &b in words.remainder() {
+            h = h.wrapping_add((b as u32).wrapping_mul(P32_5));
+            h = h.rotate_left(11).wrapping_mul(P32_1);
+        }
+        h ^= h >> 15;
+        h = h.wrapping_mul(P32_2);
+        h ^= h >> 13;
+        h = h.wrapping_mul(P32_3);
+        h ^ (h >> 16)
+    }
+}
+
+/// Returns the XXH32 of the data, with a seed of 0.
+pub fn xxh32(data: &[u8]) -> u32 {
+    let mut hasher = Xxh32::new();
+    hasher.update(data);
+    hasher.finish()
+}
+
+/// An XXH64 hasher, with a seed of 0.
+pub struct Xxh64 {
+    v: [u64; 4],
+    buf: [u8; 32],
+    buf_len: usize,
+    total_len: u64,
+}
+
+impl Xxh64 {
+    pub const fn new() -> Self {
+        Self {
+            v: [
+                P64_1.wrapping_add(P64_2),
+                P64_2,
+                0,
+                0u64.wrapping_sub(P64_1),
+            ],
+            buf: [0; 32],
+            buf_len: 0,
+            total_len: 0,
+        }
+    }
+
+    fn stripe(v: &mut [u64; 4], data: &[u8]) {
+        for (i, lane) in v.iter_mut().enumerate() {
+            *lane = round64(*lane, u64_at(data, i * 8));
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len() as u64;
+        if self.buf_len > 0 {
+            let n = (32 - self.buf_len).min(data.len());
+            self.buf[self.buf_len..self.buf_len + n].copy_from_slice(&data[..n]);
+            self.buf_len += n;
+            data = &data[n..];
+            if self.buf_len < 32 {
+                return;
+            }
+            Self::stripe(&mut self.v, &self.buf);
+            self.buf_len = 0;
+        }
+        let mut chunks = data.chunks_exact(32);
+        for chunk in &mut chunks {
+            Self::stripe(&mut self.v, chunk);
+        }
+        let rest = chunks.remainder();
+        self.buf[..rest.len()].copy_from_slice(rest);
+        self.buf_len = rest.len();
+    }
+
+    pub fn finish(&self) -> u64 {
+        let [v1, v2, v3, v4] = self.v;
+        let mut h = if self.total_len >= 32 {
+            let h = v1
+                .rotate_left(1)
+                .wrapping_add(v2.rotate_left(7))
+                .wrapping_add(v3.rotate_left(12))
+                .wrapping_add(v4.rotate_left(18));
+            [v1, v2, v3, v4].into_iter().fold(h, merge64)
+        } else {
+            P64_5
+        };
+        h = h.wrapping_add(self.total_len);
+        let rest = &self.buf[..self.buf_len];
+        let mut words = rest.chunks_exact(8);
+        for word in &mut words {
+            h ^= round64(0, u64_at(word, 0));
+            h = h.rotate_left(27).wrapping_mul(P64_1).wrapping_add(P64_4);
+        }
+        let mut rest = words.remainder();
+        if rest.len() >= 4 {
+            h ^= (u32_at(rest, 0) as u64).wrapping_mul(P64_1);
+            h = h.rotate_left(23).wrapping_mul(P64_2).wrapping_add(P64_3);
+            rest = &rest[4..];
+        }
+        for &b in rest {
+            h ^= (b as u64).wrapping_mul(P64_5);
+            h = h.rotate_left(11).wrapping_mul(P64_1);
+        }
+        h ^= h >> 33;
+        h = h.wrapping_mul(P64_2);
+        h ^= h >> 29;
+        h = h.wrapping_mul(P64_3);
+        h ^ (h >> 32)
+    }
+}
@@ -0,0 +1,102 @@
+//! The bitstreams of Zstandard.
+
+use crate::Error;
+
+/// A bitstream read forward, from the lowest bit of the first byte (for the
+/// FSE table descriptions).
+pub struct ForwardReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ForwardReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Reads `n` bits (at most 32), the ones after the end being zeros.
+    pub fn read(&mut self, n: u32) -> u32 {
+        let mut v = 0;
+        for i in 0..n as usize {
+            let bit = self.pos + i;
+            let byte = self.data.get(bit / 8).copied().unwrap_or(0);
+            v |= (((byte >> (bit % 8)) & 1) as u32) << i;
+        }
+        self.pos += n as usize;
+        v
+    }
+
+    /// Returns the number of bytes started, or an error if they are beyond
+    /// the end.
+    pub fn bytes_read(&self) -> Result<usize, Error> {
+        let n = self.pos.div_ceil(8);
+        if n > self.data.len() {
+            return Err(Error::Corrupted);
+        }
+        Ok(n)
+    }
+}
+
+/// A bitstream read backward, from the highest bit of the last byte, after
+/// the padding ending with a 1 bit (for the Huffman and FSE coded data).
+pub struct BackwardReader<'a> {
+    data: &'a [u8],
+    /// Number of bits not read yet, negative once reading past the start.
+    pos: isize,
+}
+
+impl<'a> BackwardReader<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        let last = *data.last().ok_or(Error::Corrupted)?;
+        if last == 0 {
+            return Err(Error::Corrupted);
+        }
+        let padding = last.leading_zeros() as isize + 1;
+        Ok(Self {
+            data,
+            pos: data.len() as isize * 8 - padding,
+        })
+    }
+
+    /// Returns the next `n` bits (at most 56) without consuming them, the
+    /// ones past the start being zeros.
+    pub fn peek(&self, n: u32) -> u64 {
+        if n == 0 {
+            return 0;
+        }
+        let end = self.pos;
+        let start = end - n as isize;
+        let lo = start.max(0) as usize;
+        if end <= lo as isize {
+            return 0;
+        }
+        let hi = end as usize;
+        let first = lo / 8;
+        let mut raw = 0u64;
+        for (i, &b) in self.data[first..hi.div_ceil(8)].iter().enumerate() {
+            raw |= (b as u64) << (8 * i);
+        }
+        let width = hi - lo;
+        let v = (raw >> (lo - first * 8)) & ((1u64 << width) - 1);
+        v << (lo as isize - start)
+    }
+
+    pub fn consume(&mut self, n: u32) {
+        self.pos -= n as isize;
+    }
+
+    pub fn read(&mut self, n: u32) -> u64 {
+        let v = self.peek(n);
+        self.consume(n);
+        v
+    }
+
+    /// Number of bits left, negative if more than all were read.
+    pub fn remaining(&self) -> isize {
+        self.pos
+    }
+
+    pub fn is_overflowed(&self) -> bool {
+        self.pos < 0
+    }
+}
@@ -0,0 +1,336 @@
+//! The compressed blocks: literals, and sequences copying them and matches.
+
+use alloc::vec::Vec;
+
+use super::bits::BackwardReader;
+use super::fse::{State, Table as FseTable};
+use super::huffman::Table as HuffmanTable;
+use super::MAX_BLOCK_SIZE;
+use crate::Error;
+
+const LL_MAX_SYMBOL: usize = 35;
+const ML_MAX_SYMBOL: usize = 52;
+const OF_MAX_SYMBOL: usize = 31;
+const LL_MAX_LOG: u32 = 9;
+const ML_MAX_LOG: u32 = 9;
+const OF_MAX_LOG: u32 = 8;
+
+#[rustfmt::skip]
+const LL_DEFAULT: [i16; 36] = [
+    4, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1,
+    2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 2, 1, 1, 1, 1, 1,
+    -1, -1, -1, -1,
+];
+#[rustfmt::skip]
+const ML_DEFAULT: [i16; 53] = [
+    1, 4, 3, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1,
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1,
+    -1, -1, -1, -1, -1,
+];
+#[rustfmt::skip]
+const OF_DEFAULT: [i16; 29] = [
+    1, 1, 1, 1, 1, 1, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1,
+    1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1,
+];
+
+#[rustfmt::skip]
+const LL_BASE: [u32; 36] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+    16, 18, 20, 22, 24, 28, 32, 40, 48, 64, 128, 256, 512, 1024, 2048, 4096,
+    8192, 16384, 32768, 65536,
+];
+#[rustfmt::skip]
+const LL_BITS: [u8; 36] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    1, 1, 1, 1, 2, 2, 3, 3, 4, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15, 16,
+];
+#[rustfmt::skip]
+const ML_BASE: [u32; 53] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18,
+    19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34,
+    35, 37, 39, 41, 43, 47, 51, 59, 67, 83, 99, 131, 259, 515, 1027, 2051,
+    4099, 8195, 16387, 32771, 65539,
+];
+#[rustfmt::skip]
+const ML_BITS: [u8; 53] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    1, 1, 1, 1, 2, 2, 3, 3, 4, 4, 5, 7, 8, 9, 10, 11,
+    12, 13, 14, 15, 16,
+];
+
+/// The state of a frame carried from block to block: the tables the next
+/// blocks may repeat, and the repeated offsets.
+pub struct Context {
+    huffman: Option<HuffmanTable>,
+    ll: Option<FseTable>,
+    of: Option<FseTable>,
+    ml: Option<FseTable>,
+    rep: [usize; 3],
+    literals: Vec<u8>,
+    ll_default: FseTable,
+    of_default: FseTable,
+    ml_default: FseTable,
+}
+
+/// Selects the table of a symbol type, by its compression mode, and returns
+/// the size of its description.
+fn select_table(
+    mode: u8,
+    data: &[u8],
+    table: &mut Option<FseTable>,
+    default: &FseTable,
+    max_symbol: usize,
+    max_log: u32,
+) -> Result<usize, Error> {
+    let size = match mode {
+        0 => {
+            *table = Some(default.clone());
+            0
+        }
+        1 => {
+            let symbol = *data.first().ok_or(Error::Corrupted)?;
+            if symbol as usize > max_symbol {
+                return Err(Error::Corrupted);
+            }
+            *table = Some(FseTable::rle(symbol));
+            1
+        }
+        2 => {
+            let (t, size) = FseTable::read(data, max_symbol, max_log)?;
+            *table = Some(t);
+            size
+        }
+        _ => {
+            if table.is_none() {
+                return Err(Error::Corrupted);
+            }
+            0
+        }
+    };
+    Ok(size)
+}
+
+impl Context {
+    pub fn new() -> Self {
+        let table = |probs: &[i16], log| FseTable::from_probabilities(probs, log).unwrap();
+        Self {
+            huffman: None,
+            ll: None,
+            of: None,
+            ml: None,
+            rep: [1, 4, 8],
+            literals: Vec::new(),
+            ll_default: table(&LL_DEFAULT, 6),
+            of_default: table(&OF_DEFAULT, 5),
+            ml_default: table(&ML_DEFAULT, 6),
+        }
+    }
+
+    /// Starts a frame.
+    pub fn reset(&mut self) {
+        self.huffman = None;
+        self.ll = None;
+        self.of = None;
+        self.ml = None;
+        self.rep = [1, 4, 8];
+    }
+
+    /// Reads the literals section to `self.literals`, and returns its size.
+    fn read_literals(&mut self, data: &[u8]) -> Result<usize, Error> {
+        let byte = |i: usize| {
+            data.get(i)
+                .copied()
+                .ok_or(Error::Corrupted)
+                .map(usize::from)
+        };
+        let b0 = byte(0)?;
+        let kind = b0 & 3;
+        let size_format = (b0 >> 2) & 3;
+        self.literals.clear();
+        if kind < 2 {
+            let (size, header) = match size_format {
+                0 | 2 => (b0 >> 3, 1),
+                1 => ((b0 >> 4) + (byte(1)? << 4), 2),
+                _ => ((b0 >> 4) + (byte(1)? << 4) + (byte(2)? << 12), 3),
+            };
+            if size > MAX_BLOCK_SIZE {
+                return Err(Error::Corrupted);
+            }
+            return if kind == 0 {
+                let raw = data.get(header..header + size).ok_or(Error::Corrupted)?;
+                self.literals.extend_from_slice(raw);
+                Ok(header + size)
+            } else {
+                self.literals.resize(size, byte(header)? as u8);
+                Ok(header + 1)
+            };
+        }
+
+        let (header, bits) = match size_format {
+            0 | 1 => (3, 10),
+            2 => (4, 14),
+            _ => (5, 18),
+        };
+        let mut h = 0u64;
+        for i in 0..header {
+            h |= (byte(i)? as u64) << (8 * i);
+        }
+        let mask = (1 << bits) - 1;
+        let regenerated = ((h >> 4) & mask) as usize;
+        let compressed = ((h >> (4 + bits)) & mask) as usize;
+        if regenerated > MAX_BLOCK_SIZE {
+            return Err(Error::Corrupted);
+        }
+        let mut data = data
+            .get(header..header + compressed)
+            .ok_or(Error::Corrupted)?;
+        if kind == 2 {
+            let (table, size) = HuffmanTable::read(data)?;
+            self.huffman = Some(table);
+            data = &data[size..];
+        }
+        let table = self.huffman.as_ref().ok_or(Error::Corrupted)?;
+        self.literals.resize(regenerated, 0);
+        table.decode(data, &mut self.literals, size_format != 0)?;
+        Ok(header + compressed)
+    }
+
+    /// Decodes a compressed block, appending its data to `out`.
+    pub fn decode(&mut self, data: &[u8], out: &mut Vec<u8>) -> Result<(), Error> {
+        let mut pos = self.read_literals(data)?;
+        let byte = |i: usize| {
+            data.get(i)
+                .copied()
+                .ok_or(Error::Corrupted)
+                .map(usize::from)
+        };
+        let b0 = byte(pos)?;
+        let (num_seqs, header) = match b0 {
+            0..=127 => (b0, 1),
+            128..=254 => (((b0 - 128) << 8) + byte(pos + 1)?, 2),
+            _ => (byte(pos + 1)? + (byte(pos + 2)? << 8) + 0x7f00, 3),
+        };
+        pos += header;
+        if num_seqs == 0 {
+            if pos != data.len() {
+                return Err(Error::Corrupted);
+            }
+            out.extend_from_slice(&self.literals);
+            return Ok(());
+        }
+
+        let modes = byte(pos)? as u8;
+        pos += 1;
+        if modes & 3 != 0 {
+            return Err(Error::Corrupted);
+        }
+        let rest = |pos: usize| data.get(pos..).ok_or(Error::Corrupted);
+        pos += select_table(
+            modes >> 6,
+            rest(pos)?,
+            &mut self.ll,
+            &self.ll_default,
+            LL_MAX_SYMBOL,
+            LL_MAX_LOG,
+        )?;
+        pos += select_table(
+            (modes >> 4) & 3,
+            rest(pos)?,
+            &mut self.of,
+            &self.of_default,
+            OF_MAX_SYMBOL,
+            OF_MAX_LOG,
+        )?;
+        pos += select_table(
+            (modes >> 2) & 3,
+            rest(pos)?,
+            &mut self.ml,
+            &self.ml_default,
+            ML_MAX_SYMBOL,
+            ML_MAX_LOG,
+        )?;
+
+        let mut bits = BackwardReader::new(rest(pos)?)?;
+        let mut ll_state = State::new(self.ll.as_ref().unwrap(), &mut bits);
+        let mut of_state = State::new(self.of.as_ref().unwrap(), &mut bits);
+        let mut ml_state = State::new(self.ml.as_ref().unwrap(), &mut bits);
+        let block_start = out.len();
+        let mut lit_pos = 0;
+        for i in 0..num_seqs {
+            let ll_code = ll_state.symbol() as usize;
+            let of_code = of_state.symbol() as u32;
+            let ml_code = ml_state.symbol() as usize;
+            if ll_code > LL_MAX_SYMBOL
+                || ml_code > ML_MAX_SYMBOL
+                || of_code as usize > OF_MAX_SYMBOL
+            {
+                return Err(Error::Corrupted);
+            }
+            let offset_value = (1usize << of_code) + bits.read(of_code) as usize;
+            let ml = ML_BASE[ml_code] as usize + bits.read(ML_BITS[ml_code] as u32) as usize;
+            let ll = LL_BASE[ll_code] as usize + bits.read(LL_BITS[ll_code] as u32) as usize;
+            if i + 1 < num_seqs {
+                ll_state.update(&mut bits);
+                ml_state.update(&mut bits);
+                of_state.update(&mut bits);
+            }
+            if bits.is_overflowed() {
+                return Err(Error::Corrupted);
+            }
+
+            let literals = self
+                .literals
+                .get(lit_pos..lit_pos + ll)
+                .ok_or(Error::Corrupted)?;
+            out.extend_from_slice(literals);
+            lit_pos += ll;
+
+            let rep = &mut self.rep;
+            let offset = if offset_value > 3 {
+                let offset = offset_value - 3;
+                *rep = [offset, rep[0], rep[1]];
+                offset
+            } else {
+                match offset_value + (ll == 0) as usize {
+                    1 => rep[0],
+                    2 => {
+                        *rep = [rep[1], rep[0], rep[2]];
+                        rep[0]
+                    }
+                    3 => {
+                        *rep = [rep[2], rep[0], rep[1]];
+                        rep[0]
+                    }
+                    _ => {
+                        let offset = rep[0] - 1;
+                        *rep = [offset, rep[0], rep[1]];
+                        offset
+                    }
+                }
+            };
+            if offset == 0 || offset > out.len() || out.len() - block_start + ml > MAX_BLOCK_SIZE {
+                return Err(Error::Corrupted);
+            }
+            if offset >= ml {
+                let start = out.len() - offset;
+                out.extend_from_within(start..start + ml);
+            } else {
+                // overlapping the data being written
+                for _ in 0..ml {
+                    out.push(out[out.len() - offset]);
+                }
+            }
+        }
+        if bits.remaining() != 0 {
+            return Err(Error::Corrupted);
+        }
+        out.extend_from_slice(&self.literals[lit_pos..]);
+        if out.len() - block_start > MAX_BLOCK_SIZE {
+            return Err(Error::Corrupted);
+        }
+        Ok(())
+    }
+}
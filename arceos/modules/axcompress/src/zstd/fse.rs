@@ -0,0 +1,152 @@
+//! Finite State Entropy (tANS) decoding tables.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::bits::{BackwardReader, ForwardReader};
+use crate::Error;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Entry {
+    symbol: u8,
+    nb_bits: u8,
+    baseline: u16,
+}
+
+/// An FSE decoding table.
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    entries: Vec<Entry>,
+    accuracy_log: u32,
+}
+
+impl Table {
+    /// Builds the table of normalized probabilities, -1 being "less than 1".
+    pub fn from_probabilities(probs: &[i16], accuracy_log: u32) -> Result<Self, Error> {
+        let size = 1usize << accuracy_log;
+        let mut entries = vec![Entry::default(); size];
+        // the low probability symbols at the end
+        let mut high = size;
+        for (s, &p) in probs.iter().enumerate() {
+            if p == -1 {
+                high -= 1;
+                entries[high].symbol = s as u8;
+            }
+        }
+        let step = (size >> 1) + (size >> 3) + 3;
+        let mut pos = 0;
+        for (s, &p) in probs.iter().enumerate() {
+            for _ in 0..p.max(0) {
+                entries[pos].symbol = s as u8;
+                loop {
+                    pos = (pos + step) & (size - 1);
+                    if pos < high {
+                        break;
+                    }
+                }
+            }
+        }
+        if pos != 0 {
+            return Err(Error::Corrupted);
+        }
+        let mut next: Vec<u32> = probs.iter().map(|&p| p.max(1) as u32).collect();
+        for e in &mut entries {
+            let n = &mut next[e.symbol as usize];
+            let nb_bits = accuracy_log - n.ilog2();
+            e.nb_bits = nb_bits as u8;
+            e.baseline = ((*n << nb_bits) - size as u32) as u16;
+            *n += 1;
+        }
+        Ok(Self {
+            entries,
+            accuracy_log,
+        })
+    }
+
+    /// A table of a single symbol, for the RLE mode.
+    pub fn rle(symbol: u8) -> Self {
+        Self {
+            entries: vec![Entry {
+                symbol,
+                nb_bits: 0,
+                baseline: 0,
+            }],
+            accuracy_log: 0,
+        }
+    }
+
+    /// Reads a table description, and returns the table and the size of the
+    /// description.
+    pub fn read(data: &[u8], max_symbol: usize, max_log: u32) -> Result<(Self, usize), Error> {
+        let mut bits = ForwardReader::new(data);
+        let accuracy_log = bits.read(4) + 5;
+        if accuracy_log > max_log {
+            return Err(Error::Corrupted);
+        }
+        let mut remaining = (1i32 << accuracy_log) + 1;
+        let mut threshold = 1i32 << accuracy_log;
+        let mut nb_bits = accuracy_log + 1;
+        let mut probs = Vec::with_capacity(max_symbol + 1);
+        while remaining > 1 {
+            if probs.len() > max_symbol {
+                return Err(Error::Corrupted);
+            }
+            let max = 2 * threshold - 1 - remaining;
+            let low = bits.read(nb_bits - 1) as i32;
+            let mut count = if low < max {
+                low
+            } else {
+                let v = low | (bits.read(1) as i32) << (nb_bits - 1);
+                if v >= threshold {
+                    v - max
+                } else {
+                    v
+                }
+            };
+            count -= 1;
+            remaining -= count.abs();
+            probs.push(count as i16);
+            if count == 0 {
+                // repeated zeros
+                loop {
+                    let repeat = bits.read(2);
+                    probs.extend(core::iter::repeat(0).take(repeat as usize));
+                    if repeat != 3 {
+                        break;
+                    }
+                }
+            }
+            while remaining < threshold && nb_bits > 1 {
+                nb_bits -= 1;
+                threshold >>= 1;
+            }
+        }
+        if remaining != 1 || probs.len() > max_symbol + 1 {
+            return Err(Error::Corrupted);
+        }
+        let size = bits.bytes_read()?;
+        Ok((Self::from_probabilities(&probs, accuracy_log)?, size))
+    }
+}
+
+/// A decoding state in a table.
+pub struct State<'t> {
+    table: &'t Table,
+    state: usize,
+}
+
+impl<'t> State<'t> {
+    pub fn new(table: &'t Table, bits: &mut BackwardReader) -> Self {
+        let state = bits.read(table.accuracy_log) as usize;
+        Self { table, state }
+    }
+
+    pub fn symbol(&self) -> u8 {
+        self.table.entries[self.state].symbol
+    }
+
+    pub fn update(&mut self, bits: &mut BackwardReader) {
+        let e = self.table.entries[self.state];
+        self.state = e.baseline as usize + bits.read(e.nb_bits as u32) as usize;
+    }
+}
@@ -0,0 +1,156 @@
+//! Huffman decoding of the literals.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::bits::BackwardReader;
+use super::fse::{State, Table as FseTable};
+use crate::Error;
+
+const MAX_BITS: u32 = 11;
+/// The accuracy of the FSE table of the weights.
+const MAX_WEIGHT_LOG: u32 = 6;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Entry {
+    symbol: u8,
+    nb_bits: u8,
+}
+
+/// A Huffman decoding table, indexed by the next `max_bits` bits.
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    entries: Vec<Entry>,
+    max_bits: u32,
+}
+
+impl Table {
+    /// Reads a tree description, and returns the table and the size of the
+    /// description.
+    pub fn read(data: &[u8]) -> Result<(Self, usize), Error> {
+        let header = *data.first().ok_or(Error::Corrupted)? as usize;
+        let mut weights = Vec::with_capacity(256);
+        let size = if header < 128 {
+            // FSE compressed
+            let data = data.get(1..1 + header).ok_or(Error::Corrupted)?;
+            let (table, table_size) = FseTable::read(data, 255, MAX_WEIGHT_LOG)?;
+            let mut bits = BackwardReader::new(&data[table_size..])?;
+            let mut states = [State::new(&table, &mut bits), State::new(&table, &mut bits)];
+            'decode: loop {
+                for i in 0..2 {
+                    weights.push(states[i].symbol());
+                    states[i].update(&mut bits);
+                    if bits.is_overflowed() {
+                        weights.push(states[1 - i].symbol());
+                        break 'decode;
+                    }
+                    if weights.len() > 255 {
+                        return Err(Error::Corrupted);
+                    }
+                }
+            }
+            1 + header
+        } else {
+            // 4 bits each
+            let count = header - 127;
+            let data = data.get(1..1 + count.div_ceil(2)).ok_or(Error::Corrupted)?;
+            for i in 0..count {
+                weights.push((data[i / 2] >> if i % 2 == 0 { 4 } else { 0 }) & 0xf);
+            }
+            1 + data.len()
+        };
+        Ok((Self::from_weights(&mut weights)?, size))
+    }
+
+    /// Builds the table of the weights of all the symbols but the last one,
+    /// whose weight is implied.
+    fn from_weights(weights: &mut Vec<u8>) -> Result<Self, Error> {
+        if weights.len() > 255 {
+            return Err(Error::Corrupted);
+        }
+        let mut sum = 0u32;
+        for &w in weights.iter() {
+            if w as u32 > MAX_BITS {
+                return Err(Error::Corrupted);
+            }
+            if w > 0 {
+                sum += 1 << (w - 1);
+            }
+        }
+        if sum == 0 {
+            return Err(Error::Corrupted);
+        }
+        let max_bits = sum.ilog2() + 1;
+        let left = (1 << max_bits) - sum;
+        if max_bits > MAX_BITS || !left.is_power_of_two() {
+            return Err(Error::Corrupted);
+        }
+        weights.push(left.ilog2() as u8 + 1);
+
+        // the symbols of weight 1 first
+        let mut rank_start = [0usize; MAX_BITS as usize + 2];
+        for &w in weights.iter().filter(|&&w| w > 0) {
+            rank_start[w as usize + 1] += 1 << (w - 1);
+        }
+        for w in 1..rank_start.len() {
+            rank_start[w] += rank_start[w - 1];
+        }
+        let mut entries = vec![Entry::default(); 1 << max_bits];
+        for (s, &w) in weights.iter().enumerate() {
+            if w == 0 {
+                continue;
+            }
+            let len = 1 << (w - 1);
+            let start = rank_start[w as usize];
+            entries[start..start + len].fill(Entry {
+                symbol: s as u8,
+                nb_bits: (max_bits + 1 - w as u32) as u8,
+            });
+            rank_start[w as usize] += len;
+        }
+        Ok(Self { entries, max_bits })
+    }
+
+    /// Decodes a stream to `out`, which it must fill exactly.
+    pub fn decode_stream(&self, data: &[u8], out: &mut [u8]) -> Result<(), Error> {
+        let mut bits = BackwardReader::new(data)?;
+        for b in out.iter_mut() {
+            let e = self.entries[bits.peek(self.max_bits) as usize];
+            *b = e.symbol;
+            bits.consume(e.nb_bits as u32);
+        }
+        if bits.remaining() != 0 {
+            return Err(Error::Corrupted);
+        }
+        Ok(())
+    }
+
+    /// Decodes the literals of one stream, or of four with a jump table.
+    pub fn decode(&self, data: &[u8], out: &mut [u8], four_streams: bool) -> Result<(), Error> {
+        if !four_streams {
+            return self.decode_stream(data, out);
+        }
+        if data.len() < 6 {
+            return Err(Error::Corrupted);
+        }
+        let size = |i: usize| u16::from_le_bytes([data[2 * i], data[2 * i + 1]]) as usize;
+        let sizes = [size(0), size(1), size(2)].map(Some);
+        let mut streams = &data[6..];
+        let segment = out.len().div_ceil(4);
+        // the last stream takes the rest of the data and of the output
+        for (i, size) in sizes.into_iter().chain([None]).enumerate() {
+            let len = size.unwrap_or(streams.len());
+            if len > streams.len() {
+                return Err(Error::Corrupted);
+            }
+            let start = (i * segment).min(out.len());
+            let end = match size {
+                Some(_) => (start + segment).min(out.len()),
+                None => out.len(),
+            };
+            self.decode_stream(&streams[..len], &mut out[start..end])?;
+            streams = &streams[len..];
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,267 @@
+//! Zstandard decompression.
+//!
+//! See [RFC 8878](https://www.rfc-editor.org/rfc/rfc8878). The frames with a
+//! dictionary are not supported.
+
+mod bits;
+mod block;
+mod fse;
+mod huffman;
+
+use alloc::vec::Vec;
+
+use self::block::Context;
+use crate::xxhash::Xxh64;
+use crate::{is_skippable, Decompress, Error, Staging, Status, Window};
+
+/// The magic number of a frame.
+pub const MAGIC: u32 = 0xfd2f_b528;
+
+/// The largest data of a block.
+const MAX_BLOCK_SIZE: usize = 128 * 1024;
+/// The default largest window a [`Decoder`] accepts.
+pub const DEFAULT_WINDOW_LIMIT: usize = 8 * 1024 * 1024;
+
+const BLOCK_RAW: u8 = 0;
+const BLOCK_RLE: u8 = 1;
+const BLOCK_COMPRESSED: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Magic,
+    /// The frame header descriptor.
+    Descriptor,
+    /// The window descriptor, the dictionary ID and the content size.
+    HeaderRest,
+    BlockHeader,
+    Block {
+        kind: u8,
+        last: bool,
+        size: usize,
+    },
+    Checksum,
+    SkipSize,
+    Skip(usize),
+}
+
+/// A streaming decompressor of Zstandard frames.
+///
+/// Its memory is about one and a half times the window of a frame, which the
+/// compressor sets (from 1 KiB, 8 MiB at most for the usual levels, and not
+/// more than the content of the frame), and the tables of the blocks. The
+/// frames whose window is beyond a limit are refused.
+pub struct Decoder {
+    stage: Stage,
+    staging: Staging,
+    window: Window,
+    window_limit: usize,
+    context: Context,
+    descriptor: u8,
+    block_max: usize,
+    content_size: Option<u64>,
+    produced: u64,
+    hasher: Xxh64,
+}
+
+impl Decoder {
+    /// Creates a decoder accepting windows up to [`DEFAULT_WINDOW_LIMIT`].
+    pub fn new() -> Self {
+        Self::with_window_limit(DEFAULT_WINDOW_LIMIT)
+    }
+
+    /// Creates a decoder accepting windows up to `limit` bytes.
+    pub fn with_window_limit(limit: usize) -> Self {
+        let mut staging = Staging::new();
+        staging.expect(4);
+        Self {
+            stage: Stage::Magic,
+            staging,
+            window: Window::new(),
+            window_limit: limit,
+            context: Context::new(),
+            descriptor: 0,
+            block_max: 0,
+            content_size: None,
+            produced: 0,
+            hasher: Xxh64::new(),
+        }
+    }
+
+    fn goto(&mut self, stage: Stage, need: usize) {
+        self.stage = stage;
+        self.staging.expect(need);
+    }
+
+    /// Handles the complete unit of the stage.
+    fn advance(&mut self) -> Result<(), Error> {
+        let unit = &self.staging.buf;
+        let le = |bytes: &[u8]| {
+            let mut v = 0u64;
+            for (i, &b) in bytes.iter().enumerate() {
+                v |= (b as u64) << (8 * i);
+            }
+            v
+        };
+        match self.stage {
+            Stage::Magic => match le(unit) as u32 {
+                MAGIC => self.goto(Stage::Descriptor, 1),
+                magic if is_skippable(magic) => self.goto(Stage::SkipSize, 4),
+                _ => return Err(Error::Corrupted),
+            },
+            Stage::Descriptor => {
+                let desc = unit[0];
+                if desc & 0b1000 != 0 {
+                    return Err(Error::Corrupted);
+                }
+                if desc & 0b11 != 0 {
+                    return Err(Error::Unsupported);
+                }
+                let single_segment = desc & 0b10_0000 != 0;
+                let fcs_size = match desc >> 6 {
+                    0 => single_segment as usize,
+                    flag => 1 << flag,
+                };
+                self.descriptor = desc;
+                self.goto(Stage::HeaderRest, !single_segment as usize + fcs_size);
+            }
+            Stage::HeaderRest => {
+                let single_segment = self.descriptor & 0b10_0000 != 0;
+                let (window, fcs) = if single_segment {
+                    (None, &unit[..])
+                } else {
+                    (Some(unit[0]), &unit[1..])
+                };
+                self.content_size = match fcs.len() {
+                    0 => None,
+                    2 => Some(le(fcs) + 256),
+                    _ => Some(le(fcs)),
+                };
+                let window_size = match window {
+                    Some(w) => {
+                        let base = 1u64 << (10 + (w >> 3));
+                        base + (base / 8) * (w & 7) as u64
+                    }
+                    None => self.content_size.unwrap(),
+                };
+                if window_size > self.window_limit as u64 {
+                    return Err(Error::WindowTooLarge);
+                }
+                let window_size = window_size as usize;
+                self.block_max = window_size.min(MAX_BLOCK_SIZE);
+                let content_size = self.content_size.and_then(|n| usize::try_from(n).ok());
+                self.window.reset(window_size, self.block_max, content_size);
+                self.context.reset();
+                self.produced = 0;
+                self.hasher = Xxh64::new();
+                self.goto(Stage::BlockHeader, 3);
+            }
+            Stage::BlockHeader => {
+                let header = le(unit) as u32;
+                let last = header & 1 != 0;
+                let kind = ((header >> 1) & 3) as u8;
+                let size = (header >> 3) as usize;
+                let need = match kind {
+                    BLOCK_RAW | BLOCK_COMPRESSED => size,
+                    BLOCK_RLE => 1,
+                    _ => return Err(Error::Corrupted),
+                };
+                if size > self.block_max {
+                    return Err(Error::Corrupted);
+                }
+                self.goto(Stage::Block { kind, last, size }, need);
+            }
+            Stage::Block { kind, last, size } => {
+                self.window.make_room(self.block_max);
+                let buf = &mut self.window.buf;
+                let start = buf.len();
+                match kind {
+                    BLOCK_RAW => buf.extend_from_slice(unit),
+                    BLOCK_RLE => buf.resize(start + size, unit[0]),
+                    _ => {
+                        let result = self.context.decode(unit, buf);
+                        if result.is_err() || buf.len() - start > self.block_max {
+                            return Err(Error::Corrupted);
+                        }
+                    }
+                }
+                self.produced += (buf.len() - start) as u64;
+                if self.descriptor & 0b100 != 0 {
+                    self.hasher.update(&buf[start..]);
+                }
+                if self.content_size.is_some_and(|n| self.produced > n) {
+                    return Err(Error::Corrupted);
+                }
+                if !last {
+                    self.goto(Stage::BlockHeader, 3);
+                    return Ok(());
+                }
+                if self.content_size.is_some_and(|n| self.produced != n) {
+                    return Err(Error::Corrupted);
+                }
+                if self.descriptor & 0b100 != 0 {
+                    self.goto(Stage::Checksum, 4);
+                } else {
+                    self.goto(Stage::Magic, 4);
+                }
+            }
+            Stage::Checksum => {
+                if self.hasher.finish() as u32 != le(unit) as u32 {
+                    return Err(Error::ChecksumMismatch);
+                }
+                self.goto(Stage::Magic, 4);
+            }
+            Stage::SkipSize => {
+                self.stage = Stage::Skip(le(unit) as usize);
+                self.staging.expect(0);
+            }
+            Stage::Skip(_) => self.goto(Stage::Magic, 4),
+        }
+        Ok(())
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decompress for Decoder {
+    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<Status, Error> {
+        let mut status = Status::default();
+        loop {
+            status.produced += self.window.flush(&mut output[status.produced..]);
+            if self.window.has_pending() {
+                break;
+            }
+            let input = &input[status.consumed..];
+            if let Stage::Skip(remaining) = self.stage {
+                let n = remaining.min(input.len());
+                status.consumed += n;
+                if n < remaining {
+                    self.stage = Stage::Skip(remaining - n);
+                    break;
+                }
+                self.goto(Stage::Magic, 4);
+                continue;
+            }
+            let (n, complete) = self.staging.fill(input);
+            status.consumed += n;
+            if !complete {
+                break;
+            }
+            self.advance()?;
+        }
+        status.done =
+            self.stage == Stage::Magic && self.staging.buf.is_empty() && !self.window.has_pending();
+        Ok(status)
+    }
+}
+
+/// Decompresses the frames of `src`, of `max_size` bytes at most.
+pub fn decompress(src: &[u8], max_size: usize) -> Result<Vec<u8>, Error> {
+    let mut out = alloc::vec![0; max_size];
+    let n = Decoder::new().decompress_all(src, &mut out)?;
+    out.truncate(n);
+    Ok(out)
+}
@@ -0,0 +1,234 @@
+//! AES block cipher (FIPS-197) with 128-bit and 256-bit keys.
+//!
+//! The software implementation works on bytes and does not use lookup tables
+//! other than the S-boxes. When compiled with the `aes` target feature on
+//! x86_64 (e.g. `RUSTFLAGS="-C target-feature=+aes"`), the AES-NI
+//! instructions are used instead.
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+const INV_SBOX: [u8; 256] = {
+    let mut inv = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        inv[SBOX[i] as usize] = i as u8;
+        i += 1;
+    }
+    inv
+};
+
+const RCON: [u8; 10] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1b, 0x36];
+
+/// Size of an AES block, in bytes.
+pub const AES_BLOCK_SIZE: usize = 16;
+
+pub(crate) type Block = [u8; AES_BLOCK_SIZE];
+
+/// An AES cipher with an expanded key.
+#[derive(Clone)]
+pub struct Aes {
+    round_keys: [Block; 15],
+    rounds: usize,
+}
+
+impl Aes {
+    /// Expands the given key, which must be 16 or 32 bytes long.
+    pub fn new(key: &[u8]) -> Option<Self> {
+        let nk = match key.len() {
+            16 => 4,
+            32 => 8,
+            _ => return None,
+        };
+        let rounds = nk + 6;
+        let mut w = [[0u8; 4]; 60];
+        for (i, word) in key.chunks_exact(4).enumerate() {
+            w[i].copy_from_slice(word);
+        }
+        for i in nk..4 * (rounds + 1) {
+            let mut temp = w[i - 1];
+            if i % nk == 0 {
+                temp.rotate_left(1);
+                temp = temp.map(|b| SBOX[b as usize]);
+                temp[0] ^= RCON[i / nk - 1];
+            } else if nk > 6 && i % nk == 4 {
+                temp = temp.map(|b| SBOX[b as usize]);
+            }
+            for j in 0..4 {
+                w[i][j] = w[i - nk][j] ^ temp[j];
+            }
+        }
+
+        let mut round_keys = [[0u8; AES_BLOCK_SIZE]; 15];
+        for (r, rk) in round_keys.iter_mut().take(rounds + 1).enumerate() {
+            for c in 0..4 {
+                rk[4 * c..4 * c + 4].copy_from_slice(&w[4 * r + c]);
+            }
+        }
+        Some(Self { round_keys, rounds })
+    }
+
+    /// Encrypts one block in place.
+    pub fn encrypt_block(&self, block: &mut Block) {
+        #[cfg(all(target_arch = "x86_64", target_feature = "aes"))]
+        unsafe {
+            aesni::encrypt(&self.round_keys[..=self.rounds], block)
+        }
+        #[cfg(not(all(target_arch = "x86_64", target_feature = "aes")))]
+        {
+            add_round_key(block, &self.round_keys[0]);
+            for r in 1..self.rounds {
+                sub_bytes(block, &SBOX);
+                shift_rows(block);
+                mix_columns(block);
+                add_round_key(block, &self.round_keys[r]);
+            }
+            sub_bytes(block, &SBOX);
+            shift_rows(block);
+            add_round_key(block, &self.round_keys[self.rounds]);
+        }
+    }
+
+    /// Decrypts one block in place.
+    pub fn decrypt_block(&self, block: &mut Block) {
+        #[cfg(all(target_arch = "x86_64", target_feature = "aes"))]
+        unsafe {
+            aesni::decrypt(&self.round_keys[..=self.rounds], block)
+        }
+        #[cfg(not(all(target_arch = "x86_64", target_feature = "aes")))]
+        {
+            add_round_key(block, &self.round_keys[self.rounds]);
+            for r in (1..self.rounds).rev() {
+                inv_shift_rows(block);
+                sub_bytes(block, &INV_SBOX);
+                add_round_key(block, &self.round_keys[r]);
+                inv_mix_columns(block);
+            }
+            inv_shift_rows(block);
+            sub_bytes(block, &INV_SBOX);
+            add_round_key(block, &self.round_keys[0]);
+        }
+    }
+}
+
+impl Drop for Aes {
+    fn drop(&mut self) {
+        // do not leave key material in freed memory
+        for rk in self.round_keys.iter_mut() {
+            unsafe { core::ptr::write_volatile(rk, [0; AES_BLOCK_SIZE]) };
+        }
+    }
+}
+
+#[inline]
+fn xtime(b: u8) -> u8 {
+    (b << 1) ^ (((b >> 7) & 1) * 0x1b)
+}
+
+#[inline]
+fn add_round_key(block: &mut Block, rk: &Block) {
+    for (b, k) in block.iter_mut().zip(rk) {
+        *b ^= k;
+    }
+}
+
+#[inline]
+fn sub_bytes(block: &mut Block, sbox: &[u8; 256]) {
+    for b in block.iter_mut() {
+        *b = sbox[*b as usize];
+    }
+}
+
+/// The state is stored column by column, i.e. `block[4 * c + r]` is row `r`
+/// of column `c`. Row `r` is rotated left by `r` columns.
+fn shift_rows(block: &mut Block) {
+    let s = *block;
+    for c in 0..4 {
+        for r in 0..4 {
+            block[4 * c + r] = s[4 * ((c + r) % 4) + r];
+        }
+    }
+}
+
+fn inv_shift_rows(block: &mut Block) {
+    let s = *block;
+    for c in 0..4 {
+        for r in 0..4 {
+            block[4 * ((c + r) % 4) + r] = s[4 * c + r];
+        }
+    }
+}
+
+fn mix_columns(block: &mut Block) {
+    for col in block.chunks_exact_mut(4) {
+        let [a0, a1, a2, a3] = [col[0], col[1], col[2], col[3]];
+        let t = a0 ^ a1 ^ a2 ^ a3;
+        col[0] ^= t ^ xtime(a0 ^ a1);
+        col[1] ^= t ^ xtime(a1 ^ a2);
+        col[2] ^= t ^ xtime(a2 ^ a3);
+        col[3] ^= t ^ xtime(a3 ^ a0);
+    }
+}
+
+fn inv_mix_columns(block: &mut Block) {
+    // InvMixColumns = MixColumns after multiplying each column by
+    // {04}x^2 + {05}, which is cheap to compute with xtime.
+    for col in block.chunks_exact_mut(4) {
+        let u = xtime(xtime(col[0] ^ col[2]));
+        let v = xtime(xtime(col[1] ^ col[3]));
+        col[0] ^= u;
+        col[1] ^= v;
+        col[2] ^= u;
+        col[3] ^= v;
+    }
+    mix_columns(block);
+}
+
+#[cfg(all(target_arch = "x86_64", target_feature = "aes"))]
+mod aesni {
+    use core::arch::x86_64::*;
+
+    use super::Block;
+
+    pub unsafe fn encrypt(round_keys: &[Block], block: &mut Block) {
+        let rk = |i: usize| _mm_loadu_si128(round_keys[i].as_ptr() as *const __m128i);
+        let last = round_keys.len() - 1;
+        let mut s = _mm_loadu_si128(block.as_ptr() as *const __m128i);
+        s = _mm_xor_si128(s, rk(0));
+        for i in 1..last {
+            s = _mm_aesenc_si128(s, rk(i));
+        }
+        s = _mm_aesenclast_si128(s, rk(last));
+        _mm_storeu_si128(block.as_mut_ptr() as *mut __m128i, s);
+    }
+
+    pub unsafe fn decrypt(round_keys: &[Block], block: &mut Block) {
+        // AESDEC uses the equivalent inverse cipher, which needs
+        // InvMixColumns applied to the middle round keys.
+        let rk = |i: usize| _mm_loadu_si128(round_keys[i].as_ptr() as *const __m128i);
+        let last = round_keys.len() - 1;
+        let mut s = _mm_loadu_si128(block.as_ptr() as *const __m128i);
+        s = _mm_xor_si128(s, rk(last));
+        for i in (1..last).rev() {
+            s = _mm_aesdec_si128(s, _mm_aesimc_si128(rk(i)));
+        }
+        s = _mm_aesdeclast_si128(s, rk(0));
+        _mm_storeu_si128(block.as_mut_ptr() as *mut __m128i, s);
+    }
+}
@@ -0,0 +1,225 @@
+//! ChaCha20-Poly1305 (RFC 8439).
+
+use axerrno::{ax_err, AxResult};
+
+use crate::AEAD_TAG_SIZE;
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+fn chacha20_block(key: &[u8; 32], counter: u32, nonce: &[u8; 12]) -> [u8; 64] {
+    let mut init = [0u32; 16];
+    init[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+    for (i, word) in key.chunks_exact(4).enumerate() {
+        init[4 + i] = u32::from_le_bytes(word.try_into().unwrap());
+    }
+    init[12] = counter;
+    for (i, word) in nonce.chunks_exact(4).enumerate() {
+        init[13 + i] = u32::from_le_bytes(word.try_into().unwrap());
+    }
+    let mut s = init;
+    for _ in 0..10 {
+        quarter_round(&mut s, 0, 4, 8, 12);
+        quarter_round(&mut s, 1, 5, 9, 13);
+        quarter_round(&mut s, 2, 6, 10, 14);
+        quarter_round(&mut s, 3, 7, 11, 15);
+        quarter_round(&mut s, 0, 5, 10, 15);
+        quarter_round(&mut s, 1, 6, 11, 12);
+        quarter_round(&mut s, 2, 7, 8, 13);
+        quarter_round(&mut s, 3, 4, 9, 14);
+    }
+    let mut out = [0; 64];
+    for (i, chunk) in out.chunks_exact_mut(4).enumerate() {
+        chunk.copy_from_slice(&s[i].wrapping_add(init[i]).to_le_bytes());
+    }
+    out
+}
+
+/// XORs `buf` with the ChaCha20 keystream starting at block `counter`.
+pub fn chacha20(key: &[u8; 32], counter: u32, nonce: &[u8; 12], buf: &mut [u8]) {
+    for (i, chunk) in buf.chunks_mut(64).enumerate() {
+        let keystream = chacha20_block(key, counter.wrapping_add(i as u32), nonce);
+        for (b, k) in chunk.iter_mut().zip(keystream) {
+            *b ^= k;
+        }
+    }
+}
+
+/// The Poly1305 one-time authenticator, with 26-bit limbs.
+struct Poly1305 {
+    r: [u32; 5],
+    h: [u32; 5],
+    pad: [u32; 4],
+}
+
+impl Poly1305 {
+    fn new(key: &[u8; 32]) -> Self {
+        let word = |i: usize| u32::from_le_bytes(key[i..i + 4].try_into().unwrap());
+        Self {
+            // r is clamped
+            r: [
+                word(0) & 0x3ffffff,
+                (word(3) >> 2) & 0x3ffff03,
+                (word(6) >> 4) & 0x3ffc0ff,
+                (word(9) >> 6) & 0x3f03fff,
+                (word(12) >> 8) & 0x00fffff,
+            ],
+            h: [0; 5],
+            pad: [word(16), word(20), word(24), word(28)],
+        }
+    }
+
+    /// Adds a block of at most 16 bytes, padded with zeros.
+    fn block(&mut self, data: &[u8]) {
+        let mut block = [0u8; 17];
+        block[..data.len()].copy_from_slice(data);
+        block[data.len()] = 1;
+        let word = |i: usize| u32::from_le_bytes(block[i..i + 4].try_into().unwrap());
+        let h = &mut self.h;
+        h[0] += word(0) & 0x3ffffff;
+        h[1] += (word(3) >> 2) & 0x3ffffff;
+        h[2] += (word(6) >> 4) & 0x3ffffff;
+        h[3] += (word(9) >> 6) & 0x3ffffff;
+        h[4] += (word(12) >> 8) | ((block[16] as u32) << 24);
+
+        let r = self.r.map(|r| r as u64);
+        let s = r.map(|r| r * 5);
+        let h = h.map(|h| h as u64);
+        let d = [
+            h[0] * r[0] + h[1] * s[4] + h[2] * s[3] + h[3] * s[2] + h[4] * s[1],
+            h[0] * r[1] + h[1] * r[0] + h[2] * s[4] + h[3] * s[3] + h[4] * s[2],
+            h[0] * r[2] + h[1] * r[1] + h[2] * r[0] + h[3] * s[4] + h[4] * s[3],
+            h[0] * r[3] + h[1] * r[2] + h[2] * r[1] + h[3] * r[0] + h[4] * s[4],
+            h[0] * r[4] + h[1] * r[3] + h[2] * r[2] + h[3] * r[1] + h[4] * r[0],
+        ];
+        let mut carry = 0;
+        for (h, d) in self.h.iter_mut().zip(d) {
+            let d = d + carry;
+            *h = (d & 0x3ffffff) as u32;
+            carry = d >> 26;
+        }
+        let h0 = self.h[0] as u64 + carry * 5;
+        self.h[0] = (h0 & 0x3ffffff) as u32;
+        self.h[1] += (h0 >> 26) as u32;
+    }
+
+    fn update_padded(&mut self, data: &[u8]) {
+        for chunk in data.chunks(16) {
+            let mut block = [0u8; 16];
+            block[..chunk.len()].copy_from_slice(chunk);
+            self.block(&block);
+        }
+    }
+
+    fn finalize(mut self) -> [u8; AEAD_TAG_SIZE] {
+        // fully carry h
+        let h = &mut self.h;
+        for i in 1..5 {
+            h[i] += h[i - 1] >> 26;
+            h[i - 1] &= 0x3ffffff;
+        }
+        h[0] += (h[4] >> 26) * 5;
+        h[4] &= 0x3ffffff;
+        h[1] += h[0] >> 26;
+        h[0] &= 0x3ffffff;
+
+        // g = h + 5 - 2^130, selected in constant time if not negative
+        let mut g = [0u32; 5];
+        let mut carry = 5;
+        for i in 0..4 {
+            let t = h[i] + carry;
+            g[i] = t & 0x3ffffff;
+            carry = t >> 26;
+        }
+        g[4] = (h[4] + carry).wrapping_sub(1 << 26);
+        // all ones if g is not negative, i.e. h >= 2^130 - 5
+        let mask = (g[4] >> 31).wrapping_sub(1);
+        for i in 0..5 {
+            h[i] = (h[i] & !mask) | (g[i] & mask);
+        }
+
+        // h mod 2^128, plus the pad
+        let words = [
+            h[0] | (h[1] << 26),
+            (h[1] >> 6) | (h[2] << 20),
+            (h[2] >> 12) | (h[3] << 14),
+            (h[3] >> 18) | (h[4] << 8),
+        ];
+        let mut tag = [0; AEAD_TAG_SIZE];
+        let mut carry = 0u64;
+        for (i, chunk) in tag.chunks_exact_mut(4).enumerate() {
+            let t = words[i] as u64 + self.pad[i] as u64 + carry;
+            chunk.copy_from_slice(&(t as u32).to_le_bytes());
+            carry = t >> 32;
+        }
+        tag
+    }
+}
+
+/// The ChaCha20-Poly1305 AEAD cipher.
+pub struct ChaCha20Poly1305 {
+    key: [u8; 32],
+}
+
+impl ChaCha20Poly1305 {
+    /// Creates the cipher with a 256-bit key.
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self { key: *key }
+    }
+
+    fn tag(&self, nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> [u8; AEAD_TAG_SIZE] {
+        let mut otk = chacha20_block(&self.key, 0, nonce);
+        let mut mac = Poly1305::new(otk[..32].try_into().unwrap());
+        otk.fill(0);
+        mac.update_padded(aad);
+        mac.update_padded(ciphertext);
+        let mut lengths = [0; 16];
+        lengths[..8].copy_from_slice(&(aad.len() as u64).to_le_bytes());
+        lengths[8..].copy_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+        mac.block(&lengths);
+        mac.finalize()
+    }
+
+    /// Encrypts `buf` in place, and returns the tag authenticating it with
+    /// `aad`.
+    pub fn seal_in_place(
+        &self,
+        nonce: &[u8; 12],
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> [u8; AEAD_TAG_SIZE] {
+        chacha20(&self.key, 1, nonce, buf);
+        self.tag(nonce, aad, buf)
+    }
+
+    /// Checks the tag of `buf` and `aad`, and decrypts `buf` in place.
+    ///
+    /// `buf` is left untouched if the tag does not match.
+    pub fn open_in_place(
+        &self,
+        nonce: &[u8; 12],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &[u8; AEAD_TAG_SIZE],
+    ) -> AxResult {
+        if !crate::ct_eq(&self.tag(nonce, aad, buf), tag) {
+            return ax_err!(InvalidData, "axcrypto: bad tag");
+        }
+        chacha20(&self.key, 1, nonce, buf);
+        Ok(())
+    }
+}
+
+impl Drop for ChaCha20Poly1305 {
+    fn drop(&mut self) {
+        unsafe { core::ptr::write_volatile(&mut self.key, [0; 32]) };
+    }
+}
@@ -0,0 +1,437 @@
+//! Ed25519 signatures (RFC 8032).
+//!
+//! The scalar multiplications and reductions are constant-time, as signing
+//! handles the secret key. The point decoding of verification only handles
+//! public data, so it is not.
+
+use crate::sha512::Sha512;
+
+const MASK: u64 = (1 << 51) - 1;
+
+/// An element of GF(2^255 - 19), with 51-bit limbs.
+#[derive(Clone, Copy)]
+pub(crate) struct Fe([u64; 5]);
+
+impl Fe {
+    pub const ZERO: Fe = Fe([0; 5]);
+    pub const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+    pub fn small(n: u64) -> Fe {
+        Fe([n, 0, 0, 0, 0])
+    }
+
+    pub fn from_bytes(b: &[u8; 32]) -> Fe {
+        let load = |i: usize| u64::from_le_bytes(b[i..i + 8].try_into().unwrap());
+        Fe([
+            load(0) & MASK,
+            (load(6) >> 3) & MASK,
+            (load(12) >> 6) & MASK,
+            (load(19) >> 1) & MASK,
+            (load(24) >> 12) & MASK,
+        ])
+    }
+
+    /// Carries the limbs to below 2^52.
+    fn reduce(mut l: [u64; 5]) -> Fe {
+        let c = l.map(|l| l >> 51);
+        for l in l.iter_mut() {
+            *l &= MASK;
+        }
+        l[0] += c[4] * 19;
+        for i in 1..5 {
+            l[i] += c[i - 1];
+        }
+        Fe(l)
+    }
+
+    pub fn to_bytes(self) -> [u8; 32] {
+        let mut l = Self::reduce(self.0).0;
+        // q is 1 if the value is at least p
+        let mut q = (l[0] + 19) >> 51;
+        for l in &l[1..] {
+            q = (l + q) >> 51;
+        }
+        l[0] += 19 * q;
+        for i in 0..4 {
+            l[i + 1] += l[i] >> 51;
+            l[i] &= MASK;
+        }
+        l[4] &= MASK;
+
+        let mut out = [0u8; 32];
+        let mut acc = 0u128;
+        let mut bits = 0;
+        let mut pos = 0;
+        for l in l {
+            acc |= (l as u128) << bits;
+            bits += 51;
+            while bits >= 8 && pos < 32 {
+                out[pos] = acc as u8;
+                acc >>= 8;
+                bits -= 8;
+                pos += 1;
+            }
+        }
+        if pos < 32 {
+            out[pos] = acc as u8;
+        }
+        out
+    }
+
+    pub fn add(self, rhs: Fe) -> Fe {
+        Self::reduce(core::array::from_fn(|i| self.0[i] + rhs.0[i]))
+    }
+
+    pub fn sub(self, rhs: Fe) -> Fe {
+        // adds 4p to stay positive
+        const FOUR_P: [u64; 5] = [
+            0x1f_ffff_ffff_ffb4,
+            0x1f_ffff_ffff_fffc,
+            0x1f_ffff_ffff_fffc,
+            0x1f_ffff_ffff_fffc,
+            0x1f_ffff_ffff_fffc,
+        ];
+        Self::reduce(core::array::from_fn(|i| self.0[i] + FOUR_P[i] - rhs.0[i]))
+    }
+
+    fn neg(self) -> Fe {
+        Fe::ZERO.sub(self)
+    }
+
+    pub fn mul(self, rhs: Fe) -> Fe {
+        let a = self.0.map(|a| a as u128);
+        let b = rhs.0.map(|b| b as u128);
+        let b19 = b.map(|b| b * 19);
+        let r = [
+            a[0] * b[0] + a[1] * b19[4] + a[2] * b19[3] + a[3] * b19[2] + a[4] * b19[1],
+            a[0] * b[1] + a[1] * b[0] + a[2] * b19[4] + a[3] * b19[3] + a[4] * b19[2],
+            a[0] * b[2] + a[1] * b[1] + a[2] * b[0] + a[3] * b19[4] + a[4] * b19[3],
+            a[0] * b[3] + a[1] * b[2] + a[2] * b[1] + a[3] * b[0] + a[4] * b19[4],
+            a[0] * b[4] + a[1] * b[3] + a[2] * b[2] + a[3] * b[1] + a[4] * b[0],
+        ];
+        let mut out = [0u64; 5];
+        let mut carry = 0u128;
+        for (out, r) in out.iter_mut().zip(r) {
+            let t = r + carry;
+            *out = t as u64 & MASK;
+            carry = t >> 51;
+        }
+        let t = out[0] as u128 + carry * 19;
+        out[0] = t as u64 & MASK;
+        out[1] += (t >> 51) as u64;
+        Fe(out)
+    }
+
+    pub fn square(self) -> Fe {
+        self.mul(self)
+    }
+
+    /// Raises to the power `exp`, given in little-endian.
+    fn pow(self, exp: &[u8; 32]) -> Fe {
+        let mut r = Fe::ONE;
+        for i in (0..256).rev() {
+            r = r.square();
+            if (exp[i / 8] >> (i % 8)) & 1 == 1 {
+                r = r.mul(self);
+            }
+        }
+        r
+    }
+
+    pub fn invert(self) -> Fe {
+        // p - 2
+        let mut exp = [0xff; 32];
+        exp[0] = 0xeb;
+        exp[31] = 0x7f;
+        self.pow(&exp)
+    }
+
+    fn eq(self, rhs: Fe) -> bool {
+        self.to_bytes() == rhs.to_bytes()
+    }
+
+    fn is_negative(self) -> bool {
+        self.to_bytes()[0] & 1 == 1
+    }
+
+    /// Swaps `a` and `b` if `swap` is 1, in constant time.
+    pub fn cswap(a: &mut Fe, b: &mut Fe, swap: u64) {
+        let mask = swap.wrapping_neg();
+        for i in 0..5 {
+            let t = mask & (a.0[i] ^ b.0[i]);
+            a.0[i] ^= t;
+            b.0[i] ^= t;
+        }
+    }
+}
+
+/// A point of edwards25519, in extended coordinates.
+#[derive(Clone, Copy)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+/// The curve constants.
+struct Curve {
+    d: Fe,
+    d2: Fe,
+    sqrt_m1: Fe,
+}
+
+impl Curve {
+    fn new() -> Self {
+        // d = -121665 / 121666
+        let d = Fe::small(121665).neg().mul(Fe::small(121666).invert());
+        // sqrt(-1) = 2^((p - 1) / 4)
+        let mut exp = [0xff; 32];
+        exp[0] = 0xfb;
+        exp[31] = 0x1f;
+        Self {
+            d,
+            d2: d.add(d),
+            sqrt_m1: Fe::small(2).pow(&exp),
+        }
+    }
+
+    fn identity(&self) -> Point {
+        Point {
+            x: Fe::ZERO,
+            y: Fe::ONE,
+            z: Fe::ONE,
+            t: Fe::ZERO,
+        }
+    }
+
+    /// Adds two points, with the complete formulas for a = -1.
+    fn add(&self, p: &Point, q: &Point) -> Point {
+        let a = p.y.sub(p.x).mul(q.y.sub(q.x));
+        let b = p.y.add(p.x).mul(q.y.add(q.x));
+        let c = p.t.mul(self.d2).mul(q.t);
+        let d = p.z.add(p.z).mul(q.z);
+        let (e, f, g, h) = (b.sub(a), d.sub(c), d.add(c), b.add(a));
+        Point {
+            x: e.mul(f),
+            y: g.mul(h),
+            z: f.mul(g),
+            t: e.mul(h),
+        }
+    }
+
+    /// Multiplies `p` by the little-endian scalar `k`, in constant time.
+    fn mul(&self, p: &Point, k: &[u8; 32]) -> Point {
+        let mut r = self.identity();
+        for i in (0..256).rev() {
+            r = self.add(&r, &r);
+            let mut sum = self.add(&r, p);
+            let bit = ((k[i / 8] >> (i % 8)) & 1) as u64;
+            for (a, b) in [
+                (&mut r.x, &mut sum.x),
+                (&mut r.y, &mut sum.y),
+                (&mut r.z, &mut sum.z),
+                (&mut r.t, &mut sum.t),
+            ] {
+                Fe::cswap(a, b, bit);
+            }
+        }
+        r
+    }
+
+    fn base_point(&self) -> Point {
+        let mut base = [0x66; 32];
+        base[0] = 0x58;
+        self.decompress(&base).unwrap()
+    }
+
+    fn decompress(&self, bytes: &[u8; 32]) -> Option<Point> {
+        let mut y_bytes = *bytes;
+        y_bytes[31] &= 0x7f;
+        let y = Fe::from_bytes(&y_bytes);
+        if y.to_bytes() != y_bytes {
+            return None;
+        }
+        // x^2 = (y^2 - 1) / (d y^2 + 1)
+        let yy = y.square();
+        let x2 = yy.sub(Fe::ONE).mul(self.d.mul(yy).add(Fe::ONE).invert());
+        // a square root is x2^((p + 3) / 8), maybe times sqrt(-1)
+        let mut exp = [0xff; 32];
+        exp[0] = 0xfe;
+        exp[31] = 0x0f;
+        let mut x = x2.pow(&exp);
+        if !x.square().eq(x2) {
+            x = x.mul(self.sqrt_m1);
+            if !x.square().eq(x2) {
+                return None;
+            }
+        }
+        let sign = bytes[31] >> 7 == 1;
+        if x.eq(Fe::ZERO) && sign {
+            return None;
+        }
+        if x.is_negative() != sign {
+            x = x.neg();
+        }
+        Some(Point {
+            x,
+            y,
+            z: Fe::ONE,
+            t: x.mul(y),
+        })
+    }
+
+    fn compress(&self, p: &Point) -> [u8; 32] {
+        let zinv = p.z.invert();
+        let (x, y) = (p.x.mul(zinv), p.y.mul(zinv));
+        let mut bytes = y.to_bytes();
+        bytes[31] |= (x.is_negative() as u8) << 7;
+        bytes
+    }
+}
+
+/// The order of the base point, in little-endian.
+const L: [u8; 32] = [
+    0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10,
+];
+
+fn to_words(bytes: &[u8; 32]) -> [u64; 4] {
+    core::array::from_fn(|i| u64::from_le_bytes(bytes[i * 8..i * 8 + 8].try_into().unwrap()))
+}
+
+fn less_than(a: &[u64; 4], b: &[u64; 4]) -> bool {
+    for i in (0..4).rev() {
+        if a[i] != b[i] {
+            return a[i] < b[i];
+        }
+    }
+    false
+}
+
+/// Reduces a 512-bit little-endian number modulo [`L`].
+fn reduce_scalar(bytes: &[u8; 64]) -> [u8; 32] {
+    let l = to_words(&L);
+    let mut r = [0u64; 4];
+    for i in (0..512).rev() {
+        // r = 2r + bit, which fits as r < L < 2^253
+        let bit = ((bytes[i / 8] >> (i % 8)) & 1) as u64;
+        for j in (1..4).rev() {
+            r[j] = (r[j] << 1) | (r[j - 1] >> 63);
+        }
+        r[0] = (r[0] << 1) | bit;
+        // subtracts L if r is not below it, without branches
+        let mut d = [0u64; 4];
+        let mut borrow = false;
+        for j in 0..4 {
+            let (t, b1) = r[j].overflowing_sub(l[j]);
+            let (t, b2) = t.overflowing_sub(borrow as u64);
+            d[j] = t;
+            borrow = b1 || b2;
+        }
+        let keep = (borrow as u64).wrapping_neg();
+        for j in 0..4 {
+            r[j] = (r[j] & keep) | (d[j] & !keep);
+        }
+    }
+    let mut out = [0; 32];
+    for (chunk, word) in out.chunks_exact_mut(8).zip(r) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+/// Computes `(a * b + c) mod L`.
+fn mul_add_scalar(a: &[u8; 32], b: &[u8; 32], c: &[u8; 32]) -> [u8; 32] {
+    let (a, b) = (to_words(a), to_words(b));
+    let mut w = [0u64; 8];
+    w[..4].copy_from_slice(&to_words(c));
+    for i in 0..4 {
+        let mut carry = 0u128;
+        for j in 0..4 {
+            let t = w[i + j] as u128 + a[i] as u128 * b[j] as u128 + carry;
+            w[i + j] = t as u64;
+            carry = t >> 64;
+        }
+        w[i + 4] = carry as u64;
+    }
+    let mut bytes = [0; 64];
+    for (chunk, word) in bytes.chunks_exact_mut(8).zip(w) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    reduce_scalar(&bytes)
+}
+
+/// Expands the 32-byte secret key into the secret scalar and the nonce
+/// prefix.
+fn expand_secret(secret_key: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let mut hasher = Sha512::new();
+    hasher.update(secret_key);
+    let h = hasher.finalize();
+    let mut scalar: [u8; 32] = h[..32].try_into().unwrap();
+    scalar[0] &= 248;
+    scalar[31] &= 127;
+    scalar[31] |= 64;
+    (scalar, h[32..].try_into().unwrap())
+}
+
+/// Returns the public key of the 32-byte Ed25519 secret key.
+pub fn ed25519_public_key(secret_key: &[u8; 32]) -> [u8; 32] {
+    let curve = Curve::new();
+    let (scalar, _) = expand_secret(secret_key);
+    curve.compress(&curve.mul(&curve.base_point(), &scalar))
+}
+
+/// Signs `msg` with the 32-byte Ed25519 secret key.
+pub fn ed25519_sign(secret_key: &[u8; 32], msg: &[u8]) -> [u8; 64] {
+    let curve = Curve::new();
+    let b = curve.base_point();
+    let (scalar, prefix) = expand_secret(secret_key);
+    let public_key = curve.compress(&curve.mul(&b, &scalar));
+
+    let mut hasher = Sha512::new();
+    hasher.update(&prefix);
+    hasher.update(msg);
+    let r = reduce_scalar(&hasher.finalize());
+    let r_bytes = curve.compress(&curve.mul(&b, &r));
+
+    let mut hasher = Sha512::new();
+    hasher.update(&r_bytes);
+    hasher.update(&public_key);
+    hasher.update(msg);
+    let h = reduce_scalar(&hasher.finalize());
+
+    let mut sig = [0; 64];
+    sig[..32].copy_from_slice(&r_bytes);
+    sig[32..].copy_from_slice(&mul_add_scalar(&h, &scalar, &r));
+    sig
+}
+
+/// Checks the Ed25519 signature `sig` of `msg` by `public_key`.
+pub fn ed25519_verify(public_key: &[u8; 32], msg: &[u8], sig: &[u8; 64]) -> bool {
+    let (r_bytes, s) = sig.split_at(32);
+    let s: &[u8; 32] = s.try_into().unwrap();
+    if !less_than(&to_words(s), &to_words(&L)) {
+        return false;
+    }
+    let curve = Curve::new();
+    let Some(a) = curve.decompress(public_key) else {
+        return false;
+    };
+    let b = curve.base_point();
+
+    let mut hasher = Sha512::new();
+    hasher.update(r_bytes);
+    hasher.update(public_key);
+    hasher.update(msg);
+    let h = reduce_scalar(&hasher.finalize());
+
+    // R = [s]B - [h]A
+    let neg_a = Point {
+        x: a.x.neg(),
+        t: a.t.neg(),
+        ..a
+    };
+    let r = curve.add(&curve.mul(&b, s), &curve.mul(&neg_a, &h));
+    curve.compress(&r) == r_bytes
+}
@@ -0,0 +1,111 @@
+//! AES-GCM (NIST SP 800-38D) with 96-bit nonces.
+//!
+//! The keystream goes through [`Cipher`] in CTR mode, and so through a crypto
+//! engine if one is registered. GHASH is computed in software, in constant
+//! time.
+
+use axerrno::{ax_err, AxResult};
+
+use crate::aes::{Aes, AES_BLOCK_SIZE};
+use crate::{Cipher, CipherAlg, AEAD_TAG_SIZE};
+
+/// Multiplies `x` by `h` in GF(2^128), with the bit order of GCM.
+fn gf_mul(x: u128, h: u128) -> u128 {
+    let mut z = 0;
+    let mut v = h;
+    for i in 0..128 {
+        // the masks avoid branches on secret data
+        let bit = ((x >> (127 - i)) & 1).wrapping_neg();
+        z ^= v & bit;
+        let lsb = (v & 1).wrapping_neg();
+        v = (v >> 1) ^ (0xe1 << 120 & lsb);
+    }
+    z
+}
+
+struct Ghash {
+    h: u128,
+    y: u128,
+}
+
+impl Ghash {
+    fn update_padded(&mut self, data: &[u8]) {
+        for chunk in data.chunks(AES_BLOCK_SIZE) {
+            let mut block = [0; AES_BLOCK_SIZE];
+            block[..chunk.len()].copy_from_slice(chunk);
+            self.y = gf_mul(self.y ^ u128::from_be_bytes(block), self.h);
+        }
+    }
+}
+
+/// The AES-GCM AEAD cipher.
+pub struct AesGcm {
+    ctr: Cipher,
+    /// Encrypts the hash key and the tag mask.
+    aes: Aes,
+}
+
+impl AesGcm {
+    /// Creates the cipher with a 16-byte or 32-byte key.
+    pub fn new(key: &[u8]) -> AxResult<Self> {
+        let Some(aes) = Aes::new(key) else {
+            return ax_err!(InvalidInput, "axcrypto: bad key size");
+        };
+        Ok(Self {
+            ctr: Cipher::new(CipherAlg::AesCtr, key)?,
+            aes,
+        })
+    }
+
+    fn counter_block(nonce: &[u8; 12], counter: u32) -> [u8; AES_BLOCK_SIZE] {
+        let mut block = [0; AES_BLOCK_SIZE];
+        block[..12].copy_from_slice(nonce);
+        block[12..].copy_from_slice(&counter.to_be_bytes());
+        block
+    }
+
+    fn tag(&self, nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> [u8; AEAD_TAG_SIZE] {
+        let mut h = [0; AES_BLOCK_SIZE];
+        self.aes.encrypt_block(&mut h);
+        let mut ghash = Ghash {
+            h: u128::from_be_bytes(h),
+            y: 0,
+        };
+        ghash.update_padded(aad);
+        ghash.update_padded(ciphertext);
+        let lengths = ((aad.len() as u128 * 8) << 64) | (ciphertext.len() as u128 * 8);
+        ghash.update_padded(&lengths.to_be_bytes());
+
+        let mut mask = Self::counter_block(nonce, 1);
+        self.aes.encrypt_block(&mut mask);
+        (ghash.y ^ u128::from_be_bytes(mask)).to_be_bytes()
+    }
+
+    /// Encrypts `buf` in place, and returns the tag authenticating it with
+    /// `aad`.
+    pub fn seal_in_place(
+        &self,
+        nonce: &[u8; 12],
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> AxResult<[u8; AEAD_TAG_SIZE]> {
+        self.ctr.encrypt(&Self::counter_block(nonce, 2), buf)?;
+        Ok(self.tag(nonce, aad, buf))
+    }
+
+    /// Checks the tag of `buf` and `aad`, and decrypts `buf` in place.
+    ///
+    /// `buf` is left untouched if the tag does not match.
+    pub fn open_in_place(
+        &self,
+        nonce: &[u8; 12],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &[u8; AEAD_TAG_SIZE],
+    ) -> AxResult {
+        if !crate::ct_eq(&self.tag(nonce, aad, buf), tag) {
+            return ax_err!(InvalidData, "axcrypto: bad tag");
+        }
+        self.ctr.decrypt(&Self::counter_block(nonce, 2), buf)
+    }
+}
@@ -0,0 +1,82 @@
+//! HMAC-SHA256 (RFC 2104), and HKDF (RFC 5869) on it.
+
+use crate::sha256::{Sha256, SHA256_DIGEST_SIZE};
+
+const BLOCK_SIZE: usize = 64;
+
+/// An incremental HMAC-SHA256.
+#[derive(Clone)]
+pub struct HmacSha256 {
+    inner: Sha256,
+    outer: Sha256,
+}
+
+impl HmacSha256 {
+    /// Creates a MAC keyed with `key`, of any length.
+    pub fn new(key: &[u8]) -> Self {
+        let mut block = [0u8; BLOCK_SIZE];
+        if key.len() > BLOCK_SIZE {
+            let mut hasher = Sha256::new();
+            hasher.update(key);
+            block[..SHA256_DIGEST_SIZE].copy_from_slice(&hasher.finalize());
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+        let (mut inner, mut outer) = (Sha256::new(), Sha256::new());
+        inner.update(&block.map(|b| b ^ 0x36));
+        outer.update(&block.map(|b| b ^ 0x5c));
+        block.fill(0);
+        Self { inner, outer }
+    }
+
+    /// Adds `data` to the message.
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    /// Returns the MAC of the message.
+    pub fn finalize(self) -> [u8; SHA256_DIGEST_SIZE] {
+        let mut outer = self.outer;
+        outer.update(&self.inner.finalize());
+        outer.finalize()
+    }
+
+    /// Checks the MAC of the message against `tag`, in constant time.
+    pub fn verify(self, tag: &[u8]) -> bool {
+        crate::ct_eq(&self.finalize(), tag)
+    }
+}
+
+/// Computes the HMAC-SHA256 of `data` with `key`.
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; SHA256_DIGEST_SIZE] {
+    let mut mac = HmacSha256::new(key);
+    mac.update(data);
+    mac.finalize()
+}
+
+/// HKDF-Extract: derives a pseudorandom key from the input keying material
+/// `ikm` and `salt`.
+pub fn hkdf_extract(salt: &[u8], ikm: &[u8]) -> [u8; SHA256_DIGEST_SIZE] {
+    hmac_sha256(salt, ikm)
+}
+
+/// HKDF-Expand: fills `out` with keying material derived from the
+/// pseudorandom key `prk`, bound to `info`.
+///
+/// # Panics
+///
+/// Panics if `out` is longer than 255 digests.
+pub fn hkdf_expand(prk: &[u8], info: &[u8], out: &mut [u8]) {
+    assert!(out.len() <= 255 * SHA256_DIGEST_SIZE);
+    let mut block = [0; SHA256_DIGEST_SIZE];
+    for (i, chunk) in out.chunks_mut(SHA256_DIGEST_SIZE).enumerate() {
+        let mut mac = HmacSha256::new(prk);
+        if i > 0 {
+            mac.update(&block);
+        }
+        mac.update(info);
+        mac.update(&[i as u8 + 1]);
+        block = mac.finalize();
+        chunk.copy_from_slice(&block[..chunk.len()]);
+    }
+}
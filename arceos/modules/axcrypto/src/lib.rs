@@ -0,0 +1,194 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) cryptographic algorithms.
+//!
+//! It provides AES ciphers ([`Cipher`]) and SHA-256 ([`sha256`]), computed by
+//! a hardware crypto engine if one supporting the algorithm is registered
+//! (with [`register_engine`], e.g. by the virtio-crypto driver of
+//! `axdriver`), or else in software.
+//!
+//! Built on them, or in software only:
+//!
+//! - SHA-512 ([`Sha512`]), HMAC-SHA256 ([`HmacSha256`]) and HKDF
+//!   ([`hkdf_extract`], [`hkdf_expand`]).
+//! - The AEAD ciphers AES-GCM ([`AesGcm`]) and ChaCha20-Poly1305
+//!   ([`ChaCha20Poly1305`]).
+//! - Ed25519 signatures ([`ed25519_sign`], [`ed25519_verify`]).
+//! - X25519 key exchange ([`x25519`]).
+//!
+//! Secret data is handled in constant time, except for the S-box lookups of
+//! the software AES when AES-NI is not used.
+
+#![cfg_attr(not(test), no_std)]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+#[cfg(test)]
+mod tests;
+
+mod aes;
+mod chacha20poly1305;
+mod ed25519;
+mod gcm;
+mod hmac;
+mod modes;
+mod sha256;
+mod sha512;
+mod x25519;
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+
+use axerrno::{ax_err, AxResult};
+use kspin::SpinNoIrq;
+
+pub use self::aes::AES_BLOCK_SIZE;
+pub use self::chacha20poly1305::{chacha20, ChaCha20Poly1305};
+pub use self::ed25519::{ed25519_public_key, ed25519_sign, ed25519_verify};
+pub use self::gcm::AesGcm;
+pub use self::hmac::{hkdf_expand, hkdf_extract, hmac_sha256, HmacSha256};
+pub use self::sha256::{Sha256, SHA256_DIGEST_SIZE};
+pub use self::sha512::{Sha512, SHA512_DIGEST_SIZE};
+pub use self::x25519::{x25519, x25519_public_key};
+
+/// Size of the authentication tag of the AEAD ciphers, in bytes.
+pub const AEAD_TAG_SIZE: usize = 16;
+
+/// The cipher algorithms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CipherAlg {
+    /// AES in ECB mode, with no IV.
+    AesEcb,
+    /// AES in CBC mode, with a 16-byte IV.
+    AesCbc,
+    /// AES in CTR mode, with a 16-byte big-endian initial counter.
+    AesCtr,
+    /// XTS-AES (IEEE 1619), with a 16-byte tweak, and a key of twice the AES
+    /// key size.
+    AesXts,
+}
+
+/// The hash algorithms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlg {
+    /// SHA-256.
+    Sha256,
+}
+
+/// A cipher keyed for both directions.
+pub trait CipherOps: Send + Sync {
+    /// Encrypts `buf` in place.
+    fn encrypt(&self, iv: &[u8], buf: &mut [u8]) -> AxResult;
+
+    /// Decrypts `buf` in place.
+    fn decrypt(&self, iv: &[u8], buf: &mut [u8]) -> AxResult;
+}
+
+/// A hardware crypto engine.
+pub trait CryptoEngine: Send + Sync {
+    /// The name of the engine.
+    fn name(&self) -> &str;
+
+    /// Creates a cipher, or returns [`None`] if the algorithm or the key
+    /// size is not supported.
+    fn new_cipher(&self, alg: CipherAlg, key: &[u8]) -> Option<Box<dyn CipherOps>>;
+
+    /// Computes the digest of `data` into `out`, of the size of the digest.
+    ///
+    /// Returns [`AxError::Unsupported`](axerrno::AxError::Unsupported) if the
+    /// algorithm is not supported.
+    fn hash(&self, alg: HashAlg, data: &[u8], out: &mut [u8]) -> AxResult {
+        let _ = (alg, data, out);
+        ax_err!(Unsupported)
+    }
+}
+
+static ENGINES: SpinNoIrq<Vec<Arc<dyn CryptoEngine>>> = SpinNoIrq::new(Vec::new());
+
+/// Adds a crypto engine, preferred to the ones added before.
+pub fn register_engine(engine: Arc<dyn CryptoEngine>) {
+    info!("axcrypto: registered engine {:?}", engine.name());
+    ENGINES.lock().insert(0, engine);
+}
+
+fn engines() -> Vec<Arc<dyn CryptoEngine>> {
+    ENGINES.lock().clone()
+}
+
+/// A keyed cipher, computed by a crypto engine or in software.
+pub struct Cipher {
+    ops: Box<dyn CipherOps>,
+    engine: Option<Arc<dyn CryptoEngine>>,
+}
+
+impl Cipher {
+    /// Creates a cipher with the first engine supporting it, or in software.
+    ///
+    /// AES keys are 16 or 32 bytes long (twice that for XTS).
+    pub fn new(alg: CipherAlg, key: &[u8]) -> AxResult<Self> {
+        for engine in engines() {
+            if let Some(ops) = engine.new_cipher(alg, key) {
+                return Ok(Self {
+                    ops,
+                    engine: Some(engine),
+                });
+            }
+        }
+        Self::software(alg, key)
+    }
+
+    /// Creates a cipher computed in software.
+    pub fn software(alg: CipherAlg, key: &[u8]) -> AxResult<Self> {
+        match modes::SoftCipher::new(alg, key) {
+            Some(cipher) => Ok(Self {
+                ops: Box::new(cipher),
+                engine: None,
+            }),
+            None => ax_err!(InvalidInput, "axcrypto: bad key size"),
+        }
+    }
+
+    /// The name of the engine computing the cipher, or `"software"`.
+    pub fn engine_name(&self) -> &str {
+        self.engine
+            .as_ref()
+            .map_or("software", |engine| engine.name())
+    }
+
+    /// Encrypts `buf` in place. Except for CTR, its length must be a
+    /// multiple of [`AES_BLOCK_SIZE`].
+    pub fn encrypt(&self, iv: &[u8], buf: &mut [u8]) -> AxResult {
+        self.ops.encrypt(iv, buf)
+    }
+
+    /// Decrypts `buf` in place. Except for CTR, its length must be a
+    /// multiple of [`AES_BLOCK_SIZE`].
+    pub fn decrypt(&self, iv: &[u8], buf: &mut [u8]) -> AxResult {
+        self.ops.decrypt(iv, buf)
+    }
+}
+
+/// Computes the SHA-256 digest of `data`, with the first engine supporting
+/// it, or in software.
+///
+/// Use [`Sha256`] to hash data given in pieces.
+pub fn sha256(data: &[u8]) -> [u8; SHA256_DIGEST_SIZE] {
+    let mut digest = [0; SHA256_DIGEST_SIZE];
+    for engine in engines() {
+        if engine.hash(HashAlg::Sha256, data, &mut digest).is_ok() {
+            return digest;
+        }
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Compares two byte strings in constant time for a given length.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y));
+    // keeps the compiler from short-circuiting the fold
+    unsafe { core::ptr::read_volatile(&diff) == 0 }
+}
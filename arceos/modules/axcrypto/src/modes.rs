@@ -0,0 +1,138 @@
+//! The software implementation of the AES modes of operation.
+
+use axerrno::{ax_err, AxResult};
+
+use crate::aes::{Aes, Block, AES_BLOCK_SIZE};
+use crate::{CipherAlg, CipherOps};
+
+/// An AES cipher in one of the [`CipherAlg`] modes.
+pub(crate) struct SoftCipher {
+    alg: CipherAlg,
+    data: Aes,
+    /// The key encrypting the tweak, for XTS.
+    tweak: Option<Aes>,
+}
+
+impl SoftCipher {
+    pub fn new(alg: CipherAlg, key: &[u8]) -> Option<Self> {
+        if alg == CipherAlg::AesXts {
+            // two AES keys of the same size
+            let (k1, k2) = key.split_at(key.len() / 2);
+            return Some(Self {
+                alg,
+                data: Aes::new(k1)?,
+                tweak: Some(Aes::new(k2)?),
+            });
+        }
+        Some(Self {
+            alg,
+            data: Aes::new(key)?,
+            tweak: None,
+        })
+    }
+
+    fn check(&self, iv: &[u8], buf: &[u8]) -> AxResult {
+        let iv_len = match self.alg {
+            CipherAlg::AesEcb => 0,
+            _ => AES_BLOCK_SIZE,
+        };
+        if iv.len() != iv_len {
+            return ax_err!(InvalidInput, "axcrypto: bad IV length");
+        }
+        if self.alg != CipherAlg::AesCtr && buf.len() % AES_BLOCK_SIZE != 0 {
+            return ax_err!(
+                InvalidInput,
+                "axcrypto: length not a multiple of the block size"
+            );
+        }
+        Ok(())
+    }
+}
+
+impl CipherOps for SoftCipher {
+    fn encrypt(&self, iv: &[u8], buf: &mut [u8]) -> AxResult {
+        self.check(iv, buf)?;
+        match self.alg {
+            CipherAlg::AesEcb => blocks(buf).for_each(|b| self.data.encrypt_block(b)),
+            CipherAlg::AesCbc => {
+                let mut prev: Block = iv.try_into().unwrap();
+                for block in blocks(buf) {
+                    xor(block, &prev);
+                    self.data.encrypt_block(block);
+                    prev = *block;
+                }
+            }
+            CipherAlg::AesCtr => self.ctr(iv, buf),
+            CipherAlg::AesXts => self.xts(iv, buf, Aes::encrypt_block),
+        }
+        Ok(())
+    }
+
+    fn decrypt(&self, iv: &[u8], buf: &mut [u8]) -> AxResult {
+        self.check(iv, buf)?;
+        match self.alg {
+            CipherAlg::AesEcb => blocks(buf).for_each(|b| self.data.decrypt_block(b)),
+            CipherAlg::AesCbc => {
+                let mut prev: Block = iv.try_into().unwrap();
+                for block in blocks(buf) {
+                    let ciphertext = *block;
+                    self.data.decrypt_block(block);
+                    xor(block, &prev);
+                    prev = ciphertext;
+                }
+            }
+            CipherAlg::AesCtr => self.ctr(iv, buf),
+            CipherAlg::AesXts => self.xts(iv, buf, Aes::decrypt_block),
+        }
+        Ok(())
+    }
+}
+
+impl SoftCipher {
+    /// XORs the keystream of the big-endian 128-bit counter starting at `iv`.
+    fn ctr(&self, iv: &[u8], buf: &mut [u8]) {
+        let mut counter = u128::from_be_bytes(iv.try_into().unwrap());
+        for chunk in buf.chunks_mut(AES_BLOCK_SIZE) {
+            let mut keystream = counter.to_be_bytes();
+            self.data.encrypt_block(&mut keystream);
+            for (b, k) in chunk.iter_mut().zip(keystream) {
+                *b ^= k;
+            }
+            counter = counter.wrapping_add(1);
+        }
+    }
+
+    /// XTS (IEEE 1619) of one data unit, whose tweak is `iv` (e.g. the
+    /// sector number in little endian for `plain64`).
+    fn xts(&self, iv: &[u8], buf: &mut [u8], f: fn(&Aes, &mut Block)) {
+        let mut t: Block = iv.try_into().unwrap();
+        self.tweak.as_ref().unwrap().encrypt_block(&mut t);
+        for block in blocks(buf) {
+            xor(block, &t);
+            f(&self.data, block);
+            xor(block, &t);
+            mul_alpha(&mut t);
+        }
+    }
+}
+
+fn blocks(buf: &mut [u8]) -> impl Iterator<Item = &mut Block> {
+    buf.chunks_exact_mut(AES_BLOCK_SIZE)
+        .map(|chunk| chunk.try_into().unwrap())
+}
+
+#[inline]
+fn xor(block: &mut Block, t: &Block) {
+    for (b, t) in block.iter_mut().zip(t) {
+        *b ^= t;
+    }
+}
+
+/// Multiplies the tweak by the primitive element α of GF(2^128).
+#[inline]
+fn mul_alpha(t: &mut Block) {
+    let v = u128::from_le_bytes(*t);
+    let carry = (v >> 127) as u8;
+    *t = (v << 1).to_le_bytes();
+    t[0] ^= carry * 0x87;
+}
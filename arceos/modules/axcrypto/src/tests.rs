@@ -0,0 +1,221 @@
+use crate::{
+    ed25519_public_key, ed25519_sign, ed25519_verify, hkdf_expand, hkdf_extract, hmac_sha256,
+    sha256, x25519, x25519_public_key, AesGcm, ChaCha20Poly1305, Cipher, CipherAlg, Sha256, Sha512,
+};
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_sha256() {
+    assert_eq!(
+        sha256(b"abc").to_vec(),
+        hex("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+    );
+    // across the padding boundary, given in pieces
+    let data = [b'a'; 200];
+    let mut hasher = Sha256::new();
+    for piece in data.chunks(7) {
+        hasher.update(piece);
+    }
+    assert_eq!(hasher.finalize(), sha256(&data));
+}
+
+#[test]
+fn test_aes_ecb() {
+    // FIPS-197, appendix C.1
+    let cipher = Cipher::software(CipherAlg::AesEcb, &hex("000102030405060708090a0b0c0d0e0f"));
+    let cipher = cipher.unwrap();
+    let mut buf = hex("00112233445566778899aabbccddeeff");
+    cipher.encrypt(&[], &mut buf).unwrap();
+    assert_eq!(buf, hex("69c4e0d86a7b0430d8cdb78070b4c55a"));
+    cipher.decrypt(&[], &mut buf).unwrap();
+    assert_eq!(buf, hex("00112233445566778899aabbccddeeff"));
+}
+
+#[test]
+fn test_aes_xts() {
+    // IEEE 1619, vector 1
+    let cipher = Cipher::software(CipherAlg::AesXts, &[0; 32]).unwrap();
+    let mut buf = [0; 32];
+    cipher.encrypt(&[0; 16], &mut buf).unwrap();
+    assert_eq!(
+        buf.to_vec(),
+        hex("917cf69ebd68b2ec9b9fe9a3eadda692cd43d2f59598ed858c02c2652fbf922e")
+    );
+    cipher.decrypt(&[0; 16], &mut buf).unwrap();
+    assert_eq!(buf, [0; 32]);
+}
+
+#[test]
+fn test_aes_cbc_ctr_roundtrip() {
+    let key = [7; 32];
+    let iv = [0xff; 16]; // the counter wraps around
+    for alg in [CipherAlg::AesCbc, CipherAlg::AesCtr] {
+        let cipher = Cipher::new(alg, &key).unwrap();
+        assert_eq!(cipher.engine_name(), "software");
+        let mut buf = [0x5a; 48];
+        cipher.encrypt(&iv, &mut buf).unwrap();
+        assert_ne!(buf, [0x5a; 48]);
+        cipher.decrypt(&iv, &mut buf).unwrap();
+        assert_eq!(buf, [0x5a; 48]);
+    }
+}
+
+#[test]
+fn test_sha512() {
+    let mut hasher = Sha512::new();
+    hasher.update(b"abc");
+    assert_eq!(
+        hasher.finalize().to_vec(),
+        hex(concat!(
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a",
+            "2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+        ))
+    );
+}
+
+#[test]
+fn test_hmac_sha256() {
+    // RFC 4231, test case 2
+    assert_eq!(
+        hmac_sha256(b"Jefe", b"what do ya want for nothing?").to_vec(),
+        hex("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")
+    );
+}
+
+#[test]
+fn test_hkdf() {
+    // RFC 5869, test case 1
+    let prk = hkdf_extract(&hex("000102030405060708090a0b0c"), &[0x0b; 22]);
+    assert_eq!(
+        prk.to_vec(),
+        hex("077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5")
+    );
+    let mut okm = [0; 42];
+    hkdf_expand(&prk, &hex("f0f1f2f3f4f5f6f7f8f9"), &mut okm);
+    assert_eq!(
+        okm.to_vec(),
+        hex(concat!(
+            "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf",
+            "34007208d5b887185865"
+        ))
+    );
+}
+
+#[test]
+fn test_aes_gcm() {
+    // the GCM specification, test case 4
+    let gcm = AesGcm::new(&hex("feffe9928665731c6d6a8f9467308308")).unwrap();
+    let nonce = hex("cafebabefacedbaddecaf888").try_into().unwrap();
+    let aad = hex("feedfacedeadbeeffeedfacedeadbeefabaddad2");
+    let plain = hex(concat!(
+        "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72",
+        "1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39"
+    ));
+    let mut buf = plain.clone();
+    let tag = gcm.seal_in_place(&nonce, &aad, &mut buf).unwrap();
+    assert_eq!(
+        buf,
+        hex(concat!(
+            "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e",
+            "21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091"
+        ))
+    );
+    assert_eq!(tag.to_vec(), hex("5bc94fbc3221a5db94fae95ae7121a47"));
+    gcm.open_in_place(&nonce, &aad, &mut buf, &tag).unwrap();
+    assert_eq!(buf, plain);
+}
+
+#[test]
+fn test_chacha20_poly1305() {
+    // RFC 8439, section 2.8.2
+    let key: Vec<u8> = (0x80..0xa0).collect();
+    let aead = ChaCha20Poly1305::new(key.as_slice().try_into().unwrap());
+    let nonce = hex("070000004041424344454647").try_into().unwrap();
+    let aad = hex("50515253c0c1c2c3c4c5c6c7");
+    let plain = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+    let mut buf = plain.to_vec();
+    let mut tag = aead.seal_in_place(&nonce, &aad, &mut buf);
+    assert_eq!(tag.to_vec(), hex("1ae10b594f09e26a7e902ecbd0600691"));
+    assert_eq!(buf[..16].to_vec(), hex("d31a8d34648e60db7b86afbc53ef7ec2"));
+    tag[0] ^= 1;
+    assert!(aead.open_in_place(&nonce, &aad, &mut buf, &tag).is_err());
+    tag[0] ^= 1;
+    aead.open_in_place(&nonce, &aad, &mut buf, &tag).unwrap();
+    assert_eq!(buf, plain);
+}
+
+#[test]
+fn test_ed25519_verify() {
+    // RFC 8032, section 7.1, test 2
+    let public_key = hex("3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c");
+    let public_key = public_key.try_into().unwrap();
+    let mut sig: [u8; 64] = hex(concat!(
+        "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da",
+        "085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00"
+    ))
+    .try_into()
+    .unwrap();
+    assert!(ed25519_verify(&public_key, &[0x72], &sig));
+    assert!(!ed25519_verify(&public_key, &[0x73], &sig));
+    sig[40] ^= 1;
+    assert!(!ed25519_verify(&public_key, &[0x72], &sig));
+}
+
+#[test]
+fn test_ed25519_sign() {
+    // RFC 8032, section 7.1, test 1
+    let secret_key = hex("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60");
+    let secret_key = secret_key.try_into().unwrap();
+    let public_key = ed25519_public_key(&secret_key);
+    assert_eq!(
+        public_key.to_vec(),
+        hex("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a")
+    );
+    let sig = ed25519_sign(&secret_key, &[]);
+    assert_eq!(
+        sig.to_vec(),
+        hex(concat!(
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155",
+            "5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"
+        ))
+    );
+    assert!(ed25519_verify(&public_key, &[], &sig));
+    assert!(ed25519_verify(
+        &public_key,
+        b"abc",
+        &ed25519_sign(&secret_key, b"abc")
+    ));
+}
+
+#[test]
+fn test_x25519() {
+    // RFC 7748, section 5.2
+    let scalar = hex("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4");
+    let u = hex("e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c");
+    assert_eq!(
+        x25519(&scalar.try_into().unwrap(), &u.try_into().unwrap()).to_vec(),
+        hex("c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552")
+    );
+
+    // section 6.1
+    let alice = hex("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+    let bob = hex("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+    let (alice, bob) = (alice.try_into().unwrap(), bob.try_into().unwrap());
+    let alice_public = x25519_public_key(&alice);
+    assert_eq!(
+        alice_public.to_vec(),
+        hex("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a")
+    );
+    let shared = x25519(&bob, &alice_public);
+    assert_eq!(shared, x25519(&alice, &x25519_public_key(&bob)));
+    assert_eq!(
+        shared.to_vec(),
+        hex("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742")
+    );
+}
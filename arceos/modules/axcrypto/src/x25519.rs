@@ -0,0 +1,55 @@
+//! X25519 key exchange (RFC 7748), in constant time.
+
+use crate::ed25519::Fe;
+
+/// Multiplies the point of u-coordinate `u` by `scalar`, and returns the
+/// u-coordinate of the result.
+///
+/// Both sides of a key exchange compute the shared secret with their own
+/// secret scalar and the public key of the other.
+pub fn x25519(scalar: &[u8; 32], u: &[u8; 32]) -> [u8; 32] {
+    let mut k = *scalar;
+    k[0] &= 248;
+    k[31] &= 127;
+    k[31] |= 64;
+    let mut u = *u;
+    u[31] &= 127;
+
+    // the Montgomery ladder
+    let x1 = Fe::from_bytes(&u);
+    let (mut x2, mut z2) = (Fe::ONE, Fe::ZERO);
+    let (mut x3, mut z3) = (x1, Fe::ONE);
+    let a24 = Fe::small(121665);
+    let mut swap = 0;
+    for t in (0..255).rev() {
+        let bit = ((k[t / 8] >> (t % 8)) & 1) as u64;
+        swap ^= bit;
+        Fe::cswap(&mut x2, &mut x3, swap);
+        Fe::cswap(&mut z2, &mut z3, swap);
+        swap = bit;
+
+        let a = x2.add(z2);
+        let aa = a.square();
+        let b = x2.sub(z2);
+        let bb = b.square();
+        let e = aa.sub(bb);
+        let c = x3.add(z3);
+        let d = x3.sub(z3);
+        let da = d.mul(a);
+        let cb = c.mul(b);
+        x3 = da.add(cb).square();
+        z3 = x1.mul(da.sub(cb).square());
+        x2 = aa.mul(bb);
+        z2 = e.mul(aa.add(a24.mul(e)));
+    }
+    Fe::cswap(&mut x2, &mut x3, swap);
+    Fe::cswap(&mut z2, &mut z3, swap);
+    x2.mul(z2.invert()).to_bytes()
+}
+
+/// Returns the public key of the X25519 secret scalar.
+pub fn x25519_public_key(scalar: &[u8; 32]) -> [u8; 32] {
+    let mut base = [0; 32];
+    base[0] = 9;
+    x25519(scalar, &base)
+}
@@ -25,6 +25,21 @@ pub fn init_display(mut display_devs: AxDeviceContainer<AxDisplayDevice>) {
     MAIN_DISPLAY.init_once(Mutex::new(dev));
 }
 
+/// Resumes the graphics subsystem with the devices probed again, e.g. after
+/// restoring a snapshot.
+pub fn resume_display(mut display_devs: AxDeviceContainer<AxDisplayDevice>) {
+    let Some(dev) = display_devs.take_one() else {
+        warn!("No graphics device to resume the display with");
+        return;
+    };
+    if let Some(display) = MAIN_DISPLAY.get() {
+        info!("Resume graphics subsystem with {:?}", dev.device_name());
+        let old = core::mem::replace(&mut *display.lock(), dev);
+        // dropping it would reset the queues, now used by `dev`
+        core::mem::forget(old);
+    }
+}
+
 /// Gets the framebuffer information.
 pub fn framebuffer_info() -> DisplayInfo {
     MAIN_DISPLAY.lock().info()
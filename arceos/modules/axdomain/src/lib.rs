@@ -0,0 +1,209 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) isolation domains.
+//!
+//! A [`Domain`] runs a group of tasks, e.g. a third-party component, in its
+//! own address space, so that it cannot write to the memory of the other
+//! domains:
+//!
+//! - The kernel is shared: its image is mapped the same way in all the
+//!   domains, with its code and read-only data read-only, and so is the
+//!   kernel heap.
+//! - Each domain has its own heap, writable in its address space only, and
+//!   read-only in the others. The core application, in the kernel address
+//!   space, has one as well: the [`root`] domain, set up by [`init`].
+//! - [`Domain::call`] runs a function in a domain on the current task, only
+//!   switching the page table, and [`Domain::spawn`] spawns a task in it.
+//!
+//! A buggy component writing to the heap of the core application then
+//! triggers a page fault, instead of corrupting it. The kernel heap stays
+//! writable by all the domains, since the kernel runs on their behalf: the
+//! data to protect belongs in the domain heaps.
+//!
+//! The address spaces of the domains are built like the kernel one at boot,
+//! so the regions mapped in the kernel address space afterwards (e.g. MMIO
+//! mapped by the drivers) are not in the domains.
+//!
+//! It needs the `uspace` feature of [`axhal`], giving each task its page
+//! table, which is only implemented on RISC-V.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use allocator::{BaseAllocator, ByteAllocator};
+use axerrno::{ax_err, AxError, AxResult};
+use axhal::paging::MappingFlags;
+use axmm::AddrSpace;
+use axtask::{AxTaskRef, TaskInner};
+use kspin::SpinNoIrq;
+use lazyinit::LazyInit;
+use memory_addr::{align_up_4k, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+use tlsf_allocator::TlsfByteAllocator;
+
+/// An isolation domain: an address space, and a heap writable only in it.
+pub struct Domain {
+    name: &'static str,
+    aspace: &'static SpinNoIrq<AddrSpace>,
+    /// The root of the page table of `aspace`.
+    root: PhysAddr,
+    heap_start: VirtAddr,
+    heap_size: usize,
+    heap: SpinNoIrq<TlsfByteAllocator>,
+}
+
+/// All the domains, the root one first. They are never freed.
+static DOMAINS: SpinNoIrq<Vec<&'static Domain>> = SpinNoIrq::new(Vec::new());
+
+static ROOT: LazyInit<&'static Domain> = LazyInit::new();
+
+/// Restores the page table of the caller of [`Domain::call`], even if the
+/// function panics.
+struct CallGuard(PhysAddr);
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        axtask::switch_current_page_table(self.0);
+    }
+}
+
+impl Domain {
+    /// Creates a domain with a new address space and a heap of `heap_size`
+    /// bytes, read-only in the other domains.
+    pub fn new(name: &'static str, heap_size: usize) -> AxResult<&'static Self> {
+        let aspace = axmm::new_kernel_aspace()?;
+        let aspace = Box::leak(Box::new(SpinNoIrq::new(aspace)));
+        Self::register(name, aspace, heap_size)
+    }
+
+    fn register(
+        name: &'static str,
+        aspace: &'static SpinNoIrq<AddrSpace>,
+        heap_size: usize,
+    ) -> AxResult<&'static Self> {
+        let heap_size = align_up_4k(heap_size);
+        if heap_size == 0 {
+            return ax_err!(InvalidInput, "empty domain heap");
+        }
+        let heap_start = axalloc::global_allocator()
+            .alloc_pages(heap_size / PAGE_SIZE_4K, PAGE_SIZE_4K)
+            .map_err(|_| AxError::NoMemory)?;
+        // still writable everywhere, until it is protected below
+        let mut heap = TlsfByteAllocator::new();
+        heap.init(heap_start, heap_size);
+        let root = aspace.lock().page_table_root();
+        let domain: &'static Self = Box::leak(Box::new(Self {
+            name,
+            aspace,
+            root,
+            heap_start: heap_start.into(),
+            heap_size,
+            heap: SpinNoIrq::new(heap),
+        }));
+
+        let mut domains = DOMAINS.lock();
+        for other in domains.iter() {
+            other.protect(domain.heap_start, domain.heap_size)?;
+            domain.protect(other.heap_start, other.heap_size)?;
+        }
+        domains.push(domain);
+        info!(
+            "domain {}: heap at [{:#x}, {:#x})",
+            name,
+            domain.heap_start,
+            domain.heap_start + heap_size
+        );
+        Ok(domain)
+    }
+
+    /// Makes the range read-only in the address space of the domain.
+    fn protect(&self, start: VirtAddr, size: usize) -> AxResult {
+        self.aspace.lock().protect(start, size, MappingFlags::READ)
+    }
+
+    /// Returns the name of the domain.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the address space of the domain.
+    pub fn aspace(&self) -> &'static SpinNoIrq<AddrSpace> {
+        self.aspace
+    }
+
+    /// Returns the start address and the size of the heap of the domain.
+    pub fn heap_range(&self) -> (VirtAddr, usize) {
+        (self.heap_start, self.heap_size)
+    }
+
+    /// Returns the number of bytes allocated from the heap of the domain.
+    pub fn heap_used_bytes(&self) -> usize {
+        self.heap.lock().used_bytes()
+    }
+
+    /// Runs `f` in the domain, on the current task: its page table is
+    /// switched to the domain one, and back when `f` returns.
+    ///
+    /// It costs two page table switches, with their TLB flushes, and no task
+    /// switch. The calls can be nested.
+    pub fn call<R>(&self, f: impl FnOnce() -> R) -> R {
+        let _guard = CallGuard(axtask::switch_current_page_table(self.root));
+        f()
+    }
+
+    /// Spawns a task in the domain, with the default stack size.
+    pub fn spawn<F>(&self, f: F) -> AxTaskRef
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let mut task = TaskInner::new(f, String::from(self.name), axconfig::TASK_STACK_SIZE);
+        task.ctx_mut().set_page_table_root(self.root);
+        axtask::spawn_task(task)
+    }
+
+    /// Allocates memory from the heap of the domain, writable in it only.
+    pub fn alloc(&self, layout: Layout) -> AxResult<NonNull<u8>> {
+        self.call(|| self.heap.lock().alloc(layout))
+            .map_err(|_| AxError::NoMemory)
+    }
+
+    /// Gives back the memory allocated by [`Domain::alloc`] with the same
+    /// layout.
+    pub fn dealloc(&self, pos: NonNull<u8>, layout: Layout) {
+        self.call(|| self.heap.lock().dealloc(pos, layout))
+    }
+}
+
+/// Sets up the root domain, for the core application in the kernel address
+/// space, with a heap of `heap_size` bytes.
+///
+/// It must be called once.
+pub fn init(heap_size: usize) -> AxResult {
+    if ROOT.is_inited() {
+        return ax_err!(AlreadyExists, "root domain already set up");
+    }
+    ROOT.init_once(Domain::register("root", axmm::kernel_aspace(), heap_size)?);
+    Ok(())
+}
+
+/// Returns the root domain.
+///
+/// # Panics
+///
+/// Panics if [`init`] has not been called.
+pub fn root() -> &'static Domain {
+    *ROOT
+}
+
+/// Returns the domain the current task runs in, or [`None`] if it runs in
+/// another address space (e.g. a user one).
+pub fn current() -> Option<&'static Domain> {
+    let root = axhal::arch::read_page_table_root();
+    DOMAINS.lock().iter().find(|d| d.root == root).copied()
+}
@@ -1,5 +1,11 @@
-const NET_DEV_FEATURES: &[&str] = &["ixgbe", "virtio-net"];
-const BLOCK_DEV_FEATURES: &[&str] = &["ramdisk", "bcm2835-sdhci", "virtio-blk"];
+const NET_DEV_FEATURES: &[&str] = &["hosted-net", "ixgbe", "virtio-net"];
+const BLOCK_DEV_FEATURES: &[&str] = &[
+    "hosted-blk",
+    "ramdisk",
+    "zram",
+    "bcm2835-sdhci",
+    "virtio-blk",
+];
 const DISPLAY_DEV_FEATURES: &[&str] = &["virtio-gpu"];
 
 fn make_cfg_values(str_list: &[&str]) -> String {
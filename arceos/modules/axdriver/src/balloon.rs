@@ -0,0 +1,324 @@
+//! VirtIO memory balloon driver.
+//!
+//! The host asks the guest to give back memory by raising the target size of
+//! the balloon. The driver then inflates it, by allocating free pages and
+//! telling the host that it may take them, and deflates it again (freeing the
+//! pages) when the target is lowered. [`update`] must be called periodically,
+//! or on configuration change interrupts, to follow the target.
+//!
+//! If the host allows it (`VIRTIO_BALLOON_F_DEFLATE_ON_OOM`), the balloon is
+//! also deflated when the guest runs out of memory, through a reclaim hook of
+//! the page allocator.
+
+use alloc::vec::Vec;
+use core::ptr::NonNull;
+
+use axalloc::global_allocator;
+use axhal::mem::virt_to_phys;
+use kspin::SpinNoIrq;
+use virtio_drivers::queue::VirtQueue;
+use virtio_drivers::transport::{DeviceStatus, DeviceType, Transport};
+
+use crate::virtio::VirtIoHalImpl;
+
+const PAGE_SIZE: usize = 0x1000;
+
+/// The size of balloon pages, in the unit of which page frame numbers are
+/// given to the device, whatever the guest page size is.
+const BALLOON_PFN_SHIFT: usize = 12;
+
+const INFLATE_QUEUE: u16 = 0;
+const DEFLATE_QUEUE: u16 = 1;
+const QUEUE_SIZE: usize = 8;
+
+/// Maximum number of pages inflated or deflated in one request.
+const PFNS_PER_REQUEST: usize = 256;
+
+const F_MUST_TELL_HOST: u64 = 1 << 0;
+const F_DEFLATE_ON_OOM: u64 = 1 << 2;
+const F_VERSION_1: u64 = 1 << 32;
+const SUPPORTED_FEATURES: u64 = F_MUST_TELL_HOST | F_DEFLATE_ON_OOM | F_VERSION_1;
+
+/// The configuration space of a balloon device.
+#[repr(C)]
+struct BalloonConfig {
+    /// Number of pages the host wants the balloon to hold.
+    num_pages: u32,
+    /// Number of pages the balloon holds, written by the driver.
+    actual: u32,
+}
+
+/// Statistics of the memory balloon.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BalloonStats {
+    /// Number of pages the host wants the balloon to hold.
+    pub target_pages: usize,
+    /// Number of pages the balloon holds.
+    pub pages: usize,
+    /// Number of pages taken back from the balloon when out of memory.
+    pub oom_deflated_pages: u64,
+}
+
+/// A VirtIO memory balloon device.
+pub struct VirtIoBalloon<T: Transport> {
+    transport: T,
+    inflate_queue: VirtQueue<VirtIoHalImpl, QUEUE_SIZE>,
+    deflate_queue: VirtQueue<VirtIoHalImpl, QUEUE_SIZE>,
+    config: NonNull<BalloonConfig>,
+    /// Virtual addresses of the pages given to the host.
+    pages: Vec<usize>,
+    deflate_on_oom: bool,
+    oom_deflated_pages: u64,
+}
+
+unsafe impl<T: Transport> Send for VirtIoBalloon<T> {}
+
+impl<T: Transport> VirtIoBalloon<T> {
+    /// Initializes the balloon device on the given transport.
+    pub fn new(mut transport: T) -> Result<Self, virtio_drivers::Error> {
+        if transport.device_type() != DeviceType::MemoryBalloon {
+            return Err(virtio_drivers::Error::Unsupported);
+        }
+        transport.set_status(DeviceStatus::empty());
+        transport.set_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER);
+        let features = transport.read_device_features() & SUPPORTED_FEATURES;
+        transport.write_driver_features(features);
+        transport.set_status(
+            DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER | DeviceStatus::FEATURES_OK,
+        );
+        transport.set_guest_page_size(PAGE_SIZE as u32);
+
+        let inflate_queue = VirtQueue::new(&mut transport, INFLATE_QUEUE, false, false)?;
+        let deflate_queue = VirtQueue::new(&mut transport, DEFLATE_QUEUE, false, false)?;
+        let config = transport.config_space::<BalloonConfig>()?;
+        transport.finish_init();
+
+        Ok(Self {
+            transport,
+            inflate_queue,
+            deflate_queue,
+            config,
+            pages: Vec::new(),
+            deflate_on_oom: features & F_DEFLATE_ON_OOM != 0,
+            oom_deflated_pages: 0,
+        })
+    }
+
+    fn target_pages(&self) -> usize {
+        let num_pages = unsafe { core::ptr::addr_of!((*self.config.as_ptr()).num_pages) };
+        u32::from_le(unsafe { num_pages.read_volatile() }) as usize
+    }
+
+    fn write_actual(&mut self) {
+        let actual = unsafe { core::ptr::addr_of_mut!((*self.config.as_ptr()).actual) };
+        unsafe { actual.write_volatile((self.pages.len() as u32).to_le()) };
+    }
+
+    /// Returns the statistics of the balloon.
+    pub fn stats(&self) -> BalloonStats {
+        BalloonStats {
+            target_pages: self.target_pages(),
+            pages: self.pages.len(),
+            oom_deflated_pages: self.oom_deflated_pages,
+        }
+    }
+
+    /// Inflates or deflates the balloon towards the target size set by the
+    /// host, by at most 256 pages.
+    ///
+    /// Returns the number of pages added to (positive) or removed from
+    /// (negative) the balloon.
+    pub fn update(&mut self) -> Result<isize, virtio_drivers::Error> {
+        self.transport.ack_interrupt();
+        let target = self.target_pages();
+        let current = self.pages.len();
+        if target > current {
+            Ok(self.inflate(target - current)? as isize)
+        } else if target < current {
+            Ok(-(self.deflate(current - target)? as isize))
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Gives at most `num_pages` free pages to the host.
+    fn inflate(&mut self, num_pages: usize) -> Result<usize, virtio_drivers::Error> {
+        let num_pages = num_pages.min(PFNS_PER_REQUEST);
+        // reserve first, so that pushing does not allocate between page allocations
+        self.pages.reserve(num_pages);
+
+        let mut pfns = [0u32; PFNS_PER_REQUEST];
+        let mut count = 0;
+        while count < num_pages {
+            // the reclaim hook cannot deflate the balloon meanwhile, as it is locked
+            match global_allocator().alloc_pages(1, PAGE_SIZE) {
+                Ok(vaddr) => {
+                    let paddr = virt_to_phys(vaddr.into()).as_usize();
+                    pfns[count] = ((paddr >> BALLOON_PFN_SHIFT) as u32).to_le();
+                    self.pages.push(vaddr);
+                    count += 1;
+                }
+                Err(_) => break, // no free memory to give
+            }
+        }
+        if count == 0 {
+            return Ok(0);
+        }
+
+        let res = self.inflate_queue.add_notify_wait_pop(
+            &[as_bytes(&pfns[..count])],
+            &mut [],
+            &mut self.transport,
+        );
+        if let Err(e) = res {
+            // the host did not take the pages
+            for vaddr in self.pages.drain(self.pages.len() - count..) {
+                global_allocator().dealloc_pages(vaddr, 1);
+            }
+            return Err(e);
+        }
+        self.write_actual();
+        Ok(count)
+    }
+
+    /// Takes at most `num_pages` pages back from the host, and frees them.
+    fn deflate(&mut self, num_pages: usize) -> Result<usize, virtio_drivers::Error> {
+        let count = num_pages.min(PFNS_PER_REQUEST).min(self.pages.len());
+        if count == 0 {
+            return Ok(0);
+        }
+        let mut pfns = [0u32; PFNS_PER_REQUEST];
+        let start = self.pages.len() - count;
+        for (pfn, &vaddr) in pfns.iter_mut().zip(&self.pages[start..]) {
+            let paddr = virt_to_phys(vaddr.into()).as_usize();
+            *pfn = ((paddr >> BALLOON_PFN_SHIFT) as u32).to_le();
+        }
+        // with `VIRTIO_BALLOON_F_MUST_TELL_HOST`, the pages must not be used
+        // before the host acknowledges
+        self.deflate_queue.add_notify_wait_pop(
+            &[as_bytes(&pfns[..count])],
+            &mut [],
+            &mut self.transport,
+        )?;
+        for vaddr in self.pages.drain(start..) {
+            global_allocator().dealloc_pages(vaddr, 1);
+        }
+        self.write_actual();
+        Ok(count)
+    }
+}
+
+fn as_bytes(pfns: &[u32]) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(pfns.as_ptr().cast(), core::mem::size_of_val(pfns)) }
+}
+
+#[cfg(bus = "pci")]
+type BalloonTransport = axdriver_virtio::PciTransport;
+#[cfg(bus = "mmio")]
+type BalloonTransport = axdriver_virtio::MmioTransport;
+
+static BALLOON: SpinNoIrq<Option<VirtIoBalloon<BalloonTransport>>> = SpinNoIrq::new(None);
+
+fn register(transport: BalloonTransport) -> bool {
+    match VirtIoBalloon::new(transport) {
+        Ok(balloon) => {
+            let deflate_on_oom = balloon.deflate_on_oom;
+            *BALLOON.lock() = Some(balloon);
+            if deflate_on_oom {
+                axalloc::register_reclaim_hook(reclaim);
+            }
+            true
+        }
+        Err(e) => {
+            warn!("failed to initialize virtio-balloon: {:?}", e);
+            false
+        }
+    }
+}
+
+/// Probes a balloon device at the MMIO region, and returns whether one is
+/// found.
+#[cfg(bus = "mmio")]
+pub(crate) fn probe_mmio(mmio_base: usize, _mmio_size: usize) -> bool {
+    use axhal::mem::phys_to_virt;
+    use virtio_drivers::transport::mmio::VirtIOHeader;
+
+    let header = phys_to_virt(mmio_base.into()).as_mut_ptr() as *mut VirtIOHeader;
+    let Some(header) = NonNull::new(header) else {
+        return false;
+    };
+    match unsafe { BalloonTransport::new(header) } {
+        Ok(transport) if transport.device_type() == DeviceType::MemoryBalloon => {
+            register(transport)
+        }
+        _ => false,
+    }
+}
+
+/// Probes a balloon device at the PCI function, and returns whether one is
+/// found.
+#[cfg(bus = "pci")]
+pub(crate) fn probe_pci(
+    root: &mut axdriver_pci::PciRoot,
+    bdf: axdriver_pci::DeviceFunction,
+    dev_info: &axdriver_pci::DeviceFunctionInfo,
+) -> bool {
+    if dev_info.vendor_id != 0x1af4 || !matches!(dev_info.device_id, 0x1002 | 0x1045) {
+        return false;
+    }
+    match BalloonTransport::new::<VirtIoHalImpl>(root, bdf) {
+        Ok(transport) => register(transport),
+        Err(e) => {
+            warn!("failed to probe virtio-balloon at {}: {:?}", bdf, e);
+            false
+        }
+    }
+}
+
+/// Inflates or deflates the balloon towards the target size set by the host.
+///
+/// Returns the number of pages added to (positive) or removed from (negative)
+/// the balloon, which is 0 if there is no balloon device.
+pub fn update() -> isize {
+    let mut balloon = BALLOON.lock();
+    let Some(balloon) = balloon.as_mut() else {
+        return 0;
+    };
+    match balloon.update() {
+        Ok(n) => {
+            if n != 0 {
+                debug!("balloon: {:+} pages, now {}", n, balloon.pages.len());
+            }
+            n
+        }
+        Err(e) => {
+            warn!("balloon update failed: {:?}", e);
+            0
+        }
+    }
+}
+
+/// Returns the statistics of the balloon, or `None` if there is no balloon
+/// device.
+pub fn stats() -> Option<BalloonStats> {
+    BALLOON.lock().as_ref().map(VirtIoBalloon::stats)
+}
+
+/// The reclaim hook of the page allocator, which deflates the balloon.
+fn reclaim(num_pages: usize) -> usize {
+    // the balloon may be the one allocating
+    let Some(mut balloon) = BALLOON.try_lock() else {
+        return 0;
+    };
+    let Some(balloon) = balloon.as_mut() else {
+        return 0;
+    };
+    match balloon.deflate(num_pages) {
+        Ok(count) => {
+            balloon.oom_deflated_pages += count as u64;
+            warn!("balloon: deflated {} pages on out of memory", count);
+            count
+        }
+        Err(_) => 0,
+    }
+}
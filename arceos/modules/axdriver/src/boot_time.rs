@@ -0,0 +1,45 @@
+//! Time spent probing each driver at boot.
+//!
+//! Every probe of a driver is timed, whether it finds a device or not, so a
+//! driver that is slow to give up shows as well as one slow to initialize.
+
+use kspin::SpinNoIrq;
+
+/// The maximum number of drivers recorded, the others are left out.
+const MAX_DRIVERS: usize = 16;
+
+struct ProbeTimes {
+    entries: [(&'static str, u64); MAX_DRIVERS],
+    len: usize,
+}
+
+static PROBE_TIMES: SpinNoIrq<ProbeTimes> = SpinNoIrq::new(ProbeTimes {
+    entries: [("", 0); MAX_DRIVERS],
+    len: 0,
+});
+
+/// Runs the probe `f`, and adds its duration to the probe time of `driver`.
+pub(crate) fn measure<T>(driver: &'static str, f: impl FnOnce() -> T) -> T {
+    let start = axhal::time::monotonic_time_nanos();
+    let ret = f();
+    let nanos = axhal::time::monotonic_time_nanos() - start;
+
+    let mut times = PROBE_TIMES.lock();
+    let len = times.len;
+    if let Some(entry) = times.entries[..len].iter_mut().find(|e| e.0 == driver) {
+        entry.1 += nanos;
+    } else if len < MAX_DRIVERS {
+        times.entries[len] = (driver, nanos);
+        times.len += 1;
+    }
+    ret
+}
+
+/// Calls `f` with the name of each driver probed and the total time spent
+/// probing it in nanoseconds, in the order they were first probed.
+pub fn for_each_probe_time(mut f: impl FnMut(&'static str, u64)) {
+    let times = PROBE_TIMES.lock();
+    for &(driver, nanos) in &times.entries[..times.len] {
+        f(driver, nanos);
+    }
+}
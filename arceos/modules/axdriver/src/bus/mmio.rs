@@ -1,23 +1,84 @@
 #[allow(unused_imports)]
 use crate::{prelude::*, AllDevices};
 
+/// The MMIO regions left unclaimed by the first pass, for the deferred one.
+#[cfg(feature = "deferred-probe")]
+static PENDING: kspin::SpinNoIrq<alloc::vec::Vec<(usize, usize)>> =
+    kspin::SpinNoIrq::new(alloc::vec::Vec::new());
+
 impl AllDevices {
-    pub(crate) fn probe_bus_devices(&mut self) {
+    #[allow(unused_variables)]
+    pub(crate) fn probe_bus_devices(&mut self, deferred: bool) {
+        #[cfg(feature = "deferred-probe")]
+        if deferred {
+            let pending = core::mem::take(&mut *PENDING.lock());
+            for (base, size) in pending {
+                self.probe_mmio_region(base, size, true);
+            }
+            return;
+        }
+
         // TODO: parse device tree
         #[cfg(feature = "virtio")]
         for reg in axconfig::VIRTIO_MMIO_REGIONS {
-            for_each_drivers!(type Driver, {
-                if let Some(dev) = Driver::probe_mmio(reg.0, reg.1) {
+            if !self.probe_mmio_region(reg.0, reg.1, deferred) {
+                #[cfg(feature = "deferred-probe")]
+                PENDING.lock().push(*reg);
+            }
+        }
+    }
+
+    /// Probes the drivers of the pass `deferred` on one MMIO region, returns
+    /// whether a device is found.
+    #[allow(dead_code)]
+    fn probe_mmio_region(&mut self, base: usize, size: usize, deferred: bool) -> bool {
+        #[cfg(feature = "virtio-balloon")]
+        if crate::is_deferred("virtio-balloon") == deferred
+            && timed_probe!("virtio-balloon", crate::balloon::probe_mmio(base, size))
+        {
+            info!(
+                "registered a virtio-balloon device at [PA:{:#x}, PA:{:#x})",
+                base,
+                base + size,
+            );
+            return true;
+        }
+        #[cfg(feature = "virtio-crypto")]
+        if crate::is_deferred("virtio-crypto") == deferred
+            && timed_probe!("virtio-crypto", crate::crypto::probe_mmio(base, size))
+        {
+            info!(
+                "registered a virtio-crypto device at [PA:{:#x}, PA:{:#x})",
+                base,
+                base + size,
+            );
+            return true;
+        }
+        #[cfg(feature = "virtio-vsock")]
+        if crate::is_deferred("virtio-vsock") == deferred
+            && timed_probe!("virtio-vsock", crate::vsock::probe_mmio(base, size))
+        {
+            info!(
+                "registered a virtio-vsock device at [PA:{:#x}, PA:{:#x})",
+                base,
+                base + size,
+            );
+            return true;
+        }
+        for_each_drivers!(type Driver, name DRIVER_NAME, {
+            if crate::is_deferred(DRIVER_NAME) == deferred {
+                if let Some(dev) = timed_probe!(DRIVER_NAME, Driver::probe_mmio(base, size)) {
                     info!(
                         "registered a new {:?} device at [PA:{:#x}, PA:{:#x}): {:?}",
                         dev.device_type(),
-                        reg.0, reg.0 + reg.1,
+                        base, base + size,
                         dev.device_name(),
                     );
                     self.add_device(dev);
-                    continue; // skip to the next device
+                    return true;
                 }
-            });
-        }
+            }
+        });
+        false
     }
 }
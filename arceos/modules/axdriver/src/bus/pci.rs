@@ -1,6 +1,7 @@
 use crate::{prelude::*, AllDevices};
 use axdriver_pci::{
-    BarInfo, Cam, Command, DeviceFunction, HeaderType, MemoryBarType, PciRangeAllocator, PciRoot,
+    BarInfo, Cam, Command, DeviceFunction, DeviceFunctionInfo, HeaderType, MemoryBarType,
+    PciRangeAllocator, PciRoot,
 };
 use axhal::mem::phys_to_virt;
 
@@ -82,11 +83,26 @@ fn config_pci_device(
     Ok(())
 }
 
+/// The PCI functions left unclaimed by the first pass, for the deferred one.
+#[cfg(feature = "deferred-probe")]
+static PENDING: kspin::SpinNoIrq<alloc::vec::Vec<(DeviceFunction, DeviceFunctionInfo)>> =
+    kspin::SpinNoIrq::new(alloc::vec::Vec::new());
+
 impl AllDevices {
-    pub(crate) fn probe_bus_devices(&mut self) {
+    pub(crate) fn probe_bus_devices(&mut self, deferred: bool) {
         let base_vaddr = phys_to_virt(axconfig::PCI_ECAM_BASE.into());
         let mut root = unsafe { PciRoot::new(base_vaddr.as_mut_ptr(), Cam::Ecam) };
 
+        #[cfg(feature = "deferred-probe")]
+        if deferred {
+            // already configured by the first pass
+            let pending = core::mem::take(&mut *PENDING.lock());
+            for (bdf, dev_info) in pending {
+                self.probe_pci_function(&mut root, bdf, &dev_info, true);
+            }
+            return;
+        }
+
         // PCI 32-bit MMIO space
         let mut allocator = axconfig::PCI_RANGES
             .get(1)
@@ -98,19 +114,16 @@ impl AllDevices {
                 if dev_info.header_type != HeaderType::Standard {
                     continue;
                 }
-                match config_pci_device(&mut root, bdf, &mut allocator) {
-                    Ok(_) => for_each_drivers!(type Driver, {
-                        if let Some(dev) = Driver::probe_pci(&mut root, bdf, &dev_info) {
-                            info!(
-                                "registered a new {:?} device at {}: {:?}",
-                                dev.device_type(),
-                                bdf,
-                                dev.device_name(),
-                            );
-                            self.add_device(dev);
-                            continue; // skip to the next device
+                match timed_probe!(
+                    "pci-config",
+                    config_pci_device(&mut root, bdf, &mut allocator)
+                ) {
+                    Ok(_) => {
+                        if !self.probe_pci_function(&mut root, bdf, &dev_info, deferred) {
+                            #[cfg(feature = "deferred-probe")]
+                            PENDING.lock().push((bdf, dev_info));
                         }
-                    }),
+                    }
                     Err(e) => warn!(
                         "failed to enable PCI device at {}({}): {:?}",
                         bdf, dev_info, e
@@ -119,4 +132,73 @@ impl AllDevices {
             }
         }
     }
+
+    /// Probes the drivers of the pass `deferred` on one configured PCI
+    /// function, returns whether a device is found.
+    fn probe_pci_function(
+        &mut self,
+        root: &mut PciRoot,
+        bdf: DeviceFunction,
+        dev_info: &DeviceFunctionInfo,
+        deferred: bool,
+    ) -> bool {
+        #[cfg(feature = "virtio-balloon")]
+        if crate::is_deferred("virtio-balloon") == deferred
+            && timed_probe!(
+                "virtio-balloon",
+                crate::balloon::probe_pci(root, bdf, dev_info)
+            )
+        {
+            info!("registered a virtio-balloon device at {}", bdf);
+            return true;
+        }
+        #[cfg(feature = "virtio-crypto")]
+        if crate::is_deferred("virtio-crypto") == deferred
+            && timed_probe!(
+                "virtio-crypto",
+                crate::crypto::probe_pci(root, bdf, dev_info)
+            )
+        {
+            info!("registered a virtio-crypto device at {}", bdf);
+            return true;
+        }
+        #[cfg(feature = "virtio-vsock")]
+        if crate::is_deferred("virtio-vsock") == deferred
+            && timed_probe!("virtio-vsock", crate::vsock::probe_pci(root, bdf, dev_info))
+        {
+            info!("registered a virtio-vsock device at {}", bdf);
+            return true;
+        }
+        #[cfg(feature = "ivshmem")]
+        if crate::is_deferred("ivshmem") == deferred
+            && timed_probe!("ivshmem", crate::ivshmem::probe_pci(root, bdf, dev_info))
+        {
+            info!("registered an ivshmem device at {}", bdf);
+            return true;
+        }
+        #[cfg(all(feature = "can", target_arch = "x86_64"))]
+        if crate::is_deferred("kvaser-pci") == deferred
+            && timed_probe!("kvaser-pci", crate::can::probe_pci(root, bdf, dev_info))
+        {
+            info!("registered a kvaser_pci CAN controller at {}", bdf);
+            return true;
+        }
+        for_each_drivers!(type Driver, name DRIVER_NAME, {
+            if crate::is_deferred(DRIVER_NAME) == deferred {
+                if let Some(dev) =
+                    timed_probe!(DRIVER_NAME, Driver::probe_pci(root, bdf, dev_info))
+                {
+                    info!(
+                        "registered a new {:?} device at {}: {:?}",
+                        dev.device_type(),
+                        bdf,
+                        dev.device_name(),
+                    );
+                    self.add_device(dev);
+                    return true;
+                }
+            }
+        });
+        false
+    }
 }
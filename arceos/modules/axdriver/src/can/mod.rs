@@ -0,0 +1,435 @@
+//! CAN bus controllers, and sockets sending and receiving frames on them,
+//! like the raw sockets of SocketCAN.
+//!
+//! Controllers implement [`CanDriverOps`] and are added with
+//! [`register_controller`]. The ones found at boot are:
+//!
+//! - the SJA1000 of the `kvaser_pci` device of QEMU, on x86_64 (`can0`,
+//!   `can1`, ...), see [`Sja1000`].
+//!
+//! Virtual controllers (`vcan0`, `vcan1`, ...) are added with [`add_vcan`]:
+//! their bus has no other node, so only the sockets on them exchange frames,
+//! to test the applications without hardware.
+//!
+//! A [`CanSocket`] is opened on a controller by its name. It receives the
+//! frames matching its filters ([`CanSocket::set_filters`], all by default),
+//! both from the bus and sent by the other sockets on the controller (the
+//! local loopback of SocketCAN). The frames are received from the
+//! controllers by [`poll`], which [`CanSocket::recv`] calls.
+
+#[cfg(target_arch = "x86_64")]
+mod sja1000;
+
+use alloc::{
+    boxed::Box,
+    collections::VecDeque,
+    format,
+    string::String,
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axdriver_base::{DevError, DevResult};
+use kspin::SpinNoIrq;
+
+#[cfg(all(bus = "pci", target_arch = "x86_64"))]
+pub(crate) use self::sja1000::probe_pci;
+#[cfg(target_arch = "x86_64")]
+pub use self::sja1000::Sja1000;
+
+/// The bit rate of the controllers found at boot, in bits per second.
+pub const DEFAULT_BITRATE: u32 = 500_000;
+
+/// The flag of the extended identifiers, in the raw identifiers.
+pub const CAN_EFF_FLAG: u32 = 1 << 31;
+/// The flag of the remote frames, in the raw identifiers.
+pub const CAN_RTR_FLAG: u32 = 1 << 30;
+/// The bits of a standard identifier.
+pub const CAN_SFF_MASK: u32 = 0x7ff;
+/// The bits of an extended identifier.
+pub const CAN_EFF_MASK: u32 = 0x1fff_ffff;
+
+/// The frames queued on a socket, beyond which the new ones are dropped.
+const SOCKET_QUEUE_LEN: usize = 64;
+
+/// The identifier of a frame, also its priority on the bus (the lowest
+/// first).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CanId {
+    /// A standard identifier, of 11 bits.
+    Standard(u16),
+    /// An extended identifier, of 29 bits.
+    Extended(u32),
+}
+
+impl CanId {
+    /// Returns the standard identifier `id`, or [`None`] if it is larger
+    /// than 11 bits.
+    pub const fn standard(id: u16) -> Option<Self> {
+        match id as u32 & !CAN_SFF_MASK {
+            0 => Some(Self::Standard(id)),
+            _ => None,
+        }
+    }
+
+    /// Returns the extended identifier `id`, or [`None`] if it is larger
+    /// than 29 bits.
+    pub const fn extended(id: u32) -> Option<Self> {
+        match id & !CAN_EFF_MASK {
+            0 => Some(Self::Extended(id)),
+            _ => None,
+        }
+    }
+
+    /// Returns the identifier with [`CAN_EFF_FLAG`] if extended.
+    pub const fn raw(self) -> u32 {
+        match self {
+            Self::Standard(id) => id as u32,
+            Self::Extended(id) => id | CAN_EFF_FLAG,
+        }
+    }
+
+    /// Returns whether the identifier is extended.
+    pub const fn is_extended(self) -> bool {
+        matches!(self, Self::Extended(_))
+    }
+}
+
+/// A CAN frame, of data or remote (requesting the data of its identifier).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CanFrame {
+    id: CanId,
+    remote: bool,
+    dlc: u8,
+    data: [u8; CanFrame::MAX_DATA_LEN],
+}
+
+impl CanFrame {
+    /// The maximum length of the data of a frame.
+    pub const MAX_DATA_LEN: usize = 8;
+
+    /// Creates a data frame, or returns [`None`] if `data` is longer than
+    /// [`MAX_DATA_LEN`](Self::MAX_DATA_LEN).
+    pub fn new(id: CanId, data: &[u8]) -> Option<Self> {
+        if data.len() > Self::MAX_DATA_LEN {
+            return None;
+        }
+        let mut frame = Self {
+            id,
+            remote: false,
+            dlc: data.len() as u8,
+            data: [0; Self::MAX_DATA_LEN],
+        };
+        frame.data[..data.len()].copy_from_slice(data);
+        Some(frame)
+    }
+
+    /// Creates a remote frame requesting `len` bytes of data, or returns
+    /// [`None`] if `len` is larger than [`MAX_DATA_LEN`](Self::MAX_DATA_LEN).
+    pub fn remote(id: CanId, len: usize) -> Option<Self> {
+        if len > Self::MAX_DATA_LEN {
+            return None;
+        }
+        Some(Self {
+            id,
+            remote: true,
+            dlc: len as u8,
+            data: [0; Self::MAX_DATA_LEN],
+        })
+    }
+
+    pub fn id(&self) -> CanId {
+        self.id
+    }
+
+    /// Returns the identifier with [`CAN_EFF_FLAG`] and [`CAN_RTR_FLAG`], as
+    /// matched by the [filters](CanFilter).
+    pub fn raw_id(&self) -> u32 {
+        match self.remote {
+            true => self.id.raw() | CAN_RTR_FLAG,
+            false => self.id.raw(),
+        }
+    }
+
+    pub fn is_remote(&self) -> bool {
+        self.remote
+    }
+
+    /// Returns the data length code: the length of the data, or the one
+    /// requested by a remote frame.
+    pub fn dlc(&self) -> usize {
+        self.dlc as usize
+    }
+
+    /// Returns the data, empty for a remote frame.
+    pub fn data(&self) -> &[u8] {
+        match self.remote {
+            true => &[],
+            false => &self.data[..self.dlc as usize],
+        }
+    }
+}
+
+/// A filter of the frames received by a socket, as in SocketCAN: a frame
+/// matches if `frame.raw_id() & mask == id & mask`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CanFilter {
+    /// The raw identifier, see [`CanFrame::raw_id`].
+    pub id: u32,
+    pub mask: u32,
+}
+
+impl CanFilter {
+    /// Matches all the frames.
+    pub const ALL: Self = Self { id: 0, mask: 0 };
+
+    /// Matches the frames (of data or remote) with the identifier `id`.
+    pub const fn exact(id: CanId) -> Self {
+        let mask = match id {
+            CanId::Standard(_) => CAN_SFF_MASK,
+            CanId::Extended(_) => CAN_EFF_MASK,
+        };
+        Self {
+            id: id.raw(),
+            mask: mask | CAN_EFF_FLAG,
+        }
+    }
+
+    pub fn matches(&self, frame: &CanFrame) -> bool {
+        frame.raw_id() & self.mask == self.id & self.mask
+    }
+}
+
+/// The state of a controller on the bus, following its error counters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CanState {
+    /// Sending error frames on the errors it detects, as normal.
+    ErrorActive,
+    /// An error counter has reached the warning limit (96).
+    ErrorWarning,
+    /// An error counter has reached 128: the controller only sends passive
+    /// error frames, so it does not disturb the bus.
+    ErrorPassive,
+    /// The transmit error counter has reached 256: the controller is off the
+    /// bus, until it is restarted by [`set_bitrate`].
+    BusOff,
+    /// The controller is stopped, e.g. not started yet.
+    Stopped,
+}
+
+/// The operations of a CAN controller.
+pub trait CanDriverOps: Send {
+    /// The name of the interface, e.g. `can0`.
+    fn name(&self) -> &str;
+
+    /// Sets the bit rate, in bits per second, and (re)starts the controller
+    /// on the bus.
+    ///
+    /// Returns [`DevError::InvalidParam`] if the bit rate cannot be derived
+    /// from the clock of the controller.
+    fn start(&mut self, bitrate: u32) -> DevResult;
+
+    /// Queues a frame for transmission.
+    ///
+    /// Returns [`DevError::Again`] if the transmit buffers are full, and
+    /// [`DevError::BadState`] if the controller is stopped or off the bus.
+    fn transmit(&mut self, frame: &CanFrame) -> DevResult;
+
+    /// Returns a received frame, if any.
+    fn receive(&mut self) -> Option<CanFrame>;
+
+    fn state(&mut self) -> CanState;
+}
+
+/// A virtual controller, like `vcan` of Linux: the frames sent are only
+/// received by the other sockets on it.
+pub struct VirtualCan {
+    name: String,
+}
+
+impl VirtualCan {
+    pub fn new(name: String) -> Self {
+        Self { name }
+    }
+}
+
+impl CanDriverOps for VirtualCan {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn start(&mut self, _bitrate: u32) -> DevResult {
+        Ok(())
+    }
+
+    fn transmit(&mut self, _frame: &CanFrame) -> DevResult {
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Option<CanFrame> {
+        None
+    }
+
+    fn state(&mut self) -> CanState {
+        CanState::ErrorActive
+    }
+}
+
+/// The frames received by a socket.
+struct Receiver {
+    filters: Vec<CanFilter>,
+    queue: VecDeque<CanFrame>,
+    dropped: u64,
+}
+
+struct Controller {
+    driver: Box<dyn CanDriverOps>,
+    sockets: Vec<Weak<SpinNoIrq<Receiver>>>,
+}
+
+impl Controller {
+    /// Queues a frame on the sockets whose filters match it, except on
+    /// `sender`.
+    fn deliver(&mut self, frame: &CanFrame, sender: Option<&Arc<SpinNoIrq<Receiver>>>) {
+        self.sockets.retain(|socket| socket.strong_count() > 0);
+        for socket in self.sockets.iter().filter_map(Weak::upgrade) {
+            if sender.is_some_and(|sender| Arc::ptr_eq(sender, &socket)) {
+                continue;
+            }
+            let mut rx = socket.lock();
+            if !rx.filters.iter().any(|filter| filter.matches(frame)) {
+                continue;
+            }
+            if rx.queue.len() < SOCKET_QUEUE_LEN {
+                rx.queue.push_back(*frame);
+            } else {
+                rx.dropped += 1;
+            }
+        }
+    }
+}
+
+static CONTROLLERS: SpinNoIrq<Vec<Controller>> = SpinNoIrq::new(Vec::new());
+
+static NUM_VCAN: AtomicUsize = AtomicUsize::new(0);
+
+/// Adds a controller.
+pub fn register_controller(driver: Box<dyn CanDriverOps>) {
+    info!("can: registered controller {:?}", driver.name());
+    CONTROLLERS.lock().push(Controller {
+        driver,
+        sockets: Vec::new(),
+    });
+}
+
+/// Adds a virtual controller, and returns its name.
+pub fn add_vcan() -> String {
+    let name = format!("vcan{}", NUM_VCAN.fetch_add(1, Ordering::Relaxed));
+    register_controller(Box::new(VirtualCan::new(name.clone())));
+    name
+}
+
+/// Returns the names of the controllers.
+pub fn controllers() -> Vec<String> {
+    let controllers = CONTROLLERS.lock();
+    controllers
+        .iter()
+        .map(|controller| controller.driver.name().into())
+        .collect()
+}
+
+/// Calls `f` on the controller `name`.
+fn with_controller<T>(name: &str, f: impl FnOnce(&mut Controller) -> T) -> DevResult<T> {
+    let mut controllers = CONTROLLERS.lock();
+    let controller = controllers
+        .iter_mut()
+        .find(|controller| controller.driver.name() == name)
+        .ok_or(DevError::InvalidParam)?;
+    Ok(f(controller))
+}
+
+/// Sets the bit rate of a controller, in bits per second, and restarts it,
+/// e.g. to recover from [`CanState::BusOff`].
+pub fn set_bitrate(name: &str, bitrate: u32) -> DevResult {
+    with_controller(name, |controller| controller.driver.start(bitrate))?
+}
+
+/// Returns the state of a controller on the bus.
+pub fn state(name: &str) -> DevResult<CanState> {
+    with_controller(name, |controller| controller.driver.state())
+}
+
+/// Receives the frames of all the controllers, and queues them on their
+/// sockets.
+pub fn poll() {
+    let mut controllers = CONTROLLERS.lock();
+    for controller in controllers.iter_mut() {
+        while let Some(frame) = controller.driver.receive() {
+            controller.deliver(&frame, None);
+        }
+    }
+}
+
+/// A raw socket on a controller.
+///
+/// The operations never block: they return [`DevError::Again`] when the
+/// transmit buffers of the controller are full (for [`send`](Self::send))
+/// or when no frame is received (for [`recv`](Self::recv)).
+pub struct CanSocket {
+    controller: usize,
+    rx: Arc<SpinNoIrq<Receiver>>,
+}
+
+impl CanSocket {
+    /// Opens a socket on the controller `name`, receiving all the frames.
+    ///
+    /// Returns [`DevError::InvalidParam`] if there is no such controller.
+    pub fn open(name: &str) -> DevResult<Self> {
+        let mut controllers = CONTROLLERS.lock();
+        let controller = controllers
+            .iter()
+            .position(|controller| controller.driver.name() == name)
+            .ok_or(DevError::InvalidParam)?;
+        let rx = Arc::new(SpinNoIrq::new(Receiver {
+            filters: vec![CanFilter::ALL],
+            queue: VecDeque::new(),
+            dropped: 0,
+        }));
+        controllers[controller].sockets.push(Arc::downgrade(&rx));
+        Ok(Self { controller, rx })
+    }
+
+    /// Sets the filters of the received frames: the frames matching any of
+    /// them are received, so none with no filter.
+    ///
+    /// The frames already received are kept.
+    pub fn set_filters(&self, filters: &[CanFilter]) {
+        self.rx.lock().filters = filters.to_vec();
+    }
+
+    /// Sends a frame.
+    ///
+    /// Returns [`DevError::BadState`] if the controller is stopped or off the
+    /// bus.
+    pub fn send(&self, frame: &CanFrame) -> DevResult {
+        let mut controllers = CONTROLLERS.lock();
+        let controller = &mut controllers[self.controller];
+        controller.driver.transmit(frame)?;
+        controller.deliver(frame, Some(&self.rx));
+        Ok(())
+    }
+
+    /// Receives a frame.
+    pub fn recv(&self) -> DevResult<CanFrame> {
+        poll();
+        self.rx.lock().queue.pop_front().ok_or(DevError::Again)
+    }
+
+    /// Returns the number of frames dropped because the queue of the socket
+    /// was full.
+    pub fn dropped(&self) -> u64 {
+        self.rx.lock().dropped
+    }
+}
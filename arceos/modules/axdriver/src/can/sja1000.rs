@@ -0,0 +1,284 @@
+//! The SJA1000 CAN controller, in its PeliCAN mode.
+//!
+//! It is found on the `kvaser_pci` device emulated by QEMU, attached to a
+//! bus with `-object can-bus,id=canbus0 -device kvaser_pci,canbus=canbus0`,
+//! which can be connected to a SocketCAN interface of the host with
+//! `-object can-host-socketcan,id=canhost0,if=vcan0,canbus=canbus0`.
+//!
+//! Its registers are in the I/O space of the device, so only x86_64 is
+//! supported, where the firmware assigns the I/O BARs. It is polled: its
+//! interrupts are left disabled.
+
+use alloc::string::String;
+use core::arch::asm;
+
+use axdriver_base::{DevError, DevResult};
+
+use super::{CanDriverOps, CanFrame, CanId, CanState};
+
+// The registers, in the PeliCAN mode.
+const MOD: u16 = 0;
+const CMR: u16 = 1;
+const SR: u16 = 2;
+const IER: u16 = 4;
+const BTR0: u16 = 6;
+const BTR1: u16 = 7;
+const OCR: u16 = 8;
+const RXERR: u16 = 14;
+const TXERR: u16 = 15;
+/// The acceptance code and mask registers, in the reset mode.
+const ACR0: u16 = 16;
+const AMR0: u16 = 20;
+/// The frame transmitted (when written) or received (when read), in the
+/// operating mode.
+const FRAME: u16 = 16;
+const CDR: u16 = 31;
+
+const MOD_RESET: u8 = 1 << 0;
+/// A single acceptance filter of 32 bits.
+const MOD_SINGLE_FILTER: u8 = 1 << 3;
+
+const CMR_TX_REQUEST: u8 = 1 << 0;
+const CMR_RELEASE_RX: u8 = 1 << 2;
+const CMR_CLEAR_OVERRUN: u8 = 1 << 3;
+
+const SR_RX_AVAILABLE: u8 = 1 << 0;
+const SR_OVERRUN: u8 = 1 << 1;
+const SR_TX_FREE: u8 = 1 << 2;
+const SR_ERROR: u8 = 1 << 6;
+const SR_BUS_OFF: u8 = 1 << 7;
+
+/// The normal output mode, with push-pull drivers on TX0.
+const OCR_NORMAL_PUSH_PULL: u8 = 0x1a;
+const CDR_PELICAN: u8 = 1 << 7;
+
+const FRAME_EXTENDED: u8 = 1 << 7;
+const FRAME_REMOTE: u8 = 1 << 6;
+
+/// The number of reads of the mode register waiting for a change of mode.
+const MODE_CHANGE_TRIES: usize = 100;
+
+/// An SJA1000 CAN controller.
+pub struct Sja1000 {
+    name: String,
+    base: u16,
+    clock: u32,
+}
+
+impl Sja1000 {
+    /// Creates the driver of the controller `name` with its registers at the
+    /// I/O port `base`, clocked by a crystal of `clock` Hz.
+    ///
+    /// The controller is stopped until [`start`](CanDriverOps::start)ed.
+    ///
+    /// # Safety
+    ///
+    /// `base` must be the I/O ports of an SJA1000, not used by any other
+    /// driver.
+    pub unsafe fn new(name: String, base: u16, clock: u32) -> Self {
+        Self { name, base, clock }
+    }
+
+    fn read(&self, reg: u16) -> u8 {
+        let value: u8;
+        unsafe {
+            asm!("in al, dx", out("al") value, in("dx") self.base + reg, options(nomem, nostack));
+        }
+        value
+    }
+
+    fn write(&self, reg: u16, value: u8) {
+        unsafe {
+            asm!("out dx, al", in("dx") self.base + reg, in("al") value, options(nomem, nostack));
+        }
+    }
+
+    /// Sets the mode register, and waits for the controller to enter the
+    /// (reset or operating) mode.
+    fn set_mode(&self, mode: u8) -> DevResult {
+        for _ in 0..MODE_CHANGE_TRIES {
+            self.write(MOD, mode);
+            if self.read(MOD) & MOD_RESET == mode & MOD_RESET {
+                return Ok(());
+            }
+        }
+        Err(DevError::Io)
+    }
+
+    /// Returns the bus timing registers for `bitrate`.
+    ///
+    /// A time quantum lasts `2 * (BRP + 1)` periods of the clock, and a bit
+    /// 8 to 20 of them, sampled at about 87.5%.
+    fn bus_timing(&self, bitrate: u32) -> Option<(u8, u8)> {
+        if bitrate == 0 {
+            return None;
+        }
+        (8..=20u32).rev().find_map(|quanta| {
+            let quantum_clock = 2 * quanta as u64 * bitrate as u64;
+            let prescaler = self.clock as u64 / quantum_clock;
+            if !(1..=64).contains(&prescaler) || self.clock as u64 % quantum_clock != 0 {
+                return None;
+            }
+            let tseg2 = (quanta + 4) / 8;
+            let tseg1 = quanta - 1 - tseg2;
+            Some((
+                (prescaler - 1) as u8,
+                (((tseg2 - 1) << 4) | (tseg1 - 1)) as u8,
+            ))
+        })
+    }
+}
+
+impl CanDriverOps for Sja1000 {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn start(&mut self, bitrate: u32) -> DevResult {
+        let (btr0, btr1) = self.bus_timing(bitrate).ok_or(DevError::InvalidParam)?;
+        self.set_mode(MOD_RESET)?;
+        self.write(CDR, CDR_PELICAN);
+        self.write(IER, 0);
+        // accept all the frames, the sockets filter them
+        for i in 0..4 {
+            self.write(ACR0 + i, 0);
+            self.write(AMR0 + i, 0xff);
+        }
+        self.write(BTR0, btr0);
+        self.write(BTR1, btr1);
+        self.write(OCR, OCR_NORMAL_PUSH_PULL);
+        self.set_mode(MOD_SINGLE_FILTER)
+    }
+
+    fn transmit(&mut self, frame: &CanFrame) -> DevResult {
+        if self.read(MOD) & MOD_RESET != 0 {
+            return Err(DevError::BadState);
+        }
+        if self.read(SR) & SR_TX_FREE == 0 {
+            return Err(DevError::Again);
+        }
+        let mut info = frame.dlc() as u8;
+        if frame.is_remote() {
+            info |= FRAME_REMOTE;
+        }
+        let mut reg = FRAME + 1;
+        match frame.id() {
+            CanId::Standard(id) => {
+                for byte in (id << 5).to_be_bytes() {
+                    self.write(reg, byte);
+                    reg += 1;
+                }
+            }
+            CanId::Extended(id) => {
+                info |= FRAME_EXTENDED;
+                for byte in (id << 3).to_be_bytes() {
+                    self.write(reg, byte);
+                    reg += 1;
+                }
+            }
+        }
+        self.write(FRAME, info);
+        for &byte in frame.data() {
+            self.write(reg, byte);
+            reg += 1;
+        }
+        self.write(CMR, CMR_TX_REQUEST);
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Option<CanFrame> {
+        let status = self.read(SR);
+        if status & SR_OVERRUN != 0 {
+            warn!("{}: receive overrun, frames lost", self.name);
+            self.write(CMR, CMR_CLEAR_OVERRUN);
+        }
+        if status & SR_RX_AVAILABLE == 0 {
+            return None;
+        }
+        let info = self.read(FRAME);
+        let (id, mut reg) = if info & FRAME_EXTENDED != 0 {
+            let id = [1, 2, 3, 4].map(|i| self.read(FRAME + i));
+            (CanId::Extended(u32::from_be_bytes(id) >> 3), FRAME + 5)
+        } else {
+            let id = [1, 2].map(|i| self.read(FRAME + i));
+            (CanId::Standard(u16::from_be_bytes(id) >> 5), FRAME + 3)
+        };
+        let len = (info & 0xf).min(CanFrame::MAX_DATA_LEN as u8) as usize;
+        let frame = if info & FRAME_REMOTE != 0 {
+            CanFrame::remote(id, len)
+        } else {
+            let mut data = [0; CanFrame::MAX_DATA_LEN];
+            for byte in &mut data[..len] {
+                *byte = self.read(reg);
+                reg += 1;
+            }
+            CanFrame::new(id, &data[..len])
+        };
+        self.write(CMR, CMR_RELEASE_RX);
+        frame
+    }
+
+    fn state(&mut self) -> CanState {
+        let status = self.read(SR);
+        if status & SR_BUS_OFF != 0 {
+            CanState::BusOff
+        } else if self.read(MOD) & MOD_RESET != 0 {
+            CanState::Stopped
+        } else if self.read(RXERR) >= 128 || self.read(TXERR) >= 128 {
+            CanState::ErrorPassive
+        } else if status & SR_ERROR != 0 {
+            CanState::ErrorWarning
+        } else {
+            CanState::ErrorActive
+        }
+    }
+}
+
+/// The number of `kvaser_pci` devices found, naming the next one.
+#[cfg(bus = "pci")]
+static NUM_KVASER: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+
+/// Probes a `kvaser_pci` device at the PCI function, and returns whether one
+/// is found.
+#[cfg(bus = "pci")]
+pub(crate) fn probe_pci(
+    root: &mut axdriver_pci::PciRoot,
+    bdf: axdriver_pci::DeviceFunction,
+    dev_info: &axdriver_pci::DeviceFunctionInfo,
+) -> bool {
+    use alloc::{boxed::Box, format};
+    use axdriver_pci::BarInfo;
+    use core::sync::atomic::Ordering;
+
+    const KVASER_VENDOR_ID: u16 = 0x10e8;
+    const KVASER_DEVICE_ID: u16 = 0x8406;
+    /// The BAR of the SJA1000, the others being the PCI bridge (BAR 0) and
+    /// the FPGA (BAR 2).
+    const KVASER_SJA1000_BAR: u8 = 1;
+    /// The frequency of the crystal.
+    const KVASER_CLOCK: u32 = 16_000_000;
+
+    if dev_info.vendor_id != KVASER_VENDOR_ID || dev_info.device_id != KVASER_DEVICE_ID {
+        return false;
+    }
+    let Ok(BarInfo::IO { address, .. }) = root.bar_info(bdf, KVASER_SJA1000_BAR) else {
+        warn!("kvaser_pci at {}: no SJA1000 I/O BAR", bdf);
+        return false;
+    };
+    if address == 0 {
+        warn!("kvaser_pci at {}: SJA1000 I/O BAR not assigned", bdf);
+        return false;
+    }
+    let name = format!("can{}", NUM_KVASER.load(Ordering::Relaxed));
+    let mut can = unsafe { Sja1000::new(name, address as u16, KVASER_CLOCK) };
+    if let Err(e) = can.start(super::DEFAULT_BITRATE) {
+        warn!(
+            "kvaser_pci at {}: failed to start the SJA1000: {:?}",
+            bdf, e
+        );
+        return false;
+    }
+    NUM_KVASER.fetch_add(1, Ordering::Relaxed);
+    super::register_controller(Box::new(can));
+    true
+}
@@ -0,0 +1,422 @@
+//! CPU frequency scaling (DVFS).
+//!
+//! A [`CpuFreqDriver`] changes the frequency of the CPUs among the operating
+//! points it supports, and is added with [`register_driver`]. [`init`],
+//! called at boot as a [driver init call](axhal::initcall), adds the one of
+//! the platform:
+//!
+//! - [`ScmiPerf`], the performance domain of an SCMI firmware over the SMC
+//!   transport, on aarch64 if `AX_SCMI_SHMEM` and `AX_SCMI_SMC_ID` are set at
+//!   build time (the physical address of the shared memory, which must be in
+//!   the MMIO regions of the platform, and the SMC function ID);
+//! - [`OppCpuFreq`] drives a clock through a table of operating points, e.g.
+//!   the `operating-points-v2` of a device tree, and is registered by the
+//!   platform code.
+//!
+//! The frequency is then chosen by a [`Governor`], with [`update`] called
+//! periodically with the load of the CPUs, and can be pinned with [`pin`],
+//! e.g. to get reproducible benchmarks. With the `thermal` feature, the
+//! frequency is also capped by the throttling level of [`crate::thermal`].
+
+use alloc::{boxed::Box, vec::Vec};
+
+use axdriver_base::{DevError, DevResult};
+use kspin::SpinNoIrq;
+
+/// Above this load in percent, the `Ondemand` governor goes to the highest
+/// frequency. Below, it picks the lowest one that keeps the load under it.
+const UP_THRESHOLD: u32 = 80;
+
+/// Changes the frequency of the CPUs.
+pub trait CpuFreqDriver: Send {
+    /// The name of the driver.
+    fn name(&self) -> &str;
+
+    /// The supported frequencies in kHz, in increasing order.
+    fn frequencies_khz(&self) -> &[u32];
+
+    /// Sets the frequency of all the CPUs, one of [`Self::frequencies_khz`].
+    fn set_freq_khz(&mut self, khz: u32) -> DevResult;
+}
+
+/// How the frequency is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Governor {
+    /// Always the highest frequency.
+    Performance,
+    /// Always the lowest frequency.
+    Powersave,
+    /// Follows the load of the CPUs.
+    Ondemand,
+    /// A fixed frequency, in kHz, see [`pin`].
+    Userspace(u32),
+}
+
+struct CpuFreq {
+    driver: Option<Box<dyn CpuFreqDriver>>,
+    governor: Governor,
+    /// The governor to restore by [`unpin`].
+    unpinned: Governor,
+    /// The highest frequency allowed, in kHz.
+    limit_khz: u32,
+    cur_khz: u32,
+    /// The last load given to [`update`], in percent.
+    load: u32,
+}
+
+static CPUFREQ: SpinNoIrq<CpuFreq> = SpinNoIrq::new(CpuFreq {
+    driver: None,
+    governor: Governor::Ondemand,
+    unpinned: Governor::Ondemand,
+    limit_khz: u32::MAX,
+    cur_khz: 0,
+    load: 100,
+});
+
+impl CpuFreq {
+    /// Sets the frequency wanted by the governor, within the limit, to the
+    /// closest supported one not above it.
+    fn apply(&mut self) -> DevResult {
+        let load = self.load;
+        let Some(driver) = self.driver.as_mut() else {
+            return Err(DevError::Unsupported);
+        };
+        let freqs = driver.frequencies_khz();
+        let (Some(&min), Some(&max)) = (freqs.first(), freqs.last()) else {
+            return Err(DevError::Unsupported);
+        };
+        let target = match self.governor {
+            Governor::Performance => max,
+            Governor::Powersave => min,
+            Governor::Ondemand if load >= UP_THRESHOLD => max,
+            Governor::Ondemand => (max as u64 * load as u64 / UP_THRESHOLD as u64) as u32,
+            Governor::Userspace(khz) => khz,
+        };
+        let khz = match self.governor {
+            // the lowest frequency keeping the load under the threshold
+            Governor::Ondemand => freqs.iter().find(|&&f| f >= target).copied(),
+            _ => Some(target),
+        };
+        let target = khz.unwrap_or(max).min(self.limit_khz);
+        let khz = freqs.iter().rev().find(|&&f| f <= target).copied();
+        let khz = khz.unwrap_or(min);
+        if khz != self.cur_khz {
+            driver.set_freq_khz(khz)?;
+            debug!("cpufreq: {} kHz", khz);
+            self.cur_khz = khz;
+        }
+        Ok(())
+    }
+}
+
+axhal::register_init!(axhal::initcall::priority::DRIVER, init);
+
+/// Adds the frequency driver of the platform.
+pub fn init() {
+    #[cfg(target_arch = "aarch64")]
+    if let (Some(shmem), Some(smc_id)) =
+        (option_env!("AX_SCMI_SHMEM"), option_env!("AX_SCMI_SMC_ID"))
+    {
+        let parse = |s: &str| {
+            let s = s.trim();
+            match s.strip_prefix("0x") {
+                Some(hex) => usize::from_str_radix(hex, 16).ok(),
+                None => s.parse().ok(),
+            }
+        };
+        let (Some(shmem), Some(smc_id)) = (parse(shmem), parse(smc_id)) else {
+            warn!("cpufreq: invalid AX_SCMI_SHMEM or AX_SCMI_SMC_ID");
+            return;
+        };
+        let shmem = axhal::mem::phys_to_virt(shmem.into()).as_usize();
+        match unsafe { ScmiPerf::probe(shmem, smc_id as u32, 0) } {
+            Ok(scmi) => register_driver(Box::new(scmi)),
+            Err(e) => warn!("cpufreq: failed to probe SCMI: {:?}", e),
+        }
+    }
+    #[cfg(feature = "thermal")]
+    crate::thermal::register_cpufreq(&ThermalLimit);
+}
+
+/// Sets the frequency driver.
+pub fn register_driver(driver: Box<dyn CpuFreqDriver>) {
+    info!(
+        "cpufreq: registered driver {:?}, {:?} kHz",
+        driver.name(),
+        driver.frequencies_khz()
+    );
+    let mut cpufreq = CPUFREQ.lock();
+    cpufreq.driver = Some(driver);
+    cpufreq.cur_khz = 0;
+    cpufreq.apply().ok();
+}
+
+/// Returns the current frequency in kHz, or [`None`] if there is no driver.
+pub fn cur_freq_khz() -> Option<u32> {
+    let cpufreq = CPUFREQ.lock();
+    cpufreq.driver.as_ref().map(|_| cpufreq.cur_khz)
+}
+
+/// Returns the supported frequencies in kHz, in increasing order.
+pub fn frequencies_khz() -> Vec<u32> {
+    let cpufreq = CPUFREQ.lock();
+    cpufreq
+        .driver
+        .as_ref()
+        .map_or(Vec::new(), |driver| driver.frequencies_khz().into())
+}
+
+/// Returns the current governor.
+pub fn governor() -> Governor {
+    CPUFREQ.lock().governor
+}
+
+/// Sets the governor, and applies it at once.
+pub fn set_governor(governor: Governor) -> DevResult {
+    let mut cpufreq = CPUFREQ.lock();
+    cpufreq.governor = governor;
+    cpufreq.unpinned = governor;
+    cpufreq.apply()
+}
+
+/// Pins the frequency of the CPUs to the supported one closest to `khz`
+/// from below, until [`unpin`].
+pub fn pin(khz: u32) -> DevResult {
+    let mut cpufreq = CPUFREQ.lock();
+    if !matches!(cpufreq.governor, Governor::Userspace(_)) {
+        cpufreq.unpinned = cpufreq.governor;
+    }
+    cpufreq.governor = Governor::Userspace(khz);
+    cpufreq.apply()
+}
+
+/// Gives the frequency back to the governor used before [`pin`].
+pub fn unpin() -> DevResult {
+    let mut cpufreq = CPUFREQ.lock();
+    cpufreq.governor = cpufreq.unpinned;
+    cpufreq.apply()
+}
+
+/// Applies the governor for the given load of the CPUs, in percent.
+pub fn update(load: u32) {
+    let mut cpufreq = CPUFREQ.lock();
+    cpufreq.load = load.min(100);
+    if cpufreq.driver.is_some() {
+        if let Err(e) = cpufreq.apply() {
+            warn!("cpufreq: failed to set the frequency: {:?}", e);
+        }
+    }
+}
+
+/// Caps the frequency on behalf of [`crate::thermal`].
+#[cfg(feature = "thermal")]
+struct ThermalLimit;
+
+#[cfg(feature = "thermal")]
+impl crate::thermal::CpuFreqOps for ThermalLimit {
+    fn max_freq_khz(&self) -> u32 {
+        let cpufreq = CPUFREQ.lock();
+        cpufreq
+            .driver
+            .as_ref()
+            .and_then(|driver| driver.frequencies_khz().last().copied())
+            .unwrap_or(0)
+    }
+
+    fn set_freq_limit_khz(&self, khz: u32) {
+        let mut cpufreq = CPUFREQ.lock();
+        cpufreq.limit_khz = khz;
+        if cpufreq.driver.is_some() {
+            cpufreq.apply().ok();
+        }
+    }
+}
+
+/// An operating point: a frequency and the voltage it needs.
+#[derive(Debug, Clone, Copy)]
+pub struct Opp {
+    /// The frequency in kHz.
+    pub freq_khz: u32,
+    /// The voltage of the CPU supply in microvolts, or 0 if fixed.
+    pub microvolt: u32,
+}
+
+/// Scales the frequency through a table of operating points and a function
+/// of the platform programming the clock (and regulator) for each.
+pub struct OppCpuFreq {
+    name: &'static str,
+    freqs: Vec<u32>,
+    opps: &'static [Opp],
+    set_opp: fn(&Opp) -> DevResult,
+}
+
+impl OppCpuFreq {
+    /// Creates the driver for the operating points `opps`, in any order.
+    pub fn new(name: &'static str, opps: &'static [Opp], set_opp: fn(&Opp) -> DevResult) -> Self {
+        let mut freqs: Vec<u32> = opps.iter().map(|opp| opp.freq_khz).collect();
+        freqs.sort_unstable();
+        freqs.dedup();
+        Self {
+            name,
+            freqs,
+            opps,
+            set_opp,
+        }
+    }
+}
+
+impl CpuFreqDriver for OppCpuFreq {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn frequencies_khz(&self) -> &[u32] {
+        &self.freqs
+    }
+
+    fn set_freq_khz(&mut self, khz: u32) -> DevResult {
+        let opp = self.opps.iter().find(|opp| opp.freq_khz == khz);
+        (self.set_opp)(opp.ok_or(DevError::InvalidParam)?)
+    }
+}
+
+/// The performance domain protocol of the System Control and Management
+/// Interface (SCMI), over a shared memory channel and the SMC transport.
+#[cfg(target_arch = "aarch64")]
+pub struct ScmiPerf {
+    shmem: usize,
+    smc_id: u32,
+    domain: u32,
+    /// The performance levels of the domain, in increasing order.
+    levels: Vec<u32>,
+    freqs: Vec<u32>,
+}
+
+#[cfg(target_arch = "aarch64")]
+impl ScmiPerf {
+    const PROTOCOL_PERF: u32 = 0x13;
+    const PERF_DOMAIN_ATTRIBUTES: u32 = 0x3;
+    const PERF_DESCRIBE_LEVELS: u32 = 0x4;
+    const PERF_LEVEL_SET: u32 = 0x7;
+
+    // the layout of the shared memory
+    const CHANNEL_STATUS: usize = 0x4;
+    const FLAGS: usize = 0x10;
+    const LENGTH: usize = 0x14;
+    const MSG_HEADER: usize = 0x18;
+    const PAYLOAD: usize = 0x1c;
+    const CHANNEL_FREE: u32 = 1 << 0;
+    const CHANNEL_ERROR: u32 = 1 << 1;
+    /// The payload words read at most from a response.
+    const MAX_RESPONSE_WORDS: usize = 64;
+
+    /// Reads the performance levels of `domain`.
+    ///
+    /// # Safety
+    ///
+    /// `shmem` must be the virtual address of the shared memory of an SCMI
+    /// channel whose doorbell is the SMC `smc_id`.
+    pub unsafe fn probe(shmem: usize, smc_id: u32, domain: u32) -> DevResult<Self> {
+        let mut scmi = Self {
+            shmem,
+            smc_id,
+            domain,
+            levels: Vec::new(),
+            freqs: Vec::new(),
+        };
+        // the levels are scaled to kHz by the sustained frequency and level
+        let attrs = scmi.call(Self::PERF_DOMAIN_ATTRIBUTES, &[domain])?;
+        let (sustained_khz, sustained_level) = match attrs.get(2..4) {
+            Some(&[khz, level]) if level != 0 => (khz as u64, level as u64),
+            _ => return Err(DevError::Unsupported),
+        };
+        let mut index = 0;
+        loop {
+            let resp = scmi.call(Self::PERF_DESCRIBE_LEVELS, &[domain, index])?;
+            let num_levels = resp.first().ok_or(DevError::Io)?;
+            let returned = (num_levels & 0xfff) as usize;
+            let remaining = num_levels >> 16;
+            // each level: the performance level, the power cost and the latency
+            let entries = resp[1..].chunks_exact(3).take(returned);
+            let read = entries.len();
+            scmi.levels.extend(entries.map(|entry| entry[0]));
+            index += read as u32;
+            if (remaining == 0 && read == returned) || read == 0 {
+                break;
+            }
+        }
+        scmi.levels.sort_unstable();
+        scmi.levels.dedup();
+        scmi.freqs = scmi
+            .levels
+            .iter()
+            .map(|&level| (level as u64 * sustained_khz / sustained_level) as u32)
+            .collect();
+        if scmi.levels.is_empty() {
+            return Err(DevError::Unsupported);
+        }
+        Ok(scmi)
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { ((self.shmem + offset) as *const u32).read_volatile() }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { ((self.shmem + offset) as *mut u32).write_volatile(value) }
+    }
+
+    /// Sends a command and returns the payload of the response after the
+    /// status.
+    fn call(&self, msg_id: u32, args: &[u32]) -> DevResult<Vec<u32>> {
+        if self.read(Self::CHANNEL_STATUS) & Self::CHANNEL_FREE == 0 {
+            return Err(DevError::ResourceBusy);
+        }
+        for (i, &arg) in args.iter().enumerate() {
+            self.write(Self::PAYLOAD + i * 4, arg);
+        }
+        self.write(Self::MSG_HEADER, msg_id | (Self::PROTOCOL_PERF << 10));
+        self.write(Self::LENGTH, 4 + args.len() as u32 * 4);
+        self.write(Self::FLAGS, 0); // polling, no completion interrupt
+        self.write(Self::CHANNEL_STATUS, 0);
+        unsafe {
+            core::arch::asm!("smc #0", inout("x0") self.smc_id as usize => _,
+                out("x1") _, out("x2") _, out("x3") _);
+        }
+        // the SMC returns once the platform has answered
+        let status = self.read(Self::CHANNEL_STATUS);
+        if status & Self::CHANNEL_ERROR != 0 || status & Self::CHANNEL_FREE == 0 {
+            return Err(DevError::Io);
+        }
+        let len = self.read(Self::LENGTH) as usize;
+        let words = (len.saturating_sub(4) / 4).min(Self::MAX_RESPONSE_WORDS);
+        let resp: Vec<u32> = (0..words)
+            .map(|i| self.read(Self::PAYLOAD + i * 4))
+            .collect();
+        match resp.first().map(|&s| s as i32) {
+            Some(0) => Ok(resp[1..].into()),
+            // NOT_SUPPORTED, INVALID_PARAMETERS
+            Some(-1) => Err(DevError::Unsupported),
+            Some(-2) => Err(DevError::InvalidParam),
+            _ => Err(DevError::Io),
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+impl CpuFreqDriver for ScmiPerf {
+    fn name(&self) -> &str {
+        "scmi-perf"
+    }
+
+    fn frequencies_khz(&self) -> &[u32] {
+        &self.freqs
+    }
+
+    fn set_freq_khz(&mut self, khz: u32) -> DevResult {
+        let i = self.freqs.iter().position(|&f| f == khz);
+        let level = self.levels[i.ok_or(DevError::InvalidParam)?];
+        self.call(Self::PERF_LEVEL_SET, &[self.domain, level])?;
+        Ok(())
+    }
+}
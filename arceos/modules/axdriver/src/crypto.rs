@@ -0,0 +1,346 @@
+//! VirtIO crypto device driver, registered as an [`axcrypto`] engine.
+//!
+//! Only the symmetric cipher service is used (AES in the modes of
+//! [`CipherAlg`]): hashes are computed in software, as the common backends
+//! do not offer them.
+
+use alloc::{boxed::Box, sync::Arc};
+use core::ptr::addr_of;
+
+use axcrypto::{CipherAlg, CipherOps, CryptoEngine};
+use axerrno::{ax_err, AxResult};
+use kspin::SpinNoIrq;
+use virtio_drivers::queue::VirtQueue;
+use virtio_drivers::transport::{DeviceStatus, DeviceType, Transport};
+
+use crate::virtio::VirtIoHalImpl;
+
+const PAGE_SIZE: usize = 0x1000;
+const QUEUE_SIZE: usize = 8;
+/// The data queue used, the control queue is after all the data queues.
+const DATA_QUEUE: u16 = 0;
+
+const F_VERSION_1: u64 = 1 << 32;
+
+const STATUS_HW_READY: u32 = 1 << 0;
+
+const OPCODE_CIPHER_ENCRYPT: u32 = 0x00;
+const OPCODE_CIPHER_DECRYPT: u32 = 0x01;
+const OPCODE_CIPHER_CREATE_SESSION: u32 = 0x02;
+const OPCODE_CIPHER_DESTROY_SESSION: u32 = 0x03;
+
+const OP_ENCRYPT: u32 = 1;
+const OP_DECRYPT: u32 = 2;
+const SYM_OP_CIPHER: u32 = 1;
+
+const STATUS_OK: u8 = 0;
+
+/// The size of the fixed part of control and data requests.
+const REQ_SIZE: usize = 72;
+
+/// The configuration space of a crypto device.
+#[repr(C)]
+#[allow(dead_code)]
+struct CryptoConfig {
+    status: u32,
+    max_dataqueues: u32,
+    crypto_services: u32,
+    cipher_algo_l: u32,
+    cipher_algo_h: u32,
+    hash_algo: u32,
+    mac_algo_l: u32,
+    mac_algo_h: u32,
+    aead_algo: u32,
+    max_cipher_key_len: u32,
+    max_auth_key_len: u32,
+    akcipher_algo: u32,
+    // the size is 64-bit, but the configuration space is only 32-bit aligned
+    max_size_low: u32,
+    max_size_high: u32,
+}
+
+/// The algorithm number of virtio-crypto.
+fn algo_id(alg: CipherAlg) -> u32 {
+    match alg {
+        CipherAlg::AesEcb => 2,
+        CipherAlg::AesCbc => 3,
+        CipherAlg::AesCtr => 4,
+        CipherAlg::AesXts => 13,
+    }
+}
+
+/// A request, made of little-endian 32-bit words.
+struct Request([u8; REQ_SIZE]);
+
+impl Request {
+    fn new() -> Self {
+        Self([0; REQ_SIZE])
+    }
+
+    fn set(&mut self, offset: usize, value: u32) -> &mut Self {
+        self.0[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn set_u64(&mut self, offset: usize, value: u64) -> &mut Self {
+        self.0[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        self
+    }
+}
+
+/// A VirtIO crypto device.
+pub struct VirtIoCrypto<T: Transport> {
+    transport: T,
+    data_queue: VirtQueue<VirtIoHalImpl, QUEUE_SIZE>,
+    ctrl_queue: VirtQueue<VirtIoHalImpl, QUEUE_SIZE>,
+    /// The supported cipher algorithms, bit `n` for the algorithm `n`.
+    cipher_algos: u64,
+    max_key_len: usize,
+    max_size: usize,
+}
+
+unsafe impl<T: Transport> Send for VirtIoCrypto<T> {}
+
+impl<T: Transport> VirtIoCrypto<T> {
+    /// Initializes the crypto device on the given transport.
+    pub fn new(mut transport: T) -> Result<Self, virtio_drivers::Error> {
+        if transport.device_type() != DeviceType::Crypto {
+            return Err(virtio_drivers::Error::Unsupported);
+        }
+        transport.set_status(DeviceStatus::empty());
+        transport.set_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER);
+        let features = transport.read_device_features() & F_VERSION_1;
+        transport.write_driver_features(features);
+        transport.set_status(
+            DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER | DeviceStatus::FEATURES_OK,
+        );
+        transport.set_guest_page_size(PAGE_SIZE as u32);
+
+        let config = transport.config_space::<CryptoConfig>()?.as_ptr();
+        let read = |field: *const u32| u32::from_le(unsafe { field.read_volatile() });
+        if read(unsafe { addr_of!((*config).status) }) & STATUS_HW_READY == 0 {
+            return Err(virtio_drivers::Error::NotReady);
+        }
+        let max_dataqueues = read(unsafe { addr_of!((*config).max_dataqueues) });
+        let cipher_algos = read(unsafe { addr_of!((*config).cipher_algo_l) }) as u64
+            | (read(unsafe { addr_of!((*config).cipher_algo_h) }) as u64) << 32;
+        let max_key_len = read(unsafe { addr_of!((*config).max_cipher_key_len) });
+        let max_size = read(unsafe { addr_of!((*config).max_size_low) }) as u64
+            | (read(unsafe { addr_of!((*config).max_size_high) }) as u64) << 32;
+
+        let data_queue = VirtQueue::new(&mut transport, DATA_QUEUE, false, false)?;
+        let ctrl_queue = VirtQueue::new(&mut transport, max_dataqueues as u16, false, false)?;
+        transport.finish_init();
+
+        Ok(Self {
+            transport,
+            data_queue,
+            ctrl_queue,
+            cipher_algos,
+            max_key_len: max_key_len as usize,
+            max_size: max_size.min(usize::MAX as u64) as usize,
+        })
+    }
+
+    fn supports(&self, alg: CipherAlg, key: &[u8]) -> bool {
+        self.cipher_algos & (1 << algo_id(alg)) != 0 && key.len() <= self.max_key_len
+    }
+
+    /// Creates a cipher session for one direction, and returns its ID.
+    fn create_session(&mut self, alg: CipherAlg, key: &[u8], op: u32) -> AxResult<u64> {
+        let mut req = Request::new();
+        req.set(0, OPCODE_CIPHER_CREATE_SESSION)
+            .set(4, algo_id(alg))
+            // the cipher session parameters, then the operation type
+            .set(16, algo_id(alg))
+            .set(20, key.len() as u32)
+            .set(24, op)
+            .set(64, SYM_OP_CIPHER);
+        let mut input = [0u8; 16];
+        self.ctrl_queue
+            .add_notify_wait_pop(
+                &[&req.0[..], key],
+                &mut [&mut input[..]],
+                &mut self.transport,
+            )
+            .map_err(|_| axerrno::AxError::Io)?;
+        let status = u32::from_le_bytes(input[8..12].try_into().unwrap());
+        if status != STATUS_OK as u32 {
+            return ax_err!(Unsupported, "virtio-crypto: failed to create a session");
+        }
+        Ok(u64::from_le_bytes(input[..8].try_into().unwrap()))
+    }
+
+    fn destroy_session(&mut self, session_id: u64) {
+        let mut req = Request::new();
+        req.set(0, OPCODE_CIPHER_DESTROY_SESSION)
+            .set_u64(16, session_id);
+        let mut status = [0u8];
+        let res = self.ctrl_queue.add_notify_wait_pop(
+            &[&req.0[..]],
+            &mut [&mut status[..]],
+            &mut self.transport,
+        );
+        if res.is_err() || status[0] != STATUS_OK {
+            warn!("virtio-crypto: failed to destroy session {}", session_id);
+        }
+    }
+
+    /// Encrypts or decrypts `buf` in place with the session.
+    fn cipher(&mut self, session: &Session, encrypt: bool, iv: &[u8], buf: &mut [u8]) -> AxResult {
+        if buf.len() > self.max_size {
+            return ax_err!(InvalidInput, "virtio-crypto: request too large");
+        }
+        let (opcode, session_id) = if encrypt {
+            (OPCODE_CIPHER_ENCRYPT, session.encrypt_id)
+        } else {
+            (OPCODE_CIPHER_DECRYPT, session.decrypt_id)
+        };
+        let mut req = Request::new();
+        req.set(0, opcode)
+            .set(4, algo_id(session.alg))
+            .set_u64(8, session_id)
+            // the cipher data parameters, then the operation type
+            .set(24, iv.len() as u32)
+            .set(28, buf.len() as u32)
+            .set(32, buf.len() as u32)
+            .set(64, SYM_OP_CIPHER);
+        let src = buf.to_vec();
+        let mut status = [0u8];
+        self.data_queue
+            .add_notify_wait_pop(
+                &[&req.0[..], iv, &src],
+                &mut [buf, &mut status[..]],
+                &mut self.transport,
+            )
+            .map_err(|_| axerrno::AxError::Io)?;
+        if status[0] != STATUS_OK {
+            return ax_err!(Io, "virtio-crypto: request failed");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(bus = "pci")]
+type CryptoTransport = axdriver_virtio::PciTransport;
+#[cfg(bus = "mmio")]
+type CryptoTransport = axdriver_virtio::MmioTransport;
+
+type SharedDevice = Arc<SpinNoIrq<VirtIoCrypto<CryptoTransport>>>;
+
+/// The sessions of a key, one for each direction.
+struct Session {
+    alg: CipherAlg,
+    encrypt_id: u64,
+    decrypt_id: u64,
+}
+
+struct VirtIoCipher {
+    dev: SharedDevice,
+    session: Session,
+}
+
+impl CipherOps for VirtIoCipher {
+    fn encrypt(&self, iv: &[u8], buf: &mut [u8]) -> AxResult {
+        self.dev.lock().cipher(&self.session, true, iv, buf)
+    }
+
+    fn decrypt(&self, iv: &[u8], buf: &mut [u8]) -> AxResult {
+        self.dev.lock().cipher(&self.session, false, iv, buf)
+    }
+}
+
+impl Drop for VirtIoCipher {
+    fn drop(&mut self) {
+        let mut dev = self.dev.lock();
+        dev.destroy_session(self.session.encrypt_id);
+        dev.destroy_session(self.session.decrypt_id);
+    }
+}
+
+struct VirtIoCryptoEngine {
+    dev: SharedDevice,
+}
+
+impl CryptoEngine for VirtIoCryptoEngine {
+    fn name(&self) -> &str {
+        "virtio-crypto"
+    }
+
+    fn new_cipher(&self, alg: CipherAlg, key: &[u8]) -> Option<Box<dyn CipherOps>> {
+        let mut dev = self.dev.lock();
+        if !dev.supports(alg, key) {
+            return None;
+        }
+        let encrypt_id = dev.create_session(alg, key, OP_ENCRYPT).ok()?;
+        let decrypt_id = match dev.create_session(alg, key, OP_DECRYPT) {
+            Ok(id) => id,
+            Err(_) => {
+                dev.destroy_session(encrypt_id);
+                return None;
+            }
+        };
+        drop(dev);
+        Some(Box::new(VirtIoCipher {
+            dev: self.dev.clone(),
+            session: Session {
+                alg,
+                encrypt_id,
+                decrypt_id,
+            },
+        }))
+    }
+}
+
+fn register(transport: CryptoTransport) -> bool {
+    match VirtIoCrypto::new(transport) {
+        Ok(dev) => {
+            let dev = Arc::new(SpinNoIrq::new(dev));
+            axcrypto::register_engine(Arc::new(VirtIoCryptoEngine { dev }));
+            true
+        }
+        Err(e) => {
+            warn!("failed to initialize virtio-crypto: {:?}", e);
+            false
+        }
+    }
+}
+
+/// Probes a crypto device at the MMIO region, and returns whether one is
+/// found.
+#[cfg(bus = "mmio")]
+pub(crate) fn probe_mmio(mmio_base: usize, _mmio_size: usize) -> bool {
+    use axhal::mem::phys_to_virt;
+    use core::ptr::NonNull;
+    use virtio_drivers::transport::mmio::VirtIOHeader;
+
+    let header = phys_to_virt(mmio_base.into()).as_mut_ptr() as *mut VirtIOHeader;
+    let Some(header) = NonNull::new(header) else {
+        return false;
+    };
+    match unsafe { CryptoTransport::new(header) } {
+        Ok(transport) if transport.device_type() == DeviceType::Crypto => register(transport),
+        _ => false,
+    }
+}
+
+/// Probes a crypto device at the PCI function, and returns whether one is
+/// found.
+#[cfg(bus = "pci")]
+pub(crate) fn probe_pci(
+    root: &mut axdriver_pci::PciRoot,
+    bdf: axdriver_pci::DeviceFunction,
+    dev_info: &axdriver_pci::DeviceFunctionInfo,
+) -> bool {
+    // there is no transitional device
+    if dev_info.vendor_id != 0x1af4 || dev_info.device_id != 0x1054 {
+        return false;
+    }
+    match CryptoTransport::new::<VirtIoHalImpl>(root, bdf) {
+        Ok(transport) => register(transport),
+        Err(e) => {
+            warn!("failed to probe virtio-crypto at {}: {:?}", bdf, e);
+            false
+        }
+    }
+}
@@ -67,6 +67,61 @@ cfg_if::cfg_if! {
     }
 }
 
+cfg_if::cfg_if! {
+    if #[cfg(block_dev = "zram")] {
+        pub struct ZramDriver;
+        register_block_driver!(ZramDriver, crate::zram::ZramDevice);
+
+        impl DriverProbe for ZramDriver {
+            fn probe_global() -> Option<AxDeviceEnum> {
+                Some(AxDeviceEnum::from_block(
+                    crate::zram::ZramDevice::new(0x400_0000), // 64 MiB
+                ))
+            }
+        }
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(block_dev = "hosted-blk")] {
+        pub struct HostedDiskDriver;
+        register_block_driver!(HostedDiskDriver, crate::hosted::HostedDisk);
+
+        impl DriverProbe for HostedDiskDriver {
+            fn probe_global() -> Option<AxDeviceEnum> {
+                let path = axhal::cmdline::param("hosted.disk").unwrap_or("disk.img");
+                match crate::hosted::HostedDisk::open(path) {
+                    Ok(disk) => Some(AxDeviceEnum::from_block(disk)),
+                    Err(e) => {
+                        warn!("hosted-blk: cannot open {:?}: {}", path, e);
+                        None
+                    }
+                }
+            }
+        }
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(net_dev = "hosted-net")] {
+        pub struct HostedTapDriver;
+        register_net_driver!(HostedTapDriver, crate::hosted::HostedTap);
+
+        impl DriverProbe for HostedTapDriver {
+            fn probe_global() -> Option<AxDeviceEnum> {
+                let name = axhal::cmdline::param("hosted.tap").unwrap_or("tap0");
+                match crate::hosted::HostedTap::open(name) {
+                    Ok(tap) => Some(AxDeviceEnum::from_net(tap)),
+                    Err(e) => {
+                        warn!("hosted-net: cannot attach to {:?}: {}", name, e);
+                        None
+                    }
+                }
+            }
+        }
+    }
+}
+
 cfg_if::cfg_if! {
     if #[cfg(block_dev = "bcm2835-sdhci")]{
         pub struct BcmSdhciDriver;
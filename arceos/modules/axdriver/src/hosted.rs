@@ -0,0 +1,287 @@
+//! Devices of the hosted platform (see `axhal`), backed by the host.
+//!
+//! - [`HostedDisk`] is a block device stored in a file of the host, given by
+//!   `hosted.disk=` on the command line (`disk.img` by default).
+//! - [`HostedTap`] is a NIC exchanging its frames with a TAP interface of the
+//!   host, given by `hosted.tap=` (`tap0` by default), which must exist and
+//!   be accessible to the user of the process, e.g. after
+//!   `ip tuntap add tap0 mode tap user $USER`.
+
+#[cfg(feature = "hosted-blk")]
+pub use self::blk::HostedDisk;
+#[cfg(feature = "hosted-net")]
+pub use self::net::HostedTap;
+
+#[cfg(feature = "hosted-blk")]
+mod blk {
+    use std::fs::{File, OpenOptions};
+    use std::os::unix::fs::FileExt;
+
+    use axdriver_base::{BaseDriverOps, DevError, DevResult, DeviceType};
+    use axdriver_block::BlockDriverOps;
+
+    const BLOCK_SIZE: usize = 512;
+
+    /// A block device stored in a file of the host.
+    pub struct HostedDisk {
+        file: File,
+        num_blocks: u64,
+    }
+
+    impl HostedDisk {
+        /// Opens the file at `path` as a disk, of its size rounded down to
+        /// the blocks.
+        pub fn open(path: &str) -> std::io::Result<Self> {
+            let file = OpenOptions::new().read(true).write(true).open(path)?;
+            let num_blocks = file.metadata()?.len() / BLOCK_SIZE as u64;
+            Ok(Self { file, num_blocks })
+        }
+
+        fn check_range(&self, block_id: u64, buf_len: usize) -> DevResult<u64> {
+            if buf_len % BLOCK_SIZE != 0 {
+                return Err(DevError::InvalidParam);
+            }
+            let end = block_id
+                .checked_add((buf_len / BLOCK_SIZE) as u64)
+                .ok_or(DevError::InvalidParam)?;
+            if end > self.num_blocks {
+                return Err(DevError::Io);
+            }
+            Ok(block_id * BLOCK_SIZE as u64)
+        }
+    }
+
+    impl BaseDriverOps for HostedDisk {
+        fn device_type(&self) -> DeviceType {
+            DeviceType::Block
+        }
+
+        fn device_name(&self) -> &str {
+            "hosted-blk"
+        }
+    }
+
+    impl BlockDriverOps for HostedDisk {
+        #[inline]
+        fn num_blocks(&self) -> u64 {
+            self.num_blocks
+        }
+
+        #[inline]
+        fn block_size(&self) -> usize {
+            BLOCK_SIZE
+        }
+
+        fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
+            let offset = self.check_range(block_id, buf.len())?;
+            self.file
+                .read_exact_at(buf, offset)
+                .map_err(|_| DevError::Io)
+        }
+
+        fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
+            let offset = self.check_range(block_id, buf.len())?;
+            self.file
+                .write_all_at(buf, offset)
+                .map_err(|_| DevError::Io)
+        }
+
+        fn flush(&mut self) -> DevResult {
+            self.file.sync_data().map_err(|_| DevError::Io)
+        }
+    }
+}
+
+#[cfg(feature = "hosted-net")]
+mod net {
+    use core::ptr::NonNull;
+    use std::boxed::Box;
+    use std::fs::{File, OpenOptions};
+    use std::io::{ErrorKind, IoSlice, Read, Write};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::vec;
+    use std::vec::Vec;
+
+    use axdriver_base::{BaseDriverOps, DevError, DevResult, DeviceType};
+    use axdriver_net::{EthernetAddress, NetBufPtr, NetDriverOps};
+
+    use crate::net_ext::{NetDriverExt, ETHERNET_HEADER_LEN, MAX_MTU, MIN_MTU, STANDARD_MTU};
+
+    /// The `ioctl` attaching a TUN/TAP file to an interface, see
+    /// `linux/if_tun.h`.
+    const TUNSETIFF: libc::c_ulong = 0x4004_54ca;
+    const IFF_TAP: libc::c_short = 0x0002;
+    const IFF_NO_PI: libc::c_short = 0x1000;
+
+    /// The frames which can be received or sent at once, for the stack.
+    const QUEUE_SIZE: usize = 64;
+    /// The segments of a frame written at once.
+    const MAX_SEGMENTS: usize = 16;
+    /// The address of the first NIC of QEMU: the hosts of the tests are
+    /// configured the same way.
+    const MAC_ADDRESS: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+
+    /// `struct ifreq` with the flags, see `linux/if.h`.
+    #[repr(C)]
+    struct IfReq {
+        name: [u8; libc::IFNAMSIZ],
+        flags: libc::c_short,
+        _pad: [u8; 22],
+    }
+
+    /// A NIC exchanging its frames with a TAP interface of the host.
+    ///
+    /// The buffers are allocated from the heap for each frame, and a frame
+    /// the host does not take is dropped, as on a congested link. The MTU
+    /// goes up to [`MAX_MTU`], if the one of the interface is raised on the
+    /// host as well, and the segments of a frame are written at once with
+    /// `writev`.
+    pub struct HostedTap {
+        file: File,
+        mtu: usize,
+    }
+
+    impl HostedTap {
+        /// Attaches to the TAP interface `name` of the host.
+        pub fn open(name: &str) -> std::io::Result<Self> {
+            if name.len() >= libc::IFNAMSIZ {
+                return Err(ErrorKind::InvalidInput.into());
+            }
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open("/dev/net/tun")?;
+            let mut req = IfReq {
+                name: [0; libc::IFNAMSIZ],
+                flags: IFF_TAP | IFF_NO_PI,
+                _pad: [0; 22],
+            };
+            req.name[..name.len()].copy_from_slice(name.as_bytes());
+            if unsafe { libc::ioctl(file.as_raw_fd(), TUNSETIFF, &mut req) } < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(Self {
+                file,
+                mtu: STANDARD_MTU,
+            })
+        }
+
+        /// The largest frame, without the FCS.
+        fn max_frame_size(&self) -> usize {
+            ETHERNET_HEADER_LEN + self.mtu
+        }
+
+        /// Gives the buffer of a frame to [`NetBufPtr`], which keeps the box
+        /// in its raw pointer.
+        fn into_buf_ptr(mut buf: Box<Vec<u8>>, len: usize) -> NetBufPtr {
+            let buf_ptr = NonNull::new(buf.as_mut_ptr()).unwrap();
+            let raw_ptr = NonNull::new(Box::into_raw(buf) as *mut u8).unwrap();
+            NetBufPtr::new(raw_ptr, buf_ptr, len)
+        }
+
+        fn free_buf_ptr(buf: NetBufPtr) {
+            drop(unsafe { Box::from_raw(buf.raw_ptr::<Vec<u8>>()) });
+        }
+    }
+
+    impl BaseDriverOps for HostedTap {
+        fn device_type(&self) -> DeviceType {
+            DeviceType::Net
+        }
+
+        fn device_name(&self) -> &str {
+            "hosted-net"
+        }
+    }
+
+    impl NetDriverOps for HostedTap {
+        fn mac_address(&self) -> EthernetAddress {
+            EthernetAddress(MAC_ADDRESS)
+        }
+
+        fn can_transmit(&self) -> bool {
+            true
+        }
+
+        fn can_receive(&self) -> bool {
+            true
+        }
+
+        fn rx_queue_size(&self) -> usize {
+            QUEUE_SIZE
+        }
+
+        fn tx_queue_size(&self) -> usize {
+            QUEUE_SIZE
+        }
+
+        fn recycle_rx_buffer(&mut self, rx_buf: NetBufPtr) -> DevResult {
+            Self::free_buf_ptr(rx_buf);
+            Ok(())
+        }
+
+        fn recycle_tx_buffers(&mut self) -> DevResult {
+            Ok(())
+        }
+
+        fn transmit(&mut self, tx_buf: NetBufPtr) -> DevResult {
+            if let Err(e) = self.file.write(tx_buf.packet()) {
+                debug!("hosted-net: frame dropped: {}", e);
+            }
+            Self::free_buf_ptr(tx_buf);
+            Ok(())
+        }
+
+        fn receive(&mut self) -> DevResult<NetBufPtr> {
+            let mut buf = Box::new(vec![0; self.max_frame_size()]);
+            match self.file.read(&mut buf) {
+                Ok(len) => Ok(Self::into_buf_ptr(buf, len)),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => Err(DevError::Again),
+                Err(_) => Err(DevError::Io),
+            }
+        }
+
+        fn alloc_tx_buffer(&mut self, size: usize) -> DevResult<NetBufPtr> {
+            if size > self.max_frame_size() {
+                return Err(DevError::InvalidParam);
+            }
+            Ok(Self::into_buf_ptr(Box::new(vec![0; size]), size))
+        }
+    }
+
+    impl NetDriverExt for HostedTap {
+        fn max_mtu(&self) -> usize {
+            MAX_MTU
+        }
+
+        fn mtu(&self) -> usize {
+            self.mtu
+        }
+
+        fn set_mtu(&mut self, mtu: usize) -> DevResult {
+            if !(MIN_MTU..=MAX_MTU).contains(&mtu) {
+                return Err(DevError::InvalidParam);
+            }
+            self.mtu = mtu;
+            Ok(())
+        }
+
+        fn max_tx_segments(&self) -> usize {
+            MAX_SEGMENTS
+        }
+
+        fn transmit_segments(&mut self, segments: &[&[u8]]) -> DevResult {
+            let len: usize = segments.iter().map(|s| s.len()).sum();
+            if segments.len() > MAX_SEGMENTS || len > self.max_frame_size() {
+                return Err(DevError::InvalidParam);
+            }
+            let slices: Vec<_> = segments.iter().map(|s| IoSlice::new(s)).collect();
+            if let Err(e) = self.file.write_vectored(&slices) {
+                debug!("hosted-net: frame dropped: {}", e);
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Inter-VM shared memory (ivshmem) driver.
+//!
+//! The `ivshmem-plain` PCI device of QEMU maps the same host memory (BAR 2)
+//! in all the VMs having one, so co-located instances can exchange data
+//! without going through the host network. The regions found are listed by
+//! [`regions`], and [`ShmChannel`] runs a message ring protocol over one.
+//!
+//! The region must be in the PCI memory space mapped by the kernel, i.e. its
+//! BAR assigned from `PCI_RANGES` rather than above 4 GiB by the firmware.
+
+mod ring;
+
+use alloc::vec::Vec;
+
+use kspin::SpinNoIrq;
+
+pub use self::ring::ShmChannel;
+
+/// A shared memory region of an ivshmem device.
+#[derive(Debug, Clone, Copy)]
+pub struct SharedMemory {
+    /// The physical address of the region.
+    pub paddr: usize,
+    /// The virtual address of the region.
+    pub vaddr: usize,
+    /// The size of the region, in bytes.
+    pub size: usize,
+}
+
+static REGIONS: SpinNoIrq<Vec<SharedMemory>> = SpinNoIrq::new(Vec::new());
+
+/// Returns the shared memory regions, in the order of the devices on the bus.
+pub fn regions() -> Vec<SharedMemory> {
+    REGIONS.lock().clone()
+}
+
+/// Returns the shared memory region of the `index`-th device.
+pub fn region(index: usize) -> Option<SharedMemory> {
+    REGIONS.lock().get(index).copied()
+}
+
+/// Probes an ivshmem device at the PCI function, and returns whether one is
+/// found.
+#[cfg(bus = "pci")]
+pub(crate) fn probe_pci(
+    root: &mut axdriver_pci::PciRoot,
+    bdf: axdriver_pci::DeviceFunction,
+    dev_info: &axdriver_pci::DeviceFunctionInfo,
+) -> bool {
+    use axdriver_pci::BarInfo;
+    use axhal::mem::phys_to_virt;
+
+    if dev_info.vendor_id != 0x1af4 || dev_info.device_id != 0x1110 {
+        return false;
+    }
+    // BAR 0 holds the doorbell registers, unused by `ivshmem-plain`
+    let Ok(BarInfo::Memory { address, size, .. }) = root.bar_info(bdf, 2) else {
+        warn!("ivshmem at {}: no shared memory BAR", bdf);
+        return false;
+    };
+    if address == 0 || size == 0 {
+        warn!("ivshmem at {}: shared memory BAR not assigned", bdf);
+        return false;
+    }
+    let paddr = address as usize;
+    REGIONS.lock().push(SharedMemory {
+        paddr,
+        vaddr: phys_to_virt(paddr.into()).as_usize(),
+        size: size as usize,
+    });
+    true
+}
@@ -0,0 +1,230 @@
+//! A message ring protocol over a shared memory region.
+//!
+//! The region is split in two halves, each one a ring carrying the messages
+//! of one direction: side 0 sends on the first one, and side 1 on the second.
+//! A ring is a header, written by both sides on separate cache lines, then the
+//! data, made of messages `[len: u32][payload, padded to 4 bytes]` which wrap
+//! around its end.
+//!
+//! The receiver of a ring initializes it when opening the channel, and the
+//! sender waits for that before sending.
+
+use core::ptr;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use axdriver_base::{DevError, DevResult};
+
+use super::SharedMemory;
+
+const RING_MAGIC: u32 = 0x4853_5841; // "AXSH"
+
+/// The header of a ring, at the start of its half of the region.
+#[repr(C)]
+struct RingHeader {
+    magic: AtomicU32,
+    /// The size of the data.
+    capacity: AtomicU32,
+    _pad0: [u8; 56],
+    /// The offset in the data where the sender writes next.
+    head: AtomicU32,
+    _pad1: [u8; 60],
+    /// The offset in the data where the receiver reads next.
+    tail: AtomicU32,
+    _pad2: [u8; 60],
+}
+
+const HEADER_SIZE: usize = core::mem::size_of::<RingHeader>();
+const LEN_SIZE: usize = 4;
+
+/// One direction of a channel.
+struct Ring {
+    header: *const RingHeader,
+    data: *mut u8,
+    capacity: u32,
+}
+
+impl Ring {
+    /// # Safety
+    ///
+    /// `[base, base + size)` must be mapped shared memory, aligned to 64
+    /// bytes, and larger than the header.
+    unsafe fn new(base: usize, size: usize) -> Self {
+        let capacity = (size - HEADER_SIZE).min(u32::MAX as usize) & !(LEN_SIZE - 1);
+        Self {
+            header: base as *const RingHeader,
+            data: (base + HEADER_SIZE) as *mut u8,
+            capacity: capacity as u32,
+        }
+    }
+
+    fn header(&self) -> &RingHeader {
+        unsafe { &*self.header }
+    }
+
+    /// Resets the ring, as its receiver.
+    fn init(&self) {
+        let header = self.header();
+        header.magic.store(0, Ordering::Release);
+        header.head.store(0, Ordering::Relaxed);
+        header.tail.store(0, Ordering::Relaxed);
+        header.capacity.store(self.capacity, Ordering::Relaxed);
+        header.magic.store(RING_MAGIC, Ordering::Release);
+    }
+
+    fn is_ready(&self) -> bool {
+        let header = self.header();
+        header.magic.load(Ordering::Acquire) == RING_MAGIC
+            && header.capacity.load(Ordering::Relaxed) == self.capacity
+    }
+
+    /// Number of bytes between `tail` and `head`.
+    fn used(&self, head: u32, tail: u32) -> u32 {
+        if head >= tail {
+            head - tail
+        } else {
+            self.capacity - tail + head
+        }
+    }
+
+    fn copy_in(&self, offset: u32, src: &[u8]) -> u32 {
+        let offset = offset as usize;
+        let first = src.len().min(self.capacity as usize - offset);
+        unsafe {
+            ptr::copy_nonoverlapping(src.as_ptr(), self.data.add(offset), first);
+            ptr::copy_nonoverlapping(src[first..].as_ptr(), self.data, src.len() - first);
+        }
+        ((offset + src.len()) % self.capacity as usize) as u32
+    }
+
+    fn copy_out(&self, offset: u32, dst: &mut [u8]) -> u32 {
+        let offset = offset as usize;
+        let first = dst.len().min(self.capacity as usize - offset);
+        unsafe {
+            ptr::copy_nonoverlapping(self.data.add(offset), dst.as_mut_ptr(), first);
+            let rest = dst.len() - first;
+            ptr::copy_nonoverlapping(self.data, dst[first..].as_mut_ptr(), rest);
+        }
+        ((offset + dst.len()) % self.capacity as usize) as u32
+    }
+
+    fn send(&self, msg: &[u8]) -> DevResult {
+        if !self.is_ready() {
+            return Err(DevError::BadState);
+        }
+        let size = LEN_SIZE + msg.len().next_multiple_of(LEN_SIZE);
+        // a byte is always left, so that a full ring is not taken for empty
+        if size >= self.capacity as usize {
+            return Err(DevError::InvalidParam);
+        }
+        let header = self.header();
+        let head = header.head.load(Ordering::Relaxed);
+        let tail = header.tail.load(Ordering::Acquire);
+        if head >= self.capacity || tail >= self.capacity {
+            return Err(DevError::Io);
+        }
+        let free = self.capacity - 1 - self.used(head, tail);
+        if size > free as usize {
+            return Err(DevError::Again);
+        }
+        let offset = self.copy_in(head, &(msg.len() as u32).to_le_bytes());
+        self.copy_in(offset, msg);
+        let head = (head + size as u32) % self.capacity;
+        header.head.store(head, Ordering::Release);
+        Ok(())
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> DevResult<usize> {
+        let header = self.header();
+        let head = header.head.load(Ordering::Acquire);
+        let tail = header.tail.load(Ordering::Relaxed);
+        if head == tail {
+            return Err(DevError::Again);
+        }
+        let used = self.used(head, tail) as usize;
+        let mut len = [0; LEN_SIZE];
+        let offset = self.copy_out(tail, &mut len);
+        let len = u32::from_le_bytes(len) as usize;
+        let size = LEN_SIZE + len.next_multiple_of(LEN_SIZE);
+        if head >= self.capacity || size > used {
+            // the sender is confused, drop everything
+            warn!("ivshmem: corrupted ring, {} bytes dropped", used);
+            header.tail.store(head, Ordering::Release);
+            return Err(DevError::Io);
+        }
+        if len > buf.len() {
+            return Err(DevError::NoMemory);
+        }
+        self.copy_out(offset, &mut buf[..len]);
+        header
+            .tail
+            .store((tail + size as u32) % self.capacity, Ordering::Release);
+        Ok(len)
+    }
+}
+
+/// A bidirectional message channel with the peer sharing an ivshmem region.
+///
+/// The operations never block: they return [`DevError::Again`] when the
+/// ring is full (for [`send`](Self::send)) or empty (for
+/// [`recv`](Self::recv)).
+pub struct ShmChannel {
+    tx: Ring,
+    rx: Ring,
+}
+
+unsafe impl Send for ShmChannel {}
+unsafe impl Sync for ShmChannel {}
+
+impl ShmChannel {
+    /// Opens the channel over the region, as the `side` (0 or 1), the peer
+    /// being the other one.
+    ///
+    /// It resets the ring of the messages to receive, so the messages the
+    /// peer sent before are lost.
+    ///
+    /// # Safety
+    ///
+    /// Only one channel may be opened on each side of a region.
+    pub unsafe fn open(shm: &SharedMemory, side: usize) -> DevResult<Self> {
+        let half = (shm.size / 2) & !63;
+        if side > 1 || half < HEADER_SIZE + 64 {
+            return Err(DevError::InvalidParam);
+        }
+        let first = Ring::new(shm.vaddr, half);
+        let second = Ring::new(shm.vaddr + half, half);
+        let (tx, rx) = if side == 0 {
+            (first, second)
+        } else {
+            (second, first)
+        };
+        rx.init();
+        Ok(Self { tx, rx })
+    }
+
+    /// Returns whether the peer has opened the channel.
+    pub fn is_connected(&self) -> bool {
+        self.tx.is_ready()
+    }
+
+    /// Returns the maximum size of a message.
+    pub fn max_message_size(&self) -> usize {
+        (self.tx.capacity as usize - 1 - LEN_SIZE) & !(LEN_SIZE - 1)
+    }
+
+    /// Sends a message.
+    ///
+    /// Returns [`DevError::BadState`] if the peer has not opened the
+    /// channel yet, and [`DevError::InvalidParam`] if the message is larger
+    /// than [`max_message_size`](Self::max_message_size).
+    pub fn send(&self, msg: &[u8]) -> DevResult {
+        self.tx.send(msg)
+    }
+
+    /// Receives a message to `buf`, and returns its size.
+    ///
+    /// Returns [`DevError::NoMemory`] if `buf` is too small, the message
+    /// being kept.
+    pub fn recv(&self, buf: &mut [u8]) -> DevResult<usize> {
+        self.rx.recv(buf)
+    }
+}
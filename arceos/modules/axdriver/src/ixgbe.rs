@@ -1,8 +1,10 @@
 use axdma::{alloc_coherent, dealloc_coherent, BusAddr, DMAInfo};
-use axdriver_net::ixgbe::{IxgbeHal, PhysAddr as IxgbePhysAddr};
+use axdriver_net::ixgbe::{IxgbeHal, IxgbeNic, PhysAddr as IxgbePhysAddr};
 use axhal::mem::{phys_to_virt, virt_to_phys};
 use core::{alloc::Layout, ptr::NonNull};
 
+use crate::net_ext::NetDriverExt;
+
 pub struct IxgbeHalImpl;
 
 unsafe impl IxgbeHal for IxgbeHalImpl {
@@ -37,3 +39,5 @@ unsafe impl IxgbeHal for IxgbeHalImpl {
         Ok(())
     }
 }
+
+impl<const QS: usize, const QN: u16> NetDriverExt for IxgbeNic<IxgbeHalImpl, QS, QN> {}
@@ -21,7 +21,7 @@
 //!   model provides the best performance as it avoids dynamic dispatch. But on
 //!   limitation, only one device instance is supported for each device category.
 //! - **Dynamic**: All device instance is using [trait objects] and wrapped in a
-//!   `Box<dyn Trait>`. For example, [`AxNetDevice`] will be [`Box<dyn NetDriverOps>`].
+//!   `Box<dyn Trait>`. For example, [`AxNetDevice`] will be [`Box<dyn NetDriverExt>`].
 //!   When call a method provided by the device, it uses [dynamic dispatch][dyn]
 //!   that may introduce a little overhead. But on the other hand, it is more
 //!   flexible, multiple instances of each device category are supported.
@@ -31,9 +31,19 @@
 //! | Device Category | Cargo Feature | Description |
 //! |-|-|-|
 //! | Block | `ramdisk` | A RAM disk that stores data in a vector |
+//! | Block | `zram` | A RAM disk that stores LZ4-compressed pages |
 //! | Block | `virtio-blk` | VirtIO block device |
+//! | Block | `hosted-blk` | A file of the host, on the hosted platform, see [`hosted`] |
 //! | Network | `virtio-net` | VirtIO network device |
+//! | Network | `hosted-net` | A TAP interface of the host, on the hosted platform, see [`hosted`] |
 //! | Display | `virtio-gpu` | VirtIO graphics device |
+//! | Memory | `virtio-balloon` | VirtIO memory balloon, see [`balloon`] |
+//! | Crypto | `virtio-crypto` | VirtIO crypto device, used by [`axcrypto`] |
+//! | Socket | `virtio-vsock` | VirtIO socket device, see [`vsock`] |
+//! | Shared memory | `ivshmem` | Inter-VM shared memory with message rings, see [`ivshmem`] |
+//! | Thermal | `thermal` | Temperature sensors and throttling, see [`thermal`] |
+//! | CPU frequency | `cpufreq` | SCMI and OPP-table frequency scaling with governors, see [`cpufreq`] |
+//! | CAN bus | `can` | SJA1000 (`kvaser_pci` of QEMU) and virtual CAN controllers, with raw sockets, see [`can`] |
 //!
 //! # Other Cargo Features
 //!
@@ -45,12 +55,23 @@
 //!   `virtio-net` or `virtio-gpu` is enabled.
 //! - `net`: use network devices. This is enabled if any feature of network
 //!    devices is selected. If this feature is enabled without any network device
-//!    features, a dummy struct is used for [`AxNetDevice`].
+//!    features, a dummy struct is used for [`AxNetDevice`]. It also provides
+//!    the interrupt coalescing policy of NIC drivers, see [`net_coalesce`],
+//!    and the scatter-gather and jumbo frames of NICs, see [`net_ext`].
 //! - `block`: use block storage devices. Similar to the `net` feature.
 //! - `display`: use graphics display devices. Similar to the `net` feature.
+//! - `boot-time`: record the time spent probing each driver, see
+//!   [`boot_time`].
+//! - `parallel-probe`: probe each global driver (e.g. SD/eMMC controllers)
+//!   on its own task, so the other CPUs can probe them while this one scans
+//!   the buses.
+//! - `deferred-probe`: leave the drivers listed in `AX_DEFERRED_DRIVERS`
+//!   (comma separated, e.g. `virtio-gpu,virtio-crypto`) to
+//!   [`init_deferred_drivers`], which probes them on the devices left by
+//!   [`init_drivers`].
 //!
 //! [`VirtioNetDev`]: axdriver_virtio::VirtIoNetDev
-//! [`Box<dyn NetDriverOps>`]: axdriver_net::NetDriverOps
+//! [`Box<dyn NetDriverExt>`]: net_ext::NetDriverExt
 //! [trait objects]: https://doc.rust-lang.org/book/ch17-02-trait-objects.html
 //! [dyn]: https://doc.rust-lang.org/std/keyword.dyn.html
 
@@ -61,7 +82,22 @@
 #[macro_use]
 extern crate log;
 
-#[cfg(feature = "dyn")]
+#[cfg(any(feature = "hosted-blk", feature = "hosted-net"))]
+extern crate std;
+
+#[cfg(any(
+    feature = "dyn",
+    feature = "zram",
+    feature = "virtio-balloon",
+    feature = "virtio-crypto",
+    feature = "virtio-vsock",
+    feature = "ivshmem",
+    feature = "thermal",
+    feature = "cpufreq",
+    feature = "can",
+    feature = "parallel-probe",
+    feature = "deferred-probe"
+))]
 extern crate alloc;
 
 #[macro_use]
@@ -78,6 +114,45 @@ mod virtio;
 #[cfg(feature = "ixgbe")]
 mod ixgbe;
 
+#[cfg(feature = "zram")]
+pub mod zram;
+
+#[cfg(any(feature = "hosted-blk", feature = "hosted-net"))]
+pub mod hosted;
+
+#[cfg(feature = "virtio-balloon")]
+pub mod balloon;
+
+#[cfg(feature = "virtio-crypto")]
+mod crypto;
+
+#[cfg(feature = "virtio-vsock")]
+pub mod vsock;
+
+#[cfg(feature = "ivshmem")]
+pub mod ivshmem;
+
+#[cfg(feature = "thermal")]
+pub mod thermal;
+
+#[cfg(feature = "cpufreq")]
+pub mod cpufreq;
+
+#[cfg(feature = "can")]
+pub mod can;
+
+#[cfg(feature = "net")]
+pub mod net_coalesce;
+
+#[cfg(feature = "net")]
+pub mod net_ext;
+
+#[cfg(feature = "boot-time")]
+pub mod boot_time;
+
+#[cfg(feature = "parallel-probe")]
+mod parallel;
+
 pub mod prelude;
 
 #[allow(unused_imports)]
@@ -117,20 +192,41 @@ impl AllDevices {
         }
     }
 
-    /// Probes all supported devices.
-    fn probe(&mut self) {
-        for_each_drivers!(type Driver, {
-            if let Some(dev) = Driver::probe_global() {
-                info!(
-                    "registered a new {:?} device: {:?}",
-                    dev.device_type(),
-                    dev.device_name(),
-                );
-                self.add_device(dev);
+    /// Probes all supported devices, with the drivers deferred or not.
+    fn probe(&mut self, deferred: bool) {
+        #[cfg(feature = "parallel-probe")]
+        {
+            // the global drivers probe on their own tasks, which may run on
+            // the other CPUs, while this one scans the buses
+            let probes = parallel::spawn_global_probes(deferred);
+            let mut bus_devs = AllDevices::default();
+            bus_devs.probe_bus_devices(deferred);
+            for dev in parallel::join_global_probes(probes) {
+                self.add_global_device(dev);
             }
-        });
+            self.append(bus_devs);
+        }
+        #[cfg(not(feature = "parallel-probe"))]
+        {
+            for_each_drivers!(type Driver, name DRIVER_NAME, {
+                if is_deferred(DRIVER_NAME) == deferred {
+                    if let Some(dev) = timed_probe!(DRIVER_NAME, Driver::probe_global()) {
+                        self.add_global_device(dev);
+                    }
+                }
+            });
+            self.probe_bus_devices(deferred);
+        }
+    }
 
-        self.probe_bus_devices();
+    #[allow(dead_code)]
+    fn add_global_device(&mut self, dev: AxDeviceEnum) {
+        info!(
+            "registered a new {:?} device: {:?}",
+            dev.device_type(),
+            dev.device_name(),
+        );
+        self.add_device(dev);
     }
 
     /// Adds one device into the corresponding container, according to its device category.
@@ -145,40 +241,86 @@ impl AllDevices {
             AxDeviceEnum::Display(dev) => self.display.push(dev),
         }
     }
+
+    /// Moves all the devices of `other` after the ones of `self`.
+    #[allow(dead_code, unused_mut, unused_variables)]
+    fn append(&mut self, mut other: AllDevices) {
+        #[cfg(feature = "net")]
+        while let Some(dev) = other.net.take_one() {
+            self.net.push(dev);
+        }
+        #[cfg(feature = "block")]
+        while let Some(dev) = other.block.take_one() {
+            self.block.push(dev);
+        }
+        #[cfg(feature = "display")]
+        while let Some(dev) = other.display.take_one() {
+            self.display.push(dev);
+        }
+    }
+
+    fn log_devices(&self) {
+        #[cfg(feature = "net")]
+        {
+            debug!("number of NICs: {}", self.net.len());
+            for (i, dev) in self.net.iter().enumerate() {
+                assert_eq!(dev.device_type(), DeviceType::Net);
+                debug!("  NIC {}: {:?}", i, dev.device_name());
+            }
+        }
+        #[cfg(feature = "block")]
+        {
+            debug!("number of block devices: {}", self.block.len());
+            for (i, dev) in self.block.iter().enumerate() {
+                assert_eq!(dev.device_type(), DeviceType::Block);
+                debug!("  block device {}: {:?}", i, dev.device_name());
+            }
+        }
+        #[cfg(feature = "display")]
+        {
+            debug!("number of graphics devices: {}", self.display.len());
+            for (i, dev) in self.display.iter().enumerate() {
+                assert_eq!(dev.device_type(), DeviceType::Display);
+                debug!("  graphics device {}: {:?}", i, dev.device_name());
+            }
+        }
+    }
+}
+
+/// Returns whether the driver `name` is left to [`init_deferred_drivers`],
+/// i.e. listed in `AX_DEFERRED_DRIVERS` with the `deferred-probe` feature.
+#[allow(dead_code)]
+fn is_deferred(name: &str) -> bool {
+    cfg!(feature = "deferred-probe")
+        && option_env!("AX_DEFERRED_DRIVERS")
+            .unwrap_or("")
+            .split(',')
+            .any(|n| n.trim() == name)
 }
 
 /// Probes and initializes all device drivers, returns the [`AllDevices`] struct.
+///
+/// The drivers deferred by `AX_DEFERRED_DRIVERS` are left out, to be probed
+/// by [`init_deferred_drivers`] on the remaining devices.
 pub fn init_drivers() -> AllDevices {
     info!("Initialize device drivers...");
     info!("  device model: {}", AllDevices::device_model());
 
     let mut all_devs = AllDevices::default();
-    all_devs.probe();
+    all_devs.probe(false);
+    all_devs.log_devices();
+    all_devs
+}
 
-    #[cfg(feature = "net")]
-    {
-        debug!("number of NICs: {}", all_devs.net.len());
-        for (i, dev) in all_devs.net.iter().enumerate() {
-            assert_eq!(dev.device_type(), DeviceType::Net);
-            debug!("  NIC {}: {:?}", i, dev.device_name());
-        }
-    }
-    #[cfg(feature = "block")]
-    {
-        debug!("number of block devices: {}", all_devs.block.len());
-        for (i, dev) in all_devs.block.iter().enumerate() {
-            assert_eq!(dev.device_type(), DeviceType::Block);
-            debug!("  block device {}: {:?}", i, dev.device_name());
-        }
-    }
-    #[cfg(feature = "display")]
-    {
-        debug!("number of graphics devices: {}", all_devs.display.len());
-        for (i, dev) in all_devs.display.iter().enumerate() {
-            assert_eq!(dev.device_type(), DeviceType::Display);
-            debug!("  graphics device {}: {:?}", i, dev.device_name());
-        }
-    }
+/// Probes and initializes the drivers deferred by `AX_DEFERRED_DRIVERS`, on
+/// the devices left by [`init_drivers`], returns the [`AllDevices`] struct of
+/// the new devices.
+#[cfg(feature = "deferred-probe")]
+pub fn init_deferred_drivers() -> AllDevices {
+    info!("Initialize deferred device drivers...");
 
+    let mut all_devs = AllDevices::default();
+    all_devs.probe(true);
+    all_devs.log_devices();
     all_devs
 }
@@ -26,8 +26,13 @@ macro_rules! register_display_driver {
     };
 }
 
+/// Runs `$code` for each registered driver, with `$drv_type` its type, and
+/// `$drv_name` its name if given.
 macro_rules! for_each_drivers {
-    (type $drv_type:ident, $code:block) => {{
+    (type $drv_type:ident, $code:block) => {
+        for_each_drivers!(type $drv_type, name _DRIVER_NAME, $code)
+    };
+    (type $drv_type:ident, name $drv_name:ident, $code:block) => {{
         #[allow(unused_imports)]
         use crate::drivers::DriverProbe;
         #[cfg(feature = "virtio")]
@@ -37,32 +42,77 @@ macro_rules! for_each_drivers {
         #[cfg(net_dev = "virtio-net")]
         {
             type $drv_type = <virtio::VirtIoNet as VirtIoDevMeta>::Driver;
+            #[allow(dead_code)]
+            const $drv_name: &str = "virtio-net";
             $code
         }
         #[cfg(block_dev = "virtio-blk")]
         {
             type $drv_type = <virtio::VirtIoBlk as VirtIoDevMeta>::Driver;
+            #[allow(dead_code)]
+            const $drv_name: &str = "virtio-blk";
             $code
         }
         #[cfg(display_dev = "virtio-gpu")]
         {
             type $drv_type = <virtio::VirtIoGpu as VirtIoDevMeta>::Driver;
+            #[allow(dead_code)]
+            const $drv_name: &str = "virtio-gpu";
             $code
         }
         #[cfg(block_dev = "ramdisk")]
         {
             type $drv_type = crate::drivers::RamDiskDriver;
+            #[allow(dead_code)]
+            const $drv_name: &str = "ramdisk";
+            $code
+        }
+        #[cfg(block_dev = "zram")]
+        {
+            type $drv_type = crate::drivers::ZramDriver;
+            #[allow(dead_code)]
+            const $drv_name: &str = "zram";
+            $code
+        }
+        #[cfg(block_dev = "hosted-blk")]
+        {
+            type $drv_type = crate::drivers::HostedDiskDriver;
+            #[allow(dead_code)]
+            const $drv_name: &str = "hosted-blk";
+            $code
+        }
+        #[cfg(net_dev = "hosted-net")]
+        {
+            type $drv_type = crate::drivers::HostedTapDriver;
+            #[allow(dead_code)]
+            const $drv_name: &str = "hosted-net";
             $code
         }
         #[cfg(block_dev = "bcm2835-sdhci")]
         {
             type $drv_type = crate::drivers::BcmSdhciDriver;
+            #[allow(dead_code)]
+            const $drv_name: &str = "bcm2835-sdhci";
             $code
         }
         #[cfg(net_dev = "ixgbe")]
         {
             type $drv_type = crate::drivers::IxgbeDriver;
+            #[allow(dead_code)]
+            const $drv_name: &str = "ixgbe";
             $code
         }
     }};
 }
+
+/// Evaluates the probe `$probe`, timing it as a probe of `$drv_name` with the
+/// `boot-time` feature.
+macro_rules! timed_probe {
+    ($drv_name:expr, $probe:expr) => {{
+        #[cfg(feature = "boot-time")]
+        let ret = crate::boot_time::measure($drv_name, || $probe);
+        #[cfg(not(feature = "boot-time"))]
+        let ret = $probe;
+        ret
+    }};
+}
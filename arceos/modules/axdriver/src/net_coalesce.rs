@@ -0,0 +1,195 @@
+//! Interrupt coalescing of network devices.
+//!
+//! Taking an interrupt for every received packet saturates a core at high
+//! packet rates. With coalescing, a NIC driver signals the network stack once
+//! [`CoalesceConfig::max_frames`] packets are pending, or
+//! [`CoalesceConfig::max_usecs`] after the first of them, whichever comes
+//! first.
+//!
+//! In adaptive mode, the thresholds follow the observed packet rate: no
+//! coalescing at low rates, where latency matters most, and up to the
+//! configured maximums at high rates, where throughput does.
+//!
+//! Drivers with interrupt support keep an [`InterruptModerator`], report
+//! received packets with [`InterruptModerator::on_packets`], and arm a timer
+//! for [`InterruptModerator::deadline`]. The drivers of this crate are
+//! currently polled, so they do not use it yet.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+const NANOS_PER_USEC: u64 = 1_000;
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Length of the windows over which the packet rate is measured.
+const RATE_WINDOW_NANOS: u64 = 1_000_000;
+
+/// Packet rates (per second) below which the adaptive mode does not
+/// coalesce, and above which it uses the maximum thresholds.
+const LOW_RATE: u64 = 10_000;
+const HIGH_RATE: u64 = 200_000;
+
+/// Interrupt coalescing parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoalesceConfig {
+    /// Maximum number of packets pending before signaling, at least 1.
+    pub max_frames: u32,
+    /// Maximum delay after the first pending packet before signaling, in
+    /// microseconds. 0 disables the delay.
+    pub max_usecs: u32,
+    /// Whether to scale the thresholds with the packet rate, up to the
+    /// maximums above.
+    pub adaptive: bool,
+}
+
+impl CoalesceConfig {
+    /// No coalescing: signal every packet.
+    pub const DISABLED: Self = Self {
+        max_frames: 1,
+        max_usecs: 0,
+        adaptive: false,
+    };
+
+    /// Adaptive coalescing with the default maximums.
+    pub const ADAPTIVE: Self = Self {
+        max_frames: 64,
+        max_usecs: 200,
+        adaptive: true,
+    };
+}
+
+impl Default for CoalesceConfig {
+    fn default() -> Self {
+        Self::ADAPTIVE
+    }
+}
+
+static MAX_FRAMES: AtomicU32 = AtomicU32::new(CoalesceConfig::ADAPTIVE.max_frames);
+static MAX_USECS: AtomicU32 = AtomicU32::new(CoalesceConfig::ADAPTIVE.max_usecs);
+static ADAPTIVE: AtomicBool = AtomicBool::new(CoalesceConfig::ADAPTIVE.adaptive);
+
+/// Returns the coalescing parameters of the network devices.
+pub fn config() -> CoalesceConfig {
+    CoalesceConfig {
+        max_frames: MAX_FRAMES.load(Ordering::Relaxed),
+        max_usecs: MAX_USECS.load(Ordering::Relaxed),
+        adaptive: ADAPTIVE.load(Ordering::Relaxed),
+    }
+}
+
+/// Sets the coalescing parameters of the network devices, used from their
+/// next interrupt on.
+pub fn set_config(config: CoalesceConfig) {
+    MAX_FRAMES.store(config.max_frames.max(1), Ordering::Relaxed);
+    MAX_USECS.store(config.max_usecs, Ordering::Relaxed);
+    ADAPTIVE.store(config.adaptive, Ordering::Relaxed);
+}
+
+/// Decides when a network device signals received packets.
+///
+/// Times are in nanoseconds of the monotonic clock.
+#[derive(Debug)]
+pub struct InterruptModerator {
+    /// Packets received but not signaled yet.
+    pending: u32,
+    /// Time of the first pending packet.
+    first_pending: u64,
+    /// Current thresholds.
+    frames: u32,
+    usecs: u32,
+    /// Start of the current rate window, and packets received in it.
+    window_start: u64,
+    window_packets: u64,
+    /// Packet rate measured in the last window, per second.
+    rate: u64,
+}
+
+impl InterruptModerator {
+    /// Creates a moderator with no coalescing until the rate is measured.
+    pub const fn new() -> Self {
+        Self {
+            pending: 0,
+            first_pending: 0,
+            frames: 1,
+            usecs: 0,
+            window_start: 0,
+            window_packets: 0,
+            rate: 0,
+        }
+    }
+
+    /// Returns the packet rate measured last, per second.
+    pub fn packet_rate(&self) -> u64 {
+        self.rate
+    }
+
+    /// Returns the current thresholds, as packets and microseconds.
+    pub fn thresholds(&self) -> (u32, u32) {
+        (self.frames, self.usecs)
+    }
+
+    /// Records `count` received packets at `now`, and returns whether the
+    /// pending packets should be signaled now.
+    pub fn on_packets(&mut self, count: u32, now: u64) -> bool {
+        self.update_rate(count, now);
+        if self.pending == 0 {
+            self.first_pending = now;
+        }
+        self.pending = self.pending.saturating_add(count);
+        if self.pending >= self.frames || self.expired(now) {
+            self.pending = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the time by which the pending packets must be signaled, if
+    /// any are pending.
+    pub fn deadline(&self) -> Option<u64> {
+        (self.pending > 0).then(|| self.first_pending + self.usecs as u64 * NANOS_PER_USEC)
+    }
+
+    /// Called when the timer armed for [`deadline`](Self::deadline) fires,
+    /// returns whether the pending packets should be signaled now.
+    pub fn on_timer(&mut self, now: u64) -> bool {
+        if self.pending > 0 && self.expired(now) {
+            self.pending = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expired(&self, now: u64) -> bool {
+        now.saturating_sub(self.first_pending) >= self.usecs as u64 * NANOS_PER_USEC
+    }
+
+    fn update_rate(&mut self, count: u32, now: u64) {
+        self.window_packets += count as u64;
+        let elapsed = now.saturating_sub(self.window_start);
+        if elapsed < RATE_WINDOW_NANOS {
+            return;
+        }
+        self.rate = self.window_packets * NANOS_PER_SEC / elapsed;
+        self.window_start = now;
+        self.window_packets = 0;
+
+        let config = config();
+        (self.frames, self.usecs) = if !config.adaptive {
+            (config.max_frames, config.max_usecs)
+        } else if self.rate <= LOW_RATE {
+            (1, 0)
+        } else {
+            // scale linearly between the low and the high rate
+            let rate = self.rate.min(HIGH_RATE) - LOW_RATE;
+            let scale = |max: u32| (max as u64 * rate / (HIGH_RATE - LOW_RATE)) as u32;
+            (scale(config.max_frames).max(1), scale(config.max_usecs))
+        };
+    }
+}
+
+impl Default for InterruptModerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,85 @@
+//! Scatter-gather and jumbo frames of network devices.
+//!
+//! [`NetDriverOps`] sends each frame from one buffer of the device, and has
+//! no notion of MTU: the stack assumes the standard one of Ethernet. The NIC
+//! types also implement [`NetDriverExt`], with which:
+//!
+//! - a frame is sent in segments, e.g. its headers and its payload in
+//!   different buffers, with [`NetDriverExt::transmit_segments`]. The devices
+//!   which gather them ([`NetDriverExt::max_tx_segments`] above 1) send them
+//!   as they are, the others copy them into one of their buffers.
+//! - the MTU goes up to [`MAX_MTU`] for jumbo frames, as far as the device
+//!   supports it, see [`NetDriverExt::negotiate_mtu`].
+//!
+//! The drivers of `axdriver_net` (VirtIO, ixgbe) keep the defaults: the
+//! standard MTU, and no gathering.
+
+use axdriver_base::{DevError, DevResult};
+use axdriver_net::NetDriverOps;
+
+/// The MTU of the standard Ethernet frames.
+pub const STANDARD_MTU: usize = 1500;
+/// The largest MTU, of the jumbo frames.
+pub const MAX_MTU: usize = 9000;
+/// The smallest MTU, of the links IPv4 runs on (RFC 791).
+pub const MIN_MTU: usize = 68;
+/// The length of the Ethernet header, without VLAN tags nor FCS.
+pub const ETHERNET_HEADER_LEN: usize = 14;
+
+/// The operations of a NIC beyond [`NetDriverOps`]: sending frames in
+/// segments, and MTUs other than the standard one.
+pub trait NetDriverExt: NetDriverOps {
+    /// Returns the largest MTU the device supports, [`STANDARD_MTU`] by
+    /// default.
+    fn max_mtu(&self) -> usize {
+        STANDARD_MTU
+    }
+
+    /// Returns the current MTU of the device, [`STANDARD_MTU`] by default.
+    fn mtu(&self) -> usize {
+        STANDARD_MTU
+    }
+
+    /// Sets the MTU of the device, from [`MIN_MTU`] to
+    /// [`max_mtu`](Self::max_mtu).
+    ///
+    /// By default only the current MTU is accepted, others failing with
+    /// [`DevError::InvalidParam`].
+    fn set_mtu(&mut self, mtu: usize) -> DevResult {
+        if mtu == self.mtu() {
+            Ok(())
+        } else {
+            Err(DevError::InvalidParam)
+        }
+    }
+
+    /// Returns the number of segments the device gathers in a frame, 1 if it
+    /// cannot.
+    fn max_tx_segments(&self) -> usize {
+        1
+    }
+
+    /// Transmits the frame made of `segments` one after the other.
+    ///
+    /// By default they are copied into a buffer of
+    /// [`alloc_tx_buffer`](NetDriverOps::alloc_tx_buffer), as one
+    /// [`transmit`](NetDriverOps::transmit) does.
+    fn transmit_segments(&mut self, segments: &[&[u8]]) -> DevResult {
+        let len = segments.iter().map(|s| s.len()).sum();
+        let mut tx_buf = self.alloc_tx_buffer(len)?;
+        let mut pos = 0;
+        for segment in segments {
+            tx_buf.packet_mut()[pos..pos + segment.len()].copy_from_slice(segment);
+            pos += segment.len();
+        }
+        self.transmit(tx_buf)
+    }
+
+    /// Sets the MTU of the device to the largest it supports up to `wanted`,
+    /// and returns it.
+    fn negotiate_mtu(&mut self, wanted: usize) -> DevResult<usize> {
+        let mtu = wanted.min(self.max_mtu()).clamp(MIN_MTU, MAX_MTU);
+        self.set_mtu(mtu)?;
+        Ok(mtu)
+    }
+}
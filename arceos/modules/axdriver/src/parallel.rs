@@ -0,0 +1,43 @@
+//! Probing of the global drivers on their own tasks.
+//!
+//! The global drivers (e.g. SD/eMMC controllers) do not depend on each other
+//! nor on the buses, and some are slow to probe, waiting for the card to
+//! power up. Each is probed on its own task, so the idle CPUs share the work.
+
+use alloc::{format, sync::Arc, vec::Vec};
+
+use axtask::AxTaskRef;
+use kspin::SpinNoIrq;
+
+use crate::AxDeviceEnum;
+
+/// A probe running on its task, with where it puts the device found.
+pub(crate) type GlobalProbe = (AxTaskRef, Arc<SpinNoIrq<Option<AxDeviceEnum>>>);
+
+/// Spawns a task for each global driver of the pass `deferred`.
+#[allow(unused_mut, unused_variables)]
+pub(crate) fn spawn_global_probes(deferred: bool) -> Vec<GlobalProbe> {
+    let mut probes = Vec::new();
+    for_each_drivers!(type Driver, name DRIVER_NAME, {
+        if crate::is_deferred(DRIVER_NAME) == deferred {
+            let found = Arc::new(SpinNoIrq::new(None));
+            let out = found.clone();
+            let task = axtask::spawn_raw(
+                move || *out.lock() = timed_probe!(DRIVER_NAME, Driver::probe_global()),
+                format!("probe-{}", DRIVER_NAME),
+                axconfig::TASK_STACK_SIZE,
+            );
+            probes.push((task, found));
+        }
+    });
+    probes
+}
+
+/// Waits for the probes, returns the devices found in the order of the
+/// drivers, whichever finished first.
+pub(crate) fn join_global_probes(probes: Vec<GlobalProbe>) -> impl Iterator<Item = AxDeviceEnum> {
+    probes.into_iter().filter_map(|(task, found)| {
+        task.join();
+        found.lock().take()
+    })
+}
@@ -2,9 +2,9 @@
 
 pub use axdriver_base::{BaseDriverOps, DevError, DevResult, DeviceType};
 
+#[cfg(feature = "net")]
+pub use {crate::net_ext::NetDriverExt, crate::structs::AxNetDevice, axdriver_net::NetDriverOps};
 #[cfg(feature = "block")]
 pub use {crate::structs::AxBlockDevice, axdriver_block::BlockDriverOps};
 #[cfg(feature = "display")]
 pub use {crate::structs::AxDisplayDevice, axdriver_display::DisplayDriverOps};
-#[cfg(feature = "net")]
-pub use {crate::structs::AxNetDevice, axdriver_net::NetDriverOps};
@@ -0,0 +1,282 @@
+//! Temperature sensors and the thermal throttling policy.
+//!
+//! Sensors implement [`ThermalSensor`] and are added with [`register_sensor`];
+//! [`init`], called at boot as a [driver init call](axhal::initcall), adds
+//! the ones of the platform:
+//!
+//! - the digital thermal sensor of Intel CPUs, on x86_64 (`coretemp`);
+//! - the AVS ring oscillator of the BCM2711, on the Raspberry Pi 4.
+//!
+//! [`update`] must be called periodically, e.g. every second from a
+//! background task. It reads the hottest sensor against the trip points
+//! ([`set_trip_points`]) and raises or lowers the throttling level step by
+//! step, capping the CPU frequency if a [`CpuFreqOps`] is registered. The
+//! caller applies the level to its background tasks, and shuts the system
+//! down in the [`ThermalState::Critical`] state.
+
+use alloc::{boxed::Box, vec::Vec};
+
+use kspin::SpinNoIrq;
+
+/// Level step of each [`update`] while above or below the passive trip point.
+const THROTTLE_STEP: u32 = 10;
+
+/// The maximum throttling level, in percent.
+pub const MAX_THROTTLE: u32 = 90;
+
+/// Throttling decreases below the passive trip point minus this, in
+/// millidegrees Celsius.
+const HYSTERESIS: i32 = 2_000;
+
+/// A temperature sensor.
+pub trait ThermalSensor: Send {
+    /// The name of the sensor.
+    fn name(&self) -> &str;
+
+    /// Returns the temperature in millidegrees Celsius, or [`None`] if no
+    /// valid reading is available.
+    fn read_millicelsius(&mut self) -> Option<i32>;
+}
+
+/// Controls the frequency of the CPUs, e.g. through SCMI or a clock driver.
+pub trait CpuFreqOps: Send + Sync {
+    /// The highest frequency, in kHz.
+    fn max_freq_khz(&self) -> u32;
+
+    /// Caps the frequency of all the CPUs, in kHz.
+    fn set_freq_limit_khz(&self, khz: u32);
+}
+
+/// The thermal state after an [`update`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThermalState {
+    /// Below the passive trip point, with no throttling left.
+    Normal,
+    /// Throttled by the given level, in percent.
+    Throttled(u32),
+    /// At or above the critical trip point: the system should shut down.
+    Critical,
+}
+
+/// The temperature read from a sensor, see [`temperatures`].
+#[derive(Debug, Clone)]
+pub struct SensorReading {
+    /// The name of the sensor.
+    pub name: alloc::string::String,
+    /// The temperature in millidegrees Celsius, if valid.
+    pub millicelsius: Option<i32>,
+}
+
+struct Thermal {
+    sensors: Vec<Box<dyn ThermalSensor>>,
+    cpufreq: Option<&'static dyn CpuFreqOps>,
+    /// Throttling starts above this temperature, in millidegrees Celsius.
+    passive: i32,
+    /// The system is shut down above this temperature.
+    critical: i32,
+    level: u32,
+}
+
+static THERMAL: SpinNoIrq<Thermal> = SpinNoIrq::new(Thermal {
+    sensors: Vec::new(),
+    cpufreq: None,
+    passive: 85_000,
+    critical: 100_000,
+    level: 0,
+});
+
+axhal::register_init!(axhal::initcall::priority::DRIVER, init);
+
+/// Adds the temperature sensors of the platform.
+pub fn init() {
+    #[cfg(target_arch = "x86_64")]
+    if let Some(sensor) = CoreTemp::probe() {
+        register_sensor(Box::new(sensor));
+    }
+    if axconfig::FAMILY == "aarch64-raspi" {
+        let base = axhal::mem::phys_to_virt(BCM2711_AVS_MONITOR_PADDR.into());
+        register_sensor(Box::new(unsafe { Bcm2711Thermal::new(base.as_usize()) }));
+    }
+}
+
+/// Adds a temperature sensor.
+pub fn register_sensor(sensor: Box<dyn ThermalSensor>) {
+    info!("thermal: registered sensor {:?}", sensor.name());
+    THERMAL.lock().sensors.push(sensor);
+}
+
+/// Sets the driver capping the CPU frequency when throttled.
+pub fn register_cpufreq(cpufreq: &'static dyn CpuFreqOps) {
+    THERMAL.lock().cpufreq = Some(cpufreq);
+}
+
+/// Sets the passive and critical trip points, in millidegrees Celsius.
+pub fn set_trip_points(passive: i32, critical: i32) {
+    let mut thermal = THERMAL.lock();
+    thermal.passive = passive;
+    thermal.critical = critical.max(passive);
+}
+
+/// Reads all the sensors.
+pub fn temperatures() -> Vec<SensorReading> {
+    let mut thermal = THERMAL.lock();
+    thermal
+        .sensors
+        .iter_mut()
+        .map(|sensor| SensorReading {
+            name: sensor.name().into(),
+            millicelsius: sensor.read_millicelsius(),
+        })
+        .collect()
+}
+
+/// Reads the sensors and updates the throttling level.
+pub fn update() -> ThermalState {
+    let mut thermal = THERMAL.lock();
+    let Some(temp) = thermal
+        .sensors
+        .iter_mut()
+        .filter_map(|sensor| sensor.read_millicelsius())
+        .max()
+    else {
+        return ThermalState::Normal;
+    };
+    if temp >= thermal.critical {
+        return ThermalState::Critical;
+    }
+
+    let old_level = thermal.level;
+    if temp >= thermal.passive {
+        thermal.level = (thermal.level + THROTTLE_STEP).min(MAX_THROTTLE);
+    } else if temp < thermal.passive - HYSTERESIS {
+        thermal.level = thermal.level.saturating_sub(THROTTLE_STEP);
+    }
+    if thermal.level != old_level {
+        debug!("thermal: {} m°C, throttling {}%", temp, thermal.level);
+        if let Some(cpufreq) = thermal.cpufreq {
+            let max = cpufreq.max_freq_khz() as u64;
+            cpufreq.set_freq_limit_khz((max * (100 - thermal.level) as u64 / 100) as u32);
+        }
+    }
+    match thermal.level {
+        0 => ThermalState::Normal,
+        level => ThermalState::Throttled(level),
+    }
+}
+
+/// The digital thermal sensor of Intel CPUs, read on the current CPU.
+///
+/// It reports the distance to the maximum junction temperature (TjMax), of
+/// the package if supported, else of the core.
+#[cfg(target_arch = "x86_64")]
+pub struct CoreTemp {
+    status_msr: u32,
+    tj_max: i32,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl CoreTemp {
+    const IA32_THERM_STATUS: u32 = 0x19c;
+    const IA32_PACKAGE_THERM_STATUS: u32 = 0x1b1;
+    const MSR_TEMPERATURE_TARGET: u32 = 0x1a2;
+
+    /// Returns the sensor if the CPU has one.
+    pub fn probe() -> Option<Self> {
+        use core::arch::x86_64::{__cpuid, __cpuid_count};
+        let vendor = unsafe { __cpuid(0) };
+        let is_intel =
+            (vendor.ebx, vendor.edx, vendor.ecx) == (0x756e_6547, 0x4965_6e69, 0x6c65_746e);
+        if !is_intel || vendor.eax < 6 {
+            return None;
+        }
+        // the digital thermal sensor, and the package thermal management
+        let power = unsafe { __cpuid_count(6, 0) }.eax;
+        if power & 1 == 0 {
+            return None;
+        }
+        let status_msr = if power & (1 << 6) != 0 {
+            Self::IA32_PACKAGE_THERM_STATUS
+        } else {
+            Self::IA32_THERM_STATUS
+        };
+        // TjMax is reported since Nehalem (family 6, model 0x1a)
+        let signature = unsafe { __cpuid(1) }.eax;
+        let model = ((signature >> 4) & 0xf) | ((signature >> 12) & 0xf0);
+        let family = (signature >> 8) & 0xf;
+        let tj_max = if family == 6 && model >= 0x1a {
+            match (unsafe { rdmsr(Self::MSR_TEMPERATURE_TARGET) } >> 16) & 0xff {
+                0 => 100,
+                t => t as i32,
+            }
+        } else {
+            100
+        };
+        Some(Self { status_msr, tj_max })
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn rdmsr(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    core::arch::asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high);
+    ((high as u64) << 32) | low as u64
+}
+
+#[cfg(target_arch = "x86_64")]
+impl ThermalSensor for CoreTemp {
+    fn name(&self) -> &str {
+        "coretemp"
+    }
+
+    fn read_millicelsius(&mut self) -> Option<i32> {
+        let status = unsafe { rdmsr(self.status_msr) };
+        // the reading valid bit only exists for the core status
+        if self.status_msr == Self::IA32_THERM_STATUS && status & (1 << 31) == 0 {
+            return None;
+        }
+        let below_tj_max = ((status >> 16) & 0x7f) as i32;
+        Some((self.tj_max - below_tj_max) * 1000)
+    }
+}
+
+/// The physical address of the AVS monitor of the BCM2711.
+const BCM2711_AVS_MONITOR_PADDR: usize = 0xfd5d_2000;
+
+/// The temperature sensor of the BCM2711 (Raspberry Pi 4), in its AVS
+/// monitor.
+pub struct Bcm2711Thermal {
+    base: usize,
+}
+
+impl Bcm2711Thermal {
+    const AVS_RO_TEMP_STATUS: usize = 0x200;
+    const VALID_MASK: u32 = (1 << 16) | (1 << 10);
+    const DATA_MASK: u32 = 0x3ff;
+    /// The linear conversion of the firmware: `slope * data + offset`.
+    const SLOPE: i32 = -487;
+    const OFFSET: i32 = 410_040;
+
+    /// Creates the driver of the AVS monitor mapped at `base`.
+    ///
+    /// # Safety
+    ///
+    /// `base` must be the virtual address of the AVS monitor registers.
+    pub const unsafe fn new(base: usize) -> Self {
+        Self { base }
+    }
+}
+
+impl ThermalSensor for Bcm2711Thermal {
+    fn name(&self) -> &str {
+        "bcm2711_thermal"
+    }
+
+    fn read_millicelsius(&mut self) -> Option<i32> {
+        let reg = (self.base + Self::AVS_RO_TEMP_STATUS) as *const u32;
+        let status = unsafe { reg.read_volatile() };
+        if status & Self::VALID_MASK == 0 {
+            return None;
+        }
+        Some(Self::SLOPE * (status & Self::DATA_MASK) as i32 + Self::OFFSET)
+    }
+}
@@ -40,6 +40,11 @@ cfg_if! {
                 Ok(AxDeviceEnum::from_net(Self::Device::try_new(transport)?))
             }
         }
+
+        impl crate::net_ext::NetDriverExt
+            for axdriver_virtio::VirtIoNetDev<VirtIoHalImpl, VirtIoTransport, 64>
+        {
+        }
     }
 }
 
@@ -0,0 +1,406 @@
+//! VirtIO socket (vsock) device driver.
+//!
+//! It only moves the packets between the guest and the host: the connections
+//! are handled above it (by `axnet`). Only stream sockets exist, and the
+//! received packets are polled with [`recv`].
+
+use alloc::{boxed::Box, vec::Vec};
+use core::ptr::{addr_of, NonNull};
+
+use axerrno::{ax_err, AxError, AxResult};
+use kspin::SpinNoIrq;
+use virtio_drivers::queue::VirtQueue;
+use virtio_drivers::transport::{DeviceStatus, DeviceType, Transport};
+
+use crate::virtio::VirtIoHalImpl;
+
+const PAGE_SIZE: usize = 0x1000;
+const QUEUE_SIZE: usize = 8;
+
+const RX_QUEUE: u16 = 0;
+const TX_QUEUE: u16 = 1;
+const EVENT_QUEUE: u16 = 2;
+
+const F_VERSION_1: u64 = 1 << 32;
+
+/// The size of the buffers given to the receive queue.
+const RX_BUF_SIZE: usize = 4096;
+
+/// The size of the header of a packet.
+pub const HEADER_SIZE: usize = 44;
+/// The maximum size of the payload of a received packet.
+pub const MAX_PAYLOAD: usize = RX_BUF_SIZE - HEADER_SIZE;
+
+/// The CID of the host.
+pub const VMADDR_CID_HOST: u64 = 2;
+
+const TYPE_STREAM: u16 = 1;
+const EVENT_TRANSPORT_RESET: u32 = 0;
+
+/// The operation of a packet.
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VsockOp {
+    /// Connection request.
+    Request = 1,
+    /// Connection accepted.
+    Response = 2,
+    /// Connection reset.
+    Rst = 3,
+    /// End of the connection in some directions, see [`VsockHeader::flags`].
+    Shutdown = 4,
+    /// Data.
+    Rw = 5,
+    /// The receive buffer of the sender, see [`VsockHeader::fwd_cnt`].
+    CreditUpdate = 6,
+    /// Asks for a [`VsockOp::CreditUpdate`].
+    CreditRequest = 7,
+}
+
+impl VsockOp {
+    fn from_u16(op: u16) -> Option<Self> {
+        Some(match op {
+            1 => Self::Request,
+            2 => Self::Response,
+            3 => Self::Rst,
+            4 => Self::Shutdown,
+            5 => Self::Rw,
+            6 => Self::CreditUpdate,
+            7 => Self::CreditRequest,
+            _ => return None,
+        })
+    }
+}
+
+/// The sender will receive no more data (in [`VsockHeader::flags`] of a
+/// [`VsockOp::Shutdown`]).
+pub const SHUTDOWN_RCV: u32 = 1 << 0;
+/// The sender will send no more data.
+pub const SHUTDOWN_SEND: u32 = 1 << 1;
+
+/// The header of a stream packet.
+#[derive(Debug, Clone, Copy)]
+pub struct VsockHeader {
+    /// Source CID, filled by the driver when sending.
+    pub src_cid: u64,
+    /// Destination CID.
+    pub dst_cid: u64,
+    /// Source port.
+    pub src_port: u32,
+    /// Destination port.
+    pub dst_port: u32,
+    /// Operation.
+    pub op: VsockOp,
+    /// Flags of the operation.
+    pub flags: u32,
+    /// Size of the receive buffer of the sender.
+    pub buf_alloc: u32,
+    /// Number of bytes the sender has consumed from its receive buffer.
+    pub fwd_cnt: u32,
+}
+
+impl VsockHeader {
+    fn to_bytes(self, len: usize) -> [u8; HEADER_SIZE] {
+        let mut buf = [0; HEADER_SIZE];
+        buf[0..8].copy_from_slice(&self.src_cid.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.dst_cid.to_le_bytes());
+        buf[16..20].copy_from_slice(&self.src_port.to_le_bytes());
+        buf[20..24].copy_from_slice(&self.dst_port.to_le_bytes());
+        buf[24..28].copy_from_slice(&(len as u32).to_le_bytes());
+        buf[28..30].copy_from_slice(&TYPE_STREAM.to_le_bytes());
+        buf[30..32].copy_from_slice(&(self.op as u16).to_le_bytes());
+        buf[32..36].copy_from_slice(&self.flags.to_le_bytes());
+        buf[36..40].copy_from_slice(&self.buf_alloc.to_le_bytes());
+        buf[40..44].copy_from_slice(&self.fwd_cnt.to_le_bytes());
+        buf
+    }
+
+    /// Parses a header, and returns it with the length of the payload.
+    fn from_bytes(buf: &[u8]) -> Option<(Self, usize)> {
+        let u32_at = |i: usize| u32::from_le_bytes(buf[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(buf[i..i + 8].try_into().unwrap());
+        let u16_at = |i: usize| u16::from_le_bytes(buf[i..i + 2].try_into().unwrap());
+        if buf.len() < HEADER_SIZE || u16_at(28) != TYPE_STREAM {
+            return None;
+        }
+        let hdr = Self {
+            src_cid: u64_at(0),
+            dst_cid: u64_at(8),
+            src_port: u32_at(16),
+            dst_port: u32_at(20),
+            op: VsockOp::from_u16(u16_at(30))?,
+            flags: u32_at(32),
+            buf_alloc: u32_at(36),
+            fwd_cnt: u32_at(40),
+        };
+        Some((hdr, u32_at(24) as usize))
+    }
+}
+
+/// The configuration space of a socket device.
+#[repr(C)]
+struct VsockConfig {
+    // the CID is 64-bit, but the configuration space is only 32-bit aligned
+    guest_cid_low: u32,
+    guest_cid_high: u32,
+}
+
+/// A VirtIO socket device.
+pub struct VirtIoVsock<T: Transport> {
+    transport: T,
+    rx_queue: VirtQueue<VirtIoHalImpl, QUEUE_SIZE>,
+    tx_queue: VirtQueue<VirtIoHalImpl, QUEUE_SIZE>,
+    event_queue: VirtQueue<VirtIoHalImpl, QUEUE_SIZE>,
+    /// The buffers of the receive queue, in the heap so that they stay in
+    /// place when the device is moved.
+    rx_bufs: Vec<Box<[u8; RX_BUF_SIZE]>>,
+    /// The index in `rx_bufs` of the buffer of each token.
+    rx_buf_of: [usize; QUEUE_SIZE],
+    event_bufs: Box<[[u8; 4]; QUEUE_SIZE]>,
+    event_buf_of: [usize; QUEUE_SIZE],
+    guest_cid: u64,
+}
+
+unsafe impl<T: Transport> Send for VirtIoVsock<T> {}
+
+impl<T: Transport> VirtIoVsock<T> {
+    /// Initializes the socket device on the given transport.
+    pub fn new(mut transport: T) -> Result<Self, virtio_drivers::Error> {
+        if transport.device_type() != DeviceType::Socket {
+            return Err(virtio_drivers::Error::Unsupported);
+        }
+        transport.set_status(DeviceStatus::empty());
+        transport.set_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER);
+        let features = transport.read_device_features() & F_VERSION_1;
+        transport.write_driver_features(features);
+        transport.set_status(
+            DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER | DeviceStatus::FEATURES_OK,
+        );
+        transport.set_guest_page_size(PAGE_SIZE as u32);
+
+        let config = transport.config_space::<VsockConfig>()?;
+        let rx_queue = VirtQueue::new(&mut transport, RX_QUEUE, false, false)?;
+        let tx_queue = VirtQueue::new(&mut transport, TX_QUEUE, false, false)?;
+        let event_queue = VirtQueue::new(&mut transport, EVENT_QUEUE, false, false)?;
+
+        let mut vsock = Self {
+            transport,
+            rx_queue,
+            tx_queue,
+            event_queue,
+            rx_bufs: (0..QUEUE_SIZE)
+                .map(|_| Box::new([0; RX_BUF_SIZE]))
+                .collect(),
+            rx_buf_of: [0; QUEUE_SIZE],
+            event_bufs: Box::new([[0; 4]; QUEUE_SIZE]),
+            event_buf_of: [0; QUEUE_SIZE],
+            guest_cid: read_cid(config),
+        };
+        for i in 0..QUEUE_SIZE {
+            vsock.give_rx_buf(i)?;
+            vsock.give_event_buf(i)?;
+        }
+        vsock.transport.finish_init();
+        vsock.transport.notify(RX_QUEUE);
+        vsock.transport.notify(EVENT_QUEUE);
+        Ok(vsock)
+    }
+
+    fn give_rx_buf(&mut self, index: usize) -> Result<(), virtio_drivers::Error> {
+        let buf = &mut self.rx_bufs[index][..];
+        let token = unsafe { self.rx_queue.add(&[], &mut [buf])? };
+        self.rx_buf_of[token as usize] = index;
+        Ok(())
+    }
+
+    fn give_event_buf(&mut self, index: usize) -> Result<(), virtio_drivers::Error> {
+        let buf = &mut self.event_bufs[index][..];
+        let token = unsafe { self.event_queue.add(&[], &mut [buf])? };
+        self.event_buf_of[token as usize] = index;
+        Ok(())
+    }
+
+    /// Returns the CID of the guest.
+    pub fn guest_cid(&self) -> u64 {
+        self.guest_cid
+    }
+
+    /// Sends a packet with the payload `data`, of at most [`MAX_PAYLOAD`]
+    /// bytes.
+    pub fn send(&mut self, hdr: &VsockHeader, data: &[u8]) -> Result<(), virtio_drivers::Error> {
+        let hdr = VsockHeader {
+            src_cid: self.guest_cid,
+            ..*hdr
+        };
+        let bytes = hdr.to_bytes(data.len());
+        self.tx_queue
+            .add_notify_wait_pop(&[&bytes, data], &mut [], &mut self.transport)?;
+        Ok(())
+    }
+
+    /// Receives a packet, copying its payload to `buf`.
+    ///
+    /// Returns the header and the size of the payload, or `None` if no
+    /// packet is received.
+    pub fn recv(
+        &mut self,
+        buf: &mut [u8],
+    ) -> Result<Option<(VsockHeader, usize)>, virtio_drivers::Error> {
+        self.transport.ack_interrupt();
+        loop {
+            let Some(token) = self.rx_queue.peek_used() else {
+                return Ok(None);
+            };
+            let index = self.rx_buf_of[token as usize];
+            let rx_buf = &mut self.rx_bufs[index][..];
+            let len = unsafe { self.rx_queue.pop_used(token, &[], &mut [rx_buf])? } as usize;
+            let packet = VsockHeader::from_bytes(&self.rx_bufs[index][..len]).map(|(hdr, size)| {
+                let size = size.min(len - HEADER_SIZE).min(buf.len());
+                buf[..size].copy_from_slice(&self.rx_bufs[index][HEADER_SIZE..HEADER_SIZE + size]);
+                (hdr, size)
+            });
+            self.give_rx_buf(index)?;
+            if self.rx_queue.should_notify() {
+                self.transport.notify(RX_QUEUE);
+            }
+            match packet {
+                Some(packet) => return Ok(Some(packet)),
+                None => warn!("virtio-vsock: dropped a malformed packet"),
+            }
+        }
+    }
+
+    /// Handles the events of the device, and returns whether the transport
+    /// has been reset (e.g. after a live migration), which closes all the
+    /// connections and may change the CID of the guest.
+    pub fn handle_events(&mut self) -> Result<bool, virtio_drivers::Error> {
+        let mut reset = false;
+        while let Some(token) = self.event_queue.peek_used() {
+            let index = self.event_buf_of[token as usize];
+            let event_buf = &mut self.event_bufs[index][..];
+            unsafe { self.event_queue.pop_used(token, &[], &mut [event_buf])? };
+            if u32::from_le_bytes(self.event_bufs[index]) == EVENT_TRANSPORT_RESET {
+                reset = true;
+            }
+            self.give_event_buf(index)?;
+        }
+        if self.event_queue.should_notify() {
+            self.transport.notify(EVENT_QUEUE);
+        }
+        if reset {
+            self.guest_cid = read_cid(self.transport.config_space::<VsockConfig>()?);
+        }
+        Ok(reset)
+    }
+}
+
+fn read_cid(config: NonNull<VsockConfig>) -> u64 {
+    let config = config.as_ptr();
+    let low = unsafe { addr_of!((*config).guest_cid_low).read_volatile() };
+    let high = unsafe { addr_of!((*config).guest_cid_high).read_volatile() };
+    u32::from_le(low) as u64 | (u32::from_le(high) as u64) << 32
+}
+
+#[cfg(bus = "pci")]
+type VsockTransport = axdriver_virtio::PciTransport;
+#[cfg(bus = "mmio")]
+type VsockTransport = axdriver_virtio::MmioTransport;
+
+static VSOCK: SpinNoIrq<Option<VirtIoVsock<VsockTransport>>> = SpinNoIrq::new(None);
+
+fn register(transport: VsockTransport) -> bool {
+    match VirtIoVsock::new(transport) {
+        Ok(vsock) => {
+            info!("virtio-vsock: guest CID {}", vsock.guest_cid());
+            *VSOCK.lock() = Some(vsock);
+            true
+        }
+        Err(e) => {
+            warn!("failed to initialize virtio-vsock: {:?}", e);
+            false
+        }
+    }
+}
+
+/// Probes a socket device at the MMIO region, and returns whether one is
+/// found.
+#[cfg(bus = "mmio")]
+pub(crate) fn probe_mmio(mmio_base: usize, _mmio_size: usize) -> bool {
+    use axhal::mem::phys_to_virt;
+    use virtio_drivers::transport::mmio::VirtIOHeader;
+
+    let header = phys_to_virt(mmio_base.into()).as_mut_ptr() as *mut VirtIOHeader;
+    let Some(header) = NonNull::new(header) else {
+        return false;
+    };
+    match unsafe { VsockTransport::new(header) } {
+        Ok(transport) if transport.device_type() == DeviceType::Socket => register(transport),
+        _ => false,
+    }
+}
+
+/// Probes a socket device at the PCI function, and returns whether one is
+/// found.
+#[cfg(bus = "pci")]
+pub(crate) fn probe_pci(
+    root: &mut axdriver_pci::PciRoot,
+    bdf: axdriver_pci::DeviceFunction,
+    dev_info: &axdriver_pci::DeviceFunctionInfo,
+) -> bool {
+    if dev_info.vendor_id != 0x1af4 || dev_info.device_id != 0x1053 {
+        return false;
+    }
+    match VsockTransport::new::<VirtIoHalImpl>(root, bdf) {
+        Ok(transport) => register(transport),
+        Err(e) => {
+            warn!("failed to probe virtio-vsock at {}: {:?}", bdf, e);
+            false
+        }
+    }
+}
+
+/// Returns the CID of the guest, or `None` if there is no socket device.
+pub fn guest_cid() -> Option<u64> {
+    VSOCK.lock().as_ref().map(VirtIoVsock::guest_cid)
+}
+
+/// Sends a packet with the payload `data`, of at most [`MAX_PAYLOAD`] bytes.
+pub fn send(hdr: &VsockHeader, data: &[u8]) -> AxResult {
+    let mut vsock = VSOCK.lock();
+    let Some(vsock) = vsock.as_mut() else {
+        return ax_err!(NotFound, "no virtio-vsock device");
+    };
+    vsock.send(hdr, data).map_err(|e| {
+        warn!("virtio-vsock: failed to send: {:?}", e);
+        AxError::Io
+    })
+}
+
+/// Receives a packet, copying its payload (of at most [`MAX_PAYLOAD`] bytes)
+/// to `buf`.
+///
+/// Returns the header and the size of the payload, or `None` if no packet is
+/// received.
+pub fn recv(buf: &mut [u8]) -> Option<(VsockHeader, usize)> {
+    let mut vsock = VSOCK.lock();
+    match vsock.as_mut()?.recv(buf) {
+        Ok(packet) => packet,
+        Err(e) => {
+            warn!("virtio-vsock: failed to receive: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Returns whether the transport has been reset since the last call, which
+/// closes all the connections.
+pub fn take_reset() -> bool {
+    let mut vsock = VSOCK.lock();
+    let Some(vsock) = vsock.as_mut() else {
+        return false;
+    };
+    vsock.handle_events().unwrap_or_else(|e| {
+        warn!("virtio-vsock: failed to handle the events: {:?}", e);
+        false
+    })
+}
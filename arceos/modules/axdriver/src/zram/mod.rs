@@ -0,0 +1,261 @@
+//! A RAM-backed block device with transparent LZ4 compression (zram).
+//!
+//! The disk is divided into 4 KiB pages. Each written page is compressed and
+//! stored in its own heap buffer; pages filled with a single byte value (most
+//! commonly zeros) only record that value, and pages that do not compress
+//! well are stored as-is. Pages that have never been written read as zeros
+//! and consume no memory.
+//!
+//! The most recently accessed page is kept decompressed in a one-page cache,
+//! so that consecutive 512-byte block accesses within the same page only pay
+//! for one decompression and one compression.
+
+use alloc::{boxed::Box, vec, vec::Vec};
+
+use axcompress::lz4;
+use axdriver_base::{BaseDriverOps, DevError, DevResult, DeviceType};
+use axdriver_block::BlockDriverOps;
+
+const BLOCK_SIZE: usize = 512;
+const PAGE_SIZE: usize = 0x1000;
+const BLOCKS_PER_PAGE: usize = PAGE_SIZE / BLOCK_SIZE;
+
+/// Pages whose compressed size exceeds this are stored uncompressed.
+const MAX_COMPRESSED_SIZE: usize = PAGE_SIZE / 4 * 3;
+
+enum ZramPage {
+    /// Every byte of the page has the same value.
+    Same(u8),
+    /// LZ4 compressed page data.
+    Compressed(Box<[u8]>),
+    /// The page is incompressible and is stored as-is.
+    Huge(Box<[u8]>),
+}
+
+impl ZramPage {
+    fn stored_size(&self) -> usize {
+        match self {
+            Self::Same(_) => 0,
+            Self::Compressed(data) | Self::Huge(data) => data.len(),
+        }
+    }
+}
+
+/// Statistics of a [`ZramDevice`], similar to Linux's `mm_stat`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ZramStats {
+    /// Uncompressed size of all stored pages, in bytes.
+    pub orig_data_size: usize,
+    /// Compressed size of all stored pages, in bytes.
+    pub compr_data_size: usize,
+    /// Number of pages filled with a single byte value.
+    pub same_pages: usize,
+    /// Number of pages stored uncompressed.
+    pub huge_pages: usize,
+}
+
+impl ZramStats {
+    fn account(&mut self, page: &ZramPage, add: bool) {
+        let (orig, compr) = (PAGE_SIZE, page.stored_size());
+        let (same, huge) = match page {
+            ZramPage::Same(_) => (1, 0),
+            ZramPage::Compressed(_) => (0, 0),
+            ZramPage::Huge(_) => (0, 1),
+        };
+        if add {
+            self.orig_data_size += orig;
+            self.compr_data_size += compr;
+            self.same_pages += same;
+            self.huge_pages += huge;
+        } else {
+            self.orig_data_size -= orig;
+            self.compr_data_size -= compr;
+            self.same_pages -= same;
+            self.huge_pages -= huge;
+        }
+    }
+}
+
+struct PageCache {
+    page_id: usize,
+    dirty: bool,
+    data: Box<[u8]>,
+}
+
+/// A compressed RAM disk.
+pub struct ZramDevice {
+    pages: Vec<Option<ZramPage>>,
+    cache: Option<PageCache>,
+    scratch: Box<[u8]>,
+    stats: ZramStats,
+    mem_limit: usize,
+}
+
+impl ZramDevice {
+    /// Creates a new compressed RAM disk with the given size in bytes.
+    ///
+    /// The size is rounded up to a multiple of 4 KiB. No memory is used for
+    /// the disk contents until pages are written.
+    pub fn new(disk_size: usize) -> Self {
+        let num_pages = disk_size.div_ceil(PAGE_SIZE);
+        let mut pages = Vec::with_capacity(num_pages);
+        pages.resize_with(num_pages, || None);
+        Self {
+            pages,
+            cache: None,
+            scratch: vec![0; MAX_COMPRESSED_SIZE].into_boxed_slice(),
+            stats: ZramStats::default(),
+            mem_limit: 0,
+        }
+    }
+
+    /// Limits the total memory used to store (compressed) pages, in bytes.
+    ///
+    /// Writes that would exceed the limit fail with [`DevError::NoMemory`].
+    /// Zero means no limit, which is the default.
+    pub fn set_mem_limit(&mut self, limit: usize) {
+        self.mem_limit = limit;
+    }
+
+    /// Returns the current statistics of the device.
+    pub fn stats(&self) -> ZramStats {
+        self.stats
+    }
+
+    fn read_page(&self, page_id: usize, buf: &mut [u8]) -> DevResult {
+        match &self.pages[page_id] {
+            None => buf.fill(0),
+            Some(ZramPage::Same(byte)) => buf.fill(*byte),
+            Some(ZramPage::Huge(data)) => buf.copy_from_slice(data),
+            Some(ZramPage::Compressed(data)) => {
+                if lz4::decompress_block(data, buf) != Some(PAGE_SIZE) {
+                    error!("zram: page {} is corrupted", page_id);
+                    return Err(DevError::Io);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_page(&mut self, page_id: usize, data: &[u8]) -> DevResult {
+        let page = if data.iter().all(|&b| b == data[0]) {
+            ZramPage::Same(data[0])
+        } else if let Some(len) = lz4::compress_block(data, &mut self.scratch) {
+            ZramPage::Compressed(self.scratch[..len].into())
+        } else {
+            ZramPage::Huge(data.into())
+        };
+
+        let old_size = self.pages[page_id]
+            .as_ref()
+            .map_or(0, ZramPage::stored_size);
+        if self.mem_limit != 0
+            && self.stats.compr_data_size - old_size + page.stored_size() > self.mem_limit
+        {
+            warn!("zram: memory limit {:#x} exceeded", self.mem_limit);
+            return Err(DevError::NoMemory);
+        }
+
+        if let Some(old) = self.pages[page_id].take() {
+            self.stats.account(&old, false);
+        }
+        self.stats.account(&page, true);
+        self.pages[page_id] = Some(page);
+        Ok(())
+    }
+
+    fn write_back(&mut self) -> DevResult {
+        if let Some(mut cache) = self.cache.take() {
+            let res = if cache.dirty {
+                self.write_page(cache.page_id, &cache.data)
+            } else {
+                Ok(())
+            };
+            cache.dirty = res.is_err();
+            self.cache = Some(cache);
+            res
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Loads the page into the cache, writing back the previously cached
+    /// page if it was modified.
+    fn cached_page(&mut self, page_id: usize) -> DevResult<&mut PageCache> {
+        if self.cache.as_ref().map_or(true, |c| c.page_id != page_id) {
+            self.write_back()?;
+            let mut cache = self.cache.take().unwrap_or_else(|| PageCache {
+                page_id,
+                dirty: false,
+                data: vec![0; PAGE_SIZE].into_boxed_slice(),
+            });
+            self.read_page(page_id, &mut cache.data)?;
+            cache.page_id = page_id;
+            cache.dirty = false;
+            self.cache = Some(cache);
+        }
+        Ok(self.cache.as_mut().unwrap())
+    }
+
+    fn check_range(&self, block_id: u64, buf_len: usize) -> DevResult {
+        if buf_len % BLOCK_SIZE != 0 {
+            return Err(DevError::InvalidParam);
+        }
+        let end = block_id
+            .checked_add((buf_len / BLOCK_SIZE) as u64)
+            .ok_or(DevError::InvalidParam)?;
+        if end > self.num_blocks() {
+            return Err(DevError::Io);
+        }
+        Ok(())
+    }
+}
+
+impl BaseDriverOps for ZramDevice {
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+
+    fn device_name(&self) -> &str {
+        "zram"
+    }
+}
+
+impl BlockDriverOps for ZramDevice {
+    #[inline]
+    fn num_blocks(&self) -> u64 {
+        (self.pages.len() * BLOCKS_PER_PAGE) as u64
+    }
+
+    #[inline]
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        self.check_range(block_id, buf.len())?;
+        for (i, chunk) in buf.chunks_exact_mut(BLOCK_SIZE).enumerate() {
+            let block_id = block_id as usize + i;
+            let offset = (block_id % BLOCKS_PER_PAGE) * BLOCK_SIZE;
+            let cache = self.cached_page(block_id / BLOCKS_PER_PAGE)?;
+            chunk.copy_from_slice(&cache.data[offset..offset + BLOCK_SIZE]);
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
+        self.check_range(block_id, buf.len())?;
+        for (i, chunk) in buf.chunks_exact(BLOCK_SIZE).enumerate() {
+            let block_id = block_id as usize + i;
+            let offset = (block_id % BLOCKS_PER_PAGE) * BLOCK_SIZE;
+            let cache = self.cached_page(block_id / BLOCKS_PER_PAGE)?;
+            cache.data[offset..offset + BLOCK_SIZE].copy_from_slice(chunk);
+            cache.dirty = true;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> DevResult {
+        self.write_back()
+    }
+}
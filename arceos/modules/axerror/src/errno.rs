@@ -0,0 +1,56 @@
+//! The mapping between the error kinds and the POSIX errno values.
+
+use axerrno::{AxError, LinuxError};
+
+/// The errno of each error kind, and the kind of each errno returned by the
+/// modules. It is meant to be the only mapping between them: the syscall
+/// layer (`arceos_posix_api`, under `axlibc`) converts the errors of the
+/// modules with [`ToErrno`](crate::ToErrno).
+///
+/// A kind has the errno of its first entry, and an errno the kind of its
+/// first entry. It differs from the `From` conversion of `axerrno` for
+/// `Unsupported`, which is `EOPNOTSUPP` rather than `ENOSYS`, as `ENOSYS`
+/// means that the syscall is not implemented.
+const ERRNO_TABLE: &[(AxError, LinuxError)] = &[
+    (AxError::AddrInUse, LinuxError::EADDRINUSE),
+    (AxError::AlreadyExists, LinuxError::EEXIST),
+    (AxError::BadAddress, LinuxError::EFAULT),
+    (AxError::BadState, LinuxError::EINVAL),
+    (AxError::ConnectionRefused, LinuxError::ECONNREFUSED),
+    (AxError::ConnectionReset, LinuxError::ECONNRESET),
+    (AxError::DirectoryNotEmpty, LinuxError::ENOTEMPTY),
+    (AxError::InvalidInput, LinuxError::EINVAL),
+    (AxError::InvalidData, LinuxError::EINVAL),
+    (AxError::Io, LinuxError::EIO),
+    (AxError::IsADirectory, LinuxError::EISDIR),
+    (AxError::NoMemory, LinuxError::ENOMEM),
+    (AxError::NotADirectory, LinuxError::ENOTDIR),
+    (AxError::NotConnected, LinuxError::ENOTCONN),
+    (AxError::NotFound, LinuxError::ENOENT),
+    (AxError::PermissionDenied, LinuxError::EACCES),
+    (AxError::PermissionDenied, LinuxError::EPERM),
+    (AxError::ResourceBusy, LinuxError::EBUSY),
+    (AxError::StorageFull, LinuxError::ENOSPC),
+    (AxError::TimedOut, LinuxError::ETIMEDOUT),
+    (AxError::UnexpectedEof, LinuxError::EIO),
+    (AxError::Unsupported, LinuxError::EOPNOTSUPP),
+    (AxError::Unsupported, LinuxError::ENOSYS),
+    (AxError::WouldBlock, LinuxError::EAGAIN),
+    (AxError::WriteZero, LinuxError::EIO),
+];
+
+/// Returns the errno of the error kind `kind`, `EIO` if it has none.
+pub fn errno(kind: AxError) -> LinuxError {
+    ERRNO_TABLE
+        .iter()
+        .find(|(k, _)| *k == kind)
+        .map_or(LinuxError::EIO, |&(_, errno)| errno)
+}
+
+/// Returns the error kind of the errno `errno`, `Io` if it has none.
+pub fn kind(errno: LinuxError) -> AxError {
+    ERRNO_TABLE
+        .iter()
+        .find(|(_, e)| *e == errno)
+        .map_or(AxError::Io, |&(kind, _)| kind)
+}
@@ -0,0 +1,180 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) errors with context, and
+//! their mapping to errno.
+//!
+//! An [`Error`] is an error kind of `axerrno` ([`AxError`]), or the exact
+//! errno given by a lower layer ([`LinuxError`]), with the contexts it went
+//! through, added by [`Context::context`]:
+//!
+//! ```
+//! use axerror::{AxError, Context};
+//!
+//! fn read_config() -> axerror::Result<u32> {
+//!     Err(AxError::NotFound).context("cannot open /etc/app.conf")
+//! }
+//!
+//! let err = read_config().context("cannot load the config").unwrap_err();
+//! assert_eq!(err.kind(), AxError::NotFound);
+//! assert!(format!("{}", err).starts_with("cannot load the config: cannot open /etc/app.conf: "));
+//! ```
+//!
+//! The errno of the errors is given by one table, see [`errno`] and [`kind`].
+//! An error built from an errno keeps it, so that it is not converted back
+//! and forth with the error kinds.
+
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(test)]
+mod tests;
+
+mod errno;
+
+use core::fmt;
+
+pub use axerrno::{AxError, AxResult, LinuxError, LinuxResult};
+
+pub use self::errno::{errno, kind};
+
+/// The maximum number of contexts kept by an [`Error`], the outer ones are
+/// dropped.
+pub const MAX_CONTEXTS: usize = 4;
+
+/// A [`Result`](core::result::Result) with an [`Error`].
+pub type Result<T = ()> = core::result::Result<T, Error>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Repr {
+    Kind(AxError),
+    Errno(LinuxError),
+}
+
+/// An error, with the contexts it went through.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Error {
+    repr: Repr,
+    contexts: [&'static str; MAX_CONTEXTS],
+    num_contexts: usize,
+}
+
+impl Error {
+    const fn with_repr(repr: Repr) -> Self {
+        Self {
+            repr,
+            contexts: [""; MAX_CONTEXTS],
+            num_contexts: 0,
+        }
+    }
+
+    /// Creates an error of the kind `kind`.
+    pub const fn new(kind: AxError) -> Self {
+        Self::with_repr(Repr::Kind(kind))
+    }
+
+    /// Creates an error with the errno `errno`, which [`Error::errno`]
+    /// returns as is.
+    pub const fn from_errno(errno: LinuxError) -> Self {
+        Self::with_repr(Repr::Errno(errno))
+    }
+
+    /// Returns the kind of the error.
+    pub fn kind(&self) -> AxError {
+        match self.repr {
+            Repr::Kind(kind) => kind,
+            Repr::Errno(errno) => kind(errno),
+        }
+    }
+
+    /// Returns the errno of the error.
+    pub fn errno(&self) -> LinuxError {
+        match self.repr {
+            Repr::Kind(kind) => errno(kind),
+            Repr::Errno(errno) => errno,
+        }
+    }
+
+    /// Adds the context `context` to the error.
+    pub fn context(mut self, context: &'static str) -> Self {
+        if self.num_contexts < MAX_CONTEXTS {
+            self.contexts[self.num_contexts] = context;
+            self.num_contexts += 1;
+        }
+        self
+    }
+
+    /// Returns the contexts of the error, from the outermost one.
+    pub fn contexts(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.contexts[..self.num_contexts].iter().rev().copied()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for context in self.contexts() {
+            write!(f, "{}: ", context)?;
+        }
+        match self.repr {
+            Repr::Kind(kind) => write!(f, "{}", kind.as_str()),
+            Repr::Errno(errno) => write!(f, "{}", errno.as_str()),
+        }
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut s = f.debug_struct("Error");
+        match self.repr {
+            Repr::Kind(kind) => s.field("kind", &kind),
+            Repr::Errno(errno) => s.field("errno", &errno),
+        };
+        s.field("contexts", &&self.contexts[..self.num_contexts])
+            .finish()
+    }
+}
+
+impl From<AxError> for Error {
+    fn from(kind: AxError) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl From<LinuxError> for Error {
+    fn from(errno: LinuxError) -> Self {
+        Self::from_errno(errno)
+    }
+}
+
+impl From<Error> for AxError {
+    fn from(err: Error) -> Self {
+        err.kind()
+    }
+}
+
+impl From<Error> for LinuxError {
+    fn from(err: Error) -> Self {
+        err.errno()
+    }
+}
+
+/// Adds a context to the errors of results.
+pub trait Context<T> {
+    /// Converts the error to an [`Error`], with the context `context`.
+    fn context(self, context: &'static str) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> Context<T> for core::result::Result<T, E> {
+    fn context(self, context: &'static str) -> Result<T> {
+        self.map_err(|err| err.into().context(context))
+    }
+}
+
+/// Converts the errors of the modules to errno, through the table of
+/// [`errno`] rather than the `From` conversions of `axerrno`.
+pub trait ToErrno<T> {
+    /// Converts the error to its errno.
+    fn to_errno(self) -> LinuxResult<T>;
+}
+
+impl<T, E: Into<Error>> ToErrno<T> for core::result::Result<T, E> {
+    fn to_errno(self) -> LinuxResult<T> {
+        self.map_err(|err| err.into().errno())
+    }
+}
@@ -0,0 +1,42 @@
+use crate::{errno, kind, AxError, Context, Error, LinuxError, ToErrno, MAX_CONTEXTS};
+
+#[test]
+fn test_errno_table() {
+    assert_eq!(errno(AxError::NotFound), LinuxError::ENOENT);
+    assert_eq!(errno(AxError::Unsupported), LinuxError::EOPNOTSUPP);
+    assert_eq!(errno(AxError::PermissionDenied), LinuxError::EACCES);
+    // several errno of a kind
+    assert_eq!(kind(LinuxError::ENOSYS), AxError::Unsupported);
+    assert_eq!(kind(LinuxError::EPERM), AxError::PermissionDenied);
+    assert_eq!(kind(LinuxError::E2BIG), AxError::Io);
+
+    // the errno is kept as is
+    let err = Error::from_errno(LinuxError::EPERM);
+    assert_eq!(err.errno(), LinuxError::EPERM);
+    assert_eq!(err.kind(), AxError::PermissionDenied);
+    let res: Result<(), AxError> = Err(AxError::WouldBlock);
+    assert_eq!(res.to_errno(), Err(LinuxError::EAGAIN));
+}
+
+#[test]
+fn test_context() {
+    let res: Result<(), AxError> = Err(AxError::NotFound);
+    let err = res
+        .context("cannot open the file")
+        .context("cannot load the config")
+        .unwrap_err();
+    assert_eq!(err.kind(), AxError::NotFound);
+    assert_eq!(
+        err.contexts().collect::<Vec<_>>(),
+        ["cannot load the config", "cannot open the file"]
+    );
+    let msg = format!("{}", err);
+    assert!(msg.starts_with("cannot load the config: cannot open the file: "));
+
+    // the outer ones are dropped
+    let mut err = Error::new(AxError::Io);
+    for _ in 0..MAX_CONTEXTS + 1 {
+        err = err.context("outer");
+    }
+    assert_eq!(err.contexts().count(), MAX_CONTEXTS);
+}
@@ -0,0 +1,180 @@
+//! COBS (Consistent Overhead Byte Stuffing).
+//!
+//! The data is cut at its zero bytes into blocks, each sent after a code: one
+//! plus the length of the block. A block of 254 bytes (code `0xff`) is not
+//! followed by a zero. The encoded frame has no zero byte, and ends with
+//! [`DELIMITER`].
+
+use crate::{FrameError, Writer};
+
+/// The end of a frame.
+pub const DELIMITER: u8 = 0;
+
+/// The code of the longest block, not followed by a zero.
+const MAX_CODE: u8 = 0xff;
+
+/// Returns the largest size of the frame of `len` bytes of data, with its
+/// delimiter.
+pub const fn max_encoded_len(len: usize) -> usize {
+    len + len / 254 + 2
+}
+
+/// Encodes `data` as a frame in `out`, with its delimiter, and returns the
+/// size of the frame, or [`None`] if `out` is too small (see
+/// [`max_encoded_len`]).
+pub fn encode(data: &[u8], out: &mut [u8]) -> Option<usize> {
+    let mut w = Writer::new(out);
+    // the position of the code of the current block
+    let mut code_pos = Some(w.push(0)?);
+    let mut code = 1;
+    for (i, &byte) in data.iter().enumerate() {
+        if byte != 0 {
+            w.push(byte)?;
+            code += 1;
+        }
+        if byte == 0 || code == MAX_CODE {
+            w.buf[code_pos.take()?] = code;
+            code = 1;
+            // no empty block after a full one at the end
+            if byte == 0 || i + 1 < data.len() {
+                code_pos = Some(w.push(0)?);
+            }
+        }
+    }
+    if let Some(pos) = code_pos {
+        w.buf[pos] = code;
+    }
+    w.push(DELIMITER)?;
+    Some(w.len)
+}
+
+/// Decodes a frame without its delimiter into `out`, and returns the size of
+/// the data.
+pub fn decode(frame: &[u8], out: &mut [u8]) -> Result<usize, FrameError> {
+    let mut decoder = Block::default();
+    let mut len = 0;
+    for &byte in frame {
+        let (zero, data) = decoder.push(byte)?;
+        for byte in [zero.then_some(0), data].into_iter().flatten() {
+            *out.get_mut(len).ok_or(FrameError::TooLong)? = byte;
+            len += 1;
+        }
+    }
+    decoder.finish()?;
+    Ok(len)
+}
+
+/// The state of the decoding of the blocks of a frame.
+#[derive(Default)]
+struct Block {
+    /// The code of the current block, 0 before the first one.
+    code: u8,
+    /// The bytes of the current block still to receive.
+    remaining: u8,
+}
+
+impl Block {
+    /// Decodes a byte of the frame (not the delimiter), and returns whether a
+    /// zero comes first in the data, and the byte of data it is, if any.
+    fn push(&mut self, byte: u8) -> Result<(bool, Option<u8>), FrameError> {
+        if byte == DELIMITER {
+            return Err(FrameError::Malformed);
+        }
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            return Ok((false, Some(byte)));
+        }
+        // a new block, after the zero ending the previous one
+        let zero = self.code != 0 && self.code != MAX_CODE;
+        self.code = byte;
+        self.remaining = byte - 1;
+        Ok((zero, None))
+    }
+
+    /// Checks that the frame does not end in the middle of a block.
+    fn finish(&self) -> Result<(), FrameError> {
+        match self.remaining {
+            0 => Ok(()),
+            _ => Err(FrameError::Malformed),
+        }
+    }
+}
+
+/// A COBS decoder, for frames of at most `N` bytes of data.
+pub struct CobsDecoder<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    block: Block,
+    error: Option<FrameError>,
+}
+
+impl<const N: usize> CobsDecoder<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            block: Block {
+                code: 0,
+                remaining: 0,
+            },
+            error: None,
+        }
+    }
+
+    /// Decodes a received byte, and returns the frame it ends, if any.
+    ///
+    /// A frame with an error is returned as the error, once it ends; the next
+    /// frame is decoded normally. Empty frames (two delimiters in a row) are
+    /// skipped.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&[u8], FrameError>> {
+        if byte == DELIMITER {
+            let (len, started) = (self.len, self.block.code != 0);
+            let result = self.error.take().map(Err).or_else(|| {
+                let finished = self.block.finish();
+                started.then_some(finished)
+            });
+            self.len = 0;
+            self.block = Block::default();
+            return match result? {
+                Ok(()) => Some(Ok(&self.buf[..len])),
+                Err(error) => Some(Err(error)),
+            };
+        }
+        if self.error.is_some() {
+            return None;
+        }
+        let (zero, data) = match self.block.push(byte) {
+            Ok(decoded) => decoded,
+            Err(error) => {
+                self.error = Some(error);
+                return None;
+            }
+        };
+        for byte in [zero.then_some(0), data].into_iter().flatten() {
+            match self.buf.get_mut(self.len) {
+                Some(slot) => {
+                    *slot = byte;
+                    self.len += 1;
+                }
+                None => {
+                    self.error = Some(FrameError::TooLong);
+                    break;
+                }
+            }
+        }
+        None
+    }
+
+    /// Forgets the frame being received.
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.block = Block::default();
+        self.error = None;
+    }
+}
+
+impl<const N: usize> Default for CobsDecoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
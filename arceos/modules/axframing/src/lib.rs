@@ -0,0 +1,51 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) framing of packets on byte
+//! streams, e.g. the data of a UART.
+//!
+//! It provides:
+//!
+//! - SLIP ([`slip`], RFC 1055): the frames end with an `END` byte, and the
+//!   `END` and `ESC` bytes in them are escaped.
+//! - COBS ([`cobs`], Consistent Overhead Byte Stuffing): the frames have no
+//!   zero byte, and end with one. The overhead is at most one byte in 254.
+//!
+//! Both encode into a given buffer, and decode with a decoder fed byte by
+//! byte as they are received, which holds the frame in a buffer of a fixed
+//! size. Nothing is allocated, so they fit the receive path of a driver.
+
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(test)]
+mod tests;
+
+pub mod cobs;
+pub mod slip;
+
+/// The errors of decoding a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// The frame does not fit in the buffer of the decoder.
+    TooLong,
+    /// The frame is malformed: an invalid escape (SLIP), or a block cut short
+    /// (COBS).
+    Malformed,
+}
+
+/// Writes bytes into a buffer, for the encoders.
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    /// Appends a byte, and returns its position, or [`None`] if the buffer is
+    /// full.
+    fn push(&mut self, byte: u8) -> Option<usize> {
+        *self.buf.get_mut(self.len)? = byte;
+        self.len += 1;
+        Some(self.len - 1)
+    }
+}
@@ -0,0 +1,120 @@
+//! SLIP (RFC 1055).
+//!
+//! A frame ends with [`END`]. The `END` and [`ESC`] bytes of the data are
+//! sent as `ESC ESC_END` and `ESC ESC_ESC`. The encoder also starts the
+//! frames with `END`, as the RFC suggests, to flush the noise of the line,
+//! and the decoder skips the empty frames it makes.
+
+use crate::{FrameError, Writer};
+
+/// The end of a frame.
+pub const END: u8 = 0xc0;
+/// The escape of `END` and `ESC`.
+pub const ESC: u8 = 0xdb;
+/// `END` after an `ESC`.
+pub const ESC_END: u8 = 0xdc;
+/// `ESC` after an `ESC`.
+pub const ESC_ESC: u8 = 0xdd;
+
+/// Returns the largest size of the frame of `len` bytes of data.
+pub const fn max_encoded_len(len: usize) -> usize {
+    2 * len + 2
+}
+
+/// Encodes `data` as a frame in `out`, and returns the size of the frame,
+/// or [`None`] if `out` is too small (see [`max_encoded_len`]).
+pub fn encode(data: &[u8], out: &mut [u8]) -> Option<usize> {
+    let mut w = Writer::new(out);
+    w.push(END)?;
+    for &byte in data {
+        match byte {
+            END => {
+                w.push(ESC)?;
+                w.push(ESC_END)?;
+            }
+            ESC => {
+                w.push(ESC)?;
+                w.push(ESC_ESC)?;
+            }
+            _ => {
+                w.push(byte)?;
+            }
+        }
+    }
+    w.push(END)?;
+    Some(w.len)
+}
+
+/// A SLIP decoder, for frames of at most `N` bytes of data.
+pub struct SlipDecoder<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    escaped: bool,
+    error: Option<FrameError>,
+}
+
+impl<const N: usize> SlipDecoder<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            escaped: false,
+            error: None,
+        }
+    }
+
+    /// Decodes a received byte, and returns the frame it ends, if any.
+    ///
+    /// A frame with an error is returned as the error, once it ends; the next
+    /// frame is decoded normally.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&[u8], FrameError>> {
+        let byte = match (self.escaped, byte) {
+            (false, END) => {
+                let (len, error) = (self.len, self.error.take());
+                let escaped = core::mem::replace(&mut self.escaped, false);
+                self.len = 0;
+                return match error {
+                    Some(error) => Some(Err(error)),
+                    None if escaped => Some(Err(FrameError::Malformed)),
+                    None if len == 0 => None,
+                    None => Some(Ok(&self.buf[..len])),
+                };
+            }
+            (false, ESC) => {
+                self.escaped = true;
+                return None;
+            }
+            (false, byte) => byte,
+            (true, ESC_END) => END,
+            (true, ESC_ESC) => ESC,
+            (true, byte) => {
+                self.error.get_or_insert(FrameError::Malformed);
+                byte
+            }
+        };
+        self.escaped = false;
+        match self.buf.get_mut(self.len) {
+            Some(slot) => {
+                *slot = byte;
+                self.len += 1;
+            }
+            None => {
+                self.error.get_or_insert(FrameError::TooLong);
+            }
+        }
+        None
+    }
+
+    /// Forgets the frame being received.
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.escaped = false;
+        self.error = None;
+    }
+}
+
+impl<const N: usize> Default for SlipDecoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
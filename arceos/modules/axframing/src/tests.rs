@@ -0,0 +1,158 @@
+use crate::cobs::{self, CobsDecoder};
+use crate::slip::{self, SlipDecoder, END, ESC, ESC_END, ESC_ESC};
+use crate::FrameError;
+
+fn data(n: usize) -> Vec<u8> {
+    let mut x = 1u32;
+    (0..n)
+        .map(|_| {
+            x = x.wrapping_mul(1103515245).wrapping_add(12345);
+            // many zeros, to test the blocks of COBS
+            match (x >> 16) as u8 {
+                b if b < 0x40 => 0,
+                b => b,
+            }
+        })
+        .collect()
+}
+
+fn cobs_encode(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0; cobs::max_encoded_len(data.len())];
+    let len = cobs::encode(data, &mut out).unwrap();
+    out.truncate(len);
+    out
+}
+
+#[test]
+fn test_slip_encode() {
+    let mut out = [0; 16];
+    let len = slip::encode(&[1, END, 2, ESC, 3], &mut out).unwrap();
+    assert_eq!(&out[..len], [END, 1, ESC, ESC_END, 2, ESC, ESC_ESC, 3, END]);
+    assert_eq!(slip::encode(&[END; 8], &mut out), None);
+    assert_eq!(slip::encode(&[END; 7], &mut [0; 16]), Some(16));
+}
+
+#[test]
+fn test_slip_decode() {
+    let mut decoder = SlipDecoder::<8>::new();
+    let mut frames = Vec::new();
+    let stream = [
+        END, 1, ESC, ESC_END, 2, ESC, ESC_ESC, END, END, END, 3, ESC, 4, END, 5, END,
+    ];
+    for byte in stream {
+        if let Some(frame) = decoder.push(byte) {
+            frames.push(frame.map(<[u8]>::to_vec));
+        }
+    }
+    assert_eq!(
+        frames,
+        [
+            Ok(vec![1, END, 2, ESC]),
+            Err(FrameError::Malformed),
+            Ok(vec![5]),
+        ]
+    );
+
+    let mut frames = Vec::new();
+    for byte in [1; 9].into_iter().chain([END, 2, END]) {
+        if let Some(frame) = decoder.push(byte) {
+            frames.push(frame.map(<[u8]>::to_vec));
+        }
+    }
+    assert_eq!(frames, [Err(FrameError::TooLong), Ok(vec![2])]);
+}
+
+#[test]
+fn test_cobs_vectors() {
+    // the examples of Wikipedia
+    assert_eq!(cobs_encode(&[]), [0x01, 0x00]);
+    assert_eq!(cobs_encode(&[0x00]), [0x01, 0x01, 0x00]);
+    assert_eq!(cobs_encode(&[0x00, 0x00]), [0x01, 0x01, 0x01, 0x00]);
+    assert_eq!(
+        cobs_encode(&[0x00, 0x11, 0x00]),
+        [0x01, 0x02, 0x11, 0x01, 0x00]
+    );
+    assert_eq!(
+        cobs_encode(&[0x11, 0x22, 0x00, 0x33]),
+        [0x03, 0x11, 0x22, 0x02, 0x33, 0x00]
+    );
+    assert_eq!(
+        cobs_encode(&[0x11, 0x00, 0x00, 0x00]),
+        [0x02, 0x11, 0x01, 0x01, 0x01, 0x00]
+    );
+
+    let ones: Vec<u8> = (1..=0xfe).collect();
+    let mut expected = vec![0xff];
+    expected.extend(&ones);
+    expected.push(0x00);
+    assert_eq!(cobs_encode(&ones), expected);
+
+    let mut data = ones.clone();
+    data.insert(0, 0x00);
+    let mut expected = vec![0x01, 0xff];
+    expected.extend(&ones);
+    expected.push(0x00);
+    assert_eq!(cobs_encode(&data), expected);
+
+    let data: Vec<u8> = (1..=0xff).collect();
+    let mut expected = vec![0xff];
+    expected.extend(&ones);
+    expected.extend([0x02, 0xff, 0x00]);
+    assert_eq!(cobs_encode(&data), expected);
+}
+
+#[test]
+fn test_cobs_roundtrip() {
+    for n in [0, 1, 253, 254, 255, 508, 1000] {
+        for data in [data(n), vec![0; n], vec![1; n]] {
+            let frame = cobs_encode(&data);
+            assert!(frame.len() <= cobs::max_encoded_len(n));
+            assert!(!frame[..frame.len() - 1].contains(&0));
+
+            let mut out = vec![0; n];
+            let len = cobs::decode(&frame[..frame.len() - 1], &mut out).unwrap();
+            assert_eq!(&out[..len], data);
+
+            let mut decoder = CobsDecoder::<1000>::new();
+            let (last, rest) = frame.split_last().unwrap();
+            assert!(rest.iter().all(|&byte| decoder.push(byte).is_none()));
+            assert_eq!(decoder.push(*last), Some(Ok(&data[..])));
+        }
+    }
+}
+
+#[test]
+fn test_cobs_errors() {
+    let mut out = [0; 8];
+    assert_eq!(
+        cobs::decode(&[0x03, 0x11], &mut out),
+        Err(FrameError::Malformed)
+    );
+    assert_eq!(
+        cobs::decode(&[0x02, 0x00], &mut out),
+        Err(FrameError::Malformed)
+    );
+    assert_eq!(
+        cobs::decode(&[0x01; 10], &mut out),
+        Err(FrameError::TooLong)
+    );
+
+    let mut decoder = CobsDecoder::<2>::new();
+    let mut frames = Vec::new();
+    let stream = [
+        0x00, 0x03, 0x11, 0x00, 0x04, 1, 2, 3, 0x00, 0x00, 0x02, 0x33, 0x00,
+    ];
+    for byte in stream {
+        if let Some(frame) = decoder.push(byte) {
+            frames.push(frame.map(<[u8]>::to_vec));
+        }
+    }
+    assert_eq!(
+        frames,
+        [
+            Err(FrameError::Malformed),
+            Err(FrameError::TooLong),
+            Ok(vec![0x33]),
+        ]
+    );
+}
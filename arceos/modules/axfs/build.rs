@@ -0,0 +1,57 @@
+fn main() {
+    println!("cargo:rerun-if-env-changed=AX_DISK_KEY");
+    println!("cargo:rerun-if-env-changed=AX_DISK_KEY_FILE");
+
+    // Read the disk encryption key from the key file, if given.
+    if let Some(path) = std::env::var("AX_DISK_KEY_FILE")
+        .ok()
+        .filter(|p| !p.is_empty())
+    {
+        println!("cargo:rerun-if-changed={}", path);
+        let key = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("failed to read disk key file {:?}: {}", path, e));
+        println!("cargo:rustc-env=AX_DISK_KEY={}", key.trim());
+    }
+
+    // Embed the initramfs archive, or an empty one if not given.
+    println!("cargo:rerun-if-env-changed=AX_INITRAMFS");
+    let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("initramfs.cpio");
+    match std::env::var("AX_INITRAMFS").ok().filter(|p| !p.is_empty()) {
+        Some(path) => {
+            println!("cargo:rerun-if-changed={}", path);
+            std::fs::copy(&path, &out)
+                .unwrap_or_else(|e| panic!("failed to read initramfs {:?}: {}", path, e));
+        }
+        None => std::fs::write(&out, b"").unwrap(),
+    }
+
+    // Embed the detached signature of the initramfs, and the public key to
+    // check it with, or empty files if not given.
+    println!("cargo:rerun-if-env-changed=AX_INITRAMFS_SIG");
+    let out_sig = out.with_extension("sig");
+    match std::env::var("AX_INITRAMFS_SIG")
+        .ok()
+        .filter(|p| !p.is_empty())
+    {
+        Some(path) => {
+            println!("cargo:rerun-if-changed={}", path);
+            let sig = std::fs::read(&path)
+                .unwrap_or_else(|e| panic!("failed to read initramfs signature {:?}: {}", path, e));
+            assert_eq!(sig.len(), 64, "the initramfs signature must be 64 bytes");
+            std::fs::write(&out_sig, sig).unwrap();
+        }
+        None => std::fs::write(&out_sig, b"").unwrap(),
+    }
+    println!("cargo:rerun-if-env-changed=AX_SECURE_BOOT_KEY");
+    let key = std::env::var("AX_SECURE_BOOT_KEY").unwrap_or_default();
+    let key = key.trim();
+    assert!(
+        key.is_empty() || (key.len() == 64 && key.bytes().all(|b| b.is_ascii_hexdigit())),
+        "AX_SECURE_BOOT_KEY must be an Ed25519 public key of 32 bytes in hex"
+    );
+    let key: Vec<u8> = (0..key.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&key[i..i + 2], 16).unwrap())
+        .collect();
+    std::fs::write(out.with_file_name("secure_boot.key"), key).unwrap();
+}
@@ -158,6 +158,18 @@ impl File {
         self.inner.truncate(size)
     }
 
+    /// Preallocates space for `len` bytes at `offset`, extending the file
+    /// unless `keep_size` is set. See [`fops::File::allocate`].
+    pub fn allocate(&self, offset: u64, len: u64, keep_size: bool) -> Result<()> {
+        self.inner.allocate(offset, len, keep_size)
+    }
+
+    /// Deallocates `len` bytes at `offset`, which read as zeros afterwards.
+    /// See [`fops::File::punch_hole`].
+    pub fn punch_hole(&self, offset: u64, len: u64) -> Result<()> {
+        self.inner.punch_hole(offset, len)
+    }
+
     /// Queries metadata about the underlying file.
     pub fn metadata(&self) -> Result<Metadata> {
         self.inner.get_attr().map(Metadata)
@@ -5,6 +5,7 @@ mod file;
 
 pub use self::dir::{DirBuilder, DirEntry, ReadDir};
 pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
+pub use crate::fops::FileSystemStat;
 
 use alloc::{string::String, vec::Vec};
 use axio::{self as io, prelude::*};
@@ -17,7 +18,7 @@ pub fn read_dir(path: &str) -> io::Result<ReadDir> {
 /// Returns the canonical, absolute form of a path with all intermediate
 /// components normalized.
 pub fn canonicalize(path: &str) -> io::Result<String> {
-    crate::root::absolute_path(path)
+    Ok(crate::root::canonical_path(path))
 }
 
 /// Returns the current working directory as a [`String`].
@@ -30,6 +31,14 @@ pub fn set_current_dir(path: &str) -> io::Result<()> {
     crate::root::set_current_dir(path)
 }
 
+/// Changes the root directory of the current context to the specified path.
+///
+/// Absolute paths are resolved against it afterwards, and `..` cannot leave
+/// it. The working directory is moved to the new root if it is outside.
+pub fn chroot(path: &str) -> io::Result<()> {
+    crate::root::chroot(path)
+}
+
 /// Read the entire contents of a file into a bytes vector.
 pub fn read(path: &str) -> io::Result<Vec<u8>> {
     let mut file = File::open(path)?;
@@ -59,6 +68,17 @@ pub fn metadata(path: &str) -> io::Result<Metadata> {
     File::open(path)?.metadata()
 }
 
+/// Returns the usage statistics of the filesystem containing `path`.
+pub fn statfs(path: &str) -> io::Result<FileSystemStat> {
+    crate::root::statfs(path)
+}
+
+/// Returns the paths where the filesystems are mounted, starting with the
+/// root.
+pub fn mount_points() -> Vec<String> {
+    crate::root::mount_points()
+}
+
 /// Creates a new, empty directory at the provided path.
 pub fn create_dir(path: &str) -> io::Result<()> {
     DirBuilder::new().create(path)
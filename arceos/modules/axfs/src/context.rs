@@ -0,0 +1,90 @@
+//! Root and working directories of tasks.
+//!
+//! Relative paths are resolved against the working directory, and absolute
+//! paths against the root directory (set by [`chroot`](crate::api::chroot))
+//! of the current context.
+//!
+//! By default all tasks share one context, like the threads of a process.
+//! With the `multitask` feature, a task can get its own copy with
+//! [`unshare`], and a newly spawned task (e.g. a user process) can be given
+//! a copy of the context of its parent with [`fork`].
+
+#[cfg(feature = "multitask")]
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+
+use axsync::Mutex;
+
+/// The root and working directory, both as absolute paths ending with `/`.
+#[derive(Clone)]
+pub(crate) struct FsContext {
+    /// The root directory, in the global namespace.
+    pub root: String,
+    /// The working directory, relative to `root`.
+    pub cwd: String,
+}
+
+static SHARED: Mutex<FsContext> = Mutex::new(FsContext {
+    root: String::new(),
+    cwd: String::new(),
+});
+
+/// Private contexts, by task ID.
+#[cfg(feature = "multitask")]
+static PRIVATE: Mutex<BTreeMap<u64, FsContext>> = Mutex::new(BTreeMap::new());
+
+#[cfg(feature = "multitask")]
+fn current_task_id() -> Option<u64> {
+    axtask::current_may_uninit().map(|curr| curr.id().as_u64())
+}
+
+pub(crate) fn init() {
+    let mut ctx = SHARED.lock();
+    ctx.root = "/".into();
+    ctx.cwd = "/".into();
+}
+
+/// Returns a copy of the context of the current task.
+pub(crate) fn current() -> FsContext {
+    #[cfg(feature = "multitask")]
+    if let Some(ctx) = current_task_id().and_then(|id| PRIVATE.lock().get(&id).cloned()) {
+        return ctx;
+    }
+    SHARED.lock().clone()
+}
+
+/// Replaces the context of the current task.
+pub(crate) fn set_current(ctx: FsContext) {
+    #[cfg(feature = "multitask")]
+    if let Some(id) = current_task_id() {
+        if let Some(private) = PRIVATE.lock().get_mut(&id) {
+            *private = ctx;
+            return;
+        }
+    }
+    *SHARED.lock() = ctx;
+}
+
+/// Gives the current task its own copy of its context, so later changes of
+/// the root or working directory do not affect other tasks.
+#[cfg(feature = "multitask")]
+pub fn unshare() {
+    if let Some(id) = current_task_id() {
+        let ctx = current();
+        PRIVATE.lock().insert(id, ctx);
+    }
+}
+
+/// Gives the task `child` its own copy of the context of the current task.
+#[cfg(feature = "multitask")]
+pub fn fork(child: u64) {
+    let ctx = current();
+    PRIVATE.lock().insert(child, ctx);
+}
+
+/// Releases the private context of the task `task`, if any. It should be
+/// called when the task exits.
+#[cfg(feature = "multitask")]
+pub fn release(task: u64) {
+    PRIVATE.lock().remove(&task);
+}
@@ -0,0 +1,114 @@
+//! Transparent block device encryption, similar to Linux's dm-crypt.
+//!
+//! Every 512-byte sector is encrypted with XTS-AES, using the sector number
+//! as the tweak, so the on-disk layout is the same as `cryptsetup` plain mode
+//! with `--cipher aes-xts-plain64`.
+//!
+//! The key is given in hex at build time, either directly by the
+//! `AX_DISK_KEY` environment variable, or by the path of a key file in
+//! `AX_DISK_KEY_FILE`. A 32-byte key selects XTS-AES-128 and a 64-byte key
+//! selects XTS-AES-256.
+//!
+//! The cipher is computed by [`axcrypto`], on a crypto engine if available.
+
+use alloc::vec::Vec;
+
+use axcrypto::{Cipher, CipherAlg};
+use axdriver::prelude::*;
+
+const SECTOR_SIZE: usize = 512;
+
+const DISK_KEY: Option<&str> = option_env!("AX_DISK_KEY");
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim().as_bytes();
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    let digit = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+    s.chunks_exact(2)
+        .map(|p| Some((digit(p[0])? << 4) | digit(p[1])?))
+        .collect()
+}
+
+/// The `plain64` IV of a sector: its number in little endian.
+fn plain64(sector: u64) -> [u8; 16] {
+    let mut iv = [0; 16];
+    iv[..8].copy_from_slice(&sector.to_le_bytes());
+    iv
+}
+
+/// A block device whose contents are encrypted with XTS-AES.
+pub struct CryptDevice {
+    inner: AxBlockDevice,
+    cipher: Cipher,
+}
+
+impl CryptDevice {
+    /// Creates an encrypted view of the device with the given key.
+    pub fn new(inner: AxBlockDevice, key: &[u8]) -> Option<Self> {
+        if inner.block_size() != SECTOR_SIZE {
+            return None;
+        }
+        Some(Self {
+            inner,
+            cipher: Cipher::new(CipherAlg::AesXts, key).ok()?,
+        })
+    }
+
+    /// Creates an encrypted view of the device with the key configured at
+    /// build time.
+    ///
+    /// Panics if the key is missing or malformed.
+    pub fn with_configured_key(inner: AxBlockDevice) -> Self {
+        let mut key = DISK_KEY
+            .filter(|k| !k.is_empty())
+            .and_then(parse_hex)
+            .expect("crypt: AX_DISK_KEY is missing or not a hex string");
+        let dev = Self::new(inner, &key).expect("crypt: disk key must be 32 or 64 bytes");
+        key.fill(0);
+        info!(
+            "crypt: use XTS-AES-{} ({})",
+            key.len() * 4,
+            dev.cipher.engine_name()
+        );
+        dev
+    }
+
+    /// Returns the number of sectors of the device.
+    pub fn num_blocks(&self) -> u64 {
+        self.inner.num_blocks()
+    }
+
+    /// Returns the size of a sector.
+    pub fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    /// Reads and decrypts consecutive sectors starting from `block_id`.
+    pub fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        self.inner.read_block(block_id, buf)?;
+        for (i, sector) in buf.chunks_exact_mut(SECTOR_SIZE).enumerate() {
+            let iv = plain64(block_id + i as u64);
+            self.cipher.decrypt(&iv, sector).map_err(|_| DevError::Io)?;
+        }
+        Ok(())
+    }
+
+    /// Encrypts and writes consecutive sectors starting from `block_id`.
+    ///
+    /// `buf` is left untouched; the ciphertext is produced in a copy.
+    pub fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
+        let mut data = buf.to_vec();
+        for (i, sector) in data.chunks_exact_mut(SECTOR_SIZE).enumerate() {
+            let iv = plain64(block_id + i as u64);
+            self.cipher.encrypt(&iv, sector).map_err(|_| DevError::Io)?;
+        }
+        self.inner.write_block(block_id, &data)
+    }
+
+    /// Flushes the underlying device.
+    pub fn flush(&mut self) -> DevResult {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,162 @@
+//! Path lookup cache (dentry cache).
+//!
+//! Lookups of absolute paths through [`crate::root`] are remembered, so that
+//! repeated opens of the same path don't walk the directories of the
+//! underlying filesystem every time. Paths that were not found are cached
+//! as well (negative entries).
+//!
+//! Entries are invalidated when a path (or any of its ancestors) is created,
+//! removed or renamed through the [`api`](crate::api) or [`fops`](crate::fops)
+//! functions. The oldest entry is evicted when the cache is full.
+
+use alloc::{collections::BTreeMap, collections::VecDeque, string::String};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use axerrno::{AxError, AxResult};
+use axfs_vfs::VfsNodeRef;
+use axsync::Mutex;
+
+/// The default maximum number of cached paths.
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// Statistics of the dentry cache.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DcacheStats {
+    /// Number of lookups that found a cached node.
+    pub hits: u64,
+    /// Number of lookups that found a cached "not found" entry.
+    pub negative_hits: u64,
+    /// Number of lookups that went to the filesystem.
+    pub misses: u64,
+}
+
+struct DentryCache {
+    /// `None` means the path does not exist.
+    entries: BTreeMap<String, Option<VfsNodeRef>>,
+    /// Insertion order, for eviction. May contain already removed paths.
+    order: VecDeque<String>,
+    capacity: usize,
+}
+
+static DCACHE: Mutex<DentryCache> = Mutex::new(DentryCache {
+    entries: BTreeMap::new(),
+    order: VecDeque::new(),
+    capacity: DEFAULT_CAPACITY,
+});
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static NEGATIVE_HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+
+impl DentryCache {
+    fn insert(&mut self, path: String, node: Option<VfsNodeRef>) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.entries.len() >= self.capacity {
+            match self.order.pop_front() {
+                Some(old) => {
+                    self.entries.remove(&old);
+                }
+                None => break,
+            }
+        }
+        if self.entries.insert(path.clone(), node).is_none() {
+            self.order.push_back(path);
+        }
+    }
+
+    fn invalidate(&mut self, path: &str) {
+        self.entries.remove(path);
+        let prefix = if path == "/" {
+            String::from("/")
+        } else {
+            String::from(path) + "/"
+        };
+        let children = self
+            .entries
+            .range(prefix.clone()..)
+            .take_while(|(k, _)| k.starts_with(&prefix))
+            .map(|(k, _)| k.clone())
+            .collect::<alloc::vec::Vec<_>>();
+        for k in children {
+            self.entries.remove(&k);
+        }
+        if self.entries.is_empty() {
+            self.order.clear();
+        }
+    }
+}
+
+/// Normalizes an absolute, canonical path to be used as a cache key.
+fn key_of(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        p => p,
+    }
+}
+
+/// Looks up the absolute path in the cache, or with `f` on a miss and caches
+/// the result.
+pub(crate) fn lookup_with<F>(path: &str, f: F) -> AxResult<VfsNodeRef>
+where
+    F: FnOnce() -> AxResult<VfsNodeRef>,
+{
+    let key = key_of(path);
+    if let Some(entry) = DCACHE.lock().entries.get(key) {
+        return match entry {
+            Some(node) => {
+                HITS.fetch_add(1, Ordering::Relaxed);
+                Ok(node.clone())
+            }
+            None => {
+                NEGATIVE_HITS.fetch_add(1, Ordering::Relaxed);
+                Err(AxError::NotFound)
+            }
+        };
+    }
+
+    MISSES.fetch_add(1, Ordering::Relaxed);
+    let res = f();
+    match &res {
+        Ok(node) => DCACHE.lock().insert(key.into(), Some(node.clone())),
+        Err(AxError::NotFound) => DCACHE.lock().insert(key.into(), None),
+        Err(_) => {}
+    }
+    res
+}
+
+/// Removes the absolute path and all paths under it from the cache.
+pub(crate) fn invalidate(path: &str) {
+    DCACHE.lock().invalidate(key_of(path));
+}
+
+/// Removes all entries from the cache.
+pub fn clear() {
+    let mut cache = DCACHE.lock();
+    cache.entries.clear();
+    cache.order.clear();
+}
+
+/// Sets the maximum number of cached paths. Zero disables the cache.
+pub fn set_capacity(capacity: usize) {
+    let mut cache = DCACHE.lock();
+    cache.capacity = capacity;
+    while cache.entries.len() > capacity {
+        match cache.order.pop_front() {
+            Some(old) => {
+                cache.entries.remove(&old);
+            }
+            None => break,
+        }
+    }
+}
+
+/// Returns the statistics of the dentry cache.
+pub fn stats() -> DcacheStats {
+    DcacheStats {
+        hits: HITS.load(Ordering::Relaxed),
+        negative_hits: NEGATIVE_HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+    }
+}
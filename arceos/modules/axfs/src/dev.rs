@@ -1,22 +1,21 @@
 use axdriver::prelude::*;
 
-const BLOCK_SIZE: usize = 512;
+use crate::iosched::{IoScheduler, BLOCK_SIZE, MAX_MERGE_BLOCKS};
 
 /// A disk device with a cursor.
 pub struct Disk {
     block_id: u64,
     offset: usize,
-    dev: AxBlockDevice,
+    dev: IoScheduler,
 }
 
 impl Disk {
     /// Create a new disk.
     pub fn new(dev: AxBlockDevice) -> Self {
-        assert_eq!(BLOCK_SIZE, dev.block_size());
         Self {
             block_id: 0,
             offset: 0,
-            dev,
+            dev: IoScheduler::new(dev),
         }
     }
 
@@ -36,21 +35,33 @@ impl Disk {
         self.offset = pos as usize % BLOCK_SIZE;
     }
 
+    /// Number of blocks from the cursor to the end of the disk.
+    fn remaining_blocks(&self) -> u64 {
+        self.dev.num_blocks().saturating_sub(self.block_id)
+    }
+
     /// Read within one block, returns the number of bytes read.
+    ///
+    /// Returns 0 if the cursor is at or beyond the end of the disk.
     pub fn read_one(&mut self, buf: &mut [u8]) -> DevResult<usize> {
+        if self.remaining_blocks() == 0 {
+            return Ok(0);
+        }
         let read_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
-            // whole block
+            // whole blocks, merged into one request
+            let count = ((buf.len() / BLOCK_SIZE).min(MAX_MERGE_BLOCKS) as u64)
+                .min(self.remaining_blocks()) as usize;
             self.dev
-                .read_block(self.block_id, &mut buf[0..BLOCK_SIZE])?;
-            self.block_id += 1;
-            BLOCK_SIZE
+                .read(self.block_id, &mut buf[..count * BLOCK_SIZE])?;
+            self.block_id += count as u64;
+            count * BLOCK_SIZE
         } else {
             // partial block
             let mut data = [0u8; BLOCK_SIZE];
             let start = self.offset;
             let count = buf.len().min(BLOCK_SIZE - self.offset);
 
-            self.dev.read_block(self.block_id, &mut data)?;
+            self.dev.read(self.block_id, &mut data)?;
             buf[..count].copy_from_slice(&data[start..start + count]);
 
             self.offset += count;
@@ -64,21 +75,29 @@ impl Disk {
     }
 
     /// Write within one block, returns the number of bytes written.
+    ///
+    /// Returns 0 if the cursor is at or beyond the end of the disk.
     pub fn write_one(&mut self, buf: &[u8]) -> DevResult<usize> {
+        if self.remaining_blocks() == 0 {
+            return Ok(0);
+        }
         let write_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
-            // whole block
-            self.dev.write_block(self.block_id, &buf[0..BLOCK_SIZE])?;
-            self.block_id += 1;
-            BLOCK_SIZE
+            // whole blocks, merged into one request
+            let count = ((buf.len() / BLOCK_SIZE).min(MAX_MERGE_BLOCKS) as u64)
+                .min(self.remaining_blocks()) as usize;
+            self.dev
+                .write(current_origin(), self.block_id, &buf[..count * BLOCK_SIZE])?;
+            self.block_id += count as u64;
+            count * BLOCK_SIZE
         } else {
             // partial block
             let mut data = [0u8; BLOCK_SIZE];
             let start = self.offset;
             let count = buf.len().min(BLOCK_SIZE - self.offset);
 
-            self.dev.read_block(self.block_id, &mut data)?;
+            self.dev.read(self.block_id, &mut data)?;
             data[start..start + count].copy_from_slice(&buf[..count]);
-            self.dev.write_block(self.block_id, &data)?;
+            self.dev.write(current_origin(), self.block_id, &data)?;
 
             self.offset += count;
             if self.offset >= BLOCK_SIZE {
@@ -89,4 +108,18 @@ impl Disk {
         };
         Ok(write_size)
     }
+
+    /// Writes all queued data to the device.
+    pub fn flush(&mut self) -> DevResult {
+        self.dev.flush()
+    }
+}
+
+/// Returns the origin of the current I/O request for the scheduler.
+fn current_origin() -> u64 {
+    #[cfg(feature = "multitask")]
+    if let Some(curr) = axtask::current_may_uninit() {
+        return curr.id().as_u64();
+    }
+    0
 }
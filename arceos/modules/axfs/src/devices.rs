@@ -0,0 +1,65 @@
+//! Device files of `/dev` provided by other modules.
+//!
+//! A module adds its device with [`register_device`], and, with the `devfs`
+//! feature, it appears in `/dev`, even if registered after the mount. For
+//! example, the network module provides `/dev/net/tun` this way.
+
+#![cfg_attr(not(feature = "devfs"), allow(dead_code, unused_imports))]
+
+use alloc::{sync::Arc, vec::Vec};
+
+use axfs_vfs::VfsNodeRef;
+use axsync::Mutex;
+
+#[cfg(feature = "devfs")]
+use crate::fs::devfs::{DeviceFileSystem, DirNode};
+
+/// The path in `/dev` and the node of each device.
+type DeviceEntry = (&'static str, VfsNodeRef);
+
+static DEVICES: Mutex<Vec<DeviceEntry>> = Mutex::new(Vec::new());
+
+#[cfg(feature = "devfs")]
+static DEVFS: Mutex<Option<Arc<DeviceFileSystem>>> = Mutex::new(None);
+
+/// The subdirectories of `/dev` created for the devices.
+#[cfg(feature = "devfs")]
+static DIRS: Mutex<Vec<(&'static str, Arc<DirNode>)>> = Mutex::new(Vec::new());
+
+/// Adds the device `node` at `/dev/<path>`, where `path` is a name or
+/// `<dir>/<name>`.
+pub fn register_device(path: &'static str, node: VfsNodeRef) {
+    let mut devices = DEVICES.lock();
+    #[cfg(feature = "devfs")]
+    if let Some(devfs) = DEVFS.lock().as_ref() {
+        add(devfs, path, node.clone());
+    }
+    devices.push((path, node));
+}
+
+#[cfg(feature = "devfs")]
+fn add(devfs: &DeviceFileSystem, path: &'static str, node: VfsNodeRef) {
+    let Some((dir, name)) = path.split_once('/') else {
+        devfs.add(path, node);
+        return;
+    };
+    let mut dirs = DIRS.lock();
+    match dirs.iter().find(|(d, _)| *d == dir) {
+        Some((_, dir_node)) => dir_node.add(name, node),
+        None => {
+            let dir_node = devfs.mkdir(dir);
+            dir_node.add(name, node);
+            dirs.push((dir, dir_node));
+        }
+    }
+}
+
+/// Adds the devices registered so far to `devfs`, and the later ones too.
+#[cfg(feature = "devfs")]
+pub(crate) fn attach(devfs: Arc<DeviceFileSystem>) {
+    let devices = DEVICES.lock();
+    for (path, node) in devices.iter() {
+        add(&devfs, path, node.clone());
+    }
+    *DEVFS.lock() = Some(devfs);
+}
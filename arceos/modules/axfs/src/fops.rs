@@ -11,6 +11,16 @@ pub use crate::dev::Disk;
 #[cfg(feature = "myfs")]
 pub use crate::fs::myfs::MyFileSystemIf;
 
+/// Mounts the FAT image on the block device `dev` and reads everything in it,
+/// returning an error at the first inconsistency found.
+///
+/// It must not panic whatever the content of the device is, and is used to
+/// test the driver against corrupted images.
+#[cfg(all(feature = "fatfs", not(any(feature = "myfs", feature = "rofs"))))]
+pub fn check_fat_image(dev: axdriver::prelude::AxBlockDevice) -> AxResult {
+    crate::fs::fatfs::check_image(crate::dev::Disk::new(dev))
+}
+
 /// Alias of [`axfs_vfs::VfsNodeType`].
 pub type FileType = axfs_vfs::VfsNodeType;
 /// Alias of [`axfs_vfs::VfsDirEntry`].
@@ -20,6 +30,32 @@ pub type FileAttr = axfs_vfs::VfsNodeAttr;
 /// Alias of [`axfs_vfs::VfsNodePerm`].
 pub type FilePerm = axfs_vfs::VfsNodePerm;
 
+/// Usage statistics of a mounted filesystem.
+///
+/// Filesystems without a size limit (e.g. ramfs) report zero blocks, like
+/// on Linux.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FileSystemStat {
+    /// Name of the filesystem type, e.g. `"fatfs"`.
+    pub fs_type: &'static str,
+    /// Size of a block, in bytes.
+    pub block_size: u64,
+    /// Total number of blocks.
+    pub blocks: u64,
+    /// Number of free blocks.
+    pub blocks_free: u64,
+    /// Number of free blocks available to unprivileged users.
+    pub blocks_avail: u64,
+    /// Total number of inodes, or zero if not limited.
+    pub files: u64,
+    /// Number of free inodes.
+    pub files_free: u64,
+    /// Maximum length of a file name.
+    pub name_max: u64,
+    /// Whether the filesystem is read-only.
+    pub read_only: bool,
+}
+
 /// An opened file object, with open permissions and a cursor.
 pub struct File {
     node: WithCap<VfsNodeRef>,
@@ -151,6 +187,9 @@ impl File {
         if !perm_to_cap(attr.perm()).contains(access_cap) {
             return ax_err!(PermissionDenied);
         }
+        if let Some(abs_path) = crate::root::absolute_path_at(dir, path) {
+            crate::xattr::check_label(&abs_path, access_cap)?;
+        }
 
         node.open()?;
         if opts.truncate {
@@ -175,6 +214,51 @@ impl File {
         Ok(())
     }
 
+    /// Preallocates space for the range `offset..offset + len`, like
+    /// `fallocate(2)`. The file is extended if the range ends beyond it,
+    /// unless `keep_size` is set.
+    ///
+    /// On filesystems without sparse files, the file is extended by writing
+    /// zeros, and it is a no-op if `keep_size` is set.
+    pub fn allocate(&self, offset: u64, len: u64, keep_size: bool) -> AxResult {
+        let node = self.access_node(Cap::WRITE)?;
+        let end = match offset.checked_add(len) {
+            Some(end) if len > 0 => end,
+            _ => return ax_err!(InvalidInput),
+        };
+        if let Some(file) = crate::fs::as_sparse(node) {
+            return Ok(file.allocate(offset, len, keep_size)?);
+        }
+        let mut pos = node.get_attr()?.size();
+        if keep_size {
+            return Ok(());
+        }
+        let zeros = [0; 512];
+        while pos < end {
+            let n = node.write_at(pos, &zeros[..(end - pos).min(512) as usize])?;
+            if n == 0 {
+                return ax_err!(StorageFull);
+            }
+            pos += n as u64;
+        }
+        Ok(())
+    }
+
+    /// Deallocates the range `offset..offset + len`, which reads as zeros
+    /// afterwards, without changing the file size.
+    ///
+    /// Returns [`AxError::Unsupported`] on filesystems without sparse files.
+    pub fn punch_hole(&self, offset: u64, len: u64) -> AxResult {
+        let node = self.access_node(Cap::WRITE)?;
+        if len == 0 || offset.checked_add(len).is_none() {
+            return ax_err!(InvalidInput);
+        }
+        match crate::fs::as_sparse(node) {
+            Some(file) => Ok(file.punch_hole(offset, len)?),
+            None => ax_err!(Unsupported),
+        }
+    }
+
     /// Reads the file at the current position. Returns the number of bytes
     /// read.
     ///
@@ -271,6 +355,9 @@ impl Directory {
         if !perm_to_cap(attr.perm()).contains(access_cap) {
             return ax_err!(PermissionDenied);
         }
+        if let Some(abs_path) = crate::root::absolute_path_at(dir, path) {
+            crate::xattr::check_label(&abs_path, access_cap)?;
+        }
 
         node.open()?;
         Ok(Self {
@@ -27,25 +27,24 @@ unsafe impl<'a> Sync for DirWrapper<'a> {}
 
 impl FatFileSystem {
     #[cfg(feature = "use-ramdisk")]
-    pub fn new(mut disk: Disk) -> Self {
+    pub fn new(mut disk: Disk) -> VfsResult<Self> {
         let opts = fatfs::FormatVolumeOptions::new();
-        fatfs::format_volume(&mut disk, opts).expect("failed to format volume");
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
-            .expect("failed to initialize FAT filesystem");
-        Self {
-            inner,
-            root_dir: UnsafeCell::new(None),
-        }
+        fatfs::format_volume(&mut disk, opts).map_err(as_vfs_err)?;
+        Self::mount(disk)
     }
 
     #[cfg(not(feature = "use-ramdisk"))]
-    pub fn new(disk: Disk) -> Self {
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
-            .expect("failed to initialize FAT filesystem");
-        Self {
+    pub fn new(disk: Disk) -> VfsResult<Self> {
+        Self::mount(disk)
+    }
+
+    fn mount(mut disk: Disk) -> VfsResult<Self> {
+        check_boot_sector(&mut disk)?;
+        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new()).map_err(as_vfs_err)?;
+        Ok(Self {
             inner,
             root_dir: UnsafeCell::new(None),
-        }
+        })
     }
 
     pub fn init(&'static self) {
@@ -90,6 +89,10 @@ impl VfsNodeOps for FileWrapper<'static> {
         file.seek(SeekFrom::Start(size)).map_err(as_vfs_err)?; // TODO: more efficient
         file.truncate().map_err(as_vfs_err)
     }
+
+    fn fsync(&self) -> VfsResult {
+        self.0.lock().flush().map_err(as_vfs_err)
+    }
 }
 
 impl VfsNodeOps for DirWrapper<'static> {
@@ -157,7 +160,9 @@ impl VfsNodeOps for DirWrapper<'static> {
     fn remove(&self, path: &str) -> VfsResult {
         debug!("remove at fatfs: {}", path);
         let path = path.trim_matches('/');
-        assert!(!path.is_empty()); // already check at `root.rs`
+        if path.is_empty() {
+            return Err(VfsError::InvalidInput); // already checked at `root.rs`
+        }
         if let Some(rest) = path.strip_prefix("./") {
             return self.remove(rest);
         }
@@ -175,7 +180,7 @@ impl VfsNodeOps for DirWrapper<'static> {
                     } else if entry.is_file() {
                         VfsNodeType::File
                     } else {
-                        unreachable!()
+                        return Err(VfsError::InvalidData);
                     };
                     *out_entry = VfsDirEntry::new(&entry.file_name(), ty);
                 }
@@ -205,6 +210,105 @@ impl VfsOps for FatFileSystem {
     }
 }
 
+impl crate::fs::FsUsage for FatFileSystem {
+    fn usage(&self) -> VfsResult<crate::fops::FileSystemStat> {
+        let stats = self.inner.stats().map_err(as_vfs_err)?;
+        Ok(crate::fops::FileSystemStat {
+            fs_type: "fatfs",
+            block_size: stats.cluster_size() as u64,
+            blocks: stats.total_clusters() as u64,
+            blocks_free: stats.free_clusters() as u64,
+            blocks_avail: stats.free_clusters() as u64,
+            name_max: 255,
+            ..Default::default()
+        })
+    }
+}
+
+/// Checks the boot sector against the size of the disk, before the rest of
+/// the metadata is trusted.
+fn check_boot_sector(disk: &mut Disk) -> VfsResult {
+    let mut sector = [0u8; BLOCK_SIZE];
+    disk.set_position(0);
+    let len = disk.read_one(&mut sector).map_err(|_| VfsError::Io)?;
+    disk.set_position(0);
+    if len < BLOCK_SIZE {
+        return Err(VfsError::UnexpectedEof);
+    }
+
+    let u16_at = |off: usize| u16::from_le_bytes([sector[off], sector[off + 1]]) as u64;
+    let u32_at = |off: usize| u32::from_le_bytes(sector[off..off + 4].try_into().unwrap()) as u64;
+    let bytes_per_sector = u16_at(11);
+    let sectors_per_cluster = sector[13] as u64;
+    let reserved_sectors = u16_at(14);
+    let fats = sector[16] as u64;
+    let total_sectors = match u16_at(19) {
+        0 => u32_at(32),
+        n => n,
+    };
+    let sectors_per_fat = match u16_at(22) {
+        0 => u32_at(36),
+        n => n,
+    };
+
+    let valid = sector[510..] == [0x55, 0xaa]
+        && bytes_per_sector.is_power_of_two()
+        && (512..=4096).contains(&bytes_per_sector)
+        && sectors_per_cluster.is_power_of_two()
+        && reserved_sectors > 0
+        && fats > 0
+        && sectors_per_fat > 0
+        && reserved_sectors + fats * sectors_per_fat < total_sectors
+        && total_sectors * bytes_per_sector <= disk.size();
+    if valid {
+        Ok(())
+    } else {
+        warn!("fatfs: invalid boot sector");
+        Err(VfsError::InvalidData)
+    }
+}
+
+/// Limits of [`check_image`], so that loops in a corrupted directory tree
+/// cannot make it run forever.
+const MAX_DEPTH: usize = 32;
+const MAX_DIR_ENTRIES: usize = 65536;
+
+/// Mounts the FAT image on `disk` and reads all its directories and files.
+///
+/// It returns an error at the first inconsistency found, and must never
+/// panic whatever the content of the disk is. It is used to test the driver
+/// against corrupted images.
+pub fn check_image(mut disk: Disk) -> VfsResult {
+    check_boot_sector(&mut disk)?;
+    let fs = fatfs::FileSystem::new(disk, fatfs::FsOptions::new()).map_err(as_vfs_err)?;
+    fs.stats().map_err(as_vfs_err)?;
+    check_dir(&fs.root_dir(), 0)
+}
+
+fn check_dir(
+    dir: &Dir<'_, Disk, NullTimeProvider, LossyOemCpConverter>,
+    depth: usize,
+) -> VfsResult {
+    if depth > MAX_DEPTH {
+        return Err(VfsError::InvalidData);
+    }
+    let mut buf = [0u8; BLOCK_SIZE];
+    for entry in dir.iter().take(MAX_DIR_ENTRIES) {
+        let entry = entry.map_err(as_vfs_err)?;
+        let name = entry.file_name();
+        if name == "." || name == ".." {
+            continue;
+        }
+        if entry.is_dir() {
+            check_dir(&entry.to_dir(), depth + 1)?;
+        } else {
+            let mut file = entry.to_file();
+            while file.read(&mut buf).map_err(as_vfs_err)? > 0 {}
+        }
+    }
+    Ok(())
+}
+
 impl fatfs::IoBase for Disk {
     type Error = ();
 }
@@ -243,7 +347,7 @@ impl Write for Disk {
         Ok(write_len)
     }
     fn flush(&mut self) -> Result<(), Self::Error> {
-        Ok(())
+        Disk::flush(self).map_err(|_| ())
     }
 }
 
@@ -1,6 +1,8 @@
 cfg_if::cfg_if! {
     if #[cfg(feature = "myfs")] {
         pub mod myfs;
+    } else if #[cfg(feature = "rofs")] {
+        pub mod rofs;
     } else if #[cfg(feature = "fatfs")] {
         pub mod fatfs;
     }
@@ -11,3 +13,53 @@ pub use axfs_devfs as devfs;
 
 #[cfg(feature = "ramfs")]
 pub use axfs_ramfs as ramfs;
+
+#[cfg(feature = "tmpfs")]
+pub mod tmpfs;
+
+use axfs_vfs::{VfsNodeRef, VfsResult};
+
+use crate::fops::FileSystemStat;
+
+/// Filesystems that can report their usage statistics.
+pub(crate) trait FsUsage: Send + Sync {
+    fn usage(&self) -> VfsResult<FileSystemStat>;
+}
+
+#[cfg(feature = "devfs")]
+impl FsUsage for devfs::DeviceFileSystem {
+    fn usage(&self) -> VfsResult<FileSystemStat> {
+        Ok(FileSystemStat {
+            fs_type: "devfs",
+            name_max: 255,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(any(feature = "ramfs", feature = "procfs", feature = "sysfs"))]
+impl FsUsage for axfs_ramfs::RamFileSystem {
+    fn usage(&self) -> VfsResult<FileSystemStat> {
+        Ok(FileSystemStat {
+            fs_type: "ramfs",
+            name_max: 255,
+            ..Default::default()
+        })
+    }
+}
+
+/// Files that can preallocate space and punch holes without writing zeros.
+pub(crate) trait SparseFile {
+    fn allocate(&self, offset: u64, len: u64, keep_size: bool) -> VfsResult;
+    fn punch_hole(&self, offset: u64, len: u64) -> VfsResult;
+}
+
+/// Returns the sparse file interface of `node`, if its filesystem has one.
+pub(crate) fn as_sparse(node: &VfsNodeRef) -> Option<&dyn SparseFile> {
+    #[cfg(feature = "tmpfs")]
+    if let Some(file) = node.as_any().downcast_ref::<tmpfs::FileNode>() {
+        return Some(file);
+    }
+    let _ = node;
+    None
+}
@@ -0,0 +1,424 @@
+//! A compressed, checksummed read-only filesystem for root images.
+//!
+//! The design is similar to squashfs and erofs, but much simpler. An image
+//! is built on the host with `tools/mkrofs`, and consists of:
+//!
+//! - A 64-byte superblock at offset 0.
+//! - The metadata area: an inode table followed by a block table. It is read
+//!   into memory at mount time and protected by one CRC32.
+//! - Data blocks. Every block of a file (or directory) is LZ4 compressed,
+//!   or stored as-is if it does not compress, and has its own CRC32 in the
+//!   block table, so corrupted media is reported as [`VfsError::InvalidData`]
+//!   instead of returning garbage.
+//!
+//! All integers are little-endian. Inode 0 is the root directory. The data
+//! of a directory is a list of `{ ino: u32, name_len: u16, reserved: u16,
+//! name: [u8; name_len] }` entries.
+
+use alloc::{boxed::Box, string::String, sync::Arc, vec, vec::Vec};
+
+use axchecksum::crc32;
+use axcompress::lz4;
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
+use axsync::Mutex;
+
+use crate::dev::Disk;
+
+const MAGIC: &[u8; 8] = b"AXROFS\0\0";
+const VERSION: u32 = 1;
+const SUPERBLOCK_SIZE: usize = 64;
+const INODE_SIZE: usize = 32;
+const BLOCK_ENTRY_SIZE: usize = 16;
+
+const INODE_TYPE_FILE: u8 = 1;
+const INODE_TYPE_DIR: u8 = 2;
+
+/// Set in the length of a block entry if the block is stored uncompressed.
+const BLOCK_RAW: u32 = 1 << 31;
+
+fn le_u16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes(buf[off..off + 2].try_into().unwrap())
+}
+
+fn le_u32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+fn le_u64(buf: &[u8], off: usize) -> u64 {
+    u64::from_le_bytes(buf[off..off + 8].try_into().unwrap())
+}
+
+struct Inode {
+    ty: VfsNodeType,
+    perm: VfsNodePerm,
+    parent: u32,
+    size: u64,
+    first_block: usize,
+    num_blocks: usize,
+}
+
+struct BlockEntry {
+    offset: u64,
+    len: u32,
+    crc: u32,
+}
+
+struct RofsInner {
+    disk: Mutex<Disk>,
+    block_size: usize,
+    inodes: Vec<Inode>,
+    blocks: Vec<BlockEntry>,
+    /// The most recently read block, decompressed.
+    cache: Mutex<Option<(usize, Box<[u8]>)>>,
+}
+
+/// A read-only filesystem, see the [module-level documentation](self).
+pub struct RoFileSystem {
+    inner: Arc<RofsInner>,
+}
+
+pub struct FileNode {
+    fs: Arc<RofsInner>,
+    ino: u32,
+}
+
+pub struct DirNode {
+    fs: Arc<RofsInner>,
+    ino: u32,
+}
+
+impl RoFileSystem {
+    /// Mounts the filesystem image on the disk, verifying its superblock
+    /// and metadata checksums.
+    pub fn new(mut disk: Disk) -> VfsResult<Self> {
+        let mut sb = [0u8; SUPERBLOCK_SIZE];
+        read_exact_at(&mut disk, 0, &mut sb)?;
+        if &sb[..8] != MAGIC {
+            warn!("rofs: bad magic");
+            return Err(VfsError::InvalidData);
+        }
+        if crc32(&sb[..44]) != le_u32(&sb, 44) {
+            error!("rofs: superblock checksum mismatch");
+            return Err(VfsError::InvalidData);
+        }
+        let version = le_u32(&sb, 8);
+        if version != VERSION {
+            warn!("rofs: unsupported version {}", version);
+            return Err(VfsError::Unsupported);
+        }
+
+        let block_size = le_u32(&sb, 12) as usize;
+        let num_inodes = le_u32(&sb, 16) as usize;
+        let num_blocks = le_u32(&sb, 20) as usize;
+        let meta_offset = le_u64(&sb, 24);
+        let meta_size = le_u64(&sb, 32) as usize;
+        if !block_size.is_power_of_two()
+            || block_size < 512
+            || num_inodes == 0
+            || meta_size != num_inodes * INODE_SIZE + num_blocks * BLOCK_ENTRY_SIZE
+        {
+            return Err(VfsError::InvalidData);
+        }
+
+        let mut meta = vec![0u8; meta_size];
+        read_exact_at(&mut disk, meta_offset, &mut meta)?;
+        if crc32(&meta) != le_u32(&sb, 40) {
+            error!("rofs: metadata checksum mismatch");
+            return Err(VfsError::InvalidData);
+        }
+
+        let (inode_table, block_table) = meta.split_at(num_inodes * INODE_SIZE);
+        let blocks = block_table
+            .chunks_exact(BLOCK_ENTRY_SIZE)
+            .map(|e| BlockEntry {
+                offset: le_u64(e, 0),
+                len: le_u32(e, 8),
+                crc: le_u32(e, 12),
+            })
+            .collect::<Vec<_>>();
+        let inodes = inode_table
+            .chunks_exact(INODE_SIZE)
+            .map(|e| {
+                let ty = match e[0] {
+                    INODE_TYPE_FILE => VfsNodeType::File,
+                    INODE_TYPE_DIR => VfsNodeType::Dir,
+                    _ => return Err(VfsError::InvalidData),
+                };
+                let inode = Inode {
+                    ty,
+                    perm: VfsNodePerm::from_bits_truncate(le_u16(e, 2)),
+                    parent: le_u32(e, 4),
+                    size: le_u64(e, 8),
+                    first_block: le_u32(e, 16) as usize,
+                    num_blocks: le_u32(e, 20) as usize,
+                };
+                let valid = (inode.parent as usize) < num_inodes
+                    && inode.first_block + inode.num_blocks <= blocks.len()
+                    && inode.size.div_ceil(block_size as u64) == inode.num_blocks as u64;
+                if valid {
+                    Ok(inode)
+                } else {
+                    Err(VfsError::InvalidData)
+                }
+            })
+            .collect::<VfsResult<Vec<_>>>()?;
+        if inodes[0].ty != VfsNodeType::Dir {
+            return Err(VfsError::InvalidData);
+        }
+
+        info!(
+            "rofs: {} inodes, {} blocks of {} bytes",
+            num_inodes, num_blocks, block_size
+        );
+        Ok(Self {
+            inner: Arc::new(RofsInner {
+                disk: Mutex::new(disk),
+                block_size,
+                inodes,
+                blocks,
+                cache: Mutex::new(None),
+            }),
+        })
+    }
+}
+
+impl VfsOps for RoFileSystem {
+    fn root_dir(&self) -> VfsNodeRef {
+        self.inner.node(0)
+    }
+}
+
+impl crate::fs::FsUsage for RoFileSystem {
+    fn usage(&self) -> VfsResult<crate::fops::FileSystemStat> {
+        let block_size = self.inner.block_size as u64;
+        Ok(crate::fops::FileSystemStat {
+            fs_type: "rofs",
+            block_size,
+            blocks: self.inner.disk.lock().size().div_ceil(block_size),
+            files: self.inner.inodes.len() as u64,
+            name_max: u16::MAX as u64,
+            read_only: true,
+            ..Default::default()
+        })
+    }
+}
+
+fn read_exact_at(disk: &mut Disk, offset: u64, mut buf: &mut [u8]) -> VfsResult {
+    disk.set_position(offset);
+    while !buf.is_empty() {
+        match disk.read_one(buf) {
+            Ok(0) => return Err(VfsError::UnexpectedEof),
+            Ok(n) => {
+                let tmp = buf;
+                buf = &mut tmp[n..];
+            }
+            Err(_) => return Err(VfsError::Io),
+        }
+    }
+    Ok(())
+}
+
+impl RofsInner {
+    fn node(self: &Arc<Self>, ino: u32) -> VfsNodeRef {
+        let fs = self.clone();
+        match self.inodes[ino as usize].ty {
+            VfsNodeType::Dir => Arc::new(DirNode { fs, ino }),
+            _ => Arc::new(FileNode { fs, ino }),
+        }
+    }
+
+    fn attr(&self, ino: u32) -> VfsNodeAttr {
+        let inode = &self.inodes[ino as usize];
+        let blocks = inode.size.div_ceil(512);
+        VfsNodeAttr::new(inode.perm, inode.ty, inode.size, blocks)
+    }
+
+    /// Reads, verifies and decompresses the `idx`-th block of the inode, and
+    /// passes its data to `f`.
+    fn with_block<R>(&self, inode: &Inode, idx: usize, f: impl FnOnce(&[u8]) -> R) -> VfsResult<R> {
+        let block_id = inode.first_block + idx;
+        let len = (inode.size - (idx * self.block_size) as u64).min(self.block_size as u64);
+
+        let mut cache = self.cache.lock();
+        if cache.as_ref().map_or(true, |(id, _)| *id != block_id) {
+            let mut data = cache
+                .take()
+                .map_or_else(|| vec![0; self.block_size].into_boxed_slice(), |(_, d)| d);
+            self.load_block(block_id, &mut data[..len as usize])?;
+            *cache = Some((block_id, data));
+        }
+        Ok(f(&cache.as_ref().unwrap().1[..len as usize]))
+    }
+
+    fn load_block(&self, block_id: usize, buf: &mut [u8]) -> VfsResult {
+        let entry = &self.blocks[block_id];
+        let stored_len = (entry.len & !BLOCK_RAW) as usize;
+        if stored_len > self.block_size {
+            return Err(VfsError::InvalidData);
+        }
+        let mut stored = vec![0u8; stored_len];
+        read_exact_at(&mut self.disk.lock(), entry.offset, &mut stored)?;
+        if crc32(&stored) != entry.crc {
+            error!("rofs: checksum mismatch in block {}", block_id);
+            return Err(VfsError::InvalidData);
+        }
+
+        let ok = if entry.len & BLOCK_RAW != 0 {
+            stored.len() == buf.len() && {
+                buf.copy_from_slice(&stored);
+                true
+            }
+        } else {
+            lz4::decompress_block(&stored, buf) == Some(buf.len())
+        };
+        if !ok {
+            error!("rofs: block {} is corrupted", block_id);
+            return Err(VfsError::InvalidData);
+        }
+        Ok(())
+    }
+
+    fn read_at(&self, ino: u32, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let inode = &self.inodes[ino as usize];
+        if offset >= inode.size {
+            return Ok(0);
+        }
+        let len = buf.len().min((inode.size - offset) as usize);
+        let mut read = 0;
+        while read < len {
+            let pos = offset as usize + read;
+            let (idx, start) = (pos / self.block_size, pos % self.block_size);
+            let n = (self.block_size - start).min(len - read);
+            self.with_block(inode, idx, |data| {
+                buf[read..read + n].copy_from_slice(&data[start..start + n])
+            })?;
+            read += n;
+        }
+        Ok(len)
+    }
+
+    /// Reads all entries of a directory as `(name, ino)` pairs.
+    fn dir_entries(&self, ino: u32) -> VfsResult<Vec<(String, u32)>> {
+        let size = self.inodes[ino as usize].size as usize;
+        let mut data = vec![0u8; size];
+        self.read_at(ino, 0, &mut data)?;
+
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        while pos < size {
+            if pos + 8 > size {
+                return Err(VfsError::InvalidData);
+            }
+            let child = le_u32(&data, pos);
+            let name_len = le_u16(&data, pos + 4) as usize;
+            let name = data
+                .get(pos + 8..pos + 8 + name_len)
+                .and_then(|n| core::str::from_utf8(n).ok())
+                .ok_or(VfsError::InvalidData)?;
+            if child as usize >= self.inodes.len() {
+                return Err(VfsError::InvalidData);
+            }
+            entries.push((String::from(name), child));
+            pos += 8 + name_len;
+        }
+        Ok(entries)
+    }
+}
+
+impl VfsNodeOps for FileNode {
+    axfs_vfs::impl_vfs_non_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(self.fs.attr(self.ino))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.fs.read_at(self.ino, offset, buf)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        Ok(())
+    }
+}
+
+impl VfsNodeOps for DirNode {
+    axfs_vfs::impl_vfs_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(self.fs.attr(self.ino))
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        let parent = self.fs.inodes[self.ino as usize].parent;
+        Some(self.fs.node(parent))
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        debug!("lookup at rofs: {}", path);
+        let path = path.trim_matches('/');
+        let (name, rest) = path.split_once('/').unwrap_or((path, ""));
+        let node = match name {
+            "" | "." => self.clone() as VfsNodeRef,
+            ".." => self.parent().unwrap(),
+            _ => {
+                let entries = self.fs.dir_entries(self.ino)?;
+                let (_, ino) = entries
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .ok_or(VfsError::NotFound)?;
+                self.fs.node(*ino)
+            }
+        };
+        if rest.is_empty() {
+            Ok(node)
+        } else {
+            node.lookup(rest)
+        }
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        debug!("create {:?} at rofs: {}", ty, path);
+        // Creating an existing directory (e.g. a mount point) is a no-op.
+        match self.fs.node(self.ino).lookup(path) {
+            Ok(node) if node.get_attr()?.file_type() == ty => Ok(()),
+            Ok(_) => Err(VfsError::AlreadyExists),
+            Err(_) => Err(VfsError::PermissionDenied),
+        }
+    }
+
+    fn remove(&self, _path: &str) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn rename(&self, _src_path: &str, _dst_path: &str) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let entries = self.fs.dir_entries(self.ino)?;
+        let mut children = entries.iter().skip(start_idx.max(2) - 2);
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            match i + start_idx {
+                0 => *ent = VfsDirEntry::new(".", VfsNodeType::Dir),
+                1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
+                _ => {
+                    if let Some((name, ino)) = children.next() {
+                        *ent = VfsDirEntry::new(name, self.fs.inodes[*ino as usize].ty);
+                    } else {
+                        return Ok(i);
+                    }
+                }
+            }
+        }
+        Ok(dirents.len())
+    }
+}
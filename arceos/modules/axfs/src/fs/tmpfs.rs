@@ -0,0 +1,343 @@
+//! A sparse in-memory filesystem.
+//!
+//! Unlike [`axfs_ramfs`], file data is stored in pages which are only
+//! allocated when written (or preallocated), so files may have holes: a
+//! write beyond the end of file, or extending it with `truncate`, does not
+//! fill the gap with zeros, and reading a hole returns zeros without
+//! allocating. Space can also be preallocated and released again by
+//! punching holes, see [`FileNode::allocate`] and [`FileNode::punch_hole`].
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::{boxed::Box, string::String, vec, vec::Vec};
+
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsOps, VfsResult};
+use axsync::Mutex;
+
+/// Size of a data page of a file, in bytes.
+pub const PAGE_SIZE: usize = 4096;
+
+const PAGE: u64 = PAGE_SIZE as u64;
+
+/// A sparse in-memory filesystem, see the [module-level documentation](self).
+pub struct TmpFileSystem {
+    parent: Mutex<Option<VfsNodeRef>>,
+    root: Arc<DirNode>,
+}
+
+/// A directory node of [`TmpFileSystem`].
+pub struct DirNode {
+    this: Weak<DirNode>,
+    parent: Mutex<Weak<dyn VfsNodeOps>>,
+    children: Mutex<BTreeMap<String, VfsNodeRef>>,
+}
+
+/// A regular file node of [`TmpFileSystem`].
+pub struct FileNode {
+    data: Mutex<SparseData>,
+}
+
+#[derive(Default)]
+struct SparseData {
+    size: u64,
+    /// Allocated pages, by page index. Missing pages are holes.
+    pages: BTreeMap<u64, Box<[u8]>>,
+}
+
+impl TmpFileSystem {
+    /// Creates a new, empty filesystem.
+    pub fn new() -> Self {
+        Self {
+            parent: Mutex::new(None),
+            root: DirNode::new(Weak::<DirNode>::new()),
+        }
+    }
+}
+
+impl Default for TmpFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VfsOps for TmpFileSystem {
+    fn mount(&self, _path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        let parent = mount_point.parent();
+        self.root.set_parent(parent.as_ref());
+        *self.parent.lock() = parent; // keep it alive
+        Ok(())
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+impl crate::fs::FsUsage for TmpFileSystem {
+    fn usage(&self) -> VfsResult<crate::fops::FileSystemStat> {
+        Ok(crate::fops::FileSystemStat {
+            fs_type: "tmpfs",
+            block_size: PAGE,
+            name_max: 255,
+            ..Default::default()
+        })
+    }
+}
+
+impl DirNode {
+    fn new(parent: Weak<dyn VfsNodeOps>) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            parent: Mutex::new(parent),
+            children: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    fn set_parent(&self, parent: Option<&VfsNodeRef>) {
+        *self.parent.lock() = parent.map_or(Weak::<Self>::new() as _, Arc::downgrade);
+    }
+
+    fn create_node(&self, name: &str, ty: VfsNodeType) -> VfsResult {
+        let mut children = self.children.lock();
+        if children.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        let node: VfsNodeRef = match ty {
+            VfsNodeType::File => Arc::new(FileNode::new()),
+            VfsNodeType::Dir => Self::new(self.this.clone()),
+            _ => return Err(VfsError::Unsupported),
+        };
+        children.insert(name.into(), node);
+        Ok(())
+    }
+
+    fn remove_node(&self, name: &str) -> VfsResult {
+        let mut children = self.children.lock();
+        let node = children.get(name).ok_or(VfsError::NotFound)?;
+        if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+            if !dir.children.lock().is_empty() {
+                return Err(VfsError::DirectoryNotEmpty);
+            }
+        }
+        children.remove(name);
+        Ok(())
+    }
+}
+
+impl VfsNodeOps for DirNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new_dir(4096, 0))
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.parent.lock().upgrade()
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        let node = match name {
+            "" | "." => Ok(self.clone() as VfsNodeRef),
+            ".." => self.parent().ok_or(VfsError::NotFound),
+            _ => self
+                .children
+                .lock()
+                .get(name)
+                .cloned()
+                .ok_or(VfsError::NotFound),
+        }?;
+        match rest {
+            Some(rest) => node.lookup(rest),
+            None => Ok(node),
+        }
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let children = self.children.lock();
+        let mut children = children.iter().skip(start_idx.max(2) - 2);
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            match i + start_idx {
+                0 => *ent = VfsDirEntry::new(".", VfsNodeType::Dir),
+                1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
+                _ => match children.next() {
+                    Some((name, node)) => {
+                        *ent = VfsDirEntry::new(name, node.get_attr()?.file_type())
+                    }
+                    None => return Ok(i),
+                },
+            }
+        }
+        Ok(dirents.len())
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            match name {
+                "" | "." => self.create(rest, ty),
+                ".." => self.parent().ok_or(VfsError::NotFound)?.create(rest, ty),
+                _ => {
+                    let subdir = self.children.lock().get(name).cloned();
+                    subdir.ok_or(VfsError::NotFound)?.create(rest, ty)
+                }
+            }
+        } else if name.is_empty() || name == "." || name == ".." {
+            Ok(()) // already exists
+        } else {
+            self.create_node(name, ty)
+        }
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            match name {
+                "" | "." => self.remove(rest),
+                ".." => self.parent().ok_or(VfsError::NotFound)?.remove(rest),
+                _ => {
+                    let subdir = self.children.lock().get(name).cloned();
+                    subdir.ok_or(VfsError::NotFound)?.remove(rest)
+                }
+            }
+        } else if name.is_empty() || name == "." || name == ".." {
+            Err(VfsError::InvalidInput) // remove '.' or '..
+        } else {
+            self.remove_node(name)
+        }
+    }
+
+    axfs_vfs::impl_vfs_dir_default! {}
+}
+
+impl FileNode {
+    fn new() -> Self {
+        Self {
+            data: Mutex::new(SparseData::default()),
+        }
+    }
+
+    /// Allocates zeroed pages for the holes in `offset..offset + len`.
+    ///
+    /// The file is extended if the range ends beyond it, unless `keep_size`
+    /// is set.
+    pub fn allocate(&self, offset: u64, len: u64, keep_size: bool) -> VfsResult {
+        let end = offset.checked_add(len).ok_or(VfsError::InvalidInput)?;
+        let mut data = self.data.lock();
+        for idx in offset / PAGE..end.div_ceil(PAGE) {
+            data.pages.entry(idx).or_insert_with(zeroed_page);
+        }
+        if !keep_size {
+            data.size = data.size.max(end);
+        }
+        Ok(())
+    }
+
+    /// Deallocates the range `offset..offset + len`, which reads as zeros
+    /// afterwards. The file size is not changed.
+    pub fn punch_hole(&self, offset: u64, len: u64) -> VfsResult {
+        let end = offset.checked_add(len).ok_or(VfsError::InvalidInput)?;
+        self.data.lock().punch_hole(offset, end);
+        Ok(())
+    }
+}
+
+impl crate::fs::SparseFile for FileNode {
+    fn allocate(&self, offset: u64, len: u64, keep_size: bool) -> VfsResult {
+        FileNode::allocate(self, offset, len, keep_size)
+    }
+
+    fn punch_hole(&self, offset: u64, len: u64) -> VfsResult {
+        FileNode::punch_hole(self, offset, len)
+    }
+}
+
+impl SparseData {
+    fn punch_hole(&mut self, offset: u64, end: u64) {
+        let range = offset / PAGE..end.div_ceil(PAGE);
+        let idxs = self.pages.range(range).map(|(&idx, _)| idx);
+        for idx in idxs.collect::<Vec<_>>() {
+            let start = (idx * PAGE).max(offset) - idx * PAGE;
+            let stop = ((idx + 1) * PAGE).min(end) - idx * PAGE;
+            if stop - start == PAGE {
+                self.pages.remove(&idx);
+            } else if let Some(page) = self.pages.get_mut(&idx) {
+                page[start as usize..stop as usize].fill(0);
+            }
+        }
+    }
+}
+
+impl VfsNodeOps for FileNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let data = self.data.lock();
+        let blocks = data.pages.len() as u64 * (PAGE / 512);
+        Ok(VfsNodeAttr::new_file(data.size, blocks))
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        let mut data = self.data.lock();
+        if size < data.size {
+            data.punch_hole(size, u64::MAX);
+        }
+        data.size = size; // extending leaves a hole
+        Ok(())
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let data = self.data.lock();
+        if offset >= data.size {
+            return Ok(0);
+        }
+        let len = buf.len().min((data.size - offset) as usize);
+        let mut pos = 0;
+        while pos < len {
+            let off = offset + pos as u64;
+            let in_page = (off % PAGE) as usize;
+            let n = (PAGE_SIZE - in_page).min(len - pos);
+            match data.pages.get(&(off / PAGE)) {
+                Some(page) => buf[pos..pos + n].copy_from_slice(&page[in_page..in_page + n]),
+                None => buf[pos..pos + n].fill(0), // hole
+            }
+            pos += n;
+        }
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let end = offset
+            .checked_add(buf.len() as u64)
+            .ok_or(VfsError::InvalidInput)?;
+        let mut data = self.data.lock();
+        let mut pos = 0;
+        while pos < buf.len() {
+            let off = offset + pos as u64;
+            let in_page = (off % PAGE) as usize;
+            let n = (PAGE_SIZE - in_page).min(buf.len() - pos);
+            let page = data.pages.entry(off / PAGE).or_insert_with(zeroed_page);
+            page[in_page..in_page + n].copy_from_slice(&buf[pos..pos + n]);
+            pos += n;
+        }
+        data.size = data.size.max(end);
+        Ok(buf.len())
+    }
+
+    fn fsync(&self) -> VfsResult {
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+fn zeroed_page() -> Box<[u8]> {
+    vec![0; PAGE_SIZE].into_boxed_slice()
+}
+
+fn split_path(path: &str) -> (&str, Option<&str>) {
+    let trimmed_path = path.trim_start_matches('/');
+    trimmed_path.find('/').map_or((trimmed_path, None), |n| {
+        (&trimmed_path[..n], Some(&trimmed_path[n + 1..]))
+    })
+}
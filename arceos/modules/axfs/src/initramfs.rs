@@ -0,0 +1,102 @@
+//! Initial RAM filesystem unpacked from a CPIO archive.
+//!
+//! The archive must be in the "newc" format (`cpio -H newc`), which is also
+//! used by Linux initramfs. It can be embedded in the kernel image at build
+//! time by setting the `AX_INITRAMFS` environment variable (or `INITRAMFS`
+//! of the top-level Makefile) to its path.
+//!
+//! Only directories and regular files are supported; other entries (e.g.
+//! symbolic links and device nodes) are skipped with a warning.
+
+use axfs_vfs::{VfsError, VfsNodeRef, VfsNodeType, VfsResult};
+
+/// The archive embedded at build time, empty if none is given.
+pub(crate) static EMBEDDED: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/initramfs.cpio"));
+
+const HEADER_SIZE: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+struct Header {
+    mode: u32,
+    file_size: usize,
+    name_size: usize,
+}
+
+fn parse_hex(field: &[u8]) -> VfsResult<u32> {
+    let s = core::str::from_utf8(field).map_err(|_| VfsError::InvalidData)?;
+    u32::from_str_radix(s, 16).map_err(|_| VfsError::InvalidData)
+}
+
+fn parse_header(buf: &[u8]) -> VfsResult<Header> {
+    if buf.len() < HEADER_SIZE || !matches!(&buf[..6], b"070701" | b"070702") {
+        return Err(VfsError::InvalidData);
+    }
+    // The fields after the magic are 13 8-digit hex numbers: ino, mode,
+    // uid, gid, nlink, mtime, filesize, devmajor, devminor, rdevmajor,
+    // rdevminor, namesize and check.
+    let field = |i: usize| parse_hex(&buf[6 + i * 8..14 + i * 8]);
+    Ok(Header {
+        mode: field(1)?,
+        file_size: field(6)? as usize,
+        name_size: field(11)? as usize,
+    })
+}
+
+/// Unpacks a newc CPIO archive into the given directory, returns the number
+/// of entries in the archive.
+pub fn unpack(root: &VfsNodeRef, archive: &[u8]) -> VfsResult<usize> {
+    let align4 = |n: usize| (n + 3) & !3;
+    let mut pos = 0;
+    let mut count = 0;
+    loop {
+        let header = parse_header(archive.get(pos..).ok_or(VfsError::InvalidData)?)?;
+        let name_start = pos + HEADER_SIZE;
+        let data_start = align4(name_start + header.name_size);
+        let data_end = data_start + header.file_size;
+        if header.name_size == 0 || data_end > archive.len() {
+            return Err(VfsError::InvalidData);
+        }
+        // the name is NUL-terminated
+        let name = core::str::from_utf8(&archive[name_start..name_start + header.name_size - 1])
+            .map_err(|_| VfsError::InvalidData)?;
+        if name == TRAILER {
+            break;
+        }
+
+        let path = name.trim_start_matches("./").trim_matches('/');
+        if !path.is_empty() && path != "." {
+            match header.mode & S_IFMT {
+                S_IFDIR => create(root, path, VfsNodeType::Dir)?,
+                S_IFREG => {
+                    create(root, path, VfsNodeType::File)?;
+                    let file = root.clone().lookup(path)?;
+                    file.truncate(0)?;
+                    file.write_at(0, &archive[data_start..data_end])?;
+                }
+                _ => warn!("initramfs: skip unsupported entry {:?}", path),
+            }
+            count += 1;
+        }
+        pos = align4(data_end);
+    }
+    Ok(count)
+}
+
+/// Creates a node and all its missing parent directories.
+fn create(root: &VfsNodeRef, path: &str, ty: VfsNodeType) -> VfsResult {
+    for (i, _) in path.match_indices('/') {
+        create_one(root, &path[..i], VfsNodeType::Dir)?;
+    }
+    create_one(root, path, ty)
+}
+
+fn create_one(root: &VfsNodeRef, path: &str, ty: VfsNodeType) -> VfsResult {
+    match root.create(path, ty) {
+        Err(VfsError::AlreadyExists) => Ok(()),
+        res => res,
+    }
+}
@@ -0,0 +1,310 @@
+//! Block-layer I/O scheduler.
+//!
+//! All block I/O issued by the filesystems goes through an [`IoScheduler`]
+//! before reaching the device driver. It improves throughput in two ways:
+//!
+//! - Adjacent blocks are **merged** into a single device request (up to
+//!   [`MAX_MERGE_BLOCKS`] blocks), instead of one request per 512-byte block.
+//! - With write-back enabled (see [`set_max_pending_blocks`]), writes are
+//!   queued and later dispatched **sorted by LBA** in one C-SCAN sweep.
+//!   Overwrites of a queued block are absorbed in memory.
+//!
+//! Queued writes are kept per origin (the task that issued them). Under the
+//! [`Fair`](IoSchedPolicy::Fair) policy, when the queue overflows only the
+//! origin with the largest backlog is written back, so one bulk writer
+//! cannot force the data of all other tasks out with it.
+//!
+//! Write-back is disabled by default, since queued writes only reach the
+//! device on [`flush`](IoScheduler::flush) or overflow, and would be lost on
+//! power off.
+
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use core::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+use axdriver::prelude::*;
+
+/// Size of a block, in bytes.
+pub const BLOCK_SIZE: usize = 512;
+
+/// Maximum number of blocks merged into one device request.
+pub const MAX_MERGE_BLOCKS: usize = 256;
+
+/// The policy used to dispatch queued writes.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoSchedPolicy {
+    /// Dispatch all queued writes in one sweep sorted by LBA.
+    Elevator = 0,
+    /// Like `Elevator`, but on overflow only write back the origin with the
+    /// largest backlog.
+    Fair = 1,
+}
+
+/// I/O statistics of the block layer.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IoStats {
+    /// Number of blocks read by the filesystems.
+    pub blocks_read: u64,
+    /// Number of blocks written by the filesystems.
+    pub blocks_written: u64,
+    /// Number of read requests issued to the device.
+    pub dev_reads: u64,
+    /// Number of write requests issued to the device.
+    pub dev_writes: u64,
+    /// Number of queued block writes overwritten before dispatch.
+    pub absorbed_writes: u64,
+}
+
+struct AtomicIoStats {
+    blocks_read: AtomicU64,
+    blocks_written: AtomicU64,
+    dev_reads: AtomicU64,
+    dev_writes: AtomicU64,
+    absorbed_writes: AtomicU64,
+}
+
+static POLICY: AtomicU8 = AtomicU8::new(IoSchedPolicy::Elevator as u8);
+static MAX_PENDING_BLOCKS: AtomicUsize = AtomicUsize::new(0);
+static STATS: AtomicIoStats = AtomicIoStats {
+    blocks_read: AtomicU64::new(0),
+    blocks_written: AtomicU64::new(0),
+    dev_reads: AtomicU64::new(0),
+    dev_writes: AtomicU64::new(0),
+    absorbed_writes: AtomicU64::new(0),
+};
+
+/// Sets the policy used to dispatch queued writes.
+pub fn set_policy(policy: IoSchedPolicy) {
+    POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+/// Returns the policy used to dispatch queued writes.
+pub fn policy() -> IoSchedPolicy {
+    match POLICY.load(Ordering::Relaxed) {
+        1 => IoSchedPolicy::Fair,
+        _ => IoSchedPolicy::Elevator,
+    }
+}
+
+/// Sets the maximum number of written blocks that may be queued before they
+/// are dispatched to the device.
+///
+/// Zero disables write-back, i.e. every write goes to the device
+/// immediately (still merged).
+pub fn set_max_pending_blocks(num_blocks: usize) {
+    MAX_PENDING_BLOCKS.store(num_blocks, Ordering::Relaxed);
+}
+
+/// Returns the maximum number of written blocks that may be queued.
+pub fn max_pending_blocks() -> usize {
+    MAX_PENDING_BLOCKS.load(Ordering::Relaxed)
+}
+
+/// Returns the I/O statistics of the block layer.
+pub fn stats() -> IoStats {
+    IoStats {
+        blocks_read: STATS.blocks_read.load(Ordering::Relaxed),
+        blocks_written: STATS.blocks_written.load(Ordering::Relaxed),
+        dev_reads: STATS.dev_reads.load(Ordering::Relaxed),
+        dev_writes: STATS.dev_writes.load(Ordering::Relaxed),
+        absorbed_writes: STATS.absorbed_writes.load(Ordering::Relaxed),
+    }
+}
+
+#[cfg(feature = "crypt")]
+type Device = crate::crypt::CryptDevice;
+#[cfg(not(feature = "crypt"))]
+type Device = AxBlockDevice;
+
+type BlockMap = BTreeMap<u64, Box<[u8]>>;
+
+struct OriginQueue {
+    origin: u64,
+    blocks: BlockMap,
+}
+
+/// A block device with request merging and write queueing.
+pub struct IoScheduler {
+    dev: Device,
+    queues: Vec<OriginQueue>,
+    pending: usize,
+    /// The LBA following the last dispatched request, where the next C-SCAN
+    /// sweep starts.
+    head: u64,
+}
+
+impl IoScheduler {
+    /// Creates a new scheduler wrapping the given block device.
+    pub fn new(dev: AxBlockDevice) -> Self {
+        assert_eq!(BLOCK_SIZE, dev.block_size());
+        #[cfg(feature = "crypt")]
+        let dev = crate::crypt::CryptDevice::with_configured_key(dev);
+        Self {
+            dev,
+            queues: Vec::new(),
+            pending: 0,
+            head: 0,
+        }
+    }
+
+    /// Returns the number of blocks of the underlying device.
+    pub fn num_blocks(&self) -> u64 {
+        self.dev.num_blocks()
+    }
+
+    /// Reads consecutive blocks starting from `block_id`.
+    ///
+    /// The length of `buf` must be a multiple of [`BLOCK_SIZE`]. Queued
+    /// writes are taken into account.
+    pub fn read(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        for (i, chunk) in buf.chunks_mut(MAX_MERGE_BLOCKS * BLOCK_SIZE).enumerate() {
+            self.dev
+                .read_block(block_id + (i * MAX_MERGE_BLOCKS) as u64, chunk)?;
+            STATS.dev_reads.fetch_add(1, Ordering::Relaxed);
+        }
+        let num_blocks = (buf.len() / BLOCK_SIZE) as u64;
+        STATS.blocks_read.fetch_add(num_blocks, Ordering::Relaxed);
+
+        if self.pending > 0 {
+            let range = block_id..block_id + num_blocks;
+            for q in &self.queues {
+                for (&id, data) in q.blocks.range(range.clone()) {
+                    let off = (id - block_id) as usize * BLOCK_SIZE;
+                    buf[off..off + BLOCK_SIZE].copy_from_slice(data);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes consecutive blocks starting from `block_id` on behalf of
+    /// `origin`.
+    ///
+    /// The length of `buf` must be a multiple of [`BLOCK_SIZE`].
+    pub fn write(&mut self, origin: u64, block_id: u64, buf: &[u8]) -> DevResult {
+        let num_blocks = buf.len() / BLOCK_SIZE;
+        STATS
+            .blocks_written
+            .fetch_add(num_blocks as u64, Ordering::Relaxed);
+
+        let max_pending = max_pending_blocks();
+        if max_pending == 0 {
+            // write-through, but keep the ordering with queued writes
+            self.dispatch_all()?;
+            for (i, chunk) in buf.chunks(MAX_MERGE_BLOCKS * BLOCK_SIZE).enumerate() {
+                self.dev
+                    .write_block(block_id + (i * MAX_MERGE_BLOCKS) as u64, chunk)?;
+                STATS.dev_writes.fetch_add(1, Ordering::Relaxed);
+            }
+            return Ok(());
+        }
+
+        for (i, chunk) in buf.chunks_exact(BLOCK_SIZE).enumerate() {
+            self.enqueue(origin, block_id + i as u64, chunk);
+        }
+        while self.pending > max_pending {
+            match policy() {
+                IoSchedPolicy::Elevator => self.dispatch_all()?,
+                IoSchedPolicy::Fair => self.dispatch_largest()?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Dispatches all queued writes and flushes the device.
+    pub fn flush(&mut self) -> DevResult {
+        self.dispatch_all()?;
+        self.dev.flush()
+    }
+
+    fn enqueue(&mut self, origin: u64, block_id: u64, data: &[u8]) {
+        for q in self.queues.iter_mut() {
+            if q.blocks.remove(&block_id).is_some() {
+                STATS.absorbed_writes.fetch_add(1, Ordering::Relaxed);
+                self.pending -= 1;
+                break; // a block is queued at most once
+            }
+        }
+        let idx = match self.queues.iter().position(|q| q.origin == origin) {
+            Some(idx) => idx,
+            None => {
+                self.queues.push(OriginQueue {
+                    origin,
+                    blocks: BlockMap::new(),
+                });
+                self.queues.len() - 1
+            }
+        };
+        self.queues[idx].blocks.insert(block_id, data.into());
+        self.pending += 1;
+    }
+
+    fn dispatch_all(&mut self) -> DevResult {
+        if self.pending == 0 {
+            return Ok(());
+        }
+        let mut blocks = BlockMap::new();
+        for mut q in self.queues.drain(..) {
+            blocks.append(&mut q.blocks);
+        }
+        self.pending = 0;
+        self.dispatch(blocks)
+    }
+
+    fn dispatch_largest(&mut self) -> DevResult {
+        let Some((idx, _)) = self
+            .queues
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, q)| q.blocks.len())
+        else {
+            return Ok(());
+        };
+        let q = self.queues.swap_remove(idx);
+        debug!(
+            "iosched: write back {} blocks of origin {}",
+            q.blocks.len(),
+            q.origin
+        );
+        self.pending -= q.blocks.len();
+        self.dispatch(q.blocks)
+    }
+
+    /// Writes the given blocks to the device in one C-SCAN sweep starting at
+    /// `self.head`, merging consecutive blocks into one request.
+    fn dispatch(&mut self, blocks: BlockMap) -> DevResult {
+        let mut run_start = 0;
+        let mut run = Vec::with_capacity(MAX_MERGE_BLOCKS * BLOCK_SIZE);
+        let head = self.head;
+        let sweep = blocks.range(head..).chain(blocks.range(..head));
+        for (&id, data) in sweep {
+            let run_len = (run.len() / BLOCK_SIZE) as u64;
+            if !run.is_empty()
+                && (id != run_start + run_len || run_len as usize == MAX_MERGE_BLOCKS)
+            {
+                self.submit_write(run_start, &run)?;
+                run.clear();
+            }
+            if run.is_empty() {
+                run_start = id;
+            }
+            run.extend_from_slice(data);
+        }
+        if !run.is_empty() {
+            self.submit_write(run_start, &run)?;
+        }
+        Ok(())
+    }
+
+    fn submit_write(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
+        trace!(
+            "iosched: write {} blocks at {}",
+            buf.len() / BLOCK_SIZE,
+            block_id
+        );
+        self.dev.write_block(block_id, buf)?;
+        STATS.dev_writes.fetch_add(1, Ordering::Relaxed);
+        self.head = block_id + (buf.len() / BLOCK_SIZE) as u64;
+        Ok(())
+    }
+}
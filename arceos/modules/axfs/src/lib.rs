@@ -6,15 +6,38 @@
 //!
 //! - `fatfs`: Use [FAT] as the main filesystem and mount it on `/`. This feature
 //!    is **enabled** by default.
-//! - `devfs`: Mount [`axfs_devfs::DeviceFileSystem`] on `/dev`. This feature is
+//! - `rofs`: Use a compressed, checksummed read-only filesystem built by
+//!    `tools/mkrofs` as the main filesystem instead of FAT. This feature is
+//!    **disabled** by default, but overrides `fatfs` if both are enabled.
+//! - `initramfs`: Use a [`axfs_ramfs::RamFileSystem`] unpacked from the CPIO
+//!    archive embedded at build time as the main filesystem, see [`initramfs`].
+//!    The block device becomes optional and is mounted on `/mnt` if present.
+//!    This feature is **disabled** by default.
+//! - `devfs`: Mount [`axfs_devfs::DeviceFileSystem`] on `/dev`, with the
+//!    devices registered by other modules (see [`devices`]). This feature is
 //!    **enabled** by default.
+//! - `procfs`: Mount a [`axfs_ramfs::RamFileSystem`] on `/proc`, with the
+//!    files registered by other modules in `/proc/net`, `/proc/cpu`,
+//!    `/proc/boot` and `/proc/kernel` (see [`procfs`]). This feature is **enabled** by default.
 //! - `ramfs`: Mount [`axfs_ramfs::RamFileSystem`] on `/tmp`. This feature is
 //!    **enabled** by default.
+//! - `tmpfs`: Mount a sparse in-memory filesystem on `/tmp` instead, which
+//!    supports holes and [`fops::File::punch_hole`]. This feature is
+//!    **disabled** by default.
 //! - `myfs`: Allow users to define their custom filesystems to override the
 //!    default. In this case, [`MyFileSystemIf`] is required to be implemented
 //!    to create and initialize other filesystems. This feature is **disabled** by
 //!    by default, but it will override other filesystem selection features if
 //!    both are enabled.
+//! - `crypt`: Transparently encrypt the block device with XTS-AES, see
+//!    [`crypt`] for how the key is configured.
+//! - `measured-boot`: Measure the initramfs and check its signature before
+//!    unpacking it, see [`secure_boot`]. Implies `initramfs`.
+//! - `secure-boot`: Like `measured-boot`, but also refuse to boot with an
+//!    unsigned initramfs.
+//! - `multitask`: Use the current task as the origin of block I/O requests,
+//!    allowing the [`iosched`] to balance write-back between tasks, and allow
+//!    tasks to have their own root and working directories (see [`context`]).
 //!
 //! [FAT]: https://en.wikipedia.org/wiki/File_Allocation_Table
 //! [`MyFileSystemIf`]: fops::MyFileSystemIf
@@ -32,7 +55,19 @@ mod mounts;
 mod root;
 
 pub mod api;
+pub mod context;
+#[cfg(feature = "crypt")]
+pub mod crypt;
+pub mod dcache;
+pub mod devices;
 pub mod fops;
+#[cfg(feature = "initramfs")]
+pub mod initramfs;
+pub mod iosched;
+pub mod procfs;
+#[cfg(feature = "measured-boot")]
+pub mod secure_boot;
+pub mod xattr;
 
 use axdriver::{prelude::*, AxDeviceContainer};
 
@@ -40,7 +75,9 @@ use axdriver::{prelude::*, AxDeviceContainer};
 pub fn init_filesystems(mut blk_devs: AxDeviceContainer<AxBlockDevice>) {
     info!("Initialize filesystems...");
 
-    let dev = blk_devs.take_one().expect("No block device found!");
-    info!("  use block device 0: {:?}", dev.device_name());
-    self::root::init_rootfs(self::dev::Disk::new(dev));
+    let disk = blk_devs.take_one().map(|dev| {
+        info!("  use block device 0: {:?}", dev.device_name());
+        self::dev::Disk::new(dev)
+    });
+    self::root::init_rootfs(disk);
 }
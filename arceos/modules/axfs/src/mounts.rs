@@ -13,7 +13,9 @@ pub(crate) fn devfs() -> Arc<fs::devfs::DeviceFileSystem> {
     devfs.add("null", Arc::new(null));
     devfs.add("zero", Arc::new(zero));
     foo_dir.add("bar", Arc::new(bar));
-    Arc::new(devfs)
+    let devfs = Arc::new(devfs);
+    crate::devices::attach(devfs.clone());
+    devfs
 }
 
 #[cfg(feature = "ramfs")]
@@ -21,6 +23,25 @@ pub(crate) fn ramfs() -> Arc<fs::ramfs::RamFileSystem> {
     Arc::new(fs::ramfs::RamFileSystem::new())
 }
 
+#[cfg(feature = "tmpfs")]
+pub(crate) fn tmpfs() -> Arc<fs::tmpfs::TmpFileSystem> {
+    Arc::new(fs::tmpfs::TmpFileSystem::new())
+}
+
+#[cfg(feature = "initramfs")]
+pub(crate) fn initramfs() -> Arc<fs::ramfs::RamFileSystem> {
+    let ramfs = fs::ramfs::RamFileSystem::new();
+    #[cfg(feature = "measured-boot")]
+    if !crate::secure_boot::check_initramfs(crate::initramfs::EMBEDDED) {
+        return Arc::new(ramfs);
+    }
+    match crate::initramfs::unpack(&ramfs.root_dir(), crate::initramfs::EMBEDDED) {
+        Ok(n) => info!("  unpacked {} entries from initramfs", n),
+        Err(e) => warn!("  failed to unpack initramfs: {:?}", e),
+    }
+    Arc::new(ramfs)
+}
+
 #[cfg(feature = "procfs")]
 pub(crate) fn procfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
     let procfs = fs::ramfs::RamFileSystem::new();
@@ -40,6 +61,11 @@ pub(crate) fn procfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
     let file_over = proc_root.clone().lookup("./sys/vm/overcommit_memory")?;
     file_over.write_at(0, b"0\n")?;
 
+    // Create /proc/net, /proc/cpu, ..., where the generated files are mounted
+    for (dir, _) in crate::procfs::DIRS {
+        proc_root.create(dir, VfsNodeType::Dir)?;
+    }
+
     // Create /proc/self/stat
     proc_root.create("self", VfsNodeType::Dir)?;
     proc_root.create("self/stat", VfsNodeType::File)?;
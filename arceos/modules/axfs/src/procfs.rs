@@ -0,0 +1,213 @@
+//! Files of `/proc` generated by other modules when they are read.
+//!
+//! A module registers the generator of a file with [`register_file`], and,
+//! with the `procfs` feature, the file appears in its directory of
+//! [`DIRS`] with the text it returns at the time of each read. For example,
+//! the network module provides `/proc/net/tcp` this way.
+
+#![cfg_attr(not(feature = "procfs"), allow(dead_code))]
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef};
+use axfs_vfs::{VfsNodeType, VfsOps, VfsResult};
+use axsync::Mutex;
+
+/// Generates the content of a file.
+pub type Generator = fn() -> String;
+
+/// The directories of `/proc` with generated files, and their paths.
+pub const DIRS: &[(&str, &str)] = &[
+    ("net", "/proc/net"),
+    ("cpu", "/proc/cpu"),
+    ("boot", "/proc/boot"),
+    ("kernel", "/proc/kernel"),
+];
+
+/// The directory and name of each file, with its generator.
+type FileEntry = (&'static str, &'static str, Generator);
+
+static FILES: Mutex<Vec<FileEntry>> = Mutex::new(Vec::new());
+
+/// Adds `/proc/<dir>/<name>`, or replaces its generator. `dir` is one of
+/// [`DIRS`].
+pub fn register_file(dir: &'static str, name: &'static str, generate: Generator) {
+    debug_assert!(DIRS.iter().any(|(d, _)| *d == dir));
+    let mut files = FILES.lock();
+    match files.iter_mut().find(|(d, n, _)| *d == dir && *n == name) {
+        Some(file) => file.2 = generate,
+        None => files.push((dir, name, generate)),
+    }
+}
+
+/// Adds `/proc/net/<name>`, or replaces its generator.
+pub fn register_net_file(name: &'static str, generate: Generator) {
+    register_file("net", name, generate)
+}
+
+fn generated_file(dir: &str, name: &str) -> Option<Generator> {
+    let files = FILES.lock();
+    files
+        .iter()
+        .find(|(d, n, _)| *d == dir && *n == name)
+        .map(|&(_, _, f)| f)
+}
+
+/// The filesystem mounted on a directory of [`DIRS`], listing its registered
+/// files.
+pub(crate) struct GeneratedFileSystem {
+    parent: Mutex<Option<VfsNodeRef>>,
+    root: Arc<GeneratedDir>,
+}
+
+struct GeneratedDir {
+    name: &'static str,
+    parent: Mutex<Option<VfsNodeRef>>,
+}
+
+struct GeneratedFile(Generator);
+
+impl GeneratedFileSystem {
+    pub fn new(dir: &'static str) -> Self {
+        Self {
+            parent: Mutex::new(None),
+            root: Arc::new(GeneratedDir {
+                name: dir,
+                parent: Mutex::new(None),
+            }),
+        }
+    }
+}
+
+impl VfsOps for GeneratedFileSystem {
+    fn mount(&self, _path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        let parent = mount_point.parent();
+        *self.root.parent.lock() = parent.clone();
+        *self.parent.lock() = parent; // keep it alive
+        Ok(())
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+impl crate::fs::FsUsage for GeneratedFileSystem {
+    fn usage(&self) -> VfsResult<crate::fops::FileSystemStat> {
+        Ok(crate::fops::FileSystemStat {
+            fs_type: "proc",
+            name_max: 255,
+            read_only: true,
+            ..Default::default()
+        })
+    }
+}
+
+impl VfsNodeOps for GeneratedDir {
+    axfs_vfs::impl_vfs_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o555),
+            VfsNodeType::Dir,
+            0,
+            0,
+        ))
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.parent.lock().clone()
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let path = path.trim_matches('/');
+        let (name, rest) = path.split_once('/').unwrap_or((path, ""));
+        let node = match name {
+            "" | "." => self.clone() as VfsNodeRef,
+            ".." => self.parent().ok_or(VfsError::NotFound)?,
+            _ => {
+                let generate = generated_file(self.name, name).ok_or(VfsError::NotFound)?;
+                Arc::new(GeneratedFile(generate))
+            }
+        };
+        if rest.is_empty() {
+            Ok(node)
+        } else {
+            node.lookup(rest)
+        }
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        // Creating an existing directory (e.g. a mount point) is a no-op.
+        match path.trim_matches('/') {
+            "" | "." if ty == VfsNodeType::Dir => Ok(()),
+            name if generated_file(self.name, name).is_some() => Err(VfsError::AlreadyExists),
+            _ => Err(VfsError::PermissionDenied),
+        }
+    }
+
+    fn remove(&self, _path: &str) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn rename(&self, _src_path: &str, _dst_path: &str) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let files = FILES.lock();
+        let mut children = files
+            .iter()
+            .filter(|(dir, _, _)| *dir == self.name)
+            .skip(start_idx.max(2) - 2);
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            match i + start_idx {
+                0 => *ent = VfsDirEntry::new(".", VfsNodeType::Dir),
+                1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
+                _ => {
+                    if let Some((_, name, _)) = children.next() {
+                        *ent = VfsDirEntry::new(name, VfsNodeType::File);
+                    } else {
+                        return Ok(i);
+                    }
+                }
+            }
+        }
+        Ok(dirents.len())
+    }
+}
+
+impl VfsNodeOps for GeneratedFile {
+    axfs_vfs::impl_vfs_non_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        // like on Linux, the size is not known before reading
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o444),
+            VfsNodeType::File,
+            0,
+            0,
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let text = (self.0)();
+        let data = text.as_bytes();
+        let start = (offset as usize).min(data.len());
+        let len = buf.len().min(data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        Ok(())
+    }
+}
@@ -2,50 +2,72 @@
 //!
 //! TODO: it doesn't work very well if the mount points have containment relationships.
 
-use alloc::{string::String, sync::Arc, vec::Vec};
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
 use axerrno::{ax_err, AxError, AxResult};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
-use axsync::Mutex;
 use lazyinit::LazyInit;
 
-use crate::{api::FileType, fs, mounts};
+use crate::context::{self, FsContext};
+use crate::fops::FileSystemStat;
+use crate::fs::FsUsage;
+use crate::{api::FileType, dcache, fs, mounts, xattr};
 
-static CURRENT_DIR_PATH: Mutex<String> = Mutex::new(String::new());
-static CURRENT_DIR: LazyInit<Mutex<VfsNodeRef>> = LazyInit::new();
+/// A filesystem, with the usage interface of its backend if any.
+#[derive(Clone)]
+struct MountedFs {
+    vfs: Arc<dyn VfsOps>,
+    usage: Option<Arc<dyn FsUsage>>,
+}
 
 struct MountPoint {
     path: &'static str,
-    fs: Arc<dyn VfsOps>,
+    fs: MountedFs,
 }
 
 struct RootDirectory {
-    main_fs: Arc<dyn VfsOps>,
+    main_fs: MountedFs,
     mounts: Vec<MountPoint>,
 }
 
 static ROOT_DIR: LazyInit<Arc<RootDirectory>> = LazyInit::new();
 
+impl MountedFs {
+    fn new<T: VfsOps + FsUsage + 'static>(fs: Arc<T>) -> Self {
+        Self {
+            vfs: fs.clone(),
+            usage: Some(fs),
+        }
+    }
+
+    fn usage(&self) -> AxResult<FileSystemStat> {
+        match &self.usage {
+            Some(usage) => usage.usage(),
+            None => ax_err!(Unsupported),
+        }
+    }
+}
+
 impl MountPoint {
-    pub fn new(path: &'static str, fs: Arc<dyn VfsOps>) -> Self {
+    pub fn new(path: &'static str, fs: MountedFs) -> Self {
         Self { path, fs }
     }
 }
 
 impl Drop for MountPoint {
     fn drop(&mut self) {
-        self.fs.umount().ok();
+        self.fs.vfs.umount().ok();
     }
 }
 
 impl RootDirectory {
-    pub const fn new(main_fs: Arc<dyn VfsOps>) -> Self {
+    pub const fn new(main_fs: MountedFs) -> Self {
         Self {
             main_fs,
             mounts: Vec::new(),
         }
     }
 
-    pub fn mount(&mut self, path: &'static str, fs: Arc<dyn VfsOps>) -> AxResult {
+    pub fn mount(&mut self, path: &'static str, fs: MountedFs) -> AxResult {
         if path == "/" {
             return ax_err!(InvalidInput, "cannot mount root filesystem");
         }
@@ -56,8 +78,9 @@ impl RootDirectory {
             return ax_err!(InvalidInput, "mount point already exists");
         }
         // create the mount point in the main filesystem if it does not exist
-        self.main_fs.root_dir().create(path, FileType::Dir)?;
-        fs.mount(path, self.main_fs.root_dir().lookup(path)?)?;
+        let main_root = self.main_fs.vfs.root_dir();
+        main_root.create(path, FileType::Dir)?;
+        fs.vfs.mount(path, main_root.lookup(path)?)?;
         self.mounts.push(MountPoint::new(path, fs));
         Ok(())
     }
@@ -72,7 +95,7 @@ impl RootDirectory {
 
     fn lookup_mounted_fs<F, T>(&self, path: &str, f: F) -> AxResult<T>
     where
-        F: FnOnce(Arc<dyn VfsOps>, &str) -> AxResult<T>,
+        F: FnOnce(&MountedFs, &str) -> AxResult<T>,
     {
         debug!("lookup at root: {}", path);
         let path = path.trim_matches('/');
@@ -94,9 +117,9 @@ impl RootDirectory {
         }
 
         if max_len == 0 {
-            f(self.main_fs.clone(), path) // not matched any mount point
+            f(&self.main_fs, path) // not matched any mount point
         } else {
-            f(self.mounts[idx].fs.clone(), &path[max_len..]) // matched at `idx`
+            f(&self.mounts[idx].fs, &path[max_len..]) // matched at `idx`
         }
     }
 }
@@ -105,11 +128,11 @@ impl VfsNodeOps for RootDirectory {
     axfs_vfs::impl_vfs_dir_default! {}
 
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        self.main_fs.root_dir().get_attr()
+        self.main_fs.vfs.root_dir().get_attr()
     }
 
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
-        self.lookup_mounted_fs(path, |fs, rest_path| fs.root_dir().lookup(rest_path))
+        self.lookup_mounted_fs(path, |fs, rest_path| fs.vfs.root_dir().lookup(rest_path))
     }
 
     fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
@@ -117,7 +140,7 @@ impl VfsNodeOps for RootDirectory {
             if rest_path.is_empty() {
                 Ok(()) // already exists
             } else {
-                fs.root_dir().create(rest_path, ty)
+                fs.vfs.root_dir().create(rest_path, ty)
             }
         })
     }
@@ -127,7 +150,7 @@ impl VfsNodeOps for RootDirectory {
             if rest_path.is_empty() {
                 ax_err!(PermissionDenied) // cannot remove mount points
             } else {
-                fs.root_dir().remove(rest_path)
+                fs.vfs.root_dir().remove(rest_path)
             }
         })
     }
@@ -137,67 +160,157 @@ impl VfsNodeOps for RootDirectory {
             if rest_path.is_empty() {
                 ax_err!(PermissionDenied) // cannot rename mount points
             } else {
-                fs.root_dir().rename(rest_path, dst_path)
+                fs.vfs.root_dir().rename(rest_path, dst_path)
             }
         })
     }
 }
 
-pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
+/// Creates the filesystem on the block device.
+fn disk_fs(disk: crate::dev::Disk) -> AxResult<MountedFs> {
     cfg_if::cfg_if! {
         if #[cfg(feature = "myfs")] { // override the default filesystem
-            let main_fs = fs::myfs::new_myfs(disk);
+            let main_fs = MountedFs {
+                vfs: fs::myfs::new_myfs(disk),
+                usage: None,
+            };
+        } else if #[cfg(feature = "rofs")] {
+            let main_fs = MountedFs::new(Arc::new(fs::rofs::RoFileSystem::new(disk)?));
         } else if #[cfg(feature = "fatfs")] {
             static FAT_FS: LazyInit<Arc<fs::fatfs::FatFileSystem>> = LazyInit::new();
-            FAT_FS.init_once(Arc::new(fs::fatfs::FatFileSystem::new(disk)));
+            FAT_FS.init_once(Arc::new(fs::fatfs::FatFileSystem::new(disk)?));
             FAT_FS.init();
-            let main_fs = FAT_FS.clone();
+            let main_fs = MountedFs::new(FAT_FS.clone());
         }
     }
+    Ok(main_fs)
+}
+
+pub(crate) fn init_rootfs(disk: Option<crate::dev::Disk>) {
+    #[cfg(feature = "initramfs")]
+    let main_fs = MountedFs::new(mounts::initramfs());
+    #[cfg(not(feature = "initramfs"))]
+    let main_fs = match disk_fs(disk.expect("No block device found!")) {
+        Ok(main_fs) => main_fs,
+        #[cfg(feature = "ramfs")]
+        Err(e) => {
+            // a damaged disk should not take down the whole system
+            error!(
+                "failed to mount the block device: {:?}, use ramfs on / instead",
+                e
+            );
+            MountedFs::new(mounts::ramfs())
+        }
+        #[cfg(not(feature = "ramfs"))]
+        Err(e) => panic!("failed to mount the block device: {:?}", e),
+    };
 
     let mut root_dir = RootDirectory::new(main_fs);
 
+    // With initramfs, the block device is optional and mounted on /mnt
+    #[cfg(feature = "initramfs")]
+    if let Some(disk) = disk {
+        match disk_fs(disk) {
+            Ok(disk_fs) => root_dir
+                .mount("/mnt", disk_fs)
+                .expect("failed to mount block device at /mnt"),
+            Err(e) => error!("failed to mount the block device: {:?}", e),
+        }
+    }
+
     #[cfg(feature = "devfs")]
     root_dir
-        .mount("/dev", mounts::devfs())
+        .mount("/dev", MountedFs::new(mounts::devfs()))
         .expect("failed to mount devfs at /dev");
 
-    #[cfg(feature = "ramfs")]
+    #[cfg(feature = "tmpfs")]
+    root_dir
+        .mount("/tmp", MountedFs::new(mounts::tmpfs()))
+        .expect("failed to mount tmpfs at /tmp");
+
+    #[cfg(all(feature = "ramfs", not(feature = "tmpfs")))]
     root_dir
-        .mount("/tmp", mounts::ramfs())
+        .mount("/tmp", MountedFs::new(mounts::ramfs()))
         .expect("failed to mount ramfs at /tmp");
 
     // Mount another ramfs as procfs
     #[cfg(feature = "procfs")]
     root_dir // should not fail
-        .mount("/proc", mounts::procfs().unwrap())
+        .mount("/proc", MountedFs::new(mounts::procfs().unwrap()))
         .expect("fail to mount procfs at /proc");
+    #[cfg(feature = "procfs")]
+    for &(dir, path) in crate::procfs::DIRS {
+        let fs = crate::procfs::GeneratedFileSystem::new(dir);
+        if let Err(e) = root_dir.mount(path, MountedFs::new(Arc::new(fs))) {
+            warn!("failed to mount {}: {:?}", path, e);
+        }
+    }
 
     // Mount another ramfs as sysfs
     #[cfg(feature = "sysfs")]
     root_dir // should not fail
-        .mount("/sys", mounts::sysfs().unwrap())
+        .mount("/sys", MountedFs::new(mounts::sysfs().unwrap()))
         .expect("fail to mount sysfs at /sys");
 
     ROOT_DIR.init_once(Arc::new(root_dir));
-    CURRENT_DIR.init_once(Mutex::new(ROOT_DIR.clone()));
-    *CURRENT_DIR_PATH.lock() = "/".into();
+    context::init();
+}
+
+/// Returns the node where the lookup of `path` starts, and the path relative
+/// to it.
+fn resolve(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<(VfsNodeRef, String)> {
+    match dir {
+        Some(dir) if !path.starts_with('/') => Ok((dir.clone(), path.into())),
+        _ => Ok((ROOT_DIR.clone(), absolute_path(path)?)),
+    }
 }
 
-fn parent_node_of(dir: Option<&VfsNodeRef>, path: &str) -> VfsNodeRef {
+/// Prepends the root directory `root` (ending with '/') to the absolute
+/// `path`.
+fn join_root(root: &str, path: &str) -> String {
+    match root.trim_end_matches('/') {
+        "" => path.into(),
+        root if path == "/" => root.into(),
+        root => String::from(root) + path,
+    }
+}
+
+fn canonical_path_in(ctx: &FsContext, path: &str) -> String {
     if path.starts_with('/') {
-        ROOT_DIR.clone()
+        axfs_vfs::path::canonicalize(path)
     } else {
-        dir.cloned().unwrap_or_else(|| CURRENT_DIR.lock().clone())
+        axfs_vfs::path::canonicalize(&(ctx.cwd.clone() + path))
     }
 }
 
+/// Returns the canonical, absolute form of `path` as seen by the current
+/// task, i.e. relative to its root directory.
+pub(crate) fn canonical_path(path: &str) -> String {
+    canonical_path_in(&context::current(), path)
+}
+
+/// Returns the absolute path of `path` in the global namespace, i.e.
+/// including the root directory of the current task.
 pub(crate) fn absolute_path(path: &str) -> AxResult<String> {
-    if path.starts_with('/') {
-        Ok(axfs_vfs::path::canonicalize(path))
+    let ctx = context::current();
+    Ok(join_root(&ctx.root, &canonical_path_in(&ctx, path)))
+}
+
+/// Returns the absolute path if it is known, i.e. `path` does not depend on
+/// a directory other than the current one.
+pub(crate) fn absolute_path_at(dir: Option<&VfsNodeRef>, path: &str) -> Option<String> {
+    if dir.is_none() || path.starts_with('/') {
+        absolute_path(path).ok()
     } else {
-        let path = CURRENT_DIR_PATH.lock().clone() + path;
-        Ok(axfs_vfs::path::canonicalize(&path))
+        None
+    }
+}
+
+/// Invalidates the dentry cache after `path` is created, removed or renamed.
+fn invalidate_cache(dir: Option<&VfsNodeRef>, path: &str) {
+    match absolute_path_at(dir, path) {
+        Some(abs_path) => dcache::invalidate(&abs_path),
+        None => dcache::clear(),
     }
 }
 
@@ -205,7 +318,13 @@ pub(crate) fn lookup(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRe
     if path.is_empty() {
         return ax_err!(NotFound);
     }
-    let node = parent_node_of(dir, path).lookup(path)?;
+    let node = match dir {
+        Some(dir) if !path.starts_with('/') => dir.clone().lookup(path)?,
+        _ => {
+            let abs_path = absolute_path(path)?;
+            dcache::lookup_with(&abs_path, || ROOT_DIR.clone().lookup(&abs_path))?
+        }
+    };
     if path.ends_with('/') && !node.get_attr()?.is_dir() {
         ax_err!(NotADirectory)
     } else {
@@ -219,15 +338,21 @@ pub(crate) fn create_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsN
     } else if path.ends_with('/') {
         return ax_err!(NotADirectory);
     }
-    let parent = parent_node_of(dir, path);
-    parent.create(path, VfsNodeType::File)?;
-    parent.lookup(path)
+    let (parent, rel_path) = resolve(dir, path)?;
+    parent.create(&rel_path, VfsNodeType::File)?;
+    invalidate_cache(dir, path);
+    parent.lookup(&rel_path)
 }
 
 pub(crate) fn create_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     match lookup(dir, path) {
         Ok(_) => ax_err!(AlreadyExists),
-        Err(AxError::NotFound) => parent_node_of(dir, path).create(path, VfsNodeType::Dir),
+        Err(AxError::NotFound) => {
+            let (parent, rel_path) = resolve(dir, path)?;
+            parent.create(&rel_path, VfsNodeType::Dir)?;
+            invalidate_cache(dir, path);
+            Ok(())
+        }
         Err(e) => Err(e),
     }
 }
@@ -240,7 +365,13 @@ pub(crate) fn remove_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     } else if !attr.perm().owner_writable() {
         ax_err!(PermissionDenied)
     } else {
-        parent_node_of(dir, path).remove(path)
+        let (parent, rel_path) = resolve(dir, path)?;
+        parent.remove(&rel_path)?;
+        invalidate_cache(dir, path);
+        if let Some(abs_path) = absolute_path_at(dir, path) {
+            xattr::on_remove(&abs_path);
+        }
+        Ok(())
     }
 }
 
@@ -269,42 +400,77 @@ pub(crate) fn remove_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     } else if !attr.perm().owner_writable() {
         ax_err!(PermissionDenied)
     } else {
-        parent_node_of(dir, path).remove(path)
+        let (parent, rel_path) = resolve(dir, path)?;
+        parent.remove(&rel_path)?;
+        invalidate_cache(dir, path);
+        if let Some(abs_path) = absolute_path_at(dir, path) {
+            xattr::on_remove(&abs_path);
+        }
+        Ok(())
     }
 }
 
 pub(crate) fn current_dir() -> AxResult<String> {
-    Ok(CURRENT_DIR_PATH.lock().clone())
+    Ok(context::current().cwd)
 }
 
 pub(crate) fn set_current_dir(path: &str) -> AxResult {
-    let mut abs_path = absolute_path(path)?;
-    if !abs_path.ends_with('/') {
-        abs_path += "/";
-    }
-    if abs_path == "/" {
-        *CURRENT_DIR.lock() = ROOT_DIR.clone();
-        *CURRENT_DIR_PATH.lock() = "/".into();
-        return Ok(());
+    let mut ctx = context::current();
+    let mut cwd = canonical_path_in(&ctx, path);
+    if cwd != "/" {
+        let attr = lookup(None, &cwd)?.get_attr()?;
+        if !attr.is_dir() {
+            return ax_err!(NotADirectory);
+        } else if !attr.perm().owner_executable() {
+            return ax_err!(PermissionDenied);
+        }
+        cwd += "/";
     }
+    ctx.cwd = cwd;
+    context::set_current(ctx);
+    Ok(())
+}
 
-    let node = lookup(None, &abs_path)?;
-    let attr = node.get_attr()?;
-    if !attr.is_dir() {
-        ax_err!(NotADirectory)
-    } else if !attr.perm().owner_executable() {
-        ax_err!(PermissionDenied)
-    } else {
-        *CURRENT_DIR.lock() = node;
-        *CURRENT_DIR_PATH.lock() = abs_path;
-        Ok(())
+pub(crate) fn chroot(path: &str) -> AxResult {
+    if !lookup(None, path)?.get_attr()?.is_dir() {
+        return ax_err!(NotADirectory);
+    }
+    let mut ctx = context::current();
+    let mut root = absolute_path(path)?;
+    if !root.ends_with('/') {
+        root += "/";
     }
+    // keep the working directory if it is under the new root
+    let cwd = join_root(&ctx.root, &ctx.cwd);
+    ctx.cwd = match cwd.strip_prefix(root.trim_end_matches('/')) {
+        Some(rest) if rest.starts_with('/') => rest.into(),
+        _ => "/".into(),
+    };
+    ctx.root = root;
+    context::set_current(ctx);
+    Ok(())
+}
+
+pub(crate) fn mount_points() -> Vec<String> {
+    let mut paths = vec![String::from("/")];
+    paths.extend(ROOT_DIR.mounts.iter().map(|mp| String::from(mp.path)));
+    paths
+}
+
+pub(crate) fn statfs(path: &str) -> AxResult<FileSystemStat> {
+    lookup(None, path)?;
+    ROOT_DIR.lookup_mounted_fs(&absolute_path(path)?, |fs, _| fs.usage())
 }
 
 pub(crate) fn rename(old: &str, new: &str) -> AxResult {
-    if parent_node_of(None, new).lookup(new).is_ok() {
+    if lookup(None, new).is_ok() {
         warn!("dst file already exist, now remove it");
         remove_file(None, new)?;
     }
-    parent_node_of(None, old).rename(old, new)
+    let (old, new) = (absolute_path(old)?, absolute_path(new)?);
+    ROOT_DIR.rename(&old, &new)?;
+    dcache::invalidate(&old);
+    dcache::invalidate(&new);
+    xattr::on_rename(&old, &new);
+    Ok(())
 }
@@ -0,0 +1,111 @@
+//! Measurement and signature verification of the initramfs.
+//!
+//! The image is checked against a detached Ed25519 signature, given at build
+//! time by `AX_INITRAMFS_SIG` (the path to its 64 raw bytes), with the public
+//! key given by `AX_SECURE_BOOT_KEY` (in hex). Both are embedded in the
+//! kernel image.
+//!
+//! An image with a bad signature is never unpacked. With the `secure-boot`
+//! feature, unsigned images (or kernels without a public key) are refused as
+//! well, and the system halts.
+//!
+//! The SHA-256 digest of the image and the result of the check are shown in
+//! `/proc/boot/measurement`.
+
+use alloc::{format, string::String};
+
+use axcrypto::SHA256_DIGEST_SIZE;
+use lazyinit::LazyInit;
+
+static SIGNATURE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/initramfs.sig"));
+static PUBLIC_KEY: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/secure_boot.key"));
+
+/// The result of checking an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageStatus {
+    /// The signature is valid.
+    Verified,
+    /// The image has no signature.
+    Unsigned,
+    /// No public key is embedded in the kernel.
+    NoKey,
+    /// The signature does not match the image.
+    BadSignature,
+}
+
+impl ImageStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Verified => "verified",
+            Self::Unsigned => "unsigned",
+            Self::NoKey => "no-key",
+            Self::BadSignature => "bad-signature",
+        }
+    }
+}
+
+static MEASUREMENT: LazyInit<([u8; SHA256_DIGEST_SIZE], ImageStatus)> = LazyInit::new();
+
+/// Checks `image` against the embedded signature and public key.
+pub fn verify(image: &[u8]) -> ImageStatus {
+    let (Ok(key), Ok(sig)) = (PUBLIC_KEY.try_into(), SIGNATURE.try_into()) else {
+        return if PUBLIC_KEY.is_empty() {
+            ImageStatus::NoKey
+        } else {
+            ImageStatus::Unsigned
+        };
+    };
+    if axcrypto::ed25519_verify(key, image, sig) {
+        ImageStatus::Verified
+    } else {
+        ImageStatus::BadSignature
+    }
+}
+
+/// Measures and verifies the initramfs before it is unpacked, and returns
+/// whether it may be used. Halts the system if it is refused in secure mode.
+pub(crate) fn check_initramfs(image: &[u8]) -> bool {
+    let digest = axcrypto::sha256(image);
+    let status = verify(image);
+    MEASUREMENT.init_once((digest, status));
+    info!("  initramfs sha256 {}: {}", hex(&digest), status.as_str());
+    #[cfg(feature = "procfs")]
+    crate::procfs::register_file("boot", "measurement", proc_measurement);
+
+    match status {
+        ImageStatus::Verified => true,
+        ImageStatus::BadSignature if cfg!(feature = "secure-boot") => {
+            panic!("secure boot: bad signature of the initramfs")
+        }
+        ImageStatus::BadSignature => {
+            error!("  bad signature of the initramfs, leaving it out");
+            false
+        }
+        _ if cfg!(feature = "secure-boot") => {
+            panic!("secure boot: refusing the initramfs ({})", status.as_str())
+        }
+        _ => {
+            warn!("  the initramfs is not verified ({})", status.as_str());
+            true
+        }
+    }
+}
+
+/// Returns the measurement of the initramfs, if it has been checked.
+pub fn measurement() -> Option<([u8; SHA256_DIGEST_SIZE], ImageStatus)> {
+    MEASUREMENT.get().copied()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(feature = "procfs")]
+fn proc_measurement() -> String {
+    match measurement() {
+        Some((digest, status)) => {
+            format!("initramfs sha256:{} {}\n", hex(&digest), status.as_str())
+        }
+        None => String::new(),
+    }
+}
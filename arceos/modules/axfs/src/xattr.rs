@@ -0,0 +1,178 @@
+//! Extended attributes and security labels.
+//!
+//! The filesystems behind [`axfs_vfs`] have no notion of extended attributes,
+//! so they are kept in a table indexed by absolute path. Attributes follow
+//! their file on rename and are dropped on removal. Since the table lives in
+//! memory, attributes have the same lifetime as the files of RAM-backed
+//! filesystems (e.g. the ramfs on `/tmp`), but are lost on reboot for
+//! on-disk filesystems.
+//!
+//! Names must start with one of the `user.`, `trusted.`, `security.` or
+//! `system.` namespaces, as on Linux.
+//!
+//! The [`SECURITY_LABEL`] attribute is passed to an optional checker (see
+//! [`set_label_checker`]) every time a file or directory is opened, which
+//! can be used to experiment with mandatory access control.
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+use axerrno::{ax_err, AxResult};
+use axsync::Mutex;
+use cap_access::Cap;
+
+/// Name of the attribute holding the security label of a file.
+pub const SECURITY_LABEL: &str = "security.label";
+
+/// Maximum size of an attribute value, in bytes.
+pub const MAX_VALUE_SIZE: usize = 64 * 1024;
+
+const NAMESPACES: &[&str] = &["user.", "trusted.", "security.", "system."];
+
+/// How [`set`] treats an existing attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetMode {
+    /// Create the attribute or replace its value.
+    Any,
+    /// Fail with `AlreadyExists` if the attribute exists (`XATTR_CREATE`).
+    Create,
+    /// Fail with `NotFound` if the attribute does not exist
+    /// (`XATTR_REPLACE`).
+    Replace,
+}
+
+/// A function that decides whether a file with the given security label
+/// may be opened with the given capabilities.
+///
+/// `label` is `None` if the file has no label.
+pub type LabelChecker = fn(path: &str, label: Option<&[u8]>, cap: Cap) -> AxResult;
+
+type Attrs = BTreeMap<String, Vec<u8>>;
+
+static XATTRS: Mutex<BTreeMap<String, Attrs>> = Mutex::new(BTreeMap::new());
+static LABEL_CHECKER: Mutex<Option<LabelChecker>> = Mutex::new(None);
+
+fn check_name(name: &str) -> AxResult {
+    if NAMESPACES
+        .iter()
+        .any(|ns| name.len() > ns.len() && name.starts_with(ns))
+    {
+        Ok(())
+    } else {
+        ax_err!(InvalidInput, "invalid extended attribute name")
+    }
+}
+
+/// Returns the table key of the path, checking that it exists.
+fn key_of(path: &str) -> AxResult<String> {
+    crate::root::lookup(None, path)?;
+    let abs_path = crate::root::absolute_path(path)?;
+    Ok(match abs_path.trim_end_matches('/') {
+        "" => "/".into(),
+        p => p.into(),
+    })
+}
+
+/// Returns the value of an extended attribute of the file.
+pub fn get(path: &str, name: &str) -> AxResult<Vec<u8>> {
+    check_name(name)?;
+    let key = key_of(path)?;
+    match XATTRS.lock().get(&key).and_then(|attrs| attrs.get(name)) {
+        Some(value) => Ok(value.clone()),
+        None => ax_err!(NotFound, "no such extended attribute"),
+    }
+}
+
+/// Sets an extended attribute of the file.
+pub fn set(path: &str, name: &str, value: &[u8], mode: SetMode) -> AxResult {
+    check_name(name)?;
+    if value.len() > MAX_VALUE_SIZE {
+        return ax_err!(InvalidInput, "extended attribute value too large");
+    }
+    let key = key_of(path)?;
+    let mut xattrs = XATTRS.lock();
+    let attrs = xattrs.entry(key).or_default();
+    match (mode, attrs.contains_key(name)) {
+        (SetMode::Create, true) => ax_err!(AlreadyExists),
+        (SetMode::Replace, false) => ax_err!(NotFound, "no such extended attribute"),
+        _ => {
+            attrs.insert(name.into(), value.into());
+            Ok(())
+        }
+    }
+}
+
+/// Returns the names of all extended attributes of the file.
+pub fn list(path: &str) -> AxResult<Vec<String>> {
+    let key = key_of(path)?;
+    Ok(XATTRS
+        .lock()
+        .get(&key)
+        .map(|attrs| attrs.keys().cloned().collect())
+        .unwrap_or_default())
+}
+
+/// Removes an extended attribute of the file.
+pub fn remove(path: &str, name: &str) -> AxResult {
+    check_name(name)?;
+    let key = key_of(path)?;
+    let mut xattrs = XATTRS.lock();
+    let attrs = xattrs.get_mut(&key);
+    match attrs.and_then(|attrs| attrs.remove(name)) {
+        Some(_) => {
+            xattrs.retain(|_, attrs| !attrs.is_empty());
+            Ok(())
+        }
+        None => ax_err!(NotFound, "no such extended attribute"),
+    }
+}
+
+/// Sets the function used to check security labels on open, or removes it
+/// if `None`.
+pub fn set_label_checker(checker: Option<LabelChecker>) {
+    *LABEL_CHECKER.lock() = checker;
+}
+
+/// Calls the label checker, if any, for the file at the absolute path.
+pub(crate) fn check_label(abs_path: &str, cap: Cap) -> AxResult {
+    let Some(checker) = *LABEL_CHECKER.lock() else {
+        return Ok(());
+    };
+    let key = abs_path.trim_end_matches('/');
+    let label = XATTRS
+        .lock()
+        .get(if key.is_empty() { "/" } else { key })
+        .and_then(|attrs| attrs.get(SECURITY_LABEL).cloned());
+    checker(abs_path, label.as_deref(), cap)
+}
+
+/// Returns the table keys of the path and all paths under it.
+fn keys_under(xattrs: &BTreeMap<String, Attrs>, path: &str) -> Vec<String> {
+    let prefix = String::from(path) + "/";
+    xattrs
+        .keys()
+        .filter(|k| *k == path || k.starts_with(&prefix))
+        .cloned()
+        .collect()
+}
+
+/// Drops the attributes of a removed file or directory.
+pub(crate) fn on_remove(abs_path: &str) {
+    let path = abs_path.trim_end_matches('/');
+    let mut xattrs = XATTRS.lock();
+    for k in keys_under(&xattrs, path) {
+        xattrs.remove(&k);
+    }
+}
+
+/// Moves the attributes of a renamed file or directory.
+pub(crate) fn on_rename(old_abs_path: &str, new_abs_path: &str) {
+    let (old, new) = (
+        old_abs_path.trim_end_matches('/'),
+        new_abs_path.trim_end_matches('/'),
+    );
+    let mut xattrs = XATTRS.lock();
+    for k in keys_under(&xattrs, old) {
+        let attrs = xattrs.remove(&k).unwrap();
+        xattrs.insert(String::from(new) + &k[old.len()..], attrs);
+    }
+}
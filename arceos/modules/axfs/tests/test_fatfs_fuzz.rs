@@ -0,0 +1,74 @@
+#![cfg(all(feature = "fatfs", not(any(feature = "myfs", feature = "rofs"))))]
+
+//! Feeds randomly corrupted FAT images to the driver, which must report
+//! errors instead of panicking.
+
+use axdriver_block::ramdisk::RamDisk;
+use axfs::fops::check_fat_image;
+
+const IMG_PATH: &str = "resources/fat16.img";
+
+/// Only the first bytes are corrupted, where the boot sector, the FATs and
+/// the root directory are.
+const METADATA_SIZE: usize = 128 * 1024;
+const ROUNDS: usize = 300;
+
+/// A xorshift generator, so that failures are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+fn corrupt(image: &mut Vec<u8>, rng: &mut Rng) {
+    let metadata_size = METADATA_SIZE.min(image.len());
+    match rng.below(8) {
+        // truncate the image
+        0 => image.truncate(rng.below(image.len() / 512 + 1) * 512),
+        // zero a whole sector
+        1 => {
+            let start = rng.below(metadata_size / 512) * 512;
+            image[start..start + 512].fill(0);
+        }
+        // flip random bytes
+        _ => {
+            for _ in 0..=rng.below(16) {
+                let pos = rng.below(metadata_size);
+                image[pos] = rng.next() as u8;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_fatfs_fuzz() {
+    let path = std::env::current_dir().unwrap().join(IMG_PATH);
+    let image = std::fs::read(path).expect("failed to load disk image");
+    check_fat_image(RamDisk::from(&image)).expect("the original image is corrupted");
+
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    let mut errors = 0;
+    for round in 0..ROUNDS {
+        let mut corrupted = image.clone();
+        corrupt(&mut corrupted, &mut rng);
+        match check_fat_image(RamDisk::from(&corrupted)) {
+            Err(_) => errors += 1,
+            // the check only reads the image, so it must accept it again
+            Ok(()) => assert!(
+                check_fat_image(RamDisk::from(&corrupted)).is_ok(),
+                "round {}: the image is accepted only once",
+                round
+            ),
+        }
+    }
+    assert!(errors > 0, "no corrupted image is rejected");
+}
@@ -18,6 +18,18 @@ const BUILTIN_PLATFORM_FAMILIES: &[&str] = &[
     "x86-pc",
 ];
 
+/// The unwind tables kept with the `unwind` feature, with the symbols used by
+/// the unwinder to find them (`fde-static` of the `unwinding` crate).
+const EH_FRAME: &str = r#"
+    .eh_frame : ALIGN(8) {
+        __eh_frame = .;
+        KEEP(*(.eh_frame .eh_frame.*))
+        LONG(0)
+    }
+    __executable_start = _stext;
+    __etext = _etext;
+"#;
+
 fn make_cfg_values(str_list: &[&str]) -> String {
     str_list
         .iter()
@@ -61,6 +73,11 @@ fn gen_linker_script(arch: &str, platform: &str) -> Result<()> {
         &format!("{:#x}", axconfig::KERNEL_BASE_VADDR),
     );
     let ld_content = ld_content.replace("%SMP%", &format!("{}", axconfig::SMP));
+    let ld_content = if std::env::var("CARGO_FEATURE_UNWIND").is_ok() {
+        ld_content.replace("%EH_FRAME%", EH_FRAME)
+    } else {
+        ld_content.replace("%EH_FRAME%", "")
+    };
 
     // target/<target_triple>/<mode>/build/axhal-xxxx/out
     let out_dir = std::env::var("OUT_DIR").unwrap();
@@ -15,6 +15,13 @@ pub struct TrapFrame {
     pub spsr: u64,
 }
 
+/// Size of the shadow call stack of a task, with the `shadow-call-stack`
+/// feature.
+///
+/// It only holds return addresses, 8 bytes per call, against at least 16
+/// bytes of stack for each frame that saves one.
+pub const SHADOW_CALL_STACK_SIZE: usize = axconfig::TASK_STACK_SIZE / 8;
+
 /// FP & SIMD registers.
 #[repr(C, align(16))]
 #[derive(Debug, Default)]
@@ -41,6 +48,7 @@ impl FpState {
 /// - Callee-saved registers
 /// - Stack pointer register
 /// - Thread pointer register (for thread-local storage, currently unsupported)
+/// - Shadow call stack pointer (X18)
 /// - FP/SIMD registers
 ///
 /// On context switch, current task saves its context from CPU to memory,
@@ -63,6 +71,7 @@ pub struct TaskContext {
     pub r28: u64,
     pub r29: u64,
     pub lr: u64, // r30
+    pub r18: u64,
     #[cfg(feature = "fp_simd")]
     pub fp_state: FpState,
 }
@@ -81,6 +90,13 @@ impl TaskContext {
         self.tpidr_el0 = tls_area.as_usize() as u64;
     }
 
+    /// Sets the shadow call stack of a new task, which grows upwards from
+    /// `base`.
+    #[cfg(feature = "shadow-call-stack")]
+    pub fn set_shadow_call_stack(&mut self, base: VirtAddr) {
+        self.r18 = base.as_usize() as u64;
+    }
+
     /// Switches to another task.
     ///
     /// It first saves the current task's context from CPU to this place, and then
@@ -106,6 +122,7 @@ unsafe extern "C" fn context_switch(_current_task: &mut TaskContext, _next_task:
         mov     x19, sp
         mrs     x20, tpidr_el0
         stp     x19, x20, [x0]
+        str     x18, [x0, 14 * 8]
 
         // restore new context
         ldp     x19, x20, [x1]
@@ -117,6 +134,7 @@ unsafe extern "C" fn context_switch(_current_task: &mut TaskContext, _next_task:
         ldp     x25, x26, [x1, 8 * 8]
         ldp     x27, x28, [x1, 10 * 8]
         ldp     x29, x30, [x1, 12 * 8]
+        ldr     x18, [x1, 14 * 8]
 
         ret",
         options(noreturn),
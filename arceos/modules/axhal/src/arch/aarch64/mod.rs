@@ -7,7 +7,7 @@ use aarch64_cpu::registers::{DAIF, TPIDR_EL0, TTBR0_EL1, TTBR1_EL1, VBAR_EL1};
 use memory_addr::{PhysAddr, VirtAddr};
 use tock_registers::interfaces::{Readable, Writeable};
 
-pub use self::context::{FpState, TaskContext, TrapFrame};
+pub use self::context::{FpState, TaskContext, TrapFrame, SHADOW_CALL_STACK_SIZE};
 
 /// Allows the current CPU to respond to interrupts.
 #[inline]
@@ -32,6 +32,9 @@ pub fn irqs_enabled() -> bool {
 /// It must be called with interrupts enabled, otherwise it will never return.
 #[inline]
 pub fn wait_for_irqs() {
+    #[cfg(feature = "replay")]
+    crate::replay::wait_for_irqs(aarch64_cpu::asm::wfi);
+    #[cfg(not(feature = "replay"))]
     aarch64_cpu::asm::wfi();
 }
 
@@ -87,6 +87,37 @@ impl TrapFrame {
     }
 }
 
+/// FP registers (F and D extensions).
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct FpState {
+    /// 64-bit FP registers (f0..f31)
+    pub regs: [u64; 32],
+    /// Floating-point Control and Status Register (FCSR)
+    pub fcsr: u32,
+}
+
+impl FpState {
+    /// Creates the initial FP state of a task, with all registers zeroed.
+    pub const fn new() -> Self {
+        Self {
+            regs: [0; 32],
+            fcsr: 0,
+        }
+    }
+}
+
+#[cfg(feature = "fp-lazy")]
+impl FpState {
+    pub(crate) fn save(&mut self) {
+        unsafe { fpstate_save(self) }
+    }
+
+    pub(crate) fn restore(&self) {
+        unsafe { fpstate_restore(self) }
+    }
+}
+
 /// Saved hardware states of a task.
 ///
 /// The context usually includes:
@@ -123,7 +154,10 @@ pub struct TaskContext {
     /// The `satp` register value, i.e., the page table root.
     #[cfg(feature = "uspace")]
     pub satp: PhysAddr,
-    // TODO: FP states
+    /// FP registers, only switched with `fp-lazy`.
+    // TODO: switch them eagerly with `fp_simd` only.
+    #[cfg(feature = "fp-lazy")]
+    pub fp_state: FpState,
 }
 
 impl TaskContext {
@@ -135,11 +169,13 @@ impl TaskContext {
     /// [`init`]: TaskContext::init
     /// [`switch_to`]: TaskContext::switch_to
     pub fn new() -> Self {
-        Self {
-            #[cfg(feature = "uspace")]
-            satp: crate::paging::kernel_page_table_root(),
-            ..Default::default()
+        #[allow(unused_mut)]
+        let mut ctx = Self::default();
+        #[cfg(feature = "uspace")]
+        {
+            ctx.satp = crate::paging::kernel_page_table_root();
         }
+        ctx
     }
 
     /// Initializes the context for a new task, with the given entry point and
@@ -177,10 +213,16 @@ impl TaskContext {
                 super::write_page_table_root(next_ctx.satp);
             }
         }
-        unsafe {
-            // TODO: switch FP states
-            context_switch(self, next_ctx)
-        }
+        #[cfg(feature = "fp-lazy")]
+        crate::fp::switch(&mut self.fp_state, &next_ctx.fp_state);
+        unsafe { context_switch(self, next_ctx) }
+    }
+}
+
+#[cfg(feature = "fp-lazy")]
+impl Drop for TaskContext {
+    fn drop(&mut self) {
+        crate::fp::forget(&self.fp_state);
     }
 }
 
@@ -321,3 +363,91 @@ unsafe extern "C" fn context_switch(_current_task: &mut TaskContext, _next_task:
         options(noreturn),
     )
 }
+
+#[naked]
+#[cfg(feature = "fp-lazy")]
+unsafe extern "C" fn fpstate_save(_fpstate: &mut FpState) {
+    asm!(
+        "
+        fsd     f0, 0 * 8(a0)
+        fsd     f1, 1 * 8(a0)
+        fsd     f2, 2 * 8(a0)
+        fsd     f3, 3 * 8(a0)
+        fsd     f4, 4 * 8(a0)
+        fsd     f5, 5 * 8(a0)
+        fsd     f6, 6 * 8(a0)
+        fsd     f7, 7 * 8(a0)
+        fsd     f8, 8 * 8(a0)
+        fsd     f9, 9 * 8(a0)
+        fsd     f10, 10 * 8(a0)
+        fsd     f11, 11 * 8(a0)
+        fsd     f12, 12 * 8(a0)
+        fsd     f13, 13 * 8(a0)
+        fsd     f14, 14 * 8(a0)
+        fsd     f15, 15 * 8(a0)
+        fsd     f16, 16 * 8(a0)
+        fsd     f17, 17 * 8(a0)
+        fsd     f18, 18 * 8(a0)
+        fsd     f19, 19 * 8(a0)
+        fsd     f20, 20 * 8(a0)
+        fsd     f21, 21 * 8(a0)
+        fsd     f22, 22 * 8(a0)
+        fsd     f23, 23 * 8(a0)
+        fsd     f24, 24 * 8(a0)
+        fsd     f25, 25 * 8(a0)
+        fsd     f26, 26 * 8(a0)
+        fsd     f27, 27 * 8(a0)
+        fsd     f28, 28 * 8(a0)
+        fsd     f29, 29 * 8(a0)
+        fsd     f30, 30 * 8(a0)
+        fsd     f31, 31 * 8(a0)
+        frcsr   t0
+        sw      t0, 32 * 8(a0)
+        ret",
+        options(noreturn),
+    )
+}
+
+#[naked]
+#[cfg(feature = "fp-lazy")]
+unsafe extern "C" fn fpstate_restore(_fpstate: &FpState) {
+    asm!(
+        "
+        fld     f0, 0 * 8(a0)
+        fld     f1, 1 * 8(a0)
+        fld     f2, 2 * 8(a0)
+        fld     f3, 3 * 8(a0)
+        fld     f4, 4 * 8(a0)
+        fld     f5, 5 * 8(a0)
+        fld     f6, 6 * 8(a0)
+        fld     f7, 7 * 8(a0)
+        fld     f8, 8 * 8(a0)
+        fld     f9, 9 * 8(a0)
+        fld     f10, 10 * 8(a0)
+        fld     f11, 11 * 8(a0)
+        fld     f12, 12 * 8(a0)
+        fld     f13, 13 * 8(a0)
+        fld     f14, 14 * 8(a0)
+        fld     f15, 15 * 8(a0)
+        fld     f16, 16 * 8(a0)
+        fld     f17, 17 * 8(a0)
+        fld     f18, 18 * 8(a0)
+        fld     f19, 19 * 8(a0)
+        fld     f20, 20 * 8(a0)
+        fld     f21, 21 * 8(a0)
+        fld     f22, 22 * 8(a0)
+        fld     f23, 23 * 8(a0)
+        fld     f24, 24 * 8(a0)
+        fld     f25, 25 * 8(a0)
+        fld     f26, 26 * 8(a0)
+        fld     f27, 27 * 8(a0)
+        fld     f28, 28 * 8(a0)
+        fld     f29, 29 * 8(a0)
+        fld     f30, 30 * 8(a0)
+        fld     f31, 31 * 8(a0)
+        lw      t0, 32 * 8(a0)
+        fscsr   t0
+        ret",
+        options(noreturn),
+    )
+}
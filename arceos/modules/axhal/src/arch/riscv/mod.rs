@@ -10,7 +10,7 @@ use riscv::register::{satp, sstatus, stvec};
 
 #[cfg(feature = "uspace")]
 pub use self::context::UspaceContext;
-pub use self::context::{GeneralRegisters, TaskContext, TrapFrame};
+pub use self::context::{FpState, GeneralRegisters, TaskContext, TrapFrame};
 
 /// Allows the current CPU to respond to interrupts.
 #[inline]
@@ -35,6 +35,9 @@ pub fn irqs_enabled() -> bool {
 /// It must be called with interrupts enabled, otherwise it will never return.
 #[inline]
 pub fn wait_for_irqs() {
+    #[cfg(feature = "replay")]
+    crate::replay::wait_for_irqs(riscv::asm::wfi);
+    #[cfg(not(feature = "replay"))]
     riscv::asm::wfi()
 }
 
@@ -4,6 +4,10 @@ use riscv::register::stval;
 
 use super::TrapFrame;
 
+/// The `FS` field of `sstatus`, the state of the FP unit.
+#[cfg(feature = "fp-lazy")]
+const SSTATUS_FS: usize = 0b11 << 13;
+
 include_asm_marcos!();
 
 core::arch::global_asm!(
@@ -48,6 +52,11 @@ fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
             handle_page_fault(tf, MappingFlags::EXECUTE, from_user)
         }
         Trap::Exception(E::Breakpoint) => handle_breakpoint(&mut tf.sepc),
+        #[cfg(feature = "fp-lazy")]
+        Trap::Exception(E::IllegalInstruction) if tf.sstatus & SSTATUS_FS == 0 => {
+            // Maybe an FP instruction, retried with the FP unit enabled.
+            crate::fp::handle_unavailable()
+        }
         Trap::Interrupt(_) => {
             handle_trap!(IRQ, scause.bits());
         }
@@ -60,4 +69,12 @@ fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
             );
         }
     }
+    // Keep the FP unit as the handler left it (e.g. disabled by a task
+    // switch), instead of as it was when the trap occurred.
+    #[cfg(feature = "fp-lazy")]
+    {
+        let sstatus: usize;
+        unsafe { core::arch::asm!("csrr {}, sstatus", out(reg) sstatus) };
+        tf.sstatus = (tf.sstatus & !SSTATUS_FS) | (sstatus & SSTATUS_FS);
+    }
 }
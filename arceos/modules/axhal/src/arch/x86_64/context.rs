@@ -85,16 +85,16 @@ pub struct ExtendedState {
 #[cfg(feature = "fp_simd")]
 impl ExtendedState {
     #[inline]
-    fn save(&mut self) {
+    pub(crate) fn save(&mut self) {
         unsafe { core::arch::x86_64::_fxsave64(&mut self.fxsave_area as *mut _ as *mut u8) }
     }
 
     #[inline]
-    fn restore(&self) {
+    pub(crate) fn restore(&self) {
         unsafe { core::arch::x86_64::_fxrstor64(&self.fxsave_area as *const _ as *const u8) }
     }
 
-    const fn default() -> Self {
+    pub(crate) const fn default() -> Self {
         let mut area: FxsaveArea = unsafe { core::mem::MaybeUninit::zeroed().assume_init() };
         area.fcw = 0x37f;
         area.ftw = 0xffff;
@@ -182,7 +182,9 @@ impl TaskContext {
     /// It first saves the current task's context from CPU to this place, and then
     /// restores the next task's context from `next_ctx` to CPU.
     pub fn switch_to(&mut self, next_ctx: &Self) {
-        #[cfg(feature = "fp_simd")]
+        #[cfg(feature = "fp-lazy")]
+        crate::fp::switch(&mut self.ext_state, &next_ctx.ext_state);
+        #[cfg(all(feature = "fp_simd", not(feature = "fp-lazy")))]
         {
             self.ext_state.save();
             next_ctx.ext_state.restore();
@@ -196,6 +198,13 @@ impl TaskContext {
     }
 }
 
+#[cfg(feature = "fp-lazy")]
+impl Drop for TaskContext {
+    fn drop(&mut self) {
+        crate::fp::forget(&self.ext_state);
+    }
+}
+
 #[naked]
 unsafe extern "C" fn context_switch(_current_stack: &mut u64, _next_stack: &u64) {
     asm!(
@@ -31,6 +31,8 @@ fn x86_trap_handler(tf: &TrapFrame) {
     match tf.vector as u8 {
         PAGE_FAULT_VECTOR => handle_page_fault(tf),
         BREAKPOINT_VECTOR => debug!("#BP @ {:#x} ", tf.rip),
+        #[cfg(feature = "fp-lazy")]
+        DEVICE_NOT_AVAILABLE_VECTOR => crate::fp::handle_unavailable(),
         GENERAL_PROTECTION_FAULT_VECTOR => {
             panic!(
                 "#GP @ {:#x}, error_code={:#x}:\n{:#x?}",
@@ -0,0 +1,83 @@
+//! The kernel command line.
+//!
+//! It is taken from the bootloader at boot: `/chosen/bootargs` in the device
+//! tree, the multiboot command line on x86, or the arguments of the process
+//! on the hosted platform. If the bootloader gives none, the one set at build
+//! time by `AX_CMDLINE` is used.
+//!
+//! It is a list of parameters separated by spaces, each either `key=value`
+//! or just `key`.
+
+use lazyinit::LazyInit;
+
+/// The maximum length of the command line, longer ones are truncated.
+pub const CMDLINE_MAX: usize = 1024;
+
+struct Cmdline {
+    buf: [u8; CMDLINE_MAX],
+    len: usize,
+}
+
+static CMDLINE: LazyInit<Cmdline> = LazyInit::new();
+
+/// Returns the kernel command line.
+pub fn cmdline() -> &'static str {
+    match CMDLINE.get() {
+        Some(c) if c.len > 0 => core::str::from_utf8(&c.buf[..c.len]).unwrap_or(""),
+        _ => option_env!("AX_CMDLINE").unwrap_or(""),
+    }
+}
+
+/// Returns the value of the parameter `key`, the last one if given several
+/// times. A parameter without `=` has an empty value.
+pub fn param(key: &str) -> Option<&'static str> {
+    cmdline()
+        .split_ascii_whitespace()
+        .filter_map(|p| match p.split_once('=') {
+            Some((k, v)) => (k == key).then_some(v),
+            None => (p == key).then_some(""),
+        })
+        .last()
+}
+
+fn init(args: &[u8]) {
+    let mut buf = [0; CMDLINE_MAX];
+    let len = args
+        .iter()
+        .take(CMDLINE_MAX)
+        .position(|&b| b == 0)
+        .unwrap_or(args.len().min(CMDLINE_MAX));
+    buf[..len].copy_from_slice(&args[..len]);
+    CMDLINE.init_once(Cmdline { buf, len });
+}
+
+/// Takes the command line from the device tree at `dtb` (a physical
+/// address), if any.
+#[allow(dead_code)]
+pub(crate) unsafe fn init_from_dtb(dtb: usize) {
+    if let Some(args) = crate::fdt::from_phys(dtb)
+        .and_then(|fdt| crate::fdt::find_property(fdt, &[b"chosen"], b"bootargs"))
+    {
+        init(args);
+    }
+}
+
+/// Takes the command line from the multiboot information at `mbi` (a
+/// physical address), if any.
+#[allow(dead_code)]
+pub(crate) unsafe fn init_from_multiboot(mbi: usize) {
+    const MULTIBOOT_INFO_CMDLINE: u32 = 1 << 2;
+
+    let info = crate::mem::phys_to_virt(mbi.into()).as_ptr() as *const u32;
+    if info.read() & MULTIBOOT_INFO_CMDLINE != 0 {
+        let ptr = crate::mem::phys_to_virt((info.add(4).read() as usize).into()).as_ptr();
+        init(core::slice::from_raw_parts(ptr, CMDLINE_MAX));
+    }
+}
+
+/// Takes the command line from the arguments of the process, joined by
+/// spaces, on the hosted platform.
+#[cfg(feature = "hosted")]
+pub(crate) fn init_from_args(args: &[std::string::String]) {
+    init(args.join(" ").as_bytes());
+}
@@ -1,5 +1,9 @@
 //! CPU-related operations.
 
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use kspin::SpinNoIrq;
+
 #[percpu::def_percpu]
 static CPU_ID: usize = 0;
 
@@ -9,6 +13,9 @@ static IS_BSP: bool = false;
 #[percpu::def_percpu]
 static CURRENT_TASK_PTR: usize = 0;
 
+#[percpu::def_percpu]
+static CPU_CAPACITY: u32 = MAX_CAPACITY;
+
 /// Returns the ID of the current CPU.
 #[inline]
 pub fn this_cpu_id() -> usize {
@@ -73,6 +80,199 @@ pub unsafe fn set_current_task_ptr<T>(ptr: *const T) {
     }
 }
 
+/// The position of a CPU in the system.
+///
+/// It is read from the `MPIDR_EL1` register on AArch64 and from CPUID on
+/// x86_64. RISC-V has no such information, so each hart is reported as a core
+/// of a single cluster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuTopology {
+    /// The ID of the CPU, as returned by [`this_cpu_id`].
+    pub cpu_id: usize,
+    /// The package (socket) of the CPU.
+    pub package: u32,
+    /// The cluster of the CPU in its package, e.g. the big or the LITTLE
+    /// cores on ARM. On x86_64, it is the package.
+    pub cluster: u32,
+    /// The core of the CPU in its cluster.
+    pub core: u32,
+    /// The hardware thread of the CPU in its core.
+    pub thread: u32,
+    /// The CPUs with the same value share the last level cache.
+    pub llc_id: u32,
+    /// The relative performance of the CPU, [`MAX_CAPACITY`] for the fastest
+    /// cores.
+    pub capacity: u32,
+}
+
+/// The capacity of the fastest cores.
+pub const MAX_CAPACITY: u32 = 1024;
+
+/// The capacity given to the efficiency cores (e.g. Cortex-A55 or Intel
+/// E-cores), about half of the performance ones.
+const LITTLE_CAPACITY: u32 = 512;
+
+/// The lowest and the highest capacity of the started CPUs.
+static MIN_STARTED_CAPACITY: AtomicU32 = AtomicU32::new(MAX_CAPACITY);
+static MAX_STARTED_CAPACITY: AtomicU32 = AtomicU32::new(0);
+
+/// Returns the capacity of the current CPU, see [`CpuTopology::capacity`].
+#[inline]
+pub fn this_cpu_capacity() -> u32 {
+    CPU_CAPACITY.read_current()
+}
+
+/// Returns whether the current CPU is one of the fastest started, which is
+/// the case of all CPUs if they have the same capacity.
+#[inline]
+pub fn this_cpu_is_big() -> bool {
+    this_cpu_capacity() >= MAX_STARTED_CAPACITY.load(Ordering::Relaxed)
+}
+
+/// Returns whether the started CPUs have different capacities, e.g.
+/// big.LITTLE.
+#[inline]
+pub fn is_heterogeneous() -> bool {
+    MIN_STARTED_CAPACITY.load(Ordering::Relaxed) < MAX_STARTED_CAPACITY.load(Ordering::Relaxed)
+}
+
+impl CpuTopology {
+    /// Returns whether two CPUs are in the same cluster, and so whether a
+    /// task moved between them keeps its data in a shared cache.
+    pub fn same_cluster(&self, other: &Self) -> bool {
+        self.package == other.package && self.cluster == other.cluster
+    }
+}
+
+static TOPOLOGY: SpinNoIrq<[Option<CpuTopology>; axconfig::SMP]> =
+    SpinNoIrq::new([None; axconfig::SMP]);
+
+/// Returns the topology of a CPU, or [`None`] if it is not started yet.
+pub fn cpu_topology(cpu_id: usize) -> Option<CpuTopology> {
+    TOPOLOGY.lock().get(cpu_id).copied().flatten()
+}
+
+/// Returns the topology of the started CPUs, in the order of their IDs.
+pub fn topology() -> impl Iterator<Item = CpuTopology> {
+    let topology = *TOPOLOGY.lock();
+    topology.into_iter().flatten()
+}
+
+#[cfg(target_arch = "aarch64")]
+fn read_topology(cpu_id: usize) -> CpuTopology {
+    use tock_registers::interfaces::Readable;
+    let mpidr = aarch64_cpu::registers::MPIDR_EL1.get();
+    let aff = |n: u32| {
+        let shift = if n == 3 { 32 } else { n * 8 };
+        ((mpidr >> shift) & 0xff) as u32
+    };
+    // the MT bit: the lowest affinity level is the threads of a core
+    let (thread, core, cluster, package) = if mpidr & (1 << 24) != 0 {
+        (aff(0), aff(1), aff(2), aff(3))
+    } else {
+        (0, aff(0), aff(1), aff(2))
+    };
+    CpuTopology {
+        cpu_id,
+        package,
+        cluster,
+        core,
+        thread,
+        // the L2 cache is usually shared by the cores of a cluster
+        llc_id: (package << 8) | cluster,
+        capacity: read_capacity(),
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn read_capacity() -> u32 {
+    use tock_registers::interfaces::Readable;
+    let midr = aarch64_cpu::registers::MIDR_EL1.get();
+    let implementer = (midr >> 24) & 0xff;
+    let part = (midr >> 4) & 0xfff;
+    // Cortex-A35, A53, A55, A510 and A520
+    match (implementer, part) {
+        (0x41, 0xd04 | 0xd03 | 0xd05 | 0xd46 | 0xd80) => LITTLE_CAPACITY,
+        _ => MAX_CAPACITY,
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn read_topology(cpu_id: usize) -> CpuTopology {
+    use raw_cpuid::{CpuId, TopologyType};
+    let cpuid = CpuId::new();
+    let mut apic_id = cpuid
+        .get_feature_info()
+        .map_or(cpu_id as u32, |f| f.initial_local_apic_id() as u32);
+    let (mut smt_shift, mut core_shift) = (0, 0);
+    if let Some(levels) = cpuid.get_extended_topology_info() {
+        for level in levels {
+            apic_id = level.x2apic_id();
+            match level.level_type() {
+                TopologyType::SMT => smt_shift = level.shift_right_for_next_apic_id(),
+                TopologyType::Core => core_shift = level.shift_right_for_next_apic_id(),
+                _ => {}
+            }
+        }
+    }
+    let core_shift = core_shift.max(smt_shift);
+    let package = apic_id.checked_shr(core_shift).unwrap_or(0);
+    let core = (apic_id >> smt_shift) & ((1 << (core_shift - smt_shift)) - 1);
+    let thread = apic_id & ((1 << smt_shift) - 1);
+    // the APIC IDs sharing the last level cache only differ in the low bits
+    let llc_sharing = cpuid
+        .get_cache_parameters()
+        .and_then(|caches| caches.max_by_key(|cache| cache.level()))
+        .map_or(1, |cache| cache.max_cores_for_cache());
+    let llc_id = apic_id >> llc_sharing.next_power_of_two().trailing_zeros();
+    CpuTopology {
+        cpu_id,
+        package,
+        cluster: package,
+        core,
+        thread,
+        llc_id,
+        capacity: read_capacity(),
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn read_capacity() -> u32 {
+    use core::arch::x86_64::__cpuid_count;
+    // the hybrid flag, then the core type: 0x20 is an Atom (E-core)
+    let hybrid =
+        unsafe { __cpuid_count(0, 0).eax >= 0x1a && __cpuid_count(7, 0).edx & (1 << 15) != 0 };
+    if hybrid && unsafe { __cpuid_count(0x1a, 0).eax >> 24 } == 0x20 {
+        LITTLE_CAPACITY
+    } else {
+        MAX_CAPACITY
+    }
+}
+
+#[cfg(not(any(target_arch = "aarch64", target_arch = "x86_64")))]
+fn read_topology(cpu_id: usize) -> CpuTopology {
+    CpuTopology {
+        cpu_id,
+        package: 0,
+        cluster: 0,
+        core: cpu_id as u32,
+        thread: 0,
+        llc_id: 0,
+        capacity: MAX_CAPACITY,
+    }
+}
+
+fn init_topology(cpu_id: usize) {
+    let topology = read_topology(cpu_id);
+    debug!("CPU {} topology: {:?}", cpu_id, topology);
+    unsafe { CPU_CAPACITY.write_current_raw(topology.capacity) };
+    MIN_STARTED_CAPACITY.fetch_min(topology.capacity, Ordering::Relaxed);
+    MAX_STARTED_CAPACITY.fetch_max(topology.capacity, Ordering::Relaxed);
+    if let Some(slot) = TOPOLOGY.lock().get_mut(cpu_id) {
+        *slot = Some(topology);
+    }
+}
+
 #[allow(dead_code)]
 pub(crate) fn init_primary(cpu_id: usize) {
     percpu::init(axconfig::SMP);
@@ -81,6 +281,10 @@ pub(crate) fn init_primary(cpu_id: usize) {
         CPU_ID.write_current_raw(cpu_id);
         IS_BSP.write_current_raw(true);
     }
+    #[cfg(all(feature = "fp-lazy", not(target_arch = "aarch64")))]
+    crate::fp::init_percpu();
+    crate::pmu::init_percpu();
+    init_topology(cpu_id);
 }
 
 #[allow(dead_code)]
@@ -90,4 +294,8 @@ pub(crate) fn init_secondary(cpu_id: usize) {
         CPU_ID.write_current_raw(cpu_id);
         IS_BSP.write_current_raw(false);
     }
+    #[cfg(all(feature = "fp-lazy", not(target_arch = "aarch64")))]
+    crate::fp::init_percpu();
+    crate::pmu::init_percpu();
+    init_topology(cpu_id);
 }
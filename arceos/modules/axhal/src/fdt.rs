@@ -0,0 +1,86 @@
+//! A minimal reader of the flattened device tree given by the bootloader,
+//! for the few properties needed at boot.
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_MAX_SIZE: usize = 0x20_0000;
+
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+/// Returns the device tree at `dtb` (a physical address), if it is valid.
+#[allow(dead_code)]
+pub(crate) unsafe fn from_phys(dtb: usize) -> Option<&'static [u8]> {
+    if dtb == 0 {
+        return None;
+    }
+    let ptr = crate::mem::phys_to_virt(dtb.into()).as_ptr();
+    let header = core::slice::from_raw_parts(ptr, 8);
+    let magic = u32::from_be_bytes(header[..4].try_into().unwrap());
+    let size = u32::from_be_bytes(header[4..].try_into().unwrap()) as usize;
+    if magic != FDT_MAGIC || size > FDT_MAX_SIZE {
+        return None;
+    }
+    Some(core::slice::from_raw_parts(ptr, size))
+}
+
+/// Whether the node `name` matches the component `part` of a path, ignoring
+/// the unit address (`cpu` matches `cpu@0`).
+fn name_matches(name: &[u8], part: &[u8]) -> bool {
+    name == part || name.split(|&b| b == b'@').next() == Some(part)
+}
+
+/// Finds the property `prop` of the first node at `path` from the root,
+/// e.g. `&[b"chosen"]` for `/chosen`.
+#[allow(dead_code)]
+pub(crate) fn find_property<'a>(fdt: &'a [u8], path: &[&[u8]], prop: &[u8]) -> Option<&'a [u8]> {
+    let read = |pos: usize| Some(u32::from_be_bytes(fdt.get(pos..pos + 4)?.try_into().ok()?));
+    let cstr = |pos: usize| {
+        let s = fdt.get(pos..)?;
+        Some(&s[..s.iter().position(|&b| b == 0)?])
+    };
+    let align = |pos: usize| (pos + 3) & !3;
+
+    let strings = read(12)? as usize;
+    let mut pos = read(8)? as usize;
+    // the root node is at depth 1, the components of `path` below
+    let mut depth = 0;
+    let mut matched = 0;
+    loop {
+        let token = read(pos)?;
+        pos += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = cstr(pos)?;
+                depth += 1;
+                if depth >= 2
+                    && matched == depth - 2
+                    && path
+                        .get(matched)
+                        .is_some_and(|&part| name_matches(name, part))
+                {
+                    matched += 1;
+                }
+                pos = align(pos + name.len() + 1);
+            }
+            FDT_END_NODE => {
+                if depth >= 2 && matched == depth - 1 {
+                    matched -= 1;
+                }
+                depth -= 1;
+            }
+            FDT_PROP => {
+                let len = read(pos)? as usize;
+                let name = cstr(strings + read(pos + 4)? as usize)?;
+                let value = fdt.get(pos + 8..pos + 8 + len)?;
+                if matched == path.len() && depth == path.len() + 1 && name == prop {
+                    return Some(value);
+                }
+                pos = align(pos + 8 + len);
+            }
+            FDT_NOP => {}
+            _ => return None,
+        }
+    }
+}
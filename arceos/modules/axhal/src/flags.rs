@@ -0,0 +1,130 @@
+//! Feature flags, to turn code paths on and off at runtime.
+//!
+//! A module defines a flag with [`define_flag!`](crate::define_flag), and
+//! takes its new code path only if [`Flag::is_enabled`], so that the code can
+//! be merged disabled and enabled on some machines only. The flags are set at
+//! boot by the `flags` parameter of the [command line](crate::cmdline), a
+//! list of names separated by commas, where `-name` disables a flag enabled
+//! by default:
+//!
+//! ```text
+//! flags=new-sched,-tcp-pacing
+//! ```
+//!
+//! They can then be changed with [`Flag::set`], e.g. from the `flags` command
+//! of the diagnostic shell, and are listed in `/proc/kernel/flags`.
+//!
+//! ```ignore
+//! axhal::define_flag! {
+//!     /// Picks the next task with the new scheduler.
+//!     pub static NEW_SCHED = ("new-sched", false);
+//! }
+//!
+//! if NEW_SCHED.is_enabled() {
+//!     // the new code path
+//! }
+//! ```
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use linkme::distributed_slice as def_flags;
+
+#[doc(hidden)]
+pub use linkme as __linkme;
+
+const DEFAULT: u8 = 0;
+const OFF: u8 = 1;
+const ON: u8 = 2;
+
+/// A feature flag, defined with [`define_flag!`](crate::define_flag).
+pub struct Flag {
+    name: &'static str,
+    default: bool,
+    state: AtomicU8,
+}
+
+impl Flag {
+    #[doc(hidden)]
+    pub const fn new(name: &'static str, default: bool) -> Self {
+        Self {
+            name,
+            default,
+            state: AtomicU8::new(DEFAULT),
+        }
+    }
+
+    /// Returns the name of the flag, in the command line.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns whether the flag is enabled when not set.
+    pub fn default(&self) -> bool {
+        self.default
+    }
+
+    /// Returns whether the flag is enabled.
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        match self.state.load(Ordering::Relaxed) {
+            ON => true,
+            OFF => false,
+            _ => self.default,
+        }
+    }
+
+    /// Enables or disables the flag.
+    pub fn set(&self, enabled: bool) {
+        let state = if enabled { ON } else { OFF };
+        if self.state.swap(state, Ordering::Relaxed) != state {
+            info!("flag {} {}", self.name, if enabled { "on" } else { "off" });
+        }
+    }
+
+    /// Returns the flag to its default.
+    pub fn reset(&self) {
+        self.state.store(DEFAULT, Ordering::Relaxed);
+    }
+}
+
+/// A slice of the defined flags.
+#[def_flags]
+pub static FLAGS: [&'static Flag];
+
+/// Defines a static [`Flag`] with its name and whether it is enabled by
+/// default.
+#[macro_export]
+macro_rules! define_flag {
+    ($(#[$attr:meta])* $vis:vis static $ident:ident = ($name:literal, $default:expr $(,)?);) => {
+        $(#[$attr])*
+        $vis static $ident: $crate::flags::Flag = $crate::flags::Flag::new($name, $default);
+
+        const _: () = {
+            #[$crate::flags::__linkme::distributed_slice($crate::flags::FLAGS)]
+            #[linkme(crate = $crate::flags::__linkme)]
+            static __FLAG: &$crate::flags::Flag = &$ident;
+        };
+    };
+}
+
+/// Returns the flag of the given name.
+pub fn find(name: &str) -> Option<&'static Flag> {
+    FLAGS.iter().copied().find(|flag| flag.name == name)
+}
+
+/// Sets the flags given by the `flags` parameter of the command line.
+pub fn init() {
+    let Some(list) = crate::cmdline::param("flags") else {
+        return;
+    };
+    for item in list.split(',').filter(|s| !s.is_empty()) {
+        let (name, enabled) = match item.strip_prefix('-') {
+            Some(name) => (name, false),
+            None => (item, true),
+        };
+        match find(name) {
+            Some(flag) => flag.set(enabled),
+            None => warn!("unknown flag {:?} in the command line", name),
+        }
+    }
+}
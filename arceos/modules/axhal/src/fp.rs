@@ -0,0 +1,235 @@
+//! Lazy switching of the FP/SIMD registers (feature `fp-lazy`).
+//!
+//! The registers are not switched with the tasks: [`TaskContext::switch_to`]
+//! only disables the FP unit, and the first FP instruction of the next task
+//! traps to [`handle_unavailable`], which saves the registers of the task
+//! owning them and loads the ones of the current task. A task that does not
+//! use FP never loads or saves them, and on a single CPU, a task that is the
+//! only one using FP keeps them in the registers across all switches. With
+//! SMP, the registers are saved when their task is switched out (if it used
+//! them since it was switched in), as it may then run on another CPU.
+//!
+//! Interrupt handlers run with the unit disabled ([`irq_enter`]), so they
+//! trap if they use FP: the registers of their owner are saved, and the
+//! interrupted task loads them again at its next FP instruction. The
+//! interrupted task runs with the unit as it was if the handler did not use
+//! it ([`irq_exit`]).
+//!
+//! It is implemented for x86_64 (`CR0.TS` and `#NM`) and RISC-V (`sstatus.FS`
+//! and illegal instructions, only the F/D registers). On AArch64, the kernel
+//! is compiled with NEON and about all code uses it (e.g. `memcpy`), so the
+//! registers are switched eagerly.
+//!
+//! [`TaskContext::switch_to`]: crate::arch::TaskContext::switch_to
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        use crate::arch::ExtendedState as FpState;
+
+        use x86::controlregs::{cr0, cr0_write, Cr0};
+
+        /// The state of the unit: disabled, or enabled.
+        const DISABLED: usize = 0;
+        const CLEAN: usize = 1;
+        const DIRTY: usize = 1;
+
+        const INIT_STATE: FpState = FpState::default();
+
+        fn unit_state() -> usize {
+            if unsafe { cr0() }.contains(Cr0::CR0_TASK_SWITCHED) {
+                DISABLED
+            } else {
+                CLEAN
+            }
+        }
+
+        fn set_unit_state(state: usize) {
+            unsafe {
+                if state == DISABLED {
+                    cr0_write(cr0() | Cr0::CR0_TASK_SWITCHED);
+                } else {
+                    core::arch::asm!("clts");
+                }
+            }
+        }
+
+        const fn is_dirty(state: usize) -> bool {
+            state != DISABLED
+        }
+    } else if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+        use crate::arch::FpState;
+
+        /// The state of the unit, as in `sstatus.FS`: off, initial, clean or
+        /// dirty.
+        const DISABLED: usize = 0;
+        const CLEAN: usize = 2;
+        const DIRTY: usize = 3;
+        const FS_SHIFT: usize = 13;
+
+        const INIT_STATE: FpState = FpState::new();
+
+        fn unit_state() -> usize {
+            let sstatus: usize;
+            unsafe { core::arch::asm!("csrr {}, sstatus", out(reg) sstatus) };
+            (sstatus >> FS_SHIFT) & 0b11
+        }
+
+        fn set_unit_state(state: usize) {
+            unsafe {
+                core::arch::asm!(
+                    "csrc sstatus, {mask}",
+                    "csrs sstatus, {state}",
+                    mask = in(reg) 0b11 << FS_SHIFT,
+                    state = in(reg) state << FS_SHIFT,
+                )
+            }
+        }
+
+        const fn is_dirty(state: usize) -> bool {
+            state == DIRTY
+        }
+    }
+}
+
+/// The registers of the code that ran before the first task switch, as it
+/// has no task context.
+#[percpu::def_percpu]
+static BOOT_STATE: FpState = INIT_STATE;
+
+/// The state of the task running on this CPU.
+#[percpu::def_percpu]
+static CURRENT: usize = 0;
+
+/// The state whose values are in the registers, 0 if none.
+#[percpu::def_percpu]
+static OWNER: usize = 0;
+
+/// The nesting level of interrupt handlers.
+#[percpu::def_percpu]
+static IRQ_DEPTH: usize = 0;
+
+/// The state of the FP unit when an interrupt handler was entered, to
+/// restore it when the handler returns.
+pub(crate) struct IrqFpState {
+    unit: usize,
+    owner: usize,
+}
+
+fn boot_state() -> *mut FpState {
+    unsafe { BOOT_STATE.current_ref_mut_raw() as *mut FpState }
+}
+
+/// Initializes the states of the current CPU: the registers are the ones of
+/// the boot code, with FP enabled.
+pub(crate) fn init_percpu() {
+    let boot = boot_state() as usize;
+    unsafe {
+        CURRENT.write_current_raw(boot);
+        OWNER.write_current_raw(boot);
+    }
+}
+
+/// Switches out the task with the state `prev`, before switching to the one
+/// with `next`.
+///
+/// It must be called with IRQs disabled.
+pub(crate) fn switch(prev: &mut FpState, next: &FpState) {
+    let prev_addr = prev as *mut FpState as usize;
+    unsafe {
+        let mut unit = unit_state();
+        let boot = boot_state();
+        if CURRENT.read_current_raw() == boot as usize && OWNER.read_current_raw() != boot as usize
+        {
+            // The first switch, after an interrupt handler saved the
+            // registers of the boot code: they are `prev`'s.
+            set_unit_state(CLEAN);
+            (*boot).restore();
+            OWNER.write_current_raw(boot as usize);
+            unit = DIRTY;
+        }
+        if unit != DISABLED {
+            // `prev` has used the registers since it was switched in.
+            if cfg!(feature = "smp") {
+                if OWNER.read_current_raw() != prev_addr || is_dirty(unit) {
+                    prev.save();
+                }
+                OWNER.write_current_raw(0);
+            } else {
+                OWNER.write_current_raw(prev_addr);
+            }
+            set_unit_state(DISABLED);
+        } else if cfg!(feature = "smp") {
+            OWNER.write_current_raw(0);
+        }
+        CURRENT.write_current_raw(next as *const FpState as usize);
+    }
+}
+
+/// Handles an FP instruction while the unit is disabled, by enabling it with
+/// the registers of the current task (or for an interrupt handler).
+pub(crate) fn handle_unavailable() {
+    unsafe {
+        let owner = OWNER.read_current_raw() as *mut FpState;
+        set_unit_state(CLEAN);
+        if IRQ_DEPTH.read_current_raw() > 0 {
+            // The handler may use the registers as it likes.
+            if let Some(owner) = owner.as_mut() {
+                owner.save();
+                OWNER.write_current_raw(0);
+            }
+            set_unit_state(DIRTY);
+            return;
+        }
+        let current = CURRENT.read_current_raw() as *mut FpState;
+        if owner == current {
+            // No other task used the registers since the current one did,
+            // they may differ from its saved ones.
+            set_unit_state(DIRTY);
+            return;
+        }
+        if let Some(owner) = owner.as_mut() {
+            owner.save();
+        }
+        (*current).restore();
+        OWNER.write_current_raw(current as usize);
+    }
+}
+
+/// Disables the FP unit for an interrupt handler.
+pub(crate) fn irq_enter() -> IrqFpState {
+    unsafe {
+        IRQ_DEPTH.write_current_raw(IRQ_DEPTH.read_current_raw() + 1);
+        let state = IrqFpState {
+            unit: unit_state(),
+            owner: OWNER.read_current_raw(),
+        };
+        if state.unit != DISABLED {
+            set_unit_state(DISABLED);
+        }
+        state
+    }
+}
+
+/// Restores the FP unit as it was before the interrupt handler, unless the
+/// handler used the registers.
+pub(crate) fn irq_exit(state: IrqFpState) {
+    unsafe {
+        IRQ_DEPTH.write_current_raw(IRQ_DEPTH.read_current_raw() - 1);
+        if OWNER.read_current_raw() == state.owner {
+            set_unit_state(state.unit);
+        } else {
+            set_unit_state(DISABLED);
+        }
+    }
+}
+
+/// Forgets the state of a task context being dropped, if it owns the
+/// registers.
+pub(crate) fn forget(state: &FpState) {
+    let _guard = kernel_guard::IrqSave::new();
+    unsafe {
+        if OWNER.read_current_raw() == state as *const FpState as usize {
+            OWNER.write_current_raw(0);
+        }
+    }
+}
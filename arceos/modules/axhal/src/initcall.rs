@@ -0,0 +1,81 @@
+//! Initialization functions registered by the modules themselves.
+//!
+//! A driver or a subsystem registers its initialization function with
+//! [`register_init!`](crate::register_init), in a linker section, instead of
+//! being called from the runtime. The runtime calls them all with
+//! [`run_init_calls`] once the devices, the filesystems and the network are
+//! initialized, from the lowest priority to the highest.
+//!
+//! ```ignore
+//! fn init() {
+//!     // add the sensors of the platform
+//! }
+//!
+//! axhal::register_init!(axhal::initcall::priority::DRIVER, init);
+//! ```
+
+use linkme::distributed_slice as def_init_calls;
+
+#[doc(hidden)]
+pub use linkme as __linkme;
+
+/// Priorities of the common kinds of initialization functions. The functions
+/// of the same priority are called in no particular order.
+pub mod priority {
+    /// The drivers, probing the devices which are not on a bus.
+    pub const DRIVER: u16 = 100;
+    /// The subsystems, which may use the devices of the drivers.
+    pub const SUBSYS: u16 = 200;
+    /// The functions which need all the subsystems.
+    pub const LATE: u16 = 300;
+}
+
+/// An initialization function registered with
+/// [`register_init!`](crate::register_init).
+pub struct InitCall {
+    /// The lower ones are called first.
+    pub priority: u16,
+    /// The name of the function, for the logs.
+    pub name: &'static str,
+    /// The function.
+    pub func: fn(),
+}
+
+/// A slice of the registered initialization functions.
+#[def_init_calls]
+pub static INIT_CALLS: [InitCall];
+
+/// Registers `func`, a `fn()`, to be called by [`run_init_calls`] with the
+/// given priority (a `u16`, see [`priority`]).
+#[macro_export]
+macro_rules! register_init {
+    ($priority:expr, $func:path) => {
+        const _: () = {
+            #[$crate::initcall::__linkme::distributed_slice($crate::initcall::INIT_CALLS)]
+            #[linkme(crate = $crate::initcall::__linkme)]
+            static __INIT_CALL: $crate::initcall::InitCall = $crate::initcall::InitCall {
+                priority: $priority,
+                name: stringify!($func),
+                func: $func,
+            };
+        };
+    };
+}
+
+/// Calls the registered initialization functions, by priority from the
+/// lowest.
+pub fn run_init_calls() {
+    let mut last = None;
+    while let Some(priority) = INIT_CALLS
+        .iter()
+        .map(|call| call.priority)
+        .filter(|&p| last.map_or(true, |last| p > last))
+        .min()
+    {
+        for call in INIT_CALLS.iter().filter(|call| call.priority == priority) {
+            debug!("init call {} (priority {})", call.name, priority);
+            (call.func)();
+        }
+        last = Some(priority);
+    }
+}
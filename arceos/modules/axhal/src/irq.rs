@@ -5,22 +5,42 @@ use handler_table::HandlerTable;
 use crate::platform::irq::{dispatch_irq, MAX_IRQ_COUNT};
 use crate::trap::{register_trap_handler, IRQ};
 
-pub use crate::platform::irq::{register_handler, set_enable};
+pub use crate::platform::irq::set_enable;
 
 /// The type if an IRQ handler.
 pub type IrqHandler = handler_table::Handler;
 
 static IRQ_HANDLER_TABLE: HandlerTable<MAX_IRQ_COUNT> = HandlerTable::new();
 
+/// The IRQs with handlers, as a bitmap, enabled again after restoring a
+/// snapshot.
+#[cfg(feature = "snapshot")]
+static HANDLED_IRQS: kspin::SpinNoIrq<[u64; MAX_IRQ_COUNT.div_ceil(64)]> =
+    kspin::SpinNoIrq::new([0; MAX_IRQ_COUNT.div_ceil(64)]);
+
 /// Platform-independent IRQ dispatching.
 #[allow(dead_code)]
 pub(crate) fn dispatch_irq_common(irq_num: usize) {
     trace!("IRQ {}", irq_num);
+    #[cfg(feature = "replay")]
+    if crate::replay::defer_irq(irq_num) {
+        return;
+    }
     if !IRQ_HANDLER_TABLE.handle(irq_num) {
         warn!("Unhandled IRQ {}", irq_num);
     }
 }
 
+/// Registers an IRQ handler for the given IRQ.
+///
+/// It also enables the IRQ if the registration succeeds. It returns `false` if
+/// the registration failed.
+pub fn register_handler(irq_num: usize, handler: IrqHandler) -> bool {
+    #[cfg(feature = "replay")]
+    crate::replay::register_handler(irq_num, handler);
+    crate::platform::irq::register_handler(irq_num, handler)
+}
+
 /// Platform-independent IRQ handler registration.
 ///
 /// It also enables the IRQ if the registration succeeds. It returns `false` if
@@ -28,6 +48,10 @@ pub(crate) fn dispatch_irq_common(irq_num: usize) {
 #[allow(dead_code)]
 pub(crate) fn register_handler_common(irq_num: usize, handler: IrqHandler) -> bool {
     if irq_num < MAX_IRQ_COUNT && IRQ_HANDLER_TABLE.register_handler(irq_num, handler) {
+        #[cfg(feature = "snapshot")]
+        {
+            HANDLED_IRQS.lock()[irq_num / 64] |= 1 << (irq_num % 64);
+        }
         set_enable(irq_num, true);
         return true;
     }
@@ -35,10 +59,26 @@ pub(crate) fn register_handler_common(irq_num: usize, handler: IrqHandler) -> bo
     false
 }
 
+/// Enables again the IRQs with handlers, after the interrupt controller is
+/// initialized again.
+#[cfg(feature = "snapshot")]
+pub(crate) fn reenable_handled_irqs() {
+    let handled = *HANDLED_IRQS.lock();
+    for irq_num in 0..MAX_IRQ_COUNT {
+        if handled[irq_num / 64] & (1 << (irq_num % 64)) != 0 {
+            set_enable(irq_num, true);
+        }
+    }
+}
+
 #[register_trap_handler(IRQ)]
 fn handler_irq(irq_num: usize) -> bool {
     let guard = kernel_guard::NoPreempt::new();
+    #[cfg(all(feature = "fp-lazy", not(target_arch = "aarch64")))]
+    let fp_state = crate::fp::irq_enter();
     dispatch_irq(irq_num);
+    #[cfg(all(feature = "fp-lazy", not(target_arch = "aarch64")))]
+    crate::fp::irq_exit(fp_state);
     drop(guard); // rescheduling may occur when preemption is re-enabled.
     true
 }
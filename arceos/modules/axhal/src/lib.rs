@@ -13,13 +13,38 @@
 //! - `dummy`: If none of the above platform is selected, the dummy platform
 //!    will be used. In this platform, most of the operations are no-op or
 //!    `unimplemented!()`. This platform is mainly used for [cargo test].
+//! - `hosted`: With the `hosted` feature, instead of the dummy platform, the
+//!    kernel runs as a process of a x86_64 Linux host, see below.
 //!
 //! # Cargo Features
 //!
 //! - `smp`: Enable SMP (symmetric multiprocessing) support.
 //! - `fp_simd`: Enable floating-point and SIMD support.
+//! - `fp-lazy`: Switch the FP/SIMD registers lazily, at the first FP
+//!    instruction of a task. x86_64 and RISC-V only.
 //! - `paging`: Enable page table manipulation.
 //! - `irq`: Enable interrupt handling support.
+//! - `shadow-call-stack`: Set up shadow call stacks (kept in X18) for the boot
+//!    code and for tasks, for code built with
+//!    `-Z sanitizer=shadow-call-stack`. AArch64 only.
+//! - `pointer-auth`: Enable the pointer authentication of return addresses at
+//!    boot, for code built with `-Z branch-protection=pac-ret`. AArch64 only.
+//! - `replay`: Enable the record/replay of the external inputs (the time, the
+//!    random numbers, the received frames and the interrupts) for debugging,
+//!    see [`replay`].
+//! - `snapshot`: Enable taking a snapshot of the system to a file, and
+//!    restoring it at boot, see [`snapshot`].
+//! - `fast-mem`: Replace `memcpy`, `memmove`, `memset` and `memcmp` with
+//!    versions optimized for the CPU, see [`memfuncs`].
+//! - `unwind`: Keep the unwind tables (`.eh_frame`) in the kernel image, for
+//!    the unwinding of the panics.
+//! - `hosted`: Run on the hosted platform, as a process of a x86_64 Linux
+//!    host (built for its target, e.g. `x86_64-unknown-linux-gnu`), so that
+//!    the kernel can be tested and fuzzed with the sanitizers of the host. The
+//!    console is the standard input and output, the clock is the clock of the
+//!    host, the memory is allocated from the host, and the arguments of the
+//!    process are the command line. There are no interrupts, no SMP, no TLS
+//!    and no user space.
 //!
 //! [ArceOS]: https://github.com/arceos-org/arceos
 //! [cargo test]: https://doc.rust-lang.org/cargo/guide/tests.html
@@ -39,25 +64,64 @@ extern crate log;
 #[macro_use]
 extern crate memory_addr;
 
+#[cfg(feature = "hosted")]
+extern crate std;
+
+#[cfg(all(
+    feature = "hosted",
+    not(all(target_arch = "x86_64", target_os = "linux"))
+))]
+compile_error!("the hosted platform needs a x86_64 Linux host");
+
+#[cfg(all(
+    feature = "hosted",
+    any(feature = "irq", feature = "smp", feature = "tls", feature = "uspace")
+))]
+compile_error!("the hosted platform has no interrupts, SMP, TLS or user space");
+
+#[cfg(all(feature = "shadow-call-stack", feature = "uspace"))]
+compile_error!("shadow call stacks are not supported with user space yet");
+
+mod fdt;
 mod platform;
 
+#[cfg(all(feature = "fp-lazy", not(target_arch = "aarch64")))]
+mod fp;
+
 #[macro_use]
 pub mod trap;
 
 pub mod arch;
+pub mod cmdline;
 pub mod cpu;
+pub mod flags;
+pub mod initcall;
 pub mod mem;
+pub mod pmu;
 pub mod time;
+pub mod uart;
 
 #[cfg(feature = "tls")]
 pub mod tls;
 
+#[cfg(feature = "fast-mem")]
+pub mod memfuncs;
+
 #[cfg(feature = "irq")]
 pub mod irq;
 
 #[cfg(feature = "paging")]
 pub mod paging;
 
+#[cfg(feature = "replay")]
+pub mod replay;
+
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
+
+#[cfg(any(feature = "replay", feature = "snapshot"))]
+mod semihosting;
+
 /// Console input and output.
 pub mod console {
     pub use super::platform::console::*;
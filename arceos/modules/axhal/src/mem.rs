@@ -2,6 +2,8 @@
 
 use core::fmt;
 
+use kspin::SpinNoIrq;
+
 #[doc(no_inline)]
 pub use memory_addr::{MemoryAddr, PhysAddr, VirtAddr, PAGE_SIZE_4K};
 
@@ -70,12 +72,81 @@ pub const fn phys_to_virt(paddr: PhysAddr) -> VirtAddr {
     va!(paddr.as_usize() + axconfig::PHYS_VIRT_OFFSET)
 }
 
+/// Maximum number of RAM regions that can be added at runtime.
+pub const MAX_HOTPLUG_REGIONS: usize = 4;
+
+/// RAM regions added at runtime (e.g., by virtio-mem), by start address and
+/// size.
+static HOTPLUG_REGIONS: SpinNoIrq<[Option<(PhysAddr, usize)>; MAX_HOTPLUG_REGIONS]> =
+    SpinNoIrq::new([None; MAX_HOTPLUG_REGIONS]);
+
 /// Returns an iterator over all physical memory regions.
+///
+/// It includes the RAM regions added by [`add_memory_region`].
 pub fn memory_regions() -> impl Iterator<Item = MemRegion> {
-    kernel_image_regions().chain(crate::platform::mem::platform_regions())
+    kernel_image_regions()
+        .chain(crate::platform::mem::platform_regions())
+        .chain(hotplug_regions())
+}
+
+/// Registers the free RAM region `[paddr, paddr + size)` added at runtime.
+///
+/// It only records the region, making it appear in [`memory_regions`]. Mapping
+/// it and giving it to the allocator is up to the caller.
+///
+/// Returns `false` if the region is not page aligned, overlaps with an
+/// existing region, or there are already [`MAX_HOTPLUG_REGIONS`] regions.
+pub fn add_memory_region(paddr: PhysAddr, size: usize) -> bool {
+    if size == 0 || !paddr.is_aligned_4k() || size % PAGE_SIZE_4K != 0 {
+        return false;
+    }
+    let end = paddr.as_usize() + size;
+    let overlaps = memory_regions()
+        .any(|r| paddr.as_usize() < r.paddr.as_usize() + r.size && r.paddr.as_usize() < end);
+    if overlaps {
+        return false;
+    }
+    let mut regions = HOTPLUG_REGIONS.lock();
+    match regions.iter_mut().find(|r| r.is_none()) {
+        Some(slot) => {
+            *slot = Some((paddr, size));
+            true
+        }
+        None => false,
+    }
+}
+
+/// Unregisters a RAM region added by [`add_memory_region`], with the same
+/// start address and size.
+///
+/// Returns `false` if there is no such region.
+pub fn remove_memory_region(paddr: PhysAddr, size: usize) -> bool {
+    let mut regions = HOTPLUG_REGIONS.lock();
+    match regions.iter_mut().find(|r| **r == Some((paddr, size))) {
+        Some(slot) => {
+            *slot = None;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns the RAM regions added at runtime.
+fn hotplug_regions() -> impl Iterator<Item = MemRegion> {
+    let regions = *HOTPLUG_REGIONS.lock();
+    regions
+        .into_iter()
+        .flatten()
+        .map(|(paddr, size)| MemRegion {
+            paddr,
+            size,
+            flags: MemRegionFlags::FREE | MemRegionFlags::READ | MemRegionFlags::WRITE,
+            name: "hotplug memory",
+        })
 }
 
 /// Returns the memory regions of the kernel image (code and data sections).
+#[cfg(not(feature = "hosted"))]
 fn kernel_image_regions() -> impl Iterator<Item = MemRegion> {
     [
         MemRegion {
@@ -112,6 +183,12 @@ fn kernel_image_regions() -> impl Iterator<Item = MemRegion> {
     .into_iter()
 }
 
+/// The kernel image is loaded by the host, out of the physical memory.
+#[cfg(feature = "hosted")]
+fn kernel_image_regions() -> impl Iterator<Item = MemRegion> {
+    core::iter::empty()
+}
+
 /// Returns the default MMIO memory regions (from [`axconfig::MMIO_REGIONS`]).
 #[allow(dead_code)]
 pub(crate) fn default_mmio_regions() -> impl Iterator<Item = MemRegion> {
@@ -139,6 +216,17 @@ pub(crate) fn default_free_regions() -> impl Iterator<Item = MemRegion> {
     })
 }
 
+/// Returns the code of the kernel, the `.text` section.
+#[allow(dead_code)]
+pub(crate) fn kernel_text() -> &'static [u8] {
+    unsafe {
+        core::slice::from_raw_parts(
+            _stext as usize as *const u8,
+            _etext as usize - _stext as usize,
+        )
+    }
+}
+
 /// Fills the `.bss` section with zeros.
 #[allow(dead_code)]
 pub(crate) fn clear_bss() {
@@ -0,0 +1,61 @@
+//! NEON copies and fills of 32 bytes, when the compiler uses the FP/SIMD
+//! registers too.
+
+use super::generic;
+
+#[cfg(not(target_feature = "neon"))]
+pub use self::generic::{copy_forward, fill, implementation};
+
+pub unsafe fn copy_backward(dst: *mut u8, src: *const u8, n: usize) {
+    generic::copy_backward(dst, src, n)
+}
+
+#[cfg(target_feature = "neon")]
+pub fn implementation() -> &'static str {
+    "neon"
+}
+
+#[cfg(target_feature = "neon")]
+pub unsafe fn copy_forward(dst: *mut u8, src: *const u8, n: usize) {
+    let blocks = n / 32;
+    if blocks > 0 {
+        core::arch::asm!(
+            "1:",
+            "ldp q0, q1, [{src}], #32",
+            "stp q0, q1, [{dst}], #32",
+            "subs {blocks}, {blocks}, #1",
+            "b.ne 1b",
+            src = inout(reg) src => _,
+            dst = inout(reg) dst => _,
+            blocks = inout(reg) blocks => _,
+            out("v0") _,
+            out("v1") _,
+            options(nostack),
+        );
+    }
+    let done = blocks * 32;
+    generic::copy_forward(dst.add(done), src.add(done), n - done);
+}
+
+#[cfg(target_feature = "neon")]
+pub unsafe fn fill(dst: *mut u8, c: u8, n: usize) {
+    let blocks = n / 32;
+    if blocks > 0 {
+        core::arch::asm!(
+            "dup v0.16b, {c:w}",
+            "mov v1.16b, v0.16b",
+            "1:",
+            "stp q0, q1, [{dst}], #32",
+            "subs {blocks}, {blocks}, #1",
+            "b.ne 1b",
+            c = in(reg) c as u32,
+            dst = inout(reg) dst => _,
+            blocks = inout(reg) blocks => _,
+            out("v0") _,
+            out("v1") _,
+            options(nostack),
+        );
+    }
+    let done = blocks * 32;
+    generic::fill(dst.add(done), c, n - done);
+}
@@ -0,0 +1,112 @@
+//! The routines by words, for any architecture.
+//!
+//! The accesses are volatile, otherwise the compiler would turn the loops
+//! back into calls to `memcpy` and `memset`, i.e. into themselves.
+
+use core::ptr::{read_volatile, write_volatile};
+
+const WORD: usize = core::mem::size_of::<usize>();
+
+#[allow(dead_code)]
+pub fn implementation() -> &'static str {
+    "generic"
+}
+
+fn aligned(a: usize, b: usize) -> bool {
+    (a ^ b) % WORD == 0
+}
+
+unsafe fn copy_byte(dst: *mut u8, src: *const u8, i: usize) {
+    write_volatile(dst.add(i), read_volatile(src.add(i)));
+}
+
+unsafe fn copy_word(dst: *mut u8, src: *const u8, i: usize) {
+    write_volatile(
+        dst.add(i) as *mut usize,
+        read_volatile(src.add(i) as *const usize),
+    );
+}
+
+/// Copies `n` bytes from the first one, so `dst` may overlap the end of
+/// `src`.
+pub unsafe fn copy_forward(dst: *mut u8, src: *const u8, n: usize) {
+    let mut i = 0;
+    if aligned(dst as usize, src as usize) {
+        while i < n && (dst as usize + i) % WORD != 0 {
+            copy_byte(dst, src, i);
+            i += 1;
+        }
+        while i + WORD <= n {
+            copy_word(dst, src, i);
+            i += WORD;
+        }
+    }
+    while i < n {
+        copy_byte(dst, src, i);
+        i += 1;
+    }
+}
+
+/// Copies `n` bytes from the last one, so `dst` may overlap the start of
+/// `src`.
+pub unsafe fn copy_backward(dst: *mut u8, src: *const u8, n: usize) {
+    let mut i = n;
+    if aligned(dst as usize, src as usize) {
+        while i > 0 && (dst as usize + i) % WORD != 0 {
+            i -= 1;
+            copy_byte(dst, src, i);
+        }
+        while i >= WORD {
+            i -= WORD;
+            copy_word(dst, src, i);
+        }
+    }
+    while i > 0 {
+        i -= 1;
+        copy_byte(dst, src, i);
+    }
+}
+
+pub unsafe fn fill(dst: *mut u8, c: u8, n: usize) {
+    let word = usize::from_ne_bytes([c; WORD]);
+    let mut i = 0;
+    while i < n && (dst as usize + i) % WORD != 0 {
+        write_volatile(dst.add(i), c);
+        i += 1;
+    }
+    while i + WORD <= n {
+        write_volatile(dst.add(i) as *mut usize, word);
+        i += WORD;
+    }
+    while i < n {
+        write_volatile(dst.add(i), c);
+        i += 1;
+    }
+}
+
+pub unsafe fn compare(a: *const u8, b: *const u8, n: usize) -> i32 {
+    let mut i = 0;
+    if aligned(a as usize, b as usize) {
+        while i < n && (a as usize + i) % WORD != 0 {
+            let (x, y) = (read_volatile(a.add(i)), read_volatile(b.add(i)));
+            if x != y {
+                return x as i32 - y as i32;
+            }
+            i += 1;
+        }
+        // skip the equal words, the first different one is compared by bytes
+        while i + WORD <= n
+            && read_volatile(a.add(i) as *const usize) == read_volatile(b.add(i) as *const usize)
+        {
+            i += WORD;
+        }
+    }
+    while i < n {
+        let (x, y) = (read_volatile(a.add(i)), read_volatile(b.add(i)));
+        if x != y {
+            return x as i32 - y as i32;
+        }
+        i += 1;
+    }
+    0
+}
@@ -0,0 +1,81 @@
+//! Optimized `memcpy`, `memmove`, `memset` and `memcmp`, replacing the
+//! fallbacks of `compiler_builtins`.
+//!
+//! The implementation is chosen by the CPU:
+//!
+//! - x86_64: `rep movsb` and `rep stosb` if the CPU has ERMS (enhanced
+//!   `rep movsb`), found at boot, or `rep movsq` and `rep stosq` otherwise.
+//! - AArch64: NEON copies and fills of 32 bytes, when the kernel is built
+//!   with FP/SIMD (feature `fp_simd`).
+//! - RISC-V: the V extension, if the device tree lists it for the boot hart.
+//!   The vector registers are not saved by the traps and the context
+//!   switches, so the copies from 256 bytes, which use them, are done with
+//!   the interrupts disabled.
+//!
+//! Otherwise, and for the small sizes and the tails, memory is accessed by
+//! words once aligned.
+//!
+//! The C library (`axlibc`) defines these functions too, so this is for Rust
+//! apps only.
+
+// only exported on bare metal, not in the tests on the host
+#![cfg_attr(not(target_os = "none"), allow(dead_code))]
+
+mod generic;
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        mod x86_64;
+        use self::x86_64 as arch;
+    } else if #[cfg(target_arch = "aarch64")] {
+        mod aarch64;
+        use self::aarch64 as arch;
+    } else if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+        mod riscv;
+        use self::riscv as arch;
+        pub(crate) use self::riscv::{init_from_dtb, init_secondary};
+    } else {
+        use self::generic as arch;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+pub(crate) use self::x86_64::init;
+
+/// Returns the name of the implementation in use, e.g. `"erms"`.
+pub fn implementation() -> &'static str {
+    arch::implementation()
+}
+
+#[cfg_attr(target_os = "none", no_mangle)]
+unsafe extern "C" fn memcpy(dst: *mut u8, src: *const u8, n: usize) -> *mut u8 {
+    arch::copy_forward(dst, src, n);
+    dst
+}
+
+#[cfg_attr(target_os = "none", no_mangle)]
+unsafe extern "C" fn memmove(dst: *mut u8, src: *const u8, n: usize) -> *mut u8 {
+    // copying forwards is fine unless `dst` is in `(src, src + n)`
+    if (dst as usize).wrapping_sub(src as usize) >= n {
+        arch::copy_forward(dst, src, n);
+    } else {
+        arch::copy_backward(dst, src, n);
+    }
+    dst
+}
+
+#[cfg_attr(target_os = "none", no_mangle)]
+unsafe extern "C" fn memset(dst: *mut u8, c: i32, n: usize) -> *mut u8 {
+    arch::fill(dst, c as u8, n);
+    dst
+}
+
+#[cfg_attr(target_os = "none", no_mangle)]
+unsafe extern "C" fn memcmp(a: *const u8, b: *const u8, n: usize) -> i32 {
+    generic::compare(a, b, n)
+}
+
+#[cfg_attr(target_os = "none", no_mangle)]
+unsafe extern "C" fn bcmp(a: *const u8, b: *const u8, n: usize) -> i32 {
+    generic::compare(a, b, n)
+}
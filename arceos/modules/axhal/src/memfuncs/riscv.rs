@@ -0,0 +1,113 @@
+//! Copies and fills with the V extension, if the boot hart has it.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::generic;
+
+pub use self::generic::copy_backward;
+
+/// The fewest bytes copied or filled with the vector registers, below which
+/// disabling the interrupts costs more than it saves.
+const VECTOR_MIN: usize = 256;
+
+/// `sstatus.VS`, the state of the vector registers, set to initial.
+const SSTATUS_VS_INITIAL: usize = 1 << 9;
+
+static VECTOR: AtomicBool = AtomicBool::new(false);
+
+/// Whether the ISA string of the device tree (e.g. `rv64imafdcv_zicsr`) has
+/// the V extension, among the single letters after `rv64` or `rv32`.
+fn has_vector(isa: &[u8]) -> bool {
+    let isa = isa.split(|&b| b == 0).next().unwrap_or(&[]);
+    let base = isa.split(|&b| b == b'_').next().unwrap_or(&[]);
+    base.len() > 4
+        && (base[..4].eq_ignore_ascii_case(b"rv64") || base[..4].eq_ignore_ascii_case(b"rv32"))
+        && base[4..].iter().any(|b| b.eq_ignore_ascii_case(&b'v'))
+}
+
+/// Enables the vector registers on the current hart.
+fn enable_vector() {
+    unsafe { asm!("csrs sstatus, {}", in(reg) SSTATUS_VS_INITIAL) };
+}
+
+/// Finds whether the boot hart has the V extension in the device tree at
+/// `dtb` (a physical address), and enables it.
+pub(crate) unsafe fn init_from_dtb(dtb: usize) {
+    let isa = crate::fdt::from_phys(dtb)
+        .and_then(|fdt| crate::fdt::find_property(fdt, &[b"cpus", b"cpu"], b"riscv,isa"));
+    if isa.is_some_and(has_vector) {
+        enable_vector();
+        VECTOR.store(true, Ordering::Release);
+    }
+}
+
+/// Enables the vector registers on a secondary hart, before any copy.
+pub(crate) fn init_secondary() {
+    if VECTOR.load(Ordering::Acquire) {
+        enable_vector();
+    }
+}
+
+pub fn implementation() -> &'static str {
+    if VECTOR.load(Ordering::Relaxed) {
+        "rvv"
+    } else {
+        "generic"
+    }
+}
+
+fn use_vector(n: usize) -> bool {
+    n >= VECTOR_MIN && VECTOR.load(Ordering::Relaxed)
+}
+
+pub unsafe fn copy_forward(dst: *mut u8, src: *const u8, n: usize) {
+    if !use_vector(n) {
+        return generic::copy_forward(dst, src, n);
+    }
+    // nothing else uses the vector registers, if not interrupted
+    let _guard = kernel_guard::IrqSave::new();
+    asm!(
+        ".option push",
+        ".option arch, +v",
+        "1:",
+        "vsetvli {vl}, {n}, e8, m8, ta, ma",
+        "vle8.v v0, ({src})",
+        "vse8.v v0, ({dst})",
+        "add {src}, {src}, {vl}",
+        "add {dst}, {dst}, {vl}",
+        "sub {n}, {n}, {vl}",
+        "bnez {n}, 1b",
+        ".option pop",
+        vl = out(reg) _,
+        n = inout(reg) n => _,
+        src = inout(reg) src => _,
+        dst = inout(reg) dst => _,
+        options(nostack),
+    );
+}
+
+pub unsafe fn fill(dst: *mut u8, c: u8, n: usize) {
+    if !use_vector(n) {
+        return generic::fill(dst, c, n);
+    }
+    let _guard = kernel_guard::IrqSave::new();
+    asm!(
+        ".option push",
+        ".option arch, +v",
+        "vsetvli {vl}, zero, e8, m8, ta, ma",
+        "vmv.v.x v0, {c}",
+        "1:",
+        "vsetvli {vl}, {n}, e8, m8, ta, ma",
+        "vse8.v v0, ({dst})",
+        "add {dst}, {dst}, {vl}",
+        "sub {n}, {n}, {vl}",
+        "bnez {n}, 1b",
+        ".option pop",
+        vl = out(reg) _,
+        c = in(reg) c as usize,
+        n = inout(reg) n => _,
+        dst = inout(reg) dst => _,
+        options(nostack),
+    );
+}
@@ -0,0 +1,75 @@
+//! `rep movs` and `rep stos`, by bytes with ERMS.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::generic;
+
+static ERMS: AtomicBool = AtomicBool::new(false);
+
+/// Finds whether the CPU has ERMS.
+pub(crate) fn init() {
+    let erms = raw_cpuid::CpuId::new()
+        .get_extended_feature_info()
+        .is_some_and(|f| f.has_rep_movsb_stosb());
+    ERMS.store(erms, Ordering::Relaxed);
+}
+
+pub fn implementation() -> &'static str {
+    if ERMS.load(Ordering::Relaxed) {
+        "erms"
+    } else {
+        "rep movsq"
+    }
+}
+
+pub unsafe fn copy_forward(dst: *mut u8, src: *const u8, n: usize) {
+    if ERMS.load(Ordering::Relaxed) {
+        asm!(
+            "rep movsb",
+            inout("rcx") n => _,
+            inout("rdi") dst => _,
+            inout("rsi") src => _,
+            options(nostack, preserves_flags),
+        );
+    } else {
+        asm!(
+            "rep movsq",
+            "mov rcx, {rest}",
+            "rep movsb",
+            rest = in(reg) n % 8,
+            inout("rcx") n / 8 => _,
+            inout("rdi") dst => _,
+            inout("rsi") src => _,
+            options(nostack, preserves_flags),
+        );
+    }
+}
+
+/// Without `std`: the traps do not clear the direction flag.
+pub unsafe fn copy_backward(dst: *mut u8, src: *const u8, n: usize) {
+    generic::copy_backward(dst, src, n)
+}
+
+pub unsafe fn fill(dst: *mut u8, c: u8, n: usize) {
+    if ERMS.load(Ordering::Relaxed) {
+        asm!(
+            "rep stosb",
+            inout("rcx") n => _,
+            inout("rdi") dst => _,
+            in("al") c,
+            options(nostack, preserves_flags),
+        );
+    } else {
+        asm!(
+            "rep stosq",
+            "mov rcx, {rest}",
+            "rep stosb",
+            rest = in(reg) n % 8,
+            inout("rcx") n / 8 => _,
+            inout("rdi") dst => _,
+            in("rax") u64::from_ne_bytes([c; 8]),
+            options(nostack, preserves_flags),
+        );
+    }
+}
@@ -7,6 +7,20 @@ static PARK_MILLER_LEHMER_SEED: SpinNoIrq<u32> = SpinNoIrq::new(0);
 const RAND_MAX: u64 = 2_147_483_647;
 
 pub fn random() -> u128 {
+    #[cfg(feature = "replay")]
+    return crate::replay::random(random_live);
+    #[cfg(not(feature = "replay"))]
+    random_live()
+}
+
+/// Makes [`random`] take a new seed, so that the systems restored from the
+/// same snapshot do not give the same numbers.
+#[cfg(feature = "snapshot")]
+pub(crate) fn reseed_random() {
+    *PARK_MILLER_LEHMER_SEED.lock() = 0;
+}
+
+fn random_live() -> u128 {
 	let mut seed = PARK_MILLER_LEHMER_SEED.lock();
     if *seed == 0 {
         *seed = time::current_ticks() as u32;
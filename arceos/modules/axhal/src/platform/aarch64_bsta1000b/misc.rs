@@ -1,5 +1,12 @@
 pub use crate::platform::aarch64_common::psci::system_off as terminate;
 
+/// Resets the whole system, including all CPUs.
+pub fn reboot() -> ! {
+    info!("Rebooting...");
+    reset_cpu();
+    unreachable!()
+}
+
 use crate::mem::phys_to_virt;
 use crate::time::{busy_wait, Duration};
 use core::ptr::{read_volatile, write_volatile};
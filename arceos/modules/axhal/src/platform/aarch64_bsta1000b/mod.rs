@@ -32,6 +32,7 @@ pub(crate) unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     crate::cpu::init_primary(cpu_id);
     dw_apb_uart::init_early();
     super::aarch64_common::generic_timer::init_early();
+    crate::cmdline::init_from_dtb(dtb);
     rust_main(cpu_id, dtb);
 }
 
@@ -8,6 +8,24 @@ use axconfig::TASK_STACK_SIZE;
 #[link_section = ".bss.stack"]
 static mut BOOT_STACK: [u8; TASK_STACK_SIZE] = [0; TASK_STACK_SIZE];
 
+/// Size of the shadow call stack of each CPU while booting.
+const BOOT_SCS_SIZE: usize = if cfg!(feature = "shadow-call-stack") {
+    crate::arch::SHADOW_CALL_STACK_SIZE
+} else {
+    0
+};
+
+/// The shadow call stacks of the boot code, indexed by CPU ID. Like the boot
+/// stack, it is not cleared with the BSS, as it is already in use by then.
+#[link_section = ".bss.stack"]
+static mut BOOT_SCS: [[u8; BOOT_SCS_SIZE]; axconfig::SMP] = [[0; BOOT_SCS_SIZE]; axconfig::SMP];
+
+/// The pointer authentication key shared by all CPUs, generated by the first
+/// one. It is read before the BSS is cleared.
+#[cfg(feature = "pointer-auth")]
+#[link_section = ".data"]
+static mut PAC_KEY: [u64; 2] = [0; 2];
+
 #[link_section = ".data.boot_page_table"]
 static mut BOOT_PT_L0: [A64PTE; 512] = [A64PTE::empty(); 512];
 
@@ -96,6 +114,55 @@ unsafe fn enable_fp() {
     }
 }
 
+/// Enables the pointer authentication of return addresses (for code built
+/// with `-Z branch-protection=pac-ret`) with the instruction key A, if the
+/// CPU supports it.
+///
+/// It is naked and called from the boot code, as a function signing its
+/// return address before the key is enabled fails to authenticate it after.
+#[cfg(feature = "pointer-auth")]
+#[naked]
+unsafe extern "C" fn enable_pointer_auth() {
+    core::arch::asm!("
+        mrs     x9, id_aa64isar1_el1
+        tst     x9, #0xff0              // APA or API: address authentication
+        b.eq    3f
+
+        adrp    x10, {key}
+        add     x10, x10, :lo12:{key}
+        ldp     x11, x12, [x10]
+        cbnz    x11, 2f                 // already generated by another CPU
+        mrs     x9, id_aa64isar0_el1
+        lsr     x9, x9, #60             // RNDR
+        cbz     x9, 1f
+        mrs     x11, s3_3_c2_c4_0       // rndr
+        mrs     x12, s3_3_c2_c4_0
+        b       4f
+    1:  mrs     x11, cntpct_el0         // no RNDR, derive it from the counter
+        ldr     x12, =0x9e3779b97f4a7c15
+        mul     x12, x11, x12
+        eor     x11, x11, x12, ror #29
+    4:  orr     x11, x11, #1
+        stp     x11, x12, [x10]
+
+    2:  msr     s3_0_c2_c1_0, x11       // apiakeylo_el1
+        msr     s3_0_c2_c1_1, x12       // apiakeyhi_el1
+        mrs     x9, sctlr_el1
+        orr     x9, x9, #(1 << 31)      // EnIA
+        msr     sctlr_el1, x9
+        isb
+    3:  ret",
+        key = sym PAC_KEY,
+        options(noreturn),
+    )
+}
+
+#[cfg(not(feature = "pointer-auth"))]
+#[naked]
+unsafe extern "C" fn enable_pointer_auth() {
+    core::arch::asm!("ret", options(noreturn))
+}
+
 unsafe fn init_boot_page_table() {
     crate::platform::mem::init_boot_page_table(addr_of_mut!(BOOT_PT_L0), addr_of_mut!(BOOT_PT_L1));
 }
@@ -116,13 +183,20 @@ unsafe extern "C" fn _start() -> ! {
         add     x8, x8, {boot_stack_size}
         mov     sp, x8
 
+        adrp    x18, {boot_scs}         // setup the shadow call stack
+        add     x18, x18, :lo12:{boot_scs}
+        mov     x8, {boot_scs_size}
+        madd    x18, x19, x8, x18
+
         bl      {switch_to_el1}         // switch to EL1
         bl      {enable_fp}             // enable fp/neon
         bl      {init_boot_page_table}
         bl      {init_mmu}              // setup MMU
+        bl      {enable_pointer_auth}
 
         mov     x8, {phys_virt_offset}  // set SP to the high address
         add     sp, sp, x8
+        add     x18, x18, x8
 
         mov     x0, x19                 // call rust_entry(cpu_id, dtb)
         mov     x1, x20
@@ -133,8 +207,11 @@ unsafe extern "C" fn _start() -> ! {
         init_boot_page_table = sym init_boot_page_table,
         init_mmu = sym init_mmu,
         enable_fp = sym enable_fp,
+        enable_pointer_auth = sym enable_pointer_auth,
         boot_stack = sym BOOT_STACK,
         boot_stack_size = const TASK_STACK_SIZE,
+        boot_scs = sym BOOT_SCS,
+        boot_scs_size = const BOOT_SCS_SIZE,
         phys_virt_offset = const axconfig::PHYS_VIRT_OFFSET,
         entry = sym crate::platform::rust_entry,
         options(noreturn),
@@ -152,12 +229,19 @@ unsafe extern "C" fn _start_secondary() -> ! {
         and     x19, x19, #0xffffff     // get current CPU id
 
         mov     sp, x0
+        adrp    x18, {boot_scs}         // setup the shadow call stack
+        add     x18, x18, :lo12:{boot_scs}
+        mov     x8, {boot_scs_size}
+        madd    x18, x19, x8, x18
+
         bl      {switch_to_el1}
         bl      {init_mmu}
         bl      {enable_fp}
+        bl      {enable_pointer_auth}
 
         mov     x8, {phys_virt_offset}  // set SP to the high address
         add     sp, sp, x8
+        add     x18, x18, x8
 
         mov     x0, x19                 // call rust_entry_secondary(cpu_id)
         ldr     x8, ={entry}
@@ -166,6 +250,9 @@ unsafe extern "C" fn _start_secondary() -> ! {
         switch_to_el1 = sym switch_to_el1,
         init_mmu = sym init_mmu,
         enable_fp = sym enable_fp,
+        enable_pointer_auth = sym enable_pointer_auth,
+        boot_scs = sym BOOT_SCS,
+        boot_scs_size = const BOOT_SCS_SIZE,
         phys_virt_offset = const axconfig::PHYS_VIRT_OFFSET,
         entry = sym crate::platform::rust_entry_secondary,
         options(noreturn),
@@ -18,6 +18,7 @@ pub mod time {
 
 pub mod misc {
     pub use crate::platform::aarch64_common::psci::system_off as terminate;
+    pub use crate::platform::aarch64_common::psci::system_reset as reboot;
 }
 
 extern "C" {
@@ -34,6 +35,7 @@ pub(crate) unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     crate::cpu::init_primary(cpu_id);
     super::aarch64_common::pl011::init_early();
     super::aarch64_common::generic_timer::init_early();
+    crate::cmdline::init_from_dtb(dtb);
     rust_main(cpu_id, dtb);
 }
 
@@ -23,6 +23,13 @@ pub mod misc {
             crate::arch::halt();
         }
     }
+
+    pub fn reboot() -> ! {
+        warn!("Rebooting is not supported, halting...");
+        loop {
+            crate::arch::halt();
+        }
+    }
 }
 
 extern "C" {
@@ -39,6 +46,7 @@ pub(crate) unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     crate::cpu::init_primary(cpu_id);
     super::aarch64_common::pl011::init_early();
     super::aarch64_common::generic_timer::init_early();
+    crate::cmdline::init_from_dtb(dtb);
     rust_main(cpu_id, dtb);
 }
 
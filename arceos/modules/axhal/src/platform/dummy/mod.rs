@@ -18,6 +18,11 @@ pub mod misc {
     pub fn terminate() -> ! {
         unimplemented!()
     }
+
+    /// Resets the whole system, including all CPUs.
+    pub fn reboot() -> ! {
+        unimplemented!()
+    }
 }
 
 #[cfg(feature = "smp")]
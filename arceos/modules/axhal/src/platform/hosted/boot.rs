@@ -0,0 +1,37 @@
+use std::ffi::CStr;
+use std::string::String;
+use std::vec::Vec;
+
+use lazyinit::LazyInit;
+
+/// The arguments of the process, the first one being its path.
+static ARGS: LazyInit<Vec<String>> = LazyInit::new();
+
+/// Returns the arguments of the process, the first one being its path.
+pub(super) fn args() -> &'static [String] {
+    ARGS.get().map_or(&[], |args| args.as_slice())
+}
+
+/// Called by the C runtime of the host before `main`, in place of the boot
+/// code. The application `main` is then called by `rust_main`, and the
+/// process exits in [`terminate`](super::misc::terminate).
+#[used]
+#[link_section = ".init_array"]
+static ENTRY: unsafe extern "C" fn(i32, *const *const u8) = rust_entry;
+
+unsafe extern "C" fn rust_entry(argc: i32, argv: *const *const u8) {
+    // the C runtime of glibc gives the arguments to the constructors, maybe
+    // before the initialization of `std::env::args`
+    let args = (0..argc.max(0) as usize)
+        .map(|i| {
+            let arg = CStr::from_ptr(*argv.add(i) as *const _);
+            String::from_utf8_lossy(arg.to_bytes()).into_owned()
+        })
+        .collect::<Vec<_>>();
+    crate::cmdline::init_from_args(args.get(1..).unwrap_or(&[]));
+    ARGS.init_once(args);
+    crate::cpu::init_primary(0);
+    super::time::init_early();
+    super::mem::init_early();
+    super::rust_main(0, 0);
+}
@@ -0,0 +1,29 @@
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::{Mutex, Once};
+
+/// The bytes read from the standard input, by a thread of the host which
+/// blocks on it.
+static INPUT: Mutex<VecDeque<u8>> = Mutex::new(VecDeque::new());
+static READER: Once = Once::new();
+
+/// Writes a byte to the console.
+pub fn putchar(c: u8) {
+    let mut stdout = std::io::stdout().lock();
+    // unbuffered, for the prompts without a newline
+    let _ = stdout.write_all(&[c]);
+    let _ = stdout.flush();
+}
+
+/// Reads a byte from the console, or returns [`None`] if no input is available.
+pub fn getchar() -> Option<u8> {
+    READER.call_once(|| {
+        std::thread::spawn(|| {
+            let mut buf = [0; 64];
+            while let Ok(n @ 1..) = std::io::stdin().read(&mut buf) {
+                INPUT.lock().unwrap().extend(&buf[..n]);
+            }
+        });
+    });
+    INPUT.lock().unwrap().pop_front()
+}
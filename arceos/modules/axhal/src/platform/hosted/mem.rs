@@ -0,0 +1,39 @@
+use std::alloc::Layout;
+
+use lazyinit::LazyInit;
+use memory_addr::PAGE_SIZE_4K;
+
+use crate::mem::{MemRegion, MemRegionFlags};
+
+/// Size of the arena, in MiB, if not given by `hosted.mem=`.
+const DEFAULT_MEM_MIB: usize = 128;
+
+/// The arena allocated from the host, used as the physical memory.
+static ARENA: LazyInit<(usize, usize)> = LazyInit::new();
+
+/// Returns platform-specific memory regions.
+pub(crate) fn platform_regions() -> impl Iterator<Item = MemRegion> {
+    ARENA
+        .get()
+        .map(|&(start, size)| MemRegion {
+            paddr: pa!(start),
+            size,
+            flags: MemRegionFlags::FREE | MemRegionFlags::READ | MemRegionFlags::WRITE,
+            name: "free memory",
+        })
+        .into_iter()
+}
+
+pub(super) fn init_early() {
+    let mib = crate::cmdline::param("hosted.mem")
+        .and_then(|mib| mib.parse().ok())
+        .unwrap_or(DEFAULT_MEM_MIB);
+    let size = mib << 20;
+    let layout = Layout::from_size_align(size, PAGE_SIZE_4K).unwrap();
+    // never freed, as the physical memory
+    let start = unsafe { std::alloc::alloc_zeroed(layout) };
+    if start.is_null() {
+        std::alloc::handle_alloc_error(layout);
+    }
+    ARENA.init_once((start as usize, size));
+}
@@ -0,0 +1,18 @@
+use std::os::unix::process::CommandExt;
+
+/// Shutdown the whole system, by exiting the process.
+pub fn terminate() -> ! {
+    info!("Shutting down...");
+    std::process::exit(0)
+}
+
+/// Resets the whole system, by executing the process again with the same
+/// arguments.
+pub fn reboot() -> ! {
+    info!("Rebooting...");
+    let err = std::process::Command::new("/proc/self/exe")
+        .args(super::boot::args().get(1..).unwrap_or(&[]))
+        .exec();
+    error!("failed to reboot: {}", err);
+    std::process::exit(1)
+}
@@ -0,0 +1,29 @@
+//! The hosted platform: the kernel runs as a process of a Linux host, to test
+//! and fuzz its logic with the tools of the host (sanitizers, debuggers,
+//! coverage) instead of only inside QEMU.
+//!
+//! The console is the standard input and output of the process, the clock is
+//! the monotonic clock of the host, and the physical memory is an arena
+//! allocated from the host and mapped at the same address (the offset from
+//! the physical to the virtual addresses must be 0, as in the default
+//! configuration). The arguments of the process are the kernel command line,
+//! e.g. `hosted.mem=256` for an arena of 256 MiB instead of 128 MiB.
+//!
+//! The code runs unprivileged, so there are no interrupts, no other CPUs, no
+//! page tables and no thread pointer of its own: the `irq`, `smp`, `tls` and
+//! `uspace` features are refused, and with `paging` the kernel address space
+//! is left to the host.
+
+mod boot;
+
+pub mod console;
+pub mod mem;
+pub mod misc;
+pub mod time;
+
+extern "C" {
+    fn rust_main(cpu_id: usize, dtb: usize) -> !;
+}
+
+/// Initializes the platform devices for the primary CPU.
+pub fn platform_init() {}
@@ -0,0 +1,41 @@
+//! The ticks are the nanoseconds of the monotonic clock of the host since
+//! boot.
+
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use lazyinit::LazyInit;
+
+static BOOT: LazyInit<Instant> = LazyInit::new();
+/// Wall time offset in nanoseconds at monotonic time base.
+static EPOCHOFFSET_NANOS: LazyInit<u64> = LazyInit::new();
+
+/// Returns the current clock time in hardware ticks.
+pub fn current_ticks() -> u64 {
+    BOOT.get()
+        .map_or(0, |boot| boot.elapsed().as_nanos() as u64)
+}
+
+/// Converts hardware ticks to nanoseconds.
+#[inline]
+pub const fn ticks_to_nanos(ticks: u64) -> u64 {
+    ticks
+}
+
+/// Converts nanoseconds to hardware ticks.
+#[inline]
+pub const fn nanos_to_ticks(nanos: u64) -> u64 {
+    nanos
+}
+
+/// Return epoch offset in nanoseconds (wall time offset to monotonic clock start).
+pub fn epochoffset_nanos() -> u64 {
+    EPOCHOFFSET_NANOS.get().copied().unwrap_or(0)
+}
+
+pub(super) fn init_early() {
+    BOOT.init_once(Instant::now());
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    EPOCHOFFSET_NANOS.init_once(now.as_nanos() as u64);
+}
@@ -22,6 +22,9 @@ cfg_if::cfg_if! {
     } else if #[cfg(all(target_arch = "aarch64", platform_family = "aarch64-bsta1000b"))] {
         mod aarch64_bsta1000b;
         pub use self::aarch64_bsta1000b::*;
+    } else if #[cfg(feature = "hosted")] {
+        mod hosted;
+        pub use self::hosted::*;
     } else {
         mod dummy;
         pub use self::dummy::*;
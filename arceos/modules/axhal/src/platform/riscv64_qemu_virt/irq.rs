@@ -36,8 +36,16 @@ macro_rules! with_cause {
 }
 
 /// Enables or disables the given IRQ.
-pub fn set_enable(scause: usize, _enabled: bool) {
-    if scause == S_EXT {
+pub fn set_enable(scause: usize, enabled: bool) {
+    if scause == S_TIMER {
+        unsafe {
+            if enabled {
+                sie::set_stimer();
+            } else {
+                sie::clear_stimer();
+            }
+        }
+    } else if scause == S_EXT {
         // TODO: set enable in PLIC
     }
 }
@@ -69,6 +77,10 @@ pub fn dispatch_irq(scause: usize) {
         scause,
         @TIMER => {
             trace!("IRQ: timer");
+            #[cfg(feature = "replay")]
+            if crate::replay::defer_irq(TIMER_IRQ_NUM) {
+                return;
+            }
             TIMER_HANDLER();
         },
         @EXT => crate::irq::dispatch_irq_common(0), // TODO: get IRQ number from PLIC
@@ -7,3 +7,13 @@ pub fn terminate() -> ! {
         crate::arch::halt();
     }
 }
+
+/// Resets the whole system, including all CPUs.
+pub fn reboot() -> ! {
+    info!("Rebooting...");
+    sbi_rt::system_reset(sbi_rt::ColdReboot, sbi_rt::NoReason);
+    warn!("It should reboot!");
+    loop {
+        crate::arch::halt();
+    }
+}
@@ -20,14 +20,19 @@ extern "C" {
 
 unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     crate::mem::clear_bss();
+    #[cfg(feature = "fast-mem")]
+    crate::memfuncs::init_from_dtb(dtb);
     crate::cpu::init_primary(cpu_id);
     crate::arch::set_trap_vector_base(trap_vector_base as usize);
     self::time::init_early();
+    crate::cmdline::init_from_dtb(dtb);
     rust_main(cpu_id, dtb);
 }
 
 #[cfg(feature = "smp")]
 unsafe extern "C" fn rust_entry_secondary(cpu_id: usize) {
+    #[cfg(feature = "fast-mem")]
+    crate::memfuncs::init_secondary();
     crate::arch::set_trap_vector_base(trap_vector_base as usize);
     crate::cpu::init_secondary(cpu_id);
     rust_main_secondary(cpu_id);
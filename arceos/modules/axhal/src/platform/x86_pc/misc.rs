@@ -25,3 +25,15 @@ pub fn terminate() -> ! {
         crate::arch::halt();
     }
 }
+
+/// Resets the whole system through the keyboard controller.
+///
+/// See <https://wiki.osdev.org/Reboot> for more information.
+pub fn reboot() -> ! {
+    info!("Rebooting...");
+    unsafe { PortWriteOnly::new(0x64).write(0xfeu8) };
+    warn!("It should reboot!");
+    loop {
+        crate::arch::halt();
+    }
+}
@@ -16,7 +16,11 @@ pub mod irq {
 }
 
 pub mod console {
-    pub use super::uart16550::*;
+    pub use super::uart16550::{getchar, putchar};
+}
+
+pub mod uart {
+    pub use super::uart16550::{configure, num_ports, read, write};
 }
 
 extern "C" {
@@ -32,10 +36,13 @@ fn current_cpu_id() -> usize {
     }
 }
 
-unsafe extern "C" fn rust_entry(magic: usize, _mbi: usize) {
-    // TODO: handle multiboot info
+unsafe extern "C" fn rust_entry(magic: usize, mbi: usize) {
+    // TODO: handle the other multiboot info
     if magic == self::boot::MULTIBOOT_BOOTLOADER_MAGIC {
         crate::mem::clear_bss();
+        #[cfg(feature = "fast-mem")]
+        crate::memfuncs::init();
+        crate::cmdline::init_from_multiboot(mbi);
         crate::cpu::init_primary(current_cpu_id());
         self::uart16550::init();
         self::dtables::init_primary();
@@ -3,16 +3,28 @@
 use kspin::SpinNoIrq;
 use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
 
+use crate::uart::{Parity, UartConfig, UartError};
+
 const UART_CLOCK_FACTOR: usize = 16;
 const OSC_FREQ: usize = 1_843_200;
 
 static COM1: SpinNoIrq<Uart16550> = SpinNoIrq::new(Uart16550::new(0x3f8));
 
+/// `COM2` to `COM4`, for [`crate::uart`].
+static SPARE: [SpinNoIrq<Spare>; 3] = [
+    SpinNoIrq::new(Spare::new(0x2f8)),
+    SpinNoIrq::new(Spare::new(0x3e8)),
+    SpinNoIrq::new(Spare::new(0x2e8)),
+];
+
 bitflags::bitflags! {
     /// Line status flags
     struct LineStsFlags: u8 {
         const INPUT_FULL = 1;
-        // 1 to 4 unknown
+        const OVERRUN_ERROR = 1 << 1;
+        const PARITY_ERROR = 1 << 2;
+        const FRAMING_ERROR = 1 << 3;
+        // 4 is break
         const OUTPUT_EMPTY = 1 << 5;
         // 6 and 7 unknown
     }
@@ -25,6 +37,7 @@ struct Uart16550 {
     line_ctrl: PortWriteOnly<u8>,
     modem_ctrl: PortWriteOnly<u8>,
     line_sts: PortReadOnly<u8>,
+    scratch: Port<u8>,
 }
 
 impl Uart16550 {
@@ -36,10 +49,17 @@ impl Uart16550 {
             line_ctrl: PortWriteOnly::new(port + 3),
             modem_ctrl: PortWriteOnly::new(port + 4),
             line_sts: PortReadOnly::new(port + 5),
+            scratch: Port::new(port + 7),
         }
     }
 
     fn init(&mut self, baud_rate: usize) {
+        self.set_line(baud_rate, 0x03);
+    }
+
+    /// Sets the baud rate and the line control register (the format of the
+    /// characters).
+    fn set_line(&mut self, baud_rate: usize, line_ctrl: u8) {
         unsafe {
             // Disable interrupts
             self.int_en.write(0x00);
@@ -52,8 +72,8 @@ impl Uart16550 {
             self.data.write((divisor & 0xff) as u8);
             self.int_en.write((divisor >> 8) as u8);
 
-            // Disable DLAB and set data word length to 8 bits
-            self.line_ctrl.write(0x03);
+            // Disable DLAB and set the format of the characters
+            self.line_ctrl.write(line_ctrl);
 
             // Enable FIFO, clear TX/RX queues and
             // set interrupt watermark at 14 bytes
@@ -65,6 +85,14 @@ impl Uart16550 {
         }
     }
 
+    /// Checks that the UART is there, with its scratch register.
+    fn probe(&mut self) -> bool {
+        unsafe {
+            self.scratch.write(0x5a);
+            self.scratch.read() == 0x5a
+        }
+    }
+
     fn line_sts(&mut self) -> LineStsFlags {
         unsafe { LineStsFlags::from_bits_truncate(self.line_sts.read()) }
     }
@@ -83,6 +111,92 @@ impl Uart16550 {
     }
 }
 
+struct Spare {
+    uart: Uart16550,
+    present: bool,
+    configured: bool,
+}
+
+impl Spare {
+    const fn new(port: u16) -> Self {
+        Self {
+            uart: Uart16550::new(port),
+            present: false,
+            configured: false,
+        }
+    }
+}
+
+/// Returns the spare UART `port`, counting the present ones only.
+fn spare(port: usize) -> Result<&'static SpinNoIrq<Spare>, UartError> {
+    SPARE
+        .iter()
+        .filter(|spare| spare.lock().present)
+        .nth(port)
+        .ok_or(UartError::NoSuchPort)
+}
+
+/// Returns the number of spare UARTs.
+pub fn num_ports() -> usize {
+    SPARE.iter().filter(|spare| spare.lock().present).count()
+}
+
+/// Configures a spare UART.
+pub fn configure(port: usize, config: &UartConfig) -> Result<(), UartError> {
+    let baud_rate = config.baud_rate as usize * UART_CLOCK_FACTOR;
+    if OSC_FREQ % baud_rate != 0 || OSC_FREQ / baud_rate > 0xffff {
+        return Err(UartError::InvalidConfig);
+    }
+    let mut line_ctrl = config.data_bits - 5;
+    if config.stop_bits == 2 {
+        line_ctrl |= 1 << 2;
+    }
+    line_ctrl |= match config.parity {
+        Parity::None => 0,
+        Parity::Odd => 1 << 3,
+        Parity::Even => 3 << 3,
+    };
+    let mut spare = spare(port)?.lock();
+    spare.uart.set_line(config.baud_rate as usize, line_ctrl);
+    spare.configured = true;
+    Ok(())
+}
+
+/// Sends bytes on a spare UART.
+pub fn write(port: usize, data: &[u8]) -> Result<(), UartError> {
+    let spare = spare(port)?;
+    for &byte in data {
+        // do not keep the interrupts disabled for the whole data
+        let mut spare = spare.lock();
+        if !spare.configured {
+            return Err(UartError::NotConfigured);
+        }
+        spare.uart.putchar(byte);
+    }
+    Ok(())
+}
+
+/// Reads the bytes received on a spare UART.
+pub fn read(port: usize, buf: &mut [u8]) -> Result<usize, UartError> {
+    let mut spare = spare(port)?.lock();
+    if !spare.configured {
+        return Err(UartError::NotConfigured);
+    }
+    let mut len = 0;
+    while len < buf.len() {
+        let sts = spare.uart.line_sts();
+        if !sts.contains(LineStsFlags::INPUT_FULL) {
+            break;
+        }
+        let byte = unsafe { spare.uart.data.read() };
+        if !sts.intersects(LineStsFlags::PARITY_ERROR | LineStsFlags::FRAMING_ERROR) {
+            buf[len] = byte;
+            len += 1;
+        }
+    }
+    Ok(len)
+}
+
 /// Writes a byte to the console.
 pub fn putchar(c: u8) {
     let mut uart = COM1.lock();
@@ -102,4 +216,8 @@ pub fn getchar() -> Option<u8> {
 
 pub(super) fn init() {
     COM1.lock().init(115200);
+    for spare in &SPARE {
+        let mut spare = spare.lock();
+        spare.present = spare.uart.probe();
+    }
 }
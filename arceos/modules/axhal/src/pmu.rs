@@ -0,0 +1,47 @@
+//! The performance monitoring unit (PMU) of the CPUs, for cycle-accurate
+//! timing.
+//!
+//! [`cycles`] reads the cycle counter of the current CPU:
+//!
+//! - x86_64: the time stamp counter, which counts at a constant rate (the
+//!   base frequency) on the recent CPUs, whatever their current frequency;
+//! - RISC-V: the `cycle` CSR, readable in S-mode if the SBI firmware allows
+//!   it (OpenSBI does);
+//! - AArch64: `PMCCNTR_EL0`, enabled on each CPU at boot.
+//!
+//! The counters of the CPUs are not synchronized, so an interval is only
+//! meaningful if measured on one CPU.
+
+/// Enables the cycle counter of the current CPU.
+#[allow(dead_code)]
+pub(crate) fn init_percpu() {
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        use core::arch::asm;
+        // PMCR_EL0.E enables the counters, PMCNTENSET_EL0.C the cycle
+        // counter, and PMCCFILTR_EL0 = 0 counts at EL0 and EL1.
+        let pmcr: u64;
+        asm!("mrs {}, pmcr_el0", out(reg) pmcr);
+        asm!("msr pmcr_el0, {}", in(reg) pmcr | 1);
+        asm!("msr pmccfiltr_el0, xzr");
+        asm!("msr pmcntenset_el0, {}", "isb", in(reg) 1u64 << 31);
+    }
+}
+
+/// Returns the number of cycles counted by the current CPU.
+#[inline]
+pub fn cycles() -> u64 {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            unsafe { core::arch::x86_64::_rdtsc() }
+        } else if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+            riscv::register::cycle::read64()
+        } else if #[cfg(target_arch = "aarch64")] {
+            let cycles: u64;
+            unsafe { core::arch::asm!("mrs {}, pmccntr_el0", out(reg) cycles) };
+            cycles
+        } else {
+            0
+        }
+    }
+}
@@ -0,0 +1,461 @@
+//! Deterministic record/replay of the external inputs, for debugging.
+//!
+//! A run with `replay=record` on the kernel command line writes all the
+//! nondeterministic inputs to a log: the timer reads, the epoch offset of the
+//! RTC, the random numbers, the frames received by the NICs and the
+//! interrupts. A later run of the same kernel with `replay=replay` takes them
+//! from the log instead of the hardware, so it goes through the same states,
+//! and a heisenbug seen once can be debugged at will.
+//!
+//! The log is the file of the host given by `replay.file=` (`replay.log` by
+//! default), accessed by semihosting, so it only works under QEMU (with
+//! `-semihosting`), on RISC-V and AArch64, and with one CPU.
+//!
+//! Each input point counts as a step. The interrupts are not handled when
+//! they arrive but at the next step with interrupts enabled (or when waiting
+//! for them), so that they are seen at the same step when replaying. Code
+//! spinning on the effect of an interrupt without reading the time hangs in
+//! these modes. The replay stops at the first input that differs from the
+//! log, and the system shuts down at the end of the log.
+
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use kspin::SpinNoIrq;
+
+use crate::irq::{set_enable, IrqHandler};
+use crate::semihosting;
+
+/// The magic at the start of a log, with the format version.
+const LOG_MAGIC: &[u8; 8] = b"AXRR\x00\x00\x00\x01";
+
+/// The maximum size of the data of an input, i.e. of a frame.
+pub const MAX_INPUT_SIZE: usize = 1536;
+
+/// The size of the header of a log entry: kind, step and size.
+const ENTRY_HEADER_SIZE: usize = 11;
+
+const READ_BUF_SIZE: usize = 4096;
+
+/// The maximum number of IRQs with handlers.
+const MAX_IRQS: usize = 8;
+
+/// The mode of record/replay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ReplayMode {
+    /// The inputs are taken from the hardware.
+    Off = 0,
+    /// The inputs are taken from the hardware and written to the log.
+    Record = 1,
+    /// The inputs are taken from the log.
+    Replay = 2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum InputKind {
+    Ticks = 1,
+    EpochOffset = 2,
+    Random = 3,
+    NetRx = 4,
+    Irq = 5,
+}
+
+impl InputKind {
+    fn from_u8(kind: u8) -> Option<Self> {
+        Some(match kind {
+            1 => Self::Ticks,
+            2 => Self::EpochOffset,
+            3 => Self::Random,
+            4 => Self::NetRx,
+            5 => Self::Irq,
+            _ => return None,
+        })
+    }
+}
+
+/// An entry of the log.
+struct Entry {
+    kind: InputKind,
+    step: u64,
+    len: usize,
+    data: [u8; MAX_INPUT_SIZE],
+}
+
+struct ReplayState {
+    file: usize,
+    step: u64,
+    /// The entry read ahead when replaying, `None` at the end of the log.
+    next: Option<Entry>,
+    read_buf: [u8; READ_BUF_SIZE],
+    read_pos: usize,
+    read_len: usize,
+    /// The IRQs arrived and not handled yet when recording.
+    pending: [usize; MAX_IRQS],
+    num_pending: usize,
+    handlers: [(usize, Option<IrqHandler>); MAX_IRQS],
+}
+
+static MODE: AtomicU8 = AtomicU8::new(ReplayMode::Off as u8);
+
+/// Set while taking an input from the hardware, whose own inputs (e.g. the
+/// time seeding the random numbers) are not steps.
+static IN_INPUT: AtomicBool = AtomicBool::new(false);
+
+static STATE: SpinNoIrq<ReplayState> = SpinNoIrq::new(ReplayState {
+    file: 0,
+    step: 0,
+    next: None,
+    read_buf: [0; READ_BUF_SIZE],
+    read_pos: 0,
+    read_len: 0,
+    pending: [0; MAX_IRQS],
+    num_pending: 0,
+    handlers: [(0, None); MAX_IRQS],
+});
+
+impl ReplayState {
+    fn write_entry(&mut self, kind: InputKind, data: &[u8]) {
+        let mut header = [0; ENTRY_HEADER_SIZE];
+        header[0] = kind as u8;
+        header[1..9].copy_from_slice(&self.step.to_le_bytes());
+        header[9..].copy_from_slice(&(data.len() as u16).to_le_bytes());
+        if !semihosting::write(self.file, &header) || !semihosting::write(self.file, data) {
+            // cannot log it, keep going without recording
+            MODE.store(ReplayMode::Off as u8, Ordering::Release);
+        }
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> bool {
+        for b in buf.iter_mut() {
+            if self.read_pos == self.read_len {
+                self.read_len = semihosting::read(self.file, &mut self.read_buf);
+                self.read_pos = 0;
+                if self.read_len == 0 {
+                    return false;
+                }
+            }
+            *b = self.read_buf[self.read_pos];
+            self.read_pos += 1;
+        }
+        true
+    }
+
+    /// Reads the next entry ahead. A truncated entry ends the log.
+    fn read_next(&mut self) {
+        let mut header = [0; ENTRY_HEADER_SIZE];
+        let mut entry = Entry {
+            kind: InputKind::Ticks,
+            step: 0,
+            len: 0,
+            data: [0; MAX_INPUT_SIZE],
+        };
+        self.next = None;
+        if !self.read_exact(&mut header) {
+            return;
+        }
+        let Some(kind) = InputKind::from_u8(header[0]) else {
+            return;
+        };
+        entry.kind = kind;
+        entry.step = u64::from_le_bytes(header[1..9].try_into().unwrap());
+        entry.len = u16::from_le_bytes([header[9], header[10]]) as usize;
+        if entry.len > MAX_INPUT_SIZE || !self.read_exact(&mut entry.data[..entry.len]) {
+            return;
+        }
+        self.next = Some(entry);
+    }
+
+    /// Takes the next entry if it is an input `kind` of the current step.
+    fn take_next(&mut self, kind: InputKind) -> Option<Entry> {
+        match &self.next {
+            Some(e) if e.kind == kind && e.step == self.step => {}
+            _ => return None,
+        }
+        let entry = self.next.take();
+        self.read_next();
+        entry
+    }
+
+    fn handler(&self, irq_num: usize) -> Option<IrqHandler> {
+        self.handlers.iter().find(|h| h.0 == irq_num)?.1
+    }
+}
+
+/// Returns the current mode.
+pub fn mode() -> ReplayMode {
+    match MODE.load(Ordering::Acquire) {
+        1 => ReplayMode::Record,
+        2 => ReplayMode::Replay,
+        _ => ReplayMode::Off,
+    }
+}
+
+/// Returns whether the inputs are taken from the log.
+pub fn is_replaying() -> bool {
+    mode() == ReplayMode::Replay
+}
+
+/// Returns the number of steps so far.
+pub fn steps() -> u64 {
+    STATE.lock().step
+}
+
+/// Starts recording or replaying, as set by `replay=` on the kernel command
+/// line.
+///
+/// It must be called before the application starts, and before the other
+/// CPUs.
+pub fn init() {
+    let mode = match crate::cmdline::param("replay") {
+        Some("record") => ReplayMode::Record,
+        Some("replay") => ReplayMode::Replay,
+        Some(m) => {
+            warn!("replay: unknown mode {:?}", m);
+            return;
+        }
+        None => return,
+    };
+    if !semihosting::SUPPORTED {
+        warn!("replay: semihosting is not supported on this architecture");
+        return;
+    }
+    if axconfig::SMP > 1 {
+        warn!("replay: only one CPU is supported");
+        return;
+    }
+    let path = crate::cmdline::param("replay.file").unwrap_or("replay.log");
+    let file_mode = match mode {
+        ReplayMode::Record => semihosting::MODE_WRITE,
+        _ => semihosting::MODE_READ,
+    };
+    let Some(file) = semihosting::open(path, file_mode) else {
+        warn!("replay: cannot open {:?} on the host", path);
+        return;
+    };
+
+    let mut state = STATE.lock();
+    state.file = file;
+    if mode == ReplayMode::Record {
+        if !semihosting::write(file, LOG_MAGIC) {
+            warn!("replay: cannot write {:?}", path);
+            return;
+        }
+    } else {
+        let mut magic = [0; LOG_MAGIC.len()];
+        if !state.read_exact(&mut magic) || &magic != LOG_MAGIC {
+            warn!("replay: {:?} is not a log of this version", path);
+            return;
+        }
+        state.read_next();
+    }
+    drop(state);
+    MODE.store(mode as u8, Ordering::Release);
+    info!("replay: {:?} with {:?}", mode, path);
+}
+
+/// Stops replaying at an input of the kernel not in the log.
+fn diverged(step: u64, kind: InputKind) -> ! {
+    MODE.store(ReplayMode::Off as u8, Ordering::Release);
+    panic!(
+        "replay: diverged at step {}, the log has no {:?} there",
+        step, kind
+    );
+}
+
+/// Ends the replay at the end of the log.
+fn end_of_log(step: u64) -> ! {
+    MODE.store(ReplayMode::Off as u8, Ordering::Release);
+    info!("replay: end of the log at step {}", step);
+    crate::misc::terminate()
+}
+
+/// Takes an input: from `live`, written to the log if recording, or from the
+/// log if replaying.
+fn input<const N: usize>(kind: InputKind, live: impl FnOnce() -> [u8; N]) -> [u8; N] {
+    let mode = mode();
+    if mode == ReplayMode::Off || IN_INPUT.load(Ordering::Relaxed) {
+        return live();
+    }
+    let value = if mode == ReplayMode::Record {
+        IN_INPUT.store(true, Ordering::Relaxed);
+        let value = live();
+        IN_INPUT.store(false, Ordering::Relaxed);
+        let mut state = STATE.lock();
+        state.step += 1;
+        state.write_entry(kind, &value);
+        value
+    } else {
+        let mut state = STATE.lock();
+        state.step += 1;
+        let step = state.step;
+        match state.take_next(kind) {
+            Some(entry) if entry.len == N => entry.data[..N].try_into().unwrap(),
+            _ if state.next.is_none() => {
+                drop(state);
+                end_of_log(step)
+            }
+            _ => {
+                drop(state);
+                diverged(step, kind)
+            }
+        }
+    };
+    handle_irqs();
+    value
+}
+
+/// Handles the IRQs of the current step, if interrupts are enabled: the ones
+/// arrived when recording, the ones in the log when replaying.
+fn handle_irqs() {
+    if !crate::arch::irqs_enabled() {
+        return;
+    }
+    loop {
+        let mut state = STATE.lock();
+        let irq_num = match mode() {
+            ReplayMode::Record if state.num_pending > 0 => {
+                state.num_pending -= 1;
+                let irq_num = state.pending[state.num_pending];
+                state.write_entry(InputKind::Irq, &(irq_num as u64).to_le_bytes());
+                irq_num
+            }
+            ReplayMode::Replay => match state.take_next(InputKind::Irq) {
+                Some(entry) => u64::from_le_bytes(entry.data[..8].try_into().unwrap()) as usize,
+                None => return,
+            },
+            _ => return,
+        };
+        let handler = state.handler(irq_num);
+        drop(state);
+
+        // as if the IRQ arrived here
+        let guard = kernel_guard::NoPreemptIrqSave::new();
+        match handler {
+            Some(handler) => handler(),
+            None => warn!("replay: unhandled IRQ {}", irq_num),
+        }
+        if mode() == ReplayMode::Record {
+            set_enable(irq_num, true);
+        }
+        drop(guard); // rescheduling may occur when preemption is re-enabled.
+    }
+}
+
+/// Notes the handler of an IRQ, to handle the IRQs of the log.
+pub(crate) fn register_handler(irq_num: usize, handler: IrqHandler) {
+    let mut state = STATE.lock();
+    if let Some(slot) = state
+        .handlers
+        .iter_mut()
+        .find(|h| h.1.is_none() || h.0 == irq_num)
+    {
+        *slot = (irq_num, Some(handler));
+    }
+}
+
+/// Called when an IRQ arrives, returns whether its handling is deferred to
+/// the next step (the IRQ is masked until then), or dropped when replaying.
+pub(crate) fn defer_irq(irq_num: usize) -> bool {
+    match mode() {
+        ReplayMode::Off => false,
+        ReplayMode::Record => {
+            set_enable(irq_num, false);
+            let mut state = STATE.lock();
+            if state.num_pending < MAX_IRQS {
+                let n = state.num_pending;
+                state.pending[n] = irq_num;
+                state.num_pending += 1;
+            }
+            true
+        }
+        ReplayMode::Replay => {
+            // the IRQs are the ones of the log
+            set_enable(irq_num, false);
+            true
+        }
+    }
+}
+
+/// Waits for IRQs with `wfi`, a step handling them. When replaying, it does
+/// not wait, the IRQs are the ones of the log.
+pub(crate) fn wait_for_irqs(wfi: fn()) {
+    match mode() {
+        ReplayMode::Off => return wfi(),
+        ReplayMode::Record => {
+            // not to sleep with an IRQ deferred and masked
+            crate::arch::disable_irqs();
+            if STATE.lock().num_pending == 0 {
+                wfi();
+            }
+            crate::arch::enable_irqs();
+        }
+        ReplayMode::Replay => {}
+    }
+    let mut state = STATE.lock();
+    state.step += 1;
+    let step = state.step;
+    if mode() == ReplayMode::Replay && state.next.is_none() {
+        drop(state);
+        end_of_log(step);
+    }
+    drop(state);
+    handle_irqs();
+}
+
+/// The current time in hardware ticks, from `live`.
+pub(crate) fn ticks(live: impl FnOnce() -> u64) -> u64 {
+    u64::from_le_bytes(input(InputKind::Ticks, || live().to_le_bytes()))
+}
+
+/// The epoch offset of the RTC, from `live`.
+pub(crate) fn epoch_offset(live: impl FnOnce() -> u64) -> u64 {
+    u64::from_le_bytes(input(InputKind::EpochOffset, || live().to_le_bytes()))
+}
+
+/// A random number, from `live`.
+pub(crate) fn random(live: impl FnOnce() -> u128) -> u128 {
+    u128::from_le_bytes(input(InputKind::Random, || live().to_le_bytes()))
+}
+
+/// Records an attempt to receive a frame from a NIC, and the frame received,
+/// if any. It must not be called when replaying, see [`replay_net_rx`].
+pub fn record_net_rx(frame: Option<&[u8]>) {
+    if mode() != ReplayMode::Record {
+        return;
+    }
+    let mut state = STATE.lock();
+    state.step += 1;
+    if let Some(frame) = frame {
+        state.write_entry(InputKind::NetRx, &frame[..frame.len().min(MAX_INPUT_SIZE)]);
+    }
+    drop(state);
+    handle_irqs();
+}
+
+/// Replays an attempt to receive a frame from a NIC: copies the frame
+/// received then into `buf` and returns its length, or returns `None` if
+/// none was.
+pub fn replay_net_rx(buf: &mut [u8]) -> Option<usize> {
+    if mode() != ReplayMode::Replay {
+        return None;
+    }
+    let mut state = STATE.lock();
+    state.step += 1;
+    let step = state.step;
+    let len = match state.take_next(InputKind::NetRx) {
+        Some(entry) => {
+            let len = entry.len.min(buf.len());
+            buf[..len].copy_from_slice(&entry.data[..len]);
+            Some(len)
+        }
+        None if state.next.is_none() => {
+            drop(state);
+            end_of_log(step)
+        }
+        None => None,
+    };
+    drop(state);
+    handle_irqs();
+    len
+}
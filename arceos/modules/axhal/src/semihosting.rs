@@ -0,0 +1,78 @@
+//! Semihosting calls, to access the files of the host when running under
+//! QEMU (with `-semihosting`). RISC-V and AArch64 only.
+
+const SYS_OPEN: usize = 0x01;
+const SYS_WRITE: usize = 0x05;
+const SYS_READ: usize = 0x06;
+
+/// The mode `rb` of `SYS_OPEN`.
+pub const MODE_READ: usize = 1;
+/// The mode `wb` of `SYS_OPEN`.
+pub const MODE_WRITE: usize = 5;
+
+/// The maximum length of a path.
+const PATH_MAX: usize = 255;
+
+/// Whether semihosting is supported on this architecture.
+pub const SUPPORTED: bool = cfg!(any(target_arch = "riscv64", target_arch = "aarch64"));
+
+#[cfg(target_arch = "riscv64")]
+unsafe fn call(op: usize, args: &[usize]) -> isize {
+    let ret;
+    // the sequence QEMU recognizes, not compressed and within a page
+    core::arch::asm!(
+        ".option push",
+        ".option norvc",
+        ".balign 16",
+        "slli zero, zero, 0x1f",
+        "ebreak",
+        "srai zero, zero, 0x7",
+        ".option pop",
+        inlateout("a0") op => ret,
+        in("a1") args.as_ptr(),
+        options(nostack),
+    );
+    ret
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn call(op: usize, args: &[usize]) -> isize {
+    let ret;
+    core::arch::asm!(
+        "hlt #0xf000",
+        inlateout("x0") op => ret,
+        in("x1") args.as_ptr(),
+        options(nostack),
+    );
+    ret
+}
+
+#[cfg(not(any(target_arch = "riscv64", target_arch = "aarch64")))]
+unsafe fn call(_op: usize, _args: &[usize]) -> isize {
+    -1
+}
+
+/// Opens the file of the host at `path` in `mode`, returns its handle.
+pub fn open(path: &str, mode: usize) -> Option<usize> {
+    if path.len() > PATH_MAX {
+        return None;
+    }
+    let mut name = [0u8; PATH_MAX + 1];
+    name[..path.len()].copy_from_slice(path.as_bytes());
+    let ret = unsafe { call(SYS_OPEN, &[name.as_ptr() as usize, mode, path.len()]) };
+    (ret >= 0).then_some(ret as usize)
+}
+
+/// Writes all of `data` to the file `handle`, returns whether it succeeded.
+pub fn write(handle: usize, data: &[u8]) -> bool {
+    // returns the number of bytes not written
+    unsafe { call(SYS_WRITE, &[handle, data.as_ptr() as usize, data.len()]) == 0 }
+}
+
+/// Reads from the file `handle` into `buf`, returns the number of bytes
+/// read, 0 at the end of the file.
+pub fn read(handle: usize, buf: &mut [u8]) -> usize {
+    // returns the number of bytes not read
+    let ret = unsafe { call(SYS_READ, &[handle, buf.as_mut_ptr() as usize, buf.len()]) };
+    buf.len().saturating_sub(ret.max(0) as usize)
+}
@@ -0,0 +1,455 @@
+//! Snapshot and restore of the whole state of the system, for near-instant
+//! startup.
+//!
+//! [`take_snapshot`] writes the memory image of the system to a file of the
+//! host: the writable sections of the kernel and the pages of the free memory
+//! that are not zero, with the context of the calling task. A later boot of
+//! the same kernel on the same machine calls [`restore_snapshot`] early, which
+//! loads the image back and resumes the caller of [`take_snapshot`], that
+//! sees it return `Ok(true)`.
+//!
+//! The state of the devices is not in the image. The interrupt controller and
+//! the timer are initialized again by the restore, with the IRQs that have
+//! handlers enabled. The other devices (e.g. VirtIO devices, whose queues are
+//! known to the host) must be probed again after the restore, and given to
+//! the subsystems using them. The monotonic clock goes on from the time of the
+//! snapshot, and the time until the restore counts as suspended (see
+//! [`add_suspended_time`](crate::time::add_suspended_time)).
+//!
+//! The file is accessed by semihosting, so it only works under QEMU (with
+//! `-semihosting`), on RISC-V and AArch64, and with one CPU.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ptr::{addr_of, addr_of_mut};
+
+use memory_addr::{PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+use crate::arch::TaskContext;
+use crate::mem::{memory_regions, phys_to_virt, virt_to_phys, MemRegionFlags};
+use crate::semihosting;
+
+/// The magic at the start of a snapshot, with the format version.
+const SNAPSHOT_MAGIC: &[u8; 8] = b"AXSS\x00\x00\x00\x01";
+
+/// The size of the header of a snapshot: the magic and the hash of the
+/// kernel code.
+const HEADER_SIZE: usize = 16;
+
+/// The size of the header of a record: its address and its size.
+const RECORD_HEADER_SIZE: usize = 16;
+
+/// The address of the record ending a snapshot.
+const END_OF_RECORDS: u64 = u64::MAX;
+
+/// The size of the stack writing or loading the image.
+const SCRATCH_STACK_SIZE: usize = 16 * 1024;
+
+/// The errors of taking or restoring a snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// Semihosting is not supported on this architecture, or there are
+    /// several CPUs.
+    Unsupported,
+    /// The file cannot be opened, read or written.
+    Io,
+    /// The file is not a snapshot of this kernel on this machine.
+    BadImage,
+}
+
+/// A static only accessed by one CPU, with interrupts disabled.
+struct Static<T>(UnsafeCell<T>);
+
+unsafe impl<T> Sync for Static<T> {}
+
+impl<T> Static<T> {
+    const fn new(value: T) -> Self {
+        Self(UnsafeCell::new(value))
+    }
+
+    fn get(&self) -> *mut T {
+        self.0.get()
+    }
+}
+
+/// The state saved in the image.
+struct Saved {
+    /// The context of the task taking the snapshot.
+    ctx: MaybeUninit<TaskContext>,
+    page_table_root: usize,
+    ticks: u64,
+    wall_nanos: u64,
+    /// Set when the image is restored.
+    restored: bool,
+    /// Set with `restored`: the time between the snapshot and the restore.
+    suspended_nanos: u64,
+}
+
+/// The state of writing or loading the image, not part of it.
+#[repr(C, align(4096))]
+struct Scratch {
+    stack: [u8; SCRATCH_STACK_SIZE],
+    #[cfg(feature = "shadow-call-stack")]
+    shadow_call_stack: [u8; PAGE_SIZE_4K],
+    /// The context of the task restoring the snapshot, to return to on
+    /// failure.
+    ctx: MaybeUninit<TaskContext>,
+    file: usize,
+    result: Result<(), SnapshotError>,
+    /// The wall time of the boot restoring the snapshot.
+    wall_nanos: u64,
+    /// The device tree of the boot restoring the snapshot, to clear.
+    dtb: (usize, usize),
+}
+
+static SAVED: Static<Saved> = Static::new(Saved {
+    ctx: MaybeUninit::uninit(),
+    page_table_root: 0,
+    ticks: 0,
+    wall_nanos: 0,
+    restored: false,
+    suspended_nanos: 0,
+});
+
+static SCRATCH: Static<Scratch> = Static::new(Scratch {
+    stack: [0; SCRATCH_STACK_SIZE],
+    #[cfg(feature = "shadow-call-stack")]
+    shadow_call_stack: [0; PAGE_SIZE_4K],
+    ctx: MaybeUninit::uninit(),
+    file: 0,
+    result: Ok(()),
+    wall_nanos: 0,
+    dtb: (0, 0),
+});
+
+fn check_supported() -> Result<(), SnapshotError> {
+    if !semihosting::SUPPORTED || axconfig::SMP > 1 {
+        return Err(SnapshotError::Unsupported);
+    }
+    Ok(())
+}
+
+/// The header of the snapshots of this kernel.
+fn header() -> [u8; HEADER_SIZE] {
+    // FNV-1a
+    let hash = crate::mem::kernel_text()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325u64, |h, &b| {
+            (h ^ b as u64).wrapping_mul(0x100_0000_01b3)
+        });
+    let mut header = [0; HEADER_SIZE];
+    header[..8].copy_from_slice(SNAPSHOT_MAGIC);
+    header[8..].copy_from_slice(&hash.to_le_bytes());
+    header
+}
+
+/// The physical address range of [`SCRATCH`].
+fn scratch_range() -> (usize, usize) {
+    let start = virt_to_phys(VirtAddr::from(SCRATCH.get() as usize)).as_usize();
+    (start, start + core::mem::size_of::<Scratch>())
+}
+
+/// Returns an empty context in the current address space.
+///
+/// Unlike [`TaskContext::new`], it does not need the kernel page table to be
+/// set up.
+fn new_context() -> TaskContext {
+    #[allow(unused_mut)]
+    let mut ctx: TaskContext = unsafe { MaybeUninit::zeroed().assume_init() };
+    #[cfg(all(
+        feature = "uspace",
+        any(target_arch = "riscv32", target_arch = "riscv64")
+    ))]
+    ctx.set_page_table_root(crate::arch::read_page_table_root());
+    ctx
+}
+
+/// Switches from the context `from` to `entry` on the scratch stack.
+unsafe fn switch_to_scratch(from: *mut MaybeUninit<TaskContext>, entry: fn() -> !) {
+    let scratch = SCRATCH.get();
+    let stack_top = addr_of!((*scratch).stack) as usize + SCRATCH_STACK_SIZE;
+    let mut ctx = new_context();
+    ctx.init(
+        entry as usize,
+        VirtAddr::from(stack_top),
+        VirtAddr::from(crate::arch::read_thread_pointer()),
+    );
+    #[cfg(feature = "shadow-call-stack")]
+    ctx.set_shadow_call_stack(VirtAddr::from(
+        addr_of!((*scratch).shadow_call_stack) as usize
+    ));
+    (*from).write(new_context());
+    (*from).assume_init_mut().switch_to(&ctx);
+}
+
+/// Switches back to the context `to`, from the scratch stack.
+unsafe fn switch_from_scratch(to: *const MaybeUninit<TaskContext>) -> ! {
+    let mut ctx = new_context();
+    ctx.switch_to((*to).assume_init_ref());
+    unreachable!()
+}
+
+fn write_all(file: usize, data: &[u8]) -> Result<(), SnapshotError> {
+    if semihosting::write(file, data) {
+        Ok(())
+    } else {
+        Err(SnapshotError::Io)
+    }
+}
+
+fn read_exact(file: usize, buf: &mut [u8]) -> bool {
+    let mut pos = 0;
+    while pos < buf.len() {
+        match semihosting::read(file, &mut buf[pos..]) {
+            0 => return false,
+            n => pos += n,
+        }
+    }
+    true
+}
+
+/// Writes a record of the memory `[start, end)`, a physical address range.
+fn write_record(file: usize, start: usize, end: usize) -> Result<(), SnapshotError> {
+    if start >= end {
+        return Ok(());
+    }
+    let mut header = [0; RECORD_HEADER_SIZE];
+    header[..8].copy_from_slice(&(start as u64).to_le_bytes());
+    header[8..].copy_from_slice(&((end - start) as u64).to_le_bytes());
+    write_all(file, &header)?;
+    let data =
+        unsafe { core::slice::from_raw_parts(phys_to_virt(start.into()).as_ptr(), end - start) };
+    write_all(file, data)
+}
+
+/// Writes the records of the memory `[start, end)`, but [`SCRATCH`].
+fn write_range(file: usize, start: usize, end: usize) -> Result<(), SnapshotError> {
+    let (skip_start, skip_end) = scratch_range();
+    if skip_end <= start || end <= skip_start {
+        return write_record(file, start, end);
+    }
+    write_record(file, start, skip_start)?;
+    write_record(file, skip_end, end)
+}
+
+fn is_zero_page(paddr: usize) -> bool {
+    let words = unsafe {
+        core::slice::from_raw_parts(
+            phys_to_virt(paddr.into()).as_ptr() as *const u64,
+            PAGE_SIZE_4K / 8,
+        )
+    };
+    words.iter().all(|&w| w == 0)
+}
+
+/// Writes the memory image: the writable memory, but the pages of the free
+/// memory that are zero.
+fn write_image(file: usize) -> Result<(), SnapshotError> {
+    write_all(file, &header())?;
+    for r in memory_regions() {
+        if r.flags.contains(MemRegionFlags::DEVICE) || !r.flags.contains(MemRegionFlags::WRITE) {
+            continue;
+        }
+        let start = r.paddr.as_usize();
+        let end = start + r.size;
+        if !r.flags.contains(MemRegionFlags::FREE) {
+            write_range(file, start, end)?;
+            continue;
+        }
+        // the runs of non-zero pages
+        let mut run_start = None;
+        let mut page = start;
+        while page < end {
+            match (is_zero_page(page), run_start) {
+                (false, None) => run_start = Some(page),
+                (true, Some(s)) => {
+                    write_range(file, s, page)?;
+                    run_start = None;
+                }
+                _ => {}
+            }
+            page += PAGE_SIZE_4K;
+        }
+        if let Some(s) = run_start {
+            write_range(file, s, end)?;
+        }
+    }
+    write_all(file, &END_OF_RECORDS.to_le_bytes())
+}
+
+fn write_entry() -> ! {
+    unsafe {
+        let scratch = SCRATCH.get();
+        (*scratch).result = write_image((*scratch).file);
+        switch_from_scratch(addr_of!((*SAVED.get()).ctx))
+    }
+}
+
+/// Returns whether `[start, start + len)` is in the RAM of this machine, out
+/// of [`SCRATCH`].
+fn is_loadable(start: usize, len: usize) -> bool {
+    let (skip_start, skip_end) = scratch_range();
+    let end = start.saturating_add(len);
+    let in_ram = memory_regions().any(|r| {
+        !r.flags.contains(MemRegionFlags::DEVICE)
+            && r.flags.contains(MemRegionFlags::WRITE)
+            && r.paddr.as_usize() <= start
+            && end <= r.paddr.as_usize() + r.size
+    });
+    in_ram && (end <= skip_start || skip_end <= start)
+}
+
+/// Loads the records of the image, returns whether any memory was
+/// overwritten on failure.
+fn load_image(file: usize) -> Result<(), bool> {
+    let mut loaded = false;
+    loop {
+        let mut addr = [0; 8];
+        if !read_exact(file, &mut addr) {
+            return Err(loaded);
+        }
+        let start = u64::from_le_bytes(addr);
+        if start == END_OF_RECORDS {
+            return Ok(());
+        }
+        let mut len = [0; 8];
+        if !read_exact(file, &mut len) {
+            return Err(loaded);
+        }
+        let (start, len) = (start as usize, u64::from_le_bytes(len) as usize);
+        if !is_loadable(start, len) {
+            return Err(loaded);
+        }
+        if !loaded {
+            // the memory not in the image must be zero: clear the device
+            // tree of this boot, the only data outside of the kernel so far
+            let (dtb, dtb_size) = unsafe { (*SCRATCH.get()).dtb };
+            if dtb_size > 0 {
+                unsafe {
+                    core::ptr::write_bytes(phys_to_virt(dtb.into()).as_mut_ptr(), 0, dtb_size)
+                };
+            }
+            loaded = true;
+        }
+        let buf = unsafe {
+            core::slice::from_raw_parts_mut(phys_to_virt(start.into()).as_mut_ptr(), len)
+        };
+        if !read_exact(file, buf) {
+            return Err(loaded);
+        }
+    }
+}
+
+fn restore_entry() -> ! {
+    unsafe {
+        let scratch = SCRATCH.get();
+        match load_image((*scratch).file) {
+            Ok(()) => {}
+            Err(false) => {
+                (*scratch).result = Err(SnapshotError::BadImage);
+                switch_from_scratch(addr_of!((*scratch).ctx));
+            }
+            Err(true) => {
+                // the memory is neither of this boot nor of the snapshot
+                crate::console::write_bytes(b"snapshot: truncated image, halting\n");
+                crate::misc::terminate();
+            }
+        }
+        let saved = SAVED.get();
+        crate::arch::write_page_table_root(PhysAddr::from((*saved).page_table_root));
+        crate::time::set_current_ticks((*saved).ticks);
+        (*saved).suspended_nanos = (*scratch).wall_nanos.saturating_sub((*saved).wall_nanos);
+        addr_of_mut!((*saved).restored).write_volatile(true);
+        switch_from_scratch(addr_of!((*saved).ctx))
+    }
+}
+
+/// Brings the devices of the system back after a restore.
+fn resume() {
+    crate::platform_init();
+    #[cfg(feature = "irq")]
+    {
+        crate::irq::set_enable(crate::time::TIMER_IRQ_NUM, true);
+        crate::irq::reenable_handled_irqs();
+    }
+    crate::misc::reseed_random();
+    let suspended_nanos = unsafe { (*SAVED.get()).suspended_nanos };
+    crate::time::add_suspended_time(crate::time::Duration::from_nanos(suspended_nanos));
+}
+
+/// Takes a snapshot of the system to the file of the host at `path`.
+///
+/// It returns `Ok(false)` once the snapshot is written, and `Ok(true)` when
+/// the system is resumed from it by [`restore_snapshot`]. In the latter case,
+/// the devices other than the interrupt controller and the timer must be
+/// probed again.
+///
+/// The other tasks are frozen with the caller, so any I/O in flight (e.g. on
+/// a block device) is lost at the restore.
+pub fn take_snapshot(path: &str) -> Result<bool, SnapshotError> {
+    check_supported()?;
+    let file = semihosting::open(path, semihosting::MODE_WRITE).ok_or(SnapshotError::Io)?;
+    let guard = kernel_guard::NoPreemptIrqSave::new();
+    unsafe {
+        let saved = SAVED.get();
+        addr_of_mut!((*saved).restored).write_volatile(false);
+        (*saved).page_table_root = crate::arch::read_page_table_root().as_usize();
+        (*saved).ticks = crate::time::current_ticks();
+        (*saved).wall_nanos = crate::time::wall_time_nanos();
+        let scratch = SCRATCH.get();
+        (*scratch).file = file;
+        (*scratch).result = Err(SnapshotError::Io);
+
+        switch_to_scratch(addr_of_mut!((*saved).ctx), write_entry);
+
+        if addr_of!((*saved).restored).read_volatile() {
+            resume();
+            drop(guard);
+            info!("snapshot: restored from {:?}", path);
+            return Ok(true);
+        }
+        drop(guard);
+        (*scratch).result.map(|_| false)
+    }
+}
+
+/// Restores the snapshot in the file of the host at `path`, taken by
+/// [`take_snapshot`] in a previous boot of this kernel.
+///
+/// `dtb` is the address of the device tree of this boot. It must be called
+/// on the primary CPU before the memory allocator is initialized, as all the
+/// free memory must still be zero.
+///
+/// It does not return if the snapshot is restored, the execution goes on in
+/// [`take_snapshot`]. Otherwise it returns why, before changing anything.
+pub fn restore_snapshot(path: &str, dtb: usize) -> SnapshotError {
+    const FDT_MAGIC: u32 = 0xd00d_feed;
+
+    if let Err(e) = check_supported() {
+        return e;
+    }
+    let Some(file) = semihosting::open(path, semihosting::MODE_READ) else {
+        return SnapshotError::Io;
+    };
+    let mut file_header = [0; HEADER_SIZE];
+    if !read_exact(file, &mut file_header) || file_header != header() {
+        return SnapshotError::BadImage;
+    }
+
+    let _guard = kernel_guard::IrqSave::new();
+    unsafe {
+        let scratch = SCRATCH.get();
+        (*scratch).file = file;
+        (*scratch).result = Err(SnapshotError::BadImage);
+        (*scratch).wall_nanos = crate::time::wall_time_nanos();
+        (*scratch).dtb = (dtb, 0);
+        if dtb != 0 {
+            let header = phys_to_virt(dtb.into()).as_ptr() as *const [u8; 4];
+            if u32::from_be_bytes(header.read()) == FDT_MAGIC {
+                (*scratch).dtb.1 = u32::from_be_bytes(header.add(1).read()) as usize;
+            }
+        }
+        switch_to_scratch(addr_of_mut!((*scratch).ctx), restore_entry);
+        (*scratch).result.unwrap_err()
+    }
+}
@@ -1,4 +1,19 @@
 //! Time-related operations.
+//!
+//! There are three clocks, see [`ClockId`]:
+//!
+//! - the monotonic clock counts the time since boot, and is the one to
+//!   measure intervals and set deadlines with;
+//! - the boot time clock is like the monotonic clock, but also counts the
+//!   time the system is suspended;
+//! - the realtime (wall) clock counts the time since the Unix epoch. It is
+//!   initialized from the RTC if there is one, can be set with
+//!   [`set_wall_time`], and can be gradually corrected (e.g. by NTP) with
+//!   [`adjust_wall_time`], so it may jump or run slightly faster or slower.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use kspin::SpinNoIrq;
 
 pub use core::time::Duration;
 
@@ -10,9 +25,7 @@ pub type TimeValue = Duration;
 
 #[cfg(feature = "irq")]
 pub use crate::platform::irq::TIMER_IRQ_NUM;
-#[cfg(feature = "irq")]
-pub use crate::platform::time::set_oneshot_timer;
-pub use crate::platform::time::{current_ticks, epochoffset_nanos, nanos_to_ticks, ticks_to_nanos};
+pub use crate::platform::time::{nanos_to_ticks, ticks_to_nanos};
 
 /// Number of milliseconds in a second.
 pub const MILLIS_PER_SEC: u64 = 1_000;
@@ -25,6 +38,55 @@ pub const NANOS_PER_MILLIS: u64 = 1_000_000;
 /// Number of nanoseconds in a microsecond.
 pub const NANOS_PER_MICROS: u64 = 1_000;
 
+/// Ticks added to the hardware clock, so that the monotonic clock goes on from
+/// the time of the snapshot after restoring one.
+#[cfg(feature = "snapshot")]
+static TICKS_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+#[inline]
+fn ticks_offset() -> u64 {
+    #[cfg(feature = "snapshot")]
+    return TICKS_OFFSET.load(Ordering::Relaxed);
+    #[cfg(not(feature = "snapshot"))]
+    0
+}
+
+/// Makes the monotonic clock read `ticks` now.
+#[cfg(feature = "snapshot")]
+pub(crate) fn set_current_ticks(ticks: u64) {
+    let hw_ticks = crate::platform::time::current_ticks();
+    TICKS_OFFSET.store(ticks.saturating_sub(hw_ticks), Ordering::Relaxed);
+}
+
+/// Returns the current clock time in hardware ticks.
+#[inline]
+pub fn current_ticks() -> u64 {
+    #[cfg(feature = "replay")]
+    let ticks = crate::replay::ticks(crate::platform::time::current_ticks);
+    #[cfg(not(feature = "replay"))]
+    let ticks = crate::platform::time::current_ticks();
+    ticks + ticks_offset()
+}
+
+/// Returns the epoch offset in nanoseconds (wall time offset to monotonic
+/// clock start).
+#[inline]
+pub fn epochoffset_nanos() -> u64 {
+    #[cfg(feature = "replay")]
+    return crate::replay::epoch_offset(crate::platform::time::epochoffset_nanos);
+    #[cfg(not(feature = "replay"))]
+    crate::platform::time::epochoffset_nanos()
+}
+
+/// Set a one-shot timer.
+///
+/// A timer interrupt will be triggered at the specified monotonic time deadline (in nanoseconds).
+#[cfg(feature = "irq")]
+pub fn set_oneshot_timer(deadline_ns: u64) {
+    let offset_ns = ticks_to_nanos(ticks_offset());
+    crate::platform::time::set_oneshot_timer(deadline_ns.saturating_sub(offset_ns));
+}
+
 /// Returns nanoseconds elapsed since system boot.
 pub fn monotonic_time_nanos() -> u64 {
     ticks_to_nanos(current_ticks())
@@ -35,24 +97,149 @@ pub fn monotonic_time() -> TimeValue {
     TimeValue::from_nanos(monotonic_time_nanos())
 }
 
+/// The clocks of the system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockId {
+    /// Time since epoch, which can be set and adjusted.
+    Realtime,
+    /// Time since boot, not counting the time suspended.
+    Monotonic,
+    /// Time since boot, counting the time suspended.
+    Boottime,
+}
+
+/// Maximum rate of the corrections of [`adjust_wall_time`]: 1 ns every
+/// 2000 ns, i.e. 500 ppm.
+const SLEW_RATE_DIV: u64 = 2000;
+
+/// The adjustments of the realtime clock, relative to the RTC time at boot.
+struct RealtimeAdjust {
+    /// Step set by [`set_wall_time`] and applied corrections.
+    offset: i64,
+    /// Correction not applied yet.
+    slew: i64,
+    /// Monotonic time when `slew` was last updated.
+    slew_start: u64,
+}
+
+impl RealtimeAdjust {
+    /// Returns the part of `slew` applied at monotonic time `now`.
+    fn slewed(&self, now: u64) -> i64 {
+        let max = (now.saturating_sub(self.slew_start) / SLEW_RATE_DIV) as i64;
+        self.slew.clamp(-max, max)
+    }
+
+    /// Moves the part of `slew` applied at monotonic time `now` to `offset`.
+    fn settle(&mut self, now: u64) {
+        let slewed = self.slewed(now);
+        self.offset += slewed;
+        self.slew -= slewed;
+        self.slew_start = now;
+    }
+}
+
+static REALTIME_ADJUST: SpinNoIrq<RealtimeAdjust> = SpinNoIrq::new(RealtimeAdjust {
+    offset: 0,
+    slew: 0,
+    slew_start: 0,
+});
+
+static SUSPENDED_NANOS: AtomicU64 = AtomicU64::new(0);
+
+fn realtime_nanos_at(adjust: &RealtimeAdjust, now: u64) -> u64 {
+    let unadjusted = (now + epochoffset_nanos()) as i64;
+    unadjusted
+        .saturating_add(adjust.offset + adjust.slewed(now))
+        .max(0) as u64
+}
+
 /// Returns nanoseconds elapsed since epoch (also known as realtime).
 pub fn wall_time_nanos() -> u64 {
-    monotonic_time_nanos() + epochoffset_nanos()
+    let adjust = REALTIME_ADJUST.lock();
+    realtime_nanos_at(&adjust, monotonic_time_nanos())
 }
 
 /// Returns the time elapsed since epoch (also known as realtime) in [`TimeValue`].
 pub fn wall_time() -> TimeValue {
-    TimeValue::from_nanos(monotonic_time_nanos() + epochoffset_nanos())
+    TimeValue::from_nanos(wall_time_nanos())
+}
+
+/// Sets the realtime clock to `time` since epoch.
+///
+/// It cancels the corrections of [`adjust_wall_time`] not applied yet.
+pub fn set_wall_time(time: TimeValue) {
+    let mut adjust = REALTIME_ADJUST.lock();
+    let now = monotonic_time_nanos();
+    let unadjusted = (now + epochoffset_nanos()) as i64;
+    adjust.offset = time.as_nanos() as i64 - unadjusted;
+    adjust.slew = 0;
+    adjust.slew_start = now;
+}
+
+/// Gradually corrects the realtime clock by `delta_nanos`, by speeding it up
+/// or slowing it down by at most 500 ppm, like `adjtime` on Unix.
+///
+/// It replaces the previous correction, and returns the part of it not
+/// applied yet.
+pub fn adjust_wall_time(delta_nanos: i64) -> i64 {
+    let mut adjust = REALTIME_ADJUST.lock();
+    adjust.settle(monotonic_time_nanos());
+    core::mem::replace(&mut adjust.slew, delta_nanos)
+}
+
+/// Returns the correction of the realtime clock by [`adjust_wall_time`] not
+/// applied yet, in nanoseconds.
+pub fn wall_time_adjustment() -> i64 {
+    let mut adjust = REALTIME_ADJUST.lock();
+    adjust.settle(monotonic_time_nanos());
+    adjust.slew
+}
+
+/// Returns nanoseconds elapsed since boot, including the time the system was
+/// suspended.
+pub fn boottime_nanos() -> u64 {
+    monotonic_time_nanos() + SUSPENDED_NANOS.load(Ordering::Acquire)
+}
+
+/// Returns the time elapsed since boot, including the time the system was
+/// suspended, in [`TimeValue`].
+pub fn boottime() -> TimeValue {
+    TimeValue::from_nanos(boottime_nanos())
+}
+
+/// Records that the system was suspended for `dur`, during which the
+/// monotonic clock stopped.
+///
+/// It advances the boot time clock, and the realtime clock unless it is
+/// corrected from the RTC afterwards.
+pub fn add_suspended_time(dur: Duration) {
+    SUSPENDED_NANOS.fetch_add(dur.as_nanos() as u64, Ordering::AcqRel);
+    let mut adjust = REALTIME_ADJUST.lock();
+    adjust.offset += dur.as_nanos() as i64;
+}
+
+/// Returns the current time of `clock`.
+pub fn clock_time(clock: ClockId) -> TimeValue {
+    match clock {
+        ClockId::Realtime => wall_time(),
+        ClockId::Monotonic => monotonic_time(),
+        ClockId::Boottime => boottime(),
+    }
+}
+
+/// Returns the resolution of the clocks.
+pub fn clock_resolution() -> Duration {
+    Duration::from_nanos(ticks_to_nanos(1).max(1))
 }
 
 /// Busy waiting for the given duration.
 pub fn busy_wait(dur: Duration) {
-    busy_wait_until(wall_time() + dur);
+    busy_wait_until(monotonic_time() + dur);
 }
 
-/// Busy waiting until reaching the given deadline.
+/// Busy waiting until reaching the given deadline, in monotonic time.
 pub fn busy_wait_until(deadline: TimeValue) {
-    while wall_time() < deadline {
+    while monotonic_time() < deadline {
         core::hint::spin_loop();
     }
 }
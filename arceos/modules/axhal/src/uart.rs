@@ -0,0 +1,112 @@
+//! Raw data on the spare UARTs, i.e. the UARTs other than the one of the
+//! console.
+//!
+//! The ports are numbered from 0, in the order of the platform, and must be
+//! [configured](configure) before any data is sent or received on them.
+//! Nothing is translated: no `\r` is added before the `\n`, unlike on the
+//! console. Framing the data into packets is left to the application, e.g.
+//! with SLIP or COBS.
+//!
+//! Only the x86-pc platform has spare UARTs for now: `COM2` to `COM4`, when
+//! present. On the other platforms, [`num_ports`] is 0.
+
+cfg_if::cfg_if! {
+    if #[cfg(all(target_arch = "x86_64", platform_family = "x86-pc"))] {
+        use crate::platform::uart as imp;
+    } else {
+        mod imp {
+            use super::{UartConfig, UartError};
+
+            pub fn num_ports() -> usize {
+                0
+            }
+
+            pub fn configure(_port: usize, _config: &UartConfig) -> Result<(), UartError> {
+                Err(UartError::NoSuchPort)
+            }
+
+            pub fn write(_port: usize, _data: &[u8]) -> Result<(), UartError> {
+                Err(UartError::NoSuchPort)
+            }
+
+            pub fn read(_port: usize, _buf: &mut [u8]) -> Result<usize, UartError> {
+                Err(UartError::NoSuchPort)
+            }
+        }
+    }
+}
+
+/// The parity bit of the characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+/// The configuration of a port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UartConfig {
+    /// The baud rate, in bits per second.
+    pub baud_rate: u32,
+    /// The number of data bits of the characters, 5 to 8.
+    pub data_bits: u8,
+    pub parity: Parity,
+    /// The number of stop bits, 1 or 2.
+    pub stop_bits: u8,
+}
+
+impl Default for UartConfig {
+    /// 115200 bauds, 8 data bits, no parity and 1 stop bit (8N1).
+    fn default() -> Self {
+        Self {
+            baud_rate: 115200,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+        }
+    }
+}
+
+/// The errors of the UART operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UartError {
+    /// There is no such port.
+    NoSuchPort,
+    /// The port has not been configured.
+    NotConfigured,
+    /// The configuration is not supported by the port, e.g. a baud rate its
+    /// clock cannot divide down to.
+    InvalidConfig,
+}
+
+/// Returns the number of spare UARTs.
+pub fn num_ports() -> usize {
+    imp::num_ports()
+}
+
+/// Configures a port, and clears its FIFOs.
+///
+/// It can be called again to change the configuration.
+pub fn configure(port: usize, config: &UartConfig) -> Result<(), UartError> {
+    if config.baud_rate == 0
+        || !(5..=8).contains(&config.data_bits)
+        || !(1..=2).contains(&config.stop_bits)
+    {
+        return Err(UartError::InvalidConfig);
+    }
+    imp::configure(port, config)
+}
+
+/// Sends bytes on a port, waiting for room in its FIFO.
+pub fn write(port: usize, data: &[u8]) -> Result<(), UartError> {
+    imp::write(port, data)
+}
+
+/// Reads the bytes received on a port into `buf`, without waiting, and
+/// returns their number.
+///
+/// The bytes received with a parity or framing error are dropped.
+pub fn read(port: usize, buf: &mut [u8]) -> Result<usize, UartError> {
+    imp::read(port, buf)
+}
@@ -0,0 +1,342 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) capability handles to
+//! kernel objects.
+//!
+//! A [`HandleTable`] maps the handles of a task (or of a component) to the
+//! kernel objects it can use, files, sockets, shared memory, timers..., each
+//! with the [`Rights`] it has on them. The holder of a table can only reach
+//! the objects in it, only for what their rights allow, and a handle can
+//! only be duplicated or given to another table with fewer rights:
+//!
+//! ```
+//! use std::sync::Arc;
+//! use axhandle::{HandleError, HandleTable, KernelObject, ObjectKind, Rights};
+//!
+//! struct Timer;
+//!
+//! impl KernelObject for Timer {
+//!     fn kind(&self) -> ObjectKind {
+//!         ObjectKind::Timer
+//!     }
+//!     fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+//!         self
+//!     }
+//! }
+//!
+//! let mut table: HandleTable = HandleTable::new(16);
+//! let timer = table.insert(Arc::new(Timer), Rights::all()).unwrap();
+//!
+//! // a sandboxed component only gets to read the timer
+//! let mut sandbox = HandleTable::new(16);
+//! let h = table.grant(timer, &mut sandbox, Rights::READ).unwrap();
+//! assert!(sandbox.get_as::<Timer>(h, Rights::READ).is_ok());
+//! assert_eq!(sandbox.get(h, Rights::WRITE).err(), Some(HandleError::NoRights));
+//! assert_eq!(sandbox.duplicate(h, Rights::READ).err(), Some(HandleError::NoRights));
+//! ```
+//!
+//! In the unikernel mode, where all the components share the kernel, this
+//! sandboxes the components given their own table. In the monolithic mode,
+//! the file descriptors of `arceos_posix_api` are the handles of a table.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+#[cfg(test)]
+mod tests;
+
+use alloc::{sync::Arc, vec::Vec};
+use core::any::Any;
+use core::fmt;
+
+use axerrno::AxError;
+
+bitflags::bitflags! {
+    /// The rights of a handle on its object.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Rights: u32 {
+        /// Read from, or receive from, the object.
+        const READ = 1 << 0;
+        /// Write to, or send to, the object.
+        const WRITE = 1 << 1;
+        /// Map the object in memory.
+        const MAP = 1 << 2;
+        /// Get the attributes of the object.
+        const STAT = 1 << 3;
+        /// Change the attributes of the object (e.g. its nonblocking mode),
+        /// or arm it (for a timer).
+        const CONFIGURE = 1 << 4;
+        /// Duplicate the handle in its table.
+        const DUPLICATE = 1 << 5;
+        /// Give the handle to another table.
+        const TRANSFER = 1 << 6;
+    }
+}
+
+/// The kinds of kernel objects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    /// A file or a directory.
+    File,
+    /// A network socket.
+    Socket,
+    /// An end of a pipe.
+    Pipe,
+    /// A shared memory region.
+    SharedMemory,
+    /// A timer.
+    Timer,
+    /// Any other object (e.g. an event queue).
+    Other,
+}
+
+/// A kernel object, referred to by handles.
+pub trait KernelObject: Any + Send + Sync {
+    /// Returns the kind of the object.
+    fn kind(&self) -> ObjectKind;
+
+    /// Converts the object to [`Any`], to get back its type.
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync>;
+}
+
+/// A handle, the index of an object in a [`HandleTable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Handle(usize);
+
+impl Handle {
+    /// Creates the handle of the index `raw` (e.g. a file descriptor).
+    pub const fn from_raw(raw: usize) -> Self {
+        Self(raw)
+    }
+
+    /// Returns the index of the handle.
+    pub const fn as_raw(self) -> usize {
+        self.0
+    }
+}
+
+impl fmt::Display for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The errors of the [`HandleTable`] operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandleError {
+    /// The handle does not refer to an object.
+    BadHandle,
+    /// The handle lacks the rights needed.
+    NoRights,
+    /// The object is not of the type asked for.
+    WrongType,
+    /// The table has no free handle left.
+    TableFull,
+}
+
+/// A [`Result`](core::result::Result) with a [`HandleError`].
+pub type HandleResult<T = ()> = Result<T, HandleError>;
+
+impl From<HandleError> for AxError {
+    fn from(err: HandleError) -> Self {
+        match err {
+            HandleError::BadHandle => AxError::NotFound,
+            HandleError::NoRights => AxError::PermissionDenied,
+            HandleError::WrongType => AxError::InvalidInput,
+            HandleError::TableFull => AxError::NoMemory,
+        }
+    }
+}
+
+struct Entry<T: ?Sized> {
+    object: Arc<T>,
+    rights: Rights,
+}
+
+/// A table of handles to objects of type `T`, any [`KernelObject`] by
+/// default.
+///
+/// The new handles are the lowest free ones, as the file descriptors, up to
+/// a limit.
+pub struct HandleTable<T: ?Sized = dyn KernelObject> {
+    entries: Vec<Option<Entry<T>>>,
+    len: usize,
+    limit: usize,
+}
+
+impl<T: ?Sized> HandleTable<T> {
+    /// Creates an empty table, of at most `limit` handles.
+    pub const fn new(limit: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            len: 0,
+            limit,
+        }
+    }
+
+    /// Returns the number of handles in the table.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the table has no handles.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the maximum number of handles of the table.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    fn entry(&self, handle: Handle) -> HandleResult<&Entry<T>> {
+        self.entries
+            .get(handle.0)
+            .and_then(Option::as_ref)
+            .ok_or(HandleError::BadHandle)
+    }
+
+    fn entry_with(&self, handle: Handle, rights: Rights) -> HandleResult<&Entry<T>> {
+        let entry = self.entry(handle)?;
+        if !entry.rights.contains(rights) {
+            return Err(HandleError::NoRights);
+        }
+        Ok(entry)
+    }
+
+    /// Adds `object` with `rights`, and returns its handle.
+    pub fn insert(&mut self, object: Arc<T>, rights: Rights) -> HandleResult<Handle> {
+        let index = match self.entries.iter().position(Option::is_none) {
+            Some(index) => index,
+            None if self.entries.len() < self.limit => {
+                self.entries.push(None);
+                self.entries.len() - 1
+            }
+            None => return Err(HandleError::TableFull),
+        };
+        self.entries[index] = Some(Entry { object, rights });
+        self.len += 1;
+        Ok(Handle(index))
+    }
+
+    /// Adds `object` with `rights` as `handle`, and returns the object it
+    /// replaces, if any.
+    pub fn insert_at(
+        &mut self,
+        handle: Handle,
+        object: Arc<T>,
+        rights: Rights,
+    ) -> HandleResult<Option<Arc<T>>> {
+        if handle.0 >= self.limit {
+            return Err(HandleError::BadHandle);
+        }
+        if handle.0 >= self.entries.len() {
+            self.entries.resize_with(handle.0 + 1, || None);
+        }
+        let old = self.entries[handle.0].replace(Entry { object, rights });
+        if old.is_none() {
+            self.len += 1;
+        }
+        Ok(old.map(|entry| entry.object))
+    }
+
+    /// Returns the object of `handle`, if it has all the `rights`.
+    pub fn get(&self, handle: Handle, rights: Rights) -> HandleResult<Arc<T>> {
+        Ok(self.entry_with(handle, rights)?.object.clone())
+    }
+
+    /// Returns the rights of `handle`.
+    pub fn rights(&self, handle: Handle) -> HandleResult<Rights> {
+        Ok(self.entry(handle)?.rights)
+    }
+
+    /// Drops the rights of `handle` not in `rights`.
+    pub fn restrict(&mut self, handle: Handle, rights: Rights) -> HandleResult {
+        let entry = self
+            .entries
+            .get_mut(handle.0)
+            .and_then(Option::as_mut)
+            .ok_or(HandleError::BadHandle)?;
+        entry.rights &= rights;
+        Ok(())
+    }
+
+    /// Removes `handle`, and returns its object.
+    pub fn remove(&mut self, handle: Handle) -> HandleResult<Arc<T>> {
+        let entry = self
+            .entries
+            .get_mut(handle.0)
+            .and_then(Option::take)
+            .ok_or(HandleError::BadHandle)?;
+        self.len -= 1;
+        Ok(entry.object)
+    }
+
+    /// Adds a new handle to the object of `handle`, with the `rights` it
+    /// has, and returns it.
+    ///
+    /// `handle` needs [`Rights::DUPLICATE`].
+    pub fn duplicate(&mut self, handle: Handle, rights: Rights) -> HandleResult<Handle> {
+        let entry = self.entry_with(handle, Rights::DUPLICATE)?;
+        let (object, rights) = (entry.object.clone(), entry.rights & rights);
+        self.insert(object, rights)
+    }
+
+    /// Makes `new` a handle to the object of `handle`, with the `rights` it
+    /// has, replacing its object if any (which is returned).
+    ///
+    /// `handle` needs [`Rights::DUPLICATE`].
+    pub fn duplicate_to(
+        &mut self,
+        handle: Handle,
+        new: Handle,
+        rights: Rights,
+    ) -> HandleResult<Option<Arc<T>>> {
+        let entry = self.entry_with(handle, Rights::DUPLICATE)?;
+        let (object, rights) = (entry.object.clone(), entry.rights & rights);
+        self.insert_at(new, object, rights)
+    }
+
+    /// Adds a handle to the object of `handle` to the table `to`, with the
+    /// `rights` it has, and returns it.
+    ///
+    /// `handle` needs [`Rights::TRANSFER`], and keeps its rights.
+    pub fn grant(&self, handle: Handle, to: &mut Self, rights: Rights) -> HandleResult<Handle> {
+        let entry = self.entry_with(handle, Rights::TRANSFER)?;
+        to.insert(entry.object.clone(), entry.rights & rights)
+    }
+
+    /// Moves `handle` to the table `to`, with the rights it has, and returns
+    /// its new handle.
+    ///
+    /// `handle` needs [`Rights::TRANSFER`]. It is kept if `to` is full.
+    pub fn transfer(&mut self, handle: Handle, to: &mut Self) -> HandleResult<Handle> {
+        let new = self.grant(handle, to, Rights::all())?;
+        self.remove(handle)?;
+        Ok(new)
+    }
+
+    /// Returns the handles of the table, with their objects and rights.
+    pub fn iter(&self) -> impl Iterator<Item = (Handle, &Arc<T>, Rights)> {
+        self.entries.iter().enumerate().filter_map(|(i, entry)| {
+            entry
+                .as_ref()
+                .map(|entry| (Handle(i), &entry.object, entry.rights))
+        })
+    }
+}
+
+impl<T: ?Sized + KernelObject> HandleTable<T> {
+    /// Returns the object of `handle`, if it is a `U` and the handle has all
+    /// the `rights`.
+    pub fn get_as<U: KernelObject>(&self, handle: Handle, rights: Rights) -> HandleResult<Arc<U>> {
+        self.get(handle, rights)?
+            .into_any()
+            .downcast::<U>()
+            .map_err(|_| HandleError::WrongType)
+    }
+
+    /// Returns the kind of the object of `handle`.
+    pub fn kind(&self, handle: Handle) -> HandleResult<ObjectKind> {
+        Ok(self.entry(handle)?.object.kind())
+    }
+}
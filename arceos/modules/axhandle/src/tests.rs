@@ -0,0 +1,129 @@
+use std::any::Any;
+use std::sync::Arc;
+
+use crate::{Handle, HandleError, HandleTable, KernelObject, ObjectKind, Rights};
+
+struct Shm(usize);
+
+impl KernelObject for Shm {
+    fn kind(&self) -> ObjectKind {
+        ObjectKind::SharedMemory
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+struct Timer;
+
+impl KernelObject for Timer {
+    fn kind(&self) -> ObjectKind {
+        ObjectKind::Timer
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+#[test]
+fn test_insert_remove() {
+    let mut table: HandleTable = HandleTable::new(2);
+    let a = table.insert(Arc::new(Shm(1)), Rights::all()).unwrap();
+    let b = table.insert(Arc::new(Timer), Rights::all()).unwrap();
+    assert_eq!((a.as_raw(), b.as_raw()), (0, 1));
+    assert_eq!(
+        table.insert(Arc::new(Timer), Rights::all()).err(),
+        Some(HandleError::TableFull)
+    );
+    assert_eq!(table.len(), 2);
+
+    // the lowest free handle is reused
+    table.remove(a).unwrap();
+    assert_eq!(table.remove(a).err(), Some(HandleError::BadHandle));
+    let c = table.insert(Arc::new(Shm(2)), Rights::READ).unwrap();
+    assert_eq!(c, a);
+    assert_eq!(table.kind(b), Ok(ObjectKind::Timer));
+    assert_eq!(
+        table
+            .iter()
+            .map(|(h, _, r)| (h.as_raw(), r))
+            .collect::<Vec<_>>(),
+        [(0, Rights::READ), (1, Rights::all())]
+    );
+
+    // replacing a handle returns its previous object
+    let old = table.insert_at(c, Arc::new(Timer), Rights::all()).unwrap();
+    assert!(old.is_some_and(|old| old.kind() == ObjectKind::SharedMemory));
+    assert_eq!(table.len(), 2);
+    assert_eq!(
+        table
+            .insert_at(Handle::from_raw(2), Arc::new(Timer), Rights::all())
+            .err(),
+        Some(HandleError::BadHandle)
+    );
+}
+
+#[test]
+fn test_rights_and_types() {
+    let mut table: HandleTable = HandleTable::new(8);
+    let h = table
+        .insert(Arc::new(Shm(42)), Rights::READ | Rights::DUPLICATE)
+        .unwrap();
+    assert_eq!(table.get_as::<Shm>(h, Rights::READ).unwrap().0, 42);
+    assert_eq!(
+        table.get_as::<Timer>(h, Rights::READ).err(),
+        Some(HandleError::WrongType)
+    );
+    assert_eq!(
+        table.get(h, Rights::READ | Rights::WRITE).err(),
+        Some(HandleError::NoRights)
+    );
+    assert_eq!(
+        table.get(Handle::from_raw(7), Rights::empty()).err(),
+        Some(HandleError::BadHandle)
+    );
+
+    // a duplicate cannot get more rights
+    let dup = table.duplicate(h, Rights::all()).unwrap();
+    assert_eq!(table.rights(dup), Ok(Rights::READ | Rights::DUPLICATE));
+    table.restrict(dup, Rights::READ).unwrap();
+    assert_eq!(
+        table.duplicate(dup, Rights::READ).err(),
+        Some(HandleError::NoRights)
+    );
+    let to = Handle::from_raw(5);
+    assert!(table.duplicate_to(h, to, Rights::READ).unwrap().is_none());
+    assert_eq!(table.rights(to), Ok(Rights::READ));
+}
+
+#[test]
+fn test_grant_transfer() {
+    let mut table: HandleTable = HandleTable::new(8);
+    let mut sandbox = HandleTable::new(1);
+    let shm = table
+        .insert(Arc::new(Shm(1)), Rights::READ | Rights::WRITE)
+        .unwrap();
+    assert_eq!(
+        table.grant(shm, &mut sandbox, Rights::READ).err(),
+        Some(HandleError::NoRights)
+    );
+
+    let timer = table
+        .insert(Arc::new(Timer), Rights::READ | Rights::TRANSFER)
+        .unwrap();
+    let h = table.grant(timer, &mut sandbox, Rights::READ).unwrap();
+    assert_eq!(sandbox.rights(h), Ok(Rights::READ));
+    assert_eq!(table.rights(timer), Ok(Rights::READ | Rights::TRANSFER));
+
+    // the handle is kept if the other table is full
+    assert_eq!(
+        table.transfer(timer, &mut sandbox).err(),
+        Some(HandleError::TableFull)
+    );
+    sandbox.remove(h).unwrap();
+    let h = table.transfer(timer, &mut sandbox).unwrap();
+    assert_eq!(sandbox.rights(h), Ok(Rights::READ | Rights::TRANSFER));
+    assert_eq!(table.rights(timer).err(), Some(HandleError::BadHandle));
+}
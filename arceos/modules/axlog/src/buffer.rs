@@ -0,0 +1,78 @@
+//! The in-memory buffer of the recent log messages.
+
+use core::fmt::{self, Write};
+use core::time::Duration;
+
+use kspin::SpinNoIrq;
+use log::Level;
+
+/// The size of the log buffer in bytes. The oldest messages are overwritten
+/// when it is full.
+pub const LOG_BUFFER_SIZE: usize = 16 * 1024;
+
+struct RingBuffer {
+    data: [u8; LOG_BUFFER_SIZE],
+    /// Where the next byte is written.
+    head: usize,
+    len: usize,
+}
+
+impl RingBuffer {
+    /// The byte `i` counting from the oldest.
+    fn byte(&self, i: usize) -> u8 {
+        self.data[(self.head + LOG_BUFFER_SIZE - self.len + i) % LOG_BUFFER_SIZE]
+    }
+}
+
+impl Write for RingBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &b in s.as_bytes() {
+            self.data[self.head] = b;
+            self.head = (self.head + 1) % LOG_BUFFER_SIZE;
+            self.len = (self.len + 1).min(LOG_BUFFER_SIZE);
+        }
+        Ok(())
+    }
+}
+
+static BUFFER: SpinNoIrq<RingBuffer> = SpinNoIrq::new(RingBuffer {
+    data: [0; LOG_BUFFER_SIZE],
+    head: 0,
+    len: 0,
+});
+
+pub(crate) fn record(now: Duration, level: Level, path: &str, line: u32, args: &fmt::Arguments) {
+    let mut buffer = BUFFER.lock();
+    writeln!(
+        buffer,
+        "[{:>3}.{:06} {path}:{line}] {level:<5} {args}",
+        now.as_secs(),
+        now.subsec_micros(),
+    )
+    .ok();
+}
+
+/// Copies the most recent log messages into `buf`, oldest first, and returns
+/// the number of bytes copied.
+///
+/// Only whole lines are copied, unless a single line does not fit in `buf`.
+pub fn read_log_buffer(buf: &mut [u8]) -> usize {
+    let buffer = BUFFER.lock();
+    let mut start = buffer.len - buffer.len.min(buf.len());
+    let truncated = start > 0 || buffer.len == LOG_BUFFER_SIZE;
+    if truncated {
+        // skip the partial line at the beginning
+        let mut i = start;
+        while i < buffer.len && buffer.byte(i) != b'\n' {
+            i += 1;
+        }
+        if i + 1 < buffer.len {
+            start = i + 1;
+        }
+    }
+    let len = buffer.len - start;
+    for (i, b) in buf[..len].iter_mut().enumerate() {
+        *b = buffer.byte(start + i);
+    }
+    len
+}
@@ -25,6 +25,11 @@
 //!   optimized out to a no-op.
 //! - `log-level-warn`, `log-level-info`, `log-level-debug`, `log-level-trace`:
 //!   Similar to `log-level-error`.
+//! - `buffer`: Keep the most recent log messages in memory, without colors,
+//!   to read them back with [`read_log_buffer`] (like `dmesg` on Linux).
+//! - `remote`: Queue the log messages of the level set by
+//!   [`set_remote_level`], for a sender to take with [`take_remote_record`]
+//!   and ship to a remote log server.
 //!
 //! # Examples
 //!
@@ -60,6 +65,20 @@ use crate_interface::call_interface;
 
 pub use log::{debug, error, info, trace, warn};
 
+#[cfg(all(feature = "buffer", not(feature = "std")))]
+mod buffer;
+#[cfg(all(feature = "buffer", not(feature = "std")))]
+pub use buffer::{read_log_buffer, LOG_BUFFER_SIZE};
+
+#[cfg(all(feature = "remote", not(feature = "std")))]
+mod remote;
+#[cfg(all(feature = "remote", not(feature = "std")))]
+pub use log::Level;
+#[cfg(all(feature = "remote", not(feature = "std")))]
+pub use remote::{
+    set_remote_level, take_remote_dropped, take_remote_record, REMOTE_MSG_MAX, REMOTE_QUEUE_SIZE,
+};
+
 /// Prints to the console.
 ///
 /// Equivalent to the [`ax_println!`] macro except that a newline is not printed at
@@ -164,6 +183,18 @@ impl Log for Logger {
             Level::Trace => ColorCode::BrightBlack,
         };
 
+        #[cfg(all(feature = "buffer", not(feature = "std")))]
+        buffer::record(
+            call_interface!(LogIf::current_time),
+            level,
+            path,
+            line,
+            record.args(),
+        );
+
+        #[cfg(all(feature = "remote", not(feature = "std")))]
+        remote::record(level, path, line, record.args());
+
         cfg_if::cfg_if! {
             if #[cfg(feature = "std")] {
                 __print_impl(with_color!(
@@ -224,15 +255,21 @@ impl Log for Logger {
     fn flush(&self) {}
 }
 
+/// Serializes the prints, so that they are not interleaved.
+static PRINT_LOCK: kspin::SpinNoIrq<()> = kspin::SpinNoIrq::new(()); // TODO: more efficient
+
 /// Prints the formatted string to the console.
 pub fn print_fmt(args: fmt::Arguments) -> fmt::Result {
-    use kspin::SpinNoIrq; // TODO: more efficient
-    static LOCK: SpinNoIrq<()> = SpinNoIrq::new(());
-
-    let _guard = LOCK.lock();
+    let _guard = PRINT_LOCK.lock();
     Logger.write_fmt(args)
 }
 
+/// Prints the string to the console, without the formatting machinery.
+pub fn print_str(s: &str) -> fmt::Result {
+    let _guard = PRINT_LOCK.lock();
+    Logger.write_str(s)
+}
+
 #[doc(hidden)]
 pub fn __print_impl(args: fmt::Arguments) {
     print_fmt(args).unwrap();
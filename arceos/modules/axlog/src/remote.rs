@@ -0,0 +1,131 @@
+//! The queue of the log messages to send to a remote log server.
+//!
+//! The messages are queued as they are logged, without colors, and taken by
+//! the sender (e.g. a syslog client task) with [`take_remote_record`]. When
+//! the queue is full, the new messages are dropped and counted.
+
+use core::fmt::{self, Write};
+use core::str::FromStr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use kspin::SpinNoIrq;
+use log::{Level, LevelFilter};
+
+/// The size of the queue in bytes.
+pub const REMOTE_QUEUE_SIZE: usize = 8 * 1024;
+
+/// The maximum length of a message, longer ones are truncated.
+pub const REMOTE_MSG_MAX: usize = 480;
+
+/// The size of the header of a queued message: its level and its length.
+const HEADER_SIZE: usize = 3;
+
+struct Queue {
+    data: [u8; REMOTE_QUEUE_SIZE],
+    /// Where the oldest byte is.
+    tail: usize,
+    len: usize,
+}
+
+impl Queue {
+    fn push(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.data[(self.tail + self.len) % REMOTE_QUEUE_SIZE] = b;
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> u8 {
+        let b = self.data[self.tail];
+        self.tail = (self.tail + 1) % REMOTE_QUEUE_SIZE;
+        self.len -= 1;
+        b
+    }
+}
+
+struct Message {
+    buf: [u8; REMOTE_MSG_MAX],
+    len: usize,
+}
+
+impl Write for Message {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let n = s.len().min(REMOTE_MSG_MAX - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+static QUEUE: SpinNoIrq<Queue> = SpinNoIrq::new(Queue {
+    data: [0; REMOTE_QUEUE_SIZE],
+    tail: 0,
+    len: 0,
+});
+
+/// The maximum level queued, as a [`LevelFilter`].
+static MAX_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Off as usize);
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// Starts queuing the messages of `level` or higher, or stops if it is `off`.
+///
+/// `level` should be one of `off`, `error`, `warn`, `info`, `debug`, `trace`.
+/// Only the messages allowed by the global maximum level are seen.
+pub fn set_remote_level(level: &str) {
+    let lf = LevelFilter::from_str(level)
+        .ok()
+        .unwrap_or(LevelFilter::Off);
+    MAX_LEVEL.store(lf as usize, Ordering::Relaxed);
+}
+
+pub(crate) fn record(level: Level, path: &str, line: u32, args: &fmt::Arguments) {
+    if level as usize > MAX_LEVEL.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut msg = Message {
+        buf: [0; REMOTE_MSG_MAX],
+        len: 0,
+    };
+    write!(msg, "{path}:{line}] {args}").ok();
+
+    let mut queue = QUEUE.lock();
+    if queue.len + HEADER_SIZE + msg.len > REMOTE_QUEUE_SIZE {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    queue.push(&[level as u8]);
+    queue.push(&(msg.len as u16).to_le_bytes());
+    queue.push(&msg.buf[..msg.len]);
+}
+
+/// Takes the oldest queued message into `buf`, returns its level and its
+/// length, or [`None`] if the queue is empty.
+///
+/// The message is `path:line] text`, truncated to the size of `buf`.
+pub fn take_remote_record(buf: &mut [u8]) -> Option<(Level, usize)> {
+    let mut queue = QUEUE.lock();
+    if queue.len == 0 {
+        return None;
+    }
+    let level = match queue.pop() {
+        1 => Level::Error,
+        2 => Level::Warn,
+        3 => Level::Info,
+        4 => Level::Debug,
+        _ => Level::Trace,
+    };
+    let msg_len = u16::from_le_bytes([queue.pop(), queue.pop()]) as usize;
+    for i in 0..msg_len {
+        let b = queue.pop();
+        if let Some(out) = buf.get_mut(i) {
+            *out = b;
+        }
+    }
+    Some((level, msg_len.min(buf.len())))
+}
+
+/// Returns the number of messages dropped as the queue was full since the
+/// last call.
+pub fn take_remote_dropped() -> usize {
+    DROPPED.swap(0, Ordering::Relaxed)
+}
@@ -0,0 +1,146 @@
+//! Memory usage accounting and limits.
+//!
+//! Each [`AddrSpace`](crate::AddrSpace) charges the memory of its allocation
+//! mappings to a [`MemAccount`]:
+//!
+//! - the virtual size, when a mapping is created, and
+//! - the resident size, when a physical frame is allocated for it (on mapping
+//!   for populated mappings, or on page faults for lazy ones).
+//!
+//! Both are uncharged when the memory is unmapped. If a charge would exceed
+//! the limit of the account, the mapping fails with [`AxError::NoMemory`], or
+//! the page fault is not handled.
+//!
+//! An address space has its own account by default. Several address spaces,
+//! e.g. those of a group of processes, can share one with
+//! [`AddrSpace::set_account`](crate::AddrSpace::set_account). In unikernel
+//! mode, where all tasks share the kernel address space, mappings made for a
+//! group of tasks can be charged to their account with
+//! [`AddrSpace::map_alloc_in`](crate::AddrSpace::map_alloc_in).
+//!
+//! [`AxError::NoMemory`]: axerrno::AxError::NoMemory
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A snapshot of the memory usage of a [`MemAccount`], in bytes.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MemUsage {
+    /// Size of the allocation mappings.
+    pub virt: usize,
+    /// Size of the physical memory allocated for them.
+    pub resident: usize,
+    /// Highest resident size so far.
+    pub peak_resident: usize,
+    /// Limit of the virtual size, if any.
+    pub virt_limit: Option<usize>,
+    /// Limit of the resident size, if any.
+    pub resident_limit: Option<usize>,
+}
+
+/// Memory usage counters and limits, shared by the address spaces charged
+/// to it.
+#[derive(Debug)]
+pub struct MemAccount {
+    virt: AtomicUsize,
+    resident: AtomicUsize,
+    peak_resident: AtomicUsize,
+    virt_limit: AtomicUsize,
+    resident_limit: AtomicUsize,
+}
+
+const UNLIMITED: usize = usize::MAX;
+
+fn to_limit(raw: usize) -> Option<usize> {
+    (raw != UNLIMITED).then_some(raw)
+}
+
+/// Adds `size` to `counter` if the result does not exceed `limit`.
+fn try_charge(counter: &AtomicUsize, limit: &AtomicUsize, size: usize) -> Option<usize> {
+    let limit = limit.load(Ordering::Relaxed);
+    counter
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+            used.checked_add(size).filter(|&new| new <= limit)
+        })
+        .ok()
+        .map(|old| old + size)
+}
+
+impl MemAccount {
+    /// Creates a new account with no usage and no limits.
+    pub const fn new() -> Self {
+        Self {
+            virt: AtomicUsize::new(0),
+            resident: AtomicUsize::new(0),
+            peak_resident: AtomicUsize::new(0),
+            virt_limit: AtomicUsize::new(UNLIMITED),
+            resident_limit: AtomicUsize::new(UNLIMITED),
+        }
+    }
+
+    /// Returns the current usage and limits.
+    pub fn usage(&self) -> MemUsage {
+        MemUsage {
+            virt: self.virt.load(Ordering::Relaxed),
+            resident: self.resident.load(Ordering::Relaxed),
+            peak_resident: self.peak_resident.load(Ordering::Relaxed),
+            virt_limit: to_limit(self.virt_limit.load(Ordering::Relaxed)),
+            resident_limit: to_limit(self.resident_limit.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Sets the limit of the virtual size in bytes, or removes it if `None`.
+    ///
+    /// A limit below the current usage only fails later charges.
+    pub fn set_virt_limit(&self, limit: Option<usize>) {
+        self.virt_limit
+            .store(limit.unwrap_or(UNLIMITED), Ordering::Relaxed);
+    }
+
+    /// Sets the limit of the resident size in bytes, or removes it if `None`.
+    ///
+    /// A limit below the current usage only fails later charges.
+    pub fn set_resident_limit(&self, limit: Option<usize>) {
+        self.resident_limit
+            .store(limit.unwrap_or(UNLIMITED), Ordering::Relaxed);
+    }
+
+    /// Returns whether `virt` bytes of mappings with `resident` bytes of
+    /// physical memory can be charged now.
+    pub(crate) fn can_charge(&self, virt: usize, resident: usize) -> bool {
+        let usage = self.usage();
+        let fits = |used: usize, size, limit: Option<usize>| {
+            used.checked_add(size)
+                .is_some_and(|new| new <= limit.unwrap_or(UNLIMITED))
+        };
+        fits(usage.virt, virt, usage.virt_limit)
+            && fits(usage.resident, resident, usage.resident_limit)
+    }
+
+    pub(crate) fn try_charge_virt(&self, size: usize) -> bool {
+        try_charge(&self.virt, &self.virt_limit, size).is_some()
+    }
+
+    pub(crate) fn uncharge_virt(&self, size: usize) {
+        self.virt.fetch_sub(size, Ordering::AcqRel);
+    }
+
+    pub(crate) fn try_charge_resident(&self, size: usize) -> bool {
+        match try_charge(&self.resident, &self.resident_limit, size) {
+            Some(new) => {
+                self.peak_resident.fetch_max(new, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn uncharge_resident(&self, size: usize) {
+        self.resident.fetch_sub(size, Ordering::AcqRel);
+    }
+}
+
+impl Default for MemAccount {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -12,6 +12,8 @@ use memory_set::{MemoryArea, MemorySet};
 use crate::backend::Backend;
 use crate::paging_err_to_ax_err;
 use crate::mapping_err_to_ax_err;
+use crate::{MemAccount, MemUsage, MmioAttr};
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 /// The virtual memory address space.
@@ -19,6 +21,7 @@ pub struct AddrSpace {
     va_range: VirtAddrRange,
     areas: MemorySet<Backend>,
     pt: PageTable,
+    account: Arc<MemAccount>,
 }
 
 impl AddrSpace {
@@ -59,9 +62,39 @@ impl AddrSpace {
             va_range: VirtAddrRange::from_start_size(base, size),
             areas: MemorySet::new(),
             pt: PageTable::try_new().map_err(|_| AxError::NoMemory)?,
+            account: Arc::new(MemAccount::new()),
         })
     }
 
+    /// Returns the account the memory of this address space is charged to.
+    ///
+    /// Limits set on it apply to later mappings and page faults.
+    pub fn account(&self) -> &Arc<MemAccount> {
+        &self.account
+    }
+
+    /// Returns the memory usage of the account of this address space.
+    pub fn usage(&self) -> MemUsage {
+        self.account.usage()
+    }
+
+    /// Charges the memory of this address space to `account` from now on,
+    /// e.g. to share it among the address spaces of a group of processes.
+    ///
+    /// Returns an error if there are already allocation mappings, which are
+    /// charged to the previous account.
+    pub fn set_account(&mut self, account: Arc<MemAccount>) -> AxResult {
+        if self
+            .areas
+            .iter()
+            .any(|area| matches!(area.backend(), Backend::Alloc { .. }))
+        {
+            return ax_err!(BadState, "address space has allocation mappings");
+        }
+        self.account = account;
+        Ok(())
+    }
+
     /// Copies page table mappings from another address space.
     ///
     /// It copies the page table entries only rather than the memory regions,
@@ -106,6 +139,32 @@ impl AddrSpace {
         start_paddr: PhysAddr,
         size: usize,
         flags: MappingFlags,
+    ) -> AxResult {
+        self.map_linear_inner(start_vaddr, start_paddr, size, flags, false)
+    }
+
+    /// Add a new linear mapping, like [`AddrSpace::map_linear`], but with
+    /// huge pages where both addresses are aligned enough.
+    ///
+    /// The range can only be unmapped or protected as a whole huge page at a
+    /// time.
+    pub fn map_linear_huge(
+        &mut self,
+        start_vaddr: VirtAddr,
+        start_paddr: PhysAddr,
+        size: usize,
+        flags: MappingFlags,
+    ) -> AxResult {
+        self.map_linear_inner(start_vaddr, start_paddr, size, flags, true)
+    }
+
+    fn map_linear_inner(
+        &mut self,
+        start_vaddr: VirtAddr,
+        start_paddr: PhysAddr,
+        size: usize,
+        flags: MappingFlags,
+        allow_huge: bool,
     ) -> AxResult {
         if !self.contains_range(start_vaddr, size) {
             return ax_err!(InvalidInput, "address out of range");
@@ -121,7 +180,7 @@ impl AddrSpace {
                 |va| pa!(va.as_usize() - offset),
                 size,
                 flags,
-                false, // allow_huge
+                allow_huge,
                 false, // flush_tlb_by_page
             )
             .map_err(paging_err_to_ax_err)?
@@ -136,13 +195,31 @@ impl AddrSpace {
     /// The `flags` parameter indicates the mapping permissions and attributes.
     ///
     /// Returns an error if the address range is out of the address space or not
-    /// aligned.
+    /// aligned, or [`NoMemory`](AxError::NoMemory) if the mapping would exceed
+    /// the limits of the account of the address space.
     pub fn map_alloc(
         &mut self,
         start: VirtAddr,
         size: usize,
         flags: MappingFlags,
         populate: bool,
+    ) -> AxResult {
+        let account = self.account.clone();
+        self.map_alloc_in(start, size, flags, populate, &account)
+    }
+
+    /// Add a new allocation mapping, like [`AddrSpace::map_alloc`], but charges
+    /// its memory to `account` instead of the account of the address space.
+    ///
+    /// It is used to account the memory of a group of tasks sharing one
+    /// address space, as in unikernel mode.
+    pub fn map_alloc_in(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        populate: bool,
+        account: &Arc<MemAccount>,
     ) -> AxResult {
         if !self.contains_range(start, size) {
             return ax_err!(InvalidInput, "address out of range");
@@ -150,14 +227,116 @@ impl AddrSpace {
         if !start.is_aligned_4k() || !is_aligned_4k(size) {
             return ax_err!(InvalidInput, "address not aligned");
         }
+        if !account.can_charge(size, if populate { size } else { 0 }) {
+            return ax_err!(NoMemory, "memory limit exceeded");
+        }
 
-        let area = MemoryArea::new(start, size, flags, Backend::new_alloc(populate));
+        let backend = Backend::new_alloc(populate, account.clone());
+        let area = MemoryArea::new(start, size, flags, backend);
         self.areas
             .map(area, &mut self.pt, false)
             .map_err(mapping_err_to_ax_err)?;
         Ok(())
     }
 
+    /// Maps the device MMIO region `[paddr, paddr + size)` with the memory
+    /// attribute `attr`, and returns the virtual address of `paddr`.
+    ///
+    /// The region does not need to be page aligned. It is mapped at the
+    /// linear mapping address of `paddr` if that is in this address space,
+    /// replacing the linear mapping with the default attributes if there is
+    /// one, otherwise at a free area.
+    ///
+    /// Returns an error if the region, or a part of it, is already mapped to
+    /// other physical memory, or no free area is found.
+    pub fn map_mmio(&mut self, paddr: PhysAddr, size: usize, attr: MmioAttr) -> AxResult<VirtAddr> {
+        if size == 0 {
+            return ax_err!(InvalidInput, "empty MMIO region");
+        }
+        let start_paddr = paddr.align_down_4k();
+        let size = (paddr + size).align_up_4k().as_usize() - start_paddr.as_usize();
+
+        let linear_vaddr = phys_to_virt(start_paddr);
+        let start_vaddr = if self.contains_range(linear_vaddr, size) {
+            linear_vaddr
+        } else {
+            self.find_free_area(self.base(), size, self.va_range)
+                .ok_or(AxError::NoMemory)?
+        };
+        let range = VirtAddrRange::from_start_size(start_vaddr, size);
+        if self.areas.overlaps(range) {
+            return ax_err!(AlreadyExists, "MMIO region already mapped");
+        }
+
+        // Remove the existing linear mapping (not tracked in `areas`), so that
+        // the whole region gets the new attributes.
+        let offset = start_vaddr.as_usize().wrapping_sub(start_paddr.as_usize());
+        for vaddr in PageIter4K::new(start_vaddr, range.end).unwrap() {
+            if let Ok((mapped, _, _)) = self.pt.query(vaddr) {
+                if mapped.as_usize() != vaddr.as_usize().wrapping_sub(offset) {
+                    return ax_err!(AlreadyExists, "MMIO region mapped to other memory");
+                }
+            }
+        }
+        for vaddr in PageIter4K::new(start_vaddr, range.end).unwrap() {
+            if let Ok((_, _, tlb)) = self.pt.unmap(vaddr) {
+                tlb.flush();
+            }
+        }
+
+        let area = MemoryArea::new(
+            start_vaddr,
+            size,
+            attr.mapping_flags(),
+            Backend::new_linear(offset),
+        );
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        debug!(
+            "map_mmio: [{:#x}, {:#x}) -> [{:#x}, {:#x}) {:?}",
+            start_vaddr,
+            range.end,
+            start_paddr,
+            start_paddr + size,
+            attr
+        );
+        Ok(start_vaddr + paddr.align_offset_4k())
+    }
+
+    /// Unmaps an MMIO region mapped by [`AddrSpace::map_mmio`].
+    ///
+    /// `vaddr` and `size` are the ones passed to or returned by
+    /// [`AddrSpace::map_mmio`], and do not need to be page aligned. Note that
+    /// the linear mapping the region may have replaced is not restored.
+    pub fn unmap_mmio(&mut self, vaddr: VirtAddr, size: usize) -> AxResult {
+        let start = vaddr.align_down_4k();
+        let size = (vaddr + size).align_up_4k().as_usize() - start.as_usize();
+        if !self.contains_range(start, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        match self.areas.find(start) {
+            Some(area) if MmioAttr::from_mapping_flags(area.flags()).is_some() => {}
+            _ => return ax_err!(InvalidInput, "not an MMIO region"),
+        }
+        self.areas
+            .unmap(start, size, &mut self.pt)
+            .map_err(mapping_err_to_ax_err)
+    }
+
+    /// Queries the mapping of `vaddr`, and returns the physical address it is
+    /// mapped to and the mapping flags.
+    ///
+    /// The memory attribute of an MMIO mapping can be obtained from the flags
+    /// with [`MmioAttr::from_mapping_flags`].
+    pub fn query(&self, vaddr: VirtAddr) -> AxResult<(PhysAddr, MappingFlags)> {
+        if !self.va_range.contains(vaddr) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        let (paddr, flags, _) = self.pt.query(vaddr).map_err(paging_err_to_ax_err)?;
+        Ok((paddr, flags))
+    }
+
     /// Removes mappings within the specified virtual address range.
     ///
     /// Returns an error if the address range is out of the address space or not
@@ -170,6 +349,17 @@ impl AddrSpace {
             return ax_err!(InvalidInput, "address not aligned");
         }
 
+        // Mappings tracked in `areas` are unmapped by their backends, which
+        // free the frames and uncharge them.
+        if self
+            .areas
+            .overlaps(VirtAddrRange::from_start_size(start, size))
+        {
+            return self
+                .areas
+                .unmap(start, size, &mut self.pt)
+                .map_err(mapping_err_to_ax_err);
+        }
         self.pt
             .unmap_region(start, size, true)
             .map_err(paging_err_to_ax_err)?
@@ -329,6 +519,7 @@ impl fmt::Debug for AddrSpace {
         f.debug_struct("AddrSpace")
             .field("va_range", &self.va_range)
             .field("page_table_root", &self.pt.root_paddr())
+            .field("usage", &self.account.usage())
             .finish()
     }
 }
@@ -1,37 +1,69 @@
+use alloc::sync::Arc;
+
 use axalloc::global_allocator;
-use axhal::mem::{phys_to_virt, virt_to_phys};
+use axhal::mem::virt_to_phys;
 use axhal::paging::{MappingFlags, PageSize, PageTable};
 use memory_addr::{PageIter4K, PhysAddr, VirtAddr, PAGE_SIZE_4K};
 
 use super::Backend;
+use crate::frame::{frame_init, frame_put, FrameFlags};
+use crate::zero_pool::{drain_zero_pool, take_zeroed_frame};
+use crate::MemAccount;
 
 fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
-    let vaddr = VirtAddr::from(global_allocator().alloc_pages(1, PAGE_SIZE_4K).ok()?);
+    if zeroed {
+        if let Some(frame) = take_zeroed_frame() {
+            frame_init(frame, FrameFlags::ANON);
+            return Some(frame);
+        }
+    }
+    let vaddr = match global_allocator().alloc_pages(1, PAGE_SIZE_4K) {
+        Ok(vaddr) => vaddr,
+        // Out of memory, give the pre-zeroed pages back and retry.
+        Err(_) if drain_zero_pool() > 0 => global_allocator().alloc_pages(1, PAGE_SIZE_4K).ok()?,
+        Err(_) => return None,
+    };
+    let vaddr = VirtAddr::from(vaddr);
     if zeroed {
         unsafe { core::ptr::write_bytes(vaddr.as_mut_ptr(), 0, PAGE_SIZE_4K) };
     }
     let paddr = virt_to_phys(vaddr);
+    frame_init(paddr, FrameFlags::ANON);
     Some(paddr)
 }
 
+/// Drops the reference of the mapping to `frame`, which frees it unless it
+/// is shared.
 fn dealloc_frame(frame: PhysAddr) {
-    let vaddr = phys_to_virt(frame);
-    global_allocator().dealloc_pages(vaddr.as_usize(), 1);
+    frame_put(frame);
+}
+
+/// Allocates a zeroed frame charged to `account`.
+fn alloc_charged_frame(account: &MemAccount) -> Option<PhysAddr> {
+    if !account.try_charge_resident(PAGE_SIZE_4K) {
+        return None;
+    }
+    let frame = alloc_frame(true);
+    if frame.is_none() {
+        account.uncharge_resident(PAGE_SIZE_4K);
+    }
+    frame
 }
 
 impl Backend {
-    /// Creates a new allocation mapping backend.
-    pub const fn new_alloc(populate: bool) -> Self {
-        Self::Alloc { populate }
+    /// Creates a new allocation mapping backend, charging the memory to
+    /// `account`.
+    pub fn new_alloc(populate: bool, account: Arc<MemAccount>) -> Self {
+        Self::Alloc { populate, account }
     }
 
     pub(crate) fn map_alloc(
-        &self,
         start: VirtAddr,
         size: usize,
         flags: MappingFlags,
         pt: &mut PageTable,
         populate: bool,
+        account: &MemAccount,
     ) -> bool {
         debug!(
             "map_alloc: [{:#x}, {:#x}) {:?} (populate={})",
@@ -40,10 +72,13 @@ impl Backend {
             flags,
             populate
         );
+        if !account.try_charge_virt(size) {
+            return false;
+        }
         if populate {
             // allocate all possible physical frames for populated mapping.
             for addr in PageIter4K::new(start, start + size).unwrap() {
-                if let Some(frame) = alloc_frame(true) {
+                if let Some(frame) = alloc_charged_frame(account) {
                     if let Ok(tlb) = pt.map(addr, frame, PageSize::Size4K, flags) {
                         tlb.ignore(); // TLB flush on map is unnecessary, as there are no outdated mappings.
                     } else {
@@ -55,18 +90,23 @@ impl Backend {
         } else {
             // Map to a empty entry for on-demand mapping.
             let flags = MappingFlags::empty();
-            pt.map_region(start, |_| 0.into(), size, flags, false, false)
+            let ok = pt
+                .map_region(start, |_| 0.into(), size, flags, false, false)
                 .map(|tlb| tlb.ignore())
-                .is_ok()
+                .is_ok();
+            if !ok {
+                account.uncharge_virt(size);
+            }
+            ok
         }
     }
 
     pub(crate) fn unmap_alloc(
-        &self,
         start: VirtAddr,
         size: usize,
         pt: &mut PageTable,
         _populate: bool,
+        account: &MemAccount,
     ) -> bool {
         debug!("unmap_alloc: [{:#x}, {:#x})", start, start + size);
         for addr in PageIter4K::new(start, start + size).unwrap() {
@@ -78,23 +118,25 @@ impl Backend {
                 }
                 tlb.flush();
                 dealloc_frame(frame);
+                account.uncharge_resident(PAGE_SIZE_4K);
             } else {
                 // Deallocation is needn't if the page is not mapped.
             }
         }
+        account.uncharge_virt(size);
         true
     }
 
     pub(crate) fn handle_page_fault_alloc(
-        &self,
         vaddr: VirtAddr,
         orig_flags: MappingFlags,
         pt: &mut PageTable,
         populate: bool,
+        account: &MemAccount,
     ) -> bool {
         if populate {
             false // Populated mappings should not trigger page faults.
-        } else if let Some(frame) = alloc_frame(true) {
+        } else if let Some(frame) = alloc_charged_frame(account) {
             // Allocate a physical frame lazily and map it to the fault address.
             // `vaddr` does not need to be aligned. It will be automatically
             // aligned during `pt.remap` regardless of the page size.
@@ -1,10 +1,14 @@
 //! Memory mapping backends.
 #![allow(dead_code)]
 
+use alloc::sync::Arc;
+
 use axhal::paging::{MappingFlags, PageTable};
 use memory_addr::VirtAddr;
 use memory_set::MappingBackend;
 
+use crate::MemAccount;
+
 mod alloc;
 mod linear;
 
@@ -36,6 +40,8 @@ pub enum Backend {
     Alloc {
         /// Whether to populate the physical frames when creating the mapping.
         populate: bool,
+        /// The account the mapping and its frames are charged to.
+        account: Arc<MemAccount>,
     },
 }
 
@@ -44,16 +50,20 @@ impl MappingBackend for Backend {
     type Flags = MappingFlags;
     type PageTable = PageTable;
     fn map(&self, start: VirtAddr, size: usize, flags: MappingFlags, pt: &mut PageTable) -> bool {
-        match *self {
-            Self::Linear { pa_va_offset } => self.map_linear(start, size, flags, pt, pa_va_offset),
-            Self::Alloc { populate } => self.map_alloc(start, size, flags, pt, populate),
+        match self {
+            Self::Linear { pa_va_offset } => self.map_linear(start, size, flags, pt, *pa_va_offset),
+            Self::Alloc { populate, account } => {
+                Self::map_alloc(start, size, flags, pt, *populate, account)
+            }
         }
     }
 
     fn unmap(&self, start: VirtAddr, size: usize, pt: &mut PageTable) -> bool {
-        match *self {
-            Self::Linear { pa_va_offset } => self.unmap_linear(start, size, pt, pa_va_offset),
-            Self::Alloc { populate } => self.unmap_alloc(start, size, pt, populate),
+        match self {
+            Self::Linear { pa_va_offset } => self.unmap_linear(start, size, pt, *pa_va_offset),
+            Self::Alloc { populate, account } => {
+                Self::unmap_alloc(start, size, pt, *populate, account)
+            }
         }
     }
 
@@ -78,10 +88,10 @@ impl Backend {
         orig_flags: MappingFlags,
         page_table: &mut PageTable,
     ) -> bool {
-        match *self {
+        match self {
             Self::Linear { .. } => false, // Linear mappings should not trigger page faults.
-            Self::Alloc { populate } => {
-                self.handle_page_fault_alloc(vaddr, orig_flags, page_table, populate)
+            Self::Alloc { populate, account } => {
+                Self::handle_page_fault_alloc(vaddr, orig_flags, page_table, *populate, account)
             }
         }
     }
@@ -0,0 +1,167 @@
+//! Physical frame metadata.
+//!
+//! Each 4K frame of the physical memory has a [`FrameMeta`] entry in an array
+//! allocated at initialization, holding a reference count and [`FrameFlags`].
+//! Frames allocated for mappings start with one reference, owned by the
+//! mapping. Sharing a frame (copy-on-write, shared mappings, the page cache)
+//! takes one more with [`frame_get`], and each owner gives its reference back
+//! with [`frame_put`], the last one freeing the frame.
+//!
+//! Putting a frame with no reference left is a double free, and panics
+//! instead of corrupting the allocator.
+//!
+//! Frames outside `[PHYS_MEMORY_BASE, PHYS_MEMORY_END)`, e.g. hot-plugged
+//! memory, and all frames before initialization, have no metadata: they are
+//! treated as having exactly one owner.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use axalloc::global_allocator;
+use axhal::mem::phys_to_virt;
+use bitflags::bitflags;
+use lazyinit::LazyInit;
+use memory_addr::{align_up_4k, PhysAddr, PAGE_SIZE_4K};
+
+bitflags! {
+    /// What a physical frame is used for.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct FrameFlags: u32 {
+        /// Anonymous memory of an allocation mapping.
+        const ANON = 1 << 0;
+        /// Mapped by several address spaces.
+        const SHARED = 1 << 1;
+        /// Shared copy-on-write: must be copied before being written.
+        const COW = 1 << 2;
+        /// Caches the content of a file.
+        const PAGE_CACHE = 1 << 3;
+        /// Given to the host by the memory balloon.
+        const BALLOON = 1 << 4;
+        /// Must not be moved or reclaimed, e.g. used for DMA.
+        const PINNED = 1 << 5;
+    }
+}
+
+/// The metadata of a physical frame.
+#[derive(Default)]
+pub struct FrameMeta {
+    refcount: AtomicU32,
+    flags: AtomicU32,
+}
+
+impl FrameMeta {
+    /// Returns the number of references to the frame, 0 if it is free.
+    pub fn refcount(&self) -> usize {
+        self.refcount.load(Ordering::Acquire) as usize
+    }
+
+    /// Returns the flags of the frame.
+    pub fn flags(&self) -> FrameFlags {
+        FrameFlags::from_bits_truncate(self.flags.load(Ordering::Acquire))
+    }
+
+    /// Sets `flags` on the frame.
+    pub fn insert_flags(&self, flags: FrameFlags) {
+        self.flags.fetch_or(flags.bits(), Ordering::AcqRel);
+    }
+
+    /// Clears `flags` on the frame.
+    pub fn remove_flags(&self, flags: FrameFlags) {
+        self.flags.fetch_and(!flags.bits(), Ordering::AcqRel);
+    }
+}
+
+static FRAMES: LazyInit<&'static [FrameMeta]> = LazyInit::new();
+
+const FRAMES_BASE: usize = axconfig::PHYS_MEMORY_BASE;
+
+/// Allocates the frame metadata array.
+pub(crate) fn init_frames() {
+    let num_frames = (axconfig::PHYS_MEMORY_END - FRAMES_BASE) / PAGE_SIZE_4K;
+    let size = num_frames * core::mem::size_of::<FrameMeta>();
+    let num_pages = align_up_4k(size) / PAGE_SIZE_4K;
+    let vaddr = global_allocator()
+        .alloc_pages(num_pages, PAGE_SIZE_4K)
+        .expect("failed to allocate the frame metadata");
+    // All-zero metadata is valid: no references and no flags.
+    let frames = unsafe {
+        core::ptr::write_bytes(vaddr as *mut u8, 0, num_pages * PAGE_SIZE_4K);
+        core::slice::from_raw_parts(vaddr as *const FrameMeta, num_frames)
+    };
+    debug!(
+        "frame metadata: {} frames, {} KiB",
+        num_frames,
+        num_pages * PAGE_SIZE_4K / 1024
+    );
+    FRAMES.init_once(frames);
+}
+
+/// Returns the metadata of the frame at `paddr`, if it has one.
+pub fn frame_meta(paddr: PhysAddr) -> Option<&'static FrameMeta> {
+    let frames = FRAMES.get()?;
+    let index = paddr.as_usize().checked_sub(FRAMES_BASE)? / PAGE_SIZE_4K;
+    frames.get(index)
+}
+
+/// Starts tracking the newly allocated frame at `paddr`, with one reference
+/// and `flags`.
+///
+/// Panics if the frame is still referenced, i.e. the allocator returned a
+/// frame that was not freed.
+pub(crate) fn frame_init(paddr: PhysAddr, flags: FrameFlags) {
+    let Some(meta) = frame_meta(paddr) else {
+        return;
+    };
+    if meta
+        .refcount
+        .compare_exchange(0, 1, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        panic!("frame {:#x} allocated while still in use", paddr);
+    }
+    meta.flags.store(flags.bits(), Ordering::Release);
+}
+
+/// Takes one more reference to the frame at `paddr`, and returns the new
+/// reference count.
+///
+/// Panics if the frame is free.
+pub fn frame_get(paddr: PhysAddr) -> usize {
+    let Some(meta) = frame_meta(paddr) else {
+        return 1;
+    };
+    let old = meta.refcount.fetch_add(1, Ordering::AcqRel);
+    if old == 0 {
+        panic!("reference taken to free frame {:#x}", paddr);
+    }
+    old as usize + 1
+}
+
+/// Gives back one reference to the frame at `paddr`, and frees it if it was
+/// the last one.
+///
+/// Returns whether the frame is freed.
+///
+/// Panics if the frame is already free.
+pub fn frame_put(paddr: PhysAddr) -> bool {
+    let freed = match frame_meta(paddr) {
+        Some(meta) => match meta.refcount.fetch_sub(1, Ordering::AcqRel) {
+            0 => panic!("double free of frame {:#x}", paddr),
+            1 => {
+                meta.flags.store(0, Ordering::Release);
+                true
+            }
+            _ => false,
+        },
+        None => true,
+    };
+    if freed {
+        global_allocator().dealloc_pages(phys_to_virt(paddr).as_usize(), 1);
+    }
+    freed
+}
+
+/// Returns the number of references to the frame at `paddr`, or `None` if
+/// it has no metadata.
+pub fn frame_refcount(paddr: PhysAddr) -> Option<usize> {
+    frame_meta(paddr).map(FrameMeta::refcount)
+}
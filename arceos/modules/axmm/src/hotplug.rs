@@ -0,0 +1,70 @@
+//! Memory hotplug.
+//!
+//! RAM regions can be added at runtime (e.g., by a virtio-mem device or a
+//! device tree overlay) with [`add_memory`], and removed again with
+//! [`remove_memory`] once all their pages are free, as a balloon driver would
+//! do to give memory back to the host.
+
+use axalloc::{global_allocator, AllocError};
+use axerrno::{ax_err, AxResult};
+use axhal::mem::{phys_to_virt, MemRegionFlags};
+use memory_addr::{is_aligned_4k, PhysAddr};
+
+use crate::{drain_zero_pool, kernel_aspace};
+
+/// Adds the free RAM region `[paddr, paddr + size)`.
+///
+/// The region is registered in [`axhal::mem::memory_regions`], linearly
+/// mapped in the kernel address space, and given to the page allocator.
+///
+/// Returns an error if the region is not page aligned, or overlaps with
+/// another memory region.
+pub fn add_memory(paddr: PhysAddr, size: usize) -> AxResult {
+    if !paddr.is_aligned_4k() || !is_aligned_4k(size) {
+        return ax_err!(InvalidInput, "address not aligned");
+    }
+    if !axhal::mem::add_memory_region(paddr, size) {
+        return ax_err!(AlreadyExists, "memory region overlaps or too many regions");
+    }
+    let vaddr = phys_to_virt(paddr);
+    let flags = MemRegionFlags::READ | MemRegionFlags::WRITE;
+    if let Err(e) = kernel_aspace()
+        .lock()
+        .map_linear(vaddr, paddr, size, flags.into())
+    {
+        axhal::mem::remove_memory_region(paddr, size);
+        return Err(e);
+    }
+    if let Err(e) = global_allocator().add_pages(vaddr.as_usize(), size) {
+        warn!("failed to add memory to the page allocator: {:?}", e);
+        kernel_aspace().lock().unmap(vaddr, size)?;
+        axhal::mem::remove_memory_region(paddr, size);
+        return ax_err!(NoMemory);
+    }
+    info!("hotplug memory added: [{:#x}, {:#x})", paddr, paddr + size);
+    Ok(())
+}
+
+/// Removes a RAM region added by [`add_memory`], with the same start address
+/// and size.
+///
+/// Fails with [`AxError::ResourceBusy`](axerrno::AxError::ResourceBusy) if
+/// some pages of the region are still in use.
+pub fn remove_memory(paddr: PhysAddr, size: usize) -> AxResult {
+    let vaddr = phys_to_virt(paddr);
+    // pre-zeroed pages may come from the region
+    drain_zero_pool();
+    match global_allocator().remove_pages(vaddr.as_usize(), size) {
+        Ok(()) => {}
+        Err(AllocError::NotAllocated) => return ax_err!(NotFound),
+        Err(_) => return ax_err!(ResourceBusy, "memory region in use"),
+    }
+    kernel_aspace().lock().unmap(vaddr, size)?;
+    axhal::mem::remove_memory_region(paddr, size);
+    info!(
+        "hotplug memory removed: [{:#x}, {:#x})",
+        paddr,
+        paddr + size
+    );
+    Ok(())
+}
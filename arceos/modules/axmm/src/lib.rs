@@ -6,13 +6,27 @@
 extern crate log;
 extern crate alloc;
 
+mod account;
 mod aspace;
 mod backend;
+mod frame;
+#[cfg(feature = "hotplug")]
+mod hotplug;
+mod mmio;
+mod zero_pool;
 
+pub use self::account::{MemAccount, MemUsage};
 pub use self::aspace::AddrSpace;
+pub use self::frame::{frame_get, frame_meta, frame_put, frame_refcount, FrameFlags, FrameMeta};
+#[cfg(feature = "hotplug")]
+pub use self::hotplug::{add_memory, remove_memory};
+pub use self::mmio::{map_mmio, unmap_mmio, MmioAttr};
+pub use self::zero_pool::{
+    drain_zero_pool, scrub_free_pages, zero_pool_stats, ZeroPoolStats, ZERO_POOL_CAPACITY,
+};
 
 use axerrno::{AxError, AxResult};
-use axhal::mem::phys_to_virt;
+use axhal::mem::{phys_to_virt, MemRegion, MemRegionFlags};
 use axhal::paging::PagingError;
 use kspin::SpinNoIrq;
 use lazyinit::LazyInit;
@@ -58,11 +72,47 @@ pub fn new_kernel_aspace() -> AxResult<AddrSpace> {
         axconfig::KERNEL_ASPACE_SIZE,
     )?;
     for r in axhal::mem::memory_regions() {
-        aspace.map_linear(phys_to_virt(r.paddr), r.paddr, r.size, r.flags.into())?;
+        if r.flags.contains(MemRegionFlags::FREE) {
+            map_free_region(&mut aspace, &r)?;
+        } else {
+            aspace.map_linear(phys_to_virt(r.paddr), r.paddr, r.size, r.flags.into())?;
+        }
     }
     Ok(aspace)
 }
 
+/// Maps the free memory region `r` linearly, with huge pages for the blocks
+/// of huge pages the kernel heap has grown by.
+fn map_free_region(aspace: &mut AddrSpace, r: &MemRegion) -> AxResult {
+    let start = phys_to_virt(r.paddr).as_usize();
+    let end = start + r.size;
+    let mut blocks = axalloc::global_allocator().huge_heap_blocks();
+    blocks.sort_unstable_by_key(|b| b.map(|b| b.start));
+    let mut pos = start;
+    for b in blocks.iter().flatten() {
+        if b.start < pos || b.start + b.size() > end {
+            continue;
+        }
+        if pos < b.start {
+            let paddr = r.paddr + (pos - start);
+            aspace.map_linear(va!(pos), paddr, b.start - pos, r.flags.into())?;
+        }
+        let paddr = r.paddr + (b.start - start);
+        aspace.map_linear_huge(va!(b.start), paddr, b.size(), r.flags.into())?;
+        debug!(
+            "kernel heap mapped with huge pages: [{:#x}, {:#x})",
+            b.start,
+            b.start + b.size()
+        );
+        pos = b.start + b.size();
+    }
+    if pos < end {
+        let paddr = r.paddr + (pos - start);
+        aspace.map_linear(va!(pos), paddr, end - pos, r.flags.into())?;
+    }
+    Ok(())
+}
+
 /// Returns the globally unique kernel address space.
 pub fn kernel_aspace() -> &'static SpinNoIrq<AddrSpace> {
     &KERNEL_ASPACE
@@ -80,6 +130,7 @@ pub fn kernel_page_table_root() -> PhysAddr {
 pub fn init_memory_management() {
     info!("Initialize virtual memory management...");
 
+    frame::init_frames();
     let kernel_aspace = new_kernel_aspace().expect("failed to initialize kernel address space");
     debug!("kernel address space init OK: {:#x?}", kernel_aspace);
     KERNEL_ASPACE.init_once(SpinNoIrq::new(kernel_aspace));
@@ -0,0 +1,67 @@
+//! Device MMIO mappings.
+
+use axerrno::AxResult;
+use axhal::paging::MappingFlags;
+use memory_addr::{PhysAddr, VirtAddr};
+
+use crate::kernel_aspace;
+
+/// Memory attributes of an MMIO mapping.
+///
+/// They are translated to the memory types of each architecture by the page
+/// table entries:
+///
+/// - x86_64: both device types are uncacheable (`PCD | PWT`), as is
+///   [`MmioAttr::NonCacheable`].
+/// - AArch64: [`MmioAttr::Device`] and [`MmioAttr::StronglyOrdered`] use the
+///   `Device-nGnRE` memory type, and [`MmioAttr::NonCacheable`] uses
+///   `Normal Non-cacheable`.
+/// - RISC-V: the attributes are ignored, since the memory type of MMIO regions
+///   without the Svpbmt extension is determined by the platform (PMA).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmioAttr {
+    /// Device registers: uncached, and no speculative or merged accesses.
+    Device,
+    /// Device registers whose accesses must also complete in program order.
+    ///
+    /// It is the same as [`MmioAttr::Device`] on architectures without a
+    /// stronger device memory type.
+    StronglyOrdered,
+    /// Normal memory that is not cached, e.g. frame buffers or device memory
+    /// shared with the CPU, allowing merged and out-of-order accesses.
+    NonCacheable,
+}
+
+impl MmioAttr {
+    /// Returns the mapping flags of an MMIO mapping with this attribute.
+    pub const fn mapping_flags(self) -> MappingFlags {
+        let flags = MappingFlags::READ.union(MappingFlags::WRITE);
+        match self {
+            Self::Device | Self::StronglyOrdered => flags.union(MappingFlags::DEVICE),
+            Self::NonCacheable => flags.union(MappingFlags::UNCACHED),
+        }
+    }
+
+    /// Returns the attribute of an MMIO mapping with the given flags, or
+    /// `None` if it is a mapping of cacheable memory.
+    pub fn from_mapping_flags(flags: MappingFlags) -> Option<Self> {
+        if flags.contains(MappingFlags::DEVICE) {
+            Some(Self::Device)
+        } else if flags.contains(MappingFlags::UNCACHED) {
+            Some(Self::NonCacheable)
+        } else {
+            None
+        }
+    }
+}
+
+/// Maps the device MMIO region `[paddr, paddr + size)` into the kernel
+/// address space, see [`AddrSpace::map_mmio`](crate::AddrSpace::map_mmio).
+pub fn map_mmio(paddr: PhysAddr, size: usize, attr: MmioAttr) -> AxResult<VirtAddr> {
+    kernel_aspace().lock().map_mmio(paddr, size, attr)
+}
+
+/// Unmaps an MMIO region mapped by [`map_mmio`].
+pub fn unmap_mmio(vaddr: VirtAddr, size: usize) -> AxResult {
+    kernel_aspace().lock().unmap_mmio(vaddr, size)
+}
@@ -0,0 +1,122 @@
+//! A pool of pre-zeroed physical pages.
+//!
+//! Zeroing a page on each anonymous page fault puts a full `memset` on the
+//! fault path. Instead, a background task can call [`scrub_free_pages`] when
+//! the system is idle, to allocate free pages and zero them in advance. Page
+//! faults then take zeroed pages from the pool, and only zero pages
+//! synchronously when it is empty. [`zero_pool_stats`] shows how often the
+//! pool is hit.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use axalloc::global_allocator;
+use axhal::mem::{phys_to_virt, virt_to_phys};
+use kspin::SpinNoIrq;
+use memory_addr::{PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+/// Maximum number of pages kept in the pool.
+pub const ZERO_POOL_CAPACITY: usize = 512;
+
+/// Statistics of the pool of pre-zeroed pages.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ZeroPoolStats {
+    /// Number of zeroed pages taken from the pool.
+    pub hits: u64,
+    /// Number of zeroed pages requested when the pool was empty.
+    pub misses: u64,
+    /// Number of pages zeroed in the background.
+    pub scrubbed: u64,
+    /// Number of pages currently in the pool.
+    pub pooled: usize,
+}
+
+impl ZeroPoolStats {
+    /// Returns the percentage of zeroed page requests served by the pool.
+    pub fn hit_rate(&self) -> u64 {
+        match self.hits + self.misses {
+            0 => 0,
+            total => self.hits * 100 / total,
+        }
+    }
+}
+
+struct Pool {
+    frames: [usize; ZERO_POOL_CAPACITY],
+    len: usize,
+}
+
+static POOL: SpinNoIrq<Pool> = SpinNoIrq::new(Pool {
+    frames: [0; ZERO_POOL_CAPACITY],
+    len: 0,
+});
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+static SCRUBBED: AtomicU64 = AtomicU64::new(0);
+
+/// Takes a zeroed page from the pool, or returns `None` if it is empty.
+pub(crate) fn take_zeroed_frame() -> Option<PhysAddr> {
+    let mut pool = POOL.lock();
+    if pool.len == 0 {
+        MISSES.fetch_add(1, Ordering::Relaxed);
+        return None;
+    }
+    pool.len -= 1;
+    HITS.fetch_add(1, Ordering::Relaxed);
+    Some(PhysAddr::from(pool.frames[pool.len]))
+}
+
+/// Allocates at most `max_pages` free pages, zeroes them and puts them into
+/// the pool, until it is full.
+///
+/// Returns the number of pages added, which is 0 if the pool is full or
+/// there is no free memory.
+pub fn scrub_free_pages(max_pages: usize) -> usize {
+    let mut added = 0;
+    while added < max_pages && POOL.lock().len < ZERO_POOL_CAPACITY {
+        let Ok(vaddr) = global_allocator().alloc_pages(1, PAGE_SIZE_4K) else {
+            break;
+        };
+        // zero it without holding the lock
+        unsafe { core::ptr::write_bytes(vaddr as *mut u8, 0, PAGE_SIZE_4K) };
+        let paddr = virt_to_phys(VirtAddr::from(vaddr));
+
+        let mut pool = POOL.lock();
+        if pool.len == ZERO_POOL_CAPACITY {
+            // filled by someone else
+            drop(pool);
+            global_allocator().dealloc_pages(vaddr, 1);
+            break;
+        }
+        let len = pool.len;
+        pool.frames[len] = paddr.as_usize();
+        pool.len += 1;
+        added += 1;
+    }
+    SCRUBBED.fetch_add(added as u64, Ordering::Relaxed);
+    added
+}
+
+/// Returns all pages in the pool to the global allocator, e.g. when it is
+/// short of memory.
+///
+/// Returns the number of pages released.
+pub fn drain_zero_pool() -> usize {
+    let mut pool = POOL.lock();
+    let len = pool.len;
+    for &frame in &pool.frames[..len] {
+        let vaddr = phys_to_virt(PhysAddr::from(frame));
+        global_allocator().dealloc_pages(vaddr.as_usize(), 1);
+    }
+    pool.len = 0;
+    len
+}
+
+/// Returns the statistics of the pool of pre-zeroed pages.
+pub fn zero_pool_stats() -> ZeroPoolStats {
+    ZeroPoolStats {
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+        scrubbed: SCRUBBED.load(Ordering::Relaxed),
+        pooled: POOL.lock().len,
+    }
+}
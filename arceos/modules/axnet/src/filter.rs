@@ -0,0 +1,137 @@
+//! Early packet filters.
+//!
+//! Filters registered with [`register_filter`] see each received Ethernet
+//! frame before the network stack processes it, and may modify it. Their
+//! [`Verdict`] decides what happens next:
+//!
+//! - [`Verdict::Pass`]: the next filter, then the network stack, gets it;
+//! - [`Verdict::Drop`]: the frame is discarded, e.g. to filter attacks at the
+//!   lowest cost;
+//! - [`Verdict::Redirect`]: the frame bypasses the stack and is queued for the
+//!   application, which reads it with [`recv_redirected`], e.g. to implement
+//!   a custom protocol.
+//!
+//! Filters run in the receive path with the network device locked, so they
+//! must be fast and must not block or call other network functions.
+
+use alloc::{collections::VecDeque, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use axerrno::{ax_err, AxResult};
+use spin::{Mutex, RwLock};
+
+/// What to do with a received frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Hand the frame to the next filter, then to the network stack.
+    Pass,
+    /// Discard the frame.
+    Drop,
+    /// Queue the frame for [`recv_redirected`], bypassing the network stack.
+    Redirect,
+}
+
+/// A filter on raw received Ethernet frames.
+pub type PacketFilter = fn(frame: &mut [u8]) -> Verdict;
+
+/// Maximum number of registered filters.
+pub const MAX_FILTERS: usize = 8;
+
+/// Maximum number of redirected frames queued. Later ones are dropped until
+/// the application reads the queue.
+pub const REDIRECT_QUEUE_LEN: usize = 256;
+
+static FILTERS: RwLock<[Option<PacketFilter>; MAX_FILTERS]> = RwLock::new([None; MAX_FILTERS]);
+static REDIRECTED: Mutex<VecDeque<Vec<u8>>> = Mutex::new(VecDeque::new());
+
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+static REDIRECTED_COUNT: AtomicU64 = AtomicU64::new(0);
+static OVERFLOWED: AtomicU64 = AtomicU64::new(0);
+
+/// Statistics of the packet filters.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FilterStats {
+    /// Number of frames dropped by filters.
+    pub dropped: u64,
+    /// Number of frames redirected by filters.
+    pub redirected: u64,
+    /// Number of redirected frames lost as the queue was full.
+    pub overflowed: u64,
+}
+
+/// Registers a filter, run after the ones registered before.
+///
+/// Returns an error if there are already [`MAX_FILTERS`] filters.
+pub fn register_filter(filter: PacketFilter) -> AxResult {
+    let mut filters = FILTERS.write();
+    match filters.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => {
+            *slot = Some(filter);
+            Ok(())
+        }
+        None => ax_err!(NoMemory, "too many packet filters"),
+    }
+}
+
+/// Unregisters a filter registered with [`register_filter`].
+///
+/// Returns an error if it is not registered.
+pub fn unregister_filter(filter: PacketFilter) -> AxResult {
+    let mut filters = FILTERS.write();
+    match filters
+        .iter_mut()
+        .find(|slot| slot.is_some_and(|f| f as usize == filter as usize))
+    {
+        Some(slot) => {
+            *slot = None;
+            Ok(())
+        }
+        None => ax_err!(NotFound, "packet filter not registered"),
+    }
+}
+
+/// Takes the oldest redirected frame, copies it to `buf`, and returns its
+/// length, or `None` if there is none.
+///
+/// The frame is truncated if `buf` is too small.
+pub fn recv_redirected(buf: &mut [u8]) -> Option<usize> {
+    let frame = REDIRECTED.lock().pop_front()?;
+    let len = frame.len().min(buf.len());
+    buf[..len].copy_from_slice(&frame[..len]);
+    Some(len)
+}
+
+/// Returns the statistics of the packet filters.
+pub fn filter_stats() -> FilterStats {
+    FilterStats {
+        dropped: DROPPED.load(Ordering::Relaxed),
+        redirected: REDIRECTED_COUNT.load(Ordering::Relaxed),
+        overflowed: OVERFLOWED.load(Ordering::Relaxed),
+    }
+}
+
+/// Runs the filters on a received frame, and returns whether the network
+/// stack should process it.
+pub(crate) fn filter_rx(frame: &mut [u8]) -> bool {
+    let filters = *FILTERS.read();
+    for filter in filters.iter().flatten() {
+        match filter(frame) {
+            Verdict::Pass => {}
+            Verdict::Drop => {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+            Verdict::Redirect => {
+                let mut queue = REDIRECTED.lock();
+                if queue.len() < REDIRECT_QUEUE_LEN {
+                    queue.push_back(frame.to_vec());
+                    REDIRECTED_COUNT.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    OVERFLOWED.fetch_add(1, Ordering::Relaxed);
+                }
+                return false;
+            }
+        }
+    }
+    true
+}
@@ -9,11 +9,44 @@
 //! - [`TcpSocket`]: A TCP socket that provides POSIX-like APIs.
 //! - [`UdpSocket`]: A UDP socket that provides POSIX-like APIs.
 //! - [`dns_query`]: Function for DNS query.
+//! - [`bridge_add_port`], [`nat_enable`]: Forwarding between the NICs, see
+//!   [`interfaces`].
+//! - [`neighbors`]: The neighbor (ARP) table, see also [`add_static_neighbor`]
+//!   and [`flush_neighbors`].
+//! - [`qos_set_rate`]: Egress traffic shaping and priority queues, see
+//!   [`TrafficClass`].
+//! - [`vlan_add`]: 802.1Q VLAN sub-interfaces of `eth0`, each with its own
+//!   address, see [`vlans`].
+//! - [`mdns_responder`], [`mdns_browse`]: mDNS/DNS-SD, announcing the services
+//!   registered with [`mdns_register`] and discovering peers.
+//! - [`sntp_client`]: SNTP client synchronizing the realtime clock.
+//! - [`syslog_client`]: Syslog client shipping the log messages to a server.
+//! - [`socket_stats`]: The state of the sockets, like `netstat`, also in the
+//!   format of `/proc/net/tcp` with [`proc_net_tcp`].
+//! - [`filter`]: Filters on received frames, run before the network stack.
+//! - [`skb`]: Packet buffers with headroom and tailroom, to add and remove
+//!   headers without copying the packets.
+//! - `wg_up`, `wg_add_peer`: A WireGuard tunnel (with the `wireguard`
+//!   feature).
+//! - `ppp_up`, `ppp_status`: A PPPoE client on a NIC of a DSL or 4G modem
+//!   (with the `ppp` feature).
+//! - `tap_read`, `tap_write`: The TAP interface `tap0`, also as the device
+//!   file `TunDev` of `/dev/net/tun` (with the `tun` feature).
+//! - `VsockSocket`: A vsock stream socket to talk with the host without IP
+//!   networking (with the `vsock` feature).
+//! - `QuicConnection`, `QuicListener`: QUIC connections of streams over the
+//!   UDP sockets, with 0-RTT resumption (with the `quic` feature).
 //!
 //! # Cargo Features
 //!
 //! - `smoltcp`: Use [smoltcp] as the underlying network stack. This is enabled
 //!   by default.
+//! - `wireguard`: Enable the WireGuard tunnel interface.
+//! - `ppp`: Enable the PPPoE client interface.
+//! - `tun`: Enable the TAP interface, exchanging frames with an application.
+//! - `replay`: Record and replay the received frames with `axhal::replay`.
+//! - `vsock`: Enable the vsock sockets, over the VirtIO socket device.
+//! - `quic`: Enable the QUIC connections, with `axquic`.
 //!
 //! [smoltcp]: https://github.com/smoltcp-rs/smoltcp
 
@@ -24,6 +57,18 @@
 extern crate log;
 extern crate alloc;
 
+pub mod filter;
+mod mdns;
+#[cfg(feature = "quic")]
+mod quic;
+pub mod skb;
+mod sntp;
+mod syslog;
+#[cfg(feature = "tun")]
+mod tun;
+#[cfg(feature = "vsock")]
+mod vsock;
+
 cfg_if::cfg_if! {
     if #[cfg(feature = "smoltcp")] {
         mod smoltcp_impl;
@@ -31,10 +76,51 @@ cfg_if::cfg_if! {
     }
 }
 
-pub use self::net_impl::TcpSocket;
+pub use self::mdns::{
+    mdns_browse, mdns_hostname, mdns_register, mdns_responder, mdns_unregister, MdnsPeer,
+    MdnsService, MDNS_GROUP, MDNS_PORT,
+};
 pub use self::net_impl::UdpSocket;
+pub use self::net_impl::{
+    add_static_neighbor, announce_addr, arp_conflicts, flush_neighbors, neighbors,
+    remove_static_neighbor, NeighborEntry,
+};
 pub use self::net_impl::{bench_receive, bench_transmit};
-pub use self::net_impl::{dns_query, poll_interfaces};
+pub use self::net_impl::{bridge_add_port, bridge_fdb, bridge_remove_port, BridgeFdbEntry};
+pub use self::net_impl::{dns_query, interface_info, interfaces, poll_interfaces, InterfaceInfo};
+pub use self::net_impl::{nat_connections, nat_disable, nat_enable, NatEntry};
+#[cfg(feature = "ppp")]
+pub use self::net_impl::{ppp_down, ppp_status, ppp_up, PppConfig, PppPhase, PppStatus};
+pub use self::net_impl::{
+    proc_net_tcp, proc_net_udp, socket_stats, SocketProtocol, SocketState, SocketStats,
+};
+pub use self::net_impl::{
+    qos_set_dscp_class, qos_set_port_class, qos_set_rate, qos_stats, QosClassStats, QosRate,
+    TrafficClass,
+};
+pub use self::net_impl::{set_syn_backlog, set_syn_rate_limit, syn_backlog};
+#[cfg(feature = "tun")]
+pub use self::net_impl::{tap_read, tap_readable, tap_write};
+pub use self::net_impl::{vlan_add, vlan_remove, vlans, VlanInfo};
+#[cfg(feature = "wireguard")]
+pub use self::net_impl::{
+    wg_add_peer, wg_down, wg_peers, wg_public_key, wg_remove_peer, wg_up, WgConfig, WgPeerConfig,
+    WgPeerStatus,
+};
+pub use self::net_impl::{TcpKeepAlive, TcpSocket, CONNECTION_ATTEMPT_DELAY};
+#[cfg(feature = "quic")]
+pub use self::quic::{
+    quic_random, QuicClientConfig, QuicConnection, QuicListener, QuicServerConfig,
+    QuicTransportConfig, SessionTicket, StreamId,
+};
+pub use self::sntp::{sntp_client, sntp_sync, SntpSample, NTP_PORT, STEP_THRESHOLD};
+pub use self::syslog::{parse_syslog_target, syslog_client, SyslogTransport, SYSLOG_PORT};
+#[cfg(feature = "tun")]
+pub use self::tun::TunDev;
+#[cfg(feature = "vsock")]
+pub use self::vsock::{
+    poll_vsock, VsockAddr, VsockSocket, VMADDR_CID_ANY, VMADDR_CID_HOST, VMADDR_PORT_ANY,
+};
 
 use axdriver::{prelude::*, AxDeviceContainer};
 
@@ -44,5 +130,33 @@ pub fn init_network(mut net_devs: AxDeviceContainer<AxNetDevice>) {
 
     let dev = net_devs.take_one().expect("No NIC device found!");
     info!("  use NIC 0: {:?}", dev.device_name());
-    net_impl::init(dev);
+    let mut other_devs = alloc::vec::Vec::new();
+    while let Some(dev) = net_devs.take_one() {
+        info!(
+            "  NIC {} for forwarding: {:?}",
+            other_devs.len() + 1,
+            dev.device_name()
+        );
+        other_devs.push(dev);
+    }
+    net_impl::init(dev, other_devs);
+}
+
+/// Resumes the network subsystem with the NIC devices probed again, e.g.
+/// after restoring a snapshot.
+///
+/// The devices replace the ones given to [`init_network`], in the same order,
+/// and the interfaces and the sockets are kept.
+pub fn resume_network(mut net_devs: AxDeviceContainer<AxNetDevice>) {
+    let mut devs = alloc::vec::Vec::new();
+    while let Some(dev) = net_devs.take_one() {
+        devs.push(dev);
+    }
+    if devs.is_empty() {
+        warn!("No NIC device to resume the network with");
+        return;
+    }
+    info!("Resume network subsystem with {} NICs...", devs.len());
+    let dev = devs.remove(0);
+    net_impl::resume(dev, devs);
 }
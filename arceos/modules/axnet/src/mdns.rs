@@ -0,0 +1,675 @@
+//! Multicast DNS (RFC 6762) and DNS-based service discovery (RFC 6763).
+//!
+//! [`mdns_responder`] answers the queries of the LAN for `<host>.local` and
+//! for the services registered with [`mdns_register`], and announces them
+//! when they are registered. [`mdns_browse`] discovers the instances of a
+//! service type on the LAN.
+//!
+//! Names are not probed for uniqueness before being claimed, so two devices
+//! with the same host or instance name both answer for it.
+
+use alloc::{format, string::String, vec, vec::Vec};
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+use core::time::Duration;
+
+use axerrno::{ax_err, AxError, AxResult};
+use axhal::time::monotonic_time;
+use spin::Mutex;
+
+use crate::net_impl::{ipv4_addr, join_multicast_group};
+use crate::{poll_interfaces, UdpSocket};
+
+/// The mDNS port.
+pub const MDNS_PORT: u16 = 5353;
+/// The IPv4 mDNS group.
+pub const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+/// Set in the class of the records only we answer for.
+const CACHE_FLUSH: u16 = 0x8000;
+const FLAGS_RESPONSE: u16 = 0x8400;
+
+/// TTL of the records with the host name, and of the other ones.
+const HOST_TTL: u32 = 120;
+const OTHER_TTL: u32 = 4500;
+/// Maximum TTL of replies to legacy (not port 5353) queriers.
+const LEGACY_TTL: u32 = 10;
+
+const SERVICES_META: [&str; 4] = ["_services", "_dns-sd", "_udp", "local"];
+const ANNOUNCE_COUNT: usize = 2;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+const RESPONDER_INTERVAL: Duration = Duration::from_millis(20);
+const MAX_PACKET_LEN: usize = 1500;
+
+/// A service announced on the LAN.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MdnsService {
+    /// The name of the instance, e.g. `"Gateway 3"`.
+    pub instance: String,
+    /// The service type, e.g. `"_http._tcp"`.
+    pub service_type: String,
+    /// The port of the service.
+    pub port: u16,
+    /// The TXT record, as `key=value` strings.
+    pub txt: Vec<String>,
+}
+
+/// A service instance discovered on the LAN.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MdnsPeer {
+    /// The name of the instance.
+    pub instance: String,
+    /// The host name of the peer, without `.local`.
+    pub host: String,
+    /// The address of the host, if it was in the answers.
+    pub addr: Option<Ipv4Addr>,
+    /// The port of the service.
+    pub port: u16,
+    /// The TXT record.
+    pub txt: Vec<String>,
+}
+
+struct Responder {
+    hostname: String,
+    services: Vec<MdnsService>,
+    /// Announcements left to send.
+    announce: usize,
+    /// Records to send with a TTL of 0, as they are gone.
+    goodbye: Vec<MdnsService>,
+}
+
+static RESPONDER: Mutex<Responder> = Mutex::new(Responder {
+    hostname: String::new(),
+    services: Vec::new(),
+    announce: 0,
+    goodbye: Vec::new(),
+});
+
+fn labels(s: &str) -> impl Iterator<Item = &str> {
+    s.split('.').filter(|label| !label.is_empty())
+}
+
+fn same_name(a: &[String], b: &[&str]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.eq_ignore_ascii_case(b))
+}
+
+impl MdnsService {
+    fn type_name(&self) -> Vec<&str> {
+        labels(&self.service_type).chain(["local"]).collect()
+    }
+
+    fn instance_name(&self) -> Vec<&str> {
+        let mut name = vec![self.instance.as_str()];
+        name.extend(self.type_name());
+        name
+    }
+}
+
+/// Builds DNS messages, without name compression.
+struct Message {
+    buf: Vec<u8>,
+    counts: [u16; 4],
+}
+
+impl Message {
+    fn new(id: u16, flags: u16) -> Self {
+        let mut buf = Vec::with_capacity(512);
+        buf.extend_from_slice(&id.to_be_bytes());
+        buf.extend_from_slice(&flags.to_be_bytes());
+        buf.extend_from_slice(&[0; 8]);
+        Self {
+            buf,
+            counts: [0; 4],
+        }
+    }
+
+    fn put_name(&mut self, name: &[&str]) {
+        for label in name {
+            let label = &label.as_bytes()[..label.len().min(63)];
+            self.buf.push(label.len() as u8);
+            self.buf.extend_from_slice(label);
+        }
+        self.buf.push(0);
+    }
+
+    fn question(&mut self, name: &[&str], qtype: u16, qclass: u16) {
+        self.put_name(name);
+        self.buf.extend_from_slice(&qtype.to_be_bytes());
+        self.buf.extend_from_slice(&qclass.to_be_bytes());
+        self.counts[0] += 1;
+    }
+
+    /// Appends a record to section 1 (answers) or 3 (additional).
+    fn record(&mut self, section: usize, name: &[&str], rtype: u16, unique: bool, ttl: u32) {
+        self.put_name(name);
+        let class = if unique {
+            CLASS_IN | CACHE_FLUSH
+        } else {
+            CLASS_IN
+        };
+        self.buf.extend_from_slice(&rtype.to_be_bytes());
+        self.buf.extend_from_slice(&class.to_be_bytes());
+        self.buf.extend_from_slice(&ttl.to_be_bytes());
+        self.counts[section] += 1;
+    }
+
+    /// Appends the data of the record, prefixed with its length.
+    fn rdata(&mut self, f: impl FnOnce(&mut Self)) {
+        let len_pos = self.buf.len();
+        self.buf.extend_from_slice(&[0; 2]);
+        f(self);
+        let len = (self.buf.len() - len_pos - 2) as u16;
+        self.buf[len_pos..len_pos + 2].copy_from_slice(&len.to_be_bytes());
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        for (i, count) in self.counts.iter().enumerate() {
+            self.buf[4 + 2 * i..6 + 2 * i].copy_from_slice(&count.to_be_bytes());
+        }
+        self.buf
+    }
+}
+
+/// The records of the responder, written in a message.
+struct Records<'a> {
+    host: [&'a str; 2],
+    addr: Ipv4Addr,
+    /// Overrides the TTL of all the records, e.g. 0 for goodbyes.
+    ttl: Option<u32>,
+}
+
+impl Records<'_> {
+    fn ttl(&self, default: u32) -> u32 {
+        self.ttl.map_or(default, |ttl| ttl.min(default))
+    }
+
+    fn a(&self, msg: &mut Message, section: usize) {
+        msg.record(section, &self.host, TYPE_A, true, self.ttl(HOST_TTL));
+        msg.rdata(|msg| msg.buf.extend_from_slice(&self.addr.octets()));
+    }
+
+    fn ptr(&self, msg: &mut Message, section: usize, service: &MdnsService) {
+        msg.record(
+            section,
+            &service.type_name(),
+            TYPE_PTR,
+            false,
+            self.ttl(OTHER_TTL),
+        );
+        msg.rdata(|msg| msg.put_name(&service.instance_name()));
+    }
+
+    fn meta_ptr(&self, msg: &mut Message, section: usize, service: &MdnsService) {
+        msg.record(
+            section,
+            &SERVICES_META,
+            TYPE_PTR,
+            false,
+            self.ttl(OTHER_TTL),
+        );
+        msg.rdata(|msg| msg.put_name(&service.type_name()));
+    }
+
+    fn srv(&self, msg: &mut Message, section: usize, service: &MdnsService) {
+        msg.record(
+            section,
+            &service.instance_name(),
+            TYPE_SRV,
+            true,
+            self.ttl(HOST_TTL),
+        );
+        msg.rdata(|msg| {
+            // priority and weight
+            msg.buf.extend_from_slice(&[0; 4]);
+            msg.buf.extend_from_slice(&service.port.to_be_bytes());
+            msg.put_name(&self.host);
+        });
+    }
+
+    fn txt(&self, msg: &mut Message, section: usize, service: &MdnsService) {
+        msg.record(
+            section,
+            &service.instance_name(),
+            TYPE_TXT,
+            true,
+            self.ttl(OTHER_TTL),
+        );
+        msg.rdata(|msg| {
+            if service.txt.is_empty() {
+                msg.buf.push(0);
+            }
+            for s in &service.txt {
+                let s = &s.as_bytes()[..s.len().min(255)];
+                msg.buf.push(s.len() as u8);
+                msg.buf.extend_from_slice(s);
+            }
+        });
+    }
+
+    /// Writes all the records of `service`.
+    fn service(&self, msg: &mut Message, section: usize, service: &MdnsService) {
+        self.ptr(msg, section, service);
+        self.srv(msg, section, service);
+        self.txt(msg, section, service);
+    }
+}
+
+/// A resource record or question read from a message.
+struct Parsed {
+    name: Vec<String>,
+    rtype: u16,
+    class: u16,
+    /// The range of the data in the message, empty for questions.
+    rdata: core::ops::Range<usize>,
+}
+
+/// Reads a possibly compressed name at `pos`, and returns it with the
+/// position after it.
+fn read_name(msg: &[u8], mut pos: usize) -> Option<(Vec<String>, usize)> {
+    let mut name = Vec::new();
+    let mut end = None;
+    // bound the pointers followed, against loops
+    for _ in 0..64 {
+        let len = *msg.get(pos)? as usize;
+        match len {
+            0 => return Some((name, end.unwrap_or(pos + 1))),
+            0xc0.. => {
+                let target = ((len & 0x3f) << 8) | *msg.get(pos + 1)? as usize;
+                end.get_or_insert(pos + 2);
+                pos = target;
+            }
+            1..=63 => {
+                let label = msg.get(pos + 1..pos + 1 + len)?;
+                name.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + len;
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+fn read_u16(msg: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        msg.get(pos..pos + 2)?.try_into().unwrap(),
+    ))
+}
+
+/// Parses a message, and returns its ID, flags, questions and records.
+fn parse(msg: &[u8]) -> Option<(u16, u16, Vec<Parsed>, Vec<Parsed>)> {
+    let id = read_u16(msg, 0)?;
+    let flags = read_u16(msg, 2)?;
+    let counts: Vec<u16> = (0..4)
+        .map(|i| read_u16(msg, 4 + 2 * i))
+        .collect::<Option<_>>()?;
+    let mut pos = 12;
+    let mut questions = Vec::new();
+    for _ in 0..counts[0] {
+        let (name, next) = read_name(msg, pos)?;
+        let rtype = read_u16(msg, next)?;
+        let class = read_u16(msg, next + 2)?;
+        pos = next + 4;
+        questions.push(Parsed {
+            name,
+            rtype,
+            class,
+            rdata: pos..pos,
+        });
+    }
+    let mut records = Vec::new();
+    for _ in 0..counts[1] as usize + counts[2] as usize + counts[3] as usize {
+        let (name, next) = read_name(msg, pos)?;
+        let rtype = read_u16(msg, next)?;
+        let class = read_u16(msg, next + 2)?;
+        let len = read_u16(msg, next + 8)? as usize;
+        let start = next + 10;
+        if start + len > msg.len() {
+            return None;
+        }
+        pos = start + len;
+        records.push(Parsed {
+            name,
+            rtype,
+            class,
+            rdata: start..pos,
+        });
+    }
+    Some((id, flags, questions, records))
+}
+
+impl Responder {
+    fn records(&self, addr: Ipv4Addr) -> Records<'_> {
+        Records {
+            host: [self.hostname.as_str(), "local"],
+            addr,
+            ttl: None,
+        }
+    }
+
+    /// Returns a response to the questions, if we can answer any of them.
+    fn answer(&self, questions: &[Parsed], addr: Ipv4Addr, legacy: Option<u16>) -> Option<Vec<u8>> {
+        let mut records = self.records(addr);
+        let mut msg = match legacy {
+            Some(id) => {
+                records.ttl = Some(LEGACY_TTL);
+                let mut msg = Message::new(id, FLAGS_RESPONSE);
+                for q in questions {
+                    let name: Vec<&str> = q.name.iter().map(String::as_str).collect();
+                    msg.question(&name, q.rtype, q.class & !CACHE_FLUSH);
+                }
+                msg
+            }
+            None => Message::new(0, FLAGS_RESPONSE),
+        };
+        let mut answered = false;
+        let mut host_needed = false;
+        for q in questions {
+            let any = q.rtype == TYPE_ANY;
+            if same_name(&q.name, &records.host) && (any || q.rtype == TYPE_A) {
+                records.a(&mut msg, 1);
+                answered = true;
+            }
+            for service in &self.services {
+                if same_name(&q.name, &SERVICES_META) && (any || q.rtype == TYPE_PTR) {
+                    records.meta_ptr(&mut msg, 1, service);
+                    answered = true;
+                }
+                if same_name(&q.name, &service.type_name()) && (any || q.rtype == TYPE_PTR) {
+                    records.ptr(&mut msg, 1, service);
+                    records.srv(&mut msg, 3, service);
+                    records.txt(&mut msg, 3, service);
+                    answered = true;
+                    host_needed = true;
+                }
+                if same_name(&q.name, &service.instance_name()) {
+                    if any || q.rtype == TYPE_SRV {
+                        records.srv(&mut msg, 1, service);
+                        answered = true;
+                        host_needed = true;
+                    }
+                    if any || q.rtype == TYPE_TXT {
+                        records.txt(&mut msg, 1, service);
+                        answered = true;
+                    }
+                }
+            }
+        }
+        if host_needed {
+            records.a(&mut msg, 3);
+        }
+        answered.then(|| msg.finish())
+    }
+
+    /// Returns an unsolicited response with all our records, or with the
+    /// services gone and a TTL of 0.
+    fn announcement(&self, addr: Ipv4Addr, goodbye: bool) -> Vec<u8> {
+        let mut records = self.records(addr);
+        let mut msg = Message::new(0, FLAGS_RESPONSE);
+        if goodbye {
+            records.ttl = Some(0);
+            for service in &self.goodbye {
+                records.service(&mut msg, 1, service);
+            }
+        } else {
+            records.a(&mut msg, 1);
+            for service in &self.services {
+                records.service(&mut msg, 1, service);
+                records.meta_ptr(&mut msg, 1, service);
+            }
+        }
+        msg.finish()
+    }
+}
+
+/// Registers a service, and announces it if the responder runs.
+///
+/// Returns an error if an instance with the same name and type exists.
+pub fn mdns_register(service: MdnsService) -> AxResult {
+    if service.instance.is_empty() || labels(&service.service_type).count() != 2 {
+        return ax_err!(InvalidInput, "invalid mDNS service name");
+    }
+    let mut responder = RESPONDER.lock();
+    if responder.services.iter().any(|s| {
+        s.instance_name()
+            .iter()
+            .zip(service.instance_name())
+            .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }) {
+        return ax_err!(AlreadyExists, "mDNS service already registered");
+    }
+    responder.services.push(service);
+    responder.announce = ANNOUNCE_COUNT;
+    Ok(())
+}
+
+/// Unregisters a service, and announces that it is gone.
+pub fn mdns_unregister(instance: &str, service_type: &str) -> AxResult {
+    let mut responder = RESPONDER.lock();
+    match responder
+        .services
+        .iter()
+        .position(|s| s.instance == instance && s.service_type == service_type)
+    {
+        Some(i) => {
+            let service = responder.services.remove(i);
+            responder.goodbye.push(service);
+            Ok(())
+        }
+        None => ax_err!(NotFound, "mDNS service not registered"),
+    }
+}
+
+fn group_addr() -> SocketAddr {
+    SocketAddr::new(IpAddr::V4(MDNS_GROUP), MDNS_PORT)
+}
+
+fn handle_query(socket: &UdpSocket, msg: &[u8], from: SocketAddr, addr: Ipv4Addr) {
+    let Some((id, flags, questions, _)) = parse(msg) else {
+        return;
+    };
+    if flags & 0x8000 != 0 || questions.is_empty() {
+        return;
+    }
+    // queriers not on the mDNS port are plain DNS resolvers, and want a
+    // unicast reply
+    let legacy = (from.port() != MDNS_PORT).then_some(id);
+    let Some(reply) = RESPONDER.lock().answer(&questions, addr, legacy) else {
+        return;
+    };
+    let to = if legacy.is_some() { from } else { group_addr() };
+    if let Err(e) = socket.send_to(&reply, to) {
+        debug!("mDNS: failed to reply to {}: {:?}", from, e);
+    }
+}
+
+/// Answers the mDNS queries for `<hostname>.local` and the registered
+/// services, forever.
+///
+/// It is the routine of a background task.
+pub fn mdns_responder(hostname: &str) -> ! {
+    RESPONDER.lock().hostname = String::from(hostname);
+    let socket = UdpSocket::new();
+    socket.set_nonblocking(true);
+    let res = socket
+        .bind(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            MDNS_PORT,
+        ))
+        .and_then(|_| join_multicast_group(MDNS_GROUP));
+    if let Err(e) = res {
+        warn!("mDNS responder failed to start: {:?}", e);
+        loop {
+            axtask::sleep(Duration::from_secs(3600));
+        }
+    }
+    info!("mDNS: responding for {}.local", hostname);
+    RESPONDER.lock().announce = ANNOUNCE_COUNT;
+
+    let mut buf = vec![0; MAX_PACKET_LEN];
+    let mut next_announce = monotonic_time();
+    loop {
+        poll_interfaces();
+        let Some(addr) = ipv4_addr() else {
+            axtask::sleep(RESPONDER_INTERVAL);
+            continue;
+        };
+        while let Ok((len, from)) = socket.recv_from(&mut buf) {
+            handle_query(&socket, &buf[..len], from, addr);
+        }
+
+        let announcement = {
+            let mut responder = RESPONDER.lock();
+            if !responder.goodbye.is_empty() {
+                let msg = responder.announcement(addr, true);
+                responder.goodbye.clear();
+                Some(msg)
+            } else if responder.announce > 0 && monotonic_time() >= next_announce {
+                responder.announce -= 1;
+                next_announce = monotonic_time() + ANNOUNCE_INTERVAL;
+                Some(responder.announcement(addr, false))
+            } else {
+                None
+            }
+        };
+        if let Some(msg) = announcement {
+            socket.send_to(&msg, group_addr()).ok();
+        }
+        axtask::sleep(RESPONDER_INTERVAL);
+    }
+}
+
+/// Adds what a record says about `service_type` to the peers.
+fn learn(peers: &mut Vec<MdnsPeer>, type_name: &[&str], msg: &[u8], record: &Parsed) {
+    let peer_for = |peers: &mut Vec<MdnsPeer>, instance: &str| -> usize {
+        match peers.iter().position(|p| p.instance == instance) {
+            Some(i) => i,
+            None => {
+                peers.push(MdnsPeer {
+                    instance: String::from(instance),
+                    ..Default::default()
+                });
+                peers.len() - 1
+            }
+        }
+    };
+    let data = &msg[record.rdata.clone()];
+    // the records of an instance are named `<instance>.<type>.local`
+    let instance = (record.name.len() == type_name.len() + 1
+        && same_name(&record.name[1..], type_name))
+    .then(|| record.name[0].as_str());
+    match record.rtype {
+        TYPE_PTR if same_name(&record.name, type_name) => {
+            if let Some((target, _)) = read_name(msg, record.rdata.start) {
+                if target.len() == type_name.len() + 1 && same_name(&target[1..], type_name) {
+                    peer_for(peers, &target[0]);
+                }
+            }
+        }
+        TYPE_SRV => {
+            let (Some(instance), Some(port)) = (instance, read_u16(data, 4)) else {
+                return;
+            };
+            let Some((target, _)) = read_name(msg, record.rdata.start + 6) else {
+                return;
+            };
+            let i = peer_for(peers, instance);
+            peers[i].port = port;
+            peers[i].host = target.first().cloned().unwrap_or_default();
+        }
+        TYPE_TXT => {
+            let Some(instance) = instance else {
+                return;
+            };
+            let mut txt = Vec::new();
+            let mut pos = 0;
+            while let Some(&len) = data.get(pos) {
+                let Some(s) = data.get(pos + 1..pos + 1 + len as usize) else {
+                    break;
+                };
+                if !s.is_empty() {
+                    txt.push(String::from_utf8_lossy(s).into_owned());
+                }
+                pos += 1 + len as usize;
+            }
+            let i = peer_for(peers, instance);
+            peers[i].txt = txt;
+        }
+        _ => {}
+    }
+}
+
+/// Discovers the instances of `service_type` (e.g. `"_http._tcp"`) on the
+/// LAN, collecting the answers for `timeout`.
+pub fn mdns_browse(service_type: &str, timeout: Duration) -> AxResult<Vec<MdnsPeer>> {
+    let type_name: Vec<&str> = labels(service_type).chain(["local"]).collect();
+    if type_name.len() != 3 {
+        return ax_err!(InvalidInput, "invalid mDNS service type");
+    }
+    // a one-shot query from another port, answered by unicast
+    let socket = UdpSocket::new();
+    socket.set_nonblocking(true);
+    socket.bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0))?;
+    let id = axhal::misc::random() as u16;
+    let mut query = Message::new(id, 0);
+    query.question(&type_name, TYPE_PTR, CLASS_IN);
+    let query = query.finish();
+
+    let start = monotonic_time();
+    let mut sent = false;
+    let mut peers = Vec::new();
+    let mut hosts = Vec::new();
+    let mut buf = vec![0; MAX_PACKET_LEN];
+    while monotonic_time() - start < timeout {
+        poll_interfaces();
+        if !sent {
+            match socket.send_to(&query, group_addr()) {
+                Ok(_) => sent = true,
+                Err(AxError::WouldBlock) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        match socket.recv_from(&mut buf) {
+            Ok((len, _)) => {
+                let msg = &buf[..len];
+                let Some((_, flags, _, records)) = parse(msg) else {
+                    continue;
+                };
+                if flags & 0x8000 == 0 {
+                    continue;
+                }
+                for record in &records {
+                    if record.rtype == TYPE_A
+                        && record.rdata.len() == 4
+                        && record.class & 0x7fff == CLASS_IN
+                    {
+                        let ip: [u8; 4] = msg[record.rdata.clone()].try_into().unwrap();
+                        hosts.push((record.name.clone(), Ipv4Addr::from(ip)));
+                    } else {
+                        learn(&mut peers, &type_name, msg, record);
+                    }
+                }
+            }
+            Err(AxError::WouldBlock) => axtask::sleep(RESPONDER_INTERVAL),
+            Err(e) => return Err(e),
+        }
+    }
+
+    for peer in &mut peers {
+        let host = [peer.host.as_str(), "local"];
+        peer.addr = hosts
+            .iter()
+            .find(|(name, _)| same_name(name, &host))
+            .map(|&(_, ip)| ip);
+    }
+    Ok(peers)
+}
+
+/// Returns the name under which the responder announces the host.
+pub fn mdns_hostname() -> String {
+    format!("{}.local", RESPONDER.lock().hostname)
+}
@@ -0,0 +1,441 @@
+//! QUIC connections (RFC 9000) over the UDP sockets, with `axquic`.
+//!
+//! The connections are driven by the tasks using them: each call receives
+//! the datagrams waiting on the socket, fires the timers due and sends what
+//! the connection has to, then blocks the way the other sockets do until it
+//! can complete. A connection left alone for a while is to be polled with
+//! [`QuicConnection::poll`], for the acknowledgments and the keep-alives.
+//!
+//! A [`QuicListener`] shares its socket with the connections it accepts,
+//! telling the datagrams apart by their destination connection ID.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use axerrno::{ax_err, AxError, AxResult};
+use axhal::time::monotonic_time;
+use axio::PollState;
+use axquic::Connection;
+use axsync::Mutex;
+
+pub use axquic::{
+    ClientConfig as QuicClientConfig, ServerConfig as QuicServerConfig, SessionTicket, StreamId,
+    TransportConfig as QuicTransportConfig,
+};
+
+use crate::UdpSocket;
+
+/// The largest datagram received.
+const MAX_DATAGRAM_SIZE: usize = 1500;
+
+/// Fills `buf` with random bytes from [`axhal::misc::random`], as the
+/// `random` of the configurations.
+pub fn quic_random(buf: &mut [u8]) {
+    for chunk in buf.chunks_mut(16) {
+        let bytes = axhal::misc::random().to_le_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
+
+/// A connection of an [`Endpoint`].
+struct Entry {
+    conn: Connection,
+    peer: SocketAddr,
+    /// The connection IDs of the packets for it: the one it chose, then the
+    /// one of the first packets of the client, for a server.
+    cids: Vec<Vec<u8>>,
+    /// Whether a [`QuicConnection`] has it, or will once accepted.
+    owned: bool,
+}
+
+/// The UDP socket of connections, and the connections.
+struct Endpoint {
+    socket: UdpSocket,
+    /// The configuration accepting the connections, for a listener.
+    server: Option<Arc<QuicServerConfig>>,
+    conns: BTreeMap<u64, Entry>,
+    next_id: u64,
+    /// The connections accepted, not yet given by [`QuicListener::accept`].
+    incoming: VecDeque<u64>,
+}
+
+impl Endpoint {
+    fn new(socket: UdpSocket, server: Option<Arc<QuicServerConfig>>) -> Self {
+        Self {
+            socket,
+            server,
+            conns: BTreeMap::new(),
+            next_id: 0,
+            incoming: VecDeque::new(),
+        }
+    }
+
+    fn insert(&mut self, conn: Connection, peer: SocketAddr, cids: Vec<Vec<u8>>) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        let entry = Entry {
+            conn,
+            peer,
+            cids,
+            owned: true,
+        };
+        self.conns.insert(id, entry);
+        id
+    }
+
+    fn conn(&mut self, id: u64) -> &mut Connection {
+        &mut self.conns.get_mut(&id).unwrap().conn
+    }
+
+    /// Receives the datagrams waiting, fires the timers due, and sends what
+    /// the connections have to.
+    fn drive(&mut self) {
+        crate::poll_interfaces();
+        let now = monotonic_time();
+        let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((len, from)) => self.receive(&buf[..len], from, now),
+                Err(AxError::WouldBlock) => break,
+                Err(e) => {
+                    warn!("QUIC: receive failed: {:?}", e);
+                    break;
+                }
+            }
+        }
+
+        for entry in self.conns.values_mut() {
+            if entry.conn.timeout().is_some_and(|t| t <= now) {
+                entry.conn.handle_timeout(now);
+            }
+            while let Some(len) = entry.conn.poll_transmit(now, &mut buf) {
+                // a datagram not sent is lost, and recovered as such
+                if let Err(e) = self.socket.send_to(&buf[..len], entry.peer) {
+                    debug!("QUIC: send to {} failed: {:?}", entry.peer, e);
+                    break;
+                }
+            }
+        }
+        self.conns.retain(|_, e| e.owned || !e.conn.is_closed());
+        crate::poll_interfaces();
+    }
+
+    fn receive(&mut self, datagram: &[u8], from: SocketAddr, now: Duration) {
+        let Some(dcid) = axquic::destination_cid(datagram) else {
+            return;
+        };
+        if let Some(entry) = self
+            .conns
+            .values_mut()
+            .find(|e| e.cids.iter().any(|c| c == dcid))
+        {
+            entry.conn.handle_datagram(datagram, now);
+            return;
+        }
+
+        let Some(config) = self.server.clone() else {
+            return;
+        };
+        let mut reply = [0u8; MAX_DATAGRAM_SIZE];
+        if let Some(len) = axquic::version_negotiation(datagram, &mut reply) {
+            self.socket.send_to(&reply[..len], from).ok();
+        } else if let Some(conn) = Connection::accept(config, datagram, now) {
+            debug!("QUIC: connection from {}", from);
+            let cids = alloc::vec![conn.local_cid().to_vec(), dcid.to_vec()];
+            let id = self.insert(conn, from, cids);
+            // not owned until accepted, for it to go if closed before
+            self.conns.get_mut(&id).unwrap().owned = false;
+            self.incoming.push_back(id);
+        }
+    }
+}
+
+/// Runs `f` on the endpoint driven, again after yielding while it returns
+/// [`Err(WouldBlock)`](AxError::WouldBlock), unless `nonblocking`.
+fn block_on<T>(
+    endpoint: &Mutex<Endpoint>,
+    nonblocking: bool,
+    mut f: impl FnMut(&mut Endpoint) -> AxResult<T>,
+) -> AxResult<T> {
+    loop {
+        let mut ep = endpoint.lock();
+        ep.drive();
+        let res = f(&mut ep);
+        if !matches!(res, Err(AxError::WouldBlock)) {
+            // send at once what `f` gave
+            ep.drive();
+            return res;
+        }
+        drop(ep);
+        if nonblocking {
+            return res;
+        }
+        axtask::yield_now();
+    }
+}
+
+/// Fails with the error of the connection if it is closing.
+fn check_error(conn: &Connection) -> AxResult {
+    match conn.error() {
+        Some(err) => Err(err.into()),
+        None => Ok(()),
+    }
+}
+
+/// A QUIC connection, of streams.
+pub struct QuicConnection {
+    endpoint: Arc<Mutex<Endpoint>>,
+    id: u64,
+    peer: SocketAddr,
+    nonblock: AtomicBool,
+}
+
+impl QuicConnection {
+    fn new(endpoint: Arc<Mutex<Endpoint>>, id: u64, peer: SocketAddr) -> Self {
+        Self {
+            endpoint,
+            id,
+            peer,
+            nonblock: AtomicBool::new(false),
+        }
+    }
+
+    /// Connects to the server at `addr`, from a new UDP socket, resuming the
+    /// session of `ticket` if it is still valid.
+    ///
+    /// It returns once the handshake completed, or at once when the streams
+    /// can be sent on in 0-RTT packets, see
+    /// [`early_data`](QuicClientConfig::early_data).
+    pub fn connect(
+        addr: SocketAddr,
+        config: Arc<QuicClientConfig>,
+        ticket: Option<&SessionTicket>,
+    ) -> AxResult<Self> {
+        let socket = UdpSocket::new();
+        socket.set_nonblocking(true);
+        socket.bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0))?;
+
+        let conn = Connection::connect(config, ticket, monotonic_time());
+        let early = conn.is_early_data_offered();
+        let cids = alloc::vec![conn.local_cid().to_vec()];
+        let mut endpoint = Endpoint::new(socket, None);
+        let id = endpoint.insert(conn, addr, cids);
+        let this = Self::new(Arc::new(Mutex::new(endpoint)), id, addr);
+        debug!("QUIC: connecting to {}", addr);
+        block_on(&this.endpoint, false, |ep| {
+            let conn = ep.conn(id);
+            check_error(conn)?;
+            if conn.is_established() || early {
+                Ok(())
+            } else {
+                Err(AxError::WouldBlock)
+            }
+        })?;
+        Ok(this)
+    }
+
+    /// Returns the address of the peer.
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// Returns the local address of the UDP socket.
+    pub fn local_addr(&self) -> AxResult<SocketAddr> {
+        self.endpoint.lock().socket.local_addr()
+    }
+
+    /// Returns whether the connection is in nonblocking mode.
+    #[inline]
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Acquire)
+    }
+
+    /// Moves the connection into or out of nonblocking mode, in which the
+    /// calls which would wait fail with
+    /// [`Err(WouldBlock)`](AxError::WouldBlock) instead.
+    #[inline]
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Release);
+    }
+
+    fn block_on<T>(&self, mut f: impl FnMut(&mut Connection) -> AxResult<T>) -> AxResult<T> {
+        block_on(&self.endpoint, self.is_nonblocking(), |ep| {
+            f(ep.conn(self.id))
+        })
+    }
+
+    /// Opens a stream, bidirectional or not, waiting until the peer allows
+    /// it.
+    pub fn open_stream(&self, bidi: bool) -> AxResult<StreamId> {
+        self.block_on(|conn| conn.open_stream(bidi))
+    }
+
+    /// Waits for the next stream opened by the peer.
+    pub fn accept_stream(&self) -> AxResult<StreamId> {
+        self.block_on(|conn| {
+            check_error(conn)?;
+            conn.accept_stream().ok_or(AxError::WouldBlock)
+        })
+    }
+
+    /// Sends data on a stream, and returns how much was taken, waiting for
+    /// room in its send buffer.
+    pub fn send(&self, id: StreamId, buf: &[u8]) -> AxResult<usize> {
+        self.block_on(|conn| conn.stream_send(id, buf))
+    }
+
+    /// Receives data on a stream, and returns its length, 0 at its end.
+    ///
+    /// Fails with [`ConnectionReset`](AxError::ConnectionReset) once the peer
+    /// reset the stream.
+    pub fn recv(&self, id: StreamId, buf: &mut [u8]) -> AxResult<usize> {
+        self.block_on(|conn| conn.stream_recv(id, buf))
+    }
+
+    /// Ends the sending half of a stream after the data sent.
+    pub fn finish(&self, id: StreamId) -> AxResult {
+        self.block_on(|conn| conn.stream_finish(id))
+    }
+
+    /// Abandons the sending half of a stream, with the error code `code` of
+    /// the application.
+    pub fn reset(&self, id: StreamId, code: u64) -> AxResult {
+        self.block_on(|conn| conn.stream_reset(id, code))
+    }
+
+    /// Drives the connection, and returns whether [`recv`](Self::recv) on
+    /// the stream would not block.
+    pub fn poll(&self, id: StreamId) -> AxResult<PollState> {
+        let mut ep = self.endpoint.lock();
+        ep.drive();
+        let conn = ep.conn(self.id);
+        check_error(conn)?;
+        Ok(PollState {
+            readable: conn.is_readable(id),
+            writable: true,
+        })
+    }
+
+    /// Returns the last session ticket of the server, to resume the session
+    /// with [`connect`](Self::connect).
+    pub fn session_ticket(&self) -> Option<SessionTicket> {
+        let mut ep = self.endpoint.lock();
+        ep.drive();
+        ep.conn(self.id).session_ticket().cloned()
+    }
+
+    /// Returns whether the server accepted the early data of the client.
+    pub fn is_early_data_accepted(&self) -> bool {
+        self.endpoint.lock().conn(self.id).is_early_data_accepted()
+    }
+
+    /// Returns the estimate of the round-trip time.
+    pub fn rtt(&self) -> Duration {
+        self.endpoint.lock().conn(self.id).rtt()
+    }
+
+    /// Closes the connection with the error code `code` of the application,
+    /// and waits until the peer is told.
+    pub fn close(&self, code: u64, reason: &[u8]) -> AxResult {
+        let mut ep = self.endpoint.lock();
+        let conn = ep.conn(self.id);
+        if conn.error().is_none() {
+            conn.close(code, reason, monotonic_time());
+        }
+        ep.drive();
+        Ok(())
+    }
+}
+
+impl Drop for QuicConnection {
+    fn drop(&mut self) {
+        self.close(0, b"").ok();
+        let mut ep = self.endpoint.lock();
+        if let Some(entry) = ep.conns.get_mut(&self.id) {
+            // a listener keeps it until it is closed
+            entry.owned = false;
+        }
+    }
+}
+
+/// A QUIC server, accepting the connections on a UDP socket.
+pub struct QuicListener {
+    endpoint: Arc<Mutex<Endpoint>>,
+    nonblock: AtomicBool,
+}
+
+impl QuicListener {
+    /// Accepts the connections to `addr`, with `config`.
+    pub fn bind(addr: SocketAddr, config: Arc<QuicServerConfig>) -> AxResult<Self> {
+        if config.alpn.is_empty() {
+            return ax_err!(InvalidInput, "QUIC: no application protocol");
+        }
+        let socket = UdpSocket::new();
+        socket.set_nonblocking(true);
+        socket.bind(addr)?;
+        Ok(Self {
+            endpoint: Arc::new(Mutex::new(Endpoint::new(socket, Some(config)))),
+            nonblock: AtomicBool::new(false),
+        })
+    }
+
+    /// Returns the address the listener is bound to.
+    pub fn local_addr(&self) -> AxResult<SocketAddr> {
+        self.endpoint.lock().socket.local_addr()
+    }
+
+    /// Returns whether the listener is in nonblocking mode.
+    #[inline]
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Acquire)
+    }
+
+    /// Moves the listener into or out of nonblocking mode, in which
+    /// [`accept`](Self::accept) fails with
+    /// [`Err(WouldBlock)`](AxError::WouldBlock) instead of waiting.
+    #[inline]
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Release);
+    }
+
+    /// Waits for a connection, once its handshake completed or its early
+    /// data accepted.
+    ///
+    /// The connections accepted keep being driven by the listener too.
+    pub fn accept(&self) -> AxResult<QuicConnection> {
+        let (id, peer) = block_on(&self.endpoint, self.is_nonblocking(), |ep| {
+            let Endpoint {
+                conns, incoming, ..
+            } = ep;
+            incoming.retain(|id| conns.contains_key(id));
+            let pos = incoming.iter().position(|id| {
+                let conn = &conns[id].conn;
+                conn.is_established() || conn.is_early_data_accepted()
+            });
+            let id = pos
+                .and_then(|pos| incoming.remove(pos))
+                .ok_or(AxError::WouldBlock)?;
+            let entry = conns.get_mut(&id).unwrap();
+            entry.owned = true;
+            Ok((id, entry.peer))
+        })?;
+        debug!("QUIC: accepted connection from {}", peer);
+        Ok(QuicConnection::new(self.endpoint.clone(), id, peer))
+    }
+}
+
+impl Drop for QuicListener {
+    fn drop(&mut self) {
+        // the connections accepted go on, the others are dropped
+        let mut ep = self.endpoint.lock();
+        ep.server = None;
+        let incoming = core::mem::take(&mut ep.incoming);
+        for id in incoming {
+            ep.conns.remove(&id);
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! Packet buffers with room around the data, like the `sk_buff` of Linux.
+//!
+//! A [`SkBuff`] holds a packet in the middle of its buffer: a header is
+//! added in the headroom with [`SkBuff::push`] and removed with
+//! [`SkBuff::pull`], a trailer added in the tailroom with [`SkBuff::put`],
+//! all without moving the data. So a packet is encapsulated (a VLAN tag, the
+//! header of a tunnel) in the same buffer it was built in.
+//!
+//! Cloning a buffer is cheap: the clones share the data until one of them
+//! modifies it, which copies it first. The buffers of a [`SkbPool`] are
+//! given back to it when the last clone is dropped, for the next packets.
+//!
+//! ```ignore
+//! static POOL: SkbPool = SkbPool::new(2048, 64);
+//!
+//! let mut skb = POOL.alloc(DEFAULT_HEADROOM, payload.len());
+//! skb.data_mut().copy_from_slice(payload);
+//! skb.push(4).copy_from_slice(&vlan_tag);
+//! ```
+
+use alloc::sync::Arc;
+use alloc::{vec, vec::Vec};
+
+use spin::Mutex;
+
+/// The headroom of the buffers of the stack, enough for the headers of the
+/// encapsulations (Ethernet, VLAN, IPv4, UDP and a tunnel header).
+pub const DEFAULT_HEADROOM: usize = 128;
+
+/// A packet buffer with headroom and tailroom, see the
+/// [module documentation](self).
+#[derive(Clone)]
+pub struct SkBuff {
+    storage: Arc<Storage>,
+    /// The data in the buffer.
+    start: usize,
+    end: usize,
+}
+
+/// The buffer shared by the clones of a [`SkBuff`].
+struct Storage {
+    buf: Vec<u8>,
+    pool: Option<&'static SkbPool>,
+}
+
+impl Clone for Storage {
+    fn clone(&self) -> Self {
+        let mut buf = match self.pool {
+            Some(pool) => pool.take(),
+            None => Vec::new(),
+        };
+        buf.clear();
+        buf.extend_from_slice(&self.buf);
+        Self {
+            buf,
+            pool: self.pool,
+        }
+    }
+}
+
+impl Drop for Storage {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool {
+            pool.give_back(core::mem::take(&mut self.buf));
+        }
+    }
+}
+
+impl SkBuff {
+    /// Creates a buffer of `len` zero bytes, with the given headroom and
+    /// tailroom, allocated from the heap.
+    pub fn new(headroom: usize, len: usize, tailroom: usize) -> Self {
+        Self::with_storage(
+            Storage {
+                buf: vec![0; headroom + len + tailroom],
+                pool: None,
+            },
+            headroom,
+            len,
+        )
+    }
+
+    /// Creates a buffer holding a copy of `data`, with the given headroom.
+    pub fn from_slice(headroom: usize, data: &[u8]) -> Self {
+        let mut skb = Self::new(headroom, data.len(), 0);
+        skb.data_mut().copy_from_slice(data);
+        skb
+    }
+
+    fn with_storage(storage: Storage, headroom: usize, len: usize) -> Self {
+        Self {
+            storage: Arc::new(storage),
+            start: headroom,
+            end: headroom + len,
+        }
+    }
+
+    /// Returns the length of the data.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Returns whether there is no data.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Returns the free bytes before the data.
+    #[inline]
+    pub fn headroom(&self) -> usize {
+        self.start
+    }
+
+    /// Returns the free bytes after the data.
+    #[inline]
+    pub fn tailroom(&self) -> usize {
+        self.storage.buf.len() - self.end
+    }
+
+    /// Returns whether the data is shared with clones.
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.storage) > 1
+    }
+
+    /// Returns the data.
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        &self.storage.buf[self.start..self.end]
+    }
+
+    /// Returns the data to modify, after copying it if it is shared.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        let (start, end) = (self.start, self.end);
+        &mut self.buf_mut()[start..end]
+    }
+
+    /// Adds `len` bytes before the data, and returns them to write the
+    /// header in. The buffer is reallocated if the headroom is too small.
+    pub fn push(&mut self, len: usize) -> &mut [u8] {
+        self.reserve(len, 0);
+        self.start -= len;
+        let (start, end) = (self.start, self.start + len);
+        &mut self.buf_mut()[start..end]
+    }
+
+    /// Removes `len` bytes from the start of the data, and returns them, or
+    /// [`None`] if the data is shorter.
+    pub fn pull(&mut self, len: usize) -> Option<&[u8]> {
+        if len > self.len() {
+            return None;
+        }
+        self.start += len;
+        Some(&self.storage.buf[self.start - len..self.start])
+    }
+
+    /// Adds `len` bytes after the data, and returns them to write the
+    /// trailer in. The buffer is reallocated if the tailroom is too small.
+    pub fn put(&mut self, len: usize) -> &mut [u8] {
+        self.reserve(0, len);
+        self.end += len;
+        let (start, end) = (self.end - len, self.end);
+        &mut self.buf_mut()[start..end]
+    }
+
+    /// Shortens the data to `len` bytes, if it is longer.
+    pub fn trim(&mut self, len: usize) {
+        self.end = self.end.min(self.start + len);
+    }
+
+    /// Makes sure that there are at least `headroom` bytes before the data
+    /// and `tailroom` after, by moving it to a new buffer if needed.
+    pub fn reserve(&mut self, headroom: usize, tailroom: usize) {
+        if self.headroom() >= headroom && self.tailroom() >= tailroom {
+            return;
+        }
+        // as much room as asked, at least the room it already has
+        let headroom = headroom.max(self.headroom());
+        let tailroom = tailroom.max(self.tailroom());
+        let mut skb = Self::new(headroom, self.len(), tailroom);
+        skb.data_mut().copy_from_slice(self.data());
+        *self = skb;
+    }
+
+    /// Returns the buffer to modify, copying it first if it is shared.
+    fn buf_mut(&mut self) -> &mut [u8] {
+        &mut Arc::make_mut(&mut self.storage).buf
+    }
+}
+
+impl core::fmt::Debug for SkBuff {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("SkBuff")
+            .field("len", &self.len())
+            .field("headroom", &self.headroom())
+            .field("tailroom", &self.tailroom())
+            .finish()
+    }
+}
+
+/// A pool of buffers of the same size, reused by the [`SkBuff`]s allocated
+/// from it.
+pub struct SkbPool {
+    buf_size: usize,
+    /// The maximum number of free buffers kept.
+    max_free: usize,
+    free: Mutex<Vec<Vec<u8>>>,
+}
+
+impl SkbPool {
+    /// Creates a pool of buffers of `buf_size` bytes, which keeps at most
+    /// `max_free` of them free.
+    pub const fn new(buf_size: usize, max_free: usize) -> Self {
+        Self {
+            buf_size,
+            max_free,
+            free: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the size of the buffers.
+    pub fn buf_size(&self) -> usize {
+        self.buf_size
+    }
+
+    /// Returns the number of free buffers in the pool.
+    pub fn free_buffers(&self) -> usize {
+        self.free.lock().len()
+    }
+
+    /// Allocates a buffer of `len` zero bytes after `headroom` bytes, the
+    /// rest of the buffer being the tailroom.
+    ///
+    /// The buffer comes from the heap if the pool has none, and is not kept
+    /// by the pool if its size does not fit.
+    pub fn alloc(&'static self, headroom: usize, len: usize) -> SkBuff {
+        if headroom + len > self.buf_size {
+            return SkBuff::new(headroom, len, 0);
+        }
+        let mut buf = self.take();
+        buf[headroom..headroom + len].fill(0);
+        SkBuff::with_storage(
+            Storage {
+                buf,
+                pool: Some(self),
+            },
+            headroom,
+            len,
+        )
+    }
+
+    fn take(&self) -> Vec<u8> {
+        self.free
+            .lock()
+            .pop()
+            .unwrap_or_else(|| vec![0; self.buf_size])
+    }
+
+    fn give_back(&self, buf: Vec<u8>) {
+        if buf.len() != self.buf_size {
+            return;
+        }
+        let mut free = self.free.lock();
+        if free.len() < self.max_free {
+            free.push(buf);
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Software Ethernet bridge between network interfaces.
+//!
+//! The bridge learns on which port each Ethernet address is, forwards
+//! unicast frames to the port of their destination, and floods broadcast,
+//! multicast and unknown unicast frames to all the other ports.
+//!
+//! If `eth0` is a member, the network stack is attached to the bridge: it
+//! receives the frames of all the ports sent to its address (or broadcast),
+//! and its frames are sent to the other ports too.
+
+use alloc::{collections::BTreeMap, collections::VecDeque, vec::Vec};
+use core::time::Duration;
+
+use axerrno::{ax_err, AxResult};
+use axhal::time::{monotonic_time_nanos, NANOS_PER_SEC};
+use smoltcp::wire::{EthernetAddress, EthernetFrame};
+use spin::Mutex;
+
+use super::ports::{self, STACK_PORT};
+use super::{nat, neighbor};
+
+/// Time after which an address not seen is forgotten, in nanoseconds.
+const FDB_AGING_TIME: u64 = 300 * NANOS_PER_SEC;
+/// Maximum number of addresses learned.
+const FDB_MAX_ENTRIES: usize = 4096;
+/// Maximum number of frames from other ports queued for the network stack.
+const LOCAL_QUEUE_LEN: usize = 256;
+
+/// An address learned by the bridge.
+#[derive(Debug, Clone, Copy)]
+pub struct BridgeFdbEntry {
+    /// The Ethernet address.
+    pub mac: [u8; 6],
+    /// The interface it was seen on.
+    pub port: &'static str,
+    /// Time since it was last seen.
+    pub age: Duration,
+}
+
+struct FdbEntry {
+    port: usize,
+    updated: u64,
+}
+
+struct Bridge {
+    /// Bit `i` is set if port `i` is a member.
+    members: u64,
+    fdb: BTreeMap<[u8; 6], FdbEntry>,
+}
+
+impl Bridge {
+    fn is_member(&self, port: usize) -> bool {
+        port < 64 && self.members & (1 << port) != 0
+    }
+
+    fn learn(&mut self, mac: EthernetAddress, port: usize, now: u64) {
+        if !mac.is_unicast() {
+            return;
+        }
+        if self.fdb.len() >= FDB_MAX_ENTRIES && !self.fdb.contains_key(&mac.0) {
+            self.fdb
+                .retain(|_, entry| now - entry.updated < FDB_AGING_TIME);
+            if self.fdb.len() >= FDB_MAX_ENTRIES {
+                return;
+            }
+        }
+        self.fdb.insert(mac.0, FdbEntry { port, updated: now });
+    }
+
+    /// Returns the port where `mac` is, if known.
+    fn lookup(&self, mac: EthernetAddress, now: u64) -> Option<usize> {
+        let entry = self.fdb.get(&mac.0)?;
+        (now - entry.updated < FDB_AGING_TIME && self.is_member(entry.port)).then_some(entry.port)
+    }
+
+    /// Returns the ports to send a frame to `dst` to, except `from`.
+    fn out_ports(&self, dst: EthernetAddress, from: usize, now: u64) -> Vec<usize> {
+        match self.lookup(dst, now) {
+            Some(port) if port == from => Vec::new(),
+            Some(port) => alloc::vec![port],
+            None => (0..ports::num_ports())
+                .filter(|&port| port != from && self.is_member(port))
+                .collect(),
+        }
+    }
+}
+
+static BRIDGE: Mutex<Bridge> = Mutex::new(Bridge {
+    members: 0,
+    fdb: BTreeMap::new(),
+});
+
+/// Frames from other ports for the network stack.
+static LOCAL_RX: Mutex<VecDeque<Vec<u8>>> = Mutex::new(VecDeque::new());
+
+/// Adds the interface `name` to the bridge.
+pub fn bridge_add_port(name: &str) -> AxResult {
+    let port = ports::port_index(name)?;
+    if port >= 64 {
+        return ax_err!(Unsupported, "too many bridge ports");
+    }
+    if nat::is_lan_port(port) {
+        return ax_err!(ResourceBusy, "interface used by NAT");
+    }
+    #[cfg(feature = "ppp")]
+    if super::ppp::is_port(port) {
+        return ax_err!(ResourceBusy, "interface used by ppp0");
+    }
+    BRIDGE.lock().members |= 1 << port;
+    Ok(())
+}
+
+/// Removes the interface `name` from the bridge.
+pub fn bridge_remove_port(name: &str) -> AxResult {
+    let port = ports::port_index(name)?;
+    let mut bridge = BRIDGE.lock();
+    if !bridge.is_member(port) {
+        return ax_err!(NotFound, "interface not in the bridge");
+    }
+    bridge.members &= !(1 << port);
+    bridge.fdb.retain(|_, entry| entry.port != port);
+    Ok(())
+}
+
+/// Returns whether the interface at port `port` is in the bridge.
+pub(crate) fn is_member(port: usize) -> bool {
+    BRIDGE.lock().is_member(port)
+}
+
+/// Returns the addresses learned by the bridge.
+pub fn bridge_fdb() -> Vec<BridgeFdbEntry> {
+    let now = monotonic_time_nanos();
+    let mut bridge = BRIDGE.lock();
+    bridge
+        .fdb
+        .retain(|_, entry| now - entry.updated < FDB_AGING_TIME);
+    bridge
+        .fdb
+        .iter()
+        .map(|(mac, entry)| BridgeFdbEntry {
+            mac: *mac,
+            port: ports::port_name(entry.port),
+            age: Duration::from_nanos(now - entry.updated),
+        })
+        .collect()
+}
+
+/// Returns the ports to forward a frame from `from` to `dst` to, and whether
+/// the network stack should receive it.
+fn route(src: EthernetAddress, dst: EthernetAddress, from: usize) -> Option<(Vec<usize>, bool)> {
+    let now = monotonic_time_nanos();
+    let mut bridge = BRIDGE.lock();
+    if !bridge.is_member(from) {
+        return None;
+    }
+    bridge.learn(src, from, now);
+    let stack = bridge.is_member(STACK_PORT);
+    if stack && dst == ports::port_mac(STACK_PORT) {
+        Some((Vec::new(), true))
+    } else {
+        Some((bridge.out_ports(dst, from, now), stack && !dst.is_unicast()))
+    }
+}
+
+/// Processes a frame received on `port`, other than [`STACK_PORT`].
+pub(crate) fn input(port: usize, mut frame: Vec<u8>) {
+    let Ok(eth) = EthernetFrame::new_checked(&frame[..]) else {
+        return;
+    };
+    let Some((out_ports, local)) = route(eth.src_addr(), eth.dst_addr(), port) else {
+        return;
+    };
+    for &out in &out_ports {
+        ports::send(out, &frame);
+    }
+    if local && crate::filter::filter_rx(&mut frame) {
+        if let Some(reply) = neighbor::snoop_arp(&frame) {
+            ports::send(port, &reply);
+        }
+        let mut queue = LOCAL_RX.lock();
+        if queue.len() < LOCAL_QUEUE_LEN {
+            queue.push_back(frame);
+        }
+    }
+}
+
+/// Processes a frame received on [`STACK_PORT`], and returns whether the
+/// network stack should receive it.
+///
+/// Called from the receive path of the stack.
+pub(crate) fn input_stack_port(frame: &[u8]) -> bool {
+    let Ok(eth) = EthernetFrame::new_checked(frame) else {
+        return true;
+    };
+    let Some((out_ports, local)) = route(eth.src_addr(), eth.dst_addr(), STACK_PORT) else {
+        return true;
+    };
+    for &out in &out_ports {
+        ports::send(out, frame);
+    }
+    local
+}
+
+/// Forwards a frame sent by the network stack to the other ports.
+///
+/// Called from the transmit path of the stack, which sends it on
+/// [`STACK_PORT`] itself.
+pub(crate) fn output_stack_port(frame: &[u8]) {
+    let Ok(eth) = EthernetFrame::new_checked(frame) else {
+        return;
+    };
+    let now = monotonic_time_nanos();
+    let out_ports = {
+        let bridge = BRIDGE.lock();
+        if !bridge.is_member(STACK_PORT) {
+            return;
+        }
+        bridge.out_ports(eth.dst_addr(), STACK_PORT, now)
+    };
+    for &out in &out_ports {
+        ports::send(out, frame);
+    }
+}
+
+/// Takes the next frame from another port for the network stack.
+pub(crate) fn take_local() -> Option<Vec<u8>> {
+    LOCAL_RX.lock().pop_front()
+}
@@ -68,7 +68,7 @@ impl DnsSocket {
                     }
                     return Ok(res);
                 }
-                Err(AxError::WouldBlock) => axtask::yield_now(),
+                Err(AxError::WouldBlock) => super::wait_or_time_out()?,
                 Err(e) => return Err(e),
             }
         }
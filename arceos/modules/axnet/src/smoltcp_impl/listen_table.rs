@@ -1,26 +1,138 @@
-use alloc::{boxed::Box, collections::VecDeque};
+//! Listening TCP ports.
+//!
+//! Each listener has two queues:
+//!
+//! - the SYN queue, of sockets created for incoming connection requests and
+//!   still in the handshake, bounded by [`syn_backlog`];
+//! - the accept queue, of established connections waiting for `accept()`,
+//!   bounded by the backlog of the listener.
+//!
+//! Connection requests are dropped while the accept queue is full, and the
+//! clients retry. Against SYN floods, handshakes not completed within
+//! [`SYN_RECV_TIMEOUT`] are reaped, a full SYN queue evicts its oldest half-open
+//! connection, and the rate of accepted SYNs of each listener can be limited
+//! with [`set_syn_rate_limit`].
+
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
 use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
 
 use axerrno::{ax_err, AxError, AxResult};
+use axhal::time::{monotonic_time_nanos, NANOS_PER_SEC};
 use axsync::Mutex;
 use smoltcp::iface::{SocketHandle, SocketSet};
 use smoltcp::socket::tcp::{self, State};
 use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint};
 
+use super::addr::{into_core_sockaddr, UNSPECIFIED_IP};
+use super::stats::{SocketProtocol, SocketState, SocketStats};
 use super::{SocketSetWrapper, LISTEN_QUEUE_SIZE, SOCKET_SET};
 
 const PORT_NUM: usize = 65536;
 
+/// Time after which a connection still in the handshake is reaped, in
+/// nanoseconds.
+const SYN_RECV_TIMEOUT: u64 = 30 * NANOS_PER_SEC;
+
+/// Maximum length of the SYN queue of each listener.
+static SYN_BACKLOG: AtomicUsize = AtomicUsize::new(LISTEN_QUEUE_SIZE);
+/// Maximum number of SYNs accepted per second by each listener, 0 if
+/// unlimited.
+static SYN_RATE_LIMIT: AtomicU32 = AtomicU32::new(0);
+
+/// Returns the maximum length of the SYN queue of each listener.
+pub fn syn_backlog() -> usize {
+    SYN_BACKLOG.load(Ordering::Relaxed)
+}
+
+/// Sets the maximum length of the SYN queue of each listener.
+pub fn set_syn_backlog(len: usize) {
+    SYN_BACKLOG.store(len.max(1), Ordering::Relaxed);
+}
+
+/// Limits the number of SYNs accepted per second by each listener, or removes
+/// the limit with `None` (the default).
+///
+/// Bursts up to a second worth of SYNs are accepted.
+pub fn set_syn_rate_limit(rate: Option<u32>) {
+    SYN_RATE_LIMIT.store(rate.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// A connection of a listener still in the handshake.
+struct HalfOpen {
+    handle: SocketHandle,
+    created: u64,
+}
+
 struct ListenTableEntry {
     listen_endpoint: IpListenEndpoint,
-    syn_queue: VecDeque<SocketHandle>,
+    backlog: usize,
+    syn_queue: VecDeque<HalfOpen>,
+    accept_queue: VecDeque<SocketHandle>,
+    /// SYN rate limiting: SYNs left to accept, and when they were counted.
+    syn_tokens: u32,
+    syn_tokens_time: u64,
 }
 
 impl ListenTableEntry {
-    pub fn new(listen_endpoint: IpListenEndpoint) -> Self {
+    pub fn new(listen_endpoint: IpListenEndpoint, backlog: usize) -> Self {
         Self {
             listen_endpoint,
-            syn_queue: VecDeque::with_capacity(LISTEN_QUEUE_SIZE),
+            backlog,
+            syn_queue: VecDeque::new(),
+            accept_queue: VecDeque::new(),
+            syn_tokens: 0,
+            syn_tokens_time: 0,
+        }
+    }
+
+    /// Moves the established connections from the SYN queue to the accept
+    /// queue while it has room, and returns the failed or timed out
+    /// handshakes, to be freed.
+    fn update_queues(&mut self, state: impl Fn(SocketHandle) -> State) -> Vec<SocketHandle> {
+        let now = monotonic_time_nanos();
+        let mut freed = Vec::new();
+        let accept_queue = &mut self.accept_queue;
+        let backlog = self.backlog;
+        self.syn_queue
+            .retain(|half_open| match state(half_open.handle) {
+                State::Listen | State::SynReceived
+                    if now - half_open.created < SYN_RECV_TIMEOUT =>
+                {
+                    true
+                }
+                State::Listen | State::SynReceived | State::Closed => {
+                    freed.push(half_open.handle);
+                    false
+                }
+                _ if accept_queue.len() < backlog => {
+                    accept_queue.push_back(half_open.handle);
+                    false
+                }
+                // established, waiting for room in the accept queue
+                _ => true,
+            });
+        freed
+    }
+
+    /// Whether a SYN may be accepted now, as per the SYN rate limit.
+    fn take_syn_token(&mut self) -> bool {
+        let rate = SYN_RATE_LIMIT.load(Ordering::Relaxed);
+        if rate == 0 {
+            return true;
+        }
+        let now = monotonic_time_nanos();
+        let elapsed = now - self.syn_tokens_time;
+        let refill = (elapsed as u128 * rate as u128 / NANOS_PER_SEC as u128) as u64;
+        if refill > 0 {
+            self.syn_tokens = (self.syn_tokens as u64 + refill).min(rate as u64) as u32;
+            self.syn_tokens_time = now;
+        }
+        if self.syn_tokens > 0 {
+            self.syn_tokens -= 1;
+            true
+        } else {
+            false
         }
     }
 
@@ -35,7 +147,10 @@ impl ListenTableEntry {
 
 impl Drop for ListenTableEntry {
     fn drop(&mut self) {
-        for &handle in &self.syn_queue {
+        for half_open in &self.syn_queue {
+            SOCKET_SET.remove(half_open.handle);
+        }
+        for &handle in &self.accept_queue {
             SOCKET_SET.remove(handle);
         }
     }
@@ -61,12 +176,12 @@ impl ListenTable {
         self.tcp[port as usize].lock().is_none()
     }
 
-    pub fn listen(&self, listen_endpoint: IpListenEndpoint) -> AxResult {
+    pub fn listen(&self, listen_endpoint: IpListenEndpoint, backlog: usize) -> AxResult {
         let port = listen_endpoint.port;
         assert_ne!(port, 0);
         let mut entry = self.tcp[port as usize].lock();
         if entry.is_none() {
-            *entry = Some(Box::new(ListenTableEntry::new(listen_endpoint)));
+            *entry = Some(Box::new(ListenTableEntry::new(listen_endpoint, backlog)));
             Ok(())
         } else {
             ax_err!(AddrInUse, "socket listen() failed")
@@ -78,9 +193,37 @@ impl ListenTable {
         *self.tcp[port as usize].lock() = None;
     }
 
+    pub fn set_backlog(&self, port: u16, backlog: usize) {
+        if let Some(entry) = self.tcp[port as usize].lock().deref_mut() {
+            entry.backlog = backlog;
+        }
+    }
+
+    /// Returns the listening ports, with their accept queue and backlog.
+    pub fn listeners(&self) -> Vec<SocketStats> {
+        let mut listeners = Vec::new();
+        for entry in self.tcp.iter() {
+            if let Some(entry) = entry.lock().deref() {
+                let endpoint = entry.listen_endpoint;
+                let addr = endpoint.addr.unwrap_or(UNSPECIFIED_IP);
+                listeners.push(SocketStats {
+                    protocol: SocketProtocol::Tcp,
+                    state: SocketState::Listen,
+                    local_addr: into_core_sockaddr(IpEndpoint::new(addr, endpoint.port)),
+                    remote_addr: None,
+                    recv_queue: entry.accept_queue.len(),
+                    send_queue: entry.backlog,
+                    retransmits: 0,
+                });
+            }
+        }
+        listeners
+    }
+
     pub fn can_accept(&self, port: u16) -> AxResult<bool> {
-        if let Some(entry) = self.tcp[port as usize].lock().deref() {
-            Ok(entry.syn_queue.iter().any(|&handle| is_connected(handle)))
+        if let Some(entry) = self.tcp[port as usize].lock().deref_mut() {
+            free_sockets(entry.update_queues(socket_state));
+            Ok(!entry.accept_queue.is_empty())
         } else {
             ax_err!(InvalidInput, "socket accept() failed: not listen")
         }
@@ -88,23 +231,10 @@ impl ListenTable {
 
     pub fn accept(&self, port: u16) -> AxResult<(SocketHandle, (IpEndpoint, IpEndpoint))> {
         if let Some(entry) = self.tcp[port as usize].lock().deref_mut() {
-            let syn_queue = &mut entry.syn_queue;
-            let (idx, addr_tuple) = syn_queue
-                .iter()
-                .enumerate()
-                .find_map(|(idx, &handle)| {
-                    is_connected(handle).then(|| (idx, get_addr_tuple(handle)))
-                })
-                .ok_or(AxError::WouldBlock)?; // wait for connection
-            if idx > 0 {
-                warn!(
-                    "slow SYN queue enumeration: index = {}, len = {}!",
-                    idx,
-                    syn_queue.len()
-                );
-            }
-            let handle = syn_queue.swap_remove_front(idx).unwrap();
-            Ok((handle, addr_tuple))
+            free_sockets(entry.update_queues(socket_state));
+            // wait for connection
+            let handle = entry.accept_queue.pop_front().ok_or(AxError::WouldBlock)?;
+            Ok((handle, get_addr_tuple(handle)))
         } else {
             ax_err!(InvalidInput, "socket accept() failed: not listen")
         }
@@ -121,11 +251,34 @@ impl ListenTable {
                 // not listening on this address
                 return;
             }
-            if entry.syn_queue.len() >= LISTEN_QUEUE_SIZE {
-                // SYN queue is full, drop the packet
-                warn!("SYN queue overflow!");
+            let freed = entry.update_queues(|handle| sockets.get::<tcp::Socket>(handle).state());
+            for handle in freed {
+                sockets.remove(handle);
+            }
+            if entry.syn_queue.iter().any(|half_open| {
+                sockets
+                    .get::<tcp::Socket>(half_open.handle)
+                    .remote_endpoint()
+                    == Some(src)
+            }) {
+                // a retransmitted SYN, handled by the existing socket
                 return;
             }
+            if entry.accept_queue.len() >= entry.backlog {
+                // the client will retry
+                debug!("accept queue full, SYN from {} dropped", src);
+                return;
+            }
+            if !entry.take_syn_token() {
+                debug!("SYN rate limit exceeded, SYN from {} dropped", src);
+                return;
+            }
+            if entry.syn_queue.len() >= syn_backlog() {
+                // make room by evicting the oldest half-open connection
+                warn!("SYN queue overflow!");
+                let oldest = entry.syn_queue.pop_front().unwrap();
+                sockets.remove(oldest.handle);
+            }
             let mut socket = SocketSetWrapper::new_tcp_socket();
             if socket.listen(entry.listen_endpoint).is_ok() {
                 let handle = sockets.add(socket);
@@ -133,16 +286,23 @@ impl ListenTable {
                     "TCP socket {}: prepare for connection {} -> {}",
                     handle, src, entry.listen_endpoint
                 );
-                entry.syn_queue.push_back(handle);
+                entry.syn_queue.push_back(HalfOpen {
+                    handle,
+                    created: monotonic_time_nanos(),
+                });
             }
         }
     }
 }
 
-fn is_connected(handle: SocketHandle) -> bool {
-    SOCKET_SET.with_socket::<tcp::Socket, _, _>(handle, |socket| {
-        !matches!(socket.state(), State::Listen | State::SynReceived)
-    })
+fn socket_state(handle: SocketHandle) -> State {
+    SOCKET_SET.with_socket::<tcp::Socket, _, _>(handle, |socket| socket.state())
+}
+
+fn free_sockets(handles: Vec<SocketHandle>) {
+    for handle in handles {
+        SOCKET_SET.remove(handle);
+    }
 }
 
 fn get_addr_tuple(handle: SocketHandle) -> (IpEndpoint, IpEndpoint) {
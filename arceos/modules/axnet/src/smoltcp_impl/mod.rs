@@ -1,30 +1,70 @@
 mod addr;
 mod bench;
+mod bridge;
 mod dns;
 mod listen_table;
+mod nat;
+mod neighbor;
+mod ports;
+#[cfg(feature = "ppp")]
+mod ppp;
+mod qos;
+mod stats;
+#[cfg(feature = "tun")]
+mod tap;
 mod tcp;
 mod udp;
+mod vlan;
+#[cfg(feature = "wireguard")]
+mod wireguard;
 
-use alloc::vec;
+use alloc::{vec, vec::Vec};
 use core::cell::RefCell;
 use core::ops::DerefMut;
 
+use axdriver::net_ext::{ETHERNET_HEADER_LEN, STANDARD_MTU};
 use axdriver::prelude::*;
 use axdriver_net::{DevError, NetBufPtr};
-use axhal::time::{wall_time_nanos, NANOS_PER_MICROS};
+use axerrno::{ax_err, AxError, AxResult};
+use axhal::time::{monotonic_time_nanos, NANOS_PER_MICROS};
 use axsync::Mutex;
 use lazyinit::LazyInit;
 use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
 use smoltcp::phy::{Device, DeviceCapabilities, Medium, RxToken, TxToken};
 use smoltcp::socket::{self, AnySocket};
 use smoltcp::time::Instant;
-use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr};
+use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr, Ipv4Address};
 
 use self::listen_table::ListenTable;
+use crate::skb::{SkbPool, DEFAULT_HEADROOM};
 
+pub use self::bridge::{bridge_add_port, bridge_fdb, bridge_remove_port, BridgeFdbEntry};
 pub use self::dns::dns_query;
-pub use self::tcp::TcpSocket;
+pub use self::listen_table::{set_syn_backlog, set_syn_rate_limit, syn_backlog};
+pub use self::nat::{nat_connections, nat_disable, nat_enable, NatEntry};
+pub use self::neighbor::{
+    add_static_neighbor, arp_conflicts, neighbors, remove_static_neighbor, NeighborEntry,
+};
+pub use self::ports::{interface_info, interfaces, InterfaceInfo};
+#[cfg(feature = "ppp")]
+pub use self::ppp::{ppp_down, ppp_status, ppp_up, PppConfig, PppPhase, PppStatus};
+pub use self::qos::{
+    qos_set_dscp_class, qos_set_port_class, qos_set_rate, qos_stats, QosClassStats, QosRate,
+    TrafficClass,
+};
+pub use self::stats::{
+    proc_net_tcp, proc_net_udp, socket_stats, SocketProtocol, SocketState, SocketStats,
+};
+#[cfg(feature = "tun")]
+pub use self::tap::{tap_read, tap_readable, tap_write};
+pub use self::tcp::{TcpKeepAlive, TcpSocket, CONNECTION_ATTEMPT_DELAY};
 pub use self::udp::UdpSocket;
+pub use self::vlan::{vlan_add, vlan_remove, vlans, VlanInfo};
+#[cfg(feature = "wireguard")]
+pub use self::wireguard::{
+    wg_add_peer, wg_down, wg_peers, wg_public_key, wg_remove_peer, wg_up, WgConfig, WgPeerConfig,
+    WgPeerStatus,
+};
 
 macro_rules! env_or_default {
     ($key:literal) => {
@@ -39,8 +79,13 @@ const IP: &str = env_or_default!("AX_IP");
 const GATEWAY: &str = env_or_default!("AX_GW");
 const DNS_SEVER: &str = "8.8.8.8";
 const IP_PREFIX: u8 = 24;
+/// The MTU asked of the NICs, the standard one if empty. Each of them takes
+/// the largest it supports up to it, e.g. 9000 for jumbo frames.
+const MTU: &str = env_or_default!("AX_MTU");
 
-const STANDARD_MTU: usize = 1500;
+/// The size of the buffers of [`SKB_POOL`]: a frame, its headroom and the
+/// trailer of a tunnel.
+const SKB_BUF_SIZE: usize = 2048;
 
 const RANDOM_SEED: u64 = 0xA2CE_05A2_CE05_A2CE;
 
@@ -53,6 +98,8 @@ const LISTEN_QUEUE_SIZE: usize = 512;
 static LISTEN_TABLE: LazyInit<ListenTable> = LazyInit::new();
 static SOCKET_SET: LazyInit<SocketSetWrapper> = LazyInit::new();
 static ETH0: LazyInit<InterfaceWrapper> = LazyInit::new();
+/// The buffers of the frames sent by the stack outside of the device.
+static SKB_POOL: SkbPool = SkbPool::new(SKB_BUF_SIZE, 64);
 
 struct SocketSetWrapper<'a>(Mutex<SocketSet<'a>>);
 
@@ -120,7 +167,16 @@ impl<'a> SocketSetWrapper<'a> {
     }
 
     pub fn poll_interfaces(&self) {
+        ports::poll();
         ETH0.poll(&self.0);
+        #[cfg(feature = "wireguard")]
+        if wireguard::poll() {
+            ETH0.poll(&self.0);
+        }
+        #[cfg(feature = "ppp")]
+        if ppp::poll() {
+            ETH0.poll(&self.0);
+        }
     }
 
     pub fn remove(&self, handle: SocketHandle) {
@@ -145,7 +201,7 @@ impl InterfaceWrapper {
     }
 
     fn current_time() -> Instant {
-        Instant::from_micros_const((wall_time_nanos() / NANOS_PER_MICROS) as i64)
+        Instant::from_micros_const((monotonic_time_nanos() / NANOS_PER_MICROS) as i64)
     }
 
     pub fn name(&self) -> &str {
@@ -161,6 +217,10 @@ impl InterfaceWrapper {
         iface.update_ip_addrs(|ip_addrs| {
             ip_addrs.push(IpCidr::new(ip, prefix_len)).unwrap();
         });
+        let IpAddress::Ipv4(v4) = ip;
+        if let Some(frame) = neighbor::set_local_addr(v4, self.ether_addr) {
+            self.dev.lock().send_frame(&frame);
+        }
     }
 
     pub fn setup_gateway(&self, gateway: IpAddress) {
@@ -175,7 +235,77 @@ impl InterfaceWrapper {
         let mut iface = self.iface.lock();
         let mut sockets = sockets.lock();
         let timestamp = Self::current_time();
+        neighbor::refresh_static(false);
         iface.poll(timestamp, dev.deref_mut(), &mut sockets);
+        qos::flush(&mut dev.inner.borrow_mut());
+    }
+
+    /// Forgets the neighbors learned by smoltcp and by [`neighbor`], keeping
+    /// the static entries.
+    ///
+    /// smoltcp cannot clear its neighbor cache, so the interface is rebuilt
+    /// with the same addresses and routes. Sockets are not affected.
+    pub fn flush_neighbors(&self) {
+        let mut dev = self.dev.lock();
+        let mut iface = self.iface.lock();
+        let ip_addrs: Vec<IpCidr> = iface.ip_addrs().to_vec();
+        let mut routes = None;
+        iface.routes_mut().update(|r| routes = Some(r.clone()));
+
+        let mut config = Config::new(HardwareAddress::Ethernet(self.ether_addr));
+        config.random_seed = RANDOM_SEED;
+        *iface = Interface::new(config, dev.deref_mut(), Self::current_time());
+        iface.update_ip_addrs(|addrs| addrs.extend(ip_addrs));
+        if let Some(routes) = routes {
+            iface.routes_mut().update(|r| *r = routes);
+        }
+        neighbor::flush_learned();
+        neighbor::refresh_static(true);
+    }
+
+    pub fn add_ip_addr(&self, cidr: IpCidr) -> AxResult {
+        let mut res = Ok(());
+        self.iface.lock().update_ip_addrs(|ip_addrs| {
+            if ip_addrs.push(cidr).is_err() {
+                res = ax_err!(NoMemory, "too many addresses on the interface");
+            }
+        });
+        res
+    }
+
+    pub fn remove_ip_addr(&self, cidr: IpCidr) {
+        self.iface
+            .lock()
+            .update_ip_addrs(|ip_addrs| ip_addrs.retain(|addr| *addr != cidr));
+    }
+
+    /// Joins a multicast group, reporting it by IGMP.
+    pub fn join_multicast_group(&self, addr: Ipv4Address) -> AxResult {
+        let mut dev = self.dev.lock();
+        let mut iface = self.iface.lock();
+        let timestamp = Self::current_time();
+        iface
+            .join_multicast_group(dev.deref_mut(), addr, timestamp)
+            .map(|_| ())
+            .map_err(|e| {
+                warn!("failed to join multicast group {}: {:?}", addr, e);
+                AxError::NoMemory
+            })
+    }
+
+    pub fn ipv4_addr(&self) -> Option<Ipv4Address> {
+        self.iface.lock().ipv4_addr()
+    }
+
+    pub fn ip_addrs(&self) -> Vec<IpCidr> {
+        self.iface.lock().ip_addrs().to_vec()
+    }
+
+    /// Sends a gratuitous ARP announcing the local address.
+    pub fn announce(&self) {
+        if let Some(frame) = neighbor::announcement() {
+            self.dev.lock().send_frame(&frame);
+        }
     }
 }
 
@@ -185,6 +315,56 @@ impl DeviceWrapper {
             inner: RefCell::new(inner),
         }
     }
+
+    fn send_frame(&self, frame: &[u8]) {
+        send_frame(&mut self.inner.borrow_mut(), frame);
+    }
+}
+
+/// Transmits a frame built outside of smoltcp, from its buffer if the device
+/// gathers the segments of the frames.
+fn send_frame(dev: &mut AxNetDevice, frame: &[u8]) {
+    if let Err(e) = dev.transmit_segments(&[frame]) {
+        warn!("failed to send frame: {:?}", e);
+    }
+}
+
+/// Sets the MTU of a NIC, see [`MTU`].
+fn negotiate_mtu(name: &str, dev: &mut AxNetDevice) {
+    let wanted = match MTU {
+        "" => STANDARD_MTU,
+        mtu => mtu.parse().expect("invalid MTU"),
+    };
+    match dev.negotiate_mtu(wanted) {
+        Ok(mtu) if mtu != wanted => warn!("{}: MTU {} not supported, using {}", name, wanted, mtu),
+        Ok(_) => {}
+        Err(e) => warn!("{}: failed to set the MTU: {:?}", name, e),
+    }
+}
+
+/// Receives a frame from the device, or when replaying (see
+/// [`axhal::replay`]), the one received then.
+fn receive_frame(dev: &mut AxNetDevice) -> Option<RxBuf> {
+    #[cfg(feature = "replay")]
+    if axhal::replay::is_replaying() {
+        let mut frame = [0; axhal::replay::MAX_INPUT_SIZE];
+        let len = axhal::replay::replay_net_rx(&mut frame)?;
+        return Some(RxBuf::Injected(frame[..len].to_vec()));
+    }
+    let rx_buf = match dev.receive() {
+        Ok(buf) => buf,
+        Err(err) => {
+            if !matches!(err, DevError::Again) {
+                warn!("receive failed: {:?}", err);
+            }
+            #[cfg(feature = "replay")]
+            axhal::replay::record_net_rx(None);
+            return None;
+        }
+    };
+    #[cfg(feature = "replay")]
+    axhal::replay::record_net_rx(Some(rx_buf.packet()));
+    Some(RxBuf::Device(rx_buf))
 }
 
 impl Device for DeviceWrapper {
@@ -201,13 +381,42 @@ impl Device for DeviceWrapper {
         if !dev.can_transmit() {
             return None;
         }
-        let rx_buf = match dev.receive() {
-            Ok(buf) => buf,
-            Err(err) => {
-                if !matches!(err, DevError::Again) {
-                    warn!("receive failed: {:?}", err);
+        let injected = neighbor::take_injected().or_else(bridge::take_local);
+        #[cfg(feature = "wireguard")]
+        let injected = injected.or_else(wireguard::take_inbound);
+        #[cfg(feature = "ppp")]
+        let injected = injected.or_else(ppp::take_inbound);
+        if let Some(frame) = injected {
+            let rx_buf = RxBuf::Injected(frame);
+            return Some((AxNetRxToken(&self.inner, rx_buf), AxNetTxToken(&self.inner)));
+        }
+        let rx_buf = loop {
+            let mut rx_buf = receive_frame(&mut dev)?;
+            match vlan::input(rx_buf.packet()) {
+                vlan::VlanInput::Pass => {}
+                input => {
+                    if let RxBuf::Device(rx_buf) = rx_buf {
+                        dev.recycle_rx_buffer(rx_buf).unwrap();
+                    }
+                    let vlan::VlanInput::Untagged(frame) = input else {
+                        continue;
+                    };
+                    rx_buf = RxBuf::Injected(frame);
+                }
+            }
+            if crate::filter::filter_rx(rx_buf.packet_mut())
+                && !nat::from_wan(rx_buf.packet_mut())
+                && bridge::input_stack_port(rx_buf.packet())
+            {
+                if let Some(reply) = neighbor::snoop_arp(rx_buf.packet()) {
+                    send_frame(&mut dev, &reply);
                 }
-                return None;
+                break rx_buf;
+            }
+            // dropped or redirected by a filter, translated by the NAT, or
+            // only forwarded by the bridge
+            if let RxBuf::Device(rx_buf) = rx_buf {
+                dev.recycle_rx_buffer(rx_buf).unwrap();
             }
         };
         Some((AxNetRxToken(&self.inner, rx_buf), AxNetTxToken(&self.inner)))
@@ -228,14 +437,43 @@ impl Device for DeviceWrapper {
 
     fn capabilities(&self) -> DeviceCapabilities {
         let mut caps = DeviceCapabilities::default();
-        caps.max_transmission_unit = 1514;
+        caps.max_transmission_unit = self.inner.borrow().mtu() + ETHERNET_HEADER_LEN;
+        // what fits in a PPPoE session, the stack cannot tell the packets
+        // going through it apart
+        #[cfg(feature = "ppp")]
+        if ppp::is_up() {
+            caps.max_transmission_unit = ppp::PPPOE_MTU + 14;
+        }
         caps.max_burst_size = None;
         caps.medium = Medium::Ethernet;
         caps
     }
 }
 
-struct AxNetRxToken<'a>(&'a RefCell<AxNetDevice>, NetBufPtr);
+/// A received frame: from the device, or from another source (e.g. synthesized
+/// by [`neighbor`]).
+enum RxBuf {
+    Device(NetBufPtr),
+    Injected(Vec<u8>),
+}
+
+impl RxBuf {
+    fn packet(&self) -> &[u8] {
+        match self {
+            Self::Device(buf) => buf.packet(),
+            Self::Injected(frame) => frame,
+        }
+    }
+
+    fn packet_mut(&mut self) -> &mut [u8] {
+        match self {
+            Self::Device(buf) => buf.packet_mut(),
+            Self::Injected(frame) => frame,
+        }
+    }
+}
+
+struct AxNetRxToken<'a>(&'a RefCell<AxNetDevice>, RxBuf);
 struct AxNetTxToken<'a>(&'a RefCell<AxNetDevice>);
 
 impl<'a> RxToken for AxNetRxToken<'a> {
@@ -250,11 +488,13 @@ impl<'a> RxToken for AxNetRxToken<'a> {
         let mut rx_buf = self.1;
         trace!(
             "RECV {} bytes: {:02X?}",
-            rx_buf.packet_len(),
+            rx_buf.packet().len(),
             rx_buf.packet()
         );
         let result = f(rx_buf.packet_mut());
-        self.0.borrow_mut().recycle_rx_buffer(rx_buf).unwrap();
+        if let RxBuf::Device(rx_buf) = rx_buf {
+            self.0.borrow_mut().recycle_rx_buffer(rx_buf).unwrap();
+        }
         result
     }
 }
@@ -265,9 +505,38 @@ impl<'a> TxToken for AxNetTxToken<'a> {
         F: FnOnce(&mut [u8]) -> R,
     {
         let mut dev = self.0.borrow_mut();
+        #[cfg(feature = "wireguard")]
+        let tunnel = wireguard::is_up();
+        #[cfg(not(feature = "wireguard"))]
+        let tunnel = false;
+        #[cfg(feature = "ppp")]
+        let tunnel = tunnel || ppp::is_up();
+        if tunnel || qos::is_enabled() || vlan::is_enabled() {
+            // build the frame aside, as a buffer of the device cannot be
+            // given back if the frame goes through the tunnel, cannot wait
+            // in the queues, and has no room for a VLAN tag
+            let mut frame = SKB_POOL.alloc(DEFAULT_HEADROOM, len);
+            let ret = f(frame.data_mut());
+            #[cfg(feature = "wireguard")]
+            if wireguard::output(&frame) {
+                return ret;
+            }
+            #[cfg(feature = "ppp")]
+            if ppp::output(&frame) {
+                return ret;
+            }
+            trace!("SEND {} bytes: {:02X?}", len, frame.data());
+            stats::inspect_tx(frame.data());
+            vlan::output(&mut frame);
+            bridge::output_stack_port(frame.data());
+            qos::transmit(&mut dev, frame);
+            return ret;
+        }
         let mut tx_buf = dev.alloc_tx_buffer(len).unwrap();
         let ret = f(tx_buf.packet_mut());
         trace!("SEND {} bytes: {:02X?}", len, tx_buf.packet());
+        stats::inspect_tx(tx_buf.packet());
+        bridge::output_stack_port(tx_buf.packet());
         dev.transmit(tx_buf).unwrap();
         ret
     }
@@ -292,6 +561,17 @@ fn snoop_tcp_packet(buf: &[u8], sockets: &mut SocketSet<'_>) -> Result<(), smolt
     Ok(())
 }
 
+/// Gives up the CPU while a blocking socket operation waits, or fails with
+/// `TimedOut` if the time budget of the current task is exhausted (see
+/// [`axtask::with_timeout`]).
+fn wait_or_time_out() -> AxResult {
+    if axtask::current_deadline().is_expired() {
+        return ax_err!(TimedOut, "socket operation timed out");
+    }
+    axtask::yield_now();
+    Ok(())
+}
+
 /// Poll the network stack.
 ///
 /// It may receive packets from the NIC and process them, and transmit queued
@@ -300,6 +580,27 @@ pub fn poll_interfaces() {
     SOCKET_SET.poll_interfaces();
 }
 
+/// Forgets the learned neighbors (ARP entries), keeping the static ones.
+pub fn flush_neighbors() {
+    ETH0.flush_neighbors();
+}
+
+/// Sends a gratuitous ARP announcing the local address, e.g. after taking it
+/// over from another host.
+pub fn announce_addr() {
+    ETH0.announce();
+}
+
+/// Joins an IPv4 multicast group on `eth0`.
+pub(crate) fn join_multicast_group(addr: core::net::Ipv4Addr) -> AxResult {
+    ETH0.join_multicast_group(Ipv4Address(addr.octets()))
+}
+
+/// Returns the IPv4 address of `eth0`.
+pub(crate) fn ipv4_addr() -> Option<core::net::Ipv4Addr> {
+    ETH0.ipv4_addr().map(|addr| addr.0.into())
+}
+
 /// Benchmark raw socket transmit bandwidth.
 pub fn bench_transmit() {
     ETH0.dev.lock().bench_transmit_bandwidth();
@@ -310,7 +611,19 @@ pub fn bench_receive() {
     ETH0.dev.lock().bench_receive_bandwidth();
 }
 
-pub(crate) fn init(net_dev: AxNetDevice) {
+pub(crate) fn resume(mut net_dev: AxNetDevice, other_devs: Vec<AxNetDevice>) {
+    let Some(eth0) = ETH0.get() else {
+        return;
+    };
+    negotiate_mtu(eth0.name, &mut net_dev);
+    let old = eth0.dev.lock().inner.replace(net_dev);
+    // dropping it would reset the queues, now used by `net_dev`
+    core::mem::forget(old);
+    ports::resume(other_devs);
+}
+
+pub(crate) fn init(mut net_dev: AxNetDevice, other_devs: Vec<AxNetDevice>) {
+    negotiate_mtu("eth0", &mut net_dev);
     let ether_addr = EthernetAddress(net_dev.mac_address().0);
     let eth0 = InterfaceWrapper::new("eth0", net_dev, ether_addr);
 
@@ -318,13 +631,17 @@ pub(crate) fn init(net_dev: AxNetDevice) {
     let gateway = GATEWAY.parse().expect("invalid gateway IP address");
     eth0.setup_ip_addr(ip, IP_PREFIX);
     eth0.setup_gateway(gateway);
+    let (IpAddress::Ipv4(ip_v4), IpAddress::Ipv4(gateway_v4)) = (ip, gateway);
+    nat::set_wan(ip_v4, IP_PREFIX, gateway_v4);
 
     ETH0.init_once(eth0);
     SOCKET_SET.init_once(SocketSetWrapper::new());
     LISTEN_TABLE.init_once(ListenTable::new());
+    ports::init(other_devs);
 
     info!("created net interface {:?}:", ETH0.name());
     info!("  ether:    {}", ETH0.ethernet_address());
+    info!("  mtu:      {}", ETH0.dev.lock().inner.borrow().mtu());
     info!("  ip:       {}/{}", ip, IP_PREFIX);
     info!("  gateway:  {}", gateway);
 }
@@ -0,0 +1,476 @@
+//! Network address and port translation (NAPT) between a LAN interface and
+//! `eth0`.
+//!
+//! Once enabled with [`nat_enable`], the hosts on the LAN interface use its
+//! address as their gateway. Their TCP, UDP and ICMP echo traffic leaves
+//! through `eth0` with the address of the network stack, from a port (or
+//! ICMP identifier) allocated for each connection, and the replies are
+//! translated back. The other traffic of the LAN is dropped.
+//!
+//! Connections are forgotten after some idle time, shorter once a TCP
+//! connection is closing. IP fragments are not translated.
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::net::Ipv4Addr;
+use core::time::Duration;
+
+use axchecksum::{internet_checksum, InternetChecksum};
+use axerrno::{ax_err, AxResult};
+use axhal::time::{monotonic_time_nanos, NANOS_PER_SEC};
+use smoltcp::wire::{
+    ArpOperation, ArpPacket, ArpRepr, EthernetAddress, EthernetFrame, EthernetProtocol,
+    Icmpv4Message, Icmpv4Packet, IpProtocol, Ipv4Address, Ipv4Packet, TcpPacket, UdpPacket,
+};
+use spin::Mutex;
+
+use super::ports::{self, STACK_PORT};
+use super::{bridge, neighbor};
+
+/// The external ports (and ICMP identifiers) of the translated connections,
+/// below the ephemeral ports of the network stack.
+const PORT_START: u16 = 0x8000;
+const PORT_END: u16 = 0xbfff;
+
+/// Idle time after which a connection is forgotten, in nanoseconds.
+const TCP_TIMEOUT: u64 = 7440 * NANOS_PER_SEC;
+const TCP_CLOSING_TIMEOUT: u64 = 10 * NANOS_PER_SEC;
+const UDP_TIMEOUT: u64 = 60 * NANOS_PER_SEC;
+const ICMP_TIMEOUT: u64 = 30 * NANOS_PER_SEC;
+
+/// A connection translated by the NAT.
+#[derive(Debug, Clone, Copy)]
+pub struct NatEntry {
+    /// The transport protocol number: 1 (ICMP), 6 (TCP) or 17 (UDP).
+    pub protocol: u8,
+    /// The address and port (or ICMP identifier) of the LAN host.
+    pub lan: (Ipv4Addr, u16),
+    /// The address and port of the remote host, port 0 for ICMP.
+    pub remote: (Ipv4Addr, u16),
+    /// The port (or ICMP identifier) used on `eth0`.
+    pub external_port: u16,
+    /// Time since the connection was last used.
+    pub idle: Duration,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct ConnKey {
+    protocol: u8,
+    lan: ([u8; 4], u16),
+    remote: ([u8; 4], u16),
+}
+
+struct Conn {
+    key: ConnKey,
+    lan_mac: EthernetAddress,
+    updated: u64,
+    closing: bool,
+}
+
+impl Conn {
+    fn is_expired(&self, now: u64) -> bool {
+        let timeout = match IpProtocol::from(self.key.protocol) {
+            IpProtocol::Tcp if self.closing => TCP_CLOSING_TIMEOUT,
+            IpProtocol::Tcp => TCP_TIMEOUT,
+            IpProtocol::Udp => UDP_TIMEOUT,
+            _ => ICMP_TIMEOUT,
+        };
+        now - self.updated >= timeout
+    }
+}
+
+struct Wan {
+    ip: Ipv4Address,
+    prefix_len: u8,
+    gateway: Ipv4Address,
+}
+
+struct Lan {
+    port: usize,
+    ip: Ipv4Address,
+    prefix_len: u8,
+}
+
+struct Nat {
+    wan: Option<Wan>,
+    lan: Option<Lan>,
+    /// Connections by protocol and external port.
+    conns: BTreeMap<(u8, u16), Conn>,
+    /// External ports by connection.
+    ports: BTreeMap<ConnKey, u16>,
+    next_port: u16,
+}
+
+static NAT: Mutex<Nat> = Mutex::new(Nat {
+    wan: None,
+    lan: None,
+    conns: BTreeMap::new(),
+    ports: BTreeMap::new(),
+    next_port: PORT_START,
+});
+
+fn in_subnet(ip: Ipv4Address, net: Ipv4Address, prefix_len: u8) -> bool {
+    let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+    u32::from_be_bytes(ip.0) & mask == u32::from_be_bytes(net.0) & mask
+}
+
+impl Nat {
+    /// Returns the external port of the connection `key`, allocating one if
+    /// it is new.
+    fn external_port(&mut self, key: ConnKey, lan_mac: EthernetAddress, now: u64) -> Option<u16> {
+        if let Some(&port) = self.ports.get(&key) {
+            let conn = self.conns.get_mut(&(key.protocol, port)).unwrap();
+            conn.lan_mac = lan_mac;
+            conn.updated = now;
+            return Some(port);
+        }
+        self.expire(now);
+        let num_ports = (PORT_END - PORT_START) as usize + 1;
+        for _ in 0..num_ports {
+            let port = self.next_port;
+            self.next_port = if port == PORT_END {
+                PORT_START
+            } else {
+                port + 1
+            };
+            if let alloc::collections::btree_map::Entry::Vacant(slot) =
+                self.conns.entry((key.protocol, port))
+            {
+                slot.insert(Conn {
+                    key,
+                    lan_mac,
+                    updated: now,
+                    closing: false,
+                });
+                self.ports.insert(key, port);
+                return Some(port);
+            }
+        }
+        warn!("NAT: no external port left");
+        None
+    }
+
+    fn expire(&mut self, now: u64) {
+        let ports = &mut self.ports;
+        self.conns.retain(|_, conn| {
+            let expired = conn.is_expired(now);
+            if expired {
+                ports.remove(&conn.key);
+            }
+            !expired
+        });
+    }
+}
+
+/// Sets the addresses of `eth0`, the external side of the NAT.
+pub(crate) fn set_wan(ip: Ipv4Address, prefix_len: u8, gateway: Ipv4Address) {
+    NAT.lock().wan = Some(Wan {
+        ip,
+        prefix_len,
+        gateway,
+    });
+}
+
+/// Enables the NAT from the interface `lan`, of address `ip/prefix_len`, to
+/// `eth0`.
+///
+/// The interface must not be `eth0` or in the bridge.
+pub fn nat_enable(lan: &str, ip: Ipv4Addr, prefix_len: u8) -> AxResult {
+    let port = ports::port_index(lan)?;
+    if port == STACK_PORT || prefix_len > 32 {
+        return ax_err!(InvalidInput, "invalid NAT interface");
+    }
+    if bridge::is_member(port) {
+        return ax_err!(ResourceBusy, "interface used by the bridge");
+    }
+    #[cfg(feature = "ppp")]
+    if super::ppp::is_port(port) {
+        return ax_err!(ResourceBusy, "interface used by ppp0");
+    }
+    let mut nat = NAT.lock();
+    if nat.wan.is_none() {
+        return ax_err!(BadState, "network stack not initialized");
+    }
+    nat.lan = Some(Lan {
+        port,
+        ip: Ipv4Address(ip.octets()),
+        prefix_len,
+    });
+    nat.conns.clear();
+    nat.ports.clear();
+    Ok(())
+}
+
+/// Disables the NAT, and forgets its connections.
+pub fn nat_disable() {
+    let mut nat = NAT.lock();
+    nat.lan = None;
+    nat.conns.clear();
+    nat.ports.clear();
+}
+
+/// Returns the connections translated by the NAT.
+pub fn nat_connections() -> Vec<NatEntry> {
+    let now = monotonic_time_nanos();
+    let mut nat = NAT.lock();
+    nat.expire(now);
+    nat.conns
+        .iter()
+        .map(|(&(protocol, external_port), conn)| NatEntry {
+            protocol,
+            lan: (Ipv4Addr::from(conn.key.lan.0), conn.key.lan.1),
+            remote: (Ipv4Addr::from(conn.key.remote.0), conn.key.remote.1),
+            external_port,
+            idle: Duration::from_nanos(now - conn.updated),
+        })
+        .collect()
+}
+
+/// Whether `port` is the LAN interface of the NAT.
+pub(crate) fn is_lan_port(port: usize) -> bool {
+    NAT.lock().lan.as_ref().is_some_and(|lan| lan.port == port)
+}
+
+/// The ports of a TCP or UDP packet, or the identifier of an ICMP echo
+/// message (as both ports), and whether it closes a TCP connection.
+fn transport_ports(ip: &Ipv4Packet<&mut [u8]>, echo: Icmpv4Message) -> Option<(u16, u16, bool)> {
+    match ip.next_header() {
+        IpProtocol::Tcp => {
+            let tcp = TcpPacket::new_checked(ip.payload()).ok()?;
+            Some((tcp.src_port(), tcp.dst_port(), tcp.fin() || tcp.rst()))
+        }
+        IpProtocol::Udp => {
+            let udp = UdpPacket::new_checked(ip.payload()).ok()?;
+            Some((udp.src_port(), udp.dst_port(), false))
+        }
+        IpProtocol::Icmp => {
+            let icmp = Icmpv4Packet::new_checked(ip.payload()).ok()?;
+            (icmp.msg_type() == echo).then(|| (icmp.echo_ident(), icmp.echo_ident(), false))
+        }
+        _ => None,
+    }
+}
+
+/// Rewrites the source (if `source` is set) or destination address and port
+/// of a packet, decrements its TTL, and updates its checksums.
+fn rewrite(ip: &mut Ipv4Packet<&mut [u8]>, addr: Ipv4Address, port: u16, source: bool) {
+    if source {
+        ip.set_src_addr(addr);
+    } else {
+        ip.set_dst_addr(addr);
+    }
+    ip.set_hop_limit(ip.hop_limit() - 1);
+    match ip.next_header() {
+        IpProtocol::Tcp => {
+            let mut tcp = TcpPacket::new_unchecked(ip.payload_mut());
+            if source {
+                tcp.set_src_port(port);
+            } else {
+                tcp.set_dst_port(port);
+            }
+        }
+        IpProtocol::Udp => {
+            let mut udp = UdpPacket::new_unchecked(ip.payload_mut());
+            if source {
+                udp.set_src_port(port);
+            } else {
+                udp.set_dst_port(port);
+            }
+        }
+        _ => Icmpv4Packet::new_unchecked(ip.payload_mut()).set_echo_ident(port),
+    }
+    fill_checksums(ip);
+}
+
+/// Computes the checksums of a packet and of its TCP, UDP or ICMP message,
+/// on every forwarded packet, so with the fast sums of [`axchecksum`].
+fn fill_checksums(ip: &mut Ipv4Packet<&mut [u8]>) {
+    let (src, dst) = (ip.src_addr(), ip.dst_addr());
+    let protocol = ip.next_header();
+    let payload = ip.payload_mut();
+    let offset = match protocol {
+        IpProtocol::Tcp => 16,
+        IpProtocol::Udp => 6,
+        _ => 2,
+    };
+    payload[offset..offset + 2].fill(0);
+    let checksum = if protocol == IpProtocol::Icmp {
+        internet_checksum(payload)
+    } else {
+        // with the pseudo-header
+        let mut sum = InternetChecksum::new();
+        sum.update(src.as_bytes());
+        sum.update(dst.as_bytes());
+        sum.update(&[0, protocol.into()]);
+        sum.update(&(payload.len() as u16).to_be_bytes());
+        sum.update(payload);
+        match sum.finish() {
+            // 0 means no checksum for UDP
+            0 if protocol == IpProtocol::Udp => 0xffff,
+            checksum => checksum,
+        }
+    };
+    payload[offset..offset + 2].copy_from_slice(&checksum.to_be_bytes());
+    ip.set_checksum(0);
+    let checksum = internet_checksum(&ip.as_ref()[..ip.header_len() as usize]);
+    ip.set_checksum(checksum);
+}
+
+/// Answers the ARP requests for the address of the LAN interface.
+fn answer_arp(frame: &EthernetFrame<&mut [u8]>, lan_port: usize, lan_ip: Ipv4Address) {
+    let Ok(packet) = ArpPacket::new_checked(frame.payload()) else {
+        return;
+    };
+    let Ok(ArpRepr::EthernetIpv4 {
+        operation: ArpOperation::Request,
+        source_hardware_addr,
+        source_protocol_addr,
+        target_protocol_addr,
+        ..
+    }) = ArpRepr::parse(&packet)
+    else {
+        return;
+    };
+    if target_protocol_addr != lan_ip {
+        return;
+    }
+    let lan_mac = ports::port_mac(lan_port);
+    let reply = ArpRepr::EthernetIpv4 {
+        operation: ArpOperation::Reply,
+        source_hardware_addr: lan_mac,
+        source_protocol_addr: lan_ip,
+        target_hardware_addr: source_hardware_addr,
+        target_protocol_addr: source_protocol_addr,
+    };
+    let reply = neighbor::arp_frame(source_hardware_addr, lan_mac, reply);
+    ports::send(lan_port, &reply);
+}
+
+/// Translates a frame received on the LAN interface, and sends it on `eth0`.
+pub(crate) fn from_lan(frame: &mut [u8]) {
+    let Ok(mut eth) = EthernetFrame::new_checked(frame) else {
+        return;
+    };
+    let mut nat = NAT.lock();
+    let Some(lan) = nat.lan.as_ref() else {
+        return;
+    };
+    let (lan_port, lan_ip, lan_prefix_len) = (lan.port, lan.ip, lan.prefix_len);
+    match eth.ethertype() {
+        EthernetProtocol::Arp => {
+            drop(nat);
+            return answer_arp(&eth, lan_port, lan_ip);
+        }
+        EthernetProtocol::Ipv4 if eth.dst_addr() == ports::port_mac(lan_port) => {}
+        _ => return,
+    }
+    let lan_mac = eth.src_addr();
+
+    let Ok(mut ip) = Ipv4Packet::new_checked(eth.payload_mut()) else {
+        return;
+    };
+    let dst = ip.dst_addr();
+    if ip.more_frags() || ip.frag_offset() != 0 || ip.hop_limit() <= 1 {
+        return;
+    }
+    if dst == lan_ip || in_subnet(dst, lan_ip, lan_prefix_len) || !dst.is_unicast() {
+        // not to be routed
+        return;
+    }
+    let Some((src_port, dst_port, closing)) = transport_ports(&ip, Icmpv4Message::EchoRequest)
+    else {
+        return;
+    };
+    let protocol = ip.next_header();
+    let remote_port = if protocol == IpProtocol::Icmp {
+        0
+    } else {
+        dst_port
+    };
+    let key = ConnKey {
+        protocol: protocol.into(),
+        lan: (ip.src_addr().0, src_port),
+        remote: (dst.0, remote_port),
+    };
+    let now = monotonic_time_nanos();
+    let Some(external_port) = nat.external_port(key, lan_mac, now) else {
+        return;
+    };
+    if closing {
+        let conn = nat.conns.get_mut(&(key.protocol, external_port)).unwrap();
+        conn.closing = true;
+    }
+    let wan = nat.wan.as_ref().unwrap();
+    let wan_ip = wan.ip;
+    let next_hop = if in_subnet(dst, wan.ip, wan.prefix_len) {
+        dst
+    } else {
+        wan.gateway
+    };
+    drop(nat);
+
+    rewrite(&mut ip, wan_ip, external_port, true);
+    let Some(next_hop_mac) = neighbor::lookup(next_hop) else {
+        // resolve it for the next packets
+        if let Some(request) = neighbor::arp_request(next_hop) {
+            ports::send(STACK_PORT, &request);
+        }
+        return;
+    };
+    eth.set_src_addr(ports::port_mac(STACK_PORT));
+    eth.set_dst_addr(next_hop_mac);
+    ports::send(STACK_PORT, eth.into_inner());
+}
+
+/// Translates a frame received on `eth0` if it belongs to a NAT connection,
+/// and sends it on the LAN interface.
+///
+/// Returns whether the frame was translated, and so must not be processed by
+/// the network stack. Called from the receive path of the stack.
+pub(crate) fn from_wan(frame: &mut [u8]) -> bool {
+    let Ok(mut eth) = EthernetFrame::new_checked(frame) else {
+        return false;
+    };
+    if eth.ethertype() != EthernetProtocol::Ipv4 {
+        return false;
+    }
+    let Ok(mut ip) = Ipv4Packet::new_checked(eth.payload_mut()) else {
+        return false;
+    };
+    if ip.more_frags() || ip.frag_offset() != 0 {
+        return false;
+    }
+    let Some((_, dst_port, closing)) = transport_ports(&ip, Icmpv4Message::EchoReply) else {
+        return false;
+    };
+    if !(PORT_START..=PORT_END).contains(&dst_port) {
+        return false;
+    }
+
+    let mut nat = NAT.lock();
+    let Some(lan_port) = nat.lan.as_ref().map(|lan| lan.port) else {
+        return false;
+    };
+    if nat.wan.as_ref().map(|wan| wan.ip) != Some(ip.dst_addr()) {
+        return false;
+    }
+    let protocol: u8 = ip.next_header().into();
+    let Some(conn) = nat.conns.get_mut(&(protocol, dst_port)) else {
+        return false;
+    };
+    let now = monotonic_time_nanos();
+    if conn.is_expired(now) || ip.hop_limit() <= 1 {
+        // consumed anyway, the port is not the stack's
+        return true;
+    }
+    conn.updated = now;
+    conn.closing |= closing;
+    let (lan_ip, lan_port_num) = (Ipv4Address(conn.key.lan.0), conn.key.lan.1);
+    let lan_mac = conn.lan_mac;
+    drop(nat);
+
+    rewrite(&mut ip, lan_ip, lan_port_num, false);
+    eth.set_src_addr(ports::port_mac(lan_port));
+    eth.set_dst_addr(lan_mac);
+    ports::send(lan_port, eth.into_inner());
+    true
+}
@@ -0,0 +1,323 @@
+//! Neighbor (ARP) table management.
+//!
+//! smoltcp keeps its neighbor cache private, and only learns from ARP packets
+//! aimed at the local address. This module follows the ARP traffic seen by
+//! the interface to show the neighbors, and feeds smoltcp synthetic ARP
+//! replies (queued in [`take_injected`]) to:
+//!
+//! - install static entries, refreshed before smoltcp expires them;
+//! - follow gratuitous ARP of neighbors whose address moved to another host,
+//!   e.g. on failover, which smoltcp would otherwise ignore.
+//!
+//! It also announces the local address with gratuitous ARP when it is
+//! configured, and defends it against other hosts claiming it (RFC 5227).
+
+use alloc::{collections::BTreeMap, collections::VecDeque, vec, vec::Vec};
+use core::net::Ipv4Addr;
+use core::time::Duration;
+
+use axerrno::{ax_err, AxResult};
+use axhal::time::monotonic_time_nanos;
+use smoltcp::wire::{
+    ArpOperation, ArpPacket, ArpRepr, EthernetAddress, EthernetFrame, EthernetProtocol,
+    EthernetRepr, Ipv4Address,
+};
+use spin::Mutex;
+
+/// Lifetime of learned entries, the same as in the smoltcp cache.
+const ENTRY_LIFETIME: u64 = 60_000_000_000;
+/// Interval between re-installations of static entries in smoltcp.
+const STATIC_REFRESH_INTERVAL: u64 = 30_000_000_000;
+/// Minimum interval between two defenses of the local address (RFC 5227).
+const DEFEND_INTERVAL: u64 = 10_000_000_000;
+
+/// An entry of the neighbor table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NeighborEntry {
+    /// The IPv4 address of the neighbor.
+    pub ip: Ipv4Addr,
+    /// The Ethernet address of the neighbor.
+    pub mac: [u8; 6],
+    /// Whether the entry was added with [`add_static_neighbor`] and never
+    /// expires.
+    pub is_static: bool,
+    /// Time since the entry was last confirmed.
+    pub age: Duration,
+}
+
+struct Entry {
+    mac: EthernetAddress,
+    is_static: bool,
+    /// Time it was last confirmed (learned entries) or installed in smoltcp
+    /// (static entries).
+    updated: u64,
+}
+
+struct NeighborTable {
+    local_ip: Option<Ipv4Address>,
+    local_mac: EthernetAddress,
+    entries: BTreeMap<Ipv4Addr, Entry>,
+    injected: VecDeque<Vec<u8>>,
+    last_defend: Option<u64>,
+    conflicts: u64,
+}
+
+static NEIGHBORS: Mutex<NeighborTable> = Mutex::new(NeighborTable {
+    local_ip: None,
+    local_mac: EthernetAddress([0; 6]),
+    entries: BTreeMap::new(),
+    injected: VecDeque::new(),
+    last_defend: None,
+    conflicts: 0,
+});
+
+pub(crate) fn arp_frame(
+    eth_dst: EthernetAddress,
+    eth_src: EthernetAddress,
+    arp: ArpRepr,
+) -> Vec<u8> {
+    let eth = EthernetRepr {
+        src_addr: eth_src,
+        dst_addr: eth_dst,
+        ethertype: EthernetProtocol::Arp,
+    };
+    let mut buf = vec![0; eth.buffer_len() + arp.buffer_len()];
+    let mut frame = EthernetFrame::new_unchecked(&mut buf[..]);
+    eth.emit(&mut frame);
+    arp.emit(&mut ArpPacket::new_unchecked(frame.payload_mut()));
+    buf
+}
+
+impl NeighborTable {
+    /// Queues an ARP reply from the neighbor to the local address, for
+    /// smoltcp to learn it.
+    fn inject(&mut self, ip: Ipv4Address, mac: EthernetAddress) {
+        let Some(local_ip) = self.local_ip else {
+            return;
+        };
+        let reply = ArpRepr::EthernetIpv4 {
+            operation: ArpOperation::Reply,
+            source_hardware_addr: mac,
+            source_protocol_addr: ip,
+            target_hardware_addr: self.local_mac,
+            target_protocol_addr: local_ip,
+        };
+        let frame = arp_frame(self.local_mac, mac, reply);
+        self.injected.push_back(frame);
+    }
+
+    /// Returns a gratuitous ARP request for the local address.
+    fn announcement(&self) -> Option<Vec<u8>> {
+        let local_ip = self.local_ip?;
+        let request = ArpRepr::EthernetIpv4 {
+            operation: ArpOperation::Request,
+            source_hardware_addr: self.local_mac,
+            source_protocol_addr: local_ip,
+            target_hardware_addr: EthernetAddress([0; 6]),
+            target_protocol_addr: local_ip,
+        };
+        Some(arp_frame(
+            EthernetAddress::BROADCAST,
+            self.local_mac,
+            request,
+        ))
+    }
+}
+
+/// Sets the local addresses of the interface, and returns the gratuitous ARP
+/// announcing them.
+pub(crate) fn set_local_addr(ip: Ipv4Address, mac: EthernetAddress) -> Option<Vec<u8>> {
+    let mut table = NEIGHBORS.lock();
+    table.local_ip = Some(ip);
+    table.local_mac = mac;
+    table.announcement()
+}
+
+/// Returns a gratuitous ARP announcing the local address, if it is set.
+pub(crate) fn announcement() -> Option<Vec<u8>> {
+    NEIGHBORS.lock().announcement()
+}
+
+/// Returns the Ethernet address of the neighbor `ip`, if known.
+pub(crate) fn lookup(ip: Ipv4Address) -> Option<EthernetAddress> {
+    let now = monotonic_time_nanos();
+    let table = NEIGHBORS.lock();
+    let entry = table.entries.get(&Ipv4Addr::from(ip.0))?;
+    (entry.is_static || now - entry.updated < ENTRY_LIFETIME).then_some(entry.mac)
+}
+
+/// Returns an ARP request for the neighbor `ip`, if the local address is set.
+pub(crate) fn arp_request(ip: Ipv4Address) -> Option<Vec<u8>> {
+    let table = NEIGHBORS.lock();
+    let request = ArpRepr::EthernetIpv4 {
+        operation: ArpOperation::Request,
+        source_hardware_addr: table.local_mac,
+        source_protocol_addr: table.local_ip?,
+        target_hardware_addr: EthernetAddress([0; 6]),
+        target_protocol_addr: ip,
+    };
+    Some(arp_frame(
+        EthernetAddress::BROADCAST,
+        table.local_mac,
+        request,
+    ))
+}
+
+/// Follows a received frame if it is an ARP packet, and returns a frame to
+/// send in response, if any.
+pub(crate) fn snoop_arp(buf: &[u8]) -> Option<Vec<u8>> {
+    let frame = EthernetFrame::new_checked(buf).ok()?;
+    if frame.ethertype() != EthernetProtocol::Arp {
+        return None;
+    }
+    let packet = ArpPacket::new_checked(frame.payload()).ok()?;
+    let ArpRepr::EthernetIpv4 {
+        source_hardware_addr: mac,
+        source_protocol_addr: ip,
+        target_protocol_addr: target_ip,
+        ..
+    } = ArpRepr::parse(&packet).ok()?
+    else {
+        return None;
+    };
+    if !mac.is_unicast() || ip.is_unspecified() {
+        return None;
+    }
+
+    let now = monotonic_time_nanos();
+    let mut table = NEIGHBORS.lock();
+    if Some(ip) == table.local_ip {
+        if mac == table.local_mac {
+            return None;
+        }
+        table.conflicts += 1;
+        warn!("ARP conflict: {} is also claimed by {}", ip, mac);
+        // defend the address, unless we did recently
+        if table
+            .last_defend
+            .is_some_and(|last| now - last < DEFEND_INTERVAL)
+        {
+            return None;
+        }
+        table.last_defend = Some(now);
+        return table.announcement();
+    }
+
+    let key = Ipv4Addr::from(ip.0);
+    let moved = match table.entries.get_mut(&key) {
+        Some(entry) if entry.is_static => return None,
+        Some(entry) => {
+            let moved = entry.mac != mac;
+            entry.mac = mac;
+            entry.updated = now;
+            moved
+        }
+        None => {
+            let entry = Entry {
+                mac,
+                is_static: false,
+                updated: now,
+            };
+            table.entries.insert(key, entry);
+            false
+        }
+    };
+    // smoltcp learns from packets aimed at us by itself
+    if moved && Some(target_ip) != table.local_ip {
+        debug!("ARP: {} moved to {}", ip, mac);
+        table.inject(ip, mac);
+    }
+    None
+}
+
+/// Takes the next synthetic ARP frame to feed to smoltcp.
+pub(crate) fn take_injected() -> Option<Vec<u8>> {
+    NEIGHBORS.lock().injected.pop_front()
+}
+
+/// Re-installs the static entries in smoltcp if they are about to expire
+/// there, or all of them if `force` is set.
+pub(crate) fn refresh_static(force: bool) {
+    let now = monotonic_time_nanos();
+    let mut table = NEIGHBORS.lock();
+    let mut stale = Vec::new();
+    for (ip, entry) in table.entries.iter_mut() {
+        if entry.is_static && (force || now - entry.updated >= STATIC_REFRESH_INTERVAL) {
+            entry.updated = now;
+            stale.push((Ipv4Address(ip.octets()), entry.mac));
+        }
+    }
+    for (ip, mac) in stale {
+        table.inject(ip, mac);
+    }
+}
+
+/// Forgets the learned entries, and keeps the static ones.
+pub(crate) fn flush_learned() {
+    NEIGHBORS.lock().entries.retain(|_, entry| entry.is_static);
+}
+
+/// Returns the entries of the neighbor table.
+pub fn neighbors() -> Vec<NeighborEntry> {
+    let now = monotonic_time_nanos();
+    let mut table = NEIGHBORS.lock();
+    table
+        .entries
+        .retain(|_, entry| entry.is_static || now - entry.updated < ENTRY_LIFETIME);
+    table
+        .entries
+        .iter()
+        .map(|(ip, entry)| NeighborEntry {
+            ip: *ip,
+            mac: entry.mac.0,
+            is_static: entry.is_static,
+            age: Duration::from_nanos(if entry.is_static {
+                0
+            } else {
+                now - entry.updated
+            }),
+        })
+        .collect()
+}
+
+/// Adds a static entry to the neighbor table, replacing any entry for `ip`.
+///
+/// Returns an error if `ip` is the local or the unspecified address, or if
+/// `mac` is not a unicast address.
+pub fn add_static_neighbor(ip: Ipv4Addr, mac: [u8; 6]) -> AxResult {
+    let (ip, mac) = (Ipv4Address(ip.octets()), EthernetAddress(mac));
+    if ip.is_unspecified() || !ip.is_unicast() || !mac.is_unicast() {
+        return ax_err!(InvalidInput, "invalid neighbor address");
+    }
+    let mut table = NEIGHBORS.lock();
+    if Some(ip) == table.local_ip {
+        return ax_err!(InvalidInput, "neighbor address is the local address");
+    }
+    let entry = Entry {
+        mac,
+        is_static: true,
+        updated: monotonic_time_nanos(),
+    };
+    table.entries.insert(Ipv4Addr::from(ip.0), entry);
+    table.inject(ip, mac);
+    Ok(())
+}
+
+/// Removes the static entry for `ip` from the neighbor table.
+///
+/// smoltcp keeps using it until it expires or the table is flushed.
+pub fn remove_static_neighbor(ip: Ipv4Addr) -> AxResult {
+    let mut table = NEIGHBORS.lock();
+    match table.entries.get(&ip) {
+        Some(entry) if entry.is_static => {
+            table.entries.remove(&ip);
+            Ok(())
+        }
+        _ => ax_err!(NotFound, "no static neighbor entry"),
+    }
+}
+
+/// Returns how many times another host was seen claiming the local address.
+pub fn arp_conflicts() -> u64 {
+    NEIGHBORS.lock().conflicts
+}
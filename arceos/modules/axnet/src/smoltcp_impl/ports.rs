@@ -0,0 +1,219 @@
+//! Network interfaces available for forwarding.
+//!
+//! Port 0 is `eth0`, the NIC of the network stack. The other NICs (`eth1`,
+//! `eth2`, ...) are not used by the stack: they only carry the frames
+//! forwarded by the [bridge](super::bridge) and [NAT](super::nat), or the
+//! PPPoE session of `ppp0` (with the `ppp` feature).
+//!
+//! With the `tun` feature, the last port is the [TAP interface](super::tap)
+//! `tap0`, on which the frames are exchanged with an application.
+//!
+//! Frames received on them are processed in [`poll_interfaces`], so
+//! forwarding applications call it in a loop (or from a background task).
+//!
+//! [`poll_interfaces`]: super::poll_interfaces
+
+use alloc::{format, string::String, vec::Vec};
+use core::net::IpAddr;
+
+use axdriver::prelude::*;
+use axerrno::{ax_err, AxResult};
+use axsync::Mutex;
+use lazyinit::LazyInit;
+use smoltcp::wire::EthernetAddress;
+
+use super::addr::into_core_ipaddr;
+#[cfg(feature = "tun")]
+use super::tap;
+use super::{bridge, nat, negotiate_mtu, send_frame, ETH0};
+
+/// The port of the network stack.
+pub(crate) const STACK_PORT: usize = 0;
+
+/// Maximum number of frames processed per port at each poll, so that a busy
+/// port does not starve the others.
+const POLL_BUDGET: usize = 64;
+
+struct Port {
+    name: String,
+    mac: EthernetAddress,
+    dev: PortDevice,
+}
+
+enum PortDevice {
+    Nic(Mutex<AxNetDevice>),
+    #[cfg(feature = "tun")]
+    Tap,
+}
+
+/// The ports other than [`STACK_PORT`], from index 1.
+static PORTS: LazyInit<Vec<Port>> = LazyInit::new();
+
+pub(crate) fn init(devs: Vec<AxNetDevice>) {
+    #[allow(unused_mut)]
+    let mut ports = devs
+        .into_iter()
+        .enumerate()
+        .map(|(i, mut dev)| {
+            let name = format!("eth{}", i + 1);
+            negotiate_mtu(&name, &mut dev);
+            Port {
+                name,
+                mac: EthernetAddress(dev.mac_address().0),
+                dev: PortDevice::Nic(Mutex::new(dev)),
+            }
+        })
+        .collect::<Vec<_>>();
+    #[cfg(feature = "tun")]
+    ports.push(Port {
+        name: tap::TAP_NAME.into(),
+        mac: tap::TAP_MAC,
+        dev: PortDevice::Tap,
+    });
+    for port in &ports {
+        info!("forwarding port {:?}: ether {}", port.name, port.mac);
+    }
+    PORTS.init_once(ports);
+}
+
+pub(crate) fn resume(devs: Vec<AxNetDevice>) {
+    let Some(ports) = PORTS.get() else {
+        return;
+    };
+    let nics = ports.iter().filter_map(|port| match &port.dev {
+        PortDevice::Nic(dev) => Some((&port.name, dev)),
+        #[cfg(feature = "tun")]
+        PortDevice::Tap => None,
+    });
+    for ((name, nic), mut dev) in nics.zip(devs) {
+        negotiate_mtu(name, &mut dev);
+        let old = core::mem::replace(&mut *nic.lock(), dev);
+        core::mem::forget(old);
+    }
+}
+
+fn port(index: usize) -> &'static Port {
+    &PORTS[index - 1]
+}
+
+/// Returns the number of ports, including [`STACK_PORT`].
+pub(crate) fn num_ports() -> usize {
+    PORTS.get().map_or(0, Vec::len) + 1
+}
+
+/// Returns the index of the port named `name`.
+pub(crate) fn port_index(name: &str) -> AxResult<usize> {
+    if name == ETH0.name() {
+        return Ok(STACK_PORT);
+    }
+    match PORTS.iter().position(|port| port.name == name) {
+        Some(i) => Ok(i + 1),
+        None => ax_err!(NotFound, "no such network interface"),
+    }
+}
+
+pub(crate) fn port_name(index: usize) -> &'static str {
+    match index {
+        STACK_PORT => ETH0.name(),
+        _ => &port(index).name,
+    }
+}
+
+pub(crate) fn port_mac(index: usize) -> EthernetAddress {
+    match index {
+        STACK_PORT => ETH0.ethernet_address(),
+        _ => port(index).mac,
+    }
+}
+
+/// Transmits a frame on a port.
+///
+/// It must not be called with the device of [`STACK_PORT`] locked, i.e.
+/// from the receive or transmit path of the stack, unless `index` is another
+/// port.
+pub(crate) fn send(index: usize, frame: &[u8]) {
+    match index {
+        STACK_PORT => ETH0.dev.lock().send_frame(frame),
+        _ => match &port(index).dev {
+            PortDevice::Nic(dev) => send_frame(&mut dev.lock(), frame),
+            #[cfg(feature = "tun")]
+            PortDevice::Tap => tap::output(frame),
+        },
+    }
+}
+
+fn recv(port: &Port) -> Option<Vec<u8>> {
+    let mut dev = match &port.dev {
+        PortDevice::Nic(dev) => dev.lock(),
+        #[cfg(feature = "tun")]
+        PortDevice::Tap => return tap::take_input(),
+    };
+    dev.recycle_tx_buffers().ok()?;
+    let rx_buf = dev.receive().ok()?;
+    let frame = rx_buf.packet().to_vec();
+    dev.recycle_rx_buffer(rx_buf).unwrap();
+    Some(frame)
+}
+
+/// Processes the frames received on the ports other than [`STACK_PORT`].
+pub(crate) fn poll() {
+    let Some(ports) = PORTS.get() else {
+        return;
+    };
+    for (i, port) in ports.iter().enumerate() {
+        let index = i + 1;
+        for _ in 0..POLL_BUDGET {
+            let Some(mut frame) = recv(port) else {
+                break;
+            };
+            #[cfg(feature = "ppp")]
+            if super::ppp::input(index, &frame) {
+                continue;
+            }
+            if nat::is_lan_port(index) {
+                nat::from_lan(&mut frame);
+            } else {
+                bridge::input(index, frame);
+            }
+        }
+    }
+}
+
+/// Returns the names of the network interfaces, starting with the one of the
+/// network stack.
+pub fn interfaces() -> Vec<&'static str> {
+    (0..num_ports()).map(port_name).collect()
+}
+
+/// The configuration of a network interface, see [`interface_info`].
+#[derive(Debug, Clone)]
+pub struct InterfaceInfo {
+    pub name: &'static str,
+    pub mac: [u8; 6],
+    /// The addresses with their prefix length, only set on the interface of
+    /// the network stack.
+    pub addrs: Vec<(IpAddr, u8)>,
+}
+
+/// Returns the configuration of the network interfaces, in the order of
+/// [`interfaces`].
+pub fn interface_info() -> Vec<InterfaceInfo> {
+    let mut info = Vec::with_capacity(num_ports());
+    info.push(InterfaceInfo {
+        name: ETH0.name(),
+        mac: ETH0.ethernet_address().0,
+        addrs: ETH0
+            .ip_addrs()
+            .iter()
+            .map(|cidr| (into_core_ipaddr(cidr.address()), cidr.prefix_len()))
+            .collect(),
+    });
+    for port in PORTS.iter() {
+        info.push(InterfaceInfo {
+            name: &port.name,
+            mac: port.mac.0,
+            addrs: Vec::new(),
+        });
+    }
+    info
+}
@@ -0,0 +1,234 @@
+//! The formats of PPPoE (RFC 2516) and of the PPP control protocols (RFC
+//! 1661, 1332, 1334 and 1994).
+
+use alloc::vec::Vec;
+
+use smoltcp::wire::EthernetAddress;
+
+use crate::skb::SkBuff;
+
+pub const ETHERTYPE_DISCOVERY: u16 = 0x8863;
+pub const ETHERTYPE_SESSION: u16 = 0x8864;
+
+/// The codes of the PPPoE discovery packets.
+pub const PADI: u8 = 0x09;
+pub const PADO: u8 = 0x07;
+pub const PADR: u8 = 0x19;
+pub const PADS: u8 = 0x65;
+pub const PADT: u8 = 0xa7;
+
+/// The tags of the PPPoE discovery packets.
+pub const TAG_END_OF_LIST: u16 = 0x0000;
+pub const TAG_SERVICE_NAME: u16 = 0x0101;
+pub const TAG_AC_NAME: u16 = 0x0102;
+pub const TAG_HOST_UNIQ: u16 = 0x0103;
+pub const TAG_AC_COOKIE: u16 = 0x0104;
+pub const TAG_RELAY_SESSION_ID: u16 = 0x0110;
+/// The tags of the errors, `0x0201` to `0x0203`.
+pub const TAG_ERRORS: core::ops::RangeInclusive<u16> = 0x0201..=0x0203;
+
+/// The protocols carried in a session.
+pub const PROTO_IPV4: u16 = 0x0021;
+pub const PROTO_IPCP: u16 = 0x8021;
+pub const PROTO_LCP: u16 = 0xc021;
+pub const PROTO_PAP: u16 = 0xc023;
+pub const PROTO_CHAP: u16 = 0xc223;
+
+/// The codes of the LCP and IPCP packets.
+pub const CONF_REQ: u8 = 1;
+pub const CONF_ACK: u8 = 2;
+pub const CONF_NAK: u8 = 3;
+pub const CONF_REJ: u8 = 4;
+pub const TERM_REQ: u8 = 5;
+pub const TERM_ACK: u8 = 6;
+pub const PROTO_REJ: u8 = 8;
+pub const ECHO_REQ: u8 = 9;
+pub const ECHO_REPLY: u8 = 10;
+
+/// The LCP options.
+pub const LCP_MRU: u8 = 1;
+pub const LCP_AUTH: u8 = 3;
+pub const LCP_MAGIC: u8 = 5;
+/// The IPCP options.
+pub const IPCP_ADDR: u8 = 3;
+pub const IPCP_DNS1: u8 = 129;
+pub const IPCP_DNS2: u8 = 131;
+
+/// The codes of the PAP packets.
+pub const PAP_REQ: u8 = 1;
+pub const PAP_ACK: u8 = 2;
+pub const PAP_NAK: u8 = 3;
+/// The codes of the CHAP packets.
+pub const CHAP_CHALLENGE: u8 = 1;
+pub const CHAP_RESPONSE: u8 = 2;
+pub const CHAP_SUCCESS: u8 = 3;
+pub const CHAP_FAILURE: u8 = 4;
+/// The MD5 algorithm of CHAP.
+pub const CHAP_MD5: u8 = 5;
+
+/// The length of the Ethernet, PPPoE and PPP headers of a session packet.
+pub const SESSION_HEADER_LEN: usize = ETHERNET_HEADER_LEN + PPPOE_HEADER_LEN + 2;
+const ETHERNET_HEADER_LEN: usize = 14;
+const PPPOE_HEADER_LEN: usize = 6;
+
+/// The largest PPP packet in a PPPoE session of a standard Ethernet link.
+pub const PPPOE_MTU: usize = 1492;
+
+/// A PPPoE packet, of the discovery or the session stage.
+pub struct Pppoe<'a> {
+    pub ethertype: u16,
+    pub src: EthernetAddress,
+    pub code: u8,
+    pub session_id: u16,
+    pub payload: &'a [u8],
+}
+
+impl<'a> Pppoe<'a> {
+    /// Parses a PPPoE packet from an Ethernet frame.
+    pub fn parse(frame: &'a [u8]) -> Option<Self> {
+        let header = frame.get(..ETHERNET_HEADER_LEN + PPPOE_HEADER_LEN)?;
+        let ethertype = u16::from_be_bytes([header[12], header[13]]);
+        if !matches!(ethertype, ETHERTYPE_DISCOVERY | ETHERTYPE_SESSION) || header[14] != 0x11 {
+            return None;
+        }
+        let len = u16::from_be_bytes([header[18], header[19]]) as usize;
+        let start = ETHERNET_HEADER_LEN + PPPOE_HEADER_LEN;
+        Some(Self {
+            ethertype,
+            src: EthernetAddress::from_bytes(&header[6..12]),
+            code: header[15],
+            session_id: u16::from_be_bytes([header[16], header[17]]),
+            payload: frame.get(start..start + len)?,
+        })
+    }
+
+    /// Returns the value of the first tag of type `ty` of a discovery packet.
+    pub fn tag(&self, ty: u16) -> Option<&'a [u8]> {
+        self.tags().find(|&(t, _)| t == ty).map(|(_, value)| value)
+    }
+
+    /// Iterates over the tags of a discovery packet.
+    pub fn tags(&self) -> impl Iterator<Item = (u16, &'a [u8])> {
+        Fields {
+            data: self.payload,
+            header_len: 4,
+        }
+        .map(|(ty, value)| (u16::from_be_bytes([ty[0], ty[1]]), value))
+        .take_while(|&(ty, _)| ty != TAG_END_OF_LIST)
+    }
+}
+
+/// An iterator over type-length-value fields: the tags of PPPoE (types and
+/// lengths of two bytes) or the options of PPP (of one byte, the length
+/// including them).
+struct Fields<'a> {
+    data: &'a [u8],
+    header_len: usize,
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let half = self.header_len / 2;
+        let header = self.data.get(..self.header_len)?;
+        let (ty, len) = header.split_at(half);
+        let len = match half {
+            2 => u16::from_be_bytes([len[0], len[1]]) as usize,
+            _ => (len[0] as usize).checked_sub(self.header_len)?,
+        };
+        let value = self.data.get(self.header_len..self.header_len + len)?;
+        self.data = &self.data[self.header_len + len..];
+        Some((ty, value))
+    }
+}
+
+/// Builds a PPPoE discovery packet.
+pub fn discovery(
+    dst: EthernetAddress,
+    src: EthernetAddress,
+    code: u8,
+    session_id: u16,
+    tags: &[(u16, &[u8])],
+) -> Vec<u8> {
+    let mut frame = Vec::new();
+    frame.extend_from_slice(&dst.0);
+    frame.extend_from_slice(&src.0);
+    frame.extend_from_slice(&ETHERTYPE_DISCOVERY.to_be_bytes());
+    frame.extend_from_slice(&[0x11, code]);
+    frame.extend_from_slice(&session_id.to_be_bytes());
+    let len: usize = tags.iter().map(|(_, value)| 4 + value.len()).sum();
+    frame.extend_from_slice(&(len as u16).to_be_bytes());
+    for &(ty, value) in tags {
+        frame.extend_from_slice(&ty.to_be_bytes());
+        frame.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        frame.extend_from_slice(value);
+    }
+    frame
+}
+
+/// Adds the Ethernet, PPPoE and PPP headers of a session packet in front of
+/// a packet of `protocol`.
+pub fn encapsulate(
+    packet: &mut SkBuff,
+    dst: EthernetAddress,
+    src: EthernetAddress,
+    session_id: u16,
+    protocol: u16,
+) {
+    let len = (packet.len() + 2) as u16;
+    let header = packet.push(SESSION_HEADER_LEN);
+    header[..6].copy_from_slice(&dst.0);
+    header[6..12].copy_from_slice(&src.0);
+    header[12..14].copy_from_slice(&ETHERTYPE_SESSION.to_be_bytes());
+    header[14..16].copy_from_slice(&[0x11, 0]);
+    header[16..18].copy_from_slice(&session_id.to_be_bytes());
+    header[18..20].copy_from_slice(&len.to_be_bytes());
+    header[20..22].copy_from_slice(&protocol.to_be_bytes());
+}
+
+/// A packet of a control protocol (LCP, PAP, CHAP or IPCP).
+pub struct Control<'a> {
+    pub code: u8,
+    pub id: u8,
+    pub data: &'a [u8],
+}
+
+impl<'a> Control<'a> {
+    pub fn parse(packet: &'a [u8]) -> Option<Self> {
+        let header = packet.get(..4)?;
+        let len = u16::from_be_bytes([header[2], header[3]]) as usize;
+        Some(Self {
+            code: header[0],
+            id: header[1],
+            data: packet.get(4..len)?,
+        })
+    }
+
+    /// Iterates over the options of a configure packet.
+    pub fn options(&self) -> impl Iterator<Item = (u8, &'a [u8])> {
+        Fields {
+            data: self.data,
+            header_len: 2,
+        }
+        .map(|(ty, value)| (ty[0], value))
+    }
+}
+
+/// Builds a packet of a control protocol.
+pub fn control(code: u8, id: u8, data: &[u8]) -> SkBuff {
+    let mut packet = SkBuff::new(SESSION_HEADER_LEN, 4 + data.len(), 0);
+    let buf = packet.data_mut();
+    buf[0] = code;
+    buf[1] = id;
+    buf[2..4].copy_from_slice(&((4 + data.len()) as u16).to_be_bytes());
+    buf[4..].copy_from_slice(data);
+    packet
+}
+
+/// Appends an option to the data of a configure packet.
+pub fn push_option(data: &mut Vec<u8>, ty: u8, value: &[u8]) {
+    data.push(ty);
+    data.push(2 + value.len() as u8);
+    data.extend_from_slice(value);
+}
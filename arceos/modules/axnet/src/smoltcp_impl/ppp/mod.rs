@@ -0,0 +1,1020 @@
+//! PPP over Ethernet client interface (`ppp0`).
+//!
+//! [`ppp_up`] runs a PPPoE session on a NIC other than `eth0`, the one of the
+//! DSL or 4G modem: the access concentrator is discovered, then the link is
+//! negotiated with LCP, authenticated by PAP or CHAP (MD5) and configured by
+//! IPCP, which gives the address of the interface and the DNS servers.
+//!
+//! The address is added to the network stack with a `/0` prefix, so that the
+//! stack sends everything outside the subnets of its other interfaces from
+//! it. It resolves those destinations to a virtual Ethernet address answered
+//! here, and the IPv4 packets sent to that address leave through the
+//! session; the packets received in the session are handed to the stack as
+//! if they came from that address.
+//!
+//! The link is probed with LCP echoes. A link which fails (no answer, a
+//! termination by the peer, an authentication failure) is brought down and
+//! reconnected after a delay, growing after each failed attempt.
+
+mod frame;
+
+use alloc::{collections::VecDeque, string::String, vec, vec::Vec};
+use core::net::Ipv4Addr;
+use core::time::Duration;
+
+use axerrno::{ax_err, AxResult};
+use axhal::time::{monotonic_time_nanos, NANOS_PER_SEC};
+use axsync::Mutex;
+use md5::{Digest, Md5};
+use smoltcp::wire::{
+    ArpOperation, ArpPacket, ArpRepr, EthernetAddress, EthernetFrame, EthernetProtocol,
+    EthernetRepr, Ipv4Address, Ipv4Cidr, Ipv4Packet,
+};
+use spin::RwLock;
+
+use self::frame::*;
+use super::ports::{self, STACK_PORT};
+use super::{bridge, nat, neighbor, ETH0};
+use crate::skb::SkBuff;
+
+pub(crate) use self::frame::PPPOE_MTU;
+
+/// The virtual Ethernet address of the hosts reached through the session.
+const PPP_MAC: EthernetAddress = EthernetAddress([0x02, 0x70, 0x70, 0, 0, 0]);
+
+/// Interval between the retransmissions of a request without answer.
+const RESTART_TIME: u64 = 3 * NANOS_PER_SEC;
+/// Maximum number of PADI or PADR sent before giving up.
+const MAX_DISCOVERY: u32 = 5;
+/// Maximum number of configure (or authenticate) requests sent before giving
+/// up, as the Max-Configure of RFC 1661.
+const MAX_CONFIGURE: u32 = 10;
+const ECHO_INTERVAL: u64 = 10 * NANOS_PER_SEC;
+/// Number of echoes without any answer of the peer after which the link is
+/// considered down.
+const MAX_ECHO_MISSED: u32 = 3;
+const BACKOFF_MIN: u64 = 5 * NANOS_PER_SEC;
+const BACKOFF_MAX: u64 = 60 * NANOS_PER_SEC;
+/// Maximum number of packets queued between the stack and the session, each
+/// way.
+const QUEUE_LEN: usize = 256;
+
+/// The configuration of the PPPoE client.
+#[derive(Debug, Clone, Default)]
+pub struct PppConfig {
+    /// The name to authenticate with.
+    pub username: String,
+    /// The password (PAP) or secret (CHAP).
+    pub password: String,
+    /// The service to ask the access concentrators for, any if [`None`].
+    pub service_name: Option<String>,
+}
+
+/// The phase of the link, see [`ppp_status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PppPhase {
+    /// Looking for an access concentrator, and opening a session with it.
+    Discovery,
+    /// Negotiating the link with LCP.
+    Establish,
+    /// Authenticating with PAP or CHAP.
+    Authenticate,
+    /// Negotiating the address with IPCP.
+    Network,
+    /// The link is up and carries IPv4 packets.
+    Opened,
+    /// The link failed, and waits to reconnect.
+    Reconnecting,
+}
+
+/// The state of the PPPoE client.
+#[derive(Debug, Clone)]
+pub struct PppStatus {
+    /// The NIC the session runs on.
+    pub port: &'static str,
+    pub phase: PppPhase,
+    /// The address given by the peer.
+    pub local_addr: Option<Ipv4Addr>,
+    /// The address of the peer.
+    pub peer_addr: Option<Ipv4Addr>,
+    /// The DNS servers given by the peer.
+    pub dns_servers: Vec<Ipv4Addr>,
+    /// The PPPoE session ID.
+    pub session_id: Option<u16>,
+    /// The name of the access concentrator.
+    pub ac_name: Option<String>,
+    /// Number of reconnections after a failure of the link.
+    pub reconnects: u64,
+    /// Time since the link was opened.
+    pub uptime: Option<Duration>,
+}
+
+/// The access concentrator chosen from the offers.
+struct Concentrator {
+    mac: EthernetAddress,
+    name: Option<String>,
+    cookie: Option<Vec<u8>>,
+    relay_session_id: Option<Vec<u8>>,
+}
+
+/// The state of a configure negotiation (LCP or IPCP).
+#[derive(Default)]
+struct Negotiation {
+    /// The ID of our last Configure-Request.
+    req_id: u8,
+    /// Whether the peer acknowledged our options.
+    acked_by_peer: bool,
+    /// Whether we acknowledged the options of the peer.
+    acked_peer: bool,
+}
+
+impl Negotiation {
+    fn is_opened(&self) -> bool {
+        self.acked_by_peer && self.acked_peer
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Auth {
+    Pap,
+    Chap,
+}
+
+/// The options of a Configure-Request of the peer, sorted by the reply.
+#[derive(Default)]
+struct Reply {
+    ack: Vec<u8>,
+    nak: Vec<u8>,
+    reject: Vec<u8>,
+}
+
+impl Reply {
+    /// The code and the data of the reply: the rejected options if any, else
+    /// the ones we suggest values for, else all of them.
+    fn into_packet(self) -> (u8, Vec<u8>) {
+        if !self.reject.is_empty() {
+            (CONF_REJ, self.reject)
+        } else if !self.nak.is_empty() {
+            (CONF_NAK, self.nak)
+        } else {
+            (CONF_ACK, self.ack)
+        }
+    }
+}
+
+struct Link {
+    port: usize,
+    mac: EthernetAddress,
+    config: PppConfig,
+    host_uniq: [u8; 8],
+    phase: PppPhase,
+    ac: Option<Concentrator>,
+    /// The PPPoE session ID, 0 without a session.
+    session_id: u16,
+    next_id: u8,
+    /// When the last request was sent, and how many times.
+    last_sent: u64,
+    retries: u32,
+
+    lcp: Negotiation,
+    magic: u32,
+    mru: u16,
+    mru_rejected: bool,
+    magic_rejected: bool,
+    auth: Option<Auth>,
+    auth_id: u8,
+
+    ipcp: Negotiation,
+    local: Ipv4Address,
+    peer: Option<Ipv4Address>,
+    /// The DNS servers, which the peer may reject to give.
+    dns: [Option<Ipv4Address>; 2],
+    dns_rejected: [bool; 2],
+
+    echo_missed: u32,
+    opened_at: Option<u64>,
+    reconnect_at: u64,
+    backoff: u64,
+    reconnects: u64,
+}
+
+/// The local address while the link is opened, for the transmit path of the
+/// stack, without taking the lock of the link.
+static UP: RwLock<Option<Ipv4Address>> = RwLock::new(None);
+/// IPv4 packets from the stack, to send in the session.
+static OUTBOUND: spin::Mutex<VecDeque<SkBuff>> = spin::Mutex::new(VecDeque::new());
+/// Frames for the stack: received packets and ARP replies.
+static INBOUND: spin::Mutex<VecDeque<Vec<u8>>> = spin::Mutex::new(VecDeque::new());
+
+static LINK: Mutex<Option<Link>> = Mutex::new(None);
+
+fn push_bounded<T>(queue: &spin::Mutex<VecDeque<T>>, item: T) {
+    let mut queue = queue.lock();
+    if queue.len() < QUEUE_LEN {
+        queue.push_back(item);
+    }
+}
+
+/// Where the DNS servers are in [`Link::dns`].
+fn dns_index(option: u8) -> usize {
+    (option != IPCP_DNS1) as usize
+}
+
+fn ipv4(value: &[u8]) -> Option<Ipv4Address> {
+    (value.len() == 4).then(|| Ipv4Address::from_bytes(value))
+}
+
+impl Link {
+    fn new(port: usize, config: PppConfig) -> Self {
+        Self {
+            port,
+            mac: ports::port_mac(port),
+            config,
+            host_uniq: (axhal::misc::random() as u64).to_be_bytes(),
+            phase: PppPhase::Discovery,
+            ac: None,
+            session_id: 0,
+            next_id: 0,
+            last_sent: 0,
+            retries: 0,
+            lcp: Negotiation::default(),
+            magic: 0,
+            mru: PPPOE_MTU as u16,
+            mru_rejected: false,
+            magic_rejected: false,
+            auth: None,
+            auth_id: 0,
+            ipcp: Negotiation::default(),
+            local: Ipv4Address::UNSPECIFIED,
+            peer: None,
+            dns: [None; 2],
+            dns_rejected: [false; 2],
+            echo_missed: 0,
+            opened_at: None,
+            reconnect_at: 0,
+            backoff: BACKOFF_MIN,
+            reconnects: 0,
+        }
+    }
+
+    fn next_id(&mut self) -> u8 {
+        self.next_id = self.next_id.wrapping_add(1);
+        self.next_id
+    }
+
+    fn ac_mac(&self) -> EthernetAddress {
+        self.ac
+            .as_ref()
+            .map_or(EthernetAddress::BROADCAST, |ac| ac.mac)
+    }
+
+    /// Forgets the session and the negotiated state, to discover an access
+    /// concentrator again.
+    fn reset(&mut self) {
+        let config = core::mem::take(&mut self.config);
+        *self = Self {
+            reconnect_at: self.reconnect_at,
+            backoff: self.backoff,
+            reconnects: self.reconnects,
+            ..Self::new(self.port, config)
+        };
+    }
+
+    /// Starts (or restarts) to look for an access concentrator.
+    fn start_discovery(&mut self, now: u64) {
+        self.reset();
+        self.retries = 0;
+        self.send_padi(now);
+    }
+
+    fn send_padi(&mut self, now: u64) {
+        let service = self.config.service_name.as_deref().unwrap_or("");
+        let frame = discovery(
+            EthernetAddress::BROADCAST,
+            self.mac,
+            PADI,
+            0,
+            &[
+                (TAG_SERVICE_NAME, service.as_bytes()),
+                (TAG_HOST_UNIQ, &self.host_uniq[..]),
+            ],
+        );
+        ports::send(self.port, &frame);
+        self.sent(now);
+    }
+
+    fn send_padr(&mut self, now: u64) {
+        let Some(ac) = self.ac.as_ref() else {
+            return;
+        };
+        let service = self.config.service_name.as_deref().unwrap_or("");
+        let mut tags = vec![
+            (TAG_SERVICE_NAME, service.as_bytes()),
+            (TAG_HOST_UNIQ, &self.host_uniq[..]),
+        ];
+        // both are echoed back as they were received
+        if let Some(cookie) = &ac.cookie {
+            tags.push((TAG_AC_COOKIE, &cookie[..]));
+        }
+        if let Some(relay) = &ac.relay_session_id {
+            tags.push((TAG_RELAY_SESSION_ID, &relay[..]));
+        }
+        let frame = discovery(ac.mac, self.mac, PADR, 0, &tags);
+        ports::send(self.port, &frame);
+        self.sent(now);
+    }
+
+    fn send_padt(&self) {
+        if self.session_id != 0 {
+            let frame = discovery(self.ac_mac(), self.mac, PADT, self.session_id, &[]);
+            ports::send(self.port, &frame);
+        }
+    }
+
+    fn sent(&mut self, now: u64) {
+        self.last_sent = now;
+        self.retries += 1;
+    }
+
+    /// Sends a packet of `protocol` in the session.
+    fn send(&self, protocol: u16, mut packet: SkBuff) {
+        encapsulate(
+            &mut packet,
+            self.ac_mac(),
+            self.mac,
+            self.session_id,
+            protocol,
+        );
+        ports::send(self.port, packet.data());
+    }
+
+    fn send_control(&self, protocol: u16, code: u8, id: u8, data: &[u8]) {
+        self.send(protocol, control(code, id, data));
+    }
+
+    /// Brings the link down after a failure, to reconnect later.
+    fn fail(&mut self, now: u64, reason: &str) {
+        warn!(
+            "ppp0: {}, reconnecting in {}s",
+            reason,
+            self.backoff / NANOS_PER_SEC
+        );
+        self.send_padt();
+        self.network_down();
+        self.reset();
+        self.phase = PppPhase::Reconnecting;
+        self.reconnect_at = now + self.backoff;
+        self.backoff = (self.backoff * 2).min(BACKOFF_MAX);
+        self.reconnects += 1;
+    }
+
+    /// Removes the address of the link from the stack, if it was opened.
+    fn network_down(&mut self) {
+        if let Some(local) = UP.write().take() {
+            ETH0.remove_ip_addr(Ipv4Cidr::new(local, 0).into());
+            info!("ppp0: down");
+        }
+        OUTBOUND.lock().clear();
+        self.opened_at = None;
+    }
+
+    fn handle_discovery(&mut self, pkt: &Pppoe, now: u64) {
+        if pkt.code == PADT {
+            if self.session_id != 0 && pkt.session_id == self.session_id && pkt.src == self.ac_mac()
+            {
+                self.session_id = 0;
+                self.fail(now, "session terminated by the access concentrator");
+            }
+            return;
+        }
+        if pkt.tag(TAG_HOST_UNIQ) != Some(&self.host_uniq[..]) {
+            return;
+        }
+        if let Some((_, error)) = pkt.tags().find(|(ty, _)| TAG_ERRORS.contains(ty)) {
+            warn!(
+                "ppp0: error from the access concentrator: {}",
+                String::from_utf8_lossy(error)
+            );
+            if pkt.code == PADS {
+                self.fail(now, "session refused");
+            }
+            return;
+        }
+        match pkt.code {
+            PADO if self.phase == PppPhase::Discovery && self.ac.is_none() => {
+                if let Some(service) = &self.config.service_name {
+                    let offered = pkt
+                        .tags()
+                        .any(|(ty, value)| ty == TAG_SERVICE_NAME && value == service.as_bytes());
+                    if !offered {
+                        return;
+                    }
+                }
+                self.ac = Some(Concentrator {
+                    mac: pkt.src,
+                    name: pkt
+                        .tag(TAG_AC_NAME)
+                        .map(|name| String::from_utf8_lossy(name).into()),
+                    cookie: pkt.tag(TAG_AC_COOKIE).map(<[u8]>::to_vec),
+                    relay_session_id: pkt.tag(TAG_RELAY_SESSION_ID).map(<[u8]>::to_vec),
+                });
+                self.retries = 0;
+                self.send_padr(now);
+            }
+            PADS if self.phase == PppPhase::Discovery && pkt.src == self.ac_mac() => {
+                if pkt.session_id == 0 {
+                    return self.fail(now, "session refused");
+                }
+                self.session_id = pkt.session_id;
+                info!("ppp0: session {:#06x} with {}", pkt.session_id, pkt.src);
+                self.phase = PppPhase::Establish;
+                self.magic = axhal::misc::random() as u32;
+                self.retries = 0;
+                self.send_lcp_request(now);
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_session(&mut self, pkt: &Pppoe, now: u64) {
+        if self.session_id == 0 || pkt.session_id != self.session_id || pkt.src != self.ac_mac() {
+            return;
+        }
+        if pkt.payload.len() < 2 {
+            return;
+        }
+        let (protocol, packet) = pkt.payload.split_at(2);
+        // any packet of the peer shows that the link is alive
+        self.echo_missed = 0;
+        match u16::from_be_bytes([protocol[0], protocol[1]]) {
+            PROTO_LCP => self.handle_lcp(packet, now),
+            PROTO_PAP => self.handle_pap(packet, now),
+            PROTO_CHAP => self.handle_chap(packet, now),
+            PROTO_IPCP => self.handle_ipcp(packet, now),
+            PROTO_IPV4 if self.phase == PppPhase::Opened => {
+                let eth = EthernetRepr {
+                    src_addr: PPP_MAC,
+                    dst_addr: ETH0.ethernet_address(),
+                    ethertype: EthernetProtocol::Ipv4,
+                };
+                let mut frame = vec![0; eth.buffer_len() + packet.len()];
+                let mut eth_frame = EthernetFrame::new_unchecked(&mut frame[..]);
+                eth.emit(&mut eth_frame);
+                eth_frame.payload_mut().copy_from_slice(packet);
+                push_bounded(&INBOUND, frame);
+            }
+            // IPCP is not opened yet
+            PROTO_IPV4 => {}
+            _ if self.lcp.is_opened() => {
+                // at most the MRU of the peer, which is not below the default
+                let mut data = pkt.payload.to_vec();
+                data.truncate(PPPOE_MTU - 4);
+                let id = self.next_id();
+                self.send_control(PROTO_LCP, PROTO_REJ, id, &data);
+            }
+            _ => {}
+        }
+    }
+
+    fn send_lcp_request(&mut self, now: u64) {
+        let mut data = Vec::new();
+        if !self.mru_rejected {
+            push_option(&mut data, LCP_MRU, &self.mru.to_be_bytes());
+        }
+        if !self.magic_rejected {
+            push_option(&mut data, LCP_MAGIC, &self.magic.to_be_bytes());
+        }
+        self.lcp.req_id = self.next_id();
+        self.send_control(PROTO_LCP, CONF_REQ, self.lcp.req_id, &data);
+        self.sent(now);
+    }
+
+    fn handle_lcp(&mut self, packet: &[u8], now: u64) {
+        let Some(pkt) = Control::parse(packet) else {
+            return;
+        };
+        match pkt.code {
+            CONF_REQ => {
+                if self.phase != PppPhase::Establish {
+                    // the peer renegotiates the link
+                    self.network_down();
+                    self.phase = PppPhase::Establish;
+                    self.lcp = Negotiation::default();
+                    self.ipcp = Negotiation::default();
+                    self.retries = 0;
+                    self.send_lcp_request(now);
+                }
+                let mut reply = Reply::default();
+                let mut auth = None;
+                for (ty, value) in pkt.options() {
+                    // whether to suggest PAP instead of the authentication
+                    // protocol of the peer
+                    let suggest_pap = match (ty, value.len()) {
+                        (LCP_MRU, 2) | (LCP_MAGIC, 4) => false,
+                        (LCP_AUTH, 2) if value == PROTO_PAP.to_be_bytes() => {
+                            auth = Some(Auth::Pap);
+                            false
+                        }
+                        (LCP_AUTH, 3) if value[..2] == PROTO_CHAP.to_be_bytes() => {
+                            auth = Some(Auth::Chap);
+                            value[2] != CHAP_MD5
+                        }
+                        (LCP_AUTH, _) => true,
+                        _ => {
+                            push_option(&mut reply.reject, ty, value);
+                            continue;
+                        }
+                    };
+                    push_option(&mut reply.ack, ty, value);
+                    if suggest_pap {
+                        push_option(&mut reply.nak, ty, &PROTO_PAP.to_be_bytes());
+                    }
+                }
+                let (code, data) = reply.into_packet();
+                self.send_control(PROTO_LCP, code, pkt.id, &data);
+                if code == CONF_ACK {
+                    self.auth = auth;
+                    self.lcp.acked_peer = true;
+                    self.lcp_opened(now);
+                }
+            }
+            CONF_ACK if pkt.id == self.lcp.req_id && self.phase == PppPhase::Establish => {
+                self.lcp.acked_by_peer = true;
+                self.lcp_opened(now);
+            }
+            CONF_NAK if pkt.id == self.lcp.req_id && self.phase == PppPhase::Establish => {
+                for (ty, value) in pkt.options() {
+                    match (ty, value.len()) {
+                        (LCP_MRU, 2) => {
+                            let mru = u16::from_be_bytes([value[0], value[1]]);
+                            self.mru = mru.min(PPPOE_MTU as u16);
+                        }
+                        (LCP_MAGIC, 4) => self.magic = axhal::misc::random() as u32,
+                        _ => {}
+                    }
+                }
+                self.send_lcp_request(now);
+            }
+            CONF_REJ if pkt.id == self.lcp.req_id && self.phase == PppPhase::Establish => {
+                for (ty, _) in pkt.options() {
+                    match ty {
+                        LCP_MRU => self.mru_rejected = true,
+                        LCP_MAGIC => self.magic_rejected = true,
+                        _ => {}
+                    }
+                }
+                self.send_lcp_request(now);
+            }
+            TERM_REQ => {
+                self.send_control(PROTO_LCP, TERM_ACK, pkt.id, &[]);
+                self.fail(now, "link terminated by the peer");
+            }
+            ECHO_REQ if self.lcp.is_opened() => {
+                let mut data = self.magic.to_be_bytes().to_vec();
+                data.extend_from_slice(pkt.data.get(4..).unwrap_or_default());
+                self.send_control(PROTO_LCP, ECHO_REPLY, pkt.id, &data);
+            }
+            PROTO_REJ if pkt.data.get(..2) == Some(&PROTO_IPCP.to_be_bytes()[..]) => {
+                self.fail(now, "IPCP rejected by the peer");
+            }
+            _ => {}
+        }
+    }
+
+    fn lcp_opened(&mut self, now: u64) {
+        if !self.lcp.is_opened() {
+            return;
+        }
+        self.retries = 0;
+        match self.auth {
+            Some(auth) => {
+                self.phase = PppPhase::Authenticate;
+                if auth == Auth::Pap {
+                    self.send_pap_request(now);
+                } else {
+                    // wait for the challenge
+                    self.last_sent = now;
+                }
+            }
+            None => self.start_network(now),
+        }
+    }
+
+    fn send_pap_request(&mut self, now: u64) {
+        let (user, password) = (
+            self.config.username.as_bytes(),
+            self.config.password.as_bytes(),
+        );
+        let mut data = Vec::with_capacity(2 + user.len() + password.len());
+        data.push(user.len() as u8);
+        data.extend_from_slice(user);
+        data.push(password.len() as u8);
+        data.extend_from_slice(password);
+        self.auth_id = self.next_id();
+        self.send_control(PROTO_PAP, PAP_REQ, self.auth_id, &data);
+        self.sent(now);
+    }
+
+    fn handle_pap(&mut self, packet: &[u8], now: u64) {
+        let Some(pkt) = Control::parse(packet) else {
+            return;
+        };
+        if self.phase != PppPhase::Authenticate || pkt.id != self.auth_id {
+            return;
+        }
+        match pkt.code {
+            PAP_ACK => self.start_network(now),
+            PAP_NAK => self.fail(now, "PAP authentication failed"),
+            _ => {}
+        }
+    }
+
+    fn handle_chap(&mut self, packet: &[u8], now: u64) {
+        let Some(pkt) = Control::parse(packet) else {
+            return;
+        };
+        if self.auth != Some(Auth::Chap) || !self.lcp.is_opened() {
+            return;
+        }
+        match pkt.code {
+            // also sent again while the link is up
+            CHAP_CHALLENGE => {
+                let Some((&size, rest)) = pkt.data.split_first() else {
+                    return;
+                };
+                let Some(challenge) = rest.get(..size as usize) else {
+                    return;
+                };
+                let hash = Md5::new()
+                    .chain_update([pkt.id])
+                    .chain_update(self.config.password.as_bytes())
+                    .chain_update(challenge)
+                    .finalize();
+                let mut data = vec![hash.len() as u8];
+                data.extend_from_slice(&hash);
+                data.extend_from_slice(self.config.username.as_bytes());
+                self.auth_id = pkt.id;
+                self.send_control(PROTO_CHAP, CHAP_RESPONSE, pkt.id, &data);
+            }
+            CHAP_SUCCESS if self.phase == PppPhase::Authenticate && pkt.id == self.auth_id => {
+                self.start_network(now)
+            }
+            CHAP_FAILURE if pkt.id == self.auth_id => self.fail(now, "CHAP authentication failed"),
+            _ => {}
+        }
+    }
+
+    fn start_network(&mut self, now: u64) {
+        self.phase = PppPhase::Network;
+        self.retries = 0;
+        self.send_ipcp_request(now);
+    }
+
+    fn send_ipcp_request(&mut self, now: u64) {
+        let mut data = Vec::new();
+        push_option(&mut data, IPCP_ADDR, &self.local.0);
+        for (i, option) in [IPCP_DNS1, IPCP_DNS2].into_iter().enumerate() {
+            if !self.dns_rejected[i] {
+                let dns = self.dns[i].unwrap_or(Ipv4Address::UNSPECIFIED);
+                push_option(&mut data, option, &dns.0);
+            }
+        }
+        self.ipcp.req_id = self.next_id();
+        self.send_control(PROTO_IPCP, CONF_REQ, self.ipcp.req_id, &data);
+        self.sent(now);
+    }
+
+    fn handle_ipcp(&mut self, packet: &[u8], now: u64) {
+        let Some(pkt) = Control::parse(packet) else {
+            return;
+        };
+        if !matches!(self.phase, PppPhase::Network | PppPhase::Opened) {
+            return;
+        }
+        match pkt.code {
+            CONF_REQ => {
+                if self.phase == PppPhase::Opened {
+                    // the peer renegotiates the addresses
+                    self.network_down();
+                    self.phase = PppPhase::Network;
+                    self.ipcp = Negotiation::default();
+                    self.retries = 0;
+                    self.send_ipcp_request(now);
+                }
+                let mut reply = Reply::default();
+                let mut peer = None;
+                for (ty, value) in pkt.options() {
+                    match (ty, ipv4(value)) {
+                        (IPCP_ADDR, Some(addr)) => {
+                            peer = Some(addr);
+                            push_option(&mut reply.ack, ty, value);
+                        }
+                        _ => push_option(&mut reply.reject, ty, value),
+                    }
+                }
+                let (code, data) = reply.into_packet();
+                self.send_control(PROTO_IPCP, code, pkt.id, &data);
+                if code == CONF_ACK {
+                    self.peer = peer;
+                    self.ipcp.acked_peer = true;
+                    self.ipcp_opened(now);
+                }
+            }
+            CONF_ACK if pkt.id == self.ipcp.req_id && self.phase == PppPhase::Network => {
+                self.ipcp.acked_by_peer = true;
+                self.ipcp_opened(now);
+            }
+            CONF_NAK if pkt.id == self.ipcp.req_id && self.phase == PppPhase::Network => {
+                for (ty, value) in pkt.options() {
+                    match (ty, ipv4(value)) {
+                        (IPCP_ADDR, Some(addr)) => self.local = addr,
+                        (IPCP_DNS1 | IPCP_DNS2, Some(addr)) => self.dns[dns_index(ty)] = Some(addr),
+                        _ => {}
+                    }
+                }
+                self.send_ipcp_request(now);
+            }
+            CONF_REJ if pkt.id == self.ipcp.req_id && self.phase == PppPhase::Network => {
+                for (ty, _) in pkt.options() {
+                    match ty {
+                        IPCP_ADDR => return self.fail(now, "the peer gives no address"),
+                        IPCP_DNS1 | IPCP_DNS2 => self.dns_rejected[dns_index(ty)] = true,
+                        _ => {}
+                    }
+                }
+                self.send_ipcp_request(now);
+            }
+            TERM_REQ => {
+                self.send_control(PROTO_IPCP, TERM_ACK, pkt.id, &[]);
+                self.fail(now, "IPCP terminated by the peer");
+            }
+            _ => {}
+        }
+    }
+
+    fn ipcp_opened(&mut self, now: u64) {
+        if !self.ipcp.is_opened() {
+            return;
+        }
+        if self.local.is_unspecified() {
+            return self.fail(now, "the peer gives no address");
+        }
+        // everything outside the subnets of the other interfaces goes
+        // through the link
+        if let Err(e) = ETH0.add_ip_addr(Ipv4Cidr::new(self.local, 0).into()) {
+            warn!("ppp0: failed to add the address: {:?}", e);
+            return self.fail(now, "no room for the address");
+        }
+        *UP.write() = Some(self.local);
+        self.phase = PppPhase::Opened;
+        self.opened_at = Some(now);
+        self.last_sent = now;
+        self.echo_missed = 0;
+        self.backoff = BACKOFF_MIN;
+        info!("created net interface \"ppp0\":");
+        info!("  ip:       {}", self.local);
+        if let Some(peer) = self.peer {
+            info!("  peer:     {}", peer);
+        }
+        for dns in self.dns.iter().flatten() {
+            info!("  dns:      {}", dns);
+        }
+    }
+
+    /// Retransmits the requests without answer, sends the echoes, and
+    /// reconnects.
+    fn update_timers(&mut self, now: u64) {
+        if self.phase == PppPhase::Reconnecting {
+            if now >= self.reconnect_at {
+                info!("ppp0: reconnecting (attempt {})", self.reconnects);
+                self.start_discovery(now);
+            }
+            return;
+        }
+        let interval = if self.phase == PppPhase::Opened {
+            ECHO_INTERVAL
+        } else {
+            RESTART_TIME
+        };
+        if now - self.last_sent < interval {
+            return;
+        }
+        let max = match self.phase {
+            PppPhase::Discovery => MAX_DISCOVERY,
+            _ => MAX_CONFIGURE,
+        };
+        match self.phase {
+            PppPhase::Opened => {
+                if self.echo_missed >= MAX_ECHO_MISSED {
+                    return self.fail(now, "no answer to the echoes");
+                }
+                self.echo_missed += 1;
+                let id = self.next_id();
+                self.send_control(PROTO_LCP, ECHO_REQ, id, &self.magic.to_be_bytes());
+                self.last_sent = now;
+            }
+            _ if self.retries >= max => {
+                let reason = match self.phase {
+                    PppPhase::Discovery if self.ac.is_none() => "no access concentrator",
+                    PppPhase::Discovery => "no session from the access concentrator",
+                    PppPhase::Authenticate => "no answer to the authentication",
+                    _ => "negotiation timed out",
+                };
+                self.fail(now, reason);
+            }
+            PppPhase::Discovery if self.ac.is_none() => self.send_padi(now),
+            PppPhase::Discovery => self.send_padr(now),
+            PppPhase::Establish if !self.lcp.acked_by_peer => self.send_lcp_request(now),
+            PppPhase::Authenticate if self.auth == Some(Auth::Pap) => self.send_pap_request(now),
+            PppPhase::Network if !self.ipcp.acked_by_peer => self.send_ipcp_request(now),
+            // waiting for the peer
+            _ => self.sent(now),
+        }
+    }
+}
+
+/// Returns whether the link is opened.
+pub(crate) fn is_up() -> bool {
+    UP.read().is_some()
+}
+
+/// Returns whether the session runs on `port`.
+pub(crate) fn is_port(port: usize) -> bool {
+    LINK.lock().as_ref().is_some_and(|link| link.port == port)
+}
+
+/// Handles a PPPoE frame received on a port other than [`STACK_PORT`], and
+/// returns whether it was for the session.
+///
+/// Called from [`ports::poll`].
+pub(crate) fn input(port: usize, frame: &[u8]) -> bool {
+    let Some(pkt) = Pppoe::parse(frame) else {
+        return false;
+    };
+    let mut link = LINK.lock();
+    let Some(link) = link.as_mut().filter(|link| link.port == port) else {
+        return false;
+    };
+    let now = monotonic_time_nanos();
+    match pkt.ethertype {
+        ETHERTYPE_DISCOVERY => link.handle_discovery(&pkt, now),
+        _ => link.handle_session(&pkt, now),
+    }
+    true
+}
+
+/// Takes a frame sent by the stack if it goes through the link, and returns
+/// whether it did.
+///
+/// Called from the transmit path of the stack.
+pub(crate) fn output(frame: &SkBuff) -> bool {
+    let Some(local) = *UP.read() else {
+        return false;
+    };
+    let Ok(eth) = EthernetFrame::new_checked(frame.data()) else {
+        return false;
+    };
+    match eth.ethertype() {
+        EthernetProtocol::Arp => {
+            let Ok(arp) = ArpPacket::new_checked(eth.payload()) else {
+                return false;
+            };
+            // the stack resolves the hosts it sends to from the address of
+            // the link, all behind the peer
+            let Ok(ArpRepr::EthernetIpv4 {
+                operation: ArpOperation::Request,
+                source_hardware_addr,
+                source_protocol_addr,
+                target_protocol_addr,
+                ..
+            }) = ArpRepr::parse(&arp)
+            else {
+                return false;
+            };
+            if source_protocol_addr != local || target_protocol_addr == local {
+                return false;
+            }
+            let reply = ArpRepr::EthernetIpv4 {
+                operation: ArpOperation::Reply,
+                source_hardware_addr: PPP_MAC,
+                source_protocol_addr: target_protocol_addr,
+                target_hardware_addr: source_hardware_addr,
+                target_protocol_addr: source_protocol_addr,
+            };
+            let frame = neighbor::arp_frame(source_hardware_addr, PPP_MAC, reply);
+            push_bounded(&INBOUND, frame);
+            true
+        }
+        EthernetProtocol::Ipv4 if eth.dst_addr() == PPP_MAC => {
+            let Ok(ip) = Ipv4Packet::new_checked(eth.payload()) else {
+                return false;
+            };
+            // the PPP headers take the place of the Ethernet one
+            let len = (ip.total_len() as usize).min(eth.payload().len());
+            let mut packet = frame.clone();
+            packet.pull(frame.len() - eth.payload().len());
+            packet.trim(len);
+            push_bounded(&OUTBOUND, packet);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Takes the next frame from the link for the stack.
+pub(crate) fn take_inbound() -> Option<Vec<u8>> {
+    INBOUND.lock().pop_front()
+}
+
+/// Sends what the stack queued for the link, and runs its timers. Returns
+/// whether there is new work for the stack.
+///
+/// Called after polling the stack, without any lock held.
+pub(crate) fn poll() -> bool {
+    let mut link = LINK.lock();
+    let Some(link) = link.as_mut() else {
+        return false;
+    };
+    let packets: Vec<_> = OUTBOUND.lock().drain(..).collect();
+    if link.phase == PppPhase::Opened {
+        for packet in packets {
+            link.send(PROTO_IPV4, packet);
+        }
+    }
+    link.update_timers(monotonic_time_nanos());
+    !INBOUND.lock().is_empty()
+}
+
+/// Brings up the PPPoE client interface `ppp0` on the NIC `port`.
+///
+/// The link is opened in the background, by [`poll_interfaces`], and kept
+/// up until [`ppp_down`]; see [`ppp_status`] for its progress.
+///
+/// Returns an error if a session is already up, or if the NIC is `eth0` or
+/// forwards frames for the bridge or the NAT.
+///
+/// [`poll_interfaces`]: super::poll_interfaces
+pub fn ppp_up(port: &str, config: PppConfig) -> AxResult {
+    let index = ports::port_index(port)?;
+    if index == STACK_PORT || config.username.len() > 255 || config.password.len() > 255 {
+        return ax_err!(InvalidInput, "invalid PPPoE configuration");
+    }
+    if bridge::is_member(index) {
+        return ax_err!(ResourceBusy, "interface used by the bridge");
+    }
+    if nat::is_lan_port(index) {
+        return ax_err!(ResourceBusy, "interface used by NAT");
+    }
+    let mut link = LINK.lock();
+    if link.is_some() {
+        return ax_err!(AlreadyExists, "ppp0 is already up");
+    }
+    let new = link.insert(Link::new(index, config));
+    new.start_discovery(monotonic_time_nanos());
+    info!("ppp0: looking for an access concentrator on {:?}", port);
+    Ok(())
+}
+
+/// Closes the session and brings down `ppp0`.
+pub fn ppp_down() -> AxResult {
+    let Some(mut link) = LINK.lock().take() else {
+        return ax_err!(NotFound, "ppp0 is not up");
+    };
+    if link.lcp.is_opened() {
+        let id = link.next_id();
+        link.send_control(PROTO_LCP, TERM_REQ, id, &[]);
+    }
+    link.send_padt();
+    link.network_down();
+    INBOUND.lock().clear();
+    Ok(())
+}
+
+/// Returns the state of `ppp0`, if it is up.
+pub fn ppp_status() -> Option<PppStatus> {
+    let now = monotonic_time_nanos();
+    let link = LINK.lock();
+    let link = link.as_ref()?;
+    let opened = link.phase == PppPhase::Opened;
+    Some(PppStatus {
+        port: ports::port_name(link.port),
+        phase: link.phase,
+        local_addr: opened.then(|| Ipv4Addr::from(link.local.0)),
+        peer_addr: link
+            .peer
+            .filter(|_| opened)
+            .map(|addr| Ipv4Addr::from(addr.0)),
+        dns_servers: link
+            .dns
+            .iter()
+            .flatten()
+            .map(|addr| Ipv4Addr::from(addr.0))
+            .collect(),
+        session_id: (link.session_id != 0).then_some(link.session_id),
+        ac_name: link.ac.as_ref().and_then(|ac| ac.name.clone()),
+        reconnects: link.reconnects,
+        uptime: link
+            .opened_at
+            .map(|opened_at| Duration::from_nanos(now - opened_at)),
+    })
+}
@@ -0,0 +1,242 @@
+//! Egress traffic shaping and priority queues.
+//!
+//! Once a rule or a rate is configured, each frame sent by the network stack
+//! on `eth0` is classified into a [`TrafficClass`]:
+//!
+//! - by the DSCP of IPv4 packets, see [`qos_set_dscp_class`];
+//! - else by the local TCP or UDP port, see [`qos_set_port_class`], which
+//!   classifies the traffic of the sockets bound to it;
+//! - else ARP is [`TrafficClass::Control`], and the rest is
+//!   [`TrafficClass::Normal`].
+//!
+//! Each class has its own queue, drained in strict priority order, and may
+//! be limited by a token bucket ([`qos_set_rate`]). A class out of tokens
+//! does not hold back the lower ones. Frames are dropped when the queue of
+//! their class is full, which TCP recovers from by retransmitting.
+
+use alloc::{collections::BTreeMap, collections::VecDeque};
+
+use axdriver::prelude::*;
+use axhal::time::{monotonic_time_nanos, NANOS_PER_SEC};
+use smoltcp::wire::{
+    EthernetFrame, EthernetProtocol, IpProtocol, Ipv4Packet, TcpPacket, UdpPacket,
+};
+use spin::Mutex;
+
+use super::send_frame;
+use crate::skb::SkBuff;
+
+/// Maximum number of frames queued per class.
+const QUEUE_LEN: usize = 256;
+
+/// The priority of outgoing traffic, from the highest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TrafficClass {
+    /// Control-plane traffic, always sent first.
+    Control = 0,
+    /// The default class.
+    Normal = 1,
+    /// Bulk transfers, sent when nothing else is pending.
+    Bulk = 2,
+}
+
+const NUM_CLASSES: usize = 3;
+
+/// The rate limit of a class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QosRate {
+    /// Sustained rate, in bytes per second.
+    pub bytes_per_sec: u64,
+    /// Bytes that may be sent at once after an idle period.
+    pub burst: u64,
+}
+
+/// Statistics of a traffic class.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct QosClassStats {
+    /// Frames sent.
+    pub sent_frames: u64,
+    /// Bytes sent.
+    pub sent_bytes: u64,
+    /// Frames dropped as the queue was full.
+    pub dropped_frames: u64,
+    /// Frames waiting in the queue.
+    pub queued_frames: usize,
+}
+
+struct Class {
+    queue: VecDeque<SkBuff>,
+    rate: Option<QosRate>,
+    tokens: u64,
+    updated: u64,
+    stats: QosClassStats,
+}
+
+impl Class {
+    fn refill(&mut self, now: u64) {
+        if let Some(rate) = self.rate {
+            let elapsed = now - self.updated;
+            let earned =
+                (elapsed as u128 * rate.bytes_per_sec as u128 / NANOS_PER_SEC as u128) as u64;
+            self.tokens = (self.tokens + earned).min(rate.burst);
+        }
+        self.updated = now;
+    }
+
+    fn may_send(&self, len: usize) -> bool {
+        // a frame larger than the burst would never fit
+        self.rate
+            .map_or(true, |rate| self.tokens >= (len as u64).min(rate.burst))
+    }
+}
+
+struct Qos {
+    classes: [Class; NUM_CLASSES],
+    dscp_rules: BTreeMap<u8, TrafficClass>,
+    port_rules: BTreeMap<u16, TrafficClass>,
+}
+
+impl Qos {
+    fn is_enabled(&self) -> bool {
+        !self.dscp_rules.is_empty()
+            || !self.port_rules.is_empty()
+            || self.classes.iter().any(|class| class.rate.is_some())
+    }
+
+    fn classify(&self, frame: &[u8]) -> TrafficClass {
+        let Ok(eth) = EthernetFrame::new_checked(frame) else {
+            return TrafficClass::Normal;
+        };
+        match eth.ethertype() {
+            EthernetProtocol::Arp => return TrafficClass::Control,
+            EthernetProtocol::Ipv4 => {}
+            _ => return TrafficClass::Normal,
+        }
+        let Ok(ip) = Ipv4Packet::new_checked(eth.payload()) else {
+            return TrafficClass::Normal;
+        };
+        if let Some(&class) = self.dscp_rules.get(&ip.dscp()) {
+            return class;
+        }
+        let src_port = match ip.next_header() {
+            IpProtocol::Tcp => TcpPacket::new_checked(ip.payload()).map(|tcp| tcp.src_port()),
+            IpProtocol::Udp => UdpPacket::new_checked(ip.payload()).map(|udp| udp.src_port()),
+            _ => return TrafficClass::Normal,
+        };
+        src_port
+            .ok()
+            .and_then(|port| self.port_rules.get(&port).copied())
+            .unwrap_or(TrafficClass::Normal)
+    }
+
+    /// Sends the queued frames the device and the rate limits allow, highest
+    /// class first.
+    fn flush(&mut self, dev: &mut AxNetDevice) {
+        let now = monotonic_time_nanos();
+        for class in self.classes.iter_mut() {
+            class.refill(now);
+            while let Some(frame) = class.queue.front() {
+                if !dev.can_transmit() {
+                    return;
+                }
+                if !class.may_send(frame.len()) {
+                    break;
+                }
+                let frame = class.queue.pop_front().unwrap();
+                class.tokens = class.tokens.saturating_sub(frame.len() as u64);
+                class.stats.sent_frames += 1;
+                class.stats.sent_bytes += frame.len() as u64;
+                send_frame(dev, frame.data());
+            }
+        }
+    }
+}
+
+static QOS: Mutex<Qos> = Mutex::new(Qos {
+    classes: [empty_class(), empty_class(), empty_class()],
+    dscp_rules: BTreeMap::new(),
+    port_rules: BTreeMap::new(),
+});
+
+const fn empty_class() -> Class {
+    Class {
+        queue: VecDeque::new(),
+        rate: None,
+        tokens: 0,
+        updated: 0,
+        stats: QosClassStats {
+            sent_frames: 0,
+            sent_bytes: 0,
+            dropped_frames: 0,
+            queued_frames: 0,
+        },
+    }
+}
+
+/// Returns whether frames go through the queues, and so must be built
+/// outside of the buffers of the device.
+pub(crate) fn is_enabled() -> bool {
+    QOS.lock().is_enabled()
+}
+
+/// Queues a frame sent by the stack in its class, then sends what can be.
+///
+/// Called from the transmit path of the stack.
+pub(crate) fn transmit(dev: &mut AxNetDevice, frame: SkBuff) {
+    let mut qos = QOS.lock();
+    if !qos.is_enabled() {
+        return send_frame(dev, frame.data());
+    }
+    let index = qos.classify(frame.data()) as usize;
+    let class = &mut qos.classes[index];
+    if class.queue.len() < QUEUE_LEN {
+        class.queue.push_back(frame);
+    } else {
+        class.stats.dropped_frames += 1;
+    }
+    qos.flush(dev);
+}
+
+/// Sends the queued frames that can be, e.g. once the device has room again
+/// or the buckets refilled.
+pub(crate) fn flush(dev: &mut AxNetDevice) {
+    QOS.lock().flush(dev);
+}
+
+/// Sets the class of the IPv4 packets with a DSCP, or removes its rule.
+pub fn qos_set_dscp_class(dscp: u8, class: Option<TrafficClass>) {
+    let mut qos = QOS.lock();
+    match class {
+        Some(class) => qos.dscp_rules.insert(dscp & 0x3f, class),
+        None => qos.dscp_rules.remove(&(dscp & 0x3f)),
+    };
+}
+
+/// Sets the class of the TCP and UDP traffic from a local port, or removes
+/// its rule.
+pub fn qos_set_port_class(port: u16, class: Option<TrafficClass>) {
+    let mut qos = QOS.lock();
+    match class {
+        Some(class) => qos.port_rules.insert(port, class),
+        None => qos.port_rules.remove(&port),
+    };
+}
+
+/// Limits the rate of a class, or removes its limit.
+pub fn qos_set_rate(class: TrafficClass, rate: Option<QosRate>) {
+    let mut qos = QOS.lock();
+    let class = &mut qos.classes[class as usize];
+    class.rate = rate;
+    class.tokens = rate.map_or(0, |rate| rate.burst);
+    class.updated = monotonic_time_nanos();
+}
+
+/// Returns the statistics of a class.
+pub fn qos_stats(class: TrafficClass) -> QosClassStats {
+    let qos = QOS.lock();
+    let class = &qos.classes[class as usize];
+    QosClassStats {
+        queued_frames: class.queue.len(),
+        ..class.stats
+    }
+}
@@ -0,0 +1,283 @@
+//! Socket statistics, like `netstat` or `/proc/net/tcp` on Linux.
+//!
+//! smoltcp does not count retransmissions, so outgoing TCP segments are
+//! watched: a segment starting before the highest sequence number already
+//! sent on its connection is a retransmission.
+
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use core::fmt::Write;
+use core::net::SocketAddr;
+
+use smoltcp::socket::{tcp, Socket};
+use smoltcp::wire::{
+    EthernetFrame, EthernetProtocol, IpEndpoint, IpProtocol, Ipv4Packet, TcpPacket, TcpSeqNumber,
+};
+use spin::Mutex;
+
+use super::addr::{into_core_sockaddr, UNSPECIFIED_IP};
+use super::{LISTEN_TABLE, SOCKET_SET};
+
+/// Maximum number of connections whose retransmissions are counted.
+const MAX_FLOWS: usize = 1024;
+
+/// The transport protocol of a socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketProtocol {
+    /// TCP.
+    Tcp,
+    /// UDP.
+    Udp,
+}
+
+/// The state of a socket, as in the TCP state machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketState {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+    /// A bound UDP socket.
+    Bound,
+}
+
+impl SocketState {
+    /// The state number used by Linux in `/proc/net/tcp`.
+    fn linux_code(self) -> u8 {
+        match self {
+            Self::Established => 1,
+            Self::SynSent => 2,
+            Self::SynReceived => 3,
+            Self::FinWait1 => 4,
+            Self::FinWait2 => 5,
+            Self::TimeWait => 6,
+            Self::Closed | Self::Bound => 7,
+            Self::CloseWait => 8,
+            Self::LastAck => 9,
+            Self::Listen => 10,
+            Self::Closing => 11,
+        }
+    }
+}
+
+impl From<tcp::State> for SocketState {
+    fn from(state: tcp::State) -> Self {
+        match state {
+            tcp::State::Closed => Self::Closed,
+            tcp::State::Listen => Self::Listen,
+            tcp::State::SynSent => Self::SynSent,
+            tcp::State::SynReceived => Self::SynReceived,
+            tcp::State::Established => Self::Established,
+            tcp::State::FinWait1 => Self::FinWait1,
+            tcp::State::FinWait2 => Self::FinWait2,
+            tcp::State::CloseWait => Self::CloseWait,
+            tcp::State::Closing => Self::Closing,
+            tcp::State::LastAck => Self::LastAck,
+            tcp::State::TimeWait => Self::TimeWait,
+        }
+    }
+}
+
+/// The state of a socket, see [`socket_stats`].
+#[derive(Debug, Clone)]
+pub struct SocketStats {
+    pub protocol: SocketProtocol,
+    pub state: SocketState,
+    pub local_addr: SocketAddr,
+    /// The peer, for TCP connections.
+    pub remote_addr: Option<SocketAddr>,
+    /// Bytes received and not read yet, or for a listener, the connections
+    /// waiting for `accept()`.
+    pub recv_queue: usize,
+    /// Bytes written and not acknowledged yet, or for a listener, its
+    /// backlog.
+    pub send_queue: usize,
+    /// TCP segments sent again.
+    pub retransmits: u64,
+}
+
+/// The local port and the peer of a connection.
+type FlowKey = (u16, IpEndpoint);
+
+struct Flow {
+    /// The end of the highest segment sent.
+    next_seq: TcpSeqNumber,
+    retransmits: u64,
+}
+
+static FLOWS: Mutex<BTreeMap<FlowKey, Flow>> = Mutex::new(BTreeMap::new());
+
+/// Counts the retransmission in a frame sent by the stack.
+pub(crate) fn inspect_tx(frame: &[u8]) {
+    let Ok(eth) = EthernetFrame::new_checked(frame) else {
+        return;
+    };
+    if eth.ethertype() != EthernetProtocol::Ipv4 {
+        return;
+    }
+    let Ok(ip) = Ipv4Packet::new_checked(eth.payload()) else {
+        return;
+    };
+    if ip.next_header() != IpProtocol::Tcp {
+        return;
+    }
+    let Ok(tcp) = TcpPacket::new_checked(ip.payload()) else {
+        return;
+    };
+    let key = (
+        tcp.src_port(),
+        IpEndpoint::new(ip.dst_addr().into(), tcp.dst_port()),
+    );
+    let mut flows = FLOWS.lock();
+    if tcp.rst() {
+        flows.remove(&key);
+        return;
+    }
+    // SYN and FIN take a sequence number each
+    let seg_len = tcp.payload().len() + tcp.syn() as usize + tcp.fin() as usize;
+    if seg_len == 0 {
+        return;
+    }
+    let seq = tcp.seq_number();
+    let end = seq + seg_len;
+    if let Some(flow) = flows.get_mut(&key) {
+        if tcp.syn() && flow.next_seq != end {
+            // a new connection on the same ports
+            *flow = Flow {
+                next_seq: end,
+                retransmits: 0,
+            };
+        } else if seq < flow.next_seq {
+            flow.retransmits += 1;
+            if end > flow.next_seq {
+                flow.next_seq = end;
+            }
+        } else {
+            flow.next_seq = end;
+        }
+    } else {
+        if flows.len() >= MAX_FLOWS {
+            flows.pop_first();
+        }
+        flows.insert(
+            key,
+            Flow {
+                next_seq: end,
+                retransmits: 0,
+            },
+        );
+    }
+}
+
+/// Returns the state of the TCP and UDP sockets, and of the listening TCP
+/// ports.
+pub fn socket_stats() -> Vec<SocketStats> {
+    let mut stats = LISTEN_TABLE.listeners();
+    let mut keys = Vec::new();
+    for (_, socket) in SOCKET_SET.0.lock().iter() {
+        match socket {
+            Socket::Tcp(socket) => {
+                let (Some(local), Some(remote)) =
+                    (socket.local_endpoint(), socket.remote_endpoint())
+                else {
+                    continue;
+                };
+                keys.push(Some((local.port, remote)));
+                stats.push(SocketStats {
+                    protocol: SocketProtocol::Tcp,
+                    state: socket.state().into(),
+                    local_addr: into_core_sockaddr(local),
+                    remote_addr: Some(into_core_sockaddr(remote)),
+                    recv_queue: socket.recv_queue(),
+                    send_queue: socket.send_queue(),
+                    retransmits: 0,
+                });
+            }
+            Socket::Udp(socket) => {
+                let endpoint = socket.endpoint();
+                if !endpoint.is_specified() {
+                    continue;
+                }
+                let local = IpEndpoint::new(endpoint.addr.unwrap_or(UNSPECIFIED_IP), endpoint.port);
+                keys.push(None);
+                stats.push(SocketStats {
+                    protocol: SocketProtocol::Udp,
+                    state: SocketState::Bound,
+                    local_addr: into_core_sockaddr(local),
+                    remote_addr: None,
+                    recv_queue: socket.recv_queue(),
+                    send_queue: socket.send_queue(),
+                    retransmits: 0,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    // forget the connections that are gone
+    let mut flows = FLOWS.lock();
+    flows.retain(|key, _| keys.contains(&Some(*key)));
+    let listeners = stats.len() - keys.len();
+    for (stat, key) in stats[listeners..].iter_mut().zip(&keys) {
+        if let Some(flow) = key.and_then(|key| flows.get(&key)) {
+            stat.retransmits = flow.retransmits;
+        }
+    }
+    stats
+}
+
+/// Formats an address as in `/proc/net/tcp`, in hexadecimal with the IP in
+/// host byte order.
+fn proc_addr(addr: Option<SocketAddr>) -> String {
+    match addr {
+        Some(SocketAddr::V4(addr)) => {
+            format!(
+                "{:08X}:{:04X}",
+                u32::from_le_bytes(addr.ip().octets()),
+                addr.port()
+            )
+        }
+        _ => String::from("00000000:0000"),
+    }
+}
+
+fn proc_net(protocol: SocketProtocol) -> String {
+    let mut text = String::from(
+        "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n",
+    );
+    let stats = socket_stats();
+    for (i, stat) in stats.iter().filter(|s| s.protocol == protocol).enumerate() {
+        writeln!(
+            text,
+            "{:4}: {} {} {:02X} {:08X}:{:08X} 00:00000000 {:08X} {:5} {:8} {}",
+            i,
+            proc_addr(Some(stat.local_addr)),
+            proc_addr(stat.remote_addr),
+            stat.state.linux_code(),
+            stat.send_queue,
+            stat.recv_queue,
+            stat.retransmits,
+            0,
+            0,
+            0,
+        )
+        .unwrap();
+    }
+    text
+}
+
+/// Returns the TCP sockets in the format of `/proc/net/tcp` on Linux.
+pub fn proc_net_tcp() -> String {
+    proc_net(SocketProtocol::Tcp)
+}
+
+/// Returns the UDP sockets in the format of `/proc/net/udp` on Linux.
+pub fn proc_net_udp() -> String {
+    proc_net(SocketProtocol::Udp)
+}
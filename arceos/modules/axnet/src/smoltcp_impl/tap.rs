@@ -0,0 +1,76 @@
+//! TAP interface `tap0`, exchanging Ethernet frames with an application.
+//!
+//! It is a [port](super::ports) like the other NICs, so it carries the frames
+//! of the [bridge](super::bridge) or the [NAT](super::nat): the frames written
+//! by the application are received on it, and the frames sent on it wait for
+//! the application to read them. For example, with `tap0` and `eth0` in the
+//! bridge, a network stack in the application shares the link of the system.
+
+use alloc::{collections::VecDeque, vec::Vec};
+
+use axerrno::{ax_err, AxResult};
+use smoltcp::wire::{EthernetAddress, EthernetFrame};
+use spin::Mutex;
+
+/// The name of the interface.
+pub(crate) const TAP_NAME: &str = "tap0";
+/// The Ethernet address of the interface.
+pub(crate) const TAP_MAC: EthernetAddress = EthernetAddress([0x02, 0x74, 0x61, 0x70, 0, 0]);
+
+/// Maximum length of a frame, without the FCS.
+const MAX_FRAME_LEN: usize = 1514;
+/// Maximum number of frames queued in each direction.
+const QUEUE_LEN: usize = 256;
+
+/// Frames sent on the interface, for the application.
+static TO_APP: Mutex<VecDeque<Vec<u8>>> = Mutex::new(VecDeque::new());
+/// Frames written by the application, received at the next poll.
+static FROM_APP: Mutex<VecDeque<Vec<u8>>> = Mutex::new(VecDeque::new());
+
+/// Queues a frame sent on the interface for the application, dropping it if
+/// the application does not keep up.
+pub(crate) fn output(frame: &[u8]) {
+    let mut queue = TO_APP.lock();
+    if queue.len() < QUEUE_LEN {
+        queue.push_back(frame.to_vec());
+    }
+}
+
+/// Takes the next frame written by the application.
+pub(crate) fn take_input() -> Option<Vec<u8>> {
+    FROM_APP.lock().pop_front()
+}
+
+/// Writes a frame to `tap0`, as if it was received on the interface.
+///
+/// It is processed at the next [`poll_interfaces`](super::poll_interfaces).
+/// Fails with `WouldBlock` if too many frames are waiting.
+pub fn tap_write(frame: &[u8]) -> AxResult {
+    if frame.len() > MAX_FRAME_LEN || EthernetFrame::new_checked(frame).is_err() {
+        return ax_err!(InvalidInput, "invalid Ethernet frame");
+    }
+    let mut queue = FROM_APP.lock();
+    if queue.len() >= QUEUE_LEN {
+        return ax_err!(WouldBlock);
+    }
+    queue.push_back(frame.to_vec());
+    Ok(())
+}
+
+/// Reads the next frame sent on `tap0` into `buf`, and returns its length.
+///
+/// The frame is truncated if `buf` is too small. Fails with `WouldBlock` if
+/// there is no frame.
+pub fn tap_read(buf: &mut [u8]) -> AxResult<usize> {
+    let Some(frame) = TO_APP.lock().pop_front() else {
+        return ax_err!(WouldBlock);
+    };
+    let len = frame.len().min(buf.len());
+    buf[..len].copy_from_slice(&frame[..len]);
+    Ok(len)
+}
+
+/// Returns whether a frame sent on `tap0` is waiting to be read.
+pub fn tap_readable() -> bool {
+    !TO_APP.lock().is_empty()
+}
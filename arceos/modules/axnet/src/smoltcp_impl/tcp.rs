@@ -1,8 +1,11 @@
+use alloc::{vec, vec::Vec};
 use core::cell::UnsafeCell;
-use core::net::SocketAddr;
-use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use core::net::{IpAddr, SocketAddr};
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use core::time::Duration;
 
 use axerrno::{ax_err, ax_err_type, AxError, AxResult};
+use axhal::time::monotonic_time;
 use axio::PollState;
 use axsync::Mutex;
 
@@ -11,7 +14,7 @@ use smoltcp::socket::tcp::{self, ConnectError, State};
 use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
 
 use super::addr::{from_core_sockaddr, into_core_sockaddr, is_unspecified, UNSPECIFIED_ENDPOINT};
-use super::{SocketSetWrapper, ETH0, LISTEN_TABLE, SOCKET_SET};
+use super::{SocketSetWrapper, ETH0, LISTEN_QUEUE_SIZE, LISTEN_TABLE, SOCKET_SET};
 
 // State transitions:
 // CLOSED -(connect)-> BUSY -> CONNECTING -> CONNECTED -(shutdown)-> BUSY -> CLOSED
@@ -25,6 +28,85 @@ const STATE_CONNECTING: u8 = 2;
 const STATE_CONNECTED: u8 = 3;
 const STATE_LISTENING: u8 = 4;
 
+/// Time to wait for the peer to answer a connection request, unless a user
+/// timeout is set.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(75);
+
+/// Time after which [`TcpSocket::connect_host`] tries the next address of the
+/// host, if the previous attempts are still pending.
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// TCP keepalive parameters.
+///
+/// smoltcp probes an idle connection at a fixed period, so probes are sent
+/// every `interval` of idleness, and `idle` only adds to the time the peer may
+/// stay silent: the connection is aborted once nothing was received from the
+/// peer for `idle + interval * count`, when Linux would give up too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpKeepAlive {
+    /// Idle time before the connection is considered for probing.
+    pub idle: Duration,
+    /// Interval between probes.
+    pub interval: Duration,
+    /// Number of unanswered probes before the connection is aborted.
+    pub count: u32,
+}
+
+impl TcpKeepAlive {
+    /// The Linux defaults: probe after 2 hours idle, every 75 seconds, and
+    /// give up after 9 probes.
+    pub const DEFAULT: Self = Self {
+        idle: Duration::from_secs(7200),
+        interval: Duration::from_secs(75),
+        count: 9,
+    };
+
+    /// Returns how long the peer may stay silent before the connection is
+    /// aborted.
+    fn limit(&self) -> Duration {
+        self.idle + self.interval * self.count
+    }
+}
+
+impl Default for TcpKeepAlive {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Options {
+    keepalive: Option<TcpKeepAlive>,
+    user_timeout: Option<Duration>,
+    nodelay: bool,
+}
+
+impl Options {
+    const DEFAULT: Self = Self {
+        keepalive: None,
+        user_timeout: None,
+        nodelay: false,
+    };
+
+    /// Applies the options to a smoltcp socket, which is connecting if
+    /// `connecting` is set.
+    fn apply(&self, socket: &mut tcp::Socket, connecting: bool) {
+        let keepalive_limit = self.keepalive.map(|k| k.limit());
+        let timeout = match (self.user_timeout, keepalive_limit) {
+            (Some(user), Some(limit)) => Some(user.min(limit)),
+            (user, limit) => user.or(limit),
+        };
+        let timeout = if connecting {
+            self.user_timeout.or(Some(CONNECT_TIMEOUT))
+        } else {
+            timeout
+        };
+        socket.set_keep_alive(self.keepalive.map(|k| k.interval.into()));
+        socket.set_timeout(timeout.map(Into::into));
+        socket.set_nagle_enabled(!self.nodelay);
+    }
+}
+
 /// A TCP socket that provides POSIX-like APIs.
 ///
 /// - [`connect`] is for TCP clients.
@@ -41,6 +123,8 @@ pub struct TcpSocket {
     local_addr: UnsafeCell<IpEndpoint>,
     peer_addr: UnsafeCell<IpEndpoint>,
     nonblock: AtomicBool,
+    options: Mutex<Options>,
+    backlog: AtomicUsize,
 }
 
 unsafe impl Sync for TcpSocket {}
@@ -54,6 +138,8 @@ impl TcpSocket {
             local_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT),
             peer_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT),
             nonblock: AtomicBool::new(false),
+            options: Mutex::new(Options::DEFAULT),
+            backlog: AtomicUsize::new(LISTEN_QUEUE_SIZE),
         }
     }
 
@@ -69,6 +155,8 @@ impl TcpSocket {
             local_addr: UnsafeCell::new(local_addr),
             peer_addr: UnsafeCell::new(peer_addr),
             nonblock: AtomicBool::new(false),
+            options: Mutex::new(Options::DEFAULT),
+            backlog: AtomicUsize::new(LISTEN_QUEUE_SIZE),
         }
     }
 
@@ -115,6 +203,73 @@ impl TcpSocket {
         self.nonblock.store(nonblocking, Ordering::Release);
     }
 
+    /// Returns the keepalive parameters, or `None` if keepalive is disabled.
+    pub fn keepalive(&self) -> Option<TcpKeepAlive> {
+        self.options.lock().keepalive
+    }
+
+    /// Enables keepalive probes with the given parameters, or disables them
+    /// with `None` (the default).
+    ///
+    /// When the peer stops answering, the connection is aborted, and blocked
+    /// or later operations fail.
+    pub fn set_keepalive(&self, keepalive: Option<TcpKeepAlive>) -> AxResult {
+        if keepalive.is_some_and(|k| k.interval.is_zero() || k.count == 0) {
+            return ax_err!(InvalidInput, "invalid keepalive parameters");
+        }
+        self.options.lock().keepalive = keepalive;
+        self.update_options();
+        Ok(())
+    }
+
+    /// Returns the user timeout, or `None` if there is none.
+    pub fn user_timeout(&self) -> Option<Duration> {
+        self.options.lock().user_timeout
+    }
+
+    /// Sets the time the peer may leave sent data (or a connection request)
+    /// unacknowledged before the connection is aborted, like `TCP_USER_TIMEOUT`.
+    ///
+    /// With `None` (the default), sent data is retransmitted forever, and
+    /// connection requests time out after 75 seconds.
+    pub fn set_user_timeout(&self, timeout: Option<Duration>) -> AxResult {
+        if timeout.is_some_and(|t| t.is_zero()) {
+            return ax_err!(InvalidInput, "invalid user timeout");
+        }
+        self.options.lock().user_timeout = timeout;
+        self.update_options();
+        Ok(())
+    }
+
+    /// Returns whether small segments are sent at once (Nagle's algorithm is
+    /// disabled).
+    pub fn nodelay(&self) -> bool {
+        self.options.lock().nodelay
+    }
+
+    /// Disables Nagle's algorithm if `nodelay` is set, like `TCP_NODELAY`, so
+    /// that small segments are sent without waiting for the acknowledgement
+    /// of the previous ones.
+    pub fn set_nodelay(&self, nodelay: bool) {
+        self.options.lock().nodelay = nodelay;
+        self.update_options();
+    }
+
+    /// Sets the maximum number of established connections waiting for
+    /// [`accept`](Self::accept), at least 1.
+    ///
+    /// It applies to the next [`listen`](Self::listen), or at once if the
+    /// socket is listening.
+    pub fn set_backlog(&self, backlog: usize) {
+        let backlog = backlog.max(1);
+        self.backlog.store(backlog, Ordering::Release);
+        if self.is_listening() {
+            // SAFETY: `self.local_addr` should be initialized in a listening socket.
+            let local_port = unsafe { self.local_addr.get().read().port };
+            LISTEN_TABLE.set_backlog(local_port, backlog);
+        }
+    }
+
     /// Connects to the given address and port.
     ///
     /// The local port is generated automatically.
@@ -130,6 +285,7 @@ impl TcpSocket {
             let iface = &ETH0.iface;
             let (local_endpoint, remote_endpoint) = SOCKET_SET
                 .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                    self.options.lock().apply(socket, true);
                     socket
                         .connect(iface.lock().context(), remote_endpoint, bound_endpoint)
                         .or_else(|e| match e {
@@ -173,6 +329,61 @@ impl TcpSocket {
         }
     }
 
+    /// Connects to `host`, a host name or an IP address, and `port`.
+    ///
+    /// When the host has several addresses, they are raced as in "Happy
+    /// Eyeballs" (RFC 8305): a new attempt starts every
+    /// [`CONNECTION_ATTEMPT_DELAY`], or as soon as the previous ones failed,
+    /// and the first connection established wins. Only IPv4 is supported, so
+    /// the address families are not raced.
+    ///
+    /// The returned socket is blocking.
+    pub fn connect_host(host: &str, port: u16) -> AxResult<TcpSocket> {
+        let ips = match host.parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) => super::dns_query(host)?,
+        };
+        let mut addrs = ips.into_iter().map(|ip| SocketAddr::new(ip, port));
+        let mut attempts: Vec<TcpSocket> = Vec::new();
+        let mut next_attempt = monotonic_time();
+        let mut error = ax_err_type!(NotFound, "no address for the host");
+        loop {
+            if attempts.is_empty() || monotonic_time() >= next_attempt {
+                if let Some(addr) = addrs.next() {
+                    let socket = TcpSocket::new();
+                    socket.set_nonblocking(true);
+                    match socket.connect(addr) {
+                        Ok(()) | Err(AxError::WouldBlock) => attempts.push(socket),
+                        Err(e) => error = e,
+                    }
+                    next_attempt = monotonic_time() + CONNECTION_ATTEMPT_DELAY;
+                    continue;
+                } else if attempts.is_empty() {
+                    return Err(error);
+                }
+            }
+
+            SOCKET_SET.poll_interfaces();
+            let mut i = 0;
+            while i < attempts.len() {
+                attempts[i].poll_connect()?;
+                if attempts[i].is_connected() {
+                    // the other attempts are aborted when dropped
+                    let socket = attempts.swap_remove(i);
+                    socket.set_nonblocking(false);
+                    return Ok(socket);
+                } else if attempts[i].get_state() == STATE_CLOSED {
+                    attempts.swap_remove(i);
+                    error = ax_err_type!(ConnectionRefused, "socket connect() failed");
+                    next_attempt = monotonic_time();
+                } else {
+                    i += 1;
+                }
+            }
+            super::wait_or_time_out()?;
+        }
+    }
+
     /// Binds an unbound socket to the given address and port.
     ///
     /// If the given port is 0, it generates one automatically.
@@ -209,7 +420,7 @@ impl TcpSocket {
             unsafe {
                 (*self.local_addr.get()).port = bound_endpoint.port;
             }
-            LISTEN_TABLE.listen(bound_endpoint)?;
+            LISTEN_TABLE.listen(bound_endpoint, self.backlog.load(Ordering::Acquire))?;
             debug!("TCP socket listening on {}", bound_endpoint);
             Ok(())
         })
@@ -402,6 +613,23 @@ impl TcpSocket {
         self.get_state() == STATE_LISTENING
     }
 
+    /// Applies the keepalive parameters, the user timeout and `nodelay` to the
+    /// smoltcp socket, if there is one.
+    fn update_options(&self) {
+        let connecting = match self.get_state() {
+            STATE_CONNECTING => true,
+            STATE_CONNECTED => false,
+            _ => return, // applied on connect
+        };
+        // SAFETY: `self.handle` is initialized in a connecting or connected
+        // socket.
+        let handle = unsafe { self.handle.get().read().unwrap() };
+        let options = *self.options.lock();
+        SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+            options.apply(socket, connecting)
+        });
+    }
+
     fn bound_endpoint(&self) -> AxResult<IpListenEndpoint> {
         // SAFETY: no other threads can read or write `self.local_addr`.
         let local_addr = unsafe { self.local_addr.get().read() };
@@ -443,6 +671,10 @@ impl TcpSocket {
                     true
                 }
             });
+        if self.is_connected() {
+            // replace the connection request timeout
+            self.update_options();
+        }
         Ok(PollState {
             readable: false,
             writable,
@@ -485,7 +717,7 @@ impl TcpSocket {
                 SOCKET_SET.poll_interfaces();
                 match f() {
                     Ok(t) => return Ok(t),
-                    Err(AxError::WouldBlock) => axtask::yield_now(),
+                    Err(AxError::WouldBlock) => super::wait_or_time_out()?,
                     Err(e) => return Err(e),
                 }
             }
@@ -263,7 +263,7 @@ impl UdpSocket {
                 SOCKET_SET.poll_interfaces();
                 match f() {
                     Ok(t) => return Ok(t),
-                    Err(AxError::WouldBlock) => axtask::yield_now(),
+                    Err(AxError::WouldBlock) => super::wait_or_time_out()?,
                     Err(e) => return Err(e),
                 }
             }
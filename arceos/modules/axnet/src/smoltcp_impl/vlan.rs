@@ -0,0 +1,232 @@
+//! 802.1Q VLAN sub-interfaces of `eth0` (`eth0.<vid>`).
+//!
+//! A VLAN added with [`vlan_add`] gives the network stack an address on that
+//! VLAN, next to the address of `eth0`, as the overlay of the WireGuard
+//! tunnel does. The interfaces share the sockets and the ARP cache, and the
+//! stack picks the address of the VLAN for the hosts of its subnet:
+//!
+//! - the frames sent from the address of a VLAN (IPv4 packets by their
+//!   source, ARP by the sender address) are tagged with its VLAN ID, the tag
+//!   being pushed in the headroom of the frame;
+//! - the tagged frames received are untagged before the filters and the
+//!   stack see them, and dropped if the VLAN is unknown or if they are for
+//!   an address of another interface. The untagged frames for an address of
+//!   a VLAN are dropped too.
+
+use alloc::{format, string::String, vec::Vec};
+use core::net::Ipv4Addr;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use axerrno::{ax_err, AxResult};
+use smoltcp::wire::{
+    ArpPacket, EthernetFrame, EthernetProtocol, Ipv4Address, Ipv4Cidr, Ipv4Packet,
+};
+use spin::RwLock;
+
+use super::ETH0;
+use crate::skb::SkBuff;
+
+/// The EtherType of the 802.1Q tag.
+const ETHERTYPE_VLAN: u16 = 0x8100;
+/// The length of the tag, inserted after the MAC addresses.
+const TAG_LEN: usize = 4;
+/// The offset of the tag, after the destination and source MAC addresses.
+const TAG_OFFSET: usize = 12;
+/// The largest VLAN ID, 0 and 4095 being reserved.
+const MAX_VID: u16 = 4094;
+
+/// The state of a VLAN sub-interface, see [`vlans`].
+#[derive(Debug, Clone)]
+pub struct VlanInfo {
+    /// The name of the interface, `eth0.<vid>`.
+    pub name: String,
+    /// The VLAN ID.
+    pub vid: u16,
+    /// The address of the interface.
+    pub address: Ipv4Addr,
+    /// The prefix length of its subnet.
+    pub prefix_len: u8,
+    /// Frames received on the VLAN.
+    pub rx_frames: u64,
+    /// Frames sent on the VLAN.
+    pub tx_frames: u64,
+}
+
+struct Vlan {
+    vid: u16,
+    cidr: Ipv4Cidr,
+    rx_frames: AtomicU64,
+    tx_frames: AtomicU64,
+}
+
+impl Vlan {
+    /// Whether a frame addressed to `target` belongs to the VLAN.
+    fn accepts(&self, target: Ipv4Address) -> bool {
+        target == self.cidr.address()
+            || target.is_broadcast()
+            || target.is_multicast()
+            || Some(target) == self.cidr.broadcast()
+    }
+}
+
+static VLANS: RwLock<Vec<Vlan>> = RwLock::new(Vec::new());
+
+/// What to do with a received frame, see [`input`].
+pub(crate) enum VlanInput {
+    /// The frame is not tagged, and goes to the stack as it is.
+    Pass,
+    /// The frame was tagged for a VLAN, and goes to the stack untagged.
+    Untagged(Vec<u8>),
+    /// The frame is dropped.
+    Drop,
+}
+
+/// Returns the address a frame is sent from (IPv4 source, ARP sender) if
+/// `source`, else the one it is addressed to (IPv4 destination, ARP target).
+fn frame_addr(eth: &EthernetFrame<&[u8]>, source: bool) -> Option<Ipv4Address> {
+    match eth.ethertype() {
+        EthernetProtocol::Ipv4 => {
+            let ip = Ipv4Packet::new_checked(eth.payload()).ok()?;
+            Some(if source { ip.src_addr() } else { ip.dst_addr() })
+        }
+        EthernetProtocol::Arp => {
+            let arp = ArpPacket::new_checked(eth.payload()).ok()?;
+            let addr = if source {
+                arp.source_protocol_addr()
+            } else {
+                arp.target_protocol_addr()
+            };
+            (addr.len() == 4).then(|| Ipv4Address::from_bytes(addr))
+        }
+        _ => None,
+    }
+}
+
+/// Demultiplexes a frame received on `eth0`.
+///
+/// Called from the receive path of the stack.
+pub(crate) fn input(frame: &[u8]) -> VlanInput {
+    let vlans = VLANS.read();
+    if vlans.is_empty() {
+        return VlanInput::Pass;
+    }
+    if frame.len() < TAG_OFFSET + TAG_LEN
+        || u16::from_be_bytes([frame[TAG_OFFSET], frame[TAG_OFFSET + 1]]) != ETHERTYPE_VLAN
+    {
+        // an untagged frame must not reach the address of a VLAN
+        let target = EthernetFrame::new_checked(frame)
+            .ok()
+            .and_then(|eth| frame_addr(&eth, false));
+        return match target {
+            Some(target) if vlans.iter().any(|v| v.cidr.address() == target) => VlanInput::Drop,
+            _ => VlanInput::Pass,
+        };
+    }
+    let tci = u16::from_be_bytes([frame[TAG_OFFSET + 2], frame[TAG_OFFSET + 3]]);
+    let Some(vlan) = vlans.iter().find(|v| v.vid == tci & 0xfff) else {
+        return VlanInput::Drop;
+    };
+    let mut untagged = Vec::with_capacity(frame.len() - TAG_LEN);
+    untagged.extend_from_slice(&frame[..TAG_OFFSET]);
+    untagged.extend_from_slice(&frame[TAG_OFFSET + TAG_LEN..]);
+    let target = EthernetFrame::new_checked(&untagged[..])
+        .ok()
+        .and_then(|eth| frame_addr(&eth, false));
+    if !target.is_some_and(|target| vlan.accepts(target)) {
+        return VlanInput::Drop;
+    }
+    vlan.rx_frames.fetch_add(1, Ordering::Relaxed);
+    VlanInput::Untagged(untagged)
+}
+
+/// Returns whether VLANs are configured, so that the frames sent by the
+/// stack must be built outside of the buffers of the device, to be tagged.
+pub(crate) fn is_enabled() -> bool {
+    !VLANS.read().is_empty()
+}
+
+/// Tags a frame sent by the stack if it is sent from the address of a VLAN.
+///
+/// Called from the transmit path of the stack.
+pub(crate) fn output(frame: &mut SkBuff) {
+    let vlans = VLANS.read();
+    let Some(source) = EthernetFrame::new_checked(frame.data())
+        .ok()
+        .and_then(|eth| frame_addr(&eth, true))
+    else {
+        return;
+    };
+    let Some(vlan) = vlans.iter().find(|v| v.cidr.address() == source) else {
+        return;
+    };
+    // move the MAC addresses to the front, the tag takes their place
+    frame.push(TAG_LEN);
+    let data = frame.data_mut();
+    data.copy_within(TAG_LEN..TAG_LEN + TAG_OFFSET, 0);
+    data[TAG_OFFSET..TAG_OFFSET + 2].copy_from_slice(&ETHERTYPE_VLAN.to_be_bytes());
+    data[TAG_OFFSET + 2..TAG_OFFSET + TAG_LEN].copy_from_slice(&vlan.vid.to_be_bytes());
+    vlan.tx_frames.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Adds the VLAN sub-interface `eth0.<vid>`, with the address
+/// `address/prefix_len`.
+///
+/// Returns an error if the VLAN ID is invalid or already added, or if `eth0`
+/// has no room for another address.
+pub fn vlan_add(vid: u16, address: Ipv4Addr, prefix_len: u8) -> AxResult {
+    if vid == 0 || vid > MAX_VID || prefix_len > 32 {
+        return ax_err!(InvalidInput, "invalid VLAN");
+    }
+    if VLANS.read().iter().any(|v| v.vid == vid) {
+        return ax_err!(AlreadyExists, "VLAN already added");
+    }
+    let cidr = Ipv4Cidr::new(Ipv4Address(address.octets()), prefix_len);
+    // the receive path takes the lock of the VLANs with `eth0` locked, so
+    // `eth0` is not locked with the VLANs locked
+    ETH0.add_ip_addr(cidr.into())?;
+    let mut vlans = VLANS.write();
+    if vlans.iter().any(|v| v.vid == vid) {
+        drop(vlans);
+        ETH0.remove_ip_addr(cidr.into());
+        return ax_err!(AlreadyExists, "VLAN already added");
+    }
+    vlans.push(Vlan {
+        vid,
+        cidr,
+        rx_frames: AtomicU64::new(0),
+        tx_frames: AtomicU64::new(0),
+    });
+    info!("created net interface \"eth0.{}\":", vid);
+    info!("  ip:       {}", cidr);
+    Ok(())
+}
+
+/// Removes the VLAN sub-interface `eth0.<vid>`, and its address.
+pub fn vlan_remove(vid: u16) -> AxResult {
+    let mut vlans = VLANS.write();
+    let Some(index) = vlans.iter().position(|v| v.vid == vid) else {
+        return ax_err!(NotFound, "no such VLAN");
+    };
+    let vlan = vlans.remove(index);
+    drop(vlans);
+    ETH0.remove_ip_addr(vlan.cidr.into());
+    Ok(())
+}
+
+/// Returns the VLAN sub-interfaces, by VLAN ID.
+pub fn vlans() -> Vec<VlanInfo> {
+    let mut info: Vec<VlanInfo> = VLANS
+        .read()
+        .iter()
+        .map(|v| VlanInfo {
+            name: format!("eth0.{}", v.vid),
+            vid: v.vid,
+            address: Ipv4Addr::from(v.cidr.address().0),
+            prefix_len: v.cidr.prefix_len(),
+            rx_frames: v.rx_frames.load(Ordering::Relaxed),
+            tx_frames: v.tx_frames.load(Ordering::Relaxed),
+        })
+        .collect();
+    info.sort_by_key(|v| v.vid);
+    info
+}
@@ -0,0 +1,852 @@
+//! WireGuard tunnel interface (`wg0`).
+//!
+//! The tunnel gives the network stack an overlay address, configured with
+//! [`wg_up`], next to the address of `eth0`. IPv4 packets the stack sends to
+//! the overlay subnet, or to the allowed IPs of a peer added with
+//! [`wg_add_peer`], are encrypted and sent to the endpoint of that peer over
+//! UDP; packets received from a peer are decrypted and handed to the stack if
+//! their source is one of its allowed IPs (cryptokey routing).
+//!
+//! The overlay shares the sockets, ARP cache and routes of `eth0`: the stack
+//! resolves the addresses of the overlay subnet to a virtual Ethernet address
+//! answered here, and packets to allowed IPs outside the subnet are caught
+//! on their way to the gateway. Their source is the one chosen by the
+//! socket, so sockets talking to such hosts should bind the overlay address.
+//!
+//! Sessions are rekeyed as in the WireGuard specification. Cookie replies
+//! (the protection of responders under load) are neither sent nor handled.
+
+mod noise;
+
+use alloc::{collections::VecDeque, vec, vec::Vec};
+use core::cell::Cell;
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+use core::time::Duration;
+
+use axerrno::{ax_err, AxResult};
+use axhal::time::{monotonic_time_nanos, NANOS_PER_SEC};
+use axsync::Mutex;
+use smoltcp::wire::{
+    ArpOperation, ArpPacket, ArpRepr, EthernetAddress, EthernetFrame, EthernetProtocol,
+    EthernetRepr, IpProtocol, Ipv4Address, Ipv4Cidr, Ipv4Packet, UdpPacket,
+};
+use spin::RwLock;
+
+use self::noise::{Identity, Initiation, Keys, PeerKeys};
+use super::{neighbor, UdpSocket, ETH0};
+use crate::skb::SkBuff;
+
+/// The virtual Ethernet address of the hosts behind the tunnel.
+const WG_MAC: EthernetAddress = EthernetAddress([0x02, 0x77, 0x67, 0, 0, 0]);
+
+const REKEY_AFTER_MESSAGES: u64 = 1 << 60;
+const REJECT_AFTER_MESSAGES: u64 = u64::MAX - (1 << 13);
+const REKEY_AFTER_TIME: u64 = 120 * NANOS_PER_SEC;
+const REJECT_AFTER_TIME: u64 = 180 * NANOS_PER_SEC;
+const REKEY_ATTEMPT_TIME: u64 = 90 * NANOS_PER_SEC;
+const REKEY_TIMEOUT: u64 = 5 * NANOS_PER_SEC;
+const KEEPALIVE_TIMEOUT: u64 = 10 * NANOS_PER_SEC;
+
+/// Maximum number of packets queued for a peer while its handshake is in
+/// progress.
+const PEER_QUEUE_LEN: usize = 64;
+/// Maximum number of packets queued between the stack and the tunnel, each
+/// way.
+const QUEUE_LEN: usize = 256;
+const MAX_DATAGRAM_LEN: usize = 2048;
+
+/// The configuration of the tunnel interface.
+#[derive(Debug, Clone, Copy)]
+pub struct WgConfig {
+    /// The Curve25519 private key of the interface.
+    pub private_key: [u8; 32],
+    /// The UDP port to listen on, or 0 for an ephemeral one.
+    pub listen_port: u16,
+    /// The overlay address of the interface.
+    pub address: Ipv4Addr,
+    /// The prefix length of the overlay subnet.
+    pub prefix_len: u8,
+}
+
+/// The configuration of a peer.
+#[derive(Debug, Clone)]
+pub struct WgPeerConfig {
+    /// The Curve25519 public key of the peer.
+    pub public_key: [u8; 32],
+    /// An optional symmetric key mixed in the handshakes.
+    pub preshared_key: Option<[u8; 32]>,
+    /// Where to send the handshakes. Without it, the peer must contact us
+    /// first. It then follows the address the peer sends from.
+    pub endpoint: Option<SocketAddr>,
+    /// The networks routed to the peer, as `(address, prefix length)`, and
+    /// the only sources accepted from it.
+    pub allowed_ips: Vec<(Ipv4Addr, u8)>,
+    /// Interval of keepalives sent even without traffic, to keep the
+    /// mappings of NATs and firewalls on the path open.
+    pub persistent_keepalive: Option<Duration>,
+}
+
+/// The state of a peer.
+#[derive(Debug, Clone)]
+pub struct WgPeerStatus {
+    /// The Curve25519 public key of the peer.
+    pub public_key: [u8; 32],
+    /// The current endpoint of the peer.
+    pub endpoint: Option<SocketAddr>,
+    /// The networks routed to the peer.
+    pub allowed_ips: Vec<(Ipv4Addr, u8)>,
+    /// Time since the last completed handshake, if any.
+    pub last_handshake: Option<Duration>,
+    /// Bytes of packets received from the peer.
+    pub rx_bytes: u64,
+    /// Bytes of packets sent to the peer.
+    pub tx_bytes: u64,
+}
+
+/// What the transmit path of the stack needs to know, without taking the
+/// lock of the tunnel.
+struct Overlay {
+    subnet: Ipv4Cidr,
+    listen_port: u16,
+    /// The allowed IPs of all the peers.
+    routes: Vec<Ipv4Cidr>,
+}
+
+impl Overlay {
+    fn is_tunneled(&self, dst: Ipv4Address) -> bool {
+        self.subnet.contains_addr(&dst) || self.routes.iter().any(|c| c.contains_addr(&dst))
+    }
+}
+
+static OVERLAY: RwLock<Option<Overlay>> = RwLock::new(None);
+/// IPv4 packets from the stack, to encrypt.
+static OUTBOUND: spin::Mutex<VecDeque<SkBuff>> = spin::Mutex::new(VecDeque::new());
+/// Frames for the stack: decrypted packets and ARP replies.
+static INBOUND: spin::Mutex<VecDeque<Vec<u8>>> = spin::Mutex::new(VecDeque::new());
+
+static TUNNEL: Mutex<Option<Tunnel>> = Mutex::new(None);
+
+/// Counters of the packets received in a session, to reject replays.
+#[derive(Default)]
+struct ReplayWindow {
+    /// The highest counter received, plus one.
+    next: u64,
+    /// Bit `i` is set if `next - 1 - i` was received.
+    bitmap: u64,
+}
+
+impl ReplayWindow {
+    fn is_fresh(&self, counter: u64) -> bool {
+        if counter >= REJECT_AFTER_MESSAGES {
+            return false;
+        }
+        if counter >= self.next {
+            return true;
+        }
+        let offset = self.next - 1 - counter;
+        offset < 64 && self.bitmap & (1 << offset) == 0
+    }
+
+    fn mark(&mut self, counter: u64) {
+        if counter >= self.next {
+            let shift = counter + 1 - self.next;
+            self.bitmap = if shift >= 64 { 0 } else { self.bitmap << shift };
+            self.bitmap |= 1;
+            self.next = counter + 1;
+        } else {
+            self.bitmap |= 1 << (self.next - 1 - counter);
+        }
+    }
+}
+
+struct Session {
+    keys: Keys,
+    local_index: u32,
+    created: u64,
+    initiator: bool,
+    send_counter: u64,
+    replay: ReplayWindow,
+}
+
+impl Session {
+    fn new(keys: Keys, local_index: u32, now: u64, initiator: bool) -> Self {
+        Self {
+            keys,
+            local_index,
+            created: now,
+            initiator,
+            send_counter: 0,
+            replay: ReplayWindow::default(),
+        }
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        now - self.created >= REJECT_AFTER_TIME
+    }
+
+    fn can_send(&self, now: u64) -> bool {
+        !self.is_expired(now) && self.send_counter < REJECT_AFTER_MESSAGES
+    }
+
+    fn needs_rekey(&self, now: u64) -> bool {
+        self.initiator
+            && (now - self.created >= REKEY_AFTER_TIME || self.send_counter >= REKEY_AFTER_MESSAGES)
+    }
+}
+
+/// A handshake we initiated.
+struct Pending {
+    initiation: Initiation,
+    sent: u64,
+    started: u64,
+}
+
+/// Which session of a peer a transport message is for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    Current,
+    Previous,
+    /// A session we responded to, confirmed by the first message of the
+    /// initiator.
+    Next,
+}
+
+struct Peer {
+    keys: PeerKeys,
+    endpoint: Option<SocketAddr>,
+    allowed_ips: Vec<Ipv4Cidr>,
+    persistent_keepalive: Option<u64>,
+    pending: Option<Pending>,
+    /// The greatest timestamp of the initiations of the peer.
+    last_timestamp: [u8; 12],
+    current: Option<Session>,
+    previous: Option<Session>,
+    next: Option<Session>,
+    queue: VecDeque<SkBuff>,
+    last_sent: u64,
+    last_received: u64,
+    /// Whether data was received since the last packet sent.
+    needs_keepalive: bool,
+    last_handshake: Option<u64>,
+    rx_bytes: u64,
+    tx_bytes: u64,
+}
+
+impl Peer {
+    fn session_mut(&mut self, slot: Slot) -> Option<&mut Session> {
+        match slot {
+            Slot::Current => self.current.as_mut(),
+            Slot::Previous => self.previous.as_mut(),
+            Slot::Next => self.next.as_mut(),
+        }
+    }
+
+    fn find_session(&self, local_index: u32) -> Option<Slot> {
+        [
+            (Slot::Current, &self.current),
+            (Slot::Previous, &self.previous),
+            (Slot::Next, &self.next),
+        ]
+        .into_iter()
+        .find(|(_, session)| {
+            session
+                .as_ref()
+                .is_some_and(|s| s.local_index == local_index)
+        })
+        .map(|(slot, _)| slot)
+    }
+
+    /// Makes `session` the one to send with.
+    fn install(&mut self, session: Session) {
+        self.previous = self.current.replace(session);
+    }
+
+    fn route(&self, addr: Ipv4Address) -> Option<u8> {
+        self.allowed_ips
+            .iter()
+            .filter(|cidr| cidr.contains_addr(&addr))
+            .map(|cidr| cidr.prefix_len())
+            .max()
+    }
+}
+
+/// The UDP socket carrying the encrypted packets.
+struct Link {
+    socket: UdpSocket,
+    /// Whether datagrams were sent since the last poll.
+    sent: Cell<bool>,
+}
+
+impl Link {
+    fn send(&self, to: SocketAddr, data: &[u8]) {
+        match self.socket.send_to(data, to) {
+            Ok(_) => self.sent.set(true),
+            Err(e) => debug!("wg0: failed to send to {}: {:?}", to, e),
+        }
+    }
+}
+
+struct Tunnel {
+    identity: Identity,
+    link: Link,
+    peers: Vec<Peer>,
+}
+
+/// Returns a session index not used by any peer.
+fn new_index(peers: &[Peer]) -> u32 {
+    loop {
+        let index = axhal::misc::random() as u32;
+        let used = peers.iter().any(|peer| {
+            peer.find_session(index).is_some()
+                || peer
+                    .pending
+                    .as_ref()
+                    .is_some_and(|p| p.initiation.sender_index == index)
+        });
+        if !used {
+            return index;
+        }
+    }
+}
+
+impl Tunnel {
+    /// Sends a handshake initiation to peer `i`, if its endpoint is known.
+    fn initiate(&mut self, i: usize, now: u64) {
+        let index = new_index(&self.peers);
+        let peer = &mut self.peers[i];
+        let Some(endpoint) = peer.endpoint else {
+            return;
+        };
+        let (initiation, msg) = noise::create_initiation(&self.identity, &peer.keys, index);
+        let started = peer.pending.as_ref().map_or(now, |p| p.started);
+        peer.pending = Some(Pending {
+            initiation,
+            sent: now,
+            started,
+        });
+        self.link.send(endpoint, &msg);
+    }
+
+    /// Sends a packet to peer `i`, or queues it until a session is
+    /// established. An empty packet is a keepalive.
+    fn send_packet(&mut self, i: usize, mut packet: SkBuff, now: u64) {
+        let peer = &mut self.peers[i];
+        let (Some(session), Some(endpoint)) = (peer.current.as_mut(), peer.endpoint) else {
+            if peer.queue.len() >= PEER_QUEUE_LEN {
+                peer.queue.pop_front();
+            }
+            peer.queue.push_back(packet);
+            if peer.pending.is_none() {
+                self.initiate(i, now);
+            }
+            return;
+        };
+        if !session.can_send(now) {
+            peer.current = None;
+            return self.send_packet(i, packet, now);
+        }
+        peer.tx_bytes += packet.len() as u64;
+        noise::seal_transport(
+            &session.keys.send,
+            session.keys.remote_index,
+            session.send_counter,
+            &mut packet,
+        );
+        session.send_counter += 1;
+        let rekey = session.needs_rekey(now);
+        peer.last_sent = now;
+        peer.needs_keepalive = false;
+        self.link.send(endpoint, packet.data());
+        if rekey && peer.pending.is_none() {
+            self.initiate(i, now);
+        }
+    }
+
+    /// Sends the packets queued for peer `i` during its handshake, or a
+    /// keepalive to confirm the new session.
+    fn flush_queue(&mut self, i: usize, now: u64) {
+        let queue = core::mem::take(&mut self.peers[i].queue);
+        if queue.is_empty() {
+            self.send_packet(i, empty_packet(), now);
+        }
+        for packet in queue {
+            self.send_packet(i, packet, now);
+        }
+    }
+
+    fn handle_initiation(&mut self, msg: &[u8], from: SocketAddr, now: u64) {
+        if !self.identity.check_mac1(msg) {
+            return;
+        }
+        let peers = &self.peers;
+        let Some(initiation) = noise::consume_initiation(&self.identity, msg, |key| {
+            peers
+                .iter()
+                .find(|p| p.keys.public == *key)
+                .map(|p| &p.keys)
+        }) else {
+            return;
+        };
+        let index = new_index(&self.peers);
+        let Some(peer) = self
+            .peers
+            .iter_mut()
+            .find(|p| p.keys.public == initiation.peer)
+        else {
+            return;
+        };
+        // TAI64N timestamps compare as big-endian bytes
+        if initiation.timestamp <= peer.last_timestamp {
+            debug!("wg0: replayed handshake initiation from {}", from);
+            return;
+        }
+        peer.last_timestamp = initiation.timestamp;
+        let (keys, msg) = noise::create_response(&peer.keys, initiation, index);
+        peer.next = Some(Session::new(keys, index, now, false));
+        peer.endpoint = Some(from);
+        peer.last_handshake = Some(now);
+        self.link.send(from, &msg);
+    }
+
+    fn handle_response(&mut self, msg: &[u8], from: SocketAddr, now: u64) {
+        let Some(index) = noise::response_receiver_index(msg) else {
+            return;
+        };
+        if !self.identity.check_mac1(msg) {
+            return;
+        }
+        let Some(i) = self.peers.iter().position(|p| {
+            p.pending
+                .as_ref()
+                .is_some_and(|p| p.initiation.sender_index == index)
+        }) else {
+            return;
+        };
+        let peer = &mut self.peers[i];
+        let initiation = &peer.pending.as_ref().unwrap().initiation;
+        let Some(keys) = noise::consume_response(&self.identity, &peer.keys, initiation, msg)
+        else {
+            return;
+        };
+        peer.pending = None;
+        peer.install(Session::new(keys, index, now, true));
+        peer.next = None;
+        peer.endpoint = Some(from);
+        peer.last_handshake = Some(now);
+        self.flush_queue(i, now);
+    }
+
+    fn handle_transport(&mut self, msg: &mut [u8], from: SocketAddr, now: u64) {
+        if msg.len() < noise::TRANSPORT_HEADER_LEN + noise::TAG_LEN {
+            return;
+        }
+        let index = u32::from_le_bytes(msg[4..8].try_into().unwrap());
+        let counter = u64::from_le_bytes(msg[8..16].try_into().unwrap());
+        let Some((i, slot)) = self
+            .peers
+            .iter()
+            .enumerate()
+            .find_map(|(i, p)| Some((i, p.find_session(index)?)))
+        else {
+            return;
+        };
+        let peer = &mut self.peers[i];
+        let session = peer.session_mut(slot).unwrap();
+        if session.is_expired(now) || !session.replay.is_fresh(counter) {
+            return;
+        }
+        let payload = &mut msg[noise::TRANSPORT_HEADER_LEN..];
+        let Some(len) = noise::open_transport(&session.keys.recv, counter, payload) else {
+            return;
+        };
+        session.replay.mark(counter);
+        if slot == Slot::Next {
+            let session = peer.next.take().unwrap();
+            peer.install(session);
+        }
+        peer.endpoint = Some(from);
+        peer.last_received = now;
+        if len == 0 {
+            // keepalive
+            return;
+        }
+        peer.needs_keepalive = true;
+
+        let Ok(packet) = Ipv4Packet::new_checked(&payload[..len]) else {
+            return;
+        };
+        let src = packet.src_addr();
+        if peer.route(src).is_none() {
+            debug!("wg0: dropped packet from {} not allowed for its peer", src);
+            return;
+        }
+        let packet = &payload[..packet.total_len() as usize];
+        peer.rx_bytes += packet.len() as u64;
+        deliver(packet);
+    }
+
+    fn handle_datagram(&mut self, msg: &mut [u8], from: SocketAddr, now: u64) {
+        if msg.len() < 4 || msg[1..4] != [0; 3] {
+            return;
+        }
+        match msg[0] {
+            noise::MSG_INITIATION if msg.len() == noise::INITIATION_LEN => {
+                self.handle_initiation(msg, from, now)
+            }
+            noise::MSG_RESPONSE => self.handle_response(msg, from, now),
+            noise::MSG_TRANSPORT => self.handle_transport(msg, from, now),
+            _ => {}
+        }
+    }
+
+    /// Sends an IPv4 packet from the stack to the peer it is routed to.
+    fn output(&mut self, packet: SkBuff, now: u64) {
+        let Ok(ip) = Ipv4Packet::new_checked(packet.data()) else {
+            return;
+        };
+        let dst = ip.dst_addr();
+        let best = self
+            .peers
+            .iter()
+            .enumerate()
+            .filter_map(|(i, peer)| Some((i, peer.route(dst)?)))
+            .max_by_key(|&(_, prefix_len)| prefix_len);
+        match best {
+            Some((i, _)) => self.send_packet(i, packet, now),
+            None => debug!("wg0: no peer for {}", dst),
+        }
+    }
+
+    fn update_timers(&mut self, i: usize, now: u64) {
+        let peer = &mut self.peers[i];
+        for session in [&mut peer.current, &mut peer.previous, &mut peer.next] {
+            if session.as_ref().is_some_and(|s| s.is_expired(now)) {
+                *session = None;
+            }
+        }
+        if let Some(pending) = &peer.pending {
+            if now - pending.sent >= REKEY_TIMEOUT {
+                if now - pending.started >= REKEY_ATTEMPT_TIME {
+                    debug!("wg0: handshake with {:?} timed out", peer.endpoint);
+                    peer.pending = None;
+                    peer.queue.clear();
+                } else {
+                    self.initiate(i, now);
+                }
+            }
+            return;
+        }
+        let peer = &self.peers[i];
+        let keepalive = (peer.needs_keepalive && now - peer.last_received >= KEEPALIVE_TIMEOUT)
+            || peer
+                .persistent_keepalive
+                .is_some_and(|interval| now - peer.last_sent >= interval);
+        if keepalive {
+            self.send_packet(i, empty_packet(), now);
+        }
+    }
+}
+
+/// Queues a decrypted packet for the stack, as if received from `WG_MAC`.
+fn deliver(packet: &[u8]) {
+    let eth = EthernetRepr {
+        src_addr: WG_MAC,
+        dst_addr: ETH0.ethernet_address(),
+        ethertype: EthernetProtocol::Ipv4,
+    };
+    let mut frame = vec![0; eth.buffer_len() + packet.len()];
+    let mut eth_frame = EthernetFrame::new_unchecked(&mut frame[..]);
+    eth.emit(&mut eth_frame);
+    eth_frame.payload_mut().copy_from_slice(packet);
+    push_bounded(&INBOUND, frame);
+}
+
+/// Returns an empty packet, a keepalive, with room for the transport header
+/// and tag.
+fn empty_packet() -> SkBuff {
+    SkBuff::new(noise::TRANSPORT_HEADER_LEN, 0, noise::TAG_LEN)
+}
+
+fn push_bounded<T>(queue: &spin::Mutex<VecDeque<T>>, item: T) {
+    let mut queue = queue.lock();
+    if queue.len() < QUEUE_LEN {
+        queue.push_back(item);
+    }
+}
+
+/// Returns whether the tunnel is up.
+pub(crate) fn is_up() -> bool {
+    OVERLAY.read().is_some()
+}
+
+/// Takes a frame sent by the stack if it goes through the tunnel, and
+/// returns whether it did.
+///
+/// Called from the transmit path of the stack.
+pub(crate) fn output(frame: &SkBuff) -> bool {
+    let overlay = OVERLAY.read();
+    let Some(overlay) = overlay.as_ref() else {
+        return false;
+    };
+    let Ok(eth) = EthernetFrame::new_checked(frame.data()) else {
+        return false;
+    };
+    match eth.ethertype() {
+        EthernetProtocol::Arp => {
+            let Ok(arp) = ArpPacket::new_checked(eth.payload()) else {
+                return false;
+            };
+            let Ok(ArpRepr::EthernetIpv4 {
+                operation: ArpOperation::Request,
+                source_hardware_addr,
+                source_protocol_addr,
+                target_protocol_addr,
+                ..
+            }) = ArpRepr::parse(&arp)
+            else {
+                return false;
+            };
+            if !overlay.subnet.contains_addr(&target_protocol_addr)
+                || target_protocol_addr == overlay.subnet.address()
+            {
+                return false;
+            }
+            let reply = ArpRepr::EthernetIpv4 {
+                operation: ArpOperation::Reply,
+                source_hardware_addr: WG_MAC,
+                source_protocol_addr: target_protocol_addr,
+                target_hardware_addr: source_hardware_addr,
+                target_protocol_addr: source_protocol_addr,
+            };
+            let frame = neighbor::arp_frame(source_hardware_addr, WG_MAC, reply);
+            push_bounded(&INBOUND, frame);
+            true
+        }
+        EthernetProtocol::Ipv4 => {
+            let Ok(ip) = Ipv4Packet::new_checked(eth.payload()) else {
+                return false;
+            };
+            if ip.next_header() == IpProtocol::Udp {
+                // the encrypted packets of the tunnel itself
+                if let Ok(udp) = UdpPacket::new_checked(ip.payload()) {
+                    if udp.src_port() == overlay.listen_port {
+                        return false;
+                    }
+                }
+            }
+            if eth.dst_addr() != WG_MAC && !overlay.is_tunneled(ip.dst_addr()) {
+                return false;
+            }
+            // the packet shares the buffer of the frame, where the
+            // transport header takes the place of the Ethernet one
+            let len = (ip.total_len() as usize).min(eth.payload().len());
+            let mut packet = frame.clone();
+            packet.pull(frame.len() - eth.payload().len());
+            packet.trim(len);
+            push_bounded(&OUTBOUND, packet);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Takes the next frame from the tunnel for the stack.
+pub(crate) fn take_inbound() -> Option<Vec<u8>> {
+    INBOUND.lock().pop_front()
+}
+
+/// Processes the traffic of the tunnel: decrypts what was received and
+/// encrypts what the stack sent. Returns whether there is new work for the
+/// stack.
+///
+/// Called after polling the stack, without any lock held.
+pub(crate) fn poll() -> bool {
+    let mut tunnel = TUNNEL.lock();
+    let Some(tunnel) = tunnel.as_mut() else {
+        return false;
+    };
+    let now = monotonic_time_nanos();
+    let mut buf = vec![0; MAX_DATAGRAM_LEN];
+    while let Ok((len, from)) = tunnel.link.socket.recv_from(&mut buf) {
+        tunnel.handle_datagram(&mut buf[..len], from, now);
+    }
+    let packets: Vec<_> = OUTBOUND.lock().drain(..).collect();
+    for packet in packets {
+        tunnel.output(packet, now);
+    }
+    for i in 0..tunnel.peers.len() {
+        tunnel.update_timers(i, now);
+    }
+    tunnel.link.sent.replace(false) || !INBOUND.lock().is_empty()
+}
+
+fn to_cidr(addr: Ipv4Addr, prefix_len: u8) -> AxResult<Ipv4Cidr> {
+    if prefix_len > 32 {
+        return ax_err!(InvalidInput, "invalid prefix length");
+    }
+    Ok(Ipv4Cidr::new(Ipv4Address(addr.octets()), prefix_len))
+}
+
+fn update_routes(tunnel: &Tunnel) {
+    if let Some(overlay) = OVERLAY.write().as_mut() {
+        overlay.routes = tunnel
+            .peers
+            .iter()
+            .flat_map(|peer| peer.allowed_ips.iter().copied())
+            .collect();
+    }
+}
+
+/// Brings up the tunnel interface `wg0`.
+///
+/// Returns an error if it is already up, if the UDP port is in use, or if
+/// `eth0` has no room for another address.
+pub fn wg_up(config: WgConfig) -> AxResult {
+    let subnet = to_cidr(config.address, config.prefix_len)?;
+    // closing the socket polls the interfaces, so it must outlive the lock
+    let socket = UdpSocket::new();
+    socket.set_nonblocking(true);
+    socket.bind(SocketAddr::new(
+        IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        config.listen_port,
+    ))?;
+    let listen_port = socket.local_addr()?.port();
+    let mut tunnel = TUNNEL.lock();
+    if tunnel.is_some() {
+        return ax_err!(AlreadyExists, "wg0 is already up");
+    }
+    ETH0.add_ip_addr(subnet.into())?;
+
+    *OVERLAY.write() = Some(Overlay {
+        subnet,
+        listen_port,
+        routes: Vec::new(),
+    });
+    *tunnel = Some(Tunnel {
+        identity: Identity::new(config.private_key),
+        link: Link {
+            socket,
+            sent: Cell::new(false),
+        },
+        peers: Vec::new(),
+    });
+    info!("created net interface \"wg0\":");
+    info!("  ip:       {}", subnet);
+    info!("  port:     {}", listen_port);
+    Ok(())
+}
+
+/// Brings down the tunnel interface, and forgets its peers.
+pub fn wg_down() -> AxResult {
+    let Some(tunnel) = TUNNEL.lock().take() else {
+        return ax_err!(NotFound, "wg0 is not up");
+    };
+    if let Some(overlay) = OVERLAY.write().take() {
+        ETH0.remove_ip_addr(overlay.subnet.into());
+    }
+    OUTBOUND.lock().clear();
+    INBOUND.lock().clear();
+    // closing the socket polls the interfaces, so drop it unlocked
+    drop(tunnel);
+    Ok(())
+}
+
+/// Returns the public key of the tunnel interface, if it is up.
+pub fn wg_public_key() -> Option<[u8; 32]> {
+    TUNNEL.lock().as_ref().map(|t| t.identity.public())
+}
+
+/// Adds a peer to the tunnel interface.
+///
+/// Returns an error if the interface is not up, or if the peer exists.
+pub fn wg_add_peer(config: WgPeerConfig) -> AxResult {
+    let allowed_ips = config
+        .allowed_ips
+        .iter()
+        .map(|&(addr, prefix_len)| to_cidr(addr, prefix_len))
+        .collect::<AxResult<Vec<_>>>()?;
+    let mut tunnel = TUNNEL.lock();
+    let Some(tunnel) = tunnel.as_mut() else {
+        return ax_err!(NotFound, "wg0 is not up");
+    };
+    if tunnel
+        .peers
+        .iter()
+        .any(|p| p.keys.public == config.public_key)
+    {
+        return ax_err!(AlreadyExists, "wg0 peer already exists");
+    }
+    let keys = PeerKeys::new(
+        &tunnel.identity,
+        config.public_key,
+        config.preshared_key.unwrap_or_default(),
+    );
+    let now = monotonic_time_nanos();
+    tunnel.peers.push(Peer {
+        keys,
+        endpoint: config.endpoint,
+        allowed_ips,
+        persistent_keepalive: config
+            .persistent_keepalive
+            .map(|interval| interval.as_nanos() as u64),
+        pending: None,
+        last_timestamp: [0; 12],
+        current: None,
+        previous: None,
+        next: None,
+        queue: VecDeque::new(),
+        last_sent: now,
+        last_received: now,
+        needs_keepalive: false,
+        last_handshake: None,
+        rx_bytes: 0,
+        tx_bytes: 0,
+    });
+    update_routes(tunnel);
+    Ok(())
+}
+
+/// Removes the peer with `public_key` from the tunnel interface.
+pub fn wg_remove_peer(public_key: &[u8; 32]) -> AxResult {
+    let mut tunnel = TUNNEL.lock();
+    let Some(tunnel) = tunnel.as_mut() else {
+        return ax_err!(NotFound, "wg0 is not up");
+    };
+    match tunnel
+        .peers
+        .iter()
+        .position(|p| p.keys.public == *public_key)
+    {
+        Some(i) => {
+            tunnel.peers.remove(i);
+            update_routes(tunnel);
+            Ok(())
+        }
+        None => ax_err!(NotFound, "no such wg0 peer"),
+    }
+}
+
+/// Returns the state of the peers of the tunnel interface.
+pub fn wg_peers() -> Vec<WgPeerStatus> {
+    let now = monotonic_time_nanos();
+    let tunnel = TUNNEL.lock();
+    let Some(tunnel) = tunnel.as_ref() else {
+        return Vec::new();
+    };
+    tunnel
+        .peers
+        .iter()
+        .map(|peer| WgPeerStatus {
+            public_key: peer.keys.public,
+            endpoint: peer.endpoint,
+            allowed_ips: peer
+                .allowed_ips
+                .iter()
+                .map(|cidr| (Ipv4Addr::from(cidr.address().0), cidr.prefix_len()))
+                .collect(),
+            last_handshake: peer.last_handshake.map(|t| Duration::from_nanos(now - t)),
+            rx_bytes: peer.rx_bytes,
+            tx_bytes: peer.tx_bytes,
+        })
+        .collect()
+}
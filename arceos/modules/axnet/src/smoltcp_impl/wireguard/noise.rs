@@ -0,0 +1,415 @@
+//! The WireGuard handshake: `Noise_IKpsk2_25519_ChaChaPoly_BLAKE2s`, and the
+//! transport data encryption.
+
+use alloc::vec::Vec;
+
+use axcrypto::{ChaCha20Poly1305, AEAD_TAG_SIZE};
+use blake2::digest::{consts::U16, Mac};
+use blake2::{Blake2s256, Blake2sMac, Digest};
+use hmac::SimpleHmac;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::skb::SkBuff;
+
+const CONSTRUCTION: &[u8] = b"Noise_IKpsk2_25519_ChaChaPoly_BLAKE2s";
+const IDENTIFIER: &[u8] = b"WireGuard v1 zx2c4 Jason@zx2c4.com";
+const LABEL_MAC1: &[u8] = b"mac1----";
+
+pub const MSG_INITIATION: u8 = 1;
+pub const MSG_RESPONSE: u8 = 2;
+pub const MSG_TRANSPORT: u8 = 4;
+
+pub const INITIATION_LEN: usize = 148;
+pub const RESPONSE_LEN: usize = 92;
+/// Length of the header of transport data messages.
+pub const TRANSPORT_HEADER_LEN: usize = 16;
+pub const TAG_LEN: usize = AEAD_TAG_SIZE;
+
+const TAI64N_LEN: usize = 12;
+/// Label of the TAI64 epoch, including the 10 leap seconds of 1970.
+const TAI64_BASE: u64 = 0x4000_0000_0000_000a;
+
+type HmacBlake2s = SimpleHmac<Blake2s256>;
+
+fn hash(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Blake2s256::new();
+    for part in parts {
+        Digest::update(&mut hasher, part);
+    }
+    hasher.finalize().into()
+}
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut mac = <HmacBlake2s as Mac>::new_from_slice(key).unwrap();
+    for part in parts {
+        Mac::update(&mut mac, part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+/// The HKDF of the protocol, returning `N` keys.
+fn kdf<const N: usize>(key: &[u8; 32], input: &[u8]) -> [[u8; 32]; N] {
+    let prk = hmac(key, &[input]);
+    let mut out = [[0; 32]; N];
+    for i in 0..N {
+        let prev: &[u8] = if i == 0 { &[] } else { &out[i - 1] };
+        out[i] = hmac(&prk, &[prev, &[i as u8 + 1]]);
+    }
+    out
+}
+
+/// The keyed BLAKE2s-128 MAC of handshake messages.
+fn mac(key: &[u8; 32], data: &[u8]) -> [u8; 16] {
+    let mut mac = <Blake2sMac<U16> as Mac>::new_from_slice(key).unwrap();
+    Mac::update(&mut mac, data);
+    mac.finalize().into_bytes().into()
+}
+
+fn nonce(counter: u64) -> [u8; 12] {
+    let mut nonce = [0; 12];
+    nonce[4..].copy_from_slice(&counter.to_le_bytes());
+    nonce
+}
+
+/// Encrypts `buf` in place and appends its tag.
+fn seal(key: &[u8; 32], counter: u64, aad: &[u8], buf: &mut Vec<u8>) {
+    let tag = ChaCha20Poly1305::new(key).seal_in_place(&nonce(counter), aad, buf);
+    buf.extend_from_slice(&tag);
+}
+
+/// Checks the tag at the end of `buf` and decrypts the rest in place, and
+/// returns the length of the plaintext.
+fn open(key: &[u8; 32], counter: u64, aad: &[u8], buf: &mut [u8]) -> Option<usize> {
+    let len = buf.len().checked_sub(TAG_LEN)?;
+    let (data, tag) = buf.split_at_mut(len);
+    ChaCha20Poly1305::new(key)
+        .open_in_place(&nonce(counter), aad, data, (&*tag).try_into().unwrap())
+        .ok()?;
+    Some(len)
+}
+
+fn open_array<const N: usize>(key: &[u8; 32], aad: &[u8], sealed: &[u8]) -> Option<[u8; N]> {
+    let mut buf = sealed.to_vec();
+    let len = open(key, 0, aad, &mut buf)?;
+    buf[..len].try_into().ok()
+}
+
+fn dh(secret: &StaticSecret, public: &[u8; 32]) -> [u8; 32] {
+    secret.diffie_hellman(&PublicKey::from(*public)).to_bytes()
+}
+
+/// Returns the current time as a TAI64N timestamp, which the responder
+/// checks to reject replayed initiations.
+fn tai64n() -> [u8; TAI64N_LEN] {
+    let nanos = axhal::time::wall_time_nanos();
+    let secs = nanos / axhal::time::NANOS_PER_SEC;
+    let mut out = [0; TAI64N_LEN];
+    out[..8].copy_from_slice(&(TAI64_BASE + secs).to_be_bytes());
+    out[8..].copy_from_slice(&((nanos % axhal::time::NANOS_PER_SEC) as u32).to_be_bytes());
+    out
+}
+
+/// Generates a key pair for a handshake.
+///
+/// The randomness comes from [`axhal::misc::random`], so the forward secrecy
+/// of the sessions is only as good as that generator.
+fn generate_ephemeral() -> StaticSecret {
+    let mut bytes = [0; 32];
+    bytes[..16].copy_from_slice(&axhal::misc::random().to_le_bytes());
+    bytes[16..].copy_from_slice(&axhal::misc::random().to_le_bytes());
+    StaticSecret::from(bytes)
+}
+
+/// The static identity of the local interface.
+pub struct Identity {
+    secret: StaticSecret,
+    public: [u8; 32],
+    /// The key of the `mac1` field of the messages sent to us.
+    mac1_key: [u8; 32],
+}
+
+impl Identity {
+    pub fn new(private_key: [u8; 32]) -> Self {
+        let secret = StaticSecret::from(private_key);
+        let public = PublicKey::from(&secret).to_bytes();
+        Self {
+            secret,
+            mac1_key: hash(&[LABEL_MAC1, &public]),
+            public,
+        }
+    }
+
+    pub fn public(&self) -> [u8; 32] {
+        self.public
+    }
+
+    /// Checks the `mac1` field of a handshake message sent to us.
+    pub fn check_mac1(&self, msg: &[u8]) -> bool {
+        let off = msg.len() - 32;
+        // not secret, but compare in constant time anyway
+        mac(&self.mac1_key, &msg[..off])
+            .iter()
+            .zip(&msg[off..off + 16])
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+    }
+}
+
+/// What we know about a peer for handshakes.
+pub struct PeerKeys {
+    pub public: [u8; 32],
+    pub psk: [u8; 32],
+    /// The key of the `mac1` field of the messages sent to the peer.
+    mac1_key: [u8; 32],
+    /// `DH(S_priv_local, S_pub_peer)`, which never changes.
+    static_static: [u8; 32],
+}
+
+impl PeerKeys {
+    pub fn new(identity: &Identity, public: [u8; 32], psk: [u8; 32]) -> Self {
+        Self {
+            public,
+            psk,
+            mac1_key: hash(&[LABEL_MAC1, &public]),
+            static_static: dh(&identity.secret, &public),
+        }
+    }
+
+    /// Fills the `mac1` field, and clears `mac2` as we never have a cookie.
+    fn seal_macs(&self, msg: &mut [u8]) {
+        let off = msg.len() - 32;
+        let mac1 = mac(&self.mac1_key, &msg[..off]);
+        msg[off..off + 16].copy_from_slice(&mac1);
+        msg[off + 16..].fill(0);
+    }
+}
+
+/// The symmetric state of a handshake in progress.
+#[derive(Clone)]
+struct SymmetricState {
+    chaining_key: [u8; 32],
+    hash: [u8; 32],
+}
+
+impl SymmetricState {
+    /// The state before the first message to `responder`.
+    fn new(responder: &[u8; 32]) -> Self {
+        let chaining_key = hash(&[CONSTRUCTION]);
+        let hash = hash(&[&hash(&[&chaining_key, IDENTIFIER]), responder]);
+        Self { chaining_key, hash }
+    }
+
+    fn mix_hash(&mut self, data: &[u8]) {
+        self.hash = hash(&[&self.hash, data]);
+    }
+
+    fn mix_key(&mut self, input: &[u8]) {
+        [self.chaining_key] = kdf(&self.chaining_key, input);
+    }
+
+    /// Mixes `input` in the chaining key and returns a key to encrypt with.
+    fn mix_key_encrypt(&mut self, input: &[u8]) -> [u8; 32] {
+        let [chaining_key, key] = kdf(&self.chaining_key, input);
+        self.chaining_key = chaining_key;
+        key
+    }
+
+    fn encrypt(&mut self, key: &[u8; 32], plaintext: &[u8], out: &mut [u8]) {
+        let mut buf = plaintext.to_vec();
+        seal(key, 0, &self.hash, &mut buf);
+        out.copy_from_slice(&buf);
+        self.mix_hash(&buf);
+    }
+
+    fn decrypt<const N: usize>(&mut self, key: &[u8; 32], sealed: &[u8]) -> Option<[u8; N]> {
+        let plaintext = open_array(key, &self.hash, sealed)?;
+        self.mix_hash(sealed);
+        Some(plaintext)
+    }
+
+    /// Mixes the preshared key, and returns a key to encrypt with.
+    fn mix_psk(&mut self, psk: &[u8; 32]) -> [u8; 32] {
+        let [chaining_key, tau, key] = kdf(&self.chaining_key, psk);
+        self.chaining_key = chaining_key;
+        self.mix_hash(&tau);
+        key
+    }
+
+    /// Returns the transport keys, the sending one first for the initiator.
+    fn split(&self) -> ([u8; 32], [u8; 32]) {
+        let [first, second] = kdf(&self.chaining_key, &[]);
+        (first, second)
+    }
+}
+
+/// A handshake we initiated, waiting for the response.
+pub struct Initiation {
+    pub sender_index: u32,
+    ephemeral: StaticSecret,
+    state: SymmetricState,
+}
+
+/// Transport keys established by a handshake.
+pub struct Keys {
+    pub send: [u8; 32],
+    pub recv: [u8; 32],
+    /// Index of the peer, to put in the messages we send.
+    pub remote_index: u32,
+}
+
+/// Creates a handshake initiation message for `peer`.
+pub fn create_initiation(
+    identity: &Identity,
+    peer: &PeerKeys,
+    sender_index: u32,
+) -> (Initiation, [u8; INITIATION_LEN]) {
+    let mut msg = [0u8; INITIATION_LEN];
+    msg[0] = MSG_INITIATION;
+    msg[4..8].copy_from_slice(&sender_index.to_le_bytes());
+
+    let mut state = SymmetricState::new(&peer.public);
+    let ephemeral = generate_ephemeral();
+    let ephemeral_public = PublicKey::from(&ephemeral).to_bytes();
+    msg[8..40].copy_from_slice(&ephemeral_public);
+    state.mix_key(&ephemeral_public);
+    state.mix_hash(&ephemeral_public);
+
+    let key = state.mix_key_encrypt(&dh(&ephemeral, &peer.public));
+    state.encrypt(&key, &identity.public, &mut msg[40..88]);
+    let key = state.mix_key_encrypt(&peer.static_static);
+    state.encrypt(&key, &tai64n(), &mut msg[88..116]);
+    peer.seal_macs(&mut msg);
+
+    let initiation = Initiation {
+        sender_index,
+        ephemeral,
+        state,
+    };
+    (initiation, msg)
+}
+
+/// A handshake initiation received, decrypted but not answered yet.
+pub struct ReceivedInitiation {
+    /// The static public key of the initiator.
+    pub peer: [u8; 32],
+    pub timestamp: [u8; TAI64N_LEN],
+    sender_index: u32,
+    ephemeral: [u8; 32],
+    state: SymmetricState,
+}
+
+/// Decrypts a handshake initiation sent to us, whose `mac1` was checked.
+///
+/// `lookup` returns the keys of the peer with a static public key, if known.
+pub fn consume_initiation<'a>(
+    identity: &Identity,
+    msg: &[u8],
+    lookup: impl FnOnce(&[u8; 32]) -> Option<&'a PeerKeys>,
+) -> Option<ReceivedInitiation> {
+    if msg.len() != INITIATION_LEN {
+        return None;
+    }
+    let sender_index = u32::from_le_bytes(msg[4..8].try_into().unwrap());
+    let ephemeral: [u8; 32] = msg[8..40].try_into().unwrap();
+
+    let mut state = SymmetricState::new(&identity.public);
+    state.mix_key(&ephemeral);
+    state.mix_hash(&ephemeral);
+    let key = state.mix_key_encrypt(&dh(&identity.secret, &ephemeral));
+    let peer = state.decrypt::<32>(&key, &msg[40..88])?;
+    let key = state.mix_key_encrypt(&lookup(&peer)?.static_static);
+    let timestamp = state.decrypt::<TAI64N_LEN>(&key, &msg[88..116])?;
+    Some(ReceivedInitiation {
+        peer,
+        timestamp,
+        sender_index,
+        ephemeral,
+        state,
+    })
+}
+
+/// Creates the response to a handshake initiation, and returns the keys of
+/// the new session.
+pub fn create_response(
+    peer: &PeerKeys,
+    initiation: ReceivedInitiation,
+    sender_index: u32,
+) -> (Keys, [u8; RESPONSE_LEN]) {
+    let mut msg = [0u8; RESPONSE_LEN];
+    msg[0] = MSG_RESPONSE;
+    msg[4..8].copy_from_slice(&sender_index.to_le_bytes());
+    msg[8..12].copy_from_slice(&initiation.sender_index.to_le_bytes());
+
+    let mut state = initiation.state;
+    let ephemeral = generate_ephemeral();
+    let ephemeral_public = PublicKey::from(&ephemeral).to_bytes();
+    msg[12..44].copy_from_slice(&ephemeral_public);
+    state.mix_key(&ephemeral_public);
+    state.mix_hash(&ephemeral_public);
+    state.mix_key(&dh(&ephemeral, &initiation.ephemeral));
+    state.mix_key(&dh(&ephemeral, &peer.public));
+    let key = state.mix_psk(&peer.psk);
+    state.encrypt(&key, &[], &mut msg[44..60]);
+    peer.seal_macs(&mut msg);
+
+    let (recv, send) = state.split();
+    let keys = Keys {
+        send,
+        recv,
+        remote_index: initiation.sender_index,
+    };
+    (keys, msg)
+}
+
+/// Returns the index of the initiation a handshake response answers.
+pub fn response_receiver_index(msg: &[u8]) -> Option<u32> {
+    (msg.len() == RESPONSE_LEN).then(|| u32::from_le_bytes(msg[8..12].try_into().unwrap()))
+}
+
+/// Processes the response to our initiation, and returns the keys of the new
+/// session.
+pub fn consume_response(
+    identity: &Identity,
+    peer: &PeerKeys,
+    initiation: &Initiation,
+    msg: &[u8],
+) -> Option<Keys> {
+    let remote_index = u32::from_le_bytes(msg[4..8].try_into().unwrap());
+    let ephemeral: [u8; 32] = msg[12..44].try_into().unwrap();
+
+    let mut state = initiation.state.clone();
+    state.mix_key(&ephemeral);
+    state.mix_hash(&ephemeral);
+    state.mix_key(&dh(&initiation.ephemeral, &ephemeral));
+    state.mix_key(&dh(&identity.secret, &ephemeral));
+    let key = state.mix_psk(&peer.psk);
+    state.decrypt::<0>(&key, &msg[44..60])?;
+
+    let (send, recv) = state.split();
+    Some(Keys {
+        send,
+        recv,
+        remote_index,
+    })
+}
+
+/// Builds a transport data message carrying `packet`, padded to a multiple
+/// of 16 bytes, in the buffer of the packet.
+pub fn seal_transport(key: &[u8; 32], remote_index: u32, counter: u64, packet: &mut SkBuff) {
+    let padding = packet.len().next_multiple_of(16) - packet.len();
+    packet.reserve(TRANSPORT_HEADER_LEN, padding + TAG_LEN);
+    packet.put(padding).fill(0);
+    let tag = ChaCha20Poly1305::new(key).seal_in_place(&nonce(counter), &[], packet.data_mut());
+    packet.put(TAG_LEN).copy_from_slice(&tag);
+
+    let header = packet.push(TRANSPORT_HEADER_LEN);
+    header[..4].copy_from_slice(&[MSG_TRANSPORT, 0, 0, 0]);
+    header[4..8].copy_from_slice(&remote_index.to_le_bytes());
+    header[8..].copy_from_slice(&counter.to_le_bytes());
+}
+
+/// Decrypts the payload of a transport data message in place, and returns
+/// its length, padding included.
+pub fn open_transport(key: &[u8; 32], counter: u64, payload: &mut [u8]) -> Option<usize> {
+    open(key, counter, &[], payload)
+}
@@ -0,0 +1,179 @@
+//! SNTP client (RFC 4330), to synchronize the realtime clock.
+//!
+//! Small offsets are corrected gradually with
+//! [`axhal::time::adjust_wall_time`], so that the clock never jumps, and only
+//! offsets larger than [`STEP_THRESHOLD`] set the clock at once.
+
+use core::net::{IpAddr, SocketAddr};
+use core::time::Duration;
+
+use axerrno::{ax_err, ax_err_type, AxError, AxResult};
+use axhal::time::{monotonic_time, wall_time_nanos, NANOS_PER_SEC};
+
+use crate::{dns_query, UdpSocket};
+
+/// The NTP port.
+pub const NTP_PORT: u16 = 123;
+
+/// Offsets smaller than this are slewed, larger ones are stepped.
+pub const STEP_THRESHOLD: Duration = Duration::from_millis(128);
+
+/// Time to wait for the reply of the server.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Interval between synchronizations.
+const POLL_INTERVAL: Duration = Duration::from_secs(64);
+/// Interval between retries after a failure.
+const RETRY_INTERVAL: Duration = Duration::from_secs(16);
+
+/// Seconds from 1900-01-01 (the NTP epoch) to 1970-01-01 (the Unix epoch).
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+const PACKET_LEN: usize = 48;
+const MODE_CLIENT: u8 = 3;
+const MODE_SERVER: u8 = 4;
+const VERSION: u8 = 4;
+const LEAP_UNSYNCHRONIZED: u8 = 3;
+
+/// The result of a synchronization.
+#[derive(Debug, Clone, Copy)]
+pub struct SntpSample {
+    /// Offset of the server clock from the local clock, in nanoseconds.
+    pub offset_nanos: i64,
+    /// Round-trip delay to the server, in nanoseconds.
+    pub delay_nanos: i64,
+    /// Whether the local clock was stepped, instead of slewed.
+    pub stepped: bool,
+}
+
+/// Converts nanoseconds since the Unix epoch to an NTP timestamp.
+fn to_ntp(unix_nanos: u64) -> u64 {
+    let secs = unix_nanos / NANOS_PER_SEC + NTP_UNIX_OFFSET;
+    let frac = ((unix_nanos % NANOS_PER_SEC) << 32) / NANOS_PER_SEC;
+    (secs << 32) | frac
+}
+
+/// Converts an NTP timestamp to nanoseconds since the Unix epoch.
+fn from_ntp(ntp: u64) -> i64 {
+    let secs = (ntp >> 32) as i64 - NTP_UNIX_OFFSET as i64;
+    let nanos = ((ntp & 0xffff_ffff) * NANOS_PER_SEC) >> 32;
+    secs * NANOS_PER_SEC as i64 + nanos as i64
+}
+
+fn read_timestamp(packet: &[u8; PACKET_LEN], offset: usize) -> u64 {
+    u64::from_be_bytes(packet[offset..offset + 8].try_into().unwrap())
+}
+
+/// Sends a request to `server` and returns the reply, with the local send and
+/// receive times in nanoseconds since the Unix epoch.
+fn exchange(server: SocketAddr) -> AxResult<([u8; PACKET_LEN], u64, u64)> {
+    let socket = UdpSocket::new();
+    socket.set_nonblocking(true);
+    socket.connect(server)?;
+
+    let t1 = wall_time_nanos();
+    let mut request = [0u8; PACKET_LEN];
+    request[0] = (VERSION << 3) | MODE_CLIENT;
+    request[40..48].copy_from_slice(&to_ntp(t1).to_be_bytes());
+    // measure the round trip on the monotonic clock, which is not adjusted
+    let sent = monotonic_time();
+
+    let mut reply = [0u8; PACKET_LEN];
+    let res = loop {
+        match socket.send(&request) {
+            Ok(_) => break Ok(()),
+            Err(AxError::WouldBlock) if monotonic_time() - sent < REPLY_TIMEOUT => {
+                crate::poll_interfaces();
+                axtask::yield_now();
+            }
+            Err(AxError::WouldBlock) => break ax_err!(TimedOut, "SNTP request not sent"),
+            Err(e) => break Err(e),
+        }
+    }
+    .and_then(|_| loop {
+        crate::poll_interfaces();
+        match socket.recv(&mut reply) {
+            Ok(len) if len >= PACKET_LEN && read_timestamp(&reply, 24) == to_ntp(t1) => {
+                break Ok(());
+            }
+            // a short packet, or a reply to an older request
+            Ok(_) => {}
+            Err(AxError::WouldBlock) if monotonic_time() - sent < REPLY_TIMEOUT => {
+                axtask::yield_now();
+            }
+            Err(AxError::WouldBlock) => break ax_err!(TimedOut, "no reply from SNTP server"),
+            Err(e) => break Err(e),
+        }
+    });
+    let t4 = t1 + (monotonic_time() - sent).as_nanos() as u64;
+    socket.shutdown().ok();
+    res.map(|_| (reply, t1, t4))
+}
+
+/// Queries the SNTP server `server` and corrects the realtime clock.
+pub fn sntp_sync(server: SocketAddr) -> AxResult<SntpSample> {
+    let (reply, t1, t4) = exchange(server)?;
+
+    let leap = reply[0] >> 6;
+    let mode = reply[0] & 0x7;
+    let stratum = reply[1];
+    let t3_raw = read_timestamp(&reply, 40);
+    if mode != MODE_SERVER || leap == LEAP_UNSYNCHRONIZED || stratum == 0 || t3_raw == 0 {
+        return ax_err!(InvalidData, "bad SNTP reply");
+    }
+    let t2 = from_ntp(read_timestamp(&reply, 32));
+    let t3 = from_ntp(t3_raw);
+    let (t1, t4) = (t1 as i64, t4 as i64);
+
+    let offset_nanos = ((t2 - t1) + (t3 - t4)) / 2;
+    let delay_nanos = (t4 - t1) - (t3 - t2);
+    let stepped = offset_nanos.unsigned_abs() >= STEP_THRESHOLD.as_nanos() as u64;
+    if stepped {
+        let now = (wall_time_nanos() as i64 + offset_nanos).max(0) as u64;
+        axhal::time::set_wall_time(Duration::from_nanos(now));
+    } else {
+        axhal::time::adjust_wall_time(offset_nanos);
+    }
+    Ok(SntpSample {
+        offset_nanos,
+        delay_nanos,
+        stepped,
+    })
+}
+
+/// Resolves `server`, an IP address or a host name, to the address of its
+/// NTP service.
+fn resolve(server: &str) -> AxResult<SocketAddr> {
+    let ip = match server.parse::<IpAddr>() {
+        Ok(ip) => ip,
+        Err(_) => *dns_query(server)?
+            .first()
+            .ok_or_else(|| ax_err_type!(NotFound, "SNTP server not found"))?,
+    };
+    Ok(SocketAddr::new(ip, NTP_PORT))
+}
+
+/// Synchronizes the realtime clock with the SNTP server `server` (an IP
+/// address or a host name) periodically, forever.
+///
+/// It is the routine of a background task.
+pub fn sntp_client(server: &str) -> ! {
+    loop {
+        let interval = match resolve(server).and_then(sntp_sync) {
+            Ok(sample) => {
+                debug!(
+                    "SNTP: offset {} us, delay {} us{}",
+                    sample.offset_nanos / 1000,
+                    sample.delay_nanos / 1000,
+                    if sample.stepped { ", stepped" } else { "" }
+                );
+                POLL_INTERVAL
+            }
+            Err(e) => {
+                warn!("SNTP synchronization with {} failed: {:?}", server, e);
+                RETRY_INTERVAL
+            }
+        };
+        axtask::sleep(interval);
+    }
+}
@@ -0,0 +1,205 @@
+//! Syslog client (RFC 5424), over UDP (RFC 5426) or TCP (RFC 6587), to ship
+//! the log messages to a remote server.
+
+use alloc::vec::Vec;
+use core::net::{IpAddr, SocketAddr};
+use core::time::Duration;
+
+use axerrno::{ax_err, ax_err_type, AxResult};
+use axhal::time::{wall_time_nanos, NANOS_PER_MICROS, NANOS_PER_SEC};
+use log::Level;
+
+use crate::{dns_query, TcpSocket, UdpSocket};
+
+/// The syslog port, for both UDP and TCP.
+pub const SYSLOG_PORT: u16 = 514;
+
+/// The maximum length of a message sent, longer ones are truncated.
+const MAX_MSG_LEN: usize = 1024;
+
+/// Interval between the checks for new messages.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Interval between retries after a failure to connect.
+const RETRY_INTERVAL: Duration = Duration::from_secs(16);
+
+/// The facility of the messages: kernel.
+const FACILITY_KERN: u8 = 0;
+
+/// Wall times before 2000-01-01 are taken as not set.
+const MIN_WALL_TIME_SECS: u64 = 946_684_800;
+
+/// The transport to the syslog server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyslogTransport {
+    /// UDP, a datagram per message.
+    Udp,
+    /// TCP, with the messages framed by their length.
+    Tcp,
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpSocket),
+}
+
+impl Connection {
+    fn open(transport: SyslogTransport, host: &str, port: u16) -> AxResult<Self> {
+        match transport {
+            SyslogTransport::Udp => {
+                let ip = match host.parse::<IpAddr>() {
+                    Ok(ip) => ip,
+                    Err(_) => *dns_query(host)?
+                        .first()
+                        .ok_or_else(|| ax_err_type!(NotFound, "syslog server not found"))?,
+                };
+                let socket = UdpSocket::new();
+                socket.connect(SocketAddr::new(ip, port))?;
+                Ok(Self::Udp(socket))
+            }
+            SyslogTransport::Tcp => Ok(Self::Tcp(TcpSocket::connect_host(host, port)?)),
+        }
+    }
+
+    fn send(&self, msg: &[u8]) -> AxResult {
+        match self {
+            Self::Udp(socket) => socket.send(msg).map(|_| ()),
+            Self::Tcp(socket) => {
+                let mut frame = Vec::with_capacity(msg.len() + 6);
+                frame.extend_from_slice(alloc::format!("{} ", msg.len()).as_bytes());
+                frame.extend_from_slice(msg);
+                let mut sent = 0;
+                while sent < frame.len() {
+                    match socket.send(&frame[sent..])? {
+                        0 => return ax_err!(ConnectionReset, "syslog server closed"),
+                        n => sent += n,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        match self {
+            Self::Udp(socket) => socket.shutdown().ok(),
+            Self::Tcp(socket) => socket.shutdown().ok(),
+        };
+    }
+}
+
+/// Parses a syslog target, `[udp://|tcp://]host[:port]`, UDP and
+/// [`SYSLOG_PORT`] by default.
+pub fn parse_syslog_target(target: &str) -> Option<(SyslogTransport, &str, u16)> {
+    let (transport, rest) = if let Some(rest) = target.strip_prefix("udp://") {
+        (SyslogTransport::Udp, rest)
+    } else if let Some(rest) = target.strip_prefix("tcp://") {
+        (SyslogTransport::Tcp, rest)
+    } else {
+        (SyslogTransport::Udp, target)
+    };
+    let (host, port) = match rest.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (rest, SYSLOG_PORT),
+    };
+    (!host.is_empty()).then_some((transport, host, port))
+}
+
+/// The syslog severity of a log level.
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// Writes the wall time in the format of RFC 3339, or `-` if it is not set.
+fn write_timestamp(out: &mut Vec<u8>) {
+    let nanos = wall_time_nanos();
+    let secs = nanos / NANOS_PER_SEC;
+    if secs < MIN_WALL_TIME_SECS {
+        out.push(b'-');
+        return;
+    }
+    // the civil date of the days since 1970-01-01, from Howard Hinnant's
+    // `civil_from_days`
+    let z = (secs / 86400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    let time = secs % 86400;
+    out.extend_from_slice(
+        alloc::format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+            year,
+            month,
+            day,
+            time / 3600,
+            time / 60 % 60,
+            time % 60,
+            nanos % NANOS_PER_SEC / NANOS_PER_MICROS,
+        )
+        .as_bytes(),
+    );
+}
+
+/// Formats a message of `level` from `hostname` in the format of RFC 5424.
+fn format_message(hostname: &str, level: Level, text: &[u8]) -> Vec<u8> {
+    let pri = FACILITY_KERN * 8 + severity(level);
+    let mut msg = Vec::with_capacity(MAX_MSG_LEN);
+    msg.extend_from_slice(alloc::format!("<{}>1 ", pri).as_bytes());
+    write_timestamp(&mut msg);
+    msg.extend_from_slice(alloc::format!(" {} kernel - - - ", hostname).as_bytes());
+    msg.extend_from_slice(text);
+    msg.truncate(MAX_MSG_LEN);
+    msg
+}
+
+/// Sends the log messages given by `next` to the syslog server `target`
+/// (see [`parse_syslog_target`]), as from `hostname`, forever.
+///
+/// `next` takes the oldest message into the buffer, and returns its level
+/// and length. Messages are dropped while the server cannot be reached.
+///
+/// It is the routine of a background task.
+pub fn syslog_client(
+    target: &str,
+    hostname: &str,
+    mut next: impl FnMut(&mut [u8]) -> Option<(Level, usize)>,
+) -> ! {
+    let Some((transport, host, port)) = parse_syslog_target(target) else {
+        panic!("invalid syslog target {:?}", target);
+    };
+    let mut text = [0u8; MAX_MSG_LEN];
+    let mut conn = None;
+    loop {
+        let Some((level, len)) = next(&mut text) else {
+            axtask::sleep(POLL_INTERVAL);
+            continue;
+        };
+        if conn.is_none() {
+            match Connection::open(transport, host, port) {
+                Ok(c) => conn = Some(c),
+                Err(e) => {
+                    warn!("syslog: cannot reach {}: {:?}", target, e);
+                    axtask::sleep(RETRY_INTERVAL);
+                    continue;
+                }
+            }
+        }
+        let msg = format_message(hostname, level, &text[..len]);
+        if let Err(e) = conn.as_ref().unwrap().send(&msg) {
+            warn!("syslog: sending to {} failed: {:?}", target, e);
+            conn = None;
+        }
+    }
+}
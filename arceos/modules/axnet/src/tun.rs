@@ -0,0 +1,53 @@
+//! The `/dev/net/tun` character device, over the TAP interface `tap0`.
+//!
+//! Each write injects one Ethernet frame, and each read returns one frame
+//! sent on the interface, blocking until there is one, like a Linux TAP
+//! device set up with `IFF_TAP | IFF_NO_PI`. This lets a network stack or a
+//! VPN run in user space; `tap0` is connected to the system by adding it to
+//! the bridge or by [`nat_enable`](crate::nat_enable).
+
+use axerrno::AxError;
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+
+use crate::{poll_interfaces, tap_read, tap_write};
+
+/// The device file of the TAP interface, to be added to `/dev/net/tun`.
+pub struct TunDev;
+
+impl VfsNodeOps for TunDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::default_file(),
+            VfsNodeType::CharDevice,
+            0,
+            0,
+        ))
+    }
+
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        loop {
+            poll_interfaces();
+            match tap_read(buf) {
+                Err(AxError::WouldBlock) => {
+                    if axtask::current_deadline().is_expired() {
+                        return Err(AxError::TimedOut);
+                    }
+                    axtask::yield_now();
+                }
+                res => return res,
+            }
+        }
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        tap_write(buf)?;
+        poll_interfaces();
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
@@ -0,0 +1,568 @@
+//! Stream sockets over VirtIO vsock (`AF_VSOCK`), to talk with the host
+//! (e.g. the control plane of the hypervisor) without IP networking.
+//!
+//! The connections are handled here, on the packets of `axdriver::vsock`,
+//! which are received by the blocking calls, or by [`poll_vsock`].
+
+use alloc::collections::{BTreeMap, VecDeque};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use axdriver::vsock::{self, VsockHeader, VsockOp, MAX_PAYLOAD, SHUTDOWN_RCV, SHUTDOWN_SEND};
+use axerrno::{ax_err, AxError, AxResult};
+use axio::PollState;
+use spin::Mutex;
+
+pub use axdriver::vsock::VMADDR_CID_HOST;
+
+/// Binds to the CID of the guest, whatever it is.
+pub const VMADDR_CID_ANY: u64 = u32::MAX as u64;
+/// Binds to an ephemeral port.
+pub const VMADDR_PORT_ANY: u32 = u32::MAX;
+
+/// The size of the receive buffer of a connection, told to the peer.
+const BUF_ALLOC: u32 = 64 * 1024;
+/// Maximum number of connections waiting to be accepted by a listener.
+const LISTEN_BACKLOG: usize = 16;
+
+const EPHEMERAL_PORT_START: u32 = 0xc000;
+
+/// The address of a vsock socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VsockAddr {
+    /// Context ID, [`VMADDR_CID_HOST`] for the host.
+    pub cid: u64,
+    /// Port.
+    pub port: u32,
+}
+
+impl VsockAddr {
+    /// Creates an address from a CID and a port.
+    pub const fn new(cid: u64, port: u32) -> Self {
+        Self { cid, port }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Unbound,
+    Bound,
+    Listening,
+    Connecting,
+    Connected,
+    /// Closed by a shutdown or a reset once connected.
+    Closed,
+    /// The connection request is refused.
+    Refused,
+}
+
+struct Conn {
+    state: State,
+    local_port: u32,
+    peer: VsockAddr,
+    /// The listener which accepted the connection.
+    listener: Option<usize>,
+    rx: VecDeque<u8>,
+    /// Number of bytes taken from `rx`.
+    fwd_cnt: u32,
+    /// The `fwd_cnt` last told to the peer.
+    fwd_cnt_sent: u32,
+    /// Number of bytes sent.
+    tx_cnt: u32,
+    peer_buf_alloc: u32,
+    peer_fwd_cnt: u32,
+    /// The `SHUTDOWN_*` flags of the peer.
+    peer_shutdown: u32,
+    /// Whether this side will send no more data.
+    shutdown: bool,
+    /// The connections waiting to be accepted, for a listener.
+    accept_queue: VecDeque<usize>,
+}
+
+impl Conn {
+    const fn new(state: State, local_port: u32, peer: VsockAddr) -> Self {
+        Self {
+            state,
+            local_port,
+            peer,
+            listener: None,
+            rx: VecDeque::new(),
+            fwd_cnt: 0,
+            fwd_cnt_sent: 0,
+            tx_cnt: 0,
+            peer_buf_alloc: 0,
+            peer_fwd_cnt: 0,
+            peer_shutdown: 0,
+            shutdown: false,
+            accept_queue: VecDeque::new(),
+        }
+    }
+
+    fn is_established(&self) -> bool {
+        matches!(self.state, State::Connecting | State::Connected)
+    }
+
+    /// Number of bytes the peer can still receive.
+    fn credit(&self) -> u32 {
+        let in_flight = self.tx_cnt.wrapping_sub(self.peer_fwd_cnt);
+        self.peer_buf_alloc.saturating_sub(in_flight)
+    }
+
+    fn send(&mut self, op: VsockOp, flags: u32, data: &[u8]) -> AxResult {
+        let hdr = VsockHeader {
+            src_cid: 0,
+            dst_cid: self.peer.cid,
+            src_port: self.local_port,
+            dst_port: self.peer.port,
+            op,
+            flags,
+            buf_alloc: BUF_ALLOC,
+            fwd_cnt: self.fwd_cnt,
+        };
+        vsock::send(&hdr, data)?;
+        self.fwd_cnt_sent = self.fwd_cnt;
+        self.tx_cnt = self.tx_cnt.wrapping_add(data.len() as u32);
+        Ok(())
+    }
+}
+
+static CONNS: Mutex<BTreeMap<usize, Conn>> = Mutex::new(BTreeMap::new());
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+static NEXT_PORT: AtomicU32 = AtomicU32::new(EPHEMERAL_PORT_START);
+
+fn insert(conns: &mut BTreeMap<usize, Conn>, conn: Conn) -> usize {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    conns.insert(id, conn);
+    id
+}
+
+fn port_in_use(conns: &BTreeMap<usize, Conn>, port: u32) -> bool {
+    conns
+        .values()
+        .any(|c| c.state != State::Unbound && c.listener.is_none() && c.local_port == port)
+}
+
+fn ephemeral_port(conns: &BTreeMap<usize, Conn>) -> AxResult<u32> {
+    for _ in EPHEMERAL_PORT_START..u32::MAX {
+        let port = NEXT_PORT.fetch_add(1, Ordering::Relaxed);
+        if port == u32::MAX {
+            NEXT_PORT.store(EPHEMERAL_PORT_START, Ordering::Relaxed);
+            continue;
+        }
+        if !port_in_use(conns, port) {
+            return Ok(port);
+        }
+    }
+    ax_err!(AddrInUse, "no available vsock ports")
+}
+
+/// Resets the connection of a packet no socket takes.
+fn reply_rst(hdr: &VsockHeader) {
+    if hdr.op == VsockOp::Rst {
+        return;
+    }
+    let rst = VsockHeader {
+        src_cid: 0,
+        dst_cid: hdr.src_cid,
+        src_port: hdr.dst_port,
+        dst_port: hdr.src_port,
+        op: VsockOp::Rst,
+        flags: 0,
+        buf_alloc: 0,
+        fwd_cnt: 0,
+    };
+    vsock::send(&rst, &[]).ok();
+}
+
+fn handle_packet(conns: &mut BTreeMap<usize, Conn>, hdr: &VsockHeader, data: &[u8]) {
+    let peer = VsockAddr::new(hdr.src_cid, hdr.src_port);
+    if let Some(conn) = conns
+        .values_mut()
+        .find(|c| c.is_established() && c.local_port == hdr.dst_port && c.peer == peer)
+    {
+        conn.peer_buf_alloc = hdr.buf_alloc;
+        conn.peer_fwd_cnt = hdr.fwd_cnt;
+        match hdr.op {
+            VsockOp::Response if conn.state == State::Connecting => {
+                conn.state = State::Connected;
+            }
+            VsockOp::Rw if conn.state == State::Connected => {
+                if conn.rx.len() + data.len() > BUF_ALLOC as usize {
+                    warn!("vsock: the peer {:?} exceeds its credit", peer);
+                } else {
+                    conn.rx.extend(data);
+                }
+            }
+            VsockOp::Shutdown => {
+                conn.peer_shutdown |= hdr.flags;
+                if conn.peer_shutdown & (SHUTDOWN_RCV | SHUTDOWN_SEND)
+                    == SHUTDOWN_RCV | SHUTDOWN_SEND
+                {
+                    // fully closed, confirmed by a reset
+                    conn.send(VsockOp::Rst, 0, &[]).ok();
+                    conn.state = State::Closed;
+                }
+            }
+            VsockOp::Rst => {
+                conn.peer_shutdown = SHUTDOWN_RCV | SHUTDOWN_SEND;
+                conn.state = if conn.state == State::Connecting {
+                    State::Refused
+                } else {
+                    State::Closed
+                };
+            }
+            VsockOp::CreditRequest => {
+                conn.send(VsockOp::CreditUpdate, 0, &[]).ok();
+            }
+            _ => {}
+        }
+        return;
+    }
+
+    if hdr.op != VsockOp::Request {
+        reply_rst(hdr);
+        return;
+    }
+    let listener = conns.iter().find(|(_, c)| {
+        c.state == State::Listening
+            && c.local_port == hdr.dst_port
+            && c.accept_queue.len() < LISTEN_BACKLOG
+    });
+    let Some((&listener_id, _)) = listener else {
+        reply_rst(hdr);
+        return;
+    };
+    let mut conn = Conn::new(State::Connected, hdr.dst_port, peer);
+    conn.listener = Some(listener_id);
+    conn.peer_buf_alloc = hdr.buf_alloc;
+    conn.peer_fwd_cnt = hdr.fwd_cnt;
+    if conn.send(VsockOp::Response, 0, &[]).is_err() {
+        return;
+    }
+    let id = insert(conns, conn);
+    if let Some(listener) = conns.get_mut(&listener_id) {
+        listener.accept_queue.push_back(id);
+    }
+}
+
+/// Receives the vsock packets, and handles them.
+///
+/// It is done by the blocking calls of [`VsockSocket`], so it is only needed
+/// before the nonblocking ones.
+pub fn poll_vsock() {
+    let mut conns = CONNS.lock();
+    if vsock::take_reset() {
+        warn!("vsock: transport reset, closing all the connections");
+        for conn in conns.values_mut().filter(|c| c.is_established()) {
+            conn.peer_shutdown = SHUTDOWN_RCV | SHUTDOWN_SEND;
+            conn.state = if conn.state == State::Connecting {
+                State::Refused
+            } else {
+                State::Closed
+            };
+        }
+    }
+    let mut buf = [0; MAX_PAYLOAD];
+    while let Some((hdr, len)) = vsock::recv(&mut buf) {
+        handle_packet(&mut conns, &hdr, &buf[..len]);
+    }
+}
+
+fn wait_or_time_out() -> AxResult {
+    if axtask::current_deadline().is_expired() {
+        return ax_err!(TimedOut, "socket operation timed out");
+    }
+    axtask::yield_now();
+    Ok(())
+}
+
+/// A vsock stream socket that provides POSIX-like APIs.
+///
+/// - [`connect`] is for stream clients.
+/// - [`bind`], [`listen`], and [`accept`] are for stream servers.
+/// - Other methods are for both clients and servers.
+///
+/// [`connect`]: VsockSocket::connect
+/// [`bind`]: VsockSocket::bind
+/// [`listen`]: VsockSocket::listen
+/// [`accept`]: VsockSocket::accept
+pub struct VsockSocket {
+    id: usize,
+    nonblock: AtomicBool,
+}
+
+impl VsockSocket {
+    /// Creates a new vsock socket.
+    pub fn new() -> Self {
+        let conn = Conn::new(State::Unbound, 0, VsockAddr::new(0, 0));
+        Self {
+            id: insert(&mut CONNS.lock(), conn),
+            nonblock: AtomicBool::new(false),
+        }
+    }
+
+    fn with_conn<T>(&self, f: impl FnOnce(&mut Conn) -> AxResult<T>) -> AxResult<T> {
+        let mut conns = CONNS.lock();
+        f(conns.get_mut(&self.id).ok_or(AxError::BadState)?)
+    }
+
+    /// Returns the local address and port, or
+    /// [`Err(NotConnected)`](AxError::NotConnected) if not bound.
+    pub fn local_addr(&self) -> AxResult<VsockAddr> {
+        let cid = vsock::guest_cid().unwrap_or(VMADDR_CID_ANY);
+        self.with_conn(|conn| match conn.state {
+            State::Unbound => ax_err!(NotConnected),
+            _ => Ok(VsockAddr::new(cid, conn.local_port)),
+        })
+    }
+
+    /// Returns the remote address and port, or
+    /// [`Err(NotConnected)`](AxError::NotConnected) if not connected.
+    pub fn peer_addr(&self) -> AxResult<VsockAddr> {
+        self.with_conn(|conn| match conn.state {
+            State::Connected | State::Closed => Ok(conn.peer),
+            _ => ax_err!(NotConnected),
+        })
+    }
+
+    /// Returns whether this socket is in nonblocking mode.
+    #[inline]
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Acquire)
+    }
+
+    /// Moves this socket into or out of nonblocking mode.
+    ///
+    /// This will result in `connect`, `accept`, `send`, and `recv` operations
+    /// becoming nonblocking, i.e., immediately returning from their calls.
+    /// If the IO operation is successful, `Ok` is returned and no further
+    /// action is required. If the IO operation could not be completed and
+    /// needs to be retried, an error with kind
+    /// [`Err(WouldBlock)`](AxError::WouldBlock) is returned.
+    #[inline]
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Release);
+    }
+
+    /// Binds an unbound socket to the given address and port.
+    ///
+    /// The CID must be [`VMADDR_CID_ANY`] or the one of the guest, and the
+    /// port [`VMADDR_PORT_ANY`] for an ephemeral one.
+    pub fn bind(&self, addr: VsockAddr) -> AxResult {
+        if addr.cid != VMADDR_CID_ANY && Some(addr.cid) != vsock::guest_cid() {
+            return ax_err!(InvalidInput, "not the CID of the guest");
+        }
+        let mut conns = CONNS.lock();
+        let port = if addr.port == VMADDR_PORT_ANY {
+            ephemeral_port(&conns)?
+        } else if port_in_use(&conns, addr.port) {
+            return ax_err!(AddrInUse, "vsock port already in use");
+        } else {
+            addr.port
+        };
+        let conn = conns.get_mut(&self.id).ok_or(AxError::BadState)?;
+        if conn.state != State::Unbound {
+            return ax_err!(InvalidInput, "already bound");
+        }
+        conn.state = State::Bound;
+        conn.local_port = port;
+        Ok(())
+    }
+
+    /// Starts listening on the bound address and port.
+    pub fn listen(&self) -> AxResult {
+        self.with_conn(|conn| match conn.state {
+            State::Bound | State::Listening => {
+                conn.state = State::Listening;
+                Ok(())
+            }
+            State::Unbound => ax_err!(InvalidInput, "not bound"),
+            _ => ax_err!(InvalidInput, "already connected"),
+        })
+    }
+
+    /// Accepts a new connection.
+    ///
+    /// This function will block the calling thread until a new connection
+    /// is established. When established, a new [`VsockSocket`] is returned.
+    pub fn accept(&self) -> AxResult<VsockSocket> {
+        let id = self.block_on(|| {
+            self.with_conn(|conn| {
+                if conn.state != State::Listening {
+                    return ax_err!(InvalidInput, "not listening");
+                }
+                conn.accept_queue.pop_front().ok_or(AxError::WouldBlock)
+            })
+        })?;
+        if let Some(conn) = CONNS.lock().get_mut(&id) {
+            conn.listener = None;
+        }
+        Ok(VsockSocket {
+            id,
+            nonblock: AtomicBool::new(false),
+        })
+    }
+
+    /// Connects to the given address and port, binding to an ephemeral port
+    /// first if unbound.
+    ///
+    /// The connection is refused if nothing listens on the port.
+    pub fn connect(&self, addr: VsockAddr) -> AxResult {
+        {
+            let mut conns = CONNS.lock();
+            let port = match conns.get(&self.id).map(|c| c.state) {
+                Some(State::Unbound) => Some(ephemeral_port(&conns)?),
+                Some(State::Bound) => None,
+                Some(State::Connecting) => {
+                    return ax_err!(AlreadyExists, "connection already in progress")
+                }
+                Some(State::Connected) => return ax_err!(AlreadyExists, "already connected"),
+                _ => return ax_err!(InvalidInput, "cannot connect"),
+            };
+            let conn = conns.get_mut(&self.id).ok_or(AxError::BadState)?;
+            if let Some(port) = port {
+                conn.local_port = port;
+            }
+            conn.peer = addr;
+            conn.state = State::Connecting;
+            if let Err(e) = conn.send(VsockOp::Request, 0, &[]) {
+                conn.state = State::Bound;
+                return Err(e);
+            }
+        }
+        self.block_on(|| {
+            self.with_conn(|conn| match conn.state {
+                State::Connecting => Err(AxError::WouldBlock),
+                State::Connected => Ok(()),
+                _ => ax_err!(ConnectionRefused, "vsock connection refused"),
+            })
+        })
+    }
+
+    /// Transmits data in the given buffer.
+    ///
+    /// It sends as much as the receive buffer of the peer allows, and blocks
+    /// when it is full.
+    pub fn send(&self, buf: &[u8]) -> AxResult<usize> {
+        self.block_on(|| {
+            self.with_conn(|conn| {
+                match conn.state {
+                    State::Connected => {}
+                    State::Closed => return ax_err!(ConnectionReset, "vsock connection closed"),
+                    _ => return ax_err!(NotConnected),
+                }
+                if conn.shutdown || conn.peer_shutdown & SHUTDOWN_RCV != 0 {
+                    return ax_err!(ConnectionReset, "vsock connection shut down");
+                }
+                let len = buf.len().min(conn.credit() as usize);
+                if len == 0 && !buf.is_empty() {
+                    return Err(AxError::WouldBlock);
+                }
+                for chunk in buf[..len].chunks(MAX_PAYLOAD) {
+                    conn.send(VsockOp::Rw, 0, chunk)?;
+                }
+                Ok(len)
+            })
+        })
+    }
+
+    /// Receives data from the socket, stores it in the given buffer.
+    ///
+    /// It returns 0 once the peer will send no more data.
+    pub fn recv(&self, buf: &mut [u8]) -> AxResult<usize> {
+        self.block_on(|| {
+            self.with_conn(|conn| {
+                if conn.rx.is_empty() {
+                    return match conn.state {
+                        State::Closed => Ok(0),
+                        State::Connected if conn.peer_shutdown & SHUTDOWN_SEND != 0 => Ok(0),
+                        State::Connected => Err(AxError::WouldBlock),
+                        _ => ax_err!(NotConnected),
+                    };
+                }
+                let len = buf.len().min(conn.rx.len());
+                for (dst, src) in buf.iter_mut().zip(conn.rx.drain(..len)) {
+                    *dst = src;
+                }
+                conn.fwd_cnt = conn.fwd_cnt.wrapping_add(len as u32);
+                if conn.state == State::Connected
+                    && conn.fwd_cnt.wrapping_sub(conn.fwd_cnt_sent) >= BUF_ALLOC / 2
+                {
+                    // the peer may be waiting for room
+                    conn.send(VsockOp::CreditUpdate, 0, &[]).ok();
+                }
+                Ok(len)
+            })
+        })
+    }
+
+    /// Whether the socket is readable or writable.
+    pub fn poll(&self) -> AxResult<PollState> {
+        poll_vsock();
+        self.with_conn(|conn| {
+            let readable = match conn.state {
+                State::Listening => !conn.accept_queue.is_empty(),
+                State::Connected => !conn.rx.is_empty() || conn.peer_shutdown & SHUTDOWN_SEND != 0,
+                State::Closed | State::Refused => true,
+                _ => false,
+            };
+            Ok(PollState {
+                readable,
+                writable: conn.state == State::Connected && conn.credit() > 0,
+            })
+        })
+    }
+
+    /// Closes the connection, telling the peer that this side will neither
+    /// send nor receive any more data.
+    pub fn shutdown(&self) -> AxResult {
+        self.with_conn(|conn| {
+            if conn.state == State::Connected && !conn.shutdown {
+                conn.shutdown = true;
+                conn.send(VsockOp::Shutdown, SHUTDOWN_RCV | SHUTDOWN_SEND, &[])?;
+            }
+            Ok(())
+        })
+    }
+
+    fn block_on<F, T>(&self, mut f: F) -> AxResult<T>
+    where
+        F: FnMut() -> AxResult<T>,
+    {
+        if self.is_nonblocking() {
+            f()
+        } else {
+            loop {
+                poll_vsock();
+                match f() {
+                    Ok(t) => return Ok(t),
+                    Err(AxError::WouldBlock) => wait_or_time_out()?,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+}
+
+impl Default for VsockSocket {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for VsockSocket {
+    fn drop(&mut self) {
+        let mut conns = CONNS.lock();
+        let Some(mut conn) = conns.remove(&self.id) else {
+            return;
+        };
+        if conn.is_established() {
+            // the peer is not waited for, so reset rather than shut down
+            conn.send(VsockOp::Rst, 0, &[]).ok();
+        }
+        for id in conn.accept_queue {
+            if let Some(mut pending) = conns.remove(&id) {
+                pending.send(VsockOp::Rst, 0, &[]).ok();
+            }
+        }
+    }
+}
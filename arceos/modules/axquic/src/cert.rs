@@ -0,0 +1,122 @@
+//! The X.509 certificates of the servers (RFC 5280), with Ed25519 keys
+//! (RFC 8410): the self-signed ones of [`ServerConfig::new`], and the key of
+//! the ones received.
+//!
+//! [`ServerConfig::new`]: crate::ServerConfig::new
+
+use alloc::vec::Vec;
+
+use axcrypto::{ed25519_public_key, ed25519_sign};
+
+/// The algorithm identifier of Ed25519, without parameters.
+const ED25519: [u8; 5] = [0x06, 0x03, 0x2b, 0x65, 0x70];
+const COMMON_NAME: [u8; 5] = [0x06, 0x03, 0x55, 0x04, 0x03];
+const SUBJECT_ALT_NAME: [u8; 5] = [0x06, 0x03, 0x55, 0x1d, 0x11];
+
+/// Encodes a DER element of `tag`, its content being `parts` one after the
+/// other.
+fn der(tag: u8, parts: &[&[u8]]) -> Vec<u8> {
+    let len: usize = parts.iter().map(|p| p.len()).sum();
+    let mut out = Vec::with_capacity(len + 4);
+    out.push(tag);
+    if len < 0x80 {
+        out.push(len as u8);
+    } else if len < 0x100 {
+        out.extend_from_slice(&[0x81, len as u8]);
+    } else {
+        out.extend_from_slice(&[0x82, (len >> 8) as u8, len as u8]);
+    }
+    for part in parts {
+        out.extend_from_slice(part);
+    }
+    out
+}
+
+/// Returns a self-signed certificate of the Ed25519 key `secret_key`, for
+/// the DNS name `name`, valid from 2000 on.
+pub fn self_signed(secret_key: &[u8; 32], name: &str) -> Vec<u8> {
+    let alg = der(0x30, &[&ED25519]);
+    let cn = der(0x0c, &[name.as_bytes()]);
+    let cn = der(0x30, &[&der(0x31, &[&der(0x30, &[&COMMON_NAME, &cn])])]);
+    let validity = der(
+        0x30,
+        &[
+            &der(0x17, &[b"000101000000Z"]),
+            &der(0x18, &[b"99991231235959Z"]),
+        ],
+    );
+    let public_key = ed25519_public_key(secret_key);
+    let spki = der(0x30, &[&alg, &der(0x03, &[&[0], &public_key])]);
+    let dns_name = der(0x30, &[&der(0x82, &[name.as_bytes()])]);
+    let san = der(0x30, &[&SUBJECT_ALT_NAME, &der(0x04, &[&dns_name])]);
+    let tbs = der(
+        0x30,
+        &[
+            &[0xa0, 0x03, 0x02, 0x01, 0x02],
+            &[0x02, 0x01, 0x01],
+            &alg,
+            &cn,
+            &validity,
+            &cn,
+            &spki,
+            &der(0xa3, &[&der(0x30, &[&san])]),
+        ],
+    );
+    let sig = ed25519_sign(secret_key, &tbs);
+    der(0x30, &[&tbs, &alg, &der(0x03, &[&[0], &sig])])
+}
+
+/// The DER elements of a buffer, read in order.
+struct Der<'a>(&'a [u8]);
+
+impl<'a> Der<'a> {
+    /// Reads the next element, which must be of `tag`, and returns its
+    /// content.
+    fn read(&mut self, tag: u8) -> Option<Der<'a>> {
+        let [t, first, ..] = *self.0 else {
+            return None;
+        };
+        if t != tag {
+            return None;
+        }
+        let rest = &self.0[2..];
+        let (len, rest) = match first {
+            0..=0x7f => (first as usize, rest),
+            0x81..=0x83 => {
+                let n = (first & 0x7f) as usize;
+                let len = rest
+                    .get(..n)?
+                    .iter()
+                    .fold(0, |len, b| (len << 8) | *b as usize);
+                (len, &rest[n..])
+            }
+            _ => return None,
+        };
+        let content = rest.get(..len)?;
+        self.0 = &rest[len..];
+        Some(Der(content))
+    }
+}
+
+/// Returns the Ed25519 key of the certificate `cert`, or [`None`] if it has
+/// another kind of key. Nothing else of the certificate is checked.
+pub fn ed25519_key(cert: &[u8]) -> Option<[u8; 32]> {
+    let mut cert = Der(cert).read(0x30)?;
+    let mut tbs = cert.read(0x30)?;
+    if tbs.0.first() == Some(&0xa0) {
+        tbs.read(0xa0)?;
+    }
+    tbs.read(0x02)?; // serial number
+    tbs.read(0x30)?; // signature algorithm
+    tbs.read(0x30)?; // issuer
+    tbs.read(0x30)?; // validity
+    tbs.read(0x30)?; // subject
+    let mut spki = tbs.read(0x30)?;
+    if spki.read(0x30)?.0 != ED25519 {
+        return None;
+    }
+    match spki.read(0x03)?.0 {
+        [0, key @ ..] => key.try_into().ok(),
+        _ => None,
+    }
+}
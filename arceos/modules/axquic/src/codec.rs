@@ -0,0 +1,129 @@
+//! The wire formats shared by QUIC and TLS: variable-length integers, and
+//! big-endian integers and vectors prefixed by their length.
+
+use alloc::vec::Vec;
+
+/// The largest value of a variable-length integer.
+pub const MAX_VARINT: u64 = (1 << 62) - 1;
+
+/// Reads the fields of a buffer in order. Each read returns [`None`] if the
+/// buffer is too short.
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Returns what is left, without reading it.
+    pub fn peek_rest(&self) -> &'a [u8] {
+        &self.buf[self.pos..]
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.remaining() {
+            return None;
+        }
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Some(bytes)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.bytes(N).map(|b| b.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    pub fn u16(&mut self) -> Option<u16> {
+        self.array().map(u16::from_be_bytes)
+    }
+
+    pub fn u32(&mut self) -> Option<u32> {
+        self.array().map(u32::from_be_bytes)
+    }
+
+    pub fn varint(&mut self) -> Option<u64> {
+        let first = *self.buf.get(self.pos)?;
+        let len = 1 << (first >> 6);
+        let bytes = self.bytes(len)?;
+        let mut v = (first & 0x3f) as u64;
+        for b in &bytes[1..] {
+            v = (v << 8) | *b as u64;
+        }
+        Some(v)
+    }
+
+    /// Reads a vector prefixed by its length on `N` bytes.
+    pub fn vec<const N: usize>(&mut self) -> Option<&'a [u8]> {
+        let len = self
+            .bytes(N)?
+            .iter()
+            .fold(0, |len, b| (len << 8) | *b as usize);
+        self.bytes(len)
+    }
+
+    /// Reads a vector prefixed by its length as a variable-length integer.
+    pub fn varint_vec(&mut self) -> Option<&'a [u8]> {
+        let len = self.varint()?;
+        self.bytes(usize::try_from(len).ok()?)
+    }
+}
+
+/// Returns the length of the encoding of `v`, which is at most
+/// [`MAX_VARINT`].
+pub fn varint_len(v: u64) -> usize {
+    match v {
+        0..=0x3f => 1,
+        0x40..=0x3fff => 2,
+        0x4000..=0x3fff_ffff => 4,
+        _ => 8,
+    }
+}
+
+pub fn put_varint(out: &mut Vec<u8>, v: u64) {
+    debug_assert!(v <= MAX_VARINT);
+    match varint_len(v) {
+        1 => out.push(v as u8),
+        2 => out.extend_from_slice(&(v as u16 | 0x4000).to_be_bytes()),
+        4 => out.extend_from_slice(&(v as u32 | 0x8000_0000).to_be_bytes()),
+        _ => out.extend_from_slice(&(v | 0xc000_0000_0000_0000).to_be_bytes()),
+    }
+}
+
+pub fn put_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+pub fn put_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+/// Appends a vector prefixed by its length on `N` bytes, written by `f`.
+pub fn put_vec<const N: usize>(out: &mut Vec<u8>, f: impl FnOnce(&mut Vec<u8>)) {
+    let start = out.len();
+    out.extend_from_slice(&[0; N]);
+    f(out);
+    let len = out.len() - start - N;
+    debug_assert!(N == 8 || len < 1 << (8 * N));
+    for (i, b) in out[start..start + N].iter_mut().enumerate() {
+        *b = (len >> (8 * (N - 1 - i))) as u8;
+    }
+}
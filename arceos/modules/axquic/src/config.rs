@@ -0,0 +1,131 @@
+//! The configurations of the clients and of the servers.
+
+use alloc::{string::String, vec, vec::Vec};
+use core::time::Duration;
+
+/// The configuration of the transport, for clients and servers alike.
+#[derive(Debug, Clone)]
+pub struct TransportConfig {
+    /// How long the connection lasts without packets, as proposed to the
+    /// peer. Zero for no limit.
+    pub max_idle_timeout: Duration,
+    /// If set, how long without packets before a PING is sent, to keep the
+    /// connection alive.
+    pub keep_alive: Option<Duration>,
+    /// How much data the peer may send beyond what was read, on all the
+    /// streams.
+    pub max_data: u64,
+    /// How much data the peer may send beyond what was read, on each stream.
+    pub max_stream_data: u64,
+    /// How many bidirectional streams the peer may open at once.
+    pub max_streams_bidi: u64,
+    /// How many unidirectional streams the peer may open at once.
+    pub max_streams_uni: u64,
+    /// How much data is buffered for sending on each stream, until it is
+    /// acknowledged.
+    pub send_buffer: usize,
+    /// How long the acknowledgments may be delayed.
+    pub max_ack_delay: Duration,
+    /// The RTT assumed until it is measured.
+    pub initial_rtt: Duration,
+    /// The size of the UDP payloads sent, from 1200.
+    pub max_datagram_size: usize,
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        Self {
+            max_idle_timeout: Duration::from_secs(30),
+            keep_alive: None,
+            max_data: 1 << 20,
+            max_stream_data: 1 << 18,
+            max_streams_bidi: 100,
+            max_streams_uni: 100,
+            send_buffer: 1 << 18,
+            max_ack_delay: Duration::from_millis(25),
+            initial_rtt: Duration::from_millis(333),
+            max_datagram_size: 1200,
+        }
+    }
+}
+
+/// The configuration of the clients.
+pub struct ClientConfig {
+    /// The application protocols offered (ALPN), by preference.
+    pub alpn: Vec<Vec<u8>>,
+    /// The name of the server sent (SNI), if any.
+    pub server_name: Option<String>,
+    /// The Ed25519 keys the certificate of the server may have.
+    pub server_keys: Vec<[u8; 32]>,
+    /// Whether to send early data (0-RTT) when resuming a session.
+    pub early_data: bool,
+    pub transport: TransportConfig,
+    /// Fills a buffer with random bytes, which the keys of the handshake are
+    /// made of.
+    pub random: fn(&mut [u8]),
+}
+
+impl ClientConfig {
+    /// Creates the configuration of a client offering the application
+    /// protocol `alpn`, to a server of the Ed25519 key `server_key`.
+    pub fn new(alpn: &[u8], server_key: [u8; 32], random: fn(&mut [u8])) -> Self {
+        Self {
+            alpn: vec![alpn.to_vec()],
+            server_name: None,
+            server_keys: vec![server_key],
+            early_data: false,
+            transport: TransportConfig::default(),
+            random,
+        }
+    }
+}
+
+/// The configuration of the servers.
+pub struct ServerConfig {
+    /// The application protocols accepted (ALPN), by preference.
+    pub alpn: Vec<Vec<u8>>,
+    /// The certificate chain sent, from the one of the server.
+    pub certificates: Vec<Vec<u8>>,
+    /// The Ed25519 secret key of the certificate of the server.
+    pub secret_key: [u8; 32],
+    /// Whether to accept early data (0-RTT).
+    ///
+    /// It is not protected against replays: an attacker can send it again
+    /// in other connections, so only data with no side effects should be
+    /// sent this way.
+    pub early_data: bool,
+    /// How long the session tickets sent can be used, none being sent if
+    /// zero.
+    pub ticket_lifetime: Duration,
+    pub transport: TransportConfig,
+    /// Fills a buffer with random bytes, which the keys of the handshake are
+    /// made of.
+    pub random: fn(&mut [u8]),
+    /// The key sealing the session tickets.
+    pub(crate) ticket_key: [u8; 16],
+}
+
+impl ServerConfig {
+    /// Creates the configuration of a server accepting the application
+    /// protocol `alpn`, with a self-signed certificate for `server_name` of
+    /// the Ed25519 key `secret_key`.
+    pub fn new(
+        alpn: &[u8],
+        secret_key: [u8; 32],
+        server_name: &str,
+        random: fn(&mut [u8]),
+    ) -> Self {
+        let mut ticket_key = [0; 16];
+        random(&mut ticket_key);
+        Self {
+            alpn: vec![alpn.to_vec()],
+            certificates: vec![crate::cert::self_signed(&secret_key, server_name)],
+            secret_key,
+            early_data: false,
+            ticket_lifetime: Duration::from_secs(24 * 3600),
+            transport: TransportConfig::default(),
+            random,
+            ticket_key,
+        }
+    }
+}
@@ -0,0 +1,1953 @@
+//! The state machine of a connection.
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::time::Duration;
+
+use axcrypto::AEAD_TAG_SIZE;
+use axerrno::{ax_err, AxError, AxResult};
+
+use crate::codec::Reader;
+use crate::frame::{self, Frame, MAX_STREAMS};
+use crate::packet::{
+    self, put_header, ConnectionId, Header, Keys, PacketType, Space, MIN_INITIAL_SIZE, VERSION,
+};
+use crate::params::TransportParams;
+use crate::ranges::RangeSet;
+use crate::recovery::{Recovery, SentFrame, SentPacket, Timeout};
+use crate::stream::{RecvStream, SendStream};
+use crate::tls::{expand_label, EarlyData, Secrets, SessionTicket, Tls};
+use crate::{code, ClientConfig, ServerConfig, TransportConfig, TransportError};
+
+/// The length of the connection IDs chosen by the local endpoint.
+pub const LOCAL_CID_LEN: usize = 8;
+
+/// How far the CRYPTO frames may go beyond the data of the handshake read.
+const MAX_CRYPTO_BUFFER: u64 = 0x10000;
+/// How many packets are protected with the same 1-RTT keys, far below the
+/// limit of AES-GCM (RFC 9001 section 6.6).
+const KEY_UPDATE_INTERVAL: u64 = 1 << 22;
+/// How many connection IDs of the peer are kept, the default of the
+/// `active_connection_id_limit` parameter.
+const ACTIVE_CID_LIMIT: usize = 2;
+/// The smallest room left in a datagram for another packet.
+const MIN_PACKET_ROOM: usize = 64;
+
+/// The ID of a stream (RFC 9000 section 2.1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId(pub u64);
+
+impl StreamId {
+    /// Returns whether the stream was opened by the server.
+    pub fn is_server_initiated(self) -> bool {
+        self.0 & 1 != 0
+    }
+
+    /// Returns whether the stream is unidirectional.
+    pub fn is_unidirectional(self) -> bool {
+        self.0 & 2 != 0
+    }
+}
+
+/// Why a connection is closed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionError {
+    /// Closed by [`Connection::close`].
+    Closed,
+    /// Closed by the peer, with an error code of the application, or else
+    /// of the transport.
+    PeerClosed {
+        code: u64,
+        application: bool,
+        reason: Vec<u8>,
+    },
+    /// Closed on an error of the peer, with the transport error `code`.
+    Transport { code: u64, reason: &'static str },
+    /// No packet was received for the idle timeout.
+    TimedOut,
+    /// The server does not support QUIC version 1.
+    VersionMismatch,
+}
+
+impl From<&ConnectionError> for AxError {
+    fn from(err: &ConnectionError) -> Self {
+        match err {
+            ConnectionError::Closed => AxError::NotConnected,
+            ConnectionError::PeerClosed { .. } => AxError::ConnectionReset,
+            ConnectionError::Transport { .. } => AxError::InvalidData,
+            ConnectionError::TimedOut => AxError::TimedOut,
+            ConnectionError::VersionMismatch => AxError::ConnectionRefused,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Handshake,
+    Established,
+    /// Sending CONNECTION_CLOSE frames until then.
+    Closing(Duration),
+    /// Closed by the peer, waiting until then for its last packets.
+    Draining(Duration),
+    Closed,
+}
+
+/// The state of a packet number space.
+struct PacketSpace {
+    /// The keys of the local endpoint and of the peer, if not 1-RTT.
+    keys: Option<(Keys, Keys)>,
+    next_pn: u64,
+    /// The packet numbers received, to acknowledge.
+    received: RangeSet,
+    /// The packets below are dropped, their acknowledgment being
+    /// acknowledged.
+    received_floor: u64,
+    /// The largest packet number received, and when.
+    largest_received: Option<(u64, Duration)>,
+    /// Whether packets were received since the last ACK frame sent.
+    ack_pending: bool,
+    /// The ack-eliciting packets received since the last ACK frame sent.
+    unacked: u32,
+    ack_deadline: Option<Duration>,
+    crypto_send: SendStream,
+    crypto_recv: RecvStream,
+    /// How many ack-eliciting packets to send, bypassing the congestion
+    /// control, when the probe timeout fires.
+    probes: u8,
+}
+
+impl PacketSpace {
+    fn new(keys: Option<(Keys, Keys)>) -> Self {
+        Self {
+            keys,
+            next_pn: 0,
+            received: RangeSet::new(),
+            received_floor: 0,
+            largest_received: None,
+            ack_pending: false,
+            unacked: 0,
+            ack_deadline: None,
+            crypto_send: SendStream::new(u64::MAX),
+            crypto_recv: RecvStream::new(u64::MAX),
+            probes: 0,
+        }
+    }
+
+    /// Returns whether an ACK frame must be sent now.
+    fn ack_due(&self, data: bool, now: Duration) -> bool {
+        self.unacked > 0
+            && (!data || self.unacked >= 2 || self.ack_deadline.is_some_and(|t| t <= now))
+    }
+}
+
+fn next_secret(secret: &[u8; 32]) -> [u8; 32] {
+    let mut next = [0; 32];
+    expand_label(secret, b"quic ku", &[], &mut next);
+    next
+}
+
+/// The 1-RTT keys, which are updated along the connection (RFC 9001
+/// section 6).
+struct OneRttKeys {
+    local: Keys,
+    remote: Keys,
+    /// The keys of the peer in the next phase and in the previous one.
+    next_remote: Keys,
+    prev_remote: Option<Keys>,
+    local_secret: [u8; 32],
+    remote_secret: [u8; 32],
+    /// The first secret of the peer, from which its header protection key
+    /// comes.
+    remote_base: [u8; 32],
+    phase: bool,
+    /// The first packet number received in the current phase.
+    first_received: Option<u64>,
+    /// The first packet number sent in the current phase.
+    first_sent: u64,
+    /// Whether a packet sent in the current phase was acknowledged, which
+    /// the next update waits for.
+    acked: bool,
+    sent: u64,
+}
+
+impl OneRttKeys {
+    fn new(local_secret: [u8; 32], remote_secret: [u8; 32]) -> Self {
+        Self {
+            local: Keys::new(&local_secret),
+            remote: Keys::new(&remote_secret),
+            next_remote: Keys::new(&remote_secret).update(&next_secret(&remote_secret)),
+            prev_remote: None,
+            local_secret,
+            remote_secret,
+            remote_base: remote_secret,
+            phase: false,
+            first_received: None,
+            first_sent: 0,
+            acked: false,
+            sent: 0,
+        }
+    }
+
+    /// Moves to the next key phase, the next packet sent being `next_pn`.
+    fn update(self, next_pn: u64) -> Self {
+        let local_secret = next_secret(&self.local_secret);
+        let remote_secret = next_secret(&self.remote_secret);
+        let base = Keys::new(&self.remote_base);
+        Self {
+            local: self.local.update(&local_secret),
+            remote: self.next_remote,
+            next_remote: base.update(&next_secret(&remote_secret)),
+            prev_remote: Some(self.remote),
+            local_secret,
+            remote_secret,
+            remote_base: self.remote_base,
+            phase: !self.phase,
+            first_received: None,
+            first_sent: next_pn,
+            acked: false,
+            sent: 0,
+        }
+    }
+}
+
+/// The two halves of a stream, the ones it has.
+struct Stream {
+    send: Option<SendStream>,
+    recv: Option<RecvStream>,
+}
+
+impl Stream {
+    /// Returns whether the halves it has are over.
+    fn is_over(&self) -> bool {
+        !matches!(&self.send, Some(send) if !send.is_done())
+            && !matches!(&self.recv, Some(recv) if !recv.done)
+    }
+}
+
+/// A packet being built, sealed once the datagram is complete.
+struct PendingPacket {
+    ty: PacketType,
+    pn: u64,
+    header_len: usize,
+    pn_offset: usize,
+    buf: Vec<u8>,
+    sent: SentPacket,
+}
+
+/// A QUIC connection, of a client or of a server.
+pub struct Connection {
+    server: bool,
+    transport: TransportConfig,
+    tls: Tls,
+    state: State,
+    error: Option<ConnectionError>,
+
+    local_cid: ConnectionId,
+    remote_cid: ConnectionId,
+    remote_cid_seq: u64,
+    /// The other connection IDs of the peer, by sequence number.
+    peer_cids: BTreeMap<u64, ConnectionId>,
+    retire_prior_to: u64,
+    retire_pending: Vec<u64>,
+    /// The connection ID the peer put in its first packet.
+    peer_initial_cid: Option<ConnectionId>,
+    /// The first destination connection ID of the client.
+    original_dcid: ConnectionId,
+    retry_scid: Option<ConnectionId>,
+    /// The token of the Retry, sent in the Initial packets of the client.
+    token: Vec<u8>,
+    received_packet: bool,
+
+    spaces: [PacketSpace; 3],
+    zero_rtt_keys: Option<Keys>,
+    one_rtt: Option<OneRttKeys>,
+    key_update_requested: bool,
+    recovery: Recovery,
+    peer_params: TransportParams,
+    peer_params_received: bool,
+    early_data_handled: bool,
+    handshake_acked: bool,
+    handshake_confirmed: bool,
+    handshake_done_pending: bool,
+    /// For the server, the bytes received and sent until the address of the
+    /// client is validated.
+    amplification: Option<(usize, usize)>,
+    path_responses: Vec<[u8; 8]>,
+    ping_pending: bool,
+    last_activity: Duration,
+    last_ping: Duration,
+    /// Whether an ack-eliciting packet was sent since the last packet
+    /// received.
+    ack_eliciting_sent: bool,
+    idle_timeout: Duration,
+    /// The CONNECTION_CLOSE frame to send: the code, whether it is one of
+    /// the application, and the reason.
+    close_frame: Option<(u64, bool, Vec<u8>)>,
+    close_pending: bool,
+
+    streams: BTreeMap<u64, Stream>,
+    accept_queue: VecDeque<u64>,
+    /// The last stream sent on, for the round robin.
+    last_sent_stream: u64,
+    /// By whether unidirectional: how many streams were opened locally, and
+    /// may be.
+    opened: [u64; 2],
+    peer_max_streams: [u64; 2],
+    /// By whether unidirectional: how many streams the peer opened, and may.
+    remote_opened: [u64; 2],
+    local_max_streams: [u64; 2],
+    max_streams_pending: [bool; 2],
+    data_sent: u64,
+    peer_max_data: u64,
+    data_received: u64,
+    data_read: u64,
+    local_max_data: u64,
+    max_data_pending: bool,
+}
+
+fn local_params(transport: &TransportConfig, initial_scid: ConnectionId) -> TransportParams {
+    TransportParams {
+        max_idle_timeout: transport.max_idle_timeout.as_millis() as u64,
+        initial_max_data: transport.max_data,
+        initial_max_stream_data_bidi_local: transport.max_stream_data,
+        initial_max_stream_data_bidi_remote: transport.max_stream_data,
+        initial_max_stream_data_uni: transport.max_stream_data,
+        initial_max_streams_bidi: transport.max_streams_bidi.min(MAX_STREAMS),
+        initial_max_streams_uni: transport.max_streams_uni.min(MAX_STREAMS),
+        max_ack_delay: transport.max_ack_delay.as_millis() as u64,
+        initial_scid: Some(initial_scid),
+        ..Default::default()
+    }
+}
+
+fn initial_keys(dcid: &[u8], server: bool) -> (Keys, Keys) {
+    let (client, server_secret) = packet::initial_secrets(dcid);
+    let (client, server_keys) = (Keys::new(&client), Keys::new(&server_secret));
+    if server {
+        (server_keys, client)
+    } else {
+        (client, server_keys)
+    }
+}
+
+impl Connection {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        server: bool,
+        transport: TransportConfig,
+        tls: Tls,
+        local_cid: ConnectionId,
+        remote_cid: ConnectionId,
+        original_dcid: ConnectionId,
+        now: Duration,
+    ) -> Self {
+        let initial_keys = initial_keys(original_dcid.as_slice(), server);
+        let mut recovery = Recovery::new(transport.initial_rtt, transport.max_datagram_size);
+        recovery.max_ack_delay = Duration::ZERO;
+        Self {
+            server,
+            tls,
+            state: State::Handshake,
+            error: None,
+            local_cid,
+            remote_cid,
+            remote_cid_seq: 0,
+            peer_cids: BTreeMap::new(),
+            retire_prior_to: 0,
+            retire_pending: Vec::new(),
+            peer_initial_cid: None,
+            original_dcid,
+            retry_scid: None,
+            token: Vec::new(),
+            received_packet: false,
+            spaces: [
+                PacketSpace::new(Some(initial_keys)),
+                PacketSpace::new(None),
+                PacketSpace::new(None),
+            ],
+            zero_rtt_keys: None,
+            one_rtt: None,
+            key_update_requested: false,
+            recovery,
+            peer_params: TransportParams::default(),
+            peer_params_received: false,
+            early_data_handled: false,
+            handshake_acked: false,
+            handshake_confirmed: false,
+            handshake_done_pending: false,
+            amplification: server.then_some((0, 0)),
+            path_responses: Vec::new(),
+            ping_pending: false,
+            last_activity: now,
+            last_ping: now,
+            ack_eliciting_sent: false,
+            idle_timeout: transport.max_idle_timeout,
+            close_frame: None,
+            close_pending: false,
+            streams: BTreeMap::new(),
+            accept_queue: VecDeque::new(),
+            last_sent_stream: 0,
+            opened: [0; 2],
+            peer_max_streams: [0; 2],
+            remote_opened: [0; 2],
+            local_max_streams: [transport.max_streams_bidi, transport.max_streams_uni],
+            max_streams_pending: [false; 2],
+            data_sent: 0,
+            peer_max_data: 0,
+            data_received: 0,
+            data_read: 0,
+            local_max_data: transport.max_data,
+            max_data_pending: false,
+            transport,
+        }
+    }
+
+    /// Starts a connection to a server, resuming the session of `ticket` if
+    /// it is still valid.
+    ///
+    /// With [`ClientConfig::early_data`] set and a ticket allowing it, the
+    /// streams can be sent on at once, in 0-RTT packets.
+    pub fn connect(
+        config: Arc<ClientConfig>,
+        ticket: Option<&SessionTicket>,
+        now: Duration,
+    ) -> Self {
+        let local_cid = ConnectionId::random(LOCAL_CID_LEN, config.random);
+        let original_dcid = ConnectionId::random(LOCAL_CID_LEN, config.random);
+        let params = local_params(&config.transport, local_cid).encode();
+        let tls = Tls::client(config.clone(), params, ticket, now);
+        let mut conn = Self::new(
+            false,
+            config.transport.clone(),
+            tls,
+            local_cid,
+            original_dcid,
+            original_dcid,
+            now,
+        );
+        if conn.tls.early_data == EarlyData::Offered {
+            // the early data abides by the parameters of the session resumed
+            let remembered = ticket.and_then(|t| TransportParams::decode(&t.params, true).ok());
+            if let Some(params) = remembered {
+                conn.apply_params(params);
+            }
+        }
+        conn.after_handshake_data(now).unwrap();
+        conn
+    }
+
+    /// Accepts a connection starting with `datagram`, received by a server.
+    ///
+    /// Returns [`None`] if the datagram does not start a connection: it must
+    /// begin with an Initial packet, of a datagram of at least 1200 bytes.
+    pub fn accept(config: Arc<ServerConfig>, datagram: &[u8], now: Duration) -> Option<Self> {
+        let header = Header::parse(datagram, LOCAL_CID_LEN)?;
+        if header.ty != PacketType::Initial
+            || datagram.len() < MIN_INITIAL_SIZE
+            || header.dcid.len() < 8
+        {
+            return None;
+        }
+        let original_dcid = ConnectionId::new(header.dcid)?;
+        let client_cid = ConnectionId::new(header.scid)?;
+        let local_cid = ConnectionId::random(LOCAL_CID_LEN, config.random);
+        let mut params = local_params(&config.transport, local_cid);
+        params.original_dcid = Some(original_dcid);
+        let tls = Tls::server(config.clone(), params.encode());
+        let mut conn = Self::new(
+            true,
+            config.transport.clone(),
+            tls,
+            local_cid,
+            client_cid,
+            original_dcid,
+            now,
+        );
+        conn.peer_initial_cid = Some(client_cid);
+        conn.handle_datagram(datagram, now);
+        Some(conn)
+    }
+
+    /// Returns the connection ID the peer sends to, 8 bytes long.
+    pub fn local_cid(&self) -> &[u8] {
+        self.local_cid.as_slice()
+    }
+
+    /// Returns whether the handshake completed, and the connection is not
+    /// closing.
+    pub fn is_established(&self) -> bool {
+        self.state == State::Established
+    }
+
+    /// Returns whether the connection is over: nothing more is sent nor
+    /// received.
+    pub fn is_closed(&self) -> bool {
+        self.state == State::Closed
+    }
+
+    /// Returns why the connection is closing, or closed.
+    pub fn error(&self) -> Option<&ConnectionError> {
+        self.error.as_ref()
+    }
+
+    /// Returns the application protocol negotiated.
+    pub fn alpn(&self) -> Option<&[u8]> {
+        self.tls.alpn()
+    }
+
+    /// Returns whether the client offered early data, with which the streams
+    /// are sent on before the handshake completes.
+    pub fn is_early_data_offered(&self) -> bool {
+        self.tls.early_data != EarlyData::NotOffered
+    }
+
+    /// Returns whether the server accepted the early data of the client.
+    pub fn is_early_data_accepted(&self) -> bool {
+        self.tls.early_data == EarlyData::Accepted
+    }
+
+    /// Returns the last session ticket of the server, to resume the session
+    /// with [`connect`](Self::connect).
+    pub fn session_ticket(&self) -> Option<&SessionTicket> {
+        self.tls.tickets.last()
+    }
+
+    /// Returns the estimate of the round-trip time.
+    pub fn rtt(&self) -> Duration {
+        self.recovery.smoothed_rtt()
+    }
+
+    /// Updates the 1-RTT keys, as soon as it is allowed.
+    pub fn update_keys(&mut self) {
+        self.key_update_requested = true;
+    }
+
+    fn closed_error(&self) -> AxError {
+        self.error
+            .as_ref()
+            .map_or(AxError::NotConnected, AxError::from)
+    }
+
+    fn check_open(&self) -> AxResult {
+        match self.state {
+            State::Handshake | State::Established => Ok(()),
+            _ => Err(self.closed_error()),
+        }
+    }
+
+    /// Closes the connection, with the error code `code` of the
+    /// application.
+    pub fn close(&mut self, code: u64, reason: &[u8], now: Duration) {
+        if self.check_open().is_ok() {
+            debug!("quic: closing with application error {:#x}", code);
+            self.error = Some(ConnectionError::Closed);
+            self.start_closing(code, true, reason, now);
+        }
+    }
+
+    fn close_with(&mut self, err: TransportError, now: Duration) {
+        if self.check_open().is_ok() {
+            warn!("quic: closing with error {:#x}: {}", err.code, err.reason);
+            self.error = Some(ConnectionError::Transport {
+                code: err.code,
+                reason: err.reason,
+            });
+            self.start_closing(err.code, false, err.reason.as_bytes(), now);
+        }
+    }
+
+    fn start_closing(&mut self, code: u64, application: bool, reason: &[u8], now: Duration) {
+        self.close_frame = Some((code, application, reason.to_vec()));
+        self.close_pending = true;
+        self.state = State::Closing(now + 3 * self.recovery.pto());
+    }
+
+    fn discard_space(&mut self, space: Space) {
+        if self.spaces[space as usize].keys.take().is_some() {
+            trace!("quic: discarding the {:?} keys", space);
+            self.recovery.discard(space);
+            self.spaces[space as usize].probes = 0;
+        }
+    }
+
+    /// Returns the space the client probes with nothing in flight, until the
+    /// server has its address validated.
+    fn idle_probe(&self) -> Option<Space> {
+        if self.server || self.handshake_confirmed || self.handshake_acked {
+            None
+        } else if self.spaces[Space::Handshake as usize].keys.is_some() {
+            Some(Space::Handshake)
+        } else if self.spaces[Space::Initial as usize].keys.is_some() {
+            Some(Space::Initial)
+        } else {
+            None
+        }
+    }
+
+    /// Returns how many bytes the server may send until the address of the
+    /// client is validated.
+    fn amplification_budget(&self) -> usize {
+        match self.amplification {
+            Some((received, sent)) => (3 * received).saturating_sub(sent),
+            None => usize::MAX,
+        }
+    }
+
+    /// Applies the transport parameters of the peer, or the ones remembered
+    /// from the session resumed.
+    fn apply_params(&mut self, params: TransportParams) {
+        self.peer_max_data = params.initial_max_data;
+        self.peer_max_streams = [
+            params.initial_max_streams_bidi,
+            params.initial_max_streams_uni,
+        ];
+        for (id, stream) in &mut self.streams {
+            if let Some(send) = &mut stream.send {
+                send.max_data = if id & 2 != 0 {
+                    params.initial_max_stream_data_uni
+                } else {
+                    params.initial_max_stream_data_bidi_remote
+                };
+            }
+        }
+        self.recovery.max_ack_delay = Duration::from_millis(params.max_ack_delay);
+        let peer_idle = Duration::from_millis(params.max_idle_timeout);
+        if !peer_idle.is_zero()
+            && (self.transport.max_idle_timeout.is_zero()
+                || peer_idle < self.transport.max_idle_timeout)
+        {
+            self.idle_timeout = peer_idle;
+        }
+        self.peer_params = params;
+    }
+
+    fn on_peer_params(&mut self, params: &[u8]) -> Result<(), TransportError> {
+        let params = TransportParams::decode(params, !self.server)?;
+        let bad = |reason| TransportError::new(code::TRANSPORT_PARAMETER_ERROR, reason);
+        if params.initial_scid != self.peer_initial_cid {
+            return Err(bad("initial_source_connection_id mismatch"));
+        }
+        if !self.server {
+            if params.original_dcid != Some(self.original_dcid) {
+                return Err(bad("original_destination_connection_id mismatch"));
+            } else if params.retry_scid != self.retry_scid {
+                return Err(bad("retry_source_connection_id mismatch"));
+            }
+        }
+        self.apply_params(params);
+        Ok(())
+    }
+
+    /// Takes what the handshake gave: the messages to send, the secrets, and
+    /// the transport parameters of the peer.
+    fn after_handshake_data(&mut self, now: Duration) -> Result<(), TransportError> {
+        for secrets in core::mem::take(&mut self.tls.secrets) {
+            let (local, remote) = match secrets {
+                Secrets::ZeroRtt(secret) => {
+                    self.zero_rtt_keys = Some(Keys::new(&secret));
+                    continue;
+                }
+                Secrets::Handshake { client, server } | Secrets::OneRtt { client, server }
+                    if self.server =>
+                {
+                    (server, client)
+                }
+                Secrets::Handshake { client, server } | Secrets::OneRtt { client, server } => {
+                    (client, server)
+                }
+            };
+            if matches!(secrets, Secrets::Handshake { .. }) {
+                self.spaces[Space::Handshake as usize].keys =
+                    Some((Keys::new(&local), Keys::new(&remote)));
+            } else {
+                self.one_rtt = Some(OneRttKeys::new(local, remote));
+                if !self.server {
+                    // no more 0-RTT packets once 1-RTT ones can be sent
+                    self.zero_rtt_keys = None;
+                }
+            }
+        }
+        for space in Space::ALL {
+            let out = core::mem::take(&mut self.tls.outgoing[space as usize]);
+            if !out.is_empty() {
+                self.spaces[space as usize].crypto_send.write(&out);
+            }
+        }
+        if !self.peer_params_received {
+            if let Some(params) = self.tls.peer_params().map(<[u8]>::to_vec) {
+                self.peer_params_received = true;
+                self.on_peer_params(&params)?;
+            }
+        }
+        if !self.server && !self.early_data_handled && self.tls.early_data == EarlyData::Rejected {
+            debug!("quic: early data rejected");
+            self.early_data_handled = true;
+            self.zero_rtt_keys = None;
+            self.recovery.discard(Space::Data);
+            for stream in self.streams.values_mut() {
+                if let Some(send) = &mut stream.send {
+                    send.requeue_all();
+                }
+            }
+        }
+        if self.tls.is_complete() && self.state == State::Handshake {
+            debug!("quic: handshake complete, ALPN {:?}", self.tls.alpn());
+            self.state = State::Established;
+            self.last_activity = now;
+            if self.server {
+                self.handshake_confirmed = true;
+                self.handshake_done_pending = true;
+                self.discard_space(Space::Handshake);
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a UDP datagram received.
+    pub fn handle_datagram(&mut self, datagram: &[u8], now: Duration) {
+        if matches!(self.state, State::Draining(_) | State::Closed) {
+            return;
+        }
+        if let Some((received, _)) = &mut self.amplification {
+            *received += datagram.len();
+        }
+        let mut buf = datagram.to_vec();
+        let mut rest = &mut buf[..];
+        while !rest.is_empty() {
+            match self.handle_packet(rest, datagram.len(), now) {
+                Ok(Some(len)) => rest = &mut rest[len..],
+                Ok(None) => break,
+                Err(err) => {
+                    self.close_with(err, now);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Handles the packet at the start of `buf`, and returns its length, or
+    /// [`None`] if the rest of the datagram is dropped.
+    fn handle_packet(
+        &mut self,
+        buf: &mut [u8],
+        datagram_len: usize,
+        now: Duration,
+    ) -> Result<Option<usize>, TransportError> {
+        let Some(header) = Header::parse(buf, LOCAL_CID_LEN) else {
+            return Ok(None);
+        };
+        let (ty, len, pn_offset) = (header.ty, header.len, header.pn_offset);
+        let dcid_ok = header.dcid == self.local_cid.as_slice()
+            || (self.server
+                && matches!(ty, PacketType::Initial | PacketType::ZeroRtt)
+                && header.dcid == self.original_dcid.as_slice());
+        if !dcid_ok {
+            return Ok(Some(len));
+        }
+        let scid = ConnectionId::new(header.scid);
+        match ty {
+            PacketType::VersionNegotiation => {
+                let versions = &buf[7 + header.dcid.len() + header.scid.len()..];
+                if !self.server && !self.received_packet {
+                    self.on_version_negotiation(versions);
+                }
+                return Ok(None);
+            }
+            PacketType::Retry => {
+                if !self.server && !self.received_packet && self.retry_scid.is_none() {
+                    let token = header.token.to_vec();
+                    self.on_retry(buf, scid.unwrap(), &token);
+                }
+                return Ok(None);
+            }
+            PacketType::Unsupported => return Ok(None),
+            PacketType::Initial if self.server && datagram_len < MIN_INITIAL_SIZE => {
+                return Ok(None);
+            }
+            _ => {}
+        }
+        if let State::Closing(_) = self.state {
+            self.close_pending = true;
+            return Ok(None);
+        }
+
+        let packet = &mut buf[..len];
+        let Some((pn, header_len)) = self.open_packet(ty, packet, pn_offset)? else {
+            trace!("quic: dropping a {:?} packet", ty);
+            return Ok(Some(len));
+        };
+        let space = ty.space();
+        let s = &self.spaces[space as usize];
+        if pn < s.received_floor || s.received.contains(pn) {
+            return Ok(Some(len));
+        }
+        if self.server && ty == PacketType::Handshake {
+            // the client got the Initial packets, so its address is valid
+            self.amplification = None;
+            self.discard_space(Space::Initial);
+        }
+        if !self.server && ty == PacketType::Initial && self.peer_initial_cid.is_none() {
+            self.remote_cid = scid.unwrap();
+            self.peer_initial_cid = scid;
+        }
+        self.received_packet = true;
+
+        let payload = &packet[header_len..len - AEAD_TAG_SIZE];
+        let ack_eliciting = self.handle_frames(ty, payload, now)?;
+        let s = &mut self.spaces[space as usize];
+        s.received.insert(pn..pn + 1);
+        if !matches!(s.largest_received, Some((largest, _)) if largest >= pn) {
+            s.largest_received = Some((pn, now));
+        }
+        s.ack_pending = true;
+        if ack_eliciting {
+            s.unacked += 1;
+            s.ack_deadline
+                .get_or_insert(now + self.transport.max_ack_delay);
+        }
+        self.last_activity = now;
+        self.ack_eliciting_sent = false;
+        Ok(Some(len))
+    }
+
+    /// Removes the protection of `packet`, and returns its packet number and
+    /// the length of its header, or [`None`] if it cannot be decrypted.
+    fn open_packet(
+        &mut self,
+        ty: PacketType,
+        packet: &mut [u8],
+        pn_offset: usize,
+    ) -> Result<Option<(u64, usize)>, TransportError> {
+        let space = ty.space();
+        let largest = self.spaces[space as usize]
+            .largest_received
+            .map(|(pn, _)| pn);
+        let keys = match ty {
+            PacketType::Initial | PacketType::Handshake => self.spaces[space as usize]
+                .keys
+                .as_ref()
+                .map(|(_, remote)| remote),
+            PacketType::ZeroRtt if self.server => self.zero_rtt_keys.as_ref(),
+            // the 1-RTT packets are not handled before the Finished of the
+            // client (RFC 9001 section 5.7)
+            PacketType::Short if !self.server || self.tls.is_complete() => {
+                self.one_rtt.as_ref().map(|k| &k.remote)
+            }
+            _ => None,
+        };
+        let Some(keys) = keys else {
+            return Ok(None);
+        };
+        let Some((truncated, pn_len)) = keys.unprotect_header(packet, pn_offset) else {
+            return Ok(None);
+        };
+        let pn = packet::decode_pn(largest, truncated, pn_len);
+        let header_len = pn_offset + pn_len;
+        let reserved = if ty == PacketType::Short { 0x18 } else { 0x0c };
+
+        if ty != PacketType::Short {
+            if keys.open(pn, packet, header_len).is_none() {
+                return Ok(None);
+            }
+        } else {
+            let phase = packet[0] & 0x04 != 0;
+            let keys = self.one_rtt.as_mut().unwrap();
+            if phase == keys.phase {
+                if keys.remote.open(pn, packet, header_len).is_none() {
+                    return Ok(None);
+                }
+                keys.first_received.get_or_insert(pn);
+            } else if keys.prev_remote.is_some()
+                && !matches!(keys.first_received, Some(f) if f <= pn)
+            {
+                let prev = keys.prev_remote.as_ref().unwrap();
+                if prev.open(pn, packet, header_len).is_none() {
+                    return Ok(None);
+                }
+            } else {
+                if keys.next_remote.open(pn, packet, header_len).is_none() {
+                    return Ok(None);
+                }
+                // the peer updated its keys, the local ones follow
+                debug!("quic: key update of the peer");
+                let next_pn = self.spaces[Space::Data as usize].next_pn;
+                let mut keys = self.one_rtt.take().unwrap().update(next_pn);
+                keys.first_received = Some(pn);
+                self.one_rtt = Some(keys);
+            }
+        }
+        if packet[0] & reserved != 0 {
+            return Err(TransportError::new(
+                code::PROTOCOL_VIOLATION,
+                "reserved bits set",
+            ));
+        }
+        Ok(Some((pn, header_len)))
+    }
+
+    fn on_version_negotiation(&mut self, versions: &[u8]) {
+        if versions.chunks_exact(4).any(|v| v == VERSION.to_be_bytes()) {
+            return;
+        }
+        warn!("quic: the server does not support version 1");
+        self.error = Some(ConnectionError::VersionMismatch);
+        self.state = State::Closed;
+    }
+
+    fn on_retry(&mut self, packet: &[u8], scid: ConnectionId, token: &[u8]) {
+        let (retry, tag) = packet.split_at(packet.len() - AEAD_TAG_SIZE);
+        if token.is_empty() || packet::retry_tag(self.original_dcid.as_slice(), retry) != tag {
+            return;
+        }
+        debug!("quic: retry to {:?}", scid);
+        self.retry_scid = Some(scid);
+        self.remote_cid = scid;
+        self.token = token.to_vec();
+        self.spaces[Space::Initial as usize].keys = Some(initial_keys(scid.as_slice(), false));
+        // all is sent again, to the new connection ID
+        for space in [Space::Initial, Space::Data] {
+            for packet in self.recovery.discard(space) {
+                for frame in packet.frames {
+                    self.on_frame_lost(space, frame);
+                }
+            }
+        }
+    }
+
+    /// Handles the frames of a packet, and returns whether it is
+    /// ack-eliciting.
+    fn handle_frames(
+        &mut self,
+        ty: PacketType,
+        payload: &[u8],
+        now: Duration,
+    ) -> Result<bool, TransportError> {
+        if payload.is_empty() {
+            return Err(TransportError::new(
+                code::PROTOCOL_VIOLATION,
+                "packet without frames",
+            ));
+        }
+        let space = ty.space();
+        let mut r = Reader::new(payload);
+        let mut ack_eliciting = false;
+        while !r.is_empty() {
+            let frame = Frame::parse(&mut r)?;
+            let allowed = match ty {
+                PacketType::Initial | PacketType::Handshake => frame.is_allowed_in_handshake(),
+                PacketType::ZeroRtt => frame.is_allowed_in_0rtt(),
+                _ => true,
+            };
+            if !allowed {
+                return Err(TransportError::new(
+                    code::PROTOCOL_VIOLATION,
+                    "frame not allowed in the packet",
+                ));
+            }
+            ack_eliciting |= frame.is_ack_eliciting();
+            self.handle_frame(space, frame, now)?;
+            if matches!(self.state, State::Draining(_)) {
+                break;
+            }
+        }
+        Ok(ack_eliciting)
+    }
+
+    fn handle_frame(
+        &mut self,
+        space: Space,
+        frame: Frame,
+        now: Duration,
+    ) -> Result<(), TransportError> {
+        let violation = |reason| TransportError::new(code::PROTOCOL_VIOLATION, reason);
+        let state_error = TransportError::new(code::STREAM_STATE_ERROR, "no such stream half");
+        match frame {
+            Frame::Padding | Frame::Ping => {}
+            Frame::Ack { ranges, delay } => self.on_ack(space, &ranges, delay, now)?,
+            Frame::Crypto { offset, data } => {
+                let s = &mut self.spaces[space as usize];
+                if offset + data.len() as u64 > s.crypto_recv.read_offset() + MAX_CRYPTO_BUFFER {
+                    return Err(TransportError::new(
+                        code::CRYPTO_BUFFER_EXCEEDED,
+                        "handshake data too far ahead",
+                    ));
+                }
+                s.crypto_recv.receive(offset, data, false)?;
+                let mut data = vec![0; s.crypto_recv.readable()];
+                s.crypto_recv.read(&mut data);
+                if !data.is_empty() {
+                    self.tls.read(space, &data, now)?;
+                    self.after_handshake_data(now)?;
+                }
+            }
+            Frame::NewToken if self.server => return Err(violation("NEW_TOKEN from a client")),
+            Frame::NewToken => {}
+            Frame::Stream {
+                id,
+                offset,
+                data,
+                fin,
+            } => {
+                let Some(stream) = self.stream_of_frame(id)? else {
+                    return Ok(());
+                };
+                let recv = stream.recv.as_mut().ok_or(state_error)?;
+                let more = recv.receive(offset, data, fin)?;
+                self.on_data_received(more)?;
+            }
+            Frame::ResetStream {
+                id,
+                code,
+                final_size,
+            } => {
+                let Some(stream) = self.stream_of_frame(id)? else {
+                    return Ok(());
+                };
+                let recv = stream.recv.as_mut().ok_or(state_error)?;
+                let unread = final_size.saturating_sub(recv.read_offset());
+                let first = recv.reset.is_none();
+                let more = recv.on_reset(code, final_size)?;
+                self.on_data_received(more)?;
+                if first {
+                    // the data left is not going to be read
+                    self.on_data_read(unread);
+                }
+            }
+            Frame::StopSending { id, code } => {
+                let Some(stream) = self.stream_of_frame(id)? else {
+                    return Ok(());
+                };
+                stream.send.as_mut().ok_or(state_error)?.reset(code);
+            }
+            Frame::MaxData(max) => self.peer_max_data = self.peer_max_data.max(max),
+            Frame::MaxStreamData { id, max } => {
+                let Some(stream) = self.stream_of_frame(id)? else {
+                    return Ok(());
+                };
+                let send = stream.send.as_mut().ok_or(state_error)?;
+                send.max_data = send.max_data.max(max);
+            }
+            Frame::MaxStreams { bidi, max } => {
+                let max_streams = &mut self.peer_max_streams[!bidi as usize];
+                *max_streams = (*max_streams).max(max);
+            }
+            Frame::StreamDataBlocked { id } => {
+                if let Some(stream) = self.stream_of_frame(id)? {
+                    stream.recv.as_ref().ok_or(state_error)?;
+                }
+            }
+            Frame::DataBlocked | Frame::StreamsBlocked => {}
+            Frame::NewConnectionId {
+                seq,
+                retire_prior_to,
+                cid,
+            } => self.on_new_connection_id(seq, retire_prior_to, cid)?,
+            // only the first connection ID is given, the one the packet went
+            // to
+            Frame::RetireConnectionId => {
+                return Err(violation("retiring the only connection ID"));
+            }
+            Frame::PathChallenge(data) => self.path_responses.push(data),
+            Frame::PathResponse => {}
+            Frame::ConnectionClose {
+                code,
+                application,
+                reason,
+            } => {
+                debug!("quic: closed by the peer with {:#x}", code);
+                if self.check_open().is_ok() {
+                    self.error = Some(ConnectionError::PeerClosed {
+                        code,
+                        application,
+                        reason: reason.to_vec(),
+                    });
+                }
+                self.state = State::Draining(now + 3 * self.recovery.pto());
+            }
+            Frame::HandshakeDone if self.server => {
+                return Err(violation("HANDSHAKE_DONE from a client"));
+            }
+            Frame::HandshakeDone => {
+                if !self.handshake_confirmed {
+                    self.handshake_confirmed = true;
+                    self.discard_space(Space::Handshake);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn on_ack(
+        &mut self,
+        space: Space,
+        ranges: &[core::ops::Range<u64>],
+        delay: u64,
+        now: Duration,
+    ) -> Result<(), TransportError> {
+        let largest = ranges[0].end - 1;
+        if largest >= self.spaces[space as usize].next_pn {
+            return Err(TransportError::new(
+                code::PROTOCOL_VIOLATION,
+                "acknowledging a packet not sent",
+            ));
+        }
+        let exponent = if space == Space::Data {
+            self.peer_params.ack_delay_exponent
+        } else {
+            3
+        };
+        let delay = Duration::from_micros(delay.saturating_mul(1 << exponent));
+        let (acked, lost) =
+            self.recovery
+                .on_ack_received(space, ranges, delay, now, self.handshake_confirmed);
+        if space == Space::Handshake {
+            self.handshake_acked = true;
+        }
+        if let Some(keys) = &mut self.one_rtt {
+            if space == Space::Data && !acked.is_empty() && largest >= keys.first_sent {
+                keys.acked = true;
+            }
+        }
+        for packet in acked {
+            for frame in packet.frames {
+                self.on_frame_acked(space, frame);
+            }
+        }
+        for packet in lost {
+            for frame in packet.frames {
+                self.on_frame_lost(space, frame);
+            }
+        }
+        Ok(())
+    }
+
+    fn on_frame_acked(&mut self, space: Space, frame: SentFrame) {
+        match frame {
+            SentFrame::Ack(largest) => {
+                // what the peer knows it got is not acknowledged again
+                let s = &mut self.spaces[space as usize];
+                s.received_floor = s.received_floor.max(largest + 1);
+                s.received.remove_below(s.received_floor);
+            }
+            SentFrame::Crypto { offset, len } => {
+                self.spaces[space as usize]
+                    .crypto_send
+                    .on_ack(offset, len, false);
+            }
+            SentFrame::Stream {
+                id,
+                offset,
+                len,
+                fin,
+            } => {
+                if let Some(send) = self.streams.get_mut(&id).and_then(|s| s.send.as_mut()) {
+                    send.on_ack(offset, len, fin);
+                    self.collect_stream(id);
+                }
+            }
+            SentFrame::ResetStream(id) => {
+                if let Some(send) = self.streams.get_mut(&id).and_then(|s| s.send.as_mut()) {
+                    send.on_reset_acked();
+                    self.collect_stream(id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_frame_lost(&mut self, space: Space, frame: SentFrame) {
+        match frame {
+            SentFrame::Ack(_) => {}
+            SentFrame::Crypto { offset, len } => {
+                self.spaces[space as usize]
+                    .crypto_send
+                    .on_lost(offset, len, false);
+            }
+            SentFrame::Stream {
+                id,
+                offset,
+                len,
+                fin,
+            } => {
+                if let Some(send) = self.streams.get_mut(&id).and_then(|s| s.send.as_mut()) {
+                    send.on_lost(offset, len, fin);
+                }
+            }
+            SentFrame::ResetStream(id) => {
+                if let Some(send) = self.streams.get_mut(&id).and_then(|s| s.send.as_mut()) {
+                    send.on_reset_lost();
+                }
+            }
+            SentFrame::StopSending(id) => {
+                if let Some(recv) = self.streams.get_mut(&id).and_then(|s| s.recv.as_mut()) {
+                    recv.stop_sending_pending = recv.stop_sending.is_some() && !recv.done;
+                }
+            }
+            SentFrame::MaxData => self.max_data_pending = true,
+            SentFrame::MaxStreamData(id) => {
+                if let Some(recv) = self.streams.get_mut(&id).and_then(|s| s.recv.as_mut()) {
+                    recv.max_data_pending = recv.final_size.is_none();
+                }
+            }
+            SentFrame::MaxStreams { bidi } => self.max_streams_pending[!bidi as usize] = true,
+            SentFrame::RetireConnectionId(seq) => self.retire_pending.push(seq),
+            SentFrame::HandshakeDone => self.handshake_done_pending = true,
+        }
+    }
+
+    fn on_new_connection_id(
+        &mut self,
+        seq: u64,
+        retire_prior_to: u64,
+        cid: &[u8],
+    ) -> Result<(), TransportError> {
+        if self.remote_cid.as_slice().is_empty() {
+            return Err(TransportError::new(
+                code::PROTOCOL_VIOLATION,
+                "new connection ID with a zero-length one",
+            ));
+        }
+        let cid = ConnectionId::new(cid).unwrap();
+        if seq < self.retire_prior_to {
+            self.retire_pending.push(seq);
+            return Ok(());
+        }
+        if seq != self.remote_cid_seq {
+            self.peer_cids.insert(seq, cid);
+        }
+        if retire_prior_to > self.retire_prior_to {
+            self.retire_prior_to = retire_prior_to;
+            let retired: Vec<u64> = self
+                .peer_cids
+                .range(..retire_prior_to)
+                .map(|(s, _)| *s)
+                .collect();
+            for seq in retired {
+                self.peer_cids.remove(&seq);
+                self.retire_pending.push(seq);
+            }
+            if self.remote_cid_seq < retire_prior_to {
+                let Some((seq, cid)) = self.peer_cids.pop_first() else {
+                    return Err(TransportError::new(
+                        code::PROTOCOL_VIOLATION,
+                        "all connection IDs retired",
+                    ));
+                };
+                self.retire_pending.push(self.remote_cid_seq);
+                self.remote_cid_seq = seq;
+                self.remote_cid = cid;
+            }
+        }
+        if self.peer_cids.len() + 1 > ACTIVE_CID_LIMIT {
+            return Err(TransportError::new(
+                code::CONNECTION_ID_LIMIT_ERROR,
+                "too many connection IDs",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the stream of a frame received, opening the ones of the peer
+    /// up to it, or [`None`] if it is closed.
+    fn stream_of_frame(&mut self, id: u64) -> Result<Option<&mut Stream>, TransportError> {
+        let uni = (id & 2 != 0) as usize;
+        let index = id >> 2;
+        if (id & 1 != 0) == self.server {
+            if index >= self.opened[uni] {
+                return Err(TransportError::new(
+                    code::STREAM_STATE_ERROR,
+                    "stream not opened",
+                ));
+            }
+            return Ok(self.streams.get_mut(&id));
+        }
+        if index >= self.local_max_streams[uni] {
+            return Err(TransportError::new(
+                code::STREAM_LIMIT_ERROR,
+                "too many streams",
+            ));
+        }
+        while self.remote_opened[uni] <= index {
+            let new_id = self.remote_opened[uni] << 2 | (uni as u64) << 1 | !self.server as u64;
+            let send = (uni == 0)
+                .then(|| SendStream::new(self.peer_params.initial_max_stream_data_bidi_local));
+            let recv = Some(RecvStream::new(self.transport.max_stream_data));
+            self.streams.insert(new_id, Stream { send, recv });
+            self.accept_queue.push_back(new_id);
+            self.remote_opened[uni] += 1;
+        }
+        Ok(self.streams.get_mut(&id))
+    }
+
+    fn on_data_received(&mut self, more: u64) -> Result<(), TransportError> {
+        self.data_received += more;
+        if self.data_received > self.local_max_data {
+            return Err(TransportError::new(
+                code::FLOW_CONTROL_ERROR,
+                "connection data limit exceeded",
+            ));
+        }
+        Ok(())
+    }
+
+    fn on_data_read(&mut self, len: u64) {
+        self.data_read += len;
+        if self.local_max_data - self.data_read < self.transport.max_data / 2 {
+            self.local_max_data = self.data_read + self.transport.max_data;
+            self.max_data_pending = true;
+        }
+    }
+
+    /// Forgets a stream once both of its halves are over.
+    fn collect_stream(&mut self, id: u64) {
+        let Some(stream) = self.streams.get(&id) else {
+            return;
+        };
+        if stream.is_over() {
+            self.streams.remove(&id);
+            if (id & 1 != 0) != self.server {
+                let uni = (id & 2 != 0) as usize;
+                if self.local_max_streams[uni] < MAX_STREAMS {
+                    self.local_max_streams[uni] += 1;
+                    self.max_streams_pending[uni] = true;
+                }
+            }
+        }
+    }
+
+    /// Opens a stream, bidirectional or not.
+    ///
+    /// Returns [`AxError::WouldBlock`] if the peer does not allow more
+    /// streams yet.
+    pub fn open_stream(&mut self, bidi: bool) -> AxResult<StreamId> {
+        self.check_open()?;
+        let uni = !bidi as usize;
+        if self.opened[uni] >= self.peer_max_streams[uni] {
+            return ax_err!(WouldBlock);
+        }
+        let id = self.opened[uni] << 2 | (uni as u64) << 1 | self.server as u64;
+        self.opened[uni] += 1;
+        let max_data = if bidi {
+            self.peer_params.initial_max_stream_data_bidi_remote
+        } else {
+            self.peer_params.initial_max_stream_data_uni
+        };
+        let stream = Stream {
+            send: Some(SendStream::new(max_data)),
+            recv: bidi.then(|| RecvStream::new(self.transport.max_stream_data)),
+        };
+        self.streams.insert(id, stream);
+        Ok(StreamId(id))
+    }
+
+    /// Returns the next stream opened by the peer.
+    pub fn accept_stream(&mut self) -> Option<StreamId> {
+        self.accept_queue.pop_front().map(StreamId)
+    }
+
+    fn send_half(&mut self, id: StreamId) -> AxResult<&mut SendStream> {
+        self.check_open()?;
+        match self.streams.get_mut(&id.0).map(|s| s.send.as_mut()) {
+            Some(Some(send)) if send.reset.is_some() => ax_err!(ConnectionReset),
+            Some(Some(send)) => Ok(send),
+            Some(None) => ax_err!(InvalidInput, "quic: not a sending stream"),
+            None => ax_err!(BadState, "quic: stream closed"),
+        }
+    }
+
+    /// Writes data to send on a stream, and returns how much was taken.
+    ///
+    /// Returns [`AxError::WouldBlock`] if its send buffer is full.
+    pub fn stream_send(&mut self, id: StreamId, data: &[u8]) -> AxResult<usize> {
+        let limit = self.transport.send_buffer;
+        let send = self.send_half(id)?;
+        if send.is_finished() {
+            return ax_err!(BadState, "quic: stream finished");
+        }
+        let len = data.len().min(limit.saturating_sub(send.buffered()));
+        if len == 0 && !data.is_empty() {
+            return ax_err!(WouldBlock);
+        }
+        send.write(&data[..len]);
+        Ok(len)
+    }
+
+    /// Ends the sending half of a stream after the data written.
+    pub fn stream_finish(&mut self, id: StreamId) -> AxResult {
+        let send = self.send_half(id)?;
+        if !send.is_finished() {
+            send.finish();
+        }
+        Ok(())
+    }
+
+    /// Abandons the sending half of a stream, with the error code `code` of
+    /// the application.
+    pub fn stream_reset(&mut self, id: StreamId, code: u64) -> AxResult {
+        self.send_half(id)?.reset(code);
+        Ok(())
+    }
+
+    /// Reads the data received on a stream, and returns its length, 0 at its
+    /// end.
+    ///
+    /// Returns [`AxError::WouldBlock`] if there is nothing to read yet, and
+    /// [`AxError::ConnectionReset`] once the peer reset the stream.
+    pub fn stream_recv(&mut self, id: StreamId, buf: &mut [u8]) -> AxResult<usize> {
+        if matches!(
+            self.state,
+            State::Closing(_) | State::Draining(_) | State::Closed
+        ) {
+            return Err(self.closed_error());
+        }
+        let recv = match self.streams.get_mut(&id.0).map(|s| s.recv.as_mut()) {
+            Some(Some(recv)) => recv,
+            Some(None) => return ax_err!(InvalidInput, "quic: not a receiving stream"),
+            None => return ax_err!(BadState, "quic: stream closed"),
+        };
+        if recv.reset.is_some() {
+            recv.done = true;
+            self.collect_stream(id.0);
+            return ax_err!(ConnectionReset);
+        }
+        let len = recv.read(buf);
+        if len > 0 {
+            self.on_data_read(len as u64);
+            Ok(len)
+        } else if recv.is_finished() {
+            recv.done = true;
+            self.collect_stream(id.0);
+            Ok(0)
+        } else if buf.is_empty() {
+            Ok(0)
+        } else {
+            ax_err!(WouldBlock)
+        }
+    }
+
+    /// Returns whether [`stream_recv`](Self::stream_recv) would not block:
+    /// there is data, the end of the stream, or a reset.
+    pub fn is_readable(&self, id: StreamId) -> bool {
+        match self.streams.get(&id.0).and_then(|s| s.recv.as_ref()) {
+            Some(recv) => self.check_open().is_err() || recv.is_readable(),
+            None => true,
+        }
+    }
+
+    /// Returns when [`handle_timeout`](Self::handle_timeout) is to be called.
+    pub fn timeout(&self) -> Option<Duration> {
+        match self.state {
+            State::Closed => return None,
+            State::Closing(end) | State::Draining(end) => return Some(end),
+            _ => {}
+        }
+        let mut timeout = None;
+        let mut arm = |time: Option<Duration>| {
+            if let Some(time) = time {
+                timeout = Some(timeout.map_or(time, |t: Duration| t.min(time)));
+            }
+        };
+        if self.amplification_budget() > 0 {
+            arm(self
+                .recovery
+                .loss_timer(self.handshake_confirmed, self.idle_probe()));
+        }
+        arm(self.spaces[Space::Data as usize].ack_deadline);
+        arm(self.idle_deadline());
+        if let Some(keep_alive) = self.transport.keep_alive {
+            if self.state == State::Established {
+                arm(Some(self.last_activity.max(self.last_ping) + keep_alive));
+            }
+        }
+        timeout
+    }
+
+    fn idle_deadline(&self) -> Option<Duration> {
+        (!self.idle_timeout.is_zero())
+            .then(|| self.last_activity + self.idle_timeout.max(3 * self.recovery.pto()))
+    }
+
+    /// Handles the expiry of the timer of [`timeout`](Self::timeout).
+    pub fn handle_timeout(&mut self, now: Duration) {
+        match self.state {
+            State::Closed => return,
+            State::Closing(end) | State::Draining(end) => {
+                if now >= end {
+                    self.state = State::Closed;
+                }
+                return;
+            }
+            _ => {}
+        }
+        if self.idle_deadline().is_some_and(|t| t <= now) {
+            debug!("quic: idle timeout");
+            self.error.get_or_insert(ConnectionError::TimedOut);
+            self.state = State::Closed;
+            return;
+        }
+        if let Some(keep_alive) = self.transport.keep_alive {
+            if self.state == State::Established
+                && self.last_activity.max(self.last_ping) + keep_alive <= now
+            {
+                self.ping_pending = true;
+                self.last_ping = now;
+            }
+        }
+        let idle_probe = self.idle_probe();
+        let loss_timer = self
+            .recovery
+            .loss_timer(self.handshake_confirmed, idle_probe);
+        if !matches!(loss_timer, Some(t) if t <= now) {
+            return;
+        }
+        match self
+            .recovery
+            .on_timeout(now, self.handshake_confirmed, idle_probe)
+        {
+            Some(Timeout::Lost(space, lost)) => {
+                for packet in lost {
+                    for frame in packet.frames {
+                        self.on_frame_lost(space, frame);
+                    }
+                }
+            }
+            Some(Timeout::Probe(space)) => {
+                trace!("quic: probe timeout in {:?}", space);
+                self.spaces[space as usize].probes = 2;
+                for frame in self.recovery.probe_frames(space) {
+                    self.on_frame_lost(space, frame);
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn packet_type(&self, space: Space) -> Option<PacketType> {
+        match space {
+            Space::Initial | Space::Handshake => {
+                self.spaces[space as usize].keys.as_ref()?;
+                Some(if space == Space::Initial {
+                    PacketType::Initial
+                } else {
+                    PacketType::Handshake
+                })
+            }
+            Space::Data if self.one_rtt.is_some() => Some(PacketType::Short),
+            Space::Data if !self.server && self.zero_rtt_keys.is_some() => {
+                Some(PacketType::ZeroRtt)
+            }
+            Space::Data => None,
+        }
+    }
+
+    /// Returns whether frames other than ACK are to be sent in `space`.
+    fn has_frames(&self, space: Space) -> bool {
+        if self.spaces[space as usize].crypto_send.has_data(u64::MAX) {
+            return true;
+        }
+        if space != Space::Data {
+            return false;
+        }
+        let credit = self.peer_max_data - self.data_sent;
+        self.handshake_done_pending
+            || self.ping_pending
+            || self.max_data_pending
+            || !self.path_responses.is_empty()
+            || !self.retire_pending.is_empty()
+            || self.max_streams_pending.contains(&true)
+            || self.streams.values().any(|s| {
+                s.send
+                    .as_ref()
+                    .is_some_and(|send| send.reset_pending || send.has_data(credit))
+                    || s.recv
+                        .as_ref()
+                        .is_some_and(|r| r.max_data_pending || r.stop_sending_pending)
+            })
+    }
+
+    /// Writes the next datagram to send into `buf`, of at least 1200 bytes,
+    /// and returns its length, or [`None`] if there is nothing to send.
+    pub fn poll_transmit(&mut self, now: Duration, buf: &mut [u8]) -> Option<usize> {
+        match self.state {
+            State::Draining(_) | State::Closed => return None,
+            State::Closing(_) if !self.close_pending => return None,
+            _ => {}
+        }
+        assert!(
+            buf.len() >= MIN_INITIAL_SIZE,
+            "quic: datagram buffer too small"
+        );
+        let budget = self.amplification_budget();
+        if budget < MIN_INITIAL_SIZE {
+            return None;
+        }
+        let max = buf
+            .len()
+            .min(self.transport.max_datagram_size.max(MIN_INITIAL_SIZE))
+            .min(self.peer_params.max_udp_payload_size as usize)
+            .min(budget);
+        self.maybe_update_keys();
+
+        let mut packets: Vec<PendingPacket> = Vec::new();
+        let mut size = 0;
+        let closing = matches!(self.state, State::Closing(_));
+        for space in Space::ALL {
+            if max - size < MIN_PACKET_ROOM {
+                break;
+            }
+            let packet = if closing {
+                self.build_close(space, max - size)
+            } else {
+                self.build_packet(space, max - size, now)
+            };
+            if let Some(packet) = packet {
+                size += packet.buf.len() + AEAD_TAG_SIZE;
+                let short = packet.ty == PacketType::Short;
+                packets.push(packet);
+                if short {
+                    break;
+                }
+            }
+        }
+        if closing {
+            self.close_pending = false;
+        }
+        if packets.is_empty() {
+            return None;
+        }
+
+        // the datagrams of the Initial packets of the client, and of the
+        // ack-eliciting ones of the server, are 1200 bytes at least
+        let pad = packets
+            .iter()
+            .any(|p| p.ty == PacketType::Initial && (!self.server || p.sent.ack_eliciting));
+        if pad && size < MIN_INITIAL_SIZE {
+            let last = packets.last_mut().unwrap();
+            last.buf.resize(last.buf.len() + MIN_INITIAL_SIZE - size, 0);
+            last.sent.in_flight = true;
+            size = MIN_INITIAL_SIZE;
+        }
+
+        let mut pos = 0;
+        let mut sent_handshake = false;
+        let mut ack_eliciting = false;
+        for mut packet in packets {
+            if packet.ty != PacketType::Short {
+                packet::set_length(&mut packet.buf, packet.pn_offset);
+            }
+            packet.buf.resize(packet.buf.len() + AEAD_TAG_SIZE, 0);
+            let keys = match packet.ty {
+                PacketType::Initial | PacketType::Handshake => {
+                    &self.spaces[packet.ty.space() as usize]
+                        .keys
+                        .as_ref()
+                        .unwrap()
+                        .0
+                }
+                PacketType::ZeroRtt => self.zero_rtt_keys.as_ref().unwrap(),
+                _ => &self.one_rtt.as_ref().unwrap().local,
+            };
+            keys.seal(packet.pn, &mut packet.buf, packet.header_len);
+            buf[pos..pos + packet.buf.len()].copy_from_slice(&packet.buf);
+            pos += packet.buf.len();
+
+            sent_handshake |= packet.ty == PacketType::Handshake;
+            ack_eliciting |= packet.sent.ack_eliciting;
+            if let (PacketType::Short, Some(keys)) = (packet.ty, &mut self.one_rtt) {
+                keys.sent += 1;
+            }
+            packet.sent.size = packet.buf.len();
+            packet.sent.time = now;
+            self.recovery
+                .on_packet_sent(packet.ty.space(), packet.pn, packet.sent);
+        }
+        debug_assert_eq!(pos, size);
+        if let Some((_, sent)) = &mut self.amplification {
+            *sent += pos;
+        }
+        if ack_eliciting && !self.ack_eliciting_sent {
+            self.ack_eliciting_sent = true;
+            self.last_activity = now;
+        }
+        if sent_handshake && !self.server {
+            self.discard_space(Space::Initial);
+        }
+        Some(pos)
+    }
+
+    fn maybe_update_keys(&mut self) {
+        let Some(keys) = &self.one_rtt else {
+            return;
+        };
+        if self.handshake_confirmed
+            && keys.acked
+            && (self.key_update_requested || keys.sent >= KEY_UPDATE_INTERVAL)
+        {
+            debug!("quic: updating the keys");
+            self.key_update_requested = false;
+            let next_pn = self.spaces[Space::Data as usize].next_pn;
+            self.one_rtt = Some(self.one_rtt.take().unwrap().update(next_pn));
+        }
+    }
+
+    /// Starts a packet of `space`, if its keys are there and there is room.
+    fn start_packet(&self, space: Space, max_len: usize) -> Option<PendingPacket> {
+        let ty = self.packet_type(space)?;
+        let pn = self.spaces[space as usize].next_pn;
+        let pn_len = packet::pn_len(pn, self.recovery.largest_acked(space));
+        let token = if ty == PacketType::Initial && !self.server {
+            &self.token[..]
+        } else {
+            &[]
+        };
+        let key_phase = self.one_rtt.as_ref().is_some_and(|k| k.phase);
+        let mut buf = Vec::with_capacity(max_len);
+        put_header(
+            &mut buf,
+            ty,
+            self.remote_cid.as_slice(),
+            self.local_cid.as_slice(),
+            token,
+            pn,
+            pn_len,
+            key_phase,
+        );
+        if buf.len() + AEAD_TAG_SIZE + MIN_PACKET_ROOM / 2 > max_len {
+            return None;
+        }
+        Some(PendingPacket {
+            ty,
+            pn,
+            header_len: buf.len(),
+            pn_offset: buf.len() - pn_len,
+            buf,
+            sent: SentPacket {
+                time: Duration::ZERO,
+                size: 0,
+                ack_eliciting: false,
+                in_flight: false,
+                frames: Vec::new(),
+            },
+        })
+    }
+
+    /// Ends a packet with enough bytes for the sample of the header
+    /// protection, and takes its packet number.
+    fn finish_packet(&mut self, space: Space, mut packet: PendingPacket) -> PendingPacket {
+        while packet.buf.len() < packet.pn_offset + 4 {
+            packet.buf.push(0);
+        }
+        packet.sent.in_flight = packet.sent.ack_eliciting;
+        self.spaces[space as usize].next_pn += 1;
+        packet
+    }
+
+    fn build_close(&mut self, space: Space, max_len: usize) -> Option<PendingPacket> {
+        if space == Space::Data && self.state == State::Handshake {
+            return None;
+        }
+        let mut packet = self.start_packet(space, max_len)?;
+        let (code, application, reason) = self.close_frame.as_ref().unwrap();
+        let limit = max_len - packet.buf.len() - AEAD_TAG_SIZE - 24;
+        let reason = &reason[..reason.len().min(limit)];
+        if *application && packet.ty != PacketType::Short {
+            // the code of the application is not told before the handshake
+            // completes (RFC 9000 section 10.2.3)
+            frame::put_close(&mut packet.buf, code::APPLICATION_ERROR, false, &[]);
+        } else {
+            frame::put_close(&mut packet.buf, *code, *application, reason);
+        }
+        Some(self.finish_packet(space, packet))
+    }
+
+    fn build_packet(
+        &mut self,
+        space: Space,
+        max_len: usize,
+        now: Duration,
+    ) -> Option<PendingPacket> {
+        self.packet_type(space)?;
+        let s = &self.spaces[space as usize];
+        let probe = s.probes > 0;
+        let ack_due = s.ack_due(space == Space::Data, now);
+        let can_send = probe || self.recovery.window_available() >= max_len.min(MIN_INITIAL_SIZE);
+        let has_frames = can_send && (self.has_frames(space) || probe);
+        if !ack_due && !has_frames {
+            return None;
+        }
+        let mut packet = self.start_packet(space, max_len)?;
+        let limit = max_len - AEAD_TAG_SIZE;
+        let mut ack_eliciting = false;
+
+        let s = &mut self.spaces[space as usize];
+        if s.ack_pending && packet.ty != PacketType::ZeroRtt {
+            let (largest, time) = s.largest_received.unwrap();
+            let delay = now.saturating_sub(time).as_micros() as u64 >> 3;
+            frame::put_ack(&mut packet.buf, &s.received, delay);
+            packet.sent.frames.push(SentFrame::Ack(largest));
+            s.ack_pending = false;
+            s.unacked = 0;
+            s.ack_deadline = None;
+        }
+        if has_frames {
+            if space == Space::Data {
+                ack_eliciting |= self.put_control_frames(&mut packet, limit);
+            }
+            ack_eliciting |= self.put_crypto_frames(space, &mut packet, limit);
+            if space == Space::Data {
+                ack_eliciting |= self.put_stream_frames(&mut packet, limit);
+            }
+            let s = &mut self.spaces[space as usize];
+            if (probe || (space == Space::Data && self.ping_pending)) && !ack_eliciting {
+                packet.buf.push(0x01);
+                ack_eliciting = true;
+            }
+            if space == Space::Data {
+                self.ping_pending = false;
+            }
+            if probe {
+                s.probes -= 1;
+            }
+        }
+        if packet.buf.len() == packet.header_len {
+            // nothing was written
+            return None;
+        }
+        packet.sent.ack_eliciting = ack_eliciting;
+        Some(self.finish_packet(space, packet))
+    }
+
+    /// Writes the frames of the data space other than STREAM ones, and
+    /// returns whether there was any.
+    fn put_control_frames(&mut self, packet: &mut PendingPacket, limit: usize) -> bool {
+        let start = packet.sent.frames.len();
+        let buf = &mut packet.buf;
+        let frames = &mut packet.sent.frames;
+        let room = |buf: &Vec<u8>, len| buf.len() + len <= limit;
+        let mut any = false;
+        if self.handshake_done_pending && room(buf, 1) {
+            buf.push(0x1e);
+            frames.push(SentFrame::HandshakeDone);
+            self.handshake_done_pending = false;
+        }
+        while !self.path_responses.is_empty() && room(buf, 9) {
+            buf.push(0x1b);
+            buf.extend_from_slice(&self.path_responses.remove(0));
+            any = true;
+        }
+        if self.max_data_pending && room(buf, 9) {
+            frame::put_varints(buf, 0x10, &[self.local_max_data]);
+            frames.push(SentFrame::MaxData);
+            self.max_data_pending = false;
+        }
+        for (uni, pending) in self.max_streams_pending.iter_mut().enumerate() {
+            if *pending && room(buf, 9) {
+                frame::put_varints(buf, 0x12 + uni as u64, &[self.local_max_streams[uni]]);
+                frames.push(SentFrame::MaxStreams { bidi: uni == 0 });
+                *pending = false;
+            }
+        }
+        while !self.retire_pending.is_empty() && room(buf, 9) {
+            let seq = self.retire_pending.remove(0);
+            frame::put_varints(buf, 0x19, &[seq]);
+            frames.push(SentFrame::RetireConnectionId(seq));
+        }
+        for (&id, stream) in &mut self.streams {
+            if let Some(send) = &mut stream.send {
+                if send.reset_pending && room(buf, 25) {
+                    frame::put_varints(buf, 0x04, &[id, send.reset.unwrap(), send.final_size()]);
+                    frames.push(SentFrame::ResetStream(id));
+                    send.reset_pending = false;
+                }
+            }
+            if let Some(recv) = &mut stream.recv {
+                if recv.stop_sending_pending && room(buf, 17) {
+                    frame::put_varints(buf, 0x05, &[id, recv.stop_sending.unwrap()]);
+                    frames.push(SentFrame::StopSending(id));
+                    recv.stop_sending_pending = false;
+                }
+                if recv.max_data_pending && room(buf, 17) {
+                    frame::put_varints(buf, 0x11, &[id, recv.max_data]);
+                    frames.push(SentFrame::MaxStreamData(id));
+                    recv.max_data_pending = false;
+                }
+            }
+        }
+        any || frames.len() > start
+    }
+
+    fn put_crypto_frames(
+        &mut self,
+        space: Space,
+        packet: &mut PendingPacket,
+        limit: usize,
+    ) -> bool {
+        let send = &mut self.spaces[space as usize].crypto_send;
+        let mut any = false;
+        while send.has_data(u64::MAX) {
+            let overhead = frame::crypto_overhead(send.offset(), limit);
+            let room = limit.saturating_sub(packet.buf.len() + overhead);
+            if room == 0 {
+                break;
+            }
+            let Some(chunk) = send.next_chunk(room, u64::MAX) else {
+                break;
+            };
+            frame::put_crypto(&mut packet.buf, chunk.offset, send.data(&chunk));
+            packet.sent.frames.push(SentFrame::Crypto {
+                offset: chunk.offset,
+                len: chunk.len,
+            });
+            any = true;
+        }
+        any
+    }
+
+    /// Writes the STREAM frames that fit, the streams taking turns.
+    fn put_stream_frames(&mut self, packet: &mut PendingPacket, limit: usize) -> bool {
+        let mut ids: Vec<u64> = self
+            .streams
+            .iter()
+            .filter(|(_, s)| s.send.is_some())
+            .map(|(id, _)| *id)
+            .collect();
+        let turn = ids.partition_point(|id| *id <= self.last_sent_stream);
+        ids.rotate_left(turn);
+        let mut any = false;
+        for id in ids {
+            let credit = self.peer_max_data - self.data_sent;
+            let send = self.streams.get_mut(&id).unwrap().send.as_mut().unwrap();
+            if !send.has_data(credit) {
+                continue;
+            }
+            let overhead = frame::stream_overhead(id, send.offset(), limit);
+            let Some(room) = limit.checked_sub(packet.buf.len() + overhead) else {
+                break;
+            };
+            let Some(chunk) = send.next_chunk(room, credit) else {
+                continue;
+            };
+            frame::put_stream(
+                &mut packet.buf,
+                id,
+                chunk.offset,
+                send.data(&chunk),
+                chunk.fin,
+            );
+            packet.sent.frames.push(SentFrame::Stream {
+                id,
+                offset: chunk.offset,
+                len: chunk.len,
+                fin: chunk.fin,
+            });
+            self.data_sent += chunk.new_bytes;
+            self.last_sent_stream = id;
+            any = true;
+        }
+        any
+    }
+}
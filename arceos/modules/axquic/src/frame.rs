@@ -0,0 +1,333 @@
+//! QUIC frames (RFC 9000 section 19).
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::codec::{put_varint, varint_len, Reader, MAX_VARINT};
+use crate::ranges::RangeSet;
+use crate::{code, TransportError};
+
+/// The most ranges sent in an ACK frame.
+const MAX_ACK_RANGES: usize = 32;
+
+/// The largest number of streams of one type.
+pub const MAX_STREAMS: u64 = 1 << 60;
+
+/// A frame received.
+#[derive(Debug)]
+pub enum Frame<'a> {
+    Padding,
+    Ping,
+    Ack {
+        /// The ranges of packet numbers acknowledged, from the largest.
+        ranges: Vec<Range<u64>>,
+        delay: u64,
+    },
+    ResetStream {
+        id: u64,
+        code: u64,
+        final_size: u64,
+    },
+    StopSending {
+        id: u64,
+        code: u64,
+    },
+    Crypto {
+        offset: u64,
+        data: &'a [u8],
+    },
+    NewToken,
+    Stream {
+        id: u64,
+        offset: u64,
+        data: &'a [u8],
+        fin: bool,
+    },
+    MaxData(u64),
+    MaxStreamData {
+        id: u64,
+        max: u64,
+    },
+    MaxStreams {
+        bidi: bool,
+        max: u64,
+    },
+    DataBlocked,
+    StreamDataBlocked {
+        id: u64,
+    },
+    StreamsBlocked,
+    NewConnectionId {
+        seq: u64,
+        retire_prior_to: u64,
+        cid: &'a [u8],
+    },
+    RetireConnectionId,
+    PathChallenge([u8; 8]),
+    PathResponse,
+    ConnectionClose {
+        code: u64,
+        /// Whether the code is one of the application, not of QUIC.
+        application: bool,
+        reason: &'a [u8],
+    },
+    HandshakeDone,
+}
+
+impl<'a> Frame<'a> {
+    /// Parses the next frame of a payload.
+    pub fn parse(r: &mut Reader<'a>) -> Result<Self, TransportError> {
+        Self::parse_inner(r).ok_or(TransportError::new(code::FRAME_ENCODING_ERROR, "bad frame"))?
+    }
+
+    fn parse_inner(r: &mut Reader<'a>) -> Option<Result<Self, TransportError>> {
+        let ty = r.varint()?;
+        let frame = match ty {
+            0x00 => {
+                while r.peek_rest().first() == Some(&0) {
+                    r.u8();
+                }
+                Frame::Padding
+            }
+            0x01 => Frame::Ping,
+            0x02 | 0x03 => {
+                let largest = r.varint()?;
+                let delay = r.varint()?;
+                let count = r.varint()?;
+                let first = r.varint()?;
+                let mut smallest = largest.checked_sub(first)?;
+                let mut ranges = Vec::new();
+                ranges.push(smallest..largest + 1);
+                for _ in 0..count {
+                    let gap = r.varint()?;
+                    let len = r.varint()?;
+                    let largest = smallest.checked_sub(gap)?.checked_sub(2)?;
+                    smallest = largest.checked_sub(len)?;
+                    ranges.push(smallest..largest + 1);
+                }
+                if ty == 0x03 {
+                    // the ECN counts
+                    for _ in 0..3 {
+                        r.varint()?;
+                    }
+                }
+                Frame::Ack { ranges, delay }
+            }
+            0x04 => Frame::ResetStream {
+                id: r.varint()?,
+                code: r.varint()?,
+                final_size: r.varint()?,
+            },
+            0x05 => Frame::StopSending {
+                id: r.varint()?,
+                code: r.varint()?,
+            },
+            0x06 => {
+                let offset = r.varint()?;
+                let data = r.varint_vec()?;
+                if offset + data.len() as u64 > MAX_VARINT {
+                    return None;
+                }
+                Frame::Crypto { offset, data }
+            }
+            0x07 => {
+                if r.varint_vec()?.is_empty() {
+                    return None;
+                }
+                Frame::NewToken
+            }
+            0x08..=0x0f => {
+                let id = r.varint()?;
+                let offset = if ty & 0x04 != 0 { r.varint()? } else { 0 };
+                let data = if ty & 0x02 != 0 {
+                    r.varint_vec()?
+                } else {
+                    r.bytes(r.remaining())?
+                };
+                if offset + data.len() as u64 > MAX_VARINT {
+                    return None;
+                }
+                Frame::Stream {
+                    id,
+                    offset,
+                    data,
+                    fin: ty & 0x01 != 0,
+                }
+            }
+            0x10 => Frame::MaxData(r.varint()?),
+            0x11 => Frame::MaxStreamData {
+                id: r.varint()?,
+                max: r.varint()?,
+            },
+            0x12 | 0x13 => {
+                let max = r.varint()?;
+                if max > MAX_STREAMS {
+                    return None;
+                }
+                Frame::MaxStreams {
+                    bidi: ty == 0x12,
+                    max,
+                }
+            }
+            0x14 => {
+                r.varint()?;
+                Frame::DataBlocked
+            }
+            0x15 => {
+                let id = r.varint()?;
+                r.varint()?;
+                Frame::StreamDataBlocked { id }
+            }
+            0x16 | 0x17 => {
+                if r.varint()? > MAX_STREAMS {
+                    return None;
+                }
+                Frame::StreamsBlocked
+            }
+            0x18 => {
+                let seq = r.varint()?;
+                let retire_prior_to = r.varint()?;
+                let cid = r.vec::<1>()?;
+                r.bytes(16)?;
+                if retire_prior_to > seq || cid.is_empty() || cid.len() > 20 {
+                    return None;
+                }
+                Frame::NewConnectionId {
+                    seq,
+                    retire_prior_to,
+                    cid,
+                }
+            }
+            0x19 => {
+                r.varint()?;
+                Frame::RetireConnectionId
+            }
+            0x1a => Frame::PathChallenge(r.array()?),
+            0x1b => {
+                r.bytes(8)?;
+                Frame::PathResponse
+            }
+            0x1c | 0x1d => {
+                let code = r.varint()?;
+                if ty == 0x1c {
+                    r.varint()?;
+                }
+                Frame::ConnectionClose {
+                    code,
+                    application: ty == 0x1d,
+                    reason: r.varint_vec()?,
+                }
+            }
+            0x1e => Frame::HandshakeDone,
+            _ => {
+                return Some(Err(TransportError::new(
+                    code::FRAME_ENCODING_ERROR,
+                    "unknown frame type",
+                )))
+            }
+        };
+        Some(Ok(frame))
+    }
+
+    /// Returns whether the frame makes the packet carrying it acknowledged.
+    pub fn is_ack_eliciting(&self) -> bool {
+        !matches!(
+            self,
+            Frame::Padding | Frame::Ack { .. } | Frame::ConnectionClose { .. }
+        )
+    }
+
+    /// Returns whether the frame may be in Initial and Handshake packets.
+    pub fn is_allowed_in_handshake(&self) -> bool {
+        matches!(
+            self,
+            Frame::Padding
+                | Frame::Ping
+                | Frame::Ack { .. }
+                | Frame::Crypto { .. }
+                | Frame::ConnectionClose {
+                    application: false,
+                    ..
+                }
+        )
+    }
+
+    /// Returns whether the frame may be in 0-RTT packets.
+    pub fn is_allowed_in_0rtt(&self) -> bool {
+        !matches!(
+            self,
+            Frame::Ack { .. }
+                | Frame::Crypto { .. }
+                | Frame::HandshakeDone
+                | Frame::NewToken
+                | Frame::PathResponse
+                | Frame::RetireConnectionId
+        )
+    }
+}
+
+/// Appends an ACK frame of the packet numbers `received`, the largest one
+/// received `delay` ago, in units of the ACK delay exponent.
+pub fn put_ack(out: &mut Vec<u8>, received: &RangeSet, delay: u64) {
+    let mut ranges = received.iter().rev().take(MAX_ACK_RANGES);
+    let first = ranges.next().unwrap();
+    put_varint(out, 0x02);
+    put_varint(out, first.end - 1);
+    put_varint(out, delay);
+    put_varint(out, (received.len().min(MAX_ACK_RANGES) - 1) as u64);
+    put_varint(out, first.end - 1 - first.start);
+    let mut smallest = first.start;
+    for range in ranges {
+        put_varint(out, smallest - range.end - 1);
+        put_varint(out, range.end - 1 - range.start);
+        smallest = range.start;
+    }
+}
+
+pub fn put_crypto(out: &mut Vec<u8>, offset: u64, data: &[u8]) {
+    put_varint(out, 0x06);
+    put_varint(out, offset);
+    put_varint(out, data.len() as u64);
+    out.extend_from_slice(data);
+}
+
+/// Returns the largest overhead of a CRYPTO frame at `offset` with up to
+/// `max_len` bytes.
+pub fn crypto_overhead(offset: u64, max_len: usize) -> usize {
+    1 + varint_len(offset) + varint_len(max_len as u64)
+}
+
+pub fn put_stream(out: &mut Vec<u8>, id: u64, offset: u64, data: &[u8], fin: bool) {
+    let ty = 0x08 | 0x02 | fin as u64 | if offset > 0 { 0x04 } else { 0 };
+    put_varint(out, ty);
+    put_varint(out, id);
+    if offset > 0 {
+        put_varint(out, offset);
+    }
+    put_varint(out, data.len() as u64);
+    out.extend_from_slice(data);
+}
+
+/// Returns the largest overhead of a STREAM frame of stream `id` at `offset`
+/// with up to `max_len` bytes.
+pub fn stream_overhead(id: u64, offset: u64, max_len: usize) -> usize {
+    1 + varint_len(id) + varint_len(offset) + varint_len(max_len as u64)
+}
+
+pub fn put_varints(out: &mut Vec<u8>, ty: u64, fields: &[u64]) {
+    put_varint(out, ty);
+    for field in fields {
+        put_varint(out, *field);
+    }
+}
+
+/// Appends a CONNECTION_CLOSE frame.
+pub fn put_close(out: &mut Vec<u8>, code: u64, application: bool, reason: &[u8]) {
+    if application {
+        put_varints(out, 0x1d, &[code]);
+    } else {
+        put_varints(out, 0x1c, &[code, 0]);
+    }
+    put_varint(out, reason.len() as u64);
+    out.extend_from_slice(reason);
+}
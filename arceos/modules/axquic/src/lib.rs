@@ -0,0 +1,82 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) QUIC transport (RFC 9000).
+//!
+//! A [`Connection`] is a state machine without I/O: the datagrams received
+//! are given to [`Connection::handle_datagram`], the ones to send are taken
+//! with [`Connection::poll_transmit`], and [`Connection::timeout`] tells when
+//! to call [`Connection::handle_timeout`]. `axnet` runs it over its UDP
+//! sockets.
+//!
+//! The handshake is TLS 1.3 (RFC 9001), built in on the algorithms of
+//! `axcrypto`: AES-128-GCM, X25519 and Ed25519 keys pinned by the client
+//! instead of a certificate chain. The server gives session tickets, with
+//! which a client resumes the session and sends 0-RTT data.
+//!
+//! The losses are recovered and the congestion controlled as RFC 9002 says,
+//! with NewReno. Not supported: connection migration, the Retry of a server
+//! (a client follows it), stateless resets, and the datagram extension.
+
+#![cfg_attr(not(test), no_std)]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+#[cfg(test)]
+mod tests;
+
+mod cert;
+mod codec;
+mod config;
+mod connection;
+mod frame;
+mod packet;
+mod params;
+mod ranges;
+mod recovery;
+mod stream;
+mod tls;
+
+pub use config::{ClientConfig, ServerConfig, TransportConfig};
+pub use connection::{Connection, ConnectionError, StreamId, LOCAL_CID_LEN};
+pub use tls::SessionTicket;
+
+/// Returns the destination connection ID of the first packet of `datagram`,
+/// to find its connection, or [`None`] if it is not a QUIC packet.
+pub fn destination_cid(datagram: &[u8]) -> Option<&[u8]> {
+    packet::destination_cid(datagram, LOCAL_CID_LEN)
+}
+
+/// Writes into `out` the Version Negotiation packet answering `datagram` of
+/// an unsupported version, and returns its length.
+pub fn version_negotiation(datagram: &[u8], out: &mut [u8]) -> Option<usize> {
+    packet::version_negotiation(datagram, out)
+}
+
+/// An error of the peer, closing the connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TransportError {
+    code: u64,
+    reason: &'static str,
+}
+
+impl TransportError {
+    pub(crate) const fn new(code: u64, reason: &'static str) -> Self {
+        Self { code, reason }
+    }
+}
+
+/// The transport error codes (RFC 9000 section 20.1).
+pub(crate) mod code {
+    pub const FLOW_CONTROL_ERROR: u64 = 0x03;
+    pub const STREAM_LIMIT_ERROR: u64 = 0x04;
+    pub const STREAM_STATE_ERROR: u64 = 0x05;
+    pub const FINAL_SIZE_ERROR: u64 = 0x06;
+    pub const FRAME_ENCODING_ERROR: u64 = 0x07;
+    pub const TRANSPORT_PARAMETER_ERROR: u64 = 0x08;
+    pub const CONNECTION_ID_LIMIT_ERROR: u64 = 0x09;
+    pub const PROTOCOL_VIOLATION: u64 = 0x0a;
+    pub const APPLICATION_ERROR: u64 = 0x0c;
+    pub const CRYPTO_BUFFER_EXCEEDED: u64 = 0x0d;
+    /// Plus the TLS alert.
+    pub const CRYPTO_ERROR: u64 = 0x100;
+}
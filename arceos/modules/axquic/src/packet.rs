@@ -0,0 +1,423 @@
+//! QUIC packets: their headers, and their protection (RFC 9001 section 5).
+
+use alloc::vec::Vec;
+
+use axcrypto::{hkdf_extract, AesGcm, Cipher, CipherAlg, AEAD_TAG_SIZE};
+
+use crate::codec::{put_u32, Reader};
+use crate::tls::expand_label;
+
+/// The only version spoken: QUIC version 1.
+pub const VERSION: u32 = 1;
+
+/// The longest connection IDs.
+pub const MAX_CID_LEN: usize = 20;
+
+/// The smallest datagrams carrying Initial packets.
+pub const MIN_INITIAL_SIZE: usize = 1200;
+
+const INITIAL_SALT: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad,
+    0xcc, 0xbb, 0x7f, 0x0a,
+];
+
+const RETRY_KEY: [u8; 16] = [
+    0xbe, 0x0c, 0x69, 0x0b, 0x9f, 0x66, 0x57, 0x5a, 0x1d, 0x76, 0x6b, 0x54, 0xe3, 0x68, 0xc8, 0x4e,
+];
+const RETRY_NONCE: [u8; 12] = [
+    0x46, 0x15, 0x99, 0xd3, 0x5d, 0x63, 0x2b, 0xf2, 0x23, 0x98, 0x25, 0xbb,
+];
+
+/// The packet number spaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Space {
+    Initial = 0,
+    Handshake = 1,
+    /// The 0-RTT and 1-RTT packets.
+    Data = 2,
+}
+
+impl Space {
+    pub const ALL: [Space; 3] = [Space::Initial, Space::Handshake, Space::Data];
+}
+
+/// A connection ID, of up to [`MAX_CID_LEN`] bytes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionId {
+    len: u8,
+    bytes: [u8; MAX_CID_LEN],
+}
+
+impl ConnectionId {
+    pub fn new(cid: &[u8]) -> Option<Self> {
+        if cid.len() > MAX_CID_LEN {
+            return None;
+        }
+        let mut bytes = [0; MAX_CID_LEN];
+        bytes[..cid.len()].copy_from_slice(cid);
+        Some(Self {
+            len: cid.len() as u8,
+            bytes,
+        })
+    }
+
+    /// Returns a random connection ID of `len` bytes.
+    pub fn random(len: usize, random: fn(&mut [u8])) -> Self {
+        let mut bytes = [0; MAX_CID_LEN];
+        random(&mut bytes[..len]);
+        Self {
+            len: len as u8,
+            bytes,
+        }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+impl core::fmt::Debug for ConnectionId {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        for b in self.as_slice() {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// The types of packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketType {
+    Initial,
+    ZeroRtt,
+    Handshake,
+    Retry,
+    /// The 1-RTT packets, with a short header.
+    Short,
+    VersionNegotiation,
+    /// A long header of another version than [`VERSION`], of which only the
+    /// connection IDs are known.
+    Unsupported,
+}
+
+impl PacketType {
+    pub fn space(self) -> Space {
+        match self {
+            Self::Initial => Space::Initial,
+            Self::Handshake => Space::Handshake,
+            _ => Space::Data,
+        }
+    }
+}
+
+/// The header of a packet, as far as it is not protected.
+pub struct Header<'a> {
+    pub ty: PacketType,
+    pub dcid: &'a [u8],
+    pub scid: &'a [u8],
+    /// The token of Initial and Retry packets.
+    pub token: &'a [u8],
+    /// Where the packet number starts.
+    pub pn_offset: usize,
+    /// The length of the packet, coalesced packets following it.
+    pub len: usize,
+}
+
+impl<'a> Header<'a> {
+    /// Parses the header at the start of `buf`, the short headers having
+    /// connection IDs of `short_cid_len` bytes.
+    pub fn parse(buf: &'a [u8], short_cid_len: usize) -> Option<Self> {
+        let mut r = Reader::new(buf);
+        let first = r.u8()?;
+        if first & 0x80 == 0 {
+            if first & 0x40 == 0 {
+                return None;
+            }
+            let dcid = r.bytes(short_cid_len)?;
+            return Some(Self {
+                ty: PacketType::Short,
+                dcid,
+                scid: &[],
+                token: &[],
+                pn_offset: r.position(),
+                len: buf.len(),
+            });
+        }
+
+        let version = r.u32()?;
+        let dcid = r.vec::<1>()?;
+        let scid = r.vec::<1>()?;
+        let mut header = Self {
+            ty: PacketType::Unsupported,
+            dcid,
+            scid,
+            token: &[],
+            pn_offset: 0,
+            len: buf.len(),
+        };
+        if version == 0 {
+            header.ty = PacketType::VersionNegotiation;
+            return Some(header);
+        } else if version != VERSION {
+            return Some(header);
+        } else if first & 0x40 == 0 || dcid.len() > MAX_CID_LEN || scid.len() > MAX_CID_LEN {
+            return None;
+        }
+        header.ty = match (first >> 4) & 3 {
+            0 => PacketType::Initial,
+            1 => PacketType::ZeroRtt,
+            2 => PacketType::Handshake,
+            _ => {
+                header.ty = PacketType::Retry;
+                header.token = r
+                    .peek_rest()
+                    .get(..r.remaining().checked_sub(AEAD_TAG_SIZE)?)?;
+                return Some(header);
+            }
+        };
+        if header.ty == PacketType::Initial {
+            header.token = r.varint_vec()?;
+        }
+        let len = usize::try_from(r.varint()?).ok()?;
+        header.pn_offset = r.position();
+        header.len = header.pn_offset.checked_add(len)?;
+        (header.len <= buf.len()).then_some(header)
+    }
+}
+
+/// Returns the destination connection ID of the first packet of `datagram`,
+/// the short headers having connection IDs of `short_cid_len` bytes.
+pub fn destination_cid(datagram: &[u8], short_cid_len: usize) -> Option<&[u8]> {
+    let mut r = Reader::new(datagram);
+    if r.u8()? & 0x80 == 0 {
+        r.bytes(short_cid_len)
+    } else {
+        r.u32()?;
+        r.vec::<1>()
+    }
+}
+
+/// Writes the header of a packet of type `ty`, with the packet number `pn`
+/// on `pn_len` bytes.
+///
+/// The length of the long headers takes 2 bytes, and is set by
+/// [`set_length`] once the payload is written.
+#[allow(clippy::too_many_arguments)]
+pub fn put_header(
+    out: &mut Vec<u8>,
+    ty: PacketType,
+    dcid: &[u8],
+    scid: &[u8],
+    token: &[u8],
+    pn: u64,
+    pn_len: usize,
+    key_phase: bool,
+) {
+    let pn_bits = (pn_len - 1) as u8;
+    if ty == PacketType::Short {
+        out.push(0x40 | (key_phase as u8) << 2 | pn_bits);
+        out.extend_from_slice(dcid);
+    } else {
+        let ty_bits = match ty {
+            PacketType::Initial => 0,
+            PacketType::ZeroRtt => 1,
+            PacketType::Handshake => 2,
+            _ => unreachable!(),
+        };
+        out.push(0xc0 | ty_bits << 4 | pn_bits);
+        put_u32(out, VERSION);
+        out.push(dcid.len() as u8);
+        out.extend_from_slice(dcid);
+        out.push(scid.len() as u8);
+        out.extend_from_slice(scid);
+        if ty == PacketType::Initial {
+            crate::codec::put_varint(out, token.len() as u64);
+            out.extend_from_slice(token);
+        }
+        out.extend_from_slice(&[0x40, 0]);
+    }
+    out.extend_from_slice(&pn.to_be_bytes()[8 - pn_len..]);
+}
+
+/// Sets the length of the long header `packet`, the packet number starting
+/// at `pn_offset`, counting the tag it is to be sealed with.
+pub fn set_length(packet: &mut [u8], pn_offset: usize) {
+    let len = packet.len() - pn_offset + AEAD_TAG_SIZE;
+    debug_assert!(len < 0x4000);
+    packet[pn_offset - 2..pn_offset].copy_from_slice(&(len as u16 | 0x4000).to_be_bytes());
+}
+
+/// Returns the number of bytes to send the packet number `pn` on, the peer
+/// having acknowledged up to `largest_acked`.
+pub fn pn_len(pn: u64, largest_acked: Option<u64>) -> usize {
+    let unacked = match largest_acked {
+        Some(largest) => pn - largest,
+        None => pn + 1,
+    };
+    let bits = 64 - unacked.leading_zeros() as usize + 1;
+    bits.div_ceil(8).clamp(1, 4)
+}
+
+/// Recovers a packet number from its `pn_len` lowest bytes
+/// (RFC 9000 appendix A.3).
+pub fn decode_pn(largest: Option<u64>, truncated: u64, pn_len: usize) -> u64 {
+    let expected = largest.map_or(0, |largest| largest + 1);
+    let win = 1u64 << (pn_len * 8);
+    let hwin = win / 2;
+    let candidate = (expected & !(win - 1)) | truncated;
+    if candidate + hwin <= expected && candidate < (1 << 62) - win {
+        candidate + win
+    } else if candidate > expected + hwin && candidate >= win {
+        candidate - win
+    } else {
+        candidate
+    }
+}
+
+/// The keys protecting the packets of one direction, at an encryption level.
+pub struct Keys {
+    aead: AesGcm,
+    iv: [u8; 12],
+    hp: Cipher,
+}
+
+impl Keys {
+    /// Derives the keys from the traffic secret of the direction.
+    pub fn new(secret: &[u8; 32]) -> Self {
+        let mut key = [0; 16];
+        let mut iv = [0; 12];
+        let mut hp = [0; 16];
+        expand_label(secret, b"quic key", &[], &mut key);
+        expand_label(secret, b"quic iv", &[], &mut iv);
+        expand_label(secret, b"quic hp", &[], &mut hp);
+        Self::with_hp(&key, iv, Cipher::new(CipherAlg::AesEcb, &hp).unwrap())
+    }
+
+    fn with_hp(key: &[u8; 16], iv: [u8; 12], hp: Cipher) -> Self {
+        Self {
+            aead: AesGcm::new(key).unwrap(),
+            iv,
+            hp,
+        }
+    }
+
+    /// Derives the keys of the next key phase from the next traffic secret,
+    /// keeping the header protection key.
+    pub fn update(self, next_secret: &[u8; 32]) -> Self {
+        let mut key = [0; 16];
+        let mut iv = [0; 12];
+        expand_label(next_secret, b"quic key", &[], &mut key);
+        expand_label(next_secret, b"quic iv", &[], &mut iv);
+        Self::with_hp(&key, iv, self.hp)
+    }
+
+    fn nonce(&self, pn: u64) -> [u8; 12] {
+        let mut nonce = self.iv;
+        for (n, p) in nonce[4..].iter_mut().zip(pn.to_be_bytes()) {
+            *n ^= p;
+        }
+        nonce
+    }
+
+    /// Applies or removes the header protection of `packet`, whose packet
+    /// number starts at `pn_offset`. Returns the length of the packet
+    /// number, or `None` if the packet is too short to be sampled.
+    fn toggle_header_protection(
+        &self,
+        packet: &mut [u8],
+        pn_offset: usize,
+        protect: bool,
+    ) -> Option<usize> {
+        let mut mask: [u8; 16] = packet
+            .get(pn_offset + 4..pn_offset + 20)?
+            .try_into()
+            .unwrap();
+        self.hp.encrypt(&[], &mut mask).ok()?;
+        let pn_len = |first: u8| (first & 3) as usize + 1;
+        let len = pn_len(packet[0]);
+        packet[0] ^= mask[0] & if packet[0] & 0x80 != 0 { 0x0f } else { 0x1f };
+        let len = if protect { len } else { pn_len(packet[0]) };
+        for i in 0..len {
+            packet[pn_offset + i] ^= mask[1 + i];
+        }
+        Some(len)
+    }
+
+    /// Encrypts `packet` in place: a header of `header_len` bytes ending with
+    /// the packet number `pn`, the payload, and room for the tag.
+    pub fn seal(&self, pn: u64, packet: &mut [u8], header_len: usize) {
+        let (header, rest) = packet.split_at_mut(header_len);
+        let (payload, tag) = rest.split_at_mut(rest.len() - AEAD_TAG_SIZE);
+        let t = self.aead.seal_in_place(&self.nonce(pn), header, payload);
+        tag.copy_from_slice(&t.unwrap());
+        let pn_len = (packet[0] & 3) as usize + 1;
+        self.toggle_header_protection(packet, header_len - pn_len, true)
+            .unwrap();
+    }
+
+    /// Removes the header protection of `packet`, and returns the packet
+    /// number as sent, and its length.
+    pub fn unprotect_header(&self, packet: &mut [u8], pn_offset: usize) -> Option<(u64, usize)> {
+        let len = self.toggle_header_protection(packet, pn_offset, false)?;
+        let pn = packet[pn_offset..pn_offset + len]
+            .iter()
+            .fold(0, |pn, b| (pn << 8) | *b as u64);
+        Some((pn, len))
+    }
+
+    /// Decrypts the payload of `packet` in place, after the header of
+    /// `header_len` bytes, and returns it.
+    pub fn open<'a>(&self, pn: u64, packet: &'a mut [u8], header_len: usize) -> Option<&'a [u8]> {
+        let len = packet.len().checked_sub(header_len + AEAD_TAG_SIZE)?;
+        let (header, rest) = packet.split_at_mut(header_len);
+        let (payload, tag) = rest.split_at_mut(len);
+        let tag: &[u8; AEAD_TAG_SIZE] = (&*tag).try_into().unwrap();
+        self.aead
+            .open_in_place(&self.nonce(pn), header, payload, tag)
+            .ok()?;
+        Some(payload)
+    }
+}
+
+/// Returns the client and server secrets of the Initial packets, sent to
+/// `dcid` by the client.
+pub fn initial_secrets(dcid: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let initial = hkdf_extract(&INITIAL_SALT, dcid);
+    let mut client = [0; 32];
+    let mut server = [0; 32];
+    expand_label(&initial, b"client in", &[], &mut client);
+    expand_label(&initial, b"server in", &[], &mut server);
+    (client, server)
+}
+
+/// Computes the integrity tag of the Retry packet `retry` (without the tag),
+/// answering a packet sent to `odcid`.
+pub fn retry_tag(odcid: &[u8], retry: &[u8]) -> [u8; AEAD_TAG_SIZE] {
+    let mut pseudo = Vec::with_capacity(1 + odcid.len() + retry.len());
+    pseudo.push(odcid.len() as u8);
+    pseudo.extend_from_slice(odcid);
+    pseudo.extend_from_slice(retry);
+    let aead = AesGcm::new(&RETRY_KEY).unwrap();
+    aead.seal_in_place(&RETRY_NONCE, &pseudo, &mut []).unwrap()
+}
+
+/// Writes into `out` the Version Negotiation packet answering `datagram`, if
+/// it is of another version than [`VERSION`] and could start a connection.
+///
+/// Returns the length of the packet.
+pub fn version_negotiation(datagram: &[u8], out: &mut [u8]) -> Option<usize> {
+    let header = Header::parse(datagram, 0)?;
+    if header.ty != PacketType::Unsupported || datagram.len() < MIN_INITIAL_SIZE {
+        return None;
+    }
+    let mut packet = Vec::new();
+    packet.push(0xc0);
+    put_u32(&mut packet, 0);
+    packet.push(header.scid.len() as u8);
+    packet.extend_from_slice(header.scid);
+    packet.push(header.dcid.len() as u8);
+    packet.extend_from_slice(header.dcid);
+    put_u32(&mut packet, VERSION);
+    out.get_mut(..packet.len())?.copy_from_slice(&packet);
+    Some(packet.len())
+}
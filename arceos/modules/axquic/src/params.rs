@@ -0,0 +1,219 @@
+//! The transport parameters (RFC 9000 section 18), exchanged in the TLS
+//! handshake.
+
+use alloc::vec::Vec;
+
+use crate::codec::{put_varint, varint_len, Reader};
+use crate::frame::MAX_STREAMS;
+use crate::packet::ConnectionId;
+use crate::{code, TransportError};
+
+const ORIGINAL_DCID: u64 = 0x00;
+const MAX_IDLE_TIMEOUT: u64 = 0x01;
+const STATELESS_RESET_TOKEN: u64 = 0x02;
+const MAX_UDP_PAYLOAD_SIZE: u64 = 0x03;
+const INITIAL_MAX_DATA: u64 = 0x04;
+const INITIAL_MAX_STREAM_DATA_BIDI_LOCAL: u64 = 0x05;
+const INITIAL_MAX_STREAM_DATA_BIDI_REMOTE: u64 = 0x06;
+const INITIAL_MAX_STREAM_DATA_UNI: u64 = 0x07;
+const INITIAL_MAX_STREAMS_BIDI: u64 = 0x08;
+const INITIAL_MAX_STREAMS_UNI: u64 = 0x09;
+const ACK_DELAY_EXPONENT: u64 = 0x0a;
+const MAX_ACK_DELAY: u64 = 0x0b;
+const DISABLE_ACTIVE_MIGRATION: u64 = 0x0c;
+const PREFERRED_ADDRESS: u64 = 0x0d;
+const ACTIVE_CONNECTION_ID_LIMIT: u64 = 0x0e;
+const INITIAL_SCID: u64 = 0x0f;
+const RETRY_SCID: u64 = 0x10;
+
+/// The transport parameters of an endpoint.
+#[derive(Debug, Clone)]
+pub struct TransportParams {
+    pub original_dcid: Option<ConnectionId>,
+    /// In milliseconds, 0 for none.
+    pub max_idle_timeout: u64,
+    pub max_udp_payload_size: u64,
+    pub initial_max_data: u64,
+    pub initial_max_stream_data_bidi_local: u64,
+    pub initial_max_stream_data_bidi_remote: u64,
+    pub initial_max_stream_data_uni: u64,
+    pub initial_max_streams_bidi: u64,
+    pub initial_max_streams_uni: u64,
+    pub ack_delay_exponent: u64,
+    /// In milliseconds.
+    pub max_ack_delay: u64,
+    pub disable_active_migration: bool,
+    pub active_connection_id_limit: u64,
+    pub initial_scid: Option<ConnectionId>,
+    pub retry_scid: Option<ConnectionId>,
+}
+
+impl Default for TransportParams {
+    fn default() -> Self {
+        Self {
+            original_dcid: None,
+            max_idle_timeout: 0,
+            max_udp_payload_size: 65527,
+            initial_max_data: 0,
+            initial_max_stream_data_bidi_local: 0,
+            initial_max_stream_data_bidi_remote: 0,
+            initial_max_stream_data_uni: 0,
+            initial_max_streams_bidi: 0,
+            initial_max_streams_uni: 0,
+            ack_delay_exponent: 3,
+            max_ack_delay: 25,
+            disable_active_migration: false,
+            active_connection_id_limit: 2,
+            initial_scid: None,
+            retry_scid: None,
+        }
+    }
+}
+
+fn put_param(out: &mut Vec<u8>, id: u64, value: &[u8]) {
+    put_varint(out, id);
+    put_varint(out, value.len() as u64);
+    out.extend_from_slice(value);
+}
+
+fn put_int_param(out: &mut Vec<u8>, id: u64, value: u64) {
+    put_varint(out, id);
+    put_varint(out, varint_len(value) as u64);
+    put_varint(out, value);
+}
+
+impl TransportParams {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let defaults = Self::default();
+        if let Some(cid) = &self.original_dcid {
+            put_param(&mut out, ORIGINAL_DCID, cid.as_slice());
+        }
+        for (id, value, default) in [
+            (MAX_IDLE_TIMEOUT, self.max_idle_timeout, 0),
+            (
+                MAX_UDP_PAYLOAD_SIZE,
+                self.max_udp_payload_size,
+                defaults.max_udp_payload_size,
+            ),
+            (INITIAL_MAX_DATA, self.initial_max_data, 0),
+            (
+                INITIAL_MAX_STREAM_DATA_BIDI_LOCAL,
+                self.initial_max_stream_data_bidi_local,
+                0,
+            ),
+            (
+                INITIAL_MAX_STREAM_DATA_BIDI_REMOTE,
+                self.initial_max_stream_data_bidi_remote,
+                0,
+            ),
+            (
+                INITIAL_MAX_STREAM_DATA_UNI,
+                self.initial_max_stream_data_uni,
+                0,
+            ),
+            (INITIAL_MAX_STREAMS_BIDI, self.initial_max_streams_bidi, 0),
+            (INITIAL_MAX_STREAMS_UNI, self.initial_max_streams_uni, 0),
+            (
+                ACK_DELAY_EXPONENT,
+                self.ack_delay_exponent,
+                defaults.ack_delay_exponent,
+            ),
+            (MAX_ACK_DELAY, self.max_ack_delay, defaults.max_ack_delay),
+            (
+                ACTIVE_CONNECTION_ID_LIMIT,
+                self.active_connection_id_limit,
+                defaults.active_connection_id_limit,
+            ),
+        ] {
+            if value != default {
+                put_int_param(&mut out, id, value);
+            }
+        }
+        if self.disable_active_migration {
+            put_param(&mut out, DISABLE_ACTIVE_MIGRATION, &[]);
+        }
+        if let Some(cid) = &self.initial_scid {
+            put_param(&mut out, INITIAL_SCID, cid.as_slice());
+        }
+        if let Some(cid) = &self.retry_scid {
+            put_param(&mut out, RETRY_SCID, cid.as_slice());
+        }
+        out
+    }
+
+    /// Decodes the parameters sent by the server if `from_server`, or else by
+    /// the client.
+    pub fn decode(buf: &[u8], from_server: bool) -> Result<Self, TransportError> {
+        let bad = |reason| TransportError::new(code::TRANSPORT_PARAMETER_ERROR, reason);
+        let mut params = Self::default();
+        let mut seen = Vec::new();
+        let mut r = Reader::new(buf);
+        while !r.is_empty() {
+            let id = r.varint().ok_or(bad("truncated parameter"))?;
+            let value = r.varint_vec().ok_or(bad("truncated parameter"))?;
+            if seen.contains(&id) {
+                return Err(bad("duplicate parameter"));
+            }
+            seen.push(id);
+            let int = || {
+                let mut r = Reader::new(value);
+                r.varint()
+                    .filter(|_| r.is_empty())
+                    .ok_or(bad("bad integer"))
+            };
+            let cid = || ConnectionId::new(value).ok_or(bad("bad connection ID"));
+            match id {
+                ORIGINAL_DCID | STATELESS_RESET_TOKEN | PREFERRED_ADDRESS | RETRY_SCID
+                    if !from_server =>
+                {
+                    return Err(bad("server parameter sent by the client"));
+                }
+                ORIGINAL_DCID => params.original_dcid = Some(cid()?),
+                MAX_IDLE_TIMEOUT => params.max_idle_timeout = int()?,
+                STATELESS_RESET_TOKEN if value.len() != 16 => {
+                    return Err(bad("bad stateless reset token"));
+                }
+                MAX_UDP_PAYLOAD_SIZE => params.max_udp_payload_size = int()?,
+                INITIAL_MAX_DATA => params.initial_max_data = int()?,
+                INITIAL_MAX_STREAM_DATA_BIDI_LOCAL => {
+                    params.initial_max_stream_data_bidi_local = int()?
+                }
+                INITIAL_MAX_STREAM_DATA_BIDI_REMOTE => {
+                    params.initial_max_stream_data_bidi_remote = int()?
+                }
+                INITIAL_MAX_STREAM_DATA_UNI => params.initial_max_stream_data_uni = int()?,
+                INITIAL_MAX_STREAMS_BIDI => params.initial_max_streams_bidi = int()?,
+                INITIAL_MAX_STREAMS_UNI => params.initial_max_streams_uni = int()?,
+                ACK_DELAY_EXPONENT => params.ack_delay_exponent = int()?,
+                MAX_ACK_DELAY => params.max_ack_delay = int()?,
+                DISABLE_ACTIVE_MIGRATION if !value.is_empty() => {
+                    return Err(bad("bad disable_active_migration"));
+                }
+                DISABLE_ACTIVE_MIGRATION => params.disable_active_migration = true,
+                ACTIVE_CONNECTION_ID_LIMIT => params.active_connection_id_limit = int()?,
+                INITIAL_SCID => params.initial_scid = Some(cid()?),
+                RETRY_SCID => params.retry_scid = Some(cid()?),
+                // unknown ones, the stateless reset token and the preferred
+                // address are ignored
+                _ => {}
+            }
+        }
+        if params.max_udp_payload_size < 1200
+            || params.ack_delay_exponent > 20
+            || params.max_ack_delay >= 1 << 14
+            || params.active_connection_id_limit < 2
+            || params.initial_max_streams_bidi > MAX_STREAMS
+            || params.initial_max_streams_uni > MAX_STREAMS
+        {
+            return Err(bad("parameter out of range"));
+        }
+        if params.initial_scid.is_none() {
+            return Err(bad("missing initial_source_connection_id"));
+        }
+        if from_server && params.original_dcid.is_none() {
+            return Err(bad("missing original_destination_connection_id"));
+        }
+        Ok(params)
+    }
+}
@@ -0,0 +1,77 @@
+//! Sets of integer ranges: the packet numbers received, and the parts of the
+//! streams received or acknowledged.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// A set of `u64`, as disjoint ranges kept sorted and merged.
+#[derive(Debug, Default, Clone)]
+pub struct RangeSet(Vec<Range<u64>>);
+
+impl RangeSet {
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the number of ranges.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn first(&self) -> Option<&Range<u64>> {
+        self.0.first()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Range<u64>> {
+        self.0.iter()
+    }
+
+    pub fn contains(&self, v: u64) -> bool {
+        let i = self.0.partition_point(|r| r.end <= v);
+        self.0.get(i).is_some_and(|r| r.start <= v)
+    }
+
+    /// Adds `range`, and returns whether it was not already all in the set.
+    pub fn insert(&mut self, mut range: Range<u64>) -> bool {
+        if range.is_empty() {
+            return false;
+        }
+        // the ranges from i to j overlap or touch it
+        let i = self.0.partition_point(|r| r.end < range.start);
+        let mut j = i;
+        while j < self.0.len() && self.0[j].start <= range.end {
+            if self.0[j].start <= range.start && range.end <= self.0[j].end {
+                return false;
+            }
+            range.start = range.start.min(self.0[j].start);
+            range.end = range.end.max(self.0[j].end);
+            j += 1;
+        }
+        self.0.splice(i..j, [range]);
+        true
+    }
+
+    pub fn remove(&mut self, range: Range<u64>) {
+        if range.is_empty() {
+            return;
+        }
+        let i = self.0.partition_point(|r| r.end <= range.start);
+        let j = self.0.partition_point(|r| r.start < range.end);
+        if i >= j {
+            return;
+        }
+        let left = self.0[i].start..range.start;
+        let right = range.end..self.0[j - 1].end;
+        self.0
+            .splice(i..j, [left, right].into_iter().filter(|r| !r.is_empty()));
+    }
+
+    /// Removes the values below `v`.
+    pub fn remove_below(&mut self, v: u64) {
+        self.remove(0..v);
+    }
+}
@@ -0,0 +1,364 @@
+//! Loss detection and congestion control (RFC 9002), with NewReno.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::time::Duration;
+
+use crate::packet::Space;
+
+const PACKET_THRESHOLD: u64 = 3;
+const GRANULARITY: Duration = Duration::from_millis(1);
+
+/// What a frame sent carried, to handle its acknowledgment or its loss.
+#[derive(Debug, Clone)]
+pub enum SentFrame {
+    /// The largest packet number acknowledged by an ACK frame.
+    Ack(u64),
+    Crypto {
+        offset: u64,
+        len: usize,
+    },
+    Stream {
+        id: u64,
+        offset: u64,
+        len: usize,
+        fin: bool,
+    },
+    ResetStream(u64),
+    StopSending(u64),
+    MaxData,
+    MaxStreamData(u64),
+    MaxStreams {
+        bidi: bool,
+    },
+    RetireConnectionId(u64),
+    HandshakeDone,
+}
+
+/// A packet sent, until it is acknowledged or lost.
+pub struct SentPacket {
+    pub time: Duration,
+    pub size: usize,
+    pub ack_eliciting: bool,
+    /// Whether it counts for the congestion control: not only ACK frames.
+    pub in_flight: bool,
+    pub frames: Vec<SentFrame>,
+}
+
+#[derive(Default)]
+struct SpaceState {
+    sent: BTreeMap<u64, SentPacket>,
+    largest_acked: Option<u64>,
+    loss_time: Option<Duration>,
+    last_ack_eliciting: Option<Duration>,
+}
+
+impl SpaceState {
+    fn has_ack_eliciting(&self) -> bool {
+        self.sent.values().any(|p| p.ack_eliciting)
+    }
+}
+
+/// What to do when the loss detection timer fires.
+pub enum Timeout {
+    /// The packets are lost.
+    Lost(Space, Vec<SentPacket>),
+    /// The probe timeout: send one or two ack-eliciting packets in the space.
+    Probe(Space),
+}
+
+/// The packets in flight, the RTT estimate, and the congestion window.
+pub struct Recovery {
+    spaces: [SpaceState; 3],
+    latest_rtt: Duration,
+    smoothed_rtt: Duration,
+    rttvar: Duration,
+    min_rtt: Duration,
+    has_rtt_sample: bool,
+    /// The largest delay of the acknowledgments of the peer.
+    pub max_ack_delay: Duration,
+    pto_count: u32,
+    bytes_in_flight: usize,
+    cwnd: usize,
+    ssthresh: usize,
+    recovery_start: Option<Duration>,
+    max_datagram_size: usize,
+}
+
+impl Recovery {
+    pub fn new(initial_rtt: Duration, max_datagram_size: usize) -> Self {
+        Self {
+            spaces: Default::default(),
+            latest_rtt: Duration::ZERO,
+            smoothed_rtt: initial_rtt,
+            rttvar: initial_rtt / 2,
+            min_rtt: Duration::ZERO,
+            has_rtt_sample: false,
+            max_ack_delay: Duration::from_millis(25),
+            pto_count: 0,
+            bytes_in_flight: 0,
+            cwnd: (10 * max_datagram_size).min((2 * max_datagram_size).max(14720)),
+            ssthresh: usize::MAX,
+            recovery_start: None,
+            max_datagram_size,
+        }
+    }
+
+    pub fn smoothed_rtt(&self) -> Duration {
+        self.smoothed_rtt
+    }
+
+    /// Returns how many bytes the congestion window lets be sent.
+    pub fn window_available(&self) -> usize {
+        self.cwnd.saturating_sub(self.bytes_in_flight)
+    }
+
+    pub fn largest_acked(&self, space: Space) -> Option<u64> {
+        self.spaces[space as usize].largest_acked
+    }
+
+    /// Returns the probe timeout, without the backoff.
+    pub fn pto(&self) -> Duration {
+        self.smoothed_rtt + (4 * self.rttvar).max(GRANULARITY) + self.max_ack_delay
+    }
+
+    pub fn on_packet_sent(&mut self, space: Space, pn: u64, packet: SentPacket) {
+        let state = &mut self.spaces[space as usize];
+        if packet.in_flight {
+            if packet.ack_eliciting {
+                state.last_ack_eliciting = Some(packet.time);
+            }
+            self.bytes_in_flight += packet.size;
+        }
+        state.sent.insert(pn, packet);
+    }
+
+    /// Handles the acknowledgment of the packet numbers `ranges` (from the
+    /// largest), and returns the packets acknowledged and the packets lost.
+    pub fn on_ack_received(
+        &mut self,
+        space: Space,
+        ranges: &[Range<u64>],
+        ack_delay: Duration,
+        now: Duration,
+        handshake_confirmed: bool,
+    ) -> (Vec<SentPacket>, Vec<SentPacket>) {
+        let largest = ranges[0].end - 1;
+        let state = &mut self.spaces[space as usize];
+        state.largest_acked = Some(state.largest_acked.map_or(largest, |l| l.max(largest)));
+
+        let mut acked = Vec::new();
+        let mut largest_newly_acked = None;
+        for range in ranges {
+            let pns: Vec<u64> = state.sent.range(range.clone()).map(|(pn, _)| *pn).collect();
+            for pn in pns {
+                largest_newly_acked = largest_newly_acked.max(Some(pn));
+                acked.push((pn, state.sent.remove(&pn).unwrap()));
+            }
+        }
+        if acked.is_empty() {
+            return (Vec::new(), Vec::new());
+        }
+
+        if largest_newly_acked == Some(largest) && acked.iter().any(|(_, p)| p.ack_eliciting) {
+            let sent = acked.iter().find(|(pn, _)| *pn == largest).unwrap().1.time;
+            let ack_delay = if space == Space::Data {
+                if handshake_confirmed {
+                    ack_delay.min(self.max_ack_delay)
+                } else {
+                    ack_delay
+                }
+            } else {
+                Duration::ZERO
+            };
+            self.update_rtt(now.saturating_sub(sent), ack_delay);
+        }
+
+        let lost = self.detect_lost(space, now);
+        self.on_packets_lost(&lost, now);
+        for (_, packet) in &acked {
+            self.on_packet_acked(packet);
+        }
+        self.pto_count = 0;
+        (acked.into_iter().map(|(_, p)| p).collect(), lost)
+    }
+
+    fn update_rtt(&mut self, latest_rtt: Duration, ack_delay: Duration) {
+        self.latest_rtt = latest_rtt;
+        if !self.has_rtt_sample {
+            self.has_rtt_sample = true;
+            self.min_rtt = latest_rtt;
+            self.smoothed_rtt = latest_rtt;
+            self.rttvar = latest_rtt / 2;
+            return;
+        }
+        self.min_rtt = self.min_rtt.min(latest_rtt);
+        let adjusted = if latest_rtt >= self.min_rtt + ack_delay {
+            latest_rtt - ack_delay
+        } else {
+            latest_rtt
+        };
+        let diff = self.smoothed_rtt.max(adjusted) - self.smoothed_rtt.min(adjusted);
+        self.rttvar = (3 * self.rttvar + diff) / 4;
+        self.smoothed_rtt = (7 * self.smoothed_rtt + adjusted) / 8;
+    }
+
+    /// Takes out the packets lost: sent long enough before one acknowledged,
+    /// or a few packets before.
+    fn detect_lost(&mut self, space: Space, now: Duration) -> Vec<SentPacket> {
+        let loss_delay = (self.latest_rtt.max(self.smoothed_rtt) * 9 / 8).max(GRANULARITY);
+        let state = &mut self.spaces[space as usize];
+        state.loss_time = None;
+        let Some(largest_acked) = state.largest_acked else {
+            return Vec::new();
+        };
+        let mut lost_pns = Vec::new();
+        for (pn, packet) in state.sent.range(..largest_acked) {
+            if packet.time + loss_delay <= now || largest_acked >= pn + PACKET_THRESHOLD {
+                lost_pns.push(*pn);
+            } else {
+                let time = packet.time + loss_delay;
+                state.loss_time = Some(state.loss_time.map_or(time, |t| t.min(time)));
+            }
+        }
+        lost_pns
+            .into_iter()
+            .map(|pn| state.sent.remove(&pn).unwrap())
+            .collect()
+    }
+
+    fn on_packet_acked(&mut self, packet: &SentPacket) {
+        if !packet.in_flight {
+            return;
+        }
+        self.bytes_in_flight -= packet.size;
+        if self
+            .recovery_start
+            .is_some_and(|start| packet.time <= start)
+        {
+            return;
+        }
+        if self.cwnd < self.ssthresh {
+            self.cwnd += packet.size;
+        } else {
+            self.cwnd += self.max_datagram_size * packet.size / self.cwnd;
+        }
+    }
+
+    fn on_packets_lost(&mut self, lost: &[SentPacket], now: Duration) {
+        let mut last_sent = None;
+        for packet in lost.iter().filter(|p| p.in_flight) {
+            self.bytes_in_flight -= packet.size;
+            last_sent = last_sent.max(Some(packet.time));
+        }
+        // one reduction per round trip
+        if let Some(sent) = last_sent {
+            if !matches!(self.recovery_start, Some(start) if sent <= start) {
+                self.recovery_start = Some(now);
+                self.ssthresh = self.cwnd / 2;
+                self.cwnd = self.ssthresh.max(2 * self.max_datagram_size);
+            }
+        }
+    }
+
+    /// Returns when the loss detection timer fires.
+    ///
+    /// `idle_probe` is the space to probe even with nothing in flight, for
+    /// the client until the server validated its address.
+    pub fn loss_timer(
+        &self,
+        handshake_confirmed: bool,
+        idle_probe: Option<Space>,
+    ) -> Option<Duration> {
+        if let Some(time) = self.spaces.iter().filter_map(|s| s.loss_time).min() {
+            return Some(time);
+        }
+        self.pto_time(handshake_confirmed, idle_probe)
+            .map(|(time, _)| time)
+    }
+
+    fn pto_time(
+        &self,
+        handshake_confirmed: bool,
+        idle_probe: Option<Space>,
+    ) -> Option<(Duration, Space)> {
+        let backoff = 1 << self.pto_count.min(16);
+        let duration = (self.smoothed_rtt + (4 * self.rttvar).max(GRANULARITY)) * backoff;
+        if !self.spaces.iter().any(SpaceState::has_ack_eliciting) {
+            // there is nothing to time from, so it counts from the last
+            // one sent
+            let space = idle_probe?;
+            let last = self
+                .spaces
+                .iter()
+                .filter_map(|s| s.last_ack_eliciting)
+                .max();
+            return Some((last.unwrap_or_default() + duration, space));
+        }
+        let mut timeout: Option<(Duration, Space)> = None;
+        for space in Space::ALL {
+            let state = &self.spaces[space as usize];
+            if !state.has_ack_eliciting() {
+                continue;
+            }
+            let mut duration = duration;
+            if space == Space::Data {
+                if !handshake_confirmed {
+                    break;
+                }
+                duration += self.max_ack_delay * backoff;
+            }
+            let time = state.last_ack_eliciting.unwrap() + duration;
+            if !matches!(timeout, Some((t, _)) if t <= time) {
+                timeout = Some((time, space));
+            }
+        }
+        timeout
+    }
+
+    /// Handles the expiry of the loss detection timer.
+    pub fn on_timeout(
+        &mut self,
+        now: Duration,
+        handshake_confirmed: bool,
+        idle_probe: Option<Space>,
+    ) -> Option<Timeout> {
+        let earliest = Space::ALL
+            .into_iter()
+            .filter_map(|space| Some((self.spaces[space as usize].loss_time?, space)))
+            .min_by_key(|(time, _)| *time);
+        if let Some((_, space)) = earliest {
+            let lost = self.detect_lost(space, now);
+            self.on_packets_lost(&lost, now);
+            return Some(Timeout::Lost(space, lost));
+        }
+        let (_, space) = self.pto_time(handshake_confirmed, idle_probe)?;
+        self.pto_count += 1;
+        Some(Timeout::Probe(space))
+    }
+
+    /// Returns the frames of the oldest packets in flight in `space`, to send
+    /// again in the probes.
+    pub fn probe_frames(&self, space: Space) -> Vec<SentFrame> {
+        self.spaces[space as usize]
+            .sent
+            .values()
+            .filter(|p| p.ack_eliciting)
+            .take(2)
+            .flat_map(|p| p.frames.iter().cloned())
+            .collect()
+    }
+
+    /// Forgets the packets of `space`, e.g. when its keys are discarded, and
+    /// returns them.
+    pub fn discard(&mut self, space: Space) -> Vec<SentPacket> {
+        let state = core::mem::take(&mut self.spaces[space as usize]);
+        let packets: Vec<SentPacket> = state.sent.into_values().collect();
+        for packet in packets.iter().filter(|p| p.in_flight) {
+            self.bytes_in_flight -= packet.size;
+        }
+        self.pto_count = 0;
+        packets
+    }
+}
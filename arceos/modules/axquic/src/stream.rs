@@ -0,0 +1,370 @@
+//! The send and receive halves of the streams, also used for the data of the
+//! TLS handshake at each encryption level.
+
+use alloc::vec::Vec;
+
+use crate::ranges::RangeSet;
+use crate::{code, TransportError};
+
+/// Where a chunk of the data of a send stream goes in a frame.
+pub struct Chunk {
+    pub offset: u64,
+    pub len: usize,
+    pub fin: bool,
+    /// How many bytes are sent for the first time, which the flow control of
+    /// the connection counts.
+    pub new_bytes: u64,
+}
+
+/// The sending half of a stream.
+pub struct SendStream {
+    /// The offset of the first byte of `buf`, below which all is
+    /// acknowledged.
+    base: u64,
+    buf: Vec<u8>,
+    /// The offset up to which the data was sent once.
+    sent: u64,
+    /// The ranges sent and lost, to send again.
+    lost: RangeSet,
+    acked: RangeSet,
+    /// The limit of the flow control set by the peer.
+    pub max_data: u64,
+    finished: bool,
+    fin_pending: bool,
+    fin_acked: bool,
+    /// The code of the reset, until it is acknowledged.
+    pub reset: Option<u64>,
+    pub reset_pending: bool,
+    reset_acked: bool,
+}
+
+impl SendStream {
+    pub fn new(max_data: u64) -> Self {
+        Self {
+            base: 0,
+            buf: Vec::new(),
+            sent: 0,
+            lost: RangeSet::new(),
+            acked: RangeSet::new(),
+            max_data,
+            finished: false,
+            fin_pending: false,
+            fin_acked: false,
+            reset: None,
+            reset_pending: false,
+            reset_acked: false,
+        }
+    }
+
+    /// The offset after the data written.
+    pub fn offset(&self) -> u64 {
+        self.base + self.buf.len() as u64
+    }
+
+    /// The number of bytes written and not yet acknowledged.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished || self.reset.is_some()
+    }
+
+    /// Appends `data` to the stream.
+    pub fn write(&mut self, data: &[u8]) {
+        debug_assert!(!self.is_finished());
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Ends the stream after the data written.
+    pub fn finish(&mut self) {
+        self.finished = true;
+        self.fin_pending = true;
+    }
+
+    /// Abandons the data written, with the application error `code`.
+    pub fn reset(&mut self, code: u64) {
+        if self.reset.is_none() && !self.fin_acked {
+            self.reset = Some(code);
+            self.reset_pending = true;
+            self.lost = RangeSet::new();
+            self.fin_pending = false;
+        }
+    }
+
+    /// The size of the stream for the peer, once reset.
+    pub fn final_size(&self) -> u64 {
+        self.sent
+    }
+
+    /// Returns whether there is data to send, when `credit` bytes more are
+    /// allowed by the flow control of the connection.
+    pub fn has_data(&self, credit: u64) -> bool {
+        if self.reset.is_some() {
+            return false;
+        }
+        !self.lost.is_empty()
+            || self.fin_pending
+            || (self.sent < self.offset() && self.sent < self.max_data && credit > 0)
+    }
+
+    /// Takes the next chunk to send, of up to `max_len` bytes, with at most
+    /// `credit` bytes sent for the first time.
+    ///
+    /// The data lost is sent again first.
+    pub fn next_chunk(&mut self, max_len: usize, credit: u64) -> Option<Chunk> {
+        if self.reset.is_some() {
+            return None;
+        }
+        let end = self.offset();
+        let mut chunk = if let Some(range) = self.lost.first() {
+            let len = (range.end - range.start).min(max_len as u64);
+            let range = range.start..range.start + len;
+            self.lost.remove(range.clone());
+            Chunk {
+                offset: range.start,
+                len: len as usize,
+                fin: false,
+                new_bytes: 0,
+            }
+        } else {
+            let len = (end - self.sent)
+                .min(max_len as u64)
+                .min(self.max_data.saturating_sub(self.sent))
+                .min(credit);
+            if len == 0 && !(self.fin_pending && self.sent == end) {
+                return None;
+            }
+            let chunk = Chunk {
+                offset: self.sent,
+                len: len as usize,
+                fin: false,
+                new_bytes: len,
+            };
+            self.sent += len;
+            chunk
+        };
+        if self.fin_pending && chunk.offset + chunk.len as u64 == end && self.sent == end {
+            chunk.fin = true;
+            self.fin_pending = false;
+        }
+        Some(chunk)
+    }
+
+    /// Returns the data of a chunk taken by [`next_chunk`](Self::next_chunk).
+    pub fn data(&self, chunk: &Chunk) -> &[u8] {
+        // the data sent again may have been acknowledged meanwhile
+        let start = chunk.offset.max(self.base);
+        let end = (chunk.offset + chunk.len as u64).max(start);
+        &self.buf[(start - self.base) as usize..(end - self.base) as usize]
+    }
+
+    pub fn on_ack(&mut self, offset: u64, len: usize, fin: bool) {
+        let range = offset..offset + len as u64;
+        self.lost.remove(range.clone());
+        self.acked.insert(range);
+        self.fin_acked |= fin;
+        if let Some(first) = self.acked.first() {
+            if first.start <= self.base && first.end > self.base {
+                let acked = (first.end - self.base) as usize;
+                self.buf.drain(..acked);
+                self.base = first.end;
+            }
+        }
+        self.acked.remove_below(self.base);
+    }
+
+    pub fn on_lost(&mut self, offset: u64, len: usize, fin: bool) {
+        if self.reset.is_some() {
+            return;
+        }
+        let start = offset.max(self.base);
+        let end = offset + len as u64;
+        if start < end {
+            self.lost.insert(start..end);
+            for acked in self.acked.iter() {
+                self.lost.remove(acked.clone());
+            }
+        }
+        if fin && !self.fin_acked {
+            self.fin_pending = true;
+        }
+    }
+
+    /// Requeues all the data sent and not acknowledged, e.g. when the packets
+    /// of 0-RTT are rejected.
+    pub fn requeue_all(&mut self) {
+        let fin_sent = self.finished && !self.fin_pending;
+        self.on_lost(self.base, (self.sent - self.base) as usize, fin_sent);
+    }
+
+    pub fn on_reset_acked(&mut self) {
+        self.reset_acked = true;
+    }
+
+    pub fn on_reset_lost(&mut self) {
+        if !self.reset_acked {
+            self.reset_pending = true;
+        }
+    }
+
+    /// Returns whether the peer got everything of the stream.
+    pub fn is_done(&self) -> bool {
+        if self.reset.is_some() {
+            self.reset_acked
+        } else {
+            self.fin_acked && self.buf.is_empty()
+        }
+    }
+}
+
+/// The receiving half of a stream.
+pub struct RecvStream {
+    /// The offset of the first byte of `buf`, up to which the data was read.
+    read: u64,
+    buf: Vec<u8>,
+    /// The ranges received from `read`.
+    received: RangeSet,
+    /// The offset after the data received.
+    pub max_received: u64,
+    pub final_size: Option<u64>,
+    /// The limit of the flow control of the stream, as sent to the peer.
+    pub max_data: u64,
+    window: u64,
+    /// The code of the reset of the peer.
+    pub reset: Option<u64>,
+    /// The code of a STOP_SENDING frame to send, until the stream ends.
+    pub stop_sending: Option<u64>,
+    pub stop_sending_pending: bool,
+    pub max_data_pending: bool,
+    /// Whether the application saw the end of the stream, or its reset.
+    pub done: bool,
+}
+
+impl RecvStream {
+    pub fn new(window: u64) -> Self {
+        Self {
+            read: 0,
+            buf: Vec::new(),
+            received: RangeSet::new(),
+            max_received: 0,
+            final_size: None,
+            max_data: window,
+            window,
+            reset: None,
+            stop_sending: None,
+            stop_sending_pending: false,
+            max_data_pending: false,
+            done: false,
+        }
+    }
+
+    pub fn read_offset(&self) -> u64 {
+        self.read
+    }
+
+    /// Checks the final size given by the peer, and sets it. Returns how
+    /// much further than before the stream goes.
+    fn set_final_size(&mut self, size: u64) -> Result<u64, TransportError> {
+        let bad = TransportError::new(code::FINAL_SIZE_ERROR, "final size changed");
+        match self.final_size {
+            Some(final_size) if final_size != size => return Err(bad),
+            _ if size < self.max_received => return Err(bad),
+            _ => {}
+        }
+        self.final_size = Some(size);
+        Ok(self.extend_to(size))
+    }
+
+    fn extend_to(&mut self, end: u64) -> u64 {
+        let more = end.saturating_sub(self.max_received);
+        self.max_received += more;
+        more
+    }
+
+    /// Stores `data` received at `offset`, and returns how much further than
+    /// before the stream goes, for the flow control of the connection.
+    pub fn receive(&mut self, offset: u64, data: &[u8], fin: bool) -> Result<u64, TransportError> {
+        let end = offset + data.len() as u64;
+        if end > self.max_data {
+            return Err(TransportError::new(
+                code::FLOW_CONTROL_ERROR,
+                "stream data limit exceeded",
+            ));
+        }
+        let more = if fin {
+            self.set_final_size(end)?
+        } else if self.final_size.is_some_and(|size| end > size) {
+            return Err(TransportError::new(
+                code::FINAL_SIZE_ERROR,
+                "data beyond the final size",
+            ));
+        } else {
+            self.extend_to(end)
+        };
+        if self.reset.is_some() || end <= self.read {
+            return Ok(more);
+        }
+        let start = offset.max(self.read);
+        let skip = (start - offset) as usize;
+        let pos = (start - self.read) as usize;
+        let len = (end - self.read) as usize;
+        if self.buf.len() < len {
+            self.buf.resize(len, 0);
+        }
+        self.buf[pos..len].copy_from_slice(&data[skip..]);
+        self.received.insert(start..end);
+        Ok(more)
+    }
+
+    /// Handles a reset of the peer, and returns how much further than before
+    /// the stream goes.
+    pub fn on_reset(&mut self, code: u64, final_size: u64) -> Result<u64, TransportError> {
+        if final_size > self.max_data {
+            return Err(TransportError::new(
+                code::FLOW_CONTROL_ERROR,
+                "stream data limit exceeded",
+            ));
+        }
+        let more = self.set_final_size(final_size)?;
+        if self.reset.is_none() {
+            self.reset = Some(code);
+            self.buf = Vec::new();
+            self.received = RangeSet::new();
+        }
+        Ok(more)
+    }
+
+    /// Returns the number of bytes readable at once.
+    pub fn readable(&self) -> usize {
+        match self.received.first() {
+            Some(first) if first.start == self.read => (first.end - first.start) as usize,
+            _ => 0,
+        }
+    }
+
+    /// Reads the data received in order into `out`, and returns its length.
+    pub fn read(&mut self, out: &mut [u8]) -> usize {
+        let len = self.readable().min(out.len());
+        out[..len].copy_from_slice(&self.buf[..len]);
+        self.buf.drain(..len);
+        self.read += len as u64;
+        self.received.remove_below(self.read);
+        if self.final_size.is_none() && self.max_data - self.read < self.window / 2 {
+            self.max_data = self.read + self.window;
+            self.max_data_pending = true;
+        }
+        len
+    }
+
+    /// Returns whether all the data was read, to the final size.
+    pub fn is_finished(&self) -> bool {
+        self.final_size == Some(self.read)
+    }
+
+    /// Returns whether a read would not block: there is data, the end of
+    /// the stream, or a reset.
+    pub fn is_readable(&self) -> bool {
+        self.readable() > 0 || self.is_finished() || self.reset.is_some()
+    }
+}
@@ -0,0 +1,566 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use std::sync::Arc;
+
+use axcrypto::ed25519_public_key;
+use axerrno::AxError;
+
+use crate::codec::Reader;
+use crate::frame::{self, Frame};
+use crate::packet::{self, ConnectionId, Keys};
+use crate::params::TransportParams;
+use crate::ranges::RangeSet;
+use crate::{cert, ClientConfig, Connection, ConnectionError, ServerConfig, StreamId};
+
+const SECRET_KEY: [u8; 32] = [7; 32];
+const ALPN: &[u8] = b"telemetry";
+const MS: Duration = Duration::from_millis(1);
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn random(buf: &mut [u8]) {
+    static STATE: AtomicU64 = AtomicU64::new(0x2545_f491_4f6c_dd1d);
+    for b in buf {
+        let mut x = STATE.load(Ordering::Relaxed);
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        STATE.store(x, Ordering::Relaxed);
+        *b = x as u8;
+    }
+}
+
+fn client_config() -> ClientConfig {
+    ClientConfig::new(ALPN, ed25519_public_key(&SECRET_KEY), random)
+}
+
+fn server_config() -> ServerConfig {
+    ServerConfig::new(ALPN, SECRET_KEY, "telemetry.local", random)
+}
+
+/// A client and a server, and the datagrams between them.
+struct Link {
+    client: Connection,
+    server: Option<Connection>,
+    config: Arc<ServerConfig>,
+    now: Duration,
+    /// The datagrams sent, for which `lose` tells if they are lost.
+    sent: usize,
+    lose: fn(usize) -> bool,
+}
+
+impl Link {
+    fn new(client: ClientConfig, server: ServerConfig) -> Self {
+        let client = Connection::connect(Arc::new(client), None, Duration::ZERO);
+        Self::with_client(client, Arc::new(server))
+    }
+
+    fn with_client(client: Connection, config: Arc<ServerConfig>) -> Self {
+        Self {
+            client,
+            server: None,
+            config,
+            now: Duration::ZERO,
+            sent: 0,
+            lose: |_| false,
+        }
+    }
+
+    fn server(&mut self) -> &mut Connection {
+        self.server.as_mut().unwrap()
+    }
+
+    /// Delivers the datagrams both ways until there are none, 1 ms apart,
+    /// and returns whether there were any.
+    fn flush(&mut self) -> bool {
+        let mut buf = [0; 1500];
+        let mut any = false;
+        loop {
+            let mut moved = false;
+            while let Some(len) = self.client.poll_transmit(self.now, &mut buf) {
+                moved = true;
+                self.sent += 1;
+                if (self.lose)(self.sent) {
+                    continue;
+                }
+                match &mut self.server {
+                    Some(server) => server.handle_datagram(&buf[..len], self.now),
+                    None => {
+                        self.server = Connection::accept(self.config.clone(), &buf[..len], self.now)
+                    }
+                }
+            }
+            if let Some(server) = &mut self.server {
+                while let Some(len) = server.poll_transmit(self.now, &mut buf) {
+                    moved = true;
+                    self.sent += 1;
+                    if !(self.lose)(self.sent) {
+                        self.client.handle_datagram(&buf[..len], self.now);
+                    }
+                }
+            }
+            if !moved {
+                return any;
+            }
+            any = true;
+            self.now += MS;
+        }
+    }
+
+    /// Runs the connections until `done`, firing their timers.
+    fn run(&mut self, done: impl Fn(&mut Self) -> bool) {
+        for _ in 0..10000 {
+            self.flush();
+            if done(self) {
+                return;
+            }
+            self.tick();
+        }
+        panic!("not done at {:?}", self.now);
+    }
+
+    /// Moves on to the next timer, and fires it.
+    fn tick(&mut self) {
+        let timeouts = [
+            self.client.timeout(),
+            self.server.as_ref().and_then(Connection::timeout),
+        ];
+        let next = timeouts.into_iter().flatten().min().expect("stuck");
+        self.now = self.now.max(next);
+        self.client.handle_timeout(self.now);
+        if let Some(server) = &mut self.server {
+            server.handle_timeout(self.now);
+        }
+    }
+
+    fn established(&mut self) {
+        self.run(|l| l.client.is_established() && l.server().is_established());
+    }
+}
+
+/// Sends `data` on `id` from `from`, which `to` reads to the end.
+fn transfer(link: &mut Link, id: StreamId, data: &[u8], client_sends: bool) -> Vec<u8> {
+    let mut received = Vec::new();
+    let mut written = 0;
+    let mut finished = false;
+    let mut buf = [0; 4096];
+    for _ in 0..10000 {
+        let progress = (written, received.len());
+        let (from, to) = match client_sends {
+            true => (&mut link.client, link.server.as_mut().unwrap()),
+            false => (link.server.as_mut().unwrap(), &mut link.client),
+        };
+        while written < data.len() {
+            match from.stream_send(id, &data[written..]) {
+                Ok(n) => written += n,
+                Err(AxError::WouldBlock) => break,
+                Err(e) => panic!("{:?}", e),
+            }
+        }
+        if written == data.len() && !finished {
+            from.stream_finish(id).unwrap();
+            finished = true;
+        }
+        loop {
+            match to.stream_recv(id, &mut buf) {
+                Ok(0) => return received,
+                Ok(n) => received.extend_from_slice(&buf[..n]),
+                Err(AxError::WouldBlock) => break,
+                // not opened yet
+                Err(AxError::BadState) if received.is_empty() => break,
+                Err(e) => panic!("{:?}", e),
+            }
+        }
+        if !link.flush() && progress == (written, received.len()) {
+            link.tick();
+        }
+    }
+    panic!("{} of {} bytes received", received.len(), data.len());
+}
+
+fn test_data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 + i / 256) as u8).collect()
+}
+
+#[test]
+fn test_initial_keys() {
+    // RFC 9001 appendix A
+    let dcid = hex("8394c8f03e515708");
+    let (client, server) = packet::initial_secrets(&dcid);
+    assert_eq!(
+        client[..],
+        hex("c00cf151ca5be075ed0ebfb5c80323c42d6b7db67881289af4008f1f6c357aea")
+    );
+    assert_eq!(
+        server[..],
+        hex("3c199828fd139efd216c155ad844cc81fb82fa8d7446fa7d78be803acdda951b")
+    );
+
+    let payload = hex(
+        "02000000000600405a020000560303eefce7f7b37ba1d1632e96677825ddf73988cf\
+         c79825df566dc5430b9a045a1200130100002e00330024001d00209d3c940d89690b\
+         84d08a60993c144eca684d1081287c834d5311bcf32bb9da1a002b00020304",
+    );
+    let protected = hex(
+        "cf000000010008f067a5502a4262b5004075c0d95a482cd0991cd25b0aac406a5816\
+         b6394100f37a1c69797554780bb38cc5a99f5ede4cf73c3ec2493a1839b3dbcba3f6\
+         ea46c5b7684df3548e7ddeb9c3bf9c73cc3f3bded74b562bfb19fb84022f8ef4cdd9\
+         3795d77d06edbb7aaf2f58891850abbdca3d20398c276456cbc42158407dd074ee",
+    );
+    let keys = Keys::new(&server);
+    let mut packet = hex("c1000000010008f067a5502a4262b50040750001");
+    packet.extend_from_slice(&payload);
+    packet.resize(packet.len() + 16, 0);
+    keys.seal(1, &mut packet, 20);
+    assert_eq!(packet, protected);
+
+    assert_eq!(keys.unprotect_header(&mut packet, 18), Some((1, 2)));
+    assert_eq!(keys.open(1, &mut packet, 20), Some(&payload[..]));
+}
+
+#[test]
+fn test_retry_tag() {
+    // RFC 9001 appendix A.4
+    let retry = hex("ff000000010008f067a5502a4262b5746f6b656e");
+    let tag = packet::retry_tag(&hex("8394c8f03e515708"), &retry);
+    assert_eq!(tag[..], hex("04a265ba2eff4d829058fb3f0f2496ba"));
+}
+
+#[test]
+fn test_packet_numbers() {
+    // RFC 9000 appendix A.2 and A.3
+    assert_eq!(packet::pn_len(0xac5c02, Some(0xabe8b3)), 2);
+    assert_eq!(packet::pn_len(0xace8fe, Some(0xabe8b3)), 3);
+    assert_eq!(packet::decode_pn(Some(0xa82f30ea), 0x9b32, 2), 0xa82f9b32);
+    assert_eq!(packet::decode_pn(None, 0, 1), 0);
+}
+
+#[test]
+fn test_frames() {
+    let mut received = RangeSet::new();
+    received.insert(0..3);
+    received.insert(5..6);
+    received.insert(8..10);
+    let mut buf = Vec::new();
+    frame::put_ack(&mut buf, &received, 7);
+    frame::put_stream(&mut buf, 4, 100, b"hello", true);
+    frame::put_close(&mut buf, 0x42, true, b"bye");
+
+    let mut r = Reader::new(&buf);
+    match Frame::parse(&mut r).unwrap() {
+        Frame::Ack { ranges, delay } => {
+            assert_eq!(ranges, [8..10, 5..6, 0..3]);
+            assert_eq!(delay, 7);
+        }
+        f => panic!("{:?}", f),
+    }
+    assert!(matches!(
+        Frame::parse(&mut r).unwrap(),
+        Frame::Stream {
+            id: 4,
+            offset: 100,
+            data: b"hello",
+            fin: true
+        }
+    ));
+    assert!(matches!(
+        Frame::parse(&mut r).unwrap(),
+        Frame::ConnectionClose {
+            code: 0x42,
+            application: true,
+            reason: b"bye"
+        }
+    ));
+    assert!(r.is_empty());
+
+    // a STREAM frame beyond the largest offset
+    let mut r = Reader::new(&[
+        0x0e, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01,
+    ]);
+    assert!(Frame::parse(&mut r).is_err());
+}
+
+#[test]
+fn test_params() {
+    let cid = ConnectionId::new(&[1, 2, 3, 4]).unwrap();
+    let params = TransportParams {
+        original_dcid: Some(cid),
+        max_idle_timeout: 30000,
+        initial_max_data: 1 << 20,
+        initial_max_streams_bidi: 10,
+        initial_scid: Some(cid),
+        ..Default::default()
+    };
+    let encoded = params.encode();
+    let decoded = TransportParams::decode(&encoded, true).unwrap();
+    assert_eq!(decoded.original_dcid, Some(cid));
+    assert_eq!(decoded.max_idle_timeout, 30000);
+    assert_eq!(decoded.initial_max_data, 1 << 20);
+    assert_eq!(decoded.initial_max_streams_bidi, 10);
+    assert_eq!(decoded.max_udp_payload_size, 65527);
+
+    // only servers send the original connection ID
+    assert!(TransportParams::decode(&encoded, false).is_err());
+    // and the initial one is required
+    let params = TransportParams {
+        initial_scid: None,
+        ..params
+    };
+    assert!(TransportParams::decode(&params.encode(), true).is_err());
+}
+
+#[test]
+fn test_certificate() {
+    let der = cert::self_signed(&SECRET_KEY, "telemetry.local");
+    assert_eq!(
+        cert::ed25519_key(&der),
+        Some(ed25519_public_key(&SECRET_KEY))
+    );
+    assert_eq!(cert::ed25519_key(&der[..der.len() - 1]), None);
+}
+
+#[test]
+fn test_handshake_streams() {
+    let mut link = Link::new(client_config(), server_config());
+    link.established();
+    assert_eq!(link.client.alpn(), Some(ALPN));
+    assert_eq!(link.server().alpn(), Some(ALPN));
+    assert!(!link.client.is_early_data_accepted());
+
+    let id = link.client.open_stream(true).unwrap();
+    assert_eq!(id, StreamId(0));
+    let data = test_data(300_000);
+    link.client.stream_send(id, b"").unwrap();
+    link.flush();
+    assert_eq!(link.server().accept_stream(), None);
+    let received = transfer(&mut link, id, &data, true);
+    assert_eq!(received, data);
+    assert_eq!(link.server().accept_stream(), Some(id));
+
+    // the reply on the same stream
+    let received = transfer(&mut link, id, &data[..5000], false);
+    assert_eq!(received, data[..5000]);
+    assert!(link.client.rtt() < 20 * MS);
+
+    // a unidirectional stream of the server
+    let id = link.server().open_stream(false).unwrap();
+    assert_eq!(id, StreamId(3));
+    assert!(id.is_server_initiated() && id.is_unidirectional());
+    assert_eq!(
+        link.server().stream_recv(id, &mut [0; 8]),
+        Err(AxError::InvalidInput)
+    );
+    assert_eq!(transfer(&mut link, id, b"up", false), b"up");
+}
+
+#[test]
+fn test_loss_recovery() {
+    let mut link = Link::new(client_config(), server_config());
+    // the first flights included
+    link.lose = |n| n % 4 == 1 || n % 7 == 3;
+    link.established();
+    let id = link.client.open_stream(true).unwrap();
+    let data = test_data(200_000);
+    assert_eq!(transfer(&mut link, id, &data, true), data);
+    let id = link.server().open_stream(true).unwrap();
+    assert_eq!(
+        transfer(&mut link, id, &data[..30_000], false),
+        data[..30_000]
+    );
+}
+
+#[test]
+fn test_wrong_server_key() {
+    let mut client = client_config();
+    client.server_keys = vec![ed25519_public_key(&[8; 32])];
+    let mut link = Link::new(client, server_config());
+    link.run(|l| l.client.is_closed() && l.server().is_closed());
+    assert!(matches!(
+        link.client.error(),
+        Some(ConnectionError::Transport { code: 0x12a, .. })
+    ));
+    assert!(matches!(
+        link.server().error(),
+        Some(ConnectionError::PeerClosed {
+            code: 0x12a,
+            application: false,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_resumption() {
+    let mut server = server_config();
+    server.early_data = true;
+    let server = Arc::new(server);
+    let mut client = client_config();
+    client.early_data = true;
+    let client = Arc::new(client);
+
+    let first = Connection::connect(client.clone(), None, Duration::ZERO);
+    assert!(!first.is_early_data_offered());
+    let mut link = Link::with_client(first, server.clone());
+    link.run(|l| l.client.session_ticket().is_some());
+    let ticket = link.client.session_ticket().unwrap().clone();
+    assert!(ticket.allows_early_data());
+
+    // the request goes in the first flight
+    let mut second = Connection::connect(client.clone(), Some(&ticket), link.now);
+    assert!(second.is_early_data_offered());
+    let id = second.open_stream(true).unwrap();
+    second.stream_send(id, b"GET /metrics").unwrap();
+    second.stream_finish(id).unwrap();
+    let mut buf = [0; 1500];
+    let len = second.poll_transmit(link.now, &mut buf).unwrap();
+    let mut accepted = Connection::accept(server.clone(), &buf[..len], link.now).unwrap();
+    assert_eq!(accepted.accept_stream(), Some(id));
+    let mut request = [0; 32];
+    assert_eq!(accepted.stream_recv(id, &mut request), Ok(12));
+    assert_eq!(&request[..12], b"GET /metrics");
+    assert!(accepted.is_early_data_accepted());
+
+    let mut link = Link::with_client(second, server.clone());
+    link.now = Duration::from_secs(1);
+    link.server = Some(accepted);
+    link.established();
+    assert!(link.client.is_early_data_accepted());
+    assert_eq!(transfer(&mut link, id, b"up 1", false), b"up 1");
+
+    // with early data refused, it is sent again in 1-RTT packets
+    let mut refusing = server_config();
+    refusing.ticket_key = server.ticket_key;
+    let mut third = Connection::connect(client, Some(&ticket), Duration::ZERO);
+    let id = third.open_stream(true).unwrap();
+    third.stream_send(id, b"GET /metrics").unwrap();
+    let mut link = Link::with_client(third, Arc::new(refusing));
+    link.established();
+    assert!(!link.client.is_early_data_accepted());
+    assert_eq!(transfer(&mut link, id, b"", true), b"GET /metrics");
+}
+
+#[test]
+fn test_key_update() {
+    let mut link = Link::new(client_config(), server_config());
+    link.established();
+    let id = link.client.open_stream(true).unwrap();
+    let data = test_data(20_000);
+    for i in 0..3 {
+        if i % 2 == 0 {
+            link.client.update_keys();
+        } else {
+            link.server().update_keys();
+        }
+        link.client.stream_send(id, &data).unwrap();
+        let mut received = Vec::new();
+        let mut buf = [0; 4096];
+        link.run(|l| l.server().is_readable(id));
+        while received.len() < data.len() {
+            link.flush();
+            while let Ok(n) = link.server().stream_recv(id, &mut buf) {
+                received.extend_from_slice(&buf[..n]);
+            }
+        }
+        assert_eq!(received, data);
+    }
+    let id = link.client.open_stream(true).unwrap();
+    assert_eq!(transfer(&mut link, id, b"after", true), b"after");
+    assert!(link.client.is_established() && link.server().is_established());
+}
+
+#[test]
+fn test_stream_limits() {
+    let mut server = server_config();
+    server.transport.max_streams_bidi = 1;
+    server.transport.max_stream_data = 1000;
+    let mut link = Link::new(client_config(), server);
+    link.established();
+
+    let id = link.client.open_stream(true).unwrap();
+    assert_eq!(link.client.open_stream(true), Err(AxError::WouldBlock));
+    // the stream data limit is raised as the data is read
+    let data = test_data(10_000);
+    assert_eq!(transfer(&mut link, id, &data, true), data);
+    assert_eq!(transfer(&mut link, id, b"", false), b"");
+    // the server allows another one once it is over
+    link.run(|l| l.client.open_stream(true).is_ok());
+    let id = StreamId(4);
+
+    // a reset
+    link.client.stream_send(id, b"partial").unwrap();
+    link.client.stream_reset(id, 7).unwrap();
+    link.run(|l| l.server().is_readable(id));
+    assert_eq!(
+        link.server().stream_recv(id, &mut [0; 16]),
+        Err(AxError::ConnectionReset)
+    );
+}
+
+#[test]
+fn test_close() {
+    let mut link = Link::new(client_config(), server_config());
+    link.established();
+    let now = link.now;
+    link.client.close(0x42, b"bye", now);
+    assert_eq!(link.client.error(), Some(&ConnectionError::Closed));
+    assert_eq!(link.client.open_stream(true), Err(AxError::NotConnected));
+    link.run(|l| l.client.is_closed() && l.server().is_closed());
+    assert_eq!(
+        link.server().error(),
+        Some(&ConnectionError::PeerClosed {
+            code: 0x42,
+            application: true,
+            reason: b"bye".to_vec()
+        })
+    );
+
+    // idle
+    let mut client = client_config();
+    client.transport.max_idle_timeout = Duration::from_secs(5);
+    let mut link = Link::new(client, server_config());
+    link.established();
+    link.run(|l| l.client.is_closed());
+    assert_eq!(link.client.error(), Some(&ConnectionError::TimedOut));
+    assert!(link.now >= Duration::from_secs(5));
+
+    // kept alive
+    let mut client = client_config();
+    client.transport.max_idle_timeout = Duration::from_secs(5);
+    client.transport.keep_alive = Some(Duration::from_secs(2));
+    let mut link = Link::new(client, server_config());
+    link.established();
+    link.run(|l| l.now > Duration::from_secs(60));
+    assert!(link.client.is_established() && link.server().is_established());
+}
+
+#[test]
+fn test_version_negotiation() {
+    let mut client = Connection::connect(Arc::new(client_config()), None, Duration::ZERO);
+    let mut buf = [0; 1500];
+    let len = client.poll_transmit(Duration::ZERO, &mut buf).unwrap();
+    assert_eq!(len, 1200);
+    assert_eq!(
+        crate::destination_cid(&buf[..len]).map(<[u8]>::len),
+        Some(8)
+    );
+
+    // the server answers a version it does not support
+    buf[1..5].copy_from_slice(&0x6b3343cfu32.to_be_bytes());
+    let mut out = [0; 128];
+    let len = crate::version_negotiation(&buf[..len], &mut out).unwrap();
+    assert_eq!(&out[6..14], client.local_cid());
+    assert_eq!(out[len - 4..len], 1u32.to_be_bytes());
+
+    // the client ignores where its version is
+    client.handle_datagram(&out[..len], MS);
+    assert!(!client.is_closed());
+    out[len - 4..len].copy_from_slice(&0x6b3343cfu32.to_be_bytes());
+    client.handle_datagram(&out[..len], MS);
+    assert!(client.is_closed());
+    assert_eq!(client.error(), Some(&ConnectionError::VersionMismatch));
+}
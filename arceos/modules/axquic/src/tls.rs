@@ -0,0 +1,1101 @@
+//! The TLS 1.3 handshake (RFC 8446), as QUIC uses it (RFC 9001).
+//!
+//! There is no record layer: the handshake messages are carried in the
+//! CRYPTO frames of the packets, which QUIC protects itself with the traffic
+//! secrets given by the handshake.
+//!
+//! Only what QUIC needs is supported: TLS_AES_128_GCM_SHA256, X25519 and
+//! Ed25519 signatures. The client authenticates the server by the key of its
+//! certificate, which must be one it knows
+//! ([`ClientConfig::server_keys`]), the certificate chain not being
+//! validated. There is no HelloRetryRequest, nor client authentication.
+//!
+//! Sessions are resumed with the tickets of the server, sealed with a key of
+//! its own, along with a new X25519 exchange. The client can then send early
+//! data (0-RTT).
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::time::Duration;
+
+use axcrypto::{
+    ct_eq, ed25519_sign, ed25519_verify, hkdf_expand, hkdf_extract, hmac_sha256, x25519,
+    x25519_public_key, AesGcm, Sha256, AEAD_TAG_SIZE,
+};
+
+use crate::codec::{put_u16, put_u32, put_vec, Reader};
+use crate::packet::Space;
+use crate::{cert, code, ClientConfig, ServerConfig, TransportError};
+
+const TLS12: u16 = 0x0303;
+const TLS13: u16 = 0x0304;
+const AES_128_GCM_SHA256: u16 = 0x1301;
+const X25519: u16 = 0x001d;
+const ED25519: u16 = 0x0807;
+
+const CLIENT_HELLO: u8 = 1;
+const SERVER_HELLO: u8 = 2;
+const NEW_SESSION_TICKET: u8 = 4;
+const ENCRYPTED_EXTENSIONS: u8 = 8;
+const CERTIFICATE: u8 = 11;
+const CERTIFICATE_REQUEST: u8 = 13;
+const CERTIFICATE_VERIFY: u8 = 15;
+const FINISHED: u8 = 20;
+
+const SERVER_NAME: u16 = 0;
+const SUPPORTED_GROUPS: u16 = 10;
+const SIGNATURE_ALGORITHMS: u16 = 13;
+const ALPN: u16 = 16;
+const PRE_SHARED_KEY: u16 = 41;
+const EARLY_DATA: u16 = 42;
+const SUPPORTED_VERSIONS: u16 = 43;
+const PSK_KEY_EXCHANGE_MODES: u16 = 45;
+const KEY_SHARE: u16 = 51;
+const QUIC_TRANSPORT_PARAMETERS: u16 = 57;
+
+/// The PSK mode with a new (EC)DHE exchange, the only one supported.
+const PSK_DHE_KE: u8 = 1;
+
+/// The random of the ServerHello messages which are HelloRetryRequests.
+const HRR_RANDOM: [u8; 32] = [
+    0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+    0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+];
+
+/// The longest handshake messages received.
+const MAX_MESSAGE_LEN: usize = 0x4000;
+/// The longest lifetime of the tickets (RFC 8446 section 4.6.1).
+const MAX_TICKET_LIFETIME: Duration = Duration::from_secs(7 * 24 * 3600);
+/// How much the ages of a ticket, as seen by the client and by the server,
+/// may differ for early data to be accepted, in milliseconds.
+const MAX_TICKET_AGE_SKEW: u64 = 10_000;
+
+/// The alerts closing the handshake, sent as the `CRYPTO_ERROR` codes.
+mod alert {
+    pub const UNEXPECTED_MESSAGE: u8 = 10;
+    pub const HANDSHAKE_FAILURE: u8 = 40;
+    pub const BAD_CERTIFICATE: u8 = 42;
+    pub const UNSUPPORTED_CERTIFICATE: u8 = 43;
+    pub const ILLEGAL_PARAMETER: u8 = 47;
+    pub const DECODE_ERROR: u8 = 50;
+    pub const DECRYPT_ERROR: u8 = 51;
+    pub const PROTOCOL_VERSION: u8 = 70;
+    pub const MISSING_EXTENSION: u8 = 109;
+    pub const UNSUPPORTED_EXTENSION: u8 = 110;
+    pub const NO_APPLICATION_PROTOCOL: u8 = 120;
+}
+
+const fn fail(alert: u8, reason: &'static str) -> TransportError {
+    TransportError::new(code::CRYPTO_ERROR + alert as u64, reason)
+}
+
+const fn decode_error(reason: &'static str) -> TransportError {
+    fail(alert::DECODE_ERROR, reason)
+}
+
+/// HKDF-Expand-Label of TLS 1.3, which QUIC also uses for its own keys.
+pub fn expand_label(secret: &[u8], label: &[u8], context: &[u8], out: &mut [u8]) {
+    let mut info = Vec::with_capacity(10 + label.len() + context.len());
+    put_u16(&mut info, out.len() as u16);
+    put_vec::<1>(&mut info, |v| {
+        v.extend_from_slice(b"tls13 ");
+        v.extend_from_slice(label);
+    });
+    put_vec::<1>(&mut info, |v| v.extend_from_slice(context));
+    hkdf_expand(secret, &info, out);
+}
+
+fn derive_secret(secret: &[u8; 32], label: &[u8], hash: &[u8; 32]) -> [u8; 32] {
+    let mut out = [0; 32];
+    expand_label(secret, label, hash, &mut out);
+    out
+}
+
+fn empty_hash() -> [u8; 32] {
+    Sha256::new().finalize()
+}
+
+/// Returns the early secret of the key schedule, from the PSK if any.
+fn early_secret(psk: Option<&[u8; 32]>) -> [u8; 32] {
+    hkdf_extract(&[], psk.unwrap_or(&[0; 32]))
+}
+
+/// Returns the secret of the key schedule following `secret`, adding `ikm`.
+fn next_secret(secret: &[u8; 32], ikm: &[u8]) -> [u8; 32] {
+    hkdf_extract(&derive_secret(secret, b"derived", &empty_hash()), ikm)
+}
+
+/// Computes the `verify_data` of a Finished message, or a PSK binder.
+fn finished_mac(secret: &[u8; 32], hash: &[u8; 32]) -> [u8; 32] {
+    let mut key = [0; 32];
+    expand_label(secret, b"finished", &[], &mut key);
+    hmac_sha256(&key, hash)
+}
+
+/// Returns what the CertificateVerify of the server signs.
+fn signed_content(hash: &[u8; 32]) -> Vec<u8> {
+    let mut content = Vec::with_capacity(130);
+    content.extend_from_slice(&[0x20; 64]);
+    content.extend_from_slice(b"TLS 1.3, server CertificateVerify\0");
+    content.extend_from_slice(hash);
+    content
+}
+
+fn put_message(out: &mut Vec<u8>, ty: u8, f: impl FnOnce(&mut Vec<u8>)) {
+    out.push(ty);
+    put_vec::<3>(out, f);
+}
+
+fn put_extension(out: &mut Vec<u8>, ty: u16, f: impl FnOnce(&mut Vec<u8>)) {
+    put_u16(out, ty);
+    put_vec::<2>(out, f);
+}
+
+/// Splits the extensions of `buf`, there being one of each type at most.
+fn extensions(buf: &[u8]) -> Result<Vec<(u16, &[u8])>, TransportError> {
+    let mut r = Reader::new(buf);
+    let mut exts: Vec<(u16, &[u8])> = Vec::new();
+    while !r.is_empty() {
+        let ty = r.u16().ok_or(decode_error("bad extension"))?;
+        let data = r.vec::<2>().ok_or(decode_error("bad extension"))?;
+        if exts.iter().any(|(t, _)| *t == ty) {
+            return Err(fail(alert::ILLEGAL_PARAMETER, "duplicate extension"));
+        }
+        exts.push((ty, data));
+    }
+    Ok(exts)
+}
+
+fn find<'a>(exts: &[(u16, &'a [u8])], ty: u16) -> Option<&'a [u8]> {
+    exts.iter().find(|(t, _)| *t == ty).map(|(_, data)| *data)
+}
+
+/// Returns whether the list of 16-bit values `list` contains `v`.
+fn contains_u16(list: &[u8], v: u16) -> bool {
+    list.chunks_exact(2).any(|c| c == v.to_be_bytes())
+}
+
+/// The traffic secrets given by the handshake, as they become known.
+pub enum Secrets {
+    /// The secret of the early data of the client.
+    ZeroRtt([u8; 32]),
+    Handshake {
+        client: [u8; 32],
+        server: [u8; 32],
+    },
+    OneRtt {
+        client: [u8; 32],
+        server: [u8; 32],
+    },
+}
+
+/// Where early data stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EarlyData {
+    NotOffered,
+    /// Offered by the client, not yet answered by the server.
+    Offered,
+    Accepted,
+    Rejected,
+}
+
+/// A ticket of a server, to resume the session in another connection, see
+/// [`Connection::connect`](crate::Connection::connect).
+#[derive(Clone)]
+pub struct SessionTicket {
+    pub(crate) ticket: Vec<u8>,
+    pub(crate) psk: [u8; 32],
+    pub(crate) age_add: u32,
+    pub(crate) received: Duration,
+    pub(crate) expiry: Duration,
+    pub(crate) early_data: bool,
+    pub(crate) alpn: Vec<u8>,
+    /// The transport parameters of the server, which the early data
+    /// abides by.
+    pub(crate) params: Vec<u8>,
+}
+
+impl SessionTicket {
+    /// Returns whether the ticket can still be used at `now`.
+    pub fn is_valid(&self, now: Duration) -> bool {
+        now < self.expiry
+    }
+
+    /// Returns whether the server accepts early data with the ticket.
+    pub fn allows_early_data(&self) -> bool {
+        self.early_data
+    }
+}
+
+/// What a server seals in its tickets.
+struct TicketState {
+    /// When it was issued, in milliseconds on the clock of the server.
+    issued: u64,
+    age_add: u32,
+    early_data: bool,
+    psk: [u8; 32],
+    alpn: Vec<u8>,
+}
+
+impl TicketState {
+    fn seal(&self, config: &ServerConfig) -> Vec<u8> {
+        let mut ticket = Vec::new();
+        let mut nonce = [0; 12];
+        (config.random)(&mut nonce);
+        ticket.extend_from_slice(&nonce);
+        ticket.extend_from_slice(&self.issued.to_be_bytes());
+        put_u32(&mut ticket, self.age_add);
+        ticket.push(self.early_data as u8);
+        ticket.extend_from_slice(&self.psk);
+        put_vec::<1>(&mut ticket, |t| t.extend_from_slice(&self.alpn));
+        let aead = AesGcm::new(&config.ticket_key).unwrap();
+        let tag = aead.seal_in_place(&nonce, &[], &mut ticket[12..]).unwrap();
+        ticket.extend_from_slice(&tag);
+        ticket
+    }
+
+    fn open(config: &ServerConfig, ticket: &[u8]) -> Option<Self> {
+        let len = ticket.len().checked_sub(12 + AEAD_TAG_SIZE)?;
+        let nonce = ticket[..12].try_into().unwrap();
+        let mut state = ticket[12..12 + len].to_vec();
+        let tag = ticket[12 + len..].try_into().unwrap();
+        let aead = AesGcm::new(&config.ticket_key).unwrap();
+        aead.open_in_place(nonce, &[], &mut state, tag).ok()?;
+        let mut r = Reader::new(&state);
+        Some(Self {
+            issued: u64::from_be_bytes(r.array()?),
+            age_add: r.u32()?,
+            early_data: r.u8()? != 0,
+            psk: r.array()?,
+            alpn: r.vec::<1>()?.to_vec(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// The server waits for the ClientHello.
+    ClientHello,
+    ServerHello,
+    EncryptedExtensions,
+    /// The client waits for the certificate of the server, or a request of
+    /// its own before.
+    Certificate,
+    CertificateVerify,
+    /// Waiting for the Finished of the peer.
+    Finished,
+    Connected,
+}
+
+enum Role {
+    Client {
+        config: Arc<ClientConfig>,
+        x25519_secret: [u8; 32],
+        ticket: Option<Box<SessionTicket>>,
+        server_key: Option<[u8; 32]>,
+        /// The context of the CertificateRequest of the server, if any.
+        certificate_request: Option<Vec<u8>>,
+    },
+    Server {
+        config: Arc<ServerConfig>,
+    },
+}
+
+/// One side of the handshake.
+pub struct Tls {
+    role: Role,
+    state: State,
+    transcript: Sha256,
+    /// The handshake bytes received, not yet making a whole message, by
+    /// packet number space.
+    incoming: [Vec<u8>; 3],
+    /// The handshake bytes to send, by packet number space.
+    pub outgoing: [Vec<u8>; 3],
+    /// The traffic secrets not yet taken by the connection.
+    pub secrets: Vec<Secrets>,
+    pub early_data: EarlyData,
+    /// The tickets received by the client.
+    pub tickets: Vec<SessionTicket>,
+    local_params: Vec<u8>,
+    peer_params: Option<Vec<u8>>,
+    alpn: Option<Vec<u8>>,
+    psk_accepted: bool,
+    early_secret: [u8; 32],
+    handshake_secret: [u8; 32],
+    client_hs_secret: [u8; 32],
+    server_hs_secret: [u8; 32],
+    master_secret: [u8; 32],
+    resumption_secret: [u8; 32],
+}
+
+impl Tls {
+    fn new(role: Role, local_params: Vec<u8>, state: State) -> Self {
+        Self {
+            role,
+            state,
+            transcript: Sha256::new(),
+            incoming: Default::default(),
+            outgoing: Default::default(),
+            secrets: Vec::new(),
+            early_data: EarlyData::NotOffered,
+            tickets: Vec::new(),
+            local_params,
+            peer_params: None,
+            alpn: None,
+            psk_accepted: false,
+            early_secret: [0; 32],
+            handshake_secret: [0; 32],
+            client_hs_secret: [0; 32],
+            server_hs_secret: [0; 32],
+            master_secret: [0; 32],
+            resumption_secret: [0; 32],
+        }
+    }
+
+    /// Starts the handshake of a client sending the transport parameters
+    /// `params`, and resuming the session of `ticket` if it is still valid.
+    pub fn client(
+        config: Arc<ClientConfig>,
+        params: Vec<u8>,
+        ticket: Option<&SessionTicket>,
+        now: Duration,
+    ) -> Self {
+        let mut x25519_secret = [0; 32];
+        (config.random)(&mut x25519_secret);
+        let ticket = ticket
+            .filter(|t| t.is_valid(now) && config.alpn.contains(&t.alpn))
+            .map(|t| Box::new(t.clone()));
+        let role = Role::Client {
+            config,
+            x25519_secret,
+            ticket,
+            server_key: None,
+            certificate_request: None,
+        };
+        let mut tls = Self::new(role, params, State::ServerHello);
+        tls.client_hello(now);
+        tls
+    }
+
+    /// Starts the handshake of a server sending the transport parameters
+    /// `params`.
+    pub fn server(config: Arc<ServerConfig>, params: Vec<u8>) -> Self {
+        Self::new(Role::Server { config }, params, State::ClientHello)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.state == State::Connected
+    }
+
+    /// Returns the transport parameters of the peer, once received.
+    pub fn peer_params(&self) -> Option<&[u8]> {
+        self.peer_params.as_deref()
+    }
+
+    /// Returns the application protocol negotiated.
+    pub fn alpn(&self) -> Option<&[u8]> {
+        self.alpn.as_deref()
+    }
+
+    fn hash(&self) -> [u8; 32] {
+        self.transcript.clone().finalize()
+    }
+
+    /// Queues a handshake message to send in `space`.
+    fn send(&mut self, space: Space, msg: &[u8]) {
+        self.outgoing[space as usize].extend_from_slice(msg);
+        self.transcript.update(msg);
+    }
+
+    fn handshake_keys(&mut self, shared: &[u8; 32]) {
+        self.handshake_secret = next_secret(&self.early_secret, shared);
+        let hash = self.hash();
+        self.client_hs_secret = derive_secret(&self.handshake_secret, b"c hs traffic", &hash);
+        self.server_hs_secret = derive_secret(&self.handshake_secret, b"s hs traffic", &hash);
+        self.secrets.push(Secrets::Handshake {
+            client: self.client_hs_secret,
+            server: self.server_hs_secret,
+        });
+    }
+
+    /// Derives the 1-RTT secrets, the transcript ending with the Finished of
+    /// the server.
+    fn application_keys(&mut self) {
+        self.master_secret = next_secret(&self.handshake_secret, &[0; 32]);
+        let hash = self.hash();
+        self.secrets.push(Secrets::OneRtt {
+            client: derive_secret(&self.master_secret, b"c ap traffic", &hash),
+            server: derive_secret(&self.master_secret, b"s ap traffic", &hash),
+        });
+    }
+
+    fn client_hello(&mut self, now: Duration) {
+        let Role::Client {
+            config,
+            x25519_secret,
+            ticket,
+            ..
+        } = &self.role
+        else {
+            unreachable!()
+        };
+        let early_data = config.early_data && ticket.as_ref().is_some_and(|t| t.early_data);
+        let mut random = [0; 32];
+        (config.random)(&mut random);
+
+        let mut msg = Vec::new();
+        put_message(&mut msg, CLIENT_HELLO, |m| {
+            put_u16(m, TLS12);
+            m.extend_from_slice(&random);
+            m.push(0); // no legacy session ID
+            put_vec::<2>(m, |m| put_u16(m, AES_128_GCM_SHA256));
+            m.extend_from_slice(&[1, 0]); // the null compression
+            put_vec::<2>(m, |m| {
+                if let Some(name) = &config.server_name {
+                    put_extension(m, SERVER_NAME, |m| {
+                        put_vec::<2>(m, |m| {
+                            m.push(0); // host_name
+                            put_vec::<2>(m, |m| m.extend_from_slice(name.as_bytes()));
+                        })
+                    });
+                }
+                put_extension(m, SUPPORTED_GROUPS, |m| {
+                    put_vec::<2>(m, |m| put_u16(m, X25519))
+                });
+                put_extension(m, SIGNATURE_ALGORITHMS, |m| {
+                    put_vec::<2>(m, |m| put_u16(m, ED25519))
+                });
+                put_extension(m, ALPN, |m| {
+                    put_vec::<2>(m, |m| {
+                        for protocol in &config.alpn {
+                            put_vec::<1>(m, |m| m.extend_from_slice(protocol));
+                        }
+                    })
+                });
+                put_extension(m, SUPPORTED_VERSIONS, |m| {
+                    put_vec::<1>(m, |m| put_u16(m, TLS13))
+                });
+                put_extension(m, KEY_SHARE, |m| {
+                    put_vec::<2>(m, |m| {
+                        put_u16(m, X25519);
+                        put_vec::<2>(m, |m| {
+                            m.extend_from_slice(&x25519_public_key(x25519_secret))
+                        });
+                    })
+                });
+                put_extension(m, QUIC_TRANSPORT_PARAMETERS, |m| {
+                    m.extend_from_slice(&self.local_params)
+                });
+                if let Some(ticket) = ticket {
+                    put_extension(m, PSK_KEY_EXCHANGE_MODES, |m| {
+                        put_vec::<1>(m, |m| m.push(PSK_DHE_KE))
+                    });
+                    if early_data {
+                        put_extension(m, EARLY_DATA, |_| {});
+                    }
+                    // the binder is set below, once the rest is hashed
+                    let age = now.saturating_sub(ticket.received).as_millis() as u32;
+                    put_extension(m, PRE_SHARED_KEY, |m| {
+                        put_vec::<2>(m, |m| {
+                            put_vec::<2>(m, |m| m.extend_from_slice(&ticket.ticket));
+                            put_u32(m, age.wrapping_add(ticket.age_add));
+                        });
+                        put_vec::<2>(m, |m| put_vec::<1>(m, |m| m.extend_from_slice(&[0; 32])));
+                    });
+                }
+            });
+        });
+
+        self.early_secret = early_secret(ticket.as_ref().map(|t| &t.psk));
+        if ticket.is_some() {
+            let binder_key = derive_secret(&self.early_secret, b"res binder", &empty_hash());
+            let binders = msg.len() - 35;
+            let binder = finished_mac(&binder_key, &axcrypto::sha256(&msg[..binders]));
+            msg[binders + 3..].copy_from_slice(&binder);
+        }
+        self.send(Space::Initial, &msg);
+        if early_data {
+            self.early_data = EarlyData::Offered;
+            let secret = derive_secret(&self.early_secret, b"c e traffic", &self.hash());
+            self.secrets.push(Secrets::ZeroRtt(secret));
+        }
+    }
+
+    /// Handles the handshake bytes received in `space`, which follow the
+    /// ones received before.
+    pub fn read(&mut self, space: Space, data: &[u8], now: Duration) -> Result<(), TransportError> {
+        self.incoming[space as usize].extend_from_slice(data);
+        loop {
+            let buf = &self.incoming[space as usize];
+            if buf.len() < 4 {
+                return Ok(());
+            }
+            let len = (buf[1] as usize) << 16 | (buf[2] as usize) << 8 | buf[3] as usize;
+            if len > MAX_MESSAGE_LEN {
+                return Err(decode_error("handshake message too long"));
+            } else if buf.len() < 4 + len {
+                return Ok(());
+            }
+            let msg: Vec<u8> = self.incoming[space as usize].drain(..4 + len).collect();
+            self.handle_message(space, &msg, now)?;
+        }
+    }
+
+    fn handle_message(
+        &mut self,
+        space: Space,
+        msg: &[u8],
+        now: Duration,
+    ) -> Result<(), TransportError> {
+        let expected_space = match msg[0] {
+            CLIENT_HELLO | SERVER_HELLO => Space::Initial,
+            NEW_SESSION_TICKET => Space::Data,
+            _ => Space::Handshake,
+        };
+        let unexpected = fail(alert::UNEXPECTED_MESSAGE, "unexpected handshake message");
+        if space != expected_space {
+            return Err(unexpected);
+        }
+        let client = matches!(self.role, Role::Client { .. });
+        match (client, self.state, msg[0]) {
+            (false, State::ClientHello, CLIENT_HELLO) => self.on_client_hello(msg, now),
+            (true, State::ServerHello, SERVER_HELLO) => self.on_server_hello(msg),
+            (true, State::EncryptedExtensions, ENCRYPTED_EXTENSIONS) => {
+                self.on_encrypted_extensions(msg)
+            }
+            (true, State::Certificate, CERTIFICATE_REQUEST) => self.on_certificate_request(msg),
+            (true, State::Certificate, CERTIFICATE) => self.on_certificate(msg),
+            (true, State::CertificateVerify, CERTIFICATE_VERIFY) => self.on_certificate_verify(msg),
+            (_, State::Finished, FINISHED) => self.on_finished(msg, now),
+            (true, State::Connected, NEW_SESSION_TICKET) => self.on_new_session_ticket(msg, now),
+            _ => Err(unexpected),
+        }
+    }
+
+    fn on_client_hello(&mut self, msg: &[u8], now: Duration) -> Result<(), TransportError> {
+        let Role::Server { config } = &self.role else {
+            unreachable!()
+        };
+        let config = config.clone();
+        let bad = decode_error("bad ClientHello");
+        let mut r = Reader::new(&msg[4..]);
+        r.u16().ok_or(bad)?;
+        r.bytes(32).ok_or(bad)?;
+        if !r.vec::<1>().ok_or(bad)?.is_empty() {
+            return Err(fail(alert::ILLEGAL_PARAMETER, "legacy session ID"));
+        }
+        if !contains_u16(r.vec::<2>().ok_or(bad)?, AES_128_GCM_SHA256) {
+            return Err(fail(alert::HANDSHAKE_FAILURE, "no supported cipher suite"));
+        }
+        if r.vec::<1>().ok_or(bad)? != [0] {
+            return Err(fail(alert::ILLEGAL_PARAMETER, "compression offered"));
+        }
+        let exts = extensions(r.vec::<2>().ok_or(bad)?)?;
+        if !r.is_empty() {
+            return Err(bad);
+        }
+
+        let versions = find(&exts, SUPPORTED_VERSIONS)
+            .ok_or(fail(alert::PROTOCOL_VERSION, "TLS 1.3 not offered"))?;
+        if !contains_u16(Reader::new(versions).vec::<1>().ok_or(bad)?, TLS13) {
+            return Err(fail(alert::PROTOCOL_VERSION, "TLS 1.3 not offered"));
+        }
+        let shares =
+            find(&exts, KEY_SHARE).ok_or(fail(alert::MISSING_EXTENSION, "no key share"))?;
+        let mut shares = Reader::new(Reader::new(shares).vec::<2>().ok_or(bad)?);
+        let mut client_share = None;
+        while !shares.is_empty() {
+            let group = shares.u16().ok_or(bad)?;
+            let key = shares.vec::<2>().ok_or(bad)?;
+            if group == X25519 {
+                client_share = Some(<[u8; 32]>::try_from(key).map_err(|_| bad)?);
+            }
+        }
+        let client_share =
+            client_share.ok_or(fail(alert::HANDSHAKE_FAILURE, "no X25519 key share"))?;
+        let params = find(&exts, QUIC_TRANSPORT_PARAMETERS)
+            .ok_or(fail(alert::MISSING_EXTENSION, "no transport parameters"))?;
+        let no_alpn = fail(
+            alert::NO_APPLICATION_PROTOCOL,
+            "no application protocol in common",
+        );
+        let mut protocols = Vec::new();
+        let mut list = Reader::new(
+            Reader::new(find(&exts, ALPN).ok_or(no_alpn)?)
+                .vec::<2>()
+                .ok_or(bad)?,
+        );
+        while !list.is_empty() {
+            protocols.push(list.vec::<1>().ok_or(bad)?);
+        }
+        let alpn = config
+            .alpn
+            .iter()
+            .find(|p| protocols.contains(&p.as_slice()))
+            .ok_or(no_alpn)?
+            .clone();
+
+        let mut resumed = None;
+        if let Some(psk) = find(&exts, PRE_SHARED_KEY) {
+            if exts.last().unwrap().0 != PRE_SHARED_KEY {
+                return Err(fail(alert::ILLEGAL_PARAMETER, "pre_shared_key not last"));
+            }
+            let modes = find(&exts, PSK_KEY_EXCHANGE_MODES)
+                .ok_or(fail(alert::MISSING_EXTENSION, "no PSK modes"))?;
+            let dhe = Reader::new(modes)
+                .vec::<1>()
+                .ok_or(bad)?
+                .contains(&PSK_DHE_KE);
+            let mut r = Reader::new(psk);
+            let mut identities = Reader::new(r.vec::<2>().ok_or(bad)?);
+            let binders_len = r.remaining();
+            let mut binders = Reader::new(r.vec::<2>().ok_or(bad)?);
+            // only the first identity is tried
+            let identity = identities.vec::<2>().ok_or(bad)?;
+            let obfuscated_age = identities.u32().ok_or(bad)?;
+            let binder = binders.vec::<1>().ok_or(bad)?;
+            let state = TicketState::open(&config, identity).filter(|state| {
+                let age = (now.as_millis() as u64).checked_sub(state.issued);
+                dhe && age.is_some_and(|age| age < config.ticket_lifetime.as_millis() as u64)
+            });
+            if let Some(state) = state {
+                let early_secret = early_secret(Some(&state.psk));
+                let binder_key = derive_secret(&early_secret, b"res binder", &empty_hash());
+                let hash = axcrypto::sha256(&msg[..msg.len() - binders_len]);
+                if !ct_eq(&finished_mac(&binder_key, &hash), binder) {
+                    return Err(fail(alert::DECRYPT_ERROR, "bad PSK binder"));
+                }
+                resumed = Some((state, early_secret, obfuscated_age));
+            }
+        }
+        if resumed.is_none() {
+            let algs = find(&exts, SIGNATURE_ALGORITHMS)
+                .ok_or(fail(alert::MISSING_EXTENSION, "no signature algorithms"))?;
+            if !contains_u16(Reader::new(algs).vec::<2>().ok_or(bad)?, ED25519) {
+                return Err(fail(alert::HANDSHAKE_FAILURE, "Ed25519 not offered"));
+            }
+        }
+
+        self.transcript.update(msg);
+        self.peer_params = Some(params.to_vec());
+        if find(&exts, EARLY_DATA).is_some() {
+            self.early_data = EarlyData::Rejected;
+        }
+        match &resumed {
+            Some((state, early_secret, obfuscated_age)) => {
+                self.psk_accepted = true;
+                self.early_secret = *early_secret;
+                let client_age = obfuscated_age.wrapping_sub(state.age_add) as u64;
+                let server_age = now.as_millis() as u64 - state.issued;
+                if self.early_data == EarlyData::Rejected
+                    && config.early_data
+                    && state.early_data
+                    && state.alpn == alpn
+                    && client_age.abs_diff(server_age) <= MAX_TICKET_AGE_SKEW
+                {
+                    self.early_data = EarlyData::Accepted;
+                    let secret = derive_secret(early_secret, b"c e traffic", &self.hash());
+                    self.secrets.push(Secrets::ZeroRtt(secret));
+                }
+            }
+            None => self.early_secret = early_secret(None),
+        }
+
+        let mut x25519_secret = [0; 32];
+        (config.random)(&mut x25519_secret);
+        let shared = x25519(&x25519_secret, &client_share);
+        if shared == [0; 32] {
+            return Err(fail(alert::ILLEGAL_PARAMETER, "bad X25519 key share"));
+        }
+        let mut random = [0; 32];
+        (config.random)(&mut random);
+        let mut hello = Vec::new();
+        put_message(&mut hello, SERVER_HELLO, |m| {
+            put_u16(m, TLS12);
+            m.extend_from_slice(&random);
+            m.push(0);
+            put_u16(m, AES_128_GCM_SHA256);
+            m.push(0);
+            put_vec::<2>(m, |m| {
+                put_extension(m, SUPPORTED_VERSIONS, |m| put_u16(m, TLS13));
+                put_extension(m, KEY_SHARE, |m| {
+                    put_u16(m, X25519);
+                    put_vec::<2>(m, |m| {
+                        m.extend_from_slice(&x25519_public_key(&x25519_secret))
+                    });
+                });
+                if self.psk_accepted {
+                    put_extension(m, PRE_SHARED_KEY, |m| put_u16(m, 0));
+                }
+            });
+        });
+        self.send(Space::Initial, &hello);
+        self.handshake_keys(&shared);
+
+        let mut msg = Vec::new();
+        put_message(&mut msg, ENCRYPTED_EXTENSIONS, |m| {
+            put_vec::<2>(m, |m| {
+                put_extension(m, ALPN, |m| {
+                    put_vec::<2>(m, |m| put_vec::<1>(m, |m| m.extend_from_slice(&alpn)))
+                });
+                put_extension(m, QUIC_TRANSPORT_PARAMETERS, |m| {
+                    m.extend_from_slice(&self.local_params)
+                });
+                if self.early_data == EarlyData::Accepted {
+                    put_extension(m, EARLY_DATA, |_| {});
+                }
+            })
+        });
+        self.send(Space::Handshake, &msg);
+        self.alpn = Some(alpn);
+
+        if !self.psk_accepted {
+            let mut msg = Vec::new();
+            put_message(&mut msg, CERTIFICATE, |m| {
+                m.push(0); // no request context
+                put_vec::<3>(m, |m| {
+                    for cert in &config.certificates {
+                        put_vec::<3>(m, |m| m.extend_from_slice(cert));
+                        put_u16(m, 0); // no extensions
+                    }
+                });
+            });
+            self.send(Space::Handshake, &msg);
+            let sig = ed25519_sign(&config.secret_key, &signed_content(&self.hash()));
+            let mut msg = Vec::new();
+            put_message(&mut msg, CERTIFICATE_VERIFY, |m| {
+                put_u16(m, ED25519);
+                put_vec::<2>(m, |m| m.extend_from_slice(&sig));
+            });
+            self.send(Space::Handshake, &msg);
+        }
+        let verify_data = finished_mac(&self.server_hs_secret, &self.hash());
+        let mut msg = Vec::new();
+        put_message(&mut msg, FINISHED, |m| m.extend_from_slice(&verify_data));
+        self.send(Space::Handshake, &msg);
+        self.application_keys();
+        self.state = State::Finished;
+        Ok(())
+    }
+
+    fn on_server_hello(&mut self, msg: &[u8]) -> Result<(), TransportError> {
+        let Role::Client {
+            x25519_secret,
+            ticket,
+            ..
+        } = &self.role
+        else {
+            unreachable!()
+        };
+        let bad = decode_error("bad ServerHello");
+        let mut r = Reader::new(&msg[4..]);
+        r.u16().ok_or(bad)?;
+        if r.array::<32>().ok_or(bad)? == HRR_RANDOM {
+            return Err(fail(alert::HANDSHAKE_FAILURE, "HelloRetryRequest"));
+        }
+        if !r.vec::<1>().ok_or(bad)?.is_empty() {
+            return Err(fail(alert::ILLEGAL_PARAMETER, "legacy session ID"));
+        }
+        if r.u16().ok_or(bad)? != AES_128_GCM_SHA256 || r.u8().ok_or(bad)? != 0 {
+            return Err(fail(alert::ILLEGAL_PARAMETER, "cipher suite not offered"));
+        }
+        let exts = extensions(r.vec::<2>().ok_or(bad)?)?;
+        if !r.is_empty() {
+            return Err(bad);
+        }
+        let mut server_share = None;
+        let mut psk_accepted = false;
+        let mut version = None;
+        for (ty, data) in exts {
+            let mut r = Reader::new(data);
+            match ty {
+                SUPPORTED_VERSIONS => version = r.u16(),
+                KEY_SHARE => {
+                    if r.u16() != Some(X25519) {
+                        return Err(fail(alert::ILLEGAL_PARAMETER, "group not offered"));
+                    }
+                    let key = r.vec::<2>().and_then(|k| <[u8; 32]>::try_from(k).ok());
+                    server_share = Some(key.ok_or(bad)?);
+                }
+                PRE_SHARED_KEY if ticket.is_some() && data == [0, 0] => psk_accepted = true,
+                PRE_SHARED_KEY => {
+                    return Err(fail(alert::ILLEGAL_PARAMETER, "PSK not offered"));
+                }
+                _ => return Err(fail(alert::UNSUPPORTED_EXTENSION, "unexpected extension")),
+            }
+        }
+        if version != Some(TLS13) {
+            return Err(fail(alert::PROTOCOL_VERSION, "not TLS 1.3"));
+        }
+        let server_share = server_share.ok_or(fail(alert::MISSING_EXTENSION, "no key share"))?;
+        let shared = x25519(x25519_secret, &server_share);
+        if shared == [0; 32] {
+            return Err(fail(alert::ILLEGAL_PARAMETER, "bad X25519 key share"));
+        }
+
+        self.transcript.update(msg);
+        self.psk_accepted = psk_accepted;
+        if !psk_accepted {
+            self.early_secret = early_secret(None);
+        }
+        self.handshake_keys(&shared);
+        self.state = State::EncryptedExtensions;
+        Ok(())
+    }
+
+    fn on_encrypted_extensions(&mut self, msg: &[u8]) -> Result<(), TransportError> {
+        let Role::Client { config, ticket, .. } = &self.role else {
+            unreachable!()
+        };
+        let bad = decode_error("bad EncryptedExtensions");
+        let mut r = Reader::new(&msg[4..]);
+        let exts = extensions(r.vec::<2>().ok_or(bad)?)?;
+        if !r.is_empty() {
+            return Err(bad);
+        }
+        let mut alpn = None;
+        let mut params = None;
+        let mut early_data_accepted = false;
+        for (ty, data) in exts {
+            match ty {
+                ALPN => {
+                    let mut r = Reader::new(data);
+                    let mut list = Reader::new(r.vec::<2>().ok_or(bad)?);
+                    let protocol = list.vec::<1>().ok_or(bad)?;
+                    if !list.is_empty() || !r.is_empty() {
+                        return Err(bad);
+                    }
+                    if !config.alpn.iter().any(|p| p == protocol) {
+                        return Err(fail(alert::ILLEGAL_PARAMETER, "protocol not offered"));
+                    }
+                    alpn = Some(protocol.to_vec());
+                }
+                QUIC_TRANSPORT_PARAMETERS => params = Some(data.to_vec()),
+                EARLY_DATA if data.is_empty() && self.early_data == EarlyData::Offered => {
+                    early_data_accepted = true;
+                }
+                SERVER_NAME | SUPPORTED_GROUPS => {}
+                _ => return Err(fail(alert::UNSUPPORTED_EXTENSION, "unexpected extension")),
+            }
+        }
+        let alpn = alpn.ok_or(fail(
+            alert::NO_APPLICATION_PROTOCOL,
+            "no application protocol",
+        ))?;
+        let params = params.ok_or(fail(alert::MISSING_EXTENSION, "no transport parameters"))?;
+        if early_data_accepted {
+            if !self.psk_accepted || ticket.as_ref().is_some_and(|t| t.alpn != alpn) {
+                return Err(fail(alert::ILLEGAL_PARAMETER, "bad early data acceptance"));
+            }
+            self.early_data = EarlyData::Accepted;
+        } else if self.early_data == EarlyData::Offered {
+            self.early_data = EarlyData::Rejected;
+        }
+
+        self.transcript.update(msg);
+        self.alpn = Some(alpn);
+        self.peer_params = Some(params);
+        self.state = if self.psk_accepted {
+            State::Finished
+        } else {
+            State::Certificate
+        };
+        Ok(())
+    }
+
+    fn on_certificate_request(&mut self, msg: &[u8]) -> Result<(), TransportError> {
+        let Role::Client {
+            certificate_request,
+            ..
+        } = &mut self.role
+        else {
+            unreachable!()
+        };
+        let bad = decode_error("bad CertificateRequest");
+        let mut r = Reader::new(&msg[4..]);
+        let context = r.vec::<1>().ok_or(bad)?;
+        extensions(r.vec::<2>().ok_or(bad)?)?;
+        if !r.is_empty() || certificate_request.is_some() {
+            return Err(bad);
+        }
+        // answered by an empty certificate
+        *certificate_request = Some(context.to_vec());
+        self.transcript.update(msg);
+        Ok(())
+    }
+
+    fn on_certificate(&mut self, msg: &[u8]) -> Result<(), TransportError> {
+        let Role::Client {
+            config, server_key, ..
+        } = &mut self.role
+        else {
+            unreachable!()
+        };
+        let bad = decode_error("bad Certificate");
+        let mut r = Reader::new(&msg[4..]);
+        if !r.vec::<1>().ok_or(bad)?.is_empty() {
+            return Err(fail(
+                alert::ILLEGAL_PARAMETER,
+                "certificate request context",
+            ));
+        }
+        let mut list = Reader::new(r.vec::<3>().ok_or(bad)?);
+        let leaf = list.vec::<3>().ok_or(bad)?;
+        let key = cert::ed25519_key(leaf).ok_or(fail(
+            alert::UNSUPPORTED_CERTIFICATE,
+            "not an Ed25519 certificate",
+        ))?;
+        if !config.server_keys.contains(&key) {
+            return Err(fail(alert::BAD_CERTIFICATE, "unknown server key"));
+        }
+        *server_key = Some(key);
+        self.transcript.update(msg);
+        self.state = State::CertificateVerify;
+        Ok(())
+    }
+
+    fn on_certificate_verify(&mut self, msg: &[u8]) -> Result<(), TransportError> {
+        let Role::Client {
+            server_key: Some(key),
+            ..
+        } = &self.role
+        else {
+            unreachable!()
+        };
+        let bad = decode_error("bad CertificateVerify");
+        let mut r = Reader::new(&msg[4..]);
+        if r.u16().ok_or(bad)? != ED25519 {
+            return Err(fail(
+                alert::ILLEGAL_PARAMETER,
+                "signature algorithm not offered",
+            ));
+        }
+        let sig: &[u8; 64] = r.vec::<2>().ok_or(bad)?.try_into().map_err(|_| bad)?;
+        if !r.is_empty() {
+            return Err(bad);
+        }
+        if !ed25519_verify(key, &signed_content(&self.hash()), sig) {
+            return Err(fail(alert::DECRYPT_ERROR, "bad CertificateVerify"));
+        }
+        self.transcript.update(msg);
+        self.state = State::Finished;
+        Ok(())
+    }
+
+    fn on_finished(&mut self, msg: &[u8], now: Duration) -> Result<(), TransportError> {
+        let client = matches!(self.role, Role::Client { .. });
+        let secret = if client {
+            &self.server_hs_secret
+        } else {
+            &self.client_hs_secret
+        };
+        if !ct_eq(&finished_mac(secret, &self.hash()), &msg[4..]) {
+            return Err(fail(alert::DECRYPT_ERROR, "bad Finished"));
+        }
+        self.transcript.update(msg);
+
+        match &self.role {
+            Role::Client {
+                certificate_request,
+                ..
+            } => {
+                let request = certificate_request.clone();
+                self.application_keys();
+                if let Some(context) = request {
+                    let mut msg = Vec::new();
+                    put_message(&mut msg, CERTIFICATE, |m| {
+                        put_vec::<1>(m, |m| m.extend_from_slice(&context));
+                        put_vec::<3>(m, |_| {});
+                    });
+                    self.send(Space::Handshake, &msg);
+                }
+                let verify_data = finished_mac(&self.client_hs_secret, &self.hash());
+                let mut msg = Vec::new();
+                put_message(&mut msg, FINISHED, |m| m.extend_from_slice(&verify_data));
+                self.send(Space::Handshake, &msg);
+                self.resumption_secret =
+                    derive_secret(&self.master_secret, b"res master", &self.hash());
+            }
+            Role::Server { config } => {
+                let config = config.clone();
+                self.resumption_secret =
+                    derive_secret(&self.master_secret, b"res master", &self.hash());
+                if !config.ticket_lifetime.is_zero() {
+                    self.new_session_ticket(&config, now);
+                }
+            }
+        }
+        self.state = State::Connected;
+        Ok(())
+    }
+
+    fn new_session_ticket(&mut self, config: &ServerConfig, now: Duration) {
+        let mut age_add = [0; 4];
+        (config.random)(&mut age_add);
+        let nonce = [0];
+        let mut psk = [0; 32];
+        expand_label(&self.resumption_secret, b"resumption", &nonce, &mut psk);
+        let state = TicketState {
+            issued: now.as_millis() as u64,
+            age_add: u32::from_be_bytes(age_add),
+            early_data: config.early_data,
+            psk,
+            alpn: self.alpn.clone().unwrap(),
+        };
+        let lifetime = config.ticket_lifetime.min(MAX_TICKET_LIFETIME).as_secs() as u32;
+        put_message(
+            &mut self.outgoing[Space::Data as usize],
+            NEW_SESSION_TICKET,
+            |m| {
+                put_u32(m, lifetime);
+                put_u32(m, state.age_add);
+                put_vec::<1>(m, |m| m.extend_from_slice(&nonce));
+                put_vec::<2>(m, |m| m.extend_from_slice(&state.seal(config)));
+                put_vec::<2>(m, |m| {
+                    if config.early_data {
+                        // QUIC has no limit of its own on early data
+                        put_extension(m, EARLY_DATA, |m| put_u32(m, u32::MAX));
+                    }
+                });
+            },
+        );
+    }
+
+    fn on_new_session_ticket(&mut self, msg: &[u8], now: Duration) -> Result<(), TransportError> {
+        let bad = decode_error("bad NewSessionTicket");
+        let mut r = Reader::new(&msg[4..]);
+        let lifetime = Duration::from_secs(r.u32().ok_or(bad)? as u64);
+        let age_add = r.u32().ok_or(bad)?;
+        let nonce = r.vec::<1>().ok_or(bad)?;
+        let ticket = r.vec::<2>().ok_or(bad)?;
+        let exts = extensions(r.vec::<2>().ok_or(bad)?)?;
+        if !r.is_empty() || ticket.is_empty() {
+            return Err(bad);
+        }
+        let early_data = match find(&exts, EARLY_DATA) {
+            Some(&[0xff, 0xff, 0xff, 0xff]) => true,
+            Some(_) => {
+                return Err(TransportError::new(
+                    code::PROTOCOL_VIOLATION,
+                    "bad max_early_data_size",
+                ));
+            }
+            None => false,
+        };
+        if lifetime.is_zero() {
+            return Ok(());
+        }
+        let mut psk = [0; 32];
+        expand_label(&self.resumption_secret, b"resumption", nonce, &mut psk);
+        self.tickets.push(SessionTicket {
+            ticket: ticket.to_vec(),
+            psk,
+            age_add,
+            received: now,
+            expiry: now + lifetime.min(MAX_TICKET_LIFETIME),
+            early_data,
+            alpn: self.alpn.clone().unwrap(),
+            params: self.peer_params.clone().unwrap(),
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+//! Boot time measurement.
+//!
+//! [`mark`] is called at the end of each init phase of [`rust_main`], and
+//! records the time since the end of the previous one. The breakdown, with
+//! the time spent probing each driver, is printed before entering the
+//! application's `main`, and shown in `/proc/boot/time` with `fs`.
+//!
+//! [`rust_main`]: crate::rust_main
+
+use core::fmt::{self, Write};
+
+use axhal::time::{monotonic_time_nanos, NANOS_PER_MICROS};
+use kspin::SpinNoIrq;
+
+/// The maximum number of phases recorded, the others are left out.
+const MAX_PHASES: usize = 16;
+
+struct Phases {
+    entries: [(&'static str, u64); MAX_PHASES],
+    len: usize,
+    /// The end of the last phase, in nanoseconds.
+    last: u64,
+}
+
+static PHASES: SpinNoIrq<Phases> = SpinNoIrq::new(Phases {
+    entries: [("", 0); MAX_PHASES],
+    len: 0,
+    last: 0,
+});
+
+/// Ends the phase `name`.
+///
+/// The first phase starts when the monotonic clock does, usually at reset,
+/// so it covers the firmware and the early boot code as well.
+pub(crate) fn mark(name: &'static str) {
+    let now = monotonic_time_nanos();
+    let mut phases = PHASES.lock();
+    let len = phases.len;
+    if len < MAX_PHASES {
+        phases.entries[len] = (name, now - phases.last);
+        phases.len += 1;
+    }
+    phases.last = now;
+}
+
+struct Millis(u64);
+
+impl fmt::Display for Millis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let micros = self.0 / NANOS_PER_MICROS;
+        write!(f, "{:>5}.{:03} ms", micros / 1000, micros % 1000)
+    }
+}
+
+/// Writes the breakdown of the phases recorded so far.
+fn write_summary(w: &mut impl Write) -> fmt::Result {
+    let phases = PHASES.lock();
+    writeln!(w, "Boot time until main: {}", Millis(phases.last))?;
+    for &(name, nanos) in &phases.entries[..phases.len] {
+        writeln!(w, "  {:<16}{}", name, Millis(nanos))?;
+        #[cfg(feature = "axdriver")]
+        if name == "drivers" {
+            let mut ret = Ok(());
+            axdriver::boot_time::for_each_probe_time(|driver, nanos| {
+                ret = ret.and_then(|_| writeln!(w, "    {:<14}{}", driver, Millis(nanos)));
+            });
+            ret?;
+        }
+    }
+    Ok(())
+}
+
+struct Console;
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        axhal::console::write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Prints the breakdown of the phases on the console.
+pub(crate) fn print_summary() {
+    write_summary(&mut Console).ok();
+}
+
+/// The content of `/proc/boot/time`.
+#[cfg(feature = "fs")]
+pub(crate) fn proc_boot_time() -> alloc::string::String {
+    let mut text = alloc::string::String::new();
+    write_summary(&mut text).ok();
+    text
+}
@@ -0,0 +1,37 @@
+//! Probing of the drivers deferred by `AX_DEFERRED_DRIVERS`, in a background
+//! task started with the application.
+
+/// The subsystems left without a device by the first pass, to be brought up
+/// with the deferred devices.
+#[derive(Default)]
+pub(crate) struct Pending {
+    #[cfg(feature = "net")]
+    pub net: bool,
+    #[cfg(feature = "display")]
+    pub display: bool,
+}
+
+#[allow(unused_variables)]
+pub(crate) fn deferred_probe_entry(pending: Pending) {
+    let all_devices = axdriver::init_deferred_drivers();
+
+    #[cfg(feature = "fs")]
+    if !all_devices.block.is_empty() {
+        // the root filesystem is mounted before the application starts
+        warn!("deferred block devices are not mounted");
+    }
+
+    #[cfg(feature = "net")]
+    if pending.net {
+        axnet::init_network(all_devices.net);
+    } else if !all_devices.net.is_empty() {
+        warn!("deferred NICs are not used, the network is already up");
+    }
+
+    #[cfg(feature = "display")]
+    if pending.display {
+        axdisplay::init_display(all_devices.display);
+    } else if !all_devices.display.is_empty() {
+        warn!("deferred graphics devices are not used, the display is already up");
+    }
+}
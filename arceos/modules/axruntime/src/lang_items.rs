@@ -2,6 +2,52 @@ use core::panic::PanicInfo;
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    #[cfg(not(feature = "tiny-fmt"))]
     error!("{}", info);
+    #[cfg(feature = "tiny-fmt")]
+    print_panic_location(info);
+    #[cfg(feature = "symbols")]
+    print_backtrace();
+    #[cfg(feature = "unwind")]
+    crate::panic::begin_unwind(info);
+    #[cfg(not(feature = "unwind"))]
     axhal::misc::terminate()
 }
+
+/// Prints where the panic is, but not its message, which would need the
+/// formatting machinery.
+#[cfg(feature = "tiny-fmt")]
+fn print_panic_location(info: &PanicInfo) {
+    let print = |s: &[u8]| axhal::console::write_bytes(s);
+    print(b"panicked");
+    if let Some(location) = info.location() {
+        let mut line = [0; 10];
+        let mut start = line.len();
+        let mut n = location.line();
+        loop {
+            start -= 1;
+            line[start] = b'0' + (n % 10) as u8;
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+        print(b" at ");
+        print(location.file().as_bytes());
+        print(b":");
+        print(&line[start..]);
+    }
+    print(b"\n");
+}
+
+#[cfg(feature = "symbols")]
+fn print_backtrace() {
+    error!("backtrace:");
+    axsyms::backtrace(|ret| {
+        // the return address may be past the end of the calling function
+        match axsyms::symbolize(ret - 1) {
+            Some((name, offset)) => error!("  {:#x} {}+{:#x}", ret, name, offset + 1),
+            None => error!("  {:#x}", ret),
+        }
+    });
+}
@@ -9,10 +9,63 @@
 //! - `paging`: Enable page table manipulation support.
 //! - `irq`: Enable interrupt handling support.
 //! - `multitask`: Enable multi-threading support.
+//! - `page-scrub`: Zero free pages in a background task of the lowest
+//!   priority, so that page faults can take pre-zeroed pages.
+//! - `hotplug`: Allow RAM to be added and removed at runtime, see
+//!   `axmm::add_memory`.
 //! - `smp`: Enable SMP (symmetric multiprocessing) support.
 //! - `fs`: Enable filesystem support.
 //! - `net`: Enable networking support.
+//! - `sntp`: Synchronize the realtime clock with an SNTP server in a
+//!   background task.
+//! - `mdns`: Answer mDNS queries for the host and the registered services in
+//!   a background task.
+//! - `syslog`: Send the log messages to the syslog server given by `syslog=`
+//!   on the kernel command line (`[udp://|tcp://]host[:port]`), at the level
+//!   given by `syslog.level=` (`info` by default), in a background task.
+//! - `tun`: Add the TAP device `/dev/net/tun` of `axnet` (with `fs`).
+//! - `vsock`: Probe the VirtIO socket devices, used by the vsock sockets of
+//!   `axnet`.
+//! - `ivshmem`: Probe the inter-VM shared memory devices, see
+//!   `axdriver::ivshmem`.
+//! - `can`: Probe the CAN controllers, see `axdriver::can`.
+//! - `diag-shell`: Run a diagnostic shell (`ps`, `free`, `dmesg`, ...) on the
+//!   console, or on the TCP port in `AX_DIAG_SHELL_PORT`, in a background task.
+//! - `thermal`: Monitor the temperature sensors in a background task,
+//!   throttling the background tasks when too hot, and shutting down at the
+//!   critical temperature.
+//! - `cpufreq`: Scale the CPU frequency with the governor in
+//!   `AX_CPUFREQ_GOVERNOR` (`ondemand` by default), following the load in a
+//!   background task.
 //! - `display`: Enable graphics support.
+//! - `balloon`: Enable the VirtIO memory balloon, following the size set by
+//!   the host in a background task.
+//! - `crypto`: Probe the VirtIO crypto devices, used as engines by
+//!   `axcrypto`.
+//! - `stack-protector`: Provide a random stack canary and the
+//!   `__stack_chk_fail` handler, for code built with `-Z stack-protector`.
+//! - `boot-time`: Print the time spent in each init phase, and probing each
+//!   driver, before entering the application's `main`.
+//! - `parallel-probe`: Start the secondary CPUs before probing the drivers,
+//!   so they probe the global drivers (e.g. SD/eMMC controllers)
+//!   concurrently.
+//! - `deferred-probe`: Probe the drivers in `AX_DEFERRED_DRIVERS` in a
+//!   background task started with the application, bringing up the network
+//!   or the display with their devices if the others have none.
+//! - `symbols`: Print a symbolized backtrace on panic, with the symbol table
+//!   of `axsyms`.
+//! - `tiny-fmt`: Print the boot banner and the location of the panics (but
+//!   not their messages) without the formatting machinery, for smaller images.
+//! - `replay`: Record the external inputs to a log, or replay them from it,
+//!   as set by `replay=` on the kernel command line (see `axhal::replay`).
+//! - `snapshot`: Provide [`take_snapshot`], and restore the snapshot given by
+//!   `snapshot.restore=` on the kernel command line at boot (see
+//!   `axhal::snapshot`).
+//! - `unwind`: Unwind the stack of the panicking task, so that the panics
+//!   can be caught (see [`panic`]), and a task that panics only exits (with
+//!   the code built with `-C panic=unwind`).
+//! - `hosted`: Run as a process of the host (see `axhal`), without setting up
+//!   the kernel address space with `paging`.
 //!
 //! All the features are optional and disabled by default.
 
@@ -28,9 +81,38 @@ mod lang_items;
 #[cfg(feature = "smp")]
 mod mp;
 
+#[cfg(any(
+    feature = "diag-shell",
+    feature = "fs",
+    feature = "syslog",
+    feature = "unwind"
+))]
+extern crate alloc;
+
+#[cfg(feature = "diag-shell")]
+mod shell;
+
+#[cfg(feature = "stack-protector")]
+mod stack_protector;
+
+#[cfg(feature = "boot-time")]
+mod boot_time;
+
+#[cfg(feature = "deferred-probe")]
+mod deferred;
+
+#[cfg(feature = "snapshot")]
+mod snapshot;
+
+#[cfg(feature = "unwind")]
+pub mod panic;
+
 #[cfg(feature = "smp")]
 pub use self::mp::rust_main_secondary;
 
+#[cfg(feature = "snapshot")]
+pub use self::snapshot::take_snapshot;
+
 const LOGO: &str = r#"
        d8888                            .d88888b.   .d8888b.
       d88888                           d88P" "Y88b d88P  Y88b
@@ -46,6 +128,39 @@ extern "C" {
     fn main();
 }
 
+/// Prints the logo and the build information.
+fn print_banner() {
+    let info = [
+        ("arch", option_env!("AX_ARCH")),
+        ("platform", option_env!("AX_PLATFORM")),
+        ("target", option_env!("AX_TARGET")),
+        ("smp", option_env!("AX_SMP")),
+        ("build_mode", option_env!("AX_MODE")),
+        ("log_level", option_env!("AX_LOG")),
+    ];
+    #[cfg(not(feature = "tiny-fmt"))]
+    {
+        ax_println!("{}", LOGO);
+        for (key, value) in info {
+            ax_println!("{} = {}", key, value.unwrap_or(""));
+        }
+        ax_println!();
+    }
+    #[cfg(feature = "tiny-fmt")]
+    {
+        let print = |s: &str| axhal::console::write_bytes(s.as_bytes());
+        print(LOGO);
+        print("\n");
+        for (key, value) in info {
+            print(key);
+            print(" = ");
+            print(value.unwrap_or(""));
+            print("\n");
+        }
+        print("\n");
+    }
+}
+
 struct LogIfImpl;
 
 #[crate_interface::impl_interface]
@@ -102,23 +217,12 @@ fn is_init_ok() -> bool {
 /// and the secondary CPUs call [`rust_main_secondary`].
 #[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
-    ax_println!("{}", LOGO);
-    ax_println!(
-        "\
-        arch = {}\n\
-        platform = {}\n\
-        target = {}\n\
-        smp = {}\n\
-        build_mode = {}\n\
-        log_level = {}\n\
-        ",
-        option_env!("AX_ARCH").unwrap_or(""),
-        option_env!("AX_PLATFORM").unwrap_or(""),
-        option_env!("AX_TARGET").unwrap_or(""),
-        option_env!("AX_SMP").unwrap_or(""),
-        option_env!("AX_MODE").unwrap_or(""),
-        option_env!("AX_LOG").unwrap_or(""),
-    );
+    #[cfg(feature = "stack-protector")]
+    stack_protector::init();
+    #[cfg(feature = "boot-time")]
+    boot_time::mark("early boot");
+
+    print_banner();
     #[cfg(feature = "rtc")]
     ax_println!(
         "Boot at {}\n",
@@ -127,8 +231,16 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
 
     axlog::init();
     axlog::set_max_level(option_env!("AX_LOG").unwrap_or("")); // no effect if set `log-level-*` features
+    #[cfg(feature = "replay")]
+    axhal::replay::init();
+    #[cfg(feature = "syslog")]
+    if axhal::cmdline::param("syslog").is_some() {
+        // queue the messages from now on, sent once the network is up
+        axlog::set_remote_level(axhal::cmdline::param("syslog.level").unwrap_or("info"));
+    }
     info!("Logging is enabled.");
     info!("Primary CPU {} started, dtb = {:#x}.", cpu_id, dtb);
+    axhal::flags::init();
 
     info!("Found physcial memory regions:");
     for r in axhal::mem::memory_regions() {
@@ -141,34 +253,151 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
         );
     }
 
+    #[cfg(feature = "boot-time")]
+    boot_time::mark("console");
+
+    // before any free memory is used
+    #[cfg(feature = "snapshot")]
+    snapshot::restore(dtb);
+
     #[cfg(any(feature = "alloc", feature = "alt_alloc"))]
     init_allocator();
+    #[cfg(feature = "boot-time")]
+    boot_time::mark("allocator");
 
-    #[cfg(feature = "paging")]
+    // the memory of the hosted platform is mapped by the host
+    #[cfg(all(feature = "paging", not(feature = "hosted")))]
     axmm::init_memory_management();
+    #[cfg(feature = "boot-time")]
+    boot_time::mark("paging");
 
     info!("Initialize platform devices...");
     axhal::platform_init();
+    #[cfg(feature = "boot-time")]
+    boot_time::mark("hal");
 
     #[cfg(feature = "multitask")]
     axtask::init_scheduler();
+    #[cfg(feature = "boot-time")]
+    boot_time::mark("scheduler");
+
+    #[cfg(feature = "page-scrub")]
+    axtask::spawn_raw(
+        page_scrub_entry,
+        "page_scrub".into(),
+        axconfig::TASK_STACK_SIZE,
+    );
 
-    #[cfg(any(feature = "fs", feature = "net", feature = "display"))]
+    // the secondary CPUs run the driver probes while waiting for the others
+    #[cfg(all(feature = "smp", feature = "parallel-probe"))]
+    self::mp::start_secondary_cpus(cpu_id);
+
+    #[cfg(feature = "deferred-probe")]
+    #[allow(unused_mut)]
+    let mut pending = deferred::Pending::default();
+
+    #[cfg(any(
+        feature = "fs",
+        feature = "net",
+        feature = "display",
+        feature = "balloon",
+        feature = "crypto",
+        feature = "vsock",
+        feature = "ivshmem",
+        feature = "can",
+        feature = "deferred-probe"
+    ))]
     {
         #[allow(unused_variables)]
         let all_devices = axdriver::init_drivers();
+        #[cfg(feature = "boot-time")]
+        boot_time::mark("drivers");
 
         #[cfg(feature = "fs")]
-        axfs::init_filesystems(all_devices.block);
+        {
+            #[cfg(feature = "snapshot")]
+            snapshot::set_has_block_devices(!all_devices.block.is_empty());
+            axfs::init_filesystems(all_devices.block);
+            #[cfg(feature = "boot-time")]
+            boot_time::mark("fs mount");
+        }
 
         #[cfg(feature = "net")]
-        axnet::init_network(all_devices.net);
+        if cfg!(feature = "deferred-probe") && all_devices.net.is_empty() {
+            #[cfg(feature = "deferred-probe")]
+            {
+                pending.net = true;
+            }
+        } else {
+            axnet::init_network(all_devices.net);
+            #[cfg(feature = "boot-time")]
+            boot_time::mark("net up");
+        }
+
+        #[cfg(feature = "fs")]
+        {
+            axfs::procfs::register_file("cpu", "topology", proc_cpu_topology);
+            axfs::procfs::register_file("kernel", "flags", proc_kernel_flags);
+        }
+
+        #[cfg(all(feature = "fs", feature = "boot-time"))]
+        axfs::procfs::register_file("boot", "time", boot_time::proc_boot_time);
+
+        #[cfg(all(feature = "fs", feature = "net"))]
+        {
+            axfs::procfs::register_net_file("tcp", axnet::proc_net_tcp);
+            axfs::procfs::register_net_file("udp", axnet::proc_net_udp);
+        }
+
+        #[cfg(all(feature = "fs", feature = "tun"))]
+        axfs::devices::register_device("net/tun", alloc::sync::Arc::new(axnet::TunDev));
 
         #[cfg(feature = "display")]
-        axdisplay::init_display(all_devices.display);
+        if cfg!(feature = "deferred-probe") && all_devices.display.is_empty() {
+            #[cfg(feature = "deferred-probe")]
+            {
+                pending.display = true;
+            }
+        } else {
+            axdisplay::init_display(all_devices.display);
+            #[cfg(feature = "boot-time")]
+            boot_time::mark("display");
+        }
     }
 
-    #[cfg(feature = "smp")]
+    // the functions registered with `axhal::register_init!`
+    axhal::initcall::run_init_calls();
+    #[cfg(feature = "boot-time")]
+    boot_time::mark("init calls");
+
+    #[cfg(feature = "sntp")]
+    axtask::spawn_raw(sntp_entry, "sntp".into(), axconfig::TASK_STACK_SIZE);
+
+    #[cfg(feature = "mdns")]
+    axtask::spawn_raw(mdns_entry, "mdns".into(), axconfig::TASK_STACK_SIZE);
+
+    #[cfg(feature = "syslog")]
+    if axhal::cmdline::param("syslog").is_some() {
+        axtask::spawn_raw(syslog_entry, "syslog".into(), axconfig::TASK_STACK_SIZE);
+    }
+
+    #[cfg(feature = "diag-shell")]
+    axtask::spawn_raw(
+        shell::shell_entry,
+        "diag-shell".into(),
+        axconfig::TASK_STACK_SIZE,
+    );
+
+    #[cfg(feature = "balloon")]
+    axtask::spawn_raw(balloon_entry, "balloon".into(), axconfig::TASK_STACK_SIZE);
+
+    #[cfg(feature = "thermal")]
+    axtask::spawn_raw(thermal_entry, "thermal".into(), axconfig::TASK_STACK_SIZE);
+
+    #[cfg(feature = "cpufreq")]
+    axtask::spawn_raw(cpufreq_entry, "cpufreq".into(), axconfig::TASK_STACK_SIZE);
+
+    #[cfg(all(feature = "smp", not(feature = "parallel-probe")))]
     self::mp::start_secondary_cpus(cpu_id);
 
     #[cfg(feature = "irq")]
@@ -190,6 +419,19 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
         core::hint::spin_loop();
     }
 
+    #[cfg(feature = "boot-time")]
+    {
+        boot_time::mark("services");
+        boot_time::print_summary();
+    }
+
+    #[cfg(feature = "deferred-probe")]
+    axtask::spawn_raw(
+        move || deferred::deferred_probe_entry(pending),
+        "deferred-probe".into(),
+        axconfig::TASK_STACK_SIZE,
+    );
+
     unsafe { main() };
 
     #[cfg(feature = "multitask")]
@@ -204,9 +446,12 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
 #[cfg(feature = "alloc")]
 fn init_allocator() {
     use axhal::mem::{memory_regions, phys_to_virt, MemRegionFlags};
+    /// The end of the physical memory the devices with 32-bit DMA access.
+    const DMA32_LIMIT: usize = 1 << 32;
 
     info!("Initialize global memory allocator...");
     info!("  use {} allocator.", axalloc::global_allocator().name());
+    axalloc::global_allocator().set_dma_limit(phys_to_virt(DMA32_LIMIT.into()).as_usize());
 
     let mut max_region_size = 0;
     let mut max_region_paddr = 0.into();
@@ -291,6 +536,183 @@ fn init_interrupt() {
     axhal::arch::enable_irqs();
 }
 
+/// Lists the CPUs like `lscpu -p`, for `/proc/cpu/topology`.
+#[cfg(feature = "fs")]
+fn proc_cpu_topology() -> alloc::string::String {
+    use core::fmt::Write;
+    let mut text = alloc::string::String::from("# CPU,Core,Socket,Cluster,Thread,LLC\n");
+    for cpu in axhal::cpu::topology() {
+        writeln!(
+            text,
+            "{},{},{},{},{},{}",
+            cpu.cpu_id, cpu.core, cpu.package, cpu.cluster, cpu.thread, cpu.llc_id
+        )
+        .unwrap();
+    }
+    text
+}
+
+#[cfg(feature = "fs")]
+fn proc_kernel_flags() -> alloc::string::String {
+    use core::fmt::Write;
+    let mut text = alloc::string::String::from("# Flag,Enabled,Default\n");
+    for flag in axhal::flags::FLAGS {
+        writeln!(
+            text,
+            "{},{},{}",
+            flag.name(),
+            flag.is_enabled() as u8,
+            flag.default() as u8
+        )
+        .unwrap();
+    }
+    text
+}
+
+#[cfg(feature = "balloon")]
+fn balloon_entry() {
+    loop {
+        if axdriver::balloon::update() == 0 {
+            // the balloon has the size set by the host
+            #[cfg(feature = "irq")]
+            axtask::sleep(core::time::Duration::from_millis(100));
+        }
+        axtask::yield_now();
+    }
+}
+
+#[cfg(feature = "thermal")]
+fn thermal_entry() {
+    use axdriver::thermal::ThermalState;
+
+    let mut throttled = false;
+    loop {
+        match axdriver::thermal::update() {
+            ThermalState::Normal if throttled => {
+                info!("thermal: throttling stopped");
+                axtask::set_background_throttle(0);
+                throttled = false;
+            }
+            ThermalState::Normal => {}
+            ThermalState::Throttled(level) => {
+                if !throttled {
+                    warn!("thermal: too hot, throttling the background tasks");
+                    throttled = true;
+                }
+                axtask::set_background_throttle(level as u8);
+            }
+            ThermalState::Critical => {
+                error!("thermal: critical temperature reached, shutting down");
+                axhal::misc::terminate();
+            }
+        }
+        #[cfg(feature = "irq")]
+        axtask::sleep(core::time::Duration::from_secs(1));
+        #[cfg(not(feature = "irq"))]
+        axtask::yield_now();
+    }
+}
+
+#[cfg(feature = "cpufreq")]
+fn cpufreq_entry() {
+    use axdriver::cpufreq::Governor;
+
+    /// The governor: `performance`, `powersave` or `ondemand`.
+    const GOVERNOR: &str = match option_env!("AX_CPUFREQ_GOVERNOR") {
+        Some(governor) if !governor.is_empty() => governor,
+        _ => "ondemand",
+    };
+    /// How often the load is sampled, in milliseconds.
+    const SAMPLING_PERIOD_MS: u64 = 100;
+
+    let governor = match GOVERNOR {
+        "performance" => Governor::Performance,
+        "powersave" => Governor::Powersave,
+        "ondemand" => Governor::Ondemand,
+        _ => {
+            warn!("cpufreq: unknown governor {:?}, using ondemand", GOVERNOR);
+            Governor::Ondemand
+        }
+    };
+    axdriver::cpufreq::set_governor(governor).ok();
+
+    let cpus = if cfg!(feature = "smp") {
+        axconfig::SMP
+    } else {
+        1
+    };
+    let mut last_time = axhal::time::monotonic_time();
+    let mut last_idle = axtask::idle_time();
+    loop {
+        #[cfg(feature = "irq")]
+        axtask::sleep(core::time::Duration::from_millis(SAMPLING_PERIOD_MS));
+        #[cfg(not(feature = "irq"))]
+        axtask::yield_now();
+
+        let (now, idle) = (axhal::time::monotonic_time(), axtask::idle_time());
+        let total = (now - last_time).as_nanos() * cpus as u128;
+        let idle_ratio = ((idle - last_idle).as_nanos() * 100)
+            .checked_div(total)
+            .unwrap_or(0);
+        axdriver::cpufreq::update(100 - idle_ratio.min(100) as u32);
+        (last_time, last_idle) = (now, idle);
+    }
+}
+
+#[cfg(feature = "sntp")]
+fn sntp_entry() {
+    /// The SNTP server, an IP address or a host name.
+    const NTP_SERVER: &str = match option_env!("AX_NTP_SERVER") {
+        Some(server) if !server.is_empty() => server,
+        _ => "pool.ntp.org",
+    };
+    axnet::sntp_client(NTP_SERVER)
+}
+
+/// The host name, announced as `<name>.local` by mDNS.
+#[cfg(any(feature = "mdns", feature = "syslog"))]
+const HOSTNAME: &str = match option_env!("AX_HOSTNAME") {
+    Some(name) if !name.is_empty() => name,
+    _ => "arceos",
+};
+
+#[cfg(feature = "mdns")]
+fn mdns_entry() {
+    axnet::mdns_responder(HOSTNAME)
+}
+
+#[cfg(feature = "syslog")]
+fn syslog_entry() {
+    let target = axhal::cmdline::param("syslog").unwrap();
+    axnet::syslog_client(target, HOSTNAME, |buf| {
+        let dropped = axlog::take_remote_dropped();
+        if dropped > 0 {
+            let msg = alloc::format!("{} log messages dropped", dropped);
+            let len = msg.len().min(buf.len());
+            buf[..len].copy_from_slice(&msg.as_bytes()[..len]);
+            return Some((axlog::Level::Warn, len));
+        }
+        axlog::take_remote_record(buf)
+    })
+}
+
+#[cfg(feature = "page-scrub")]
+fn page_scrub_entry() {
+    /// Number of pages zeroed before giving up the CPU.
+    const SCRUB_BATCH: usize = 16;
+
+    axtask::set_priority(19); // the lowest, if supported by the scheduler
+    axtask::current().set_background(true);
+    loop {
+        if axmm::scrub_free_pages(SCRUB_BATCH) == 0 {
+            // the pool is full, or there are no free pages
+            #[cfg(feature = "irq")]
+            axtask::sleep(core::time::Duration::from_millis(10));
+        }
+        axtask::yield_now();
+    }
+}
+
 #[cfg(all(feature = "tls", not(feature = "multitask")))]
 fn init_tls() {
     let main_tls = axhal::tls::TlsArea::alloc();
@@ -47,6 +47,10 @@ pub extern "C" fn rust_main_secondary(cpu_id: usize) -> ! {
     super::INITED_CPUS.fetch_add(1, Ordering::Relaxed);
 
     while !super::is_init_ok() {
+        // runs the driver probes spawned by the primary CPU meanwhile
+        #[cfg(feature = "parallel-probe")]
+        axtask::yield_now();
+        #[cfg(not(feature = "parallel-probe"))]
         core::hint::spin_loop();
     }
 
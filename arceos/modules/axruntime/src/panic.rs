@@ -0,0 +1,39 @@
+//! Unwinding of the panics (feature `unwind`).
+//!
+//! A panic unwinds the stack of the panicking task, running the destructors
+//! of its frames, up to the innermost [`catch_unwind`]. A task whose panic is
+//! not caught exits with `PANIC_EXIT_CODE`, the other tasks keep running.
+//! The system is terminated if the panic is not caught by any frame, e.g. in
+//! the main task or an interrupt handler.
+//!
+//! It requires the code to be built with `-C panic=unwind`, as done by
+//! `UNWIND=y`.
+
+use alloc::boxed::Box;
+use alloc::string::ToString;
+use core::any::Any;
+use core::panic::{PanicInfo, UnwindSafe};
+
+#[cfg(feature = "multitask")]
+pub use axtask::PANIC_EXIT_CODE;
+
+/// Invokes the closure `f`, returning `Err` with the payload of the panic if
+/// it panics.
+pub fn catch_unwind<F: FnOnce() -> R + UnwindSafe, R>(f: F) -> Result<R, Box<dyn Any + Send>> {
+    unwinding::panic::catch_unwind(f)
+}
+
+/// Unwinds the stack with `payload`, without calling the panic handler.
+///
+/// The system is terminated if no frame catches it.
+pub fn resume_unwind(payload: Box<dyn Any + Send>) -> ! {
+    let code = unwinding::panic::begin_panic(payload);
+    error!("failed to unwind: {}", code.0);
+    axhal::misc::terminate()
+}
+
+/// Unwinds the stack from the panic handler, with the message of the panic
+/// as payload.
+pub(crate) fn begin_unwind(info: &PanicInfo) -> ! {
+    resume_unwind(Box::new(info.to_string()))
+}
@@ -0,0 +1,419 @@
+//! The built-in diagnostic shell.
+//!
+//! It reads commands on the console, or with the `net` feature, from the TCP
+//! port in `AX_DIAG_SHELL_PORT` if set, so that the state of the kernel can be
+//! inspected even when there is no userspace shell.
+
+use alloc::{string::String, vec, vec::Vec};
+use core::fmt::{self, Write};
+
+const PROMPT: &str = "diag> ";
+
+/// Maximum length of a command line.
+const MAX_LINE_LEN: usize = 256;
+
+const COMMANDS: &[(&str, &str)] = &[
+    ("help", "show this help"),
+    ("ps", "list the tasks"),
+    ("free", "show the memory usage"),
+    ("mounts", "list the mounted filesystems"),
+    ("ifconfig", "show the network interfaces"),
+    ("netstat", "list the sockets"),
+    ("dmesg", "show the recent log messages"),
+    ("sensors", "show the temperatures"),
+    ("cpufreq", "show the CPU frequency"),
+    ("flags", "list or set the feature flags"),
+    ("uptime", "show the time since boot"),
+    ("reboot", "reset the system"),
+    ("poweroff", "shut down the system"),
+];
+
+/// Writes to the console, like `ax_print!`.
+struct Console;
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        axlog::print_fmt(format_args!("{}", s))
+    }
+}
+
+fn idle() {
+    #[cfg(feature = "irq")]
+    axtask::sleep(core::time::Duration::from_millis(10));
+    #[cfg(not(feature = "irq"))]
+    axtask::yield_now();
+}
+
+/// Runs a command line, writing the output to `out`.
+fn run(line: &str, out: &mut dyn Write) -> fmt::Result {
+    let mut args = line.split_whitespace();
+    let Some(cmd) = args.next() else {
+        return Ok(());
+    };
+    match cmd {
+        "help" => {
+            for (name, help) in COMMANDS {
+                writeln!(out, "  {:<10} {}", name, help)?;
+            }
+        }
+        "ps" => ps(out)?,
+        "free" => free(out)?,
+        "mounts" => mounts(out)?,
+        "ifconfig" => ifconfig(out)?,
+        "netstat" => netstat(out)?,
+        "dmesg" => dmesg(out)?,
+        "sensors" => sensors(out)?,
+        "cpufreq" => cpufreq(out)?,
+        "flags" => flags(&mut args, out)?,
+        "uptime" => {
+            let now = axhal::time::monotonic_time();
+            writeln!(out, "up {}.{:06}s", now.as_secs(), now.subsec_micros())?;
+        }
+        "reboot" => axhal::misc::reboot(),
+        "poweroff" => axhal::misc::terminate(),
+        _ => writeln!(out, "{}: command not found, try `help`", cmd)?,
+    }
+    Ok(())
+}
+
+fn ps(out: &mut dyn Write) -> fmt::Result {
+    let mut tasks = axtask::all_tasks();
+    tasks.sort_by_key(|task| task.id().as_u64());
+    writeln!(out, "{:>6}  {:<8} NAME", "ID", "STATE")?;
+    for task in tasks {
+        writeln!(
+            out,
+            "{:>6}  {:<8} {}",
+            task.id().as_u64(),
+            task.state_name(),
+            task.name()
+        )?;
+    }
+    Ok(())
+}
+
+fn free(out: &mut dyn Write) -> fmt::Result {
+    let allocator = axalloc::global_allocator();
+    let stats = allocator.stats();
+    writeln!(
+        out,
+        "{:<6} {:>12} {:>12} {:>12}",
+        "", "used", "free", "peak"
+    )?;
+    writeln!(
+        out,
+        "{:<6} {:>12} {:>12} {:>12}",
+        "bytes",
+        allocator.used_bytes(),
+        allocator.available_bytes(),
+        stats.peak_bytes
+    )?;
+    writeln!(
+        out,
+        "{:<6} {:>12} {:>12} {:>12}",
+        "pages",
+        allocator.used_pages(),
+        allocator.available_pages(),
+        stats.peak_pages
+    )?;
+    writeln!(
+        out,
+        "failed: {} allocations, {} page allocations",
+        stats.alloc_failures, stats.page_alloc_failures
+    )
+}
+
+#[cfg(feature = "fs")]
+fn mounts(out: &mut dyn Write) -> fmt::Result {
+    writeln!(
+        out,
+        "{:<16} {:<8} {:>12} {:>12}",
+        "PATH", "TYPE", "SIZE", "FREE"
+    )?;
+    for path in axfs::api::mount_points() {
+        match axfs::api::statfs(&path) {
+            Ok(stat) => writeln!(
+                out,
+                "{:<16} {:<8} {:>12} {:>12}{}",
+                path,
+                stat.fs_type,
+                stat.blocks * stat.block_size,
+                stat.blocks_free * stat.block_size,
+                if stat.read_only { " ro" } else { "" }
+            )?,
+            Err(e) => writeln!(out, "{:<16} ({:?})", path, e)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "fs"))]
+fn mounts(out: &mut dyn Write) -> fmt::Result {
+    writeln!(out, "no filesystem support")
+}
+
+#[cfg(feature = "net")]
+fn ifconfig(out: &mut dyn Write) -> fmt::Result {
+    for iface in axnet::interface_info() {
+        let m = iface.mac;
+        writeln!(
+            out,
+            "{}: ether {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            iface.name, m[0], m[1], m[2], m[3], m[4], m[5]
+        )?;
+        for (addr, prefix_len) in iface.addrs {
+            writeln!(out, "    inet {}/{}", addr, prefix_len)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "net")]
+fn netstat(out: &mut dyn Write) -> fmt::Result {
+    writeln!(
+        out,
+        "{:<5} {:>7} {:>7} {:<22} {:<22} STATE",
+        "PROTO", "RECV-Q", "SEND-Q", "LOCAL", "REMOTE"
+    )?;
+    for stat in axnet::socket_stats() {
+        let remote = match stat.remote_addr {
+            Some(addr) => alloc::format!("{}", addr),
+            None => String::from("*"),
+        };
+        writeln!(
+            out,
+            "{:<5} {:>7} {:>7} {:<22} {:<22} {:?}",
+            match stat.protocol {
+                axnet::SocketProtocol::Tcp => "tcp",
+                axnet::SocketProtocol::Udp => "udp",
+            },
+            stat.recv_queue,
+            stat.send_queue,
+            alloc::format!("{}", stat.local_addr),
+            remote,
+            stat.state
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "net"))]
+fn ifconfig(out: &mut dyn Write) -> fmt::Result {
+    writeln!(out, "no network support")
+}
+
+#[cfg(not(feature = "net"))]
+fn netstat(out: &mut dyn Write) -> fmt::Result {
+    ifconfig(out)
+}
+
+#[cfg(feature = "thermal")]
+fn sensors(out: &mut dyn Write) -> fmt::Result {
+    for sensor in axdriver::thermal::temperatures() {
+        match sensor.millicelsius {
+            Some(t) => writeln!(
+                out,
+                "{:<16} {}.{:01}°C",
+                sensor.name,
+                t / 1000,
+                t.abs() % 1000 / 100
+            )?,
+            None => writeln!(out, "{:<16} N/A", sensor.name)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "thermal"))]
+fn sensors(out: &mut dyn Write) -> fmt::Result {
+    writeln!(out, "no thermal support")
+}
+
+#[cfg(feature = "cpufreq")]
+fn cpufreq(out: &mut dyn Write) -> fmt::Result {
+    let Some(khz) = axdriver::cpufreq::cur_freq_khz() else {
+        return writeln!(out, "no frequency scaling driver");
+    };
+    writeln!(out, "current: {} kHz", khz)?;
+    writeln!(out, "governor: {:?}", axdriver::cpufreq::governor())?;
+    writeln!(
+        out,
+        "available: {:?} kHz",
+        axdriver::cpufreq::frequencies_khz()
+    )
+}
+
+#[cfg(not(feature = "cpufreq"))]
+fn cpufreq(out: &mut dyn Write) -> fmt::Result {
+    writeln!(out, "no frequency scaling support")
+}
+
+fn flags<'a>(args: &mut impl Iterator<Item = &'a str>, out: &mut dyn Write) -> fmt::Result {
+    let Some(name) = args.next() else {
+        for flag in axhal::flags::FLAGS {
+            let on_off = |enabled| if enabled { "on" } else { "off" };
+            writeln!(
+                out,
+                "{:<24} {:<3} (default {})",
+                flag.name(),
+                on_off(flag.is_enabled()),
+                on_off(flag.default())
+            )?;
+        }
+        return Ok(());
+    };
+    let Some(flag) = axhal::flags::find(name) else {
+        return writeln!(out, "{}: unknown flag", name);
+    };
+    match args.next() {
+        Some("on") => flag.set(true),
+        Some("off") => flag.set(false),
+        Some("default") => flag.reset(),
+        _ => return writeln!(out, "usage: flags NAME on|off|default"),
+    }
+    Ok(())
+}
+
+fn dmesg(out: &mut dyn Write) -> fmt::Result {
+    let mut buf = vec![0; axlog::LOG_BUFFER_SIZE];
+    let len = axlog::read_log_buffer(&mut buf);
+    out.write_str(&String::from_utf8_lossy(&buf[..len]))
+}
+
+/// Edits a command line with the bytes received.
+struct LineEditor {
+    line: Vec<u8>,
+}
+
+impl LineEditor {
+    const fn new() -> Self {
+        Self { line: Vec::new() }
+    }
+
+    /// Handles a byte, echoing it to `echo`. Returns the line once complete.
+    fn input(&mut self, c: u8, echo: &mut dyn Write) -> Option<String> {
+        match c {
+            b'\r' | b'\n' => {
+                echo.write_str("\n").ok();
+                let line = String::from_utf8_lossy(&self.line).into_owned();
+                self.line.clear();
+                return Some(line);
+            }
+            // backspace or delete
+            0x08 | 0x7f => {
+                if self.line.pop().is_some() {
+                    echo.write_str("\x08 \x08").ok();
+                }
+            }
+            c if (0x20..0x7f).contains(&c) && self.line.len() < MAX_LINE_LEN => {
+                self.line.push(c);
+                echo.write_char(c as char).ok();
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+/// Discards the output, for the clients that echo locally.
+struct NoEcho;
+
+impl Write for NoEcho {
+    fn write_str(&mut self, _s: &str) -> fmt::Result {
+        Ok(())
+    }
+}
+
+fn console_shell() -> ! {
+    let mut editor = LineEditor::new();
+    Console.write_str(PROMPT).ok();
+    loop {
+        let Some(c) = axhal::console::getchar() else {
+            idle();
+            continue;
+        };
+        if let Some(line) = editor.input(c, &mut Console) {
+            run(&line, &mut Console).ok();
+            Console.write_str(PROMPT).ok();
+        }
+    }
+}
+
+#[cfg(feature = "net")]
+fn tcp_shell(port: u16) -> ! {
+    use axnet::TcpSocket;
+    use core::net::{Ipv4Addr, SocketAddr};
+
+    fn serve(client: &TcpSocket) -> axerrno::AxResult {
+        let mut editor = LineEditor::new();
+        let mut buf = [0; 256];
+        client.send(PROMPT.as_bytes())?;
+        loop {
+            let n = client.recv(&mut buf)?;
+            if n == 0 {
+                return Ok(());
+            }
+            for &c in &buf[..n] {
+                if c == b'\n' && editor.line.is_empty() {
+                    continue; // the end of a "\r\n"
+                }
+                if let Some(line) = editor.input(c, &mut NoEcho) {
+                    if line.trim() == "exit" {
+                        return Ok(());
+                    }
+                    let mut output = String::new();
+                    run(&line, &mut output).ok();
+                    output.push_str(PROMPT);
+                    send_all(client, output.as_bytes())?;
+                }
+            }
+        }
+    }
+
+    fn send_all(client: &TcpSocket, mut data: &[u8]) -> axerrno::AxResult {
+        while !data.is_empty() {
+            let n = client.send(data)?;
+            data = &data[n..];
+        }
+        Ok(())
+    }
+
+    let listener = TcpSocket::new();
+    let addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port);
+    listener.bind(addr).expect("diag-shell: failed to bind");
+    listener.listen().expect("diag-shell: failed to listen");
+    info!("diagnostic shell listening on TCP port {}", port);
+    loop {
+        match listener.accept() {
+            Ok(client) => {
+                if let Err(e) = serve(&client) {
+                    warn!("diag-shell: {:?}", e);
+                }
+                client.shutdown().ok();
+            }
+            Err(e) => {
+                warn!("diag-shell: failed to accept: {:?}", e);
+                idle();
+            }
+        }
+    }
+}
+
+/// The entry of the shell task.
+pub(crate) fn shell_entry() {
+    /// The TCP port of the shell, or empty to use the console.
+    const PORT: &str = match option_env!("AX_DIAG_SHELL_PORT") {
+        Some(port) => port,
+        None => "",
+    };
+    #[cfg(feature = "net")]
+    if !PORT.is_empty() {
+        let port = PORT.parse().expect("invalid AX_DIAG_SHELL_PORT");
+        tcp_shell(port);
+    }
+    #[cfg(not(feature = "net"))]
+    if !PORT.is_empty() {
+        warn!("diag-shell: no network support, using the console");
+    }
+    console_shell()
+}
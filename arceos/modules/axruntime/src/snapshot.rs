@@ -0,0 +1,69 @@
+//! Snapshot and restore of the system, see `axhal::snapshot`.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use axerrno::{ax_err, AxResult};
+use axhal::snapshot::SnapshotError;
+
+/// Whether there are block devices, whose state (e.g. the caches of the
+/// filesystems) cannot be resumed.
+static HAS_BLOCK_DEVICES: AtomicBool = AtomicBool::new(false);
+
+/// Restores the snapshot given by `snapshot.restore=` on the kernel command
+/// line, if any. It does not return if it is restored.
+pub(crate) fn restore(dtb: usize) {
+    let Some(path) = axhal::cmdline::param("snapshot.restore") else {
+        return;
+    };
+    info!("Restore the snapshot {:?}...", path);
+    let err = axhal::snapshot::restore_snapshot(path, dtb);
+    warn!("Cannot restore the snapshot {:?}: {:?}, booting", path, err);
+}
+
+#[allow(dead_code)]
+pub(crate) fn set_has_block_devices(has: bool) {
+    HAS_BLOCK_DEVICES.store(has, Ordering::Relaxed);
+}
+
+/// Probes the devices again after a restore, and gives them to the
+/// subsystems.
+fn resume_devices() {
+    #[cfg(any(feature = "net", feature = "display"))]
+    {
+        #[allow(unused_variables)]
+        let all_devices = axdriver::init_drivers();
+        #[cfg(feature = "net")]
+        axnet::resume_network(all_devices.net);
+        #[cfg(feature = "display")]
+        axdisplay::resume_display(all_devices.display);
+    }
+}
+
+/// Takes a snapshot of the system to the file of the host at `path`.
+///
+/// It returns `false` once the snapshot is written, and `true` when the
+/// system is resumed from it by a later boot with `snapshot.restore=<path>`
+/// on the kernel command line, with the devices probed again.
+///
+/// It is refused if there are block devices. The network connections are
+/// kept, but the peers have likely forgotten them at the restore.
+pub fn take_snapshot(path: &str) -> AxResult<bool> {
+    if HAS_BLOCK_DEVICES.load(Ordering::Relaxed) {
+        return ax_err!(Unsupported, "the block devices cannot be resumed");
+    }
+    match axhal::snapshot::take_snapshot(path) {
+        Ok(false) => {
+            info!("Snapshot written to {:?}", path);
+            Ok(false)
+        }
+        Ok(true) => {
+            resume_devices();
+            Ok(true)
+        }
+        Err(SnapshotError::Unsupported) => {
+            ax_err!(Unsupported, "snapshots need semihosting and one CPU")
+        }
+        Err(SnapshotError::Io) => ax_err!(Io, "cannot write the snapshot"),
+        Err(SnapshotError::BadImage) => ax_err!(InvalidData),
+    }
+}
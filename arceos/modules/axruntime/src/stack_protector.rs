@@ -0,0 +1,30 @@
+//! Stack smashing protection, for code built with `-Z stack-protector`.
+
+use core::ptr::addr_of_mut;
+
+/// The canary that protected functions put in their frames, and check before
+/// returning. It is randomized at boot.
+#[no_mangle]
+#[allow(non_upper_case_globals)]
+static mut __stack_chk_guard: usize = 0x595e_9fbd_94fd_a700;
+
+/// Randomizes the canary.
+///
+/// It must be inlined into a function that never returns, as the functions
+/// on the stack when the canary changes would fail their checks.
+#[inline(always)]
+pub(crate) fn init() {
+    // the low byte is 0 to stop overflows through string functions
+    let guard = axhal::misc::random() as usize & !0xff;
+    unsafe { core::ptr::write_volatile(addr_of_mut!(__stack_chk_guard), guard) };
+}
+
+/// Called by protected functions when their canary was overwritten.
+#[no_mangle]
+extern "C" fn __stack_chk_fail() -> ! {
+    #[cfg(feature = "multitask")]
+    if let Some(curr) = axtask::current_may_uninit() {
+        panic!("stack smashing detected in task {}", curr.id_name());
+    }
+    panic!("stack smashing detected")
+}
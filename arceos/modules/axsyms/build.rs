@@ -0,0 +1,14 @@
+fn main() {
+    // Embed the symbol table built by `tools/mksyms`, or an empty one if not
+    // given.
+    println!("cargo:rerun-if-env-changed=AX_KSYMS");
+    let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("ksyms.bin");
+    match std::env::var("AX_KSYMS").ok().filter(|p| !p.is_empty()) {
+        Some(path) => {
+            println!("cargo:rerun-if-changed={}", path);
+            std::fs::copy(&path, &out)
+                .unwrap_or_else(|e| panic!("failed to read symbol table {:?}: {}", path, e));
+        }
+        None => std::fs::write(&out, b"").unwrap(),
+    }
+}
@@ -0,0 +1,103 @@
+//! Stack backtraces following the frame pointers.
+//!
+//! The code must be built with frame pointers (`-C force-frame-pointers=yes`,
+//! added by `make KSYMS=y`), or the backtrace stops early.
+
+/// The maximum number of frames walked.
+const MAX_DEPTH: usize = 64;
+
+#[inline(always)]
+fn frame_pointer() -> usize {
+    let fp: usize;
+    unsafe {
+        #[cfg(target_arch = "x86_64")]
+        core::arch::asm!("mov {}, rbp", out(reg) fp);
+        #[cfg(target_arch = "aarch64")]
+        core::arch::asm!("mov {}, x29", out(reg) fp);
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        core::arch::asm!("mv {}, s0", out(reg) fp);
+        #[cfg(not(any(
+            target_arch = "x86_64",
+            target_arch = "aarch64",
+            target_arch = "riscv32",
+            target_arch = "riscv64"
+        )))]
+        {
+            fp = 0;
+        }
+    }
+    fp
+}
+
+/// Returns the caller's frame pointer and return address saved in the frame
+/// at `fp`.
+///
+/// # Safety
+///
+/// `fp` must point to a frame record.
+unsafe fn read_frame(fp: usize) -> (usize, usize) {
+    let fp = fp as *const usize;
+    if cfg!(any(target_arch = "riscv32", target_arch = "riscv64")) {
+        // the record is below the frame pointer
+        (*fp.sub(2), *fp.sub(1))
+    } else {
+        (*fp, *fp.add(1))
+    }
+}
+
+/// Calls `f` with the return address of each frame on the current stack,
+/// from the function `walk` is inlined into outwards, until it returns
+/// `false`.
+///
+/// The walk stops at a null, misaligned or decreasing frame pointer, or one
+/// beyond the size of a task stack, so a corrupted stack does not fault.
+#[inline(always)]
+fn walk(mut f: impl FnMut(usize) -> bool) {
+    let mut fp = frame_pointer();
+    let limit = fp.saturating_add(axconfig::TASK_STACK_SIZE);
+    for _ in 0..MAX_DEPTH {
+        let align = core::mem::align_of::<usize>();
+        if fp < 2 * align || fp % align != 0 || fp >= limit {
+            break;
+        }
+        let (next, ret) = unsafe { read_frame(fp) };
+        if ret == 0 || !f(ret) {
+            break;
+        }
+        if next <= fp {
+            break;
+        }
+        fp = next;
+    }
+}
+
+/// Calls `f` with the return address of each frame on the current stack,
+/// from the caller of `backtrace` outwards.
+#[inline(never)]
+pub fn backtrace(mut f: impl FnMut(usize)) {
+    walk(|ret| {
+        f(ret);
+        true
+    })
+}
+
+/// Returns the return address of the frame `depth` levels above the caller
+/// of `return_address`: 0 gives the address in the caller, 1 the address in
+/// the caller's caller...
+///
+/// Returns `None` if the stack is not that deep, or its frames cannot be
+/// followed.
+#[inline(never)]
+pub fn return_address(depth: usize) -> Option<usize> {
+    let mut level = 0;
+    let mut found = None;
+    walk(|ret| {
+        if level == depth {
+            found = Some(ret);
+            return false;
+        }
+        level += 1;
+        true
+    });
+    found
+}
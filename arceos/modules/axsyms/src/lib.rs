@@ -0,0 +1,160 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) kernel symbol table.
+//!
+//! The table of the code symbols is built by `tools/mksyms` from the kernel
+//! image, and embedded at build time by setting the `AX_KSYMS` environment
+//! variable to its path (`make KSYMS=y` does both). [`symbolize`] looks up an
+//! address in it, and [`backtrace`] walks the call stack.
+//!
+//! # Table layout
+//!
+//! All integers are little-endian.
+//!
+//! | Offset | Size | Content |
+//! |--------|------|---------|
+//! | 0 | 8 | Magic `b"AXSYMS\0\0"` |
+//! | 8 | 4 | Number of symbols |
+//! | 12 | 4 | Number of blocks |
+//! | 16 | 16 × blocks | Index: address of the first symbol (8), offset of the block in the data (4), reserved (4) |
+//! | ... | | Data |
+//!
+//! The symbols are sorted by address, in blocks of [`BLOCK_LEN`]. Each
+//! symbol in the data is the address delta from the previous one (ULEB128,
+//! 0 for the first of a block), the length of the prefix shared with the
+//! name of the previous one (1 byte, 0 for the first of a block), the length
+//! of the rest of the name (1 byte), and the rest of the name.
+
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(test)]
+mod tests;
+
+mod backtrace;
+
+use core::fmt;
+
+pub use self::backtrace::{backtrace, return_address};
+
+/// The number of symbols in a block of the table.
+pub const BLOCK_LEN: usize = 64;
+
+/// The maximum length of a symbol name, longer ones are truncated.
+pub const MAX_NAME_LEN: usize = 255;
+
+const MAGIC: &[u8; 8] = b"AXSYMS\0\0";
+const HEADER_SIZE: usize = 16;
+const INDEX_ENTRY_SIZE: usize = 16;
+
+/// The table embedded at build time, empty if none is given.
+static KSYMS: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/ksyms.bin"));
+
+/// The name of a symbol.
+#[derive(Clone)]
+pub struct Name {
+    buf: [u8; MAX_NAME_LEN],
+    len: usize,
+}
+
+impl Name {
+    /// Returns the name as a string.
+    pub fn as_str(&self) -> &str {
+        // names are truncated at character boundaries
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("?")
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+fn read_u32(table: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        table.get(pos..pos + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(table: &[u8], pos: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        table.get(pos..pos + 8)?.try_into().ok()?,
+    ))
+}
+
+fn read_uleb128(data: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut n = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        n |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(n);
+        }
+    }
+    None
+}
+
+/// Looks up `addr` in `table`, returns the name of the symbol containing it
+/// and the offset of `addr` in it.
+fn lookup(table: &[u8], addr: u64) -> Option<(Name, u64)> {
+    if table.get(..8)? != MAGIC {
+        return None;
+    }
+    let count = read_u32(table, 8)? as usize;
+    let blocks = read_u32(table, 12)? as usize;
+    let data = table.get(HEADER_SIZE + blocks * INDEX_ENTRY_SIZE..)?;
+    let block_addr = |i: usize| read_u64(table, HEADER_SIZE + i * INDEX_ENTRY_SIZE);
+
+    // the last block starting at or before `addr`
+    let (mut lo, mut hi) = (0, blocks);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        if block_addr(mid)? <= addr {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    let block = lo.checked_sub(1)?;
+    let mut pos = read_u32(table, HEADER_SIZE + block * INDEX_ENTRY_SIZE + 8)? as usize;
+
+    let mut name = Name {
+        buf: [0; MAX_NAME_LEN],
+        len: 0,
+    };
+    let mut found = None;
+    let mut sym_addr = block_addr(block)?;
+    for _ in 0..BLOCK_LEN.min(count.saturating_sub(block * BLOCK_LEN)) {
+        sym_addr += read_uleb128(data, &mut pos)?;
+        if sym_addr > addr {
+            break;
+        }
+        let prefix = *data.get(pos)? as usize;
+        let suffix = *data.get(pos + 1)? as usize;
+        let rest = data.get(pos + 2..pos + 2 + suffix)?;
+        if prefix > name.len || prefix + suffix > MAX_NAME_LEN {
+            return None;
+        }
+        name.buf[prefix..prefix + suffix].copy_from_slice(rest);
+        name.len = prefix + suffix;
+        pos += 2 + suffix;
+        found = Some(sym_addr);
+    }
+    found.map(|sym_addr| (name, addr - sym_addr))
+}
+
+/// Returns the name of the function containing `addr` and the offset of
+/// `addr` in it, or [`None`] if no symbol table is embedded.
+///
+/// An address after the last function is attributed to it.
+pub fn symbolize(addr: usize) -> Option<(Name, usize)> {
+    // keeps the compiler from relying on the content of the table, so the
+    // code is the same with and without it
+    let table = core::hint::black_box(KSYMS);
+    lookup(table, addr as u64).map(|(name, offset)| (name, offset as usize))
+}
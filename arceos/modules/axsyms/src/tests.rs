@@ -0,0 +1,24 @@
+use crate::lookup;
+
+fn table() -> Vec<u8> {
+    let mut table = b"AXSYMS\0\0".to_vec();
+    table.extend_from_slice(&3u32.to_le_bytes());
+    table.extend_from_slice(&1u32.to_le_bytes());
+    table.extend_from_slice(&0x1000u64.to_le_bytes());
+    table.extend_from_slice(&[0; 8]);
+    table.extend_from_slice(b"\x00\x00\x06a::foo");
+    table.extend_from_slice(b"\x10\x03\x03bar");
+    table.extend_from_slice(b"\x20\x00\x01b");
+    table
+}
+
+#[test]
+fn test_lookup() {
+    let table = table();
+    let sym = |addr| lookup(&table, addr).map(|(name, off)| (name.as_str().to_string(), off));
+    assert_eq!(sym(0xfff), None);
+    assert_eq!(sym(0x1000), Some(("a::foo".into(), 0)));
+    assert_eq!(sym(0x1015), Some(("a::bar".into(), 5)));
+    assert_eq!(sym(0x2000), Some(("b".into(), 0xfd0)));
+    assert!(lookup(&[], 0x1000).is_none());
+}
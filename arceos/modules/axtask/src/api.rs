@@ -5,7 +5,7 @@ use alloc::{string::String, sync::Arc};
 pub(crate) use crate::run_queue::{AxRunQueue, RUN_QUEUE};
 
 #[doc(cfg(feature = "multitask"))]
-pub use crate::task::{CurrentTask, TaskId, TaskInner};
+pub use crate::task::{CpuPlacement, CurrentTask, TaskId, TaskInner};
 #[doc(cfg(feature = "multitask"))]
 pub use crate::task_ext::{TaskExtMut, TaskExtRef};
 #[doc(cfg(feature = "multitask"))]
@@ -29,6 +29,18 @@ cfg_if::cfg_if! {
     }
 }
 
+/// The range of the priorities accepted by [`set_priority`], from the highest
+/// to the lowest, or [`None`] if the scheduler has no priorities.
+pub const PRIORITY_RANGE: Option<(isize, isize)> = if cfg!(feature = "sched_cfs") {
+    Some((-20, 19)) // nice values
+} else {
+    None
+};
+
+/// The exit code of a task whose panic is not caught, with the `unwind`
+/// feature.
+pub const PANIC_EXIT_CODE: i32 = 101;
+
 #[cfg(feature = "preempt")]
 struct KernelGuardIfImpl;
 
@@ -63,6 +75,11 @@ pub fn current() -> CurrentTask {
     CurrentTask::get()
 }
 
+/// Returns the name of the scheduler in use, e.g. `"CFS"`.
+pub fn scheduler_name() -> &'static str {
+    Scheduler::scheduler_name()
+}
+
 /// Initializes the task scheduler (for the primary CPU).
 pub fn init_scheduler() {
     info!("Initialize scheduling...");
@@ -119,6 +136,12 @@ where
     spawn_raw(f, "".into(), axconfig::TASK_STACK_SIZE)
 }
 
+/// Returns all the tasks that are not dropped yet, including the exited ones
+/// whose resources are not recycled.
+pub fn all_tasks() -> alloc::vec::Vec<AxTaskRef> {
+    crate::task::all_tasks()
+}
+
 /// Set the priority for current task.
 ///
 /// The range of the priority is dependent on the underlying scheduler. For
@@ -132,6 +155,21 @@ pub fn set_priority(prio: isize) -> bool {
     RUN_QUEUE.lock().set_current_priority(prio)
 }
 
+/// Sets the kind of CPUs the current task runs on, moving it to one of them
+/// if needed.
+pub fn set_current_placement(placement: CpuPlacement) {
+    current().set_placement(placement);
+    if !placement.allows_this_cpu() {
+        yield_now();
+    }
+}
+
+/// Keeps the background tasks (see [`TaskInner::set_background`]) from
+/// running `percent` of the time, e.g. to cool the system down.
+pub fn set_background_throttle(percent: u8) {
+    crate::task::set_background_throttle(percent);
+}
+
 /// Current task gives up the CPU time voluntarily, and switches to another
 /// ready task.
 pub fn yield_now() {
@@ -142,10 +180,11 @@ pub fn yield_now() {
 ///
 /// If the feature `irq` is not enabled, it uses busy-wait instead.
 pub fn sleep(dur: core::time::Duration) {
-    sleep_until(axhal::time::wall_time() + dur);
+    sleep_until(axhal::time::monotonic_time() + dur);
 }
 
-/// Current task is going to sleep, it will be woken up at the given deadline.
+/// Current task is going to sleep, it will be woken up at the given deadline
+/// (in monotonic time).
 ///
 /// If the feature `irq` is not enabled, it uses busy-wait instead.
 pub fn sleep_until(deadline: axhal::time::TimeValue) {
@@ -155,11 +194,37 @@ pub fn sleep_until(deadline: axhal::time::TimeValue) {
     axhal::time::busy_wait_until(deadline);
 }
 
+/// Switches the current task to the page table whose root is `root`, and
+/// returns the root of the previous one.
+///
+/// The task keeps it when it is switched out and back in, until it is
+/// switched again.
+#[cfg(feature = "uspace")]
+pub fn switch_current_page_table(root: memory_addr::PhysAddr) -> memory_addr::PhysAddr {
+    let _guard = kernel_guard::NoPreemptIrqSave::new();
+    let old = axhal::arch::read_page_table_root();
+    unsafe {
+        (*current().ctx_mut_ptr()).set_page_table_root(root);
+        axhal::arch::write_page_table_root(root);
+    }
+    old
+}
+
 /// Exits the current task.
 pub fn exit(exit_code: i32) -> ! {
     RUN_QUEUE.lock().exit_current(exit_code)
 }
 
+/// Nanoseconds spent by the idle tasks waiting for IRQs, on all the CPUs.
+static IDLE_NANOS: core::sync::atomic::AtomicU64 = core::sync::atomic::AtomicU64::new(0);
+
+/// Returns the time spent idle since boot, summed over all the CPUs.
+///
+/// It only grows with the `irq` feature, as the CPUs never wait otherwise.
+pub fn idle_time() -> core::time::Duration {
+    core::time::Duration::from_nanos(IDLE_NANOS.load(core::sync::atomic::Ordering::Relaxed))
+}
+
 /// The idle task routine.
 ///
 /// It runs an infinite loop that keeps calling [`yield_now()`].
@@ -168,6 +233,11 @@ pub fn run_idle() -> ! {
         yield_now();
         debug!("idle task: waiting for IRQs...");
         #[cfg(feature = "irq")]
-        axhal::arch::wait_for_irqs();
+        {
+            let start = axhal::time::monotonic_time_nanos();
+            axhal::arch::wait_for_irqs();
+            let idle = axhal::time::monotonic_time_nanos() - start;
+            IDLE_NANOS.fetch_add(idle, core::sync::atomic::Ordering::Relaxed);
+        }
     }
 }
@@ -0,0 +1,125 @@
+//! Time budgets of composite blocking operations.
+//!
+//! An operation made of several blocking steps (e.g. resolving a name,
+//! connecting, then sending a request) can be given one overall [`Deadline`]
+//! with [`with_deadline`] or [`with_timeout`]. The deadline is attached to the
+//! current task, and the blocking primitives called meanwhile respect the
+//! remaining budget:
+//!
+//! - [`WaitQueue::wait_timeout`] and [`WaitQueue::wait_timeout_until`] time
+//!   out at the deadline at the latest;
+//! - blocking socket operations fail with `TimedOut` once it has passed.
+//!
+//! Nested budgets can only shorten the deadline of the enclosing one.
+//!
+//! Untimed waits ([`WaitQueue::wait`], [`WaitQueue::wait_until`]) are not
+//! bounded, as their callers (e.g. locks) rely on them returning only when
+//! notified.
+//!
+//! [`WaitQueue::wait_timeout`]: crate::WaitQueue::wait_timeout
+//! [`WaitQueue::wait_timeout_until`]: crate::WaitQueue::wait_timeout_until
+//! [`WaitQueue::wait`]: crate::WaitQueue::wait
+//! [`WaitQueue::wait_until`]: crate::WaitQueue::wait_until
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use axhal::time::{monotonic_time, monotonic_time_nanos, TimeValue};
+
+/// The raw value of [`Deadline::NONE`].
+const NO_DEADLINE: u64 = u64::MAX;
+
+/// A point in monotonic time by which an operation must complete, or no
+/// limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadline(u64);
+
+impl Deadline {
+    /// No deadline.
+    pub const NONE: Self = Self(NO_DEADLINE);
+
+    /// A deadline at the monotonic time `time`.
+    pub fn at(time: TimeValue) -> Self {
+        Self((time.as_nanos() as u64).min(NO_DEADLINE - 1))
+    }
+
+    /// A deadline `dur` from now.
+    pub fn after(dur: Duration) -> Self {
+        match monotonic_time().checked_add(dur) {
+            Some(time) => Self::at(time),
+            None => Self::NONE,
+        }
+    }
+
+    /// Returns the monotonic time of the deadline, or `None` if there is no
+    /// limit.
+    pub fn time(&self) -> Option<TimeValue> {
+        (self.0 != NO_DEADLINE).then(|| TimeValue::from_nanos(self.0))
+    }
+
+    /// Returns the time left until the deadline, zero if it has passed, or
+    /// `None` if there is no limit.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.time()
+            .map(|time| time.saturating_sub(monotonic_time()))
+    }
+
+    /// Whether the deadline has passed.
+    pub fn is_expired(&self) -> bool {
+        self.0 != NO_DEADLINE && monotonic_time_nanos() >= self.0
+    }
+
+    /// Returns the earlier of the two deadlines.
+    pub fn min(self, other: Self) -> Self {
+        Self(self.0.min(other.0))
+    }
+
+    /// Returns `timeout`, shortened to the time left until the deadline.
+    pub fn clamp(&self, timeout: Duration) -> Duration {
+        self.remaining().map_or(timeout, |left| timeout.min(left))
+    }
+}
+
+impl Default for Deadline {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+/// The deadline when there is no current task.
+static GLOBAL_DEADLINE: AtomicU64 = AtomicU64::new(NO_DEADLINE);
+
+fn with_slot<R>(f: impl FnOnce(&AtomicU64) -> R) -> R {
+    #[cfg(feature = "multitask")]
+    if let Some(curr) = crate::current_may_uninit() {
+        return f(curr.deadline());
+    }
+    f(&GLOBAL_DEADLINE)
+}
+
+/// Returns the deadline of the current task.
+pub fn current_deadline() -> Deadline {
+    with_slot(|slot| Deadline(slot.load(Ordering::Acquire)))
+}
+
+/// Replaces the deadline of the current task, and returns the previous one.
+///
+/// Prefer [`with_deadline`], which restores the previous deadline.
+pub fn set_deadline(deadline: Deadline) -> Deadline {
+    with_slot(|slot| Deadline(slot.swap(deadline.0, Ordering::AcqRel)))
+}
+
+/// Runs `f` with the deadline of the current task shortened to `deadline`.
+pub fn with_deadline<R>(deadline: Deadline, f: impl FnOnce() -> R) -> R {
+    let prev = current_deadline();
+    set_deadline(prev.min(deadline));
+    let ret = f();
+    set_deadline(prev);
+    ret
+}
+
+/// Runs `f` with the deadline of the current task shortened to `timeout`
+/// from now.
+pub fn with_timeout<R>(timeout: Duration, f: impl FnOnce() -> R) -> R {
+    with_deadline(Deadline::after(timeout), f)
+}
@@ -20,6 +20,12 @@
 //!   the `multitask` and `preempt` features if it is enabled.
 //! - `sched_cfs`: Use the [Completely Fair Scheduler][3]. It also enables the
 //!   the `multitask` and `preempt` features if it is enabled.
+//! - `shadow-call-stack`: Give each task its own shadow call stack on AArch64.
+//! - `sanitizer`: Log the windows with preemption disabled longer than
+//!   [`latency_threshold`], and the tasks blocking with preemption disabled,
+//!   with backtraces. It also enables the `preempt` feature.
+//! - `unwind`: Catch the panics unwinding out of the tasks, which exit with
+//!   [`PANIC_EXIT_CODE`] instead.
 //!
 //! [1]: scheduler::FifoScheduler
 //! [2]: scheduler::RRScheduler
@@ -36,6 +42,10 @@
 #[cfg(test)]
 mod tests;
 
+mod budget;
+
+pub use self::budget::{current_deadline, set_deadline, with_deadline, with_timeout, Deadline};
+
 cfg_if::cfg_if! {
     if #[cfg(feature = "multitask")] {
         #[macro_use]
@@ -50,10 +60,14 @@ cfg_if::cfg_if! {
 
         #[cfg(feature = "irq")]
         mod timers;
+        #[cfg(feature = "sanitizer")]
+        mod sanitizer;
 
         #[doc(cfg(feature = "multitask"))]
         pub use self::api::*;
         pub use self::api::{sleep, sleep_until, yield_now};
+        #[cfg(feature = "sanitizer")]
+        pub use self::sanitizer::{latency_threshold, set_latency_threshold};
     } else {
         mod api_s;
         pub use self::api_s::{sleep, sleep_until, yield_now};
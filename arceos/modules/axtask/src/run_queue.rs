@@ -15,6 +15,11 @@ static EXITED_TASKS: SpinNoIrq<VecDeque<AxTaskRef>> = SpinNoIrq::new(VecDeque::n
 
 static WAIT_FOR_EXIT: WaitQueue = WaitQueue::new();
 
+/// Maximum number of ready tasks looked at at each reschedule to find one
+/// allowed to run on the current CPU, by its [placement](crate::CpuPlacement)
+/// and the throttling of background tasks.
+const MAX_PLACEMENT_LOOKAHEAD: usize = 4;
+
 #[percpu::def_percpu]
 static IDLE_TASK: LazyInit<AxTaskRef> = LazyInit::new();
 
@@ -49,6 +54,8 @@ impl AxRunQueue {
         let curr = crate::current();
         trace!("task yield: {}", curr.id_name());
         assert!(curr.is_running());
+        #[cfg(feature = "sanitizer")]
+        crate::sanitizer::check_may_block(&curr, "yields");
         self.resched(false);
     }
 
@@ -109,6 +116,8 @@ impl AxRunQueue {
         assert!(!curr.is_idle());
 
         // we must not block current task with preemption disabled.
+        #[cfg(feature = "sanitizer")]
+        crate::sanitizer::check_may_block(&curr, "blocks");
         #[cfg(feature = "preempt")]
         assert!(curr.can_preempt(1));
 
@@ -135,8 +144,10 @@ impl AxRunQueue {
         debug!("task sleep: {}, deadline={:?}", curr.id_name(), deadline);
         assert!(curr.is_running());
         assert!(!curr.is_idle());
+        #[cfg(feature = "sanitizer")]
+        crate::sanitizer::check_may_block(&curr, "sleeps");
 
-        let now = axhal::time::wall_time();
+        let now = axhal::time::monotonic_time();
         if now < deadline {
             crate::timers::set_alarm_wakeup(deadline, curr.clone());
             curr.set_state(TaskState::Blocked);
@@ -156,13 +167,35 @@ impl AxRunQueue {
                 self.scheduler.put_prev_task(prev.clone(), preempt);
             }
         }
-        let next = self.scheduler.pick_next_task().unwrap_or_else(|| unsafe {
+        let next = self.pick_next_task().unwrap_or_else(|| unsafe {
             // Safety: IRQs must be disabled at this time.
             IDLE_TASK.current_ref_raw().get_unchecked().clone()
         });
         self.switch_to(prev, next);
     }
 
+    /// Picks the next task that may run on the current CPU. The tasks skipped
+    /// are put back for the other CPUs.
+    fn pick_next_task(&mut self) -> Option<AxTaskRef> {
+        let mut skipped: [Option<AxTaskRef>; MAX_PLACEMENT_LOOKAHEAD] = Default::default();
+        let mut next = None;
+        for slot in skipped.iter_mut() {
+            let Some(task) = self.scheduler.pick_next_task() else {
+                break;
+            };
+            if task.may_run_now() {
+                next = Some(task);
+                break;
+            }
+            *slot = Some(task);
+        }
+        // as preempted, so that they keep their place if the scheduler allows
+        for task in skipped.into_iter().rev().flatten() {
+            self.scheduler.put_prev_task(task, true);
+        }
+        next
+    }
+
     fn switch_to(&mut self, prev_task: CurrentTask, next_task: AxTaskRef) {
         trace!(
             "context switch: {} -> {}",
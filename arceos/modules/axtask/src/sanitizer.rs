@@ -0,0 +1,90 @@
+//! Detection of the sections hurting the scheduling latency (feature
+//! `sanitizer`).
+//!
+//! It measures how long each CPU runs with preemption disabled, from the
+//! first guard disabling it to the last one enabling it again. These windows
+//! contain the ones with IRQs disabled by `kernel_guard::NoPreemptIrqSave`
+//! (e.g. holding a `SpinNoIrq` lock), and the interrupt handlers. The
+//! windows longer than [`latency_threshold`] are logged with a backtrace of
+//! the code ending them, symbolized if the kernel embeds its symbol table
+//! (`KSYMS=y`).
+//!
+//! It also logs the tasks blocking, sleeping or yielding while preemption is
+//! disabled, e.g. holding a lock or in an interrupt handler.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use axhal::time::{monotonic_time_nanos, NANOS_PER_MICROS};
+
+use crate::task::TaskInner;
+
+static THRESHOLD_NANOS: AtomicU64 = AtomicU64::new(1_000_000);
+
+/// The time at which preemption was disabled on this CPU, 0 if it is
+/// enabled.
+#[percpu::def_percpu]
+static OFF_SINCE: u64 = 0;
+
+/// Returns the duration above which the windows with preemption disabled are
+/// logged, 1 ms by default.
+pub fn latency_threshold() -> Duration {
+    Duration::from_nanos(THRESHOLD_NANOS.load(Ordering::Relaxed))
+}
+
+/// Sets the duration above which the windows with preemption disabled are
+/// logged.
+pub fn set_latency_threshold(threshold: Duration) {
+    let nanos = threshold.as_nanos().min(u64::MAX as u128) as u64;
+    THRESHOLD_NANOS.store(nanos, Ordering::Relaxed);
+}
+
+/// Starts a window, when the current task disables preemption.
+///
+/// It must be called with preemption disabled, so that the task stays on
+/// this CPU.
+pub(crate) fn preempt_disabled() {
+    unsafe { OFF_SINCE.write_current_raw(monotonic_time_nanos()) };
+}
+
+/// Ends the window, when the current task is about to enable preemption, or
+/// starts with it enabled.
+///
+/// It must be called with preemption still disabled.
+pub(crate) fn preempt_enabled() {
+    let since = unsafe { OFF_SINCE.read_current_raw() };
+    if since == 0 {
+        return;
+    }
+    unsafe { OFF_SINCE.write_current_raw(0) };
+    let elapsed = monotonic_time_nanos().saturating_sub(since);
+    if elapsed > THRESHOLD_NANOS.load(Ordering::Relaxed) {
+        // preemption is still disabled, so logging starts no window
+        warn!(
+            "preemption disabled for {} us on CPU {}, ended by {}:",
+            elapsed / NANOS_PER_MICROS,
+            axhal::cpu::this_cpu_id(),
+            crate::current().id_name(),
+        );
+        print_backtrace();
+    }
+}
+
+/// Logs the current task `curr` if it blocks (`what` is "blocks", "sleeps",
+/// "yields") with preemption disabled, other than by the run queue lock.
+pub(crate) fn check_may_block(curr: &TaskInner, what: &str) {
+    if !curr.can_preempt(1) {
+        warn!("task {} {} with preemption disabled:", curr.id_name(), what);
+        print_backtrace();
+    }
+}
+
+fn print_backtrace() {
+    axsyms::backtrace(|ret| {
+        // the return address may be past the end of the calling function
+        match axsyms::symbolize(ret - 1) {
+            Some((name, offset)) => warn!("  {:#x} {}+{:#x}", ret, name, offset + 1),
+            None => warn!("  {:#x}", ret),
+        }
+    });
+}
@@ -1,4 +1,4 @@
-use alloc::{boxed::Box, string::String, sync::Arc};
+use alloc::{boxed::Box, string::String, sync::Arc, sync::Weak, vec::Vec};
 use core::ops::Deref;
 use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicU8, Ordering};
 use core::{alloc::Layout, cell::UnsafeCell, fmt, ptr::NonNull};
@@ -10,6 +10,7 @@ use core::sync::atomic::AtomicUsize;
 use axhal::tls::TlsArea;
 
 use axhal::arch::TaskContext;
+use kspin::SpinNoIrq;
 use memory_addr::{align_up_4k, VirtAddr};
 
 use crate::task_ext::AxTaskExt;
@@ -29,6 +30,52 @@ pub(crate) enum TaskState {
     Exited = 4,
 }
 
+/// The kind of CPUs a task runs on, on systems with cores of different
+/// capacities (e.g. big.LITTLE).
+///
+/// On other systems, all the CPUs are big and little at the same time.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CpuPlacement {
+    /// Any CPU.
+    Any = 0,
+    /// The fastest CPUs, e.g. for latency-critical tasks.
+    Big = 1,
+    /// The slower CPUs, e.g. for background tasks.
+    Little = 2,
+}
+
+impl CpuPlacement {
+    /// Returns whether the task may run on the current CPU.
+    pub(crate) fn allows_this_cpu(self) -> bool {
+        match self {
+            Self::Any => true,
+            _ if !axhal::cpu::is_heterogeneous() => true,
+            Self::Big => axhal::cpu::this_cpu_is_big(),
+            Self::Little => !axhal::cpu::this_cpu_is_big(),
+        }
+    }
+}
+
+/// The share of time background tasks are kept from running, in percent, see
+/// [`crate::set_background_throttle`].
+static BACKGROUND_THROTTLE: AtomicU8 = AtomicU8::new(0);
+
+/// The period over which [`BACKGROUND_THROTTLE`] applies, in milliseconds.
+const THROTTLE_PERIOD_MS: u64 = 100;
+
+pub(crate) fn set_background_throttle(percent: u8) {
+    BACKGROUND_THROTTLE.store(percent.min(100), Ordering::Relaxed);
+}
+
+/// All the tasks created, to list them in [`crate::all_tasks`].
+static ALL_TASKS: SpinNoIrq<Vec<Weak<AxTask>>> = SpinNoIrq::new(Vec::new());
+
+pub(crate) fn all_tasks() -> Vec<AxTaskRef> {
+    let all = ALL_TASKS.lock();
+    all.iter().filter_map(Weak::upgrade).collect()
+}
+
 /// The inner task structure.
 pub struct TaskInner {
     id: TaskId,
@@ -38,11 +85,16 @@ pub struct TaskInner {
 
     entry: Option<*mut dyn FnOnce()>,
     state: AtomicU8,
+    placement: AtomicU8,
+    is_background: AtomicBool,
 
     in_wait_queue: AtomicBool,
     #[cfg(feature = "irq")]
     in_timer_list: AtomicBool,
 
+    /// Deadline of the current time budget, see [`crate::Deadline`].
+    deadline: AtomicU64,
+
     #[cfg(feature = "preempt")]
     need_resched: AtomicBool,
     #[cfg(feature = "preempt")]
@@ -52,6 +104,8 @@ pub struct TaskInner {
     wait_for_exit: WaitQueue,
 
     kstack: Option<TaskStack>,
+    #[cfg(all(feature = "shadow-call-stack", target_arch = "aarch64"))]
+    shadow_stack: Option<TaskStack>,
     ctx: UnsafeCell<TaskContext>,
     task_ext: AxTaskExt,
 
@@ -105,6 +159,12 @@ impl TaskInner {
         t.entry = Some(Box::into_raw(Box::new(entry)));
         t.ctx_mut().init(task_entry as usize, kstack.top(), tls);
         t.kstack = Some(kstack);
+        #[cfg(all(feature = "shadow-call-stack", target_arch = "aarch64"))]
+        {
+            let shadow_stack = TaskStack::alloc(axhal::arch::SHADOW_CALL_STACK_SIZE);
+            t.ctx_mut().set_shadow_call_stack(shadow_stack.base());
+            t.shadow_stack = Some(shadow_stack);
+        }
         if t.name == "idle" {
             t.is_idle = true;
         }
@@ -126,6 +186,51 @@ impl TaskInner {
         alloc::format!("Task({}, {:?})", self.id.as_u64(), self.name)
     }
 
+    /// Gets the state of the task as a string, e.g. `"ready"`.
+    pub fn state_name(&self) -> &'static str {
+        match self.state() {
+            TaskState::Running => "running",
+            TaskState::Ready => "ready",
+            TaskState::Blocked => "blocked",
+            TaskState::Exited => "exited",
+        }
+    }
+
+    /// Gets the kind of CPUs the task runs on.
+    pub fn placement(&self) -> CpuPlacement {
+        match self.placement.load(Ordering::Relaxed) {
+            1 => CpuPlacement::Big,
+            2 => CpuPlacement::Little,
+            _ => CpuPlacement::Any,
+        }
+    }
+
+    /// Sets the kind of CPUs the task runs on. It takes effect the next time
+    /// the task is scheduled.
+    pub fn set_placement(&self, placement: CpuPlacement) {
+        self.placement.store(placement as u8, Ordering::Relaxed);
+    }
+
+    /// Marks the task as a background one, which is throttled when the system
+    /// is too hot, see [`crate::set_background_throttle`].
+    pub fn set_background(&self, background: bool) {
+        self.is_background.store(background, Ordering::Relaxed);
+    }
+
+    /// Returns whether the task may be picked by the current CPU now, as
+    /// allowed by its placement and throttling.
+    pub(crate) fn may_run_now(&self) -> bool {
+        if !self.placement().allows_this_cpu() {
+            return false;
+        }
+        if self.is_background.load(Ordering::Relaxed) {
+            let throttle = BACKGROUND_THROTTLE.load(Ordering::Relaxed) as u64;
+            let ms = axhal::time::monotonic_time_nanos() / axhal::time::NANOS_PER_MILLIS;
+            return ms % THROTTLE_PERIOD_MS * 100 >= throttle * THROTTLE_PERIOD_MS;
+        }
+        true
+    }
+
     /// Wait for the task to exit, and return the exit code.
     ///
     /// It will return immediately if the task has already exited (but not dropped).
@@ -171,9 +276,12 @@ impl TaskInner {
             is_init: false,
             entry: None,
             state: AtomicU8::new(TaskState::Ready as u8),
+            placement: AtomicU8::new(CpuPlacement::Any as u8),
+            is_background: AtomicBool::new(false),
             in_wait_queue: AtomicBool::new(false),
             #[cfg(feature = "irq")]
             in_timer_list: AtomicBool::new(false),
+            deadline: AtomicU64::new(u64::MAX),
             #[cfg(feature = "preempt")]
             need_resched: AtomicBool::new(false),
             #[cfg(feature = "preempt")]
@@ -181,6 +289,8 @@ impl TaskInner {
             exit_code: AtomicI32::new(0),
             wait_for_exit: WaitQueue::new(),
             kstack: None,
+            #[cfg(all(feature = "shadow-call-stack", target_arch = "aarch64"))]
+            shadow_stack: None,
             ctx: UnsafeCell::new(TaskContext::new()),
             task_ext: AxTaskExt::empty(),
             #[cfg(feature = "tls")]
@@ -206,7 +316,11 @@ impl TaskInner {
     }
 
     pub(crate) fn into_arc(self) -> AxTaskRef {
-        Arc::new(AxTask::new(self))
+        let task = Arc::new(AxTask::new(self));
+        let mut all = ALL_TASKS.lock();
+        all.retain(|t| t.strong_count() > 0);
+        all.push(Arc::downgrade(&task));
+        task
     }
 
     #[inline]
@@ -244,6 +358,11 @@ impl TaskInner {
         self.is_idle
     }
 
+    #[inline]
+    pub(crate) fn deadline(&self) -> &AtomicU64 {
+        &self.deadline
+    }
+
     #[inline]
     pub(crate) fn in_wait_queue(&self) -> bool {
         self.in_wait_queue.load(Ordering::Acquire)
@@ -281,12 +400,21 @@ impl TaskInner {
     #[inline]
     #[cfg(feature = "preempt")]
     pub(crate) fn disable_preempt(&self) {
-        self.preempt_disable_count.fetch_add(1, Ordering::Relaxed);
+        let _count = self.preempt_disable_count.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "sanitizer")]
+        if _count == 0 {
+            crate::sanitizer::preempt_disabled();
+        }
     }
 
     #[inline]
     #[cfg(feature = "preempt")]
     pub(crate) fn enable_preempt(&self, resched: bool) {
+        // before the task can be moved to another CPU
+        #[cfg(feature = "sanitizer")]
+        if self.can_preempt(1) {
+            crate::sanitizer::preempt_enabled();
+        }
         if self.preempt_disable_count.fetch_sub(1, Ordering::Relaxed) == 1 && resched {
             // If current task is pending to be preempted, do rescheduling.
             Self::current_check_preempt_pending();
@@ -363,6 +491,11 @@ impl TaskStack {
     pub const fn top(&self) -> VirtAddr {
         unsafe { core::mem::transmute(self.ptr.as_ptr().add(self.layout.size())) }
     }
+
+    #[cfg(all(feature = "shadow-call-stack", target_arch = "aarch64"))]
+    pub fn base(&self) -> VirtAddr {
+        VirtAddr::from(self.ptr.as_ptr() as usize)
+    }
 }
 
 impl Drop for TaskStack {
@@ -431,11 +564,21 @@ impl Deref for CurrentTask {
 extern "C" fn task_entry() -> ! {
     // release the lock that was implicitly held across the reschedule
     unsafe { crate::RUN_QUEUE.force_unlock() };
+    // the task starts with preemption enabled
+    #[cfg(feature = "sanitizer")]
+    crate::sanitizer::preempt_enabled();
     #[cfg(feature = "irq")]
     axhal::arch::enable_irqs();
     let task = crate::current();
     if let Some(entry) = task.entry {
-        unsafe { Box::from_raw(entry)() };
+        let entry = unsafe { Box::from_raw(entry) };
+        #[cfg(feature = "unwind")]
+        if unwinding::panic::catch_unwind(entry).is_err() {
+            // the panic handler has logged it
+            crate::exit(crate::PANIC_EXIT_CODE);
+        }
+        #[cfg(not(feature = "unwind"))]
+        entry();
     }
     crate::exit(0);
 }
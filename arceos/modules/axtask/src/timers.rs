@@ -1,5 +1,5 @@
 use alloc::sync::Arc;
-use axhal::time::wall_time;
+use axhal::time::monotonic_time;
 use kspin::SpinNoIrq;
 use lazyinit::LazyInit;
 use timer_list::{TimeValue, TimerEvent, TimerList};
@@ -33,7 +33,7 @@ pub fn cancel_alarm(task: &AxTaskRef) {
 
 pub fn check_events() {
     loop {
-        let now = wall_time();
+        let now = monotonic_time();
         let event = TIMER_LIST.lock().expire_one(now);
         if let Some((_deadline, event)) = event {
             event.callback(now);
@@ -96,10 +96,13 @@ impl WaitQueue {
 
     /// Blocks the current task and put it into the wait queue, until other tasks
     /// notify it, or the given duration has elapsed.
+    ///
+    /// It times out at the [deadline](crate::Deadline) of the current task at
+    /// the latest.
     #[cfg(feature = "irq")]
     pub fn wait_timeout(&self, dur: core::time::Duration) -> bool {
         let curr = crate::current();
-        let deadline = axhal::time::wall_time() + dur;
+        let deadline = axhal::time::monotonic_time() + crate::current_deadline().clamp(dur);
         debug!(
             "task wait_timeout: {} deadline={:?}",
             curr.id_name(),
@@ -121,13 +124,16 @@ impl WaitQueue {
     ///
     /// Note that even other tasks notify this task, it will not wake up until
     /// the above conditions are met.
+    ///
+    /// It times out at the [deadline](crate::Deadline) of the current task at
+    /// the latest.
     #[cfg(feature = "irq")]
     pub fn wait_timeout_until<F>(&self, dur: core::time::Duration, condition: F) -> bool
     where
         F: Fn() -> bool,
     {
         let curr = crate::current();
-        let deadline = axhal::time::wall_time() + dur;
+        let deadline = axhal::time::monotonic_time() + crate::current_deadline().clamp(dur);
         debug!(
             "task wait_timeout: {}, deadline={:?}",
             curr.id_name(),
@@ -136,7 +142,7 @@ impl WaitQueue {
         crate::timers::set_alarm_wakeup(deadline, curr.clone());
 
         let mut timeout = true;
-        while axhal::time::wall_time() < deadline {
+        while axhal::time::monotonic_time() < deadline {
             let mut rq = RUN_QUEUE.lock();
             if condition() {
                 timeout = false;
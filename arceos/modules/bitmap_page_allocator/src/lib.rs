@@ -0,0 +1,145 @@
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(test)]
+mod tests;
+
+use allocator::{AllocError, AllocResult, BaseAllocator, PageAllocator};
+use allocator_pages_at::PageAllocatorAt;
+use bitmap_allocator::BitAlloc;
+
+/// Support up to 4 GiB of memory with 4K pages.
+type BitAllocUsed = bitmap_allocator::BitAlloc1M;
+
+/// The largest alignment of the allocations. The bitmap starts at an address
+/// aligned to it, so that the aligned pages are the ones at aligned indexes.
+const MAX_ALIGN_1GB: usize = 0x4000_0000;
+
+/// Bitmap page allocator
+/// One bit per page tells whether it is free, for a single memory range.
+/// - Alloc looks for the first free run of pages with the alignment.
+/// - Alloc at a given address takes the pages if they are all free.
+/// - Dealloc sets the bits back.
+pub struct BitmapPageAllocator<const PAGE_SIZE: usize> {
+    base: usize,
+    /// The address of the first page.
+    start: usize,
+    total_pages: usize,
+    used_pages: usize,
+    inner: BitAllocUsed,
+}
+
+impl<const PAGE_SIZE: usize> BitmapPageAllocator<PAGE_SIZE> {
+    /// Creates an empty allocator, to be given its range by
+    /// [`BaseAllocator::init`].
+    pub const fn new() -> Self {
+        Self {
+            base: 0,
+            start: 0,
+            total_pages: 0,
+            used_pages: 0,
+            inner: BitAllocUsed::DEFAULT,
+        }
+    }
+
+    /// Gives up the range of the allocator, so that it can be given another
+    /// one by [`BaseAllocator::init`] in place, without building a new
+    /// (large) bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if some pages are still allocated.
+    pub fn reset(&mut self) {
+        assert_eq!(self.used_pages, 0, "pages still in use");
+        let first = self.index(self.start);
+        self.inner.remove(first..first + self.total_pages);
+        self.total_pages = 0;
+    }
+
+    /// Returns the index of the page at `addr` in the bitmap.
+    fn index(&self, addr: usize) -> usize {
+        (addr - self.base) / PAGE_SIZE
+    }
+}
+
+impl<const PAGE_SIZE: usize> Default for BitmapPageAllocator<PAGE_SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const PAGE_SIZE: usize> BaseAllocator for BitmapPageAllocator<PAGE_SIZE> {
+    fn init(&mut self, start: usize, size: usize) {
+        assert!(PAGE_SIZE.is_power_of_two());
+        let end = (start + size) & !(PAGE_SIZE - 1);
+        let start = start.next_multiple_of(PAGE_SIZE);
+        self.total_pages = end.saturating_sub(start) / PAGE_SIZE;
+        self.start = start;
+        self.base = start & !(MAX_ALIGN_1GB - 1);
+        let first = self.index(start);
+        assert!(first + self.total_pages <= BitAllocUsed::CAP);
+        self.inner.insert(first..first + self.total_pages);
+    }
+
+    fn add_memory(&mut self, _start: usize, _size: usize) -> AllocResult {
+        Err(AllocError::NoMemory) // only one memory range
+    }
+}
+
+impl<const PAGE_SIZE: usize> PageAllocator for BitmapPageAllocator<PAGE_SIZE> {
+    const PAGE_SIZE: usize = PAGE_SIZE;
+
+    fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        if align_pow2 > MAX_ALIGN_1GB
+            || !align_pow2.is_power_of_two()
+            || align_pow2 % PAGE_SIZE != 0
+        {
+            return Err(AllocError::InvalidParam);
+        }
+        let align_log2 = (align_pow2 / PAGE_SIZE).trailing_zeros() as usize;
+        let index = self
+            .inner
+            .alloc_contiguous(num_pages, align_log2)
+            .ok_or(AllocError::NoMemory)?;
+        self.used_pages += num_pages;
+        Ok(self.base + index * PAGE_SIZE)
+    }
+
+    fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
+        let first = self.index(pos);
+        self.inner.insert(first..first + num_pages);
+        self.used_pages -= num_pages;
+    }
+
+    fn total_pages(&self) -> usize {
+        self.total_pages
+    }
+
+    fn used_pages(&self) -> usize {
+        self.used_pages
+    }
+
+    fn available_pages(&self) -> usize {
+        self.total_pages - self.used_pages
+    }
+}
+
+impl<const PAGE_SIZE: usize> PageAllocatorAt for BitmapPageAllocator<PAGE_SIZE> {
+    fn alloc_pages_at(&mut self, base: usize, num_pages: usize) -> AllocResult<usize> {
+        let end = self.start + self.total_pages * PAGE_SIZE;
+        let in_memory = num_pages
+            .checked_mul(PAGE_SIZE)
+            .and_then(|size| base.checked_add(size))
+            .is_some_and(|e| self.start <= base && e <= end);
+        if base % PAGE_SIZE != 0 || num_pages == 0 || !in_memory {
+            return Err(AllocError::InvalidParam);
+        }
+        let first = self.index(base);
+        let pages = first..first + num_pages;
+        if !pages.clone().all(|i| self.inner.test(i)) {
+            return Err(AllocError::MemoryOverlap);
+        }
+        self.inner.remove(pages);
+        self.used_pages += num_pages;
+        Ok(base)
+    }
+}
@@ -0,0 +1,79 @@
+use allocator::{AllocError, BaseAllocator, PageAllocator};
+use allocator_pages_at::PageAllocatorAt;
+
+use crate::BitmapPageAllocator;
+
+const PAGE_SIZE: usize = 0x1000;
+const BASE: usize = 0x8000_0000;
+
+fn new_bitmap(num_pages: usize) -> BitmapPageAllocator<PAGE_SIZE> {
+    let mut bitmap = BitmapPageAllocator::new();
+    bitmap.init(BASE, num_pages * PAGE_SIZE);
+    bitmap
+}
+
+#[test]
+fn test_alloc_dealloc() {
+    let mut bitmap = new_bitmap(64);
+    let a = bitmap.alloc_pages(1, PAGE_SIZE).unwrap();
+    let b = bitmap.alloc_pages(4, 4 * PAGE_SIZE).unwrap();
+    assert_eq!(a, BASE);
+    assert_eq!(b % (4 * PAGE_SIZE), 0);
+    assert_eq!(bitmap.used_pages(), 5);
+    assert_eq!(bitmap.alloc_pages(64, PAGE_SIZE), Err(AllocError::NoMemory));
+    assert_eq!(
+        bitmap.alloc_pages(1, 3 * PAGE_SIZE),
+        Err(AllocError::InvalidParam)
+    );
+
+    bitmap.dealloc_pages(b, 4);
+    bitmap.dealloc_pages(a, 1);
+    assert_eq!(bitmap.used_pages(), 0);
+    assert_eq!(bitmap.alloc_pages(64, PAGE_SIZE), Ok(BASE));
+}
+
+#[test]
+fn test_alloc_pages_at() {
+    let mut bitmap = new_bitmap(64);
+    let at = BASE + 8 * PAGE_SIZE;
+    assert_eq!(bitmap.alloc_pages_at(at, 4), Ok(at));
+    assert_eq!(bitmap.used_pages(), 4);
+
+    // in use, or out of the memory
+    assert_eq!(
+        bitmap.alloc_pages_at(at + 3 * PAGE_SIZE, 2),
+        Err(AllocError::MemoryOverlap)
+    );
+    assert_eq!(
+        bitmap.alloc_pages_at(BASE + 63 * PAGE_SIZE, 2),
+        Err(AllocError::InvalidParam)
+    );
+    assert_eq!(
+        bitmap.alloc_pages_at(at + 1, 1),
+        Err(AllocError::InvalidParam)
+    );
+
+    // the others go around them
+    let all = bitmap.alloc_pages(8, PAGE_SIZE).unwrap();
+    assert_eq!(all, BASE);
+    assert_eq!(bitmap.alloc_pages(1, PAGE_SIZE), Ok(at + 4 * PAGE_SIZE));
+    bitmap.dealloc_pages(at, 4);
+    assert_eq!(bitmap.alloc_pages_at(at, 4), Ok(at));
+}
+
+#[test]
+fn test_reset() {
+    let mut bitmap = new_bitmap(64);
+    let a = bitmap.alloc_pages(4, PAGE_SIZE).unwrap();
+    bitmap.dealloc_pages(a, 4);
+    bitmap.reset();
+    assert_eq!(bitmap.total_pages(), 0);
+    assert_eq!(bitmap.alloc_pages(1, PAGE_SIZE), Err(AllocError::NoMemory));
+
+    // the old range is gone
+    let other = BASE + 0x4000_0000;
+    bitmap.init(other, 16 * PAGE_SIZE);
+    assert_eq!(bitmap.total_pages(), 16);
+    assert_eq!(bitmap.alloc_pages(32, PAGE_SIZE), Err(AllocError::NoMemory));
+    assert_eq!(bitmap.alloc_pages(16, PAGE_SIZE), Ok(other));
+}
@@ -0,0 +1,286 @@
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(test)]
+mod tests;
+
+use allocator::{AllocError, AllocResult, BaseAllocator, PageAllocator};
+
+/// The maximum number of memory ranges a [`BuddyPageAllocator`] manages.
+pub const MAX_ZONES: usize = 32;
+
+/// Number of block orders, a block of order `k` having `2^k` pages.
+const NUM_ORDERS: usize = 32;
+
+/// Buddy page allocator
+/// Free pages are kept in blocks of `2^k` pages aligned to their size, one
+/// free list per order:
+/// - Alloc takes the smallest block large enough, splitting it in halves
+///   (buddies) and giving back the pages beyond the request.
+/// - Dealloc merges the freed blocks with their free buddies, again and
+///   again, so the large blocks come back after fragmentation.
+///
+/// The free lists are written in the free blocks themselves. Each memory
+/// range (the one given to `init`, and the ones added by `add_memory`) starts
+/// with a bitmap of the pages beginning a free block, to find the buddies:
+///
+/// [ bitmap | pages ... ]
+/// |        |           |
+/// start   first       end
+pub struct BuddyPageAllocator<const PAGE_SIZE: usize> {
+    zones: [Zone; MAX_ZONES],
+    num_zones: usize,
+    /// The address of the first free block of each order, 0 if none.
+    free_lists: [usize; NUM_ORDERS],
+    total_pages: usize,
+    used_pages: usize,
+}
+
+/// A memory range of a [`BuddyPageAllocator`].
+#[derive(Clone, Copy)]
+struct Zone {
+    start: usize,
+    first: usize,
+    end: usize,
+}
+
+impl Zone {
+    const EMPTY: Self = Self {
+        start: 0,
+        first: 0,
+        end: 0,
+    };
+
+    fn contains(&self, start: usize, end: usize) -> bool {
+        self.first <= start && start < end && end <= self.end
+    }
+}
+
+/// The header of a free block, written in its first page.
+struct FreeBlock {
+    order: usize,
+    prev: usize,
+    next: usize,
+}
+
+impl FreeBlock {
+    fn at<'a>(addr: usize) -> &'a mut Self {
+        unsafe { &mut *(addr as *mut Self) }
+    }
+}
+
+impl<const PAGE_SIZE: usize> BuddyPageAllocator<PAGE_SIZE> {
+    /// Creates an empty allocator, to be given its range by
+    /// [`BaseAllocator::init`].
+    pub const fn new() -> Self {
+        Self {
+            zones: [Zone::EMPTY; MAX_ZONES],
+            num_zones: 0,
+            free_lists: [0; NUM_ORDERS],
+            total_pages: 0,
+            used_pages: 0,
+        }
+    }
+
+    const fn block_size(order: usize) -> usize {
+        PAGE_SIZE << order
+    }
+
+    fn zone_of(&self, start: usize, end: usize) -> Option<&Zone> {
+        self.zones[..self.num_zones]
+            .iter()
+            .find(|z| z.contains(start, end))
+    }
+
+    /// Returns the word of the bitmap and the bit of the page at `addr`.
+    fn bit_of(zone: &Zone, addr: usize) -> (*mut u64, u64) {
+        let page = (addr - zone.first) / PAGE_SIZE;
+        let word = unsafe { (zone.start as *mut u64).add(page / 64) };
+        (word, 1 << (page % 64))
+    }
+
+    fn set_free_bit(&self, addr: usize, free: bool) {
+        let zone = self.zone_of(addr, addr + PAGE_SIZE).unwrap();
+        let (word, bit) = Self::bit_of(zone, addr);
+        unsafe {
+            if free {
+                *word |= bit;
+            } else {
+                *word &= !bit;
+            }
+        }
+    }
+
+    /// Returns whether a free block of the order starts at `addr`.
+    fn is_free_block(&self, addr: usize, order: usize) -> bool {
+        let Some(zone) = self.zone_of(addr, addr + Self::block_size(order)) else {
+            return false;
+        };
+        let (word, bit) = Self::bit_of(zone, addr);
+        let free = unsafe { *word & bit != 0 };
+        free && FreeBlock::at(addr).order == order
+    }
+
+    fn push(&mut self, addr: usize, order: usize) {
+        let next = self.free_lists[order];
+        if next != 0 {
+            FreeBlock::at(next).prev = addr;
+        }
+        *FreeBlock::at(addr) = FreeBlock {
+            order,
+            prev: 0,
+            next,
+        };
+        self.free_lists[order] = addr;
+        self.set_free_bit(addr, true);
+    }
+
+    fn remove(&mut self, addr: usize, order: usize) {
+        let FreeBlock { prev, next, .. } = *FreeBlock::at(addr);
+        if prev == 0 {
+            self.free_lists[order] = next;
+        } else {
+            FreeBlock::at(prev).next = next;
+        }
+        if next != 0 {
+            FreeBlock::at(next).prev = prev;
+        }
+        self.set_free_bit(addr, false);
+    }
+
+    /// Frees a block, merging it with its buddies.
+    fn free_block(&mut self, mut addr: usize, mut order: usize) {
+        while order + 1 < NUM_ORDERS {
+            let buddy = addr ^ Self::block_size(order);
+            if !self.is_free_block(buddy, order) {
+                break;
+            }
+            self.remove(buddy, order);
+            addr = addr.min(buddy);
+            order += 1;
+        }
+        self.push(addr, order);
+    }
+
+    /// Frees the pages `[start, end)`, as the largest aligned blocks.
+    fn free_range(&mut self, mut start: usize, end: usize) {
+        while start < end {
+            let align_order = (start / PAGE_SIZE).trailing_zeros() as usize;
+            let fit_order = ((end - start) / PAGE_SIZE).ilog2() as usize;
+            let order = align_order.min(fit_order).min(NUM_ORDERS - 1);
+            self.free_block(start, order);
+            start += Self::block_size(order);
+        }
+    }
+
+    fn add_zone(&mut self, start: usize, size: usize) -> AllocResult {
+        let end = start.checked_add(size).ok_or(AllocError::InvalidParam)?;
+        let start = start
+            .checked_next_multiple_of(PAGE_SIZE)
+            .ok_or(AllocError::InvalidParam)?;
+        let end = end & !(PAGE_SIZE - 1);
+        if start >= end {
+            return Err(AllocError::InvalidParam);
+        }
+        if self.zones[..self.num_zones]
+            .iter()
+            .any(|z| start < z.end && z.start < end)
+        {
+            return Err(AllocError::MemoryOverlap);
+        }
+        if self.num_zones == MAX_ZONES {
+            return Err(AllocError::NoMemory);
+        }
+        // the bitmap, rounded up to pages
+        let pages = (end - start) / PAGE_SIZE;
+        let bitmap_size = (pages.div_ceil(64) * 8).next_multiple_of(PAGE_SIZE);
+        let first = start + bitmap_size;
+        if first >= end {
+            return Err(AllocError::InvalidParam);
+        }
+        unsafe { core::ptr::write_bytes(start as *mut u8, 0, bitmap_size) };
+
+        self.zones[self.num_zones] = Zone { start, first, end };
+        self.num_zones += 1;
+        self.total_pages += (end - first) / PAGE_SIZE;
+        self.free_range(first, end);
+        Ok(())
+    }
+}
+
+impl<const PAGE_SIZE: usize> Default for BuddyPageAllocator<PAGE_SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const PAGE_SIZE: usize> BaseAllocator for BuddyPageAllocator<PAGE_SIZE> {
+    fn init(&mut self, start: usize, size: usize) {
+        *self = Self::new();
+        self.add_zone(start, size).unwrap();
+    }
+
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        self.add_zone(start, size)
+    }
+}
+
+impl<const PAGE_SIZE: usize> PageAllocator for BuddyPageAllocator<PAGE_SIZE> {
+    const PAGE_SIZE: usize = PAGE_SIZE;
+
+    fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        if !align_pow2.is_power_of_two() || num_pages == 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        // blocks are aligned to their size
+        let size_order = num_pages
+            .checked_next_power_of_two()
+            .ok_or(AllocError::NoMemory)?
+            .trailing_zeros() as usize;
+        let align_order = (align_pow2 / PAGE_SIZE).max(1).trailing_zeros() as usize;
+        let order = size_order.max(align_order);
+        if order >= NUM_ORDERS {
+            return Err(AllocError::NoMemory);
+        }
+
+        let mut found = (order..NUM_ORDERS)
+            .find(|&o| self.free_lists[o] != 0)
+            .ok_or(AllocError::NoMemory)?;
+        let addr = self.free_lists[found];
+        self.remove(addr, found);
+        while found > order {
+            found -= 1;
+            self.push(addr + Self::block_size(found), found);
+        }
+        // give back the pages beyond the request
+        let end = addr + num_pages * PAGE_SIZE;
+        self.free_range(end, addr + Self::block_size(order));
+        self.used_pages += num_pages;
+        Ok(addr)
+    }
+
+    fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
+        let Some(end) = num_pages
+            .checked_mul(PAGE_SIZE)
+            .and_then(|size| pos.checked_add(size))
+        else {
+            return;
+        };
+        if pos % PAGE_SIZE != 0 || self.zone_of(pos, end).is_none() {
+            return;
+        }
+        self.free_range(pos, end);
+        self.used_pages -= num_pages.min(self.used_pages);
+    }
+
+    fn total_pages(&self) -> usize {
+        self.total_pages
+    }
+
+    fn used_pages(&self) -> usize {
+        self.used_pages
+    }
+
+    fn available_pages(&self) -> usize {
+        self.total_pages - self.used_pages
+    }
+}
@@ -0,0 +1,64 @@
+use allocator::{AllocError, BaseAllocator, PageAllocator};
+
+use crate::BuddyPageAllocator;
+
+const PAGE_SIZE: usize = 0x1000;
+const NUM_PAGES: usize = 64;
+
+#[repr(align(0x40000))]
+struct Arena([u8; NUM_PAGES * PAGE_SIZE]);
+
+/// Returns an allocator on the arena, whose first page is the bitmap.
+fn new_buddy(arena: &Arena) -> (BuddyPageAllocator<PAGE_SIZE>, usize) {
+    let start = arena.0.as_ptr() as usize;
+    let mut buddy = BuddyPageAllocator::<PAGE_SIZE>::new();
+    buddy.init(start, NUM_PAGES * PAGE_SIZE);
+    assert_eq!(buddy.total_pages(), NUM_PAGES - 1);
+    (buddy, start)
+}
+
+#[test]
+fn test_coalescing() {
+    let arena = Box::new(Arena([0; NUM_PAGES * PAGE_SIZE]));
+    let (mut buddy, start) = new_buddy(&arena);
+
+    let pages: Vec<usize> = (0..NUM_PAGES - 1)
+        .map(|_| buddy.alloc_pages(1, PAGE_SIZE).unwrap())
+        .collect();
+    assert_eq!(buddy.alloc_pages(1, PAGE_SIZE), Err(AllocError::NoMemory));
+
+    // fragmented: no two contiguous free pages
+    for &page in pages.iter().step_by(2) {
+        buddy.dealloc_pages(page, 1);
+    }
+    assert!(buddy.alloc_pages(2, PAGE_SIZE).is_err());
+
+    // merged back
+    for &page in pages.iter().skip(1).step_by(2) {
+        buddy.dealloc_pages(page, 1);
+    }
+    assert_eq!(buddy.available_pages(), NUM_PAGES - 1);
+    assert_eq!(buddy.alloc_pages(32, PAGE_SIZE), Ok(start + 32 * PAGE_SIZE));
+    assert_eq!(buddy.alloc_pages(16, PAGE_SIZE), Ok(start + 16 * PAGE_SIZE));
+}
+
+#[test]
+fn test_exact_size_and_align() {
+    let arena = Box::new(Arena([0; NUM_PAGES * PAGE_SIZE]));
+    let (mut buddy, start) = new_buddy(&arena);
+
+    // only 3 pages are taken from the block of 4
+    let a = buddy.alloc_pages(3, PAGE_SIZE).unwrap();
+    assert_eq!(a % (4 * PAGE_SIZE), 0);
+    assert_eq!(buddy.used_pages(), 3);
+    assert_eq!(buddy.alloc_pages(1, PAGE_SIZE), Ok(a + 3 * PAGE_SIZE));
+
+    let b = buddy.alloc_pages(1, 16 * PAGE_SIZE).unwrap();
+    assert_eq!(b % (16 * PAGE_SIZE), 0);
+    assert_ne!(b, start);
+
+    buddy.dealloc_pages(a, 4);
+    buddy.dealloc_pages(b, 1);
+    assert_eq!(buddy.used_pages(), 0);
+    assert_eq!(buddy.alloc_pages(32, PAGE_SIZE), Ok(start + 32 * PAGE_SIZE));
+}
@@ -1,10 +1,28 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
-use allocator::{BaseAllocator, ByteAllocator, PageAllocator};
+#[cfg(test)]
+mod tests;
+
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
+use allocator_pages_at::PageAllocatorAt;
+use allocator_realloc::ReallocAllocator;
+use core::alloc::Layout;
+use core::iter;
+use core::ops::Range;
+use core::ptr::NonNull;
+
+/// The maximum number of memory ranges an [`EarlyAllocator`] manages.
+pub const MAX_REGIONS: usize = 8;
+/// The maximum number of nested marks of an [`EarlyAllocator`].
+pub const MAX_MARKS: usize = 4;
+/// The end of each byte allocation is rounded up to this alignment, so that
+/// rolling `b_pos` back leaves no padding behind the previous allocation.
+const MIN_ALIGN: usize = 8;
 
 /// Early memory allocator
 /// Use it before formal bytes-allocator and pages-allocator can work!
-/// This is a double-end memory range:
+/// Each of its memory ranges (the one given to `init`, and the ones added by
+/// `add_memory`) is a double-end memory range:
 /// - Alloc bytes forward
 /// - Alloc pages backward
 ///
@@ -14,18 +32,693 @@ use allocator::{BaseAllocator, ByteAllocator, PageAllocator};
 ///
 /// For bytes area, 'count' records number of allocations.
 /// When it goes down to ZERO, free bytes-used area.
-/// For pages area, it will never be freed!
+/// Freeing the most recent allocation also rolls `b_pos` back to its start,
+/// so that temporary buffers freed in LIFO order are reused at once, and lets
+/// it grow in place. The ends of the allocations are rounded up to 8 bytes,
+/// so that the ones aligned to at most 8 bytes leave no gaps rolled back.
+/// For pages area, the freed page runs are kept in a free list, sorted by
+/// address and written in the free pages themselves, to be reused by later
+/// allocations. The ones reaching `p_pos` give the pages back to the
+/// avail-area.
+///
+/// Bytes are taken from the first range with room, pages from the last one.
+///
+/// The bytes-used area of each range can be limited to a part of it, see
+/// [`set_byte_limit`](Self::set_byte_limit), so that the byte allocations
+/// cannot take the pages needed at boot, e.g. for the page tables.
+///
+/// A boot stage can also use it as a region allocator: take a [`mark`] before
+/// it, allocate freely, and [`restore`] the mark after it to free all the
+/// memory allocated since. [`reset`] frees all the allocations.
 ///
-pub struct EarlyAllocator;
+/// [`mark`]: Self::mark
+/// [`restore`]: Self::restore
+/// [`reset`]: Self::reset
+pub struct EarlyAllocator<const PAGE_SIZE: usize> {
+    regions: [Region; MAX_REGIONS],
+    num_regions: usize,
+    /// The percentage of each range the bytes-used area may take.
+    byte_limit: usize,
+    /// Number of marks not restored.
+    depth: usize,
+    /// The number of memory ranges at each mark.
+    mark_regions: [usize; MAX_MARKS],
+}
+
+/// A memory range of an [`EarlyAllocator`].
+#[derive(Clone, Copy)]
+struct Region {
+    start: usize,
+    end: usize,
+    b_pos: usize,
+    p_pos: usize,
+    /// The highest `b_pos` allowed by the byte limit.
+    b_max: usize,
+    count: usize,
+    /// The address of the first free page run, 0 if none.
+    free_list: usize,
+    /// Number of bytes in the free page runs.
+    free_size: usize,
+    /// Number of marks taken since the range was added, not restored.
+    depth: usize,
+    /// The marks, from the outermost.
+    marks: [RegionMark; MAX_MARKS],
+}
+
+/// A mark of the allocations of an [`EarlyAllocator`], returned by
+/// [`EarlyAllocator::mark`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EarlyMark {
+    /// Number of marks before this one.
+    depth: usize,
+}
+
+/// A mark in a [`Region`]: the allocations made since it stay between its
+/// positions, so that restoring it frees them all.
+#[derive(Clone, Copy)]
+struct RegionMark {
+    b_pos: usize,
+    p_pos: usize,
+    /// Number of byte allocations from `b_pos` to the next mark, still live.
+    count: usize,
+}
+
+impl RegionMark {
+    const EMPTY: Self = Self {
+        b_pos: 0,
+        p_pos: 0,
+        count: 0,
+    };
+}
+
+/// The header of a free page run, written in its first page.
+struct FreeRun {
+    size: usize,
+    /// The address of the next free page run, 0 if none.
+    next: usize,
+}
+
+/// Iterator over the free page runs of a [`Region`], as `(start, size)`.
+struct FreeRuns {
+    addr: usize,
+}
+
+impl Iterator for FreeRuns {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.addr == 0 {
+            return None;
+        }
+        let run = FreeRun::read(self.addr);
+        let start = core::mem::replace(&mut self.addr, run.next);
+        Some((start, run.size))
+    }
+}
+
+impl FreeRun {
+    fn read(addr: usize) -> Self {
+        unsafe { (addr as *const Self).read() }
+    }
+
+    fn write(addr: usize, size: usize, next: usize) {
+        unsafe { (addr as *mut Self).write(Self { size, next }) }
+    }
+}
+
+impl Region {
+    const EMPTY: Self = Self::new(0, 0, 100);
+
+    const fn new(start: usize, size: usize, byte_limit: usize) -> Self {
+        Self {
+            start,
+            end: start + size,
+            b_pos: start,
+            p_pos: start + size,
+            b_max: start + percent_of(size, byte_limit),
+            count: 0,
+            free_list: 0,
+            free_size: 0,
+            depth: 0,
+            marks: [RegionMark::EMPTY; MAX_MARKS],
+        }
+    }
+
+    /// The bytes-used area position of the innermost mark, not to go below.
+    fn b_mark(&self) -> usize {
+        match self.depth {
+            0 => self.start,
+            depth => self.marks[depth - 1].b_pos,
+        }
+    }
+
+    /// The `p_pos` of the innermost mark, not to go beyond.
+    fn p_mark(&self) -> usize {
+        match self.depth {
+            0 => self.end,
+            depth => self.marks[depth - 1].p_pos,
+        }
+    }
+
+    /// Whether `addr` is the `p_pos` of a mark.
+    fn at_mark(&self, addr: usize) -> bool {
+        self.marks[..self.depth].iter().any(|m| m.p_pos == addr)
+    }
+
+    fn mark(&mut self) {
+        self.marks[self.depth] = RegionMark {
+            b_pos: self.b_pos,
+            p_pos: self.p_pos,
+            count: 0,
+        };
+        self.depth += 1;
+    }
+
+    /// Restores the innermost mark.
+    fn restore(&mut self) {
+        self.depth -= 1;
+        let mark = self.marks[self.depth];
+        self.count -= mark.count;
+        self.b_pos = if self.count == 0 {
+            self.b_mark()
+        } else {
+            mark.b_pos
+        };
+        // the free page runs below the mark are freed with the avail-area,
+        // the ones above were freed before it or since
+        while self.free_list != 0 && self.free_list < mark.p_pos {
+            let run = FreeRun::read(self.free_list);
+            self.free_size -= run.size;
+            self.free_list = run.next;
+        }
+        self.p_pos = mark.p_pos;
+        self.absorb_free_runs();
+    }
+
+    fn alloc(&mut self, layout: Layout) -> Option<usize> {
+        let start = self.b_pos.checked_next_multiple_of(layout.align())?;
+        let end = start
+            .checked_add(layout.size())?
+            .checked_next_multiple_of(MIN_ALIGN)?;
+        if end > self.p_pos || end > self.b_max {
+            return None;
+        }
+        self.b_pos = end;
+        self.count += 1;
+        if self.depth > 0 {
+            self.marks[self.depth - 1].count += 1;
+        }
+        Some(start)
+    }
+
+    fn dealloc(&mut self, start: usize, size: usize) {
+        let end = (start + size).next_multiple_of(MIN_ALIGN);
+        if self.count == 0 || end > self.b_pos {
+            return;
+        }
+        self.count -= 1;
+        // the allocations since a mark are all beyond it
+        let marks = &mut self.marks[..self.depth];
+        if let Some(mark) = marks.iter_mut().rev().find(|m| m.b_pos <= start) {
+            mark.count -= 1;
+        }
+        if self.count == 0 {
+            self.b_pos = self.b_mark();
+        } else if end == self.b_pos {
+            // the most recent allocation, not before the mark
+            self.b_pos = start.max(self.b_mark());
+        }
+    }
+
+    /// Resizes the most recent allocation by moving `b_pos`. The others can
+    /// only shrink, their freed end being lost until `count` goes down to 0.
+    fn resize(&mut self, start: usize, old_size: usize, new_size: usize) -> bool {
+        let Some(end) = start
+            .checked_add(new_size)
+            .and_then(|end| end.checked_next_multiple_of(MIN_ALIGN))
+        else {
+            return false;
+        };
+        if (start + old_size).next_multiple_of(MIN_ALIGN) == self.b_pos
+            && start >= self.b_mark()
+            && end <= self.p_pos
+            && end <= self.b_max
+        {
+            self.b_pos = end;
+            return true;
+        }
+        new_size <= old_size
+    }
+
+    fn alloc_pages<const PAGE_SIZE: usize>(&mut self, size: usize, align: usize) -> Option<usize> {
+        let align = align.max(PAGE_SIZE);
+        if let Some(start) = self.alloc_free_pages(size, align) {
+            return Some(start);
+        }
+        let start = self.p_pos.checked_sub(size)? & !(align - 1);
+        if start < self.b_pos {
+            return None;
+        }
+        // the pages skipped for the alignment become a free run
+        let p_pos = core::mem::replace(&mut self.p_pos, start);
+        if start + size < p_pos {
+            self.free_pages(start + size, p_pos - start - size);
+        }
+        Some(start)
+    }
+
+    /// Takes the pages from the first free page run large enough, below the
+    /// mark.
+    fn alloc_free_pages(&mut self, size: usize, align: usize) -> Option<usize> {
+        let mut prev = 0;
+        let mut addr = self.free_list;
+        while addr != 0 && addr < self.p_mark() {
+            let run = FreeRun::read(addr);
+            let end = addr + run.size;
+            if let Some(start) = addr.checked_next_multiple_of(align) {
+                if start.checked_add(size).is_some_and(|e| e <= end) {
+                    self.unlink(prev, run.next);
+                    self.free_size -= run.size;
+                    // give back what is left on both sides
+                    if addr < start {
+                        self.free_pages(addr, start - addr);
+                    }
+                    if start + size < end {
+                        self.free_pages(start + size, end - start - size);
+                    }
+                    return Some(start);
+                }
+            }
+            prev = addr;
+            addr = run.next;
+        }
+        None
+    }
+
+    /// Takes the pages `[start, end)`, from the avail-area or from a free page
+    /// run below the mark.
+    fn alloc_pages_at(&mut self, start: usize, end: usize) -> AllocResult {
+        if end <= self.p_pos {
+            if start < self.b_pos {
+                return Err(AllocError::MemoryOverlap);
+            }
+            // the pages between them and `p_pos` become a free run
+            let p_pos = core::mem::replace(&mut self.p_pos, start);
+            if end < p_pos {
+                self.free_pages(end, p_pos - end);
+            }
+            return Ok(());
+        }
+        let mut prev = 0;
+        let mut addr = self.free_list;
+        while addr != 0 && addr <= start && addr < self.p_mark() {
+            let run = FreeRun::read(addr);
+            let run_end = addr + run.size;
+            if end <= run_end {
+                self.unlink(prev, run.next);
+                self.free_size -= run.size;
+                if addr < start {
+                    self.free_pages(addr, start - addr);
+                }
+                if end < run_end {
+                    self.free_pages(end, run_end - end);
+                }
+                return Ok(());
+            }
+            prev = addr;
+            addr = run.next;
+        }
+        Err(AllocError::MemoryOverlap)
+    }
 
-impl EarlyAllocator {
+    fn unlink(&mut self, prev: usize, next: usize) {
+        if prev == 0 {
+            self.free_list = next;
+        } else {
+            FreeRun::write(prev, FreeRun::read(prev).size, next);
+        }
+    }
+
+    /// Frees the page run `[start, start + size)`, merging it with the
+    /// neighbouring free runs. The runs are not merged across a mark, nor is
+    /// `p_pos` moved beyond the innermost one.
+    fn free_pages(&mut self, start: usize, size: usize) {
+        if start == self.p_pos && start != self.p_mark() {
+            self.p_pos += size;
+            self.absorb_free_runs();
+            return;
+        }
+        let mut prev = 0;
+        let mut next = self.free_list;
+        while next != 0 && next < start {
+            prev = next;
+            next = FreeRun::read(next).next;
+        }
+        self.free_size += size;
+        let (mut start, mut size) = (start, size);
+        if next == start + size && !self.at_mark(next) {
+            let run = FreeRun::read(next);
+            size += run.size;
+            next = run.next;
+        }
+        if prev != 0 {
+            let prev_run = FreeRun::read(prev);
+            if prev + prev_run.size == start && !self.at_mark(start) {
+                start = prev;
+                size += prev_run.size;
+                FreeRun::write(start, size, next);
+                return;
+            }
+            FreeRun::write(prev, prev_run.size, start);
+        } else {
+            self.free_list = start;
+        }
+        FreeRun::write(start, size, next);
+    }
+
+    /// Gives the free page runs reaching `p_pos` back to the avail-area, up to
+    /// the mark.
+    fn absorb_free_runs(&mut self) {
+        while self.free_list == self.p_pos && self.p_pos != self.p_mark() {
+            let run = FreeRun::read(self.free_list);
+            self.p_pos += run.size;
+            self.free_size -= run.size;
+            self.free_list = run.next;
+        }
+    }
+
+    fn dealloc_pages(&mut self, start: usize, size: usize) {
+        let Some(end) = start.checked_add(size) else {
+            return;
+        };
+        if size == 0 || start < self.p_pos || end > self.end {
+            return;
+        }
+        self.free_pages(start, size);
+    }
+
+    fn contains(&self, addr: usize) -> bool {
+        self.start <= addr && addr < self.end
+    }
+
+    fn free_runs(&self) -> FreeRuns {
+        FreeRuns {
+            addr: self.free_list,
+        }
+    }
+
+    /// The bytes-used area, and the pages used between the free page runs.
+    fn live_ranges(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        let mut pos = self.p_pos;
+        let pages = self
+            .free_runs()
+            .chain(iter::once((self.end, 0)))
+            .map(move |(start, size)| {
+                let used = pos..start;
+                pos = start + size;
+                used
+            });
+        iter::once(self.start..self.b_pos)
+            .chain(pages)
+            .filter(|r| !r.is_empty())
+    }
+
+    /// The avail-area, and the free page runs.
+    fn free_ranges(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        iter::once(self.b_pos..self.p_pos)
+            .chain(self.free_runs().map(|(start, size)| start..start + size))
+            .filter(|r| !r.is_empty())
+    }
+}
+
+impl<const PAGE_SIZE: usize> EarlyAllocator<PAGE_SIZE> {
+    /// Creates an empty allocator, to be given its range by
+    /// [`BaseAllocator::init`].
+    pub const fn new() -> Self {
+        Self::with_byte_limit(100)
+    }
+
+    /// Creates an empty allocator whose bytes-used areas may take at most
+    /// `percent` of each memory range, see [`set_byte_limit`](Self::set_byte_limit).
+    pub const fn with_byte_limit(percent: usize) -> Self {
+        assert!(percent <= 100);
+        Self {
+            regions: [Region::EMPTY; MAX_REGIONS],
+            num_regions: 0,
+            byte_limit: percent,
+            depth: 0,
+            mark_regions: [0; MAX_MARKS],
+        }
+    }
+
+    /// Limits the bytes-used area of each memory range to `percent` of it.
+    ///
+    /// The byte allocations beyond it fail with [`AllocError::NoMemory`],
+    /// even if there is room before the pages. The ones already made are
+    /// kept.
+    pub fn set_byte_limit(&mut self, percent: usize) {
+        assert!(percent <= 100);
+        self.byte_limit = percent;
+        for r in self.regions_mut() {
+            r.b_max = r.start + percent_of(r.end - r.start, percent);
+        }
+    }
+
+    /// Returns the percentage of each memory range the bytes-used area may
+    /// take.
+    pub fn byte_limit(&self) -> usize {
+        self.byte_limit
+    }
+
+    /// Returns a mark of the allocations made so far, to free the ones made
+    /// after it with [`restore`](Self::restore), or [`None`] if there are
+    /// already [`MAX_MARKS`] marks not restored.
+    ///
+    /// Until then, the allocations do not reuse the memory freed before the
+    /// mark, which stays free after it is restored.
+    pub fn mark(&mut self) -> Option<EarlyMark> {
+        if self.depth == MAX_MARKS {
+            return None;
+        }
+        for r in self.regions_mut() {
+            r.mark();
+        }
+        let depth = self.depth;
+        self.mark_regions[depth] = self.num_regions;
+        self.depth += 1;
+        Some(EarlyMark { depth })
+    }
+
+    /// Frees all the allocations made since `mark` was returned by
+    /// [`mark`](Self::mark), and restores the marks taken after it too. The
+    /// memory ranges added since are emptied, but kept.
+    ///
+    /// The allocations made before the mark and freed since stay freed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the mark was already restored.
+    pub fn restore(&mut self, mark: EarlyMark) {
+        assert!(mark.depth < self.depth, "mark already restored");
+        let levels = self.depth - mark.depth;
+        let num_regions = self.mark_regions[mark.depth];
+        let byte_limit = self.byte_limit;
+        for (i, r) in self.regions_mut().iter_mut().enumerate() {
+            if i < num_regions {
+                for _ in 0..levels {
+                    r.restore();
+                }
+            } else {
+                *r = Region::new(r.start, r.end - r.start, byte_limit);
+            }
+        }
+        self.depth = mark.depth;
+    }
+
+    /// Frees all the allocations, and forgets the marks.
+    pub fn reset(&mut self) {
+        let byte_limit = self.byte_limit;
+        for r in self.regions_mut() {
+            *r = Region::new(r.start, r.end - r.start, byte_limit);
+        }
+        self.depth = 0;
+    }
+
+    fn regions(&self) -> &[Region] {
+        &self.regions[..self.num_regions]
+    }
+
+    fn regions_mut(&mut self) -> &mut [Region] {
+        &mut self.regions[..self.num_regions]
+    }
+
+    /// Returns the memory ranges still used by allocations, bytes or pages,
+    /// in address order within each memory range of the allocator.
+    ///
+    /// The byte allocations are not tracked one by one: the range of each
+    /// bytes-used area contains all its allocations, and the space between
+    /// them.
+    pub fn live_ranges(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        self.regions().iter().flat_map(Region::live_ranges)
+    }
+
+    /// Returns the memory ranges not used by any allocation, which the final
+    /// allocator can take over.
+    ///
+    /// With [`live_ranges`](Self::live_ranges), they cover all the memory of
+    /// the allocator.
+    pub fn free_ranges(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        self.regions().iter().flat_map(Region::free_ranges)
+    }
 }
 
-impl BaseAllocator for EarlyAllocator {
+/// Returns `percent` of `size`, without overflow.
+const fn percent_of(size: usize, percent: usize) -> usize {
+    size / 100 * percent + size % 100 * percent / 100
 }
 
-impl ByteAllocator for EarlyAllocator {
+impl<const PAGE_SIZE: usize> Default for EarlyAllocator<PAGE_SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl PageAllocator for EarlyAllocator {
+impl<const PAGE_SIZE: usize> BaseAllocator for EarlyAllocator<PAGE_SIZE> {
+    fn init(&mut self, start: usize, size: usize) {
+        self.regions[0] = Region::new(start, size, self.byte_limit);
+        self.num_regions = 1;
+        self.depth = 0;
+    }
+
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        let end = start.checked_add(size).ok_or(AllocError::InvalidParam)?;
+        if size == 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        if self
+            .regions()
+            .iter()
+            .any(|r| start < r.end && r.start < end)
+        {
+            return Err(AllocError::MemoryOverlap);
+        }
+        if self.num_regions == MAX_REGIONS {
+            return Err(AllocError::NoMemory);
+        }
+        self.regions[self.num_regions] = Region::new(start, size, self.byte_limit);
+        self.num_regions += 1;
+        Ok(())
+    }
+}
+
+impl<const PAGE_SIZE: usize> ByteAllocator for EarlyAllocator<PAGE_SIZE> {
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let start = self
+            .regions_mut()
+            .iter_mut()
+            .find_map(|r| r.alloc(layout))
+            .ok_or(AllocError::NoMemory)?;
+        NonNull::new(start as *mut u8).ok_or(AllocError::NoMemory)
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
+        let start = pos.as_ptr() as usize;
+        if let Some(r) = self.regions_mut().iter_mut().find(|r| r.contains(start)) {
+            r.dealloc(start, layout.size());
+        }
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.regions().iter().map(|r| r.end - r.start).sum()
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.regions().iter().map(|r| r.b_pos - r.start).sum()
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.regions()
+            .iter()
+            .map(|r| r.p_pos.min(r.b_max).saturating_sub(r.b_pos))
+            .sum()
+    }
+}
+
+impl<const PAGE_SIZE: usize> PageAllocatorAt for EarlyAllocator<PAGE_SIZE> {
+    /// Takes the pages from the avail-area, moving `p_pos` down to them, or
+    /// from a free page run.
+    fn alloc_pages_at(&mut self, base: usize, num_pages: usize) -> AllocResult<usize> {
+        let end = num_pages
+            .checked_mul(PAGE_SIZE)
+            .and_then(|size| base.checked_add(size))
+            .ok_or(AllocError::InvalidParam)?;
+        if base % PAGE_SIZE != 0 || num_pages == 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        let r = self
+            .regions_mut()
+            .iter_mut()
+            .find(|r| r.contains(base) && end <= r.end)
+            .ok_or(AllocError::InvalidParam)?;
+        r.alloc_pages_at(base, end)?;
+        Ok(base)
+    }
+}
+
+impl<const PAGE_SIZE: usize> ReallocAllocator for EarlyAllocator<PAGE_SIZE> {
+    fn resize_in_place(&mut self, pos: NonNull<u8>, old_layout: Layout, new_size: usize) -> bool {
+        let start = pos.as_ptr() as usize;
+        self.regions_mut()
+            .iter_mut()
+            .find(|r| r.contains(start))
+            .is_some_and(|r| r.resize(start, old_layout.size(), new_size))
+    }
+}
+
+impl<const PAGE_SIZE: usize> PageAllocator for EarlyAllocator<PAGE_SIZE> {
+    const PAGE_SIZE: usize = PAGE_SIZE;
+
+    fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        if !align_pow2.is_power_of_two() {
+            return Err(AllocError::InvalidParam);
+        }
+        let size = num_pages
+            .checked_mul(PAGE_SIZE)
+            .ok_or(AllocError::NoMemory)?;
+        self.regions_mut()
+            .iter_mut()
+            .rev()
+            .find_map(|r| r.alloc_pages::<PAGE_SIZE>(size, align_pow2))
+            .ok_or(AllocError::NoMemory)
+    }
+
+    fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
+        if pos % PAGE_SIZE != 0 {
+            return;
+        }
+        if let Some(r) = self.regions_mut().iter_mut().find(|r| r.contains(pos)) {
+            r.dealloc_pages(pos, num_pages.saturating_mul(PAGE_SIZE));
+        }
+    }
+
+    fn total_pages(&self) -> usize {
+        self.regions()
+            .iter()
+            .map(|r| (r.end - r.start) / PAGE_SIZE)
+            .sum()
+    }
+
+    fn used_pages(&self) -> usize {
+        self.regions()
+            .iter()
+            .map(|r| (r.end - r.p_pos - r.free_size) / PAGE_SIZE)
+            .sum()
+    }
+
+    fn available_pages(&self) -> usize {
+        self.regions()
+            .iter()
+            .map(|r| (r.p_pos - r.b_pos + r.free_size) / PAGE_SIZE)
+            .sum()
+    }
 }
@@ -0,0 +1,266 @@
+use core::alloc::Layout;
+
+use allocator::{AllocError, BaseAllocator, ByteAllocator, PageAllocator};
+use allocator_pages_at::PageAllocatorAt;
+use allocator_realloc::ReallocAllocator;
+
+use crate::EarlyAllocator;
+
+const PAGE_SIZE: usize = 0x1000;
+
+#[repr(align(0x4000))]
+struct Arena([u8; 4 * PAGE_SIZE]);
+
+#[test]
+fn test_dealloc_rollback() {
+    let arena = Box::new(Arena([0; 4 * PAGE_SIZE]));
+    let start = arena.0.as_ptr() as usize;
+    let mut early = EarlyAllocator::<PAGE_SIZE>::new();
+    early.init(start, 4 * PAGE_SIZE);
+
+    let layout = Layout::from_size_align(100, 8).unwrap();
+    let a = early.alloc(layout).unwrap();
+    let b = early.alloc(layout).unwrap();
+    assert_eq!(a.as_ptr() as usize, start);
+    let used = early.used_bytes();
+
+    // the most recent one rolls back
+    let c = early.alloc(layout).unwrap();
+    early.dealloc(c, layout);
+    assert_eq!(early.used_bytes(), used);
+
+    // not the most recent one, kept until all are freed
+    early.dealloc(a, layout);
+    assert_eq!(early.used_bytes(), used);
+    early.dealloc(b, layout);
+    assert_eq!(early.used_bytes(), 0);
+    assert_eq!(early.alloc(layout).unwrap(), a);
+
+    let pages = early.alloc_pages(1, PAGE_SIZE).unwrap();
+    assert_eq!(pages, start + 3 * PAGE_SIZE);
+    assert_eq!(early.used_pages(), 1);
+    assert!(early.alloc_pages(4, PAGE_SIZE).is_err());
+}
+
+#[test]
+fn test_add_memory() {
+    let arena = Box::new(Arena([0; 4 * PAGE_SIZE]));
+    let start = arena.0.as_ptr() as usize;
+    let mut early = EarlyAllocator::<PAGE_SIZE>::new();
+    early.init(start, PAGE_SIZE);
+    assert!(early.add_memory(start + PAGE_SIZE / 2, PAGE_SIZE).is_err());
+    early
+        .add_memory(start + 2 * PAGE_SIZE, 2 * PAGE_SIZE)
+        .unwrap();
+    assert_eq!(early.total_bytes(), 3 * PAGE_SIZE);
+
+    // too big for the first range
+    let layout = Layout::from_size_align(2 * PAGE_SIZE, 8).unwrap();
+    let a = early.alloc(layout).unwrap();
+    assert_eq!(a.as_ptr() as usize, start + 2 * PAGE_SIZE);
+    assert!(early.alloc_pages(1, PAGE_SIZE).is_ok());
+    assert_eq!(early.alloc_pages(1, PAGE_SIZE), Err(AllocError::NoMemory));
+    early.dealloc(a, layout);
+    assert_eq!(early.alloc_pages(1, PAGE_SIZE), Ok(start + 3 * PAGE_SIZE));
+}
+
+#[test]
+fn test_byte_limit() {
+    let arena = Box::new(Arena([0; 4 * PAGE_SIZE]));
+    let start = arena.0.as_ptr() as usize;
+    let mut early = EarlyAllocator::<PAGE_SIZE>::with_byte_limit(50);
+    early.init(start, 4 * PAGE_SIZE);
+    assert_eq!(early.available_bytes(), 2 * PAGE_SIZE);
+
+    // the bytes stop at the half, the pages do not
+    let layout = Layout::from_size_align(PAGE_SIZE, 8).unwrap();
+    let a = early.alloc(layout).unwrap();
+    early.alloc(layout).unwrap();
+    assert_eq!(early.alloc(layout), Err(AllocError::NoMemory));
+    assert!(!early.resize_in_place(a, layout, 3 * PAGE_SIZE));
+    assert_eq!(early.alloc_pages(2, PAGE_SIZE), Ok(start + 2 * PAGE_SIZE));
+
+    early.set_byte_limit(25);
+    assert_eq!(early.available_bytes(), 0);
+    early.set_byte_limit(100);
+    assert_eq!(early.alloc_pages(1, PAGE_SIZE), Err(AllocError::NoMemory));
+}
+
+#[test]
+fn test_dealloc_pages() {
+    let arena = Box::new(Arena([0; 4 * PAGE_SIZE]));
+    let start = arena.0.as_ptr() as usize;
+    let mut early = EarlyAllocator::<PAGE_SIZE>::new();
+    early.init(start, 4 * PAGE_SIZE);
+
+    let a = early.alloc_pages(1, PAGE_SIZE).unwrap();
+    let b = early.alloc_pages(1, PAGE_SIZE).unwrap();
+    let c = early.alloc_pages(1, PAGE_SIZE).unwrap();
+    assert_eq!(c, start + PAGE_SIZE);
+
+    // reused from the free list
+    early.dealloc_pages(b, 1);
+    assert_eq!(early.used_pages(), 2);
+    assert_eq!(early.available_pages(), 2);
+    assert_eq!(early.alloc_pages(1, PAGE_SIZE), Ok(b));
+
+    // merged, then given back to the avail-area
+    early.dealloc_pages(a, 1);
+    early.dealloc_pages(b, 1);
+    assert_eq!(early.alloc_pages(2, PAGE_SIZE), Ok(b));
+    early.dealloc_pages(b, 2);
+    early.dealloc_pages(c, 1);
+    assert_eq!(early.used_pages(), 0);
+    assert_eq!(early.alloc_pages(4, PAGE_SIZE), Ok(start));
+}
+
+#[test]
+fn test_alloc_pages_align() {
+    let arena = Box::new(Arena([0; 4 * PAGE_SIZE]));
+    let start = arena.0.as_ptr() as usize;
+    let mut early = EarlyAllocator::<PAGE_SIZE>::new();
+    early.init(start, 4 * PAGE_SIZE);
+
+    // the pages skipped for the alignment are not lost
+    let a = early.alloc_pages(1, PAGE_SIZE).unwrap();
+    let b = early.alloc_pages(1, 4 * PAGE_SIZE).unwrap();
+    assert_eq!(b, start);
+    assert_eq!(early.used_pages(), 2);
+    assert_eq!(early.alloc_pages(2, PAGE_SIZE), Ok(start + PAGE_SIZE));
+    early.dealloc_pages(start + PAGE_SIZE, 2);
+    early.dealloc_pages(b, 1);
+    early.dealloc_pages(a, 1);
+    assert_eq!(early.used_pages(), 0);
+    assert_eq!(early.alloc_pages(4, PAGE_SIZE), Ok(start));
+}
+
+#[test]
+fn test_handoff_ranges() {
+    let arena = Box::new(Arena([0; 4 * PAGE_SIZE]));
+    let start = arena.0.as_ptr() as usize;
+    let end = start + 4 * PAGE_SIZE;
+    let mut early = EarlyAllocator::<PAGE_SIZE>::new();
+    early.init(start, 4 * PAGE_SIZE);
+
+    let layout = Layout::from_size_align(96, 8).unwrap();
+    early.alloc(layout).unwrap();
+    let a = early.alloc_pages(1, PAGE_SIZE).unwrap();
+    let b = early.alloc_pages(1, PAGE_SIZE).unwrap();
+    early.alloc_pages(1, PAGE_SIZE).unwrap();
+    early.dealloc_pages(b, 1);
+
+    let live: Vec<_> = early.live_ranges().collect();
+    assert_eq!(live, [start..start + 96, b - PAGE_SIZE..b, a..end]);
+    let free: Vec<_> = early.free_ranges().collect();
+    assert_eq!(free, [start + 96..start + PAGE_SIZE, b..a]);
+
+    // nothing left once all are freed
+    early.dealloc_pages(a, 1);
+    early.dealloc_pages(b - PAGE_SIZE, 1);
+    assert_eq!(early.live_ranges().count(), 1);
+    assert_eq!(
+        early.free_ranges().collect::<Vec<_>>(),
+        vec![start + 96..end]
+    );
+}
+
+#[test]
+fn test_realloc() {
+    let arena = Box::new(Arena([0; 4 * PAGE_SIZE]));
+    let start = arena.0.as_ptr() as usize;
+    let mut early = EarlyAllocator::<PAGE_SIZE>::new();
+    early.init(start, 4 * PAGE_SIZE);
+
+    // the most recent allocation grows in place, up to the pages
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let a = early.alloc(layout).unwrap();
+    early.alloc_pages(1, PAGE_SIZE).unwrap();
+    assert!(early.resize_in_place(a, layout, 2 * PAGE_SIZE));
+    assert_eq!(early.used_bytes(), 2 * PAGE_SIZE);
+    let big = Layout::from_size_align(2 * PAGE_SIZE, 8).unwrap();
+    assert!(!early.resize_in_place(a, big, 3 * PAGE_SIZE + 1));
+
+    // the others are moved
+    let b = early.alloc(layout).unwrap();
+    assert!(!early.resize_in_place(a, big, 2 * PAGE_SIZE + 8));
+    assert!(early.resize_in_place(a, big, 8));
+    assert!(early.resize_in_place(b, layout, 128));
+    assert_eq!(early.used_bytes(), 2 * PAGE_SIZE + 128);
+}
+
+#[test]
+fn test_alloc_pages_at() {
+    let arena = Box::new(Arena([0; 4 * PAGE_SIZE]));
+    let start = arena.0.as_ptr() as usize;
+    let mut early = EarlyAllocator::<PAGE_SIZE>::new();
+    early.init(start, 4 * PAGE_SIZE);
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    early.alloc(layout).unwrap();
+
+    // from the avail-area, leaving a free run after them
+    assert_eq!(
+        early.alloc_pages_at(start + PAGE_SIZE, 1),
+        Ok(start + PAGE_SIZE)
+    );
+    assert_eq!(early.used_pages(), 1);
+    assert_eq!(early.available_pages(), 2);
+    assert_eq!(
+        early.alloc_pages_at(start, 1),
+        Err(AllocError::MemoryOverlap)
+    );
+    assert_eq!(
+        early.alloc_pages_at(start + PAGE_SIZE, 2),
+        Err(AllocError::MemoryOverlap)
+    );
+    assert_eq!(
+        early.alloc_pages_at(start + 3 * PAGE_SIZE, 2),
+        Err(AllocError::InvalidParam)
+    );
+
+    // from the free run
+    assert_eq!(
+        early.alloc_pages_at(start + 3 * PAGE_SIZE, 1),
+        Ok(start + 3 * PAGE_SIZE)
+    );
+    assert_eq!(early.alloc_pages(1, PAGE_SIZE), Ok(start + 2 * PAGE_SIZE));
+    assert_eq!(early.available_pages(), 0);
+}
+
+#[test]
+fn test_mark_restore() {
+    let arena = Box::new(Arena([0; 4 * PAGE_SIZE]));
+    let start = arena.0.as_ptr() as usize;
+    let mut early = EarlyAllocator::<PAGE_SIZE>::new();
+    early.init(start, 4 * PAGE_SIZE);
+
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let a = early.alloc(layout).unwrap();
+    let p = early.alloc_pages(1, PAGE_SIZE).unwrap();
+    let q = early.alloc_pages(1, PAGE_SIZE).unwrap();
+    early.dealloc_pages(p, 1);
+    assert_eq!(early.used_pages(), 1);
+
+    let mark = early.mark().unwrap();
+    // the page freed before the mark is not reused
+    assert_eq!(early.alloc_pages(1, PAGE_SIZE), Ok(start + PAGE_SIZE));
+    early.alloc(layout).unwrap();
+    let inner = early.mark().unwrap();
+    early.alloc(layout).unwrap();
+    assert_eq!(early.alloc_pages(1, PAGE_SIZE), Err(AllocError::NoMemory));
+    early.restore(inner);
+    assert_eq!(early.used_bytes(), 2 * layout.size());
+    assert_eq!(early.used_pages(), 2);
+
+    // freed since the mark, but allocated before it
+    early.dealloc_pages(q, 1);
+    early.restore(mark);
+    assert_eq!(early.used_bytes(), layout.size());
+    assert_eq!(early.used_pages(), 0);
+    assert_eq!(early.alloc(layout).unwrap().as_ptr() as usize, start + 64);
+    assert_eq!(early.alloc_pages(1, PAGE_SIZE), Ok(p));
+
+    early.reset();
+    assert_eq!(early.used_bytes(), 0);
+    assert_eq!(early.used_pages(), 0);
+    assert_eq!(early.alloc(layout).unwrap(), a);
+}
@@ -0,0 +1,186 @@
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(test)]
+mod tests;
+
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
+use allocator_pages_at::PageAllocatorAt;
+use allocator_realloc::ReallocAllocator;
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+const WORD: usize = core::mem::size_of::<usize>();
+/// The smallest redzone before a block, which ends with the size of the block.
+const FRONT_REDZONE: usize = 2 * WORD;
+/// The redzone after a block.
+const BACK_REDZONE: usize = 2 * WORD;
+
+/// The byte filling the redzones.
+pub const CANARY: u8 = 0xfd;
+/// The byte filling the blocks when they are allocated.
+pub const UNINIT: u8 = 0xcd;
+/// The byte filling the blocks when they are freed.
+pub const POISON: u8 = 0xdd;
+
+/// Debugging wrapper of a byte allocator
+/// It catches the overflows, the mismatched layouts and some double frees of
+/// the code using it, at the cost of memory and time:
+/// - Each block is surrounded by redzones filled with [`CANARY`], and the
+///   front one ends with the size of the block.
+/// - The blocks are filled with [`UNINIT`] when allocated, and with
+///   [`POISON`] when freed, so reading uninitialized or freed memory gives
+///   recognizable values.
+/// - Dealloc checks the redzones and the size, and panics with the address
+///   and the layout of the block if they were overwritten.
+///
+/// [ canary | size ][ block ][ canary ]
+/// |                |
+/// start            pos
+///
+/// The front redzone is at least as large as the alignment of the block, so
+/// that the block is aligned. The page allocations are not checked.
+pub struct DebugAllocator<A> {
+    inner: A,
+}
+
+impl<A> DebugAllocator<A> {
+    /// Wraps the allocator `inner`.
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+
+    /// Returns the wrapped allocator.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+}
+
+impl<A: Default> Default for DebugAllocator<A> {
+    fn default() -> Self {
+        Self::new(A::default())
+    }
+}
+
+/// Returns the size of the front redzone and the layout given to the inner
+/// allocator for a block of `layout`.
+fn inner_layout(layout: Layout) -> AllocResult<(usize, Layout)> {
+    let front = layout.align().max(FRONT_REDZONE);
+    let size = layout
+        .size()
+        .checked_add(front + BACK_REDZONE)
+        .ok_or(AllocError::NoMemory)?;
+    let inner = Layout::from_size_align(size, layout.align().max(WORD))
+        .map_err(|_| AllocError::NoMemory)?;
+    Ok((front, inner))
+}
+
+fn corrupted(what: &str, pos: usize, layout: Layout) -> ! {
+    panic!(
+        "DebugAllocator: {} of the block {:#x} ({:?})",
+        what, pos, layout
+    );
+}
+
+impl<A: BaseAllocator> BaseAllocator for DebugAllocator<A> {
+    fn init(&mut self, start: usize, size: usize) {
+        self.inner.init(start, size);
+    }
+
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        self.inner.add_memory(start, size)
+    }
+}
+
+impl<A: ByteAllocator> ByteAllocator for DebugAllocator<A> {
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let (front, inner_layout) = inner_layout(layout)?;
+        let start = self.inner.alloc(inner_layout)?.as_ptr();
+        unsafe {
+            let pos = start.add(front);
+            start.write_bytes(CANARY, front - WORD);
+            (pos.sub(WORD) as *mut usize).write(layout.size());
+            pos.write_bytes(UNINIT, layout.size());
+            pos.add(layout.size()).write_bytes(CANARY, BACK_REDZONE);
+            Ok(NonNull::new_unchecked(pos))
+        }
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
+        let Ok((front, inner_layout)) = inner_layout(layout) else {
+            corrupted("invalid layout", pos.as_ptr() as usize, layout);
+        };
+        let addr = pos.as_ptr() as usize;
+        unsafe {
+            let pos = pos.as_ptr();
+            let start = pos.sub(front);
+            let front_zone = core::slice::from_raw_parts(start, front - WORD);
+            let back_zone = core::slice::from_raw_parts(pos.add(layout.size()), BACK_REDZONE);
+            let size = (pos.sub(WORD) as *const usize).read();
+            // the inner allocator may have reused the start of a freed block
+            if back_zone.iter().all(|&b| b == POISON) {
+                corrupted("double free", addr, layout);
+            }
+            if front_zone.iter().any(|&b| b != CANARY) {
+                corrupted("underflow", addr, layout);
+            }
+            if size != layout.size() {
+                panic!(
+                    "DebugAllocator: the block {:#x} of {} bytes freed with {:?}",
+                    addr, size, layout
+                );
+            }
+            if back_zone.iter().any(|&b| b != CANARY) {
+                corrupted("overflow", addr, layout);
+            }
+            start.write_bytes(POISON, inner_layout.size());
+            self.inner
+                .dealloc(NonNull::new_unchecked(start), inner_layout);
+        }
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.inner.total_bytes()
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.inner.used_bytes()
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.inner.available_bytes()
+    }
+}
+
+/// The blocks are always moved, so that the redzones and the poisoning are
+/// set up again.
+impl<A: ByteAllocator> ReallocAllocator for DebugAllocator<A> {}
+
+impl<A: PageAllocator> PageAllocator for DebugAllocator<A> {
+    const PAGE_SIZE: usize = A::PAGE_SIZE;
+
+    fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        self.inner.alloc_pages(num_pages, align_pow2)
+    }
+
+    fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
+        self.inner.dealloc_pages(pos, num_pages)
+    }
+
+    fn total_pages(&self) -> usize {
+        self.inner.total_pages()
+    }
+
+    fn used_pages(&self) -> usize {
+        self.inner.used_pages()
+    }
+
+    fn available_pages(&self) -> usize {
+        self.inner.available_pages()
+    }
+}
+
+impl<A: PageAllocatorAt> PageAllocatorAt for DebugAllocator<A> {
+    fn alloc_pages_at(&mut self, base: usize, num_pages: usize) -> AllocResult<usize> {
+        self.inner.alloc_pages_at(base, num_pages)
+    }
+}
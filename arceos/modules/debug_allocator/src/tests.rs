@@ -0,0 +1,87 @@
+use core::alloc::Layout;
+
+use allocator::{BaseAllocator, ByteAllocator};
+use tlsf_allocator::TlsfByteAllocator;
+
+use crate::{DebugAllocator, POISON, UNINIT};
+
+const HEAP_SIZE: usize = 0x10000;
+
+#[repr(align(4096))]
+struct Heap([u8; HEAP_SIZE]);
+
+fn new_debug(heap: &Heap) -> DebugAllocator<TlsfByteAllocator> {
+    let mut debug = DebugAllocator::new(TlsfByteAllocator::new());
+    debug.init(heap.0.as_ptr() as usize, HEAP_SIZE);
+    debug
+}
+
+#[test]
+fn test_alloc_dealloc() {
+    let heap = Box::new(Heap([0; HEAP_SIZE]));
+    let mut debug = new_debug(&heap);
+
+    let mut ptrs = Vec::new();
+    for (size, align) in [(1, 1), (24, 8), (100, 4), (16, 64), (3000, 8), (200, 4096)] {
+        let layout = Layout::from_size_align(size, align).unwrap();
+        let ptr = debug.alloc(layout).unwrap();
+        assert_eq!(ptr.as_ptr() as usize % align, 0);
+        let block = unsafe { core::slice::from_raw_parts_mut(ptr.as_ptr(), size) };
+        assert!(block.iter().all(|&b| b == UNINIT));
+        block.fill(0xaa);
+        ptrs.push((ptr, layout));
+    }
+    for &(ptr, layout) in &ptrs {
+        debug.dealloc(ptr, layout);
+    }
+    assert_eq!(debug.used_bytes(), 0);
+
+    // freed blocks are poisoned, past what the inner allocator reuses
+    let (ptr, layout) = ptrs[4];
+    let block = unsafe { core::slice::from_raw_parts(ptr.as_ptr(), layout.size()) };
+    assert!(block[64..].iter().all(|&b| b == POISON));
+}
+
+#[test]
+#[should_panic(expected = "overflow of the block")]
+fn test_overflow() {
+    let heap = Box::new(Heap([0; HEAP_SIZE]));
+    let mut debug = new_debug(&heap);
+    let layout = Layout::from_size_align(10, 2).unwrap();
+    let ptr = debug.alloc(layout).unwrap();
+    unsafe { ptr.as_ptr().add(10).write(0) };
+    debug.dealloc(ptr, layout);
+}
+
+#[test]
+#[should_panic(expected = "underflow of the block")]
+fn test_underflow() {
+    let heap = Box::new(Heap([0; HEAP_SIZE]));
+    let mut debug = new_debug(&heap);
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    let ptr = debug.alloc(layout).unwrap();
+    unsafe { ptr.as_ptr().sub(9).write(0) };
+    debug.dealloc(ptr, layout);
+}
+
+#[test]
+#[should_panic(expected = "of 32 bytes freed with")]
+fn test_layout_mismatch() {
+    let heap = Box::new(Heap([0; HEAP_SIZE]));
+    let mut debug = new_debug(&heap);
+    let ptr = debug
+        .alloc(Layout::from_size_align(32, 8).unwrap())
+        .unwrap();
+    debug.dealloc(ptr, Layout::from_size_align(16, 8).unwrap());
+}
+
+#[test]
+#[should_panic(expected = "double free of the block")]
+fn test_double_free() {
+    let heap = Box::new(Heap([0; HEAP_SIZE]));
+    let mut debug = new_debug(&heap);
+    let layout = Layout::from_size_align(48, 8).unwrap();
+    let ptr = debug.alloc(layout).unwrap();
+    debug.dealloc(ptr, layout);
+    debug.dealloc(ptr, layout);
+}
@@ -0,0 +1,282 @@
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(test)]
+mod tests;
+
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
+use allocator_realloc::ReallocAllocator;
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+/// The smallest size class.
+const MIN_SIZE: usize = 8;
+/// The largest size class, the larger allocations taking whole pages.
+pub const MAX_SIZE: usize = 4096;
+/// Number of size classes, from [`MIN_SIZE`] to [`MAX_SIZE`].
+const NUM_CLASSES: usize = (MAX_SIZE / MIN_SIZE).trailing_zeros() as usize + 1;
+
+/// The minimum number of objects of a slab, for the classes larger than a
+/// page over that number.
+const MIN_OBJECTS: usize = 8;
+/// Number of empty slabs a cache keeps, the others going back to the page
+/// allocator.
+const MAX_EMPTY_SLABS: usize = 1;
+
+/// Slab byte allocator
+/// Allocations are rounded up to a power of two size class, from 8 to
+/// [`MAX_SIZE`] bytes, and each class has a cache of slabs: runs of pages,
+/// taken from the page allocator `P`, split in objects of the class.
+/// - Alloc takes an object from a slab with free ones, in O(1), without
+///   searching for a fit; objects of a size are packed together, so the
+///   allocations of many small objects leave no fragmentation.
+/// - Dealloc gives the object back to its slab; a slab with no objects in
+///   use goes back to the page allocator, except one kept per class.
+///
+/// The larger allocations are done in whole pages by `P`.
+///
+/// Each slab is aligned to its size, with a header in its first bytes, so
+/// an object finds its slab by its address:
+///
+/// [ header | object | object | ... ]
+/// |        |                       |
+/// slab   first                slab + size
+pub struct SlabByteAllocator<P: PageAllocator> {
+    pages: P,
+    caches: [Cache; NUM_CLASSES],
+    /// Number of bytes of the objects or pages in use.
+    used_bytes: usize,
+    /// Number of bytes of the free objects in the slabs.
+    cached_bytes: usize,
+}
+
+/// The slabs of a size class.
+#[derive(Clone, Copy)]
+struct Cache {
+    /// The address of the first slab with free objects, 0 if none. The full
+    /// slabs are in no list.
+    partial: usize,
+    /// Number of slabs with no objects in use.
+    empty: usize,
+}
+
+/// The header of a slab.
+struct Slab {
+    /// The address of the first free object, 0 if none. Each free object
+    /// starts with the address of the next one.
+    free: usize,
+    in_use: usize,
+    prev: usize,
+    next: usize,
+}
+
+impl Slab {
+    fn at<'a>(addr: usize) -> &'a mut Self {
+        unsafe { &mut *(addr as *mut Self) }
+    }
+}
+
+impl<P: PageAllocator> SlabByteAllocator<P> {
+    /// Creates an allocator taking its slabs from `pages`, to be given its
+    /// memory by [`BaseAllocator::init`] or by `pages` itself.
+    pub const fn new(pages: P) -> Self {
+        Self {
+            pages,
+            caches: [Cache {
+                partial: 0,
+                empty: 0,
+            }; NUM_CLASSES],
+            used_bytes: 0,
+            cached_bytes: 0,
+        }
+    }
+
+    /// Returns the size class of the layout, `None` for the larger ones.
+    fn class_of(layout: Layout) -> Option<usize> {
+        let size = layout.size().max(layout.align()).max(MIN_SIZE);
+        (size <= MAX_SIZE).then(|| (size.next_power_of_two() / MIN_SIZE).trailing_zeros() as usize)
+    }
+
+    const fn object_size(class: usize) -> usize {
+        MIN_SIZE << class
+    }
+
+    /// Number of pages of a slab of the class, a power of two.
+    fn slab_pages(class: usize) -> usize {
+        (Self::object_size(class) * MIN_OBJECTS / P::PAGE_SIZE).max(1)
+    }
+
+    /// The offset of the first object in a slab, after the header.
+    fn first_offset(class: usize) -> usize {
+        core::mem::size_of::<Slab>().next_multiple_of(Self::object_size(class))
+    }
+
+    fn objects_per_slab(class: usize) -> usize {
+        let slab_size = Self::slab_pages(class) * P::PAGE_SIZE;
+        (slab_size - Self::first_offset(class)) / Self::object_size(class)
+    }
+
+    fn push_partial(&mut self, class: usize, slab: usize) {
+        let next = self.caches[class].partial;
+        if next != 0 {
+            Slab::at(next).prev = slab;
+        }
+        let s = Slab::at(slab);
+        s.prev = 0;
+        s.next = next;
+        self.caches[class].partial = slab;
+    }
+
+    fn remove_partial(&mut self, class: usize, slab: usize) {
+        let Slab { prev, next, .. } = *Slab::at(slab);
+        if prev == 0 {
+            self.caches[class].partial = next;
+        } else {
+            Slab::at(prev).next = next;
+        }
+        if next != 0 {
+            Slab::at(next).prev = prev;
+        }
+    }
+
+    /// Takes a new slab from the page allocator, with all its objects free.
+    fn new_slab(&mut self, class: usize) -> AllocResult<usize> {
+        let num_pages = Self::slab_pages(class);
+        let slab = self
+            .pages
+            .alloc_pages(num_pages, num_pages * P::PAGE_SIZE)?;
+        let size = Self::object_size(class);
+        let count = Self::objects_per_slab(class);
+        let first = slab + Self::first_offset(class);
+        // linked in address order
+        let mut free = 0;
+        for i in (0..count).rev() {
+            let obj = first + i * size;
+            unsafe { *(obj as *mut usize) = free };
+            free = obj;
+        }
+        *Slab::at(slab) = Slab {
+            free,
+            in_use: 0,
+            prev: 0,
+            next: 0,
+        };
+        self.push_partial(class, slab);
+        self.caches[class].empty += 1;
+        self.cached_bytes += count * size;
+        Ok(slab)
+    }
+
+    fn alloc_object(&mut self, class: usize) -> AllocResult<usize> {
+        let mut slab = self.caches[class].partial;
+        if slab == 0 {
+            slab = self.new_slab(class)?;
+        }
+        let s = Slab::at(slab);
+        let obj = s.free;
+        s.free = unsafe { *(obj as *const usize) };
+        if s.in_use == 0 {
+            self.caches[class].empty -= 1;
+        }
+        s.in_use += 1;
+        if s.free == 0 {
+            self.remove_partial(class, slab);
+        }
+        let size = Self::object_size(class);
+        self.cached_bytes -= size;
+        self.used_bytes += size;
+        Ok(obj)
+    }
+
+    fn dealloc_object(&mut self, class: usize, obj: usize) {
+        let num_pages = Self::slab_pages(class);
+        let slab = obj & !(num_pages * P::PAGE_SIZE - 1);
+        let s = Slab::at(slab);
+        let was_full = s.free == 0;
+        unsafe { *(obj as *mut usize) = s.free };
+        s.free = obj;
+        s.in_use -= 1;
+        let in_use = s.in_use;
+        let size = Self::object_size(class);
+        self.cached_bytes += size;
+        self.used_bytes -= size;
+        if was_full {
+            self.push_partial(class, slab);
+        }
+        if in_use == 0 {
+            if self.caches[class].empty < MAX_EMPTY_SLABS {
+                self.caches[class].empty += 1;
+            } else {
+                self.remove_partial(class, slab);
+                self.cached_bytes -= Self::objects_per_slab(class) * size;
+                self.pages.dealloc_pages(slab, num_pages);
+            }
+        }
+    }
+}
+
+impl<P: PageAllocator> BaseAllocator for SlabByteAllocator<P> {
+    fn init(&mut self, start: usize, size: usize) {
+        self.pages.init(start, size);
+    }
+
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        self.pages.add_memory(start, size)
+    }
+}
+
+impl<P: PageAllocator> ByteAllocator for SlabByteAllocator<P> {
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let addr = match Self::class_of(layout) {
+            Some(class) => self.alloc_object(class)?,
+            None => {
+                let num_pages = layout.size().div_ceil(P::PAGE_SIZE);
+                let align = layout.align().max(P::PAGE_SIZE);
+                let addr = self.pages.alloc_pages(num_pages, align)?;
+                self.used_bytes += num_pages * P::PAGE_SIZE;
+                addr
+            }
+        };
+        NonNull::new(addr as *mut u8).ok_or(AllocError::NoMemory)
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
+        let addr = pos.as_ptr() as usize;
+        match Self::class_of(layout) {
+            Some(class) => self.dealloc_object(class, addr),
+            None => {
+                let num_pages = layout.size().div_ceil(P::PAGE_SIZE);
+                self.pages.dealloc_pages(addr, num_pages);
+                self.used_bytes -= num_pages * P::PAGE_SIZE;
+            }
+        }
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.pages.total_pages() * P::PAGE_SIZE
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.pages.available_pages() * P::PAGE_SIZE + self.cached_bytes
+    }
+}
+
+impl<P: PageAllocator> ReallocAllocator for SlabByteAllocator<P> {
+    /// Keeps the object if the new size is in the same size class, or the
+    /// pages if it needs as many of them.
+    fn resize_in_place(&mut self, _pos: NonNull<u8>, old_layout: Layout, new_size: usize) -> bool {
+        let Ok(new_layout) = Layout::from_size_align(new_size, old_layout.align()) else {
+            return false;
+        };
+        match (Self::class_of(old_layout), Self::class_of(new_layout)) {
+            (Some(old), Some(new)) => old == new,
+            (None, None) => {
+                old_layout.size().div_ceil(P::PAGE_SIZE) == new_size.div_ceil(P::PAGE_SIZE)
+            }
+            _ => false,
+        }
+    }
+}
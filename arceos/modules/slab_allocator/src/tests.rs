@@ -0,0 +1,92 @@
+use core::alloc::Layout;
+
+use allocator::{BaseAllocator, ByteAllocator, PageAllocator};
+use allocator_realloc::ReallocAllocator;
+use buddy_allocator::BuddyPageAllocator;
+
+use crate::SlabByteAllocator;
+
+const PAGE_SIZE: usize = 0x1000;
+const NUM_PAGES: usize = 64;
+
+#[repr(align(0x40000))]
+struct Arena([u8; NUM_PAGES * PAGE_SIZE]);
+
+fn new_slab(arena: &Arena) -> SlabByteAllocator<BuddyPageAllocator<PAGE_SIZE>> {
+    let mut slab = SlabByteAllocator::new(BuddyPageAllocator::<PAGE_SIZE>::new());
+    slab.init(arena.0.as_ptr() as usize, NUM_PAGES * PAGE_SIZE);
+    slab
+}
+
+#[test]
+fn test_size_classes() {
+    let arena = Box::new(Arena([0; NUM_PAGES * PAGE_SIZE]));
+    let mut slab = new_slab(&arena);
+
+    for (size, align) in [(1, 1), (24, 8), (100, 4), (16, 64), (3000, 8), (4096, 4096)] {
+        let layout = Layout::from_size_align(size, align).unwrap();
+        let a = slab.alloc(layout).unwrap();
+        let b = slab.alloc(layout).unwrap();
+        // objects are aligned to their class
+        let class = size.max(align).max(8).next_power_of_two();
+        assert_eq!(a.as_ptr() as usize % class, 0);
+        assert!((a.as_ptr() as usize).abs_diff(b.as_ptr() as usize) >= class);
+        slab.dealloc(b, layout);
+        // the last freed object comes back first
+        assert_eq!(slab.alloc(layout), Ok(b));
+    }
+
+    // whole pages
+    let layout = Layout::from_size_align(5 * PAGE_SIZE, 8).unwrap();
+    let used = slab.used_bytes();
+    let big = slab.alloc(layout).unwrap();
+    assert_eq!(big.as_ptr() as usize % PAGE_SIZE, 0);
+    assert_eq!(slab.used_bytes(), used + 5 * PAGE_SIZE);
+    slab.dealloc(big, layout);
+    assert_eq!(slab.used_bytes(), used);
+}
+
+#[test]
+fn test_empty_slabs_released() {
+    let arena = Box::new(Arena([0; NUM_PAGES * PAGE_SIZE]));
+    let mut slab = new_slab(&arena);
+    let free_pages = slab.pages.available_pages();
+
+    // several slabs of one class
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let objects: Vec<_> = (0..300).map(|_| slab.alloc(layout).unwrap()).collect();
+    assert_eq!(slab.used_bytes(), 300 * 64);
+    assert!(slab.pages.available_pages() <= free_pages - 4);
+
+    for obj in objects {
+        slab.dealloc(obj, layout);
+    }
+    // all but one go back to the page allocator
+    assert_eq!(slab.used_bytes(), 0);
+    assert_eq!(slab.pages.available_pages(), free_pages - 1);
+}
+
+#[test]
+fn test_realloc() {
+    let arena = Box::new(Arena([0; NUM_PAGES * PAGE_SIZE]));
+    let mut slab = new_slab(&arena);
+
+    // in place within a size class, or within the pages
+    let layout = Layout::from_size_align(40, 8).unwrap();
+    let ptr = slab.alloc(layout).unwrap();
+    assert!(slab.resize_in_place(ptr, layout, 64));
+    assert!(!slab.resize_in_place(ptr, layout, 65));
+    let big = Layout::from_size_align(PAGE_SIZE + 1, 8).unwrap();
+    let pages = slab.alloc(big).unwrap();
+    assert!(slab.resize_in_place(pages, big, 2 * PAGE_SIZE));
+    assert!(!slab.resize_in_place(pages, big, 2 * PAGE_SIZE + 1));
+
+    // moved to the next class, with the data
+    unsafe { core::ptr::write_bytes(ptr.as_ptr(), 0xaa, 40) };
+    let moved = slab.realloc(ptr, layout, 100).unwrap();
+    assert_ne!(moved, ptr);
+    assert_eq!(unsafe { *moved.as_ptr().add(39) }, 0xaa);
+    slab.dealloc(moved, Layout::from_size_align(100, 8).unwrap());
+    slab.dealloc(pages, Layout::from_size_align(2 * PAGE_SIZE, 8).unwrap());
+    assert_eq!(slab.used_bytes(), 0);
+}
@@ -0,0 +1,317 @@
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(test)]
+mod tests;
+
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator};
+use allocator_realloc::ReallocAllocator;
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+const WORD: usize = core::mem::size_of::<usize>();
+/// The header of a block: the address of the previous block in memory, and
+/// the size of the block.
+const HEADER: usize = 2 * WORD;
+/// The alignment of the blocks and of their sizes.
+const ALIGN: usize = 2 * WORD;
+/// The smallest block, with room for the links of a free list.
+const MIN_BLOCK: usize = HEADER + 2 * WORD;
+/// The low bit of the size of a free block.
+const FREE: usize = 1;
+
+/// Log2 of the number of second-level lists of a first-level range.
+const SL_LOG: u32 = 4;
+const SL_COUNT: usize = 1 << SL_LOG;
+/// Number of first-level ranges: the small blocks, then one per power of two.
+const FL_COUNT: usize = (usize::BITS - ALIGN.trailing_zeros() - SL_LOG + 1) as usize;
+
+/// TLSF (two-level segregated fit) byte allocator
+/// Free blocks are kept in lists by size: a first level per power of two,
+/// split linearly in 16 second-level lists, with a bitmap of the non-empty
+/// lists at each level.
+/// - Alloc looks in the bitmaps for the first list whose blocks are all
+///   large enough, takes a block, and gives back what is beyond the request.
+/// - Dealloc merges the block with its free neighbors in memory.
+///
+/// Both are in O(1), with a few bit scans and no search in the lists, so the
+/// latency of an allocation is bounded.
+///
+/// Each block starts with a header, and each memory range ends with an empty
+/// block in use, so the merges stop there:
+///
+/// [ header | data ] [ header | data ] ... [ header ]
+/// |                                       |        |
+/// start                                sentinel   end
+pub struct TlsfByteAllocator {
+    fl_bitmap: usize,
+    sl_bitmaps: [u16; FL_COUNT],
+    /// The address of the first block of each list, 0 if none.
+    heads: [[usize; SL_COUNT]; FL_COUNT],
+    total_bytes: usize,
+    /// Number of bytes of the blocks in use, headers included.
+    used_bytes: usize,
+}
+
+/// The header of a block, and the links of the free list of a free block.
+struct Block {
+    /// The address of the previous block in memory, 0 if none.
+    prev_phys: usize,
+    /// The size of the block with the header, and [`FREE`].
+    size: usize,
+    prev_free: usize,
+    next_free: usize,
+}
+
+impl Block {
+    fn at<'a>(addr: usize) -> &'a mut Self {
+        unsafe { &mut *(addr as *mut Self) }
+    }
+
+    fn size(&self) -> usize {
+        self.size & !FREE
+    }
+
+    fn is_free(&self) -> bool {
+        self.size & FREE != 0
+    }
+}
+
+/// Returns the list of the blocks of `size` bytes.
+fn mapping(size: usize) -> (usize, usize) {
+    let units = size / ALIGN;
+    if units < SL_COUNT {
+        (0, units)
+    } else {
+        let log = units.ilog2();
+        (
+            (log - SL_LOG + 1) as usize,
+            (units >> (log - SL_LOG)) - SL_COUNT,
+        )
+    }
+}
+
+/// Returns the size of the block, header included, for `size` bytes of
+/// data.
+fn block_size(size: usize) -> Option<usize> {
+    Some(
+        size.max(MIN_BLOCK - HEADER)
+            .checked_add(HEADER + ALIGN - 1)?
+            & !(ALIGN - 1),
+    )
+}
+
+impl TlsfByteAllocator {
+    /// Creates an empty allocator, to be given its memory by
+    /// [`BaseAllocator::init`] and [`BaseAllocator::add_memory`].
+    pub const fn new() -> Self {
+        Self {
+            fl_bitmap: 0,
+            sl_bitmaps: [0; FL_COUNT],
+            heads: [[0; SL_COUNT]; FL_COUNT],
+            total_bytes: 0,
+            used_bytes: 0,
+        }
+    }
+
+    fn insert(&mut self, block: usize, size: usize) {
+        let (fl, sl) = mapping(size);
+        let next = self.heads[fl][sl];
+        if next != 0 {
+            Block::at(next).prev_free = block;
+        }
+        let b = Block::at(block);
+        b.size = size | FREE;
+        b.prev_free = 0;
+        b.next_free = next;
+        self.heads[fl][sl] = block;
+        self.fl_bitmap |= 1 << fl;
+        self.sl_bitmaps[fl] |= 1 << sl;
+    }
+
+    fn remove(&mut self, block: usize) {
+        let b = Block::at(block);
+        let (prev, next) = (b.prev_free, b.next_free);
+        b.size &= !FREE;
+        let (fl, sl) = mapping(b.size);
+        if prev == 0 {
+            self.heads[fl][sl] = next;
+            if next == 0 {
+                self.sl_bitmaps[fl] &= !(1 << sl);
+                if self.sl_bitmaps[fl] == 0 {
+                    self.fl_bitmap &= !(1 << fl);
+                }
+            }
+        } else {
+            Block::at(prev).next_free = next;
+        }
+        if next != 0 {
+            Block::at(next).prev_free = prev;
+        }
+    }
+
+    /// Returns a free block of at least `size` bytes, from the first list
+    /// whose blocks are all large enough.
+    fn find(&self, size: usize) -> Option<usize> {
+        // rounded up to the next list
+        let units = size / ALIGN;
+        let size = if units < SL_COUNT {
+            size
+        } else {
+            size.checked_add(((1 << (units.ilog2() - SL_LOG)) - 1) * ALIGN)?
+        };
+        let (mut fl, mut sl) = mapping(size);
+        let sl_map = self.sl_bitmaps.get(fl)? & (u16::MAX << sl);
+        if sl_map == 0 {
+            let fl_map = self.fl_bitmap & usize::MAX.checked_shl(fl as u32 + 1).unwrap_or(0);
+            if fl_map == 0 {
+                return None;
+            }
+            fl = fl_map.trailing_zeros() as usize;
+            sl = self.sl_bitmaps[fl].trailing_zeros() as usize;
+        } else {
+            sl = sl_map.trailing_zeros() as usize;
+        }
+        Some(self.heads[fl][sl])
+    }
+
+    /// Splits the block whose first `size` bytes are used, giving back the
+    /// rest if large enough for a block.
+    fn split(&mut self, block: usize, size: usize) {
+        let b = Block::at(block);
+        let rest_size = b.size() - size;
+        if rest_size < MIN_BLOCK {
+            return;
+        }
+        let next = block + b.size();
+        b.size = size;
+        let rest = block + size;
+        Block::at(rest).prev_phys = block;
+        Block::at(next).prev_phys = rest;
+        self.insert(rest, rest_size);
+    }
+}
+
+impl Default for TlsfByteAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BaseAllocator for TlsfByteAllocator {
+    fn init(&mut self, start: usize, size: usize) {
+        self.add_memory(start, size).unwrap();
+    }
+
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        let first = start.next_multiple_of(ALIGN);
+        let end = start.checked_add(size).ok_or(AllocError::InvalidParam)? & !(ALIGN - 1);
+        if end < first + MIN_BLOCK + HEADER {
+            return Err(AllocError::InvalidParam);
+        }
+        let sentinel = end - HEADER;
+        // only the header
+        Block::at(sentinel).prev_phys = first;
+        Block::at(sentinel).size = 0;
+        Block::at(first).prev_phys = 0;
+        self.insert(first, sentinel - first);
+        self.total_bytes += size;
+        Ok(())
+    }
+}
+
+impl ByteAllocator for TlsfByteAllocator {
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let size = block_size(layout.size()).ok_or(AllocError::NoMemory)?;
+        let align = layout.align();
+        // room to move the data to its alignment, leaving a free block before
+        let search = if align <= ALIGN {
+            size
+        } else {
+            size.checked_add(align + MIN_BLOCK)
+                .ok_or(AllocError::NoMemory)?
+        };
+        let mut block = self.find(search).ok_or(AllocError::NoMemory)?;
+        self.remove(block);
+
+        if align > ALIGN {
+            let data = block + HEADER;
+            if data % align != 0 {
+                let gap = (data + MIN_BLOCK).next_multiple_of(align) - data;
+                let b = Block::at(block);
+                let rest_size = b.size() - gap;
+                let next = block + b.size();
+                let aligned = block + gap;
+                *Block::at(aligned) = Block {
+                    prev_phys: block,
+                    size: rest_size,
+                    prev_free: 0,
+                    next_free: 0,
+                };
+                Block::at(next).prev_phys = aligned;
+                self.insert(block, gap);
+                block = aligned;
+            }
+        }
+        self.split(block, size);
+        self.used_bytes += Block::at(block).size();
+        NonNull::new((block + HEADER) as *mut u8).ok_or(AllocError::NoMemory)
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, _layout: Layout) {
+        let mut block = pos.as_ptr() as usize - HEADER;
+        let mut size = Block::at(block).size();
+        self.used_bytes -= size;
+        let next = block + size;
+        if Block::at(next).is_free() {
+            size += Block::at(next).size();
+            self.remove(next);
+        }
+        let prev = Block::at(block).prev_phys;
+        if prev != 0 && Block::at(prev).is_free() {
+            self.remove(prev);
+            size += Block::at(prev).size();
+            block = prev;
+        }
+        Block::at(block + size).prev_phys = block;
+        self.insert(block, size);
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.total_bytes - self.used_bytes
+    }
+}
+
+impl ReallocAllocator for TlsfByteAllocator {
+    /// Grows the block over the next one if it is free and large enough, and
+    /// gives back the end of a shrunk block, merged with the next one.
+    fn resize_in_place(&mut self, pos: NonNull<u8>, _old_layout: Layout, new_size: usize) -> bool {
+        let Some(size) = block_size(new_size) else {
+            return false;
+        };
+        let block = pos.as_ptr() as usize - HEADER;
+        let old_size = Block::at(block).size();
+        let next = block + old_size;
+        if size != old_size && Block::at(next).is_free() {
+            let merged = old_size + Block::at(next).size();
+            if merged < size {
+                return false;
+            }
+            self.remove(next);
+            Block::at(block).size = merged;
+            Block::at(block + merged).prev_phys = block;
+        } else if size > old_size {
+            return false;
+        }
+        self.split(block, size);
+        self.used_bytes = self.used_bytes - old_size + Block::at(block).size();
+        true
+    }
+}
@@ -0,0 +1,102 @@
+use core::alloc::Layout;
+
+use allocator::{BaseAllocator, ByteAllocator};
+use allocator_realloc::ReallocAllocator;
+
+use crate::TlsfByteAllocator;
+
+const HEAP_SIZE: usize = 0x10000;
+
+#[repr(align(4096))]
+struct Heap([u8; HEAP_SIZE]);
+
+fn new_tlsf(heap: &Heap) -> TlsfByteAllocator {
+    let mut tlsf = TlsfByteAllocator::new();
+    tlsf.init(heap.0.as_ptr() as usize, HEAP_SIZE);
+    tlsf
+}
+
+#[test]
+fn test_alloc_dealloc() {
+    let heap = Box::new(Heap([0; HEAP_SIZE]));
+    let mut tlsf = new_tlsf(&heap);
+    let start = heap.0.as_ptr() as usize;
+
+    let mut ptrs = Vec::new();
+    for (size, align) in [(1, 1), (24, 8), (100, 4), (16, 64), (3000, 8), (200, 4096)] {
+        let layout = Layout::from_size_align(size, align).unwrap();
+        let ptr = tlsf.alloc(layout).unwrap();
+        let addr = ptr.as_ptr() as usize;
+        assert_eq!(addr % align, 0);
+        assert!(addr >= start && addr + size <= start + HEAP_SIZE);
+        unsafe { core::ptr::write_bytes(ptr.as_ptr(), 0xaa, size) };
+        ptrs.push((ptr, layout));
+    }
+    for (ptr, layout) in ptrs {
+        tlsf.dealloc(ptr, layout);
+    }
+    assert_eq!(tlsf.used_bytes(), 0);
+
+    // too large
+    let layout = Layout::from_size_align(HEAP_SIZE, 8).unwrap();
+    assert!(tlsf.alloc(layout).is_err());
+}
+
+#[test]
+fn test_merge() {
+    let heap = Box::new(Heap([0; HEAP_SIZE]));
+    let mut tlsf = new_tlsf(&heap);
+
+    // fragment the heap, then free in an interleaved order
+    let layout = Layout::from_size_align(100, 8).unwrap();
+    let mut ptrs = Vec::new();
+    while let Ok(ptr) = tlsf.alloc(layout) {
+        ptrs.push(ptr);
+    }
+    assert!(ptrs.len() > HEAP_SIZE / 129);
+    let (even, odd): (Vec<_>, Vec<_>) = ptrs.iter().enumerate().partition(|(i, _)| i % 2 == 0);
+    for (_, &ptr) in even.into_iter().chain(odd) {
+        tlsf.dealloc(ptr, layout);
+    }
+
+    // all merged back in one block
+    let big = Layout::from_size_align(HEAP_SIZE - 0x1000, 8).unwrap();
+    let ptr = tlsf.alloc(big).unwrap();
+    assert_eq!(ptr.as_ptr() as usize, heap.0.as_ptr() as usize + 16);
+    tlsf.dealloc(ptr, big);
+    assert_eq!(tlsf.used_bytes(), 0);
+}
+
+#[test]
+fn test_realloc() {
+    let heap = Box::new(Heap([0; HEAP_SIZE]));
+    let mut tlsf = new_tlsf(&heap);
+
+    // the last block grows in place, over the free end of the heap
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let ptr = tlsf.alloc(layout).unwrap();
+    unsafe { core::ptr::write_bytes(ptr.as_ptr(), 0xaa, 64) };
+    assert!(tlsf.resize_in_place(ptr, layout, 4096));
+    let used = tlsf.used_bytes();
+    assert!(used >= 4096);
+
+    // a block followed by one in use is moved, with its data
+    let layout = Layout::from_size_align(4096, 8).unwrap();
+    let next = tlsf.alloc(layout).unwrap();
+    assert!(!tlsf.resize_in_place(ptr, layout, 8192));
+    let moved = tlsf.realloc(ptr, layout, 8192).unwrap();
+    assert_ne!(moved, ptr);
+    assert!(unsafe { core::slice::from_raw_parts(moved.as_ptr(), 64) }
+        .iter()
+        .all(|&b| b == 0xaa));
+
+    // shrinking gives back the end of the block
+    let big = Layout::from_size_align(8192, 8).unwrap();
+    let used = tlsf.used_bytes();
+    assert!(tlsf.resize_in_place(moved, big, 100));
+    assert!(tlsf.used_bytes() <= used - 8000);
+
+    tlsf.dealloc(moved, Layout::from_size_align(100, 8).unwrap());
+    tlsf.dealloc(next, layout);
+    assert_eq!(tlsf.used_bytes(), 0);
+}
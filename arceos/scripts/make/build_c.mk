@@ -23,6 +23,8 @@ LDFLAGS += -nostdlib -static -no-pie --gc-sections -znostart-stop-gc -T$(LD_SCRI
 
 ifeq ($(MODE), release)
   CFLAGS += -O3
+else ifeq ($(MODE), small)
+  CFLAGS += -Os
 endif
 
 ifeq ($(ARCH), riscv64)
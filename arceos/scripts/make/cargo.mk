@@ -9,6 +9,7 @@ else
 endif
 
 build_args-release := --release
+build_args-small := --profile small
 
 build_args := \
   -Z unstable-options \
@@ -18,6 +19,36 @@ build_args := \
   $(verbose)
 
 RUSTFLAGS := -C link-arg=-T$(LD_SCRIPT) -C link-arg=-no-pie -C link-arg=-znostart-stop-gc
+
+ifeq ($(STACK_PROTECTOR), y)
+  RUSTFLAGS += -Z stack-protector=strong
+endif
+ifeq ($(UNWIND), y)
+  # `core` and `alloc` are rebuilt with the unwind tables and landing pads
+  RUSTFLAGS += -C panic=unwind -C force-unwind-tables=yes
+  build_args += -Z build-std=core,alloc -Z build-std-features=compiler-builtins-mem
+endif
+ifeq ($(MODE), small)
+  ifneq ($(UNWIND), y)
+    # `core` and `alloc` are rebuilt optimized for size too
+    build_args += -Z build-std=core,alloc -Z build-std-features=compiler-builtins-mem
+  endif
+endif
+ifeq ($(KSYMS), y)
+  RUSTFLAGS += -C force-frame-pointers=yes
+else ifneq ($(filter alloc-trace alloc-leak task-sanitizer,$(FEATURES)),)
+  # for the callers of the allocations, and the backtraces of the sanitizer
+  RUSTFLAGS += -C force-frame-pointers=yes
+endif
+ifeq ($(ARCH), aarch64)
+  ifeq ($(SHADOW_CALL_STACK), y)
+    RUSTFLAGS += -Z sanitizer=shadow-call-stack -C target-feature=+reserve-x18
+  endif
+  ifeq ($(PAC), y)
+    RUSTFLAGS += -Z branch-protection=pac-ret
+  endif
+endif
+
 RUSTDOCFLAGS := -Z unstable-options --enable-index-page -D rustdoc::broken_intra_doc_links
 
 ifeq ($(MAKECMDGOALS), doc_check_missing)
@@ -28,6 +59,14 @@ define cargo_build
   $(call run_cmd,cargo -C $(1) build,$(build_args) --features "$(strip $(2))")
 endef
 
+# Builds again with the symbol table of the ELF `$(3)` embedded. The table is
+# in `.rodata`, after the code, so the code addresses do not change.
+define cargo_build_ksyms
+  @printf "    $(GREEN_C)Building$(END_C) kernel symbol table\n"
+  @$(NM) -n -C --defined-only $(3) | RUSTFLAGS="" cargo run -q --release --manifest-path tools/mksyms/Cargo.toml -- $(3).ksyms
+  $(call run_cmd,AX_KSYMS=$(abspath $(3).ksyms) cargo -C $(1) build,$(build_args) --features "$(strip $(2))")
+endef
+
 clippy_args := -A clippy::new_without_default
 
 define cargo_clippy
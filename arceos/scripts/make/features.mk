@@ -48,6 +48,47 @@ ifeq ($(BUS),mmio)
   ax_feat += bus-mmio
 endif
 
+ifeq ($(PARALLEL_PROBE), y)
+  ax_feat += parallel-probe
+endif
+
+ifneq ($(DEFERRED_DRIVERS),)
+  ax_feat += deferred-probe
+endif
+
+ifeq ($(STACK_PROTECTOR), y)
+  ax_feat += stack-protector
+endif
+
+ifeq ($(KSYMS), y)
+  ax_feat += symbols
+endif
+
+ifeq ($(BOOT_TIME), y)
+  ax_feat += boot-time
+endif
+
+ifneq ($(REPLAY),)
+  ax_feat += replay
+endif
+
+ifeq ($(SNAPSHOT), y)
+  ax_feat += snapshot
+endif
+
+ifeq ($(UNWIND), y)
+  ax_feat += unwind
+endif
+
+ifeq ($(ARCH), aarch64)
+  ifeq ($(SHADOW_CALL_STACK), y)
+    ax_feat += shadow-call-stack
+  endif
+  ifeq ($(PAC), y)
+    ax_feat += pointer-auth
+  endif
+endif
+
 ifeq ($(shell test $(SMP) -gt 1; echo $$?),0)
   lib_feat += smp
 endif
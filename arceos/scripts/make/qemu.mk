@@ -57,6 +57,18 @@ ifeq ($(NET_DUMP), y)
   qemu_args-$(NET) += -object filter-dump,id=dump0,netdev=net0,file=netdump.pcap
 endif
 
+qemu_args-$(BALLOON) += \
+  -device virtio-balloon-$(vdev-suffix),deflate-on-oom=on
+
+qemu_args-$(VSOCK) += \
+  -device vhost-vsock-$(vdev-suffix),guest-cid=$(VSOCK_CID)
+
+ifneq ($(IVSHMEM),)
+  qemu_args-y += \
+    -object memory-backend-file,id=ivshmem0,share=on,mem-path=$(IVSHMEM),size=$(IVSHMEM_SIZE) \
+    -device ivshmem-plain,memdev=ivshmem0
+endif
+
 qemu_args-$(GRAPHIC) += \
   -device virtio-gpu-$(vdev-suffix) -vga none \
   -serial mon:stdio
@@ -65,6 +77,26 @@ ifeq ($(GRAPHIC), n)
   qemu_args-y += -nographic
 endif
 
+qemu_cmdline := $(CMDLINE)
+
+ifneq ($(REPLAY),)
+  semihosting := y
+  qemu_cmdline += replay=$(REPLAY) replay.file=$(REPLAY_LOG)
+endif
+
+ifeq ($(SNAPSHOT), y)
+  semihosting := y
+  ifneq ($(SNAPSHOT_RESTORE),)
+    qemu_cmdline += snapshot.restore=$(SNAPSHOT_RESTORE)
+  endif
+endif
+
+ifeq ($(semihosting), y)
+  qemu_args-y += \
+    -semihosting-config enable=on,target=native \
+    -append "$(strip $(qemu_cmdline))"
+endif
+
 ifeq ($(QEMU_LOG), y)
   qemu_args-y += -D qemu.log -d in_asm,int,mmu,pcall,cpu_reset,guest_errors
 endif
@@ -0,0 +1,226 @@
+//! Builds an image of the compressed, checksummed read-only filesystem
+//! (`rofs`) of axfs from a host directory.
+//!
+//! See `modules/axfs/src/fs/rofs/mod.rs` for the image layout.
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"AXROFS\0\0";
+const VERSION: u32 = 1;
+const SUPERBLOCK_SIZE: usize = 64;
+const INODE_TYPE_FILE: u8 = 1;
+const INODE_TYPE_DIR: u8 = 2;
+const BLOCK_RAW: u32 = 1 << 31;
+const MOUNT_POINTS: &[&str] = &["dev", "tmp", "proc", "sys"];
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+struct Inode {
+    ty: u8,
+    mode: u16,
+    parent: u32,
+    size: u64,
+    first_block: u32,
+    num_blocks: u32,
+}
+
+struct Builder {
+    block_size: usize,
+    inodes: Vec<Inode>,
+    /// `(offset, len, crc)` of every data block.
+    blocks: Vec<(u64, u32, u32)>,
+    data: Vec<u8>,
+}
+
+impl Builder {
+    /// Appends the content of a file or directory as data blocks.
+    fn add_data(&mut self, content: &[u8]) -> (u32, u32) {
+        let first_block = self.blocks.len() as u32;
+        for chunk in content.chunks(self.block_size) {
+            let compressed = lz4_flex::block::compress(chunk);
+            let (stored, len) = if compressed.len() < chunk.len() {
+                (&compressed[..], compressed.len() as u32)
+            } else {
+                (chunk, chunk.len() as u32 | BLOCK_RAW)
+            };
+            let offset = (SUPERBLOCK_SIZE + self.data.len()) as u64;
+            self.blocks.push((offset, len, crc32(stored)));
+            self.data.extend_from_slice(stored);
+        }
+        (first_block, self.blocks.len() as u32 - first_block)
+    }
+
+    fn add_file(&mut self, path: &Path, parent: u32) -> io::Result<u32> {
+        let content = fs::read(path)?;
+        let mode = fs::metadata(path)?.permissions().mode() as u16 & 0o777;
+        let (first_block, num_blocks) = self.add_data(&content);
+        self.inodes.push(Inode {
+            ty: INODE_TYPE_FILE,
+            mode,
+            parent,
+            size: content.len() as u64,
+            first_block,
+            num_blocks,
+        });
+        Ok(self.inodes.len() as u32 - 1)
+    }
+
+    fn add_dir(&mut self, path: &Path, parent: Option<u32>) -> io::Result<u32> {
+        // reserve the inode first, so that children can refer to it
+        let ino = self.inodes.len() as u32;
+        let mode = fs::metadata(path)?.permissions().mode() as u16 & 0o777;
+        self.inodes.push(Inode {
+            ty: INODE_TYPE_DIR,
+            mode,
+            parent: parent.unwrap_or(ino),
+            size: 0,
+            first_block: 0,
+            num_blocks: 0,
+        });
+
+        let mut children = fs::read_dir(path)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        children.sort();
+
+        let mut entries = Vec::new();
+        let mut push_entry = |name: &str, child: u32| {
+            entries.extend_from_slice(&child.to_le_bytes());
+            entries.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            entries.extend_from_slice(&0u16.to_le_bytes());
+            entries.extend_from_slice(name.as_bytes());
+        };
+        let mut names = Vec::new();
+        for child in children {
+            let name = child.file_name().unwrap().to_string_lossy().into_owned();
+            let meta = fs::metadata(&child)?;
+            let child_ino = if meta.is_dir() {
+                self.add_dir(&child, Some(ino))?
+            } else if meta.is_file() {
+                self.add_file(&child, ino)?
+            } else {
+                eprintln!("skipping {:?}: not a regular file or directory", child);
+                continue;
+            };
+            push_entry(&name, child_ino);
+            names.push(name);
+        }
+        if parent.is_none() {
+            for name in MOUNT_POINTS
+                .iter()
+                .filter(|n| !names.iter().any(|m| m == *n))
+            {
+                let child_ino = self.inodes.len() as u32;
+                self.inodes.push(Inode {
+                    ty: INODE_TYPE_DIR,
+                    mode: 0o755,
+                    parent: ino,
+                    size: 0,
+                    first_block: 0,
+                    num_blocks: 0,
+                });
+                push_entry(name, child_ino);
+            }
+        }
+
+        let (first_block, num_blocks) = self.add_data(&entries);
+        let inode = &mut self.inodes[ino as usize];
+        inode.size = entries.len() as u64;
+        inode.first_block = first_block;
+        inode.num_blocks = num_blocks;
+        Ok(ino)
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut meta = Vec::new();
+        for inode in &self.inodes {
+            meta.push(inode.ty);
+            meta.push(0);
+            meta.extend_from_slice(&inode.mode.to_le_bytes());
+            meta.extend_from_slice(&inode.parent.to_le_bytes());
+            meta.extend_from_slice(&inode.size.to_le_bytes());
+            meta.extend_from_slice(&inode.first_block.to_le_bytes());
+            meta.extend_from_slice(&inode.num_blocks.to_le_bytes());
+            meta.extend_from_slice(&[0; 8]);
+        }
+        for &(offset, len, crc) in &self.blocks {
+            meta.extend_from_slice(&offset.to_le_bytes());
+            meta.extend_from_slice(&len.to_le_bytes());
+            meta.extend_from_slice(&crc.to_le_bytes());
+        }
+
+        let mut sb = Vec::with_capacity(SUPERBLOCK_SIZE);
+        sb.extend_from_slice(MAGIC);
+        sb.extend_from_slice(&VERSION.to_le_bytes());
+        sb.extend_from_slice(&(self.block_size as u32).to_le_bytes());
+        sb.extend_from_slice(&(self.inodes.len() as u32).to_le_bytes());
+        sb.extend_from_slice(&(self.blocks.len() as u32).to_le_bytes());
+        sb.extend_from_slice(&((SUPERBLOCK_SIZE + self.data.len()) as u64).to_le_bytes());
+        sb.extend_from_slice(&(meta.len() as u64).to_le_bytes());
+        sb.extend_from_slice(&crc32(&meta).to_le_bytes());
+        let sb_crc = crc32(&sb);
+        sb.extend_from_slice(&sb_crc.to_le_bytes());
+        sb.resize(SUPERBLOCK_SIZE, 0);
+
+        let mut image = sb;
+        image.extend_from_slice(&self.data);
+        image.extend_from_slice(&meta);
+        // pad to whole disk sectors
+        image.resize(image.len().next_multiple_of(512), 0);
+        image
+    }
+}
+
+fn main() -> io::Result<()> {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() < 3 {
+        eprintln!(
+            "Usage: {} <source directory> <image path> [block size]",
+            args[0]
+        );
+        std::process::exit(1);
+    }
+    let block_size = match args.get(3) {
+        Some(s) => s.parse().expect("invalid block size"),
+        None => 4096,
+    };
+    assert!(
+        usize::is_power_of_two(block_size) && block_size >= 512,
+        "block size must be a power of two and at least 512"
+    );
+
+    let mut builder = Builder {
+        block_size,
+        inodes: Vec::new(),
+        blocks: Vec::new(),
+        data: Vec::new(),
+    };
+    builder.add_dir(Path::new(&args[1]), None)?;
+    let (num_inodes, num_blocks) = (builder.inodes.len(), builder.blocks.len());
+    let image = builder.finish();
+    fs::write(&args[2], &image)?;
+    println!(
+        "{}: {} inodes, {} blocks, {} bytes",
+        args[2],
+        num_inodes,
+        num_blocks,
+        image.len()
+    );
+    Ok(())
+}
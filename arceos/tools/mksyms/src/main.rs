@@ -0,0 +1,115 @@
+//! Builds the compressed kernel symbol table of axsyms from the output of
+//! `nm` on the kernel image.
+//!
+//! See `modules/axsyms/src/lib.rs` for the table layout.
+
+use std::fs;
+use std::io::{self, BufRead};
+
+const MAGIC: &[u8; 8] = b"AXSYMS\0\0";
+const BLOCK_LEN: usize = 64;
+const MAX_NAME_LEN: usize = 255;
+
+/// Removes the hash that ends legacy Rust symbol names (`::h` followed by 16
+/// hex digits).
+fn strip_hash(name: &str) -> &str {
+    match name.len().checked_sub(19) {
+        Some(i)
+            if name.is_char_boundary(i)
+                && name[i..].starts_with("::h")
+                && name[i + 3..].bytes().all(|b| b.is_ascii_hexdigit()) =>
+        {
+            &name[..i]
+        }
+        _ => name,
+    }
+}
+
+fn truncate(name: &str) -> &str {
+    let mut len = name.len().min(MAX_NAME_LEN);
+    while !name.is_char_boundary(len) {
+        len -= 1;
+    }
+    &name[..len]
+}
+
+/// Parses a line of `nm` output, e.g. `ffffffc080200000 T _start`, and
+/// returns the address and name of code symbols.
+fn parse_line(line: &str) -> Option<(u64, &str)> {
+    let (addr, rest) = line.trim_end().split_once(' ')?;
+    let (ty, name) = rest.split_once(' ')?;
+    if !matches!(ty, "T" | "t" | "W" | "w") || name.is_empty() {
+        return None;
+    }
+    let addr = u64::from_str_radix(addr, 16).ok()?;
+    Some((addr, truncate(strip_hash(name))))
+}
+
+fn push_uleb128(out: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(byte);
+            break;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn build(symbols: &[(u64, String)]) -> Vec<u8> {
+    let mut index = Vec::new();
+    let mut data = Vec::new();
+    for block in symbols.chunks(BLOCK_LEN) {
+        index.extend_from_slice(&block[0].0.to_le_bytes());
+        index.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        index.extend_from_slice(&0u32.to_le_bytes());
+        let (mut prev_addr, mut prev_name) = (block[0].0, "");
+        for (addr, name) in block {
+            let prefix = prev_name
+                .bytes()
+                .zip(name.bytes())
+                .take_while(|(a, b)| a == b)
+                .count();
+            push_uleb128(&mut data, addr - prev_addr);
+            data.push(prefix as u8);
+            data.push((name.len() - prefix) as u8);
+            data.extend_from_slice(&name.as_bytes()[prefix..]);
+            (prev_addr, prev_name) = (*addr, name);
+        }
+    }
+
+    let mut out = Vec::with_capacity(16 + index.len() + data.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&(symbols.len() as u32).to_le_bytes());
+    out.extend_from_slice(&(index.len() as u32 / 16).to_le_bytes());
+    out.extend_from_slice(&index);
+    out.extend_from_slice(&data);
+    out
+}
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 2 {
+        eprintln!(
+            "Usage: nm -n -C --defined-only <kernel ELF> | {} <table path>",
+            args[0]
+        );
+        std::process::exit(1);
+    }
+
+    let mut symbols = Vec::new();
+    for line in io::stdin().lock().lines() {
+        if let Some((addr, name)) = parse_line(&line?) {
+            symbols.push((addr, name.to_string()));
+        }
+    }
+    // keep the first name of each address
+    symbols.sort_by_key(|(addr, _)| *addr);
+    symbols.dedup_by_key(|(addr, _)| *addr);
+
+    let table = build(&symbols);
+    fs::write(&args[1], &table)?;
+    println!("{} symbols, {} bytes", symbols.len(), table.len());
+    Ok(())
+}
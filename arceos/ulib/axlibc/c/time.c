@@ -160,6 +160,20 @@ int gettimeofday(struct timeval *tv, struct timezone *tz)
     return 0;
 }
 
+int settimeofday(const struct timeval *tv, const struct timezone *tz)
+{
+    struct timespec ts;
+    if (!tv)
+        return 0;
+    if (tv->tv_usec < 0 || tv->tv_usec >= 1000000) {
+        errno = EINVAL;
+        return -1;
+    }
+    ts.tv_sec = tv->tv_sec;
+    ts.tv_nsec = tv->tv_usec * 1000;
+    return clock_settime(CLOCK_REALTIME, &ts);
+}
+
 // TODO:
 int utimes(const char *filename, const struct timeval times[2])
 {
@@ -110,13 +110,6 @@ int ftruncate(int fd, off_t length)
     return 0;
 }
 
-// TODO
-int chdir(const char *__path)
-{
-    unimplemented();
-    return 0;
-}
-
 // TODO
 int truncate(const char *path, off_t length)
 {
@@ -33,6 +33,8 @@ struct itimerval {
 };
 
 int gettimeofday(struct timeval *tv, struct timezone *tz);
+int settimeofday(const struct timeval *tv, const struct timezone *tz);
+int adjtime(const struct timeval *delta, struct timeval *olddelta);
 
 int getitimer(int, struct itimerval *);
 int setitimer(int, const struct itimerval *__restrict, struct itimerval *__restrict);
@@ -0,0 +1,23 @@
+#ifndef _SYS_XATTR_H
+#define _SYS_XATTR_H
+
+#include <stddef.h>
+#include <sys/types.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define XATTR_CREATE  1
+#define XATTR_REPLACE 2
+
+ssize_t getxattr(const char *, const char *, void *, size_t);
+int setxattr(const char *, const char *, const void *, size_t, int);
+ssize_t listxattr(const char *, char *, size_t);
+int removexattr(const char *, const char *);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif // _SYS_XATTR_H
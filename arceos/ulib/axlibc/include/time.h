@@ -4,8 +4,12 @@
 #include <stddef.h>
 #include <sys/time.h>
 
-#define CLOCK_REALTIME  0
-#define CLOCK_MONOTONIC 1
+#define CLOCK_REALTIME           0
+#define CLOCK_MONOTONIC          1
+#define CLOCK_MONOTONIC_RAW      4
+#define CLOCK_REALTIME_COARSE    5
+#define CLOCK_MONOTONIC_COARSE   6
+#define CLOCK_BOOTTIME           7
 #define CLOCKS_PER_SEC  1000000L
 
 struct tm {
@@ -39,5 +43,7 @@ void tzset(void);
 
 int nanosleep(const struct timespec *requested_time, struct timespec *remaining);
 int clock_gettime(clockid_t _clk, struct timespec *ts);
+int clock_settime(clockid_t _clk, const struct timespec *ts);
+int clock_getres(clockid_t _clk, struct timespec *res);
 
 #endif // __TIME_H__
@@ -57,6 +57,7 @@ int access(const char *, int);
 int faccessat(int, const char *, int, int);
 
 int chdir(const char *);
+int chroot(const char *);
 int fchdir(int);
 char *getcwd(char *, size_t);
 
@@ -1,7 +1,9 @@
-use core::ffi::{c_char, c_int};
+use core::ffi::{c_char, c_int, c_void};
 
 use arceos_posix_api::{
-    sys_fstat, sys_getcwd, sys_lseek, sys_lstat, sys_open, sys_rename, sys_stat,
+    sys_chdir, sys_chroot, sys_fallocate, sys_fstat, sys_getcwd, sys_getxattr, sys_listxattr,
+    sys_lseek, sys_lstat, sys_open, sys_removexattr, sys_rename, sys_setxattr, sys_stat,
+    sys_statfs, sys_statvfs,
 };
 
 use crate::{ctypes, utils::e};
@@ -27,6 +29,19 @@ pub unsafe extern "C" fn lseek(fd: c_int, offset: ctypes::off_t, whence: c_int)
     e(sys_lseek(fd, offset, whence) as _) as _
 }
 
+/// Manipulate the allocated space of the file `fd`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn fallocate(
+    fd: c_int,
+    mode: c_int,
+    offset: ctypes::off_t,
+    len: ctypes::off_t,
+) -> c_int {
+    e(sys_fallocate(fd, mode, offset, len))
+}
+
 /// Get the file metadata by `path` and write into `buf`.
 ///
 /// Return 0 if success.
@@ -35,6 +50,24 @@ pub unsafe extern "C" fn stat(path: *const c_char, buf: *mut ctypes::stat) -> c_
     e(sys_stat(path, buf))
 }
 
+/// Get the statistics of the filesystem containing `path` and write into
+/// `buf`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn statfs(path: *const c_char, buf: *mut ctypes::statfs) -> c_int {
+    e(sys_statfs(path, buf))
+}
+
+/// Get the statistics of the filesystem containing `path` in the POSIX
+/// format and write into `buf`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn statvfs(path: *const c_char, buf: *mut ctypes::statvfs) -> c_int {
+    e(sys_statvfs(path, buf))
+}
+
 /// Get file metadata by `fd` and write into `buf`.
 ///
 /// Return 0 if success.
@@ -57,6 +90,22 @@ pub unsafe extern "C" fn getcwd(buf: *mut c_char, size: usize) -> *mut c_char {
     sys_getcwd(buf, size)
 }
 
+/// Change the current working directory to `path`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn chdir(path: *const c_char) -> c_int {
+    e(sys_chdir(path))
+}
+
+/// Change the root directory to `path`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn chroot(path: *const c_char) -> c_int {
+    e(sys_chroot(path))
+}
+
 /// Rename `old` to `new`
 /// If new exists, it is first removed.
 ///
@@ -65,3 +114,50 @@ pub unsafe extern "C" fn getcwd(buf: *mut c_char, size: usize) -> *mut c_char {
 pub unsafe extern "C" fn rename(old: *const c_char, new: *const c_char) -> c_int {
     e(sys_rename(old, new))
 }
+
+/// Get the value of the extended attribute `name` of the file at `path`.
+///
+/// Return the size of the value. If `size` is 0, only the size is returned.
+#[no_mangle]
+pub unsafe extern "C" fn getxattr(
+    path: *const c_char,
+    name: *const c_char,
+    value: *mut c_void,
+    size: usize,
+) -> ctypes::ssize_t {
+    e(sys_getxattr(path, name, value, size) as _) as _
+}
+
+/// Set the value of the extended attribute `name` of the file at `path`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn setxattr(
+    path: *const c_char,
+    name: *const c_char,
+    value: *const c_void,
+    size: usize,
+    flags: c_int,
+) -> c_int {
+    e(sys_setxattr(path, name, value, size, flags))
+}
+
+/// List the names of the extended attributes of the file at `path`.
+///
+/// Return the size of the list. If `size` is 0, only the size is returned.
+#[no_mangle]
+pub unsafe extern "C" fn listxattr(
+    path: *const c_char,
+    list: *mut c_char,
+    size: usize,
+) -> ctypes::ssize_t {
+    e(sys_listxattr(path, list, size) as _) as _
+}
+
+/// Remove the extended attribute `name` of the file at `path`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn removexattr(path: *const c_char, name: *const c_char) -> c_int {
+    e(sys_removexattr(path, name))
+}
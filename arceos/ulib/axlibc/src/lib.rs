@@ -88,7 +88,7 @@ pub use self::rand::{rand, random, srand};
 pub use self::resource::{getrlimit, setrlimit};
 pub use self::setjmp::{longjmp, setjmp};
 pub use self::sys::sysconf;
-pub use self::time::{clock_gettime, nanosleep};
+pub use self::time::{adjtime, clock_getres, clock_gettime, clock_settime, nanosleep};
 pub use self::unistd::{abort, exit, getpid};
 
 #[cfg(feature = "alloc")]
@@ -100,12 +100,15 @@ pub use self::strftime::strftime;
 pub use self::fd_ops::{ax_fcntl, close, dup, dup2, dup3};
 
 #[cfg(feature = "fs")]
-pub use self::fs::{ax_open, fstat, getcwd, lseek, lstat, rename, stat};
+pub use self::fs::{
+    ax_open, chdir, chroot, fallocate, fstat, getcwd, getxattr, listxattr, lseek, lstat,
+    removexattr, rename, setxattr, stat, statfs, statvfs,
+};
 
 #[cfg(feature = "net")]
 pub use self::net::{
-    accept, bind, connect, freeaddrinfo, getaddrinfo, getpeername, getsockname, listen, recv,
-    recvfrom, send, sendto, shutdown, socket,
+    accept, bind, connect, freeaddrinfo, getaddrinfo, getpeername, getsockname, getsockopt, listen,
+    recv, recvfrom, send, sendto, setsockopt, shutdown, socket,
 };
 
 #[cfg(feature = "multitask")]
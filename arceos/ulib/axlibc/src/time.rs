@@ -1,14 +1,40 @@
-use arceos_posix_api::{sys_clock_gettime, sys_nanosleep};
+use arceos_posix_api::{
+    sys_adjtime, sys_clock_getres, sys_clock_gettime, sys_clock_settime, sys_nanosleep,
+};
 use core::ffi::c_int;
 
 use crate::{ctypes, utils::e};
 
-/// Get clock time since booting
+/// Get the time of the clock `clk`
 #[no_mangle]
 pub unsafe extern "C" fn clock_gettime(clk: ctypes::clockid_t, ts: *mut ctypes::timespec) -> c_int {
     e(sys_clock_gettime(clk, ts))
 }
 
+/// Set the time of the clock `clk`
+#[no_mangle]
+pub unsafe extern "C" fn clock_settime(
+    clk: ctypes::clockid_t,
+    ts: *const ctypes::timespec,
+) -> c_int {
+    e(sys_clock_settime(clk, ts))
+}
+
+/// Get the resolution of the clock `clk`
+#[no_mangle]
+pub unsafe extern "C" fn clock_getres(clk: ctypes::clockid_t, res: *mut ctypes::timespec) -> c_int {
+    e(sys_clock_getres(clk, res))
+}
+
+/// Gradually correct the realtime clock
+#[no_mangle]
+pub unsafe extern "C" fn adjtime(
+    delta: *const ctypes::timeval,
+    olddelta: *mut ctypes::timeval,
+) -> c_int {
+    e(sys_adjtime(delta, olddelta))
+}
+
 /// Sleep some nanoseconds
 ///
 /// TODO: should be woken by signals, and set errno
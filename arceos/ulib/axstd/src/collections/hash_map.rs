@@ -0,0 +1,542 @@
+//! A hash map implemented with quadratic probing and SIMD lookup.
+//!
+//! The table is the one of `hashbrown`, as in std, and the keys are hashed by
+//! default with SipHash, keyed by a random number of the kernel for each map
+//! so that the order of the entries cannot be predicted.
+
+use alloc::collections::{TryReserveError, TryReserveErrorKind};
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hash, Hasher};
+use core::ops::Index;
+
+#[allow(deprecated)]
+use core::hash::SipHasher;
+
+use hashbrown::hash_map::{RustcEntry, RustcOccupiedEntry, RustcVacantEntry};
+
+#[doc(inline)]
+pub use hashbrown::hash_map::{Drain, IntoIter, Iter, IterMut, Keys, Values, ValuesMut};
+
+/// The default [`BuildHasher`] of [`HashMap`], which builds [`DefaultHasher`]s
+/// with random keys.
+#[derive(Debug, Clone)]
+pub struct RandomState {
+    k0: u64,
+    k1: u64,
+}
+
+impl RandomState {
+    /// Constructs a new `RandomState`, with new random keys.
+    pub fn new() -> Self {
+        let keys = arceos_api::sys::ax_random();
+        Self {
+            k0: keys as u64,
+            k1: (keys >> 64) as u64,
+        }
+    }
+}
+
+impl Default for RandomState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BuildHasher for RandomState {
+    type Hasher = DefaultHasher;
+
+    fn build_hasher(&self) -> DefaultHasher {
+        #[allow(deprecated)]
+        DefaultHasher(SipHasher::new_with_keys(self.k0, self.k1))
+    }
+}
+
+/// The default [`Hasher`] of [`RandomState`], SipHash.
+#[allow(deprecated)]
+#[derive(Debug, Clone)]
+pub struct DefaultHasher(SipHasher);
+
+impl DefaultHasher {
+    /// Creates a new `DefaultHasher`, with zero keys: it always gives the
+    /// same hashes, unlike the ones built by [`RandomState`].
+    #[allow(deprecated)]
+    pub fn new() -> Self {
+        Self(SipHasher::new_with_keys(0, 0))
+    }
+}
+
+impl Default for DefaultHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for DefaultHasher {
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.write(bytes)
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.0.finish()
+    }
+}
+
+/// A hash map, with the interface of `std::collections::HashMap`.
+///
+/// The keys must implement [`Eq`] and [`Hash`], and two equal keys must have
+/// the same hash. They are hashed with `S`, [`RandomState`] by default.
+pub struct HashMap<K, V, S = RandomState> {
+    base: hashbrown::HashMap<K, V, S>,
+}
+
+impl<K, V> HashMap<K, V, RandomState> {
+    /// Creates an empty `HashMap`.
+    ///
+    /// It does not allocate until the first insertion.
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+
+    /// Creates an empty `HashMap` with room for at least `capacity` entries,
+    /// so that they are inserted without reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    /// Creates an empty `HashMap` which hashes the keys with `hash_builder`.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            base: hashbrown::HashMap::with_hasher(hash_builder),
+        }
+    }
+
+    /// Creates an empty `HashMap` with room for at least `capacity` entries,
+    /// which hashes the keys with `hash_builder`.
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            base: hashbrown::HashMap::with_capacity_and_hasher(capacity, hash_builder),
+        }
+    }
+
+    /// Returns the number of entries the map can hold without reallocating.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.base.capacity()
+    }
+
+    /// Returns the number of entries in the map.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.base.len()
+    }
+
+    /// Returns `true` if the map has no entries.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.base.is_empty()
+    }
+
+    /// Removes all the entries, keeping the allocated memory for reuse.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.base.clear();
+    }
+
+    /// An iterator over the entries, in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.base.iter()
+    }
+
+    /// An iterator over the entries, with mutable references to the values,
+    /// in arbitrary order.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        self.base.iter_mut()
+    }
+
+    /// An iterator over the keys, in arbitrary order.
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        self.base.keys()
+    }
+
+    /// An iterator over the values, in arbitrary order.
+    pub fn values(&self) -> Values<'_, K, V> {
+        self.base.values()
+    }
+
+    /// An iterator over mutable references to the values, in arbitrary order.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        self.base.values_mut()
+    }
+
+    /// Removes all the entries and returns them as an iterator, keeping the
+    /// allocated memory for reuse.
+    ///
+    /// The map is empty even if the iterator is dropped before the end.
+    pub fn drain(&mut self) -> Drain<'_, K, V> {
+        self.base.drain()
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    /// Inserts a key-value pair in the map.
+    ///
+    /// If the map already had the key, the value is updated and the old one
+    /// returned, the key is not updated.
+    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
+        self.base.insert(k, v)
+    }
+
+    /// Reserves room for at least `additional` more entries, so that they
+    /// are inserted without reallocating.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new capacity overflows `usize`, and aborts if the
+    /// allocation fails. See [`try_reserve`](Self::try_reserve).
+    pub fn reserve(&mut self, additional: usize) {
+        self.base.reserve(additional)
+    }
+
+    /// Tries to reserve room for at least `additional` more entries, and
+    /// returns an error instead of panicking or aborting if the capacity
+    /// overflows or the allocation fails.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.base
+            .try_reserve(additional)
+            .map_err(map_try_reserve_error)
+    }
+
+    /// Shrinks the capacity of the map as much as possible, keeping the room
+    /// the table needs for its entries.
+    pub fn shrink_to_fit(&mut self) {
+        self.base.shrink_to_fit()
+    }
+
+    /// Returns the entry of the key, to read, insert or update its value
+    /// with a single lookup.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        match self.base.rustc_entry(key) {
+            RustcEntry::Occupied(base) => Entry::Occupied(OccupiedEntry { base }),
+            RustcEntry::Vacant(base) => Entry::Vacant(VacantEntry { base }),
+        }
+    }
+
+    /// Returns a reference to the value of the key.
+    ///
+    /// The key may be any borrowed form of the type of the keys, e.g. `&str`
+    /// for `String` keys.
+    pub fn get<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.get(k)
+    }
+
+    /// Returns a mutable reference to the value of the key.
+    pub fn get_mut<Q>(&mut self, k: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.get_mut(k)
+    }
+
+    /// Returns `true` if the map has a value for the key.
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.contains_key(k)
+    }
+
+    /// Removes the key from the map, and returns its value if it was there.
+    pub fn remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.remove(k)
+    }
+}
+
+impl<K: Clone, V: Clone, S: Clone> Clone for HashMap<K, V, S> {
+    fn clone(&self) -> Self {
+        Self {
+            base: self.base.clone(),
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.base.clone_from(&source.base);
+    }
+}
+
+/// Two maps are equal if they have the same keys, with equal values.
+impl<K, V, S> PartialEq for HashMap<K, V, S>
+where
+    K: Eq + Hash,
+    V: PartialEq,
+    S: BuildHasher,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.base == other.base
+    }
+}
+
+impl<K, V, S> Eq for HashMap<K, V, S>
+where
+    K: Eq + Hash,
+    V: Eq,
+    S: BuildHasher,
+{
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, S> fmt::Debug for HashMap<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, S: Default> Default for HashMap<K, V, S> {
+    /// Creates an empty `HashMap`, with the default hasher.
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, Q, V, S> Index<&Q> for HashMap<K, V, S>
+where
+    K: Eq + Hash + Borrow<Q>,
+    Q: Eq + Hash + ?Sized,
+    S: BuildHasher,
+{
+    type Output = V;
+
+    /// Returns a reference to the value of the key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is not in the map.
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a HashMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a mut HashMap<K, V, S> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+/// Consumes the map into an iterator over its entries, in arbitrary order.
+impl<K, V, S> IntoIterator for HashMap<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        self.base.into_iter()
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for HashMap<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self {
+            base: hashbrown::HashMap::from_iter(iter),
+        }
+    }
+}
+
+/// Inserts the entries, replacing the values of the keys already there.
+impl<K, V, S> Extend<(K, V)> for HashMap<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        self.base.extend(iter)
+    }
+}
+
+impl<'a, K, V, S> Extend<(&'a K, &'a V)> for HashMap<K, V, S>
+where
+    K: Eq + Hash + Copy,
+    V: Copy,
+    S: BuildHasher,
+{
+    fn extend<I: IntoIterator<Item = (&'a K, &'a V)>>(&mut self, iter: I) {
+        self.base.extend(iter)
+    }
+}
+
+fn map_try_reserve_error(err: hashbrown::TryReserveError) -> TryReserveError {
+    match err {
+        hashbrown::TryReserveError::CapacityOverflow => TryReserveErrorKind::CapacityOverflow,
+        hashbrown::TryReserveError::AllocError { layout } => TryReserveErrorKind::AllocError {
+            layout,
+            non_exhaustive: (),
+        },
+    }
+    .into()
+}
+
+/// The entry of a key in a [`HashMap`], returned by [`HashMap::entry`].
+pub enum Entry<'a, K: 'a, V: 'a> {
+    /// The map has the key.
+    Occupied(OccupiedEntry<'a, K, V>),
+    /// The map does not have the key.
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+impl<'a, K, V> Entry<'a, K, V> {
+    /// Inserts `default` if the entry is vacant, and returns a mutable
+    /// reference to the value.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Self::Occupied(entry) => entry.into_mut(),
+            Self::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Inserts the value returned by `default` if the entry is vacant, and
+    /// returns a mutable reference to the value.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Self::Occupied(entry) => entry.into_mut(),
+            Self::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Inserts the value returned by `default`, called with the key, if the
+    /// entry is vacant, and returns a mutable reference to the value.
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Self::Occupied(entry) => entry.into_mut(),
+            Self::Vacant(entry) => {
+                let value = default(entry.key());
+                entry.insert(value)
+            }
+        }
+    }
+
+    /// Returns the key of the entry.
+    pub fn key(&self) -> &K {
+        match self {
+            Self::Occupied(entry) => entry.key(),
+            Self::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Calls `f` with the value if the entry is occupied, and returns the
+    /// entry, e.g. to insert a value then.
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Self::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Self::Occupied(entry)
+            }
+            Self::Vacant(entry) => Self::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, K, V: Default> Entry<'a, K, V> {
+    /// Inserts the default value if the entry is vacant, and returns a
+    /// mutable reference to the value.
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+/// An occupied [`Entry`].
+pub struct OccupiedEntry<'a, K, V> {
+    base: RustcOccupiedEntry<'a, K, V>,
+}
+
+impl<'a, K, V> OccupiedEntry<'a, K, V> {
+    /// Returns the key in the map.
+    pub fn key(&self) -> &K {
+        self.base.key()
+    }
+
+    /// Removes the entry from the map, and returns the key and the value.
+    pub fn remove_entry(self) -> (K, V) {
+        self.base.remove_entry()
+    }
+
+    /// Returns a reference to the value.
+    pub fn get(&self) -> &V {
+        self.base.get()
+    }
+
+    /// Returns a mutable reference to the value, which lives as long as the
+    /// entry; see [`into_mut`](Self::into_mut) for one borrowing the map.
+    pub fn get_mut(&mut self) -> &mut V {
+        self.base.get_mut()
+    }
+
+    /// Returns a mutable reference to the value, which lives as long as the
+    /// borrow of the map.
+    pub fn into_mut(self) -> &'a mut V {
+        self.base.into_mut()
+    }
+
+    /// Sets the value, and returns the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        self.base.insert(value)
+    }
+
+    /// Removes the entry from the map, and returns the value.
+    pub fn remove(self) -> V {
+        self.base.remove()
+    }
+}
+
+/// A vacant [`Entry`].
+pub struct VacantEntry<'a, K, V> {
+    base: RustcVacantEntry<'a, K, V>,
+}
+
+impl<'a, K, V> VacantEntry<'a, K, V> {
+    /// Returns the key which would be inserted.
+    pub fn key(&self) -> &K {
+        self.base.key()
+    }
+
+    /// Returns the key, without inserting it.
+    pub fn into_key(self) -> K {
+        self.base.into_key()
+    }
+
+    /// Inserts the key with `value`, and returns a mutable reference to the
+    /// value.
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.base.insert(value)
+    }
+}
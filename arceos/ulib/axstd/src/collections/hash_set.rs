@@ -0,0 +1,142 @@
+//! A hash set wrapping the one of `hashbrown`, with the same table and
+//! default hasher as [`HashMap`](super::HashMap).
+
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+
+use super::hash_map::RandomState;
+
+#[doc(inline)]
+pub use hashbrown::hash_set::{Difference, Intersection, Iter, SymmetricDifference, Union};
+
+/// A hash set, with the interface of `std::collections::HashSet`.
+///
+/// As with [`HashMap`](super::HashMap), the values must implement [`Eq`] and
+/// [`Hash`], and are hashed with `S`, [`RandomState`] by default.
+pub struct HashSet<T, S = RandomState> {
+    base: hashbrown::HashSet<T, S>,
+}
+
+impl<T> HashSet<T, RandomState> {
+    /// Creates an empty `HashSet`.
+    ///
+    /// It does not allocate until the first insertion.
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<T, S> HashSet<T, S> {
+    /// Creates an empty `HashSet` which hashes the values with
+    /// `hash_builder`.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            base: hashbrown::HashSet::with_hasher(hash_builder),
+        }
+    }
+
+    /// Returns the number of values in the set.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.base.len()
+    }
+
+    /// Returns `true` if the set has no values.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.base.is_empty()
+    }
+
+    /// Removes all the values, keeping the allocated memory for reuse.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.base.clear();
+    }
+
+    /// An iterator over the values, in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.base.iter()
+    }
+}
+
+impl<T, S> HashSet<T, S>
+where
+    T: Eq + Hash,
+    S: BuildHasher,
+{
+    /// Adds a value to the set, and returns whether it was not there.
+    pub fn insert(&mut self, value: T) -> bool {
+        self.base.insert(value)
+    }
+
+    /// Returns `true` if the set has the value.
+    ///
+    /// The value may be any borrowed form of the type of the values, e.g.
+    /// `&str` for `String` values.
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.contains(value)
+    }
+
+    /// Removes a value from the set, and returns whether it was there.
+    pub fn remove<Q>(&mut self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.remove(value)
+    }
+
+    /// An iterator over the values in `self` or `other`, without duplicates.
+    pub fn union<'a>(&'a self, other: &'a HashSet<T, S>) -> Union<'a, T, S> {
+        self.base.union(&other.base)
+    }
+
+    /// An iterator over the values in both `self` and `other`.
+    pub fn intersection<'a>(&'a self, other: &'a HashSet<T, S>) -> Intersection<'a, T, S> {
+        self.base.intersection(&other.base)
+    }
+
+    /// An iterator over the values in `self` but not in `other`.
+    pub fn difference<'a>(&'a self, other: &'a HashSet<T, S>) -> Difference<'a, T, S> {
+        self.base.difference(&other.base)
+    }
+
+    /// An iterator over the values in `self` or `other`, but not in both.
+    pub fn symmetric_difference<'a>(
+        &'a self,
+        other: &'a HashSet<T, S>,
+    ) -> SymmetricDifference<'a, T, S> {
+        self.base.symmetric_difference(&other.base)
+    }
+
+    /// Returns `true` if `self` and `other` have no value in common.
+    pub fn is_disjoint(&self, other: &HashSet<T, S>) -> bool {
+        self.base.is_disjoint(&other.base)
+    }
+
+    /// Returns `true` if all the values of `self` are in `other`.
+    pub fn is_subset(&self, other: &HashSet<T, S>) -> bool {
+        self.base.is_subset(&other.base)
+    }
+
+    /// Returns `true` if all the values of `other` are in `self`.
+    pub fn is_superset(&self, other: &HashSet<T, S>) -> bool {
+        self.base.is_superset(&other.base)
+    }
+}
+
+impl<T, S> FromIterator<T> for HashSet<T, S>
+where
+    T: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self {
+            base: hashbrown::HashSet::from_iter(iter),
+        }
+    }
+}
@@ -0,0 +1,23 @@
+//! Collection types.
+//!
+//! The collections of [`alloc::collections`] under the paths of the ones of
+//! std, and a [`HashMap`] and a [`HashSet`] with the interface of the ones of
+//! std, built on `hashbrown`.
+
+#[doc(no_inline)]
+pub use alloc::collections::{binary_heap, btree_map, btree_set, linked_list, vec_deque};
+#[doc(no_inline)]
+pub use alloc::collections::{BTreeMap, BTreeSet, BinaryHeap, LinkedList, VecDeque};
+
+pub use alloc::collections::TryReserveError;
+
+pub mod hash_map;
+pub mod hash_set;
+
+#[doc(inline)]
+pub use self::hash_map::HashMap;
+#[doc(inline)]
+pub use self::hash_set::HashSet;
+
+#[cfg(test)]
+mod tests;
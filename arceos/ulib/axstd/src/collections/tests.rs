@@ -0,0 +1,112 @@
+use alloc::collections::TryReserveErrorKind;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::hash::BuildHasher;
+
+use super::hash_map::{Entry, RandomState};
+use super::HashMap;
+
+#[test]
+fn test_random_state() {
+    let state = RandomState::new();
+    assert_eq!(state.hash_one(42u32), state.hash_one(42u32));
+    assert_eq!(state.clone().hash_one("key"), state.hash_one("key"));
+
+    // the same keys give the same order
+    let mut a = HashMap::with_hasher(state.clone());
+    let mut b = HashMap::with_hasher(state);
+    for i in 0..32 {
+        a.insert(i, ());
+        b.insert(i, ());
+    }
+    assert!(a.keys().eq(b.keys()));
+}
+
+#[test]
+fn test_insert_remove() {
+    let mut map = HashMap::new();
+    assert!(map.is_empty());
+    assert_eq!(map.insert("a", 1), None);
+    assert_eq!(map.insert("a", 2), Some(1));
+    assert_eq!(map.insert("b", 3), None);
+    assert_eq!(map.get("a"), Some(&2));
+    *map.get_mut("b").unwrap() += 1;
+    assert_eq!(map.remove("b"), Some(4));
+    assert!(!map.contains_key("b"));
+    assert_eq!(map.len(), 1);
+    map.clear();
+    assert!(map.is_empty());
+}
+
+#[test]
+fn test_entry() {
+    let mut map = HashMap::new();
+    *map.entry("a").or_insert(0) += 1;
+    *map.entry("a").or_insert(0) += 1;
+    map.entry("b").and_modify(|v| *v = 10).or_default();
+    assert_eq!(map.get("a"), Some(&2));
+    assert_eq!(map.get("b"), Some(&0));
+
+    match map.entry("a") {
+        Entry::Occupied(mut e) => {
+            assert_eq!(e.insert(5), 2);
+            assert_eq!(e.remove_entry(), ("a", 5));
+        }
+        Entry::Vacant(_) => panic!("the key is in the map"),
+    }
+    match map.entry("c") {
+        Entry::Vacant(e) => {
+            assert_eq!(e.key(), &"c");
+            *e.insert(1) += 1;
+        }
+        Entry::Occupied(_) => panic!("the key is not in the map"),
+    }
+    assert_eq!(map.get("a"), None);
+    assert_eq!(map.get("c"), Some(&2));
+    assert_eq!(map.len(), 2);
+}
+
+#[test]
+fn test_capacity() {
+    let mut map: HashMap<u32, u32> = HashMap::new();
+    let err = map.try_reserve(usize::MAX).unwrap_err();
+    assert_eq!(err.kind(), TryReserveErrorKind::CapacityOverflow);
+
+    map.try_reserve(100).unwrap();
+    assert!(map.capacity() >= 100);
+    for i in 0..10 {
+        map.insert(i, i);
+    }
+    map.shrink_to_fit();
+    assert!(map.capacity() >= 10 && map.capacity() < 100);
+    assert_eq!(map.len(), 10);
+
+    let map: HashMap<u32, u32> = HashMap::with_capacity(20);
+    assert!(map.capacity() >= 20);
+}
+
+#[test]
+fn test_traits() {
+    let map: HashMap<u32, u32> = (0..4).map(|i| (i, i * i)).collect();
+    let mut other = HashMap::new();
+    other.extend([(3, 9), (2, 4)]);
+    assert_ne!(map, other);
+    other.extend([(&1, &1), (&0, &0)]);
+    assert_eq!(map, other);
+    assert_eq!(map[&2], 4);
+
+    let one: HashMap<&str, String> = [("a", String::from("b"))].into_iter().collect();
+    assert_eq!(format!("{one:?}"), r#"{"a": "b"}"#);
+
+    let mut pairs: Vec<_> = map.into_iter().collect();
+    pairs.sort();
+    assert_eq!(pairs, [(0, 0), (1, 1), (2, 4), (3, 9)]);
+}
+
+#[test]
+#[should_panic]
+fn test_index_missing() {
+    let map: HashMap<u32, u32> = HashMap::new();
+    let _ = map[&1];
+}
@@ -0,0 +1,165 @@
+//! Configuration and data files in JSON or TOML.
+//!
+//! Both formats are parsed to the same [`Value`] tree, so the applications
+//! read their settings the same way whatever the format of the file:
+//!
+//! * [`json`] parses and prints JSON documents (with the `json` feature)
+//! * [`toml`] parses TOML documents (with the `toml` feature)
+//! * [`load`] reads a file in the format given by its extension (with the
+//!   `fs` feature)
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "toml")]
+pub mod toml;
+
+/// A table of values, sorted by key.
+pub type Table = BTreeMap<String, Value>;
+
+/// A value of a JSON or TOML document.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// JSON `null` (TOML has none).
+    Null,
+    Bool(bool),
+    /// A number without fraction or exponent, in the range of `i64`.
+    Integer(i64),
+    Float(f64),
+    /// A string (TOML dates and times are kept as written).
+    String(String),
+    Array(Vec<Value>),
+    /// A JSON object or a TOML table.
+    Table(Table),
+}
+
+impl Value {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Self::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Returns the number, integer or not.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Self::Integer(i) => Some(*i as f64),
+            Self::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Self::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&Table> {
+        match self {
+            Self::Table(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the key, if this is a table.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.as_table()?.get(key)
+    }
+
+    /// Returns the value at the dot-separated path of keys, e.g.
+    /// `"net.dns.server"`.
+    pub fn lookup(&self, path: &str) -> Option<&Value> {
+        path.split('.').try_fold(self, |v, key| v.get(key))
+    }
+}
+
+/// An error in a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseError {
+    /// The line of the error, from 1.
+    pub line: usize,
+    pub msg: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+/// Returns the line of the byte offset in the text.
+fn line_of(text: &[u8], pos: usize) -> usize {
+    1 + text[..pos.min(text.len())]
+        .iter()
+        .filter(|&&c| c == b'\n')
+        .count()
+}
+
+/// Decodes the 4 hex digits of a `\u` escape.
+fn hex4(digits: &[u8]) -> Option<u32> {
+    let digits = core::str::from_utf8(digits.get(..4)?).ok()?;
+    u32::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(feature = "fs")]
+mod load {
+    use super::{ParseError, Value};
+    use crate::io;
+
+    /// An error loading a file.
+    #[derive(Debug)]
+    pub enum LoadError {
+        Io(io::Error),
+        Parse(ParseError),
+        /// The extension is not one of the enabled formats.
+        UnknownFormat,
+    }
+
+    impl core::fmt::Display for LoadError {
+        fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+            match self {
+                Self::Io(e) => write!(f, "I/O error: {:?}", e),
+                Self::Parse(e) => write!(f, "{}", e),
+                Self::UnknownFormat => write!(f, "unknown format"),
+            }
+        }
+    }
+
+    /// Reads and parses the file at `path`, as JSON if it ends with `.json`
+    /// and as TOML if it ends with `.toml`.
+    pub fn load(path: &str) -> Result<Value, LoadError> {
+        let parse: fn(&str) -> Result<Value, ParseError> = match path.rsplit_once('.') {
+            #[cfg(feature = "json")]
+            Some((_, "json")) => super::json::parse,
+            #[cfg(feature = "toml")]
+            Some((_, "toml")) => super::toml::parse,
+            _ => return Err(LoadError::UnknownFormat),
+        };
+        let text = crate::fs::read_to_string(path).map_err(LoadError::Io)?;
+        parse(&text).map_err(LoadError::Parse)
+    }
+}
+
+#[cfg(feature = "fs")]
+pub use self::load::{load, LoadError};
@@ -0,0 +1,492 @@
+//! TOML documents ([TOML 1.0]).
+//!
+//! The dates and times are not interpreted, but kept as strings.
+//!
+//! [TOML 1.0]: https://toml.io/en/v1.0.0
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::{hex4, line_of, ParseError, Table, Value};
+
+/// The maximum nesting of arrays and inline tables, bounding the recursion.
+const MAX_DEPTH: usize = 64;
+
+type Result<T> = core::result::Result<T, ParseError>;
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, msg: &'static str) -> ParseError {
+        ParseError {
+            line: line_of(self.text, self.pos),
+            msg,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).copied()
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.text[self.pos..]
+    }
+
+    fn eat(&mut self, s: &[u8]) -> bool {
+        let matches = self.rest().starts_with(s);
+        if matches {
+            self.pos += s.len();
+        }
+        matches
+    }
+
+    fn skip_spaces(&mut self) {
+        while let Some(b' ' | b'\t') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn eat_newline(&mut self) -> bool {
+        self.eat(b"\n") || self.eat(b"\r\n")
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some(b'#') {
+            while !matches!(self.peek(), None | Some(b'\n' | b'\r')) {
+                self.pos += 1;
+            }
+        }
+    }
+
+    /// Skips the spaces, comments and newlines (in arrays, and between the
+    /// lines of the document).
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            if !self.eat_newline() {
+                return;
+            }
+        }
+    }
+
+    /// Skips the end of a line, which may only be a comment.
+    fn end_of_line(&mut self) -> Result<()> {
+        self.skip_spaces();
+        self.skip_comment();
+        if self.peek().is_some() && !self.eat_newline() {
+            return Err(self.error("expected a newline"));
+        }
+        Ok(())
+    }
+
+    fn key(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(b'"') => self.basic_string(),
+            Some(b'\'') => self.literal_string(),
+            _ => {
+                let len = self
+                    .rest()
+                    .iter()
+                    .take_while(|&&c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-')
+                    .count();
+                if len == 0 {
+                    return Err(self.error("expected a key"));
+                }
+                let key = &self.text[self.pos..self.pos + len];
+                self.pos += len;
+                Ok(String::from_utf8(key.to_vec()).unwrap())
+            }
+        }
+    }
+
+    /// Parses a key made of dot-separated parts.
+    fn dotted_key(&mut self) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        loop {
+            self.skip_spaces();
+            keys.push(self.key()?);
+            self.skip_spaces();
+            if !self.eat(b".") {
+                return Ok(keys);
+            }
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value> {
+        match self.peek() {
+            None => Err(self.error("unexpected end")),
+            Some(b'"') => self.basic_string().map(Value::String),
+            Some(b'\'') => self.literal_string().map(Value::String),
+            Some(b'[' | b'{') if depth == MAX_DEPTH => Err(self.error("nested too deeply")),
+            Some(b'[') => self.array(depth + 1),
+            Some(b'{') => self.inline_table(depth + 1),
+            Some(_) if self.eat(b"true") => Ok(Value::Bool(true)),
+            Some(_) if self.eat(b"false") => Ok(Value::Bool(false)),
+            Some(_) => self.number_or_date(),
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value> {
+        self.pos += 1;
+        let mut array = Vec::new();
+        loop {
+            self.skip_blank();
+            if self.eat(b"]") {
+                return Ok(Value::Array(array));
+            }
+            array.push(self.value(depth)?);
+            self.skip_blank();
+            if self.eat(b"]") {
+                return Ok(Value::Array(array));
+            }
+            if !self.eat(b",") {
+                return Err(self.error("expected `,` or `]`"));
+            }
+        }
+    }
+
+    fn inline_table(&mut self, depth: usize) -> Result<Value> {
+        self.pos += 1;
+        let mut table = Table::new();
+        self.skip_spaces();
+        if self.eat(b"}") {
+            return Ok(Value::Table(table));
+        }
+        loop {
+            self.key_value(&mut table, depth)?;
+            self.skip_spaces();
+            if self.eat(b"}") {
+                return Ok(Value::Table(table));
+            }
+            if !self.eat(b",") {
+                return Err(self.error("expected `,` or `}`"));
+            }
+        }
+    }
+
+    /// Parses `key = value` into the table.
+    fn key_value(&mut self, table: &mut Table, depth: usize) -> Result<()> {
+        let keys = self.dotted_key()?;
+        if !self.eat(b"=") {
+            return Err(self.error("expected `=`"));
+        }
+        self.skip_spaces();
+        let value = self.value(depth)?;
+        let (last, parents) = keys.split_last().unwrap();
+        let table = table_at(table, parents).map_err(|msg| self.error(msg))?;
+        if table.contains_key(last) {
+            return Err(self.error("duplicate key"));
+        }
+        table.insert(last.clone(), value);
+        Ok(())
+    }
+
+    fn basic_string(&mut self) -> Result<String> {
+        let multiline = self.eat(b"\"\"\"");
+        if !multiline {
+            self.pos += 1;
+        } else {
+            // a newline right after the delimiter is trimmed
+            self.eat_newline();
+        }
+        let mut s = Vec::new();
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| self.error("unterminated string"))?;
+            match c {
+                b'"' if !multiline => {
+                    self.pos += 1;
+                    break;
+                }
+                b'"' => {
+                    if self.end_of_multiline(b'"', &mut s) {
+                        break;
+                    }
+                }
+                b'\\' => {
+                    self.pos += 1;
+                    self.escape(&mut s, multiline)?;
+                }
+                b'\n' | b'\r' if multiline && self.eat_newline() => s.push(b'\n'),
+                c if c < 0x20 && c != b'\t' || c == 0x7f => {
+                    return Err(self.error("control character in string"));
+                }
+                c => {
+                    s.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+        // the text is a `str`, and the escapes are pushed as UTF-8
+        Ok(String::from_utf8(s).unwrap())
+    }
+
+    fn literal_string(&mut self) -> Result<String> {
+        let multiline = self.eat(b"'''");
+        if !multiline {
+            self.pos += 1;
+        } else {
+            self.eat_newline();
+        }
+        let mut s = Vec::new();
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| self.error("unterminated string"))?;
+            match c {
+                b'\'' if !multiline => {
+                    self.pos += 1;
+                    break;
+                }
+                b'\'' => {
+                    if self.end_of_multiline(b'\'', &mut s) {
+                        break;
+                    }
+                }
+                b'\n' | b'\r' if multiline && self.eat_newline() => s.push(b'\n'),
+                c if c < 0x20 && c != b'\t' || c == 0x7f => {
+                    return Err(self.error("control character in string"));
+                }
+                c => {
+                    s.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+        Ok(String::from_utf8(s).unwrap())
+    }
+
+    /// Handles the quotes in a multi-line string, and returns whether they
+    /// end it. Up to two quotes may precede the closing delimiter.
+    fn end_of_multiline(&mut self, quote: u8, s: &mut Vec<u8>) -> bool {
+        let n = self.rest().iter().take_while(|&&c| c == quote).count();
+        if n < 3 {
+            s.extend(core::iter::repeat(quote).take(n));
+            self.pos += n;
+            return false;
+        }
+        let n = n.min(5);
+        s.extend(core::iter::repeat(quote).take(n - 3));
+        self.pos += n;
+        true
+    }
+
+    fn escape(&mut self, s: &mut Vec<u8>, multiline: bool) -> Result<()> {
+        let c = self
+            .peek()
+            .ok_or_else(|| self.error("unterminated string"))?;
+        self.pos += 1;
+        let c = match c {
+            b'"' => '"',
+            b'\\' => '\\',
+            b'b' => '\x08',
+            b'f' => '\x0c',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' | b'U' => {
+                const BAD: &str = "invalid unicode escape";
+                let digits = self.rest();
+                let code = if c == b'u' {
+                    hex4(digits)
+                } else {
+                    hex4(digits)
+                        .zip(hex4(digits.get(4..).unwrap_or_default()))
+                        .map(|(high, low)| high << 16 | low)
+                };
+                self.pos += if c == b'u' { 4 } else { 8 };
+                code.and_then(char::from_u32)
+                    .ok_or_else(|| self.error(BAD))?
+            }
+            // a line ending backslash trims the whitespace up to the next
+            // non-blank character
+            b' ' | b'\t' | b'\n' | b'\r' if multiline => {
+                self.pos -= 1;
+                self.skip_spaces();
+                if !self.eat_newline() {
+                    return Err(self.error("invalid escape"));
+                }
+                while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+                    self.pos += 1;
+                }
+                return Ok(());
+            }
+            _ => return Err(self.error("invalid escape")),
+        };
+        s.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        Ok(())
+    }
+
+    fn number_or_date(&mut self) -> Result<Value> {
+        let token_len = |text: &[u8]| {
+            text.iter()
+                .take_while(|&&c| c.is_ascii_alphanumeric() || b"_+-.:".contains(&c))
+                .count()
+        };
+        let start = self.pos;
+        let mut end = start + token_len(self.rest());
+        let token = &self.text[start..end];
+        let is_date = token.len() >= 10 && token[4] == b'-' && token[7] == b'-';
+        if is_date || token.contains(&b':') {
+            // a date and a time may be separated by a space
+            let after = &self.text[end..];
+            if is_date && token.len() == 10 && after.len() > 3 && after[0] == b' ' {
+                if after[1].is_ascii_digit() && after[3] == b':' {
+                    end += 1 + token_len(&after[1..]);
+                }
+            }
+            self.pos = end;
+            let date = core::str::from_utf8(&self.text[start..end]).unwrap();
+            return Ok(Value::String(date.into()));
+        }
+        if token.is_empty() {
+            return Err(self.error("expected a value"));
+        }
+        let value = parse_number(token).ok_or_else(|| self.error("invalid number"))?;
+        self.pos = end;
+        Ok(value)
+    }
+}
+
+fn parse_number(token: &[u8]) -> Option<Value> {
+    let token = core::str::from_utf8(token).ok()?;
+    let (sign, unsigned) = match token.as_bytes()[0] {
+        b'+' | b'-' => token.split_at(1),
+        _ => ("", token),
+    };
+    match unsigned {
+        "inf" => {
+            return Some(Value::Float(if sign == "-" {
+                -f64::INFINITY
+            } else {
+                f64::INFINITY
+            }))
+        }
+        "nan" => return Some(Value::Float(f64::NAN)),
+        _ => {}
+    }
+    // underscores only between digits
+    let bytes = unsigned.as_bytes();
+    let digit = |i: usize| bytes.get(i).is_some_and(u8::is_ascii_alphanumeric);
+    let misplaced =
+        (0..bytes.len()).any(|i| bytes[i] == b'_' && !(i > 0 && digit(i - 1) && digit(i + 1)));
+    if misplaced {
+        return None;
+    }
+    let digits: String = unsigned.chars().filter(|&c| c != '_').collect();
+
+    let radix = match digits.get(..2) {
+        Some("0x") => 16,
+        Some("0o") => 8,
+        Some("0b") => 2,
+        _ => 10,
+    };
+    if radix != 10 {
+        if !sign.is_empty() {
+            return None;
+        }
+        return i64::from_str_radix(&digits[2..], radix)
+            .ok()
+            .map(Value::Integer);
+    }
+    // no leading zeros
+    let int_part = digits.split(['.', 'e', 'E']).next()?;
+    if int_part.is_empty() || int_part.len() > 1 && int_part.starts_with('0') {
+        return None;
+    }
+    if digits.contains(['.', 'e', 'E']) {
+        // a dot has digits on both sides
+        if digits.contains(".e") || digits.contains(".E") || digits.ends_with('.') {
+            return None;
+        }
+        let f: f64 = digits.parse().ok()?;
+        return Some(Value::Float(if sign == "-" { -f } else { f }));
+    }
+    if !digits.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let mut signed = String::from(sign);
+    signed.push_str(&digits);
+    signed.parse().ok().map(Value::Integer)
+}
+
+/// Returns the table at the path of keys in `root`, creating the missing
+/// ones. An array of tables on the path stands for its last table.
+fn table_at<'t>(
+    root: &'t mut Table,
+    path: &[String],
+) -> core::result::Result<&'t mut Table, &'static str> {
+    let mut table = root;
+    for key in path {
+        let value = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        table = match value {
+            Value::Table(t) => t,
+            Value::Array(array) => match array.last_mut() {
+                Some(Value::Table(t)) => t,
+                _ => return Err("key is not a table"),
+            },
+            _ => return Err("key is not a table"),
+        };
+    }
+    Ok(table)
+}
+
+/// Parses a TOML document.
+pub fn parse(text: &str) -> Result<Value> {
+    let mut parser = Parser {
+        text: text.as_bytes(),
+        pos: 0,
+    };
+    let mut root = Table::new();
+    // the header of the current table, and the ones already defined
+    let mut current = Vec::new();
+    let mut defined: Vec<Vec<String>> = Vec::new();
+    loop {
+        parser.skip_blank();
+        if parser.peek().is_none() {
+            return Ok(Value::Table(root));
+        }
+        if parser.eat(b"[[") {
+            let path = parser.dotted_key()?;
+            if !parser.eat(b"]]") {
+                return Err(parser.error("expected `]]`"));
+            }
+            let (last, parents) = path.split_last().unwrap();
+            let table = table_at(&mut root, parents).map_err(|msg| parser.error(msg))?;
+            match table
+                .entry(last.clone())
+                .or_insert_with(|| Value::Array(Vec::new()))
+            {
+                Value::Array(array) if array.iter().all(|v| matches!(v, Value::Table(_))) => {
+                    array.push(Value::Table(Table::new()))
+                }
+                _ => return Err(parser.error("key is not an array of tables")),
+            }
+            // the tables under the previous element may be defined again
+            defined.retain(|d| !d.starts_with(&path));
+            current = path;
+        } else if parser.eat(b"[") {
+            let path = parser.dotted_key()?;
+            if !parser.eat(b"]") {
+                return Err(parser.error("expected `]`"));
+            }
+            if defined.contains(&path) {
+                return Err(parser.error("duplicate table"));
+            }
+            table_at(&mut root, &path).map_err(|msg| parser.error(msg))?;
+            defined.push(path.clone());
+            current = path;
+        } else {
+            let table = table_at(&mut root, &current).map_err(|msg| parser.error(msg))?;
+            parser.key_value(table, 0)?;
+        }
+        parser.end_of_line()?;
+    }
+}
@@ -42,6 +42,15 @@ pub fn metadata(path: &str) -> io::Result<Metadata> {
     File::open(path)?.metadata()
 }
 
+/// Usage statistics of a mounted filesystem, see [`statfs`].
+pub type FileSystemStat = arceos_api::fs::AxFileSystemStat;
+
+/// Returns the usage statistics of the filesystem containing `path`, e.g.
+/// to check the free space before writing.
+pub fn statfs(path: &str) -> io::Result<FileSystemStat> {
+    arceos_api::fs::ax_statfs(path)
+}
+
 /// Returns an iterator over the entries within a directory.
 pub fn read_dir(path: &str) -> io::Result<ReadDir> {
     ReadDir::new(path)
@@ -162,7 +162,43 @@ pub fn stdout() -> Stdout {
 }
 
 #[doc(hidden)]
+#[cfg_attr(feature = "tiny-fmt", inline(always))]
 pub fn __print_impl(args: core::fmt::Arguments) {
+    // inlined, so that the strings without arguments are known at compile
+    // time and printed without the formatting machinery
+    #[cfg(feature = "tiny-fmt")]
+    if let Some(s) = args.as_str() {
+        return print_str(s, "");
+    }
+    print_fmt(args)
+}
+
+#[doc(hidden)]
+#[cfg_attr(feature = "tiny-fmt", inline(always))]
+pub fn __println_impl(args: core::fmt::Arguments) {
+    #[cfg(feature = "tiny-fmt")]
+    if let Some(s) = args.as_str() {
+        return print_str(s, "\n");
+    }
+    print_fmt(format_args!("{}\n", args))
+}
+
+/// Prints `s` then `end`, not interleaved with the other prints.
+#[cfg(feature = "tiny-fmt")]
+fn print_str(s: &str, end: &str) {
+    // the console does not fail, and unwrapping would format a panic message
+    let mut stdout = stdout().lock();
+    if cfg!(feature = "smp") {
+        // also synchronize with the kernel logs, like `print_fmt`
+        arceos_api::stdio::ax_console_write_str(s).ok();
+        arceos_api::stdio::ax_console_write_str(end).ok();
+    } else {
+        stdout.write_all(s.as_bytes()).ok();
+        stdout.write_all(end.as_bytes()).ok();
+    }
+}
+
+fn print_fmt(args: core::fmt::Arguments) {
     if cfg!(feature = "smp") {
         // synchronize using the lock in axlog, to avoid interleaving
         // with kernel logs
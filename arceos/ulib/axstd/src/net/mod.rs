@@ -13,14 +13,18 @@
 //!   and [`SocketAddrV6`] are respectively IPv4 and IPv6 socket addresses
 //! * [`ToSocketAddrs`] is a trait that is used for generic address resolution when interacting
 //!   with networking objects like [`TcpListener`], [`TcpStream`] or [`UdpSocket`]
+//! * [`rpc`] provides a client of unary RPCs over gRPC-Web (with the `rpc` feature)
 
 mod socket_addr;
 mod tcp;
 mod udp;
 
+#[cfg(feature = "rpc")]
+pub mod rpc;
+
 pub use self::socket_addr::{IpAddr, Ipv4Addr, Ipv6Addr};
 pub use self::socket_addr::{SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs};
-pub use self::tcp::{TcpListener, TcpStream};
+pub use self::tcp::{TcpKeepAlive, TcpListener, TcpStream};
 pub use self::udp::UdpSocket;
 
 use crate::io;
@@ -0,0 +1,247 @@
+//! A client of unary RPCs, speaking gRPC-Web over HTTP/1.1.
+//!
+//! A call sends one protobuf message in a `POST` to `/<package>.<Service>/<Method>`,
+//! framed as in gRPC (`application/grpc-web+proto`), and reads back one
+//! message and the `grpc-status` of the server. It works with the servers
+//! accepting gRPC-Web directly or through a proxy, on a new connection for
+//! each call. Compressed messages and streaming calls are not supported.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use super::{SocketAddr, TcpStream, ToSocketAddrs};
+use crate::io::{self, prelude::*};
+use crate::protobuf::{DecodeError, Message};
+
+/// The maximum size of a response, headers included.
+const MAX_RESPONSE_SIZE: usize = 1 << 20;
+
+const FRAME_HEADER_SIZE: usize = 5;
+const FRAME_TRAILERS: u8 = 0x80;
+const FRAME_COMPRESSED: u8 = 0x01;
+
+/// An error of an RPC.
+#[derive(Debug)]
+pub enum RpcError {
+    /// The connection failed.
+    Io(io::Error),
+    /// The HTTP status of the response is not 200.
+    Http(u16),
+    /// The server returned an error status (gRPC status code and message).
+    Status(u32, String),
+    /// The response message cannot be decoded.
+    Decode(DecodeError),
+    /// The response is not a valid gRPC-Web response.
+    InvalidResponse,
+}
+
+impl From<io::Error> for RpcError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<DecodeError> for RpcError {
+    fn from(e: DecodeError) -> Self {
+        Self::Decode(e)
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {:?}", e),
+            Self::Http(code) => write!(f, "HTTP status {}", code),
+            Self::Status(code, msg) => write!(f, "RPC status {}: {}", code, msg),
+            Self::Decode(e) => write!(f, "bad response message: {}", e),
+            Self::InvalidResponse => write!(f, "invalid response"),
+        }
+    }
+}
+
+/// A client of the RPC server at an address.
+pub struct RpcClient {
+    addr: SocketAddr,
+    authority: String,
+}
+
+impl RpcClient {
+    /// Creates a client of the server at `addr`, whose name (for the `Host`
+    /// header) is `authority`.
+    pub fn new<A: ToSocketAddrs>(addr: A, authority: &str) -> io::Result<Self> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| axerrno::ax_err_type!(InvalidInput, "no address"))?;
+        Ok(Self {
+            addr,
+            authority: authority.to_string(),
+        })
+    }
+
+    /// Calls the method at `path` (`/<package>.<Service>/<Method>`) with the
+    /// request message, and returns the response message.
+    pub fn call<Req: Message, Resp: Message>(
+        &self,
+        path: &str,
+        req: &Req,
+    ) -> Result<Resp, RpcError> {
+        let resp = self.call_raw(path, &req.encode_to_vec())?;
+        Ok(Resp::decode_from(&resp)?)
+    }
+
+    /// Calls the method at `path` with the encoded request message, and
+    /// returns the encoded response message.
+    pub fn call_raw(&self, path: &str, msg: &[u8]) -> Result<Vec<u8>, RpcError> {
+        let mut req = alloc::format!(
+            "POST {} HTTP/1.1\r\n\
+             Host: {}\r\n\
+             Content-Type: application/grpc-web+proto\r\n\
+             Accept: application/grpc-web+proto\r\n\
+             X-Grpc-Web: 1\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\r\n",
+            path,
+            self.authority,
+            FRAME_HEADER_SIZE + msg.len(),
+        )
+        .into_bytes();
+        req.push(0);
+        req.extend_from_slice(&(msg.len() as u32).to_be_bytes());
+        req.extend_from_slice(msg);
+
+        let mut stream = TcpStream::connect(self.addr)?;
+        stream.write_all(&req)?;
+        let resp = read_response(&mut stream)?;
+        parse_response(&resp)
+    }
+}
+
+/// Reads until the server closes the connection.
+fn read_response(stream: &mut TcpStream) -> Result<Vec<u8>, RpcError> {
+    let mut resp = Vec::new();
+    let mut buf = [0; 1024];
+    loop {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            return Ok(resp);
+        }
+        if resp.len() + n > MAX_RESPONSE_SIZE {
+            return Err(RpcError::InvalidResponse);
+        }
+        resp.extend_from_slice(&buf[..n]);
+    }
+}
+
+/// The gRPC status and message, from the headers or the trailers.
+#[derive(Default)]
+struct Status {
+    code: Option<u32>,
+    message: String,
+}
+
+impl Status {
+    fn parse_header(&mut self, name: &str, value: &str) {
+        if name.eq_ignore_ascii_case("grpc-status") {
+            self.code = value.trim().parse().ok();
+        } else if name.eq_ignore_ascii_case("grpc-message") {
+            self.message = value.trim().to_string();
+        }
+    }
+}
+
+fn parse_response(resp: &[u8]) -> Result<Vec<u8>, RpcError> {
+    let head_len = resp
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or(RpcError::InvalidResponse)?;
+    let head = core::str::from_utf8(&resp[..head_len]).map_err(|_| RpcError::InvalidResponse)?;
+    let mut body = &resp[head_len + 4..];
+
+    let mut lines = head.split("\r\n");
+    let code = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or(RpcError::InvalidResponse)?;
+    if code != 200 {
+        return Err(RpcError::Http(code));
+    }
+    let mut status = Status::default();
+    let mut chunked = false;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            let len: usize = value.parse().map_err(|_| RpcError::InvalidResponse)?;
+            body = body.get(..len).ok_or(RpcError::InvalidResponse)?;
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.eq_ignore_ascii_case("chunked");
+        } else {
+            // a response with no message may only have headers
+            status.parse_header(name, value);
+        }
+    }
+    let dechunked;
+    if chunked {
+        dechunked = dechunk(body)?;
+        body = &dechunked;
+    }
+
+    let mut msg = None;
+    while !body.is_empty() {
+        if body.len() < FRAME_HEADER_SIZE {
+            return Err(RpcError::InvalidResponse);
+        }
+        let flags = body[0];
+        let len = u32::from_be_bytes(body[1..5].try_into().unwrap()) as usize;
+        let data = body
+            .get(FRAME_HEADER_SIZE..FRAME_HEADER_SIZE + len)
+            .ok_or(RpcError::InvalidResponse)?;
+        body = &body[FRAME_HEADER_SIZE + len..];
+        if flags & FRAME_TRAILERS != 0 {
+            let trailers = core::str::from_utf8(data).map_err(|_| RpcError::InvalidResponse)?;
+            for line in trailers.split("\r\n") {
+                if let Some((name, value)) = line.split_once(':') {
+                    status.parse_header(name, value);
+                }
+            }
+        } else if flags & FRAME_COMPRESSED != 0 {
+            return Err(RpcError::InvalidResponse);
+        } else if msg.is_none() {
+            msg = Some(data.to_vec());
+        }
+    }
+
+    match status.code {
+        Some(0) => Ok(msg.unwrap_or_default()),
+        Some(code) => Err(RpcError::Status(code, status.message)),
+        None => Err(RpcError::InvalidResponse),
+    }
+}
+
+/// Decodes a body in the chunked transfer encoding.
+fn dechunk(mut body: &[u8]) -> Result<Vec<u8>, RpcError> {
+    let mut out = Vec::new();
+    loop {
+        let line_len = body
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or(RpcError::InvalidResponse)?;
+        let size = core::str::from_utf8(&body[..line_len])
+            .ok()
+            .and_then(|line| line.split(';').next())
+            .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+            .ok_or(RpcError::InvalidResponse)?;
+        body = &body[line_len + 2..];
+        if size == 0 {
+            return Ok(out);
+        }
+        let chunk = body.get(..size).ok_or(RpcError::InvalidResponse)?;
+        out.extend_from_slice(chunk);
+        body = body.get(size + 2..).ok_or(RpcError::InvalidResponse)?;
+    }
+}
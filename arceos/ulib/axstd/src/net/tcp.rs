@@ -1,8 +1,11 @@
 use super::{SocketAddr, ToSocketAddrs};
 use crate::io::{self, prelude::*};
+use crate::time::Duration;
 
 use arceos_api::net::{self as api, AxTcpSocketHandle};
 
+pub use arceos_api::net::AxTcpKeepAlive as TcpKeepAlive;
+
 /// A TCP stream between a local and a remote socket.
 pub struct TcpStream(AxTcpSocketHandle);
 
@@ -43,6 +46,21 @@ impl TcpStream {
     pub fn shutdown(&self) -> io::Result<()> {
         api::ax_tcp_shutdown(&self.0)
     }
+
+    /// Enables keepalive probes with the given parameters, or disables them
+    /// with `None`.
+    ///
+    /// When the peer stops answering the probes, the connection is aborted
+    /// and reads and writes fail.
+    pub fn set_keepalive(&self, keepalive: Option<TcpKeepAlive>) -> io::Result<()> {
+        api::ax_tcp_set_keepalive(&self.0, keepalive)
+    }
+
+    /// Sets how long sent data may stay unacknowledged before the connection
+    /// is aborted, or removes the limit with `None`.
+    pub fn set_user_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        api::ax_tcp_set_user_timeout(&self.0, timeout)
+    }
 }
 
 impl Read for TcpStream {
@@ -89,6 +107,13 @@ impl TcpListener {
         })
     }
 
+    /// Sets the maximum number of established connections waiting to be
+    /// accepted (128 by default).
+    pub fn set_backlog(&self, backlog: usize) -> io::Result<()> {
+        // listening again only updates the backlog
+        api::ax_tcp_listen(&self.0, backlog)
+    }
+
     /// Returns the local socket address of this listener.
     pub fn local_addr(&self) -> io::Result<SocketAddr> {
         api::ax_tcp_socket_addr(&self.0)
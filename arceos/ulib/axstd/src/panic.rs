@@ -0,0 +1,10 @@
+//! Panic support, with the unwinding of the panics (`unwind` feature).
+//!
+//! A panic unwinds the stack up to the innermost [`catch_unwind`]. A thread
+//! whose panic is not caught exits, and the whole system is shut down if it
+//! is the main thread.
+
+#[doc(no_inline)]
+pub use core::panic::{AssertUnwindSafe, Location, PanicInfo, RefUnwindSafe, UnwindSafe};
+
+pub use arceos_api::modules::axruntime::panic::{catch_unwind, resume_unwind};
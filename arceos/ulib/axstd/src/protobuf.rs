@@ -0,0 +1,377 @@
+//! Protocol Buffers encoding and decoding.
+//!
+//! Messages are written field by field with an [`Encoder`], and read back
+//! with a [`Decoder`], which yields each field with its number and its
+//! [`Value`] as on the wire; the caller knows the schema and picks the
+//! conversion. Types implementing [`Message`] can be sent by
+//! [`net::rpc`](crate::net::rpc) (with the `rpc` feature).
+//!
+//! Groups (deprecated wire types 3 and 4) are not supported.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_I64: u8 = 1;
+const WIRE_LEN: u8 = 2;
+const WIRE_I32: u8 = 5;
+
+/// The largest field number.
+pub const MAX_FIELD: u32 = (1 << 29) - 1;
+
+/// An error when decoding a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The message ends in the middle of a field.
+    Truncated,
+    /// A varint is longer than 10 bytes.
+    VarintOverflow,
+    /// The wire type is unknown, or a group.
+    InvalidWireType(u8),
+    /// The field number is 0, or too large.
+    InvalidField,
+    /// A field has not the expected type or value.
+    InvalidValue,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "truncated message"),
+            Self::VarintOverflow => write!(f, "varint too long"),
+            Self::InvalidWireType(t) => write!(f, "invalid wire type {}", t),
+            Self::InvalidField => write!(f, "invalid field number"),
+            Self::InvalidValue => write!(f, "invalid field value"),
+        }
+    }
+}
+
+/// A message that can be encoded and decoded.
+pub trait Message: Sized {
+    /// Writes the fields of the message.
+    fn encode(&self, enc: &mut Encoder);
+
+    /// Reads a message, from all its fields.
+    fn decode(dec: Decoder) -> Result<Self, DecodeError>;
+
+    /// Encodes the message to a buffer.
+    fn encode_to_vec(&self) -> Vec<u8> {
+        let mut enc = Encoder::new();
+        self.encode(&mut enc);
+        enc.finish()
+    }
+
+    /// Decodes a message from a buffer.
+    fn decode_from(buf: &[u8]) -> Result<Self, DecodeError> {
+        Self::decode(Decoder::new(buf))
+    }
+}
+
+/// Writes the fields of a message.
+///
+/// The fields are written in the order of the calls; repeated fields are
+/// written by calling the same method again, or packed with
+/// [`packed_uint64`](Self::packed_uint64) and the like.
+#[derive(Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    /// Creates an empty encoder.
+    pub const fn new() -> Self {
+        Self { buf: Vec::new() }
+    }
+
+    /// Returns the encoded message.
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+
+    /// Returns the bytes encoded so far.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    fn varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.buf.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        self.buf.push(v as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u8) {
+        debug_assert!(field != 0 && field <= MAX_FIELD);
+        self.varint((field as u64) << 3 | wire_type as u64);
+    }
+
+    /// Writes a `uint64` (or `uint32`, or an enum) field.
+    pub fn uint64(&mut self, field: u32, v: u64) -> &mut Self {
+        self.key(field, WIRE_VARINT);
+        self.varint(v);
+        self
+    }
+
+    /// Writes an `int64` (or `int32`) field, negative values taking 10
+    /// bytes.
+    pub fn int64(&mut self, field: u32, v: i64) -> &mut Self {
+        self.uint64(field, v as u64)
+    }
+
+    /// Writes a `sint64` (or `sint32`) field, zigzag encoded so that small
+    /// negative values are short.
+    pub fn sint64(&mut self, field: u32, v: i64) -> &mut Self {
+        self.uint64(field, ((v << 1) ^ (v >> 63)) as u64)
+    }
+
+    /// Writes a `bool` field.
+    pub fn bool(&mut self, field: u32, v: bool) -> &mut Self {
+        self.uint64(field, v as u64)
+    }
+
+    /// Writes a `fixed64` (or `sfixed64`) field.
+    pub fn fixed64(&mut self, field: u32, v: u64) -> &mut Self {
+        self.key(field, WIRE_I64);
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    /// Writes a `fixed32` (or `sfixed32`) field.
+    pub fn fixed32(&mut self, field: u32, v: u32) -> &mut Self {
+        self.key(field, WIRE_I32);
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    /// Writes a `double` field.
+    pub fn double(&mut self, field: u32, v: f64) -> &mut Self {
+        self.fixed64(field, v.to_bits())
+    }
+
+    /// Writes a `float` field.
+    pub fn float(&mut self, field: u32, v: f32) -> &mut Self {
+        self.fixed32(field, v.to_bits())
+    }
+
+    /// Writes a `bytes` field.
+    pub fn bytes(&mut self, field: u32, v: &[u8]) -> &mut Self {
+        self.key(field, WIRE_LEN);
+        self.varint(v.len() as u64);
+        self.buf.extend_from_slice(v);
+        self
+    }
+
+    /// Writes a `string` field.
+    pub fn string(&mut self, field: u32, v: &str) -> &mut Self {
+        self.bytes(field, v.as_bytes())
+    }
+
+    /// Writes an embedded message field, whose fields are written by `f`.
+    pub fn message(&mut self, field: u32, f: impl FnOnce(&mut Encoder)) -> &mut Self {
+        let mut sub = Encoder::new();
+        f(&mut sub);
+        self.bytes(field, &sub.buf)
+    }
+
+    /// Writes an embedded [`Message`] field.
+    pub fn message_of<M: Message>(&mut self, field: u32, msg: &M) -> &mut Self {
+        self.message(field, |enc| msg.encode(enc))
+    }
+
+    /// Writes a packed repeated `uint64` (or `uint32`, `int64`, enum) field.
+    pub fn packed_uint64(&mut self, field: u32, values: &[u64]) -> &mut Self {
+        self.message(field, |enc| {
+            for &v in values {
+                enc.varint(v);
+            }
+        })
+    }
+
+    /// Writes a packed repeated `sint64` (or `sint32`) field.
+    pub fn packed_sint64(&mut self, field: u32, values: &[i64]) -> &mut Self {
+        self.message(field, |enc| {
+            for &v in values {
+                enc.varint(((v << 1) ^ (v >> 63)) as u64);
+            }
+        })
+    }
+
+    /// Writes a packed repeated `double` field.
+    pub fn packed_double(&mut self, field: u32, values: &[f64]) -> &mut Self {
+        self.message(field, |enc| {
+            for v in values {
+                enc.buf.extend_from_slice(&v.to_bits().to_le_bytes());
+            }
+        })
+    }
+}
+
+/// The value of a field, as on the wire.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value<'a> {
+    /// A varint: `int32`, `int64`, `uint32`, `uint64`, `sint32`, `sint64`,
+    /// `bool` or enum.
+    Varint(u64),
+    /// 8 bytes: `fixed64`, `sfixed64` or `double`.
+    I64(u64),
+    /// Length-delimited: `string`, `bytes`, embedded message or packed
+    /// repeated field.
+    Len(&'a [u8]),
+    /// 4 bytes: `fixed32`, `sfixed32` or `float`.
+    I32(u32),
+}
+
+impl<'a> Value<'a> {
+    /// Returns a `uint64`, `uint32`, `int64` or enum value.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Self::Varint(v) | Self::I64(v) => Some(v),
+            Self::I32(v) => Some(v as u64),
+            Self::Len(_) => None,
+        }
+    }
+
+    /// Returns an `int64` or `int32` value.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Self::I32(v) => Some(v as i32 as i64),
+            _ => self.as_u64().map(|v| v as i64),
+        }
+    }
+
+    /// Returns a zigzag encoded `sint64` or `sint32` value.
+    pub fn as_sint64(&self) -> Option<i64> {
+        match *self {
+            Self::Varint(v) => Some((v >> 1) as i64 ^ -((v & 1) as i64)),
+            _ => None,
+        }
+    }
+
+    /// Returns a `bool` value.
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Self::Varint(v) => Some(v != 0),
+            _ => None,
+        }
+    }
+
+    /// Returns a `double` value.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Self::I64(v) => Some(f64::from_bits(v)),
+            _ => None,
+        }
+    }
+
+    /// Returns a `float` value.
+    pub fn as_f32(&self) -> Option<f32> {
+        match *self {
+            Self::I32(v) => Some(f32::from_bits(v)),
+            _ => None,
+        }
+    }
+
+    /// Returns a `bytes` value.
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match *self {
+            Self::Len(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns a `string` value, if valid UTF-8.
+    pub fn as_str(&self) -> Option<&'a str> {
+        core::str::from_utf8(self.as_bytes()?).ok()
+    }
+
+    /// Returns a decoder of an embedded message.
+    pub fn as_message(&self) -> Option<Decoder<'a>> {
+        self.as_bytes().map(Decoder::new)
+    }
+
+    /// Decodes an embedded [`Message`].
+    pub fn decode_message<M: Message>(&self) -> Result<M, DecodeError> {
+        M::decode(self.as_message().ok_or(DecodeError::InvalidValue)?)
+    }
+
+    /// Returns the values of a packed repeated varint field (or of a single
+    /// non-packed one, which parsers must accept too).
+    pub fn packed_varints(&self) -> impl Iterator<Item = Result<u64, DecodeError>> + 'a {
+        let (single, mut dec) = match *self {
+            Self::Varint(v) => (Some(v), Decoder::new(&[])),
+            Self::Len(buf) => (None, Decoder::new(buf)),
+            _ => (None, Decoder::new(&[])),
+        };
+        let mut single = single.map(Ok);
+        core::iter::from_fn(move || {
+            single
+                .take()
+                .or_else(|| (!dec.is_empty()).then(|| dec.varint()))
+        })
+    }
+}
+
+/// Reads the fields of a message.
+#[derive(Debug, Clone)]
+pub struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    /// Creates a decoder of the encoded message.
+    pub const fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    /// Returns whether all the fields are read.
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if len > self.buf.len() {
+            return Err(DecodeError::Truncated);
+        }
+        let (head, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(head)
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut v = 0;
+        for i in 0..10 {
+            let b = *self.take(1)?.first().unwrap();
+            v |= ((b & 0x7f) as u64) << (i * 7);
+            if b < 0x80 {
+                return Ok(v);
+            }
+        }
+        Err(DecodeError::VarintOverflow)
+    }
+
+    /// Reads the next field, and returns its number and value, or `None` at
+    /// the end of the message.
+    pub fn next_field(&mut self) -> Result<Option<(u32, Value<'a>)>, DecodeError> {
+        if self.buf.is_empty() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let field = key >> 3;
+        if field == 0 || field > MAX_FIELD as u64 {
+            return Err(DecodeError::InvalidField);
+        }
+        let value = match (key & 7) as u8 {
+            WIRE_VARINT => Value::Varint(self.varint()?),
+            WIRE_I64 => Value::I64(u64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            WIRE_LEN => {
+                let len = self.varint()?;
+                let len = usize::try_from(len).map_err(|_| DecodeError::Truncated)?;
+                Value::Len(self.take(len)?)
+            }
+            WIRE_I32 => Value::I32(u32::from_le_bytes(self.take(4)?.try_into().unwrap())),
+            t => return Err(DecodeError::InvalidWireType(t)),
+        };
+        Ok(Some((field as u32, value)))
+    }
+}
@@ -9,6 +9,51 @@ use core::{cell::UnsafeCell, num::NonZeroU64};
 use arceos_api::task::{self as api, AxTaskHandle};
 use axerrno::ax_err_type;
 
+pub use arceos_api::task::AxCpuPlacement as CpuPlacement;
+
+/// The highest priority of a thread.
+///
+/// Priorities are given as nice values, from [`MAX_PRIORITY`] (-20, the
+/// highest) to [`MIN_PRIORITY`] (19, the lowest), and threads start with 0.
+/// They are mapped onto the priorities of the scheduler in use, see
+/// [`scheduler_name`]. Schedulers without priorities (FIFO, round-robin)
+/// accept and ignore them.
+pub const MAX_PRIORITY: isize = -20;
+
+/// The lowest priority of a thread, see [`MAX_PRIORITY`].
+pub const MIN_PRIORITY: isize = 19;
+
+/// Maps a priority onto the range of the scheduler, or returns [`None`] if it
+/// has no priorities.
+fn sched_priority(prio: isize) -> io::Result<Option<isize>> {
+    if !(MAX_PRIORITY..=MIN_PRIORITY).contains(&prio) {
+        return Err(ax_err_type!(InvalidInput, "priority out of range"));
+    }
+    Ok(api::ax_priority_range().map(|(highest, lowest)| {
+        highest + (prio - MAX_PRIORITY) * (lowest - highest) / (MIN_PRIORITY - MAX_PRIORITY)
+    }))
+}
+
+/// Sets the priority of the current thread, see [`MAX_PRIORITY`].
+pub fn set_current_priority(prio: isize) -> io::Result<()> {
+    match sched_priority(prio)? {
+        Some(prio) => api::ax_set_current_priority(prio),
+        None => Ok(()),
+    }
+}
+
+/// Sets the kind of CPUs the current thread runs on, on systems with cores of
+/// different capacities (e.g. big.LITTLE). It is ignored on the other ones.
+pub fn set_current_placement(placement: CpuPlacement) {
+    api::ax_set_current_placement(placement)
+}
+
+/// Returns the name of the scheduler, e.g. `"CFS"`, as selected by the
+/// `sched_*` features.
+pub fn scheduler_name() -> &'static str {
+    api::ax_scheduler_name()
+}
+
 /// A unique identifier for a running thread.
 #[derive(Eq, PartialEq, Clone, Copy, Debug)]
 pub struct ThreadId(NonZeroU64);
@@ -49,6 +94,10 @@ pub struct Builder {
     name: Option<String>,
     // The size of the stack for the spawned thread in bytes
     stack_size: Option<usize>,
+    // The priority of the spawned thread
+    priority: Option<isize>,
+    // The kind of CPUs the spawned thread runs on
+    placement: Option<CpuPlacement>,
 }
 
 impl Builder {
@@ -58,6 +107,8 @@ impl Builder {
         Builder {
             name: None,
             stack_size: None,
+            priority: None,
+            placement: None,
         }
     }
 
@@ -73,6 +124,19 @@ impl Builder {
         self
     }
 
+    /// Sets the priority of the new thread, see [`MAX_PRIORITY`].
+    pub fn priority(mut self, prio: isize) -> Builder {
+        self.priority = Some(prio);
+        self
+    }
+
+    /// Sets the kind of CPUs the new thread runs on, see
+    /// [`set_current_placement`].
+    pub fn placement(mut self, placement: CpuPlacement) -> Builder {
+        self.placement = Some(placement);
+        self
+    }
+
     /// Spawns a new thread by taking ownership of the `Builder`, and returns an
     /// [`io::Result`] to its [`JoinHandle`].
     ///
@@ -99,6 +163,11 @@ impl Builder {
         let stack_size = self
             .stack_size
             .unwrap_or(arceos_api::config::TASK_STACK_SIZE);
+        let priority = match self.priority {
+            Some(prio) => sched_priority(prio)?,
+            None => None,
+        };
+        let placement = self.placement;
 
         let my_packet = Arc::new(Packet {
             result: UnsafeCell::new(None),
@@ -106,6 +175,13 @@ impl Builder {
         let their_packet = my_packet.clone();
 
         let main = move || {
+            if let Some(placement) = placement {
+                api::ax_set_current_placement(placement);
+            }
+            if let Some(prio) = priority {
+                // the scheduler only sets the priority of the current task
+                api::ax_set_current_priority(prio).ok();
+            }
             let ret = f();
             // SAFETY: `their_packet` as been built just above and moved by the
             // closure (it is an Arc<...>) and `my_packet` will be stored in the
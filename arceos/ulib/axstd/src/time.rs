@@ -1,6 +1,7 @@
 //! Temporal quantification.
 
 use arceos_api::time::AxTimeValue;
+use core::fmt;
 use core::ops::{Add, AddAssign, Sub, SubAssign};
 
 pub use core::time::Duration;
@@ -13,7 +14,7 @@ pub struct Instant(AxTimeValue);
 impl Instant {
     /// Returns an instant corresponding to "now".
     pub fn now() -> Instant {
-        Instant(arceos_api::time::ax_wall_time())
+        Instant(arceos_api::time::ax_monotonic_time())
     }
 
     /// Returns the amount of time elapsed from another instant to this one,
@@ -95,3 +96,130 @@ impl Sub<Instant> for Instant {
         self.duration_since(other)
     }
 }
+
+/// A measurement of the system clock, i.e. the realtime clock.
+///
+/// Unlike [`Instant`], it may go backwards, as the system clock can be set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SystemTime(AxTimeValue);
+
+/// An anchor in time, "1970-01-01 00:00:00 UTC".
+pub const UNIX_EPOCH: SystemTime = SystemTime(Duration::ZERO);
+
+/// An error returned from [`SystemTime::duration_since`] and
+/// [`SystemTime::elapsed`], when the second time is later than the first.
+#[derive(Clone, Debug)]
+pub struct SystemTimeError(Duration);
+
+impl SystemTimeError {
+    /// Returns how far the second time is later than the first.
+    pub fn duration(&self) -> Duration {
+        self.0
+    }
+}
+
+impl fmt::Display for SystemTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "second time provided was later than self")
+    }
+}
+
+impl SystemTime {
+    /// An anchor in time, "1970-01-01 00:00:00 UTC".
+    pub const UNIX_EPOCH: SystemTime = UNIX_EPOCH;
+
+    /// Returns the system time corresponding to "now".
+    pub fn now() -> SystemTime {
+        SystemTime(arceos_api::time::ax_wall_time())
+    }
+
+    /// Returns the amount of time elapsed from an earlier point in time, or
+    /// an error if `earlier` is later than `self`.
+    pub fn duration_since(&self, earlier: SystemTime) -> Result<Duration, SystemTimeError> {
+        self.0
+            .checked_sub(earlier.0)
+            .ok_or_else(|| SystemTimeError(earlier.0 - self.0))
+    }
+
+    /// Returns the amount of time elapsed since this system time was created,
+    /// or an error if the system clock was set back meanwhile.
+    pub fn elapsed(&self) -> Result<Duration, SystemTimeError> {
+        SystemTime::now().duration_since(*self)
+    }
+
+    /// Returns `Some(t)` where `t` is the time `self + duration` if `t` can be
+    /// represented, `None` otherwise.
+    pub fn checked_add(&self, duration: Duration) -> Option<SystemTime> {
+        self.0.checked_add(duration).map(SystemTime)
+    }
+
+    /// Returns `Some(t)` where `t` is the time `self - duration` if `t` can be
+    /// represented, `None` otherwise.
+    pub fn checked_sub(&self, duration: Duration) -> Option<SystemTime> {
+        self.0.checked_sub(duration).map(SystemTime)
+    }
+}
+
+impl Add<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    /// # Panics
+    ///
+    /// This function may panic if the resulting point in time cannot be represented by the
+    /// underlying data structure.
+    fn add(self, dur: Duration) -> SystemTime {
+        self.checked_add(dur)
+            .expect("overflow when adding duration to system time")
+    }
+}
+
+impl AddAssign<Duration> for SystemTime {
+    fn add_assign(&mut self, other: Duration) {
+        *self = *self + other;
+    }
+}
+
+impl Sub<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn sub(self, dur: Duration) -> SystemTime {
+        self.checked_sub(dur)
+            .expect("overflow when subtracting duration from system time")
+    }
+}
+
+impl SubAssign<Duration> for SystemTime {
+    fn sub_assign(&mut self, other: Duration) {
+        *self = *self - other;
+    }
+}
+
+/// Sets the system clock to `time`.
+pub fn set_system_time(time: SystemTime) {
+    arceos_api::time::ax_set_wall_time(time.0)
+}
+
+/// Runs `f` with a time budget ending at `deadline`.
+///
+/// Blocking I/O operations made by `f` (e.g. resolving, connecting, reading
+/// and writing sockets) fail with [`TimedOut`] once the deadline has passed.
+/// The budget is shared by all the operations, and a nested budget can only
+/// shorten it.
+///
+/// [`TimedOut`]: crate::io::Error::TimedOut
+pub fn with_deadline<R>(deadline: Instant, f: impl FnOnce() -> R) -> R {
+    let prev = arceos_api::time::ax_set_deadline(None);
+    let deadline = prev.map_or(deadline.0, |prev| prev.min(deadline.0));
+    arceos_api::time::ax_set_deadline(Some(deadline));
+    let ret = f();
+    arceos_api::time::ax_set_deadline(prev);
+    ret
+}
+
+/// Runs `f` with a time budget of `timeout` from now, see [`with_deadline`].
+pub fn with_timeout<R>(timeout: Duration, f: impl FnOnce() -> R) -> R {
+    match Instant::now().checked_add(timeout) {
+        Some(deadline) => with_deadline(deadline, f),
+        None => f(),
+    }
+}